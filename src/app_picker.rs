@@ -2,6 +2,10 @@
 //!
 //! This also includes a license text viewer. The license text viewer is needed
 //! on Android, where the command-line way to view license text doesn't exist.
+//!
+//! Apps can be searched by typing on the host keyboard, sorted by name or by
+//! when they were last launched (see [record_app_launch]), and given per-app
+//! settings that are saved to [paths::USER_OPTIONS_FILE].
 
 use crate::bundle::Bundle;
 use crate::frameworks::core_graphics::cg_bitmap_context::{
@@ -26,11 +30,11 @@ use crate::frameworks::uikit::ui_view::ui_control::ui_button::{
 use crate::frameworks::uikit::ui_view::ui_control::{
     UIControlEventTouchUpInside, UIControlStateNormal,
 };
-use crate::fs::BundleData;
+use crate::fs::{BundleData, Fs};
 use crate::image::Image;
 use crate::mem::Ptr;
 use crate::objc::{id, msg, msg_class, nil, objc_classes, release, ClassExports, HostObject};
-use crate::options::Options;
+use crate::options::{self, Options, Rumble};
 use crate::paths;
 use crate::window::DeviceOrientation;
 use crate::Environment;
@@ -38,13 +42,24 @@ use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::num::NonZeroU32;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 struct AppInfo {
     path: PathBuf,
     display_name: String,
-    icon: Option<Image>,
+    app_id: String,
+    /// Whether there are options for this app in [paths::USER_OPTIONS_FILE].
+    has_options: bool,
+    /// Whether the app has saved anything in its sandbox directory.
+    has_data: bool,
+    /// When the app was last launched (seconds since the Unix epoch).
+    last_played: Option<u64>,
+    /// Kept until the app's icon is first displayed. Decoding icons is slow
+    /// enough to cause a noticeable hang for large libraries, so it is only
+    /// done for the page being shown.
+    bundle_and_fs: Option<(Bundle, Fs)>,
     /// `NSString*`
-    display_name_ns_string: Option<id>,
+    label_ns_string: Option<id>,
     /// `UIImage*`
     icon_ui_image: Option<id>,
 }
@@ -82,6 +97,9 @@ pub fn app_picker(
 }
 
 fn enumerate_apps(apps_dir: &Path) -> Result<Vec<AppInfo>, std::io::Error> {
+    let user_options = options::read_user_options_file().unwrap_or_default();
+    let last_played = read_last_played_file();
+
     let mut apps = Vec::new();
     for app in std::fs::read_dir(apps_dir)? {
         let app_path = app?.path();
@@ -106,28 +124,209 @@ fn enumerate_apps(apps_dir: &Path) -> Result<Vec<AppInfo>, std::io::Error> {
             }
         };
 
-        // TODO: what if this crashes?
         let display_name = bundle.display_name().to_owned();
-
-        let icon = match bundle.load_icon(&fs) {
-            Ok(icon) => Some(icon),
-            Err(e) => {
-                log!("Warning: couldn't load icon for app bundle {}: {} (displaying placeholder instead)", app_path.display(), e);
-                None
-            }
-        };
+        let app_id = bundle.bundle_identifier().to_owned();
 
         apps.push(AppInfo {
             path: app_path,
             display_name,
-            icon,
-            display_name_ns_string: None,
+            has_options: get_user_options(&user_options, &app_id).is_some(),
+            has_data: has_sandbox_data(&app_id),
+            last_played: last_played.get(&app_id).copied(),
+            app_id,
+            bundle_and_fs: Some((bundle, fs)),
+            label_ns_string: None,
             icon_ui_image: None,
         });
     }
     Ok(apps)
 }
 
+fn get_user_options(user_options: &str, app_id: &str) -> Option<String> {
+    options::get_options_from_file(user_options.as_bytes(), app_id)
+        .ok()
+        .flatten()
+}
+
+/// Whether the app has saved any files in its sandbox directory. The
+/// directories themselves are created on every launch, so they don't count.
+fn has_sandbox_data(app_id: &str) -> bool {
+    fn dir_has_files(path: &Path) -> bool {
+        let Ok(entries) = std::fs::read_dir(path) else {
+            return false;
+        };
+        entries
+            .flatten()
+            .any(|entry| !entry.path().is_dir() || dir_has_files(&entry.path()))
+    }
+    dir_has_files(
+        &paths::user_data_base_path()
+            .join(paths::SANDBOX_DIR)
+            .join(app_id),
+    )
+}
+
+fn last_played_file_path() -> PathBuf {
+    paths::user_data_base_path().join(paths::LAST_PLAYED_FILE)
+}
+
+/// Read [paths::LAST_PLAYED_FILE]. Each line is an app ID and the time it was
+/// last launched (seconds since the Unix epoch), separated by a colon.
+/// Malformed lines are ignored.
+fn read_last_played_file() -> HashMap<String, u64> {
+    let Ok(contents) = std::fs::read_to_string(last_played_file_path()) else {
+        return HashMap::new();
+    };
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (app_id, time) = line.split_once(':')?;
+            Some((app_id.trim().to_string(), time.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// Record that an app is being launched, so that the app picker can sort apps
+/// by when they were last played.
+pub fn record_app_launch(app_id: &str) {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let mut last_played = read_last_played_file();
+    last_played.insert(app_id.to_string(), now);
+
+    let mut last_played: Vec<_> = last_played.into_iter().collect();
+    last_played.sort();
+    let contents: String = last_played
+        .into_iter()
+        .map(|(app_id, time)| format!("{}: {}\n", app_id, time))
+        .collect();
+    let path = last_played_file_path();
+    if let Err(e) = std::fs::write(&path, contents) {
+        log!("Warning: couldn't write {}: {}", path.display(), e);
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum SortOrder {
+    Name,
+    LastPlayed,
+}
+
+/// Get the indices of the apps whose names contain the search string, in the
+/// order they should be displayed.
+fn filter_and_sort_apps(apps: &[AppInfo], search: &str, sort_order: SortOrder) -> Vec<usize> {
+    let search = search.to_lowercase();
+    let mut app_idxs: Vec<usize> = (0..apps.len())
+        .filter(|&app_idx| apps[app_idx].display_name.to_lowercase().contains(&search))
+        .collect();
+    app_idxs.sort_by_cached_key(|&app_idx| {
+        let app = &apps[app_idx];
+        // Apps that have never been played go last.
+        let last_played = match sort_order {
+            SortOrder::Name => None,
+            SortOrder::LastPlayed => app.last_played,
+        };
+        (
+            std::cmp::Reverse(last_played),
+            app.display_name.to_lowercase(),
+        )
+    });
+    app_idxs
+}
+
+/// Choices made in the quick options screen, which only apply to the next
+/// launch, or in the per-app settings screen, which are saved. [None] means
+/// the option isn't overridden.
+#[derive(Default)]
+struct OptionChoices {
+    scale_hack: Option<NonZeroU32>,
+    orientation: Option<DeviceOrientation>,
+    fullscreen: Option<()>,
+    rumble: Option<Rumble>,
+}
+impl OptionChoices {
+    /// Pick out the options this screen can change from an options string.
+    /// The other options are returned separately so they can be preserved.
+    fn from_options_string(options_string: &str) -> (Self, Vec<String>) {
+        let mut choices = Self::default();
+        let mut other_args = Vec::new();
+        for arg in options_string.split_ascii_whitespace() {
+            if let Some(scale_hack) = arg
+                .strip_prefix("--scale-hack=")
+                .and_then(|value| value.parse().ok())
+            {
+                choices.scale_hack = Some(scale_hack);
+            } else if arg == "--landscape-left" {
+                choices.orientation = Some(DeviceOrientation::LandscapeLeft);
+            } else if arg == "--landscape-right" {
+                choices.orientation = Some(DeviceOrientation::LandscapeRight);
+            } else if arg == "--fullscreen" {
+                choices.fullscreen = Some(());
+            } else if let Some(rumble) = arg.strip_prefix("--rumble=").and_then(|value| match value
+            {
+                "off" => Some(Rumble::Off),
+                "low" => Some(Rumble::Low),
+                "high" => Some(Rumble::High),
+                _ => None,
+            }) {
+                choices.rumble = Some(rumble);
+            } else {
+                other_args.push(arg.to_string());
+            }
+        }
+        (choices, other_args)
+    }
+
+    fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(scale_hack) = self.scale_hack {
+            args.push(format!("--scale-hack={}", scale_hack.get()));
+        }
+        if let Some(orientation) = self.orientation {
+            args.push(
+                match orientation {
+                    DeviceOrientation::LandscapeLeft => "--landscape-left",
+                    DeviceOrientation::LandscapeRight => "--landscape-right",
+                    _ => todo!(),
+                }
+                .to_string(),
+            );
+        }
+        if let Some(()) = self.fullscreen {
+            args.push("--fullscreen".to_string());
+        }
+        if let Some(rumble) = self.rumble {
+            args.push(
+                match rumble {
+                    Rumble::Off => "--rumble=off",
+                    Rumble::Low => "--rumble=low",
+                    Rumble::High => "--rumble=high",
+                }
+                .to_string(),
+            );
+        }
+        args
+    }
+}
+
+/// A button in the quick options or per-app settings screen was tapped.
+enum OptionChoice {
+    ScaleHack(Option<NonZeroU32>),
+    Orientation(Option<DeviceOrientation>),
+    Fullscreen(Option<()>),
+    Rumble(Option<Rumble>),
+}
+
+/// State of the per-app settings screen while it is open.
+struct AppSettings {
+    app_idx: usize,
+    choices: OptionChoices,
+    /// Options from the app's line in [paths::USER_OPTIONS_FILE] that can't be
+    /// changed in the settings screen.
+    other_args: Vec<String>,
+}
+
 #[derive(Default)]
 struct AppPickerDelegateHostObject {
     icon_tapped: id,
@@ -137,16 +336,10 @@ struct AppPickerDelegateHostObject {
     copyright_next: bool,
     quick_options_show: bool,
     quick_options_hide: bool,
-    scale_hack_default: bool,
-    scale_hack1: bool,
-    scale_hack2: bool,
-    scale_hack3: bool,
-    scale_hack4: bool,
-    orientation_default: bool,
-    orientation_landscape_left: bool,
-    orientation_landscape_right: bool,
-    fullscreen_default: bool,
-    fullscreen_on: bool,
+    option_choice: Option<OptionChoice>,
+    sort_order_toggle: bool,
+    app_settings_mode_toggle: bool,
+    app_settings_save: bool,
 }
 impl HostObject for AppPickerDelegateHostObject {}
 
@@ -187,37 +380,65 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).quick_options_hide = true;
 }
 - (())scaleHackDefault {
-    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).scale_hack_default = true;
+    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).option_choice = Some(OptionChoice::ScaleHack(None));
 }
 - (())scaleHack1 {
-    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).scale_hack1 = true;
+    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).option_choice = Some(OptionChoice::ScaleHack(NonZeroU32::new(1)));
 }
 - (())scaleHack2 {
-    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).scale_hack2 = true;
+    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).option_choice = Some(OptionChoice::ScaleHack(NonZeroU32::new(2)));
 }
 - (())scaleHack3 {
-    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).scale_hack3 = true;
+    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).option_choice = Some(OptionChoice::ScaleHack(NonZeroU32::new(3)));
 }
 - (())scaleHack4 {
-    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).scale_hack4 = true;
-}
-- (())orientationDefault {
-    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).orientation_default = true;
+    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).option_choice = Some(OptionChoice::ScaleHack(NonZeroU32::new(4)));
 }
 - (())orientationDefault {
-    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).orientation_default = true;
+    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).option_choice = Some(OptionChoice::Orientation(None));
 }
 - (())orientationLandscapeLeft {
-    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).orientation_landscape_left = true;
+    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).option_choice = Some(OptionChoice::Orientation(Some(DeviceOrientation::LandscapeLeft)));
 }
 - (())orientationLandscapeRight {
-    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).orientation_landscape_right = true;
+    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).option_choice = Some(OptionChoice::Orientation(Some(DeviceOrientation::LandscapeRight)));
 }
 - (())fullscreenDefault {
-    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).fullscreen_default = true;
+    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).option_choice = Some(OptionChoice::Fullscreen(None));
 }
 - (())fullscreenOn {
-    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).fullscreen_on = true;
+    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).option_choice = Some(OptionChoice::Fullscreen(Some(())));
+}
+- (())rumbleDefault {
+    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).option_choice = Some(OptionChoice::Rumble(None));
+}
+- (())rumbleOff {
+    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).option_choice = Some(OptionChoice::Rumble(Some(Rumble::Off)));
+}
+- (())rumbleLow {
+    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).option_choice = Some(OptionChoice::Rumble(Some(Rumble::Low)));
+}
+- (())rumbleHigh {
+    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).option_choice = Some(OptionChoice::Rumble(Some(Rumble::High)));
+}
+
+- (())sortOrderToggle {
+    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).sort_order_toggle = true;
+}
+- (())appSettingsModeToggle {
+    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).app_settings_mode_toggle = true;
+}
+- (())appSettingsSave {
+    env.objc.borrow_mut::<AppPickerDelegateHostObject>(this).app_settings_save = true;
+}
+
+// UITextFieldDelegate implementation for the search field
+- (bool)textFieldShouldReturn:(id)text_field {
+    // Assert (see above).
+    let _ = env.objc.borrow_mut::<AppPickerDelegateHostObject>(this);
+
+    let _: bool = msg![env; text_field resignFirstResponder];
+    true
 }
 
 - (())openFileManager {
@@ -376,11 +597,43 @@ fn show_app_picker_gui(
 
     let divider = app_frame.size.height - 100.0;
 
+    let mut sort_order = SortOrder::Name;
+    let mut search_string = String::new();
+    let mut shown_apps = Vec::new();
+    let mut page_idx = 0;
+
     let mut icon_grid_stuff = match &mut apps {
         Ok(ref mut apps) => {
-            let mut icon_grid_stuff =
-                make_icon_grid(env, delegate, main_view, app_frame, apps.len());
-            update_icon_grid(env, &mut icon_grid_stuff, apps, 0);
+            let mut icon_grid_stuff = make_icon_grid(env, delegate, main_view, app_frame);
+            shown_apps = filter_and_sort_apps(apps, &search_string, sort_order);
+            paginate_icon_grid(&mut icon_grid_stuff, shown_apps.len());
+            update_icon_grid(env, &mut icon_grid_stuff, apps, &shown_apps, page_idx);
+
+            // Legend for the markers added to app names
+            let label_frame = CGRect {
+                origin: CGPoint {
+                    x: 5.0,
+                    y: app_frame.size.height - 20.0,
+                },
+                size: CGSize {
+                    width: app_frame.size.width / 2.0,
+                    height: 15.0,
+                },
+            };
+            let label: id = msg_class![env; UILabel alloc];
+            let label: id = msg![env; label initWithFrame:label_frame];
+            let text = ns_string::get_static_str(env, "* has options  • has saved data");
+            () = msg![env; label setText:text];
+            () = msg![env; label setTextAlignment:UITextAlignmentLeft];
+            let font_size: CGFloat = 10.0;
+            let font: id = msg_class![env; UIFont systemFontOfSize:font_size];
+            () = msg![env; label setFont:font];
+            let text_color: id = msg_class![env; UIColor lightGrayColor];
+            () = msg![env; label setTextColor:text_color];
+            let bg_color: id = msg_class![env; UIColor clearColor];
+            () = msg![env; label setBackgroundColor:bg_color];
+            () = msg![env; main_view addSubview:label];
+
             Some(icon_grid_stuff)
         }
         Err(e) => {
@@ -438,74 +691,81 @@ fn show_app_picker_gui(
     let mut copyright_info_page_idx = 0;
 
     let quick_options_stuff = setup_quick_options(env, delegate, main_view, app_frame);
-    let mut quick_options_scale_hack: Option<NonZeroU32> = None;
-    let mut quick_options_fullscreen: Option<()> = None;
-    let mut quick_options_orientation: Option<DeviceOrientation> = None;
-
-    fn update_quick_option_buttons(env: &mut Environment, buttons: &[id], selected_idx: usize) {
-        for (idx, &button) in buttons.iter().enumerate() {
-            let color: id = if idx == selected_idx {
-                msg_class![env; UIColor magentaColor]
-            } else {
-                msg_class![env; UIColor grayColor]
-            };
-            () = msg![env; button setBackgroundColor:color];
-        }
-    }
-    fn update_scale_hack_buttons(env: &mut Environment, buttons: &[id], value: Option<NonZeroU32>) {
-        update_quick_option_buttons(env, buttons, value.map_or(0, |v| (v.get() as usize)));
-    }
-    fn update_fullscreen_buttons(env: &mut Environment, buttons: &[id], value: Option<()>) {
-        update_quick_option_buttons(env, buttons, value.map_or(0, |_| 1));
-    }
-    fn update_orientation_buttons(
-        env: &mut Environment,
-        buttons: &[id],
-        value: Option<DeviceOrientation>,
-    ) {
-        update_quick_option_buttons(
-            env,
-            buttons,
-            value.map_or(0, |v| match v {
-                DeviceOrientation::LandscapeLeft => 1,
-                DeviceOrientation::LandscapeRight => 2,
-                _ => panic!(),
-            }),
-        );
-    }
-    update_scale_hack_buttons(
-        env,
-        &quick_options_stuff.scale_hack_buttons,
-        quick_options_scale_hack,
-    );
-    if let Some(ref buttons) = quick_options_stuff.fullscreen_buttons {
-        update_fullscreen_buttons(env, buttons, quick_options_fullscreen);
-    }
-    update_orientation_buttons(
-        env,
-        &quick_options_stuff.orientation_buttons,
-        quick_options_orientation,
-    );
+    let mut quick_options_choices = OptionChoices::default();
+    let mut app_settings: Option<AppSettings> = None;
+    let mut app_settings_mode = false;
 
     let main_run_loop: id = msg_class![env; NSRunLoop mainRunLoop];
     // If an app is picked, this loop returns. If the user quits touchHLE, the
     // process exits.
     let app_path = loop {
         run_run_loop_single_iteration(env, main_run_loop);
+
+        if let (Some(icon_grid_stuff), Ok(apps)) = (icon_grid_stuff.as_mut(), apps.as_mut()) {
+            let text: id = msg![env; (icon_grid_stuff.search_field) text];
+            let text = if text == nil {
+                String::new()
+            } else {
+                ns_string::to_rust_string(env, text).into_owned()
+            };
+            if text != search_string {
+                search_string = text;
+                shown_apps = filter_and_sort_apps(apps, &search_string, sort_order);
+                paginate_icon_grid(icon_grid_stuff, shown_apps.len());
+                page_idx = 0;
+                update_icon_grid(env, icon_grid_stuff, apps, &shown_apps, page_idx);
+            }
+        }
+
         let host_obj = env.objc.borrow_mut::<AppPickerDelegateHostObject>(delegate);
         let icon_tapped = std::mem::take(&mut host_obj.icon_tapped);
         if icon_tapped != nil {
             match icon_grid_stuff.as_ref().unwrap().icon_map.get(&icon_tapped) {
+                Some(&TappedIcon::App(app_idx)) if app_settings_mode => {
+                    app_settings_mode = false;
+                    set_app_settings_mode_button(
+                        env,
+                        icon_grid_stuff.as_ref().unwrap(),
+                        app_settings_mode,
+                    );
+
+                    let app = &apps.as_ref().unwrap()[app_idx];
+                    let user_options = match options::read_user_options_file() {
+                        Ok(user_options) => user_options,
+                        Err(e) => {
+                            echo!("{}", e);
+                            continue;
+                        }
+                    };
+                    let (choices, other_args) = OptionChoices::from_options_string(
+                        &get_user_options(&user_options, &app.app_id).unwrap_or_default(),
+                    );
+                    update_option_choice_buttons(env, &quick_options_stuff, &choices);
+                    app_settings = Some(AppSettings {
+                        app_idx,
+                        choices,
+                        other_args,
+                    });
+                    let title = ns_string::from_rust_string(
+                        env,
+                        format!("Settings for {}", app.display_name),
+                    );
+                    () = msg![env; (quick_options_stuff.title_label) setText:title];
+                    () = msg![env; (quick_options_stuff.save_button) setHidden:false];
+                    () = msg![env; (quick_options_stuff.main_view) setHidden:false];
+                }
                 Some(&TappedIcon::App(app_idx)) => {
                     let app_path = &apps.as_ref().unwrap()[app_idx].path;
                     echo!("Picked: {}", app_path.display());
                     break app_path.clone();
                 }
-                Some(&TappedIcon::ChangePage(page_idx)) => {
+                Some(&TappedIcon::ChangePage(new_page_idx)) => {
+                    page_idx = new_page_idx;
                     update_icon_grid(
                         env,
                         icon_grid_stuff.as_mut().unwrap(),
                         apps.as_mut().unwrap(),
+                        &shown_apps,
                         page_idx,
                     );
                 }
@@ -543,99 +803,81 @@ fn show_app_picker_gui(
                 copyright_info_page_idx,
             );
         } else if std::mem::take(&mut host_obj.quick_options_show) {
+            update_option_choice_buttons(env, &quick_options_stuff, &quick_options_choices);
+            let title = ns_string::get_static_str(env, "Quick options (this launch only)");
+            () = msg![env; (quick_options_stuff.title_label) setText:title];
+            () = msg![env; (quick_options_stuff.save_button) setHidden:true];
             () = msg![env; (quick_options_stuff.main_view) setHidden:false];
         } else if std::mem::take(&mut host_obj.quick_options_hide) {
+            // Closing the per-app settings without saving discards them.
+            app_settings = None;
             () = msg![env; (quick_options_stuff.main_view) setHidden:true];
-        } else if std::mem::take(&mut host_obj.scale_hack_default) {
-            quick_options_scale_hack = None;
-            update_scale_hack_buttons(
-                env,
-                &quick_options_stuff.scale_hack_buttons,
-                quick_options_scale_hack,
-            );
-        } else if std::mem::take(&mut host_obj.scale_hack1) {
-            quick_options_scale_hack = Some(NonZeroU32::new(1).unwrap());
-            update_scale_hack_buttons(
-                env,
-                &quick_options_stuff.scale_hack_buttons,
-                quick_options_scale_hack,
-            );
-        } else if std::mem::take(&mut host_obj.scale_hack2) {
-            quick_options_scale_hack = Some(NonZeroU32::new(2).unwrap());
-            update_scale_hack_buttons(
-                env,
-                &quick_options_stuff.scale_hack_buttons,
-                quick_options_scale_hack,
-            );
-        } else if std::mem::take(&mut host_obj.scale_hack3) {
-            quick_options_scale_hack = Some(NonZeroU32::new(3).unwrap());
-            update_scale_hack_buttons(
-                env,
-                &quick_options_stuff.scale_hack_buttons,
-                quick_options_scale_hack,
-            );
-        } else if std::mem::take(&mut host_obj.scale_hack4) {
-            quick_options_scale_hack = Some(NonZeroU32::new(4).unwrap());
-            update_scale_hack_buttons(
-                env,
-                &quick_options_stuff.scale_hack_buttons,
-                quick_options_scale_hack,
-            );
-        } else if std::mem::take(&mut host_obj.orientation_default) {
-            quick_options_orientation = None;
-            update_orientation_buttons(
-                env,
-                &quick_options_stuff.orientation_buttons,
-                quick_options_orientation,
-            );
-        } else if std::mem::take(&mut host_obj.orientation_landscape_left) {
-            quick_options_orientation = Some(DeviceOrientation::LandscapeLeft);
-            update_orientation_buttons(
-                env,
-                &quick_options_stuff.orientation_buttons,
-                quick_options_orientation,
-            );
-        } else if std::mem::take(&mut host_obj.orientation_landscape_right) {
-            quick_options_orientation = Some(DeviceOrientation::LandscapeRight);
-            update_orientation_buttons(
-                env,
-                &quick_options_stuff.orientation_buttons,
-                quick_options_orientation,
-            );
-        } else if std::mem::take(&mut host_obj.fullscreen_default) {
-            quick_options_fullscreen = None;
-            update_fullscreen_buttons(
-                env,
-                &quick_options_stuff.fullscreen_buttons.unwrap(),
-                quick_options_fullscreen,
-            );
-        } else if std::mem::take(&mut host_obj.fullscreen_on) {
-            quick_options_fullscreen = Some(());
-            update_fullscreen_buttons(
-                env,
-                &quick_options_stuff.fullscreen_buttons.unwrap(),
-                quick_options_fullscreen,
-            );
+        } else if let Some(choice) = host_obj.option_choice.take() {
+            let choices = match app_settings {
+                Some(ref mut app_settings) => &mut app_settings.choices,
+                None => &mut quick_options_choices,
+            };
+            match choice {
+                OptionChoice::ScaleHack(value) => choices.scale_hack = value,
+                OptionChoice::Orientation(value) => choices.orientation = value,
+                OptionChoice::Fullscreen(value) => choices.fullscreen = value,
+                OptionChoice::Rumble(value) => choices.rumble = value,
+            }
+            update_option_choice_buttons(env, &quick_options_stuff, choices);
+        } else if std::mem::take(&mut host_obj.app_settings_save) {
+            let Some(AppSettings {
+                app_idx,
+                choices,
+                mut other_args,
+            }) = app_settings.take()
+            else {
+                continue;
+            };
+            let app = &mut apps.as_mut().unwrap()[app_idx];
+            other_args.extend(choices.to_args());
+            let app_options = other_args.join(" ");
+            match options::save_user_options(&app.app_id, &app_options) {
+                Ok(()) => {
+                    echo!(
+                        "Saved options for {} to {}: {:?}",
+                        app.app_id,
+                        paths::USER_OPTIONS_FILE,
+                        app_options
+                    );
+                    app.has_options = !app_options.is_empty();
+                    // Update the marker in the app's label.
+                    app.label_ns_string = None;
+                    update_icon_grid(
+                        env,
+                        icon_grid_stuff.as_mut().unwrap(),
+                        apps.as_mut().unwrap(),
+                        &shown_apps,
+                        page_idx,
+                    );
+                }
+                Err(e) => echo!("{}", e),
+            }
+            () = msg![env; (quick_options_stuff.main_view) setHidden:true];
+        } else if std::mem::take(&mut host_obj.sort_order_toggle) {
+            sort_order = match sort_order {
+                SortOrder::Name => SortOrder::LastPlayed,
+                SortOrder::LastPlayed => SortOrder::Name,
+            };
+            let icon_grid_stuff = icon_grid_stuff.as_mut().unwrap();
+            set_sort_order_button(env, icon_grid_stuff, sort_order);
+            let apps = apps.as_mut().unwrap();
+            shown_apps = filter_and_sort_apps(apps, &search_string, sort_order);
+            paginate_icon_grid(icon_grid_stuff, shown_apps.len());
+            page_idx = 0;
+            update_icon_grid(env, icon_grid_stuff, apps, &shown_apps, page_idx);
+        } else if std::mem::take(&mut host_obj.app_settings_mode_toggle) {
+            app_settings_mode = !app_settings_mode;
+            set_app_settings_mode_button(env, icon_grid_stuff.as_ref().unwrap(), app_settings_mode);
         }
     };
 
     // Apply user-specified overrides
-    if let Some(scale_hack) = quick_options_scale_hack {
-        option_args.push(format!("--scale-hack={}", scale_hack.get()));
-    }
-    if let Some(orientation) = quick_options_orientation {
-        option_args.push(
-            match orientation {
-                DeviceOrientation::LandscapeLeft => "--landscape-left",
-                DeviceOrientation::LandscapeRight => "--landscape-right",
-                _ => todo!(),
-            }
-            .to_string(),
-        );
-    }
-    if let Some(()) = quick_options_fullscreen {
-        option_args.push("--fullscreen".to_string());
-    }
+    option_args.extend(quick_options_choices.to_args());
 
     // Return the environment so some parts of it can be salvaged.
     Ok((app_path, environment))
@@ -652,6 +894,10 @@ enum TappedIcon {
 }
 
 struct IconGridStuff {
+    /// `UITextField*`
+    search_field: id,
+    sort_order_button: id,
+    app_settings_mode_button: id,
     icon_buttons_and_labels: Vec<(id, id)>,
     placeholder_icon: Option<id>,
     prev_icon: Option<id>,
@@ -665,7 +911,6 @@ fn make_icon_grid(
     delegate: id,
     main_view: id,
     app_frame: CGRect,
-    total_app_count: usize,
 ) -> IconGridStuff {
     let num_cols = 4;
     let num_cols_f = num_cols as CGFloat;
@@ -675,13 +920,88 @@ fn make_icon_grid(
         height: 13.0,
     };
     let icon_gap_x: CGFloat = 19.0;
-    let icon_gap_y: CGFloat = 4.0 + label_size.height + 14.0;
+    let icon_gap_y: CGFloat = 4.0 + label_size.height + 7.0;
     let icon_grid_width = (ICON_SIZE.width * num_cols_f) + icon_gap_x * (num_cols_f - 1.0);
     let icon_grid_origin = CGPoint {
         x: (app_frame.size.width - icon_grid_width) / 2.0,
-        y: 12.0,
+        y: 40.0,
     };
 
+    // Search field and buttons above the icon grid
+
+    let margin = 8.0;
+    let bar_height = 26.0;
+    let button_width = 56.0;
+    let search_label_width = 44.0;
+
+    let label_frame = CGRect {
+        origin: CGPoint {
+            x: margin,
+            y: margin,
+        },
+        size: CGSize {
+            width: search_label_width,
+            height: bar_height,
+        },
+    };
+    let label: id = msg_class![env; UILabel alloc];
+    let label: id = msg![env; label initWithFrame:label_frame];
+    let text = ns_string::get_static_str(env, "Search:");
+    () = msg![env; label setText:text];
+    let font: id = msg_class![env; UIFont systemFontOfSize:(12.0 as CGFloat)];
+    () = msg![env; label setFont:font];
+    let text_color: id = msg_class![env; UIColor lightGrayColor];
+    () = msg![env; label setTextColor:text_color];
+    let bg_color: id = msg_class![env; UIColor clearColor];
+    () = msg![env; label setBackgroundColor:bg_color];
+    () = msg![env; main_view addSubview:label];
+
+    let search_field_frame = CGRect {
+        origin: CGPoint {
+            x: margin * 2.0 + search_label_width,
+            y: margin,
+        },
+        size: CGSize {
+            width: app_frame.size.width - margin * 5.0 - search_label_width - button_width * 2.0,
+            height: bar_height,
+        },
+    };
+    let search_field: id = msg_class![env; UITextField alloc];
+    let search_field: id = msg![env; search_field initWithFrame:search_field_frame];
+    let font: id = msg_class![env; UIFont systemFontOfSize:(14.0 as CGFloat)];
+    () = msg![env; search_field setFont:font];
+    () = msg![env; search_field setDelegate:delegate];
+    () = msg![env; main_view addSubview:search_field];
+
+    let mut button_frame = CGRect {
+        origin: CGPoint {
+            x: search_field_frame.origin.x + search_field_frame.size.width + margin,
+            y: margin,
+        },
+        size: CGSize {
+            width: button_width,
+            height: bar_height,
+        },
+    };
+    let mut bar_buttons = Vec::new();
+    for selector in ["sortOrderToggle", "appSettingsModeToggle"] {
+        let button: id = msg_class![env; UIButton buttonWithType:UIButtonTypeRoundedRect];
+        () = msg![env; button setFrame:button_frame];
+        // FIXME: manually calling layoutSubviews shouldn't be needed?
+        () = msg![env; button layoutSubviews];
+        let label: id = msg![env; button titleLabel];
+        let font: id = msg_class![env; UIFont systemFontOfSize:(12.0 as CGFloat)];
+        () = msg![env; label setFont:font];
+        let selector = env.objc.lookup_selector(selector).unwrap();
+        () = msg![env; button addTarget:delegate
+                                 action:selector
+                       forControlEvents:UIControlEventTouchUpInside];
+        () = msg![env; main_view addSubview:button];
+
+        button_frame.origin.x += button_width + margin;
+        bar_buttons.push(button);
+    }
+
     let icon_tapped_sel = env.objc.lookup_selector("iconTapped:").unwrap();
 
     let mut icon_buttons_and_labels = Vec::new();
@@ -729,11 +1049,54 @@ fn make_icon_grid(
         icon_buttons_and_labels.push((icon_button, label));
     }
 
+    let icon_grid_stuff = IconGridStuff {
+        search_field,
+        sort_order_button: bar_buttons[0],
+        app_settings_mode_button: bar_buttons[1],
+        icon_buttons_and_labels,
+        placeholder_icon: None,
+        prev_icon: None,
+        next_icon: None,
+        pages: Vec::new(),
+        icon_map: HashMap::new(),
+    };
+    set_sort_order_button(env, &icon_grid_stuff, SortOrder::Name);
+    set_app_settings_mode_button(env, &icon_grid_stuff, false);
+    icon_grid_stuff
+}
+
+fn set_sort_order_button(env: &mut Environment, icon_grid_stuff: &IconGridStuff, value: SortOrder) {
+    let text = ns_string::get_static_str(
+        env,
+        match value {
+            SortOrder::Name => "A–Z",
+            SortOrder::LastPlayed => "Recent",
+        },
+    );
+    let button = icon_grid_stuff.sort_order_button;
+    () = msg![env; button setTitle:text forState:UIControlStateNormal];
+}
+
+/// In app settings mode, tapping an icon opens the per-app settings screen
+/// instead of launching the app.
+fn set_app_settings_mode_button(
+    env: &mut Environment,
+    icon_grid_stuff: &IconGridStuff,
+    value: bool,
+) {
+    let text = ns_string::get_static_str(env, if value { "Cancel" } else { "Settings" });
+    let button = icon_grid_stuff.app_settings_mode_button;
+    () = msg![env; button setTitle:text forState:UIControlStateNormal];
+}
+
+/// Split the apps being shown into pages of icons.
+fn paginate_icon_grid(icon_grid_stuff: &mut IconGridStuff, total_app_count: usize) {
     // TODO: Use UIScrollView pagination and UIPageControl once available.
-    let mut pages = Vec::new();
+    let pages = &mut icon_grid_stuff.pages;
+    pages.clear();
     let mut start = 0;
     while start < total_app_count {
-        let mut end = start + icon_buttons_and_labels.len();
+        let mut end = start + icon_grid_stuff.icon_buttons_and_labels.len();
         if start > 0 {
             end -= 1; // one icon space taken by "previous" button
         }
@@ -745,14 +1108,9 @@ fn make_icon_grid(
         pages.push(start..end);
         start = end;
     }
-
-    IconGridStuff {
-        icon_buttons_and_labels,
-        placeholder_icon: None,
-        prev_icon: None,
-        next_icon: None,
-        pages,
-        icon_map: HashMap::new(),
+    // If no apps match the search, there is still an (empty) page.
+    if pages.is_empty() {
+        pages.push(0..0);
     }
 }
 
@@ -820,13 +1178,14 @@ fn update_icon_grid(
     env: &mut Environment,
     icon_grid_stuff: &mut IconGridStuff,
     apps: &mut [AppInfo],
+    shown_apps: &[usize],
     page_idx: usize,
 ) {
     icon_grid_stuff.icon_map.clear();
 
-    let app_idx_range = icon_grid_stuff.pages[page_idx].clone();
+    let shown_apps_range = icon_grid_stuff.pages[page_idx].clone();
     let have_prev_icon = page_idx != 0;
-    let have_next_icon = app_idx_range.end != apps.len();
+    let have_next_icon = shown_apps_range.end != shown_apps.len();
 
     let mut icon_iter = icon_grid_stuff.icon_buttons_and_labels.iter();
 
@@ -842,15 +1201,22 @@ fn update_icon_grid(
             .insert(icon_button, TappedIcon::ChangePage(page_idx - 1));
     }
 
-    for app_idx in app_idx_range.clone() {
+    for &app_idx in &shown_apps[shown_apps_range] {
         let app = &mut apps[app_idx];
 
         let &(icon_button, label) = icon_iter.next().unwrap();
 
-        if let Some(icon) = app.icon.take() {
-            let image = cg_image::from_image(env, icon);
-            let image: id = msg_class![env; UIImage imageWithCGImage:image];
-            app.icon_ui_image = Some(image);
+        if let Some((bundle, fs)) = app.bundle_and_fs.take() {
+            match bundle.load_icon(&fs) {
+                Ok(icon) => {
+                    let image = cg_image::from_image(env, icon);
+                    let image: id = msg_class![env; UIImage imageWithCGImage:image];
+                    app.icon_ui_image = Some(image);
+                }
+                Err(e) => {
+                    log!("Warning: couldn't load icon for app bundle {}: {} (displaying placeholder instead)", app.path.display(), e);
+                }
+            }
         }
 
         let image = app.icon_ui_image.unwrap_or_else(|| {
@@ -860,9 +1226,15 @@ fn update_icon_grid(
         });
         () = msg![env; icon_button setImage:image forState:UIControlStateNormal];
 
-        let text = *app
-            .display_name_ns_string
-            .get_or_insert_with(|| ns_string::from_rust_string(env, app.display_name.clone()));
+        let text = *app.label_ns_string.get_or_insert_with(|| {
+            let text = format!(
+                "{}{}{}",
+                if app.has_data { "• " } else { "" },
+                app.display_name,
+                if app.has_options { "*" } else { "" },
+            );
+            ns_string::from_rust_string(env, text)
+        });
         () = msg![env; label setText:text];
 
         icon_grid_stuff
@@ -1135,11 +1507,16 @@ fn change_copyright_page(
     () = msg![env; next_page_button setHidden:(Some(page_idx) == *last_page_idx)];
 }
 
+/// This screen is shared between quick options and per-app settings.
 struct QuickOptionsStuff {
     main_view: id,
+    title_label: id,
     scale_hack_buttons: [id; 5],
     orientation_buttons: [id; 3],
     fullscreen_buttons: Option<[id; 2]>,
+    rumble_buttons: [id; 4],
+    /// Only shown for per-app settings.
+    save_button: id,
 }
 
 fn setup_quick_options(
@@ -1167,6 +1544,23 @@ fn setup_quick_options(
 
     let divider = 40.0;
 
+    // Title
+    let title_label = {
+        let frame = CGRect {
+            origin: CGPoint { x: 10.0, y: 10.0 },
+            size: CGSize {
+                width: main_frame.size.width - 50.0,
+                height: 20.0,
+            },
+        };
+        let label: id = msg_class![env; UILabel alloc];
+        let label: id = msg![env; label initWithFrame:frame];
+        let font: id = msg_class![env; UIFont boldSystemFontOfSize:(16.0 as CGFloat)];
+        () = msg![env; label setFont:font];
+        () = msg![env; main_view addSubview:label];
+        label
+    };
+
     // Close button
     {
         let button_frame = CGRect {
@@ -1217,6 +1611,14 @@ fn setup_quick_options(
             ("←", "orientationLandscapeLeft"),
             ("→", "orientationLandscapeRight"),
         ]),
+        RowKind::Label("Controller rumble"),
+        RowKind::Buttons(&[
+            ("Default", "rumbleDefault"),
+            ("Off", "rumbleOff"),
+            ("Low", "rumbleLow"),
+            ("High", "rumbleHigh"),
+        ]),
+        RowKind::Buttons(&[("Save for this app", "appSettingsSave")]),
         // ---- (divider for stuff skipped below)
         RowKind::Label("Fullscreen"),
         RowKind::Buttons(&[("Default", "fullscreenDefault"), ("On", "fullscreenOn")]),
@@ -1271,8 +1673,55 @@ fn setup_quick_options(
 
     QuickOptionsStuff {
         main_view,
+        title_label,
         scale_hack_buttons: button_rows[0][..].try_into().unwrap(),
         orientation_buttons: button_rows[1][..].try_into().unwrap(),
-        fullscreen_buttons: button_rows.get(2).map(|r| r[..].try_into().unwrap()),
+        rumble_buttons: button_rows[2][..].try_into().unwrap(),
+        save_button: button_rows[3][0],
+        fullscreen_buttons: button_rows.get(4).map(|r| r[..].try_into().unwrap()),
+    }
+}
+
+fn update_option_choice_buttons(
+    env: &mut Environment,
+    quick_options_stuff: &QuickOptionsStuff,
+    choices: &OptionChoices,
+) {
+    fn update_buttons(env: &mut Environment, buttons: &[id], selected_idx: usize) {
+        for (idx, &button) in buttons.iter().enumerate() {
+            let color: id = if idx == selected_idx {
+                msg_class![env; UIColor magentaColor]
+            } else {
+                msg_class![env; UIColor grayColor]
+            };
+            () = msg![env; button setBackgroundColor:color];
+        }
+    }
+
+    update_buttons(
+        env,
+        &quick_options_stuff.scale_hack_buttons,
+        choices.scale_hack.map_or(0, |v| v.get() as usize),
+    );
+    update_buttons(
+        env,
+        &quick_options_stuff.orientation_buttons,
+        choices.orientation.map_or(0, |v| match v {
+            DeviceOrientation::LandscapeLeft => 1,
+            DeviceOrientation::LandscapeRight => 2,
+            _ => panic!(),
+        }),
+    );
+    if let Some(ref buttons) = quick_options_stuff.fullscreen_buttons {
+        update_buttons(env, buttons, choices.fullscreen.map_or(0, |_| 1));
     }
+    update_buttons(
+        env,
+        &quick_options_stuff.rumble_buttons,
+        choices.rumble.map_or(0, |v| match v {
+            Rumble::Off => 1,
+            Rumble::Low => 2,
+            Rumble::High => 3,
+        }),
+    );
 }
@@ -15,6 +15,7 @@
 mod aac;
 mod ima4;
 
+pub use aac::probe_mp4_duration;
 pub use ima4::decode_ima4;
 use touchHLE_dr_mp3_wrapper as dr_mp3;
 pub use touchHLE_openal_soft_wrapper as openal;
@@ -247,6 +248,142 @@ impl AudioFile {
         self.packet_size_fixed() // variable size not implemented
     }
 
+    /// The total number of frames (one sample per channel) in the file.
+    pub fn frame_count(&self) -> u64 {
+        match self.0 {
+            AudioFileInner::Wave(ref wave_reader) => wave_reader.duration().into(),
+            AudioFileInner::Caf(_) => {
+                self.packet_count() * u64::from(self.audio_description().frames_per_packet)
+            }
+            AudioFileInner::Mp3(dr_mp3::Mp3DecodedToPcm {
+                ref bytes,
+                channels,
+                ..
+            })
+            | AudioFileInner::Aac(aac::AacDecodedToPcm {
+                ref bytes,
+                channels,
+                ..
+            }) => bytes.len() as u64 / (2 * u64::from(channels)),
+        }
+    }
+
+    /// Read up to `frame_count` frames starting at frame `start_frame`,
+    /// decoding them if necessary, and return them as interleaved signed 16-bit
+    /// samples. Fewer frames are returned at the end of the file.
+    ///
+    /// This is for consumers like Extended Audio File Services that need
+    /// decoded audio regardless of the file's own format.
+    pub fn read_frames_i16(&mut self, start_frame: u64, frame_count: u64) -> Result<Vec<i16>, ()> {
+        let AudioDescription {
+            format,
+            bytes_per_packet,
+            frames_per_packet,
+            channels_per_frame,
+            bits_per_channel,
+            ..
+        } = self.audio_description();
+        let total_frames = self.frame_count();
+        let start_frame = start_frame.min(total_frames);
+        let frame_count = frame_count.min(total_frames - start_frame);
+        let channels = u64::from(channels_per_frame);
+
+        if let AudioFileInner::Wave(ref mut wave_reader) = self.0 {
+            wave_reader
+                .seek(start_frame.try_into().unwrap())
+                .map_err(|_| ())?;
+            let sample_count: usize = (frame_count * channels).try_into().unwrap();
+            // hound gives samples at their original width, so they have to be
+            // scaled to 16 bits.
+            let bits_per_channel = match bits_per_channel {
+                1..=32 => bits_per_channel,
+                _ => return Err(()),
+            };
+            let convert = move |sample: i32| {
+                if bits_per_channel <= 16 {
+                    (sample << (16 - bits_per_channel)) as i16
+                } else {
+                    (sample >> (bits_per_channel - 16)) as i16
+                }
+            };
+            return wave_reader
+                .samples::<i32>()
+                .take(sample_count)
+                .map(|sample| sample.map(convert).map_err(|_| ()))
+                .collect();
+        }
+
+        match format {
+            AudioFormat::AppleIma4 => {
+                // Each packet contains 64 frames for one channel, and the
+                // channels' packets are interleaved.
+                let frames_per_packet = u64::from(frames_per_packet);
+                let first_packet = start_frame / frames_per_packet;
+                let last_packet = (start_frame + frame_count).div_ceil(frames_per_packet);
+                let mut bytes = vec![
+                    0u8;
+                    ((last_packet - first_packet) * u64::from(bytes_per_packet))
+                        as usize
+                ];
+                let bytes_read =
+                    self.read_bytes(first_packet * u64::from(bytes_per_packet), &mut bytes)?;
+                bytes.truncate(bytes_read);
+
+                let mut samples = Vec::with_capacity(bytes.len() / 34 * 64);
+                for packet in bytes.chunks_exact(bytes_per_packet as usize) {
+                    let decoded: Vec<[i16; 64]> = packet
+                        .chunks_exact(34)
+                        .map(|chunk| decode_ima4(chunk.try_into().unwrap()))
+                        .collect();
+                    for i in 0..64 {
+                        for channel in &decoded {
+                            samples.push(channel[i]);
+                        }
+                    }
+                }
+                let skip = ((start_frame - first_packet * frames_per_packet) * channels) as usize;
+                let take = (frame_count * channels) as usize;
+                Ok(samples.into_iter().skip(skip).take(take).collect())
+            }
+            AudioFormat::LinearPcm {
+                is_float,
+                is_little_endian,
+            } => {
+                let bytes_per_sample = u64::from(bits_per_channel / 8);
+                let mut bytes = vec![0u8; (frame_count * channels * bytes_per_sample) as usize];
+                let bytes_read =
+                    self.read_bytes(start_frame * channels * bytes_per_sample, &mut bytes)?;
+                bytes.truncate(bytes_read);
+
+                let samples = bytes
+                    .chunks_exact(bytes_per_sample as usize)
+                    .map(|sample| match (is_float, bits_per_channel) {
+                        (false, 8) => i16::from(sample[0] as i8) << 8,
+                        (false, 16) if is_little_endian => {
+                            i16::from_le_bytes([sample[0], sample[1]])
+                        }
+                        (false, 16) => i16::from_be_bytes([sample[0], sample[1]]),
+                        (true, 32) => {
+                            let sample: [u8; 4] = sample.try_into().unwrap();
+                            let sample = if is_little_endian {
+                                f32::from_le_bytes(sample)
+                            } else {
+                                f32::from_be_bytes(sample)
+                            };
+                            (sample.clamp(-1.0, 1.0) * 32767.0) as i16
+                        }
+                        _ => unimplemented!(
+                            "{}-bit {} PCM",
+                            bits_per_channel,
+                            if is_float { "float" } else { "integer" }
+                        ),
+                    })
+                    .collect();
+                Ok(samples)
+            }
+        }
+    }
+
     /// Read `buffer.len()` bytes of audio data from byte offset `offset`.
     /// Returns the number of bytes read.
     pub fn read_bytes(&mut self, offset: u64, buffer: &mut [u8]) -> Result<usize, ()> {
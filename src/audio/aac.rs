@@ -3,13 +3,15 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
-//! Quick-and-dirty AAC-LC decoding.
+//! Quick-and-dirty AAC-LC decoding, and finding out how long MPEG-4 movies
+//! are.
 //!
 //! This should be the only module in touchHLE that makes use of [symphonia].
 //! Only the LC profile and MPEG-4 container format are supported (see feature
 //! list in Cargo.toml).
 
 use std::io::Cursor;
+use std::time::Duration;
 use symphonia::core::audio::{RawSampleBuffer, SignalSpec};
 use symphonia::core::codecs::CODEC_TYPE_AAC;
 use symphonia::core::io::MediaSourceStream;
@@ -46,6 +48,10 @@ pub fn decode_aac_to_pcm(file: Cursor<Vec<u8>>) -> Result<AacDecodedToPcm, ()> {
         .find(|t| t.codec_params.codec == CODEC_TYPE_AAC)
         .ok_or(())?;
     let track_id = track.id;
+    // Encoder delay ("priming") and padding, if the file says what they are.
+    // These must be trimmed for music to loop without a gap or click.
+    let delay = track.codec_params.delay.unwrap_or(0);
+    let padding = track.codec_params.padding.unwrap_or(0);
 
     // Not sure why this would fail, maybe an unusual AAC track.
     let mut decoder = symphonia::default::get_codecs()
@@ -91,10 +97,44 @@ pub fn decode_aac_to_pcm(file: Cursor<Vec<u8>>) -> Result<AacDecodedToPcm, ()> {
         }
     }
     let signal_spec = signal_spec.ok_or(())?;
+    let channels: u32 = signal_spec.channels.count().try_into().unwrap();
+
+    let bytes_per_frame = 2 * channels as usize;
+    let trim_start = (delay as usize * bytes_per_frame).min(out_pcm.len());
+    let trim_end = (padding as usize * bytes_per_frame).min(out_pcm.len() - trim_start);
+    out_pcm.truncate(out_pcm.len() - trim_end);
+    out_pcm.drain(..trim_start);
 
     Ok(AacDecodedToPcm {
         bytes: out_pcm,
         sample_rate: signal_spec.rate,
-        channels: signal_spec.channels.count().try_into().unwrap(),
+        channels,
     })
 }
+
+/// Get the duration of an MPEG-4 file (e.g. a movie), which is that of its
+/// longest track. Returns [None] if the file can't be parsed or no track says
+/// how long it is.
+pub fn probe_mp4_duration(file: Cursor<Vec<u8>>) -> Option<Duration> {
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let probed = symphonia::default::get_probe()
+        .format(
+            &Default::default(),
+            mss,
+            &Default::default(),
+            &Default::default(),
+        )
+        .ok()?;
+    probed
+        .format
+        .tracks()
+        .iter()
+        .filter_map(|track| {
+            let time = track
+                .codec_params
+                .time_base?
+                .calc_time(track.codec_params.n_frames?);
+            Some(Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac))
+        })
+        .max()
+}
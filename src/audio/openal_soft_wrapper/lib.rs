@@ -49,6 +49,13 @@ pub const ALC_FALSE: ALCboolean = 0;
 pub const ALC_TRUE: ALCboolean = 1;
 
 pub const ALC_DEVICE_SPECIFIER: ALCenum = 0x1005;
+pub const ALC_FREQUENCY: ALCenum = 0x1007;
+
+// ALC_SOFT_loopback extension
+pub const ALC_FORMAT_CHANNELS_SOFT: ALCenum = 0x1990;
+pub const ALC_FORMAT_TYPE_SOFT: ALCenum = 0x1991;
+pub const ALC_SHORT_SOFT: ALCenum = 0x1402;
+pub const ALC_STEREO_SOFT: ALCenum = 0x1501;
 
 extern "C" {
     pub fn alcOpenDevice(devicename: *const ALCchar) -> *mut ALCdevice;
@@ -67,6 +74,20 @@ extern "C" {
     pub fn alcGetError(device: *mut ALCdevice) -> ALCenum;
 
     pub fn alcGetString(device: *mut ALCdevice, param: ALCenum) -> *const ALCchar;
+
+    // ALC_SOFT_pause_device extension
+    pub fn alcDevicePauseSOFT(device: *mut ALCdevice);
+    pub fn alcDeviceResumeSOFT(device: *mut ALCdevice);
+
+    // ALC_SOFT_loopback extension
+    pub fn alcLoopbackOpenDeviceSOFT(deviceName: *const ALCchar) -> *mut ALCdevice;
+    pub fn alcIsRenderFormatSupportedSOFT(
+        device: *mut ALCdevice,
+        freq: ALCsizei,
+        channels: ALCenum,
+        type_: ALCenum,
+    ) -> ALCboolean;
+    pub fn alcRenderSamplesSOFT(device: *mut ALCdevice, buffer: *mut ALCvoid, samples: ALCsizei);
 }
 
 // === al.h ===
@@ -93,6 +114,8 @@ use al_types::*;
 
 pub const AL_NO_ERROR: ALenum = 0;
 
+pub const AL_PITCH: ALenum = 0x1003;
+pub const AL_GAIN: ALenum = 0x100A;
 pub const AL_MAX_GAIN: ALenum = 0x100E;
 
 pub const AL_SOURCE_STATE: ALenum = 0x1010;
@@ -184,6 +207,11 @@ extern "C" {
     pub fn alSourceStop(source: ALuint);
     pub fn alSourceRewind(source: ALuint);
 
+    pub fn alSourcePlayv(nsources: ALsizei, sources: *const ALuint);
+    pub fn alSourcePausev(nsources: ALsizei, sources: *const ALuint);
+    pub fn alSourceStopv(nsources: ALsizei, sources: *const ALuint);
+    pub fn alSourceRewindv(nsources: ALsizei, sources: *const ALuint);
+
     pub fn alSourceQueueBuffers(source: ALuint, nb: ALsizei, buffers: *const ALuint);
     pub fn alSourceUnqueueBuffers(source: ALuint, nb: ALsizei, buffers: *mut ALuint);
 
@@ -198,6 +226,10 @@ extern "C" {
         samplerate: ALsizei,
     );
 
+    pub fn alGetBufferf(buffer: ALuint, param: ALenum, value: *mut ALfloat);
+    pub fn alGetBufferi(buffer: ALuint, param: ALenum, value: *mut ALint);
+    pub fn alGetBufferiv(buffer: ALuint, param: ALenum, values: *mut ALint);
+
     pub fn alDopplerFactor(dopplerFactor: ALfloat);
     pub fn alDopplerVelocity(dopplerVelocity: ALfloat);
     pub fn alSpeedOfSound(speed: ALfloat);
@@ -0,0 +1,232 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Scripted actions for unattended runs (`--input-script=`, `--exit-after=` and
+//! `--screenshot-at=`). These are mostly meant to be used with `--headless`,
+//! e.g. for automated regression testing.
+//!
+//! Times are measured on the guest's clock (see [crate::environment::Clock])
+//! from when the app was launched, so they are affected by fast-forwarding and
+//! pausing.
+//!
+//! The input script is a text file with one action per line, for example:
+//!
+//! ```text
+//! # Skip the intro
+//! 5s tap 160 240
+//! # Drag across the screen
+//! 7.5s down 20 240
+//! 8s move 160 240
+//! 8.5s up 300 240
+//! ```
+//!
+//! Co-ordinates are in points on the portrait screen, regardless of the
+//! device's orientation, with (0, 0) in the top-left corner. All touches use
+//! the same simulated finger.
+
+use crate::window::{Coords, Event, FingerId};
+use crate::Environment;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How long the finger stays down for a `tap`.
+const TAP_DURATION: Duration = Duration::from_millis(100);
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    TouchDown(Coords),
+    TouchMove(Coords),
+    TouchUp(Coords),
+    Screenshot(PathBuf),
+    Quit,
+}
+
+pub struct Automation {
+    /// Guest time when the app was launched.
+    start: Instant,
+    /// Actions that haven't happened yet, sorted by time since [Self::start].
+    actions: VecDeque<(Duration, Action)>,
+}
+
+impl Automation {
+    /// Set up the scripted actions requested by the options, if there are any.
+    pub fn new(options: &crate::options::Options, start: Instant) -> Result<Option<Self>, String> {
+        let mut actions = Vec::new();
+        if let Some(ref path) = options.input_script {
+            actions.extend(read_script(path)?);
+        }
+        for (time, path) in &options.screenshot_at {
+            actions.push((*time, Action::Screenshot(path.clone())));
+        }
+        if let Some(time) = options.exit_after {
+            actions.push((time, Action::Quit));
+        }
+        if actions.is_empty() {
+            return Ok(None);
+        }
+        // This is a stable sort, so simultaneous actions keep their order.
+        actions.sort_by_key(|&(time, _)| time);
+        Ok(Some(Automation {
+            start,
+            actions: actions.into(),
+        }))
+    }
+}
+
+/// Parse a duration like `30s`, `500ms`, `2m` or `1.5` (seconds).
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let (number, scale) = if let Some(number) = value.strip_suffix("ms") {
+        (number, 0.001)
+    } else if let Some(number) = value.strip_suffix('s') {
+        (number, 1.0)
+    } else if let Some(number) = value.strip_suffix('m') {
+        (number, 60.0)
+    } else {
+        (value, 1.0)
+    };
+    let number: f64 = number.parse().ok()?;
+    Duration::try_from_secs_f64(number * scale).ok()
+}
+
+fn read_script(path: &Path) -> Result<Vec<(Duration, Action)>, String> {
+    let script = std::fs::read_to_string(path)
+        .map_err(|e| format!("Could not read input script {}: {}", path.display(), e))?;
+    parse_script(&script).map_err(|e| format!("Error in input script {}: {}", path.display(), e))
+}
+
+fn parse_script(script: &str) -> Result<Vec<(Duration, Action)>, String> {
+    let mut actions = Vec::new();
+    for (line_idx, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message: &str| format!("line {}: {}", line_idx + 1, message);
+
+        let parts: Vec<&str> = line.split_ascii_whitespace().collect();
+        let [time, action, x, y] = parts[..] else {
+            return Err(error("expected a time, an action and co-ordinates"));
+        };
+        let time = parse_duration(time).ok_or_else(|| error("invalid time"))?;
+        let x: f32 = x.parse().map_err(|_| error("invalid X co-ordinate"))?;
+        let y: f32 = y.parse().map_err(|_| error("invalid Y co-ordinate"))?;
+        let coords = (x, y);
+        match action {
+            "tap" => {
+                actions.push((time, Action::TouchDown(coords)));
+                actions.push((time + TAP_DURATION, Action::TouchUp(coords)));
+            }
+            "down" => actions.push((time, Action::TouchDown(coords))),
+            "move" => actions.push((time, Action::TouchMove(coords))),
+            "up" => actions.push((time, Action::TouchUp(coords))),
+            _ => return Err(error(&format!("unknown action {:?}", action))),
+        }
+    }
+    Ok(actions)
+}
+
+/// Perform the scripted actions that are due. Returns the time the next one
+/// is due, if any. This should be called regularly, e.g. by `NSRunLoop`.
+pub fn handle_automation(env: &mut Environment) -> Option<Instant> {
+    let automation = env.automation.as_mut()?;
+    let elapsed = env.clock.now().saturating_duration_since(automation.start);
+
+    let mut due = Vec::new();
+    while let Some(&(time, _)) = automation.actions.front() {
+        if time > elapsed {
+            break;
+        }
+        due.push(automation.actions.pop_front().unwrap().1);
+    }
+    let next_due = automation
+        .actions
+        .front()
+        .map(|&(time, _)| automation.start + time);
+
+    for action in due {
+        log_dbg!("Scripted action: {:?}", action);
+        let event = match action {
+            Action::TouchDown(coords) => {
+                Event::TouchesDown(HashMap::from([(FingerId::Script, coords)]))
+            }
+            Action::TouchMove(coords) => {
+                Event::TouchesMove(HashMap::from([(FingerId::Script, coords)]))
+            }
+            Action::TouchUp(coords) => {
+                Event::TouchesUp(HashMap::from([(FingerId::Script, coords)]))
+            }
+            Action::Screenshot(path) => {
+                crate::recording::request_screenshot_to(env, path);
+                continue;
+            }
+            Action::Quit => Event::ScriptedQuit,
+        };
+        // Going through the event queue means these are handled the same way
+        // as real input.
+        let Some(window) = env.window.as_mut() else {
+            log!("Warning: Ignoring scripted action because there is no window.");
+            continue;
+        };
+        window.push_event(event);
+    }
+
+    next_due
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("2m"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1.5"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("-1s"), None);
+        assert_eq!(parse_duration("s"), None);
+        assert_eq!(parse_duration("10x"), None);
+    }
+
+    #[test]
+    fn script() {
+        let actions = parse_script(
+            "# comment\n\
+             \n\
+             1s tap 10 20\n\
+             2.5s down 1.5 2\n\
+             3s move 3 4\n\
+             3s up 3 4\n",
+        )
+        .unwrap();
+        assert_eq!(
+            actions,
+            [
+                (Duration::from_secs(1), Action::TouchDown((10.0, 20.0))),
+                (Duration::from_millis(1100), Action::TouchUp((10.0, 20.0))),
+                (Duration::from_millis(2500), Action::TouchDown((1.5, 2.0))),
+                (Duration::from_secs(3), Action::TouchMove((3.0, 4.0))),
+                (Duration::from_secs(3), Action::TouchUp((3.0, 4.0))),
+            ]
+        );
+    }
+
+    #[test]
+    fn script_errors() {
+        assert_eq!(
+            parse_script("1s tap 10"),
+            Err("line 1: expected a time, an action and co-ordinates".to_string())
+        );
+        assert_eq!(
+            parse_script("\n1s poke 10 20"),
+            Err("line 2: unknown action \"poke\"".to_string())
+        );
+        assert_eq!(
+            parse_script("soon tap 10 20"),
+            Err("line 1: invalid time".to_string())
+        );
+    }
+}
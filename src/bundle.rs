@@ -66,6 +66,11 @@ impl Bundle {
         }
     }
 
+    /// Whether this is a fake bundle (see [Bundle::new_fake_bundle]).
+    pub fn is_fake(&self) -> bool {
+        self.path.as_str().is_empty()
+    }
+
     pub fn bundle_path(&self) -> &GuestPath {
         &self.path
     }
@@ -99,8 +104,14 @@ impl Bundle {
         self.path.file_name().unwrap().strip_suffix(".app").unwrap()
     }
 
+    /// Display name for the bundle. Like iPhone OS, this falls back to the
+    /// bundle name if the Info.plist doesn't specify one.
     pub fn display_name(&self) -> &str {
-        self.plist["CFBundleDisplayName"].as_string().unwrap()
+        self.plist
+            .get("CFBundleDisplayName")
+            .and_then(|name| name.as_string())
+            .or_else(|| self.canonical_bundle_name())
+            .unwrap_or_else(|| self.bundle_name())
     }
 
     pub fn minimum_os_version(&self) -> Option<&str> {
@@ -158,6 +169,36 @@ impl Bundle {
         Ok(image)
     }
 
+    /// Paths of the font files the app bundles for its own use
+    /// (`UIAppFonts`).
+    pub fn app_font_paths(&self) -> Vec<GuestPathBuf> {
+        self.plist
+            .get("UIAppFonts")
+            .and_then(|v| v.as_array())
+            .map_or(&[][..], |v| v)
+            .iter()
+            .filter_map(|name| name.as_string())
+            .map(|name| self.path.join(name))
+            .collect()
+    }
+
+    /// Whether the status bar should be hidden at startup
+    /// (`UIStatusBarHidden`).
+    pub fn status_bar_hidden(&self) -> bool {
+        self.plist
+            .get("UIStatusBarHidden")
+            .and_then(|v| v.as_boolean())
+            .unwrap_or(false)
+    }
+
+    /// The name of the status bar style to use at startup
+    /// (`UIStatusBarStyle`), if any.
+    pub fn status_bar_style(&self) -> Option<&str> {
+        self.plist
+            .get("UIStatusBarStyle")
+            .and_then(|v| v.as_string())
+    }
+
     pub fn main_nib_file_path(&self) -> Option<GuestPathBuf> {
         self.plist.get("NSMainNibFile").map(|filename| {
             let filename = filename.as_string().unwrap();
@@ -200,10 +200,30 @@ impl Cpu {
         }
     }
 
-    pub fn dump_regs(&self) {
+    /// Get the VFP registers, as 32-bit single-precision registers (S0-S31).
+    /// Each double-precision register Dn is made up of S(2n) and S(2n+1).
+    /// Dynarmic has space for 64 of these, but only the first 32 exist in
+    /// ARMv6's VFPv2.
+    pub fn extregs(&self) -> &[u32; 64] {
+        unsafe {
+            let ptr = touchHLE_DynarmicWrapper_extregs_const(self.dynarmic_wrapper);
+            &*(ptr as *const [u32; 64])
+        }
+    }
+    pub fn extregs_mut(&mut self) -> &mut [u32; 64] {
+        unsafe {
+            let ptr = touchHLE_DynarmicWrapper_extregs_mut(self.dynarmic_wrapper);
+            &mut *(ptr as *mut [u32; 64])
+        }
+    }
+
+    /// Format the registers as four rows of four, plus the CPSR, for use in a
+    /// crash report or similar.
+    pub fn format_regs(&self) -> String {
+        use std::fmt::Write;
         let regs = self.regs();
+        let mut text = String::new();
         for row in 0..4 {
-            use std::fmt::Write;
             let mut line = String::new();
             for col in 0..4 {
                 let reg_idx = row * 4 + col;
@@ -217,8 +237,21 @@ impl Cpu {
                 .unwrap();
                 write!(&mut line, "{:#010x}", regs[reg_idx]).unwrap();
             }
-            echo!("{}", line);
+            writeln!(&mut text, "{}", line).unwrap();
         }
+        let cpsr = self.cpsr();
+        writeln!(
+            &mut text,
+            "\tCPSR: {:#010x} ({})",
+            cpsr,
+            if (cpsr & Self::CPSR_THUMB) != 0 {
+                "Thumb"
+            } else {
+                "ARM"
+            }
+        )
+        .unwrap();
+        text
     }
 
     pub fn cpsr(&self) -> u32 {
@@ -0,0 +1,308 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Crash reports, for when touchHLE panics while running an app.
+//!
+//! A Rust panic message on its own says where touchHLE was, but not where the
+//! app was, so [report_crash] also describes the state of the guest thread that
+//! was running: its registers, a backtrace, and which host functions (and
+//! Objective-C methods) it was calling. The report is printed and saved to a
+//! file in [crate::paths::CRASH_REPORTS_DIR], so it can be attached to a bug
+//! report.
+//!
+//! The backtrace follows the chain of frame pointers (`r7`) on the stack. If
+//! the chain is broken, e.g. because the crash happened before a function set
+//! up its frame, the rest of the stack is scanned for words that look like
+//! return addresses into the app's code. Some of those will be stale, so they
+//! are listed separately. Addresses are symbolized like in the profiler (see
+//! [crate::profiler::Symbolizer]), and the stubs that [crate::dyld] creates
+//! for host functions are named after the host function.
+//!
+//! Reporting a crash mustn't cause another panic, so guest memory is only
+//! accessed with [crate::mem::Mem::read_fallible] here.
+
+use crate::abi::{GuestArg, FRAME_POINTER};
+use crate::cpu::Cpu;
+use crate::mem::{ConstPtr, Ptr};
+use crate::objc::{id, objc_super, SEL};
+use crate::profiler::Symbolizer;
+use crate::Environment;
+use std::any::Any;
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+use std::time::SystemTime;
+
+/// Maximum number of frames to follow in the frame pointer chain.
+const MAX_FRAMES: usize = 64;
+/// Maximum number of words to scan if the frame pointer chain is broken.
+const MAX_SCAN_WORDS: u32 = 2048;
+/// Maximum number of possible return addresses to list from the scan.
+const MAX_SCAN_RESULTS: usize = 32;
+
+#[derive(Default)]
+pub struct State {
+    /// Host function calls from guest code that were unwound by the panic,
+    /// innermost first.
+    unwound_host_calls: Vec<UnwoundHostCall>,
+}
+
+struct UnwoundHostCall {
+    symbol: &'static str,
+    /// For `objc_msgSend` and friends, the method that was being called, if
+    /// it could be determined.
+    method: Option<String>,
+}
+
+/// For use by [Environment]: record that a panic (not an Objective-C
+/// exception) unwound a call to a host function from guest code. `arg_regs`
+/// are the values of `r0` to `r2` when the function was called.
+pub fn host_call_unwound(env: &mut Environment, symbol: &'static str, arg_regs: [u32; 3]) {
+    let [r0, r1, r2] = arg_regs;
+    let describe = |receiver: u32, selector: u32| {
+        let receiver: id = Ptr::from_bits(receiver);
+        let selector = <SEL as GuestArg>::from_regs(&[selector]);
+        env.objc.describe_message_send(receiver, selector, &env.mem)
+    };
+    let method = match symbol {
+        "_objc_msgSend" => describe(r0, r1),
+        "_objc_msgSend_stret" => describe(r1, r2),
+        "_objc_msgSendSuper2" => {
+            let super_ptr: ConstPtr<objc_super> = Ptr::from_bits(r0);
+            env.mem
+                .read_fallible(super_ptr)
+                .and_then(|objc_super { receiver, .. }| describe(receiver.to_bits(), r1))
+        }
+        _ => None,
+    };
+    env.crash_report
+        .unwound_host_calls
+        .push(UnwoundHostCall { symbol, method });
+}
+
+/// For use by [Environment::run]: print a crash report for a panic and save it
+/// to a file.
+pub fn report_crash(env: &Environment, payload: &(dyn Any + Send)) {
+    let report = format_report(env, payload);
+    echo!("{}", report);
+
+    let dir = crate::paths::user_data_base_path().join(crate::paths::CRASH_REPORTS_DIR);
+    let path = dir.join(format!(
+        "{}-{}.txt",
+        if env.bundle.is_fake() {
+            "touchHLE"
+        } else {
+            env.bundle.bundle_identifier()
+        },
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis()
+    ));
+    match std::fs::create_dir_all(&dir).and_then(|()| std::fs::write(&path, report)) {
+        Ok(()) => echo!(
+            "The crash report has been saved to {}. Please include it if you report this crash.",
+            path.display()
+        ),
+        Err(e) => echo!(
+            "Warning: Couldn't save the crash report to {}: {}",
+            path.display(),
+            e
+        ),
+    }
+}
+
+fn format_report(env: &Environment, payload: &(dyn Any + Send)) -> String {
+    let mut report = String::new();
+    let r = &mut report;
+
+    writeln!(r, "==== touchHLE crash report ====").unwrap();
+    writeln!(
+        r,
+        "touchHLE {}{}{}",
+        crate::branding(),
+        if crate::branding().is_empty() {
+            ""
+        } else {
+            " "
+        },
+        crate::VERSION,
+    )
+    .unwrap();
+    if env.bundle.is_fake() {
+        writeln!(r, "App: (none, crashed in the app picker)").unwrap();
+    } else {
+        writeln!(
+            r,
+            "App: {} ({})",
+            env.bundle.display_name(),
+            env.bundle.bundle_identifier()
+        )
+        .unwrap();
+    }
+
+    let message: &str = if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "(non-string payload)"
+    };
+    writeln!(r, "Panic: {}", message).unwrap();
+    if env.current_thread == 0 {
+        writeln!(r, "Thread: main thread").unwrap();
+    } else {
+        writeln!(r, "Thread: thread {}", env.current_thread).unwrap();
+    }
+
+    let symbolizer = Symbolizer::new(env);
+
+    if !env.crash_report.unwound_host_calls.is_empty() {
+        writeln!(r).unwrap();
+        writeln!(r, "Host function calls in progress (innermost first):").unwrap();
+        for UnwoundHostCall { symbol, method } in &env.crash_report.unwound_host_calls {
+            let symbol = symbol.strip_prefix('_').unwrap_or(symbol);
+            match method {
+                Some(method) => writeln!(r, "  {}: {}", symbol, method),
+                None => writeln!(r, "  {}", symbol),
+            }
+            .unwrap();
+        }
+    }
+
+    writeln!(r).unwrap();
+    writeln!(r, "Registers:").unwrap();
+    write!(r, "{}", env.cpu.format_regs()).unwrap();
+
+    let regs = env.cpu.regs();
+    writeln!(r).unwrap();
+    writeln!(r, "Backtrace:").unwrap();
+    let pc = env.cpu.pc_with_thumb_bit().addr_with_thumb_bit();
+    write_frame(r, env, &symbolizer, 0, pc, " (PC)");
+    write_frame(r, env, &symbolizer, 1, regs[Cpu::LR], " (LR)");
+    let Some(stack_range) = env.current_stack_range() else {
+        writeln!(r, "The thread has no stack, so it can't be walked.").unwrap();
+        return report;
+    };
+    let (return_addresses, complete) = walk_frame_pointers(env, &stack_range);
+    for (i, &addr) in return_addresses.iter().enumerate() {
+        write_frame(r, env, &symbolizer, i + 2, addr, "");
+    }
+    if complete {
+        return report;
+    }
+
+    writeln!(r).unwrap();
+    writeln!(
+        r,
+        "The frame pointer chain is broken. These words on the stack look like return addresses into the app's code, but some of them may be stale:"
+    )
+    .unwrap();
+    let code_sections: Vec<_> = env
+        .bins
+        .iter()
+        .flat_map(|bin| &bin.sections)
+        .filter(|section| section.name == "__text")
+        .map(|section| section.addr..(section.addr + section.size))
+        .collect();
+    let mut found = 0;
+    let mut addr = regs[Cpu::SP];
+    for _ in 0..MAX_SCAN_WORDS {
+        if found == MAX_SCAN_RESULTS || !stack_range.contains(&addr) {
+            break;
+        }
+        let Some(word) = env.mem.read_fallible(ConstPtr::<u32>::from_bits(addr)) else {
+            break;
+        };
+        // ARM code is word-aligned, Thumb return addresses have the low bit
+        // set.
+        let plausible = (word & 1) == 1 || (word & 3) == 0;
+        if plausible && code_sections.iter().any(|range| range.contains(&word)) {
+            let suffix = format!(" (at {:#010x})", addr);
+            write_frame(r, env, &symbolizer, found, word, &suffix);
+            found += 1;
+        }
+        let Some(next_addr) = addr.checked_add(4) else {
+            break;
+        };
+        addr = next_addr;
+    }
+    if found == 0 {
+        writeln!(r, "  (none found)").unwrap();
+    }
+
+    report
+}
+
+/// Follow the chain of frame pointers on the stack and return the return
+/// addresses found, innermost first, and whether the end of the chain was
+/// reached.
+fn walk_frame_pointers(env: &Environment, stack_range: &RangeInclusive<u32>) -> (Vec<u32>, bool) {
+    let thread_exit_routine_addr = env.dyld.thread_exit_routine().addr_with_thumb_bit();
+    let mut return_addresses = Vec::new();
+    let mut fp = env.cpu.regs()[FRAME_POINTER];
+    while return_addresses.len() < MAX_FRAMES {
+        if fp == 0 {
+            return (return_addresses, true);
+        }
+        if !stack_range.contains(&fp) {
+            break;
+        }
+        let next_fp = env.mem.read_fallible(ConstPtr::<u32>::from_bits(fp));
+        let lr = fp
+            .checked_add(4)
+            .and_then(|addr| env.mem.read_fallible(ConstPtr::<u32>::from_bits(addr)));
+        let (Some(next_fp), Some(lr)) = (next_fp, lr) else {
+            break;
+        };
+        return_addresses.push(lr);
+        if lr == thread_exit_routine_addr {
+            return (return_addresses, true);
+        }
+        // The stack grows downwards, so each caller's frame is higher up.
+        if next_fp != 0 && next_fp <= fp {
+            break;
+        }
+        fp = next_fp;
+    }
+    (return_addresses, false)
+}
+
+fn write_frame(
+    r: &mut String,
+    env: &Environment,
+    symbolizer: &Symbolizer,
+    idx: usize,
+    addr: u32,
+    suffix: &str,
+) {
+    writeln!(
+        r,
+        "{:3}. {:#010x} {}{}",
+        idx,
+        addr,
+        describe_address(env, symbolizer, addr),
+        suffix
+    )
+    .unwrap();
+}
+
+fn describe_address(env: &Environment, symbolizer: &Symbolizer, addr: u32) -> String {
+    if addr == env.dyld.return_to_host_routine().addr_with_thumb_bit() {
+        return "[return to host]".to_string();
+    }
+    if addr == env.dyld.thread_exit_routine().addr_with_thumb_bit() {
+        return "[thread exit]".to_string();
+    }
+    if let Some(symbol) = env.dyld.host_function_at(&env.mem, addr) {
+        let symbol = symbol.strip_prefix('_').unwrap_or(symbol);
+        return format!("{} [host function]", symbol);
+    }
+    let addr = addr & !1;
+    match symbolizer.lookup(addr) {
+        Some((sym_addr, name)) if sym_addr == addr => name.to_string(),
+        Some((sym_addr, name)) => format!("{} + {:#x}", name, addr - sym_addr),
+        None => "???".to_string(),
+    }
+}
@@ -27,10 +27,11 @@ use crate::abi::{CallFromGuest, GuestFunction};
 use crate::cpu::Cpu;
 use crate::frameworks::foundation::ns_string;
 use crate::mach_o::{MachO, SectionType};
-use crate::mem::{ConstVoidPtr, GuestUSize, Mem, MutPtr, Ptr};
+use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, Mem, MutPtr, Ptr};
 use crate::objc::{nil, ObjC};
+use crate::options::Options;
 use crate::Environment;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 pub type HostFunction = &'static dyn CallFromGuest;
 
@@ -177,6 +178,22 @@ pub struct Dyld {
     thread_exit_routine: Option<GuestFunction>,
     constants_to_link_later: Vec<(MutPtr<ConstVoidPtr>, &'static HostConstant)>,
     non_lazy_host_functions: HashMap<&'static str, GuestFunction>,
+    /// See [Options::strict_binding].
+    strict_binding: bool,
+}
+
+/// Address that non-lazy symbol pointers for missing symbols are set to,
+/// unless [Options::strict_binding] is used or the symbol is a weak reference
+/// (those are left NULL). It's within the null page, so
+/// using it will fail, and the address in the error message will be
+/// recognizable.
+const POISON_ADDR: u32 = 0xBAD;
+
+/// Host function used for calls to functions touchHLE doesn't implement,
+/// unless [Options::strict_binding] is used. This clears both `r0` and `r1`,
+/// so it returns 0 (or NULL, or 0.0) regardless of the return type.
+fn unimplemented_function_stub(_env: &mut Environment) -> u64 {
+    0
 }
 
 impl Dyld {
@@ -193,13 +210,14 @@ impl Dyld {
     const SYMBOL_STUB_INSTRUCTIONS: [u32; 2] = [0xe59fc000, 0xe59cf000];
     const PIC_SYMBOL_STUB_INSTRUCTIONS: [u32; 3] = [0xe59fc004, 0xe08fc00c, 0xe59cf000];
 
-    pub fn new() -> Dyld {
+    pub fn new(options: &Options) -> Dyld {
         Dyld {
             linked_host_functions: Vec::new(),
             return_to_host_routine: None,
             thread_exit_routine: None,
             constants_to_link_later: Vec::new(),
             non_lazy_host_functions: HashMap::new(),
+            strict_binding: options.strict_binding,
         }
     }
 
@@ -211,6 +229,27 @@ impl Dyld {
         self.thread_exit_routine.unwrap()
     }
 
+    /// Get the symbol of the host function that is called by the stub at a
+    /// guest address, if there is one. The address can be that of the SVC or
+    /// of the instruction after it (i.e. the PC while the host function is
+    /// running). For debugging purposes (see [crate::crash_report]), so this
+    /// doesn't panic if the address isn't valid.
+    pub fn host_function_at(&self, mem: &Mem, addr: u32) -> Option<&'static str> {
+        let ptr: ConstPtr<u32> = Ptr::from_bits(addr & !3);
+        let mut instr = mem.read_fallible(ptr)?;
+        if instr == encode_a32_ret() || instr == encode_a32_trap() {
+            instr = mem.read_fallible(ptr - 1)?;
+        }
+        if instr & 0xff000000 != encode_a32_svc(0) {
+            return None;
+        }
+        let svc = instr & 0x00ffffff;
+        let idx = svc.checked_sub(Self::SVC_LINKED_FUNCTIONS_BASE)?;
+        self.linked_host_functions
+            .get(idx as usize)
+            .map(|&(symbol, _)| symbol)
+    }
+
     /// Do linking-related tasks that need doing right after loading the
     /// binaries.
     pub fn do_initial_linking(&mut self, bins: &[MachO], mem: &mut Mem, objc: &mut ObjC) {
@@ -220,9 +259,9 @@ impl Dyld {
             Some(write_return_to_host_routine(mem, Self::SVC_RETURN_TO_HOST));
         self.thread_exit_routine = Some(write_return_to_host_routine(mem, Self::SVC_THREAD_EXIT));
 
-        // Currently assuming only the app binary contains Objective-C things.
-
-        objc.register_bin_selectors(&bins[0], mem);
+        for bin in bins {
+            objc.register_bin_selectors(bin, mem);
+        }
         objc.register_host_selectors(mem);
 
         for bin in bins {
@@ -232,10 +271,16 @@ impl Dyld {
             self.do_non_lazy_linking(bin, bins, mem, objc);
         }
 
-        objc.register_bin_classes(&bins[0], mem);
-        objc.register_bin_categories(&bins[0], mem);
+        // Classes in dylibs must be registered before the app's classes, which
+        // may be subclasses of them or have categories on them.
+        for bin in bins.iter().skip(1).chain(bins.first()) {
+            objc.register_bin_classes(bin, mem);
+            objc.register_bin_categories(bin, mem);
+        }
 
-        ns_string::register_constant_strings(&bins[0], mem, objc);
+        for bin in bins {
+            ns_string::register_constant_strings(bin, mem, objc);
+        }
     }
 
     /// [Self::do_initial_linking] but for when this is the app picker's special
@@ -312,13 +357,24 @@ impl Dyld {
     /// binaries symbols may be looked up in.
     fn do_non_lazy_linking(&mut self, bin: &MachO, bins: &[MachO], mem: &mut Mem, objc: &mut ObjC) {
         let mut unhandled_relocations: HashMap<&str, Vec<u32>> = HashMap::new();
+        // Binaries using LC_DYLD_INFO list non-lazy pointers as external
+        // relocations too, which shouldn't be linked a second time below.
+        let mut relocated = HashSet::new();
         for &(ptr_ptr, ref name) in &bin.external_relocations {
             let ptr_ptr: MutPtr<ConstVoidPtr> = Ptr::from_bits(ptr_ptr);
             // There will be an existing value at the address, which is an
             // offset that should be applied to the external symbol's address.
             // It is often 0, but not always.
             let offset: u32 = mem.read(ptr_ptr).to_bits();
-            let target: ConstVoidPtr = if let Some(name) = name.strip_prefix("_OBJC_CLASS_$_") {
+            let target: ConstVoidPtr = if let Some(&external_addr) = bins
+                .iter()
+                .flat_map(|other_bin| other_bin.exported_symbols.get(name))
+                .next()
+            {
+                // Often used for C++ RTTI, and for Objective-C classes defined
+                // in a dylib bundled with the app.
+                Ptr::from_bits(external_addr)
+            } else if let Some(name) = name.strip_prefix("_OBJC_CLASS_$_") {
                 objc.link_class(name, /* is_metaclass: */ false, mem)
                     .cast()
                     .cast_const()
@@ -326,19 +382,16 @@ impl Dyld {
                 objc.link_class(name, /* is_metaclass: */ true, mem)
                     .cast()
                     .cast_const()
+            } else if let Some(class_name) = crate::objc::concrete_block_class_name(name) {
+                objc.link_class(class_name, /* is_metaclass: */ false, mem)
+                    .cast()
+                    .cast_const()
             } else if name == "___CFConstantStringClassReference" {
                 // See ns_string::register_constant_strings
                 nil.cast().cast_const()
             } else if name == "__objc_empty_vtable" || name == "__objc_empty_cache" {
                 // Our Objective-C runtime doesn't use these
                 Ptr::null()
-            } else if let Some(&external_addr) = bins
-                .iter()
-                .flat_map(|other_bin| other_bin.exported_symbols.get(name))
-                .next()
-            {
-                // Often used for C++ RTTI
-                Ptr::from_bits(external_addr)
             } else {
                 unhandled_relocations
                     .entry(name)
@@ -351,7 +404,8 @@ impl Dyld {
             mem.write(
                 ptr_ptr,
                 Ptr::from_bits(target.to_bits().wrapping_add(offset)),
-            )
+            );
+            relocated.insert(ptr_ptr.to_bits());
         }
         // Collecting unhandled relocations for the same symbol onto one line
         // makes the log output much less spammy.
@@ -383,6 +437,9 @@ impl Dyld {
             };
 
             let ptr_ptr: MutPtr<ConstVoidPtr> = Ptr::from_bits(ptrs.addr + i * entry_size);
+            if relocated.contains(&ptr_ptr.to_bits()) {
+                continue;
+            }
 
             for other_bin in bins {
                 if let Some(&addr) = other_bin.exported_symbols.get(symbol) {
@@ -391,6 +448,12 @@ impl Dyld {
                 }
             }
 
+            if let Some(class_name) = crate::objc::concrete_block_class_name(symbol) {
+                let class = objc.link_class(class_name, /* is_metaclass: */ false, mem);
+                mem.write(ptr_ptr, class.cast().cast_const());
+                continue;
+            }
+
             if let Some((symbol, _)) = search_lists(function_lists::FUNCTION_LISTS, symbol) {
                 // We want the same symbol name to always point to the same
                 // function. It could point to a specific stub entry, but it's
@@ -416,12 +479,34 @@ impl Dyld {
                 continue;
             }
 
-            log!(
-                "Warning: unhandled non-lazy symbol {:?} at {:?} in \"{}\"",
-                symbol,
-                ptr_ptr,
-                bin.name
-            );
+            if info.weak_undef_symbols[i as usize] {
+                // Weak imports are allowed to be missing, and apps check for
+                // NULL to find out whether they're available, so the pointer
+                // is left as it is.
+                log_dbg!(
+                    "Leaving missing weak non-lazy symbol {:?} at {:?} in \"{}\" as NULL",
+                    symbol,
+                    ptr_ptr,
+                    bin.name
+                );
+                mem.write(ptr_ptr, Ptr::null());
+            } else if self.strict_binding {
+                log!(
+                    "Warning: unhandled non-lazy symbol {:?} at {:?} in \"{}\"",
+                    symbol,
+                    ptr_ptr,
+                    bin.name
+                );
+            } else {
+                log!(
+                    "Warning: unhandled non-lazy symbol {:?} at {:?} in \"{}\", using poison address {:#x}",
+                    symbol,
+                    ptr_ptr,
+                    bin.name,
+                    POISON_ADDR
+                );
+                mem.write(ptr_ptr, Ptr::from_bits(POISON_ADDR));
+            }
         }
 
         // FIXME: check for internal relocations?
@@ -452,8 +537,8 @@ impl Dyld {
     }
 
     /// Return a host function that can be called to handle an SVC instruction
-    /// encountered during CPU emulation. If `None` is returned, the execution
-    /// needs to resume at `svc_pc`.
+    /// encountered during CPU emulation, along with its symbol name. If `None`
+    /// is returned, the execution needs to resume at `svc_pc`.
     pub fn get_svc_handler(
         &mut self,
         bins: &[MachO],
@@ -461,7 +546,7 @@ impl Dyld {
         cpu: &mut Cpu,
         svc_pc: u32,
         svc: u32,
-    ) -> Option<HostFunction> {
+    ) -> Option<(&'static str, HostFunction)> {
         match svc {
             Self::SVC_LAZY_LINK => self.do_lazy_link(bins, mem, cpu, svc_pc),
             Self::SVC_THREAD_EXIT | Self::SVC_RETURN_TO_HOST => unreachable!(), // don't handle here
@@ -473,7 +558,7 @@ impl Dyld {
                     panic!("Unexpected SVC #{} at {:#x}", svc, svc_pc);
                 };
                 log_dbg!("Call to host function, already linked: {}", symbol);
-                Some(f)
+                Some((symbol, f))
             }
         }
     }
@@ -484,7 +569,7 @@ impl Dyld {
         mem: &mut Mem,
         cpu: &mut Cpu,
         svc_pc: u32,
-    ) -> Option<HostFunction> {
+    ) -> Option<(&'static str, HostFunction)> {
         // Links by restoring the original stub function, then updating
         // __la_symbol_ptr to the appropriate function.
         fn link_by_restoring_stub(
@@ -581,7 +666,7 @@ impl Dyld {
 
             // Return the host function so that we can call it now that we're
             // done.
-            return Some(f);
+            return Some((symbol, f));
         }
 
         for dylib in bins.iter() {
@@ -601,7 +686,24 @@ impl Dyld {
             }
         }
 
-        panic!("Call to unimplemented function {}", symbol);
+        // The stub isn't rewritten, so that every call ends up here and gets
+        // logged.
+        let caller = cpu.regs()[Cpu::LR];
+        if self.strict_binding {
+            panic!(
+                "Call to unimplemented function {} from {:#x}",
+                symbol, caller
+            );
+        }
+        log!(
+            "Warning: call to unimplemented function {} from {:#x}, returning 0",
+            symbol,
+            caller
+        );
+        Some((
+            "[unimplemented function]",
+            &(unimplemented_function_stub as fn(&mut Environment) -> u64),
+        ))
     }
 
     /// Creates a guest function that will call a host function with the name
@@ -7,7 +7,8 @@
 //! very long and frequently-updated list.
 
 use crate::frameworks::{
-    core_animation, core_foundation, core_graphics, foundation, media_player, opengles, uikit,
+    audio_toolbox, cf_network, core_animation, core_foundation, core_graphics, core_location,
+    foundation, game_kit, media_player, message_ui, opengles, store_kit, uikit,
 };
 use crate::libc;
 
@@ -15,21 +16,32 @@ use crate::libc;
 pub const CONSTANT_LISTS: &[super::ConstantExports] = &[
     libc::ctype::CONSTANTS,
     libc::stdio::CONSTANTS,
+    audio_toolbox::audio_session::CONSTANTS,
+    cf_network::cf_http_message::CONSTANTS,
+    cf_network::cf_http_stream::CONSTANTS,
+    core_animation::ca_animation::CONSTANTS,
     core_animation::ca_layer::CONSTANTS,
+    core_animation::ca_media_timing_function::CONSTANTS,
     core_foundation::cf_allocator::CONSTANTS,
     core_foundation::cf_bundle::CONSTANTS,
     core_foundation::cf_run_loop::CONSTANTS,
     core_graphics::cg_affine_transform::CONSTANTS,
     core_graphics::cg_color_space::CONSTANTS,
     core_graphics::cg_geometry::CONSTANTS,
+    core_location::cl_location_manager::CONSTANTS,
     foundation::ns_error::CONSTANTS,
     foundation::ns_exception::CONSTANTS,
     foundation::ns_keyed_unarchiver::CONSTANTS,
     foundation::ns_locale::CONSTANTS,
     foundation::ns_run_loop::CONSTANTS,
+    foundation::ns_stream::CONSTANTS,
+    foundation::ns_undo_manager::CONSTANTS,
+    game_kit::CONSTANTS,
     media_player::movie_player::CONSTANTS,
     media_player::music_player::CONSTANTS,
+    message_ui::mf_mail_compose_view_controller::CONSTANTS,
     opengles::eagl::CONSTANTS,
+    store_kit::CONSTANTS,
     uikit::ui_application::CONSTANTS,
     uikit::ui_device::CONSTANTS,
 ];
@@ -7,12 +7,14 @@
 //! very long and frequently-updated list.
 
 use crate::frameworks::{
-    audio_toolbox, core_foundation, core_graphics, dnssd, foundation, openal, opengles, uikit,
+    accelerate, audio_toolbox, cf_network, core_animation, core_foundation, core_graphics,
+    core_location, dnssd, foundation, libsqlite3, libz, openal, opengles, uikit,
 };
 use crate::libc;
 
 /// All the lists of functions that the linker should search through.
 pub const FUNCTION_LISTS: &[super::FunctionExports] = &[
+    libc::arpa::inet::FUNCTIONS,
     libc::clocale::FUNCTIONS,
     libc::ctype::FUNCTIONS,
     libc::cxxabi::FUNCTIONS,
@@ -34,6 +36,7 @@ pub const FUNCTION_LISTS: &[super::FunctionExports] = &[
     libc::pthread::key::FUNCTIONS,
     libc::pthread::mutex::FUNCTIONS,
     libc::pthread::once::FUNCTIONS,
+    libc::pthread::rwlock::FUNCTIONS,
     libc::pthread::thread::FUNCTIONS,
     libc::sched::FUNCTIONS,
     libc::semaphore::FUNCTIONS,
@@ -44,6 +47,8 @@ pub const FUNCTION_LISTS: &[super::FunctionExports] = &[
     libc::stdlib::FUNCTIONS,
     libc::stdlib::qsort::FUNCTIONS,
     libc::string::FUNCTIONS,
+    libc::sys::select::FUNCTIONS,
+    libc::sys::socket::FUNCTIONS,
     libc::sys::timeb::FUNCTIONS,
     libc::sys::utsname::FUNCTIONS,
     libc::sysctl::FUNCTIONS,
@@ -51,18 +56,25 @@ pub const FUNCTION_LISTS: &[super::FunctionExports] = &[
     libc::unistd::FUNCTIONS,
     libc::wchar::FUNCTIONS,
     crate::objc::FUNCTIONS,
+    accelerate::FUNCTIONS,
     audio_toolbox::audio_components::FUNCTIONS,
     audio_toolbox::audio_file::FUNCTIONS,
     audio_toolbox::audio_queue::FUNCTIONS,
     audio_toolbox::audio_services::FUNCTIONS,
     audio_toolbox::audio_session::FUNCTIONS,
     audio_toolbox::audio_unit::FUNCTIONS,
+    audio_toolbox::ext_audio_file::FUNCTIONS,
+    cf_network::cf_http_message::FUNCTIONS,
+    cf_network::cf_http_stream::FUNCTIONS,
+    core_animation::ca_animation::FUNCTIONS,
     core_foundation::cf_array::FUNCTIONS,
     core_foundation::cf_bundle::FUNCTIONS,
     core_foundation::cf_data::FUNCTIONS,
     core_foundation::cf_locale::FUNCTIONS,
     core_foundation::cf_run_loop::FUNCTIONS,
+    core_foundation::cf_run_loop_observer::FUNCTIONS,
     core_foundation::cf_run_loop_timer::FUNCTIONS,
+    core_foundation::cf_stream::FUNCTIONS,
     core_foundation::cf_string::FUNCTIONS,
     core_foundation::cf_type::FUNCTIONS,
     core_foundation::cf_url::FUNCTIONS,
@@ -74,16 +86,21 @@ pub const FUNCTION_LISTS: &[super::FunctionExports] = &[
     core_graphics::cg_context::FUNCTIONS,
     core_graphics::cg_data_provider::FUNCTIONS,
     core_graphics::cg_geometry::FUNCTIONS,
+    core_graphics::cg_gradient::FUNCTIONS,
     core_graphics::cg_image::FUNCTIONS,
+    core_location::cl_location::FUNCTIONS,
     dnssd::FUNCTIONS,
     foundation::FUNCTIONS,
     foundation::ns_exception::FUNCTIONS,
     foundation::ns_file_manager::FUNCTIONS,
     foundation::ns_log::FUNCTIONS,
     foundation::ns_objc_runtime::FUNCTIONS,
+    libsqlite3::FUNCTIONS,
+    libz::FUNCTIONS,
     openal::FUNCTIONS,
     opengles::FUNCTIONS,
     uikit::ui_application::FUNCTIONS,
     uikit::ui_geometry::FUNCTIONS,
     uikit::ui_graphics::FUNCTIONS,
+    uikit::ui_image::FUNCTIONS,
 ];
@@ -8,20 +8,25 @@
 //! Unlike its siblings, this module should be considered private and only used
 //! via the re-exports one level up.
 
+mod clock;
 mod mutex;
 
 use crate::abi::{CallFromHost, GuestRet};
 use crate::libc::semaphore::sem_t;
+use crate::libc::sys::socket::{self as libc_socket, SocketWait};
 use crate::mem::{MutPtr, MutVoidPtr};
 use crate::{
-    abi, bundle, cpu, dyld, frameworks, fs, gdb, image, libc, mach_o, mem, objc, options, stack,
-    window,
+    abi, automation, bundle, cpu, crash_report, dyld, frameworks, fs, gdb, image, libc, mach_o,
+    mem, objc, options, profiler, recording, stack, window,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::net::TcpListener;
 use std::time::{Duration, Instant};
 
-use crate::libc::pthread::cond::pthread_cond_t;
+use crate::libc::pthread::cond::CondId;
+use crate::libc::pthread::once::pthread_once_t;
+use crate::libc::pthread::rwlock::RwLockId;
+pub use clock::{Clock, SpeedIndicator};
 pub use mutex::{MutexId, MutexType, PTHREAD_MUTEX_DEFAULT};
 
 /// Index into the [Vec] of threads. Thread 0 is always the main thread.
@@ -56,6 +61,10 @@ pub struct Thread {
     /// frame" of the thread is a host function, not whether there are any host
     /// functions at all.
     in_host_function: bool,
+    /// Number of host-to-guest calls ([Environment::run_call]) in progress on
+    /// this thread. This is used to find the host stack frame an Objective-C
+    /// exception needs to be unwound to, see [crate::objc::catch_exception].
+    guest_call_depth: usize,
     /// Context object containing the CPU state for this thread.
     ///
     /// There should always be `(threads.len() - 1)` contexts in existence.
@@ -79,9 +88,13 @@ impl Thread {
 pub struct Environment {
     /// Reference point for various timing functions.
     pub startup_time: Instant,
+    /// The guest's clock. See [Clock] for why this must be used instead of
+    /// [Instant::now].
+    pub clock: Clock,
     pub bundle: bundle::Bundle,
     pub fs: fs::Fs,
-    /// The window is only absent when running in headless mode.
+    /// The window is only absent when running in headless mode, and even then
+    /// only if an offscreen window couldn't be created.
     pub window: Option<window::Window>,
     pub mem: mem::Mem,
     /// Loaded binaries. Index `0` is always the app binary, other entries are
@@ -96,6 +109,12 @@ pub struct Environment {
     pub framework_state: frameworks::State,
     pub mutex_state: mutex::MutexState,
     pub options: options::Options,
+    pub recording: recording::State,
+    /// Present if profiling is enabled, see [profiler].
+    pub profiler: Option<profiler::Profiler>,
+    /// Present if any scripted actions were requested, see [automation].
+    pub automation: Option<automation::Automation>,
+    pub crash_report: crash_report::State,
     gdb_server: Option<gdb::GdbServer>,
     pub env_vars: HashMap<Vec<u8>, MutPtr<u8>>,
 }
@@ -123,14 +142,94 @@ pub enum ThreadBlock {
     Mutex(MutexId),
     // Thread is waiting on a semaphore.
     Semaphore(MutPtr<sem_t>),
-    // Thread is wating on a condition variable
-    Condition(pthread_cond_t),
+    // Thread is waiting on a condition variable, and must relock the mutex
+    // once woken. (until Instant, if it's a timed wait)
+    Condition(CondId, MutexId, Option<Instant>),
+    // Thread is waiting for a reader/writer lock. (for writing if true)
+    RwLock(RwLockId, bool),
+    // Thread is waiting for another thread to finish a `pthread_once`.
+    Once(MutPtr<pthread_once_t>),
     // Thread is waiting for another thread to finish (joining).
     Joining(ThreadId, MutPtr<MutVoidPtr>),
+    // Thread is waiting for a socket to become ready, which is polled. (until
+    // Instant, if there's a timeout)
+    Socket(SocketWait, Option<Instant>),
     // Deferred guest-to-host return
     DeferredReturn,
 }
 
+/// Non-framework dylibs that we have host implementations of.
+const HOST_DYLIBS: &[&str] = &[
+    "/usr/lib/libSystem.B.dylib",
+    "/usr/lib/libobjc.A.dylib",
+    "/usr/lib/libsqlite3.dylib",
+    "/usr/lib/libsqlite3.0.dylib",
+    "/usr/lib/libz.dylib",
+    "/usr/lib/libz.1.dylib",
+    "/usr/lib/libz.1.2.3.dylib",
+];
+
+/// Load the dylibs that a binary depends on, and their own dependencies, into
+/// `dylibs`. Dependencies come before the dylibs that depend on them. `loaded`
+/// tracks which paths have already been seen, so that each dylib is only
+/// loaded once even if there are cycles.
+fn load_dylibs(
+    bin: &mach_o::MachO,
+    bin_path: &fs::GuestPath,
+    executable_dir: &fs::GuestPath,
+    fs: &fs::Fs,
+    mem: &mut mem::Mem,
+    loaded: &mut HashSet<String>,
+    dylibs: &mut Vec<mach_o::MachO>,
+) -> Result<(), String> {
+    for dylib in &bin.dynamic_libraries {
+        if HOST_DYLIBS.contains(&dylib.as_str()) {
+            continue;
+        }
+
+        // Dylibs and frameworks embedded in the app bundle are usually
+        // referred to relative to the executable or the binary loading them.
+        let path = if let Some(rest) = dylib.strip_prefix("@executable_path/") {
+            executable_dir.join(rest)
+        } else if let Some(rest) = dylib.strip_prefix("@loader_path/") {
+            bin_path.parent().unwrap().join(rest)
+        } else {
+            if dylib.starts_with("@rpath/") {
+                // TODO: support LC_RPATH
+                log!(
+                    "Warning: {:?} depends on dylib \"{}\" but @rpath is not supported",
+                    bin.name,
+                    dylib
+                );
+            }
+            fs::GuestPathBuf::from(fs::GuestPath::new(dylib))
+        };
+        if !loaded.insert(path.as_str().to_string()) {
+            continue;
+        }
+
+        // There are some Free Software libraries bundled with touchHLE and
+        // exposed via the guest file system (see Fs::new()).
+        if fs.is_file(&path) {
+            let dylib = mach_o::MachO::load_from_file(&path, fs, mem)
+                .map_err(|e| format!("Could not load dylib {:?}: {}", path.as_str(), e))?;
+            load_dylibs(&dylib, &path, executable_dir, fs, mem, loaded, dylibs)?;
+            dylibs.push(dylib);
+        } else {
+            // System frameworks will have host implementations.
+            // TODO: warn about unimplemented frameworks?
+            if !dylib.starts_with("/System/Library/Frameworks/") {
+                log!(
+                    "Warning: {:?} depends on unexpected dylib \"{}\"",
+                    bin.name,
+                    dylib
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
 impl Environment {
     /// Loads the binary and sets up the emulator.
     ///
@@ -160,7 +259,19 @@ impl Environment {
         };
 
         let window = if options.headless {
-            None
+            // OpenAL Soft has no way to be told this other than its
+            // environment variables, and it reads them when it's first used.
+            std::env::set_var("ALSOFT_DRIVERS", "null");
+
+            // The offscreen window has no icon or launch image to show.
+            match window::Window::new(bundle.display_name(), None, None, &options) {
+                Ok(window) => Some(window),
+                Err(e) => {
+                    // This is fine for command-line apps.
+                    log!("Warning: Could not create an offscreen window, there will be no graphics or input: {}", e);
+                    None
+                }
+            }
         } else {
             let icon = bundle.load_icon(&fs);
             if let Err(ref e) = icon {
@@ -199,7 +310,7 @@ impl Environment {
                 icon.ok(),
                 launch_image,
                 &options,
-            ))
+            )?)
         };
 
         let mut mem = if let Some(mem) = mem_for_salvage {
@@ -207,35 +318,22 @@ impl Environment {
         } else {
             mem::Mem::new()
         };
+        mem.set_heap_limit(options.guest_memory);
 
-        let executable = mach_o::MachO::load_from_file(bundle.executable_path(), &fs, &mut mem)
+        let executable_path = bundle.executable_path();
+        let executable = mach_o::MachO::load_from_file(&executable_path, &fs, &mut mem)
             .map_err(|e| format!("Could not load executable: {}", e))?;
 
         let mut dylibs = Vec::new();
-        for dylib in &executable.dynamic_libraries {
-            if dylib == "/usr/lib/libSystem.B.dylib" || dylib == "/usr/lib/libobjc.A.dylib" {
-                // We have host implementations of these
-                continue;
-            }
-
-            // There are some Free Software libraries bundled with touchHLE and
-            // exposed via the guest file system (see Fs::new()).
-            if fs.is_file(fs::GuestPath::new(dylib)) {
-                let dylib = mach_o::MachO::load_from_file(fs::GuestPath::new(dylib), &fs, &mut mem)
-                    .map_err(|e| format!("Could not load bundled dylib: {}", e))?;
-                dylibs.push(dylib);
-            } else {
-                // System frameworks will have host implementations.
-                // TODO: warn about unimplemented frameworks?
-                if !dylib.starts_with("/System/Library/Frameworks/") {
-                    log!(
-                        "Warning: app binary depends on unexpected dylib \"{}\"",
-                        dylib
-                    );
-                }
-                continue;
-            };
-        }
+        load_dylibs(
+            &executable,
+            &executable_path,
+            executable_path.parent().unwrap(),
+            &fs,
+            &mut mem,
+            &mut HashSet::new(),
+            &mut dylibs,
+        )?;
 
         let entry_point_addr = executable.entry_point_pc.ok_or_else(|| {
             "Mach-O file does not specify an entry point PC, perhaps it is not an executable?"
@@ -250,7 +348,7 @@ impl Environment {
 
         let mut objc = objc::ObjC::new();
 
-        let mut dyld = dyld::Dyld::new();
+        let mut dyld = dyld::Dyld::new(&options);
         dyld.do_initial_linking(&bins, &mut mem, &mut objc);
 
         let cpu = cpu::Cpu::new(match options.direct_memory_access {
@@ -264,12 +362,14 @@ impl Environment {
             return_value: None,
             in_start_routine: false, // main thread never terminates
             in_host_function: false,
+            guest_call_depth: 0,
             context: None,
             stack: Some(mem::Mem::MAIN_THREAD_STACK_LOW_END..=0u32.wrapping_sub(1)),
         };
 
         let mut env = Environment {
             startup_time,
+            clock: Clock::new(),
             bundle,
             fs,
             window,
@@ -284,12 +384,32 @@ impl Environment {
             mutex_state: Default::default(),
             framework_state: Default::default(),
             options,
+            recording: Default::default(),
+            profiler: None,
+            automation: None,
+            crash_report: Default::default(),
             gdb_server: None,
             env_vars: Default::default(),
         };
 
         env.set_up_initial_env_vars();
 
+        // This must happen before the app can open any audio devices.
+        if let Some(path) = env.options.record_video.clone() {
+            recording::start_recording(&mut env, &path)?;
+        }
+
+        if env.options.profile.is_some() || env.options.profile_stacks.is_some() {
+            env.profiler = Some(profiler::Profiler::new(
+                env.options.profile.clone(),
+                env.options.profile_stacks.clone(),
+            ));
+        }
+
+        frameworks::audio_toolbox::audio_session::init_output_volume(&mut env);
+
+        env.automation = automation::Automation::new(&env.options, env.clock.now())?;
+
         dyld::Dyld::do_late_linking(&mut env);
 
         {
@@ -345,22 +465,40 @@ impl Environment {
         echo!("CPU emulation begins now.");
 
         // Static initializers for libraries must be run before the initializer
-        // in the app binary.
-        // TODO: once we support more libraries, replace this hard-coded order
-        //       with e.g. a topological sort.
-        assert!(env.bins.len() <= 3);
-        for bin_idx in [1, 2, 0] {
-            let Some(bin) = env.bins.get(bin_idx) else {
-                continue;
-            };
-            let Some(section) = bin.get_section(mach_o::SectionType::ModInitFuncPointers) else {
+        // in the app binary. The libraries are already in dependency order
+        // (see load_dylibs()).
+        let bin_count = env.bins.len();
+        for bin_idx in (1..bin_count).chain([0]) {
+            if bin_idx == 0 {
+                // Objective-C +load methods are called before the static
+                // initializers of the app binary.
+                // TODO: call the +load methods of each dylib before its
+                //       initializers, rather than all at once.
+                objc::call_load_methods(&mut env);
+            }
+            let bin = &env.bins[bin_idx];
+            let init_routine = bin.init_routine_pc;
+            let section = bin
+                .get_section(mach_o::SectionType::ModInitFuncPointers)
+                .map(|section| (section.addr, section.size));
+
+            // The LC_ROUTINES initialization routine runs before the others.
+            if let Some(init_routine) = init_routine {
+                log_dbg!("Calling initialization routine for {:?}", bin.name);
+                let func = abi::GuestFunction::from_addr_with_thumb_bit(init_routine);
+                () = func.call_from_host(&mut env, ());
+            }
+
+            let Some((addr, size)) = section else {
                 continue;
             };
-
-            log_dbg!("Calling static initializers for {:?}", bin.name);
-            assert!(section.size % 4 == 0);
-            let base: mem::ConstPtr<abi::GuestFunction> = mem::Ptr::from_bits(section.addr);
-            let count = section.size / 4;
+            log_dbg!(
+                "Calling static initializers for {:?}",
+                env.bins[bin_idx].name
+            );
+            assert!(size % 4 == 0);
+            let base: mem::ConstPtr<abi::GuestFunction> = mem::Ptr::from_bits(addr);
+            let count = size / 4;
             for i in 0..count {
                 let func = env.mem.read(base + i);
                 () = func.call_from_host(&mut env, ());
@@ -405,15 +543,16 @@ impl Environment {
             Some(icon),
             launch_image,
             &options,
-        ));
+        )?);
 
         let mut mem = mem::Mem::new();
+        mem.set_heap_limit(options.guest_memory);
 
         let bins = Vec::new();
 
         let mut objc = objc::ObjC::new();
 
-        let mut dyld = dyld::Dyld::new();
+        let mut dyld = dyld::Dyld::new(&options);
         dyld.do_initial_linking_with_no_bins(&mut mem, &mut objc);
 
         let cpu = cpu::Cpu::new(match options.direct_memory_access {
@@ -427,12 +566,14 @@ impl Environment {
             return_value: None,
             in_start_routine: false, // main thread never terminates
             in_host_function: false,
+            guest_call_depth: 0,
             context: None,
             stack: Some(mem::Mem::MAIN_THREAD_STACK_LOW_END..=0u32.wrapping_sub(1)),
         };
 
         let mut env = Environment {
             startup_time,
+            clock: Clock::new(),
             bundle,
             fs,
             window,
@@ -447,6 +588,10 @@ impl Environment {
             mutex_state: Default::default(),
             framework_state: Default::default(),
             options,
+            recording: Default::default(),
+            profiler: None,
+            automation: None,
+            crash_report: Default::default(),
             gdb_server: None,
             env_vars: Default::default(),
         };
@@ -536,6 +681,34 @@ impl Environment {
         }
     }
 
+    /// Get the return addresses on the current thread's stack, innermost
+    /// first, by following the chain of frame pointers. This stops after
+    /// `limit` addresses, at the thread's exit routine, or at the first frame
+    /// pointer that isn't on the stack. Unlike [Self::stack_trace], this
+    /// doesn't include the link register.
+    pub fn stack_return_addresses(&self, limit: usize) -> Vec<u32> {
+        let Some(stack_range) = self.threads[self.current_thread].stack.clone() else {
+            return Vec::new();
+        };
+        let thread_exit_routine_addr = self.dyld.thread_exit_routine().addr_with_thumb_bit();
+        let mut addresses = Vec::new();
+        let mut fp: mem::ConstPtr<u8> = mem::Ptr::from_bits(self.cpu.regs()[abi::FRAME_POINTER]);
+        while addresses.len() < limit && stack_range.contains(&fp.to_bits()) {
+            let lr: u32 = self.mem.read((fp + 4).cast());
+            fp = self.mem.read(fp.cast());
+            if lr == thread_exit_routine_addr {
+                break;
+            }
+            addresses.push(lr);
+        }
+        addresses
+    }
+
+    /// Get the address range of the current thread's stack, if it has one.
+    pub fn current_stack_range(&self) -> Option<std::ops::RangeInclusive<u32>> {
+        self.threads[self.current_thread].stack.clone()
+    }
+
     /// Create a new thread and return its ID. The `start_routine` and
     /// `user_data` arguments have the same meaning as the last two arguments to
     /// `pthread_create`.
@@ -555,6 +728,7 @@ impl Environment {
             return_value: None,
             in_start_routine: true,
             in_host_function: false,
+            guest_call_depth: 0,
             context: Some(cpu::CpuContext::new()),
             stack: Some(stack_alloc.to_bits()..=(stack_high_addr - 1)),
         });
@@ -593,7 +767,7 @@ impl Environment {
             self.current_thread,
             duration
         );
-        let until = Instant::now().checked_add(duration).unwrap();
+        let until = self.clock.now().checked_add(duration).unwrap();
         self.threads[self.current_thread].blocked_by = ThreadBlock::Sleeping(until);
         // For non tail-call sleeps (such as in NSRunLoop), we want to poll
         // other threads but can't return back to the run loop, since it would
@@ -719,18 +893,51 @@ impl Environment {
         self.threads[self.current_thread].blocked_by = ThreadBlock::Joining(joinee_thread, ptr);
     }
 
+    /// Block the current thread until a socket is ready or the deadline (if
+    /// any) passes, running other threads in the meantime.
+    ///
+    /// Unlike [Self::block_on_mutex] and friends, this returns to the calling
+    /// host function once the thread is unblocked, like a non-tail-call
+    /// [Self::sleep], so that it can retry the operation.
+    pub fn block_on_socket(&mut self, wait: SocketWait, deadline: Option<Instant>) {
+        assert!(matches!(
+            self.threads[self.current_thread].blocked_by,
+            ThreadBlock::NotBlocked
+        ));
+        log_dbg!(
+            "Thread {} blocking on socket wait {:?} (deadline {:?}).",
+            self.current_thread,
+            wait,
+            deadline
+        );
+        self.threads[self.current_thread].blocked_by = ThreadBlock::Socket(wait, deadline);
+        let old_pc = self.cpu.pc_with_thumb_bit();
+        self.cpu.branch(self.dyld.return_to_host_routine());
+        self.run_call();
+        self.cpu.branch(old_pc);
+    }
+
     /// Run the emulator. This is the main loop and won't return until app exit.
     /// Only `main.rs` should call this.
-    pub fn run(&mut self) {
+    ///
+    /// If touchHLE panics while running the app, a crash report is produced
+    /// (see [crash_report]) and an error is returned, so that the window and
+    /// audio can be cleaned up normally.
+    pub fn run(&mut self) -> Result<(), String> {
         // I'm not sure if this actually is unwind-safe, but considering
         // the emulator will crash anyway, maybe this is okay.
         let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| self.run_inner(true)));
         if let Err(e) = res {
-            echo!("Register state immediately after panic:");
-            self.cpu.dump_regs();
-            self.stack_trace();
-            std::panic::resume_unwind(e);
+            crash_report::report_crash(self, &*e);
+            frameworks::audio_toolbox::audio_session::silence_output(self);
+            // Salvage the recording, which is probably useful for debugging.
+            recording::finish_recording(self);
+            profiler::write_report(self);
+            return Err("The app crashed.".to_string());
         }
+        recording::finish_recording(self);
+        profiler::write_report(self);
+        Ok(())
     }
 
     /// Run the emulator until the app returns control to the host. This is for
@@ -742,11 +949,18 @@ impl Environment {
         let was_in_host_function = self.threads[self.current_thread].in_host_function;
         let old_thread = self.current_thread;
         self.threads[self.current_thread].in_host_function = false;
+        self.threads[self.current_thread].guest_call_depth += 1;
         self.run_inner(false);
         assert!(self.current_thread == old_thread);
+        self.threads[self.current_thread].guest_call_depth -= 1;
         self.threads[self.current_thread].in_host_function = was_in_host_function;
     }
 
+    /// Number of host-to-guest calls in progress on the current thread.
+    pub fn guest_call_depth(&self) -> usize {
+        self.threads[self.current_thread].guest_call_depth
+    }
+
     fn switch_thread(&mut self, new_thread: ThreadId) {
         assert!(new_thread != self.current_thread);
 
@@ -880,7 +1094,7 @@ impl Environment {
                         }
                     }
                     dyld::Dyld::SVC_LAZY_LINK | dyld::Dyld::SVC_LINKED_FUNCTIONS_BASE.. => {
-                        if let Some(f) = self.dyld.get_svc_handler(
+                        if let Some((symbol, f)) = self.dyld.get_svc_handler(
                             &self.bins,
                             &mut self.mem,
                             &mut self.cpu,
@@ -889,10 +1103,33 @@ impl Environment {
                         ) {
                             let was_in_host_function =
                                 self.threads[self.current_thread].in_host_function;
+                            let guest_call_depth = self.guest_call_depth();
+                            let return_pc = self.cpu.pc_with_thumb_bit();
+                            let arg_regs: [u32; 3] = self.cpu.regs()[..3].try_into().unwrap();
                             self.threads[self.current_thread].in_host_function = true;
-                            f.call_from_guest(self);
+                            let profiling = profiler::host_call_start(
+                                self,
+                                profiler::HostCall::Function(symbol),
+                            );
+                            // An Objective-C exception thrown by or through
+                            // the host function unwinds the host stack. If
+                            // the guest code that called it has the handler,
+                            // execution resumes there instead of returning.
+                            let res =
+                                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                                    f.call_from_guest(self)
+                                }));
+                            profiler::host_call_end(self, profiling);
                             self.threads[self.current_thread].in_host_function =
                                 was_in_host_function;
+                            if let Err(payload) = res {
+                                if !objc::is_exception_unwind(&*payload) {
+                                    crash_report::host_call_unwound(self, symbol, arg_regs);
+                                }
+                                self.threads[self.current_thread].guest_call_depth =
+                                    guest_call_depth;
+                                objc::catch_exception(self, payload, guest_call_depth, return_pc);
+                            }
                             // Host function might have put the thread to sleep.
                             if let ThreadBlock::NotBlocked =
                                 self.threads[self.current_thread].blocked_by
@@ -933,14 +1170,21 @@ impl Environment {
             };
             let mut step_and_debug = false;
             while ticks > 0 {
-                let state = self.cpu.run_or_step(
-                    &mut self.mem,
-                    if step_and_debug {
-                        None
-                    } else {
-                        Some(&mut ticks)
-                    },
-                );
+                let state = if step_and_debug {
+                    self.cpu.run_or_step(&mut self.mem, None)
+                } else if let Some(ticks_until_sample) =
+                    self.profiler.as_ref().map(|p| p.ticks_until_sample())
+                {
+                    // Stop when the next sample is due.
+                    let slice = ticks.min(ticks_until_sample);
+                    let mut slice_left = slice;
+                    let state = self.cpu.run_or_step(&mut self.mem, Some(&mut slice_left));
+                    ticks -= slice - slice_left;
+                    profiler::guest_ticks_elapsed(self, slice - slice_left);
+                    state
+                } else {
+                    self.cpu.run_or_step(&mut self.mem, Some(&mut ticks))
+                };
                 match self.handle_cpu_state(state, initial_thread, root) {
                     ThreadNextAction::Continue => {
                         if step_and_debug {
@@ -975,6 +1219,9 @@ impl Environment {
                 let mut suitable_thread: Option<ThreadId> = None;
                 let mut next_awakening: Option<Instant> = None;
                 let mut mutex_to_relock: Option<MutexId> = None;
+                // Mutex to lock for a thread woken from a condition variable
+                // wait, and whether that wait timed out.
+                let mut cond_mutex_to_lock: Option<(MutexId, bool)> = None;
                 for i in 0..self.threads.len() {
                     let i = (self.current_thread + 1 + i) % self.threads.len();
                     let candidate = &mut self.threads[i];
@@ -984,7 +1231,7 @@ impl Environment {
                     }
                     match candidate.blocked_by {
                         ThreadBlock::Sleeping(sleeping_until) => {
-                            if sleeping_until <= Instant::now() {
+                            if sleeping_until <= self.clock.now() {
                                 log_dbg!("Thread {} finished sleeping.", i);
                                 candidate.blocked_by = ThreadBlock::NotBlocked;
                                 suitable_thread = Some(i);
@@ -1026,25 +1273,62 @@ impl Environment {
                                 break;
                             }
                         }
-                        ThreadBlock::Condition(cond) => {
-                            let host_cond = self
+                        ThreadBlock::Condition(cond_id, mutex_id, deadline) => {
+                            let cond_state = &mut self.libc_state.pthread.cond;
+                            let timed_out = if !cond_state.is_waiting(cond_id, i) {
+                                false
+                            } else if let Some(deadline) =
+                                deadline.filter(|&deadline| deadline <= self.clock.now())
+                            {
+                                log_dbg!(
+                                    "Thread {}'s wait on cond var #{} timed out (deadline {:?}).",
+                                    i,
+                                    cond_id,
+                                    deadline
+                                );
+                                cond_state.stop_waiting(cond_id, i);
+                                true
+                            } else {
+                                if let Some(deadline) = deadline {
+                                    next_awakening = match next_awakening {
+                                        None => Some(deadline),
+                                        Some(other) => Some(other.min(deadline)),
+                                    };
+                                }
+                                continue;
+                            };
+                            log_dbg!("Thread {} is unblocking on cond var #{}.", i, cond_id);
+                            self.threads[i].blocked_by = ThreadBlock::NotBlocked;
+                            suitable_thread = Some(i);
+                            cond_mutex_to_lock = Some((mutex_id, timed_out));
+                            break;
+                        }
+                        ThreadBlock::RwLock(rwlock_id, write) => {
+                            if self
                                 .libc_state
                                 .pthread
-                                .cond
-                                .condition_variables
-                                .get(&cond)
-                                .unwrap();
-                            if host_cond.done {
+                                .rwlock
+                                .try_lock_for_blocked_thread(rwlock_id, i, write)
+                            {
                                 log_dbg!(
-                                    "Thread {} is unblocking on cond var {:?}.",
-                                    self.current_thread,
-                                    cond
+                                    "Thread {} was unblocked by taking reader/writer lock #{}.",
+                                    i,
+                                    rwlock_id
+                                );
+                                self.threads[i].blocked_by = ThreadBlock::NotBlocked;
+                                suitable_thread = Some(i);
+                                break;
+                            }
+                        }
+                        ThreadBlock::Once(once_control) => {
+                            if libc::pthread::once::once_is_done(self, once_control) {
+                                log_dbg!(
+                                    "Thread {} was unblocked by pthread_once {:?} finishing.",
+                                    i,
+                                    once_control
                                 );
                                 self.threads[i].blocked_by = ThreadBlock::NotBlocked;
                                 suitable_thread = Some(i);
-                                let used_mutex =
-                                    self.libc_state.pthread.cond.mutexes.remove(&cond).unwrap();
-                                mutex_to_relock = Some(used_mutex.mutex_id);
                                 break;
                             }
                         }
@@ -1068,6 +1352,27 @@ impl Environment {
                                 break;
                             }
                         }
+                        ThreadBlock::Socket(ref wait, deadline) => {
+                            let wait = wait.clone();
+                            let now = self.clock.now();
+                            let ready = deadline.map_or(false, |deadline| deadline <= now)
+                                || wait.is_ready(self);
+                            if ready {
+                                log_dbg!("Thread {} is unblocking on socket wait.", i);
+                                self.threads[i].blocked_by = ThreadBlock::NotBlocked;
+                                suitable_thread = Some(i);
+                                break;
+                            }
+                            // There's no way to be notified when a host socket
+                            // becomes ready, so poll it.
+                            let poll_at = now + libc_socket::POLL_INTERVAL;
+                            let poll_at =
+                                deadline.map_or(poll_at, |deadline| deadline.min(poll_at));
+                            next_awakening = match next_awakening {
+                                None => Some(poll_at),
+                                Some(other) => Some(other.min(poll_at)),
+                            };
+                        }
                         ThreadBlock::DeferredReturn => {
                             if i == initial_thread {
                                 log_dbg!("Thread {} is now able to return, returning", i);
@@ -1093,11 +1398,20 @@ impl Environment {
                     if let Some(mutex_id) = mutex_to_relock {
                         self.relock_unblocked_mutex(mutex_id);
                     }
+                    if let Some((mutex_id, timed_out)) = cond_mutex_to_lock {
+                        if timed_out {
+                            // Replace pthread_cond_timedwait's return value.
+                            self.cpu.regs_mut()[0] = libc::errno::ETIMEDOUT as u32;
+                        }
+                        // If another thread holds the mutex, this blocks the
+                        // thread again until it can take it.
+                        self.lock_mutex(mutex_id).unwrap();
+                    }
                     break;
                 // All suitable threads are blocked and at least one is asleep.
                 // Sleep until one of them wakes up.
                 } else if let Some(next_awakening) = next_awakening {
-                    let duration = next_awakening.duration_since(Instant::now());
+                    let duration = self.clock.host_duration_until(next_awakening);
                     log_dbg!("All threads blocked/asleep, sleeping for {:?}.", duration);
                     std::thread::sleep(duration);
                     // Try again, there should be some thread awake now (or
@@ -0,0 +1,227 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! The guest's clock, and fast-forward and pause controls that affect it.
+//!
+//! Everything the app can observe the passage of time through (timers, display
+//! links, `mach_absolute_time()`, `NSDate`, `gettimeofday()`, sleeping, the
+//! frame limiter…) must use [Clock::now] or [Clock::system_now] rather than
+//! [Instant::now] or [SystemTime::now]. When fast-forwarding, the guest clock
+//! runs faster than real time, so all of these stay consistent with each other
+//! and with the framerate. When paused, the guest clock stops and no guest code
+//! runs.
+//!
+//! Host waits for some guest time to pass must be converted with
+//! [Clock::host_duration_until].
+
+use super::Environment;
+use crate::frameworks::{audio_toolbox, openal};
+use crate::options::FastForwardAudio;
+use crate::window::Event;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long the speed indicator is shown for after the speed changes.
+const INDICATOR_DURATION: Duration = Duration::from_millis(1500);
+
+/// If no frame is presented this long after a pause was requested, pause
+/// anyway.
+const PAUSE_REQUEST_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// What [crate::gles::present::present_frame] should overlay to show the
+/// current speed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SpeedIndicator {
+    Normal,
+    /// The value is the speed multiplier.
+    FastForward(f64),
+    Paused,
+}
+
+pub struct Clock {
+    /// Host time when the speed last changed.
+    host_base: Instant,
+    /// Guest time when the speed last changed.
+    guest_base: Instant,
+    /// Guest time when the clock was created, which corresponds to
+    /// `system_start`.
+    guest_start: Instant,
+    system_start: SystemTime,
+    /// How many seconds of guest time pass per second of host time.
+    speed: f64,
+    paused: bool,
+    /// Host time when a pause was requested. The pause takes effect once the
+    /// next frame has been presented, so that the frame on screen is current
+    /// and shows the pause indicator.
+    pause_requested: Option<Instant>,
+    /// Host time until which the speed indicator should be shown.
+    indicator_until: Option<Instant>,
+}
+
+impl Clock {
+    pub fn new() -> Clock {
+        let now = Instant::now();
+        Clock {
+            host_base: now,
+            guest_base: now,
+            guest_start: now,
+            system_start: SystemTime::now(),
+            speed: 1.0,
+            paused: false,
+            pause_requested: None,
+            indicator_until: None,
+        }
+    }
+
+    /// The current guest time. Use this instead of [Instant::now].
+    pub fn now(&self) -> Instant {
+        if self.paused {
+            return self.guest_base;
+        }
+        let host_elapsed = Instant::now().duration_since(self.host_base);
+        self.guest_base + host_elapsed.mul_f64(self.speed)
+    }
+
+    /// The current guest wall-clock time. Use this instead of
+    /// [SystemTime::now].
+    pub fn system_now(&self) -> SystemTime {
+        self.system_start + self.now().duration_since(self.guest_start)
+    }
+
+    /// How long the host has to wait for the guest clock to reach `deadline`.
+    pub fn host_duration_until(&self, deadline: Instant) -> Duration {
+        deadline
+            .saturating_duration_since(self.now())
+            .div_f64(self.speed)
+    }
+
+    /// Change the speed or pause state, keeping the guest time continuous.
+    fn rebase(&mut self, speed: f64, paused: bool) {
+        self.guest_base = self.now();
+        self.host_base = Instant::now();
+        self.speed = speed;
+        self.paused = paused;
+        self.indicator_until = Some(self.host_base + INDICATOR_DURATION);
+    }
+
+    /// What speed indicator should be drawn over the current frame, if any.
+    pub fn speed_indicator(&self) -> Option<SpeedIndicator> {
+        if self.paused || self.pause_requested.is_some() {
+            Some(SpeedIndicator::Paused)
+        } else if self.indicator_until? > Instant::now() {
+            Some(if self.speed == 1.0 {
+                SpeedIndicator::Normal
+            } else {
+                SpeedIndicator::FastForward(self.speed)
+            })
+        } else {
+            None
+        }
+    }
+
+    /// The pitch multiplier and gain to apply to audio output, so that audio
+    /// is consumed at the same rate the app produces it while fast-forwarding.
+    pub fn audio_pitch_and_gain(&self, ff_audio: FastForwardAudio) -> (f32, f32) {
+        if self.speed == 1.0 {
+            return (1.0, 1.0);
+        }
+        let gain = match ff_audio {
+            FastForwardAudio::SpeedUp => 1.0,
+            FastForwardAudio::Mute => 0.0,
+        };
+        (self.speed as f32, gain)
+    }
+}
+
+impl Environment {
+    fn set_speed(&mut self, speed: f64) {
+        self.clock.rebase(speed, self.clock.paused);
+        openal::apply_fast_forward(self);
+        audio_toolbox::audio_queue::apply_fast_forward(self);
+    }
+
+    /// Start or stop fast-forwarding (see `--ff-speed=`).
+    pub fn toggle_fast_forward(&mut self) {
+        let speed = if self.clock.speed == 1.0 {
+            self.options.fast_forward_speed
+        } else {
+            1.0
+        };
+        echo!("Speed: {}×", speed);
+        self.set_speed(speed);
+    }
+
+    /// Request a pause, or resume if already paused.
+    pub fn toggle_pause(&mut self) {
+        if self.clock.paused || self.clock.pause_requested.is_some() {
+            echo!("Resuming.");
+            self.clock.pause_requested = None;
+            self.clock.rebase(self.clock.speed, false);
+        } else {
+            echo!("Pausing.");
+            self.clock.pause_requested = Some(Instant::now());
+        }
+    }
+
+    /// For use by code that presents frames: makes a requested pause take
+    /// effect.
+    pub fn frame_presented(&mut self) {
+        if self.clock.pause_requested.take().is_some() {
+            self.clock.rebase(self.clock.speed, true);
+        }
+    }
+
+    /// For use by `NSRunLoop` via [crate::frameworks::uikit::handle_events]:
+    /// if the app is paused, block until it is resumed, the user advances a
+    /// single frame, or the user quits.
+    ///
+    /// Only speed control events are handled while paused. Other events are
+    /// left in the queue for after the pause.
+    pub fn wait_while_paused(&mut self) {
+        if let Some(requested) = self.clock.pause_requested {
+            if requested.elapsed() >= PAUSE_REQUEST_TIMEOUT {
+                self.frame_presented();
+            }
+        }
+        if !self.clock.paused {
+            return;
+        }
+
+        let mut deferred_events = Vec::new();
+        'paused: loop {
+            std::thread::sleep(Duration::from_millis(1000 / 60));
+
+            self.window.as_mut().unwrap().poll_for_events(&self.options);
+            while let Some(event) = self.window.as_mut().unwrap().pop_event() {
+                match event {
+                    Event::TogglePause => {
+                        self.toggle_pause();
+                        break 'paused;
+                    }
+                    Event::AdvanceFrame => {
+                        // Run until the next frame is presented.
+                        self.clock.rebase(self.clock.speed, false);
+                        self.clock.pause_requested = Some(Instant::now());
+                        break 'paused;
+                    }
+                    Event::ToggleFastForward => {
+                        // This takes effect once resumed.
+                        self.toggle_fast_forward();
+                    }
+                    Event::Quit => {
+                        // Let the normal event handling deal with this.
+                        deferred_events.push(event);
+                        self.clock.rebase(self.clock.speed, false);
+                        break 'paused;
+                    }
+                    _ => deferred_events.push(event),
+                }
+            }
+        }
+        self.window
+            .as_mut()
+            .unwrap()
+            .push_front_events(deferred_events);
+    }
+}
@@ -14,11 +14,22 @@
 //! dependencies.
 
 use crate::paths;
-use rusttype::{Point, Scale};
+use rusttype::{point, GlyphId, Point, PositionedGlyph, Scale};
+use std::borrow::Cow;
 use std::io::Read;
 
 pub struct Font {
     font: rusttype::Font<'static>,
+    /// Fonts to take glyphs from when this font doesn't have them.
+    fallbacks: Vec<rusttype::Font<'static>>,
+}
+
+/// Names from a font file's `name` table.
+#[derive(Default)]
+pub struct FontNames {
+    pub family: Option<String>,
+    pub full_name: Option<String>,
+    pub postscript_name: Option<String>,
 }
 
 pub enum TextAlignment {
@@ -33,6 +44,30 @@ pub enum WrapMode {
     Char,
 }
 
+/// How to show that text didn't fit within the maximum number of lines.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Truncation {
+    /// The last line is shown as-is, and the rest is dropped.
+    Clip,
+    /// The end of the last line is replaced with an ellipsis.
+    Tail,
+    /// The start of the last line is replaced with an ellipsis.
+    Head,
+    /// The middle of the last line is replaced with an ellipsis.
+    Middle,
+}
+
+/// Constraints for fitting text in a box.
+#[derive(Copy, Clone)]
+pub struct Wrap {
+    /// Maximum width of a line.
+    pub width: f32,
+    pub mode: WrapMode,
+    /// Maximum number of lines, if any. See [Font::max_lines_for_height].
+    pub max_lines: Option<usize>,
+    pub truncation: Truncation,
+}
+
 fn scale(font_size: f32) -> Scale {
     // iPhone OS's interpretation of font size is slightly different, reason
     // unknown. This is not the same as the Windows pt vs Mac pt issue.
@@ -83,7 +118,35 @@ impl Font {
             panic!("Couldn't parse bundled font file {:?}. This probably means the file is corrupt. Try re-downloading it.", path);
         };
 
-        Font { font }
+        Font {
+            font,
+            fallbacks: Vec::new(),
+        }
+    }
+
+    /// Like [Self::from_resource_file], but for fonts that are optional, so
+    /// it returns [None] rather than panicking if the file is missing.
+    pub fn try_from_resource_file(filename: &str) -> Option<Font> {
+        let path = format!("{}/{}", paths::FONTS_DIR, filename);
+        let mut bytes = Vec::new();
+        paths::ResourceFile::open(&path)
+            .and_then(|mut f| f.get().read_to_end(&mut bytes).map_err(|e| e.to_string()))
+            .ok()?;
+        Self::from_bytes(bytes)
+    }
+
+    /// Load a TrueType or OpenType font file, e.g. one from an app bundle.
+    pub fn from_bytes(bytes: Vec<u8>) -> Option<Font> {
+        Some(Font {
+            font: rusttype::Font::try_from_vec(bytes)?,
+            fallbacks: Vec::new(),
+        })
+    }
+
+    /// Use glyphs from another font for any characters this font lacks.
+    /// Fallbacks are tried in the order they were added.
+    pub fn add_fallback(&mut self, fallback: &Font) {
+        self.fallbacks.push(fallback.font.clone());
     }
 
     pub fn sans_regular() -> Font {
@@ -107,12 +170,62 @@ impl Font {
         (v_metrics.ascent - v_metrics.descent, v_metrics.line_gap)
     }
 
+    /// Lay out a single line of text, like [rusttype::Font::layout], except
+    /// that glyphs this font lacks are taken from the fallback fonts.
+    fn layout(&self, text: &str, scale: Scale, start: Point<f32>) -> Vec<PositionedGlyph<'static>> {
+        let mut glyphs = Vec::new();
+        let mut caret = start.x;
+        let mut last: Option<(usize, GlyphId)> = None;
+        for c in text.chars() {
+            if c.is_control() {
+                continue;
+            }
+            // Glyph 0 is always the "missing glyph" box.
+            let (font_idx, glyph) = std::iter::once(&self.font)
+                .chain(self.fallbacks.iter())
+                .map(|font| font.glyph(c))
+                .enumerate()
+                .find(|(_, glyph)| glyph.id().0 != 0)
+                .unwrap_or_else(|| (0, self.font.glyph(c)));
+            let glyph = glyph.scaled(scale);
+            // Kerning is only possible between glyphs from the same font.
+            if let Some((last_font_idx, last_id)) = last {
+                if last_font_idx == font_idx {
+                    caret += glyph.font().pair_kerning(scale, last_id, glyph.id());
+                }
+            }
+            let advance_width = glyph.h_metrics().advance_width;
+            let glyph = glyph.positioned(point(caret, start.y));
+            last = Some((font_idx, glyph.id()));
+            caret += advance_width;
+            glyphs.push(glyph);
+        }
+        glyphs
+    }
+
+    /// Get the distance between the tops of two consecutive lines of text.
+    pub fn leading(&self, font_size: f32) -> f32 {
+        let (line_height, line_gap) = self.line_height_and_gap(font_size);
+        line_height + line_gap
+    }
+
+    /// Get the number of lines of text that fit within a given height. This is
+    /// always at least one.
+    pub fn max_lines_for_height(&self, font_size: f32, height: f32) -> usize {
+        let (line_height, line_gap) = self.line_height_and_gap(font_size);
+        // The last line has no gap after it. The small extra amount protects
+        // against rounding errors when the height came from
+        // [Self::calculate_text_size].
+        let lines = ((height + line_gap) / (line_height + line_gap) + 0.001).floor();
+        (lines as usize).max(1)
+    }
+
     /// Calculate the width of a line. This does not handle newlines!
     fn calculate_line_width(&self, font_size: f32, line: &str) -> f32 {
         let mut line_x_min: f32 = 0.0;
         let mut line_x_max: f32 = 0.0;
 
-        for glyph in self.font.layout(line, scale(font_size), Default::default()) {
+        for glyph in self.layout(line, scale(font_size), Point { x: 0.0, y: 0.0 }) {
             let position = glyph.position();
             let h_metrics = glyph.unpositioned().h_metrics();
 
@@ -135,8 +248,97 @@ impl Font {
         line_x_max.ceil() - line_x_min.floor()
     }
 
-    /// Break text into lines with known widths.
+    /// Break text into lines with known widths, then drop and truncate lines
+    /// as needed.
     fn break_lines<'a>(
+        &self,
+        font_size: f32,
+        text: &'a str,
+        wrap: Option<Wrap>,
+    ) -> Vec<(f32, Cow<'a, str>)> {
+        let lines = self.wrap_lines(font_size, text, wrap.map(|wrap| (wrap.width, wrap.mode)));
+        let mut lines: Vec<_> = lines
+            .into_iter()
+            .map(|(width, line)| (width, Cow::Borrowed(line)))
+            .collect();
+
+        let Some(Wrap {
+            width: wrap_width,
+            max_lines: Some(max_lines),
+            truncation,
+            ..
+        }) = wrap
+        else {
+            return lines;
+        };
+        if lines.len() <= max_lines.max(1) {
+            return lines;
+        }
+        lines.truncate(max_lines.max(1));
+        if truncation == Truncation::Clip {
+            return lines;
+        }
+
+        // The last line gets all the remaining text. The lines are slices of
+        // the original text, so their offsets can be found from pointers.
+        let (_, last_line) = lines.pop().unwrap();
+        let Cow::Borrowed(last_line) = last_line else {
+            unreachable!();
+        };
+        let offset = last_line.as_ptr() as usize - text.as_ptr() as usize;
+        let remaining = &text[offset..];
+        let truncated = self.truncate_line(font_size, remaining, wrap_width, truncation);
+        lines.push((
+            self.calculate_line_width(font_size, &truncated),
+            Cow::Owned(truncated),
+        ));
+        lines
+    }
+
+    /// Fit text on a single line with the given width by replacing part of it
+    /// with an ellipsis. Newlines in the text are treated as spaces.
+    fn truncate_line(
+        &self,
+        font_size: f32,
+        text: &str,
+        width: f32,
+        truncation: Truncation,
+    ) -> String {
+        let text = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let chars: Vec<char> = text.chars().collect();
+
+        let with_ellipsis = |kept: usize| -> String {
+            let (head_len, tail_len) = match truncation {
+                Truncation::Tail => (kept, 0),
+                Truncation::Head => (0, kept),
+                Truncation::Middle => ((kept + 1) / 2, kept / 2),
+                Truncation::Clip => unreachable!(),
+            };
+            let head: String = chars[..head_len].iter().collect();
+            let tail: String = chars[chars.len() - tail_len..].iter().collect();
+            format!("{}\u{2026}{}", head.trim_end(), tail.trim_start())
+        };
+
+        // Find the largest number of characters that fit by binary search.
+        let (mut low, mut high) = (0, chars.len());
+        while low < high {
+            let mid = (low + high + 1) / 2;
+            if self.calculate_line_width(font_size, &with_ellipsis(mid)) <= width {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+        with_ellipsis(low)
+    }
+
+    /// Break text into lines with known widths.
+    fn wrap_lines<'a>(
         &self,
         font_size: f32,
         text: &'a str,
@@ -219,7 +421,7 @@ impl Font {
                             // Try to break the word.
                             let word_end = wrap_points[next_wrap_point_idx];
                             let word = &line[line_start..word_end];
-                            let broken_words = self.break_lines(
+                            let broken_words = self.wrap_lines(
                                 font_size,
                                 word,
                                 Some((wrap_width, WrapMode::Char)),
@@ -262,13 +464,13 @@ impl Font {
         &self,
         font_size: f32,
         text: &str,
-        wrap: Option<(f32, WrapMode)>,
+        wrap: Option<Wrap>,
     ) -> (f32, f32) {
         let lines = self.break_lines(font_size, text, wrap);
 
         let width = lines
             .iter()
-            .fold(0f32, |widest, &(line_width, _line)| widest.max(line_width));
+            .fold(0f32, |widest, &(line_width, _)| widest.max(line_width));
         let (line_height, line_gap) = self.line_height_and_gap(font_size);
         let height =
             line_height * (lines.len() as f32) + line_gap * (lines.len().saturating_sub(1) as f32);
@@ -283,7 +485,7 @@ impl Font {
         font_size: f32,
         text: &str,
         origin: (f32, f32),
-        wrap: Option<(f32, WrapMode)>,
+        wrap: Option<Wrap>,
         alignment: TextAlignment,
         mut draw_glyph: F,
     ) {
@@ -313,8 +515,8 @@ impl Font {
                 TextAlignment::Center => -line_width / 2.0,
                 TextAlignment::Right => -line_width,
             };
-            for glyph in self.font.layout(
-                line_text,
+            for glyph in self.layout(
+                &line_text,
                 scale(font_size),
                 Point {
                     x: origin.0 + line_x_offset,
@@ -332,7 +534,10 @@ impl Font {
                 // TODO: Refactor this method to support y clipping too.
                 // It's not mandatory since the caller can do it, but it would
                 // be more efficient.
-                if let Some((wrap_width, _)) = wrap {
+                if let Some(Wrap {
+                    width: wrap_width, ..
+                }) = wrap
+                {
                     if glyph_bounds.min.x as f32 > origin.0 + wrap_width {
                         // Avoid wasting effort on glyphs that are entirely
                         // clipped. Partial clipping is the responsibility of
@@ -364,3 +569,89 @@ impl Font {
         }
     }
 }
+
+/// Read the family, full and PostScript names from a TrueType or OpenType
+/// font file's `name` table. Any names that can't be found are [None].
+pub fn parse_font_names(bytes: &[u8]) -> FontNames {
+    let read_u16 = |offset: usize| -> Option<u16> {
+        Some(u16::from_be_bytes(
+            bytes.get(offset..offset + 2)?.try_into().unwrap(),
+        ))
+    };
+    let read_u32 = |offset: usize| -> Option<u32> {
+        Some(u32::from_be_bytes(
+            bytes.get(offset..offset + 4)?.try_into().unwrap(),
+        ))
+    };
+
+    let mut names = FontNames::default();
+
+    // Find the name table in the table directory.
+    let Some(num_tables) = read_u16(4) else {
+        return names;
+    };
+    let Some(name_table) = (0..num_tables as usize)
+        .map(|i| 12 + i * 16)
+        .find(|&record| bytes.get(record..record + 4) == Some(b"name"))
+        .and_then(|record| read_u32(record + 8))
+    else {
+        return names;
+    };
+    let name_table = name_table as usize;
+
+    let (Some(count), Some(string_offset)) = (read_u16(name_table + 2), read_u16(name_table + 4))
+    else {
+        return names;
+    };
+    let strings = name_table + string_offset as usize;
+    // Priorities of the names found so far, for family, full and PostScript
+    // names respectively.
+    let mut priorities = [0; 3];
+    for i in 0..count as usize {
+        let record = name_table + 6 + i * 12;
+        let (Some(platform_id), Some(language_id), Some(name_id), Some(length), Some(offset)) = (
+            read_u16(record),
+            read_u16(record + 4),
+            read_u16(record + 6),
+            read_u16(record + 8),
+            read_u16(record + 10),
+        ) else {
+            break;
+        };
+        let start = strings + offset as usize;
+        let Some(data) = bytes.get(start..start + length as usize) else {
+            continue;
+        };
+        let name = match platform_id {
+            // Unicode and Windows platforms use UTF-16BE.
+            0 | 3 => {
+                let units: Vec<u16> = data
+                    .chunks_exact(2)
+                    .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                    .collect();
+                String::from_utf16_lossy(&units)
+            }
+            // The Mac platform uses Mac Roman, which is ASCII-compatible.
+            1 => data.iter().map(|&byte| byte as char).collect(),
+            _ => continue,
+        };
+        let (field, priority) = match name_id {
+            1 => (&mut names.family, &mut priorities[0]),
+            4 => (&mut names.full_name, &mut priorities[1]),
+            6 => (&mut names.postscript_name, &mut priorities[2]),
+            _ => continue,
+        };
+        // US English names on the Windows platform are preferred, since
+        // they're the ones apps are most likely to use.
+        let new_priority = match (platform_id, language_id) {
+            (3, 0x409) => 3,
+            (3, _) => 2,
+            _ => 1,
+        };
+        if new_priority > *priority {
+            *field = Some(name);
+            *priority = new_priority;
+        }
+    }
+    names
+}
@@ -20,16 +20,23 @@
 #![allow(non_upper_case_globals)] // Lots of Apple constants begin with "k"
 #![allow(clippy::too_many_arguments)] // It's not our fault!
 
+pub mod accelerate;
 pub mod audio_toolbox;
 pub mod av_audio;
 pub mod carbon_core;
+pub mod cf_network;
 pub mod core_animation;
 pub mod core_audio_types;
 pub mod core_foundation;
 pub mod core_graphics;
+pub mod core_location;
 pub mod dnssd;
 pub mod foundation;
+pub mod game_kit;
+pub mod libsqlite3;
+pub mod libz;
 pub mod media_player;
+pub mod message_ui;
 pub mod openal;
 pub mod opengles;
 pub mod store_kit;
@@ -38,11 +45,17 @@ pub mod uikit;
 /// Container for state of various child modules
 #[derive(Default)]
 pub struct State {
+    accelerate: accelerate::State,
     audio_toolbox: audio_toolbox::State,
     core_animation: core_animation::State,
+    core_location: core_location::State,
     foundation: foundation::State,
+    game_kit: game_kit::State,
+    libsqlite3: libsqlite3::State,
+    libz: libz::State,
     media_player: media_player::State,
     openal: openal::State,
     opengles: opengles::State,
+    store_kit: store_kit::State,
     uikit: uikit::State,
 }
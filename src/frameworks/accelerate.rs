@@ -0,0 +1,501 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! The Accelerate framework. Only a subset of vDSP is implemented.
+//!
+//! vDSP functions take vectors as a pointer and a stride, which is counted in
+//! elements, not bytes, and may be zero or negative. Each vector is read from
+//! or written to guest memory in one go to avoid per-element bounds checks.
+//!
+//! Resources:
+//! - Apple's [vDSP Programming Guide](https://developer.apple.com/library/archive/documentation/Performance/Conceptual/vDSP_Programming_Guide/Introduction/Introduction.html), in particular "Using Fourier Transforms" for the packing of real FFT data.
+
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::mem::{ConstPtr, GuestUSize, Mem, MutPtr, MutVoidPtr, Ptr, SafeRead};
+use crate::Environment;
+use std::collections::HashMap;
+
+#[derive(Default)]
+pub struct State {
+    fft_setups: HashMap<FFTSetup, FftSetupHostObject>,
+}
+impl State {
+    fn get(env: &mut Environment) -> &mut Self {
+        &mut env.framework_state.accelerate
+    }
+}
+
+#[allow(non_camel_case_types)]
+type vDSP_Stride = i32;
+#[allow(non_camel_case_types)]
+type vDSP_Length = u32;
+
+/// Opaque pointer. The guest allocation only serves as a unique handle.
+type FFTSetup = MutVoidPtr;
+
+type FFTRadix = i32;
+const kFFTRadix2: FFTRadix = 0;
+
+type FFTDirection = i32;
+const kFFTDirection_Forward: FFTDirection = 1;
+const kFFTDirection_Inverse: FFTDirection = -1;
+
+#[derive(Copy, Clone)]
+#[repr(C, packed)]
+struct DSPSplitComplex {
+    realp: MutPtr<f32>,
+    imagp: MutPtr<f32>,
+}
+unsafe impl SafeRead for DSPSplitComplex {}
+
+struct FftSetupHostObject {
+    log2n: vDSP_Length,
+    /// `e^(-2πik/n)` for `k` in `0..n/2`, where `n` is `1 << log2n`. Smaller
+    /// transforms use every `m`th entry.
+    twiddles: Vec<Complex>,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct Complex {
+    re: f32,
+    im: f32,
+}
+impl Complex {
+    fn add(self, other: Complex) -> Complex {
+        Complex {
+            re: self.re + other.re,
+            im: self.im + other.im,
+        }
+    }
+    fn sub(self, other: Complex) -> Complex {
+        Complex {
+            re: self.re - other.re,
+            im: self.im - other.im,
+        }
+    }
+    fn mul(self, other: Complex) -> Complex {
+        Complex {
+            re: self.re * other.re - self.im * other.im,
+            im: self.re * other.im + self.im * other.re,
+        }
+    }
+    fn conj(self) -> Complex {
+        Complex {
+            re: self.re,
+            im: -self.im,
+        }
+    }
+}
+
+impl FftSetupHostObject {
+    fn new(log2n: vDSP_Length) -> Self {
+        let n = 1usize << log2n;
+        let twiddles = (0..n / 2)
+            .map(|k| {
+                let angle = -2.0 * std::f64::consts::PI * (k as f64) / (n as f64);
+                Complex {
+                    re: angle.cos() as f32,
+                    im: angle.sin() as f32,
+                }
+            })
+            .collect();
+        FftSetupHostObject { log2n, twiddles }
+    }
+
+    /// Unscaled in-place radix-2 complex FFT. The length of `data` must be a
+    /// power of two no larger than `1 << self.log2n`.
+    fn fft(&self, data: &mut [Complex], direction: FFTDirection) {
+        let n = data.len();
+        assert!(n.is_power_of_two() && n <= 1 << self.log2n);
+
+        if n == 1 {
+            return;
+        }
+
+        // Bit-reversal permutation
+        let bits = n.trailing_zeros();
+        for i in 0..n {
+            let j = i.reverse_bits() >> (usize::BITS - bits);
+            if i < j {
+                data.swap(i, j);
+            }
+        }
+
+        let mut len = 2;
+        while len <= n {
+            let twiddle_step = (1 << self.log2n) / len;
+            for start in (0..n).step_by(len) {
+                for k in 0..len / 2 {
+                    let mut w = self.twiddles[k * twiddle_step];
+                    if direction == kFFTDirection_Inverse {
+                        w = w.conj();
+                    }
+                    let a = data[start + k];
+                    let b = data[start + k + len / 2].mul(w);
+                    data[start + k] = a.add(b);
+                    data[start + k + len / 2] = a.sub(b);
+                }
+            }
+            len *= 2;
+        }
+    }
+
+    /// In-place real FFT of `2 * realp.len()` values, which are stored in the
+    /// even-odd split format produced by `vDSP_ctoz`. The frequency domain
+    /// uses Apple's packed format: `realp[0]` and `imagp[0]` are the purely
+    /// real DC and Nyquist terms, and the rest are the positive frequencies.
+    ///
+    /// Like vDSP, the forward transform's results are twice the usual DFT,
+    /// and the inverse isn't scaled, so a round trip scales by `2 * n`.
+    fn real_fft(&self, realp: &mut [f32], imagp: &mut [f32], direction: FFTDirection) {
+        let half = realp.len();
+        assert!(imagp.len() == half && half != 0);
+        let n = half * 2;
+
+        // TODO: Use an n/2 complex FFT instead of an n-point one.
+        let mut data = vec![Complex::default(); n];
+        if direction == kFFTDirection_Forward {
+            for k in 0..half {
+                data[2 * k].re = realp[k];
+                data[2 * k + 1].re = imagp[k];
+            }
+            self.fft(&mut data, direction);
+            realp[0] = 2.0 * data[0].re;
+            imagp[0] = 2.0 * data[half].re;
+            for k in 1..half {
+                realp[k] = 2.0 * data[k].re;
+                imagp[k] = 2.0 * data[k].im;
+            }
+        } else {
+            data[0].re = realp[0];
+            data[half].re = imagp[0];
+            for k in 1..half {
+                let value = Complex {
+                    re: realp[k],
+                    im: imagp[k],
+                };
+                data[k] = value;
+                data[n - k] = value.conj();
+            }
+            self.fft(&mut data, direction);
+            for k in 0..half {
+                realp[k] = data[2 * k].re;
+                imagp[k] = data[2 * k + 1].re;
+            }
+        }
+    }
+}
+
+/// The part of guest memory covered by a strided vector.
+struct VectorSpan {
+    /// Address of the element with the lowest address.
+    base: u32,
+    size: GuestUSize,
+    /// Byte offset of the first element from `base`.
+    first: i64,
+    /// Byte offset between elements.
+    step: i64,
+}
+impl VectorSpan {
+    fn new(addr: u32, stride: vDSP_Stride, count: vDSP_Length) -> Self {
+        assert!(count != 0);
+        let step = i64::from(stride) * 4;
+        let last = (i64::from(count) - 1) * step;
+        let lowest = last.min(0);
+        VectorSpan {
+            base: (i64::from(addr) + lowest) as u32,
+            size: (last.unsigned_abs() + 4) as GuestUSize,
+            first: -lowest,
+            step,
+        }
+    }
+
+    fn offset(&self, index: usize) -> usize {
+        (self.first + index as i64 * self.step) as usize
+    }
+}
+
+fn read_vector<const MUT: bool>(
+    mem: &Mem,
+    ptr: Ptr<f32, MUT>,
+    stride: vDSP_Stride,
+    count: vDSP_Length,
+) -> Vec<f32> {
+    if count == 0 {
+        return Vec::new();
+    }
+    let span = VectorSpan::new(ptr.to_bits(), stride, count);
+    let bytes = mem.bytes_at(Ptr::<u8, false>::from_bits(span.base), span.size);
+    (0..count as usize)
+        .map(|i| {
+            let offset = span.offset(i);
+            f32::from_le_bytes(bytes[offset..][..4].try_into().unwrap())
+        })
+        .collect()
+}
+
+fn write_vector(mem: &mut Mem, ptr: MutPtr<f32>, stride: vDSP_Stride, values: &[f32]) {
+    if values.is_empty() {
+        return;
+    }
+    let span = VectorSpan::new(ptr.to_bits(), stride, values.len() as vDSP_Length);
+    let bytes = mem.bytes_at_mut(Ptr::from_bits(span.base), span.size);
+    for (i, value) in values.iter().enumerate() {
+        let offset = span.offset(i);
+        bytes[offset..][..4].copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn vDSP_create_fftsetup(env: &mut Environment, log2n: vDSP_Length, radix: FFTRadix) -> FFTSetup {
+    if radix != kFFTRadix2 {
+        log!(
+            "TODO: vDSP_create_fftsetup({}, {}) with unsupported radix, returning NULL",
+            log2n,
+            radix
+        );
+        return Ptr::null();
+    }
+    // One word, just to get a unique pointer.
+    let setup = env.mem.alloc(4);
+    State::get(env)
+        .fft_setups
+        .insert(setup, FftSetupHostObject::new(log2n));
+    log_dbg!("vDSP_create_fftsetup({}, {}) => {:?}", log2n, radix, setup);
+    setup
+}
+
+fn vDSP_destroy_fftsetup(env: &mut Environment, setup: FFTSetup) {
+    if setup.is_null() {
+        return;
+    }
+    State::get(env).fft_setups.remove(&setup).unwrap();
+    env.mem.free(setup);
+}
+
+fn vDSP_fft_zrip(
+    env: &mut Environment,
+    setup: FFTSetup,
+    c: ConstPtr<DSPSplitComplex>,
+    stride: vDSP_Stride,
+    log2n: vDSP_Length,
+    direction: FFTDirection,
+) {
+    assert!(direction == kFFTDirection_Forward || direction == kFFTDirection_Inverse);
+    assert!(log2n >= 1);
+
+    let DSPSplitComplex { realp, imagp } = env.mem.read(c);
+    let half = 1 << (log2n - 1);
+    let mut real = read_vector(&env.mem, realp, stride, half);
+    let mut imag = read_vector(&env.mem, imagp, stride, half);
+    let setup = State::get(env).fft_setups.get(&setup).unwrap();
+    assert!(log2n <= setup.log2n);
+    setup.real_fft(&mut real, &mut imag, direction);
+    write_vector(&mut env.mem, realp, stride, &real);
+    write_vector(&mut env.mem, imagp, stride, &imag);
+}
+
+/// `vDSP_ctoz`: copy an interleaved complex vector into a split one. `c_stride`
+/// is counted in floats, so it's 2 for a contiguous vector.
+fn vDSP_ctoz(
+    env: &mut Environment,
+    c: ConstPtr<f32>, // const DSPComplex*
+    c_stride: vDSP_Stride,
+    z: ConstPtr<DSPSplitComplex>,
+    z_stride: vDSP_Stride,
+    count: vDSP_Length,
+) {
+    let DSPSplitComplex { realp, imagp } = env.mem.read(z);
+    let real = read_vector(&env.mem, c, c_stride, count);
+    let imag = read_vector(&env.mem, c + 1, c_stride, count);
+    write_vector(&mut env.mem, realp, z_stride, &real);
+    write_vector(&mut env.mem, imagp, z_stride, &imag);
+}
+
+/// `vDSP_ztoc`: the inverse of [vDSP_ctoz].
+fn vDSP_ztoc(
+    env: &mut Environment,
+    z: ConstPtr<DSPSplitComplex>,
+    z_stride: vDSP_Stride,
+    c: MutPtr<f32>, // DSPComplex*
+    c_stride: vDSP_Stride,
+    count: vDSP_Length,
+) {
+    let DSPSplitComplex { realp, imagp } = env.mem.read(z);
+    let real = read_vector(&env.mem, realp, z_stride, count);
+    let imag = read_vector(&env.mem, imagp, z_stride, count);
+    write_vector(&mut env.mem, c, c_stride, &real);
+    write_vector(&mut env.mem, c + 1, c_stride, &imag);
+}
+
+fn vDSP_vadd(
+    env: &mut Environment,
+    a: ConstPtr<f32>,
+    a_stride: vDSP_Stride,
+    b: ConstPtr<f32>,
+    b_stride: vDSP_Stride,
+    c: MutPtr<f32>,
+    c_stride: vDSP_Stride,
+    count: vDSP_Length,
+) {
+    let a = read_vector(&env.mem, a, a_stride, count);
+    let b = read_vector(&env.mem, b, b_stride, count);
+    let c_values: Vec<f32> = a.iter().zip(b).map(|(a, b)| a + b).collect();
+    write_vector(&mut env.mem, c, c_stride, &c_values);
+}
+
+fn vDSP_vmul(
+    env: &mut Environment,
+    a: ConstPtr<f32>,
+    a_stride: vDSP_Stride,
+    b: ConstPtr<f32>,
+    b_stride: vDSP_Stride,
+    c: MutPtr<f32>,
+    c_stride: vDSP_Stride,
+    count: vDSP_Length,
+) {
+    let a = read_vector(&env.mem, a, a_stride, count);
+    let b = read_vector(&env.mem, b, b_stride, count);
+    let c_values: Vec<f32> = a.iter().zip(b).map(|(a, b)| a * b).collect();
+    write_vector(&mut env.mem, c, c_stride, &c_values);
+}
+
+fn vDSP_vsmul(
+    env: &mut Environment,
+    a: ConstPtr<f32>,
+    a_stride: vDSP_Stride,
+    b: ConstPtr<f32>,
+    c: MutPtr<f32>,
+    c_stride: vDSP_Stride,
+    count: vDSP_Length,
+) {
+    let a = read_vector(&env.mem, a, a_stride, count);
+    let b = env.mem.read(b);
+    let c_values: Vec<f32> = a.iter().map(|a| a * b).collect();
+    write_vector(&mut env.mem, c, c_stride, &c_values);
+}
+
+fn vDSP_maxv(
+    env: &mut Environment,
+    a: ConstPtr<f32>,
+    a_stride: vDSP_Stride,
+    c: MutPtr<f32>,
+    count: vDSP_Length,
+) {
+    let a = read_vector(&env.mem, a, a_stride, count);
+    let max = a.into_iter().fold(f32::NEG_INFINITY, f32::max);
+    env.mem.write(c, max);
+}
+
+/// Mean of magnitudes.
+fn vDSP_meamgv(
+    env: &mut Environment,
+    a: ConstPtr<f32>,
+    a_stride: vDSP_Stride,
+    c: MutPtr<f32>,
+    count: vDSP_Length,
+) {
+    let a = read_vector(&env.mem, a, a_stride, count);
+    let sum: f32 = a.iter().map(|a| a.abs()).sum();
+    env.mem.write(c, sum / count as f32);
+}
+
+/// Squared magnitudes of a split complex vector.
+fn vDSP_zvmags(
+    env: &mut Environment,
+    a: ConstPtr<DSPSplitComplex>,
+    a_stride: vDSP_Stride,
+    c: MutPtr<f32>,
+    c_stride: vDSP_Stride,
+    count: vDSP_Length,
+) {
+    let DSPSplitComplex { realp, imagp } = env.mem.read(a);
+    let real = read_vector(&env.mem, realp, a_stride, count);
+    let imag = read_vector(&env.mem, imagp, a_stride, count);
+    let c_values: Vec<f32> = real
+        .iter()
+        .zip(imag)
+        .map(|(re, im)| re * re + im * im)
+        .collect();
+    write_vector(&mut env.mem, c, c_stride, &c_values);
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(vDSP_create_fftsetup(_, _)),
+    export_c_func!(vDSP_destroy_fftsetup(_)),
+    export_c_func!(vDSP_fft_zrip(_, _, _, _, _)),
+    export_c_func!(vDSP_ctoz(_, _, _, _, _)),
+    export_c_func!(vDSP_ztoc(_, _, _, _, _)),
+    export_c_func!(vDSP_vadd(_, _, _, _, _, _, _)),
+    export_c_func!(vDSP_vmul(_, _, _, _, _, _, _)),
+    export_c_func!(vDSP_vsmul(_, _, _, _, _, _)),
+    export_c_func!(vDSP_maxv(_, _, _, _)),
+    export_c_func!(vDSP_meamgv(_, _, _, _)),
+    export_c_func!(vDSP_zvmags(_, _, _, _, _)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn real_fft_matches_dft() {
+        let setup = FftSetupHostObject::new(3);
+        let input = [1.0, -2.0, 3.5, 0.25, -1.0, 4.0, 0.0, 2.0];
+        let mut realp: Vec<f32> = input.iter().step_by(2).copied().collect();
+        let mut imagp: Vec<f32> = input.iter().skip(1).step_by(2).copied().collect();
+        setup.real_fft(&mut realp, &mut imagp, kFFTDirection_Forward);
+
+        let dft = |k: usize| {
+            input
+                .iter()
+                .enumerate()
+                .fold((0.0, 0.0), |(re, im), (j, &x)| {
+                    let angle = -2.0 * std::f64::consts::PI * (j * k) as f64 / 8.0;
+                    (re + x as f64 * angle.cos(), im + x as f64 * angle.sin())
+                })
+        };
+        assert!((realp[0] as f64 - 2.0 * dft(0).0).abs() < 1e-4);
+        assert!((imagp[0] as f64 - 2.0 * dft(4).0).abs() < 1e-4);
+        for k in 1..4 {
+            let (re, im) = dft(k);
+            assert!((realp[k] as f64 - 2.0 * re).abs() < 1e-4);
+            assert!((imagp[k] as f64 - 2.0 * im).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn real_fft_round_trip() {
+        let setup = FftSetupHostObject::new(10);
+        // A smaller transform than the setup supports.
+        let n = 512;
+        let input: Vec<f32> = (0..n)
+            .map(|i| ((i * 7919) % 101) as f32 / 50.0 - 1.0)
+            .collect();
+        let mut realp: Vec<f32> = input.iter().step_by(2).copied().collect();
+        let mut imagp: Vec<f32> = input.iter().skip(1).step_by(2).copied().collect();
+        setup.real_fft(&mut realp, &mut imagp, kFFTDirection_Forward);
+        setup.real_fft(&mut realp, &mut imagp, kFFTDirection_Inverse);
+        let scale = 1.0 / (2.0 * n as f32);
+        for k in 0..n / 2 {
+            assert!((realp[k] * scale - input[2 * k]).abs() < 1e-4);
+            assert!((imagp[k] * scale - input[2 * k + 1]).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn vector_span_strides() {
+        let span = VectorSpan::new(0x1000, 2, 3);
+        assert_eq!((span.base, span.size), (0x1000, 20));
+        assert_eq!((span.offset(0), span.offset(2)), (0, 16));
+
+        let span = VectorSpan::new(0x1000, -1, 3);
+        assert_eq!((span.base, span.size), (0x1000 - 8, 12));
+        assert_eq!((span.offset(0), span.offset(2)), (8, 0));
+
+        let span = VectorSpan::new(0x1000, 0, 5);
+        assert_eq!((span.base, span.size), (0x1000, 4));
+        assert_eq!(span.offset(4), 0);
+    }
+}
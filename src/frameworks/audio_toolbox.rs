@@ -30,26 +30,52 @@ pub mod audio_queue;
 pub mod audio_services;
 pub mod audio_session;
 pub mod audio_unit;
+pub mod ext_audio_file;
 
 #[derive(Default)]
 pub struct State {
     audio_file: audio_file::State,
     audio_queue: audio_queue::State,
+    audio_session: audio_session::State,
     audio_components: audio_components::State,
+    ext_audio_file: ext_audio_file::State,
     al_device_and_context: Option<(*mut ALCdevice, *mut ALCcontext)>,
 }
 impl State {
+    /// Whether the app's audio should currently be mixed to the output. See
+    /// [audio_session].
+    pub fn output_enabled(&self) -> bool {
+        self.audio_session.output_enabled()
+    }
+
+    /// The simulated hardware output volume, which scales the gain of all the
+    /// app's audio. See [audio_session::set_output_volume].
+    pub fn output_volume(&self) -> f32 {
+        self.audio_session.output_volume
+    }
+
+    /// For use by [crate::gles::present::present_frame]: the volume to show
+    /// in the volume indicator, if it should currently be shown. See
+    /// [audio_session::press_volume_button].
+    pub fn volume_indicator(&self) -> Option<f32> {
+        (self.audio_session.volume_indicator_until? > std::time::Instant::now())
+            .then_some(self.audio_session.output_volume)
+    }
+
     pub fn make_al_context_current(&mut self) -> ContextManager {
         if self.al_device_and_context.is_none() {
-            let device = unsafe { al::alcOpenDevice(std::ptr::null()) };
+            let device = crate::recording::open_al_device();
             assert!(!device.is_null());
-            let context = unsafe { al::alcCreateContext(device, std::ptr::null()) };
+            let context = crate::recording::create_al_context(device);
             assert!(!context.is_null());
             log_dbg!(
                 "New internal OpenAL device ({:?}) and context ({:?})",
                 device,
                 context
             );
+            if !self.output_enabled() {
+                crate::recording::set_al_device_paused(device, true);
+            }
             self.al_device_and_context = Some((device, context));
         }
         let (device, context) = self.al_device_and_context.unwrap();
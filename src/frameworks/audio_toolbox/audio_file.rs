@@ -41,6 +41,14 @@ unsafe impl SafeRead for OpaqueAudioFileID {}
 
 pub type AudioFileID = MutPtr<OpaqueAudioFileID>;
 
+#[repr(C, packed)]
+struct AudioFilePacketTableInfo {
+    number_valid_frames: i64,
+    priming_frames: i32,
+    remainder_frames: i32,
+}
+unsafe impl SafeRead for AudioFilePacketTableInfo {}
+
 #[allow(dead_code)]
 const kAudioFileFileNotFoundError: OSStatus = -43;
 const kAudioFileBadPropertySizeError: OSStatus = fourcc(b"!siz") as _;
@@ -61,6 +69,7 @@ pub const kAudioFilePropertyDataFormat: AudioFilePropertyID = fourcc(b"dfmt");
 const kAudioFilePropertyAudioDataByteCount: AudioFilePropertyID = fourcc(b"bcnt");
 const kAudioFilePropertyAudioDataPacketCount: AudioFilePropertyID = fourcc(b"pcnt");
 pub const kAudioFilePropertyPacketSizeUpperBound: AudioFilePropertyID = fourcc(b"pkub");
+const kAudioFilePropertyPacketTableInfo: AudioFilePropertyID = fourcc(b"pnfo");
 const kAudioFilePropertyMagicCookieData: AudioFilePropertyID = fourcc(b"mgic");
 const kAudioFilePropertyChannelLayout: AudioFilePropertyID = fourcc(b"cmap");
 
@@ -119,12 +128,65 @@ pub fn AudioFileOpenURL(
     0 // success
 }
 
+/// Convert touchHLE's internal description of an audio file's format to the
+/// Core Audio equivalent.
+pub fn audio_description_to_asbd(desc: audio::AudioDescription) -> AudioStreamBasicDescription {
+    let audio::AudioDescription {
+        sample_rate,
+        format,
+        bytes_per_packet,
+        frames_per_packet,
+        channels_per_frame,
+        bits_per_channel,
+    } = desc;
+
+    match format {
+        audio::AudioFormat::LinearPcm {
+            is_float,
+            is_little_endian,
+        } => {
+            let is_packed = (bits_per_channel * channels_per_frame * frames_per_packet)
+                == (bytes_per_packet * 8);
+            let format_flags = (u32::from(is_float) * kAudioFormatFlagIsFloat)
+                | (u32::from((!is_float) && matches!(bits_per_channel, 16 | 24))
+                    * kAudioFormatFlagIsSignedInteger)
+                | (u32::from(is_packed) * kAudioFormatFlagIsPacked)
+                | (u32::from(!is_little_endian) * kAudioFormatFlagIsBigEndian);
+            AudioStreamBasicDescription {
+                sample_rate,
+                format_id: kAudioFormatLinearPCM,
+                format_flags,
+                bytes_per_packet,
+                frames_per_packet,
+                bytes_per_frame: bytes_per_packet / frames_per_packet,
+                channels_per_frame,
+                bits_per_channel,
+                _reserved: 0,
+            }
+        }
+        audio::AudioFormat::AppleIma4 => {
+            AudioStreamBasicDescription {
+                sample_rate,
+                format_id: kAudioFormatAppleIMA4,
+                format_flags: 0,
+                bytes_per_packet,
+                frames_per_packet,
+                bytes_per_frame: 0, // compressed
+                channels_per_frame,
+                bits_per_channel,
+                _reserved: 0,
+            }
+        }
+    }
+}
+
 fn property_size(property_id: AudioFilePropertyID) -> GuestUSize {
     match property_id {
         kAudioFilePropertyDataFormat => guest_size_of::<AudioStreamBasicDescription>(),
         kAudioFilePropertyAudioDataByteCount => guest_size_of::<u64>(),
         kAudioFilePropertyAudioDataPacketCount => guest_size_of::<u64>(),
         kAudioFilePropertyPacketSizeUpperBound => guest_size_of::<u32>(),
+        kAudioFilePropertyPacketTableInfo => guest_size_of::<AudioFilePacketTableInfo>(),
         _ => unimplemented!("Unimplemented property ID: {}", debug_fourcc(property_id)),
     }
 }
@@ -183,53 +245,7 @@ pub fn AudioFileGetProperty(
 
     match in_property_id {
         kAudioFilePropertyDataFormat => {
-            let audio::AudioDescription {
-                sample_rate,
-                format,
-                bytes_per_packet,
-                frames_per_packet,
-                channels_per_frame,
-                bits_per_channel,
-            } = host_object.audio_file.audio_description();
-
-            let desc: AudioStreamBasicDescription = match format {
-                audio::AudioFormat::LinearPcm {
-                    is_float,
-                    is_little_endian,
-                } => {
-                    let is_packed = (bits_per_channel * channels_per_frame * frames_per_packet)
-                        == (bytes_per_packet * 8);
-                    let format_flags = (u32::from(is_float) * kAudioFormatFlagIsFloat)
-                        | (u32::from((!is_float) && matches!(bits_per_channel, 16 | 24))
-                            * kAudioFormatFlagIsSignedInteger)
-                        | (u32::from(is_packed) * kAudioFormatFlagIsPacked)
-                        | (u32::from(!is_little_endian) * kAudioFormatFlagIsBigEndian);
-                    AudioStreamBasicDescription {
-                        sample_rate,
-                        format_id: kAudioFormatLinearPCM,
-                        format_flags,
-                        bytes_per_packet,
-                        frames_per_packet,
-                        bytes_per_frame: bytes_per_packet / frames_per_packet,
-                        channels_per_frame,
-                        bits_per_channel,
-                        _reserved: 0,
-                    }
-                }
-                audio::AudioFormat::AppleIma4 => {
-                    AudioStreamBasicDescription {
-                        sample_rate,
-                        format_id: kAudioFormatAppleIMA4,
-                        format_flags: 0,
-                        bytes_per_packet,
-                        frames_per_packet,
-                        bytes_per_frame: 0, // compressed
-                        channels_per_frame,
-                        bits_per_channel,
-                        _reserved: 0,
-                    }
-                }
-            };
+            let desc = audio_description_to_asbd(host_object.audio_file.audio_description());
             env.mem.write(out_property_data.cast(), desc);
         }
         kAudioFilePropertyAudioDataByteCount => {
@@ -245,6 +261,17 @@ pub fn AudioFileGetProperty(
             env.mem
                 .write(out_property_data.cast(), packet_size_upper_bound);
         }
+        kAudioFilePropertyPacketTableInfo => {
+            // Compressed formats are decoded when the file is opened, with the
+            // encoder delay and padding already trimmed, so every frame is
+            // valid.
+            let info = AudioFilePacketTableInfo {
+                number_valid_frames: host_object.audio_file.frame_count().try_into().unwrap(),
+                priming_frames: 0,
+                remainder_frames: 0,
+            };
+            env.mem.write(out_property_data.cast(), info);
+        }
         _ => unreachable!(),
     }
 
@@ -17,8 +17,8 @@ use crate::frameworks::audio_toolbox::ContextManager;
 use crate::frameworks::carbon_core::OSStatus;
 use crate::frameworks::core_audio_types::{
     debug_fourcc, fourcc, kAudioFormatAppleIMA4, kAudioFormatFlagIsBigEndian,
-    kAudioFormatFlagIsFloat, kAudioFormatFlagIsPacked, kAudioFormatLinearPCM,
-    AudioStreamBasicDescription,
+    kAudioFormatFlagIsFloat, kAudioFormatFlagIsPacked, kAudioFormatFlagIsSignedInteger,
+    kAudioFormatLinearPCM, AudioStreamBasicDescription,
 };
 use crate::frameworks::core_foundation::cf_run_loop::{
     kCFRunLoopCommonModes, CFRunLoopGetMain, CFRunLoopMode, CFRunLoopRef,
@@ -59,6 +59,9 @@ struct AudioQueueHostObject {
     al_unused_buffers: Vec<ALuint>,
     aq_is_running_proc: Option<AudioQueuePropertyListenerProc>,
     aq_is_running_user_data: Option<MutVoidPtr>,
+    /// Set by a non-immediate [AudioQueueDispose]: the queue will be disposed
+    /// of once it has finished playing the buffers already enqueued.
+    dispose_when_stopped: bool,
 }
 
 /// Track whether the audio queue is meant to be running, in order to handle
@@ -108,9 +111,10 @@ pub type AudioQueuePropertyID = u32;
 pub const kAudioQueueProperty_IsRunning: AudioQueuePropertyID = fourcc(b"aqrn");
 
 /// (*void)(void *in_user_data, AudioQueueRef in_aq, AudioQueuePropertyID in_id)
-type AudioQueuePropertyListenerProc = GuestFunction;
+pub type AudioQueuePropertyListenerProc = GuestFunction;
 
 const kAudioQueueErr_InvalidBuffer: OSStatus = -66687;
+const kAudioQueueErr_InvalidParameter: OSStatus = -66682;
 const kAudioQueueErr_InvalidPropertySize: OSStatus = -66683;
 const kAudioQueueErr_BufferInQueue: OSStatus = -66679;
 
@@ -177,6 +181,7 @@ pub fn AudioQueueNewOutput(
         al_unused_buffers: Vec::new(),
         aq_is_running_proc: None,
         aq_is_running_user_data: None,
+        dispose_when_stopped: false,
     };
 
     let aq_ref = env.mem.alloc_and_write(OpaqueAudioQueue { _filler: 0 });
@@ -208,7 +213,15 @@ fn AudioQueueGetParameter(
 ) -> OSStatus {
     return_if_null!(in_aq);
 
-    assert!(in_param_id == kAudioQueueParam_Volume); // others unimplemented
+    if in_param_id != kAudioQueueParam_Volume {
+        log!(
+            "TODO: AudioQueueGetParameter({:?}, {}, {:?})",
+            in_aq,
+            in_param_id,
+            out_value
+        );
+        return kAudioQueueErr_InvalidParameter;
+    }
 
     let state = State::get(&mut env.framework_state);
     let host_object = state.audio_queues.get_mut(&in_aq).unwrap();
@@ -226,11 +239,21 @@ pub fn AudioQueueSetParameter(
 ) -> OSStatus {
     return_if_null!(in_aq);
 
-    assert!(in_param_id == kAudioQueueParam_Volume); // others unimplemented
+    if in_param_id != kAudioQueueParam_Volume {
+        log!(
+            "TODO: AudioQueueSetParameter({:?}, {}, {})",
+            in_aq,
+            in_param_id,
+            in_value
+        );
+        return kAudioQueueErr_InvalidParameter;
+    }
 
     let state = State::get(&mut env.framework_state);
     let host_object = state.audio_queues.get_mut(&in_aq).unwrap();
 
+    // Volume is a linear gain in the range 0 to 1.
+    let in_value = in_value.clamp(0.0, 1.0);
     host_object.volume = in_value;
     if let Some(al_source) = host_object.al_source {
         let _context_manager = env.framework_state.audio_toolbox.make_al_context_current();
@@ -322,7 +345,7 @@ pub fn AudioQueueEnqueueBuffer(
     0 // success
 }
 
-fn AudioQueueAddPropertyListener(
+pub fn AudioQueueAddPropertyListener(
     env: &mut Environment,
     in_aq: AudioQueueRef,
     in_id: AudioQueuePropertyID,
@@ -398,7 +421,7 @@ fn AudioQueueGetPropertySize(
     0 // success
 }
 
-fn AudioQueueGetProperty(
+pub fn AudioQueueGetProperty(
     env: &mut Environment,
     in_aq: AudioQueueRef,
     in_property_id: AudioQueuePropertyID,
@@ -523,22 +546,65 @@ fn decode_buffer(
                 (2, 16) => al::AL_FORMAT_STEREO16,
                 _ => unreachable!(),
             };
-            (f, format.sample_rate as ALsizei, data_slice.to_owned())
+            let mut data = data_slice.to_owned();
+            // OpenAL's 8-bit formats are unsigned.
+            if format.bits_per_channel == 8
+                && (format.format_flags & kAudioFormatFlagIsSignedInteger) != 0
+            {
+                for sample in data.iter_mut() {
+                    *sample ^= 0x80;
+                }
+            }
+            // OpenAL takes care of converting to the output sample rate.
+            (f, format.sample_rate as ALsizei, data)
         }
         _ => unreachable!(),
     }
 }
 
+/// For use by [Environment::toggle_fast_forward]: update the pitch and gain of
+/// all audio queues' OpenAL sources to match the current speed (and output
+/// volume, see [super::audio_session::set_output_volume]).
+pub fn apply_fast_forward(env: &mut Environment) {
+    let (pitch, gain) = env
+        .clock
+        .audio_pitch_and_gain(env.options.fast_forward_audio);
+    let gain = gain * env.framework_state.audio_toolbox.output_volume();
+    if State::get(&mut env.framework_state).audio_queues.is_empty() {
+        return;
+    }
+
+    let _context_manager = env.framework_state.audio_toolbox.make_al_context_current();
+    let state = State::get(&mut env.framework_state);
+    for host_object in state.audio_queues.values() {
+        if let Some(al_source) = host_object.al_source {
+            unsafe {
+                al::alSourcef(al_source, al::AL_PITCH, pitch);
+                al::alSourcef(al_source, al::AL_GAIN, gain);
+            }
+        }
+    }
+}
+
 /// Ensure an audio queue has an OpenAL source and at least one queued OpenAL
 /// buffer.
+///
+/// If `all` is [true], every enqueued buffer is passed to OpenAL, rather than
+/// only as many as are needed to avoid running out of data.
 fn prime_audio_queue(
     env: &mut Environment,
     in_aq: AudioQueueRef,
     context_manager: Option<ContextManager>,
+    all: bool,
 ) -> ContextManager {
     let context_manager = context_manager
         .unwrap_or_else(|| env.framework_state.audio_toolbox.make_al_context_current());
 
+    let (pitch, gain) = env
+        .clock
+        .audio_pitch_and_gain(env.options.fast_forward_audio);
+    let gain = gain * env.framework_state.audio_toolbox.output_volume();
+
     let state = State::get(&mut env.framework_state);
     let host_object = state.audio_queues.get_mut(&in_aq).unwrap();
 
@@ -551,6 +617,8 @@ fn prime_audio_queue(
         unsafe {
             al::alGenSources(1, &mut al_source);
             al::alSourcef(al_source, al::AL_MAX_GAIN, host_object.volume);
+            al::alSourcef(al_source, al::AL_PITCH, pitch);
+            al::alSourcef(al_source, al::AL_GAIN, gain);
             assert!(al::alGetError() == 0);
         };
         host_object.al_source = Some(al_source);
@@ -575,7 +643,8 @@ fn prime_audio_queue(
         assert!(al_buffers_queued <= host_object.buffer_queue.len());
         let unprocessed_buffers = al_buffers_queued - al_buffers_processed;
 
-        if unprocessed_buffers > 1 || al_buffers_queued == host_object.buffer_queue.len() {
+        if (!all && unprocessed_buffers > 1) || al_buffers_queued == host_object.buffer_queue.len()
+        {
             break;
         }
 
@@ -649,7 +718,10 @@ pub fn handle_audio_queue(env: &mut Environment, in_aq: AudioQueueRef) {
 
     let state = State::get(&mut env.framework_state);
 
-    let host_object = state.audio_queues.get_mut(&in_aq).unwrap();
+    // The queue might have been disposed of by a callback for another queue.
+    let Some(host_object) = state.audio_queues.get_mut(&in_aq) else {
+        return;
+    };
     let Some(al_source) = host_object.al_source else {
         return;
     };
@@ -673,6 +745,13 @@ pub fn handle_audio_queue(env: &mut Environment, in_aq: AudioQueueRef) {
     } = host_object;
 
     for buffer_ref in buffers_to_reuse.drain(..) {
+        // The callback might dispose of the queue.
+        if !State::get(&mut env.framework_state)
+            .audio_queues
+            .contains_key(&in_aq)
+        {
+            return;
+        }
         log_dbg!(
             "Recyling buffer {:?} for queue {:?}. Calling callback {:?} with user data {:?}.",
             buffer_ref,
@@ -684,9 +763,17 @@ pub fn handle_audio_queue(env: &mut Environment, in_aq: AudioQueueRef) {
         let () = callback_proc.call_from_host(env, (callback_user_data, in_aq, buffer_ref));
     }
 
+    // The callback might have disposed of the queue.
+    if !State::get(&mut env.framework_state)
+        .audio_queues
+        .contains_key(&in_aq)
+    {
+        return;
+    }
+
     // Push new buffers etc.
 
-    let _context_manager = prime_audio_queue(env, in_aq, Some(context_manager));
+    let _context_manager = prime_audio_queue(env, in_aq, Some(context_manager), false);
 
     if is_running != AudioQueueIsRunning::Stopped {
         unsafe {
@@ -720,6 +807,15 @@ pub fn handle_audio_queue(env: &mut Environment, in_aq: AudioQueueRef) {
                 in_aq
             );
             finish_stopping_audio_queue(env, in_aq);
+
+            let dispose = State::get(&mut env.framework_state)
+                .audio_queues
+                .get(&in_aq)
+                .map_or(false, |host_object| host_object.dispose_when_stopped);
+            if dispose {
+                log_dbg!("Completing deferred disposal of queue {:?}.", in_aq);
+                AudioQueueDispose(env, in_aq, true);
+            }
         }
     }
 }
@@ -733,7 +829,7 @@ fn AudioQueuePrime(
     return_if_null!(in_aq);
 
     assert!(out_number_of_frames_prepared.is_null()); // TODO
-    let _context_manager = prime_audio_queue(env, in_aq, None);
+    let _context_manager = prime_audio_queue(env, in_aq, None, false);
     0 // success
 }
 
@@ -763,7 +859,7 @@ pub fn AudioQueueStart(
 
     assert!(in_device_start_time.is_null()); // TODO
 
-    let _context_manager = prime_audio_queue(env, in_aq, None);
+    let _context_manager = prime_audio_queue(env, in_aq, None, false);
 
     let host_object = State::get(&mut env.framework_state)
         .audio_queues
@@ -888,9 +984,12 @@ fn AudioQueueReset(env: &mut Environment, in_aq: AudioQueueRef) -> OSStatus {
     0 // success
 }
 
-fn AudioQueueFlush(_env: &mut Environment, in_aq: AudioQueueRef) -> OSStatus {
+fn AudioQueueFlush(env: &mut Environment, in_aq: AudioQueueRef) -> OSStatus {
     return_if_null!(in_aq);
-    // TODO
+    // Make sure nothing enqueued so far is held back, so that a subsequent
+    // asynchronous stop plays everything.
+    log_dbg!("Flushing queue {:?}.", in_aq);
+    let _context_manager = prime_audio_queue(env, in_aq, None, /* all: */ true);
     0 // success
 }
 
@@ -932,7 +1031,20 @@ pub fn AudioQueueDispose(
 ) -> OSStatus {
     return_if_null!(in_aq);
 
-    assert!(in_immediate); // TODO
+    if !in_immediate {
+        let host_object = State::get(&mut env.framework_state)
+            .audio_queues
+            .get_mut(&in_aq)
+            .unwrap();
+        if host_object.is_running != AudioQueueIsRunning::Stopped {
+            log_dbg!(
+                "Deferring disposal of queue {:?} until it has finished playing.",
+                in_aq
+            );
+            host_object.dispose_when_stopped = true;
+            return AudioQueueStop(env, in_aq, /* in_immediate: */ false);
+        }
+    }
 
     let state = State::get(&mut env.framework_state);
 
@@ -35,13 +35,36 @@ fn AudioServicesGetProperty(
     }
 }
 
-fn AudioServicesPlaySystemSound(_env: &mut Environment, in_system_sound_id: SystemSoundID) {
+fn AudioServicesPlaySystemSound(env: &mut Environment, in_system_sound_id: SystemSoundID) {
     assert_eq!(in_system_sound_id, kSystemSoundID_Vibrate);
-    log!("TODO: vibration (AudioServicesPlaySystemSound)");
+    vibrate(env);
     // TODO: implement other system sounds
 }
 
+fn AudioServicesPlayAlertSound(env: &mut Environment, in_system_sound_id: SystemSoundID) {
+    // On a real device, this plays the sound and vibrates, but the vibration
+    // can't be simulated without the sound. Since the sound isn't implemented,
+    // the only case that does something is kSystemSoundID_Vibrate.
+    if in_system_sound_id == kSystemSoundID_Vibrate {
+        vibrate(env);
+    } else {
+        log!(
+            "TODO: alert sound {:#x} (AudioServicesPlayAlertSound)",
+            in_system_sound_id
+        );
+    }
+}
+
+/// Simulate the device's vibration with game controller rumble, if possible.
+fn vibrate(env: &mut Environment) {
+    log_dbg!("Vibrating");
+    if let Some(window) = env.window.as_mut() {
+        window.rumble(&env.options);
+    }
+}
+
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(AudioServicesGetProperty(_, _, _, _, _)),
     export_c_func!(AudioServicesPlaySystemSound(_)),
+    export_c_func!(AudioServicesPlayAlertSound(_)),
 ];
@@ -4,40 +4,345 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 //! `AudioSession.h` (Audio Session) // TODO: is this the real name?
+//!
+//! touchHLE doesn't have a real audio session to manage, but the app's
+//! session state is used to decide whether its audio is mixed to the output:
+//! nothing is heard while the session is inactive or interrupted.
+//! Interruptions are simulated when the user presses F11 or, if the option
+//! is enabled, when the window loses focus.
+//!
+//! The session also has the simulated hardware output volume, which scales
+//! all of the app's audio. The app can change it (e.g. via
+//! `MPMusicPlayerController`), and so can the user, with the simulated volume
+//! buttons (see `--volume-up-key=`) or an `MPVolumeView`. The user's choice is
+//! saved as the app's `--volume=` option.
 
-use crate::abi::GuestFunction;
-use crate::dyld::{export_c_func, FunctionExports};
+use crate::abi::{CallFromHost, GuestFunction};
+use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant};
+use crate::frameworks::audio_toolbox::audio_queue;
 use crate::frameworks::carbon_core::OSStatus;
 use crate::frameworks::core_audio_types::{debug_fourcc, fourcc};
 use crate::frameworks::core_foundation::cf_run_loop::{CFRunLoopMode, CFRunLoopRef};
+use crate::frameworks::foundation::{ns_string, NSInteger};
+use crate::frameworks::{media_player, openal};
 use crate::mem::{guest_size_of, ConstVoidPtr, GuestUSize, MutPtr, MutVoidPtr};
+use crate::objc::{id, msg, msg_class, release};
+use crate::options;
 use crate::Environment;
+use std::time::{Duration, Instant};
 
 type AudioSessionInterruptionListener = GuestFunction;
 type AudioSessionPropertyListener = GuestFunction;
 
+const kAudioSessionNotInitialized: OSStatus = fourcc(b"!ini") as _;
+const kAudioSessionAlreadyInitialized: OSStatus = fourcc(b"init") as _;
+const kAudioSessionUnsupportedPropertyError: OSStatus = fourcc(b"pty?") as _;
 const kAudioSessionBadPropertySizeError: OSStatus = fourcc(b"!siz") as _;
 
+/// How much the simulated volume buttons change the volume by. The iPhone has
+/// 16 volume steps.
+const VOLUME_STEP: f32 = 1.0 / 16.0;
+/// How long the volume indicator is shown for after the volume buttons are
+/// pressed.
+const VOLUME_INDICATOR_DURATION: Duration = Duration::from_millis(1500);
+
+const kAudioSessionBeginInterruption: u32 = 1;
+const kAudioSessionEndInterruption: u32 = 0;
+
 /// Usually a FourCC.
 type AudioSessionPropertyID = u32;
-const kAudioSessionProperty_OtherAudioIsPlaying: AudioSessionPropertyID = fourcc(b"othr");
+const kAudioSessionProperty_PreferredHardwareSampleRate: AudioSessionPropertyID = fourcc(b"hwsr");
+const kAudioSessionProperty_PreferredHardwareIOBufferDuration: AudioSessionPropertyID =
+    fourcc(b"iobd");
 const kAudioSessionProperty_AudioCategory: AudioSessionPropertyID = fourcc(b"acat");
+const kAudioSessionProperty_AudioRouteChange: AudioSessionPropertyID = fourcc(b"roch");
 const kAudioSessionProperty_CurrentHardwareSampleRate: AudioSessionPropertyID = fourcc(b"chsr");
 const kAudioSessionProperty_CurrentHardwareOutputNumberChannels: AudioSessionPropertyID =
     fourcc(b"choc");
-const kAudioSessionProperty_PreferredHardwareIOBufferDuration: AudioSessionPropertyID =
-    fourcc(b"iobd");
+const kAudioSessionProperty_CurrentHardwareIOBufferDuration: AudioSessionPropertyID =
+    fourcc(b"chbd");
+const kAudioSessionProperty_AudioRoute: AudioSessionPropertyID = fourcc(b"rout");
+const kAudioSessionProperty_OtherAudioIsPlaying: AudioSessionPropertyID = fourcc(b"othr");
+const kAudioSessionProperty_CurrentHardwareOutputVolume: AudioSessionPropertyID = fourcc(b"chov");
 
 const kAudioSessionCategory_SoloAmbientSound: u32 = fourcc(b"solo");
 
+const kAudioSessionRouteChangeReason_CategoryChange: NSInteger = 8;
+
+pub const kAudioSession_AudioRouteChangeKey_Reason: &str = "OutputDeviceDidChange_Reason";
+pub const kAudioSession_AudioRouteChangeKey_OldRoute: &str = "OutputDeviceDidChange_OldRoute";
+
+pub const CONSTANTS: ConstantExports = &[
+    (
+        "_kAudioSession_AudioRouteChangeKey_Reason",
+        HostConstant::NSString(kAudioSession_AudioRouteChangeKey_Reason),
+    ),
+    (
+        "_kAudioSession_AudioRouteChangeKey_OldRoute",
+        HostConstant::NSString(kAudioSession_AudioRouteChangeKey_OldRoute),
+    ),
+];
+
+/// The only audio route touchHLE has.
+const AUDIO_ROUTE: &str = "Speaker";
+
+/// Hardware sample rate, taken from an iOS 2 simulator.
+const HARDWARE_SAMPLE_RATE: f64 = 44100.0;
+/// Default I/O buffer duration: 1024 frames at the hardware sample rate.
+const DEFAULT_IO_BUFFER_DURATION: f32 = (1024.0 / HARDWARE_SAMPLE_RATE) as f32;
+
+pub struct State {
+    initialized: bool,
+    interruption_listener: Option<(AudioSessionInterruptionListener, MutVoidPtr)>,
+    property_listeners: Vec<(
+        AudioSessionPropertyID,
+        AudioSessionPropertyListener,
+        MutVoidPtr,
+    )>,
+    category: u32,
+    /// Apps that never touch the audio session still get to play audio, so
+    /// this starts out `true`.
+    active: bool,
+    interrupted: bool,
+    /// Whether the app's OpenAL devices are currently paused.
+    output_paused: bool,
+    preferred_hardware_sample_rate: f64,
+    preferred_io_buffer_duration: f32,
+    /// See [set_output_volume].
+    pub(super) output_volume: f32,
+    /// Host time until which the volume indicator should be shown.
+    pub(super) volume_indicator_until: Option<Instant>,
+}
+impl Default for State {
+    fn default() -> Self {
+        State {
+            initialized: false,
+            interruption_listener: None,
+            property_listeners: Vec::new(),
+            category: kAudioSessionCategory_SoloAmbientSound,
+            active: true,
+            interrupted: false,
+            output_paused: false,
+            preferred_hardware_sample_rate: HARDWARE_SAMPLE_RATE,
+            preferred_io_buffer_duration: DEFAULT_IO_BUFFER_DURATION,
+            output_volume: 1.0,
+            volume_indicator_until: None,
+        }
+    }
+}
+impl State {
+    fn get(env: &mut Environment) -> &mut Self {
+        &mut env.framework_state.audio_toolbox.audio_session
+    }
+
+    pub fn output_enabled(&self) -> bool {
+        self.active && !self.interrupted
+    }
+}
+
+/// Pause or resume all of the app's audio output to match the session state.
+fn update_output(env: &mut Environment) {
+    let state = State::get(env);
+    let enabled = state.output_enabled();
+    if state.output_paused != enabled {
+        return;
+    }
+    state.output_paused = !enabled;
+    log_dbg!(
+        "Audio output is now {}",
+        if enabled { "enabled" } else { "disabled" }
+    );
+    if let Some((device, _)) = env.framework_state.audio_toolbox.al_device_and_context {
+        crate::recording::set_al_device_paused(device, !enabled);
+    }
+    openal::set_devices_paused(env, !enabled);
+}
+
+/// For use by [Environment::run] after the app has crashed: pause all of the
+/// app's audio output for good, without telling the app.
+pub fn silence_output(env: &mut Environment) {
+    State::get(env).output_paused = true;
+    if let Some((device, _)) = env.framework_state.audio_toolbox.al_device_and_context {
+        crate::recording::set_al_device_paused(device, true);
+    }
+    openal::set_devices_paused(env, true);
+}
+
+/// Begin or end a simulated audio interruption, e.g. because the window lost
+/// focus or the user pressed the hotkey. The app's interruption listener is
+/// called if there is one.
+pub fn set_interrupted(env: &mut Environment, interrupted: bool) {
+    let state = State::get(env);
+    if state.interrupted == interrupted {
+        return;
+    }
+    state.interrupted = interrupted;
+    let listener = state.interruption_listener;
+
+    echo!(
+        "Audio interruption {}.",
+        if interrupted { "began" } else { "ended" }
+    );
+
+    // The app is told about the interruption beginning after its audio has
+    // already stopped, and about the end before it gets a chance to resume.
+    if interrupted {
+        update_output(env);
+    }
+    if let Some((callback, client_data)) = listener {
+        let interruption_state = if interrupted {
+            kAudioSessionBeginInterruption
+        } else {
+            kAudioSessionEndInterruption
+        };
+        log_dbg!(
+            "Calling interruption listener {:?} with state {}",
+            callback,
+            interruption_state
+        );
+        let () = callback.call_from_host(env, (client_data, interruption_state));
+    }
+    if !interrupted {
+        update_output(env);
+    }
+}
+
+pub fn is_interrupted(env: &mut Environment) -> bool {
+    State::get(env).interrupted
+}
+
+/// Get the simulated hardware output volume, from 0 to 1.
+pub fn output_volume(env: &mut Environment) -> f32 {
+    State::get(env).output_volume
+}
+
+/// Set the simulated hardware output volume, from 0 to 1. This scales the gain
+/// of all the app's audio, and the app's listeners for
+/// `kAudioSessionProperty_CurrentHardwareOutputVolume` are called.
+pub fn set_output_volume(env: &mut Environment, volume: f32) {
+    let volume = volume.clamp(0.0, 1.0);
+    let state = State::get(env);
+    if state.output_volume == volume {
+        return;
+    }
+    state.output_volume = volume;
+    log_dbg!("Output volume is now {}", volume);
+    openal::apply_fast_forward(env);
+    audio_queue::apply_fast_forward(env);
+
+    let volume_ptr = env.mem.alloc_and_write(volume);
+    notify_property_listeners(
+        env,
+        kAudioSessionProperty_CurrentHardwareOutputVolume,
+        guest_size_of::<f32>(),
+        volume_ptr.cast().cast_const(),
+    );
+    env.mem.free(volume_ptr.cast());
+
+    media_player::output_volume_did_change(env, volume);
+}
+
+/// Apply the `--volume=` option. This should be called before the app starts
+/// running, so there is no-one to notify.
+pub fn init_output_volume(env: &mut Environment) {
+    let volume = env.options.volume;
+    State::get(env).output_volume = volume;
+}
+
+/// Set the simulated hardware output volume at the user's request, and save it
+/// as the app's `--volume=` option so that it's kept next time the app is
+/// launched.
+pub fn set_output_volume_from_user(env: &mut Environment, volume: f32) {
+    let old_volume = output_volume(env);
+    set_output_volume(env, volume);
+    let volume = output_volume(env);
+    if volume == old_volume {
+        return;
+    }
+    echo!("Output volume set to {:.0}%.", volume * 100.0);
+    // The app picker has no app to save the option for.
+    if env.bundle.is_fake() {
+        return;
+    }
+    let arg = format!("--volume={}", volume);
+    if let Err(e) = options::save_user_option(env.bundle.bundle_identifier(), "--volume=", &arg) {
+        log!("Warning: Couldn't save output volume: {}", e);
+    }
+}
+
+/// Handle the user pressing one of the simulated volume buttons: change the
+/// volume by one step and show the volume indicator.
+pub fn press_volume_button(env: &mut Environment, up: bool) {
+    // The app might have set a volume between two steps.
+    let step = (output_volume(env) / VOLUME_STEP).round();
+    let step = if up { step + 1.0 } else { step - 1.0 };
+    set_output_volume_from_user(env, step * VOLUME_STEP);
+    State::get(env).volume_indicator_until = Some(Instant::now() + VOLUME_INDICATOR_DURATION);
+}
+
+fn notify_property_listeners(
+    env: &mut Environment,
+    property_id: AudioSessionPropertyID,
+    data_size: GuestUSize,
+    data: ConstVoidPtr,
+) {
+    let listeners: Vec<_> = State::get(env)
+        .property_listeners
+        .iter()
+        .filter(|&&(id, _, _)| id == property_id)
+        .map(|&(_, callback, client_data)| (callback, client_data))
+        .collect();
+    for (callback, client_data) in listeners {
+        log_dbg!(
+            "Calling property listener {:?} for property {}",
+            callback,
+            debug_fourcc(property_id)
+        );
+        let () = callback.call_from_host(env, (client_data, property_id, data_size, data));
+    }
+}
+
+/// On a real device, changing the category can change the route, and apps
+/// are told about this with a route change notification.
+fn notify_category_change(env: &mut Environment) {
+    let dict: id = msg_class![env; NSMutableDictionary alloc];
+    let dict: id = msg![env; dict init];
+    let reason: id =
+        msg_class![env; NSNumber numberWithInteger:kAudioSessionRouteChangeReason_CategoryChange];
+    let reason_key = ns_string::get_static_str(env, kAudioSession_AudioRouteChangeKey_Reason);
+    () = msg![env; dict setObject:reason forKey:reason_key];
+    let old_route = ns_string::get_static_str(env, AUDIO_ROUTE);
+    let old_route_key = ns_string::get_static_str(env, kAudioSession_AudioRouteChangeKey_OldRoute);
+    () = msg![env; dict setObject:old_route forKey:old_route_key];
+
+    let dict_ptr = env.mem.alloc_and_write(dict);
+    notify_property_listeners(
+        env,
+        kAudioSessionProperty_AudioRouteChange,
+        guest_size_of::<id>(),
+        dict_ptr.cast().cast_const(),
+    );
+    env.mem.free(dict_ptr.cast());
+    release(env, dict);
+}
+
 fn AudioSessionInitialize(
-    _env: &mut Environment,
+    env: &mut Environment,
     _in_run_loop: CFRunLoopRef,
     _in_run_loop_mode: CFRunLoopMode,
-    _in_interruption_listener: AudioSessionInterruptionListener,
-    _in_client_data: MutVoidPtr,
+    in_interruption_listener: AudioSessionInterruptionListener,
+    in_client_data: MutVoidPtr,
 ) -> OSStatus {
-    // TODO: actually implement this
+    // The listener is always called directly from the main thread's run loop
+    // in touchHLE, regardless of what run loop was requested.
+    let state = State::get(env);
+    if state.initialized {
+        return kAudioSessionAlreadyInitialized;
+    }
+    state.initialized = true;
+    if in_interruption_listener.addr_with_thumb_bit() != 0 {
+        state.interruption_listener = Some((in_interruption_listener, in_client_data));
+    }
     0 // success
 }
 
@@ -48,35 +353,70 @@ fn AudioSessionGetProperty(
     out_data: MutVoidPtr,
 ) -> OSStatus {
     let required_size: GuestUSize = match in_ID {
-        kAudioSessionProperty_OtherAudioIsPlaying => guest_size_of::<u32>(),
+        kAudioSessionProperty_PreferredHardwareSampleRate => guest_size_of::<f64>(),
+        kAudioSessionProperty_PreferredHardwareIOBufferDuration => guest_size_of::<f32>(),
         kAudioSessionProperty_AudioCategory => guest_size_of::<u32>(),
         kAudioSessionProperty_CurrentHardwareSampleRate => guest_size_of::<f64>(),
         kAudioSessionProperty_CurrentHardwareOutputNumberChannels => guest_size_of::<u32>(),
-        _ => unimplemented!("Unimplemented property ID: {}", debug_fourcc(in_ID)),
+        kAudioSessionProperty_CurrentHardwareIOBufferDuration => guest_size_of::<f32>(),
+        kAudioSessionProperty_AudioRoute => guest_size_of::<id>(),
+        kAudioSessionProperty_OtherAudioIsPlaying => guest_size_of::<u32>(),
+        kAudioSessionProperty_CurrentHardwareOutputVolume => guest_size_of::<f32>(),
+        _ => {
+            log!(
+                "TODO: AudioSessionGetProperty() for property {}",
+                debug_fourcc(in_ID)
+            );
+            return kAudioSessionUnsupportedPropertyError;
+        }
     };
     if env.mem.read(io_data_size) != required_size {
         log!("Warning: AudioSessionGetProperty() failed");
         return kAudioSessionBadPropertySizeError;
     }
 
+    let state = State::get(env);
     match in_ID {
-        kAudioSessionProperty_OtherAudioIsPlaying => {
-            let value: u32 = 0;
+        kAudioSessionProperty_PreferredHardwareSampleRate => {
+            let value: f64 = state.preferred_hardware_sample_rate;
+            env.mem.write(out_data.cast(), value);
+        }
+        kAudioSessionProperty_PreferredHardwareIOBufferDuration => {
+            let value: f32 = state.preferred_io_buffer_duration;
             env.mem.write(out_data.cast(), value);
         }
         kAudioSessionProperty_AudioCategory => {
-            // This is the default value. TODO: Actually support changing it?
-            let value: u32 = kAudioSessionCategory_SoloAmbientSound;
+            let value: u32 = state.category;
             env.mem.write(out_data.cast(), value);
         }
         kAudioSessionProperty_CurrentHardwareSampleRate => {
-            let value: f64 = 44100.0; // Value taken from an iOS 2 simulator
+            // The host's sample rate doesn't matter, OpenAL resamples anyway.
+            let value: f64 = HARDWARE_SAMPLE_RATE;
             env.mem.write(out_data.cast(), value);
         }
         kAudioSessionProperty_CurrentHardwareOutputNumberChannels => {
             let value: u32 = 2; // Value taken from an iOS 2 simulator
             env.mem.write(out_data.cast(), value);
         }
+        kAudioSessionProperty_CurrentHardwareIOBufferDuration => {
+            // Pretend the preferred duration was granted, so the two values
+            // are consistent.
+            let value: f32 = state.preferred_io_buffer_duration;
+            env.mem.write(out_data.cast(), value);
+        }
+        kAudioSessionProperty_AudioRoute => {
+            // The caller is responsible for releasing this.
+            let value = ns_string::from_rust_string(env, AUDIO_ROUTE.to_string());
+            env.mem.write(out_data.cast(), value);
+        }
+        kAudioSessionProperty_OtherAudioIsPlaying => {
+            let value: u32 = env.options.other_audio_is_playing.into();
+            env.mem.write(out_data.cast(), value);
+        }
+        kAudioSessionProperty_CurrentHardwareOutputVolume => {
+            let value: f32 = state.output_volume;
+            env.mem.write(out_data.cast(), value);
+        }
         _ => unreachable!(),
     }
 
@@ -84,45 +424,117 @@ fn AudioSessionGetProperty(
 }
 
 fn AudioSessionSetProperty(
-    _env: &mut Environment,
+    env: &mut Environment,
     in_ID: AudioSessionPropertyID,
     in_data_size: u32,
-    _in_data: ConstVoidPtr,
+    in_data: ConstVoidPtr,
 ) -> OSStatus {
     let required_size: GuestUSize = match in_ID {
-        kAudioSessionProperty_AudioCategory => guest_size_of::<u32>(),
+        kAudioSessionProperty_PreferredHardwareSampleRate => guest_size_of::<f64>(),
         kAudioSessionProperty_PreferredHardwareIOBufferDuration => guest_size_of::<f32>(),
-        _ => unimplemented!("Unimplemented property ID: {}", debug_fourcc(in_ID)),
+        kAudioSessionProperty_AudioCategory => guest_size_of::<u32>(),
+        _ => {
+            log!(
+                "TODO: AudioSessionSetProperty() for property {}",
+                debug_fourcc(in_ID)
+            );
+            return kAudioSessionUnsupportedPropertyError;
+        }
     };
     if in_data_size != required_size {
-        log!("Warning: AudioSessionGetProperty() failed");
+        log!("Warning: AudioSessionSetProperty() failed");
         return kAudioSessionBadPropertySizeError;
     }
 
-    // TODO: actually implement this
+    match in_ID {
+        kAudioSessionProperty_PreferredHardwareSampleRate => {
+            let value: f64 = env.mem.read(in_data.cast());
+            log_dbg!("AudioSessionSetProperty() preferred sample rate: {}", value);
+            if value > 0.0 {
+                State::get(env).preferred_hardware_sample_rate = value;
+            }
+        }
+        kAudioSessionProperty_PreferredHardwareIOBufferDuration => {
+            let value: f32 = env.mem.read(in_data.cast());
+            log_dbg!(
+                "AudioSessionSetProperty() preferred I/O buffer duration: {}",
+                value
+            );
+            // Apps may divide by this, so never accept zero.
+            if value > 0.0 {
+                State::get(env).preferred_io_buffer_duration = value;
+            }
+        }
+        kAudioSessionProperty_AudioCategory => {
+            let value: u32 = env.mem.read(in_data.cast());
+            log_dbg!(
+                "AudioSessionSetProperty() category: {}",
+                debug_fourcc(value)
+            );
+            let state = State::get(env);
+            if state.category != value {
+                state.category = value;
+                notify_category_change(env);
+            }
+        }
+        _ => unreachable!(),
+    }
 
     0 // success
 }
 
-fn AudioSessionSetActive(_env: &mut Environment, _active: bool) -> OSStatus {
+fn AudioSessionSetActive(env: &mut Environment, active: bool) -> OSStatus {
+    let state = State::get(env);
+    if !state.initialized {
+        return kAudioSessionNotInitialized;
+    }
+    state.active = active;
+    update_output(env);
     0 // success
 }
 
 fn AudioSessionAddPropertyListener(
-    _env: &mut Environment,
+    env: &mut Environment,
     inID: AudioSessionPropertyID,
     inProc: AudioSessionPropertyListener,
     inClientData: MutVoidPtr,
 ) -> OSStatus {
-    let result = 0; // success
-    log!(
-        "TODO: AudioSessionAddPropertyListener({:?}, {:?}, {:?}) -> {}",
-        inID,
+    log_dbg!(
+        "AudioSessionAddPropertyListener({}, {:?}, {:?})",
+        debug_fourcc(inID),
         inProc,
         inClientData,
-        result
     );
-    result
+    State::get(env)
+        .property_listeners
+        .push((inID, inProc, inClientData));
+    0 // success
+}
+
+fn AudioSessionRemovePropertyListenerWithUserData(
+    env: &mut Environment,
+    inID: AudioSessionPropertyID,
+    inProc: AudioSessionPropertyListener,
+    inClientData: MutVoidPtr,
+) -> OSStatus {
+    State::get(env)
+        .property_listeners
+        .retain(|&(id, callback, client_data)| {
+            id != inID
+                || callback.addr_with_thumb_bit() != inProc.addr_with_thumb_bit()
+                || client_data != inClientData
+        });
+    0 // success
+}
+
+fn AudioSessionRemovePropertyListener(
+    env: &mut Environment,
+    inID: AudioSessionPropertyID,
+) -> OSStatus {
+    State::get(env)
+        .property_listeners
+        .retain(|&(id, _, _)| id != inID);
+    0 // success
 }
 
 pub const FUNCTIONS: FunctionExports = &[
@@ -131,4 +543,6 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(AudioSessionSetProperty(_, _, _)),
     export_c_func!(AudioSessionSetActive(_)),
     export_c_func!(AudioSessionAddPropertyListener(_, _, _)),
+    export_c_func!(AudioSessionRemovePropertyListenerWithUserData(_, _, _)),
+    export_c_func!(AudioSessionRemovePropertyListener(_)),
 ];
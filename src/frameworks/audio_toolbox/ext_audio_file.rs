@@ -0,0 +1,477 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `ExtendedAudioFile.h` (Extended Audio File Services)
+//!
+//! This is built on the same host-side audio file support as Audio File
+//! Services, but always decodes the audio and converts it to the "client data
+//! format" requested by the app, which must be some kind of linear PCM.
+
+use super::audio_file::audio_description_to_asbd;
+use crate::audio; // Keep this module namespaced to avoid confusion
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::frameworks::carbon_core::OSStatus;
+use crate::frameworks::core_audio_types::{
+    audio_buffer_list_buffers, debug_fourcc, fourcc, kAudioFormatFlagIsBigEndian,
+    kAudioFormatFlagIsFloat, kAudioFormatFlagIsNonInterleaved, kAudioFormatFlagIsSignedInteger,
+    kAudioFormatLinearPCM, AudioBufferList, AudioStreamBasicDescription,
+};
+use crate::frameworks::core_foundation::cf_url::CFURLRef;
+use crate::frameworks::foundation::ns_url::to_rust_path;
+use crate::mem::{guest_size_of, ConstVoidPtr, GuestUSize, MutPtr, MutVoidPtr, SafeRead};
+use crate::Environment;
+use std::collections::HashMap;
+
+#[derive(Default)]
+pub struct State {
+    ext_audio_files: HashMap<ExtAudioFileRef, ExtAudioFileHostObject>,
+}
+impl State {
+    fn get(framework_state: &mut crate::frameworks::State) -> &mut Self {
+        &mut framework_state.audio_toolbox.ext_audio_file
+    }
+}
+
+struct ExtAudioFileHostObject {
+    audio_file: audio::AudioFile,
+    file_format: AudioStreamBasicDescription,
+    client_format: AudioStreamBasicDescription,
+    /// Read position in frames of the file's data format. This can be
+    /// fractional if the client format has a different sample rate.
+    position: f64,
+}
+
+#[repr(C, packed)]
+pub struct OpaqueExtAudioFile {
+    _filler: u8,
+}
+unsafe impl SafeRead for OpaqueExtAudioFile {}
+
+pub type ExtAudioFileRef = MutPtr<OpaqueExtAudioFile>;
+
+const kExtAudioFileError_InvalidProperty: OSStatus = -66561;
+const kExtAudioFileError_InvalidPropertySize: OSStatus = -66562;
+const kExtAudioFileError_NonPCMClientFormat: OSStatus = -66563;
+const kExtAudioFileError_InvalidDataFormat: OSStatus = -66566;
+const kExtAudioFileError_InvalidSeek: OSStatus = -66568;
+const kAudioFileUnsupportedFileTypeError: OSStatus = fourcc(b"typ?") as _;
+const kAudioFileUnspecifiedError: OSStatus = fourcc(b"wht?") as _;
+
+/// Usually a FourCC.
+type ExtAudioFilePropertyID = u32;
+const kExtAudioFileProperty_FileDataFormat: ExtAudioFilePropertyID = fourcc(b"ffmt");
+const kExtAudioFileProperty_ClientDataFormat: ExtAudioFilePropertyID = fourcc(b"cfmt");
+const kExtAudioFileProperty_FileLengthFrames: ExtAudioFilePropertyID = fourcc(b"#frm");
+
+fn ExtAudioFileOpenURL(
+    env: &mut Environment,
+    in_url: CFURLRef,
+    out_ext_audio_file: MutPtr<ExtAudioFileRef>,
+) -> OSStatus {
+    return_if_null!(in_url);
+
+    let path = to_rust_path(env, in_url);
+    let audio_file = match audio::AudioFile::open_for_reading(path, &env.fs) {
+        Ok(audio_file) => audio_file,
+        Err(error) => {
+            log!(
+                "Warning: ExtAudioFileOpenURL() for path {:?} failed",
+                in_url
+            );
+            return match error {
+                audio::AudioFileOpenError::FileDecodeError => kAudioFileUnsupportedFileTypeError,
+                _ => kAudioFileUnspecifiedError,
+            };
+        }
+    };
+
+    let file_format = audio_description_to_asbd(audio_file.audio_description());
+    // Until the app sets a client format, it's the same as the file format.
+    // That's only useful if the file is PCM, but we can worry about that when
+    // the app tries to read.
+    let host_object = ExtAudioFileHostObject {
+        audio_file,
+        file_format,
+        client_format: file_format,
+        position: 0.0,
+    };
+
+    let guest_ext_audio_file = env.mem.alloc_and_write(OpaqueExtAudioFile { _filler: 0 });
+    State::get(&mut env.framework_state)
+        .ext_audio_files
+        .insert(guest_ext_audio_file, host_object);
+
+    env.mem.write(out_ext_audio_file, guest_ext_audio_file);
+
+    log_dbg!(
+        "ExtAudioFileOpenURL() opened path {:?}, new audio file handle: {:?}",
+        in_url,
+        guest_ext_audio_file
+    );
+
+    0 // success
+}
+
+fn property_size(property_id: ExtAudioFilePropertyID) -> Option<GuestUSize> {
+    match property_id {
+        kExtAudioFileProperty_FileDataFormat => {
+            Some(guest_size_of::<AudioStreamBasicDescription>())
+        }
+        kExtAudioFileProperty_ClientDataFormat => {
+            Some(guest_size_of::<AudioStreamBasicDescription>())
+        }
+        kExtAudioFileProperty_FileLengthFrames => Some(guest_size_of::<i64>()),
+        _ => None,
+    }
+}
+
+fn ExtAudioFileGetPropertyInfo(
+    env: &mut Environment,
+    in_ext_audio_file: ExtAudioFileRef,
+    in_property_id: ExtAudioFilePropertyID,
+    out_size: MutPtr<u32>,
+    out_writable: MutPtr<bool>,
+) -> OSStatus {
+    return_if_null!(in_ext_audio_file);
+
+    let Some(size) = property_size(in_property_id) else {
+        log!(
+            "TODO: ExtAudioFileGetPropertyInfo() for property {}",
+            debug_fourcc(in_property_id)
+        );
+        return kExtAudioFileError_InvalidProperty;
+    };
+    if !out_size.is_null() {
+        env.mem.write(out_size, size);
+    }
+    if !out_writable.is_null() {
+        let writable = in_property_id == kExtAudioFileProperty_ClientDataFormat;
+        env.mem.write(out_writable, writable);
+    }
+    0 // success
+}
+
+fn ExtAudioFileGetProperty(
+    env: &mut Environment,
+    in_ext_audio_file: ExtAudioFileRef,
+    in_property_id: ExtAudioFilePropertyID,
+    io_property_data_size: MutPtr<u32>,
+    out_property_data: MutVoidPtr,
+) -> OSStatus {
+    return_if_null!(in_ext_audio_file);
+
+    let Some(required_size) = property_size(in_property_id) else {
+        log!(
+            "TODO: ExtAudioFileGetProperty() for property {}",
+            debug_fourcc(in_property_id)
+        );
+        return kExtAudioFileError_InvalidProperty;
+    };
+    if env.mem.read(io_property_data_size) < required_size {
+        log!("Warning: ExtAudioFileGetProperty() failed");
+        return kExtAudioFileError_InvalidPropertySize;
+    }
+    env.mem.write(io_property_data_size, required_size);
+
+    let host_object = State::get(&mut env.framework_state)
+        .ext_audio_files
+        .get_mut(&in_ext_audio_file)
+        .unwrap();
+
+    match in_property_id {
+        kExtAudioFileProperty_FileDataFormat => {
+            env.mem
+                .write(out_property_data.cast(), host_object.file_format);
+        }
+        kExtAudioFileProperty_ClientDataFormat => {
+            env.mem
+                .write(out_property_data.cast(), host_object.client_format);
+        }
+        kExtAudioFileProperty_FileLengthFrames => {
+            let frames: i64 = host_object.audio_file.frame_count().try_into().unwrap();
+            env.mem.write(out_property_data.cast(), frames);
+        }
+        _ => unreachable!(),
+    }
+
+    0 // success
+}
+
+fn ExtAudioFileSetProperty(
+    env: &mut Environment,
+    in_ext_audio_file: ExtAudioFileRef,
+    in_property_id: ExtAudioFilePropertyID,
+    in_property_data_size: u32,
+    in_property_data: ConstVoidPtr,
+) -> OSStatus {
+    return_if_null!(in_ext_audio_file);
+
+    if in_property_id != kExtAudioFileProperty_ClientDataFormat {
+        log!(
+            "TODO: ExtAudioFileSetProperty() for property {}",
+            debug_fourcc(in_property_id)
+        );
+        return kExtAudioFileError_InvalidProperty;
+    }
+    if in_property_data_size != guest_size_of::<AudioStreamBasicDescription>() {
+        return kExtAudioFileError_InvalidPropertySize;
+    }
+
+    let client_format: AudioStreamBasicDescription = env.mem.read(in_property_data.cast());
+    log_dbg!(
+        "ExtAudioFileSetProperty() client data format: {:#?}",
+        client_format
+    );
+    if client_format.format_id != kAudioFormatLinearPCM {
+        return kExtAudioFileError_NonPCMClientFormat;
+    }
+    if !is_supported_client_format(&client_format) {
+        log!(
+            "Warning: unsupported client data format for ExtAudioFile: {:#?}",
+            client_format
+        );
+        return kExtAudioFileError_InvalidDataFormat;
+    }
+
+    State::get(&mut env.framework_state)
+        .ext_audio_files
+        .get_mut(&in_ext_audio_file)
+        .unwrap()
+        .client_format = client_format;
+
+    0 // success
+}
+
+fn is_supported_client_format(format: &AudioStreamBasicDescription) -> bool {
+    let &AudioStreamBasicDescription {
+        format_id,
+        format_flags,
+        bits_per_channel,
+        channels_per_frame,
+        bytes_per_frame,
+        frames_per_packet,
+        sample_rate,
+        ..
+    } = format;
+    let bytes_per_sample = bits_per_channel / 8;
+    let channels_per_buffer = if (format_flags & kAudioFormatFlagIsNonInterleaved) != 0 {
+        1
+    } else {
+        channels_per_frame
+    };
+    format_id == kAudioFormatLinearPCM
+        && frames_per_packet == 1
+        && sample_rate > 0.0
+        && channels_per_frame != 0
+        && bytes_per_frame == bytes_per_sample * channels_per_buffer
+        && if (format_flags & kAudioFormatFlagIsFloat) != 0 {
+            bits_per_channel == 32
+        } else {
+            matches!(bits_per_channel, 8 | 16 | 32)
+        }
+}
+
+/// Convert a sample to the client data format and append its bytes.
+fn encode_sample(format: &AudioStreamBasicDescription, sample: f32, out: &mut Vec<u8>) {
+    let big_endian = (format.format_flags & kAudioFormatFlagIsBigEndian) != 0;
+    let signed = (format.format_flags & kAudioFormatFlagIsSignedInteger) != 0;
+    macro_rules! push {
+        ($value:expr) => {
+            if big_endian {
+                out.extend_from_slice(&$value.to_be_bytes())
+            } else {
+                out.extend_from_slice(&$value.to_le_bytes())
+            }
+        };
+    }
+    if (format.format_flags & kAudioFormatFlagIsFloat) != 0 {
+        push!(sample);
+        return;
+    }
+    match format.bits_per_channel {
+        8 if signed => push!((sample * 127.0) as i8),
+        8 => push!(((sample * 127.0) as i16 + 128) as u8),
+        16 => push!((sample * 32767.0) as i16),
+        32 => push!((f64::from(sample) * f64::from(i32::MAX)) as i32),
+        _ => unreachable!(),
+    }
+}
+
+fn ExtAudioFileRead(
+    env: &mut Environment,
+    in_ext_audio_file: ExtAudioFileRef,
+    io_number_frames: MutPtr<u32>,
+    io_data: MutPtr<AudioBufferList>,
+) -> OSStatus {
+    return_if_null!(in_ext_audio_file);
+
+    let host_object = State::get(&mut env.framework_state)
+        .ext_audio_files
+        .get_mut(&in_ext_audio_file)
+        .unwrap();
+    let client_format = host_object.client_format;
+    if !is_supported_client_format(&client_format) {
+        log!(
+            "Warning: ExtAudioFileRead() without a usable client data format: {:#?}",
+            client_format
+        );
+        return kExtAudioFileError_NonPCMClientFormat;
+    }
+
+    let non_interleaved = (client_format.format_flags & kAudioFormatFlagIsNonInterleaved) != 0;
+    let client_channels = client_format.channels_per_frame as usize;
+    let file_channels = host_object.file_format.channels_per_frame as usize;
+
+    let number_buffers = env.mem.read(io_data).number_buffers;
+    let buffers_ptr = audio_buffer_list_buffers(io_data);
+    let expected_buffers = if non_interleaved { client_channels } else { 1 };
+    if number_buffers as usize != expected_buffers {
+        log!(
+            "Warning: ExtAudioFileRead() got {} buffers, expected {}",
+            number_buffers,
+            expected_buffers
+        );
+        return kExtAudioFileError_InvalidDataFormat;
+    }
+
+    // Don't write more frames than the buffers have room for.
+    let mut frames_wanted = env.mem.read(io_number_frames);
+    for i in 0..number_buffers {
+        let buffer = env.mem.read(buffers_ptr + i);
+        frames_wanted = frames_wanted.min(buffer.data_byte_size / client_format.bytes_per_frame);
+    }
+
+    // Work out which frames of the file are needed. Sample rate conversion is
+    // done by linear interpolation, so one extra frame is needed.
+    let ratio = host_object.file_format.sample_rate / client_format.sample_rate;
+    let start_frame = host_object.position.floor() as u64;
+    let end_position = host_object.position + f64::from(frames_wanted) * ratio;
+    let frames_to_read = (end_position.ceil() as u64 + 1).saturating_sub(start_frame);
+    let Ok(samples) = host_object
+        .audio_file
+        .read_frames_i16(start_frame, frames_to_read)
+    else {
+        log!("Warning: ExtAudioFileRead() failed to read from the file");
+        return kAudioFileUnspecifiedError;
+    };
+    let frames_available = samples.len() / file_channels;
+
+    let get_sample = |frame: usize, channel: usize| -> f32 {
+        let frame = frame.min(frames_available - 1);
+        // Mono is duplicated to stereo, stereo is mixed down to mono.
+        let sample = if file_channels == 1 {
+            f32::from(samples[frame])
+        } else if client_channels == 1 {
+            let sum: f32 = samples[frame * file_channels..][..file_channels]
+                .iter()
+                .map(|&sample| f32::from(sample))
+                .sum();
+            sum / file_channels as f32
+        } else {
+            f32::from(samples[frame * file_channels + channel.min(file_channels - 1)])
+        };
+        sample / 32768.0
+    };
+
+    let mut out_data: Vec<Vec<u8>> = vec![Vec::new(); expected_buffers];
+    let mut frames_read = 0;
+    while frames_read < frames_wanted {
+        let position = host_object.position + f64::from(frames_read) * ratio;
+        let frame = (position.floor() as u64 - start_frame) as usize;
+        if frame >= frames_available {
+            break;
+        }
+        let fraction = (position - position.floor()) as f32;
+        for channel in 0..client_channels {
+            let a = get_sample(frame, channel);
+            let b = get_sample(frame + 1, channel);
+            let sample = a + (b - a) * fraction;
+            let out = if non_interleaved {
+                &mut out_data[channel]
+            } else {
+                &mut out_data[0]
+            };
+            encode_sample(&client_format, sample, out);
+        }
+        frames_read += 1;
+    }
+    host_object.position += f64::from(frames_read) * ratio;
+
+    for (i, data) in out_data.into_iter().enumerate() {
+        let buffer_ptr = buffers_ptr + i as GuestUSize;
+        let mut buffer = env.mem.read(buffer_ptr);
+        let size: GuestUSize = data.len().try_into().unwrap();
+        env.mem
+            .bytes_at_mut(buffer.data.cast(), size)
+            .copy_from_slice(&data);
+        buffer.data_byte_size = size;
+        env.mem.write(buffer_ptr, buffer);
+    }
+    env.mem.write(io_number_frames, frames_read);
+
+    // Reaching the end of the file is signalled by reading zero frames.
+    0 // success
+}
+
+fn ExtAudioFileSeek(
+    env: &mut Environment,
+    in_ext_audio_file: ExtAudioFileRef,
+    in_frame_offset: i64,
+) -> OSStatus {
+    return_if_null!(in_ext_audio_file);
+
+    let host_object = State::get(&mut env.framework_state)
+        .ext_audio_files
+        .get_mut(&in_ext_audio_file)
+        .unwrap();
+    // The offset is in frames of the file's data format.
+    if in_frame_offset < 0 || in_frame_offset as u64 > host_object.audio_file.frame_count() {
+        return kExtAudioFileError_InvalidSeek;
+    }
+    host_object.position = in_frame_offset as f64;
+    0 // success
+}
+
+fn ExtAudioFileTell(
+    env: &mut Environment,
+    in_ext_audio_file: ExtAudioFileRef,
+    out_frame_offset: MutPtr<i64>,
+) -> OSStatus {
+    return_if_null!(in_ext_audio_file);
+
+    let position = State::get(&mut env.framework_state)
+        .ext_audio_files
+        .get(&in_ext_audio_file)
+        .unwrap()
+        .position;
+    env.mem.write(out_frame_offset, position as i64);
+    0 // success
+}
+
+fn ExtAudioFileDispose(env: &mut Environment, in_ext_audio_file: ExtAudioFileRef) -> OSStatus {
+    return_if_null!(in_ext_audio_file);
+
+    State::get(&mut env.framework_state)
+        .ext_audio_files
+        .remove(&in_ext_audio_file)
+        .unwrap();
+    env.mem.free(in_ext_audio_file.cast());
+    log_dbg!(
+        "ExtAudioFileDispose() destroyed audio file handle: {:?}",
+        in_ext_audio_file
+    );
+    0 // success
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(ExtAudioFileOpenURL(_, _)),
+    export_c_func!(ExtAudioFileGetPropertyInfo(_, _, _, _)),
+    export_c_func!(ExtAudioFileGetProperty(_, _, _, _)),
+    export_c_func!(ExtAudioFileSetProperty(_, _, _, _)),
+    export_c_func!(ExtAudioFileRead(_, _, _)),
+    export_c_func!(ExtAudioFileSeek(_)),
+    export_c_func!(ExtAudioFileTell(_, _)),
+    export_c_func!(ExtAudioFileDispose(_)),
+];
@@ -14,9 +14,11 @@ use crate::frameworks::audio_toolbox::audio_file::{
     AudioFileReadPackets,
 };
 use crate::frameworks::audio_toolbox::audio_queue::{
-    kAudioQueueParam_Volume, AudioQueueAllocateBuffer, AudioQueueBufferRef, AudioQueueDispose,
-    AudioQueueEnqueueBuffer, AudioQueueNewOutput, AudioQueueOutputCallback, AudioQueuePause,
-    AudioQueueRef, AudioQueueSetParameter, AudioQueueStart, AudioQueueStop,
+    kAudioQueueParam_Volume, kAudioQueueProperty_IsRunning, AudioQueueAddPropertyListener,
+    AudioQueueAllocateBuffer, AudioQueueBufferRef, AudioQueueDispose, AudioQueueEnqueueBuffer,
+    AudioQueueGetProperty, AudioQueueNewOutput, AudioQueueOutputCallback, AudioQueuePause,
+    AudioQueuePropertyID, AudioQueuePropertyListenerProc, AudioQueueRef, AudioQueueSetParameter,
+    AudioQueueStart, AudioQueueStop,
 };
 use crate::frameworks::carbon_core::eofErr;
 use crate::frameworks::core_audio_types::AudioStreamBasicDescription;
@@ -25,28 +27,43 @@ use crate::frameworks::foundation::ns_error::NSOSStatusErrorDomain;
 use crate::frameworks::foundation::{ns_string, NSInteger, NSTimeInterval};
 use crate::mem::{guest_size_of, GuestUSize, MutPtr, MutVoidPtr, Ptr};
 use crate::objc::{
-    id, msg, msg_class, nil, release, retain, Class, ClassExports, HostObject, NSZonePtr,
+    id, msg, msg_class, nil, release, retain, selector, Class, ClassExports, HostObject, NSZonePtr,
 };
 use crate::objc_classes;
 use crate::Environment;
+use std::collections::VecDeque;
 
 const kNumberBuffers: usize = 3;
 
 struct AVAudioPlayerHostObject {
     audio_file_url: id,
+    /// Weak reference
+    delegate: id,
     output_callback: AudioQueueOutputCallback,
+    is_running_callback: AudioQueuePropertyListenerProc,
     audio_file_id: Option<AudioFileID>,
     audio_desc: Option<AudioStreamBasicDescription>,
     audio_queue: Option<AudioQueueRef>,
     audio_queue_buffers: Option<MutPtr<AudioQueueBufferRef>>,
     num_packets_to_read: u32,
+    /// The next packet to be read from the file.
     current_packet: i64,
+    /// Buffers currently enqueued, in order, with the packet they start at.
+    /// This is used to get the position that's actually being played, which
+    /// lags behind [Self::current_packet].
+    enqueued_buffers: VecDeque<(AudioQueueBufferRef, i64)>,
     // The time set by calling setCurrentTime is stored here in case it's set
     // before prepareToPlay is called; so it can be applied when it's called
     set_current_time: NSTimeInterval,
     volume: f32,
     is_playing: bool,
     num_of_loops: NSInteger,
+    /// How many more times playback will loop. Reset each time playback
+    /// starts from the beginning.
+    loops_remaining: NSInteger,
+    /// The end of the file has been reached and there's no more looping to do,
+    /// so the audio queue is stopping once it runs out of buffers.
+    reached_end: bool,
 }
 impl HostObject for AVAudioPlayerHostObject {}
 
@@ -63,19 +80,30 @@ pub const CLASSES: ClassExports = objc_classes! {
         .dyld
         .create_guest_function(&mut env.mem, symb, hf);
 
+    let symb = "__touchHLE_AVAudioPlayerIsRunningHelper";
+    let hf: HostFunction = &(_touchHLE_AVAudioPlayerIsRunningHelper as fn(&mut Environment, _, _, _) -> _);
+    let is_running_callback = env
+        .dyld
+        .create_guest_function(&mut env.mem, symb, hf);
+
     let host_object = Box::new(AVAudioPlayerHostObject {
         audio_file_url: nil,
+        delegate: nil,
         output_callback: callback,
+        is_running_callback,
         audio_file_id: None,
         audio_desc: None,
         audio_queue: None,
         audio_queue_buffers: None,
         num_packets_to_read: 0,
         current_packet: 0,
+        enqueued_buffers: VecDeque::new(),
         set_current_time: 0.0,
         volume: 1.0,
         is_playing: false,
-        num_of_loops: 0
+        num_of_loops: 0,
+        loops_remaining: 0,
+        reached_end: false,
     });
     env.objc.alloc_object(this, host_object, &mut env.mem)
 }
@@ -93,7 +121,6 @@ pub const CLASSES: ClassExports = objc_classes! {
     let tmp_afi_ptr: MutPtr<AudioFileID> = env.mem.alloc(guest_size_of::<AudioFileID>()).cast();
     let status = AudioFileOpenURL(env, url, kAudioFileReadPermission, 0, tmp_afi_ptr) as NSInteger;
     let audio_file_id = env.mem.read(tmp_afi_ptr);
-    env.objc.borrow_mut::<AVAudioPlayerHostObject>(this).audio_file_id = Some(audio_file_id);
     env.mem.free(tmp_afi_ptr.cast());
     if status != 0 {
         if !outError.is_null() {
@@ -104,14 +131,31 @@ pub const CLASSES: ClassExports = objc_classes! {
         }
         return nil;
     }
+    env.objc.borrow_mut::<AVAudioPlayerHostObject>(this).audio_file_id = Some(audio_file_id);
+
+    // The format is needed for currentTime and duration even before
+    // prepareToPlay is called.
+    let audio_desc = get_audio_desc(env, audio_file_id);
+    log_dbg!("audio_desc {:?}", audio_desc);
+    env.objc.borrow_mut::<AVAudioPlayerHostObject>(this).audio_desc = Some(audio_desc);
 
     this
 }
 
+- (id)delegate {
+    env.objc.borrow::<AVAudioPlayerHostObject>(this).delegate
+}
 - (())setDelegate:(id)delegate {
-    log!("TODO: [(AVAudioPlayer*){:?} setDelegate:{:?}]", this, delegate);
+    env.objc.borrow_mut::<AVAudioPlayerHostObject>(this).delegate = delegate;
 }
 
+- (id)url {
+    env.objc.borrow::<AVAudioPlayerHostObject>(this).audio_file_url
+}
+
+- (f32)volume {
+    env.objc.borrow::<AVAudioPlayerHostObject>(this).volume
+}
 - (())setVolume:(f32)volume {
     let host_object = env.objc.borrow_mut::<AVAudioPlayerHostObject>(this);
     host_object.volume = volume;
@@ -121,28 +165,23 @@ pub const CLASSES: ClassExports = objc_classes! {
     }
 }
 
-- (())prepareToPlay {
+- (bool)prepareToPlay {
     let audio_queue = env.objc.borrow_mut::<AVAudioPlayerHostObject>(this).audio_queue;
     if audio_queue.is_some() {
-        return;
+        return true;
     }
 
-    let audio_file_id = env.objc.borrow::<AVAudioPlayerHostObject>(this).audio_file_id.unwrap();
-    let callback = env.objc.borrow::<AVAudioPlayerHostObject>(this).output_callback;
-
-    let size = guest_size_of::<AudioStreamBasicDescription>();
-    let tmp_size_ptr: MutPtr<GuestUSize> = env.mem.alloc(guest_size_of::<GuestUSize>()).cast();
-    env.mem.write(tmp_size_ptr, size);
-    let tmp_data_ptr: MutPtr<AudioStreamBasicDescription> = env.mem.alloc(size).cast();
-    let status = AudioFileGetProperty(
-        env, audio_file_id, kAudioFilePropertyDataFormat, tmp_size_ptr, tmp_data_ptr.cast()
-    );
-    assert_eq!(status, 0);
-    assert_eq!(size, env.mem.read(tmp_size_ptr));
-    let audio_desc = env.mem.read(tmp_data_ptr);
-    log_dbg!("audio_desc {:?}", audio_desc);
-    env.objc.borrow_mut::<AVAudioPlayerHostObject>(this).audio_desc = Some(audio_desc);
+    let &AVAudioPlayerHostObject {
+        audio_file_id,
+        audio_desc,
+        output_callback: callback,
+        is_running_callback,
+        ..
+    } = env.objc.borrow(this);
+    let audio_file_id = audio_file_id.unwrap();
+    let audio_desc = audio_desc.unwrap();
 
+    let tmp_data_ptr = env.mem.alloc_and_write(audio_desc);
     let aq_ref_ptr: MutPtr<AudioQueueRef> = env.mem.alloc(guest_size_of::<AudioQueueRef>()).cast();
     let common_modes = ns_string::get_static_str(env, kCFRunLoopCommonModes);
     let status = AudioQueueNewOutput(
@@ -153,15 +192,18 @@ pub const CLASSES: ClassExports = objc_classes! {
     let aq_ref = env.mem.read(aq_ref_ptr);
     env.objc.borrow_mut::<AVAudioPlayerHostObject>(this).audio_queue = Some(aq_ref);
 
-    // Reapply the previously set current time and volume in case
-    // setVolume/setCurrentTime were called before prepareToPlay
+    let status = AudioQueueAddPropertyListener(
+        env, aq_ref, kAudioQueueProperty_IsRunning, is_running_callback, this.cast()
+    );
+    assert_eq!(status, 0);
+
+    // Reapply the previously set volume in case setVolume was called before
+    // prepareToPlay
     let volume = env.objc.borrow::<AVAudioPlayerHostObject>(this).volume;
     () = msg![env; this setVolume:volume];
-    let set_current_time = env.objc.borrow::<AVAudioPlayerHostObject>(this).set_current_time;
-    () = msg![env; this setCurrentTime:set_current_time];
 
     let size = guest_size_of::<u32>();
-    env.mem.write(tmp_size_ptr, size);
+    let tmp_size_ptr: MutPtr<GuestUSize> = env.mem.alloc_and_write(size);
     let prop_size_ptr: MutPtr<u32> = env.mem.alloc(size).cast();
     let status = AudioFileGetProperty(
         env, audio_file_id, kAudioFilePropertyPacketSizeUpperBound, tmp_size_ptr, prop_size_ptr.cast()
@@ -176,18 +218,21 @@ pub const CLASSES: ClassExports = objc_classes! {
     let buffers: MutPtr<AudioQueueBufferRef> = env.mem.alloc(kNumberBuffers as GuestUSize * guest_size_of::<AudioQueueBufferRef>()).cast();
     env.objc.borrow_mut::<AVAudioPlayerHostObject>(this).audio_queue_buffers = Some(buffers);
 
-    env.objc.borrow_mut::<AVAudioPlayerHostObject>(this).is_playing = true;
     for i in 0..kNumberBuffers {
         let status = AudioQueueAllocateBuffer(env, aq_ref, buffer_byte_size, buffers + i as u32);
         assert_eq!(status, 0);
-
-        _touchHLE_AVAudioPlayerOutputBufferHelper(env, this.cast(), aq_ref, env.mem.read(buffers + i as u32));
     }
-    env.objc.borrow_mut::<AVAudioPlayerHostObject>(this).is_playing = false;
 
+    // Applying the previously set current time also fills the buffers.
+    let set_current_time = env.objc.borrow::<AVAudioPlayerHostObject>(this).set_current_time;
+    () = msg![env; this setCurrentTime:set_current_time];
+
+    env.mem.free(prop_size_ptr.cast());
     env.mem.free(tmp_size_ptr.cast());
     env.mem.free(aq_ref_ptr.cast());
     env.mem.free(tmp_data_ptr.cast());
+
+    true
 }
 
 - (bool)isPlaying {
@@ -195,15 +240,27 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (bool)play {
-    () = msg![env; this prepareToPlay];
+    if env.objc.borrow::<AVAudioPlayerHostObject>(this).is_playing {
+        return true;
+    }
 
-    let aq_ref = env.objc.borrow_mut::<AVAudioPlayerHostObject>(this).audio_queue.unwrap();
+    let _: bool = msg![env; this prepareToPlay];
 
-    env.objc.borrow_mut::<AVAudioPlayerHostObject>(this).is_playing = true;
+    let host_object = env.objc.borrow_mut::<AVAudioPlayerHostObject>(this);
+    let aq_ref = host_object.audio_queue.unwrap();
+    host_object.is_playing = true;
+    let reached_end = host_object.reached_end;
 
     let status = AudioQueueStart(env, aq_ref, Ptr::null());
     assert_eq!(status, 0);
 
+    // If the whole file fit into the buffers, the end was reached before the
+    // queue was started, so the queue needs to be told to stop once it's done.
+    if reached_end {
+        let status = AudioQueueStop(env, aq_ref, false);
+        assert_eq!(status, 0);
+    }
+
     true
 }
 
@@ -214,39 +271,43 @@ pub const CLASSES: ClassExports = objc_classes! {
     }
 }
 
+// Unlike pause, this undoes prepareToPlay, but like pause, the current time is
+// kept.
 - (())stop {
+    let current_time: NSTimeInterval = msg![env; this currentTime];
+
     let &mut AVAudioPlayerHostObject {
         audio_queue,
         audio_queue_buffers,
         ..
     } = env.objc.borrow_mut::<AVAudioPlayerHostObject>(this);
-    if audio_queue.is_none() {
+    let Some(audio_queue) = audio_queue else {
         // already being stopped
+        env.objc.borrow_mut::<AVAudioPlayerHostObject>(this).is_playing = false;
         return;
-    }
-    AudioQueueDispose(env, audio_queue.unwrap(), true);
+    };
+    AudioQueueDispose(env, audio_queue, true);
     env.mem.free(audio_queue_buffers.unwrap().cast());
 
-    let &AVAudioPlayerHostObject { audio_file_url, output_callback, num_of_loops, audio_file_id, .. } = env.objc.borrow(this);
-    *env.objc.borrow_mut::<AVAudioPlayerHostObject>(this) = AVAudioPlayerHostObject {
-        audio_file_url,
-        output_callback,
-        num_of_loops,
-        audio_file_id,
-        audio_desc: None,
-        audio_queue: None,
-        audio_queue_buffers: None,
-        num_packets_to_read: 0,
-        current_packet: 0,
-        set_current_time: 0.0,
-        volume: 1.0,
-        is_playing: false
-    };
+    let host_object = env.objc.borrow_mut::<AVAudioPlayerHostObject>(this);
+    host_object.audio_queue = None;
+    host_object.audio_queue_buffers = None;
+    host_object.num_packets_to_read = 0;
+    host_object.current_packet = 0;
+    host_object.enqueued_buffers.clear();
+    host_object.set_current_time = current_time;
+    host_object.is_playing = false;
+    host_object.reached_end = false;
 }
 
+- (NSInteger)numberOfLoops {
+    env.objc.borrow::<AVAudioPlayerHostObject>(this).num_of_loops
+}
 - (())setNumberOfLoops:(NSInteger)numberOfLoops {
     log_dbg!("[(AVAudioPlayer *) {:?} setNumberOfLoops:{:?}]", this, numberOfLoops);
-    env.objc.borrow_mut::<AVAudioPlayerHostObject>(this).num_of_loops = numberOfLoops;
+    let host_object = env.objc.borrow_mut::<AVAudioPlayerHostObject>(this);
+    host_object.num_of_loops = numberOfLoops;
+    host_object.loops_remaining = numberOfLoops;
 }
 
 - (())dealloc {
@@ -259,38 +320,121 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.dealloc_object(this, &mut env.mem)
 }
 
+- (NSTimeInterval)duration {
+    let &AVAudioPlayerHostObject { audio_file_id, audio_desc, .. } = env.objc.borrow(this);
+    let (Some(audio_file_id), Some(audio_desc)) = (audio_file_id, audio_desc) else {
+        return 0.0;
+    };
+    let total_packets = get_packet_count(env, audio_file_id);
+    (total_packets as f64) * (audio_desc.frames_per_packet as f64) / audio_desc.sample_rate
+}
+
 - (NSTimeInterval)currentTime {
     let host_object = env.objc.borrow::<AVAudioPlayerHostObject>(this);
-    let current_time = if let Some(audio_desc) = host_object.audio_desc {
-        let current_frame = (host_object.current_packet as f64) * (audio_desc.frames_per_packet as f64);
-        current_frame / audio_desc.sample_rate
-    } else {
-        0.0
+    let current_time = match (host_object.audio_queue, host_object.audio_desc) {
+        (Some(_), Some(audio_desc)) if !host_object.reached_end || !host_object.enqueued_buffers.is_empty() => {
+            // The oldest buffer still in the queue is the one being played.
+            let current_packet = host_object
+                .enqueued_buffers
+                .front()
+                .map(|&(_, packet)| packet)
+                .unwrap_or(host_object.current_packet);
+            let current_frame = (current_packet as f64) * (audio_desc.frames_per_packet as f64);
+            current_frame / audio_desc.sample_rate
+        }
+        _ => host_object.set_current_time,
     };
     log_dbg!("[(AVAudioPlayer *) {:?} currentTime] -> {:?}", this, current_time);
     current_time
 }
 - (())setCurrentTime:(NSTimeInterval)currentTime {
-    // TODO: Support setting current time before having an audio description
+    log_dbg!("[(AVAudioPlayer *) {:?} setCurrentTime: {}]", this, currentTime);
     let host_object = env.objc.borrow_mut::<AVAudioPlayerHostObject>(this);
     host_object.set_current_time = currentTime;
-    if let (Some(audio_desc), Some(audio_file_id)) = (host_object.audio_desc, host_object.audio_file_id) {
-        let total_packets = audio_file::State::get(&mut env.framework_state).audio_files.get(&audio_file_id).unwrap().audio_file.packet_count();
-        let total_frames = total_packets * audio_desc.frames_per_packet as u64;
-        let new_current_frame = audio_desc.sample_rate * currentTime;
-        if new_current_frame < 0.0 || new_current_frame > total_frames as f64 {
-            host_object.current_packet = 0;
-        } else {
-            host_object.current_packet = (new_current_frame / (audio_desc.frames_per_packet as f64)) as i64;
+    let (Some(aq_ref), Some(audio_desc), Some(audio_file_id)) = (
+        host_object.audio_queue,
+        host_object.audio_desc,
+        host_object.audio_file_id,
+    ) else {
+        // Will be applied by prepareToPlay.
+        return;
+    };
+    let is_playing = host_object.is_playing;
+    // Make sure the stop below isn't mistaken for the end of playback.
+    host_object.reached_end = false;
+
+    let total_packets = get_packet_count(env, audio_file_id);
+    let total_frames = total_packets * audio_desc.frames_per_packet as u64;
+    let new_current_frame = audio_desc.sample_rate * currentTime;
+    let new_current_packet = if new_current_frame < 0.0 || new_current_frame > total_frames as f64 {
+        0
+    } else {
+        (new_current_frame / (audio_desc.frames_per_packet as f64)) as i64
+    };
+
+    // Throw away whatever has been buffered so far and refill the buffers
+    // from the new position. Stopping the queue is only way to take the
+    // buffers back.
+    let status = AudioQueueStop(env, aq_ref, true);
+    assert_eq!(status, 0);
+
+    let host_object = env.objc.borrow_mut::<AVAudioPlayerHostObject>(this);
+    host_object.current_packet = new_current_packet;
+    host_object.enqueued_buffers.clear();
+    host_object.reached_end = false;
+    host_object.loops_remaining = host_object.num_of_loops;
+    let buffers = host_object.audio_queue_buffers.unwrap();
+    for i in 0..kNumberBuffers {
+        let buffer = env.mem.read(buffers + i as u32);
+        fill_buffer(env, this, aq_ref, buffer);
+    }
+
+    if is_playing {
+        let reached_end = env.objc.borrow::<AVAudioPlayerHostObject>(this).reached_end;
+        let status = AudioQueueStart(env, aq_ref, Ptr::null());
+        assert_eq!(status, 0);
+        if reached_end {
+            let status = AudioQueueStop(env, aq_ref, false);
+            assert_eq!(status, 0);
         }
     }
-    log_dbg!("[(AVAudioPlayer *) {:?} setCurrentTime: {}]", this, currentTime);
 }
 
 @end
 
 };
 
+fn get_audio_desc(
+    env: &mut Environment,
+    audio_file_id: AudioFileID,
+) -> AudioStreamBasicDescription {
+    let size = guest_size_of::<AudioStreamBasicDescription>();
+    let tmp_size_ptr: MutPtr<GuestUSize> = env.mem.alloc_and_write(size);
+    let tmp_data_ptr: MutPtr<AudioStreamBasicDescription> = env.mem.alloc(size).cast();
+    let status = AudioFileGetProperty(
+        env,
+        audio_file_id,
+        kAudioFilePropertyDataFormat,
+        tmp_size_ptr,
+        tmp_data_ptr.cast(),
+    );
+    assert_eq!(status, 0);
+    assert_eq!(size, env.mem.read(tmp_size_ptr));
+    let audio_desc = env.mem.read(tmp_data_ptr);
+    env.mem.free(tmp_data_ptr.cast());
+    env.mem.free(tmp_size_ptr.cast());
+    audio_desc
+}
+
+fn get_packet_count(env: &mut Environment, audio_file_id: AudioFileID) -> u64 {
+    audio_file::State::get(&mut env.framework_state)
+        .audio_files
+        .get(&audio_file_id)
+        .unwrap()
+        .audio_file
+        .packet_count()
+}
+
 // Listing 3-7 from `Deriving a playback audio queue buffer size`
 // from the Apple's guide
 fn derive_buffer_size(
@@ -325,43 +469,22 @@ fn derive_buffer_size(
     (out_buffer_size, out_num_packets_to_read)
 }
 
-/// (*void)(void *in_user_data, AudioQueueRef in_aq, AudioQueueBufferRef in_buf)
-fn _touchHLE_AVAudioPlayerOutputBufferHelper(
-    env: &mut Environment,
-    in_user_data: MutVoidPtr,
-    in_aq: AudioQueueRef,
-    in_buf: AudioQueueBufferRef,
-) {
-    let av_audio_player: id = in_user_data.cast();
-    let class: Class = msg![env; av_audio_player class];
-    log_dbg!(
-        "_touchHLE_AVAudioPlayerOutputBufferHelper on object of class: {}",
-        env.objc.get_class_name(class)
-    );
-    assert_eq!(
-        class,
-        env.objc.get_known_class("AVAudioPlayer", &mut env.mem)
-    );
-
+/// Read the next packets from the file into a buffer and enqueue it, looping
+/// back to the start of the file if necessary. If the end has been reached for
+/// good, the audio queue is told to stop once it runs out of buffers.
+fn fill_buffer(env: &mut Environment, player: id, aq: AudioQueueRef, buf: AudioQueueBufferRef) {
     let &AVAudioPlayerHostObject {
         audio_file_id,
-        audio_queue,
         num_packets_to_read,
         current_packet,
+        loops_remaining,
         is_playing,
         ..
-    } = env.objc.borrow(av_audio_player);
-    let aq = audio_queue.unwrap();
-    assert_eq!(aq, in_aq);
-
-    if !is_playing {
-        return;
-    }
+    } = env.objc.borrow(player);
 
     let num_bytes_ptr: MutPtr<u32> = env.mem.alloc(guest_size_of::<u32>()).cast();
-    let num_packets_ptr: MutPtr<u32> = env.mem.alloc(guest_size_of::<u32>()).cast();
-    env.mem.write(num_packets_ptr, num_packets_to_read);
-    let mut audio_queue_buffer = env.mem.read(in_buf);
+    let num_packets_ptr: MutPtr<u32> = env.mem.alloc_and_write(num_packets_to_read);
+    let mut audio_queue_buffer = env.mem.read(buf);
     let status = AudioFileReadPackets(
         env,
         audio_file_id.unwrap(),
@@ -380,34 +503,143 @@ fn _touchHLE_AVAudioPlayerOutputBufferHelper(
     if num_packets > 0 {
         assert!(status == 0 || status == eofErr);
         audio_queue_buffer.audio_data_byte_size = num_bytes;
-        env.mem.write(in_buf, audio_queue_buffer);
-        let status = AudioQueueEnqueueBuffer(env, aq, in_buf, 0, Ptr::null());
+        env.mem.write(buf, audio_queue_buffer);
+        let status = AudioQueueEnqueueBuffer(env, aq, buf, 0, Ptr::null());
         assert_eq!(status, 0);
+        let host_object = env.objc.borrow_mut::<AVAudioPlayerHostObject>(player);
+        host_object
+            .enqueued_buffers
+            .push_back((buf, current_packet));
+        host_object.current_packet = current_packet + num_packets as i64;
+        return;
+    }
+
+    assert_eq!(status, eofErr);
+    // An empty file must not loop forever.
+    if loops_remaining == 0 || current_packet == 0 {
         env.objc
-            .borrow_mut::<AVAudioPlayerHostObject>(av_audio_player)
-            .current_packet = current_packet + num_packets as i64;
-    } else {
-        assert_eq!(status, eofErr);
-        let number_of_loops = env
-            .objc
-            .borrow::<AVAudioPlayerHostObject>(av_audio_player)
-            .num_of_loops;
-        if number_of_loops == 0 {
+            .borrow_mut::<AVAudioPlayerHostObject>(player)
+            .reached_end = true;
+        // If the queue isn't running yet, play will take care of this.
+        if is_playing {
             let status = AudioQueueStop(env, aq, false);
             assert_eq!(status, 0);
-            env.objc
-                .borrow_mut::<AVAudioPlayerHostObject>(av_audio_player)
-                .is_playing = false;
-        } else {
-            if number_of_loops > 0 {
-                env.objc
-                    .borrow_mut::<AVAudioPlayerHostObject>(av_audio_player)
-                    .num_of_loops -= 1;
-            }
-            env.objc
-                .borrow_mut::<AVAudioPlayerHostObject>(av_audio_player)
-                .current_packet = 0;
-            _touchHLE_AVAudioPlayerOutputBufferHelper(env, in_user_data, in_aq, in_buf);
+        }
+    } else {
+        let host_object = env.objc.borrow_mut::<AVAudioPlayerHostObject>(player);
+        // Negative means loop forever.
+        if loops_remaining > 0 {
+            host_object.loops_remaining -= 1;
+        }
+        host_object.current_packet = 0;
+        fill_buffer(env, player, aq, buf);
+    }
+}
+
+/// (*void)(void *in_user_data, AudioQueueRef in_aq, AudioQueueBufferRef in_buf)
+fn _touchHLE_AVAudioPlayerOutputBufferHelper(
+    env: &mut Environment,
+    in_user_data: MutVoidPtr,
+    in_aq: AudioQueueRef,
+    in_buf: AudioQueueBufferRef,
+) {
+    let av_audio_player: id = in_user_data.cast();
+    let class: Class = msg![env; av_audio_player class];
+    log_dbg!(
+        "_touchHLE_AVAudioPlayerOutputBufferHelper on object of class: {}",
+        env.objc.get_class_name(class)
+    );
+    assert_eq!(
+        class,
+        env.objc.get_known_class("AVAudioPlayer", &mut env.mem)
+    );
+
+    let host_object = env
+        .objc
+        .borrow_mut::<AVAudioPlayerHostObject>(av_audio_player);
+    assert_eq!(host_object.audio_queue.unwrap(), in_aq);
+
+    // The buffer has been played.
+    if let Some(idx) = host_object
+        .enqueued_buffers
+        .iter()
+        .position(|&(buf, _)| buf == in_buf)
+    {
+        host_object.enqueued_buffers.drain(..=idx);
+    }
+
+    if host_object.reached_end {
+        return;
+    }
+
+    fill_buffer(env, av_audio_player, in_aq, in_buf);
+}
+
+/// (*void)(void *in_user_data, AudioQueueRef in_aq, AudioQueuePropertyID in_id)
+fn _touchHLE_AVAudioPlayerIsRunningHelper(
+    env: &mut Environment,
+    in_user_data: MutVoidPtr,
+    in_aq: AudioQueueRef,
+    in_id: AudioQueuePropertyID,
+) {
+    assert_eq!(in_id, kAudioQueueProperty_IsRunning);
+    let av_audio_player: id = in_user_data.cast();
+
+    let &AVAudioPlayerHostObject {
+        reached_end,
+        is_playing,
+        delegate,
+        ..
+    } = env.objc.borrow(av_audio_player);
+    if !reached_end || !is_playing {
+        return;
+    }
+
+    let size_ptr: MutPtr<u32> = env.mem.alloc_and_write(guest_size_of::<u32>());
+    let is_running_ptr: MutPtr<u32> = env.mem.alloc(guest_size_of::<u32>()).cast();
+    let status = AudioQueueGetProperty(
+        env,
+        in_aq,
+        kAudioQueueProperty_IsRunning,
+        is_running_ptr.cast(),
+        size_ptr,
+    );
+    assert_eq!(status, 0);
+    let is_running = env.mem.read(is_running_ptr) != 0;
+    env.mem.free(is_running_ptr.cast());
+    env.mem.free(size_ptr.cast());
+    if is_running {
+        return;
+    }
+
+    // Playback has finished normally. The next play should start from the
+    // beginning.
+    log_dbg!("AVAudioPlayer {:?} finished playing", av_audio_player);
+    let host_object = env
+        .objc
+        .borrow_mut::<AVAudioPlayerHostObject>(av_audio_player);
+    host_object.is_playing = false;
+    host_object.reached_end = false;
+    host_object.current_packet = 0;
+    host_object.enqueued_buffers.clear();
+    host_object.set_current_time = 0.0;
+    host_object.loops_remaining = host_object.num_of_loops;
+    // The audio queue has been reset, so the buffers need refilling.
+    let buffers = host_object.audio_queue_buffers.unwrap();
+    for i in 0..kNumberBuffers {
+        let buffer = env.mem.read(buffers + i as u32);
+        fill_buffer(env, av_audio_player, in_aq, buffer);
+    }
+
+    if delegate != nil {
+        let sel = selector!(audioPlayerDidFinishPlaying:successfully:);
+        let responds: bool = msg![env; delegate respondsToSelector:sel];
+        if responds {
+            // The player must stay alive while the delegate is notified.
+            retain(env, av_audio_player);
+            () = msg![env; delegate audioPlayerDidFinishPlaying:av_audio_player
+                                                    successfully:true];
+            release(env, av_audio_player);
         }
     }
 }
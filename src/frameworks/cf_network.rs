@@ -0,0 +1,12 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! The CFNetwork framework.
+//!
+//! Only plain HTTP is supported, using the client in `src/http.rs`, and only
+//! if network access is allowed with `--allow-network-access`.
+
+pub mod cf_http_message;
+pub mod cf_http_stream;
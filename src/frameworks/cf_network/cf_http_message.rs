@@ -0,0 +1,402 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CFHTTPMessage`.
+//!
+//! This is not toll-free bridged to anything, but here it is an Objective-C
+//! object like every other CF type, so that `CFRetain` and `CFRelease` work.
+
+use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant};
+use crate::frameworks::core_foundation::cf_allocator::{kCFAllocatorDefault, CFAllocatorRef};
+use crate::frameworks::core_foundation::cf_data::CFDataRef;
+use crate::frameworks::core_foundation::cf_dictionary::CFDictionaryRef;
+use crate::frameworks::core_foundation::cf_string::CFStringRef;
+use crate::frameworks::core_foundation::cf_url::CFURLRef;
+use crate::frameworks::core_foundation::{CFIndex, CFTypeRef};
+use crate::frameworks::foundation::{ns_data, ns_dictionary, ns_string, NSUInteger};
+use crate::http;
+use crate::objc::{
+    id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
+};
+use crate::Environment;
+
+pub type CFHTTPMessageRef = CFTypeRef;
+
+pub const kCFHTTPVersion1_0: &str = "HTTP/1.0";
+pub const kCFHTTPVersion1_1: &str = "HTTP/1.1";
+
+pub const CONSTANTS: ConstantExports = &[
+    (
+        "_kCFHTTPVersion1_0",
+        HostConstant::NSString(kCFHTTPVersion1_0),
+    ),
+    (
+        "_kCFHTTPVersion1_1",
+        HostConstant::NSString(kCFHTTPVersion1_1),
+    ),
+];
+
+struct CFHTTPMessageHostObject {
+    /// `None` for responses.
+    request: Option<RequestLine>,
+    /// `None` for requests.
+    response: Option<StatusLine>,
+    version: String,
+    /// Header fields in the order they were set. Names are case-insensitive.
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+impl HostObject for CFHTTPMessageHostObject {}
+
+struct RequestLine {
+    method: String,
+    /// `NSURL*`
+    url: id,
+}
+
+struct StatusLine {
+    code: CFIndex,
+    line: String,
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation _touchHLE_CFHTTPMessage: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(CFHTTPMessageHostObject {
+        request: None,
+        response: None,
+        version: kCFHTTPVersion1_1.to_string(),
+        headers: Vec::new(),
+        body: Vec::new(),
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (())dealloc {
+    if let Some(RequestLine { url, .. }) = env.objc.borrow::<CFHTTPMessageHostObject>(this).request {
+        release(env, url);
+    }
+    env.objc.dealloc_object(this, &mut env.mem);
+}
+
+@end
+
+};
+
+fn new_message(env: &mut Environment, host_object: CFHTTPMessageHostObject) -> CFHTTPMessageRef {
+    let message: id = msg_class![env; _touchHLE_CFHTTPMessage alloc];
+    *env.objc.borrow_mut(message) = host_object;
+    message
+}
+
+/// For use by `CFReadStreamCopyProperty`: create a response message. The
+/// caller owns the result.
+pub fn new_response_message(env: &mut Environment, response: &http::Response) -> id {
+    let version = response
+        .status_line
+        .split(' ')
+        .next()
+        .unwrap_or(kCFHTTPVersion1_0)
+        .to_string();
+    new_message(
+        env,
+        CFHTTPMessageHostObject {
+            request: None,
+            response: Some(StatusLine {
+                code: response.status_code.into(),
+                line: response.status_line.clone(),
+            }),
+            version,
+            headers: response.headers.clone(),
+            body: Vec::new(),
+        },
+    )
+}
+
+/// For use by `CFReadStreamCreateForHTTPRequest`: get the request that should
+/// be made for a request message.
+pub fn to_http_request(env: &mut Environment, message: CFHTTPMessageRef) -> http::Request {
+    let host_object = env.objc.borrow::<CFHTTPMessageHostObject>(message);
+    let Some(RequestLine { ref method, url }) = host_object.request else {
+        panic!("{:?} is not a request message", message);
+    };
+    let method = method.clone();
+    let headers = host_object.headers.clone();
+    let body = host_object.body.clone();
+    let url: id = msg![env; url absoluteString];
+    let url = ns_string::to_rust_string(env, url).into_owned();
+    http::Request {
+        method,
+        url,
+        headers,
+        body,
+    }
+}
+
+fn CFHTTPMessageCreateRequest(
+    env: &mut Environment,
+    allocator: CFAllocatorRef,
+    request_method: CFStringRef,
+    url: CFURLRef,
+    http_version: CFStringRef,
+) -> CFHTTPMessageRef {
+    assert!(allocator == kCFAllocatorDefault); // unimplemented
+    let method = ns_string::to_rust_string(env, request_method).into_owned();
+    let version = ns_string::to_rust_string(env, http_version).into_owned();
+    retain(env, url);
+    new_message(
+        env,
+        CFHTTPMessageHostObject {
+            request: Some(RequestLine { method, url }),
+            response: None,
+            version,
+            headers: Vec::new(),
+            body: Vec::new(),
+        },
+    )
+}
+
+fn CFHTTPMessageCreateResponse(
+    env: &mut Environment,
+    allocator: CFAllocatorRef,
+    status_code: CFIndex,
+    status_description: CFStringRef,
+    http_version: CFStringRef,
+) -> CFHTTPMessageRef {
+    assert!(allocator == kCFAllocatorDefault); // unimplemented
+    let version = ns_string::to_rust_string(env, http_version).into_owned();
+    let line = if status_description == nil {
+        format!("{} {}", version, status_code)
+    } else {
+        let description = ns_string::to_rust_string(env, status_description);
+        format!("{} {} {}", version, status_code, description)
+    };
+    new_message(
+        env,
+        CFHTTPMessageHostObject {
+            request: None,
+            response: Some(StatusLine {
+                code: status_code,
+                line,
+            }),
+            version,
+            headers: Vec::new(),
+            body: Vec::new(),
+        },
+    )
+}
+
+fn CFHTTPMessageIsRequest(env: &mut Environment, message: CFHTTPMessageRef) -> bool {
+    env.objc
+        .borrow::<CFHTTPMessageHostObject>(message)
+        .request
+        .is_some()
+}
+
+/// Messages can only be created complete.
+fn CFHTTPMessageIsHeaderComplete(_env: &mut Environment, _message: CFHTTPMessageRef) -> bool {
+    true
+}
+
+fn CFHTTPMessageCopyVersion(env: &mut Environment, message: CFHTTPMessageRef) -> CFStringRef {
+    let version = env
+        .objc
+        .borrow::<CFHTTPMessageHostObject>(message)
+        .version
+        .clone();
+    ns_string::from_rust_string(env, version)
+}
+
+fn CFHTTPMessageCopyRequestURL(env: &mut Environment, message: CFHTTPMessageRef) -> CFURLRef {
+    match env.objc.borrow::<CFHTTPMessageHostObject>(message).request {
+        Some(RequestLine { url, .. }) => retain(env, url),
+        None => nil,
+    }
+}
+
+fn CFHTTPMessageCopyRequestMethod(env: &mut Environment, message: CFHTTPMessageRef) -> CFStringRef {
+    match env.objc.borrow::<CFHTTPMessageHostObject>(message).request {
+        Some(RequestLine { ref method, .. }) => {
+            let method = method.clone();
+            ns_string::from_rust_string(env, method)
+        }
+        None => nil,
+    }
+}
+
+fn CFHTTPMessageGetResponseStatusCode(env: &mut Environment, message: CFHTTPMessageRef) -> CFIndex {
+    match env.objc.borrow::<CFHTTPMessageHostObject>(message).response {
+        Some(StatusLine { code, .. }) => code,
+        None => 0,
+    }
+}
+
+fn CFHTTPMessageCopyResponseStatusLine(
+    env: &mut Environment,
+    message: CFHTTPMessageRef,
+) -> CFStringRef {
+    match env.objc.borrow::<CFHTTPMessageHostObject>(message).response {
+        Some(StatusLine { ref line, .. }) => {
+            let line = line.clone();
+            ns_string::from_rust_string(env, line)
+        }
+        None => nil,
+    }
+}
+
+fn CFHTTPMessageCopyHeaderFieldValue(
+    env: &mut Environment,
+    message: CFHTTPMessageRef,
+    header_field: CFStringRef,
+) -> CFStringRef {
+    let name = ns_string::to_rust_string(env, header_field);
+    let value = env
+        .objc
+        .borrow::<CFHTTPMessageHostObject>(message)
+        .headers
+        .iter()
+        .find(|(other, _)| other.eq_ignore_ascii_case(&name))
+        .map(|(_, value)| value.clone());
+    match value {
+        Some(value) => ns_string::from_rust_string(env, value),
+        None => nil,
+    }
+}
+
+fn CFHTTPMessageSetHeaderFieldValue(
+    env: &mut Environment,
+    message: CFHTTPMessageRef,
+    header_field: CFStringRef,
+    value: CFStringRef,
+) {
+    let name = ns_string::to_rust_string(env, header_field).into_owned();
+    let value = if value == nil {
+        None
+    } else {
+        Some(ns_string::to_rust_string(env, value).into_owned())
+    };
+    let headers = &mut env
+        .objc
+        .borrow_mut::<CFHTTPMessageHostObject>(message)
+        .headers;
+    let existing = headers
+        .iter()
+        .position(|(other, _)| other.eq_ignore_ascii_case(&name));
+    match (existing, value) {
+        (Some(index), Some(value)) => headers[index].1 = value,
+        (Some(index), None) => {
+            headers.remove(index);
+        }
+        (None, Some(value)) => headers.push((name, value)),
+        (None, None) => (),
+    }
+}
+
+fn CFHTTPMessageCopyAllHeaderFields(
+    env: &mut Environment,
+    message: CFHTTPMessageRef,
+) -> CFDictionaryRef {
+    let headers = env
+        .objc
+        .borrow::<CFHTTPMessageHostObject>(message)
+        .headers
+        .clone();
+    let keys_and_objects: Vec<(id, id)> = headers
+        .into_iter()
+        .map(|(name, value)| {
+            (
+                ns_string::from_rust_string(env, name),
+                ns_string::from_rust_string(env, value),
+            )
+        })
+        .collect();
+    let dict = ns_dictionary::dict_from_keys_and_objects(env, &keys_and_objects);
+    for (key, object) in keys_and_objects {
+        release(env, key);
+        release(env, object);
+    }
+    dict
+}
+
+fn CFHTTPMessageSetBody(env: &mut Environment, message: CFHTTPMessageRef, body_data: CFDataRef) {
+    let length: NSUInteger = msg![env; body_data length];
+    let body = if length == 0 {
+        Vec::new()
+    } else {
+        ns_data::to_rust_slice(env, body_data).to_vec()
+    };
+    env.objc.borrow_mut::<CFHTTPMessageHostObject>(message).body = body;
+}
+
+fn CFHTTPMessageCopyBody(env: &mut Environment, message: CFHTTPMessageRef) -> CFDataRef {
+    let body = env
+        .objc
+        .borrow::<CFHTTPMessageHostObject>(message)
+        .body
+        .clone();
+    if body.is_empty() {
+        nil
+    } else {
+        ns_data::from_vec(env, body)
+    }
+}
+
+fn CFHTTPMessageCopySerializedMessage(
+    env: &mut Environment,
+    message: CFHTTPMessageRef,
+) -> CFDataRef {
+    let first_line = match env.objc.borrow::<CFHTTPMessageHostObject>(message) {
+        CFHTTPMessageHostObject {
+            response: Some(StatusLine { line, .. }),
+            ..
+        } => line.clone(),
+        CFHTTPMessageHostObject {
+            request: Some(RequestLine { method, .. }),
+            version,
+            ..
+        } => {
+            let (method, version) = (method.clone(), version.clone());
+            let request = to_http_request(env, message);
+            let path = request
+                .url
+                .split_once("://")
+                .and_then(|(_, rest)| rest.find('/').map(|index| rest[index..].to_string()))
+                .unwrap_or_else(|| "/".to_string());
+            format!("{} {} {}", method, path, version)
+        }
+        _ => unreachable!(),
+    };
+    let host_object = env.objc.borrow::<CFHTTPMessageHostObject>(message);
+    let mut serialized = format!("{}\r\n", first_line);
+    for (name, value) in &host_object.headers {
+        serialized.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    serialized.push_str("\r\n");
+    let mut serialized = serialized.into_bytes();
+    serialized.extend_from_slice(&host_object.body);
+    ns_data::from_vec(env, serialized)
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(CFHTTPMessageCreateRequest(_, _, _, _)),
+    export_c_func!(CFHTTPMessageCreateResponse(_, _, _, _)),
+    export_c_func!(CFHTTPMessageIsRequest(_)),
+    export_c_func!(CFHTTPMessageIsHeaderComplete(_)),
+    export_c_func!(CFHTTPMessageCopyVersion(_)),
+    export_c_func!(CFHTTPMessageCopyRequestURL(_)),
+    export_c_func!(CFHTTPMessageCopyRequestMethod(_)),
+    export_c_func!(CFHTTPMessageGetResponseStatusCode(_)),
+    export_c_func!(CFHTTPMessageCopyResponseStatusLine(_)),
+    export_c_func!(CFHTTPMessageCopyHeaderFieldValue(_, _)),
+    export_c_func!(CFHTTPMessageSetHeaderFieldValue(_, _, _)),
+    export_c_func!(CFHTTPMessageCopyAllHeaderFields(_)),
+    export_c_func!(CFHTTPMessageSetBody(_, _)),
+    export_c_func!(CFHTTPMessageCopyBody(_)),
+    export_c_func!(CFHTTPMessageCopySerializedMessage(_)),
+];
@@ -0,0 +1,43 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CFHTTPStream.h`: read streams for HTTP requests.
+//!
+//! The stream itself is an `NSInputStream`, see `ns_stream.rs`. The request is
+//! made when the stream is opened, and the stream's data is the body of the
+//! response. Redirects aren't followed.
+
+use super::cf_http_message::{to_http_request, CFHTTPMessageRef};
+use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant};
+use crate::frameworks::core_foundation::cf_allocator::{kCFAllocatorDefault, CFAllocatorRef};
+use crate::frameworks::core_foundation::cf_stream::CFReadStreamRef;
+use crate::frameworks::foundation::ns_stream;
+use crate::Environment;
+
+pub const kCFStreamPropertyHTTPResponseHeader: &str = "kCFStreamPropertyHTTPResponseHeader";
+pub const kCFStreamPropertyHTTPShouldAutoredirect: &str = "kCFStreamPropertyHTTPShouldAutoredirect";
+
+pub const CONSTANTS: ConstantExports = &[
+    (
+        "_kCFStreamPropertyHTTPResponseHeader",
+        HostConstant::NSString(kCFStreamPropertyHTTPResponseHeader),
+    ),
+    (
+        "_kCFStreamPropertyHTTPShouldAutoredirect",
+        HostConstant::NSString(kCFStreamPropertyHTTPShouldAutoredirect),
+    ),
+];
+
+fn CFReadStreamCreateForHTTPRequest(
+    env: &mut Environment,
+    allocator: CFAllocatorRef,
+    request: CFHTTPMessageRef,
+) -> CFReadStreamRef {
+    assert!(allocator == kCFAllocatorDefault); // unimplemented
+    let request = to_http_request(env, request);
+    ns_stream::new_http_stream(env, request)
+}
+
+pub const FUNCTIONS: FunctionExports = &[export_c_func!(CFReadStreamCreateForHTTPRequest(_, _))];
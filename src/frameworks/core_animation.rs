@@ -8,13 +8,19 @@
 //! Useful resources:
 //! - Apple's [Core Animation Programming Guide](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/CoreAnimation_guide/Introduction/Introduction.html)
 
+pub mod ca_animation;
+pub mod ca_display_link;
 pub mod ca_eagl_layer;
 pub mod ca_layer;
+pub mod ca_media_timing_function;
 
 mod composition;
+pub use ca_animation::handle_animations;
+pub use ca_display_link::handle_display_link;
 pub use composition::recomposite_if_necessary;
 
 #[derive(Default)]
 pub struct State {
+    animation: ca_animation::State,
     composition: composition::State,
 }
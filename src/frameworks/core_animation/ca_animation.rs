@@ -0,0 +1,872 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CAAnimation` and its subclasses, and the implementation of animations.
+//!
+//! Animations never change the model values of a layer (the ones set by the
+//! app), they only affect what is presented on screen. The compositor uses
+//! [presentation_properties] to get the values to draw with, and
+//! [handle_animations] takes care of removing finished animations and calling
+//! the delegates.
+
+use super::ca_layer::{CALayerHostObject, LayerProperties};
+use super::ca_media_timing_function::{self, ControlPoints};
+use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant};
+use crate::frameworks::core_graphics::cg_affine_transform::CGAffineTransform;
+use crate::frameworks::core_graphics::{CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::ns_string;
+use crate::objc::{
+    autorelease, id, msg, nil, objc_classes, release, retain, Class, ClassExports, HostObject,
+    NSZonePtr,
+};
+use crate::Environment;
+use std::time::{Duration, Instant};
+
+pub type CFTimeInterval = f64;
+
+pub const kCAFillModeRemoved: &str = "removed";
+pub const kCAFillModeForwards: &str = "forwards";
+pub const kCAFillModeBackwards: &str = "backwards";
+pub const kCAFillModeBoth: &str = "both";
+
+pub const CONSTANTS: ConstantExports = &[
+    (
+        "_kCAFillModeRemoved",
+        HostConstant::NSString(kCAFillModeRemoved),
+    ),
+    (
+        "_kCAFillModeForwards",
+        HostConstant::NSString(kCAFillModeForwards),
+    ),
+    (
+        "_kCAFillModeBackwards",
+        HostConstant::NSString(kCAFillModeBackwards),
+    ),
+    ("_kCAFillModeBoth", HostConstant::NSString(kCAFillModeBoth)),
+];
+
+/// The duration used for animations that have a duration of 0.
+const DEFAULT_DURATION: CFTimeInterval = 0.25;
+
+#[derive(Default)]
+pub struct State {
+    /// Layers that have animations attached, in no particular order. These are
+    /// weak references.
+    animating_layers: Vec<id>,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+enum FillMode {
+    #[default]
+    Removed,
+    Forwards,
+    Backwards,
+    Both,
+}
+impl FillMode {
+    fn fills_backwards(self) -> bool {
+        matches!(self, FillMode::Backwards | FillMode::Both)
+    }
+    fn fills_forwards(self) -> bool {
+        matches!(self, FillMode::Forwards | FillMode::Both)
+    }
+}
+
+/// Host object shared by `CAAnimation` and its subclasses.
+#[derive(Clone)]
+struct CAAnimationHostObject {
+    duration: CFTimeInterval,
+    begin_time: CFTimeInterval,
+    /// `CAMediaTimingFunction*`, possibly `nil`. This is a strong reference.
+    timing_function: id,
+    /// Unusually, this is a strong reference.
+    delegate: id,
+    removed_on_completion: bool,
+    fill_mode: FillMode,
+    repeat_count: f32,
+    autoreverses: bool,
+    /// `NSString*`, for `CAPropertyAnimation` and subclasses only. This is a
+    /// strong reference.
+    key_path: id,
+    /// For `CABasicAnimation` only. These are strong references.
+    from_value: id,
+    to_value: id,
+    by_value: id,
+}
+impl HostObject for CAAnimationHostObject {}
+impl Default for CAAnimationHostObject {
+    fn default() -> Self {
+        CAAnimationHostObject {
+            duration: 0.0,
+            begin_time: 0.0,
+            timing_function: nil,
+            delegate: nil,
+            removed_on_completion: true,
+            fill_mode: FillMode::Removed,
+            repeat_count: 0.0,
+            autoreverses: false,
+            key_path: nil,
+            from_value: nil,
+            to_value: nil,
+            by_value: nil,
+        }
+    }
+}
+impl CAAnimationHostObject {
+    fn strong_references(&self) -> [id; 6] {
+        [
+            self.timing_function,
+            self.delegate,
+            self.key_path,
+            self.from_value,
+            self.to_value,
+            self.by_value,
+        ]
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation CAAnimation: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<CAAnimationHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)animation {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new init];
+    autorelease(env, new)
+}
+
+- (())dealloc {
+    let references = env.objc.borrow::<CAAnimationHostObject>(this).strong_references();
+    for object in references {
+        release(env, object);
+    }
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+// NSCopying implementation
+- (id)copyWithZone:(NSZonePtr)_zone {
+    let host_object = env.objc.borrow::<CAAnimationHostObject>(this).clone();
+    for object in host_object.strong_references() {
+        retain(env, object);
+    }
+    let class: Class = msg![env; this class];
+    env.objc.alloc_object(class, Box::new(host_object), &mut env.mem)
+}
+
+- (CFTimeInterval)duration {
+    env.objc.borrow::<CAAnimationHostObject>(this).duration
+}
+- (())setDuration:(CFTimeInterval)duration {
+    env.objc.borrow_mut::<CAAnimationHostObject>(this).duration = duration;
+}
+
+- (CFTimeInterval)beginTime {
+    env.objc.borrow::<CAAnimationHostObject>(this).begin_time
+}
+- (())setBeginTime:(CFTimeInterval)begin_time {
+    env.objc.borrow_mut::<CAAnimationHostObject>(this).begin_time = begin_time;
+}
+
+- (id)timingFunction {
+    env.objc.borrow::<CAAnimationHostObject>(this).timing_function
+}
+- (())setTimingFunction:(id)function { // CAMediaTimingFunction*
+    retain(env, function);
+    let host_object = env.objc.borrow_mut::<CAAnimationHostObject>(this);
+    let old = std::mem::replace(&mut host_object.timing_function, function);
+    release(env, old);
+}
+
+- (id)delegate {
+    env.objc.borrow::<CAAnimationHostObject>(this).delegate
+}
+- (())setDelegate:(id)delegate {
+    retain(env, delegate);
+    let host_object = env.objc.borrow_mut::<CAAnimationHostObject>(this);
+    let old = std::mem::replace(&mut host_object.delegate, delegate);
+    release(env, old);
+}
+
+- (bool)isRemovedOnCompletion {
+    env.objc.borrow::<CAAnimationHostObject>(this).removed_on_completion
+}
+- (())setRemovedOnCompletion:(bool)removed_on_completion {
+    env.objc.borrow_mut::<CAAnimationHostObject>(this).removed_on_completion = removed_on_completion;
+}
+
+- (id)fillMode {
+    let fill_mode = match env.objc.borrow::<CAAnimationHostObject>(this).fill_mode {
+        FillMode::Removed => kCAFillModeRemoved,
+        FillMode::Forwards => kCAFillModeForwards,
+        FillMode::Backwards => kCAFillModeBackwards,
+        FillMode::Both => kCAFillModeBoth,
+    };
+    ns_string::get_static_str(env, fill_mode)
+}
+- (())setFillMode:(id)fill_mode { // NSString*
+    let fill_mode = ns_string::to_rust_string(env, fill_mode);
+    let fill_mode = match &*fill_mode {
+        kCAFillModeRemoved => FillMode::Removed,
+        kCAFillModeForwards => FillMode::Forwards,
+        kCAFillModeBackwards => FillMode::Backwards,
+        kCAFillModeBoth => FillMode::Both,
+        _ => panic!("Unknown fill mode {:?}", fill_mode),
+    };
+    env.objc.borrow_mut::<CAAnimationHostObject>(this).fill_mode = fill_mode;
+}
+
+- (f32)repeatCount {
+    env.objc.borrow::<CAAnimationHostObject>(this).repeat_count
+}
+- (())setRepeatCount:(f32)repeat_count {
+    env.objc.borrow_mut::<CAAnimationHostObject>(this).repeat_count = repeat_count;
+}
+
+- (bool)autoreverses {
+    env.objc.borrow::<CAAnimationHostObject>(this).autoreverses
+}
+- (())setAutoreverses:(bool)autoreverses {
+    env.objc.borrow_mut::<CAAnimationHostObject>(this).autoreverses = autoreverses;
+}
+
+@end
+
+@implementation CAPropertyAnimation: CAAnimation
+
++ (id)animationWithKeyPath:(id)key_path { // NSString*
+    let new: id = msg![env; this animation];
+    () = msg![env; new setKeyPath:key_path];
+    new
+}
+
+- (id)keyPath {
+    env.objc.borrow::<CAAnimationHostObject>(this).key_path
+}
+- (())setKeyPath:(id)key_path { // NSString*
+    let key_path: id = msg![env; key_path copy];
+    let host_object = env.objc.borrow_mut::<CAAnimationHostObject>(this);
+    let old = std::mem::replace(&mut host_object.key_path, key_path);
+    release(env, old);
+}
+
+@end
+
+@implementation CABasicAnimation: CAPropertyAnimation
+
+- (id)fromValue {
+    env.objc.borrow::<CAAnimationHostObject>(this).from_value
+}
+- (())setFromValue:(id)value {
+    retain(env, value);
+    let host_object = env.objc.borrow_mut::<CAAnimationHostObject>(this);
+    let old = std::mem::replace(&mut host_object.from_value, value);
+    release(env, old);
+}
+
+- (id)toValue {
+    env.objc.borrow::<CAAnimationHostObject>(this).to_value
+}
+- (())setToValue:(id)value {
+    retain(env, value);
+    let host_object = env.objc.borrow_mut::<CAAnimationHostObject>(this);
+    let old = std::mem::replace(&mut host_object.to_value, value);
+    release(env, old);
+}
+
+- (id)byValue {
+    env.objc.borrow::<CAAnimationHostObject>(this).by_value
+}
+- (())setByValue:(id)value {
+    retain(env, value);
+    let host_object = env.objc.borrow_mut::<CAAnimationHostObject>(this);
+    let old = std::mem::replace(&mut host_object.by_value, value);
+    release(env, old);
+}
+
+@end
+
+};
+
+/// Get the current time in the timebase used for animations. This is the same
+/// as the one used by `mach_absolute_time()`.
+pub fn CACurrentMediaTime(env: &mut Environment) -> CFTimeInterval {
+    current_media_time(env)
+}
+
+pub(super) fn current_media_time(env: &Environment) -> CFTimeInterval {
+    env.clock
+        .now()
+        .duration_since(env.startup_time)
+        .as_secs_f64()
+}
+
+pub const FUNCTIONS: FunctionExports = &[export_c_func!(CACurrentMediaTime())];
+
+/// A value of some animatable property.
+#[derive(Copy, Clone, Debug)]
+enum Value {
+    Float(f32),
+    Point(CGPoint),
+    Size(CGSize),
+    Rect(CGRect),
+    Transform(CGAffineTransform),
+}
+impl Value {
+    fn zip_with(self, other: Value, f: impl Fn(f32, f32) -> f32) -> Value {
+        match (self, other) {
+            (Value::Float(a), Value::Float(b)) => Value::Float(f(a, b)),
+            (Value::Point(a), Value::Point(b)) => Value::Point(CGPoint {
+                x: f(a.x, b.x),
+                y: f(a.y, b.y),
+            }),
+            (Value::Size(a), Value::Size(b)) => Value::Size(CGSize {
+                width: f(a.width, b.width),
+                height: f(a.height, b.height),
+            }),
+            (Value::Rect(a), Value::Rect(b)) => Value::Rect(CGRect {
+                origin: CGPoint {
+                    x: f(a.origin.x, b.origin.x),
+                    y: f(a.origin.y, b.origin.y),
+                },
+                size: CGSize {
+                    width: f(a.size.width, b.size.width),
+                    height: f(a.size.height, b.size.height),
+                },
+            }),
+            _ => panic!("Mismatched value types: {:?}, {:?}", self, other),
+        }
+    }
+
+    fn add(self, other: Value) -> Value {
+        match (self, other) {
+            (Value::Transform(a), Value::Transform(b)) => Value::Transform(a.concat(b)),
+            _ => self.zip_with(other, |a, b| a + b),
+        }
+    }
+
+    fn subtract(self, other: Value) -> Value {
+        match (self, other) {
+            (Value::Transform(a), Value::Transform(b)) => Value::Transform(a.concat(b.invert())),
+            _ => self.zip_with(other, |a, b| a - b),
+        }
+    }
+
+    fn interpolate(self, other: Value, t: f32) -> Value {
+        match (self, other) {
+            (Value::Transform(a), Value::Transform(b)) => {
+                // Interpolating the matrix elements directly would distort
+                // rotations, so interpolate the decomposed parts instead.
+                let a = decompose_transform(a);
+                let mut b = decompose_transform(b);
+                // Rotate the short way round.
+                let pi = std::f32::consts::PI;
+                let rotation_delta = (b[2] - a[2] + pi).rem_euclid(2.0 * pi) - pi;
+                b[2] = a[2] + rotation_delta;
+                let mut result = [0.0; 6];
+                for i in 0..6 {
+                    result[i] = a[i] + (b[i] - a[i]) * t;
+                }
+                Value::Transform(recompose_transform(result))
+            }
+            _ => self.zip_with(other, |a, b| a + (b - a) * t),
+        }
+    }
+}
+
+/// Decompose an affine transform into
+/// `[translation x, translation y, rotation, scale x, scale y, shear]`.
+fn decompose_transform(transform: CGAffineTransform) -> [f32; 6] {
+    let CGAffineTransform { a, b, c, d, tx, ty } = transform;
+    let scale_x = a.hypot(b);
+    let rotation = b.atan2(a);
+    let (sin, cos) = rotation.sin_cos();
+    let shear = c * cos + d * sin;
+    let scale_y = d * cos - c * sin;
+    [tx, ty, rotation, scale_x, scale_y, shear]
+}
+
+/// Inverse of [decompose_transform].
+fn recompose_transform(parts: [f32; 6]) -> CGAffineTransform {
+    let [tx, ty, rotation, scale_x, scale_y, shear] = parts;
+    let (sin, cos) = rotation.sin_cos();
+    CGAffineTransform {
+        a: scale_x * cos,
+        b: scale_x * sin,
+        c: shear * cos - scale_y * sin,
+        d: shear * sin + scale_y * cos,
+        tx,
+        ty,
+    }
+}
+
+/// A layer property that can be animated, identified by its key path.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum AnimatedProperty {
+    Opacity,
+    Position,
+    PositionX,
+    PositionY,
+    Bounds,
+    BoundsSize,
+    Transform,
+    TransformRotation,
+    TransformScale,
+    TransformScaleX,
+    TransformScaleY,
+    TransformTranslationX,
+    TransformTranslationY,
+}
+impl AnimatedProperty {
+    fn from_key_path(key_path: &str) -> Option<AnimatedProperty> {
+        Some(match key_path {
+            "opacity" => AnimatedProperty::Opacity,
+            "position" => AnimatedProperty::Position,
+            "position.x" => AnimatedProperty::PositionX,
+            "position.y" => AnimatedProperty::PositionY,
+            "bounds" => AnimatedProperty::Bounds,
+            "bounds.size" => AnimatedProperty::BoundsSize,
+            "transform" => AnimatedProperty::Transform,
+            "transform.rotation" | "transform.rotation.z" => AnimatedProperty::TransformRotation,
+            "transform.scale" => AnimatedProperty::TransformScale,
+            "transform.scale.x" => AnimatedProperty::TransformScaleX,
+            "transform.scale.y" => AnimatedProperty::TransformScaleY,
+            "transform.translation.x" => AnimatedProperty::TransformTranslationX,
+            "transform.translation.y" => AnimatedProperty::TransformTranslationY,
+            _ => return None,
+        })
+    }
+
+    /// For the parts of the transform that can be animated separately, the
+    /// index into the result of [decompose_transform].
+    fn transform_part(self) -> Option<usize> {
+        match self {
+            AnimatedProperty::TransformTranslationX => Some(0),
+            AnimatedProperty::TransformTranslationY => Some(1),
+            AnimatedProperty::TransformRotation => Some(2),
+            AnimatedProperty::TransformScale | AnimatedProperty::TransformScaleX => Some(3),
+            AnimatedProperty::TransformScaleY => Some(4),
+            _ => None,
+        }
+    }
+
+    fn get(self, properties: &LayerProperties) -> Value {
+        if let Some(part) = self.transform_part() {
+            return Value::Float(decompose_transform(properties.affine_transform)[part]);
+        }
+        match self {
+            AnimatedProperty::Opacity => Value::Float(properties.opacity),
+            AnimatedProperty::Position => Value::Point(properties.position),
+            AnimatedProperty::PositionX => Value::Float(properties.position.x),
+            AnimatedProperty::PositionY => Value::Float(properties.position.y),
+            AnimatedProperty::Bounds => Value::Rect(properties.bounds),
+            AnimatedProperty::BoundsSize => Value::Size(properties.bounds.size),
+            AnimatedProperty::Transform => Value::Transform(properties.affine_transform),
+            _ => unreachable!(),
+        }
+    }
+
+    fn set(self, properties: &mut LayerProperties, value: Value) {
+        if let Some(part) = self.transform_part() {
+            let Value::Float(value) = value else {
+                unreachable!();
+            };
+            let mut parts = decompose_transform(properties.affine_transform);
+            parts[part] = value;
+            if self == AnimatedProperty::TransformScale {
+                parts[4] = value;
+            }
+            properties.affine_transform = recompose_transform(parts);
+            return;
+        }
+        match (self, value) {
+            (AnimatedProperty::Opacity, Value::Float(opacity)) => properties.opacity = opacity,
+            (AnimatedProperty::Position, Value::Point(position)) => properties.position = position,
+            (AnimatedProperty::PositionX, Value::Float(x)) => properties.position.x = x,
+            (AnimatedProperty::PositionY, Value::Float(y)) => properties.position.y = y,
+            (AnimatedProperty::Bounds, Value::Rect(bounds)) => properties.bounds = bounds,
+            (AnimatedProperty::BoundsSize, Value::Size(size)) => properties.bounds.size = size,
+            (AnimatedProperty::Transform, Value::Transform(transform)) => {
+                properties.affine_transform = transform
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Read a `fromValue`, `toValue` or `byValue` (an `NSNumber*` or
+    /// `NSValue*`) for this property.
+    fn read_value(self, env: &mut Environment, value: id) -> Value {
+        match self {
+            AnimatedProperty::Position => Value::Point(msg![env; value CGPointValue]),
+            AnimatedProperty::Bounds => Value::Rect(msg![env; value CGRectValue]),
+            AnimatedProperty::BoundsSize => Value::Size(msg![env; value CGSizeValue]),
+            // TODO: CATransform3D values
+            AnimatedProperty::Transform => {
+                Value::Transform(msg![env; value CGAffineTransformValue])
+            }
+            _ => Value::Float(msg![env; value floatValue]),
+        }
+    }
+}
+
+/// An animation that has been added to a layer.
+pub(super) struct LayerAnimation {
+    key: Option<String>,
+    /// The layer's own copy of the `CAAnimation*`. This is a strong reference.
+    animation: id,
+    property: AnimatedProperty,
+    from: Value,
+    /// [None] means the model value is used.
+    to: Option<Value>,
+    /// In the timebase of [CACurrentMediaTime].
+    begin: CFTimeInterval,
+    /// Duration of a single cycle (not accounting for `autoreverses`).
+    duration: CFTimeInterval,
+    repeat_count: f32,
+    autoreverses: bool,
+    fill_mode: FillMode,
+    timing_function: ControlPoints,
+    removed_on_completion: bool,
+    started: bool,
+    finished: bool,
+}
+impl LayerAnimation {
+    pub(super) fn key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
+
+    fn active_duration(&self) -> CFTimeInterval {
+        let cycle = if self.autoreverses {
+            self.duration * 2.0
+        } else {
+            self.duration
+        };
+        cycle * (self.repeat_count.max(1.0) as CFTimeInterval)
+    }
+
+    fn is_finished(&self, now: CFTimeInterval) -> bool {
+        now >= self.begin + self.active_duration()
+    }
+
+    /// Get the progress of the animation at some time, with the timing function
+    /// applied, or [None] if the animation doesn't have an effect at that
+    /// time.
+    fn progress(&self, now: CFTimeInterval) -> Option<f32> {
+        let elapsed = now - self.begin;
+        let active_duration = self.active_duration();
+        if (elapsed < 0.0 && !self.fill_mode.fills_backwards())
+            || (elapsed >= active_duration && !self.fill_mode.fills_forwards())
+        {
+            return None;
+        }
+        let elapsed = elapsed.clamp(0.0, active_duration);
+
+        let cycle = active_duration / (self.repeat_count.max(1.0) as CFTimeInterval);
+        let mut local_time = elapsed % cycle;
+        // At the very end, the animation should be at the end of its last
+        // cycle, not the start of a new one.
+        if local_time == 0.0 && elapsed > 0.0 {
+            local_time = cycle;
+        }
+        if self.autoreverses && local_time > self.duration {
+            local_time = cycle - local_time;
+        }
+        let t = (local_time / self.duration) as f32;
+        Some(ca_media_timing_function::evaluate(self.timing_function, t))
+    }
+}
+
+/// Get the properties of a layer as they should be presented at some time,
+/// i.e. with all its animations applied to the model values.
+pub(super) fn presentation_properties(
+    host_obj: &CALayerHostObject,
+    now: CFTimeInterval,
+) -> LayerProperties {
+    let model = host_obj.model_properties();
+    let mut presentation = model;
+    for animation in &host_obj.animations {
+        let Some(progress) = animation.progress(now) else {
+            continue;
+        };
+        let to = animation
+            .to
+            .unwrap_or_else(|| animation.property.get(&model));
+        let value = animation.from.interpolate(to, progress);
+        animation.property.set(&mut presentation, value);
+    }
+    presentation
+}
+
+/// Implementation of `-[CALayer addAnimation:forKey:]`.
+pub(super) fn add_animation(env: &mut Environment, layer: id, animation: id, key: id) {
+    let key = (key != nil).then(|| ns_string::to_rust_string(env, key).into_owned());
+
+    // The layer gets its own copy, so later changes by the app have no effect.
+    let animation: id = msg![env; animation copy];
+    let CAAnimationHostObject {
+        duration,
+        begin_time,
+        timing_function,
+        removed_on_completion,
+        fill_mode,
+        repeat_count,
+        autoreverses,
+        key_path,
+        from_value,
+        to_value,
+        by_value,
+        ..
+    } = env.objc.borrow::<CAAnimationHostObject>(animation).clone();
+
+    if key_path == nil {
+        log!(
+            "TODO: Animation {:?} has no key path, ignoring (only CABasicAnimation is supported)",
+            animation
+        );
+        release(env, animation);
+        return;
+    }
+    let key_path = ns_string::to_rust_string(env, key_path);
+    let Some(property) = AnimatedProperty::from_key_path(&key_path) else {
+        log!(
+            "TODO: Animating key path {:?} is not supported, ignoring animation {:?}",
+            key_path,
+            animation
+        );
+        release(env, animation);
+        return;
+    };
+
+    // An animation with the same key replaces the existing one.
+    if let Some(ref key) = key {
+        remove_animations(env, layer, |existing| existing.key.as_deref() == Some(key));
+    }
+
+    let now = current_media_time(env);
+    let current = property.get(&presentation_properties(env.objc.borrow(layer), now));
+    let [from_value, to_value, by_value] = [from_value, to_value, by_value]
+        .map(|value| (value != nil).then(|| property.read_value(env, value)));
+    // See the CABasicAnimation documentation for what the combinations mean.
+    let (from, to) = match (from_value, to_value, by_value) {
+        (Some(from), Some(to), _) => (from, Some(to)),
+        (Some(from), None, Some(by)) => (from, Some(from.add(by))),
+        (None, Some(to), Some(by)) => (to.subtract(by), Some(to)),
+        (Some(from), None, None) => (from, None),
+        (None, Some(to), None) => (current, Some(to)),
+        (None, None, Some(by)) => (current, Some(current.add(by))),
+        (None, None, None) => (current, None),
+    };
+
+    let layer_animation = LayerAnimation {
+        key,
+        animation,
+        property,
+        from,
+        to,
+        begin: if begin_time != 0.0 { begin_time } else { now },
+        duration: if duration > 0.0 {
+            duration
+        } else {
+            DEFAULT_DURATION
+        },
+        repeat_count,
+        autoreverses,
+        fill_mode,
+        timing_function: ca_media_timing_function::get_control_points(&env.objc, timing_function),
+        removed_on_completion,
+        started: false,
+        finished: false,
+    };
+    log_dbg!(
+        "Adding animation {:?} of {:?} to layer {:?}: {:?} => {:?} over {}s",
+        animation,
+        property,
+        layer,
+        layer_animation.from,
+        layer_animation.to,
+        layer_animation.duration
+    );
+    env.objc
+        .borrow_mut::<CALayerHostObject>(layer)
+        .animations
+        .push(layer_animation);
+
+    let animating_layers = &mut env
+        .framework_state
+        .core_animation
+        .animation
+        .animating_layers;
+    if !animating_layers.contains(&layer) {
+        animating_layers.push(layer);
+    }
+}
+
+/// Get the `CAAnimation*` for some key on a layer, if any.
+pub(super) fn animation_for_key(env: &mut Environment, layer: id, key: &str) -> id {
+    env.objc
+        .borrow::<CALayerHostObject>(layer)
+        .animations
+        .iter()
+        .find(|animation| animation.key.as_deref() == Some(key))
+        .map_or(nil, |animation| animation.animation)
+}
+
+/// Get the keys of the animations on a layer, in the order they were added.
+pub(super) fn animation_keys(env: &mut Environment, layer: id) -> Vec<String> {
+    env.objc
+        .borrow::<CALayerHostObject>(layer)
+        .animations
+        .iter()
+        .filter_map(|animation| animation.key.clone())
+        .collect()
+}
+
+/// Remove the animations matching a predicate from a layer. Delegates of
+/// animations that haven't finished yet are told they were stopped.
+pub(super) fn remove_animations<F>(env: &mut Environment, layer: id, predicate: F)
+where
+    F: Fn(&LayerAnimation) -> bool,
+{
+    let animations = &mut env.objc.borrow_mut::<CALayerHostObject>(layer).animations;
+    let mut removed = Vec::new();
+    let mut i = 0;
+    while i < animations.len() {
+        if predicate(&animations[i]) {
+            let animation = animations.remove(i);
+            removed.push((animation.animation, animation.finished));
+        } else {
+            i += 1;
+        }
+    }
+    if animations.is_empty() {
+        forget_layer(env, layer);
+    }
+
+    for (animation, finished) in removed {
+        if !finished {
+            animation_did_stop(env, animation, false);
+        }
+        release(env, animation);
+    }
+}
+
+/// For use by `-[CALayer dealloc]`: remove all the animations of a layer
+/// without notifying any delegates.
+pub(super) fn dealloc_animations(env: &mut Environment, layer: id) {
+    let animations =
+        std::mem::take(&mut env.objc.borrow_mut::<CALayerHostObject>(layer).animations);
+    forget_layer(env, layer);
+    for animation in animations {
+        release(env, animation.animation);
+    }
+}
+
+fn forget_layer(env: &mut Environment, layer: id) {
+    env.framework_state
+        .core_animation
+        .animation
+        .animating_layers
+        .retain(|&other| other != layer);
+}
+
+fn animation_did_start(env: &mut Environment, animation: id) {
+    let delegate = env.objc.borrow::<CAAnimationHostObject>(animation).delegate;
+    if delegate == nil {
+        return;
+    }
+    let sel = env
+        .objc
+        .register_host_selector("animationDidStart:".to_string(), &mut env.mem);
+    let responds: bool = msg![env; delegate respondsToSelector:sel];
+    if responds {
+        () = msg![env; delegate animationDidStart:animation];
+    }
+}
+
+fn animation_did_stop(env: &mut Environment, animation: id, finished: bool) {
+    let delegate = env.objc.borrow::<CAAnimationHostObject>(animation).delegate;
+    if delegate == nil {
+        return;
+    }
+    let sel = env
+        .objc
+        .register_host_selector("animationDidStop:finished:".to_string(), &mut env.mem);
+    let responds: bool = msg![env; delegate respondsToSelector:sel];
+    if responds {
+        () = msg![env; delegate animationDidStop:animation finished:finished];
+    }
+}
+
+/// For use by `NSRunLoop`: notifies animation delegates about animations that
+/// started or finished, and removes finished animations where appropriate.
+///
+/// Returns the next time this function must be called, if any.
+pub fn handle_animations(env: &mut Environment) -> Option<Instant> {
+    let layers = env
+        .framework_state
+        .core_animation
+        .animation
+        .animating_layers
+        .clone();
+    if layers.is_empty() {
+        return None;
+    }
+
+    let now = current_media_time(env);
+    let mut any_ongoing = false;
+    for layer in layers {
+        // A delegate could have removed the animations, or even deallocated
+        // the layer, while handling an earlier layer.
+        if !env
+            .framework_state
+            .core_animation
+            .animation
+            .animating_layers
+            .contains(&layer)
+        {
+            continue;
+        }
+
+        let mut started = Vec::new();
+        let mut stopped = Vec::new();
+        for animation in &mut env.objc.borrow_mut::<CALayerHostObject>(layer).animations {
+            if !animation.started && now >= animation.begin {
+                animation.started = true;
+                started.push(animation.animation);
+            }
+            if !animation.finished && animation.is_finished(now) {
+                animation.finished = true;
+                stopped.push(animation.animation);
+            }
+            any_ongoing |= !animation.finished;
+        }
+
+        // The animations must stay alive until the delegates are notified,
+        // even if they are removed from the layer.
+        for &animation in started.iter().chain(stopped.iter()) {
+            retain(env, animation);
+        }
+        remove_animations(env, layer, |animation| {
+            animation.finished && animation.removed_on_completion
+        });
+        for &animation in &started {
+            animation_did_start(env, animation);
+        }
+        for &animation in &stopped {
+            animation_did_stop(env, animation, true);
+        }
+        for animation in started.into_iter().chain(stopped) {
+            release(env, animation);
+        }
+    }
+
+    any_ongoing.then(|| env.clock.now() + Duration::from_secs_f64(1.0 / 60.0))
+}
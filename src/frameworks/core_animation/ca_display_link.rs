@@ -0,0 +1,225 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CADisplayLink`.
+
+use super::ca_animation::CFTimeInterval;
+use crate::frameworks::foundation::{ns_run_loop, NSInteger};
+use crate::objc::{
+    autorelease, id, msg_class, msg_send, nil, objc_classes, release, retain, ClassExports,
+    HostObject, SEL,
+};
+use crate::Environment;
+use std::time::{Duration, Instant};
+
+/// The refresh interval of the emulated display. The iPhone's display is 60Hz.
+/// This is independent of the host's display, and of the framerate limit
+/// applied when presenting (see `limit_framerate` in EAGL), which should
+/// normally be the same.
+const REFRESH_INTERVAL: f64 = 1.0 / 60.0;
+
+struct CADisplayLinkHostObject {
+    /// Strong reference, released when the display link is invalidated.
+    target: id,
+    selector: SEL,
+    frame_interval: NSInteger,
+    paused: bool,
+    /// Time of the last firing, in the timebase of `CACurrentMediaTime()`.
+    timestamp: CFTimeInterval,
+    /// When the display link should fire next, if it's scheduled at all.
+    due_by: Option<Instant>,
+    /// Weak reference. The run loop the display link was added to, if any.
+    run_loop: id,
+}
+impl HostObject for CADisplayLinkHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation CADisplayLink: NSObject
+
++ (id)displayLinkWithTarget:(id)target
+                   selector:(SEL)selector {
+    retain(env, target);
+    let host_object = Box::new(CADisplayLinkHostObject {
+        target,
+        selector,
+        frame_interval: 1,
+        paused: false,
+        timestamp: 0.0,
+        due_by: None,
+        run_loop: nil,
+    });
+    let new = env.objc.alloc_object(this, host_object, &mut env.mem);
+    log_dbg!(
+        "New display link {:?}, target [{:?} {}]",
+        new,
+        target,
+        selector.as_str(&env.mem),
+    );
+    autorelease(env, new)
+}
+
+- (())dealloc {
+    let target = env.objc.borrow::<CADisplayLinkHostObject>(this).target;
+    release(env, target);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (())addToRunLoop:(id)run_loop // NSRunLoop*
+           forMode:(id)_mode { // NSRunLoopMode
+    // TODO: handle modes (see NSRunLoop's addTimer:forMode:)
+    let host_object = env.objc.borrow_mut::<CADisplayLinkHostObject>(this);
+    if host_object.target == nil {
+        log!("Warning: display link {:?} was already invalidated, ignoring addToRunLoop:forMode:", this);
+        return;
+    }
+    if host_object.run_loop == run_loop {
+        return;
+    }
+    assert!(host_object.run_loop == nil); // TODO: multiple run loops
+    host_object.run_loop = run_loop;
+    if !host_object.paused {
+        host_object.due_by = Some(next_refresh(env.clock.now()));
+    }
+    ns_run_loop::add_display_link(env, run_loop, this);
+}
+
+- (())removeFromRunLoop:(id)run_loop // NSRunLoop*
+                forMode:(id)_mode { // NSRunLoopMode
+    let host_object = env.objc.borrow_mut::<CADisplayLinkHostObject>(this);
+    if host_object.run_loop != run_loop {
+        return;
+    }
+    host_object.run_loop = nil;
+    host_object.due_by = None;
+    ns_run_loop::remove_display_link(env, run_loop, this);
+}
+
+- (())invalidate {
+    let host_object = env.objc.borrow_mut::<CADisplayLinkHostObject>(this);
+    let target = std::mem::take(&mut host_object.target);
+    let run_loop = std::mem::take(&mut host_object.run_loop);
+    host_object.due_by = None;
+    // The display link may be deallocated once it's removed from the run loop.
+    retain(env, this);
+    if run_loop != nil {
+        ns_run_loop::remove_display_link(env, run_loop, this);
+    }
+    release(env, target);
+    release(env, this);
+}
+
+- (CFTimeInterval)timestamp {
+    env.objc.borrow::<CADisplayLinkHostObject>(this).timestamp
+}
+
+- (CFTimeInterval)duration {
+    REFRESH_INTERVAL
+}
+
+- (NSInteger)frameInterval {
+    env.objc.borrow::<CADisplayLinkHostObject>(this).frame_interval
+}
+- (())setFrameInterval:(NSInteger)frame_interval {
+    // Values less than 1 are documented to have undefined behavior.
+    if frame_interval < 1 {
+        log!("Warning: ignoring invalid frame interval {} for display link {:?}", frame_interval, this);
+        return;
+    }
+    env.objc.borrow_mut::<CADisplayLinkHostObject>(this).frame_interval = frame_interval;
+}
+
+- (bool)isPaused {
+    env.objc.borrow::<CADisplayLinkHostObject>(this).paused
+}
+- (())setPaused:(bool)paused {
+    let host_object = env.objc.borrow_mut::<CADisplayLinkHostObject>(this);
+    host_object.paused = paused;
+    host_object.due_by = if paused || host_object.run_loop == nil {
+        None
+    } else {
+        Some(host_object.due_by.unwrap_or_else(|| next_refresh(env.clock.now())))
+    };
+}
+
+@end
+
+};
+
+fn next_refresh(now: Instant) -> Instant {
+    now + Duration::from_secs_f64(REFRESH_INTERVAL)
+}
+
+/// For use by `NSRunLoop`: check if a display link is due to fire and fire it
+/// if necessary.
+///
+/// Returns the next firing time, if any.
+pub fn handle_display_link(env: &mut Environment, display_link: id) -> Option<Instant> {
+    let &CADisplayLinkHostObject {
+        target,
+        selector,
+        frame_interval,
+        due_by,
+        ..
+    } = env.objc.borrow(display_link);
+
+    // Paused display links just sit in the run loop.
+    let due_by = due_by?;
+
+    let now = env.clock.now();
+    if due_by > now {
+        return Some(due_by);
+    }
+
+    // Like with NSTimer, the next firing is based on when this one was due,
+    // so there is no drift, but missed refreshes are skipped rather than
+    // caught up on.
+    let interval = REFRESH_INTERVAL * frame_interval as f64;
+    let overdue_by = now.duration_since(due_by).as_secs_f64();
+    let advance_by = (overdue_by / interval).max(1.0).ceil();
+    if advance_by > 1.0 {
+        log_dbg!(
+            "Warning: Display link {:?} is lagging. It is overdue by {}s and has missed {} interval(s)!",
+            display_link,
+            overdue_by,
+            advance_by - 1.0
+        );
+    }
+    let new_due_by = due_by + Duration::from_secs_f64(interval * advance_by);
+
+    // The timestamp is that of the most recent refresh, in the same timebase
+    // as CACurrentMediaTime().
+    let timestamp =
+        due_by.duration_since(env.startup_time).as_secs_f64() + interval * (advance_by - 1.0);
+
+    let host_object = env.objc.borrow_mut::<CADisplayLinkHostObject>(display_link);
+    host_object.due_by = Some(new_due_by);
+    host_object.timestamp = timestamp;
+
+    log_dbg!(
+        "Display link {:?} fired, sending {:?} message to {:?}",
+        display_link,
+        selector.as_str(&env.mem),
+        target
+    );
+
+    // The display link could be invalidated and released by the target.
+    retain(env, display_link);
+    let pool: id = msg_class![env; NSAutoreleasePool new];
+
+    // Signature should be `- (void)displayLinkDidFire:(CADisplayLink *)link`.
+    let _: () = msg_send(env, (target, selector, display_link));
+
+    release(env, pool);
+    let due_by = env
+        .objc
+        .borrow::<CADisplayLinkHostObject>(display_link)
+        .due_by;
+    release(env, display_link);
+
+    due_by
+}
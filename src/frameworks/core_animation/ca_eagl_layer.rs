@@ -7,6 +7,7 @@
 
 use super::ca_layer::CALayerHostObject;
 use crate::frameworks::core_graphics::{CGPoint, CGRect};
+use crate::frameworks::uikit::status_bar;
 use crate::objc::{id, msg, msg_class, nil, objc_classes, Class, ClassExports};
 use crate::Environment;
 
@@ -59,6 +60,11 @@ pub fn find_fullscreen_eagl_layer(env: &mut Environment) -> id {
         return nil;
     };
 
+    // The status bar has to be composited on top.
+    if status_bar::frame(env).is_some() {
+        return nil;
+    }
+
     let screen_bounds: CGRect = {
         let screen: id = msg_class![env; UIScreen mainScreen];
         msg![env; screen bounds]
@@ -85,6 +91,7 @@ pub fn find_fullscreen_eagl_layer(env: &mut Environment) -> id {
                     x: screen_bounds.size.width / 2.0,
                     y: screen_bounds.size.height / 2.0,
                 })
+            || !layer_host_obj.affine_transform.is_identity()
             || layer_host_obj.hidden
             || layer_host_obj.opacity != 1.0
         {
@@ -5,11 +5,15 @@
  */
 //! `CALayer`.
 
+use super::ca_animation::{self, LayerAnimation};
 use crate::dyld::{ConstantExports, HostConstant};
+use crate::frameworks::core_graphics::cg_affine_transform::{
+    CGAffineTransform, CGAffineTransformIdentity,
+};
 use crate::frameworks::core_graphics::cg_bitmap_context::{
     CGBitmapContextCreate, CGBitmapContextGetHeight, CGBitmapContextGetWidth,
 };
-use crate::frameworks::core_graphics::cg_color::{CGColorRef, CGColorRelease, CGColorRetain};
+use crate::frameworks::core_graphics::cg_color::{self, CGColorRef, CGColorRelease, CGColorRetain};
 use crate::frameworks::core_graphics::cg_color_space::CGColorSpaceCreateDeviceRGB;
 use crate::frameworks::core_graphics::cg_context::{
     CGContextClearRect, CGContextRef, CGContextRelease, CGContextTranslateCTM,
@@ -17,10 +21,13 @@ use crate::frameworks::core_graphics::cg_context::{
 use crate::frameworks::core_graphics::cg_image::{
     kCGImageAlphaPremultipliedLast, kCGImageByteOrder32Big,
 };
-use crate::frameworks::core_graphics::{CGPoint, CGRect, CGSize};
-use crate::frameworks::foundation::ns_string;
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::{ns_array, ns_string};
 use crate::mem::{GuestUSize, Ptr};
-use crate::objc::{id, msg, nil, objc_classes, release, retain, ClassExports, HostObject, ObjC};
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
+    ObjC,
+};
 use std::collections::HashMap;
 
 pub(super) struct CALayerHostObject {
@@ -33,6 +40,8 @@ pub(super) struct CALayerHostObject {
     pub(super) bounds: CGRect,
     pub(super) position: CGPoint,
     pub(super) anchor_point: CGPoint,
+    /// Applied around the anchor point, relative to the superlayer.
+    pub(super) affine_transform: CGAffineTransform,
     pub(super) hidden: bool,
     pub(super) opaque: bool,
     pub(super) opacity: f32,
@@ -40,6 +49,17 @@ pub(super) struct CALayerHostObject {
     pub(super) needs_display: bool,
     /// `CGImageRef*`
     pub(super) contents: id,
+    pub(super) contents_gravity: ContentsGravity,
+    pub(super) corner_radius: CGFloat,
+    pub(super) masks_to_bounds: bool,
+    pub(super) border_width: CGFloat,
+    /// Possibly nil, which means opaque black (the default).
+    pub(super) border_color: CGColorRef,
+    /// Possibly nil, which means opaque black (the default).
+    pub(super) shadow_color: CGColorRef,
+    pub(super) shadow_opacity: f32,
+    pub(super) shadow_offset: CGSize,
+    pub(super) shadow_radius: CGFloat,
     /// For CAEAGLLayer only
     pub(super) drawable_properties: id,
     /// For CAEAGLLayer only (internal state for compositor)
@@ -50,14 +70,201 @@ pub(super) struct CALayerHostObject {
     pub(super) gles_texture: Option<crate::gles::gles11_raw::types::GLuint>,
     /// Internal state for compositor
     pub(super) gles_texture_is_up_to_date: bool,
+    /// Animations in the order they were added.
+    pub(super) animations: Vec<LayerAnimation>,
 }
 impl HostObject for CALayerHostObject {}
 
+impl CALayerHostObject {
+    /// Get the model values of the properties needed for compositing.
+    pub(super) fn model_properties(&self) -> LayerProperties {
+        LayerProperties {
+            bounds: self.bounds,
+            position: self.position,
+            anchor_point: self.anchor_point,
+            affine_transform: self.affine_transform,
+            opacity: self.opacity,
+        }
+    }
+}
+
+/// A snapshot of the geometry and opacity of a layer. This is either the model
+/// (the values set by the app) or the presentation (the values currently
+/// visible on screen, which may be mid-animation).
+#[derive(Copy, Clone, Debug)]
+pub(super) struct LayerProperties {
+    pub(super) bounds: CGRect,
+    pub(super) position: CGPoint,
+    pub(super) anchor_point: CGPoint,
+    pub(super) affine_transform: CGAffineTransform,
+    pub(super) opacity: f32,
+}
+impl LayerProperties {
+    /// Get the transform that maps points in a layer's own co-ordinate space
+    /// (the one its `bounds` are in) to its superlayer's co-ordinate space,
+    /// taking into account the position, anchor point and affine transform.
+    pub(super) fn transform_to_superlayer(&self) -> CGAffineTransform {
+        let LayerProperties {
+            bounds,
+            position,
+            anchor_point,
+            affine_transform,
+            ..
+        } = *self;
+        let anchor = CGPoint {
+            x: bounds.origin.x + bounds.size.width * anchor_point.x,
+            y: bounds.origin.y + bounds.size.height * anchor_point.y,
+        };
+        CGAffineTransform::make_translation(-anchor.x, -anchor.y)
+            .concat(affine_transform)
+            .concat(CGAffineTransform::make_translation(position.x, position.y))
+    }
+}
+
+/// How the `contents` image is positioned within the bounds of a layer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(super) enum ContentsGravity {
+    Center,
+    Top,
+    Bottom,
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Resize,
+    ResizeAspect,
+    ResizeAspectFill,
+}
+
+pub const kCAGravityCenter: &str = "center";
+pub const kCAGravityTop: &str = "top";
+pub const kCAGravityBottom: &str = "bottom";
+pub const kCAGravityLeft: &str = "left";
+pub const kCAGravityRight: &str = "right";
+pub const kCAGravityTopLeft: &str = "topLeft";
+pub const kCAGravityTopRight: &str = "topRight";
+pub const kCAGravityBottomLeft: &str = "bottomLeft";
+pub const kCAGravityBottomRight: &str = "bottomRight";
+pub const kCAGravityResize: &str = "resize";
+pub const kCAGravityResizeAspect: &str = "resizeAspect";
+pub const kCAGravityResizeAspectFill: &str = "resizeAspectFill";
+
+const GRAVITY_NAMES: &[(&str, ContentsGravity)] = &[
+    (kCAGravityCenter, ContentsGravity::Center),
+    (kCAGravityTop, ContentsGravity::Top),
+    (kCAGravityBottom, ContentsGravity::Bottom),
+    (kCAGravityLeft, ContentsGravity::Left),
+    (kCAGravityRight, ContentsGravity::Right),
+    (kCAGravityTopLeft, ContentsGravity::TopLeft),
+    (kCAGravityTopRight, ContentsGravity::TopRight),
+    (kCAGravityBottomLeft, ContentsGravity::BottomLeft),
+    (kCAGravityBottomRight, ContentsGravity::BottomRight),
+    (kCAGravityResize, ContentsGravity::Resize),
+    (kCAGravityResizeAspect, ContentsGravity::ResizeAspect),
+    (
+        kCAGravityResizeAspectFill,
+        ContentsGravity::ResizeAspectFill,
+    ),
+];
+
+impl ContentsGravity {
+    /// Get the rectangle, in the layer's co-ordinate space, that an image of
+    /// the given size is drawn into. This can extend outside the bounds.
+    pub(super) fn contents_rect(self, bounds: CGRect, image_size: CGSize) -> CGRect {
+        let CGRect {
+            origin: CGPoint { x, y },
+            size: CGSize { width, height },
+        } = bounds;
+        let size = match self {
+            ContentsGravity::Resize => return bounds,
+            ContentsGravity::ResizeAspect | ContentsGravity::ResizeAspectFill => {
+                let scale_x = width / image_size.width;
+                let scale_y = height / image_size.height;
+                let scale = if self == ContentsGravity::ResizeAspect {
+                    scale_x.min(scale_y)
+                } else {
+                    scale_x.max(scale_y)
+                };
+                if !scale.is_finite() {
+                    return bounds;
+                }
+                CGSize {
+                    width: image_size.width * scale,
+                    height: image_size.height * scale,
+                }
+            }
+            _ => image_size,
+        };
+        // The layer's co-ordinate space isn't flipped on iPhone OS, but the
+        // gravity names assume it is (as on Mac OS X), so "top" ends up being
+        // the bottom edge on screen and vice-versa.
+        let (fx, fy) = match self {
+            ContentsGravity::Left => (0.0, 0.5),
+            ContentsGravity::Right => (1.0, 0.5),
+            ContentsGravity::Top => (0.5, 1.0),
+            ContentsGravity::Bottom => (0.5, 0.0),
+            ContentsGravity::TopLeft => (0.0, 1.0),
+            ContentsGravity::TopRight => (1.0, 1.0),
+            ContentsGravity::BottomLeft => (0.0, 0.0),
+            ContentsGravity::BottomRight => (1.0, 0.0),
+            _ => (0.5, 0.5),
+        };
+        CGRect {
+            origin: CGPoint {
+                x: x + (width - size.width) * fx,
+                y: y + (height - size.height) * fy,
+            },
+            size,
+        }
+    }
+}
+
 pub const kCAFilterLinear: &str = "kCAFilterLinear";
 pub const kCAFilterNearest: &str = "kCAFilterNearest";
 pub const kCAFilterTrilinear: &str = "kCAFilterTrilinear";
 
 pub const CONSTANTS: ConstantExports = &[
+    (
+        "_kCAGravityCenter",
+        HostConstant::NSString(kCAGravityCenter),
+    ),
+    ("_kCAGravityTop", HostConstant::NSString(kCAGravityTop)),
+    (
+        "_kCAGravityBottom",
+        HostConstant::NSString(kCAGravityBottom),
+    ),
+    ("_kCAGravityLeft", HostConstant::NSString(kCAGravityLeft)),
+    ("_kCAGravityRight", HostConstant::NSString(kCAGravityRight)),
+    (
+        "_kCAGravityTopLeft",
+        HostConstant::NSString(kCAGravityTopLeft),
+    ),
+    (
+        "_kCAGravityTopRight",
+        HostConstant::NSString(kCAGravityTopRight),
+    ),
+    (
+        "_kCAGravityBottomLeft",
+        HostConstant::NSString(kCAGravityBottomLeft),
+    ),
+    (
+        "_kCAGravityBottomRight",
+        HostConstant::NSString(kCAGravityBottomRight),
+    ),
+    (
+        "_kCAGravityResize",
+        HostConstant::NSString(kCAGravityResize),
+    ),
+    (
+        "_kCAGravityResizeAspect",
+        HostConstant::NSString(kCAGravityResizeAspect),
+    ),
+    (
+        "_kCAGravityResizeAspectFill",
+        HostConstant::NSString(kCAGravityResizeAspectFill),
+    ),
     ("_kCAFilterLinear", HostConstant::NSString(kCAFilterLinear)),
     (
         "_kCAFilterNearest",
@@ -86,17 +293,29 @@ pub const CLASSES: ClassExports = objc_classes! {
         },
         position: CGPoint { x: 0.0, y: 0.0 },
         anchor_point: CGPoint { x: 0.5, y: 0.5 },
+        affine_transform: CGAffineTransformIdentity,
         hidden: false,
         opaque: false,
         opacity: 1.0,
         background_color: nil, // transparency
         needs_display: true,
         contents: nil,
+        contents_gravity: ContentsGravity::Resize,
+        corner_radius: 0.0,
+        masks_to_bounds: false,
+        border_width: 0.0,
+        border_color: nil,
+        shadow_color: nil,
+        shadow_opacity: 0.0,
+        // Yes, the default shadow is cast upwards.
+        shadow_offset: CGSize { width: 0.0, height: -3.0 },
+        shadow_radius: 3.0,
         drawable_properties: nil,
         presented_pixels: None,
         cg_context: None,
         gles_texture: None,
         gles_texture_is_up_to_date: false,
+        animations: Vec::new(),
     });
     env.objc.alloc_object(this, host_object, &mut env.mem)
 }
@@ -107,11 +326,15 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (())dealloc {
+    ca_animation::dealloc_animations(env, this);
+
     let &mut CALayerHostObject {
         drawable_properties,
         contents,
         superlayer,
         background_color,
+        border_color,
+        shadow_color,
         cg_context,
         ref mut sublayers,
         ..
@@ -127,6 +350,8 @@ pub const CLASSES: ClassExports = objc_classes! {
     }
 
     CGColorRelease(env, background_color);
+    CGColorRelease(env, border_color);
+    CGColorRelease(env, shadow_color);
 
     if let Some(cg_context) = cg_context {
         CGContextRelease(env, cg_context);
@@ -202,33 +427,57 @@ pub const CLASSES: ClassExports = objc_classes! {
         bounds,
         position,
         anchor_point,
+        affine_transform,
         ..
     } = env.objc.borrow(this);
-    CGRect {
+    let frame = CGRect {
         origin: CGPoint {
             x: position.x - bounds.size.width * anchor_point.x,
             y: position.y - bounds.size.height * anchor_point.y,
         },
         size: bounds.size,
+    };
+    if affine_transform.is_identity() {
+        return frame;
     }
+    // The frame is the bounding box of the transformed bounds.
+    CGAffineTransform::make_translation(-position.x, -position.y)
+        .concat(affine_transform)
+        .concat(CGAffineTransform::make_translation(position.x, position.y))
+        .apply_to_rect(frame)
 }
 - (())setFrame:(CGRect)frame {
     let CALayerHostObject {
         bounds,
         position,
         anchor_point,
+        affine_transform,
         ..
     } = env.objc.borrow_mut(this);
     *position = CGPoint {
         x: frame.origin.x + frame.size.width * anchor_point.x,
         y: frame.origin.y + frame.size.height * anchor_point.y,
     };
+    // Apple say the frame is undefined when the transform isn't the identity.
+    // Keeping the bounds and only moving the layer seems the least surprising
+    // thing to do in that case.
+    if !affine_transform.is_identity() {
+        log_dbg!("[(CALayer*){:?} setFrame:{:?}] with non-identity transform, ignoring size", this, frame);
+        return;
+    }
     *bounds = CGRect {
         origin: CGPoint { x: 0.0, y: 0.0 },
         size: frame.size,
     };
 }
 
+- (CGAffineTransform)affineTransform {
+    env.objc.borrow::<CALayerHostObject>(this).affine_transform
+}
+- (())setAffineTransform:(CGAffineTransform)transform {
+    env.objc.borrow_mut::<CALayerHostObject>(this).affine_transform = transform;
+}
+
 - (bool)isHidden {
     env.objc.borrow::<CALayerHostObject>(this).hidden
 }
@@ -384,6 +633,94 @@ pub const CLASSES: ClassExports = objc_classes! {
     release(env, old_contents);
 }
 
+- (id)contentsGravity {
+    let gravity = env.objc.borrow::<CALayerHostObject>(this).contents_gravity;
+    let &(name, _) = GRAVITY_NAMES.iter().find(|&&(_, g)| g == gravity).unwrap();
+    ns_string::get_static_str(env, name)
+}
+- (())setContentsGravity:(id)gravity { // NSString*
+    let name = ns_string::to_rust_string(env, gravity);
+    if let Some(&(_, gravity)) = GRAVITY_NAMES.iter().find(|&&(n, _)| n == name) {
+        env.objc.borrow_mut::<CALayerHostObject>(this).contents_gravity = gravity;
+    } else {
+        log!("Warning: ignoring unknown contents gravity {:?} for {:?}", name, this);
+    }
+}
+
+- (CGFloat)cornerRadius {
+    env.objc.borrow::<CALayerHostObject>(this).corner_radius
+}
+- (())setCornerRadius:(CGFloat)radius {
+    env.objc.borrow_mut::<CALayerHostObject>(this).corner_radius = radius;
+}
+
+- (bool)masksToBounds {
+    env.objc.borrow::<CALayerHostObject>(this).masks_to_bounds
+}
+- (())setMasksToBounds:(bool)masks {
+    env.objc.borrow_mut::<CALayerHostObject>(this).masks_to_bounds = masks;
+}
+
+- (CGFloat)borderWidth {
+    env.objc.borrow::<CALayerHostObject>(this).border_width
+}
+- (())setBorderWidth:(CGFloat)width {
+    env.objc.borrow_mut::<CALayerHostObject>(this).border_width = width;
+}
+
+- (CGColorRef)borderColor {
+    let color = env.objc.borrow::<CALayerHostObject>(this).border_color;
+    if color != nil {
+        return color;
+    }
+    let color = cg_color::from_rgba(env, (0.0, 0.0, 0.0, 1.0));
+    env.objc.borrow_mut::<CALayerHostObject>(this).border_color = color;
+    color
+}
+- (())setBorderColor:(CGColorRef)new_color {
+    let host_obj = env.objc.borrow_mut::<CALayerHostObject>(this);
+    let old_color = std::mem::replace(&mut host_obj.border_color, new_color);
+    CGColorRetain(env, new_color);
+    CGColorRelease(env, old_color);
+}
+
+- (CGColorRef)shadowColor {
+    let color = env.objc.borrow::<CALayerHostObject>(this).shadow_color;
+    if color != nil {
+        return color;
+    }
+    let color = cg_color::from_rgba(env, (0.0, 0.0, 0.0, 1.0));
+    env.objc.borrow_mut::<CALayerHostObject>(this).shadow_color = color;
+    color
+}
+- (())setShadowColor:(CGColorRef)new_color {
+    let host_obj = env.objc.borrow_mut::<CALayerHostObject>(this);
+    let old_color = std::mem::replace(&mut host_obj.shadow_color, new_color);
+    CGColorRetain(env, new_color);
+    CGColorRelease(env, old_color);
+}
+
+- (f32)shadowOpacity {
+    env.objc.borrow::<CALayerHostObject>(this).shadow_opacity
+}
+- (())setShadowOpacity:(f32)opacity {
+    env.objc.borrow_mut::<CALayerHostObject>(this).shadow_opacity = opacity;
+}
+
+- (CGSize)shadowOffset {
+    env.objc.borrow::<CALayerHostObject>(this).shadow_offset
+}
+- (())setShadowOffset:(CGSize)offset {
+    env.objc.borrow_mut::<CALayerHostObject>(this).shadow_offset = offset;
+}
+
+- (CGFloat)shadowRadius {
+    env.objc.borrow::<CALayerHostObject>(this).shadow_radius
+}
+- (())setShadowRadius:(CGFloat)radius {
+    env.objc.borrow_mut::<CALayerHostObject>(this).shadow_radius = radius;
+}
+
 - (())setEdgeAntialiasingMask:(u32)mask {
     log!("TODO: [(CALayer*){:?} setEdgeAntialiasingMask: {}]", this, mask); // TODO
 }
@@ -416,32 +753,30 @@ pub const CLASSES: ClassExports = objc_classes! {
     // The idea is to walk up each layer's superlayer chain, one at a time,
     // alternating between layers until we find a match.
 
-    // Maps of layer pointers to origins in that layer's co-ordinate space.
-    let mut this_map = HashMap::from([(this, CGPoint { x: 0.0, y: 0.0 })]);
-    let mut other_map = HashMap::from([(other, CGPoint { x: 0.0, y: 0.0 })]);
+    // Maps of layer pointers to transforms from the starting layer's
+    // co-ordinate space to that layer's co-ordinate space.
+    let mut this_map = HashMap::from([(this, CGAffineTransformIdentity)]);
+    let mut other_map = HashMap::from([(other, CGAffineTransformIdentity)]);
     // Current iteration state.
     let mut this_superlayer = this;
-    let mut this_origin = CGPoint { x: 0.0, y: 0.0 };
+    let mut this_transform = CGAffineTransformIdentity;
     let mut other_superlayer = other;
-    let mut other_origin = CGPoint { x: 0.0, y: 0.0 };
-    let (common_ancestor, this_origin, other_origin) = loop {
+    let mut other_transform = CGAffineTransformIdentity;
+    let (common_ancestor, this_transform, other_transform) = loop {
         if this_superlayer != nil {
             let next: id = msg![env; this_superlayer superlayer];
             if next == nil {
                 this_superlayer = nil;
             } else {
-                let bounds: CGRect = msg![env; this_superlayer bounds];
-                let frame: CGRect = msg![env; this_superlayer frame];
-                let next_origin = CGPoint {
-                    x: this_origin.x + frame.origin.x - bounds.origin.x,
-                    y: this_origin.y + frame.origin.y - bounds.origin.y,
-                };
-                if let Some(&other_origin) = other_map.get(&next) {
-                    break (next, next_origin, other_origin);
+                let host_obj = env.objc.borrow::<CALayerHostObject>(this_superlayer);
+                let to_superlayer = host_obj.model_properties().transform_to_superlayer();
+                let next_transform = this_transform.concat(to_superlayer);
+                if let Some(&other_transform) = other_map.get(&next) {
+                    break (next, next_transform, other_transform);
                 }
-                this_map.insert(next, next_origin);
+                this_map.insert(next, next_transform);
                 this_superlayer = next;
-                this_origin = next_origin;
+                this_transform = next_transform;
             }
         }
 
@@ -450,18 +785,15 @@ pub const CLASSES: ClassExports = objc_classes! {
             if next == nil {
                 other_superlayer = nil;
             } else {
-                let bounds: CGRect = msg![env; other_superlayer bounds];
-                let frame: CGRect = msg![env; other_superlayer frame];
-                let next_origin = CGPoint {
-                    x: other_origin.x + frame.origin.x - bounds.origin.x,
-                    y: other_origin.y + frame.origin.y - bounds.origin.y,
-                };
-                if let Some(&this_origin) = this_map.get(&next) {
-                    break (next, this_origin, next_origin);
+                let host_obj = env.objc.borrow::<CALayerHostObject>(other_superlayer);
+                let to_superlayer = host_obj.model_properties().transform_to_superlayer();
+                let next_transform = other_transform.concat(to_superlayer);
+                if let Some(&this_transform) = this_map.get(&next) {
+                    break (next, this_transform, next_transform);
                 }
-                other_map.insert(next, next_origin);
+                other_map.insert(next, next_transform);
                 other_superlayer = next;
-                other_origin = next_origin;
+                other_transform = next_transform;
             }
         }
 
@@ -472,12 +804,11 @@ pub const CLASSES: ClassExports = objc_classes! {
     };
 
     log_dbg!("{:?} and {:?}'s common ancestor: {:?}", this, other, common_ancestor);
-    log_dbg!("{:?}'s origin in common ancestor: {:?}", this, this_origin);
-    log_dbg!("{:?}'s origin in common ancestor: {:?}", other, other_origin);
-    let res = CGPoint {
-        x: point.x + other_origin.x - this_origin.x,
-        y: point.y + other_origin.y - this_origin.y,
-    };
+    log_dbg!("{:?}'s transform to common ancestor: {:?}", this, this_transform);
+    log_dbg!("{:?}'s transform to common ancestor: {:?}", other, other_transform);
+    let res = this_transform
+        .invert()
+        .apply_to_point(other_transform.apply_to_point(point));
     log_dbg!("Converted {:?} from {:?} to {:?}: {:?}", point, other, this, res);
     res
 }
@@ -489,6 +820,63 @@ pub const CLASSES: ClassExports = objc_classes! {
     msg![env; other convertPoint:point fromLayer:this]
 }
 
+// Animation
+
+- (())addAnimation:(id)animation // CAAnimation*
+            forKey:(id)key { // NSString*
+    ca_animation::add_animation(env, this, animation, key);
+}
+
+- (id)animationForKey:(id)key { // NSString*
+    let key = ns_string::to_rust_string(env, key);
+    ca_animation::animation_for_key(env, this, &key)
+}
+
+- (id)animationKeys {
+    let keys = ca_animation::animation_keys(env, this);
+    if keys.is_empty() {
+        return nil;
+    }
+    let keys = keys.into_iter().map(|key| ns_string::from_rust_string(env, key)).collect();
+    let keys = ns_array::from_vec(env, keys);
+    autorelease(env, keys)
+}
+
+- (())removeAnimationForKey:(id)key { // NSString*
+    let key = ns_string::to_rust_string(env, key);
+    ca_animation::remove_animations(env, this, |animation| {
+        animation.key() == Some(&*key)
+    });
+}
+
+- (())removeAllAnimations {
+    ca_animation::remove_animations(env, this, |_| true);
+}
+
+- (id)presentationLayer {
+    let now = ca_animation::current_media_time(env);
+    let host_obj = env.objc.borrow::<CALayerHostObject>(this);
+    let properties = ca_animation::presentation_properties(host_obj, now);
+    let (hidden, opaque) = (host_obj.hidden, host_obj.opaque);
+
+    // The presentation layer is a snapshot that isn't part of the layer tree.
+    let new: id = msg_class![env; CALayer alloc];
+    let new: id = msg![env; new init];
+    let new_host_obj = env.objc.borrow_mut::<CALayerHostObject>(new);
+    new_host_obj.bounds = properties.bounds;
+    new_host_obj.position = properties.position;
+    new_host_obj.anchor_point = properties.anchor_point;
+    new_host_obj.affine_transform = properties.affine_transform;
+    new_host_obj.opacity = properties.opacity;
+    new_host_obj.hidden = hidden;
+    new_host_obj.opaque = opaque;
+    autorelease(env, new)
+}
+
+- (id)modelLayer {
+    this
+}
+
 // TODO: more
 
 @end
@@ -0,0 +1,186 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CAMediaTimingFunction`.
+
+use crate::dyld::{ConstantExports, HostConstant};
+use crate::frameworks::foundation::ns_string;
+use crate::mem::{GuestUSize, MutPtr};
+use crate::objc::{
+    autorelease, id, nil, objc_classes, retain, ClassExports, HostObject, NSZonePtr, ObjC,
+};
+
+pub const kCAMediaTimingFunctionLinear: &str = "linear";
+pub const kCAMediaTimingFunctionEaseIn: &str = "easeIn";
+pub const kCAMediaTimingFunctionEaseOut: &str = "easeOut";
+pub const kCAMediaTimingFunctionEaseInEaseOut: &str = "easeInEaseOut";
+pub const kCAMediaTimingFunctionDefault: &str = "default";
+
+pub const CONSTANTS: ConstantExports = &[
+    (
+        "_kCAMediaTimingFunctionLinear",
+        HostConstant::NSString(kCAMediaTimingFunctionLinear),
+    ),
+    (
+        "_kCAMediaTimingFunctionEaseIn",
+        HostConstant::NSString(kCAMediaTimingFunctionEaseIn),
+    ),
+    (
+        "_kCAMediaTimingFunctionEaseOut",
+        HostConstant::NSString(kCAMediaTimingFunctionEaseOut),
+    ),
+    (
+        "_kCAMediaTimingFunctionEaseInEaseOut",
+        HostConstant::NSString(kCAMediaTimingFunctionEaseInEaseOut),
+    ),
+    (
+        "_kCAMediaTimingFunctionDefault",
+        HostConstant::NSString(kCAMediaTimingFunctionDefault),
+    ),
+];
+
+/// The two inner control points of a cubic Bézier curve from (0, 0) to (1, 1),
+/// as `[x1, y1, x2, y2]`.
+pub(super) type ControlPoints = [f32; 4];
+
+pub(super) const LINEAR: ControlPoints = [0.0, 0.0, 1.0, 1.0];
+pub(super) const EASE_IN: ControlPoints = [0.42, 0.0, 1.0, 1.0];
+pub(super) const EASE_OUT: ControlPoints = [0.0, 0.0, 0.58, 1.0];
+pub(super) const EASE_IN_EASE_OUT: ControlPoints = [0.42, 0.0, 0.58, 1.0];
+pub(super) const DEFAULT: ControlPoints = [0.25, 0.1, 0.25, 1.0];
+
+struct CAMediaTimingFunctionHostObject {
+    control_points: ControlPoints,
+}
+impl HostObject for CAMediaTimingFunctionHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation CAMediaTimingFunction: NSObject
+
++ (id)functionWithName:(id)name { // NSString*
+    let name = ns_string::to_rust_string(env, name);
+    let control_points = match &*name {
+        kCAMediaTimingFunctionLinear => LINEAR,
+        kCAMediaTimingFunctionEaseIn => EASE_IN,
+        kCAMediaTimingFunctionEaseOut => EASE_OUT,
+        kCAMediaTimingFunctionEaseInEaseOut => EASE_IN_EASE_OUT,
+        kCAMediaTimingFunctionDefault => DEFAULT,
+        _ => panic!("Unknown timing function name {:?}", name),
+    };
+    let host_object = Box::new(CAMediaTimingFunctionHostObject { control_points });
+    let new = env.objc.alloc_object(this, host_object, &mut env.mem);
+    autorelease(env, new)
+}
+
+// TODO: functionWithControlPoints:::: and initWithControlPoints::::
+// (selectors with unnamed parts aren't supported by objc_classes! yet)
+
+- (())getControlPointAtIndex:(GuestUSize)index
+                      values:(MutPtr<f32>)values { // float[2]
+    let [x1, y1, x2, y2] = env
+        .objc
+        .borrow::<CAMediaTimingFunctionHostObject>(this)
+        .control_points;
+    let (x, y) = match index {
+        0 => (0.0, 0.0),
+        1 => (x1, y1),
+        2 => (x2, y2),
+        3 => (1.0, 1.0),
+        _ => panic!("Invalid control point index {}", index),
+    };
+    env.mem.write(values, x);
+    env.mem.write(values + 1, y);
+}
+
+// NSCopying implementation
+- (id)copyWithZone:(NSZonePtr)_zone {
+    // Timing functions are immutable.
+    retain(env, this)
+}
+
+@end
+
+};
+
+/// Get the control points of a `CAMediaTimingFunction*`, or the linear ones if
+/// it is `nil`.
+pub(super) fn get_control_points(objc: &ObjC, function: id) -> ControlPoints {
+    if function == nil {
+        LINEAR
+    } else {
+        objc.borrow::<CAMediaTimingFunctionHostObject>(function)
+            .control_points
+    }
+}
+
+/// Evaluate a timing function for some input time `t` (between 0 and 1).
+pub(super) fn evaluate(control_points: ControlPoints, t: f32) -> f32 {
+    let [x1, y1, x2, y2] = control_points;
+    if control_points == LINEAR || t <= 0.0 || t >= 1.0 {
+        return t.clamp(0.0, 1.0);
+    }
+
+    // Cubic Bézier with end points (0, 0) and (1, 1), one axis at a time.
+    let bezier = |p1: f32, p2: f32, s: f32| {
+        let u = 1.0 - s;
+        3.0 * u * u * s * p1 + 3.0 * u * s * s * p2 + s * s * s
+    };
+    let bezier_derivative = |p1: f32, p2: f32, s: f32| {
+        let u = 1.0 - s;
+        3.0 * u * u * p1 + 6.0 * u * s * (p2 - p1) + 3.0 * s * s * (1.0 - p2)
+    };
+
+    // Find the curve parameter for which x equals t. Newton's method usually
+    // converges very quickly, but bisection is the fallback for flat parts of
+    // the curve.
+    let mut s = t;
+    for _ in 0..8 {
+        let error = bezier(x1, x2, s) - t;
+        if error.abs() < 1e-5 {
+            return bezier(y1, y2, s);
+        }
+        let derivative = bezier_derivative(x1, x2, s);
+        if derivative.abs() < 1e-6 {
+            break;
+        }
+        s = (s - error / derivative).clamp(0.0, 1.0);
+    }
+    let (mut low, mut high) = (0.0, 1.0);
+    s = t;
+    for _ in 0..32 {
+        let x = bezier(x1, x2, s);
+        if (x - t).abs() < 1e-5 {
+            break;
+        }
+        if x < t {
+            low = s;
+        } else {
+            high = s;
+        }
+        s = (low + high) / 2.0;
+    }
+    bezier(y1, y2, s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        for control_points in [LINEAR, EASE_IN, EASE_OUT, EASE_IN_EASE_OUT, DEFAULT] {
+            assert_eq!(evaluate(control_points, 0.0), 0.0);
+            assert_eq!(evaluate(control_points, 1.0), 1.0);
+        }
+        assert_eq!(evaluate(LINEAR, 0.25), 0.25);
+        // Symmetric curve
+        assert!((evaluate(EASE_IN_EASE_OUT, 0.5) - 0.5).abs() < 1e-4);
+        assert!(evaluate(EASE_IN, 0.5) < 0.5);
+        assert!(evaluate(EASE_OUT, 0.5) > 0.5);
+    }
+}
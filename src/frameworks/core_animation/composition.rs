@@ -9,25 +9,37 @@
 //! I haven't attempted to reverse-engineer the details. As such, it probably
 //! diverges wildly from what the real iPhone OS does.
 
+use super::ca_animation::{current_media_time, presentation_properties, CFTimeInterval};
 use super::ca_eagl_layer::find_fullscreen_eagl_layer;
 use super::ca_layer::CALayerHostObject;
+use crate::frameworks::core_graphics::cg_affine_transform::{
+    CGAffineTransform, CGAffineTransformIdentity,
+};
 use crate::frameworks::core_graphics::{
     cg_bitmap_context, cg_color, cg_image, CGFloat, CGPoint, CGRect, CGSize,
 };
+use crate::frameworks::uikit::status_bar;
 use crate::gles::gles11_raw as gles11; // constants only
 use crate::gles::gles11_raw::types::*;
 use crate::gles::present::{present_frame, FpsCounter};
 use crate::gles::GLES;
 use crate::mem::Mem;
 use crate::objc::{id, msg, msg_class, nil, ObjC};
+use crate::recording;
 use crate::Environment;
 use std::time::{Duration, Instant};
 
 #[derive(Default)]
 pub(super) struct State {
     texture_framebuffer: Option<(GLuint, GLuint)>,
+    /// Textures used to save parts of the framebuffer when clipping sublayers
+    /// to rounded corners, one per level of nesting.
+    mask_textures: Vec<GLuint>,
     recomposite_next: Option<Instant>,
     fps_counter: Option<FpsCounter>,
+    /// Texture containing the status bar, and what it looked like when it was
+    /// drawn.
+    status_bar: Option<(GLuint, status_bar::Appearance)>,
 }
 
 /// For use by `NSRunLoop`: call this 60 times per second. Composites the app's
@@ -67,7 +79,7 @@ pub fn recomposite_if_necessary(env: &mut Environment) -> Option<Instant> {
             .count_frame(format_args!("Core Animation compositor"));
     }
 
-    let now = Instant::now();
+    let now = env.clock.now();
     let interval = 1.0 / 60.0; // 60Hz
     let new_recomposite_next = if let Some(recomposite_next) = env
         .framework_state
@@ -118,17 +130,34 @@ pub fn recomposite_if_necessary(env: &mut Environment) -> Option<Instant> {
         env.window().viewport(),
         env.window().rotation_matrix(),
         env.window().virtual_cursor_visible_at(),
+        env.window().touch_indicators_visible_at(),
+        env.clock.speed_indicator(),
+        env.framework_state.audio_toolbox.volume_indicator(),
     );
+    let capture_frame = recording::wants_frame(env);
 
-    // TODO: draw status bar if it's not hidden
+    // The status bar only needs to be redrawn when it changes, e.g. when the
+    // time shown changes.
+    let status_bar = status_bar::appearance(env, scale_hack).map(|appearance| {
+        let up_to_date = env
+            .framework_state
+            .core_animation
+            .composition
+            .status_bar
+            .as_ref()
+            .is_some_and(|(_, drawn)| *drawn == appearance);
+        let pixels = (!up_to_date).then(|| status_bar::rasterize(env, &appearance));
+        (appearance, pixels)
+    });
 
     // Initial state for layer tree traversal (see composite_layer_recursive)
-    let origin = CGPoint { x: 0.0, y: 0.0 };
+    let transform = CGAffineTransformIdentity;
     let clip_to = CGRect {
-        origin,
+        origin: CGPoint { x: 0.0, y: 0.0 },
         size: screen_bounds.size,
     };
     let opacity = 1.0;
+    let now = current_media_time(env);
 
     let window = env.window.as_mut().unwrap();
     window.make_internal_gl_ctx_current();
@@ -214,14 +243,34 @@ pub fn recomposite_if_necessary(env: &mut Environment) -> Option<Instant> {
             &mut env.objc,
             &env.mem,
             root_layer,
-            origin,
+            transform,
             clip_to,
             opacity,
+            screen_bounds.size,
+            now,
             scale_hack,
+            fb_width,
             fb_height,
+            &mut env.framework_state.core_animation.composition.mask_textures,
+            0,
         );
     }
 
+    // The status bar goes on top of everything the app draws.
+    if let Some((appearance, pixels)) = status_bar {
+        unsafe {
+            draw_status_bar(
+                gles,
+                &mut env.framework_state.core_animation.composition.status_bar,
+                appearance,
+                pixels,
+                screen_bounds.size,
+                fb_width,
+                fb_height,
+            );
+        }
+    }
+
     // Clean up some GL state
     unsafe {
         gles.Viewport(0, 0, fb_width as _, fb_height as _);
@@ -231,6 +280,23 @@ pub fn recomposite_if_necessary(env: &mut Environment) -> Option<Instant> {
         assert_eq!(gles.GetError(), 0);
     }
 
+    // Read back the frame before it's scaled and rotated for the window.
+    let captured_frame = capture_frame.then(|| {
+        let mut pixels = vec![0u8; fb_width as usize * fb_height as usize * 4];
+        unsafe {
+            gles.ReadPixels(
+                0,
+                0,
+                fb_width as _,
+                fb_height as _,
+                gles11::RGBA,
+                gles11::UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as *mut _,
+            );
+        }
+        pixels
+    });
+
     // Present our rendered frame (bound to TEXTURE_2D). This copies it to the
     // default framebuffer (0) so we need to unbind our internal framebuffer.
     unsafe {
@@ -241,9 +307,17 @@ pub fn recomposite_if_necessary(env: &mut Environment) -> Option<Instant> {
             present_frame_args.0,
             present_frame_args.1,
             present_frame_args.2,
+            &present_frame_args.3,
+            present_frame_args.4,
+            present_frame_args.5,
         );
     }
     env.window().swap_window();
+    env.frame_presented();
+
+    if let Some(pixels) = captured_frame {
+        recording::frame_presented(env, pixels, fb_width, fb_height);
+    }
 
     new_recomposite_next
 }
@@ -280,14 +354,19 @@ unsafe fn composite_layer_recursive(
     objc: &mut ObjC,
     mem: &Mem,
     layer: id,
-    origin: CGPoint,
+    transform: CGAffineTransform,
     clip_to: CGRect,
     opacity: CGFloat,
+    screen_size: CGSize,
+    now: CFTimeInterval,
     scale_hack: u32,
+    fb_width: u32,
     fb_height: u32,
+    mask_textures: &mut Vec<GLuint>,
+    mask_depth: usize,
 ) {
-    // TODO: this can't handle zPosition, non-AABB layer transforms, rounded
-    // corners, and many other things, but none of these are supported yet :)
+    // TODO: this can't handle zPosition, 3D layer transforms, and many other
+    // things, but none of these are supported yet :)
     // TODO: back-to-front drawing is not efficient, could we use front-to-back?
 
     let host_obj = objc.borrow::<CALayerHostObject>(layer);
@@ -296,44 +375,91 @@ unsafe fn composite_layer_recursive(
         return;
     }
 
-    let opacity = opacity * host_obj.opacity;
-    let bounds = host_obj.bounds;
-    let absolute_frame = {
-        let position = host_obj.position;
-        let anchor_point = host_obj.anchor_point;
-        CGRect {
-            origin: CGPoint {
-                x: origin.x + position.x - bounds.size.width * anchor_point.x,
-                y: origin.y + position.y - bounds.size.height * anchor_point.y,
-            },
-            size: bounds.size,
-        }
+    // Animations only affect what is presented, not the model values.
+    let properties = presentation_properties(host_obj, now);
+
+    let opacity = opacity * properties.opacity;
+    let bounds = properties.bounds;
+    // Maps points in this layer's co-ordinate space to screen co-ordinates.
+    // The transforms of all the superlayers are already included in the one
+    // passed in, so this is what the sublayers get.
+    let transform = properties.transform_to_superlayer().concat(transform);
+
+    let &CALayerHostObject {
+        background_color,
+        contents,
+        contents_gravity,
+        corner_radius,
+        masks_to_bounds,
+        border_width,
+        border_color,
+        shadow_color,
+        shadow_opacity,
+        shadow_offset,
+        shadow_radius,
+        ..
+    } = host_obj;
+
+    // Only a contents image has a size of its own, other kinds of content are
+    // always drawn to fill the bounds.
+    let contents_rect = if contents != nil {
+        let (width, height) = cg_image::borrow_image(objc, contents).dimensions();
+        let image_size = CGSize {
+            width: width as CGFloat,
+            height: height as CGFloat,
+        };
+        contents_gravity.contents_rect(bounds, image_size)
+    } else {
+        bounds
     };
-    let absolute_frame_clipped = clip_rects(clip_to, absolute_frame);
 
-    // Draw background color, if any
-    let have_background = if host_obj.background_color == nil {
-        false
+    let visible = {
+        let extent = union_rects(bounds, contents_rect);
+        let clipped = clip_rects(clip_to, transform.apply_to_rect(extent));
+        clipped.size.width > 0.0 && clipped.size.height > 0.0
+    };
+
+    // The layer is drawn as polygons in the layer's co-ordinate space, which
+    // are transformed to screen co-ordinates and then to normalized device
+    // co-ordinates. The scissor test is used for clipping to rectangles.
+    let outline = rounded_rect_outline(bounds, corner_radius);
+    // The contents are only clipped to the bounds (and their rounded corners)
+    // if masksToBounds is set.
+    let contents_outline = if masks_to_bounds {
+        clip_polygon_to_rect(&outline, contents_rect)
     } else {
-        let (r, g, b, a) = cg_color::to_rgba(objc, host_obj.background_color);
-        // TODO: fully support alpha transparency for backgrounds
-        if a == 0.0 || opacity == 0.0 {
-            false
-        } else {
-            gles.ClearColor(r * opacity, g * opacity, b * opacity, a * opacity);
-            let (x, y, w, h) = gl_rect_from_cg_rect(absolute_frame_clipped, scale_hack, fb_height);
-            gles.Scissor(x, y, w, h);
-            gles.Clear(gles11::COLOR_BUFFER_BIT);
-            true
-        }
+        rounded_rect_outline(contents_rect, 0.0)
+    };
+    {
+        let (x, y, w, h) = gl_rect_from_cg_rect(clip_to, scale_hack, fb_height);
+        gles.Scissor(x, y, w, h);
+    }
+    gles.BindBuffer(gles11::ARRAY_BUFFER, 0);
+    gles.EnableClientState(gles11::VERTEX_ARRAY);
+
+    let background_rgba = if background_color == nil {
+        None
+    } else {
+        Some(cg_color::to_rgba(objc, background_color))
+    };
+    let shadow_rgba = if shadow_color == nil {
+        (0.0, 0.0, 0.0, 1.0)
+    } else {
+        cg_color::to_rgba(objc, shadow_color)
+    };
+    let border_rgba = if border_color == nil {
+        (0.0, 0.0, 0.0, 1.0)
+    } else {
+        cg_color::to_rgba(objc, border_color)
     };
 
     // re-borrow mutably
     let host_obj = objc.borrow_mut::<CALayerHostObject>(layer);
 
-    let need_texture = host_obj.presented_pixels.is_some()
-        || host_obj.contents != nil
-        || host_obj.cg_context.is_some();
+    let need_texture = visible
+        && (host_obj.presented_pixels.is_some()
+            || host_obj.contents != nil
+            || host_obj.cg_context.is_some());
     let need_update = need_texture && !host_obj.gles_texture_is_up_to_date;
 
     if need_texture {
@@ -387,15 +513,60 @@ unsafe fn composite_layer_recursive(
         }
     }
 
-    // re-borrow mutably
-    let host_obj = objc.borrow_mut::<CALayerHostObject>(layer);
+    // Normal images will have top-to-bottom row order, but OpenGL ES
+    // expects bottom-to-top, so flip the UVs in that case.
+    let contents_tex_coords = need_texture
+        .then(|| tex_coords_for_points(&contents_outline, contents_rect, host_obj.contents != nil));
 
-    if need_update {
-        host_obj.gles_texture_is_up_to_date = true;
+    // Draw shadow, if any. Real Core Animation uses the alpha channel of the
+    // layer and all its sublayers to cast the shadow, but here only the
+    // background, or failing that the layer's own content, is used. The
+    // shadow can't be seen if the layer masks to its bounds.
+    let (r, g, b, a) = shadow_rgba;
+    let shadow_alpha = a * shadow_opacity * opacity;
+    if !masks_to_bounds && shadow_alpha > 0.0 {
+        let shadow =
+            if let Some((_, _, _, background_alpha)) = background_rgba.filter(|c| c.3 > 0.0) {
+                Some((&outline, None, shadow_alpha * background_alpha))
+            } else {
+                contents_tex_coords
+                    .as_deref()
+                    .map(|tex_coords| (&contents_outline, Some(tex_coords), shadow_alpha))
+            };
+        if let Some((points, tex_coords, alpha)) = shadow {
+            draw_shadow(
+                gles,
+                transform,
+                points,
+                tex_coords,
+                (r, g, b, alpha),
+                shadow_offset,
+                shadow_radius,
+                screen_size,
+            );
+        }
     }
 
+    // Draw background color, if any
+    let have_background = match background_rgba {
+        Some((r, g, b, a)) if visible && a * opacity != 0.0 => {
+            let a = a * opacity;
+            if a == 1.0 {
+                gles.Disable(gles11::BLEND);
+            } else {
+                gles.Enable(gles11::BLEND);
+                gles.BlendFunc(gles11::ONE, gles11::ONE_MINUS_SRC_ALPHA);
+            }
+            gles.Color4f(r * a, g * a, b * a, a);
+            let vertices = ndc_vertices(transform, &outline, screen_size);
+            draw_vertices(gles, gles11::TRIANGLE_FAN, &vertices, None);
+            true
+        }
+        _ => false,
+    };
+
     // Draw texture, if any
-    if need_texture {
+    if let Some(ref tex_coords) = contents_tex_coords {
         gles.Color4f(opacity, opacity, opacity, opacity);
         if opacity == 1.0 && host_obj.opaque && !have_background {
             gles.Disable(gles11::BLEND);
@@ -403,29 +574,64 @@ unsafe fn composite_layer_recursive(
             gles.Enable(gles11::BLEND);
             gles.BlendFunc(gles11::ONE, gles11::ONE_MINUS_SRC_ALPHA);
         }
+        let vertices = ndc_vertices(transform, &contents_outline, screen_size);
+        draw_vertices(gles, gles11::TRIANGLE_FAN, &vertices, Some(tex_coords));
+    }
 
-        let (x, y, w, h) = gl_rect_from_cg_rect(absolute_frame_clipped, scale_hack, fb_height);
-        gles.Scissor(x, y, w, h);
-        gles.Viewport(x, y, w, h);
+    // Sublayers are clipped to the bounds using the scissor test, which can
+    // only clip to rectangles. For rounded corners, the parts of the
+    // framebuffer under the corners are saved before drawing the sublayers and
+    // restored afterwards.
+    // TODO: clip precisely when the layer is rotated or skewed.
+    let sublayer_clip_to = if masks_to_bounds {
+        clip_rects(clip_to, transform.apply_to_rect(bounds))
+    } else {
+        clip_to
+    };
+    let sublayer_clip_rect = gl_rect_from_cg_rect(sublayer_clip_to, scale_hack, fb_height);
+    let save_corners = masks_to_bounds
+        && corner_radius > 0.0
+        && !host_obj.sublayers.is_empty()
+        && sublayer_clip_rect.2 > 0
+        && sublayer_clip_rect.3 > 0;
+    if save_corners {
+        if mask_textures.len() == mask_depth {
+            let mut texture = 0;
+            gles.GenTextures(1, &mut texture);
+            gles.BindTexture(gles11::TEXTURE_2D, texture);
+            gles.TexImage2D(
+                gles11::TEXTURE_2D,
+                0,
+                gles11::RGBA as _,
+                fb_width as _,
+                fb_height as _,
+                0,
+                gles11::RGBA,
+                gles11::UNSIGNED_BYTE,
+                std::ptr::null(),
+            );
+            gles.TexParameteri(
+                gles11::TEXTURE_2D,
+                gles11::TEXTURE_MIN_FILTER,
+                gles11::NEAREST as _,
+            );
+            gles.TexParameteri(
+                gles11::TEXTURE_2D,
+                gles11::TEXTURE_MAG_FILTER,
+                gles11::NEAREST as _,
+            );
+            mask_textures.push(texture);
+        }
+        gles.BindTexture(gles11::TEXTURE_2D, mask_textures[mask_depth]);
+        let (x, y, w, h) = sublayer_clip_rect;
+        gles.CopyTexSubImage2D(gles11::TEXTURE_2D, 0, x, y, x, y, w, h);
+    }
 
-        gles.BindBuffer(gles11::ARRAY_BUFFER, 0);
-        let vertices: [f32; 12] = [
-            -1.0, -1.0, -1.0, 1.0, 1.0, -1.0, 1.0, -1.0, -1.0, 1.0, 1.0, 1.0,
-        ];
-        gles.EnableClientState(gles11::VERTEX_ARRAY);
-        gles.VertexPointer(2, gles11::FLOAT, 0, vertices.as_ptr() as *const GLvoid);
+    // re-borrow mutably
+    let host_obj = objc.borrow_mut::<CALayerHostObject>(layer);
 
-        // Normal images will have top-to-bottom row order, but OpenGL ES
-        // expects bottom-to-top, so flip the UVs in that case.
-        let tex_coords: [f32; 12] = if host_obj.contents != nil {
-            [0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0]
-        } else {
-            [0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0]
-        };
-        gles.EnableClientState(gles11::TEXTURE_COORD_ARRAY);
-        gles.TexCoordPointer(2, gles11::FLOAT, 0, tex_coords.as_ptr() as *const GLvoid);
-        gles.Enable(gles11::TEXTURE_2D);
-        gles.DrawArrays(gles11::TRIANGLES, 0, 6);
+    if need_update {
+        host_obj.gles_texture_is_up_to_date = true;
     }
 
     // avoid holding mutable borrow while recursing
@@ -436,19 +642,316 @@ unsafe fn composite_layer_recursive(
             objc,
             mem,
             child_layer,
-            /* origin: */
-            CGPoint {
-                x: absolute_frame.origin.x - bounds.origin.x,
-                y: absolute_frame.origin.y - bounds.origin.y,
-            },
-            // TODO: clipping goes here (when masksToBounds is implemented)
-            clip_to,
+            transform,
+            sublayer_clip_to,
             opacity,
+            screen_size,
+            now,
             scale_hack,
+            fb_width,
             fb_height,
+            mask_textures,
+            mask_depth + save_corners as usize,
         )
     }
     objc.borrow_mut::<CALayerHostObject>(layer).sublayers = sublayers;
+
+    gles.BindBuffer(gles11::ARRAY_BUFFER, 0);
+    gles.EnableClientState(gles11::VERTEX_ARRAY);
+
+    if save_corners {
+        let (x, y, w, h) = sublayer_clip_rect;
+        gles.Scissor(x, y, w, h);
+        gles.Disable(gles11::BLEND);
+        gles.Color4f(1.0, 1.0, 1.0, 1.0);
+        gles.BindTexture(gles11::TEXTURE_2D, mask_textures[mask_depth]);
+        let corners = [
+            (bounds.origin.x, bounds.origin.y),
+            (bounds.origin.x + bounds.size.width, bounds.origin.y),
+            (
+                bounds.origin.x + bounds.size.width,
+                bounds.origin.y + bounds.size.height,
+            ),
+            (bounds.origin.x, bounds.origin.y + bounds.size.height),
+        ];
+        for (i, (x, y)) in corners.into_iter().enumerate() {
+            let arc = &outline[i * (CORNER_SEGMENTS + 1)..][..CORNER_SEGMENTS + 1];
+            let mut wedge = vec![CGPoint { x, y }];
+            wedge.extend_from_slice(arc);
+            let vertices = ndc_vertices(transform, &wedge, screen_size);
+            // The saved texture covers the whole framebuffer.
+            let tex_coords: Vec<f32> = vertices.iter().map(|&v| (v + 1.0) / 2.0).collect();
+            draw_vertices(gles, gles11::TRIANGLE_FAN, &vertices, Some(&tex_coords));
+        }
+    }
+
+    // Draw border, if any. It goes on top of the sublayers.
+    let (r, g, b, a) = border_rgba;
+    let a = a * opacity;
+    if border_width > 0.0 && a > 0.0 && visible {
+        let (x, y, w, h) = gl_rect_from_cg_rect(clip_to, scale_hack, fb_height);
+        gles.Scissor(x, y, w, h);
+        if a == 1.0 {
+            gles.Disable(gles11::BLEND);
+        } else {
+            gles.Enable(gles11::BLEND);
+            gles.BlendFunc(gles11::ONE, gles11::ONE_MINUS_SRC_ALPHA);
+        }
+        gles.Color4f(r * a, g * a, b * a, a);
+        let inset = border_width
+            .min(bounds.size.width / 2.0)
+            .min(bounds.size.height / 2.0)
+            .max(0.0);
+        let inner_bounds = CGRect {
+            origin: CGPoint {
+                x: bounds.origin.x + inset,
+                y: bounds.origin.y + inset,
+            },
+            size: CGSize {
+                width: bounds.size.width - inset * 2.0,
+                height: bounds.size.height - inset * 2.0,
+            },
+        };
+        let inner_outline = rounded_rect_outline(inner_bounds, corner_radius - inset);
+        // Triangle strip zig-zagging between the outer and inner edges.
+        let mut ring = Vec::with_capacity((outline.len() + 1) * 2);
+        for i in 0..=outline.len() {
+            let i = i % outline.len();
+            ring.push(outline[i]);
+            ring.push(inner_outline[i]);
+        }
+        let vertices = ndc_vertices(transform, &ring, screen_size);
+        draw_vertices(gles, gles11::TRIANGLE_STRIP, &vertices, None);
+    }
+}
+
+/// Draw the status bar, updating its texture first if there are new pixels.
+unsafe fn draw_status_bar(
+    gles: &mut dyn GLES,
+    texture_cache: &mut Option<(GLuint, status_bar::Appearance)>,
+    appearance: status_bar::Appearance,
+    pixels: Option<Vec<u8>>,
+    screen_size: CGSize,
+    fb_width: u32,
+    fb_height: u32,
+) {
+    let (size, transform) = appearance.geometry();
+
+    let texture = if let Some((texture, _)) = *texture_cache {
+        texture
+    } else {
+        let mut texture = 0;
+        gles.GenTextures(1, &mut texture);
+        texture
+    };
+    gles.BindTexture(gles11::TEXTURE_2D, texture);
+    if let Some(pixels) = pixels {
+        upload_rgba8_pixels(gles, &pixels, appearance.pixel_dimensions());
+        *texture_cache = Some((texture, appearance));
+    }
+
+    gles.Scissor(0, 0, fb_width as _, fb_height as _);
+    gles.BindBuffer(gles11::ARRAY_BUFFER, 0);
+    gles.EnableClientState(gles11::VERTEX_ARRAY);
+    gles.Enable(gles11::BLEND);
+    gles.BlendFunc(gles11::ONE, gles11::ONE_MINUS_SRC_ALPHA);
+    gles.Color4f(1.0, 1.0, 1.0, 1.0);
+    let rect = CGRect {
+        origin: CGPoint { x: 0.0, y: 0.0 },
+        size,
+    };
+    let outline = rounded_rect_outline(rect, 0.0);
+    let tex_coords = tex_coords_for_points(&outline, rect, true);
+    let vertices = ndc_vertices(transform, &outline, screen_size);
+    draw_vertices(gles, gles11::TRIANGLE_FAN, &vertices, Some(&tex_coords));
+}
+
+/// Number of line segments used to approximate each rounded corner.
+const CORNER_SEGMENTS: usize = 8;
+
+/// Get the outline of a rectangle with rounded corners as a convex polygon,
+/// going clockwise (on screen) from the top-left corner. The number of points
+/// is always the same, so that outlines can be paired up. If the radius is
+/// zero, each corner is just the same point repeated.
+fn rounded_rect_outline(rect: CGRect, radius: CGFloat) -> Vec<CGPoint> {
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    let CGRect {
+        origin: CGPoint { x, y },
+        size: CGSize { width, height },
+    } = rect;
+    let radius = radius.min(width / 2.0).min(height / 2.0).max(0.0);
+    // Centers of the corner arcs and the angles they start at.
+    let corners = [
+        (x + radius, y + radius, PI),
+        (x + width - radius, y + radius, PI + FRAC_PI_2),
+        (x + width - radius, y + height - radius, 0.0),
+        (x + radius, y + height - radius, FRAC_PI_2),
+    ];
+    let mut points = Vec::with_capacity(corners.len() * (CORNER_SEGMENTS + 1));
+    for (center_x, center_y, start) in corners {
+        for i in 0..=CORNER_SEGMENTS {
+            let angle = start + FRAC_PI_2 * (i as f32 / CORNER_SEGMENTS as f32);
+            points.push(CGPoint {
+                x: center_x + radius * angle.cos(),
+                y: center_y + radius * angle.sin(),
+            });
+        }
+    }
+    points
+}
+
+/// Clip a convex polygon to a rectangle (Sutherland-Hodgman algorithm).
+fn clip_polygon_to_rect(polygon: &[CGPoint], rect: CGRect) -> Vec<CGPoint> {
+    let x1 = rect.origin.x;
+    let y1 = rect.origin.y;
+    let x2 = x1 + rect.size.width;
+    let y2 = y1 + rect.size.height;
+    // Each edge is represented by how far inside of it a point is.
+    let edges: [&dyn Fn(CGPoint) -> CGFloat; 4] =
+        [&|p| p.x - x1, &|p| x2 - p.x, &|p| p.y - y1, &|p| y2 - p.y];
+
+    let mut points = polygon.to_vec();
+    for inside in edges {
+        let input = std::mem::take(&mut points);
+        for (i, &current) in input.iter().enumerate() {
+            let next = input[(i + 1) % input.len()];
+            let (current_inside, next_inside) = (inside(current), inside(next));
+            if current_inside >= 0.0 {
+                points.push(current);
+            }
+            if (current_inside >= 0.0) != (next_inside >= 0.0) {
+                let t = current_inside / (current_inside - next_inside);
+                points.push(CGPoint {
+                    x: current.x + (next.x - current.x) * t,
+                    y: current.y + (next.y - current.y) * t,
+                });
+            }
+        }
+    }
+    points
+}
+
+/// Get texture co-ordinates for points in a layer's co-ordinate space, such
+/// that the texture fills `rect`.
+fn tex_coords_for_points(points: &[CGPoint], rect: CGRect, top_to_bottom: bool) -> Vec<f32> {
+    let mut tex_coords = Vec::with_capacity(points.len() * 2);
+    for &CGPoint { x, y } in points {
+        let u = (x - rect.origin.x) / rect.size.width;
+        let v = (y - rect.origin.y) / rect.size.height;
+        tex_coords.push(u);
+        tex_coords.push(if top_to_bottom { v } else { 1.0 - v });
+    }
+    tex_coords
+}
+
+/// Transform points in a layer's co-ordinate space to normalized device
+/// co-ordinates, in the format expected by `glVertexPointer()`.
+fn ndc_vertices(transform: CGAffineTransform, points: &[CGPoint], screen_size: CGSize) -> Vec<f32> {
+    let mut vertices = Vec::with_capacity(points.len() * 2);
+    for &point in points {
+        let CGPoint { x, y } = transform.apply_to_point(point);
+        // y points up in OpenGL ES, but down in UIKit and Core Animation
+        vertices.push(x / screen_size.width * 2.0 - 1.0);
+        vertices.push(1.0 - y / screen_size.height * 2.0);
+    }
+    vertices
+}
+
+/// Draw vertices from [ndc_vertices], textured with the currently bound
+/// texture if texture co-ordinates are provided.
+unsafe fn draw_vertices(
+    gles: &mut dyn GLES,
+    mode: GLenum,
+    vertices: &[f32],
+    tex_coords: Option<&[f32]>,
+) {
+    gles.VertexPointer(2, gles11::FLOAT, 0, vertices.as_ptr() as *const GLvoid);
+    if let Some(tex_coords) = tex_coords {
+        assert!(tex_coords.len() == vertices.len());
+        gles.EnableClientState(gles11::TEXTURE_COORD_ARRAY);
+        gles.TexCoordPointer(2, gles11::FLOAT, 0, tex_coords.as_ptr() as *const GLvoid);
+        gles.Enable(gles11::TEXTURE_2D);
+    } else {
+        gles.DisableClientState(gles11::TEXTURE_COORD_ARRAY);
+        gles.Disable(gles11::TEXTURE_2D);
+    }
+    gles.DrawArrays(mode, 0, (vertices.len() / 2) as GLsizei);
+}
+
+/// Offsets, as fractions of the shadow radius, at which a shadow is drawn.
+/// Drawing the shadow repeatedly like this is a cheap approximation of a blur.
+const SHADOW_SAMPLES: [(CGFloat, CGFloat); 17] = [
+    (0.0, 0.0),
+    (0.5, 0.0),
+    (-0.5, 0.0),
+    (0.0, 0.5),
+    (0.0, -0.5),
+    (0.35, 0.35),
+    (-0.35, 0.35),
+    (0.35, -0.35),
+    (-0.35, -0.35),
+    (1.0, 0.0),
+    (-1.0, 0.0),
+    (0.0, 1.0),
+    (0.0, -1.0),
+    (0.71, 0.71),
+    (-0.71, 0.71),
+    (0.71, -0.71),
+    (-0.71, -0.71),
+];
+
+/// Draw the shadow of a convex polygon in a layer's co-ordinate space. If
+/// texture co-ordinates are provided, the alpha channel of the currently bound
+/// texture is used as a mask.
+unsafe fn draw_shadow(
+    gles: &mut dyn GLES,
+    transform: CGAffineTransform,
+    points: &[CGPoint],
+    tex_coords: Option<&[f32]>,
+    rgba: (CGFloat, CGFloat, CGFloat, CGFloat),
+    offset: CGSize,
+    radius: CGFloat,
+    screen_size: CGSize,
+) {
+    let samples = if radius >= 0.5 {
+        &SHADOW_SAMPLES[..]
+    } else {
+        &SHADOW_SAMPLES[..1]
+    };
+    // The samples are composited on top of each other, so each must be more
+    // transparent for the shadow to have the right opacity where they overlap.
+    let (r, g, b, a) = rgba;
+    let sample_alpha = 1.0 - (1.0 - a.min(1.0)).powf(1.0 / samples.len() as f32);
+
+    gles.Enable(gles11::BLEND);
+    gles.BlendFunc(gles11::SRC_ALPHA, gles11::ONE_MINUS_SRC_ALPHA);
+    gles.Color4f(r, g, b, sample_alpha);
+    if tex_coords.is_some() {
+        // Use the shadow color, with the alpha multiplied by the texture's.
+        let env = gles11::TEXTURE_ENV;
+        gles.TexEnvi(env, gles11::TEXTURE_ENV_MODE, gles11::COMBINE as _);
+        gles.TexEnvi(env, gles11::COMBINE_RGB, gles11::REPLACE as _);
+        gles.TexEnvi(env, gles11::SRC0_RGB, gles11::PRIMARY_COLOR as _);
+        gles.TexEnvi(env, gles11::COMBINE_ALPHA, gles11::MODULATE as _);
+        gles.TexEnvi(env, gles11::SRC0_ALPHA, gles11::TEXTURE as _);
+        gles.TexEnvi(env, gles11::SRC1_ALPHA, gles11::PRIMARY_COLOR as _);
+    }
+    for &(x, y) in samples {
+        let offset_transform = CGAffineTransform::make_translation(
+            offset.width + x * radius,
+            offset.height + y * radius,
+        );
+        let vertices = ndc_vertices(offset_transform.concat(transform), points, screen_size);
+        draw_vertices(gles, gles11::TRIANGLE_FAN, &vertices, tex_coords);
+    }
+    if tex_coords.is_some() {
+        gles.TexEnvi(
+            gles11::TEXTURE_ENV,
+            gles11::TEXTURE_ENV_MODE,
+            gles11::MODULATE as _,
+        );
+    }
 }
 
 unsafe fn upload_rgba8_pixels(gles: &mut dyn GLES, pixels: &[u8], dimensions: (u32, u32)) {
@@ -475,6 +978,20 @@ unsafe fn upload_rgba8_pixels(gles: &mut dyn GLES, pixels: &[u8], dimensions: (u
     );
 }
 
+fn union_rects(a: CGRect, b: CGRect) -> CGRect {
+    let x1 = a.origin.x.min(b.origin.x);
+    let y1 = a.origin.y.min(b.origin.y);
+    let x2 = (a.origin.x + a.size.width).max(b.origin.x + b.size.width);
+    let y2 = (a.origin.y + a.size.height).max(b.origin.y + b.size.height);
+    CGRect {
+        origin: CGPoint { x: x1, y: y1 },
+        size: CGSize {
+            width: x2 - x1,
+            height: y2 - y1,
+        },
+    }
+}
+
 fn clip_rects(a_clip: CGRect, b_clip: CGRect) -> CGRect {
     let a_x1 = a_clip.origin.x;
     let a_y1 = a_clip.origin.y;
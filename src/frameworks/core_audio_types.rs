@@ -5,7 +5,7 @@
  */
 //! The Core Audio Types framework. (Yes, it's not part of Core Audio?)
 
-use crate::mem::SafeRead;
+use crate::mem::{guest_size_of, MutPtr, MutVoidPtr, SafeRead};
 
 // The audio frameworks love FourCC's, and we currently don't need these
 // anywhere else, so this is as good a place to put this as any.
@@ -90,3 +90,27 @@ pub const kAudioFormatFlagIsBigEndian: AudioFormatFlags = 1 << 1;
 pub const kAudioFormatFlagIsSignedInteger: AudioFormatFlags = 1 << 2;
 pub const kAudioFormatFlagIsPacked: AudioFormatFlags = 1 << 3;
 pub const kAudioFormatFlagIsAlignedHigh: AudioFormatFlags = 1 << 4;
+pub const kAudioFormatFlagIsNonInterleaved: AudioFormatFlags = 1 << 5;
+
+/// One buffer in an `AudioBufferList`.
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct AudioBuffer {
+    pub number_channels: u32,
+    pub data_byte_size: u32,
+    pub data: MutVoidPtr,
+}
+unsafe impl SafeRead for AudioBuffer {}
+
+/// Only the header of an `AudioBufferList`: it's followed by a variable-length
+/// array of [AudioBuffer], see [audio_buffer_list_buffers].
+#[repr(C, packed)]
+pub struct AudioBufferList {
+    pub number_buffers: u32,
+}
+unsafe impl SafeRead for AudioBufferList {}
+
+/// Get a pointer to the array of buffers in an `AudioBufferList`.
+pub fn audio_buffer_list_buffers(list: MutPtr<AudioBufferList>) -> MutPtr<AudioBuffer> {
+    (list.cast::<u8>() + guest_size_of::<AudioBufferList>()).cast()
+}
@@ -9,6 +9,7 @@
 //! but here it is the same type.
 
 use super::cf_array::CFArrayRef;
+use super::cf_dictionary::CFDictionaryRef;
 use super::cf_string::CFStringRef;
 use super::cf_url::CFURLRef;
 use super::CFTypeRef;
@@ -31,6 +32,16 @@ fn CFBundleGetMainBundle(env: &mut Environment) -> CFBundleRef {
     msg_class![env; NSBundle mainBundle]
 }
 
+fn CFBundleGetIdentifier(env: &mut Environment, bundle: CFBundleRef) -> CFStringRef {
+    msg![env; bundle bundleIdentifier]
+}
+
+fn CFBundleGetInfoDictionary(env: &mut Environment, bundle: CFBundleRef) -> CFDictionaryRef {
+    // The property list objects are already CF types, since these are
+    // toll-free bridged.
+    msg![env; bundle infoDictionary]
+}
+
 fn CFBundleGetValueForInfoDictionaryKey(
     env: &mut Environment,
     bundle: CFBundleRef,
@@ -90,6 +101,17 @@ fn CFBundleCopyResourceURL(
     msg![env; url copy]
 }
 
+fn CFBundleCopyResourceURLsOfType(
+    env: &mut Environment,
+    bundle: CFBundleRef,
+    resource_type: CFStringRef,
+    sub_dir_name: CFStringRef,
+) -> CFArrayRef {
+    let urls: CFArrayRef = msg![env; bundle URLsForResourcesWithExtension:resource_type
+                                                          subdirectory:sub_dir_name];
+    retain(env, urls)
+}
+
 pub fn CFBundleCopyBundleLocalizations(env: &mut Environment, bundle: CFBundleRef) -> CFArrayRef {
     let bundle_localizations = env
         .objc
@@ -156,11 +178,14 @@ pub fn CFBundleCopyPreferredLocalizationsFromArray(
 
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(CFBundleGetMainBundle()),
+    export_c_func!(CFBundleGetIdentifier(_)),
+    export_c_func!(CFBundleGetInfoDictionary(_)),
     export_c_func!(CFBundleGetValueForInfoDictionaryKey(_, _)),
     export_c_func!(CFBundleGetVersionNumber(_)),
     export_c_func!(CFBundleCopyBundleURL(_)),
     export_c_func!(CFBundleCopyResourcesDirectoryURL(_)),
     export_c_func!(CFBundleCopyResourceURL(_, _, _, _)),
+    export_c_func!(CFBundleCopyResourceURLsOfType(_, _, _)),
     export_c_func!(CFBundleCopyBundleLocalizations(_)),
     export_c_func!(CFBundleCopyPreferredLocalizationsFromArray(_)),
 ];
@@ -10,14 +10,21 @@
 
 use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant};
 use crate::frameworks::core_foundation::time::CFTimeInterval;
-use crate::frameworks::foundation::ns_run_loop::run_run_loop_single_iteration;
+use crate::frameworks::foundation::ns_run_loop::{self, run_run_loop, RunLoopLimit};
 use crate::frameworks::foundation::ns_string;
 use crate::objc::{msg, msg_class};
 use crate::Environment;
+use std::time::Duration;
 
 pub type CFRunLoopRef = super::CFTypeRef;
 pub type CFRunLoopMode = super::cf_string::CFStringRef;
 
+pub type CFRunLoopRunResult = i32;
+pub const kCFRunLoopRunFinished: CFRunLoopRunResult = 1;
+pub const kCFRunLoopRunStopped: CFRunLoopRunResult = 2;
+pub const kCFRunLoopRunTimedOut: CFRunLoopRunResult = 3;
+pub const kCFRunLoopRunHandledSource: CFRunLoopRunResult = 4;
+
 fn CFRunLoopGetCurrent(env: &mut Environment) -> CFRunLoopRef {
     msg_class![env; NSRunLoop currentRunLoop]
 }
@@ -26,12 +33,18 @@ pub fn CFRunLoopGetMain(env: &mut Environment) -> CFRunLoopRef {
     msg_class![env; NSRunLoop mainRunLoop]
 }
 
+fn CFRunLoopRun(env: &mut Environment) {
+    // TODO: we're currently supporting only the main run loop
+    let main_run_loop = CFRunLoopGetMain(env);
+    run_run_loop(env, main_run_loop, RunLoopLimit::UntilStopped);
+}
+
 fn CFRunLoopRunInMode(
     env: &mut Environment,
     mode: CFRunLoopMode,
     seconds: CFTimeInterval,
-    _return_something: bool,
-) -> i32 {
+    return_after_source_handled: bool,
+) -> CFRunLoopRunResult {
     let default_mode = ns_string::get_static_str(env, kCFRunLoopDefaultMode);
     let common_modes = ns_string::get_static_str(env, kCFRunLoopCommonModes);
     // TODO: handle other modes
@@ -39,15 +52,31 @@ fn CFRunLoopRunInMode(
         msg![env; mode isEqualToString:default_mode]
             || msg![env; mode isEqualToString:common_modes]
     );
-    assert!(seconds <= 0.001);
     // TODO: we're currently supporting only the main run loop
-    log_dbg!(
-        "TODO: properly implement CFRunLoopRunInMode [current thread {}], running a single iteration of the main run loop",
-        env.current_thread
-    );
+    if env.current_thread != 0 {
+        log_dbg!(
+            "TODO: CFRunLoopRunInMode on thread {}, running the main run loop",
+            env.current_thread
+        );
+    }
+    let deadline = env.clock.now() + Duration::from_secs_f64(seconds.max(0.0));
     let main_run_loop = CFRunLoopGetMain(env);
-    run_run_loop_single_iteration(env, main_run_loop);
-    1 // kCFRunLoopRunFinished
+    run_run_loop(
+        env,
+        main_run_loop,
+        RunLoopLimit::Until {
+            deadline,
+            return_after_source_handled,
+        },
+    )
+}
+
+fn CFRunLoopStop(env: &mut Environment, run_loop: CFRunLoopRef) {
+    ns_run_loop::stop(env, run_loop);
+}
+
+fn CFRunLoopWakeUp(env: &mut Environment, run_loop: CFRunLoopRef) {
+    ns_run_loop::wake_up(env, run_loop);
 }
 
 pub const kCFRunLoopCommonModes: &str = "kCFRunLoopCommonModes";
@@ -67,5 +96,8 @@ pub const CONSTANTS: ConstantExports = &[
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(CFRunLoopGetCurrent()),
     export_c_func!(CFRunLoopGetMain()),
+    export_c_func!(CFRunLoopRun()),
     export_c_func!(CFRunLoopRunInMode(_, _, _)),
+    export_c_func!(CFRunLoopStop(_)),
+    export_c_func!(CFRunLoopWakeUp(_)),
 ];
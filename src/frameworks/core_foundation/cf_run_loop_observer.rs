@@ -0,0 +1,243 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CFRunLoopObserver`.
+//!
+//! There's no Foundation equivalent, so this is implemented with a special
+//! internal class.
+
+use super::cf_allocator::{kCFAllocatorDefault, CFAllocatorRef};
+use super::cf_run_loop::{CFRunLoopMode, CFRunLoopRef};
+use super::{CFIndex, CFOptionFlags};
+use crate::abi::{CallFromHost, GuestFunction};
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::frameworks::foundation::ns_run_loop;
+use crate::mem::{MutPtr, MutVoidPtr, SafeRead};
+use crate::objc::{id, nil, objc_classes, release, retain, ClassExports, HostObject};
+use crate::Environment;
+
+pub type CFRunLoopObserverRef = super::CFTypeRef;
+
+pub type CFRunLoopActivity = CFOptionFlags;
+pub const kCFRunLoopEntry: CFRunLoopActivity = 1 << 0;
+pub const kCFRunLoopBeforeTimers: CFRunLoopActivity = 1 << 1;
+pub const kCFRunLoopBeforeSources: CFRunLoopActivity = 1 << 2;
+pub const kCFRunLoopBeforeWaiting: CFRunLoopActivity = 1 << 5;
+pub const kCFRunLoopAfterWaiting: CFRunLoopActivity = 1 << 6;
+pub const kCFRunLoopExit: CFRunLoopActivity = 1 << 7;
+
+// void (*)(CFRunLoopObserverRef observer, CFRunLoopActivity activity,
+//          void *info)
+type CFRunLoopObserverCallBack = GuestFunction;
+
+#[repr(C, packed)]
+pub struct CFRunLoopObserverContext {
+    version: CFIndex,
+    info: MutVoidPtr,
+    retain_callback: GuestFunction,
+    release_callback: GuestFunction,
+    #[allow(dead_code)]
+    copy_desc_callback: GuestFunction,
+}
+unsafe impl SafeRead for CFRunLoopObserverContext {}
+
+/// Belongs to _touchHLE_CFRunLoopObserver
+struct CFRunLoopObserverHostObject {
+    activities: CFRunLoopActivity,
+    repeats: bool,
+    order: CFIndex,
+    callout: CFRunLoopObserverCallBack,
+    info: MutVoidPtr,
+    /// `void (*)(const void *info)`, may be NULL.
+    release_callback: GuestFunction,
+    valid: bool,
+    /// Weak reference. The observer removes itself when invalidated.
+    run_loop: id,
+}
+impl HostObject for CFRunLoopObserverHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation _touchHLE_CFRunLoopObserver: NSObject
+
+- (())dealloc {
+    let &CFRunLoopObserverHostObject {
+        info,
+        release_callback,
+        ..
+    } = env.objc.borrow(this);
+    if !release_callback.to_ptr().is_null() {
+        () = release_callback.call_from_host(env, (info,));
+    }
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+@end
+
+};
+
+fn CFRunLoopObserverCreate(
+    env: &mut Environment,
+    allocator: CFAllocatorRef,
+    activities: CFRunLoopActivity,
+    repeats: bool,
+    order: CFIndex,
+    callout: CFRunLoopObserverCallBack,
+    context_ptr: MutPtr<CFRunLoopObserverContext>,
+) -> CFRunLoopObserverRef {
+    assert_eq!(allocator, kCFAllocatorDefault); // unimplemented
+
+    let (info, release_callback) = if context_ptr.is_null() {
+        (
+            MutVoidPtr::null(),
+            GuestFunction::from_addr_with_thumb_bit(0),
+        )
+    } else {
+        let context = env.mem.read(context_ptr);
+        let version = context.version;
+        assert_eq!(version, 0);
+        let mut info = context.info;
+        let retain_callback = context.retain_callback;
+        if !retain_callback.to_ptr().is_null() {
+            info = retain_callback.call_from_host(env, (info,));
+        }
+        (info, context.release_callback)
+    };
+
+    let host_object = Box::new(CFRunLoopObserverHostObject {
+        activities,
+        repeats,
+        order,
+        callout,
+        info,
+        release_callback,
+        valid: true,
+        run_loop: nil,
+    });
+    let class = env
+        .objc
+        .get_known_class("_touchHLE_CFRunLoopObserver", &mut env.mem);
+    let observer = env.objc.alloc_object(class, host_object, &mut env.mem);
+    log_dbg!(
+        "CFRunLoopObserverCreate(activities: {:#x}, repeats: {}, order: {}, callout: {:?}, info: {:?}) => {:?}",
+        activities,
+        repeats,
+        order,
+        callout,
+        info,
+        observer,
+    );
+    observer
+}
+
+fn CFRunLoopAddObserver(
+    env: &mut Environment,
+    run_loop: CFRunLoopRef,
+    observer: CFRunLoopObserverRef,
+    _mode: CFRunLoopMode,
+) {
+    // TODO: handle run loop modes. Currently assumes the common modes.
+    let host_object = env.objc.borrow_mut::<CFRunLoopObserverHostObject>(observer);
+    // touchHLE only has one run loop, so the observer can't be in another.
+    if !host_object.valid || host_object.run_loop != nil {
+        return;
+    }
+    host_object.run_loop = run_loop;
+    let order = host_object.order;
+    ns_run_loop::add_observer(env, run_loop, observer, order);
+}
+
+fn CFRunLoopRemoveObserver(
+    env: &mut Environment,
+    run_loop: CFRunLoopRef,
+    observer: CFRunLoopObserverRef,
+    _mode: CFRunLoopMode,
+) {
+    let host_object = env.objc.borrow_mut::<CFRunLoopObserverHostObject>(observer);
+    if host_object.run_loop == run_loop {
+        host_object.run_loop = nil;
+        ns_run_loop::remove_observer(env, run_loop, observer);
+    }
+}
+
+fn CFRunLoopObserverInvalidate(env: &mut Environment, observer: CFRunLoopObserverRef) {
+    let host_object = env.objc.borrow_mut::<CFRunLoopObserverHostObject>(observer);
+    host_object.valid = false;
+    let run_loop = std::mem::replace(&mut host_object.run_loop, nil);
+    if run_loop != nil {
+        ns_run_loop::remove_observer(env, run_loop, observer);
+    }
+}
+
+fn CFRunLoopObserverIsValid(env: &mut Environment, observer: CFRunLoopObserverRef) -> bool {
+    env.objc
+        .borrow::<CFRunLoopObserverHostObject>(observer)
+        .valid
+}
+
+fn CFRunLoopObserverGetActivities(
+    env: &mut Environment,
+    observer: CFRunLoopObserverRef,
+) -> CFRunLoopActivity {
+    env.objc
+        .borrow::<CFRunLoopObserverHostObject>(observer)
+        .activities
+}
+
+fn CFRunLoopObserverDoesRepeat(env: &mut Environment, observer: CFRunLoopObserverRef) -> bool {
+    env.objc
+        .borrow::<CFRunLoopObserverHostObject>(observer)
+        .repeats
+}
+
+fn CFRunLoopObserverGetOrder(env: &mut Environment, observer: CFRunLoopObserverRef) -> CFIndex {
+    env.objc
+        .borrow::<CFRunLoopObserverHostObject>(observer)
+        .order
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(CFRunLoopObserverCreate(_, _, _, _, _, _)),
+    export_c_func!(CFRunLoopAddObserver(_, _, _)),
+    export_c_func!(CFRunLoopRemoveObserver(_, _, _)),
+    export_c_func!(CFRunLoopObserverInvalidate(_)),
+    export_c_func!(CFRunLoopObserverIsValid(_)),
+    export_c_func!(CFRunLoopObserverGetActivities(_)),
+    export_c_func!(CFRunLoopObserverDoesRepeat(_)),
+    export_c_func!(CFRunLoopObserverGetOrder(_)),
+];
+
+/// For use by `NSRunLoop`: tell an observer about a run loop activity, if it's
+/// interested in it.
+pub fn handle_observer(env: &mut Environment, observer: id, activity: CFRunLoopActivity) {
+    let &CFRunLoopObserverHostObject {
+        activities,
+        repeats,
+        callout,
+        info,
+        valid,
+        ..
+    } = env.objc.borrow(observer);
+    if !valid || activities & activity == 0 {
+        return;
+    }
+
+    // The observer may be released when it's invalidated, so it needs to be
+    // retained so it's still around to pass to the callout.
+    retain(env, observer);
+    if !repeats {
+        CFRunLoopObserverInvalidate(env, observer);
+    }
+    log_dbg!(
+        "Run loop observer {:?} activity {:#x}, calling {:?}",
+        observer,
+        activity,
+        callout
+    );
+    () = callout.call_from_host(env, (observer, activity, info));
+    release(env, observer);
+}
@@ -12,11 +12,14 @@ use crate::abi::GuestFunction;
 use crate::dyld::{export_c_func, FunctionExports};
 use crate::frameworks::core_foundation::cf_allocator::{kCFAllocatorDefault, CFAllocatorRef};
 use crate::frameworks::core_foundation::cf_run_loop::{CFRunLoopMode, CFRunLoopRef};
-use crate::frameworks::core_foundation::time::{CFAbsoluteTime, CFTimeInterval};
+use crate::frameworks::core_foundation::time::{
+    absolute_time_to_instant, instant_to_absolute_time, CFAbsoluteTime, CFTimeInterval,
+};
 use crate::frameworks::core_foundation::CFIndex;
+use crate::frameworks::foundation::ns_timer;
 use crate::mem::{MutPtr, MutVoidPtr, SafeRead};
 use crate::objc::{
-    id, msg, msg_class, nil, objc_classes, Class, ClassExports, HostObject, NSZonePtr,
+    id, msg, msg_class, objc_classes, release, Class, ClassExports, HostObject, NSZonePtr,
 };
 use crate::Environment;
 
@@ -32,6 +35,7 @@ pub struct CFRunLoopTimerContext {
     info: MutVoidPtr,
     retain_callback: GuestFunction,
     release_callback: GuestFunction,
+    #[allow(dead_code)]
     copy_desc_callback: GuestFunction,
 }
 unsafe impl SafeRead for CFRunLoopTimerContext {}
@@ -39,7 +43,7 @@ unsafe impl SafeRead for CFRunLoopTimerContext {}
 fn CFRunLoopTimerCreate(
     env: &mut Environment,
     allocator: CFAllocatorRef,
-    _fire_date: CFAbsoluteTime, // TODO
+    fire_date: CFAbsoluteTime,
     interval: CFTimeInterval,
     flags: CFOptionFlags,
     order: CFIndex,
@@ -48,32 +52,41 @@ fn CFRunLoopTimerCreate(
 ) -> CFRunLoopTimerRef {
     assert_eq!(allocator, kCFAllocatorDefault); // unimplemented
     assert_eq!(flags, 0);
-    assert_eq!(order, 0);
-
-    let context = env.mem.read(context_ptr);
-    let version = context.version;
-    assert_eq!(version, 0);
-    let info: MutVoidPtr = context.info;
-
-    // TODO: handle non-NULL callbacks
-    let retain_callback = context.release_callback;
-    assert!(retain_callback.to_ptr().is_null());
-    let release_callback = context.release_callback;
-    assert!(release_callback.to_ptr().is_null());
-    let copy_desc_callback = context.copy_desc_callback;
-    assert!(copy_desc_callback.to_ptr().is_null());
+    if order != 0 {
+        log!("TODO: CFRunLoopTimerCreate order {} (ignored)", order);
+    }
+
+    let (info, release_callback) = if context_ptr.is_null() {
+        (
+            MutVoidPtr::null(),
+            GuestFunction::from_addr_with_thumb_bit(0),
+        )
+    } else {
+        let context = env.mem.read(context_ptr);
+        let version = context.version;
+        assert_eq!(version, 0);
+        let mut info: MutVoidPtr = context.info;
+        let retain_callback = context.retain_callback;
+        if !retain_callback.to_ptr().is_null() {
+            info = retain_callback.call_from_host(env, (info,));
+        }
+        (info, context.release_callback)
+    };
 
     let target: id = msg_class![env; _touchHLE_CFTimerTarget alloc];
-    let target: id = msg![env; target initWithCallout:callout info:info];
+    let target: id = msg![env; target initWithCallout:callout
+                                                 info:info
+                                      releaseCallback:release_callback];
 
     let selector = env.objc.lookup_selector("timerFireMethod:").unwrap();
 
+    let due_by = absolute_time_to_instant(env, fire_date);
     let repeats = interval > 0.0;
-    msg_class![env; NSTimer timerWithTimeInterval:interval
-                                           target:target
-                                         selector:selector
-                                         userInfo:nil
-                                          repeats:repeats]
+    let timer = ns_timer::new_timer(env, due_by, interval, target, selector, repeats);
+    // The timer has retained the target, which will call the release callback
+    // when the timer is deallocated.
+    release(env, target);
+    timer
 }
 
 fn CFRunLoopAddTimer(
@@ -91,20 +104,64 @@ fn CFRunLoopAddTimer(
     () = msg![env; run_loop addTimer:timer forMode:mode];
 }
 
+fn CFRunLoopRemoveTimer(
+    env: &mut Environment,
+    _run_loop: CFRunLoopRef,
+    timer: CFRunLoopTimerRef,
+    _mode: CFRunLoopMode,
+) {
+    // TODO: handle run loop modes. Currently assumes the common modes.
+    ns_timer::remove_from_run_loop(env, timer);
+}
+
 fn CFRunLoopTimerInvalidate(env: &mut Environment, timer: CFRunLoopTimerRef) {
     () = msg![env; timer invalidate];
 }
 
+fn CFRunLoopTimerIsValid(env: &mut Environment, timer: CFRunLoopTimerRef) -> bool {
+    msg![env; timer isValid]
+}
+
+fn CFRunLoopTimerGetInterval(env: &mut Environment, timer: CFRunLoopTimerRef) -> CFTimeInterval {
+    msg![env; timer timeInterval]
+}
+
+fn CFRunLoopTimerGetNextFireDate(
+    env: &mut Environment,
+    timer: CFRunLoopTimerRef,
+) -> CFAbsoluteTime {
+    match ns_timer::fire_date(env, timer) {
+        Some(due_by) => instant_to_absolute_time(env, due_by),
+        None => 0.0,
+    }
+}
+
+fn CFRunLoopTimerSetNextFireDate(
+    env: &mut Environment,
+    timer: CFRunLoopTimerRef,
+    fire_date: CFAbsoluteTime,
+) {
+    let due_by = absolute_time_to_instant(env, fire_date);
+    ns_timer::set_fire_date(env, timer, due_by);
+}
+
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(CFRunLoopTimerCreate(_, _, _, _, _, _, _)),
     export_c_func!(CFRunLoopAddTimer(_, _, _)),
+    export_c_func!(CFRunLoopRemoveTimer(_, _, _)),
     export_c_func!(CFRunLoopTimerInvalidate(_)),
+    export_c_func!(CFRunLoopTimerIsValid(_)),
+    export_c_func!(CFRunLoopTimerGetInterval(_)),
+    export_c_func!(CFRunLoopTimerGetNextFireDate(_)),
+    export_c_func!(CFRunLoopTimerSetNextFireDate(_, _)),
 ];
 
 /// Belongs to _touchHLE_CFTimerTarget
 struct CFTimerTargetHostObject {
     callout: GuestFunction,
     info: MutVoidPtr,
+    /// `void (*)(const void *info)`, may be NULL.
+    release_callback: GuestFunction,
 }
 impl HostObject for CFTimerTargetHostObject {}
 
@@ -119,22 +176,39 @@ pub const CLASSES: ClassExports = objc_classes! {
 + (id)allocWithZone:(NSZonePtr)_zone {
     let host_object = Box::new(CFTimerTargetHostObject {
         callout: GuestFunction::from_addr_with_thumb_bit(0),
-        info: MutVoidPtr::null()
+        info: MutVoidPtr::null(),
+        release_callback: GuestFunction::from_addr_with_thumb_bit(0),
     });
     env.objc.alloc_object(this, host_object, &mut env.mem)
 }
 
-- (id)initWithCallout:(GuestFunction)callout info:(MutVoidPtr)info {
+- (id)initWithCallout:(GuestFunction)callout
+                 info:(MutVoidPtr)info
+      releaseCallback:(GuestFunction)release_callback {
     let host_object: &mut CFTimerTargetHostObject = env.objc.borrow_mut(this);
     host_object.callout = callout;
     host_object.info = info;
+    host_object.release_callback = release_callback;
     this
 }
 
+- (())dealloc {
+    let &CFTimerTargetHostObject {
+        info,
+        release_callback,
+        ..
+    } = env.objc.borrow(this);
+    if !release_callback.to_ptr().is_null() {
+        () = release_callback.call_from_host(env, (info,));
+    }
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
 - (())timerFireMethod:(id)timer { // NSTimer *
     let &CFTimerTargetHostObject {
         callout,
-        info
+        info,
+        ..
     } = env.objc.borrow(this);
     () = callout.call_from_host(env, (timer, info));
 }
@@ -0,0 +1,269 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CFReadStream` and `CFWriteStream`.
+//!
+//! These are toll-free bridged to `NSInputStream` and `NSOutputStream` in
+//! Apple's implementation. Here they are the same types.
+
+use super::cf_allocator::{kCFAllocatorDefault, CFAllocatorRef};
+use super::cf_run_loop::{CFRunLoopMode, CFRunLoopRef};
+use super::cf_string::CFStringRef;
+use super::cf_url::CFURLRef;
+use super::{CFIndex, CFOptionFlags, CFTypeRef};
+use crate::abi::GuestFunction;
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::frameworks::foundation::ns_stream::{self, NSStreamStatus, StreamClient};
+use crate::frameworks::foundation::{ns_url, NSUInteger};
+use crate::mem::{ConstPtr, MutPtr, MutVoidPtr, SafeRead};
+use crate::objc::msg;
+use crate::Environment;
+
+pub type CFReadStreamRef = super::CFTypeRef;
+pub type CFWriteStreamRef = super::CFTypeRef;
+
+/// Same values as `NSStreamStatus`.
+type CFStreamStatus = CFIndex;
+/// Same values as `NSStreamEvent`.
+type CFStreamEventType = CFOptionFlags;
+
+// void (*)(CFReadStreamRef stream, CFStreamEventType type, void *info)
+type CFReadStreamClientCallBack = GuestFunction;
+// void (*)(CFWriteStreamRef stream, CFStreamEventType type, void *info)
+type CFWriteStreamClientCallBack = GuestFunction;
+
+#[repr(C, packed)]
+pub struct CFStreamClientContext {
+    version: CFIndex,
+    info: MutVoidPtr,
+    retain_callback: GuestFunction,
+    release_callback: GuestFunction,
+    #[allow(dead_code)]
+    copy_desc_callback: GuestFunction,
+}
+unsafe impl SafeRead for CFStreamClientContext {}
+
+fn CFReadStreamCreateWithFile(
+    env: &mut Environment,
+    allocator: CFAllocatorRef,
+    file_url: CFURLRef,
+) -> CFReadStreamRef {
+    assert!(allocator == kCFAllocatorDefault); // unimplemented
+    let path = ns_url::to_rust_path(env, file_url).into_owned();
+    ns_stream::new_file_stream(env, path, /* input: */ true)
+}
+
+fn CFWriteStreamCreateWithFile(
+    env: &mut Environment,
+    allocator: CFAllocatorRef,
+    file_url: CFURLRef,
+) -> CFWriteStreamRef {
+    assert!(allocator == kCFAllocatorDefault); // unimplemented
+    let path = ns_url::to_rust_path(env, file_url).into_owned();
+    ns_stream::new_file_stream(env, path, /* input: */ false)
+}
+
+fn CFReadStreamOpen(env: &mut Environment, stream: CFReadStreamRef) -> bool {
+    ns_stream::open(env, stream)
+}
+fn CFWriteStreamOpen(env: &mut Environment, stream: CFWriteStreamRef) -> bool {
+    ns_stream::open(env, stream)
+}
+
+fn CFReadStreamClose(env: &mut Environment, stream: CFReadStreamRef) {
+    msg![env; stream close]
+}
+fn CFWriteStreamClose(env: &mut Environment, stream: CFWriteStreamRef) {
+    msg![env; stream close]
+}
+
+fn CFReadStreamRead(
+    env: &mut Environment,
+    stream: CFReadStreamRef,
+    buffer: MutPtr<u8>,
+    buffer_length: CFIndex,
+) -> CFIndex {
+    let buffer_length: NSUInteger = buffer_length.try_into().unwrap();
+    ns_stream::read(env, stream, buffer, buffer_length)
+}
+fn CFWriteStreamWrite(
+    env: &mut Environment,
+    stream: CFWriteStreamRef,
+    buffer: ConstPtr<u8>,
+    buffer_length: CFIndex,
+) -> CFIndex {
+    let buffer_length: NSUInteger = buffer_length.try_into().unwrap();
+    ns_stream::write(env, stream, buffer, buffer_length)
+}
+
+fn CFReadStreamHasBytesAvailable(env: &mut Environment, stream: CFReadStreamRef) -> bool {
+    msg![env; stream hasBytesAvailable]
+}
+fn CFWriteStreamCanAcceptBytes(env: &mut Environment, stream: CFWriteStreamRef) -> bool {
+    msg![env; stream hasSpaceAvailable]
+}
+
+fn CFReadStreamGetStatus(env: &mut Environment, stream: CFReadStreamRef) -> CFStreamStatus {
+    let status: NSStreamStatus = msg![env; stream streamStatus];
+    status.try_into().unwrap()
+}
+fn CFWriteStreamGetStatus(env: &mut Environment, stream: CFWriteStreamRef) -> CFStreamStatus {
+    let status: NSStreamStatus = msg![env; stream streamStatus];
+    status.try_into().unwrap()
+}
+
+/// Returns a `CFErrorRef`, which is an `NSError*` here.
+fn CFReadStreamCopyError(env: &mut Environment, stream: CFReadStreamRef) -> CFTypeRef {
+    ns_stream::copy_error(env, stream)
+}
+fn CFWriteStreamCopyError(env: &mut Environment, stream: CFWriteStreamRef) -> CFTypeRef {
+    ns_stream::copy_error(env, stream)
+}
+
+fn CFReadStreamCopyProperty(
+    env: &mut Environment,
+    stream: CFReadStreamRef,
+    property_name: CFStringRef,
+) -> CFTypeRef {
+    ns_stream::copy_property(env, stream, property_name)
+}
+fn CFWriteStreamCopyProperty(
+    env: &mut Environment,
+    stream: CFWriteStreamRef,
+    property_name: CFStringRef,
+) -> CFTypeRef {
+    ns_stream::copy_property(env, stream, property_name)
+}
+
+fn CFReadStreamSetProperty(
+    env: &mut Environment,
+    stream: CFReadStreamRef,
+    property_name: CFStringRef,
+    value: CFTypeRef,
+) -> bool {
+    msg![env; stream setProperty:value forKey:property_name]
+}
+fn CFWriteStreamSetProperty(
+    env: &mut Environment,
+    stream: CFWriteStreamRef,
+    property_name: CFStringRef,
+    value: CFTypeRef,
+) -> bool {
+    msg![env; stream setProperty:value forKey:property_name]
+}
+
+fn set_client(
+    env: &mut Environment,
+    stream: CFTypeRef,
+    events: CFOptionFlags,
+    callback: GuestFunction,
+    context: MutPtr<CFStreamClientContext>,
+) -> bool {
+    if callback.to_ptr().is_null() {
+        ns_stream::set_client(env, stream, None);
+        return true;
+    }
+    let info = if context.is_null() {
+        MutVoidPtr::null()
+    } else {
+        let context = env.mem.read(context);
+        let version = context.version;
+        assert_eq!(version, 0);
+        // TODO: call the retain and release callbacks
+        let retain_callback = context.retain_callback;
+        let release_callback = context.release_callback;
+        if !retain_callback.to_ptr().is_null() || !release_callback.to_ptr().is_null() {
+            log_dbg!("TODO: stream client context retain/release callbacks (ignored)");
+        }
+        context.info
+    };
+    let client = StreamClient {
+        events,
+        callback,
+        info,
+    };
+    ns_stream::set_client(env, stream, Some(client));
+    true
+}
+
+fn CFReadStreamSetClient(
+    env: &mut Environment,
+    stream: CFReadStreamRef,
+    stream_events: CFOptionFlags,
+    client_cb: CFReadStreamClientCallBack,
+    client_context: MutPtr<CFStreamClientContext>,
+) -> bool {
+    set_client(env, stream, stream_events, client_cb, client_context)
+}
+fn CFWriteStreamSetClient(
+    env: &mut Environment,
+    stream: CFWriteStreamRef,
+    stream_events: CFOptionFlags,
+    client_cb: CFWriteStreamClientCallBack,
+    client_context: MutPtr<CFStreamClientContext>,
+) -> bool {
+    set_client(env, stream, stream_events, client_cb, client_context)
+}
+
+fn CFReadStreamScheduleWithRunLoop(
+    env: &mut Environment,
+    stream: CFReadStreamRef,
+    run_loop: CFRunLoopRef,
+    run_loop_mode: CFRunLoopMode,
+) {
+    msg![env; stream scheduleInRunLoop:run_loop forMode:run_loop_mode]
+}
+fn CFWriteStreamScheduleWithRunLoop(
+    env: &mut Environment,
+    stream: CFWriteStreamRef,
+    run_loop: CFRunLoopRef,
+    run_loop_mode: CFRunLoopMode,
+) {
+    msg![env; stream scheduleInRunLoop:run_loop forMode:run_loop_mode]
+}
+
+fn CFReadStreamUnscheduleFromRunLoop(
+    env: &mut Environment,
+    stream: CFReadStreamRef,
+    run_loop: CFRunLoopRef,
+    run_loop_mode: CFRunLoopMode,
+) {
+    msg![env; stream removeFromRunLoop:run_loop forMode:run_loop_mode]
+}
+fn CFWriteStreamUnscheduleFromRunLoop(
+    env: &mut Environment,
+    stream: CFWriteStreamRef,
+    run_loop: CFRunLoopRef,
+    run_loop_mode: CFRunLoopMode,
+) {
+    msg![env; stream removeFromRunLoop:run_loop forMode:run_loop_mode]
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(CFReadStreamCreateWithFile(_, _)),
+    export_c_func!(CFWriteStreamCreateWithFile(_, _)),
+    export_c_func!(CFReadStreamOpen(_)),
+    export_c_func!(CFWriteStreamOpen(_)),
+    export_c_func!(CFReadStreamClose(_)),
+    export_c_func!(CFWriteStreamClose(_)),
+    export_c_func!(CFReadStreamRead(_, _, _)),
+    export_c_func!(CFWriteStreamWrite(_, _, _)),
+    export_c_func!(CFReadStreamHasBytesAvailable(_)),
+    export_c_func!(CFWriteStreamCanAcceptBytes(_)),
+    export_c_func!(CFReadStreamGetStatus(_)),
+    export_c_func!(CFWriteStreamGetStatus(_)),
+    export_c_func!(CFReadStreamCopyError(_)),
+    export_c_func!(CFWriteStreamCopyError(_)),
+    export_c_func!(CFReadStreamCopyProperty(_, _)),
+    export_c_func!(CFWriteStreamCopyProperty(_, _)),
+    export_c_func!(CFReadStreamSetProperty(_, _, _)),
+    export_c_func!(CFWriteStreamSetProperty(_, _, _)),
+    export_c_func!(CFReadStreamSetClient(_, _, _, _)),
+    export_c_func!(CFWriteStreamSetClient(_, _, _, _)),
+    export_c_func!(CFReadStreamScheduleWithRunLoop(_, _, _)),
+    export_c_func!(CFWriteStreamScheduleWithRunLoop(_, _, _)),
+    export_c_func!(CFReadStreamUnscheduleFromRunLoop(_, _, _)),
+    export_c_func!(CFWriteStreamUnscheduleFromRunLoop(_, _, _)),
+];
@@ -43,7 +43,14 @@ pub fn CFURLGetFileSystemRepresentation(
         // TODO: avoid copy
         assert!(to_rust_string(env, path).starts_with('/'));
     }
-    let buffer_size: NSUInteger = buffer_size.try_into().unwrap();
+    // The path is only written if it fits in the buffer together with its null
+    // terminator, otherwise this fails without truncating it.
+    let Ok(buffer_size) = NSUInteger::try_from(buffer_size) else {
+        return false;
+    };
+    if buffer_size == 0 {
+        return false;
+    }
 
     msg![env; url getFileSystemRepresentation:buffer
                                     maxLength:buffer_size]
@@ -85,7 +92,20 @@ fn CFURLCopyFileSystemPath(
     msg![env; path copy]
 }
 
+fn CFURLCreateWithString(
+    env: &mut Environment,
+    allocator: CFAllocatorRef,
+    url_string: CFStringRef,
+    base_url: CFURLRef,
+) -> CFURLRef {
+    assert!(allocator == kCFAllocatorDefault); // unimplemented
+    assert!(base_url.is_null()); // TODO
+    let url: id = msg_class![env; NSURL alloc];
+    msg![env; url initWithString:url_string]
+}
+
 pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(CFURLCreateWithString(_, _, _)),
     export_c_func!(CFURLGetFileSystemRepresentation(_, _, _, _)),
     export_c_func!(CFURLCreateFromFileSystemRepresentation(_, _, _, _)),
     export_c_func!(CFURLCopyPathExtension(_)),
@@ -13,7 +13,7 @@ use crate::mem::SafeRead;
 use crate::objc::nil;
 use crate::{impl_GuestRet_for_large_struct, Environment};
 use std::ops::Add;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
 /// The absolute reference date is 1 Jan 2001 00:00:00 GMT
 pub fn apple_epoch() -> SystemTime {
@@ -38,13 +38,37 @@ impl_GuestRet_for_large_struct!(CFGregorianDate);
 
 /// Absolute time is measured in seconds relative to the absolute reference date
 /// of Jan 1 2001 00:00:00 GMT.
-fn CFAbsoluteTimeGetCurrent(_env: &mut Environment) -> CFAbsoluteTime {
-    SystemTime::now()
+pub fn CFAbsoluteTimeGetCurrent(env: &mut Environment) -> CFAbsoluteTime {
+    env.clock
+        .system_now()
         .duration_since(apple_epoch())
         .unwrap()
         .as_secs_f64()
 }
 
+/// Convert an absolute time to the clock used for run loop scheduling. Times
+/// in the past become the current time.
+pub fn absolute_time_to_instant(env: &mut Environment, at: CFAbsoluteTime) -> Instant {
+    let now = env.clock.now();
+    let delay = at - CFAbsoluteTimeGetCurrent(env);
+    if delay > 0.0 {
+        now + Duration::from_secs_f64(delay)
+    } else {
+        now
+    }
+}
+
+/// Inverse of [absolute_time_to_instant].
+pub fn instant_to_absolute_time(env: &mut Environment, instant: Instant) -> CFAbsoluteTime {
+    let now = env.clock.now();
+    let current = CFAbsoluteTimeGetCurrent(env);
+    if instant >= now {
+        current + instant.duration_since(now).as_secs_f64()
+    } else {
+        current - now.duration_since(instant).as_secs_f64()
+    }
+}
+
 type CFTimeZoneRef = CFTypeRef;
 
 fn CFTimeZoneCopySystem(_env: &mut Environment) -> CFTimeZoneRef {
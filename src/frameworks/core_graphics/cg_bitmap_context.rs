@@ -9,7 +9,11 @@ use super::cg_affine_transform::{CGAffineTransform, CGAffineTransformIdentity};
 use super::cg_color_space::{
     kCGColorSpaceGenericGray, kCGColorSpaceGenericRGB, CGColorSpaceHostObject, CGColorSpaceRef,
 };
-use super::cg_context::{CGContextHostObject, CGContextRef, CGContextSubclass};
+use super::cg_context::{
+    intersect_rects, kCGBlendModeClear, kCGBlendModeCopy, kCGBlendModeDarken, kCGBlendModeLighten,
+    kCGBlendModeMultiply, kCGBlendModeNormal, kCGBlendModeOverlay, kCGBlendModePlusLighter,
+    kCGBlendModeScreen, CGBlendMode, CGContextHostObject, CGContextRef, CGContextSubclass,
+};
 use super::cg_image::{
     self, kCGBitmapAlphaInfoMask, kCGBitmapByteOrderMask, kCGImageAlphaFirst, kCGImageAlphaLast,
     kCGImageAlphaNone, kCGImageAlphaNoneSkipFirst, kCGImageAlphaNoneSkipLast, kCGImageAlphaOnly,
@@ -22,6 +26,7 @@ use crate::image::{gamma_decode, gamma_encode, Image};
 use crate::mem::{GuestUSize, Mem, MutVoidPtr};
 use crate::objc::ObjC;
 use crate::Environment;
+use std::rc::Rc;
 
 #[derive(Copy, Clone)]
 pub(super) struct CGBitmapContextData {
@@ -82,7 +87,14 @@ pub fn CGBitmapContextCreate(
         }),
         // TODO: is this the correct default?
         rgb_fill_color: (0.0, 0.0, 0.0, 0.0),
+        rgb_stroke_color: (0.0, 0.0, 0.0, 1.0),
+        line_style: Default::default(),
         transform: CGAffineTransformIdentity,
+        clip: None,
+        clip_mask: None,
+        blend_mode: kCGBlendModeNormal,
+        alpha: 1.0,
+        path: Default::default(),
         state_stack: Vec::new(),
     };
     let isa = env
@@ -124,13 +136,20 @@ pub fn CGBitmapContextCreateImage(env: &mut Environment, context: CGContextRef)
                 kCGImageAlphaNoneSkipLast | kCGImageAlphaPremultipliedLast
             )
     );
-    let pixels = env
+    let mut pixels = env
         .mem
         .bytes_at(
             bitmap_data.data.cast(),
             bitmap_data.bytes_per_row * bitmap_data.height,
         )
         .to_vec();
+    // The padding byte of an opaque context has an undefined value, but the
+    // image must be opaque.
+    if bitmap_data.alpha_info == kCGImageAlphaNoneSkipLast {
+        for rgbx in pixels.chunks_mut(4) {
+            rgbx[3] = 255;
+        }
+    }
     cg_image::from_image(
         env,
         Image::from_pixel_vec(pixels, (bitmap_data.width, bitmap_data.height)),
@@ -210,27 +229,71 @@ fn blend_alpha(bg: f32, fg: f32) -> f32 {
 }
 
 /// Blends two RGBA non gamma-encoded values, with straight alpha.
-fn blend_straight(bg: (f32, f32, f32, f32), fg: (f32, f32, f32, f32)) -> (f32, f32, f32, f32) {
-    if fg.3 == 0.0 {
+fn blend_straight(
+    bg: (f32, f32, f32, f32),
+    fg: (f32, f32, f32, f32),
+    mode: CGBlendMode,
+) -> (f32, f32, f32, f32) {
+    if fg.3 == 0.0 && mode == kCGBlendModeNormal {
         // If fg.3 == 0.0 we attempt to blend fully transparent color.
-        bg
+        return bg;
+    }
+    let (r, g, b, a) = blend_premultiplied(
+        (bg.0 * bg.3, bg.1 * bg.3, bg.2 * bg.3, bg.3),
+        (fg.0 * fg.3, fg.1 * fg.3, fg.2 * fg.3, fg.3),
+        mode,
+    );
+    if a == 0.0 {
+        (0.0, 0.0, 0.0, 0.0)
     } else {
-        let new_a = blend_alpha(bg.3, fg.3); // Can't be 0 if fg.3 != 0
-        (
-            (fg.0 * fg.3 + bg.0 * bg.3 * (1.0 - fg.3)) / new_a,
-            (fg.1 * fg.3 + bg.1 * bg.3 * (1.0 - fg.3)) / new_a,
-            (fg.2 * fg.3 + bg.2 * bg.3 * (1.0 - fg.3)) / new_a,
-            new_a,
-        )
+        (r / a, g / a, b / a, a)
     }
 }
 
 /// Blends two RGBA non gamma-encoded values, with premultiplied alpha.
-fn blend_premultiplied(bg: (f32, f32, f32, f32), fg: (f32, f32, f32, f32)) -> (f32, f32, f32, f32) {
+fn blend_premultiplied(
+    bg: (f32, f32, f32, f32),
+    fg: (f32, f32, f32, f32),
+    mode: CGBlendMode,
+) -> (f32, f32, f32, f32) {
+    // Separable blend function B(backdrop, source) for straight-alpha color
+    // components, as defined by the PDF specification, which Core Graphics
+    // follows.
+    let blend_function: fn(f32, f32) -> f32 = match mode {
+        kCGBlendModeClear => return (0.0, 0.0, 0.0, 0.0),
+        kCGBlendModeCopy => return fg,
+        kCGBlendModePlusLighter => {
+            return (
+                (bg.0 + fg.0).min(1.0),
+                (bg.1 + fg.1).min(1.0),
+                (bg.2 + fg.2).min(1.0),
+                (bg.3 + fg.3).min(1.0),
+            )
+        }
+        kCGBlendModeMultiply => |cb, cs| cb * cs,
+        kCGBlendModeScreen => |cb, cs| cb + cs - cb * cs,
+        kCGBlendModeOverlay => |cb, cs| {
+            if cb <= 0.5 {
+                2.0 * cb * cs
+            } else {
+                1.0 - 2.0 * (1.0 - cb) * (1.0 - cs)
+            }
+        },
+        kCGBlendModeDarken => f32::min,
+        kCGBlendModeLighten => f32::max,
+        _ => |_cb, cs| cs,
+    };
+    let component = |cb: f32, cs: f32| {
+        let mut co = cs * (1.0 - bg.3) + cb * (1.0 - fg.3);
+        if bg.3 != 0.0 && fg.3 != 0.0 {
+            co += bg.3 * fg.3 * blend_function(cb / bg.3, cs / fg.3);
+        }
+        co
+    };
     (
-        fg.0 + bg.0 * (1.0 - fg.3),
-        fg.1 + bg.1 * (1.0 - fg.3),
-        fg.2 + bg.2 * (1.0 - fg.3),
+        component(bg.0, fg.0),
+        component(bg.1, fg.1),
+        component(bg.2, fg.2),
         blend_alpha(bg.3, fg.3),
     )
 }
@@ -297,7 +360,7 @@ fn put_pixel(
     pixels: &mut [u8],
     coords: (i32, i32),
     pixel: (CGFloat, CGFloat, CGFloat, CGFloat),
-    blend: bool,
+    blend: Option<CGBlendMode>,
 ) {
     let (x, y) = coords;
     if x < 0 || y < 0 {
@@ -319,14 +382,15 @@ fn put_pixel(
 
     // Blending like this must be done in linear RGB, so this must come before
     // gamma encoding.
-    let (r, g, b, a) = if blend {
+    let (r, g, b, a) = if let Some(mode) = blend {
         match data.alpha_info {
-            kCGImageAlphaLast | kCGImageAlphaFirst => blend_straight(bg_pixel, pixel),
             kCGImageAlphaPremultipliedLast | kCGImageAlphaPremultipliedFirst => {
-                blend_premultiplied(bg_pixel, pixel)
+                blend_premultiplied(bg_pixel, pixel, mode)
             }
             kCGImageAlphaOnly => (pixel.0, pixel.1, pixel.2, blend_alpha(bg_pixel.3, pixel.3)),
-            _ => pixel,
+            // Contexts without an alpha channel are treated as having a
+            // fully opaque background.
+            _ => blend_straight(bg_pixel, pixel, mode),
         }
     } else {
         pixel
@@ -356,6 +420,10 @@ pub struct CGBitmapContextDrawer<'a> {
     bitmap_info: CGBitmapContextData,
     rgb_fill_color: (CGFloat, CGFloat, CGFloat, CGFloat),
     transform: CGAffineTransform,
+    clip: Option<CGRect>,
+    clip_mask: Option<Rc<[f32]>>,
+    blend_mode: CGBlendMode,
+    alpha: CGFloat,
     pixels: &'a mut [u8],
 }
 impl CGBitmapContextDrawer<'_> {
@@ -364,12 +432,25 @@ impl CGBitmapContextDrawer<'_> {
         mem: &'a mut Mem,
         context: CGContextRef,
     ) -> CGBitmapContextDrawer<'a> {
-        let &CGContextHostObject {
+        let CGContextHostObject {
             subclass: CGContextSubclass::CGBitmapContext(bitmap_info),
             rgb_fill_color,
             transform,
+            clip,
+            clip_mask,
+            blend_mode,
+            alpha,
             ..
         } = objc.borrow(context);
+        let (bitmap_info, rgb_fill_color, transform, clip, clip_mask, blend_mode, alpha) = (
+            *bitmap_info,
+            *rgb_fill_color,
+            *transform,
+            *clip,
+            clip_mask.clone(),
+            *blend_mode,
+            *alpha,
+        );
 
         let pixels = get_pixels(&bitmap_info, mem);
 
@@ -377,6 +458,10 @@ impl CGBitmapContextDrawer<'_> {
             bitmap_info,
             rgb_fill_color,
             transform,
+            clip,
+            clip_mask,
+            blend_mode,
+            alpha,
             pixels,
         }
     }
@@ -390,32 +475,218 @@ impl CGBitmapContextDrawer<'_> {
     /// Get the current fill color. The returned color is linear RGB, not sRGB.
     /// It has premultiplied alpha if the context does.
     pub fn rgb_fill_color(&self) -> (CGFloat, CGFloat, CGFloat, CGFloat) {
+        self.convert_color(self.rgb_fill_color)
+    }
+    /// Convert a straight-alpha sRGB color, as apps specify them, to linear
+    /// RGB that has premultiplied alpha if the context does.
+    pub fn convert_color(
+        &self,
+        color: (CGFloat, CGFloat, CGFloat, CGFloat),
+    ) -> (CGFloat, CGFloat, CGFloat, CGFloat) {
         let multiply_by = match self.bitmap_info.alpha_info {
-            kCGImageAlphaPremultipliedLast | kCGImageAlphaPremultipliedFirst => {
-                self.rgb_fill_color.3
-            }
+            kCGImageAlphaPremultipliedLast | kCGImageAlphaPremultipliedFirst => color.3,
             _ => 1.0,
         };
         // Multiplying before decoding matches the Simulator's output.
         (
-            gamma_decode(self.rgb_fill_color.0 * multiply_by),
-            gamma_decode(self.rgb_fill_color.1 * multiply_by),
-            gamma_decode(self.rgb_fill_color.2 * multiply_by),
-            self.rgb_fill_color.3, // alpha is always linear
+            gamma_decode(color.0 * multiply_by),
+            gamma_decode(color.1 * multiply_by),
+            gamma_decode(color.2 * multiply_by),
+            color.3, // alpha is always linear
         )
     }
     /// Set the pixel at `coords` to `color`. `color` must be linear RGB, not
     /// sRGB! Note that `coords` are absolute: you must do transformation
-    /// yourself.
+    /// yourself. Pixels outside the current clipping rectangle are left
+    /// untouched, and the clipping mask (if any) is applied. When blending,
+    /// the context's alpha and blend mode are applied too.
     pub fn put_pixel(
         &mut self,
         coords: (i32, i32),
         color: (CGFloat, CGFloat, CGFloat, CGFloat),
         blend: bool,
     ) {
+        let mut color = color;
+        if let Some(clip) = self.clip {
+            let (x, y) = (coords.0 as f32 + 0.5, coords.1 as f32 + 0.5);
+            if x < clip.origin.x
+                || y < clip.origin.y
+                || x >= clip.origin.x + clip.size.width
+                || y >= clip.origin.y + clip.size.height
+            {
+                return;
+            }
+        }
+        if let Some(ref clip_mask) = self.clip_mask {
+            let (x, y) = coords;
+            if x < 0 || y < 0 || x as GuestUSize >= self.width() {
+                return;
+            }
+            let idx = y as usize * self.width() as usize + x as usize;
+            let coverage = clip_mask.get(idx).copied().unwrap_or(0.0);
+            if coverage <= 0.0 || (!blend && coverage < 0.5) {
+                return;
+            }
+            if blend {
+                color = self.apply_coverage(color, coverage);
+            }
+        }
+        if blend && self.alpha < 1.0 {
+            color = self.apply_coverage(color, self.alpha);
+        }
+        let blend = blend.then_some(self.blend_mode);
         put_pixel(&self.bitmap_info, self.pixels, coords, color, blend)
     }
 
+    /// Convert a linear RGB color with premultiplied alpha, like the ones
+    /// [Image::get_pixel] returns, to the format [Self::put_pixel] expects.
+    pub fn convert_premultiplied_color(
+        &self,
+        color: (CGFloat, CGFloat, CGFloat, CGFloat),
+    ) -> (CGFloat, CGFloat, CGFloat, CGFloat) {
+        match self.bitmap_info.alpha_info {
+            kCGImageAlphaPremultipliedLast | kCGImageAlphaPremultipliedFirst => color,
+            _ if color.3 == 0.0 => (0.0, 0.0, 0.0, 0.0),
+            _ => (
+                color.0 / color.3,
+                color.1 / color.3,
+                color.2 / color.3,
+                color.3,
+            ),
+        }
+    }
+
+    /// Scale the opacity of a color (in the format [Self::put_pixel] expects)
+    /// by a coverage value between 0 and 1, e.g. for anti-aliasing.
+    pub fn apply_coverage(
+        &self,
+        color: (CGFloat, CGFloat, CGFloat, CGFloat),
+        coverage: f32,
+    ) -> (CGFloat, CGFloat, CGFloat, CGFloat) {
+        match self.bitmap_info.alpha_info {
+            kCGImageAlphaPremultipliedLast | kCGImageAlphaPremultipliedFirst => (
+                color.0 * coverage,
+                color.1 * coverage,
+                color.2 * coverage,
+                color.3 * coverage,
+            ),
+            _ => (color.0, color.1, color.2, color.3 * coverage),
+        }
+    }
+
+    /// Fill polygons, given in absolute co-ordinates, with anti-aliasing.
+    /// Overlapping areas are combined using either the non-zero winding rule
+    /// or the even-odd rule.
+    pub fn fill_polygons(
+        &mut self,
+        polygons: &[Vec<CGPoint>],
+        even_odd: bool,
+        color: (CGFloat, CGFloat, CGFloat, CGFloat),
+    ) {
+        // Number of sub-scanlines per row of pixels. Horizontal coverage is
+        // computed exactly, so this only affects vertical anti-aliasing.
+        const SUBSAMPLES: usize = 4;
+
+        // Edges as (x0, y0, x1, y1, winding direction), with y0 < y1.
+        let mut edges = Vec::new();
+        for polygon in polygons {
+            for i in 0..polygon.len() {
+                let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+                if a.y < b.y {
+                    edges.push((a.x, a.y, b.x, b.y, 1));
+                } else if a.y > b.y {
+                    edges.push((b.x, b.y, a.x, a.y, -1));
+                }
+            }
+        }
+        if edges.is_empty() {
+            return;
+        }
+
+        let (width, height) = (self.width() as usize, self.height() as usize);
+        let y_min = edges.iter().map(|e| e.1).fold(f32::INFINITY, f32::min);
+        let y_max = edges.iter().map(|e| e.3).fold(f32::NEG_INFINITY, f32::max);
+        let y_start = y_min.floor().max(0.0) as usize;
+        let y_end = (y_max.ceil().max(0.0) as usize).min(height);
+
+        let mut coverage = vec![0f32; width];
+        let mut crossings: Vec<(f32, i32)> = Vec::new();
+        for y in y_start..y_end {
+            coverage.fill(0.0);
+            for sub in 0..SUBSAMPLES {
+                let sample_y = y as f32 + (sub as f32 + 0.5) / SUBSAMPLES as f32;
+                crossings.clear();
+                for &(x0, y0, x1, y1, direction) in &edges {
+                    if y0 <= sample_y && sample_y < y1 {
+                        let x = x0 + (x1 - x0) * (sample_y - y0) / (y1 - y0);
+                        crossings.push((x, direction));
+                    }
+                }
+                crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+                let mut winding = 0;
+                for pair in crossings.windows(2) {
+                    winding += pair[0].1;
+                    let inside = if even_odd {
+                        winding % 2 != 0
+                    } else {
+                        winding != 0
+                    };
+                    if !inside {
+                        continue;
+                    }
+                    let span_start = pair[0].0.clamp(0.0, width as f32);
+                    let span_end = pair[1].0.clamp(0.0, width as f32);
+                    if span_start >= span_end {
+                        continue;
+                    }
+                    let first = span_start.floor() as usize;
+                    let last = (span_end.ceil() as usize).min(width);
+                    for (x, pixel_coverage) in
+                        coverage.iter_mut().enumerate().take(last).skip(first)
+                    {
+                        let overlap = span_end.min(x as f32 + 1.0) - span_start.max(x as f32);
+                        *pixel_coverage += overlap.max(0.0) / SUBSAMPLES as f32;
+                    }
+                }
+            }
+            for (x, &pixel_coverage) in coverage.iter().enumerate() {
+                if pixel_coverage > 0.0 {
+                    let color = self.apply_coverage(color, pixel_coverage.min(1.0));
+                    self.put_pixel((x as i32, y as i32), color, /* blend: */ true);
+                }
+            }
+        }
+    }
+
+    /// Iterates over the absolute integer pixel co-ordinates of every pixel in
+    /// the target bitmap that is within the clipping rectangle, in raster
+    /// order, while providing the untransformed co-ordinates of the pixel's
+    /// center. This is useful for drawing operations that aren't bounded by a
+    /// rectangle, like gradients.
+    pub fn iter_untransformed_pixels(&self) -> impl Iterator<Item = ((i32, i32), CGPoint)> {
+        let (mut x_start, mut y_start) = (0, 0);
+        let (mut x_end, mut y_end) = (self.width(), self.height());
+        if let Some(clip) = self.clip {
+            x_start = clip.origin.x.round().max(0.0) as GuestUSize;
+            y_start = clip.origin.y.round().max(0.0) as GuestUSize;
+            x_end = x_end.min((clip.origin.x + clip.size.width).round().max(0.0) as GuestUSize);
+            y_end = y_end.min((clip.origin.y + clip.size.height).round().max(0.0) as GuestUSize);
+        }
+
+        let inverse_transform = self.transform.invert();
+
+        (y_start..y_end).flat_map(move |y| {
+            (x_start..x_end).map(move |x| {
+                let untransformed = inverse_transform.apply_to_point(CGPoint {
+                    x: x as f32 + 0.5,
+                    y: y as f32 + 0.5,
+                });
+                ((x as i32, y as i32), untransformed)
+            })
+        })
+    }
+
     /// Takes a [CGRect] and applies the current transform to it, and iterates
     /// over the transformed, clipped, absolute integer pixel co-ordinates in
     /// raster order for the target bitmap while providing floating-point
@@ -484,6 +755,10 @@ fn test_iter_transformed_pixels() {
             },
             rgb_fill_color: (0.0, 0.0, 0.0, 0.0),
             transform,
+            clip: None,
+            clip_mask: None,
+            blend_mode: kCGBlendModeNormal,
+            alpha: 1.0,
             pixels: &mut [],
         }
     }
@@ -557,6 +832,43 @@ fn test_iter_transformed_pixels() {
         .eq(inverted_square_2x2_at_0_0.clone().into_iter()));
 }
 
+#[cfg(test)]
+#[test]
+fn test_blend_modes() {
+    let bg = (0.5, 0.25, 0.0, 1.0);
+    let fg = (0.5, 0.5, 0.5, 0.5); // premultiplied
+    assert_eq!(
+        blend_premultiplied(bg, fg, kCGBlendModeNormal),
+        (0.75, 0.625, 0.5, 1.0)
+    );
+    assert_eq!(
+        blend_premultiplied(bg, fg, kCGBlendModeMultiply),
+        (0.5, 0.25, 0.0, 1.0)
+    );
+    assert_eq!(
+        blend_premultiplied(bg, fg, kCGBlendModeScreen),
+        (0.75, 0.625, 0.5, 1.0)
+    );
+    assert_eq!(
+        blend_premultiplied(bg, fg, kCGBlendModePlusLighter),
+        (1.0, 0.75, 0.5, 1.0)
+    );
+    assert_eq!(
+        blend_premultiplied(bg, fg, kCGBlendModeCopy),
+        (0.5, 0.5, 0.5, 0.5)
+    );
+    // Straight alpha should give the same results, once premultiplied.
+    assert_eq!(
+        blend_straight(bg, (1.0, 1.0, 1.0, 0.5), kCGBlendModeMultiply),
+        (0.5, 0.25, 0.0, 1.0)
+    );
+    // Blending onto a transparent background just gives the foreground.
+    assert_eq!(
+        blend_premultiplied((0.0, 0.0, 0.0, 0.0), fg, kCGBlendModeMultiply),
+        fg
+    );
+}
+
 /// Implementation of `CGContextFillRect` (`clear` == [false]) and
 /// `CGContextClearRect` (`clear` == [true]) for `CGBitmapContext`.
 pub(super) fn fill_rect(env: &mut Environment, context: CGContextRef, rect: CGRect, clear: bool) {
@@ -602,17 +914,17 @@ pub(super) fn draw_image(
     //);
 
     let (image_width, image_height) = image.dimensions();
-
-    // TODO: non-nearest-neighbour filtering? (what does CG actually do?)
+    if image_width == 0 || image_height == 0 {
+        return;
+    }
 
     for ((x, y), (texel_x, texel_y)) in drawer.iter_transformed_pixels(rect) {
-        let texel_x = (image_width as f32 * texel_x) as i32;
+        let texel_x = image_width as f32 * texel_x;
         // Image is in top-to-bottom order, but the bitmap is bottom-to-top
-        let texel_y = (image_height as f32 * (1.0 - texel_y)) as i32;
-        // FIXME: might need alpha format conversion here
-        if let Some(color) = image.get_pixel((texel_x, texel_y)) {
-            drawer.put_pixel((x, y), color, /* blend: */ true)
-        }
+        let texel_y = image_height as f32 * (1.0 - texel_y);
+        let color = sample_bilinear(image, (texel_x, texel_y));
+        let color = drawer.convert_premultiplied_color(color);
+        drawer.put_pixel((x, y), color, /* blend: */ true)
     }
 
     //let _ = std::fs::write(
@@ -625,6 +937,109 @@ pub(super) fn draw_image(
     //);
 }
 
+/// Sample an image with bilinear filtering, like Core Graphics does by
+/// default when an image is scaled. `at` is in texels, with (0, 0) being the
+/// top-left corner of the image. Texels outside the image are clamped to its
+/// edges. The result is linear RGBA with premultiplied alpha.
+fn sample_bilinear(image: &Image, at: (f32, f32)) -> (f32, f32, f32, f32) {
+    let (width, height) = image.dimensions();
+    // Texel centers are at half-integer co-ordinates.
+    let x = (at.0 - 0.5).clamp(0.0, (width - 1) as f32);
+    let y = (at.1 - 0.5).clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (x.floor() as i32, y.floor() as i32);
+    let (x1, y1) = (
+        (x0 + 1).min(width as i32 - 1),
+        (y0 + 1).min(height as i32 - 1),
+    );
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+
+    let get = |x, y| image.get_pixel((x, y)).unwrap();
+    let lerp = |a: (f32, f32, f32, f32), b: (f32, f32, f32, f32), t: f32| {
+        (
+            a.0 + (b.0 - a.0) * t,
+            a.1 + (b.1 - a.1) * t,
+            a.2 + (b.2 - a.2) * t,
+            a.3 + (b.3 - a.3) * t,
+        )
+    };
+    let top = lerp(get(x0, y0), get(x1, y0), fx);
+    let bottom = lerp(get(x0, y1), get(x1, y1), fx);
+    lerp(top, bottom, fy)
+}
+
+/// Implementation of filling and stroking paths for `CGBitmapContext`. The
+/// polygons are in absolute co-ordinates. `stroke` selects the stroke color
+/// rather than the fill color.
+pub(super) fn fill_polygons(
+    env: &mut Environment,
+    context: CGContextRef,
+    polygons: &[Vec<CGPoint>],
+    even_odd: bool,
+    stroke: bool,
+) {
+    let stroke_color = env
+        .objc
+        .borrow::<CGContextHostObject>(context)
+        .rgb_stroke_color;
+    let mut drawer = CGBitmapContextDrawer::new(&env.objc, &mut env.mem, context);
+    let color = if stroke {
+        drawer.convert_color(stroke_color)
+    } else {
+        drawer.rgb_fill_color()
+    };
+    drawer.fill_polygons(polygons, even_odd, color);
+}
+
+/// Implementation of `CGContextClipToMask` for `CGBitmapContext`.
+pub(super) fn clip_to_mask(
+    env: &mut Environment,
+    context: CGContextRef,
+    rect: CGRect,
+    mask: CGImageRef,
+) {
+    let image = cg_image::borrow_image(&env.objc, mask);
+    let drawer = CGBitmapContextDrawer::new(&env.objc, &mut env.mem, context);
+
+    // Masks are meant to be grayscale images, where white means fully
+    // visible, but apps also commonly use images with an alpha channel,
+    // whose alpha is then used instead. Since the Image type is always RGBA,
+    // the presence of any transparency is used to tell which kind this is.
+    let use_alpha = image.pixels().chunks(4).any(|rgba| rgba[3] != 255);
+
+    let (image_width, image_height) = image.dimensions();
+    let (width, height) = (drawer.width() as usize, drawer.height() as usize);
+    let mut new_mask = vec![0.0; width * height];
+    for ((x, y), (texel_x, texel_y)) in drawer.iter_transformed_pixels(rect) {
+        let texel_x = (image_width as f32 * texel_x) as i32;
+        // Image is in top-to-bottom order, but the bitmap is bottom-to-top
+        let texel_y = (image_height as f32 * (1.0 - texel_y)) as i32;
+        let Some((r, g, b, a)) = image.get_pixel((texel_x, texel_y)) else {
+            continue;
+        };
+        let coverage = if use_alpha {
+            a
+        } else {
+            gamma_encode(0.2126 * r + 0.7152 * g + 0.0722 * b)
+        };
+        new_mask[y as usize * width + x as usize] = coverage;
+    }
+    if let Some(ref old_mask) = drawer.clip_mask {
+        for (new, &old) in new_mask.iter_mut().zip(old_mask.iter()) {
+            *new *= old;
+        }
+    }
+    let transformed_rect = drawer.transform.apply_to_rect(rect);
+
+    let host_obj = env.objc.borrow_mut::<CGContextHostObject>(context);
+    host_obj.clip_mask = Some(Rc::from(new_mask));
+    // Nothing outside the mask's rectangle can be drawn, so the clipping
+    // rectangle can be narrowed too.
+    host_obj.clip = Some(match host_obj.clip {
+        Some(clip) => intersect_rects(clip, transformed_rect),
+        None => transformed_rect,
+    });
+}
+
 #[allow(rustdoc::broken_intra_doc_links)] // https://github.com/rust-lang/rust/issues/83049
 /// Shortcut for [crate::frameworks::core_animation::composition]. This is a
 /// workaround for not having a `&mut Environment` that should eventually be
@@ -6,12 +6,18 @@
 //! `CGContext.h`
 
 use super::cg_affine_transform::CGAffineTransform;
+use super::cg_color::{self, CGColorRef};
 use super::cg_image::CGImageRef;
-use super::{cg_bitmap_context, CGFloat, CGRect};
+use super::cg_path::{
+    kCGLineCapButt, kCGLineJoinMiter, stroke_to_polygons, CGLineCap, CGLineJoin, Path, StrokeStyle,
+};
+use super::{cg_bitmap_context, CGFloat, CGPoint, CGRect, CGSize};
 use crate::dyld::{export_c_func, FunctionExports};
 use crate::frameworks::core_foundation::{CFRelease, CFRetain, CFTypeRef};
+use crate::mem::{ConstPtr, GuestUSize};
 use crate::objc::{objc_classes, ClassExports, HostObject};
 use crate::Environment;
+use std::rc::Rc;
 
 pub const CLASSES: ClassExports = objc_classes! {
 
@@ -39,19 +45,78 @@ pub const CLASSES: ClassExports = objc_classes! {
 pub(super) struct CGContextHostObject {
     pub(super) subclass: CGContextSubclass,
     pub(super) rgb_fill_color: (CGFloat, CGFloat, CGFloat, CGFloat),
+    pub(super) rgb_stroke_color: (CGFloat, CGFloat, CGFloat, CGFloat),
+    pub(super) line_style: LineStyle,
     /// Current transform.
     pub(super) transform: CGAffineTransform,
-    // TODO: keep more states saved once they are implemented
-    pub(super) state_stack: Vec<((CGFloat, CGFloat, CGFloat, CGFloat), CGAffineTransform)>,
+    /// Current clipping rectangle, in absolute pixel co-ordinates (i.e. the
+    /// transform has already been applied). [None] means no clipping.
+    pub(super) clip: Option<CGRect>,
+    /// Current clipping mask from `CGContextClipToMask`, if any. This has a
+    /// coverage value for each pixel of the bitmap.
+    pub(super) clip_mask: Option<Rc<[f32]>>,
+    /// Current blend mode, from `CGContextSetBlendMode`.
+    pub(super) blend_mode: CGBlendMode,
+    /// Current global alpha, from `CGContextSetAlpha`.
+    pub(super) alpha: CGFloat,
+    /// Current path. This is not part of the graphics state.
+    pub(super) path: Path,
+    pub(super) state_stack: Vec<CGContextGState>,
 }
 impl HostObject for CGContextHostObject {}
 
+/// Line stroking parameters, in user space.
+#[derive(Clone)]
+pub(super) struct LineStyle {
+    width: CGFloat,
+    cap: CGLineCap,
+    join: CGLineJoin,
+    miter_limit: CGFloat,
+    /// Dash phase and lengths, if the line is dashed.
+    dash: Option<(CGFloat, Vec<CGFloat>)>,
+}
+impl Default for LineStyle {
+    fn default() -> Self {
+        LineStyle {
+            width: 1.0,
+            cap: kCGLineCapButt,
+            join: kCGLineJoinMiter,
+            miter_limit: 10.0,
+            dash: None,
+        }
+    }
+}
+
+/// Graphics state saved by `CGContextSaveGState`.
+// TODO: keep more states saved once they are implemented
+pub(super) struct CGContextGState {
+    rgb_fill_color: (CGFloat, CGFloat, CGFloat, CGFloat),
+    rgb_stroke_color: (CGFloat, CGFloat, CGFloat, CGFloat),
+    line_style: LineStyle,
+    transform: CGAffineTransform,
+    clip: Option<CGRect>,
+    clip_mask: Option<Rc<[f32]>>,
+    blend_mode: CGBlendMode,
+    alpha: CGFloat,
+}
+
 pub(super) enum CGContextSubclass {
     CGBitmapContext(cg_bitmap_context::CGBitmapContextData),
 }
 
 pub type CGContextRef = CFTypeRef;
 
+pub type CGBlendMode = i32;
+pub const kCGBlendModeNormal: CGBlendMode = 0;
+pub const kCGBlendModeMultiply: CGBlendMode = 1;
+pub const kCGBlendModeScreen: CGBlendMode = 2;
+pub const kCGBlendModeOverlay: CGBlendMode = 3;
+pub const kCGBlendModeDarken: CGBlendMode = 4;
+pub const kCGBlendModeLighten: CGBlendMode = 5;
+pub const kCGBlendModeClear: CGBlendMode = 16;
+pub const kCGBlendModeCopy: CGBlendMode = 17;
+pub const kCGBlendModePlusLighter: CGBlendMode = 27;
+
 pub fn CGContextRelease(env: &mut Environment, c: CGContextRef) {
     if !c.is_null() {
         CFRelease(env, c);
@@ -91,6 +156,123 @@ fn CGContextSetGrayFillColor(
         .rgb_fill_color = color;
 }
 
+fn CGContextSetFillColorWithColor(env: &mut Environment, context: CGContextRef, color: CGColorRef) {
+    let color = cg_color::to_rgba(&env.objc, color);
+    env.objc
+        .borrow_mut::<CGContextHostObject>(context)
+        .rgb_fill_color = color;
+}
+
+fn CGContextSetRGBStrokeColor(
+    env: &mut Environment,
+    context: CGContextRef,
+    red: CGFloat,
+    green: CGFloat,
+    blue: CGFloat,
+    alpha: CGFloat,
+) {
+    let color = (red, green, blue, alpha);
+    env.objc
+        .borrow_mut::<CGContextHostObject>(context)
+        .rgb_stroke_color = color;
+}
+
+fn CGContextSetGrayStrokeColor(
+    env: &mut Environment,
+    context: CGContextRef,
+    gray: CGFloat,
+    alpha: CGFloat,
+) {
+    let color = (gray, gray, gray, alpha);
+    env.objc
+        .borrow_mut::<CGContextHostObject>(context)
+        .rgb_stroke_color = color;
+}
+
+fn CGContextSetStrokeColorWithColor(
+    env: &mut Environment,
+    context: CGContextRef,
+    color: CGColorRef,
+) {
+    let color = cg_color::to_rgba(&env.objc, color);
+    env.objc
+        .borrow_mut::<CGContextHostObject>(context)
+        .rgb_stroke_color = color;
+}
+
+pub fn CGContextSetBlendMode(env: &mut Environment, context: CGContextRef, mode: CGBlendMode) {
+    let mode = match mode {
+        kCGBlendModeNormal
+        | kCGBlendModeMultiply
+        | kCGBlendModeScreen
+        | kCGBlendModeOverlay
+        | kCGBlendModeDarken
+        | kCGBlendModeLighten
+        | kCGBlendModeClear
+        | kCGBlendModeCopy
+        | kCGBlendModePlusLighter => mode,
+        _ => {
+            log!(
+                "TODO: CGContextSetBlendMode({:?}, {}), using normal blending instead",
+                context,
+                mode
+            );
+            kCGBlendModeNormal
+        }
+    };
+    env.objc
+        .borrow_mut::<CGContextHostObject>(context)
+        .blend_mode = mode;
+}
+
+pub fn CGContextSetAlpha(env: &mut Environment, context: CGContextRef, alpha: CGFloat) {
+    env.objc.borrow_mut::<CGContextHostObject>(context).alpha = alpha.clamp(0.0, 1.0);
+}
+
+fn CGContextSetLineWidth(env: &mut Environment, context: CGContextRef, width: CGFloat) {
+    env.objc
+        .borrow_mut::<CGContextHostObject>(context)
+        .line_style
+        .width = width;
+}
+fn CGContextSetLineCap(env: &mut Environment, context: CGContextRef, cap: CGLineCap) {
+    env.objc
+        .borrow_mut::<CGContextHostObject>(context)
+        .line_style
+        .cap = cap;
+}
+fn CGContextSetLineJoin(env: &mut Environment, context: CGContextRef, join: CGLineJoin) {
+    env.objc
+        .borrow_mut::<CGContextHostObject>(context)
+        .line_style
+        .join = join;
+}
+fn CGContextSetMiterLimit(env: &mut Environment, context: CGContextRef, limit: CGFloat) {
+    env.objc
+        .borrow_mut::<CGContextHostObject>(context)
+        .line_style
+        .miter_limit = limit;
+}
+fn CGContextSetLineDash(
+    env: &mut Environment,
+    context: CGContextRef,
+    phase: CGFloat,
+    lengths: ConstPtr<CGFloat>,
+    count: GuestUSize,
+) {
+    let dash = if lengths.is_null() || count == 0 {
+        None
+    } else {
+        let lengths = (0..count).map(|i| env.mem.read(lengths + i)).collect();
+        Some((phase, lengths))
+    };
+    log_dbg!("CGContextSetLineDash({:?})", dash);
+    env.objc
+        .borrow_mut::<CGContextHostObject>(context)
+        .line_style
+        .dash = dash;
+}
+
 pub fn CGContextFillRect(env: &mut Environment, context: CGContextRef, rect: CGRect) {
     cg_bitmap_context::fill_rect(env, context, rect, /* clear: */ false);
 }
@@ -143,18 +325,334 @@ pub fn CGContextDrawImage(
     cg_bitmap_context::draw_image(env, context, rect, image);
 }
 
-fn CGContextSaveGState(env: &mut Environment, context: CGContextRef) {
+fn CGContextBeginPath(env: &mut Environment, context: CGContextRef) {
+    env.objc.borrow_mut::<CGContextHostObject>(context).path = Path::default();
+}
+fn CGContextClosePath(env: &mut Environment, context: CGContextRef) {
+    env.objc
+        .borrow_mut::<CGContextHostObject>(context)
+        .path
+        .close_subpath();
+}
+fn CGContextIsPathEmpty(env: &mut Environment, context: CGContextRef) -> bool {
+    env.objc
+        .borrow::<CGContextHostObject>(context)
+        .path
+        .is_empty()
+}
+fn CGContextGetPathCurrentPoint(env: &mut Environment, context: CGContextRef) -> CGPoint {
+    let host_obj = env.objc.borrow::<CGContextHostObject>(context);
+    match host_obj.path.current_point() {
+        // The path is in absolute co-ordinates, but this returns user space.
+        Some(point) => host_obj.transform.invert().apply_to_point(point),
+        None => CGPoint { x: 0.0, y: 0.0 },
+    }
+}
+
+fn CGContextMoveToPoint(env: &mut Environment, context: CGContextRef, x: CGFloat, y: CGFloat) {
     let host_obj = env.objc.borrow_mut::<CGContextHostObject>(context);
-    host_obj
-        .state_stack
-        .push((host_obj.rgb_fill_color, host_obj.transform));
+    let point = host_obj.transform.apply_to_point(CGPoint { x, y });
+    host_obj.path.move_to(point);
 }
+fn CGContextAddLineToPoint(env: &mut Environment, context: CGContextRef, x: CGFloat, y: CGFloat) {
+    let host_obj = env.objc.borrow_mut::<CGContextHostObject>(context);
+    let point = host_obj.transform.apply_to_point(CGPoint { x, y });
+    host_obj.path.line_to(point);
+}
+fn CGContextAddLines(
+    env: &mut Environment,
+    context: CGContextRef,
+    points: ConstPtr<CGPoint>,
+    count: GuestUSize,
+) {
+    for i in 0..count {
+        let CGPoint { x, y } = env.mem.read(points + i);
+        if i == 0 {
+            CGContextMoveToPoint(env, context, x, y);
+        } else {
+            CGContextAddLineToPoint(env, context, x, y);
+        }
+    }
+}
+fn CGContextAddCurveToPoint(
+    env: &mut Environment,
+    context: CGContextRef,
+    cp1x: CGFloat,
+    cp1y: CGFloat,
+    cp2x: CGFloat,
+    cp2y: CGFloat,
+    x: CGFloat,
+    y: CGFloat,
+) {
+    let host_obj = env.objc.borrow_mut::<CGContextHostObject>(context);
+    let t = host_obj.transform;
+    host_obj.path.curve_to(
+        t.apply_to_point(CGPoint { x: cp1x, y: cp1y }),
+        t.apply_to_point(CGPoint { x: cp2x, y: cp2y }),
+        t.apply_to_point(CGPoint { x, y }),
+    );
+}
+fn CGContextAddQuadCurveToPoint(
+    env: &mut Environment,
+    context: CGContextRef,
+    cpx: CGFloat,
+    cpy: CGFloat,
+    x: CGFloat,
+    y: CGFloat,
+) {
+    let host_obj = env.objc.borrow_mut::<CGContextHostObject>(context);
+    let t = host_obj.transform;
+    host_obj.path.quad_curve_to(
+        t.apply_to_point(CGPoint { x: cpx, y: cpy }),
+        t.apply_to_point(CGPoint { x, y }),
+    );
+}
+fn CGContextAddArc(
+    env: &mut Environment,
+    context: CGContextRef,
+    x: CGFloat,
+    y: CGFloat,
+    radius: CGFloat,
+    start_angle: CGFloat,
+    end_angle: CGFloat,
+    clockwise: i32,
+) {
+    let host_obj = env.objc.borrow_mut::<CGContextHostObject>(context);
+    host_obj.path.add_arc(
+        host_obj.transform,
+        CGPoint { x, y },
+        radius,
+        start_angle,
+        end_angle,
+        clockwise != 0,
+    );
+}
+fn CGContextAddArcToPoint(
+    env: &mut Environment,
+    context: CGContextRef,
+    x1: CGFloat,
+    y1: CGFloat,
+    x2: CGFloat,
+    y2: CGFloat,
+    radius: CGFloat,
+) {
+    let host_obj = env.objc.borrow_mut::<CGContextHostObject>(context);
+    host_obj.path.add_arc_to_point(
+        host_obj.transform,
+        CGPoint { x: x1, y: y1 },
+        CGPoint { x: x2, y: y2 },
+        radius,
+    );
+}
+fn CGContextAddRect(env: &mut Environment, context: CGContextRef, rect: CGRect) {
+    let CGRect {
+        origin: CGPoint { x, y },
+        size: CGSize { width, height },
+    } = rect;
+    CGContextMoveToPoint(env, context, x, y);
+    CGContextAddLineToPoint(env, context, x + width, y);
+    CGContextAddLineToPoint(env, context, x + width, y + height);
+    CGContextAddLineToPoint(env, context, x, y + height);
+    CGContextClosePath(env, context);
+}
+fn CGContextAddRects(
+    env: &mut Environment,
+    context: CGContextRef,
+    rects: ConstPtr<CGRect>,
+    count: GuestUSize,
+) {
+    for i in 0..count {
+        let rect = env.mem.read(rects + i);
+        CGContextAddRect(env, context, rect);
+    }
+}
+fn CGContextAddEllipseInRect(env: &mut Environment, context: CGContextRef, rect: CGRect) {
+    let host_obj = env.objc.borrow_mut::<CGContextHostObject>(context);
+    host_obj.path.add_ellipse(
+        host_obj.transform,
+        rect.origin,
+        CGPoint {
+            x: rect.size.width,
+            y: rect.size.height,
+        },
+    );
+}
+
+pub type CGPathDrawingMode = i32;
+pub const kCGPathFill: CGPathDrawingMode = 0;
+pub const kCGPathEOFill: CGPathDrawingMode = 1;
+pub const kCGPathStroke: CGPathDrawingMode = 2;
+pub const kCGPathFillStroke: CGPathDrawingMode = 3;
+pub const kCGPathEOFillStroke: CGPathDrawingMode = 4;
+
+fn CGContextDrawPath(env: &mut Environment, context: CGContextRef, mode: CGPathDrawingMode) {
+    let host_obj = env.objc.borrow_mut::<CGContextHostObject>(context);
+    // Drawing consumes the current path.
+    let path = std::mem::take(&mut host_obj.path);
+    let polylines = path.flatten();
+
+    let (fill, even_odd, stroke) = match mode {
+        kCGPathFill => (true, false, false),
+        kCGPathEOFill => (true, true, false),
+        kCGPathStroke => (false, false, true),
+        kCGPathFillStroke => (true, false, true),
+        kCGPathEOFillStroke => (true, true, true),
+        _ => unimplemented!("CGContextDrawPath() mode {}", mode),
+    };
 
-fn CGContextRestoreGState(env: &mut Environment, context: CGContextRef) {
+    if fill {
+        // Filling implicitly closes all subpaths.
+        let polygons: Vec<_> = polylines
+            .iter()
+            .map(|(points, _closed)| points.clone())
+            .collect();
+        cg_bitmap_context::fill_polygons(
+            env, context, &polygons, even_odd, /* stroke: */ false,
+        );
+    }
+    if stroke {
+        let host_obj = env.objc.borrow::<CGContextHostObject>(context);
+        // The path is in absolute co-ordinates, so the line width and dash
+        // lengths must be scaled to match. This assumes the transform doesn't
+        // stretch things unevenly.
+        let t = host_obj.transform;
+        let scale = (t.a * t.d - t.b * t.c).abs().sqrt();
+        let line_style = &host_obj.line_style;
+        let style = StrokeStyle {
+            width: line_style.width * scale,
+            cap: line_style.cap,
+            join: line_style.join,
+            miter_limit: line_style.miter_limit,
+            dash: line_style.dash.as_ref().map(|(phase, lengths)| {
+                (
+                    phase * scale,
+                    lengths.iter().map(|length| length * scale).collect(),
+                )
+            }),
+        };
+        let polygons = stroke_to_polygons(polylines, &style);
+        cg_bitmap_context::fill_polygons(
+            env, context, &polygons, /* even_odd: */ false, /* stroke: */ true,
+        );
+    }
+}
+fn CGContextFillPath(env: &mut Environment, context: CGContextRef) {
+    CGContextDrawPath(env, context, kCGPathFill);
+}
+fn CGContextEOFillPath(env: &mut Environment, context: CGContextRef) {
+    CGContextDrawPath(env, context, kCGPathEOFill);
+}
+fn CGContextStrokePath(env: &mut Environment, context: CGContextRef) {
+    CGContextDrawPath(env, context, kCGPathStroke);
+}
+
+fn CGContextStrokeRect(env: &mut Environment, context: CGContextRef, rect: CGRect) {
+    CGContextBeginPath(env, context);
+    CGContextAddRect(env, context, rect);
+    CGContextStrokePath(env, context);
+}
+fn CGContextStrokeRectWithWidth(
+    env: &mut Environment,
+    context: CGContextRef,
+    rect: CGRect,
+    width: CGFloat,
+) {
+    let host_obj = env.objc.borrow_mut::<CGContextHostObject>(context);
+    let old_width = host_obj.line_style.width;
+    host_obj.line_style.width = width;
+    CGContextStrokeRect(env, context, rect);
+    env.objc
+        .borrow_mut::<CGContextHostObject>(context)
+        .line_style
+        .width = old_width;
+}
+fn CGContextFillEllipseInRect(env: &mut Environment, context: CGContextRef, rect: CGRect) {
+    CGContextBeginPath(env, context);
+    CGContextAddEllipseInRect(env, context, rect);
+    CGContextFillPath(env, context);
+}
+fn CGContextStrokeEllipseInRect(env: &mut Environment, context: CGContextRef, rect: CGRect) {
+    CGContextBeginPath(env, context);
+    CGContextAddEllipseInRect(env, context, rect);
+    CGContextStrokePath(env, context);
+}
+fn CGContextStrokeLineSegments(
+    env: &mut Environment,
+    context: CGContextRef,
+    points: ConstPtr<CGPoint>,
+    count: GuestUSize,
+) {
+    CGContextBeginPath(env, context);
+    for i in 0..(count / 2) {
+        let CGPoint { x: x1, y: y1 } = env.mem.read(points + i * 2);
+        let CGPoint { x: x2, y: y2 } = env.mem.read(points + i * 2 + 1);
+        CGContextMoveToPoint(env, context, x1, y1);
+        CGContextAddLineToPoint(env, context, x2, y2);
+    }
+    CGContextStrokePath(env, context);
+}
+
+pub fn CGContextSaveGState(env: &mut Environment, context: CGContextRef) {
+    let host_obj = env.objc.borrow_mut::<CGContextHostObject>(context);
+    host_obj.state_stack.push(CGContextGState {
+        rgb_fill_color: host_obj.rgb_fill_color,
+        rgb_stroke_color: host_obj.rgb_stroke_color,
+        line_style: host_obj.line_style.clone(),
+        transform: host_obj.transform,
+        clip: host_obj.clip,
+        clip_mask: host_obj.clip_mask.clone(),
+        blend_mode: host_obj.blend_mode,
+        alpha: host_obj.alpha,
+    });
+}
+
+pub fn CGContextRestoreGState(env: &mut Environment, context: CGContextRef) {
     let host_obj = env.objc.borrow_mut::<CGContextHostObject>(context);
     let state = host_obj.state_stack.pop().unwrap();
-    host_obj.rgb_fill_color = state.0;
-    host_obj.transform = state.1;
+    host_obj.rgb_fill_color = state.rgb_fill_color;
+    host_obj.rgb_stroke_color = state.rgb_stroke_color;
+    host_obj.line_style = state.line_style;
+    host_obj.transform = state.transform;
+    host_obj.clip = state.clip;
+    host_obj.clip_mask = state.clip_mask;
+    host_obj.blend_mode = state.blend_mode;
+    host_obj.alpha = state.alpha;
+}
+
+fn CGContextClipToRect(env: &mut Environment, context: CGContextRef, rect: CGRect) {
+    log_dbg!("CGContextClipToRect({:?})", rect);
+    let host_obj = env.objc.borrow_mut::<CGContextHostObject>(context);
+    // TODO: Clipping to a rotated rectangle should not clip to its bounding
+    // box.
+    let rect = host_obj.transform.apply_to_rect(rect);
+    host_obj.clip = Some(match host_obj.clip {
+        Some(clip) => intersect_rects(clip, rect),
+        None => rect,
+    });
+}
+
+fn CGContextClipToMask(
+    env: &mut Environment,
+    context: CGContextRef,
+    rect: CGRect,
+    mask: CGImageRef,
+) {
+    log_dbg!("CGContextClipToMask({:?}, {:?})", rect, mask);
+    cg_bitmap_context::clip_to_mask(env, context, rect, mask);
+}
+
+pub(super) fn intersect_rects(a: CGRect, b: CGRect) -> CGRect {
+    let x_min = a.origin.x.max(b.origin.x);
+    let y_min = a.origin.y.max(b.origin.y);
+    let x_max = (a.origin.x + a.size.width).min(b.origin.x + b.size.width);
+    let y_max = (a.origin.y + a.size.height).min(b.origin.y + b.size.height);
+    CGRect {
+        origin: CGPoint { x: x_min, y: y_min },
+        size: CGSize {
+            width: (x_max - x_min).max(0.0),
+            height: (y_max - y_min).max(0.0),
+        },
+    }
 }
 
 pub const FUNCTIONS: FunctionExports = &[
@@ -162,6 +660,17 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(CGContextRelease(_)),
     export_c_func!(CGContextSetRGBFillColor(_, _, _, _, _)),
     export_c_func!(CGContextSetGrayFillColor(_, _, _)),
+    export_c_func!(CGContextSetFillColorWithColor(_, _)),
+    export_c_func!(CGContextSetRGBStrokeColor(_, _, _, _, _)),
+    export_c_func!(CGContextSetGrayStrokeColor(_, _, _)),
+    export_c_func!(CGContextSetStrokeColorWithColor(_, _)),
+    export_c_func!(CGContextSetBlendMode(_, _)),
+    export_c_func!(CGContextSetAlpha(_, _)),
+    export_c_func!(CGContextSetLineWidth(_, _)),
+    export_c_func!(CGContextSetLineCap(_, _)),
+    export_c_func!(CGContextSetLineJoin(_, _)),
+    export_c_func!(CGContextSetMiterLimit(_, _)),
+    export_c_func!(CGContextSetLineDash(_, _, _, _)),
     export_c_func!(CGContextFillRect(_, _)),
     export_c_func!(CGContextClearRect(_, _)),
     export_c_func!(CGContextConcatCTM(_, _)),
@@ -170,6 +679,31 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(CGContextScaleCTM(_, _, _)),
     export_c_func!(CGContextTranslateCTM(_, _, _)),
     export_c_func!(CGContextDrawImage(_, _, _)),
+    export_c_func!(CGContextBeginPath(_)),
+    export_c_func!(CGContextClosePath(_)),
+    export_c_func!(CGContextIsPathEmpty(_)),
+    export_c_func!(CGContextGetPathCurrentPoint(_)),
+    export_c_func!(CGContextMoveToPoint(_, _, _)),
+    export_c_func!(CGContextAddLineToPoint(_, _, _)),
+    export_c_func!(CGContextAddLines(_, _, _)),
+    export_c_func!(CGContextAddCurveToPoint(_, _, _, _, _, _, _)),
+    export_c_func!(CGContextAddQuadCurveToPoint(_, _, _, _, _)),
+    export_c_func!(CGContextAddArc(_, _, _, _, _, _, _)),
+    export_c_func!(CGContextAddArcToPoint(_, _, _, _, _, _)),
+    export_c_func!(CGContextAddRect(_, _)),
+    export_c_func!(CGContextAddRects(_, _, _)),
+    export_c_func!(CGContextAddEllipseInRect(_, _)),
+    export_c_func!(CGContextDrawPath(_, _)),
+    export_c_func!(CGContextFillPath(_)),
+    export_c_func!(CGContextEOFillPath(_)),
+    export_c_func!(CGContextStrokePath(_)),
+    export_c_func!(CGContextStrokeRect(_, _)),
+    export_c_func!(CGContextStrokeRectWithWidth(_, _, _)),
+    export_c_func!(CGContextFillEllipseInRect(_, _)),
+    export_c_func!(CGContextStrokeEllipseInRect(_, _)),
+    export_c_func!(CGContextStrokeLineSegments(_, _, _)),
     export_c_func!(CGContextSaveGState(_)),
     export_c_func!(CGContextRestoreGState(_)),
+    export_c_func!(CGContextClipToRect(_, _)),
+    export_c_func!(CGContextClipToMask(_, _, _)),
 ];
@@ -0,0 +1,289 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CGGradient.h` and the gradient drawing functions from `CGContext.h`
+
+use super::cg_bitmap_context::CGBitmapContextDrawer;
+use super::cg_color;
+use super::cg_color_space::{
+    kCGColorSpaceGenericGray, kCGColorSpaceGenericRGB, CGColorSpaceHostObject, CGColorSpaceRef,
+};
+use super::cg_context::CGContextRef;
+use super::{CGFloat, CGPoint};
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::frameworks::core_foundation::cf_array::CFArrayRef;
+use crate::frameworks::core_foundation::{CFRelease, CFRetain, CFTypeRef};
+use crate::frameworks::foundation::NSUInteger;
+use crate::mem::{ConstPtr, GuestUSize};
+use crate::objc::{id, msg, objc_classes, ClassExports, HostObject};
+use crate::Environment;
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+// CGGradient seems to be a CFType-based type, but in our implementation those
+// are just Objective-C types, so we need a class for it, but its name is not
+// visible anywhere.
+@implementation _touchHLE_CGGradient: NSObject
+@end
+
+};
+
+type Color = (CGFloat, CGFloat, CGFloat, CGFloat);
+
+struct CGGradientHostObject {
+    /// Gradient stops, sorted by location. The colors are straight-alpha RGBA
+    /// in the gradient's color space, so interpolation happens in that space.
+    stops: Vec<(CGFloat, Color)>,
+}
+impl HostObject for CGGradientHostObject {}
+
+pub type CGGradientRef = CFTypeRef;
+
+pub type CGGradientDrawingOptions = u32;
+pub const kCGGradientDrawsBeforeStartLocation: CGGradientDrawingOptions = 1 << 0;
+pub const kCGGradientDrawsAfterEndLocation: CGGradientDrawingOptions = 1 << 1;
+
+pub fn CGGradientRelease(env: &mut Environment, gradient: CGGradientRef) {
+    if !gradient.is_null() {
+        CFRelease(env, gradient);
+    }
+}
+pub fn CGGradientRetain(env: &mut Environment, gradient: CGGradientRef) -> CGGradientRef {
+    if !gradient.is_null() {
+        CFRetain(env, gradient)
+    } else {
+        gradient
+    }
+}
+
+fn create_gradient(
+    env: &mut Environment,
+    colors: Vec<Color>,
+    locations: ConstPtr<CGFloat>,
+) -> CGGradientRef {
+    let count = colors.len();
+    let mut stops: Vec<(CGFloat, Color)> = colors
+        .into_iter()
+        .enumerate()
+        .map(|(i, color)| {
+            let location = if locations.is_null() {
+                // Stops are evenly distributed if no locations are given.
+                if count == 1 {
+                    0.0
+                } else {
+                    i as CGFloat / (count - 1) as CGFloat
+                }
+            } else {
+                env.mem.read(locations + i as GuestUSize)
+            };
+            (location, color)
+        })
+        .collect();
+    stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let isa = env
+        .objc
+        .get_known_class("_touchHLE_CGGradient", &mut env.mem);
+    env.objc
+        .alloc_object(isa, Box::new(CGGradientHostObject { stops }), &mut env.mem)
+}
+
+fn CGGradientCreateWithColorComponents(
+    env: &mut Environment,
+    space: CGColorSpaceRef,
+    components: ConstPtr<CGFloat>,
+    locations: ConstPtr<CGFloat>,
+    count: GuestUSize,
+) -> CGGradientRef {
+    let color_space = env.objc.borrow::<CGColorSpaceHostObject>(space).name;
+    let colors = (0..count)
+        .map(|i| match color_space {
+            kCGColorSpaceGenericRGB => {
+                let base = components + i * 4;
+                (
+                    env.mem.read(base),
+                    env.mem.read(base + 1),
+                    env.mem.read(base + 2),
+                    env.mem.read(base + 3),
+                )
+            }
+            kCGColorSpaceGenericGray => {
+                let base = components + i * 2;
+                let gray = env.mem.read(base);
+                (gray, gray, gray, env.mem.read(base + 1))
+            }
+            _ => unimplemented!("support other color spaces"),
+        })
+        .collect();
+    create_gradient(env, colors, locations)
+}
+
+fn CGGradientCreateWithColors(
+    env: &mut Environment,
+    _space: CGColorSpaceRef,
+    colors: CFArrayRef,
+    locations: ConstPtr<CGFloat>,
+) -> CGGradientRef {
+    // TODO: colors should be converted to the gradient's color space, but
+    // CGColor only supports RGB so far.
+    let count: NSUInteger = msg![env; colors count];
+    let colors = (0..count)
+        .map(|i| {
+            let color: id = msg![env; colors objectAtIndex:i];
+            cg_color::to_rgba(&env.objc, color)
+        })
+        .collect();
+    create_gradient(env, colors, locations)
+}
+
+/// Look up the color at location `t` in a gradient's (sorted) stops.
+fn color_at(stops: &[(CGFloat, Color)], t: CGFloat) -> Color {
+    let (first_location, first_color) = stops[0];
+    if t <= first_location {
+        return first_color;
+    }
+    for pair in stops.windows(2) {
+        let [(l0, c0), (l1, c1)] = [pair[0], pair[1]];
+        if t <= l1 {
+            let f = if l1 > l0 { (t - l0) / (l1 - l0) } else { 1.0 };
+            return (
+                c0.0 + (c1.0 - c0.0) * f,
+                c0.1 + (c1.1 - c0.1) * f,
+                c0.2 + (c1.2 - c0.2) * f,
+                c0.3 + (c1.3 - c0.3) * f,
+            );
+        }
+    }
+    stops.last().unwrap().1
+}
+
+/// Shared implementation of the gradient drawing functions. `get_t` maps a
+/// point in user space to a gradient location, where 0 is the start and 1 is
+/// the end, or [None] if the point isn't covered by the gradient at all.
+fn draw_gradient<F>(
+    env: &mut Environment,
+    context: CGContextRef,
+    gradient: CGGradientRef,
+    options: CGGradientDrawingOptions,
+    get_t: F,
+) where
+    F: Fn(CGPoint) -> Option<CGFloat>,
+{
+    let stops = env
+        .objc
+        .borrow::<CGGradientHostObject>(gradient)
+        .stops
+        .clone();
+    if stops.is_empty() {
+        return;
+    }
+
+    let draws_before = (options & kCGGradientDrawsBeforeStartLocation) != 0;
+    let draws_after = (options & kCGGradientDrawsAfterEndLocation) != 0;
+
+    let mut drawer = CGBitmapContextDrawer::new(&env.objc, &mut env.mem, context);
+    let pixels: Vec<_> = drawer.iter_untransformed_pixels().collect();
+    for (coords, point) in pixels {
+        let Some(t) = get_t(point) else {
+            continue;
+        };
+        if (t < 0.0 && !draws_before) || (t > 1.0 && !draws_after) {
+            continue;
+        }
+        let color = drawer.convert_color(color_at(&stops, t.clamp(0.0, 1.0)));
+        drawer.put_pixel(coords, color, /* blend: */ true);
+    }
+}
+
+fn CGContextDrawLinearGradient(
+    env: &mut Environment,
+    context: CGContextRef,
+    gradient: CGGradientRef,
+    start_point: CGPoint,
+    end_point: CGPoint,
+    options: CGGradientDrawingOptions,
+) {
+    let dx = end_point.x - start_point.x;
+    let dy = end_point.y - start_point.y;
+    let length_squared = dx * dx + dy * dy;
+    draw_gradient(env, context, gradient, options, |point| {
+        if length_squared == 0.0 {
+            // Degenerate gradient: there is no direction to draw it in.
+            return None;
+        }
+        // Project the point onto the line from the start to the end point.
+        let px = point.x - start_point.x;
+        let py = point.y - start_point.y;
+        Some((px * dx + py * dy) / length_squared)
+    });
+}
+
+fn CGContextDrawRadialGradient(
+    env: &mut Environment,
+    context: CGContextRef,
+    gradient: CGGradientRef,
+    start_center: CGPoint,
+    start_radius: CGFloat,
+    end_center: CGPoint,
+    end_radius: CGFloat,
+    options: CGGradientDrawingOptions,
+) {
+    let draws_before = (options & kCGGradientDrawsBeforeStartLocation) != 0;
+    let draws_after = (options & kCGGradientDrawsAfterEndLocation) != 0;
+
+    // The gradient is made of circles whose center and radius are
+    // interpolated from the start circle (t = 0) to the end circle (t = 1).
+    // For each point, find the largest t with a non-negative radius for which
+    // the point lies on that circle, by solving a quadratic equation:
+    //   |point - center(t)|² = radius(t)²
+    let dcx = end_center.x - start_center.x;
+    let dcy = end_center.y - start_center.y;
+    let dr = end_radius - start_radius;
+    let a = dcx * dcx + dcy * dcy - dr * dr;
+    draw_gradient(env, context, gradient, options, |point| {
+        let px = point.x - start_center.x;
+        let py = point.y - start_center.y;
+        let b = px * dcx + py * dcy + start_radius * dr;
+        let c = px * px + py * py - start_radius * start_radius;
+
+        let is_usable = |t: CGFloat| {
+            start_radius + t * dr >= 0.0 && (t >= 0.0 || draws_before) && (t <= 1.0 || draws_after)
+        };
+
+        if a.abs() < 1e-6 {
+            if b == 0.0 {
+                return None;
+            }
+            let t = c / (2.0 * b);
+            return is_usable(t).then_some(t);
+        }
+
+        let discriminant = b * b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        let (t1, t2) = ((b + root) / a, (b - root) / a);
+        let (larger, smaller) = (t1.max(t2), t1.min(t2));
+        if is_usable(larger) {
+            Some(larger)
+        } else if is_usable(smaller) {
+            Some(smaller)
+        } else {
+            None
+        }
+    });
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(CGGradientRetain(_)),
+    export_c_func!(CGGradientRelease(_)),
+    export_c_func!(CGGradientCreateWithColorComponents(_, _, _, _)),
+    export_c_func!(CGGradientCreateWithColors(_, _, _)),
+    export_c_func!(CGContextDrawLinearGradient(_, _, _, _, _)),
+    export_c_func!(CGContextDrawRadialGradient(_, _, _, _, _, _, _)),
+];
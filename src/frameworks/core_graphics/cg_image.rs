@@ -7,7 +7,7 @@
 
 use super::cg_color_space::{kCGColorSpaceGenericRGB, CGColorSpaceCreateWithName, CGColorSpaceRef};
 use super::cg_data_provider::{self, CGDataProviderRef};
-use super::CGFloat;
+use super::{CGFloat, CGRect};
 use crate::dyld::{export_c_func, FunctionExports};
 use crate::frameworks::core_foundation::{CFRelease, CFRetain, CFTypeRef};
 use crate::frameworks::foundation::ns_string;
@@ -113,6 +113,48 @@ fn CGImageCreateWithPNGDataProvider(
     from_image(env, image)
 }
 
+pub fn CGImageCreateWithImageInRect(
+    env: &mut Environment,
+    image: CGImageRef,
+    rect: CGRect,
+) -> CGImageRef {
+    let (width, height) = borrow_image(&env.objc, image).dimensions();
+
+    // The rect is first made integral (like CGRectIntegral), then intersected
+    // with the image's bounds. Unlike most of Core Graphics, the co-ordinates
+    // are relative to the top-left corner.
+    let x_min = rect.origin.x.min(rect.origin.x + rect.size.width);
+    let y_min = rect.origin.y.min(rect.origin.y + rect.size.height);
+    let x_max = rect.origin.x.max(rect.origin.x + rect.size.width);
+    let y_max = rect.origin.y.max(rect.origin.y + rect.size.height);
+    let x_min = x_min.floor().max(0.0);
+    let y_min = y_min.floor().max(0.0);
+    let x_max = x_max.ceil().min(width as CGFloat);
+    let y_max = y_max.ceil().min(height as CGFloat);
+    if !(x_min < x_max && y_min < y_max) {
+        log_dbg!(
+            "CGImageCreateWithImageInRect({:?}, {:?}) => NULL (empty intersection)",
+            image,
+            rect
+        );
+        return nil;
+    }
+
+    let origin = (x_min as u32, y_min as u32);
+    let size = ((x_max - x_min) as u32, (y_max - y_min) as u32);
+    let cropped = borrow_image(&env.objc, image).crop(origin, size);
+    let new_image = from_image(env, cropped);
+    log_dbg!(
+        "CGImageCreateWithImageInRect({:?}, {:?}) => {:?} ({:?} at {:?})",
+        image,
+        rect,
+        new_image,
+        size,
+        origin
+    );
+    new_image
+}
+
 fn CGImageGetAlphaInfo(_env: &mut Environment, _image: CGImageRef) -> CGImageAlphaInfo {
     // our Image type always returns premultiplied RGBA
     // (the premultiplied part must match what the real UIImage does, but
@@ -148,6 +190,11 @@ fn CGImageGetBitsPerPixel(_env: &mut Environment, _image: CGImageRef) -> GuestUS
     32
 }
 
+fn CGImageGetBytesPerRow(env: &mut Environment, image: CGImageRef) -> GuestUSize {
+    // Our Image type always has tightly-packed rows of RGBA pixels.
+    CGImageGetWidth(env, image) * 4
+}
+
 fn CGImageGetDataProvider(env: &mut Environment, image: CGImageRef) -> CGDataProviderRef {
     // CGImageGetDataProvider() seems to be intended to return the underlying
     // data provider that is retained by the CGImage. That's not how CGImage is
@@ -168,11 +215,13 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(CGImageRelease(_)),
     export_c_func!(CGImageRetain(_)),
     export_c_func!(CGImageCreateWithPNGDataProvider(_, _, _, _)),
+    export_c_func!(CGImageCreateWithImageInRect(_, _)),
     export_c_func!(CGImageGetAlphaInfo(_)),
     export_c_func!(CGImageGetColorSpace(_)),
     export_c_func!(CGImageGetWidth(_)),
     export_c_func!(CGImageGetHeight(_)),
     export_c_func!(CGImageGetBitsPerPixel(_)),
+    export_c_func!(CGImageGetBytesPerRow(_)),
     export_c_func!(CGImageGetDataProvider(_)),
     export_c_func!(CGImageGetBitsPerComponent(_)),
 ];
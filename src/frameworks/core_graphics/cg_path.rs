@@ -0,0 +1,568 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CGPath.h`
+//!
+//! Only the path model used internally by `CGContext`'s path functions is
+//! implemented so far, not the `CGPath` type itself. This module also contains
+//! the host-side geometry for flattening curves and stroking.
+
+use super::cg_affine_transform::CGAffineTransform;
+use super::{CGFloat, CGPoint};
+use std::f32::consts::{FRAC_PI_2, PI};
+
+pub type CGLineCap = i32;
+pub const kCGLineCapButt: CGLineCap = 0;
+pub const kCGLineCapRound: CGLineCap = 1;
+pub const kCGLineCapSquare: CGLineCap = 2;
+
+pub type CGLineJoin = i32;
+pub const kCGLineJoinMiter: CGLineJoin = 0;
+pub const kCGLineJoinRound: CGLineJoin = 1;
+pub const kCGLineJoinBevel: CGLineJoin = 2;
+
+/// Maximum distance in pixels between a curve and its flattened form.
+const FLATNESS: CGFloat = 0.25;
+
+#[derive(Copy, Clone, Debug)]
+enum PathElement {
+    MoveTo(CGPoint),
+    LineTo(CGPoint),
+    QuadCurveTo(CGPoint, CGPoint),
+    CurveTo(CGPoint, CGPoint, CGPoint),
+    CloseSubpath,
+}
+
+/// A path made of lines and curves. Like the current path of a real
+/// `CGContext`, the points are in absolute (device) co-ordinates: the CTM is
+/// applied when elements are added, not when the path is drawn.
+#[derive(Clone, Debug, Default)]
+pub struct Path {
+    elements: Vec<PathElement>,
+    current_point: Option<CGPoint>,
+    subpath_start: Option<CGPoint>,
+}
+
+impl Path {
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+    pub fn current_point(&self) -> Option<CGPoint> {
+        self.current_point
+    }
+
+    pub fn move_to(&mut self, point: CGPoint) {
+        // Consecutive moves replace each other.
+        if let Some(PathElement::MoveTo(_)) = self.elements.last() {
+            self.elements.pop();
+        }
+        self.elements.push(PathElement::MoveTo(point));
+        self.current_point = Some(point);
+        self.subpath_start = Some(point);
+    }
+    pub fn line_to(&mut self, point: CGPoint) {
+        if self.current_point.is_none() {
+            log!("Warning: adding a line to a path with no current point");
+            self.move_to(point);
+            return;
+        }
+        self.elements.push(PathElement::LineTo(point));
+        self.current_point = Some(point);
+    }
+    pub fn quad_curve_to(&mut self, control: CGPoint, point: CGPoint) {
+        if self.current_point.is_none() {
+            log!("Warning: adding a curve to a path with no current point");
+            self.move_to(point);
+            return;
+        }
+        self.elements.push(PathElement::QuadCurveTo(control, point));
+        self.current_point = Some(point);
+    }
+    pub fn curve_to(&mut self, control1: CGPoint, control2: CGPoint, point: CGPoint) {
+        if self.current_point.is_none() {
+            log!("Warning: adding a curve to a path with no current point");
+            self.move_to(point);
+            return;
+        }
+        self.elements
+            .push(PathElement::CurveTo(control1, control2, point));
+        self.current_point = Some(point);
+    }
+    pub fn close_subpath(&mut self) {
+        if self.current_point.is_none() {
+            return;
+        }
+        self.elements.push(PathElement::CloseSubpath);
+        self.current_point = self.subpath_start;
+    }
+
+    /// Add a circular arc. The center, radius and angles are in user space,
+    /// and `transform` is the CTM. If there's a current point, a line is first
+    /// added from it to the start of the arc.
+    pub fn add_arc(
+        &mut self,
+        transform: CGAffineTransform,
+        center: CGPoint,
+        radius: CGFloat,
+        start_angle: CGFloat,
+        end_angle: CGFloat,
+        clockwise: bool,
+    ) {
+        // Angles increase from the positive x axis towards the positive y
+        // axis, and "clockwise" means decreasing angles.
+        let mut sweep = end_angle - start_angle;
+        if clockwise {
+            if sweep > 0.0 {
+                sweep -= (sweep / (2.0 * PI)).ceil() * 2.0 * PI;
+            }
+            sweep = sweep.max(-2.0 * PI);
+        } else {
+            if sweep < 0.0 {
+                sweep += (-sweep / (2.0 * PI)).ceil() * 2.0 * PI;
+            }
+            sweep = sweep.min(2.0 * PI);
+        }
+
+        let point_at = |angle: CGFloat| CGPoint {
+            x: center.x + radius * angle.cos(),
+            y: center.y + radius * angle.sin(),
+        };
+        let start = transform.apply_to_point(point_at(start_angle));
+        if self.current_point.is_some() {
+            self.line_to(start);
+        } else {
+            self.move_to(start);
+        }
+
+        // Each piece of at most 90° is approximated by a cubic Bézier curve.
+        let pieces = ((sweep.abs() / FRAC_PI_2).ceil() as u32).max(1);
+        let piece_sweep = sweep / pieces as CGFloat;
+        let k = 4.0 / 3.0 * (piece_sweep / 4.0).tan();
+        for i in 0..pieces {
+            let a0 = start_angle + piece_sweep * i as CGFloat;
+            let a1 = a0 + piece_sweep;
+            let (p0, p3) = (point_at(a0), point_at(a1));
+            let p1 = CGPoint {
+                x: p0.x - k * radius * a0.sin(),
+                y: p0.y + k * radius * a0.cos(),
+            };
+            let p2 = CGPoint {
+                x: p3.x + k * radius * a1.sin(),
+                y: p3.y - k * radius * a1.cos(),
+            };
+            self.curve_to(
+                transform.apply_to_point(p1),
+                transform.apply_to_point(p2),
+                transform.apply_to_point(p3),
+            );
+        }
+    }
+
+    /// Add an arc of the given radius that is tangent to the line from the
+    /// current point to `point1` and the line from `point1` to `point2`, as in
+    /// `CGContextAddArcToPoint`. The points are in user space.
+    pub fn add_arc_to_point(
+        &mut self,
+        transform: CGAffineTransform,
+        point1: CGPoint,
+        point2: CGPoint,
+        radius: CGFloat,
+    ) {
+        let Some(current) = self.current_point else {
+            log!("Warning: adding an arc to a path with no current point");
+            self.move_to(transform.apply_to_point(point1));
+            return;
+        };
+        let p0 = transform.invert().apply_to_point(current);
+
+        let (v1x, v1y) = (p0.x - point1.x, p0.y - point1.y);
+        let (v2x, v2y) = (point2.x - point1.x, point2.y - point1.y);
+        let (len1, len2) = (v1x.hypot(v1y), v2x.hypot(v2y));
+        let cross = v1x * v2y - v1y * v2x;
+        if len1 == 0.0 || len2 == 0.0 || cross.abs() < 1e-6 || radius == 0.0 {
+            // Degenerate: the points are collinear, so there's no arc.
+            self.line_to(transform.apply_to_point(point1));
+            return;
+        }
+        let (u1x, u1y) = (v1x / len1, v1y / len1);
+        let (u2x, u2y) = (v2x / len2, v2y / len2);
+        let cos_angle = (u1x * u2x + u1y * u2y).clamp(-1.0, 1.0);
+        let angle = cos_angle.acos();
+        // Distance from point1 to the tangent points.
+        let tangent_distance = radius / (angle / 2.0).tan();
+        let tangent1 = CGPoint {
+            x: point1.x + u1x * tangent_distance,
+            y: point1.y + u1y * tangent_distance,
+        };
+        let tangent2 = CGPoint {
+            x: point1.x + u2x * tangent_distance,
+            y: point1.y + u2y * tangent_distance,
+        };
+        // The center is along the angle bisector.
+        let (bx, by) = (u1x + u2x, u1y + u2y);
+        let b_len = bx.hypot(by);
+        let center_distance = radius / (angle / 2.0).sin();
+        let center = CGPoint {
+            x: point1.x + bx / b_len * center_distance,
+            y: point1.y + by / b_len * center_distance,
+        };
+        let start_angle = (tangent1.y - center.y).atan2(tangent1.x - center.x);
+        let end_angle = (tangent2.y - center.y).atan2(tangent2.x - center.x);
+        // v1 points backwards along the incoming line, so a positive cross
+        // product means the path turns right, i.e. goes clockwise around the
+        // center.
+        let clockwise = cross > 0.0;
+        self.add_arc(transform, center, radius, start_angle, end_angle, clockwise);
+    }
+
+    /// Add an ellipse inscribed in a rectangle (in user space) as a closed
+    /// subpath.
+    pub fn add_ellipse(&mut self, transform: CGAffineTransform, origin: CGPoint, size: CGPoint) {
+        // Magic number for approximating a quarter-circle with a cubic Bézier.
+        const KAPPA: CGFloat = 0.552_284_8;
+        let (rx, ry) = (size.x / 2.0, size.y / 2.0);
+        let (cx, cy) = (origin.x + rx, origin.y + ry);
+        let (kx, ky) = (rx * KAPPA, ry * KAPPA);
+        let t = |x: CGFloat, y: CGFloat| transform.apply_to_point(CGPoint { x, y });
+        self.move_to(t(cx + rx, cy));
+        self.curve_to(t(cx + rx, cy + ky), t(cx + kx, cy + ry), t(cx, cy + ry));
+        self.curve_to(t(cx - kx, cy + ry), t(cx - rx, cy + ky), t(cx - rx, cy));
+        self.curve_to(t(cx - rx, cy - ky), t(cx - kx, cy - ry), t(cx, cy - ry));
+        self.curve_to(t(cx + kx, cy - ry), t(cx + rx, cy - ky), t(cx + rx, cy));
+        self.close_subpath();
+    }
+
+    /// Convert the path to polylines, returning each subpath's points and
+    /// whether it is closed.
+    pub fn flatten(&self) -> Vec<(Vec<CGPoint>, bool)> {
+        let mut polylines = Vec::new();
+        let mut points: Vec<CGPoint> = Vec::new();
+        let mut current = CGPoint { x: 0.0, y: 0.0 };
+        for &element in &self.elements {
+            match element {
+                PathElement::MoveTo(point) => {
+                    if !points.is_empty() {
+                        polylines.push((std::mem::take(&mut points), false));
+                    }
+                    points.push(point);
+                    current = point;
+                }
+                PathElement::LineTo(point) => {
+                    points.push(point);
+                    current = point;
+                }
+                PathElement::QuadCurveTo(control, point) => {
+                    // Elevate to a cubic curve to share the flattening code.
+                    let control1 = lerp(current, control, 2.0 / 3.0);
+                    let control2 = lerp(point, control, 2.0 / 3.0);
+                    flatten_cubic(&mut points, [current, control1, control2, point]);
+                    current = point;
+                }
+                PathElement::CurveTo(control1, control2, point) => {
+                    flatten_cubic(&mut points, [current, control1, control2, point]);
+                    current = point;
+                }
+                PathElement::CloseSubpath => {
+                    if !points.is_empty() {
+                        let start = points[0];
+                        polylines.push((std::mem::take(&mut points), true));
+                        // A new subpath implicitly starts where this one did.
+                        current = start;
+                    }
+                }
+            }
+        }
+        if !points.is_empty() {
+            polylines.push((points, false));
+        }
+        polylines
+    }
+}
+
+fn lerp(a: CGPoint, b: CGPoint, t: CGFloat) -> CGPoint {
+    CGPoint {
+        x: a.x + (b.x - a.x) * t,
+        y: a.y + (b.y - a.y) * t,
+    }
+}
+
+fn distance(a: CGPoint, b: CGPoint) -> CGFloat {
+    (b.x - a.x).hypot(b.y - a.y)
+}
+
+/// Append points approximating a cubic Bézier curve, excluding its first
+/// point.
+fn flatten_cubic(points: &mut Vec<CGPoint>, [p0, p1, p2, p3]: [CGPoint; 4]) {
+    // The length of the control polygon is an upper bound on the curve's
+    // length, so it's a cheap way to pick the number of segments.
+    let length = distance(p0, p1) + distance(p1, p2) + distance(p2, p3);
+    let segments = ((length / (FLATNESS * 8.0)).sqrt().ceil() as u32).clamp(1, 100) * 2;
+    for i in 1..=segments {
+        let t = i as CGFloat / segments as CGFloat;
+        let a = lerp(p0, p1, t);
+        let b = lerp(p1, p2, t);
+        let c = lerp(p2, p3, t);
+        let d = lerp(a, b, t);
+        let e = lerp(b, c, t);
+        points.push(lerp(d, e, t));
+    }
+}
+
+/// Line stroking parameters. Lengths are in absolute (device) units.
+#[derive(Clone, Debug)]
+pub struct StrokeStyle {
+    pub width: CGFloat,
+    pub cap: CGLineCap,
+    pub join: CGLineJoin,
+    pub miter_limit: CGFloat,
+    /// Dash phase and lengths, if the line is dashed.
+    pub dash: Option<(CGFloat, Vec<CGFloat>)>,
+}
+
+/// Split polylines into dashes. Each dash is an open polyline.
+fn apply_dash(
+    polylines: Vec<(Vec<CGPoint>, bool)>,
+    phase: CGFloat,
+    lengths: &[CGFloat],
+) -> Vec<(Vec<CGPoint>, bool)> {
+    let total: CGFloat = lengths.iter().sum();
+    if total <= 0.0 {
+        return polylines;
+    }
+
+    let mut dashes = Vec::new();
+    for (mut points, closed) in polylines {
+        if closed {
+            points.push(points[0]);
+        }
+        // The dash pattern restarts for each subpath.
+        let mut index = 0;
+        let mut remaining = lengths[0];
+        let mut offset = phase.rem_euclid(total);
+        while offset > 0.0 {
+            if offset >= remaining {
+                offset -= remaining;
+                index = (index + 1) % lengths.len();
+                remaining = lengths[index];
+            } else {
+                remaining -= offset;
+                offset = 0.0;
+            }
+        }
+
+        let mut dash: Vec<CGPoint> = Vec::new();
+        if index % 2 == 0 {
+            dash.push(points[0]);
+        }
+        for pair in points.windows(2) {
+            let (mut a, b) = (pair[0], pair[1]);
+            let mut segment_length = distance(a, b);
+            while segment_length > remaining {
+                let point = lerp(a, b, remaining / segment_length);
+                segment_length -= remaining;
+                a = point;
+                if index % 2 == 0 {
+                    dash.push(point);
+                    dashes.push((std::mem::take(&mut dash), false));
+                } else {
+                    dash.push(point);
+                }
+                index = (index + 1) % lengths.len();
+                remaining = lengths[index];
+            }
+            remaining -= segment_length;
+            if index % 2 == 0 {
+                dash.push(b);
+            }
+        }
+        if index % 2 == 0 && dash.len() > 1 {
+            dashes.push((dash, false));
+        }
+    }
+    dashes
+}
+
+/// Make a polygon approximating a circle.
+fn circle(center: CGPoint, radius: CGFloat) -> Vec<CGPoint> {
+    let segments = ((2.0 * PI * radius / 2.0).ceil() as u32).clamp(8, 128);
+    (0..segments)
+        .map(|i| {
+            let angle = 2.0 * PI * i as CGFloat / segments as CGFloat;
+            CGPoint {
+                x: center.x + radius * angle.cos(),
+                y: center.y + radius * angle.sin(),
+            }
+        })
+        .collect()
+}
+
+/// Convert polylines to polygons covering the stroked area. All polygons have
+/// the same orientation, so they can be filled together with the non-zero
+/// winding rule to get their union.
+pub fn stroke_to_polygons(
+    polylines: Vec<(Vec<CGPoint>, bool)>,
+    style: &StrokeStyle,
+) -> Vec<Vec<CGPoint>> {
+    let half_width = style.width / 2.0;
+    if half_width <= 0.0 {
+        return Vec::new();
+    }
+    let polylines = match style.dash {
+        Some((phase, ref lengths)) => apply_dash(polylines, phase, lengths),
+        None => polylines,
+    };
+
+    let mut polygons = Vec::new();
+    for (mut points, closed) in polylines {
+        points.dedup_by(|a, b| distance(*a, *b) < 1e-4);
+        if closed && points.len() > 1 && distance(points[0], *points.last().unwrap()) < 1e-4 {
+            points.pop();
+        }
+
+        if points.len() == 1 {
+            // Zero-length subpaths only get caps.
+            let p = points[0];
+            match style.cap {
+                kCGLineCapRound => polygons.push(circle(p, half_width)),
+                kCGLineCapSquare => polygons.push(vec![
+                    CGPoint {
+                        x: p.x - half_width,
+                        y: p.y - half_width,
+                    },
+                    CGPoint {
+                        x: p.x + half_width,
+                        y: p.y - half_width,
+                    },
+                    CGPoint {
+                        x: p.x + half_width,
+                        y: p.y + half_width,
+                    },
+                    CGPoint {
+                        x: p.x - half_width,
+                        y: p.y + half_width,
+                    },
+                ]),
+                _ => (),
+            }
+            continue;
+        }
+
+        let segment_count = if closed {
+            points.len()
+        } else {
+            points.len() - 1
+        };
+        let direction = |i: usize| {
+            let (a, b) = (points[i % points.len()], points[(i + 1) % points.len()]);
+            let length = distance(a, b);
+            ((b.x - a.x) / length, (b.y - a.y) / length)
+        };
+
+        for i in 0..segment_count {
+            let (a, b) = (points[i], points[(i + 1) % points.len()]);
+            let (dx, dy) = direction(i);
+            let (nx, ny) = (-dy * half_width, dx * half_width);
+            let (mut a, mut b) = (a, b);
+            if !closed && style.cap == kCGLineCapSquare {
+                if i == 0 {
+                    a = CGPoint {
+                        x: a.x - dx * half_width,
+                        y: a.y - dy * half_width,
+                    };
+                }
+                if i == segment_count - 1 {
+                    b = CGPoint {
+                        x: b.x + dx * half_width,
+                        y: b.y + dy * half_width,
+                    };
+                }
+            }
+            polygons.push(vec![
+                CGPoint {
+                    x: a.x + nx,
+                    y: a.y + ny,
+                },
+                CGPoint {
+                    x: b.x + nx,
+                    y: b.y + ny,
+                },
+                CGPoint {
+                    x: b.x - nx,
+                    y: b.y - ny,
+                },
+                CGPoint {
+                    x: a.x - nx,
+                    y: a.y - ny,
+                },
+            ]);
+        }
+
+        // Joins between consecutive segments.
+        let join_count = if closed {
+            points.len()
+        } else {
+            points.len() - 2
+        };
+        for j in 0..join_count {
+            let i = if closed { j } else { j + 1 };
+            let v = points[i];
+            let (d1x, d1y) = direction((i + points.len() - 1) % points.len());
+            let (d2x, d2y) = direction(i);
+            let cross = d1x * d2y - d1y * d2x;
+            if cross.abs() < 1e-6 && d1x * d2x + d1y * d2y > 0.0 {
+                continue; // Straight continuation, no join needed.
+            }
+            if style.join == kCGLineJoinRound {
+                polygons.push(circle(v, half_width));
+                continue;
+            }
+            // The join is on the outer side of the turn.
+            let side = if cross > 0.0 { -1.0 } else { 1.0 };
+            let o1 = CGPoint {
+                x: v.x - d1y * half_width * side,
+                y: v.y + d1x * half_width * side,
+            };
+            let o2 = CGPoint {
+                x: v.x - d2y * half_width * side,
+                y: v.y + d2x * half_width * side,
+            };
+            let cos_turn = (d1x * d2x + d1y * d2y).clamp(-1.0, 1.0);
+            // Ratio of the miter length to the line width.
+            let miter_ratio = 1.0 / ((1.0 + cos_turn) / 2.0).sqrt();
+            if style.join == kCGLineJoinMiter && miter_ratio <= style.miter_limit {
+                let (mx, my) = ((o1.x + o2.x) / 2.0 - v.x, (o1.y + o2.y) / 2.0 - v.y);
+                let m_len = mx.hypot(my);
+                let tip = CGPoint {
+                    x: v.x + mx / m_len * half_width * miter_ratio,
+                    y: v.y + my / m_len * half_width * miter_ratio,
+                };
+                polygons.push(vec![v, o1, tip, o2]);
+            } else {
+                polygons.push(vec![v, o1, o2]);
+            }
+        }
+
+        if !closed && style.cap == kCGLineCapRound {
+            polygons.push(circle(points[0], half_width));
+            polygons.push(circle(*points.last().unwrap(), half_width));
+        }
+    }
+
+    for polygon in polygons.iter_mut() {
+        if signed_area(polygon) < 0.0 {
+            polygon.reverse();
+        }
+    }
+    polygons
+}
+
+fn signed_area(polygon: &[CGPoint]) -> CGFloat {
+    let mut area = 0.0;
+    for i in 0..polygon.len() {
+        let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+        area += a.x * b.y - b.x * a.y;
+    }
+    area / 2.0
+}
@@ -0,0 +1,60 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! The Core Location framework.
+//!
+//! touchHLE has no access to the host's location. By default, location
+//! services are reported as disabled and denied, which apps have to handle
+//! anyway. With `--location=`, a fixed location is reported instead.
+
+pub mod cl_location;
+pub mod cl_location_manager;
+
+use crate::abi::{impl_GuestRet_for_large_struct, GuestArg};
+use crate::mem::SafeRead;
+use std::time::Instant;
+
+#[derive(Default)]
+pub struct State {
+    cl_location_manager: cl_location_manager::State,
+}
+
+/// Latitude or longitude in degrees.
+pub type CLLocationDegrees = f64;
+/// Distance in meters.
+pub type CLLocationDistance = f64;
+/// Accuracy in meters.
+pub type CLLocationAccuracy = f64;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[repr(C, packed)]
+pub struct CLLocationCoordinate2D {
+    pub latitude: CLLocationDegrees,
+    pub longitude: CLLocationDegrees,
+}
+unsafe impl SafeRead for CLLocationCoordinate2D {}
+impl_GuestRet_for_large_struct!(CLLocationCoordinate2D);
+impl GuestArg for CLLocationCoordinate2D {
+    const REG_COUNT: usize = 4;
+
+    fn from_regs(regs: &[u32]) -> Self {
+        CLLocationCoordinate2D {
+            latitude: GuestArg::from_regs(&regs[0..2]),
+            longitude: GuestArg::from_regs(&regs[2..4]),
+        }
+    }
+    fn to_regs(self, regs: &mut [u32]) {
+        self.latitude.to_regs(&mut regs[0..2]);
+        self.longitude.to_regs(&mut regs[2..4]);
+    }
+}
+
+/// For use by `NSRunLoop`: deliver any location updates or errors that are
+/// due.
+///
+/// Returns the time the next update is due, if any.
+pub fn handle_location_managers(env: &mut crate::Environment) -> Option<Instant> {
+    cl_location_manager::handle_location_managers(env)
+}
@@ -0,0 +1,168 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CLLocation`.
+
+use super::{CLLocationAccuracy, CLLocationCoordinate2D, CLLocationDegrees, CLLocationDistance};
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::frameworks::foundation::ns_string;
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
+    NSZonePtr,
+};
+use crate::Environment;
+
+/// Mean radius of the Earth in meters, for distance calculations.
+const EARTH_RADIUS: CLLocationDistance = 6_371_009.0;
+
+struct CLLocationHostObject {
+    coordinate: CLLocationCoordinate2D,
+    altitude: CLLocationDistance,
+    horizontal_accuracy: CLLocationAccuracy,
+    vertical_accuracy: CLLocationAccuracy,
+    /// `NSDate*`
+    timestamp: id,
+}
+impl HostObject for CLLocationHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation CLLocation: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(CLLocationHostObject {
+        coordinate: CLLocationCoordinate2D::default(),
+        altitude: 0.0,
+        // Negative accuracy means the value is invalid.
+        horizontal_accuracy: -1.0,
+        vertical_accuracy: -1.0,
+        timestamp: nil,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithLatitude:(CLLocationDegrees)latitude
+             longitude:(CLLocationDegrees)longitude {
+    let coordinate = CLLocationCoordinate2D { latitude, longitude };
+    let timestamp: id = msg_class![env; NSDate date];
+    msg![env; this initWithCoordinate:coordinate
+                             altitude:(0.0 as CLLocationDistance)
+                   horizontalAccuracy:(0.0 as CLLocationAccuracy)
+                     verticalAccuracy:(-1.0 as CLLocationAccuracy)
+                            timestamp:timestamp]
+}
+
+- (id)initWithCoordinate:(CLLocationCoordinate2D)coordinate
+                altitude:(CLLocationDistance)altitude
+      horizontalAccuracy:(CLLocationAccuracy)horizontal_accuracy
+        verticalAccuracy:(CLLocationAccuracy)vertical_accuracy
+               timestamp:(id)timestamp { // NSDate*
+    retain(env, timestamp);
+    *env.objc.borrow_mut(this) = CLLocationHostObject {
+        coordinate,
+        altitude,
+        horizontal_accuracy,
+        vertical_accuracy,
+        timestamp,
+    };
+    this
+}
+
+- (())dealloc {
+    let timestamp = env.objc.borrow::<CLLocationHostObject>(this).timestamp;
+    release(env, timestamp);
+    env.objc.dealloc_object(this, &mut env.mem);
+}
+
+- (id)copyWithZone:(NSZonePtr)_zone {
+    // Immutable object
+    retain(env, this)
+}
+
+- (CLLocationCoordinate2D)coordinate {
+    env.objc.borrow::<CLLocationHostObject>(this).coordinate
+}
+- (CLLocationDistance)altitude {
+    env.objc.borrow::<CLLocationHostObject>(this).altitude
+}
+- (CLLocationAccuracy)horizontalAccuracy {
+    env.objc.borrow::<CLLocationHostObject>(this).horizontal_accuracy
+}
+- (CLLocationAccuracy)verticalAccuracy {
+    env.objc.borrow::<CLLocationHostObject>(this).vertical_accuracy
+}
+- (id)timestamp {
+    env.objc.borrow::<CLLocationHostObject>(this).timestamp
+}
+// Negative values mean the course and speed are unknown.
+- (f64)course {
+    -1.0
+}
+- (f64)speed {
+    -1.0
+}
+
+- (CLLocationDistance)distanceFromLocation:(id)other { // CLLocation*
+    let a = env.objc.borrow::<CLLocationHostObject>(this).coordinate;
+    let b = env.objc.borrow::<CLLocationHostObject>(other).coordinate;
+    distance_between(a, b)
+}
+// Deprecated name for distanceFromLocation: used by iPhone OS 2 and 3 apps.
+- (CLLocationDistance)getDistanceFrom:(id)other { // CLLocation*
+    msg![env; this distanceFromLocation:other]
+}
+
+- (id)description {
+    let &CLLocationHostObject {
+        coordinate: CLLocationCoordinate2D { latitude, longitude },
+        horizontal_accuracy,
+        ..
+    } = env.objc.borrow(this);
+    let description = format!(
+        "<{:+.8}, {:+.8}> +/- {:.2}m",
+        latitude, longitude, horizontal_accuracy
+    );
+    let description = ns_string::from_rust_string(env, description);
+    autorelease(env, description)
+}
+
+@end
+
+};
+
+/// Great-circle distance between two coordinates, using the haversine formula.
+fn distance_between(a: CLLocationCoordinate2D, b: CLLocationCoordinate2D) -> CLLocationDistance {
+    let (lat_a, lat_b) = (a.latitude.to_radians(), b.latitude.to_radians());
+    let d_lat = lat_b - lat_a;
+    let d_lon = (b.longitude - a.longitude).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * h.sqrt().asin()
+}
+
+fn CLLocationCoordinate2DIsValid(_env: &mut Environment, coord: CLLocationCoordinate2D) -> bool {
+    let CLLocationCoordinate2D {
+        latitude,
+        longitude,
+    } = coord;
+    (-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude)
+}
+
+fn CLLocationCoordinate2DMake(
+    _env: &mut Environment,
+    latitude: CLLocationDegrees,
+    longitude: CLLocationDegrees,
+) -> CLLocationCoordinate2D {
+    CLLocationCoordinate2D {
+        latitude,
+        longitude,
+    }
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(CLLocationCoordinate2DIsValid(_)),
+    export_c_func!(CLLocationCoordinate2DMake(_, _)),
+];
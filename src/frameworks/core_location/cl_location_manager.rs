@@ -0,0 +1,376 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `CLLocationManager`.
+
+use super::{CLLocationAccuracy, CLLocationCoordinate2D, CLLocationDistance};
+use crate::dyld::{ConstantExports, HostConstant};
+use crate::frameworks::foundation::{ns_string, NSInteger};
+use crate::mem::{ConstVoidPtr, Mem};
+use crate::objc::{
+    id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
+};
+use crate::Environment;
+use std::time::{Duration, Instant};
+
+pub const kCLErrorDomain: &str = "kCLErrorDomain";
+
+type CLError = NSInteger;
+const kCLErrorDenied: CLError = 1;
+
+const kCLLocationAccuracyBest: CLLocationAccuracy = -1.0;
+const kCLDistanceFilterNone: CLLocationDistance = -1.0;
+
+/// Accuracy reported for the fixed location.
+const FIXED_LOCATION_ACCURACY: CLLocationAccuracy = 10.0;
+
+/// How often the fixed location is re-sent when there's no distance filter.
+/// The location never changes, so this only matters for apps that wait for
+/// several updates before trusting the location.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+pub const CONSTANTS: ConstantExports = &[
+    ("_kCLErrorDomain", HostConstant::NSString(kCLErrorDomain)),
+    (
+        "_kCLLocationAccuracyBest",
+        HostConstant::Custom(|mem: &mut Mem| -> ConstVoidPtr {
+            mem.alloc_and_write(kCLLocationAccuracyBest)
+                .cast()
+                .cast_const()
+        }),
+    ),
+    (
+        "_kCLLocationAccuracyNearestTenMeters",
+        HostConstant::Custom(|mem: &mut Mem| -> ConstVoidPtr {
+            mem.alloc_and_write(10.0 as CLLocationAccuracy)
+                .cast()
+                .cast_const()
+        }),
+    ),
+    (
+        "_kCLLocationAccuracyHundredMeters",
+        HostConstant::Custom(|mem: &mut Mem| -> ConstVoidPtr {
+            mem.alloc_and_write(100.0 as CLLocationAccuracy)
+                .cast()
+                .cast_const()
+        }),
+    ),
+    (
+        "_kCLLocationAccuracyKilometer",
+        HostConstant::Custom(|mem: &mut Mem| -> ConstVoidPtr {
+            mem.alloc_and_write(1000.0 as CLLocationAccuracy)
+                .cast()
+                .cast_const()
+        }),
+    ),
+    (
+        "_kCLLocationAccuracyThreeKilometers",
+        HostConstant::Custom(|mem: &mut Mem| -> ConstVoidPtr {
+            mem.alloc_and_write(3000.0 as CLLocationAccuracy)
+                .cast()
+                .cast_const()
+        }),
+    ),
+    (
+        "_kCLDistanceFilterNone",
+        HostConstant::Custom(|mem: &mut Mem| -> ConstVoidPtr {
+            mem.alloc_and_write(kCLDistanceFilterNone)
+                .cast()
+                .cast_const()
+        }),
+    ),
+];
+
+#[derive(Default)]
+pub struct State {
+    /// Location managers between `startUpdatingLocation` and
+    /// `stopUpdatingLocation`. Weak references: managers remove themselves
+    /// when deallocated.
+    updating_managers: Vec<id>,
+}
+
+struct CLLocationManagerHostObject {
+    /// Weak reference.
+    delegate: id,
+    desired_accuracy: CLLocationAccuracy,
+    distance_filter: CLLocationDistance,
+    /// When the next update or error should be delivered, if updating.
+    due_by: Option<Instant>,
+    /// `CLLocation*`, the most recently delivered location.
+    location: id,
+}
+impl HostObject for CLLocationManagerHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation CLLocationManager: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(CLLocationManagerHostObject {
+        delegate: nil,
+        desired_accuracy: kCLLocationAccuracyBest,
+        distance_filter: kCLDistanceFilterNone,
+        due_by: None,
+        location: nil,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (bool)locationServicesEnabled {
+    env.options.location.is_some()
+}
+// Deprecated instance method version used by iPhone OS 2 apps.
+- (bool)locationServicesEnabled {
+    env.options.location.is_some()
+}
+
+- (())dealloc {
+    env.framework_state
+        .core_location
+        .cl_location_manager
+        .updating_managers
+        .retain(|&manager| manager != this);
+    let location = env.objc.borrow::<CLLocationManagerHostObject>(this).location;
+    release(env, location);
+    env.objc.dealloc_object(this, &mut env.mem);
+}
+
+- (id)delegate {
+    env.objc.borrow::<CLLocationManagerHostObject>(this).delegate
+}
+- (())setDelegate:(id)delegate { // id<CLLocationManagerDelegate>
+    env.objc.borrow_mut::<CLLocationManagerHostObject>(this).delegate = delegate;
+}
+
+- (CLLocationAccuracy)desiredAccuracy {
+    env.objc.borrow::<CLLocationManagerHostObject>(this).desired_accuracy
+}
+- (())setDesiredAccuracy:(CLLocationAccuracy)accuracy {
+    env.objc.borrow_mut::<CLLocationManagerHostObject>(this).desired_accuracy = accuracy;
+}
+
+- (CLLocationDistance)distanceFilter {
+    env.objc.borrow::<CLLocationManagerHostObject>(this).distance_filter
+}
+- (())setDistanceFilter:(CLLocationDistance)distance {
+    env.objc.borrow_mut::<CLLocationManagerHostObject>(this).distance_filter = distance;
+}
+
+- (id)location {
+    env.objc.borrow::<CLLocationManagerHostObject>(this).location
+}
+
+- (())startUpdatingLocation {
+    log_dbg!("[(CLLocationManager*){:?} startUpdatingLocation]", this);
+    let updating_managers = &mut env.framework_state
+        .core_location
+        .cl_location_manager
+        .updating_managers;
+    if !updating_managers.contains(&this) {
+        updating_managers.push(this);
+    }
+    // Updates are always delivered by the run loop, never from within this
+    // method, like on a real device.
+    let now = env.clock.now();
+    env.objc.borrow_mut::<CLLocationManagerHostObject>(this).due_by = Some(now);
+}
+
+- (())stopUpdatingLocation {
+    log_dbg!("[(CLLocationManager*){:?} stopUpdatingLocation]", this);
+    env.framework_state
+        .core_location
+        .cl_location_manager
+        .updating_managers
+        .retain(|&manager| manager != this);
+    env.objc.borrow_mut::<CLLocationManagerHostObject>(this).due_by = None;
+}
+
+// Heading and significant-change monitoring aren't supported, which apps can
+// check for with these.
++ (bool)headingAvailable {
+    false
+}
++ (bool)significantLocationChangeMonitoringAvailable {
+    false
+}
+
+@end
+
+};
+
+/// Check whether a location manager's delegate implements a callback.
+fn delegate_responds_to(env: &mut Environment, delegate: id, callback: &str) -> bool {
+    if delegate == nil {
+        return false;
+    }
+    let sel = env
+        .objc
+        .register_host_selector(callback.to_string(), &mut env.mem);
+    msg![env; delegate respondsToSelector:sel]
+}
+
+/// Deliver the fixed location, or an error if there isn't one, to a single
+/// location manager's delegate.
+fn deliver_update(env: &mut Environment, manager: id) {
+    let delegate = env
+        .objc
+        .borrow::<CLLocationManagerHostObject>(manager)
+        .delegate;
+
+    let Some((latitude, longitude)) = env.options.location else {
+        // The user "denied" the app access to location services. A real
+        // device sends this once and then stops updating.
+        env.objc
+            .borrow_mut::<CLLocationManagerHostObject>(manager)
+            .due_by = None;
+        env.framework_state
+            .core_location
+            .cl_location_manager
+            .updating_managers
+            .retain(|&m| m != manager);
+
+        if !delegate_responds_to(env, delegate, "locationManager:didFailWithError:") {
+            return;
+        }
+        let domain = ns_string::get_static_str(env, kCLErrorDomain);
+        let error: id = msg_class![env; NSError alloc];
+        let error: id = msg![env; error initWithDomain:domain
+                                                  code:kCLErrorDenied
+                                              userInfo:nil];
+        log_dbg!(
+            "Sending [{:?} locationManager:{:?} didFailWithError:{:?}]",
+            delegate,
+            manager,
+            error
+        );
+        () = msg![env; delegate locationManager:manager didFailWithError:error];
+        release(env, error);
+        return;
+    };
+
+    let distance_filter = env
+        .objc
+        .borrow::<CLLocationManagerHostObject>(manager)
+        .distance_filter;
+    // The location never changes, so with a distance filter, there's never
+    // any reason to send another update.
+    let next_due_by = if distance_filter <= 0.0 {
+        Some(env.clock.now() + UPDATE_INTERVAL)
+    } else {
+        None
+    };
+    env.objc
+        .borrow_mut::<CLLocationManagerHostObject>(manager)
+        .due_by = next_due_by;
+
+    let coordinate = CLLocationCoordinate2D {
+        latitude,
+        longitude,
+    };
+    let timestamp: id = msg_class![env; NSDate date];
+    let new_location: id = msg_class![env; CLLocation alloc];
+    let new_location: id = msg![env; new_location initWithCoordinate:coordinate
+                                                              altitude:(0.0 as CLLocationDistance)
+                                                    horizontalAccuracy:FIXED_LOCATION_ACCURACY
+                                                      verticalAccuracy:(-1.0 as CLLocationAccuracy)
+                                                             timestamp:timestamp];
+    let old_location = std::mem::replace(
+        &mut env
+            .objc
+            .borrow_mut::<CLLocationManagerHostObject>(manager)
+            .location,
+        new_location,
+    );
+
+    if delegate_responds_to(
+        env,
+        delegate,
+        "locationManager:didUpdateToLocation:fromLocation:",
+    ) {
+        log_dbg!(
+            "Sending [{:?} locationManager:{:?} didUpdateToLocation:{:?} fromLocation:{:?}]",
+            delegate,
+            manager,
+            new_location,
+            old_location
+        );
+        () = msg![env; delegate locationManager:manager
+                            didUpdateToLocation:new_location
+                                   fromLocation:old_location];
+    }
+    release(env, old_location);
+}
+
+pub(super) fn handle_location_managers(env: &mut Environment) -> Option<Instant> {
+    if env
+        .framework_state
+        .core_location
+        .cl_location_manager
+        .updating_managers
+        .is_empty()
+    {
+        return None;
+    }
+
+    let now = env.clock.now();
+    let mut next_due_by: Option<Instant> = None;
+    let mut due_managers = Vec::new();
+    for &manager in &env
+        .framework_state
+        .core_location
+        .cl_location_manager
+        .updating_managers
+    {
+        let Some(due_by) = env
+            .objc
+            .borrow::<CLLocationManagerHostObject>(manager)
+            .due_by
+        else {
+            continue;
+        };
+        if due_by <= now {
+            due_managers.push(manager);
+        } else {
+            next_due_by = Some(next_due_by.map_or(due_by, |next| next.min(due_by)));
+        }
+    }
+
+    if due_managers.is_empty() {
+        return next_due_by;
+    }
+
+    // UIKit creates and drains autorelease pools when handling events.
+    let pool: id = msg_class![env; NSAutoreleasePool new];
+
+    for manager in due_managers {
+        // The delegate could release the manager or stop another manager in
+        // its callback.
+        if !env
+            .framework_state
+            .core_location
+            .cl_location_manager
+            .updating_managers
+            .contains(&manager)
+        {
+            continue;
+        }
+        retain(env, manager);
+        deliver_update(env, manager);
+        let due_by = env
+            .objc
+            .borrow::<CLLocationManagerHostObject>(manager)
+            .due_by;
+        release(env, manager);
+        if let Some(due_by) = due_by {
+            next_due_by = Some(next_due_by.map_or(due_by, |next| next.min(due_by)));
+        }
+    }
+
+    release(env, pool);
+
+    next_due_by
+}
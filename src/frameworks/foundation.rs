@@ -18,6 +18,7 @@ use crate::Environment;
 pub mod ns_array;
 pub mod ns_autorelease_pool;
 pub mod ns_bundle;
+pub mod ns_cache;
 pub mod ns_character_set;
 pub mod ns_coder;
 pub mod ns_data;
@@ -29,6 +30,8 @@ pub mod ns_error;
 pub mod ns_exception;
 pub mod ns_file_handle;
 pub mod ns_file_manager;
+pub mod ns_index_path;
+pub mod ns_invocation;
 pub mod ns_keyed_unarchiver;
 pub mod ns_locale;
 pub mod ns_lock;
@@ -36,16 +39,19 @@ pub mod ns_log;
 pub mod ns_notification;
 pub mod ns_notification_center;
 pub mod ns_null;
+pub mod ns_number_formatter;
 pub mod ns_objc_runtime;
 pub mod ns_object;
 pub mod ns_process_info;
 pub mod ns_property_list_serialization;
 pub mod ns_run_loop;
 pub mod ns_set;
+pub mod ns_stream;
 pub mod ns_string;
 pub mod ns_thread;
 pub mod ns_time_zone;
 pub mod ns_timer;
+pub mod ns_undo_manager;
 pub mod ns_url;
 pub mod ns_url_connection;
 pub mod ns_url_request;
@@ -56,12 +62,15 @@ pub mod ns_value;
 pub struct State {
     ns_autorelease_pool: ns_autorelease_pool::State,
     ns_bundle: ns_bundle::State,
+    ns_cache: ns_cache::State,
     ns_file_manager: ns_file_manager::State,
     ns_locale: ns_locale::State,
     ns_notification_center: ns_notification_center::State,
     ns_null: ns_null::State,
+    ns_process_info: ns_process_info::State,
     ns_run_loop: ns_run_loop::State,
     ns_string: ns_string::State,
+    ns_undo_manager: ns_undo_manager::State,
     ns_user_defaults: ns_user_defaults::State,
 }
 
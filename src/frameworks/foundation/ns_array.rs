@@ -7,7 +7,7 @@
 
 use super::ns_enumerator::{fast_enumeration_helper, NSFastEnumerationState};
 use super::ns_property_list_serialization::deserialize_plist_from_file;
-use super::{ns_keyed_unarchiver, ns_string, ns_url, NSNotFound, NSUInteger};
+use super::{ns_exception, ns_keyed_unarchiver, ns_string, ns_url, NSNotFound, NSUInteger};
 use crate::fs::GuestPath;
 use crate::mem::MutPtr;
 use crate::objc::{
@@ -213,7 +213,7 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.borrow::<ArrayHostObject>(this).array.len().try_into().unwrap()
 }
 - (id)objectAtIndex:(NSUInteger)index {
-    // TODO: throw real exception rather than panic if out-of-bounds?
+    check_index(env, this, "objectAtIndex:", index);
     env.objc.borrow::<ArrayHostObject>(this).array[index as usize]
 }
 
@@ -271,7 +271,7 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.borrow::<ArrayHostObject>(this).array.len().try_into().unwrap()
 }
 - (id)objectAtIndex:(NSUInteger)index {
-    // TODO: throw real exception rather than panic if out-of-bounds?
+    check_index(env, this, "objectAtIndex:", index);
     env.objc.borrow::<ArrayHostObject>(this).array[index as usize]
 }
 
@@ -283,6 +283,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (())removeObjectAtIndex:(NSUInteger)index {
+    check_index(env, this, "removeObjectAtIndex:", index);
     let object = env.objc.borrow_mut::<ArrayHostObject>(this).array.remove(index as usize);
     release(env, object)
 }
@@ -324,6 +325,25 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 };
 
+/// Raise `NSRangeException` if `index` is out of range for the array, like
+/// Apple's implementations of `objectAtIndex:` etc. do.
+fn check_index(env: &mut Environment, this: id, method: &str, index: NSUInteger) {
+    let count = env.objc.borrow::<ArrayHostObject>(this).array.len();
+    if (index as usize) < count {
+        return;
+    }
+    let bounds = if count == 0 {
+        "for empty array".to_string()
+    } else {
+        format!("[0 .. {}]", count - 1)
+    };
+    let reason = format!(
+        "*** -[NSArray {}]: index {} beyond bounds {}",
+        method, index, bounds
+    );
+    ns_exception::raise(env, "NSRangeException", reason)
+}
+
 /// Shortcut for host code, roughly equivalent to
 /// `[[NSArray alloc] initWithObjects:count]` but without copying.
 /// The elements should already be "retained by" the `Vec`.
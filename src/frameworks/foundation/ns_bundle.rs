@@ -5,14 +5,14 @@
  */
 //! `NSBundle`.
 
-use super::{ns_string, NSUInteger};
+use super::{ns_array, ns_string, NSUInteger};
 use crate::bundle::Bundle;
 use crate::frameworks::core_foundation::cf_bundle::{
     CFBundleCopyBundleLocalizations, CFBundleCopyPreferredLocalizationsFromArray,
 };
 use crate::frameworks::foundation::ns_string::{from_rust_string, get_static_str, to_rust_string};
 use crate::frameworks::uikit::ui_nib::load_nib_file;
-use crate::fs::GuestPathBuf;
+use crate::fs::{GuestPath, GuestPathBuf};
 use crate::objc::{
     autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
 };
@@ -164,41 +164,26 @@ pub const CLASSES: ClassExports = objc_classes! {
 - (id)pathForResource:(id)name // NSString*
                ofType:(id)extension // NSString*
           inDirectory:(id)directory { // NSString*
-    assert!(name != nil); // TODO
+    if name == nil {
+        // Any resource with the right extension will do.
+        let paths: id = msg![env; this pathsForResourcesOfType:extension
+                                                   inDirectory:directory];
+        let count: NSUInteger = msg![env; paths count];
+        if count == 0 {
+            return nil;
+        }
+        return msg![env; paths objectAtIndex:0u32];
+    }
 
     // TODO: cache result of lookups
 
-    let path = path_for_resource_helper(env, this, name, nil, directory, extension);
-    if path != nil {
-        return path
-    }
-
-    // Try preferred languages in order of preference
-    let langs: id = msg_class![env; NSLocale preferredLanguages];
-    let lang_count: NSUInteger = msg![env; langs count];
-    let mut unknown_codes = HashSet::new();
-    for i in 0..lang_count {
-        let lang_code: id = msg![env; langs objectAtIndex:i];
-        let lang_code = ns_string::to_rust_string(env, lang_code); // TODO: avoid copy
-        if let Some(&(_, lproj)) = LANG_ID_TO_LANG_PROJ.iter().find(|&&(code, _)| code == lang_code) {
-            let lproj: id = ns_string::get_static_str(env, lproj);
-            let localized_path = path_for_resource_helper(env, this, name, lproj, directory, extension);
-            if localized_path != nil {
-                return localized_path;
-            }
-        } else {
-            unknown_codes.insert(lang_code);
+    for lproj in resource_lprojs(env) {
+        let path = path_for_resource_helper(env, this, name, lproj, directory, extension);
+        if path != nil {
+            return path;
         }
     }
-
-    // As a last resort, fallback to English
-    // TODO: fallback to a development language (CFBundleDevelopmentRegion from
-    // Info.plist)
-    if !unknown_codes.is_empty() {
-        log!("TODO: language codes {:?} aren't mapped to a language name, falling back to English", unknown_codes);
-    }
-    let lproj: id = ns_string::get_static_str(env, "English.lproj");
-    path_for_resource_helper(env, this, name, lproj, directory, extension)
+    nil
 }
 - (id)pathForResource:(id)name // NSString*
                ofType:(id)extension { // NSString*
@@ -222,6 +207,30 @@ pub const CLASSES: ClassExports = objc_classes! {
     msg![env; this URLForResource:name withExtension:extension subdirectory:nil]
 }
 
+- (id)pathsForResourcesOfType:(id)extension // NSString*
+                  inDirectory:(id)directory { // NSString*
+    let mut paths = Vec::new();
+    for lproj in resource_lprojs(env) {
+        paths_for_resources_helper(env, this, lproj, directory, extension, &mut paths);
+    }
+    let paths = ns_array::from_vec(env, paths);
+    autorelease(env, paths)
+}
+- (id)URLsForResourcesWithExtension:(id)extension // NSString*
+                       subdirectory:(id)subpath { // NSString*
+    let paths: id = msg![env; this pathsForResourcesOfType:extension
+                                               inDirectory:subpath];
+    let count: NSUInteger = msg![env; paths count];
+    let mut urls = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        let path: id = msg![env; paths objectAtIndex:i];
+        let url: id = msg_class![env; NSURL alloc];
+        urls.push(msg![env; url initFileURLWithPath:path]);
+    }
+    let urls = ns_array::from_vec(env, urls);
+    autorelease(env, urls)
+}
+
 - (id)localizedStringForKey:(id)key
                       value:(id)value
                       table:(id)tableName {
@@ -302,6 +311,43 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 };
 
+/// The localization directories to search for resources in, in order: `nil`
+/// (the non-localized resources), then the user's preferred languages, and
+/// finally English as a last resort.
+fn resource_lprojs(env: &mut Environment) -> Vec<id> {
+    let mut lprojs = vec![nil];
+
+    let langs: id = msg_class![env; NSLocale preferredLanguages];
+    let lang_count: NSUInteger = msg![env; langs count];
+    let mut unknown_codes = HashSet::new();
+    for i in 0..lang_count {
+        let lang_code: id = msg![env; langs objectAtIndex:i];
+        let lang_code = ns_string::to_rust_string(env, lang_code); // TODO: avoid copy
+        if let Some(&(_, lproj)) = LANG_ID_TO_LANG_PROJ
+            .iter()
+            .find(|&&(code, _)| code == lang_code)
+        {
+            lprojs.push(ns_string::get_static_str(env, lproj));
+        } else {
+            unknown_codes.insert(lang_code);
+        }
+    }
+
+    // TODO: fallback to a development language (CFBundleDevelopmentRegion from
+    // Info.plist)
+    if !unknown_codes.is_empty() {
+        log_dbg!(
+            "TODO: language codes {:?} aren't mapped to a language name, falling back to English",
+            unknown_codes
+        );
+    }
+    let english = ns_string::get_static_str(env, "English.lproj");
+    if !lprojs.contains(&english) {
+        lprojs.push(english);
+    }
+    lprojs
+}
+
 fn path_for_resource_helper(
     env: &mut Environment,
     bundle: id,
@@ -328,3 +374,47 @@ fn path_for_resource_helper(
     }
     nil
 }
+
+/// Append the paths of the files in a resource directory that have a
+/// particular extension (or any extension if it is `nil`) to `paths`.
+fn paths_for_resources_helper(
+    env: &mut Environment,
+    bundle: id,
+    lproj: id,
+    directory: id,
+    extension: id,
+    paths: &mut Vec<id>,
+) {
+    let mut dir_path: id = msg![env; bundle resourcePath];
+    if lproj != nil {
+        dir_path = msg![env; dir_path stringByAppendingPathComponent:lproj];
+    }
+    if directory != nil {
+        dir_path = msg![env; dir_path stringByAppendingPathComponent:directory];
+    }
+    let dir_path_str = ns_string::to_rust_string(env, dir_path); // TODO: avoid copy
+    let Ok(names) = env.fs.enumerate(GuestPath::new(&dir_path_str)) else {
+        return;
+    };
+    let mut names: Vec<String> = names.map(|name| name.to_string()).collect();
+    names.sort();
+    let extension = (extension != nil).then(|| ns_string::to_rust_string(env, extension));
+    for name in names {
+        if let Some(ref extension) = extension {
+            if extension.is_empty() {
+                if name.contains('.') {
+                    continue;
+                }
+            } else if !name
+                .strip_suffix(extension.as_str())
+                .is_some_and(|stem| stem.ends_with('.'))
+            {
+                continue;
+            }
+        }
+        let name = ns_string::from_rust_string(env, name);
+        let path: id = msg![env; dir_path stringByAppendingPathComponent:name];
+        release(env, name);
+        paths.push(retain(env, path));
+    }
+}
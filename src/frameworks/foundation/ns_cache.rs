@@ -0,0 +1,280 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSCache`.
+//!
+//! Apple don't document the eviction policy. This implementation evicts the
+//! least recently used objects first, and evicts everything when there's a
+//! memory warning (see [evict_all_caches]).
+
+use super::NSUInteger;
+use crate::objc::{
+    id, msg, nil, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
+};
+use crate::Environment;
+
+/// Alias for the return type of the `hash` method of the `NSObject` protocol.
+type Hash = NSUInteger;
+
+#[derive(Default)]
+pub struct State {
+    /// All the caches that currently exist, so they can be emptied when there's
+    /// a memory warning. These are weak references.
+    caches: Vec<id>,
+}
+
+struct CacheEntry {
+    hash: Hash,
+    /// Strong reference. Unlike with `NSMutableDictionary`, keys aren't copied.
+    key: id,
+    /// Strong reference.
+    object: id,
+    cost: NSUInteger,
+}
+
+#[derive(Default)]
+struct NSCacheHostObject {
+    /// Least recently used first.
+    entries: Vec<CacheEntry>,
+    total_cost: NSUInteger,
+    /// 0 means no limit.
+    count_limit: NSUInteger,
+    /// 0 means no limit.
+    total_cost_limit: NSUInteger,
+    /// Weak reference.
+    delegate: id,
+    /// `NSString*`, strong reference.
+    name: id,
+    evicts_objects_with_discarded_content: bool,
+}
+impl HostObject for NSCacheHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSCache: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(NSCacheHostObject {
+        evicts_objects_with_discarded_content: true,
+        ..Default::default()
+    });
+    let new = env.objc.alloc_object(this, host_object, &mut env.mem);
+    env.framework_state.foundation.ns_cache.caches.push(new);
+    new
+}
+
+- (())dealloc {
+    let caches = &mut env.framework_state.foundation.ns_cache.caches;
+    caches.swap_remove(caches.iter().position(|&cache| cache == this).unwrap());
+
+    let host_obj = env.objc.borrow_mut::<NSCacheHostObject>(this);
+    let entries = std::mem::take(&mut host_obj.entries);
+    let name = host_obj.name;
+    // The delegate isn't told about objects that are released because the
+    // cache itself is going away.
+    for CacheEntry { key, object, .. } in entries {
+        release(env, key);
+        release(env, object);
+    }
+    release(env, name);
+
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (id)delegate {
+    env.objc.borrow::<NSCacheHostObject>(this).delegate
+}
+- (())setDelegate:(id)delegate {
+    env.objc.borrow_mut::<NSCacheHostObject>(this).delegate = delegate;
+}
+
+- (id)name {
+    env.objc.borrow::<NSCacheHostObject>(this).name
+}
+- (())setName:(id)new_name { // NSString*
+    let new_name: id = msg![env; new_name copy];
+    let old_name = std::mem::replace(
+        &mut env.objc.borrow_mut::<NSCacheHostObject>(this).name,
+        new_name,
+    );
+    release(env, old_name);
+}
+
+- (NSUInteger)countLimit {
+    env.objc.borrow::<NSCacheHostObject>(this).count_limit
+}
+- (())setCountLimit:(NSUInteger)limit {
+    env.objc.borrow_mut::<NSCacheHostObject>(this).count_limit = limit;
+    evict_to_limits(env, this);
+}
+
+- (NSUInteger)totalCostLimit {
+    env.objc.borrow::<NSCacheHostObject>(this).total_cost_limit
+}
+- (())setTotalCostLimit:(NSUInteger)limit {
+    env.objc.borrow_mut::<NSCacheHostObject>(this).total_cost_limit = limit;
+    evict_to_limits(env, this);
+}
+
+// This only matters for objects implementing NSDiscardableContent, which
+// touchHLE doesn't have any of, but the property still has to exist.
+- (bool)evictsObjectsWithDiscardedContent {
+    env.objc.borrow::<NSCacheHostObject>(this).evicts_objects_with_discarded_content
+}
+- (())setEvictsObjectsWithDiscardedContent:(bool)evicts {
+    env.objc.borrow_mut::<NSCacheHostObject>(this).evicts_objects_with_discarded_content = evicts;
+}
+
+- (id)objectForKey:(id)key {
+    let Some(index) = find_entry(env, this, key) else {
+        return nil;
+    };
+    // Mark as the most recently used.
+    let host_obj = env.objc.borrow_mut::<NSCacheHostObject>(this);
+    let entry = host_obj.entries.remove(index);
+    let object = entry.object;
+    host_obj.entries.push(entry);
+    object
+}
+
+- (())setObject:(id)object
+         forKey:(id)key {
+    msg![env; this setObject:object forKey:key cost:0u32]
+}
+- (())setObject:(id)object
+         forKey:(id)key
+           cost:(NSUInteger)cost {
+    // Unlike NSMutableDictionary, this is silently ignored.
+    if object == nil || key == nil {
+        return;
+    }
+
+    // Replacing an object isn't an eviction, so the delegate isn't told.
+    if let Some(index) = find_entry(env, this, key) {
+        remove_entry(env, this, index, /* notify: */ false);
+    }
+
+    let hash: Hash = msg![env; key hash];
+    retain(env, key);
+    retain(env, object);
+    let host_obj = env.objc.borrow_mut::<NSCacheHostObject>(this);
+    host_obj.entries.push(CacheEntry {
+        hash,
+        key,
+        object,
+        cost,
+    });
+    host_obj.total_cost = host_obj.total_cost.saturating_add(cost);
+
+    evict_to_limits(env, this);
+}
+
+- (())removeObjectForKey:(id)key {
+    if let Some(index) = find_entry(env, this, key) {
+        remove_entry(env, this, index, /* notify: */ true);
+    }
+}
+
+- (())removeAllObjects {
+    while !env.objc.borrow::<NSCacheHostObject>(this).entries.is_empty() {
+        remove_entry(env, this, 0, /* notify: */ true);
+    }
+}
+
+@end
+
+};
+
+/// Find the index of the entry with a key equal to `key`, if any.
+fn find_entry(env: &mut Environment, cache: id, key: id) -> Option<usize> {
+    if key == nil {
+        return None;
+    }
+    let hash: Hash = msg![env; key hash];
+    let mut index = 0;
+    // The entries can't be borrowed while sending isEqual:, so this has to
+    // look them up by index every time.
+    while let Some(entry) = env
+        .objc
+        .borrow::<NSCacheHostObject>(cache)
+        .entries
+        .get(index)
+    {
+        let candidate_key = entry.key;
+        if entry.hash == hash && (candidate_key == key || msg![env; candidate_key isEqual:key]) {
+            return Some(index);
+        }
+        index += 1;
+    }
+    None
+}
+
+/// Remove an entry, telling the delegate about it first if `notify` is true.
+fn remove_entry(env: &mut Environment, cache: id, index: usize, notify: bool) {
+    let host_obj = env.objc.borrow::<NSCacheHostObject>(cache);
+    let delegate = host_obj.delegate;
+    let &CacheEntry { key, object, .. } = &host_obj.entries[index];
+    if notify
+        && delegate != nil
+        && env
+            .objc
+            .object_has_method_named(&env.mem, delegate, "cache:willEvictObject:")
+    {
+        () = msg![env; delegate cache:cache willEvictObject:object];
+    }
+
+    // The delegate might have changed the cache, so find the entry again.
+    let host_obj = env.objc.borrow_mut::<NSCacheHostObject>(cache);
+    let Some(index) = host_obj.entries.iter().position(|entry| entry.key == key) else {
+        return;
+    };
+    let CacheEntry {
+        key, object, cost, ..
+    } = host_obj.entries.remove(index);
+    host_obj.total_cost = host_obj.total_cost.saturating_sub(cost);
+    release(env, key);
+    release(env, object);
+}
+
+/// Evict the least recently used objects until the cache is within its limits.
+/// The most recently used object is never evicted.
+fn evict_to_limits(env: &mut Environment, cache: id) {
+    loop {
+        let &NSCacheHostObject {
+            ref entries,
+            total_cost,
+            count_limit,
+            total_cost_limit,
+            ..
+        } = env.objc.borrow(cache);
+        let over_count = count_limit != 0 && entries.len() > count_limit as usize;
+        let over_cost = total_cost_limit != 0 && total_cost > total_cost_limit;
+        if entries.len() <= 1 || !(over_count || over_cost) {
+            break;
+        }
+        remove_entry(env, cache, 0, /* notify: */ true);
+    }
+}
+
+/// For use when there's a memory warning: empty every `NSCache`, like the real
+/// system does under memory pressure.
+pub fn evict_all_caches(env: &mut Environment) {
+    let caches = env.framework_state.foundation.ns_cache.caches.clone();
+    for cache in caches {
+        // An eviction delegate could release another cache.
+        if !env
+            .framework_state
+            .foundation
+            .ns_cache
+            .caches
+            .contains(&cache)
+        {
+            continue;
+        }
+        () = msg![env; cache removeAllObjects];
+    }
+}
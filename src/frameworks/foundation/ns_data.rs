@@ -240,3 +240,21 @@ pub fn to_rust_slice(env: &mut Environment, data: id) -> &[u8] {
     env.mem
         .bytes_at(borrowed_data.bytes.cast(), borrowed_data.length)
 }
+
+/// Shortcut for host code, roughly equivalent to
+/// `[[NSData alloc] initWithBytes:length:]` with a copy of `bytes`.
+pub fn from_vec(env: &mut Environment, bytes: Vec<u8>) -> id {
+    let data: id = msg_class![env; NSData alloc];
+    if bytes.is_empty() {
+        return data;
+    }
+    let length: NSUInteger = bytes.len().try_into().unwrap();
+    let alloc = env.mem.alloc(length);
+    env.mem
+        .bytes_at_mut(alloc.cast(), length)
+        .copy_from_slice(&bytes);
+    let host_object = env.objc.borrow_mut::<NSDataHostObject>(data);
+    host_object.bytes = alloc;
+    host_object.length = length;
+    data
+}
@@ -25,7 +25,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 @implementation NSDate: NSObject
 
 + (NSTimeInterval)timeIntervalSinceReferenceDate {
-    SystemTime::now()
+    env.clock.system_now()
         .duration_since(apple_epoch())
         .unwrap()
         .as_secs_f64()
@@ -34,7 +34,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 + (id)date {
     // "Date objects are immutable, representing an invariant time interval
     // relative to an absolute reference date (00:00:00 UTC on 1 January 2001)."
-    let time_interval = SystemTime::now()
+    let time_interval = env.clock.system_now()
         .duration_since(apple_epoch())
         .unwrap()
         .as_secs_f64();
@@ -46,6 +46,14 @@ pub const CLASSES: ClassExports = objc_classes! {
     autorelease(env, new)
 }
 
++ (id)dateWithTimeIntervalSinceReferenceDate:(NSTimeInterval)time_interval {
+    let host_object = Box::new(NSDateHostObject {
+        time_interval
+    });
+    let new = env.objc.alloc_object(this, host_object, &mut env.mem);
+    autorelease(env, new)
+}
+
 - (NSTimeInterval)timeIntervalSinceDate:(id)anotherDate {
     assert!(!anotherDate.is_null());
     let host_object = env.objc.borrow::<NSDateHostObject>(this);
@@ -61,7 +69,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 - (NSTimeInterval)timeIntervalSinceNow {
     let host_object = env.objc.borrow::<NSDateHostObject>(this);
-    let time_interval = SystemTime::now()
+    let time_interval = env.clock.system_now()
         .duration_since(apple_epoch())
         .unwrap()
         .as_secs_f64();
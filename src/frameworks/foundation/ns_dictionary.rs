@@ -6,7 +6,7 @@
 //! The `NSDictionary` class cluster, including `NSMutableDictionary`.
 
 use super::ns_property_list_serialization::deserialize_plist_from_file;
-use super::{ns_string, ns_url, NSUInteger};
+use super::{ns_exception, ns_string, ns_url, NSUInteger};
 use crate::abi::VaList;
 use crate::frameworks::foundation::ns_string::{from_rust_string, to_rust_string};
 use crate::fs::GuestPath;
@@ -93,7 +93,9 @@ pub fn init_with_objects_and_keys(
     mut va_args: VaList,
 ) -> id {
     let first_key: id = va_args.next(env);
-    assert!(first_key != nil); // TODO: raise proper exception
+    if first_key == nil {
+        raise_nil_key_in_pair(env);
+    }
 
     let mut host_object = <DictionaryHostObject as Default>::default();
     host_object.insert(env, first_key, first_object, /* copy_key: */ true);
@@ -104,7 +106,9 @@ pub fn init_with_objects_and_keys(
             break;
         }
         let key: id = va_args.next(env);
-        assert!(key != nil); // TODO: raise proper exception
+        if key == nil {
+            raise_nil_key_in_pair(env);
+        }
         host_object.insert(env, key, object, /* copy_key: */ true);
     }
 
@@ -113,6 +117,16 @@ pub fn init_with_objects_and_keys(
     this
 }
 
+fn raise_nil_key_in_pair(env: &mut Environment) -> ! {
+    ns_exception::raise(
+        env,
+        "NSInvalidArgumentException",
+        "+[NSDictionary dictionaryWithObjectsAndKeys:]: second object of each pair must be \
+         non-nil"
+            .to_string(),
+    )
+}
+
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
@@ -140,7 +154,13 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 + (id)dictionaryWithObject:(id)object forKey:(id)key {
-    assert_ne!(key, nil); // TODO: raise proper exception
+    if key == nil {
+        ns_exception::raise(
+            env,
+            "NSInvalidArgumentException",
+            "*** +[NSDictionary dictionaryWithObject:forKey:]: key cannot be nil".to_string(),
+        );
+    }
 
     let new_dict = dict_from_keys_and_objects(env, &[(key, object)]);
     autorelease(env, new_dict)
@@ -341,10 +361,20 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 - (())setObject:(id)object
          forKey:(id)key {
-    // TODO: raise NSInvalidArgumentException
-    assert_ne!(object, nil);
-    // TODO: raise NSInvalidArgumentException
-    assert_ne!(key, nil);
+    if object == nil {
+        ns_exception::raise(
+            env,
+            "NSInvalidArgumentException",
+            "*** -[NSMutableDictionary setObject:forKey:]: object cannot be nil".to_string(),
+        );
+    }
+    if key == nil {
+        ns_exception::raise(
+            env,
+            "NSInvalidArgumentException",
+            "*** -[NSMutableDictionary setObject:forKey:]: key cannot be nil".to_string(),
+        );
+    }
     let mut host_obj: DictionaryHostObject = std::mem::take(env.objc.borrow_mut(this));
     host_obj.insert(env, object, key, /* copy_key: */ true);
     *env.objc.borrow_mut(this) = host_obj;
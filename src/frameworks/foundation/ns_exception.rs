@@ -3,9 +3,18 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
+//! `NSException` and related functions.
+//!
+//! The machinery for throwing and catching exceptions is part of the
+//! Objective-C runtime, see [crate::objc::throw_exception].
 
+use super::{ns_array, ns_string};
+use crate::abi::{GuestFunction, VaList};
 use crate::dyld::{ConstantExports, FunctionExports, HostConstant};
-use crate::mem::MutVoidPtr;
+use crate::objc::{
+    autorelease, id, msg, nil, objc_classes, objc_setUncaughtExceptionHandler, release,
+    throw_exception, Class, ClassExports, HostObject, NSZonePtr,
+};
 use crate::{export_c_func, Environment};
 
 // All constants are NSExceptionName
@@ -276,13 +285,145 @@ pub const CONSTANTS: ConstantExports = &[
     ),
 ];
 
-/// This exception handler is supposed to do last-minute logging before the
-/// program terminates. For our purposes, it's completely safe to ignore that.
-fn NSSetUncaughtExceptionHandler(_env: &mut Environment, handler: MutVoidPtr) {
-    log!(
-        "TODO: Ignoring uncaught exception handler at address {:?}",
-        handler
-    );
+struct NSExceptionHostObject {
+    /// `NSString*`
+    name: id,
+    /// `NSString*`
+    reason: id,
+    /// `NSDictionary*`
+    user_info: id,
 }
+impl HostObject for NSExceptionHostObject {}
 
-pub const FUNCTIONS: FunctionExports = &[export_c_func!(NSSetUncaughtExceptionHandler(_))];
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSException: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(NSExceptionHostObject {
+        name: nil,
+        reason: nil,
+        user_info: nil,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)exceptionWithName:(id)name // NSString*
+                 reason:(id)reason // NSString*
+               userInfo:(id)user_info { // NSDictionary*
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithName:name reason:reason userInfo:user_info];
+    autorelease(env, new)
+}
+
++ (())raise:(id)name // NSString*
+     format:(id)format, // NSString*
+            ...args {
+    let reason = ns_string::with_format(env, format, args.start());
+    raise_with_class(env, this, name, reason)
+}
+
++ (())raise:(id)name // NSString*
+     format:(id)format // NSString*
+  arguments:(VaList)args {
+    let reason = ns_string::with_format(env, format, args);
+    raise_with_class(env, this, name, reason)
+}
+
+- (id)initWithName:(id)name // NSString*
+            reason:(id)reason // NSString*
+          userInfo:(id)user_info { // NSDictionary*
+    let name: id = msg![env; name copy];
+    let reason: id = msg![env; reason copy];
+    let user_info: id = msg![env; user_info copy];
+    *env.objc.borrow_mut::<NSExceptionHostObject>(this) = NSExceptionHostObject {
+        name,
+        reason,
+        user_info,
+    };
+    this
+}
+
+- (())dealloc {
+    let &NSExceptionHostObject {
+        name,
+        reason,
+        user_info,
+    } = env.objc.borrow(this);
+    release(env, name);
+    release(env, reason);
+    release(env, user_info);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (id)copyWithZone:(NSZonePtr)_zone {
+    // Exceptions are immutable.
+    msg![env; this retain]
+}
+
+- (id)name {
+    env.objc.borrow::<NSExceptionHostObject>(this).name
+}
+- (id)reason {
+    env.objc.borrow::<NSExceptionHostObject>(this).reason
+}
+- (id)userInfo {
+    env.objc.borrow::<NSExceptionHostObject>(this).user_info
+}
+
+- (id)callStackReturnAddresses {
+    // TODO: Record the guest call stack when raising?
+    let array = ns_array::from_vec(env, Vec::new());
+    autorelease(env, array)
+}
+
+- (id)description {
+    env.objc.borrow::<NSExceptionHostObject>(this).reason
+}
+
+- (())raise {
+    throw_exception(env, this)
+}
+
+@end
+
+};
+
+fn raise_with_class(env: &mut Environment, class: Class, name: id, reason: String) -> ! {
+    let reason = ns_string::from_rust_string(env, reason);
+    autorelease(env, reason);
+    let exception: id = msg![env; class exceptionWithName:name reason:reason userInfo:nil];
+    () = msg![env; exception raise];
+    // Guest subclasses could override -raise and return.
+    throw_exception(env, exception)
+}
+
+/// Raise an `NSException` from host code, like `+[NSException raise:format:]`
+/// does. `name` should be one of the exception names in [CONSTANTS] (without
+/// the underscore), so that guest code comparing it to the constant works.
+pub fn raise(env: &mut Environment, name: &'static str, reason: String) -> ! {
+    log_dbg!("Raising {}: {}", name, reason);
+    let name = ns_string::get_static_str(env, name);
+    let class = env.objc.get_known_class("NSException", &mut env.mem);
+    raise_with_class(env, class, name, reason)
+}
+
+/// The handler is called before the app is terminated due to an uncaught
+/// exception, so that it can do some last-minute logging.
+fn NSSetUncaughtExceptionHandler(env: &mut Environment, handler: GuestFunction) {
+    objc_setUncaughtExceptionHandler(env, handler);
+}
+
+fn NSGetUncaughtExceptionHandler(env: &mut Environment) -> GuestFunction {
+    // There's no getter in the runtime, but setting returns the old value.
+    let handler = objc_setUncaughtExceptionHandler(env, GuestFunction::from_addr_with_thumb_bit(0));
+    objc_setUncaughtExceptionHandler(env, handler);
+    handler
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(NSSetUncaughtExceptionHandler(_)),
+    export_c_func!(NSGetUncaughtExceptionHandler()),
+];
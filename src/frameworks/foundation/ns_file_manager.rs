@@ -86,7 +86,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (bool)changeCurrentDirectoryPath:(id)path {
-    let path = ns_string::to_rust_string(env, path); // TODO: avoid copy
+    let path = ns_string::to_rust_str(&env.objc, path);
     let path = GuestPath::new(&path);
     match env.fs.change_working_directory(path) {
         Ok(_) => true,
@@ -98,7 +98,7 @@ pub const CLASSES: ClassExports = objc_classes! {
     let res_exists = if path == nil {
         false
     } else {
-        let path = ns_string::to_rust_string(env, path); // TODO: avoid copy
+        let path = ns_string::to_rust_str(&env.objc, path);
         // fileExistsAtPath: will return true for directories
         // hence Fs::exists() rather than Fs::is_file() is appropriate.
         env.fs.exists(GuestPath::new(&path))
@@ -113,7 +113,7 @@ pub const CLASSES: ClassExports = objc_classes! {
         (false, false)
     } else {
         // TODO: mutualize with fileExistsAtPath:
-        let path = ns_string::to_rust_string(env, path); // TODO: avoid copy
+        let path = ns_string::to_rust_str(&env.objc, path);
         let guest_path = GuestPath::new(&path);
         (env.fs.exists(guest_path), !env.fs.is_file(guest_path))
     };
@@ -131,7 +131,7 @@ pub const CLASSES: ClassExports = objc_classes! {
               attributes:(id)attributes { // NSDictionary*
     assert!(attributes == nil); // TODO
 
-    let path_str = ns_string::to_rust_string(env, path); // TODO: avoid copy
+    let path_str = ns_string::to_rust_str(&env.objc, path);
     // createFileAtPath: returns true if there's already a file at a given path.
     // If there's a directory, that's an error, though.
     if env.fs.is_file(GuestPath::new(&path_str)) {
@@ -0,0 +1,140 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSIndexPath`, including UIKit's `row` and `section` additions.
+
+use super::{
+    NSComparisonResult, NSNotFound, NSOrderedAscending, NSOrderedDescending, NSOrderedSame,
+    NSUInteger,
+};
+use crate::mem::ConstPtr;
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, retain, Class, ClassExports, HostObject,
+    NSZonePtr,
+};
+use crate::Environment;
+use std::cmp::Ordering;
+
+struct NSIndexPathHostObject {
+    indexes: Vec<NSUInteger>,
+}
+impl HostObject for NSIndexPathHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSIndexPath: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(NSIndexPathHostObject {
+        indexes: Vec::new(),
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)indexPathWithIndex:(NSUInteger)index {
+    let new: id = msg![env; this alloc];
+    env.objc.borrow_mut::<NSIndexPathHostObject>(new).indexes = vec![index];
+    autorelease(env, new)
+}
+
++ (id)indexPathWithIndexes:(ConstPtr<NSUInteger>)indexes
+                    length:(NSUInteger)length {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithIndexes:indexes length:length];
+    autorelease(env, new)
+}
+
+// UIKit addition
++ (id)indexPathForRow:(NSUInteger)row
+            inSection:(NSUInteger)section {
+    let new: id = msg![env; this alloc];
+    env.objc.borrow_mut::<NSIndexPathHostObject>(new).indexes = vec![section, row];
+    autorelease(env, new)
+}
+
+- (id)initWithIndexes:(ConstPtr<NSUInteger>)indexes
+               length:(NSUInteger)length {
+    let indexes = (0..length).map(|i| env.mem.read(indexes + i)).collect();
+    env.objc.borrow_mut::<NSIndexPathHostObject>(this).indexes = indexes;
+    this
+}
+
+- (id)copyWithZone:(NSZonePtr)_zone {
+    // This is an immutable type.
+    retain(env, this)
+}
+
+- (NSUInteger)length {
+    env.objc.borrow::<NSIndexPathHostObject>(this).indexes.len().try_into().unwrap()
+}
+
+- (NSUInteger)indexAtPosition:(NSUInteger)position {
+    let indexes = &env.objc.borrow::<NSIndexPathHostObject>(this).indexes;
+    indexes.get(position as usize).copied().unwrap_or(NSNotFound as NSUInteger)
+}
+
+- (id)indexPathByAddingIndex:(NSUInteger)index {
+    let mut indexes = env.objc.borrow::<NSIndexPathHostObject>(this).indexes.clone();
+    indexes.push(index);
+    let new: id = msg_class![env; NSIndexPath alloc];
+    env.objc.borrow_mut::<NSIndexPathHostObject>(new).indexes = indexes;
+    autorelease(env, new)
+}
+
+// UIKit addition
+- (NSUInteger)section {
+    msg![env; this indexAtPosition:0u32]
+}
+// UIKit addition
+- (NSUInteger)row {
+    msg![env; this indexAtPosition:1u32]
+}
+
+- (NSComparisonResult)compare:(id)other { // NSIndexPath*
+    let a = &env.objc.borrow::<NSIndexPathHostObject>(this).indexes;
+    let b = &env.objc.borrow::<NSIndexPathHostObject>(other).indexes;
+    match a.cmp(b) {
+        Ordering::Less => NSOrderedAscending,
+        Ordering::Equal => NSOrderedSame,
+        Ordering::Greater => NSOrderedDescending,
+    }
+}
+
+- (bool)isEqual:(id)other {
+    if this == other {
+        return true;
+    }
+    if other == nil {
+        return false;
+    }
+    let class: Class = msg_class![env; NSIndexPath class];
+    if !msg![env; other isKindOfClass:class] {
+        return false;
+    }
+    let a = &env.objc.borrow::<NSIndexPathHostObject>(this).indexes;
+    let b = &env.objc.borrow::<NSIndexPathHostObject>(other).indexes;
+    a == b
+}
+
+- (NSUInteger)hash {
+    super::hash_helper(&env.objc.borrow::<NSIndexPathHostObject>(this).indexes)
+}
+
+@end
+
+};
+
+/// Create an `NSIndexPath*` for a table view row. The result is autoreleased.
+pub fn index_path_for_row(env: &mut Environment, section: NSUInteger, row: NSUInteger) -> id {
+    msg_class![env; NSIndexPath indexPathForRow:row inSection:section]
+}
+
+/// Get the section and row from an `NSIndexPath*` for a table view row.
+pub fn to_section_and_row(env: &mut Environment, index_path: id) -> (NSUInteger, NSUInteger) {
+    let indexes = &env.objc.borrow::<NSIndexPathHostObject>(index_path).indexes;
+    (indexes[0], indexes[1])
+}
@@ -0,0 +1,379 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSMethodSignature` and `NSInvocation`.
+//!
+//! These are also used by `objc_msgSend` for message forwarding, see
+//! [crate::objc]'s `forwarding` module.
+//!
+//! Resources:
+//! - Apple's [Message Forwarding](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/ObjCRuntimeGuide/Articles/ocrtForwarding.html)
+
+use super::{ns_exception, NSInteger, NSUInteger};
+use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, MutPtr, MutVoidPtr, Ptr};
+use crate::objc::{
+    autorelease, id, msg, nil, objc_classes, release, retain, split_type_encoding, ClassExports,
+    EncodedType, ForwardedMessage, HostObject, MethodSignature, ValueKind, SEL,
+};
+use crate::Environment;
+
+struct NSMethodSignatureHostObject {
+    /// The return type followed by the argument types, including `self` and
+    /// `_cmd`.
+    types: Vec<EncodedType>,
+    signature: MethodSignature,
+    /// Guest copies of the encodings in `types`, for `methodReturnType` and
+    /// `getArgumentTypeAtIndex:`.
+    type_encodings: Vec<MutPtr<u8>>,
+}
+impl HostObject for NSMethodSignatureHostObject {}
+
+struct NSInvocationHostObject {
+    /// `NSMethodSignature*`, strong reference.
+    signature: id,
+    /// The value of each argument (including `self` and `_cmd`) in the form it
+    /// is passed in registers or on the stack, i.e. padded to whole words.
+    args: Vec<Vec<u8>>,
+    return_value: Vec<u8>,
+    /// If this is set, the target and any object arguments are strong
+    /// references. C string arguments aren't copied, unlike on iOS.
+    arguments_retained: bool,
+}
+impl HostObject for NSInvocationHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSMethodSignature: NSObject
+
++ (id)signatureWithObjCTypes:(ConstPtr<u8>)types {
+    let encoding = env.mem.cstr_at_utf8(types).unwrap().to_string();
+    match new_method_signature(env, &encoding) {
+        Some(signature) => autorelease(env, signature),
+        None => {
+            // TODO: raise NSInvalidArgumentException
+            log!("Warning: couldn't parse type encoding {:?}, returning nil", encoding);
+            nil
+        }
+    }
+}
+
+- (())dealloc {
+    let type_encodings = std::mem::take(
+        &mut env.objc.borrow_mut::<NSMethodSignatureHostObject>(this).type_encodings
+    );
+    for type_encoding in type_encodings {
+        env.mem.free(type_encoding.cast());
+    }
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (NSUInteger)numberOfArguments {
+    (env.objc.borrow::<NSMethodSignatureHostObject>(this).types.len() - 1) as NSUInteger
+}
+
+- (ConstPtr<u8>)getArgumentTypeAtIndex:(NSUInteger)index {
+    let count = env.objc.borrow::<NSMethodSignatureHostObject>(this).types.len() - 1;
+    if index as usize >= count {
+        let reason = format!(
+            "-[NSMethodSignature getArgumentTypeAtIndex:]: index ({}) out of bounds [0, {}]",
+            index,
+            count as isize - 1,
+        );
+        ns_exception::raise(env, "NSInvalidArgumentException", reason);
+    }
+    env.objc.borrow::<NSMethodSignatureHostObject>(this).type_encodings[index as usize + 1]
+        .cast_const()
+}
+
+- (ConstPtr<u8>)methodReturnType {
+    env.objc.borrow::<NSMethodSignatureHostObject>(this).type_encodings[0].cast_const()
+}
+
+- (NSUInteger)methodReturnLength {
+    env.objc.borrow::<NSMethodSignatureHostObject>(this).types[0].size
+}
+
+- (NSUInteger)frameLength {
+    let host_object = env.objc.borrow::<NSMethodSignatureHostObject>(this);
+    host_object.types[1..].iter().map(|ty| ty.kind.word_count() as NSUInteger * 4).sum()
+}
+
+- (bool)isOneway {
+    env.objc.borrow::<NSMethodSignatureHostObject>(this).types[0].encoding.starts_with('V')
+}
+
+@end
+
+@implementation NSInvocation: NSObject
+
++ (id)invocationWithMethodSignature:(id)signature { // NSMethodSignature*
+    if signature == nil {
+        ns_exception::raise(
+            env,
+            "NSInvalidArgumentException",
+            "+[NSInvocation invocationWithMethodSignature:]: method signature argument cannot be \
+             nil".to_string(),
+        );
+    }
+    let types = &env.objc.borrow::<NSMethodSignatureHostObject>(signature).types;
+    let args = types[1..]
+        .iter()
+        .map(|ty| vec![0; ty.kind.word_count() * 4])
+        .collect();
+    let return_value = vec![0; types[0].size as usize];
+    retain(env, signature);
+    let host_object = Box::new(NSInvocationHostObject {
+        signature,
+        args,
+        return_value,
+        arguments_retained: false,
+    });
+    let invocation = env.objc.alloc_object(this, host_object, &mut env.mem);
+    autorelease(env, invocation)
+}
+
+- (())dealloc {
+    let &NSInvocationHostObject {
+        signature,
+        arguments_retained,
+        ..
+    } = env.objc.borrow(this);
+    if arguments_retained {
+        for object in object_args(env, this) {
+            release(env, object);
+        }
+    }
+    release(env, signature);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (id)methodSignature {
+    env.objc.borrow::<NSInvocationHostObject>(this).signature
+}
+
+- (id)target {
+    Ptr::from_bits(arg_word(env, this, 0))
+}
+- (())setTarget:(id)target {
+    set_arg(env, this, 0, target.to_bits().to_le_bytes().to_vec());
+}
+
+- (SEL)selector {
+    Ptr::from_bits(arg_word(env, this, 1))
+}
+- (())setSelector:(SEL)selector {
+    set_arg(env, this, 1, selector.to_bits().to_le_bytes().to_vec());
+}
+
+- (())getArgument:(MutVoidPtr)buffer
+          atIndex:(NSInteger)index {
+    let index = checked_arg_index(env, this, "getArgument:atIndex:", index);
+    let size = arg_type(env, this, index).size;
+    let arg = &env.objc.borrow::<NSInvocationHostObject>(this).args[index];
+    let value = arg[..size as usize].to_vec();
+    env.mem.bytes_at_mut(buffer.cast(), size).copy_from_slice(&value);
+}
+- (())setArgument:(ConstVoidPtr)buffer
+          atIndex:(NSInteger)index {
+    let index = checked_arg_index(env, this, "setArgument:atIndex:", index);
+    let size = arg_type(env, this, index).size;
+    let value = env.mem.bytes_at(buffer.cast(), size).to_vec();
+    set_arg(env, this, index, value);
+}
+
+- (())getReturnValue:(MutVoidPtr)buffer {
+    let value = return_value(env, this);
+    if value.is_empty() {
+        return;
+    }
+    env.mem.bytes_at_mut(buffer.cast(), value.len() as GuestUSize).copy_from_slice(&value);
+}
+- (())setReturnValue:(ConstVoidPtr)buffer {
+    let size = env.objc.borrow::<NSInvocationHostObject>(this).return_value.len();
+    let value = env.mem.bytes_at(buffer.cast(), size as GuestUSize).to_vec();
+    env.objc.borrow_mut::<NSInvocationHostObject>(this).return_value = value;
+}
+
+- (())retainArguments {
+    if env.objc.borrow::<NSInvocationHostObject>(this).arguments_retained {
+        return;
+    }
+    env.objc.borrow_mut::<NSInvocationHostObject>(this).arguments_retained = true;
+    for object in object_args(env, this) {
+        retain(env, object);
+    }
+}
+- (bool)argumentsRetained {
+    env.objc.borrow::<NSInvocationHostObject>(this).arguments_retained
+}
+
+- (())invoke {
+    let target: id = msg![env; this target];
+    () = msg![env; this invokeWithTarget:target];
+}
+- (())invokeWithTarget:(id)target {
+    () = msg![env; this setTarget:target];
+
+    let host_object = env.objc.borrow::<NSInvocationHostObject>(this);
+    let signature = host_object.signature;
+    let selector: SEL = Ptr::from_bits(arg_word(env, this, 1));
+    let message = ForwardedMessage {
+        selector,
+        signature: method_signature_for(env, signature),
+        arg_words: env.objc.borrow::<NSInvocationHostObject>(this).args[2..]
+            .iter()
+            .flat_map(|arg| arg.chunks(4))
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect(),
+    };
+
+    let size = env.objc.borrow::<NSInvocationHostObject>(this).return_value.len();
+    let mut return_value = if target == nil {
+        Vec::new()
+    } else {
+        message.send(env, target)
+    };
+    return_value.resize(size, 0);
+    env.objc.borrow_mut::<NSInvocationHostObject>(this).return_value = return_value;
+}
+
+@end
+
+};
+
+/// Create an `NSMethodSignature` from a type encoding. Returns [None] if it
+/// couldn't be parsed. The caller owns the returned reference.
+pub fn new_method_signature(env: &mut Environment, encoding: &str) -> Option<id> {
+    let types = split_type_encoding(encoding)?;
+    let signature = MethodSignature::from_encoded_types(&types)?;
+    let type_encodings = types
+        .iter()
+        .map(|ty| env.mem.alloc_and_write_cstr(ty.encoding.as_bytes()))
+        .collect();
+    let host_object = Box::new(NSMethodSignatureHostObject {
+        types,
+        signature,
+        type_encodings,
+    });
+    let class = env.objc.get_known_class("NSMethodSignature", &mut env.mem);
+    Some(env.objc.alloc_object(class, host_object, &mut env.mem))
+}
+
+/// Get the [MethodSignature] for an `NSMethodSignature`.
+pub fn method_signature_for(env: &Environment, signature: id) -> MethodSignature {
+    env.objc
+        .borrow::<NSMethodSignatureHostObject>(signature)
+        .signature
+        .clone()
+}
+
+/// For use by message forwarding: create an `NSInvocation` for a
+/// message that is being forwarded. The caller owns the returned reference.
+pub fn new_invocation(
+    env: &mut Environment,
+    signature: id,
+    target: id,
+    message: &ForwardedMessage,
+) -> id {
+    let class = env.objc.get_known_class("NSInvocation", &mut env.mem);
+    let invocation: id = msg![env; class invocationWithMethodSignature:signature];
+    retain(env, invocation);
+
+    let mut args = vec![
+        target.to_bits().to_le_bytes().to_vec(),
+        message.selector.to_bits().to_le_bytes().to_vec(),
+    ];
+    let mut words = message.arg_words.iter();
+    for kind in &message.signature.arg_kinds {
+        args.push(
+            words
+                .by_ref()
+                .take(kind.word_count())
+                .flat_map(|word| word.to_le_bytes())
+                .collect(),
+        );
+    }
+    env.objc
+        .borrow_mut::<NSInvocationHostObject>(invocation)
+        .args = args;
+    invocation
+}
+
+/// Get the return value of an `NSInvocation`, which has the size of the return
+/// type.
+pub fn return_value(env: &Environment, invocation: id) -> Vec<u8> {
+    env.objc
+        .borrow::<NSInvocationHostObject>(invocation)
+        .return_value
+        .clone()
+}
+
+fn arg_type(env: &Environment, invocation: id, index: usize) -> &EncodedType {
+    let signature = env
+        .objc
+        .borrow::<NSInvocationHostObject>(invocation)
+        .signature;
+    &env.objc
+        .borrow::<NSMethodSignatureHostObject>(signature)
+        .types[index + 1]
+}
+
+fn checked_arg_index(
+    env: &mut Environment,
+    invocation: id,
+    method: &str,
+    index: NSInteger,
+) -> usize {
+    let count = env
+        .objc
+        .borrow::<NSInvocationHostObject>(invocation)
+        .args
+        .len();
+    if !(0..count as NSInteger).contains(&index) {
+        let reason = format!(
+            "-[NSInvocation {}]: index ({}) out of bounds [-1, {}]",
+            method,
+            index,
+            count as NSInteger - 1
+        );
+        ns_exception::raise(env, "NSInvalidArgumentException", reason);
+    }
+    index as usize
+}
+
+fn arg_word(env: &Environment, invocation: id, index: usize) -> u32 {
+    let arg = &env.objc.borrow::<NSInvocationHostObject>(invocation).args[index];
+    u32::from_le_bytes(arg[..4].try_into().unwrap())
+}
+
+/// Set an argument, retaining and releasing objects if needed. `value` doesn't
+/// need to be padded.
+fn set_arg(env: &mut Environment, invocation: id, index: usize, mut value: Vec<u8>) {
+    let is_object = arg_type(env, invocation, index).kind == ValueKind::Object;
+    let host_object = env.objc.borrow_mut::<NSInvocationHostObject>(invocation);
+    value.resize(host_object.args[index].len(), 0);
+    let old_value = std::mem::replace(&mut host_object.args[index], value);
+    if is_object && host_object.arguments_retained {
+        let new_object: id = Ptr::from_bits(arg_word(env, invocation, index));
+        let old_object: id = Ptr::from_bits(u32::from_le_bytes(old_value[..4].try_into().unwrap()));
+        retain(env, new_object);
+        release(env, old_object);
+    }
+}
+
+/// Get the target and object arguments.
+fn object_args(env: &Environment, invocation: id) -> Vec<id> {
+    let args_count = env
+        .objc
+        .borrow::<NSInvocationHostObject>(invocation)
+        .args
+        .len();
+    (0..args_count)
+        .filter(|&index| arg_type(env, invocation, index).kind == ValueKind::Object)
+        .map(|index| Ptr::from_bits(arg_word(env, invocation, index)))
+        .collect()
+}
@@ -7,17 +7,103 @@
 
 use super::{ns_array, ns_string};
 use crate::dyld::{ConstantExports, HostConstant};
-use crate::objc::{id, objc_classes, ClassExports, HostObject};
+use crate::objc::{autorelease, id, objc_classes, ClassExports, HostObject};
 use crate::options::Options;
 use crate::Environment;
 use std::ffi::CStr;
 
 const NSLocaleCountryCode: &str = "NSLocaleCountryCode";
+const NSLocaleDecimalSeparator: &str = "NSLocaleDecimalSeparator";
+const NSLocaleGroupingSeparator: &str = "NSLocaleGroupingSeparator";
+const NSLocaleCurrencySymbol: &str = "NSLocaleCurrencySymbol";
+const NSLocaleCurrencyCode: &str = "NSLocaleCurrencyCode";
 
-pub const CONSTANTS: ConstantExports = &[(
-    "_NSLocaleCountryCode",
-    HostConstant::NSString(NSLocaleCountryCode),
-)];
+pub const CONSTANTS: ConstantExports = &[
+    (
+        "_NSLocaleCountryCode",
+        HostConstant::NSString(NSLocaleCountryCode),
+    ),
+    (
+        "_NSLocaleDecimalSeparator",
+        HostConstant::NSString(NSLocaleDecimalSeparator),
+    ),
+    (
+        "_NSLocaleGroupingSeparator",
+        HostConstant::NSString(NSLocaleGroupingSeparator),
+    ),
+    (
+        "_NSLocaleCurrencySymbol",
+        HostConstant::NSString(NSLocaleCurrencySymbol),
+    ),
+    (
+        "_NSLocaleCurrencyCode",
+        HostConstant::NSString(NSLocaleCurrencyCode),
+    ),
+];
+
+/// Number formatting conventions of a region, for `NSNumberFormatter` and
+/// the corresponding `NSLocale` keys.
+pub struct NumberConventions {
+    pub decimal_separator: &'static str,
+    pub grouping_separator: &'static str,
+    pub currency_symbol: &'static str,
+    pub currency_code: &'static str,
+    /// Number of fraction digits normally used for the currency.
+    pub currency_fraction_digits: u32,
+    /// Currency format pattern (see `NSNumberFormatter`), where `¤` is the
+    /// currency symbol.
+    pub currency_format: &'static str,
+}
+
+/// Get the number formatting conventions for a country code. This only covers
+/// some common regions; others get the US conventions.
+pub fn number_conventions(country_code: &str) -> NumberConventions {
+    const fn conventions(
+        decimal_separator: &'static str,
+        grouping_separator: &'static str,
+        currency_symbol: &'static str,
+        currency_code: &'static str,
+        currency_fraction_digits: u32,
+        currency_format: &'static str,
+    ) -> NumberConventions {
+        NumberConventions {
+            decimal_separator,
+            grouping_separator,
+            currency_symbol,
+            currency_code,
+            currency_fraction_digits,
+            currency_format,
+        }
+    }
+    // U+00A0 NO-BREAK SPACE is used where the separator is a space.
+    match country_code {
+        "GB" => conventions(".", ",", "£", "GBP", 2, "¤#,##0.00"),
+        "IE" => conventions(".", ",", "€", "EUR", 2, "¤#,##0.00"),
+        "DE" | "AT" | "IT" | "ES" | "GR" => conventions(",", ".", "€", "EUR", 2, "#,##0.00\u{a0}¤"),
+        "NL" | "BE" => conventions(",", ".", "€", "EUR", 2, "¤\u{a0}#,##0.00"),
+        "FR" | "FI" | "PT" => conventions(",", "\u{a0}", "€", "EUR", 2, "#,##0.00\u{a0}¤"),
+        "CH" => conventions(".", "'", "CHF", "CHF", 2, "¤\u{a0}#,##0.00"),
+        "SE" => conventions(",", "\u{a0}", "kr", "SEK", 2, "#,##0.00\u{a0}¤"),
+        "NO" => conventions(",", "\u{a0}", "kr", "NOK", 2, "¤\u{a0}#,##0.00"),
+        "DK" => conventions(",", ".", "kr", "DKK", 2, "¤\u{a0}#,##0.00"),
+        "RU" => conventions(",", "\u{a0}", "руб.", "RUB", 2, "#,##0.00\u{a0}¤"),
+        "BR" => conventions(",", ".", "R$", "BRL", 2, "¤#,##0.00"),
+        "JP" => conventions(".", ",", "¥", "JPY", 0, "¤#,##0"),
+        "CN" => conventions(".", ",", "¥", "CNY", 2, "¤#,##0.00"),
+        "KR" => conventions(".", ",", "₩", "KRW", 0, "¤#,##0"),
+        "CA" => conventions(".", ",", "$", "CAD", 2, "¤#,##0.00"),
+        "AU" => conventions(".", ",", "$", "AUD", 2, "¤#,##0.00"),
+        "NZ" => conventions(".", ",", "$", "NZD", 2, "¤#,##0.00"),
+        "MX" => conventions(".", ",", "$", "MXN", 2, "¤#,##0.00"),
+        _ => conventions(".", ",", "$", "USD", 2, "¤#,##0.00"),
+    }
+}
+
+/// Get the country code of an `NSLocale`.
+pub fn country_code(env: &mut Environment, locale: id) -> String {
+    let country_code = env.objc.borrow::<NSLocaleHostObject>(locale).country_code;
+    ns_string::to_rust_string(env, country_code).to_string()
+}
 
 #[derive(Default)]
 pub struct State {
@@ -162,6 +248,18 @@ pub const CLASSES: ClassExports = objc_classes! {
             let &NSLocaleHostObject { country_code } = env.objc.borrow(this);
             country_code
         },
+        NSLocaleDecimalSeparator | NSLocaleGroupingSeparator | NSLocaleCurrencySymbol
+        | NSLocaleCurrencyCode => {
+            let conventions = number_conventions(&country_code(env, this));
+            let value = match key_str {
+                NSLocaleDecimalSeparator => conventions.decimal_separator,
+                NSLocaleGroupingSeparator => conventions.grouping_separator,
+                NSLocaleCurrencySymbol => conventions.currency_symbol,
+                _ => conventions.currency_code,
+            };
+            let value = ns_string::from_rust_string(env, value.to_string());
+            autorelease(env, value)
+        },
         _ => unimplemented!()
     }
 }
@@ -0,0 +1,741 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSNumberFormatter`.
+//!
+//! Only the 10.4-style behavior is implemented, with a subset of the format
+//! pattern syntax: `#`, `0`, `,` and `.` in the number, a literal prefix and
+//! suffix, and `¤` and `%` in those.
+//!
+//! Resources:
+//! - Apple's [Introduction to Data Formatting Programming Guide For Cocoa](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/DataFormatting/DataFormatting.html)
+//! - [Unicode Technical Standard #35](https://unicode.org/reports/tr35/tr35-10.html#Number_Format_Patterns)
+
+use super::{ns_locale, ns_string, NSUInteger};
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
+    NSZonePtr,
+};
+use crate::Environment;
+
+type NSNumberFormatterStyle = NSUInteger;
+const NSNumberFormatterNoStyle: NSNumberFormatterStyle = 0;
+const NSNumberFormatterDecimalStyle: NSNumberFormatterStyle = 1;
+const NSNumberFormatterCurrencyStyle: NSNumberFormatterStyle = 2;
+const NSNumberFormatterPercentStyle: NSNumberFormatterStyle = 3;
+
+/// A parsed format pattern.
+#[derive(Debug, Clone, PartialEq)]
+struct Pattern {
+    prefix: String,
+    suffix: String,
+    minimum_integer_digits: u32,
+    minimum_fraction_digits: u32,
+    maximum_fraction_digits: u32,
+    /// 0 if there's no grouping separator.
+    grouping_size: u32,
+}
+impl Pattern {
+    fn parse(pattern: &str) -> Pattern {
+        let is_number_char = |c: char| matches!(c, '#' | '0' | ',' | '.');
+        let number_start = pattern.find(is_number_char).unwrap_or(pattern.len());
+        let (prefix, rest) = pattern.split_at(number_start);
+        let number_end = rest.find(|c| !is_number_char(c)).unwrap_or(rest.len());
+        let (number, suffix) = rest.split_at(number_end);
+
+        let (integer, fraction) = number.split_once('.').unwrap_or((number, ""));
+        Pattern {
+            prefix: prefix.to_string(),
+            suffix: suffix.to_string(),
+            minimum_integer_digits: integer.matches('0').count() as u32,
+            minimum_fraction_digits: fraction.matches('0').count() as u32,
+            maximum_fraction_digits: fraction.matches(['0', '#']).count() as u32,
+            grouping_size: integer
+                .rfind(',')
+                .map_or(0, |i| (integer.len() - i - 1) as u32),
+        }
+    }
+}
+
+/// Everything needed to format and parse numbers, after applying the style,
+/// locale and any properties the app has set.
+#[derive(Debug, Clone, PartialEq)]
+struct NumberFormat {
+    positive_prefix: String,
+    positive_suffix: String,
+    negative_prefix: String,
+    negative_suffix: String,
+    minimum_integer_digits: u32,
+    minimum_fraction_digits: u32,
+    maximum_fraction_digits: u32,
+    /// 0 if grouping is disabled.
+    grouping_size: u32,
+    decimal_separator: String,
+    grouping_separator: String,
+    multiplier: f64,
+}
+impl NumberFormat {
+    fn format(&self, value: f64) -> String {
+        if value.is_nan() {
+            return "NaN".to_string();
+        }
+        let (integer, fraction) = if value.is_infinite() {
+            ("∞".to_string(), String::new())
+        } else {
+            round_to_digits(
+                (value * self.multiplier).abs(),
+                self.maximum_fraction_digits,
+            )
+        };
+        let negative = value < 0.0 && !(integer.is_empty() && fraction.is_empty());
+
+        let mut fraction = fraction;
+        while fraction.len() < self.minimum_fraction_digits as usize {
+            fraction.push('0');
+        }
+        let mut integer = integer;
+        while integer.len() < self.minimum_integer_digits as usize {
+            integer.insert(0, '0');
+        }
+        if integer.is_empty() && fraction.is_empty() {
+            integer.push('0');
+        }
+
+        let mut body = String::new();
+        let grouping_size = self.grouping_size as usize;
+        for (i, digit) in integer.chars().enumerate() {
+            let digits_left = integer.len() - i;
+            if grouping_size != 0 && i != 0 && digits_left % grouping_size == 0 {
+                body.push_str(&self.grouping_separator);
+            }
+            body.push(digit);
+        }
+        if !fraction.is_empty() {
+            body.push_str(&self.decimal_separator);
+            body.push_str(&fraction);
+        }
+
+        if negative {
+            format!("{}{}{}", self.negative_prefix, body, self.negative_suffix)
+        } else {
+            format!("{}{}{}", self.positive_prefix, body, self.positive_suffix)
+        }
+    }
+
+    /// Parse a number. Strings that aren't entirely a number in this format are
+    /// rejected.
+    fn parse(&self, string: &str) -> Option<f64> {
+        let strip = |prefix: &str, suffix: &str| string.strip_prefix(prefix)?.strip_suffix(suffix);
+        let (body, negative) = if let Some(body) =
+            strip(&self.positive_prefix, &self.positive_suffix)
+                .filter(|body| !body.starts_with('-'))
+        {
+            (body, false)
+        } else {
+            (strip(&self.negative_prefix, &self.negative_suffix)?, true)
+        };
+
+        let mut integer = String::new();
+        let mut fraction = String::new();
+        let mut seen_decimal_separator = false;
+        let mut rest = body;
+        while let Some(c) = rest.chars().next() {
+            if c.is_ascii_digit() {
+                if seen_decimal_separator {
+                    fraction.push(c);
+                } else {
+                    integer.push(c);
+                }
+                rest = &rest[1..];
+            } else if let Some(after) = rest
+                .strip_prefix(self.decimal_separator.as_str())
+                .filter(|_| !seen_decimal_separator)
+            {
+                seen_decimal_separator = true;
+                rest = after;
+            } else if let Some(after) = self
+                .grouping_separators()
+                .find_map(|separator| rest.strip_prefix(separator))
+                .filter(|_| !seen_decimal_separator && !integer.is_empty())
+            {
+                // A grouping separator must be between digits.
+                if !after.starts_with(|c: char| c.is_ascii_digit()) {
+                    return None;
+                }
+                rest = after;
+            } else {
+                return None;
+            }
+        }
+        if integer.is_empty() && fraction.is_empty() {
+            return None;
+        }
+
+        let value: f64 = format!("{}.{}0", integer, fraction).parse().ok()?;
+        let value = value / self.multiplier;
+        Some(if negative { -value } else { value })
+    }
+
+    /// Grouping separators accepted when parsing. People can't easily type a
+    /// no-break space, so a normal space is accepted instead.
+    fn grouping_separators(&self) -> impl Iterator<Item = &str> {
+        let enabled = self.grouping_size != 0;
+        let alternative = (self.grouping_separator == "\u{a0}").then_some(" ");
+        std::iter::once(self.grouping_separator.as_str())
+            .chain(alternative)
+            .filter(move |separator| enabled && !separator.is_empty())
+    }
+}
+
+/// Round a finite, non-negative number to at most `maximum_fraction_digits`
+/// decimal places, with ties going to the even neighbour, and return its
+/// integer and fraction digits without leading or trailing zeros respectively.
+///
+/// Like ICU, this rounds the shortest decimal representation of the number, so
+/// that e.g. 0.125 and 2.675 both count as ties, even though the latter's
+/// closest `f64` is slightly less than 2.675.
+fn round_to_digits(value: f64, maximum_fraction_digits: u32) -> (String, String) {
+    // e.g. "1.2345e3"
+    let scientific = format!("{:e}", value);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let mut digits: Vec<u8> = mantissa
+        .bytes()
+        .filter(u8::is_ascii_digit)
+        .map(|digit| digit - b'0')
+        .collect();
+    // The number is 0.(digits) × 10^point.
+    let mut point: i32 = exponent.parse::<i32>().unwrap() + 1;
+    if digits.iter().all(|&digit| digit == 0) {
+        digits.clear();
+    }
+
+    let keep = point + maximum_fraction_digits as i32;
+    if keep < 0 {
+        digits.clear();
+    } else if (keep as usize) < digits.len() {
+        let keep = keep as usize;
+        let first_dropped = digits[keep];
+        let rest_nonzero = digits[keep + 1..].iter().any(|&digit| digit != 0);
+        let last_kept_odd = keep > 0 && digits[keep - 1] % 2 == 1;
+        let round_up = first_dropped > 5 || (first_dropped == 5 && (rest_nonzero || last_kept_odd));
+        digits.truncate(keep);
+        if round_up {
+            let mut i = keep;
+            loop {
+                if i == 0 {
+                    digits.insert(0, 1);
+                    point += 1;
+                    break;
+                }
+                i -= 1;
+                if digits[i] == 9 {
+                    digits[i] = 0;
+                } else {
+                    digits[i] += 1;
+                    break;
+                }
+            }
+        }
+    }
+
+    let digit_char = |i: i32| {
+        usize::try_from(i)
+            .ok()
+            .and_then(|i| digits.get(i))
+            .map_or('0', |&digit| (b'0' + digit) as char)
+    };
+    let integer: String = (0..point).map(digit_char).collect();
+    let fraction: String = (point.min(0)..digits.len() as i32)
+        .filter(|&i| i >= point)
+        .map(digit_char)
+        .collect();
+    let integer = integer.trim_start_matches('0').to_string();
+    let fraction = fraction.trim_end_matches('0').to_string();
+    (integer, fraction)
+}
+
+struct NSNumberFormatterHostObject {
+    number_style: NSNumberFormatterStyle,
+    /// Strong reference. [None] means the current locale.
+    locale: Option<id>,
+    decimal_separator: Option<String>,
+    grouping_separator: Option<String>,
+    currency_symbol: Option<String>,
+    uses_grouping_separator: Option<bool>,
+    grouping_size: Option<u32>,
+    minimum_integer_digits: Option<u32>,
+    minimum_fraction_digits: Option<u32>,
+    maximum_fraction_digits: Option<u32>,
+    positive_format: Option<String>,
+    negative_format: Option<String>,
+    multiplier: Option<f64>,
+}
+impl HostObject for NSNumberFormatterHostObject {}
+
+/// The default positive format for a style.
+fn default_positive_format(style: NSNumberFormatterStyle, currency_format: &str) -> String {
+    match style {
+        NSNumberFormatterNoStyle => "0".to_string(),
+        NSNumberFormatterDecimalStyle => "#,##0.###".to_string(),
+        NSNumberFormatterCurrencyStyle => currency_format.to_string(),
+        NSNumberFormatterPercentStyle => "#,##0%".to_string(),
+        _ => {
+            log!(
+                "TODO: NSNumberFormatter style {}, using decimal style instead",
+                style
+            );
+            "#,##0.###".to_string()
+        }
+    }
+}
+
+/// Work out the [NumberFormat] from the formatter's properties.
+fn number_format(env: &mut Environment, formatter: id) -> NumberFormat {
+    let locale = match env
+        .objc
+        .borrow::<NSNumberFormatterHostObject>(formatter)
+        .locale
+    {
+        Some(locale) => locale,
+        None => msg_class![env; NSLocale currentLocale],
+    };
+    let conventions = ns_locale::number_conventions(&ns_locale::country_code(env, locale));
+    let host_object = env.objc.borrow::<NSNumberFormatterHostObject>(formatter);
+
+    let positive_format = host_object.positive_format.clone().unwrap_or_else(|| {
+        default_positive_format(host_object.number_style, conventions.currency_format)
+    });
+    let positive = Pattern::parse(&positive_format);
+    let negative = match host_object.negative_format {
+        Some(ref negative_format) => Pattern::parse(negative_format),
+        None => Pattern {
+            prefix: format!("-{}", positive.prefix),
+            ..positive.clone()
+        },
+    };
+
+    let currency_symbol = host_object
+        .currency_symbol
+        .as_deref()
+        .unwrap_or(conventions.currency_symbol);
+    let substitute = |affix: &str| affix.replace('¤', currency_symbol);
+
+    let minimum_integer_digits = host_object
+        .minimum_integer_digits
+        .unwrap_or(positive.minimum_integer_digits);
+    let maximum_fraction_digits = host_object
+        .maximum_fraction_digits
+        .unwrap_or(positive.maximum_fraction_digits);
+    let minimum_fraction_digits = host_object
+        .minimum_fraction_digits
+        .unwrap_or(positive.minimum_fraction_digits)
+        .min(maximum_fraction_digits);
+    let grouping_size = match host_object.uses_grouping_separator {
+        Some(false) => 0,
+        Some(true) => host_object.grouping_size.unwrap_or(3),
+        None if positive.grouping_size == 0 => 0,
+        None => host_object.grouping_size.unwrap_or(positive.grouping_size),
+    };
+    let multiplier = host_object.multiplier.unwrap_or(
+        if positive.prefix.contains('%') || positive.suffix.contains('%') {
+            100.0
+        } else {
+            1.0
+        },
+    );
+
+    NumberFormat {
+        positive_prefix: substitute(&positive.prefix),
+        positive_suffix: substitute(&positive.suffix),
+        negative_prefix: substitute(&negative.prefix),
+        negative_suffix: substitute(&negative.suffix),
+        minimum_integer_digits,
+        minimum_fraction_digits,
+        maximum_fraction_digits,
+        grouping_size,
+        decimal_separator: host_object
+            .decimal_separator
+            .clone()
+            .unwrap_or_else(|| conventions.decimal_separator.to_string()),
+        grouping_separator: host_object
+            .grouping_separator
+            .clone()
+            .unwrap_or_else(|| conventions.grouping_separator.to_string()),
+        multiplier,
+    }
+}
+
+fn string_property(env: &mut Environment, value: String) -> id {
+    let string = ns_string::from_rust_string(env, value);
+    autorelease(env, string)
+}
+
+fn optional_string_argument(env: &mut Environment, string: id) -> Option<String> {
+    if string == nil {
+        None
+    } else {
+        Some(ns_string::to_rust_string(env, string).to_string())
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSNumberFormatter: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(NSNumberFormatterHostObject {
+        number_style: NSNumberFormatterNoStyle,
+        locale: None,
+        decimal_separator: None,
+        grouping_separator: None,
+        currency_symbol: None,
+        uses_grouping_separator: None,
+        grouping_size: None,
+        minimum_integer_digits: None,
+        minimum_fraction_digits: None,
+        maximum_fraction_digits: None,
+        positive_format: None,
+        negative_format: None,
+        multiplier: None,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)localizedStringFromNumber:(id)number // NSNumber*
+                    numberStyle:(NSNumberFormatterStyle)style {
+    let formatter: id = msg_class![env; NSNumberFormatter new];
+    () = msg![env; formatter setNumberStyle:style];
+    let string: id = msg![env; formatter stringFromNumber:number];
+    release(env, formatter);
+    string
+}
+
+- (())dealloc {
+    if let Some(locale) = env.objc.borrow::<NSNumberFormatterHostObject>(this).locale {
+        release(env, locale);
+    }
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (NSNumberFormatterStyle)numberStyle {
+    env.objc.borrow::<NSNumberFormatterHostObject>(this).number_style
+}
+- (())setNumberStyle:(NSNumberFormatterStyle)style {
+    // Changing the style resets the properties that the style determines.
+    let host_object = env.objc.borrow_mut::<NSNumberFormatterHostObject>(this);
+    host_object.number_style = style;
+    host_object.uses_grouping_separator = None;
+    host_object.grouping_size = None;
+    host_object.minimum_integer_digits = None;
+    host_object.minimum_fraction_digits = None;
+    host_object.maximum_fraction_digits = None;
+    host_object.positive_format = None;
+    host_object.negative_format = None;
+    host_object.multiplier = None;
+}
+
+- (id)locale {
+    match env.objc.borrow::<NSNumberFormatterHostObject>(this).locale {
+        Some(locale) => locale,
+        None => msg_class![env; NSLocale currentLocale],
+    }
+}
+- (())setLocale:(id)locale { // NSLocale*
+    let new = if locale == nil { None } else { Some(retain(env, locale)) };
+    let old = std::mem::replace(
+        &mut env.objc.borrow_mut::<NSNumberFormatterHostObject>(this).locale,
+        new,
+    );
+    if let Some(old) = old {
+        release(env, old);
+    }
+}
+
+- (id)decimalSeparator {
+    let separator = number_format(env, this).decimal_separator;
+    string_property(env, separator)
+}
+- (())setDecimalSeparator:(id)separator { // NSString*
+    let separator = optional_string_argument(env, separator);
+    env.objc.borrow_mut::<NSNumberFormatterHostObject>(this).decimal_separator = separator;
+}
+
+- (id)groupingSeparator {
+    let separator = number_format(env, this).grouping_separator;
+    string_property(env, separator)
+}
+- (())setGroupingSeparator:(id)separator { // NSString*
+    let separator = optional_string_argument(env, separator);
+    env.objc.borrow_mut::<NSNumberFormatterHostObject>(this).grouping_separator = separator;
+}
+
+- (id)currencySymbol {
+    let symbol = match env.objc.borrow::<NSNumberFormatterHostObject>(this).currency_symbol {
+        Some(ref symbol) => symbol.clone(),
+        None => {
+            let locale: id = msg![env; this locale];
+            let country_code = ns_locale::country_code(env, locale);
+            ns_locale::number_conventions(&country_code).currency_symbol.to_string()
+        }
+    };
+    string_property(env, symbol)
+}
+- (())setCurrencySymbol:(id)symbol { // NSString*
+    let symbol = optional_string_argument(env, symbol);
+    env.objc.borrow_mut::<NSNumberFormatterHostObject>(this).currency_symbol = symbol;
+}
+
+- (bool)usesGroupingSeparator {
+    number_format(env, this).grouping_size != 0
+}
+- (())setUsesGroupingSeparator:(bool)uses {
+    env.objc.borrow_mut::<NSNumberFormatterHostObject>(this).uses_grouping_separator = Some(uses);
+}
+
+- (NSUInteger)groupingSize {
+    let host_object = env.objc.borrow::<NSNumberFormatterHostObject>(this);
+    if let Some(size) = host_object.grouping_size {
+        return size;
+    }
+    match number_format(env, this).grouping_size {
+        0 => 3,
+        size => size,
+    }
+}
+- (())setGroupingSize:(NSUInteger)size {
+    env.objc.borrow_mut::<NSNumberFormatterHostObject>(this).grouping_size = Some(size);
+}
+
+- (NSUInteger)minimumIntegerDigits {
+    number_format(env, this).minimum_integer_digits
+}
+- (())setMinimumIntegerDigits:(NSUInteger)digits {
+    env.objc.borrow_mut::<NSNumberFormatterHostObject>(this).minimum_integer_digits = Some(digits);
+}
+
+- (NSUInteger)minimumFractionDigits {
+    number_format(env, this).minimum_fraction_digits
+}
+- (())setMinimumFractionDigits:(NSUInteger)digits {
+    let host_object = env.objc.borrow_mut::<NSNumberFormatterHostObject>(this);
+    host_object.minimum_fraction_digits = Some(digits);
+    // The maximum can't be less than the minimum.
+    if host_object.maximum_fraction_digits.is_some_and(|max| max < digits) {
+        host_object.maximum_fraction_digits = Some(digits);
+    }
+}
+
+- (NSUInteger)maximumFractionDigits {
+    number_format(env, this).maximum_fraction_digits
+}
+- (())setMaximumFractionDigits:(NSUInteger)digits {
+    env.objc.borrow_mut::<NSNumberFormatterHostObject>(this).maximum_fraction_digits = Some(digits);
+}
+
+- (id)positiveFormat {
+    let host_object = env.objc.borrow::<NSNumberFormatterHostObject>(this);
+    let format = match host_object.positive_format {
+        Some(ref format) => format.clone(),
+        None => {
+            let style = host_object.number_style;
+            let locale: id = msg![env; this locale];
+            let country_code = ns_locale::country_code(env, locale);
+            let conventions = ns_locale::number_conventions(&country_code);
+            default_positive_format(style, conventions.currency_format)
+        }
+    };
+    string_property(env, format)
+}
+- (())setPositiveFormat:(id)format { // NSString*
+    let format = optional_string_argument(env, format);
+    let host_object = env.objc.borrow_mut::<NSNumberFormatterHostObject>(this);
+    // The format determines these, so any earlier values are overridden.
+    host_object.uses_grouping_separator = None;
+    host_object.minimum_integer_digits = None;
+    host_object.minimum_fraction_digits = None;
+    host_object.maximum_fraction_digits = None;
+    host_object.positive_format = format;
+}
+
+- (id)negativeFormat {
+    let format = match env.objc.borrow::<NSNumberFormatterHostObject>(this).negative_format {
+        Some(ref format) => format.clone(),
+        None => {
+            let positive: id = msg![env; this positiveFormat];
+            format!("-{}", ns_string::to_rust_string(env, positive))
+        }
+    };
+    string_property(env, format)
+}
+- (())setNegativeFormat:(id)format { // NSString*
+    let format = optional_string_argument(env, format);
+    env.objc.borrow_mut::<NSNumberFormatterHostObject>(this).negative_format = format;
+}
+
+- (id)multiplier {
+    let multiplier = number_format(env, this).multiplier;
+    msg_class![env; NSNumber numberWithDouble:multiplier]
+}
+- (())setMultiplier:(id)multiplier { // NSNumber*
+    let multiplier = if multiplier == nil {
+        None
+    } else {
+        Some(msg![env; multiplier doubleValue])
+    };
+    env.objc.borrow_mut::<NSNumberFormatterHostObject>(this).multiplier = multiplier;
+}
+
+- (id)stringFromNumber:(id)number { // NSNumber*
+    if number == nil {
+        return nil;
+    }
+    let value: f64 = msg![env; number doubleValue];
+    let string = number_format(env, this).format(value);
+    log_dbg!("[{:?} stringFromNumber:{}] => {:?}", this, value, string);
+    string_property(env, string)
+}
+
+- (id)stringForObjectValue:(id)object {
+    msg![env; this stringFromNumber:object]
+}
+
+- (id)numberFromString:(id)string { // NSString*
+    if string == nil {
+        return nil;
+    }
+    let string = ns_string::to_rust_string(env, string).to_string();
+    let result = number_format(env, this).parse(&string);
+    log_dbg!("[{:?} numberFromString:{:?}] => {:?}", this, string, result);
+    match result {
+        None => nil,
+        Some(value) if value.fract() == 0.0 && value.abs() < (1u64 << 53) as f64 => {
+            msg_class![env; NSNumber numberWithLongLong:(value as i64)]
+        },
+        Some(value) => msg_class![env; NSNumber numberWithDouble:value],
+    }
+}
+
+@end
+
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal_format() -> NumberFormat {
+        let pattern = Pattern::parse("#,##0.###");
+        NumberFormat {
+            positive_prefix: pattern.prefix.clone(),
+            positive_suffix: pattern.suffix.clone(),
+            negative_prefix: format!("-{}", pattern.prefix),
+            negative_suffix: pattern.suffix.clone(),
+            minimum_integer_digits: pattern.minimum_integer_digits,
+            minimum_fraction_digits: pattern.minimum_fraction_digits,
+            maximum_fraction_digits: pattern.maximum_fraction_digits,
+            grouping_size: pattern.grouping_size,
+            decimal_separator: ".".to_string(),
+            grouping_separator: ",".to_string(),
+            multiplier: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_rounding() {
+        let round = |value, digits| {
+            let (integer, fraction) = round_to_digits(value, digits);
+            format!("{}.{}", integer, fraction)
+        };
+        assert_eq!(round(0.5, 0), ".");
+        assert_eq!(round(1.5, 0), "2.");
+        assert_eq!(round(2.5, 0), "2.");
+        assert_eq!(round(0.125, 2), ".12");
+        assert_eq!(round(2.675, 2), "2.68");
+        assert_eq!(round(9.996, 2), "10.");
+        assert_eq!(round(0.0004, 3), ".");
+        assert_eq!(round(1234567.0, 3), "1234567.");
+        assert_eq!(round(0.0123, 3), ".012");
+    }
+
+    #[test]
+    fn test_format() {
+        let format = decimal_format();
+        assert_eq!(format.format(1234567.0), "1,234,567");
+        assert_eq!(format.format(-1234.5678), "-1,234.568");
+        assert_eq!(format.format(0.0), "0");
+        assert_eq!(format.format(-0.0001), "0");
+        assert_eq!(format.format(123.0), "123");
+
+        let currency = NumberFormat {
+            positive_prefix: "$".to_string(),
+            negative_prefix: "($".to_string(),
+            negative_suffix: ")".to_string(),
+            minimum_fraction_digits: 2,
+            maximum_fraction_digits: 2,
+            ..decimal_format()
+        };
+        assert_eq!(currency.format(1234.5), "$1,234.50");
+        assert_eq!(currency.format(-0.999), "($1.00)");
+
+        let percent = NumberFormat {
+            positive_suffix: "%".to_string(),
+            negative_suffix: "%".to_string(),
+            maximum_fraction_digits: 0,
+            multiplier: 100.0,
+            ..decimal_format()
+        };
+        assert_eq!(percent.format(0.256), "26%");
+    }
+
+    #[test]
+    fn test_parse() {
+        let format = decimal_format();
+        assert_eq!(format.parse("1,234,567"), Some(1234567.0));
+        assert_eq!(format.parse("-1,234.5"), Some(-1234.5));
+        assert_eq!(format.parse(".5"), Some(0.5));
+        assert_eq!(format.parse(""), None);
+        assert_eq!(format.parse("12a"), None);
+        assert_eq!(format.parse("1,"), None);
+        assert_eq!(format.parse(",1"), None);
+        assert_eq!(format.parse("1.2.3"), None);
+        assert_eq!(format.parse("--1"), None);
+
+        let no_grouping = NumberFormat {
+            grouping_size: 0,
+            ..decimal_format()
+        };
+        assert_eq!(no_grouping.parse("1,234"), None);
+
+        let german = NumberFormat {
+            decimal_separator: ",".to_string(),
+            grouping_separator: ".".to_string(),
+            ..decimal_format()
+        };
+        assert_eq!(german.format(1234.5), "1.234,5");
+        assert_eq!(german.parse("1.234,5"), Some(1234.5));
+    }
+
+    #[test]
+    fn test_pattern() {
+        assert_eq!(
+            Pattern::parse("¤#,##0.00"),
+            Pattern {
+                prefix: "¤".to_string(),
+                suffix: String::new(),
+                minimum_integer_digits: 1,
+                minimum_fraction_digits: 2,
+                maximum_fraction_digits: 2,
+                grouping_size: 3,
+            }
+        );
+        let pattern = Pattern::parse("#,####.0# pts");
+        assert_eq!(pattern.suffix, " pts");
+        assert_eq!(pattern.grouping_size, 4);
+        assert_eq!(pattern.minimum_fraction_digits, 1);
+        assert_eq!(pattern.maximum_fraction_digits, 2);
+    }
+}
@@ -18,11 +18,42 @@ use super::ns_dictionary::dict_from_keys_and_objects;
 use super::ns_run_loop::NSDefaultRunLoopMode;
 use super::ns_string::{from_rust_string, get_static_str, to_rust_string};
 use super::NSUInteger;
+use super::{ns_exception, ns_invocation};
 use crate::mem::MutVoidPtr;
 use crate::objc::{
-    id, msg, msg_class, msg_send, objc_classes, Class, ClassExports, NSZonePtr, ObjC,
-    TrivialHostObject, SEL,
+    autorelease, id, msg, msg_class, msg_send, nil, objc_classes, Class, ClassExports, NSZonePtr,
+    ObjC, TrivialHostObject, SEL,
 };
+use crate::Environment;
+
+/// Get an `NSMethodSignature` for the method instances of `class` would use for
+/// `selector`, or [nil] if there's no such method.
+fn method_signature(env: &mut Environment, class: Class, selector: SEL) -> id {
+    if !env.objc.class_has_method(class, selector) {
+        return nil;
+    }
+    let encoding = match env.objc.method_type_encoding(&env.mem, class, selector) {
+        Some(encoding) => encoding.to_string(),
+        None => {
+            // Host methods don't have type encodings, so assume every argument
+            // and the return value is a single word.
+            let arg_count = selector.as_str(&env.mem).matches(':').count();
+            let guess = format!("^v@:{}", "^v".repeat(arg_count));
+            log!(
+                "Warning: type encoding of {} unknown for {:?}, guessing {:?}",
+                selector.as_str(&env.mem),
+                class,
+                guess
+            );
+            guess
+        }
+    };
+    let Some(signature) = ns_invocation::new_method_signature(env, &encoding) else {
+        log!("Warning: couldn't parse type encoding {:?}", encoding);
+        return nil;
+    };
+    autorelease(env, signature)
+}
 
 pub const CLASSES: ClassExports = objc_classes! {
 
@@ -62,6 +93,10 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.class_has_method(this, selector)
 }
 
++ (id)instanceMethodSignatureForSelector:(SEL)selector {
+    method_signature(env, this, selector)
+}
+
 - (id)init {
     this
 }
@@ -159,6 +194,34 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.class_has_method(class, selector)
 }
 
+// Message forwarding, see crate::objc's forwarding module.
+- (id)forwardingTargetForSelector:(SEL)_selector {
+    nil
+}
+- (id)methodSignatureForSelector:(SEL)selector {
+    let class = ObjC::read_isa(this, &env.mem);
+    method_signature(env, class, selector)
+}
+- (())forwardInvocation:(id)invocation { // NSInvocation*
+    let selector: SEL = msg![env; invocation selector];
+    msg![env; this doesNotRecognizeSelector:selector]
+}
+- (())doesNotRecognizeSelector:(SEL)selector {
+    let class = ObjC::read_isa(this, &env.mem);
+    // +[NSObject class] returns self.
+    let this_class: Class = msg![env; this class];
+    let is_class = this_class == this;
+    let reason = format!(
+        "{}[{} {}]: unrecognized selector sent to {} {:#x}",
+        if is_class { '+' } else { '-' },
+        env.objc.get_class_name(class),
+        selector.as_str(&env.mem),
+        if is_class { "class" } else { "instance" },
+        this.to_bits(),
+    );
+    ns_exception::raise(env, "NSInvalidArgumentException", reason)
+}
+
 - (id)performSelector:(SEL)sel {
     assert!(!sel.is_null());
     msg_send(env, (this, sel))
@@ -5,9 +5,14 @@
  */
 //! `NSProcessInfo`.
 
-use super::NSTimeInterval;
-use crate::objc::{objc_classes, ClassExports};
-use std::time::Instant;
+use super::{ns_string, NSTimeInterval};
+use crate::frameworks::uikit::ui_device::os_build;
+use crate::objc::{autorelease, id, msg, objc_classes, ClassExports};
+
+#[derive(Default)]
+pub struct State {
+    process_info: Option<id>,
+}
 
 pub const CLASSES: ClassExports = objc_classes! {
 
@@ -15,8 +20,32 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 @implementation NSProcessInfo: NSObject
 
++ (id)processInfo {
+    if let Some(existing) = env.framework_state.foundation.ns_process_info.process_info {
+        existing
+    } else {
+        let new: id = msg![env; this new];
+        env.framework_state.foundation.ns_process_info.process_info = Some(new);
+        new
+    }
+}
+
 + (NSTimeInterval)systemUptime {
-    Instant::now().duration_since(env.startup_time).as_secs_f64()
+    env.clock.now().duration_since(env.startup_time).as_secs_f64()
+}
+- (NSTimeInterval)systemUptime {
+    env.clock.now().duration_since(env.startup_time).as_secs_f64()
+}
+
+// NSString*
+- (id)operatingSystemVersionString {
+    let version = &env.options.os_version;
+    let string = match os_build(version) {
+        Some(build) => format!("Version {} (Build {})", version, build),
+        None => format!("Version {}", version),
+    };
+    let string = ns_string::from_rust_string(env, string);
+    autorelease(env, string)
 }
 
 @end
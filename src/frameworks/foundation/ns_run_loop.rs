@@ -8,15 +8,23 @@
 //! Resources:
 //! - Apple's [Threading Programming Guide](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/Multithreading/Introduction/Introduction.html)
 
-use super::{ns_string, ns_timer};
+use super::{ns_stream, ns_string, ns_timer, ns_undo_manager};
 use crate::dyld::{ConstantExports, HostConstant};
 use crate::frameworks::audio_toolbox::audio_queue::{handle_audio_queue, AudioQueueRef};
 use crate::frameworks::core_foundation::cf_run_loop::{
-    kCFRunLoopCommonModes, kCFRunLoopDefaultMode, CFRunLoopRef,
+    kCFRunLoopCommonModes, kCFRunLoopDefaultMode, kCFRunLoopRunFinished,
+    kCFRunLoopRunHandledSource, kCFRunLoopRunStopped, kCFRunLoopRunTimedOut, CFRunLoopRef,
+    CFRunLoopRunResult,
 };
-use crate::frameworks::{core_animation, media_player, uikit};
+use crate::frameworks::core_foundation::cf_run_loop_observer::{
+    self, kCFRunLoopAfterWaiting, kCFRunLoopBeforeSources, kCFRunLoopBeforeTimers,
+    kCFRunLoopBeforeWaiting, kCFRunLoopEntry, kCFRunLoopExit, CFRunLoopActivity,
+};
+use crate::frameworks::core_foundation::CFIndex;
+use crate::frameworks::{core_animation, core_location, game_kit, media_player, store_kit, uikit};
 use crate::objc::{id, msg, objc_classes, release, retain, ClassExports, HostObject};
 use crate::Environment;
+use crate::{automation, recording};
 use std::time::{Duration, Instant};
 
 /// `NSString*`
@@ -48,6 +56,19 @@ struct NSRunLoopHostObject {
     /// Strong references to `NSTimer*` in no particular order. Timers are owned
     /// by the run loop. The timer must remove itself when invalidated.
     timers: Vec<id>,
+    /// Strong references to `CADisplayLink*` in no particular order. The
+    /// display link must remove itself when invalidated or removed.
+    display_links: Vec<id>,
+    /// Strong references to `NSStream*` in no particular order. The stream
+    /// must remove itself when closed or unscheduled.
+    streams: Vec<id>,
+    /// Strong references to `CFRunLoopObserverRef`s, sorted by their order.
+    /// The observer must remove itself when invalidated or removed.
+    observers: Vec<(CFIndex, id)>,
+    /// Set by `CFRunLoopStop()`.
+    stop_requested: bool,
+    /// Set by `CFRunLoopWakeUp()`.
+    wake_up_requested: bool,
 }
 impl HostObject for NSRunLoopHostObject {}
 
@@ -64,6 +85,11 @@ pub const CLASSES: ClassExports = objc_classes! {
         let host_object = Box::new(NSRunLoopHostObject {
             audio_queues: Vec::new(),
             timers: Vec::new(),
+            display_links: Vec::new(),
+            streams: Vec::new(),
+            observers: Vec::new(),
+            stop_requested: false,
+            wake_up_requested: false,
         });
         let new = env.objc.alloc_static_object(this, host_object, &mut env.mem);
         env.framework_state.foundation.ns_run_loop.main_thread_run_loop = Some(new);
@@ -110,7 +136,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (())run {
-    run_run_loop(env, this, /* single_iteration: */ false);
+    run_run_loop(env, this, RunLoopLimit::Forever);
 }
 // TODO: other run methods
 
@@ -147,7 +173,9 @@ pub(super) fn remove_timer(env: &mut Environment, run_loop: id, timer: id) {
     let mut release_count = 0;
     while i < timers.len() {
         if timers[i] == timer {
-            timers.swap_remove(i);
+            // Order is preserved so that timers due at the same time fire in
+            // the order they were added.
+            timers.remove(i);
             release_count += 1;
         } else {
             i += 1;
@@ -159,24 +187,174 @@ pub(super) fn remove_timer(env: &mut Environment, run_loop: id, timer: id) {
     }
 }
 
+/// For use by `CADisplayLink`, which has its own `addToRunLoop:forMode:`.
+pub fn add_display_link(env: &mut Environment, run_loop: id, display_link: id) {
+    log_dbg!(
+        "Adding display link {:?} to run loop {:?}",
+        display_link,
+        run_loop
+    );
+    retain(env, display_link);
+    let host_object = env.objc.borrow_mut::<NSRunLoopHostObject>(run_loop);
+    assert!(!host_object.display_links.contains(&display_link));
+    host_object.display_links.push(display_link);
+}
+
+/// For use by `CADisplayLink` so it can remove itself once it's invalidated or
+/// removed.
+pub fn remove_display_link(env: &mut Environment, run_loop: id, display_link: id) {
+    let display_links = &mut env
+        .objc
+        .borrow_mut::<NSRunLoopHostObject>(run_loop)
+        .display_links;
+    let idx = display_links
+        .iter()
+        .position(|&item| item == display_link)
+        .unwrap();
+    display_links.swap_remove(idx);
+    release(env, display_link);
+}
+
+/// For use by `NSStream`, which has its own `scheduleInRunLoop:forMode:`.
+pub(super) fn add_stream(env: &mut Environment, run_loop: id, stream: id) {
+    log_dbg!("Adding stream {:?} to run loop {:?}", stream, run_loop);
+    retain(env, stream);
+    let host_object = env.objc.borrow_mut::<NSRunLoopHostObject>(run_loop);
+    assert!(!host_object.streams.contains(&stream));
+    host_object.streams.push(stream);
+}
+
+/// For use by `NSStream` so it can remove itself once it's closed or
+/// unscheduled.
+pub(super) fn remove_stream(env: &mut Environment, run_loop: id, stream: id) {
+    let streams = &mut env.objc.borrow_mut::<NSRunLoopHostObject>(run_loop).streams;
+    let idx = streams.iter().position(|&item| item == stream).unwrap();
+    streams.swap_remove(idx);
+    release(env, stream);
+}
+
+/// For use by `CFRunLoopObserver`.
+pub fn add_observer(env: &mut Environment, run_loop: id, observer: id, order: CFIndex) {
+    log_dbg!(
+        "Adding observer {:?} to run loop {:?} with order {}",
+        observer,
+        run_loop,
+        order
+    );
+    retain(env, observer);
+    let observers = &mut env
+        .objc
+        .borrow_mut::<NSRunLoopHostObject>(run_loop)
+        .observers;
+    assert!(!observers.iter().any(|&(_, item)| item == observer));
+    // Observers with the same order are called in the order they were added.
+    let idx = observers.partition_point(|&(item_order, _)| item_order <= order);
+    observers.insert(idx, (order, observer));
+}
+
+/// For use by `CFRunLoopObserver` so it can remove itself once it's
+/// invalidated or removed.
+pub fn remove_observer(env: &mut Environment, run_loop: id, observer: id) {
+    let observers = &mut env
+        .objc
+        .borrow_mut::<NSRunLoopHostObject>(run_loop)
+        .observers;
+    let idx = observers
+        .iter()
+        .position(|&(_, item)| item == observer)
+        .unwrap();
+    observers.remove(idx);
+    release(env, observer);
+}
+
+/// For use by `CFRunLoopStop()`. The current or next run of the run loop will
+/// return at the end of its current iteration.
+pub fn stop(env: &mut Environment, run_loop: id) {
+    env.objc
+        .borrow_mut::<NSRunLoopHostObject>(run_loop)
+        .stop_requested = true;
+}
+
+/// For use by `CFRunLoopWakeUp()`. The run loop won't sleep at the end of its
+/// current iteration.
+pub fn wake_up(env: &mut Environment, run_loop: id) {
+    env.objc
+        .borrow_mut::<NSRunLoopHostObject>(run_loop)
+        .wake_up_requested = true;
+}
+
+/// How long a run loop should run for.
+#[derive(Copy, Clone, Debug)]
+pub enum RunLoopLimit {
+    /// `-[NSRunLoop run]`: the run loop never returns.
+    Forever,
+    /// `CFRunLoopRun()`: the run loop returns once it is stopped.
+    UntilStopped,
+    /// `CFRunLoopRunInMode()`: the run loop returns once it is stopped, once
+    /// the deadline passes, or optionally once a source has been handled.
+    Until {
+        deadline: Instant,
+        return_after_source_handled: bool,
+    },
+    /// A special mode just for the app picker, since we don't have
+    /// `runMode:beforeDate:` or `runUntilDate:` yet. (TODO: implement those to
+    /// replace this.)
+    SingleIteration,
+}
+
 /// Run the run loop for just a single iteration. This is a special mode just
-/// for the app picker, since we don't have `runMode:beforeDate:` or
-/// `runUntilDate:` yet. (TODO: implement those to replace this.)
+/// for the app picker, see [RunLoopLimit::SingleIteration].
 pub fn run_run_loop_single_iteration(env: &mut Environment, run_loop: id) {
-    run_run_loop(env, run_loop, /* single_iteration: */ true)
+    run_run_loop(env, run_loop, RunLoopLimit::SingleIteration);
 }
 
-fn run_run_loop(env: &mut Environment, run_loop: id, single_iteration: bool) {
-    if single_iteration {
-        log_dbg!("Entering run loop {:?} (single iteration)", run_loop);
-    } else {
-        log_dbg!("Entering run loop {:?} (indefinitely)", run_loop);
+/// Tell the run loop's observers about an activity.
+fn notify_observers(
+    env: &mut Environment,
+    run_loop: id,
+    activity: CFRunLoopActivity,
+    observers_tmp: &mut Vec<id>,
+) {
+    assert!(observers_tmp.is_empty());
+    observers_tmp.extend(
+        env.objc
+            .borrow::<NSRunLoopHostObject>(run_loop)
+            .observers
+            .iter()
+            .map(|&(_, observer)| observer),
+    );
+
+    for observer in observers_tmp.drain(..) {
+        // An observer's callout might remove another observer.
+        if !env
+            .objc
+            .borrow::<NSRunLoopHostObject>(run_loop)
+            .observers
+            .iter()
+            .any(|&(_, item)| item == observer)
+        {
+            continue;
+        }
+        cf_run_loop_observer::handle_observer(env, observer, activity);
     }
+}
+
+/// Run the run loop. The result is only meaningful for
+/// [RunLoopLimit::Until].
+pub fn run_run_loop(
+    env: &mut Environment,
+    run_loop: id,
+    limit: RunLoopLimit,
+) -> CFRunLoopRunResult {
+    log_dbg!("Entering run loop {:?} ({:?})", run_loop, limit);
 
     // Temporary vectors used to track things without needing a reference to the
     // environment or to lock the object. Re-used each iteration for efficiency.
     let mut timers_tmp = Vec::new();
+    let mut display_links_tmp = Vec::new();
     let mut audio_queues_tmp = Vec::new();
+    let mut streams_tmp = Vec::new();
+    let mut observers_tmp = Vec::new();
 
     fn limit_sleep_time(current: &mut Option<Instant>, new: Option<Instant>) {
         if let Some(new) = new {
@@ -184,28 +362,65 @@ fn run_run_loop(env: &mut Environment, run_loop: id, single_iteration: bool) {
         }
     }
 
-    loop {
+    notify_observers(env, run_loop, kCFRunLoopEntry, &mut observers_tmp);
+
+    let result = loop {
         let mut sleep_until = None;
+        let mut handled_source = false;
+
+        notify_observers(env, run_loop, kCFRunLoopBeforeTimers, &mut observers_tmp);
+        notify_observers(env, run_loop, kCFRunLoopBeforeSources, &mut observers_tmp);
 
         env.window
             .as_mut()
             .expect("NSRunLoop not supported in headless mode")
             .poll_for_events(&env.options);
 
+        let next_due = automation::handle_automation(env);
+        limit_sleep_time(&mut sleep_until, next_due);
+
         let next_due = uikit::handle_events(env);
         limit_sleep_time(&mut sleep_until, next_due);
 
+        let next_due = core_animation::handle_animations(env);
+        limit_sleep_time(&mut sleep_until, next_due);
+
         let next_due = core_animation::recomposite_if_necessary(env);
         limit_sleep_time(&mut sleep_until, next_due);
 
         assert!(timers_tmp.is_empty());
         timers_tmp.extend_from_slice(&env.objc.borrow::<NSRunLoopHostObject>(run_loop).timers);
+        // Timers are fired in the order they are due, and timers that are due
+        // at the same time are fired in the order they were added. This
+        // includes `CFRunLoopTimer`s, which are `NSTimer`s here.
+        timers_tmp.sort_by_cached_key(|&timer| ns_timer::fire_date(env, timer));
 
         for timer in timers_tmp.drain(..) {
+            // A timer's target might invalidate another timer.
+            if !env
+                .objc
+                .borrow::<NSRunLoopHostObject>(run_loop)
+                .timers
+                .contains(&timer)
+            {
+                continue;
+            }
             let next_due = ns_timer::handle_timer(env, timer);
             limit_sleep_time(&mut sleep_until, next_due);
         }
 
+        assert!(display_links_tmp.is_empty());
+        display_links_tmp.extend_from_slice(
+            &env.objc
+                .borrow::<NSRunLoopHostObject>(run_loop)
+                .display_links,
+        );
+
+        for display_link in display_links_tmp.drain(..) {
+            let next_due = core_animation::handle_display_link(env, display_link);
+            limit_sleep_time(&mut sleep_until, next_due);
+        }
+
         assert!(audio_queues_tmp.is_empty());
         audio_queues_tmp.extend_from_slice(
             &env.objc
@@ -217,7 +432,65 @@ fn run_run_loop(env: &mut Environment, run_loop: id, single_iteration: bool) {
             handle_audio_queue(env, audio_queue);
         }
 
-        media_player::handle_players(env);
+        assert!(streams_tmp.is_empty());
+        streams_tmp.extend_from_slice(&env.objc.borrow::<NSRunLoopHostObject>(run_loop).streams);
+
+        for stream in streams_tmp.drain(..) {
+            // A stream's callbacks might close another stream.
+            if !env
+                .objc
+                .borrow::<NSRunLoopHostObject>(run_loop)
+                .streams
+                .contains(&stream)
+            {
+                continue;
+            }
+            let (handled, next_due) = ns_stream::handle_stream(env, stream);
+            handled_source |= handled;
+            limit_sleep_time(&mut sleep_until, next_due);
+        }
+
+        let next_due = media_player::handle_players(env);
+        limit_sleep_time(&mut sleep_until, next_due);
+
+        let next_due = core_location::handle_location_managers(env);
+        limit_sleep_time(&mut sleep_until, next_due);
+
+        let next_due = game_kit::handle_completion_handlers(env);
+        limit_sleep_time(&mut sleep_until, next_due);
+
+        let next_due = store_kit::handle_events(env);
+        limit_sleep_time(&mut sleep_until, next_due);
+
+        recording::pump_audio(env);
+
+        let host_object = env.objc.borrow_mut::<NSRunLoopHostObject>(run_loop);
+        let stop_requested = std::mem::take(&mut host_object.stop_requested);
+        let wake_up_requested = std::mem::take(&mut host_object.wake_up_requested);
+
+        match limit {
+            RunLoopLimit::Forever | RunLoopLimit::SingleIteration => (),
+            RunLoopLimit::UntilStopped | RunLoopLimit::Until { .. } if stop_requested => {
+                break kCFRunLoopRunStopped;
+            }
+            RunLoopLimit::UntilStopped => (),
+            RunLoopLimit::Until {
+                deadline,
+                return_after_source_handled,
+            } => {
+                if return_after_source_handled && handled_source {
+                    break kCFRunLoopRunHandledSource;
+                }
+                if env.clock.now() >= deadline {
+                    break kCFRunLoopRunTimedOut;
+                }
+                limit_sleep_time(&mut sleep_until, Some(deadline));
+            }
+        }
+
+        ns_undo_manager::handle_undo_managers(env);
+
+        notify_observers(env, run_loop, kCFRunLoopBeforeWaiting, &mut observers_tmp);
 
         // Unfortunately, touchHLE has to poll for certain things repeatedly;
         // it can't just wait until the next event appears.
@@ -233,14 +506,22 @@ fn run_run_loop(env: &mut Environment, run_loop: id, single_iteration: bool) {
         // The compromise used here is that we will wait for a 60th of a second,
         // or until the next scheduled event, whichever is sooner. iPhone OS
         // apps can't do more than 60fps so this should be fine.
-        let limit = Duration::from_millis(1000 / 60);
-        env.sleep(
-            sleep_until.map_or(limit, |i| i.duration_since(Instant::now()).min(limit)),
-            false,
-        );
+        if !wake_up_requested {
+            let limit = Duration::from_millis(1000 / 60);
+            let now = env.clock.now();
+            env.sleep(
+                sleep_until.map_or(limit, |i| i.saturating_duration_since(now).min(limit)),
+                false,
+            );
+        }
+        notify_observers(env, run_loop, kCFRunLoopAfterWaiting, &mut observers_tmp);
 
-        if single_iteration {
-            break;
+        if let RunLoopLimit::SingleIteration = limit {
+            break kCFRunLoopRunFinished;
         }
-    }
+    };
+
+    notify_observers(env, run_loop, kCFRunLoopExit, &mut observers_tmp);
+
+    result
 }
@@ -0,0 +1,755 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSStream`, `NSInputStream` and `NSOutputStream`.
+//!
+//! These are toll-free bridged to `CFReadStream` and `CFWriteStream` in Apple's
+//! implementation. Here they are the same types, so the stream events for
+//! both the delegate and the Core Foundation client callback are delivered
+//! from here.
+
+use super::ns_run_loop::{add_stream, remove_stream, NSRunLoopMode};
+use super::{ns_data, ns_string, ns_url, NSInteger, NSUInteger};
+use crate::abi::{CallFromHost, GuestFunction};
+use crate::dyld::{ConstantExports, HostConstant};
+use crate::frameworks::cf_network::{cf_http_message, cf_http_stream};
+use crate::frameworks::core_foundation::CFOptionFlags;
+use crate::fs::{GuestFile, GuestOpenOptions, GuestPathBuf};
+use crate::http;
+use crate::libc::errno::{ENETUNREACH, ENOENT};
+use crate::libc::sys::socket::errno_for_io_error;
+use crate::mem::{ConstPtr, MutPtr, MutVoidPtr};
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
+    NSZonePtr,
+};
+use crate::Environment;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::time::{Duration, Instant};
+
+pub type NSStreamStatus = NSUInteger;
+pub const NSStreamStatusNotOpen: NSStreamStatus = 0;
+pub const NSStreamStatusOpening: NSStreamStatus = 1;
+pub const NSStreamStatusOpen: NSStreamStatus = 2;
+pub const NSStreamStatusAtEnd: NSStreamStatus = 5;
+pub const NSStreamStatusClosed: NSStreamStatus = 6;
+pub const NSStreamStatusError: NSStreamStatus = 7;
+
+pub type NSStreamEvent = NSUInteger;
+pub const NSStreamEventNone: NSStreamEvent = 0;
+pub const NSStreamEventOpenCompleted: NSStreamEvent = 1;
+pub const NSStreamEventHasBytesAvailable: NSStreamEvent = 2;
+pub const NSStreamEventHasSpaceAvailable: NSStreamEvent = 4;
+pub const NSStreamEventErrorOccurred: NSStreamEvent = 8;
+pub const NSStreamEventEndEncountered: NSStreamEvent = 16;
+
+pub const NSStreamDataWrittenToMemoryStreamKey: &str = "kCFStreamPropertyDataWritten";
+pub const NSStreamFileCurrentOffsetKey: &str = "kCFStreamPropertyFileCurrentOffset";
+
+pub const CONSTANTS: ConstantExports = &[
+    (
+        "_NSStreamDataWrittenToMemoryStreamKey",
+        HostConstant::NSString(NSStreamDataWrittenToMemoryStreamKey),
+    ),
+    (
+        "_kCFStreamPropertyDataWritten",
+        HostConstant::NSString(NSStreamDataWrittenToMemoryStreamKey),
+    ),
+    (
+        "_NSStreamFileCurrentOffsetKey",
+        HostConstant::NSString(NSStreamFileCurrentOffsetKey),
+    ),
+    (
+        "_kCFStreamPropertyFileCurrentOffset",
+        HostConstant::NSString(NSStreamFileCurrentOffsetKey),
+    ),
+];
+
+/// How often to check whether an HTTP response has arrived.
+const HTTP_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+enum Backend {
+    InputFile {
+        path: GuestPathBuf,
+        /// [None] until opened.
+        file: Option<GuestFile>,
+        remaining: u64,
+    },
+    InputData {
+        bytes: Vec<u8>,
+        position: usize,
+    },
+    InputHttp {
+        request: http::Request,
+        /// [Some] while waiting for the response.
+        pending: Option<http::PendingRequest>,
+        /// [Some] once the response has arrived.
+        response: Option<http::Response>,
+        position: usize,
+    },
+    OutputFile {
+        path: GuestPathBuf,
+        append: bool,
+        /// [None] until opened.
+        file: Option<GuestFile>,
+    },
+    OutputMemory {
+        bytes: Vec<u8>,
+    },
+}
+
+/// A `CFReadStreamClientCallBack` or `CFWriteStreamClientCallBack` and the
+/// `info` from its context.
+#[derive(Copy, Clone)]
+pub struct StreamClient {
+    pub events: CFOptionFlags,
+    // void (*)(CFReadStreamRef stream, CFStreamEventType type, void *info)
+    pub callback: GuestFunction,
+    pub info: MutVoidPtr,
+}
+
+struct NSStreamHostObject {
+    backend: Backend,
+    status: NSStreamStatus,
+    /// `errno` value describing the error, if the status is
+    /// [NSStreamStatusError].
+    error: i32,
+    /// Weak reference. `nil` means the stream is its own delegate.
+    delegate: id,
+    client: Option<StreamClient>,
+    /// Weak reference to the `NSRunLoop*` the stream is scheduled in, if any.
+    /// The run loop retains the stream.
+    run_loop: Option<id>,
+    open_completed_sent: bool,
+    /// Whether the next "has bytes available" or "has space available" event
+    /// can be sent. Like on a real device, it isn't sent again until the app
+    /// has read or written something.
+    can_notify: bool,
+    end_sent: bool,
+    error_sent: bool,
+}
+impl HostObject for NSStreamHostObject {}
+
+impl NSStreamHostObject {
+    fn new(backend: Backend) -> NSStreamHostObject {
+        NSStreamHostObject {
+            backend,
+            status: NSStreamStatusNotOpen,
+            error: 0,
+            delegate: nil,
+            client: None,
+            run_loop: None,
+            open_completed_sent: false,
+            can_notify: true,
+            end_sent: false,
+            error_sent: false,
+        }
+    }
+
+    fn is_input(&self) -> bool {
+        matches!(
+            self.backend,
+            Backend::InputFile { .. } | Backend::InputData { .. } | Backend::InputHttp { .. }
+        )
+    }
+
+    fn has_bytes_available(&self) -> bool {
+        match self.backend {
+            Backend::InputFile { remaining, .. } => remaining > 0,
+            Backend::InputData {
+                ref bytes,
+                position,
+            } => position < bytes.len(),
+            Backend::InputHttp {
+                response: Some(ref response),
+                position,
+                ..
+            } => position < response.body.len(),
+            _ => false,
+        }
+    }
+
+    fn fail(&mut self, error: i32) {
+        self.status = NSStreamStatusError;
+        self.error = error;
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+// NSStream is an abstract class, but since NSInputStream and NSOutputStream
+// share a host object, the common methods are implemented here.
+@implementation NSStream: NSObject
+
+- (())open {
+    open(env, this);
+}
+
+- (())close {
+    let host_object = env.objc.borrow_mut::<NSStreamHostObject>(this);
+    host_object.status = NSStreamStatusClosed;
+    match host_object.backend {
+        Backend::InputFile { ref mut file, .. } | Backend::OutputFile { ref mut file, .. } => {
+            *file = None;
+        }
+        Backend::InputHttp { ref mut pending, .. } => {
+            *pending = None;
+        }
+        _ => (),
+    }
+    // Closing a stream also unschedules it.
+    if let Some(run_loop) = host_object.run_loop.take() {
+        remove_stream(env, run_loop, this);
+    }
+}
+
+- (NSStreamStatus)streamStatus {
+    env.objc.borrow::<NSStreamHostObject>(this).status
+}
+
+- (id)streamError {
+    let error = copy_error(env, this);
+    autorelease(env, error)
+}
+
+- (id)delegate {
+    let delegate = env.objc.borrow::<NSStreamHostObject>(this).delegate;
+    if delegate == nil {
+        this
+    } else {
+        delegate
+    }
+}
+- (())setDelegate:(id)delegate {
+    let delegate = if delegate == this { nil } else { delegate };
+    env.objc.borrow_mut::<NSStreamHostObject>(this).delegate = delegate;
+}
+
+- (())scheduleInRunLoop:(id)run_loop // NSRunLoop*
+                forMode:(NSRunLoopMode)_mode {
+    // TODO: handle run loop modes
+    let host_object = env.objc.borrow_mut::<NSStreamHostObject>(this);
+    if host_object.run_loop.is_some() {
+        return;
+    }
+    host_object.run_loop = Some(run_loop);
+    add_stream(env, run_loop, this);
+}
+- (())removeFromRunLoop:(id)run_loop // NSRunLoop*
+                forMode:(NSRunLoopMode)_mode {
+    let host_object = env.objc.borrow_mut::<NSStreamHostObject>(this);
+    if host_object.run_loop == Some(run_loop) {
+        host_object.run_loop = None;
+        remove_stream(env, run_loop, this);
+    }
+}
+
+- (id)propertyForKey:(id)key { // NSString*
+    let property = copy_property(env, this, key);
+    autorelease(env, property)
+}
+- (bool)setProperty:(id)property forKey:(id)key {
+    log!(
+        "TODO: [(NSStream*){:?} setProperty:{:?} forKey:{:?}] (ignored)",
+        this,
+        property,
+        ns_string::to_rust_string(env, key),
+    );
+    false
+}
+
+@end
+
+@implementation NSInputStream: NSStream
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let backend = Backend::InputData { bytes: Vec::new(), position: 0 };
+    let host_object = Box::new(NSStreamHostObject::new(backend));
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)inputStreamWithFileAtPath:(id)path { // NSString*
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithFileAtPath:path];
+    autorelease(env, new)
+}
++ (id)inputStreamWithData:(id)data { // NSData*
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithData:data];
+    autorelease(env, new)
+}
++ (id)inputStreamWithURL:(id)url { // NSURL*
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithURL:url];
+    autorelease(env, new)
+}
+
+- (id)initWithFileAtPath:(id)path { // NSString*
+    let path = GuestPathBuf::from(ns_string::to_rust_string(env, path).into_owned());
+    env.objc.borrow_mut::<NSStreamHostObject>(this).backend = Backend::InputFile {
+        path,
+        file: None,
+        remaining: 0,
+    };
+    this
+}
+- (id)initWithData:(id)data { // NSData*
+    let length: NSUInteger = msg![env; data length];
+    let bytes = if length == 0 {
+        Vec::new()
+    } else {
+        ns_data::to_rust_slice(env, data).to_vec()
+    };
+    env.objc.borrow_mut::<NSStreamHostObject>(this).backend = Backend::InputData {
+        bytes,
+        position: 0,
+    };
+    this
+}
+- (id)initWithURL:(id)url { // NSURL*
+    let path = ns_url::to_rust_path(env, url).into_owned();
+    env.objc.borrow_mut::<NSStreamHostObject>(this).backend = Backend::InputFile {
+        path,
+        file: None,
+        remaining: 0,
+    };
+    this
+}
+
+- (NSInteger)read:(MutPtr<u8>)buffer maxLength:(NSUInteger)max_length {
+    read(env, this, buffer, max_length)
+}
+
+- (bool)hasBytesAvailable {
+    let host_object = env.objc.borrow::<NSStreamHostObject>(this);
+    host_object.status == NSStreamStatusOpen && host_object.has_bytes_available()
+}
+
+- (bool)getBuffer:(MutPtr<MutPtr<u8>>)_buffer length:(MutPtr<NSUInteger>)_length {
+    false
+}
+
+@end
+
+@implementation NSOutputStream: NSStream
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let backend = Backend::OutputMemory { bytes: Vec::new() };
+    let host_object = Box::new(NSStreamHostObject::new(backend));
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)outputStreamToMemory {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initToMemory];
+    autorelease(env, new)
+}
++ (id)outputStreamToFileAtPath:(id)path // NSString*
+                        append:(bool)append {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initToFileAtPath:path append:append];
+    autorelease(env, new)
+}
+
+- (id)initToMemory {
+    this
+}
+- (id)initToFileAtPath:(id)path // NSString*
+                append:(bool)append {
+    let path = GuestPathBuf::from(ns_string::to_rust_string(env, path).into_owned());
+    env.objc.borrow_mut::<NSStreamHostObject>(this).backend = Backend::OutputFile {
+        path,
+        append,
+        file: None,
+    };
+    this
+}
+
+- (NSInteger)write:(ConstPtr<u8>)buffer maxLength:(NSUInteger)max_length {
+    write(env, this, buffer, max_length)
+}
+
+- (bool)hasSpaceAvailable {
+    env.objc.borrow::<NSStreamHostObject>(this).status == NSStreamStatusOpen
+}
+
+@end
+
+};
+
+/// For use by `CFReadStreamCreateWithFile` and `CFWriteStreamCreateWithFile`.
+/// The caller owns the result.
+pub fn new_file_stream(env: &mut Environment, path: GuestPathBuf, input: bool) -> id {
+    let (class, backend) = if input {
+        let backend = Backend::InputFile {
+            path,
+            file: None,
+            remaining: 0,
+        };
+        ("NSInputStream", backend)
+    } else {
+        let backend = Backend::OutputFile {
+            path,
+            append: false,
+            file: None,
+        };
+        ("NSOutputStream", backend)
+    };
+    let class = env.objc.get_known_class(class, &mut env.mem);
+    let host_object = Box::new(NSStreamHostObject::new(backend));
+    env.objc.alloc_object(class, host_object, &mut env.mem)
+}
+
+/// For use by `CFReadStreamCreateForHTTPRequest`. The caller owns the result.
+pub fn new_http_stream(env: &mut Environment, request: http::Request) -> id {
+    let backend = Backend::InputHttp {
+        request,
+        pending: None,
+        response: None,
+        position: 0,
+    };
+    let class = env.objc.get_known_class("NSInputStream", &mut env.mem);
+    let host_object = Box::new(NSStreamHostObject::new(backend));
+    env.objc.alloc_object(class, host_object, &mut env.mem)
+}
+
+/// For use by `CFReadStreamSetClient` and `CFWriteStreamSetClient`.
+pub fn set_client(env: &mut Environment, stream: id, client: Option<StreamClient>) {
+    env.objc.borrow_mut::<NSStreamHostObject>(stream).client = client;
+}
+
+/// Open a stream. Returns `false` if this failed immediately.
+pub fn open(env: &mut Environment, stream: id) -> bool {
+    let network_access = env.options.network_access;
+    let host_object = env.objc.borrow_mut::<NSStreamHostObject>(stream);
+    if host_object.status != NSStreamStatusNotOpen {
+        log!(
+            "Warning: attempt to open stream {:?} with status {}, ignoring",
+            stream,
+            host_object.status
+        );
+        return false;
+    }
+    host_object.status = NSStreamStatusOpen;
+    match host_object.backend {
+        Backend::InputFile {
+            ref path,
+            ref mut file,
+            ref mut remaining,
+        } => match env.fs.open(path) {
+            Ok(mut opened) => {
+                *remaining = opened.seek(SeekFrom::End(0)).unwrap();
+                opened.seek(SeekFrom::Start(0)).unwrap();
+                *file = Some(opened);
+            }
+            Err(()) => {
+                log!("Warning: couldn't open {:?} for stream {:?}", path, stream);
+                host_object.fail(ENOENT);
+            }
+        },
+        Backend::OutputFile {
+            ref path,
+            append,
+            ref mut file,
+        } => {
+            let mut options = GuestOpenOptions::new();
+            options.write().create();
+            if append {
+                options.append();
+            } else {
+                options.truncate();
+            }
+            match env.fs.open_with_options(path, options) {
+                Ok(opened) => *file = Some(opened),
+                Err(()) => {
+                    log!("Warning: couldn't open {:?} for stream {:?}", path, stream);
+                    host_object.fail(ENOENT);
+                }
+            }
+        }
+        Backend::InputHttp {
+            ref request,
+            ref mut pending,
+            ..
+        } => {
+            if network_access {
+                log!("HTTP {} {}", request.method, request.url);
+                *pending = Some(http::PendingRequest::start(request.clone()));
+                host_object.status = NSStreamStatusOpening;
+            } else {
+                log!(
+                    "Refusing HTTP request to {} because network access is disabled. Use the --allow-network-access option to enable it.",
+                    request.url
+                );
+                host_object.fail(ENETUNREACH);
+            }
+        }
+        Backend::InputData { .. } | Backend::OutputMemory { .. } => (),
+    }
+    host_object.status != NSStreamStatusError
+}
+
+/// Read from an input stream. Returns the number of bytes read, 0 at the end of
+/// the stream or -1 if there was an error.
+pub fn read(
+    env: &mut Environment,
+    stream: id,
+    buffer: MutPtr<u8>,
+    max_length: NSUInteger,
+) -> NSInteger {
+    // Like on a real device, reading blocks until the HTTP response has
+    // arrived.
+    if env.objc.borrow::<NSStreamHostObject>(stream).status == NSStreamStatusOpening {
+        wait_for_http_response(env, stream);
+    }
+
+    let host_object = env.objc.borrow_mut::<NSStreamHostObject>(stream);
+    match host_object.status {
+        NSStreamStatusOpen => (),
+        NSStreamStatusAtEnd => return 0,
+        _ => return -1,
+    }
+    host_object.can_notify = true;
+    let dest = env.mem.bytes_at_mut(buffer, max_length);
+    let read = match host_object.backend {
+        Backend::InputFile {
+            ref mut file,
+            ref mut remaining,
+            ..
+        } => match file.as_mut().unwrap().read(dest) {
+            Ok(read) => {
+                *remaining = remaining.saturating_sub(read as u64);
+                read
+            }
+            Err(e) => {
+                host_object.fail(errno_for_io_error(&e));
+                return -1;
+            }
+        },
+        Backend::InputData {
+            ref bytes,
+            ref mut position,
+        } => read_from_slice(&bytes[*position..], dest, position),
+        Backend::InputHttp {
+            response: Some(ref response),
+            ref mut position,
+            ..
+        } => read_from_slice(&response.body[*position..], dest, position),
+        _ => panic!("{:?} is not an input stream", stream),
+    };
+    if read == 0 && max_length > 0 {
+        host_object.status = NSStreamStatusAtEnd;
+    }
+    read.try_into().unwrap()
+}
+
+fn read_from_slice(src: &[u8], dest: &mut [u8], position: &mut usize) -> usize {
+    let count = src.len().min(dest.len());
+    dest[..count].copy_from_slice(&src[..count]);
+    *position += count;
+    count
+}
+
+/// Write to an output stream. Returns the number of bytes written, or -1 if
+/// there was an error.
+pub fn write(
+    env: &mut Environment,
+    stream: id,
+    buffer: ConstPtr<u8>,
+    max_length: NSUInteger,
+) -> NSInteger {
+    let host_object = env.objc.borrow_mut::<NSStreamHostObject>(stream);
+    if host_object.status != NSStreamStatusOpen {
+        return -1;
+    }
+    host_object.can_notify = true;
+    let src = env.mem.bytes_at(buffer, max_length);
+    let written = match host_object.backend {
+        Backend::OutputFile { ref mut file, .. } => match file.as_mut().unwrap().write(src) {
+            Ok(written) => written,
+            Err(e) => {
+                host_object.fail(errno_for_io_error(&e));
+                return -1;
+            }
+        },
+        Backend::OutputMemory { ref mut bytes } => {
+            bytes.extend_from_slice(src);
+            src.len()
+        }
+        _ => panic!("{:?} is not an output stream", stream),
+    };
+    written.try_into().unwrap()
+}
+
+/// Get the stream's error as an `NSError*`, or `nil` if there is none. The
+/// caller owns the result.
+pub fn copy_error(env: &mut Environment, stream: id) -> id {
+    let host_object = env.objc.borrow::<NSStreamHostObject>(stream);
+    if host_object.status != NSStreamStatusError {
+        return nil;
+    }
+    let code: NSInteger = host_object.error;
+    let domain = ns_string::get_static_str(env, "NSPOSIXErrorDomain");
+    let error: id = msg_class![env; NSError alloc];
+    msg![env; error initWithDomain:domain code:code userInfo:nil]
+}
+
+/// Get a stream property, or `nil` if it's unknown. The caller owns the result.
+pub fn copy_property(env: &mut Environment, stream: id, key: id) -> id {
+    let key = ns_string::to_rust_string(env, key);
+    let host_object = env.objc.borrow::<NSStreamHostObject>(stream);
+    match (&*key, &host_object.backend) {
+        (NSStreamDataWrittenToMemoryStreamKey, Backend::OutputMemory { bytes }) => {
+            let bytes = bytes.clone();
+            ns_data::from_vec(env, bytes)
+        }
+        (
+            cf_http_stream::kCFStreamPropertyHTTPResponseHeader,
+            Backend::InputHttp {
+                response: Some(response),
+                ..
+            },
+        ) => {
+            let response = response.clone();
+            cf_http_message::new_response_message(env, &response)
+        }
+        (NSStreamFileCurrentOffsetKey, Backend::InputFile { file: Some(_), .. }) => {
+            let Backend::InputFile {
+                file: Some(ref mut file),
+                ..
+            } = env.objc.borrow_mut::<NSStreamHostObject>(stream).backend
+            else {
+                unreachable!()
+            };
+            let offset = file.stream_position().unwrap();
+            let number: id = msg_class![env; NSNumber alloc];
+            msg![env; number initWithLongLong:(offset as i64)]
+        }
+        _ => {
+            log_dbg!(
+                "Unknown property {:?} requested for stream {:?}",
+                key,
+                stream
+            );
+            nil
+        }
+    }
+}
+
+/// Block until a stream's HTTP response has arrived.
+fn wait_for_http_response(env: &mut Environment, stream: id) {
+    while env.objc.borrow::<NSStreamHostObject>(stream).status == NSStreamStatusOpening {
+        if !poll_http_response(env, stream) {
+            std::thread::sleep(HTTP_POLL_INTERVAL);
+        }
+    }
+}
+
+/// Check whether a stream's HTTP response has arrived, and if it has, update
+/// the stream. Returns `true` if the stream is no longer waiting.
+fn poll_http_response(env: &mut Environment, stream: id) -> bool {
+    let host_object = env.objc.borrow_mut::<NSStreamHostObject>(stream);
+    let Backend::InputHttp {
+        ref mut pending,
+        ref mut response,
+        ..
+    } = host_object.backend
+    else {
+        return true;
+    };
+    let Some(result) = pending.as_ref().and_then(|pending| pending.poll()) else {
+        return pending.is_none();
+    };
+    *pending = None;
+    match result {
+        Ok(new_response) => {
+            log!(
+                "HTTP response received: {:?}, {} bytes",
+                new_response.status_line,
+                new_response.body.len()
+            );
+            *response = Some(new_response);
+            host_object.status = NSStreamStatusOpen;
+        }
+        Err(e) => {
+            log!("HTTP request failed: {}", e);
+            host_object.fail(errno_for_io_error(&e));
+        }
+    }
+    true
+}
+
+/// For use by `NSRunLoop`: deliver the next event for a scheduled stream, if
+/// there is one.
+///
+/// Returns whether an event was delivered, and the time the stream should next
+/// be checked, if any.
+pub fn handle_stream(env: &mut Environment, stream: id) -> (bool, Option<Instant>) {
+    if env.objc.borrow::<NSStreamHostObject>(stream).status == NSStreamStatusOpening
+        && !poll_http_response(env, stream)
+    {
+        return (false, Some(env.clock.now() + HTTP_POLL_INTERVAL));
+    }
+
+    let host_object = env.objc.borrow_mut::<NSStreamHostObject>(stream);
+    let event = match host_object.status {
+        NSStreamStatusError if !host_object.error_sent => {
+            host_object.error_sent = true;
+            NSStreamEventErrorOccurred
+        }
+        NSStreamStatusOpen if !host_object.open_completed_sent => {
+            host_object.open_completed_sent = true;
+            NSStreamEventOpenCompleted
+        }
+        NSStreamStatusOpen if host_object.can_notify => {
+            host_object.can_notify = false;
+            if !host_object.is_input() {
+                NSStreamEventHasSpaceAvailable
+            } else if host_object.has_bytes_available() {
+                NSStreamEventHasBytesAvailable
+            } else {
+                host_object.status = NSStreamStatusAtEnd;
+                host_object.end_sent = true;
+                NSStreamEventEndEncountered
+            }
+        }
+        NSStreamStatusAtEnd if !host_object.end_sent => {
+            host_object.end_sent = true;
+            NSStreamEventEndEncountered
+        }
+        _ => NSStreamEventNone,
+    };
+    if event == NSStreamEventNone {
+        return (false, None);
+    }
+
+    let &mut NSStreamHostObject {
+        delegate, client, ..
+    } = host_object;
+    log_dbg!("Stream {:?} event {}", stream, event);
+
+    // The callbacks might close and release the stream.
+    retain(env, stream);
+    if let Some(client) = client {
+        if client.events & event != 0 {
+            () = client
+                .callback
+                .call_from_host(env, (stream, event, client.info));
+        }
+    }
+    let delegate = if delegate == nil { stream } else { delegate };
+    let sel = env
+        .objc
+        .register_host_selector("stream:handleEvent:".to_string(), &mut env.mem);
+    if msg![env; delegate respondsToSelector:sel] {
+        () = msg![env; delegate stream:stream handleEvent:event];
+    }
+    release(env, stream);
+
+    // There may be another event to deliver.
+    (true, Some(env.clock.now()))
+}
@@ -29,6 +29,7 @@ use crate::objc::{
 };
 use crate::Environment;
 use std::borrow::Cow;
+use std::cell::OnceCell;
 use std::collections::HashMap;
 use std::io::Write;
 use std::iter::Peekable;
@@ -79,13 +80,30 @@ unsafe impl SafeRead for cfstringStruct {}
 type Utf16String = Vec<u16>;
 
 /// Belongs to _touchHLE_NSString.
+///
+/// Host objects are replaced wholesale rather than edited when an
+/// `NSMutableString` is mutated, so the cached UTF-8 of a UTF-16 string never
+/// goes stale.
 enum StringHostObject {
     Utf8(Cow<'static, str>),
-    /// Not necessarily well-formed UTF-16: might contain unpaired surrogates.
-    Utf16(Utf16String),
+    Utf16 {
+        /// Not necessarily well-formed UTF-16: might contain unpaired
+        /// surrogates.
+        utf16: Utf16String,
+        /// The string converted to UTF-8, filled in the first time host code
+        /// needs it. [None] inside if the UTF-16 is not well-formed.
+        utf8_cache: OnceCell<Option<Cow<'static, str>>>,
+    },
 }
 impl HostObject for StringHostObject {}
 impl StringHostObject {
+    fn from_utf16(utf16: Utf16String) -> StringHostObject {
+        StringHostObject::Utf16 {
+            utf16,
+            utf8_cache: OnceCell::new(),
+        }
+    }
+
     fn decode(bytes: Cow<[u8]>, encoding: NSStringEncoding) -> StringHostObject {
         if bytes.len() == 0 {
             return StringHostObject::Utf8(Cow::Borrowed(""));
@@ -125,7 +143,7 @@ impl StringHostObject {
                 };
                 // TODO: Should the BOM be stripped? Always/sometimes/never?
 
-                StringHostObject::Utf16(if is_big_endian {
+                StringHostObject::from_utf16(if is_big_endian {
                     bytes
                         .chunks(2)
                         .map(|chunk| u16::from_be_bytes(chunk.try_into().unwrap()))
@@ -141,9 +159,27 @@ impl StringHostObject {
         }
     }
     fn to_utf8(&self) -> Result<Cow<'static, str>, FromUtf16Error> {
+        self.utf8().cloned()
+    }
+    /// Like [Self::to_utf8], but borrows the string rather than copying it.
+    fn as_utf8(&self) -> Result<&str, FromUtf16Error> {
+        self.utf8().map(|utf8| &**utf8)
+    }
+    /// Get the string as UTF-8. UTF-16 strings are only converted the first
+    /// time, the result is cached.
+    fn utf8(&self) -> Result<&Cow<'static, str>, FromUtf16Error> {
         match self {
-            StringHostObject::Utf8(utf8) => Ok(utf8.clone()),
-            StringHostObject::Utf16(utf16) => Ok(Cow::Owned(String::from_utf16(utf16)?)),
+            StringHostObject::Utf8(utf8) => Ok(utf8),
+            StringHostObject::Utf16 { utf16, utf8_cache } => {
+                let cached =
+                    utf8_cache.get_or_init(|| String::from_utf16(utf16).ok().map(Cow::Owned));
+                match cached {
+                    Some(utf8) => Ok(utf8),
+                    // Converting again is the only way to get the error value,
+                    // but this is the unhappy path anyway.
+                    None => Err(String::from_utf16(utf16).unwrap_err()),
+                }
+            }
         }
     }
     /// Mutate the object, converting to UTF-16 if the string was not already
@@ -151,22 +187,48 @@ impl StringHostObject {
     /// [true] if a conversion happened.
     fn convert_to_utf16_inplace(&mut self) -> (&mut Utf16String, bool) {
         let converted = match self {
-            Self::Utf8(_) => {
-                *self = Self::Utf16(self.iter_code_units().collect());
+            Self::Utf8(utf8) => {
+                let utf16 = utf8.encode_utf16().collect();
+                // The UTF-8 version is still useful to host code.
+                let utf8 = std::mem::take(utf8);
+                *self = Self::Utf16 {
+                    utf16,
+                    utf8_cache: OnceCell::from(Some(utf8)),
+                };
                 true
             }
-            Self::Utf16(_) => false,
+            Self::Utf16 { .. } => false,
         };
-        let Self::Utf16(utf16) = self else {
+        let Self::Utf16 { utf16, .. } = self else {
             unreachable!();
         };
         (utf16, converted)
     }
+    /// Check whether two strings have the same contents, comparing the
+    /// native representations where possible rather than converting them.
+    fn contents_equal(&self, other: &StringHostObject) -> bool {
+        match (self, other) {
+            (Self::Utf8(a), Self::Utf8(b)) => a == b,
+            (Self::Utf16 { utf16: a, .. }, Self::Utf16 { utf16: b, .. }) => a == b,
+            _ => self.iter_code_units().eq(other.iter_code_units()),
+        }
+    }
     /// Iterate over the string as UTF-16 code units.
     fn iter_code_units(&self) -> CodeUnitIterator {
         match self {
             StringHostObject::Utf8(utf8) => CodeUnitIterator::Utf8(utf8.encode_utf16()),
-            StringHostObject::Utf16(utf16) => CodeUnitIterator::Utf16(utf16.iter()),
+            StringHostObject::Utf16 { utf16, .. } => CodeUnitIterator::Utf16(utf16.iter()),
+        }
+    }
+}
+
+/// Hashes a string as its sequence of UTF-16 code units, so the result doesn't
+/// depend on the representation.
+struct HashableCodeUnits<'a>(&'a StringHostObject);
+impl std::hash::Hash for HashableCodeUnits<'_> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        for unit in self.0.iter_code_units() {
+            state.write_u16(unit);
         }
     }
 }
@@ -396,8 +458,9 @@ pub const CLASSES: ClassExports = objc_classes! {
 // TODO: debugDescription, localized description (is that a thing for NSString?)
 
 - (NSUInteger)hash {
-    // TODO: avoid copying
-    super::hash_helper(&to_rust_string(env, this))
+    // TODO: handle foreign subclasses of NSString
+    let host_object = env.objc.borrow::<StringHostObject>(this);
+    super::hash_helper(&HashableCodeUnits(host_object))
 }
 - (bool)isEqualTo:(id)other {
     if this == other {
@@ -407,21 +470,25 @@ pub const CLASSES: ClassExports = objc_classes! {
     if !msg![env; other isKindOfClass:class] {
         return false;
     }
-    // TODO: avoid copying
-    to_rust_string(env, this) == to_rust_string(env, other)
+    msg![env; this isEqualToString:other]
 }
 - (bool)isEqualToString:(id)other { // NSString*
     if this == other {
         return true;
     }
-    // TODO: avoid copying
-    to_rust_string(env, this) == to_rust_string(env, other)
+    if other == nil {
+        return false;
+    }
+    // TODO: handle foreign subclasses of NSString
+    let a = env.objc.borrow::<StringHostObject>(this);
+    let b = env.objc.borrow::<StringHostObject>(other);
+    a.contents_equal(b)
 }
 
 - (bool)hasPrefix:(id)str { // NSString*
-    // TODO: avoid copying
-    let str = to_rust_string(env, str).to_string();
-    to_rust_string(env, this).starts_with(&str)
+    // TODO: handle foreign subclasses of NSString
+    let prefix = env.objc.borrow::<StringHostObject>(str).iter_code_units();
+    env.objc.borrow::<StringHostObject>(this).iter_code_units().strip_prefix(&prefix).is_some()
 }
 
 - (NSComparisonResult)localizedCompare:(id)other { // NSString*
@@ -594,7 +661,7 @@ pub const CLASSES: ClassExports = objc_classes! {
     let class = env.objc.get_known_class("_touchHLE_NSString", &mut env.mem);
 
     let component_ns_strings = components.drain(..).map(|utf16| {
-        let host_object = Box::new(StringHostObject::Utf16(utf16));
+        let host_object = Box::new(StringHostObject::from_utf16(utf16));
         env.objc.alloc_object(class, host_object, &mut env.mem)
     }).collect();
     let array = ns_array::from_vec(env, component_ns_strings);
@@ -668,7 +735,7 @@ pub const CLASSES: ClassExports = objc_classes! {
     });
 
     let res = msg_class![env; _touchHLE_NSString alloc];
-    *env.objc.borrow_mut(res) = StringHostObject::Utf16(res_utf16);
+    *env.objc.borrow_mut(res) = StringHostObject::from_utf16(res_utf16);
     autorelease(env, res)
 }
 
@@ -682,7 +749,7 @@ pub const CLASSES: ClassExports = objc_classes! {
     });
 
     let res = msg_class![env; _touchHLE_NSString alloc];
-    *env.objc.borrow_mut(res) = StringHostObject::Utf16(res_utf16);
+    *env.objc.borrow_mut(res) = StringHostObject::from_utf16(res_utf16);
     autorelease(env, res)
 }
 
@@ -728,7 +795,7 @@ pub const CLASSES: ClassExports = objc_classes! {
         });
 
         let res = msg_class![env; _touchHLE_NSString alloc];
-        *env.objc.borrow_mut(res) = StringHostObject::Utf16(res_utf16);
+        *env.objc.borrow_mut(res) = StringHostObject::from_utf16(res_utf16);
         res
     };
     autorelease(env, res)
@@ -767,7 +834,7 @@ pub const CLASSES: ClassExports = objc_classes! {
     // subclass? The signature implies this isn't the case and it's probably not
     // worth the effort, but it's an interesting question.
     let result_ns_string = msg_class![env; _touchHLE_NSString alloc];
-    *env.objc.borrow_mut(result_ns_string) = StringHostObject::Utf16(result);
+    *env.objc.borrow_mut(result_ns_string) = StringHostObject::from_utf16(result);
     autorelease(env, result_ns_string)
 }
 
@@ -789,7 +856,7 @@ pub const CLASSES: ClassExports = objc_classes! {
     // subclass? The signature implies this isn't the case and it's probably not
     // worth the effort, but it's an interesting question.
     let class = env.objc.get_known_class("_touchHLE_NSString", &mut env.mem);
-    let host_object = Box::new(StringHostObject::Utf16(new_utf16));
+    let host_object = Box::new(StringHostObject::from_utf16(new_utf16));
     env.objc.alloc_object(class, host_object, &mut env.mem)
 }
 
@@ -801,48 +868,50 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (id)stringByDeletingLastPathComponent {
-    let string = to_rust_string(env, this); // TODO: avoid copying
-    let (res, _) = path_algorithms::split_last_path_component(&string);
-    let new_string = from_rust_string(env, String::from(res));
+    let string = to_rust_str(&env.objc, this);
+    let res = String::from(path_algorithms::split_last_path_component(&string).0);
+    let new_string = from_rust_string(env, res);
     autorelease(env, new_string)
 }
 
 - (id)lastPathComponent {
-    let string = to_rust_string(env, this); // TODO: avoid copying
-    let (_, res) = path_algorithms::split_last_path_component(&string);
-    let new_string = from_rust_string(env, String::from(res));
+    let string = to_rust_str(&env.objc, this);
+    let res = String::from(path_algorithms::split_last_path_component(&string).1);
+    let new_string = from_rust_string(env, res);
     autorelease(env, new_string)
 }
 
 - (id)pathComponents {
-    let string = to_rust_string(env, this); // TODO: avoid copying
-    let vec = path_algorithms::split_path_components(&string);
-    let vec = vec.iter().map(|component| {
-        from_rust_string(env, component.to_string())
+    let string = to_rust_str(&env.objc, this);
+    let vec: Vec<String> = path_algorithms::split_path_components(&string)
+        .iter()
+        .map(|component| component.to_string())
+        .collect();
+    let vec = vec.into_iter().map(|component| {
+        from_rust_string(env, component)
     }).collect();
     let array = ns_array::from_vec(env, vec);
     autorelease(env, array)
 }
 
 - (id)stringByDeletingPathExtension {
-    let string = to_rust_string(env, this); // TODO: avoid copying
-    let (res, _) = path_algorithms::split_path_extension(&string);
-    let new_string = from_rust_string(env, String::from(res));
+    let string = to_rust_str(&env.objc, this);
+    let res = String::from(path_algorithms::split_path_extension(&string).0);
+    let new_string = from_rust_string(env, res);
     autorelease(env, new_string)
 }
 
 - (id)pathExtension {
-    let string = to_rust_string(env, this); // TODO: avoid copying
-    let (_, res) = path_algorithms::split_path_extension(&string);
-    let new_string = from_rust_string(env, String::from(res));
+    let string = to_rust_str(&env.objc, this);
+    let res = String::from(path_algorithms::split_path_extension(&string).1);
+    let new_string = from_rust_string(env, res);
     autorelease(env, new_string)
 }
 
 - (id)stringByAppendingPathComponent:(id)component { // NSString*
-    // TODO: avoid copying
     // FIXME: check if Rust join() matches NSString (it probably doesn't)
-    let combined = GuestPath::new(&to_rust_string(env, this))
-        .join(to_rust_string(env, component));
+    let combined = GuestPath::new(&to_rust_str(&env.objc, this))
+        .join(to_rust_str(&env.objc, component));
     let new_string = from_rust_string(env, String::from(combined));
     autorelease(env, new_string)
 }
@@ -850,8 +919,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 - (id)stringByAppendingPathExtension:(id)extension { // NSString*
     // FIXME: handle edge cases like trailing '/' (may differ from Rust!)
     let mut combined = to_rust_string(env, this).into_owned();
-    // TODO: avoid copying
-    let extension_string = to_rust_string(env, extension);
+    let extension_string = to_rust_str(&env.objc, extension);
     if extension_string.len() > 0 {
         combined.push('.');
         combined.push_str(&extension_string);
@@ -906,6 +974,14 @@ pub const CLASSES: ClassExports = objc_classes! {
     ui_font::size_with_font(env, font, &text, Some((size, line_break_mode)))
 }
 
+- (CGSize)sizeWithFont:(id)font // UIFont*
+              forWidth:(CGFloat)width
+         lineBreakMode:(UILineBreakMode)line_break_mode {
+    // TODO: avoid copy
+    let text = to_rust_string(env, this);
+    ui_font::size_with_font_for_width(env, font, &text, width, line_break_mode)
+}
+
 - (CGSize)drawAtPoint:(CGPoint)point
              withFont:(id)font { // UIFont*
     // TODO: avoid copy
@@ -1108,7 +1184,7 @@ pub const CLASSES: ClassExports = objc_classes! {
     // TODO: optimize for more common cases (or maybe just call copy?)
     let mut code_units = Vec::new();
     for_each_code_unit(env, string, |_, c| code_units.push(c));
-    *env.objc.borrow_mut(this) = StringHostObject::Utf16(code_units);
+    *env.objc.borrow_mut(this) = StringHostObject::from_utf16(code_units);
     this
 }
 
@@ -1135,8 +1211,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 - (id)initWithContentsOfFile:(id)path // NSString*
                     encoding:(NSStringEncoding)encoding
                        error:(MutPtr<id>)error { // NSError**
-    // TODO: avoid copy?
-    let path = to_rust_string(env, path);
+    let path = to_rust_str(&env.objc, path);
     let Ok(bytes) = env.fs.read(GuestPath::new(&path)) else {
         assert!(error.is_null()); // TODO: error handling
         return nil;
@@ -1151,8 +1226,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (bool)isAbsolutePath {
-    // TODO: avoid copy?
-    let path = to_rust_string(env, this);
+    let path = to_rust_str(&env.objc, this);
     path.starts_with('/') || path.starts_with('~')
 }
 
@@ -1253,9 +1327,12 @@ pub fn register_constant_strings(bin: &MachO, mem: &mut Mem, objc: &mut ObjC) {
         let (host_object, class_name) = if flags == 0x7C8 {
             // ASCII
             let decoded = std::str::from_utf8(mem.bytes_at(bytes, length)).unwrap();
+            // These strings are never deallocated, so leaking a copy means
+            // host code can borrow them rather than copying them every time.
+            let decoded: &'static str = Box::leak(decoded.into());
 
             (
-                StringHostObject::Utf8(Cow::Owned(String::from(decoded))),
+                StringHostObject::Utf8(Cow::Borrowed(decoded)),
                 "_touchHLE_NSString_CFConstantString_UTF8",
             )
         } else if flags == 0x7D0 {
@@ -1267,7 +1344,7 @@ pub fn register_constant_strings(bin: &MachO, mem: &mut Mem, objc: &mut ObjC) {
                 .collect();
 
             (
-                StringHostObject::Utf16(decoded),
+                StringHostObject::from_utf16(decoded),
                 "_touchHLE_NSString_CFConstantString_UTF16",
             )
         } else {
@@ -1306,9 +1383,10 @@ pub fn from_rust_string(env: &mut Environment, from: String) -> id {
 /// Shortcut for host code, provides a view of a string in UTF-8.
 /// Warning: This may panic if the string is not valid UTF-16!
 ///
-/// TODO: Try to avoid allocating a new String in more cases.
-///
-/// TODO: Try to avoid converting from UTF-16 in more cases.
+/// This doesn't copy static strings (see [get_static_str]) and constant
+/// strings from the app binary, but it does copy other strings. Prefer
+/// [to_rust_str] when the result doesn't need to outlive a borrow of
+/// [ObjC].
 pub fn to_rust_string(env: &mut Environment, string: id) -> Cow<'static, str> {
     // TODO: handle foreign subclasses of NSString
     env.objc
@@ -1317,6 +1395,15 @@ pub fn to_rust_string(env: &mut Environment, string: id) -> Cow<'static, str> {
         .unwrap()
 }
 
+/// Shortcut for host code, like [to_rust_string] but borrowing the string's
+/// contents rather than copying them. UTF-16 strings are converted to UTF-8
+/// only once, the result is cached.
+/// Warning: This may panic if the string is not valid UTF-16!
+pub fn to_rust_str(objc: &ObjC, string: id) -> Cow<'_, str> {
+    // TODO: handle foreign subclasses of NSString
+    Cow::Borrowed(objc.borrow::<StringHostObject>(string).as_utf8().unwrap())
+}
+
 /// Shortcut for host code, calls a callback once for each UTF-16 code-unit in a
 /// string. This is equivalent to a for loop using the `length` and
 /// `characterAtIndex:` methods, but much more efficient.
@@ -1357,3 +1444,133 @@ fn is_match_at_position<F: Fn(u16, u16) -> bool>(
         }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Strings with non-ASCII characters, characters outside the BMP (which
+    /// take two UTF-16 code units) and interior nulls.
+    const MIXED_STRINGS: &[&str] = &[
+        "",
+        "hello",
+        "h\u{e9}llo w\u{f6}rld",
+        "\u{65e5}\u{672c}\u{8a9e}",
+        "emoji \u{1f600} and \u{1f44d}\u{1f3fd}",
+        "interior\0null",
+        "\0",
+        "trailing null\0",
+    ];
+
+    fn both_representations(string: &str) -> [StringHostObject; 2] {
+        [
+            StringHostObject::Utf8(Cow::Owned(string.to_string())),
+            StringHostObject::from_utf16(string.encode_utf16().collect()),
+        ]
+    }
+
+    fn hash(host_object: &StringHostObject) -> NSUInteger {
+        super::super::hash_helper(&HashableCodeUnits(host_object))
+    }
+
+    #[test]
+    fn utf8_is_the_same_for_both_representations() {
+        for &string in MIXED_STRINGS {
+            for host_object in both_representations(string) {
+                // What to_rust_string used to do every time: convert from
+                // scratch.
+                let reference = match &host_object {
+                    StringHostObject::Utf8(utf8) => utf8.to_string(),
+                    StringHostObject::Utf16 { utf16, .. } => String::from_utf16(utf16).unwrap(),
+                };
+                assert_eq!(reference, string);
+                assert_eq!(host_object.to_utf8().unwrap(), string);
+                assert_eq!(host_object.as_utf8().unwrap(), string);
+                // The second time, the cached conversion is used.
+                assert_eq!(host_object.as_utf8().unwrap(), string);
+                assert_eq!(host_object.to_utf8().unwrap(), string);
+            }
+        }
+    }
+
+    #[test]
+    fn length_is_in_utf16_code_units() {
+        for &string in MIXED_STRINGS {
+            let expected = string.encode_utf16().count();
+            for host_object in both_representations(string) {
+                assert_eq!(host_object.iter_code_units().count(), expected);
+            }
+        }
+        let [utf8, utf16] = both_representations("a\u{1f600}\0b");
+        assert_eq!(utf8.iter_code_units().count(), 5);
+        assert_eq!(utf16.iter_code_units().count(), 5);
+    }
+
+    #[test]
+    fn equality_and_hash_ignore_representation() {
+        for &a in MIXED_STRINGS {
+            for &b in MIXED_STRINGS {
+                for a_obj in both_representations(a) {
+                    for b_obj in both_representations(b) {
+                        assert_eq!(a_obj.contents_equal(&b_obj), a == b, "{:?} {:?}", a, b);
+                        if a == b {
+                            assert_eq!(hash(&a_obj), hash(&b_obj));
+                        }
+                    }
+                }
+            }
+        }
+        // Interior nulls are significant.
+        let [a, _] = both_representations("a\0b");
+        let [_, b] = both_representations("a");
+        assert!(!a.contents_equal(&b));
+        assert_ne!(hash(&a), hash(&b));
+    }
+
+    #[test]
+    fn conversion_to_utf16_keeps_utf8() {
+        for &string in MIXED_STRINGS {
+            let mut host_object = StringHostObject::Utf8(Cow::Borrowed(string));
+            let (utf16, converted) = host_object.convert_to_utf16_inplace();
+            assert!(converted);
+            assert_eq!(*utf16, string.encode_utf16().collect::<Vec<_>>());
+            let StringHostObject::Utf16 { ref utf8_cache, .. } = host_object else {
+                unreachable!();
+            };
+            assert_eq!(utf8_cache.get().unwrap().as_deref(), Some(string));
+            // Static strings stay borrowed.
+            assert!(matches!(host_object.to_utf8().unwrap(), Cow::Borrowed(_)));
+            let (_, converted) = host_object.convert_to_utf16_inplace();
+            assert!(!converted);
+        }
+    }
+
+    #[test]
+    fn ill_formed_utf16() {
+        // Unpaired surrogates can't be converted to UTF-8, but can still be
+        // compared and hashed.
+        let lone_surrogate = || StringHostObject::from_utf16(vec![0x61, 0xD800, 0x62]);
+        let a = lone_surrogate();
+        assert!(a.as_utf8().is_err());
+        assert!(a.as_utf8().is_err()); // the failure is cached too
+        assert!(a.to_utf8().is_err());
+        assert!(a.contents_equal(&lone_surrogate()));
+        assert_eq!(hash(&a), hash(&lone_surrogate()));
+        let [utf8, _] = both_representations("a\u{fffd}b");
+        assert!(!a.contents_equal(&utf8));
+        assert!(!utf8.contents_equal(&a));
+    }
+
+    #[test]
+    fn decode_utf16_matches_utf8() {
+        for &string in MIXED_STRINGS {
+            let utf8 =
+                StringHostObject::decode(Cow::Borrowed(string.as_bytes()), NSUTF8StringEncoding);
+            let le_bytes: Vec<u8> = string.encode_utf16().flat_map(u16::to_le_bytes).collect();
+            let utf16 =
+                StringHostObject::decode(Cow::Owned(le_bytes), NSUTF16LittleEndianStringEncoding);
+            assert!(utf8.contents_equal(&utf16));
+            assert_eq!(utf16.as_utf8().unwrap(), string);
+        }
+    }
+}
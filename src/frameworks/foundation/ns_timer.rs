@@ -8,9 +8,12 @@
 use super::ns_run_loop::NSDefaultRunLoopMode;
 use super::NSTimeInterval;
 use super::{ns_run_loop, ns_string};
+use crate::frameworks::core_foundation::time::{
+    absolute_time_to_instant, instant_to_absolute_time,
+};
 use crate::objc::{
-    autorelease, id, msg, msg_class, msg_send, nil, objc_classes, release, retain, ClassExports,
-    HostObject, SEL,
+    autorelease, id, msg, msg_class, msg_send, nil, objc_classes, release, retain, Class,
+    ClassExports, HostObject, SEL,
 };
 use crate::Environment;
 use std::time::{Duration, Instant};
@@ -43,34 +46,8 @@ pub const CLASSES: ClassExports = objc_classes! {
                    selector:(SEL)selector
                    userInfo:(id)user_info
                     repeats:(bool)repeats {
-    let ns_interval = ns_interval.max(0.0001);
-    let rust_interval = Duration::from_secs_f64(ns_interval);
-
-    retain(env, target);
-    retain(env, user_info);
-
-    let host_object = Box::new(NSTimerHostObject {
-        ns_interval,
-        rust_interval,
-        target,
-        selector,
-        user_info,
-        repeats,
-        due_by: Some(Instant::now().checked_add(rust_interval).unwrap()),
-        run_loop: nil,
-    });
-    let new = env.objc.alloc_object(this, host_object, &mut env.mem);
-
-    log_dbg!(
-        "New {} timer {:?}, interval {}s, target [{:?} {}], user info {:?}",
-        if repeats { "repeating" } else { "single-use" },
-        new,
-        ns_interval,
-        target,
-        selector.as_str(&env.mem),
-        user_info,
-    );
-
+    let due_by = env.clock.now() + Duration::from_secs_f64(ns_interval.max(0.0001));
+    let new = alloc_timer(env, this, due_by, ns_interval, target, selector, user_info, repeats);
     autorelease(env, new)
 }
 
@@ -118,11 +95,23 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.borrow::<NSTimerHostObject>(this).due_by.is_some()
 }
 
+- (id)fireDate {
+    let due_by = env.objc.borrow::<NSTimerHostObject>(this).due_by;
+    // The documentation doesn't say what an invalid timer returns.
+    let due_by = due_by.unwrap_or_else(|| env.clock.now());
+    let time_interval = instant_to_absolute_time(env, due_by);
+    msg_class![env; NSDate dateWithTimeIntervalSinceReferenceDate:time_interval]
+}
+- (())setFireDate:(id)date { // NSDate*
+    let time_interval: NSTimeInterval = msg![env; date timeIntervalSinceReferenceDate];
+    let due_by = absolute_time_to_instant(env, time_interval);
+    set_fire_date(env, this, due_by);
+}
+
 - (())invalidate {
     // Timer might already be invalid, don't try to remove it twice.
     if env.objc.borrow_mut::<NSTimerHostObject>(this).due_by.take().is_some() {
-        let run_loop: id = msg_class![env; NSRunLoop currentRunLoop];
-        ns_run_loop::remove_timer(env, run_loop, this);
+        remove_from_run_loop(env, this);
     }
 }
 
@@ -153,6 +142,97 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 };
 
+#[allow(clippy::too_many_arguments)]
+fn alloc_timer(
+    env: &mut Environment,
+    class: Class,
+    due_by: Instant,
+    ns_interval: NSTimeInterval,
+    target: id,
+    selector: SEL,
+    user_info: id,
+    repeats: bool,
+) -> id {
+    let ns_interval = ns_interval.max(0.0001);
+    let rust_interval = Duration::from_secs_f64(ns_interval);
+
+    retain(env, target);
+    retain(env, user_info);
+
+    let host_object = Box::new(NSTimerHostObject {
+        ns_interval,
+        rust_interval,
+        target,
+        selector,
+        user_info,
+        repeats,
+        due_by: Some(due_by),
+        run_loop: nil,
+    });
+    let new = env.objc.alloc_object(class, host_object, &mut env.mem);
+
+    log_dbg!(
+        "New {} timer {:?}, interval {}s, target [{:?} {}], user info {:?}",
+        if repeats { "repeating" } else { "single-use" },
+        new,
+        ns_interval,
+        target,
+        selector.as_str(&env.mem),
+        user_info,
+    );
+
+    new
+}
+
+/// For use by `CFRunLoopTimer`: create a timer that first fires at a
+/// particular time. The caller owns the result.
+pub fn new_timer(
+    env: &mut Environment,
+    due_by: Instant,
+    ns_interval: NSTimeInterval,
+    target: id,
+    selector: SEL,
+    repeats: bool,
+) -> id {
+    let class = env.objc.get_known_class("NSTimer", &mut env.mem);
+    alloc_timer(
+        env,
+        class,
+        due_by,
+        ns_interval,
+        target,
+        selector,
+        nil,
+        repeats,
+    )
+}
+
+/// Change when a timer will next fire. This does nothing if the timer has
+/// been invalidated.
+pub fn set_fire_date(env: &mut Environment, timer: id, due_by: Instant) {
+    let host_object = env.objc.borrow_mut::<NSTimerHostObject>(timer);
+    if let Some(old_due_by) = host_object.due_by.as_mut() {
+        *old_due_by = due_by;
+    }
+}
+
+/// When a timer will next fire, or [None] if it has been invalidated.
+pub fn fire_date(env: &mut Environment, timer: id) -> Option<Instant> {
+    env.objc.borrow::<NSTimerHostObject>(timer).due_by
+}
+
+/// Remove a timer from the run loop it's scheduled in, if any, without
+/// invalidating it.
+pub fn remove_from_run_loop(env: &mut Environment, timer: id) {
+    let run_loop = std::mem::replace(
+        &mut env.objc.borrow_mut::<NSTimerHostObject>(timer).run_loop,
+        nil,
+    );
+    if run_loop != nil {
+        ns_run_loop::remove_timer(env, run_loop, timer);
+    }
+}
+
 /// For use by `NSRunLoop`
 pub(super) fn set_run_loop(env: &mut Environment, timer: id, run_loop: id) {
     let host_object = env.objc.borrow_mut::<NSTimerHostObject>(timer);
@@ -179,7 +259,7 @@ pub(super) fn handle_timer(env: &mut Environment, timer: id) -> Option<Instant>
     // invalidated timers should have already been removed from the run loop
     let due_by = due_by.unwrap();
 
-    let now = Instant::now();
+    let now = env.clock.now();
 
     if due_by > now {
         return Some(due_by);
@@ -216,6 +296,7 @@ pub(super) fn handle_timer(env: &mut Environment, timer: id) -> Option<Instant>
         let advance_by = rust_interval.checked_mul(advance_by).unwrap();
         Some(due_by.checked_add(advance_by).unwrap())
     } else {
+        env.objc.borrow_mut::<NSTimerHostObject>(timer).run_loop = nil;
         ns_run_loop::remove_timer(env, run_loop, timer);
         None
     };
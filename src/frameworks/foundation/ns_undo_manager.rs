@@ -0,0 +1,691 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `NSUndoManager`.
+//!
+//! Resources:
+//! - Apple's [Undo Architecture](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/UndoArchitecture/UndoArchitecture.html)
+
+use super::ns_string::{from_rust_string, get_static_str, to_rust_string};
+use super::NSUInteger;
+use crate::dyld::{ConstantExports, HostConstant};
+use crate::objc::{
+    autorelease, id, msg, msg_class, msg_send, nil, objc_classes, release, retain, ClassExports,
+    ForwardedMessage, HostObject, MethodSignature, NSZonePtr, SEL,
+};
+use crate::Environment;
+
+pub const NSUndoManagerCheckpointNotification: &str = "NSUndoManagerCheckpointNotification";
+pub const NSUndoManagerDidOpenUndoGroupNotification: &str =
+    "NSUndoManagerDidOpenUndoGroupNotification";
+pub const NSUndoManagerWillCloseUndoGroupNotification: &str =
+    "NSUndoManagerWillCloseUndoGroupNotification";
+pub const NSUndoManagerWillUndoChangeNotification: &str = "NSUndoManagerWillUndoChangeNotification";
+pub const NSUndoManagerDidUndoChangeNotification: &str = "NSUndoManagerDidUndoChangeNotification";
+pub const NSUndoManagerWillRedoChangeNotification: &str = "NSUndoManagerWillRedoChangeNotification";
+pub const NSUndoManagerDidRedoChangeNotification: &str = "NSUndoManagerDidRedoChangeNotification";
+
+pub const CONSTANTS: ConstantExports = &[
+    (
+        "_NSUndoManagerCheckpointNotification",
+        HostConstant::NSString(NSUndoManagerCheckpointNotification),
+    ),
+    (
+        "_NSUndoManagerDidOpenUndoGroupNotification",
+        HostConstant::NSString(NSUndoManagerDidOpenUndoGroupNotification),
+    ),
+    (
+        "_NSUndoManagerWillCloseUndoGroupNotification",
+        HostConstant::NSString(NSUndoManagerWillCloseUndoGroupNotification),
+    ),
+    (
+        "_NSUndoManagerWillUndoChangeNotification",
+        HostConstant::NSString(NSUndoManagerWillUndoChangeNotification),
+    ),
+    (
+        "_NSUndoManagerDidUndoChangeNotification",
+        HostConstant::NSString(NSUndoManagerDidUndoChangeNotification),
+    ),
+    (
+        "_NSUndoManagerWillRedoChangeNotification",
+        HostConstant::NSString(NSUndoManagerWillRedoChangeNotification),
+    ),
+    (
+        "_NSUndoManagerDidRedoChangeNotification",
+        HostConstant::NSString(NSUndoManagerDidRedoChangeNotification),
+    ),
+];
+
+#[derive(Default)]
+pub struct State {
+    /// Undo managers with a group that was opened automatically because of
+    /// `groupsByEvent`, which should be closed at the end of the current run
+    /// loop iteration. These are weak references.
+    open_event_groups: Vec<id>,
+}
+
+enum UndoAction {
+    /// From `registerUndoWithTarget:selector:object:`. The target is a weak
+    /// reference and the object is a strong reference.
+    Selector {
+        target: id,
+        selector: SEL,
+        object: id,
+    },
+    /// From a message sent to the proxy returned by
+    /// `prepareWithInvocationTarget:`. The target is a weak reference and
+    /// any object arguments are strong references.
+    Invocation {
+        target: id,
+        message: ForwardedMessage,
+    },
+}
+
+#[derive(Default)]
+struct UndoGroup {
+    /// In the order they were registered. They are performed in reverse.
+    actions: Vec<UndoAction>,
+    /// `NSString*`, strong reference, may be `nil`.
+    action_name: id,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum UndoState {
+    Normal,
+    Undoing,
+    Redoing,
+}
+
+struct NSUndoManagerHostObject {
+    /// Oldest group first.
+    undo_stack: Vec<UndoGroup>,
+    /// Oldest group first.
+    redo_stack: Vec<UndoGroup>,
+    /// Groups that have been opened but not closed yet, outermost first.
+    open_groups: Vec<UndoGroup>,
+    state: UndoState,
+    /// Maximum size of the undo stack, or 0 for no limit.
+    levels_of_undo: NSUInteger,
+    groups_by_event: bool,
+    /// The outermost open group was opened automatically because of
+    /// `groupsByEvent`.
+    event_group_open: bool,
+    /// Incremented by `disableUndoRegistration`, decremented by
+    /// `enableUndoRegistration`.
+    registration_disabled: NSUInteger,
+    /// Target for the next message sent to the proxy. Weak reference.
+    prepared_target: id,
+    /// `_touchHLE_NSUndoManagerProxy*`, strong reference, created lazily.
+    proxy: id,
+}
+impl HostObject for NSUndoManagerHostObject {}
+
+/// Belongs to `_touchHLE_NSUndoManagerProxy`.
+struct UndoManagerProxyHostObject {
+    /// Weak reference, the undo manager owns the proxy.
+    undo_manager: id,
+}
+impl HostObject for UndoManagerProxyHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation NSUndoManager: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(NSUndoManagerHostObject {
+        undo_stack: Vec::new(),
+        redo_stack: Vec::new(),
+        open_groups: Vec::new(),
+        state: UndoState::Normal,
+        levels_of_undo: 0,
+        groups_by_event: true,
+        event_group_open: false,
+        registration_disabled: 0,
+        prepared_target: nil,
+        proxy: nil,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (())dealloc {
+    env.framework_state
+        .foundation
+        .ns_undo_manager
+        .open_event_groups
+        .retain(|&undo_manager| undo_manager != this);
+
+    let host_object = env.objc.borrow_mut::<NSUndoManagerHostObject>(this);
+    let groups: Vec<UndoGroup> = std::mem::take(&mut host_object.undo_stack)
+        .into_iter()
+        .chain(std::mem::take(&mut host_object.redo_stack))
+        .chain(std::mem::take(&mut host_object.open_groups))
+        .collect();
+    let proxy = host_object.proxy;
+    for group in groups {
+        release_group(env, group);
+    }
+    release(env, proxy);
+
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+// Registering undo operations
+
+- (())registerUndoWithTarget:(id)target
+                    selector:(SEL)selector
+                      object:(id)object {
+    retain(env, object);
+    register_action(env, this, UndoAction::Selector {
+        target,
+        selector,
+        object,
+    });
+}
+
+- (id)prepareWithInvocationTarget:(id)target {
+    let proxy = env.objc.borrow::<NSUndoManagerHostObject>(this).proxy;
+    let proxy = if proxy == nil {
+        let class = env.objc.get_known_class("_touchHLE_NSUndoManagerProxy", &mut env.mem);
+        env.objc.set_forwarding_handler(class, forward_to_undo_manager);
+        let host_object = Box::new(UndoManagerProxyHostObject { undo_manager: this });
+        let proxy = env.objc.alloc_object(class, host_object, &mut env.mem);
+        env.objc.borrow_mut::<NSUndoManagerHostObject>(this).proxy = proxy;
+        proxy
+    } else {
+        proxy
+    };
+    env.objc.borrow_mut::<NSUndoManagerHostObject>(this).prepared_target = target;
+    proxy
+}
+
+- (())removeAllActions {
+    let host_object = env.objc.borrow_mut::<NSUndoManagerHostObject>(this);
+    host_object.registration_disabled = 0;
+    let groups: Vec<UndoGroup> = std::mem::take(&mut host_object.undo_stack)
+        .into_iter()
+        .chain(std::mem::take(&mut host_object.redo_stack))
+        .collect();
+    for group in groups {
+        release_group(env, group);
+    }
+}
+
+- (())removeAllActionsWithTarget:(id)target {
+    let host_object = env.objc.borrow_mut::<NSUndoManagerHostObject>(this);
+    let mut removed = Vec::new();
+    for group in host_object
+        .undo_stack
+        .iter_mut()
+        .chain(host_object.redo_stack.iter_mut())
+        .chain(host_object.open_groups.iter_mut())
+    {
+        let (keep, remove): (Vec<_>, Vec<_>) = std::mem::take(&mut group.actions)
+            .into_iter()
+            .partition(|action| action_target(action) != target);
+        group.actions = keep;
+        removed.extend(remove);
+    }
+    let mut empty_groups = Vec::new();
+    for stack in [&mut host_object.undo_stack, &mut host_object.redo_stack] {
+        let (keep, remove): (Vec<_>, Vec<_>) = std::mem::take(stack)
+            .into_iter()
+            .partition(|group| !group.actions.is_empty());
+        *stack = keep;
+        empty_groups.extend(remove);
+    }
+    for action in removed {
+        release_action(env, action);
+    }
+    for group in empty_groups {
+        release_group(env, group);
+    }
+}
+
+- (bool)isUndoRegistrationEnabled {
+    env.objc.borrow::<NSUndoManagerHostObject>(this).registration_disabled == 0
+}
+- (())disableUndoRegistration {
+    env.objc.borrow_mut::<NSUndoManagerHostObject>(this).registration_disabled += 1;
+}
+- (())enableUndoRegistration {
+    let host_object = env.objc.borrow_mut::<NSUndoManagerHostObject>(this);
+    if host_object.registration_disabled == 0 {
+        log!("Warning: [(NSUndoManager*){:?} enableUndoRegistration] without a matching disableUndoRegistration, ignoring", this);
+        return;
+    }
+    host_object.registration_disabled -= 1;
+}
+
+// Grouping
+
+- (())beginUndoGrouping {
+    begin_group(env, this);
+}
+- (())endUndoGrouping {
+    let host_object = env.objc.borrow::<NSUndoManagerHostObject>(this);
+    if host_object.open_groups.is_empty() {
+        log!("Warning: [(NSUndoManager*){:?} endUndoGrouping] without a matching beginUndoGrouping, ignoring", this);
+        return;
+    }
+    end_group(env, this);
+}
+
+- (NSUInteger)groupingLevel {
+    env.objc.borrow::<NSUndoManagerHostObject>(this).open_groups.len().try_into().unwrap()
+}
+
+- (bool)groupsByEvent {
+    env.objc.borrow::<NSUndoManagerHostObject>(this).groups_by_event
+}
+- (())setGroupsByEvent:(bool)groups_by_event {
+    env.objc.borrow_mut::<NSUndoManagerHostObject>(this).groups_by_event = groups_by_event;
+}
+
+- (())setRunLoopModes:(id)_modes { // NSArray*
+    // TODO: the group is closed at the end of every run loop iteration,
+    // regardless of mode.
+}
+
+// Undo and redo
+
+- (NSUInteger)levelsOfUndo {
+    env.objc.borrow::<NSUndoManagerHostObject>(this).levels_of_undo
+}
+- (())setLevelsOfUndo:(NSUInteger)levels {
+    env.objc.borrow_mut::<NSUndoManagerHostObject>(this).levels_of_undo = levels;
+    evict_old_groups(env, this);
+}
+
+- (bool)canUndo {
+    let host_object = env.objc.borrow::<NSUndoManagerHostObject>(this);
+    !host_object.undo_stack.is_empty()
+        || host_object.open_groups.iter().any(|group| !group.actions.is_empty())
+}
+- (bool)canRedo {
+    !env.objc.borrow::<NSUndoManagerHostObject>(this).redo_stack.is_empty()
+}
+
+- (bool)isUndoing {
+    env.objc.borrow::<NSUndoManagerHostObject>(this).state == UndoState::Undoing
+}
+- (bool)isRedoing {
+    env.objc.borrow::<NSUndoManagerHostObject>(this).state == UndoState::Redoing
+}
+
+- (())undo {
+    // An automatically opened group is closed first, so its actions can be
+    // undone.
+    let host_object = env.objc.borrow_mut::<NSUndoManagerHostObject>(this);
+    if host_object.event_group_open && host_object.open_groups.len() == 1 {
+        host_object.event_group_open = false;
+        end_group(env, this);
+    }
+    () = msg![env; this undoNestedGroup];
+}
+- (())undoNestedGroup {
+    perform_group(env, this, UndoState::Undoing);
+}
+- (())redo {
+    perform_group(env, this, UndoState::Redoing);
+}
+
+// Action names
+
+- (id)undoActionName {
+    let host_object = env.objc.borrow::<NSUndoManagerHostObject>(this);
+    let name = match host_object.open_groups.first() {
+        Some(group) if !group.actions.is_empty() => group.action_name,
+        _ => host_object.undo_stack.last().map_or(nil, |group| group.action_name),
+    };
+    if name == nil {
+        get_static_str(env, "")
+    } else {
+        name
+    }
+}
+- (id)redoActionName {
+    let host_object = env.objc.borrow::<NSUndoManagerHostObject>(this);
+    let name = host_object.redo_stack.last().map_or(nil, |group| group.action_name);
+    if name == nil {
+        get_static_str(env, "")
+    } else {
+        name
+    }
+}
+- (())setActionName:(id)name { // NSString*
+    let name: id = msg![env; name copy];
+    let host_object = env.objc.borrow_mut::<NSUndoManagerHostObject>(this);
+    // The name belongs to the group currently being registered, or if there
+    // is none, the one registered most recently.
+    let group = if !host_object.open_groups.is_empty() {
+        host_object.open_groups.first_mut()
+    } else if host_object.state == UndoState::Undoing {
+        host_object.redo_stack.last_mut()
+    } else {
+        host_object.undo_stack.last_mut()
+    };
+    let old_name = match group {
+        Some(group) => std::mem::replace(&mut group.action_name, name),
+        None => name,
+    };
+    release(env, old_name);
+}
+
+- (id)undoMenuItemTitle {
+    let name: id = msg![env; this undoActionName];
+    msg![env; this undoMenuTitleForUndoActionName:name]
+}
+- (id)redoMenuItemTitle {
+    let name: id = msg![env; this redoActionName];
+    msg![env; this redoMenuTitleForUndoActionName:name]
+}
+- (id)undoMenuTitleForUndoActionName:(id)name { // NSString*
+    menu_title(env, "Undo", name)
+}
+- (id)redoMenuTitleForUndoActionName:(id)name { // NSString*
+    menu_title(env, "Redo", name)
+}
+
+@end
+
+// Messages sent to this that it has no method for are recorded as undo
+// actions, see forward_to_undo_manager().
+@implementation _touchHLE_NSUndoManagerProxy: NSObject
+@end
+
+};
+
+fn menu_title(env: &mut Environment, verb: &str, name: id) -> id {
+    let name = to_rust_string(env, name);
+    let title = if name.is_empty() {
+        verb.to_string()
+    } else {
+        format!("{} {}", verb, name)
+    };
+    let title = from_rust_string(env, title);
+    autorelease(env, title)
+}
+
+fn action_target(action: &UndoAction) -> id {
+    match *action {
+        UndoAction::Selector { target, .. } | UndoAction::Invocation { target, .. } => target,
+    }
+}
+
+fn release_action(env: &mut Environment, action: UndoAction) {
+    match action {
+        UndoAction::Selector { object, .. } => release(env, object),
+        UndoAction::Invocation { message, .. } => {
+            for object in message.object_args() {
+                release(env, object);
+            }
+        }
+    }
+}
+
+fn release_group(env: &mut Environment, group: UndoGroup) {
+    for action in group.actions {
+        release_action(env, action);
+    }
+    release(env, group.action_name);
+}
+
+fn post_notification(env: &mut Environment, this: id, name: &'static str) {
+    let name = get_static_str(env, name);
+    let center: id = msg_class![env; NSNotificationCenter defaultCenter];
+    () = msg![env; center postNotificationName:name object:this];
+}
+
+fn begin_group(env: &mut Environment, this: id) {
+    let host_object = env.objc.borrow_mut::<NSUndoManagerHostObject>(this);
+    host_object.open_groups.push(UndoGroup::default());
+    if host_object.state == UndoState::Normal {
+        post_notification(env, this, NSUndoManagerDidOpenUndoGroupNotification);
+    }
+}
+
+fn end_group(env: &mut Environment, this: id) {
+    if env.objc.borrow::<NSUndoManagerHostObject>(this).state == UndoState::Normal {
+        post_notification(env, this, NSUndoManagerWillCloseUndoGroupNotification);
+    }
+    let host_object = env.objc.borrow_mut::<NSUndoManagerHostObject>(this);
+    let group = host_object.open_groups.pop().unwrap();
+
+    // A nested group becomes part of its parent group.
+    if let Some(parent) = host_object.open_groups.last_mut() {
+        parent.actions.extend(group.actions);
+        if parent.action_name == nil {
+            parent.action_name = group.action_name;
+        } else {
+            release(env, group.action_name);
+        }
+        return;
+    }
+
+    if group.actions.is_empty() {
+        release_group(env, group);
+        return;
+    }
+
+    // While undoing, the actions registered are for redoing, and vice-versa.
+    let state = host_object.state;
+    if state == UndoState::Undoing {
+        host_object.redo_stack.push(group);
+        return;
+    }
+    host_object.undo_stack.push(group);
+    // Doing something new makes the old redo actions invalid.
+    let redo_stack = if state == UndoState::Normal {
+        std::mem::take(&mut host_object.redo_stack)
+    } else {
+        Vec::new()
+    };
+    for group in redo_stack {
+        release_group(env, group);
+    }
+    evict_old_groups(env, this);
+}
+
+/// Remove the oldest groups from the undo stack if there are too many.
+fn evict_old_groups(env: &mut Environment, this: id) {
+    let host_object = env.objc.borrow_mut::<NSUndoManagerHostObject>(this);
+    let levels = host_object.levels_of_undo as usize;
+    if levels == 0 || host_object.undo_stack.len() <= levels {
+        return;
+    }
+    let excess = host_object.undo_stack.len() - levels;
+    let evicted: Vec<_> = host_object.undo_stack.drain(..excess).collect();
+    for group in evicted {
+        release_group(env, group);
+    }
+}
+
+fn register_action(env: &mut Environment, this: id, action: UndoAction) {
+    let host_object = env.objc.borrow_mut::<NSUndoManagerHostObject>(this);
+    if host_object.registration_disabled != 0 {
+        release_action(env, action);
+        return;
+    }
+    if host_object.open_groups.is_empty() {
+        if !host_object.groups_by_event {
+            log!(
+                "Warning: undo action registered with (NSUndoManager*){:?} outside of a group, ignoring",
+                this
+            );
+            release_action(env, action);
+            return;
+        }
+        host_object.event_group_open = true;
+        env.framework_state
+            .foundation
+            .ns_undo_manager
+            .open_event_groups
+            .push(this);
+        begin_group(env, this);
+    }
+    env.objc
+        .borrow_mut::<NSUndoManagerHostObject>(this)
+        .open_groups
+        .last_mut()
+        .unwrap()
+        .actions
+        .push(action);
+}
+
+/// Implementation of `undoNestedGroup` (`state` is [UndoState::Undoing]) and
+/// `redo` (`state` is [UndoState::Redoing]).
+fn perform_group(env: &mut Environment, this: id, state: UndoState) {
+    let host_object = env.objc.borrow_mut::<NSUndoManagerHostObject>(this);
+    if !host_object.open_groups.is_empty() {
+        log!(
+            "Warning: (NSUndoManager*){:?} can't undo or redo while a group is open, ignoring",
+            this
+        );
+        return;
+    }
+    assert_eq!(host_object.state, UndoState::Normal);
+    let stack = if state == UndoState::Undoing {
+        &mut host_object.undo_stack
+    } else {
+        &mut host_object.redo_stack
+    };
+    let Some(group) = stack.pop() else {
+        return;
+    };
+
+    post_notification(
+        env,
+        this,
+        if state == UndoState::Undoing {
+            NSUndoManagerWillUndoChangeNotification
+        } else {
+            NSUndoManagerWillRedoChangeNotification
+        },
+    );
+
+    // Actions registered while performing the group go into a new group with
+    // the same name, which ends up on the opposite stack.
+    retain(env, group.action_name);
+    let host_object = env.objc.borrow_mut::<NSUndoManagerHostObject>(this);
+    host_object.state = state;
+    host_object.open_groups.push(UndoGroup {
+        actions: Vec::new(),
+        action_name: group.action_name,
+    });
+
+    log_dbg!(
+        "(NSUndoManager*){:?} performing {} actions ({:?})",
+        this,
+        group.actions.len(),
+        state
+    );
+    for action in group.actions.iter().rev() {
+        match *action {
+            UndoAction::Selector {
+                target,
+                selector,
+                object,
+            } => {
+                () = msg_send(env, (target, selector, object));
+            }
+            UndoAction::Invocation {
+                target,
+                ref message,
+            } => message.send(env, target),
+        }
+    }
+
+    end_group(env, this);
+    env.objc.borrow_mut::<NSUndoManagerHostObject>(this).state = UndoState::Normal;
+    release_group(env, group);
+
+    post_notification(
+        env,
+        this,
+        if state == UndoState::Undoing {
+            NSUndoManagerDidUndoChangeNotification
+        } else {
+            NSUndoManagerDidRedoChangeNotification
+        },
+    );
+}
+
+/// [crate::objc::ForwardingHandler] for `_touchHLE_NSUndoManagerProxy`.
+fn forward_to_undo_manager(env: &mut Environment, proxy: id, selector: SEL) {
+    // Like a message to nil, nothing is returned.
+    let undo_manager = env
+        .objc
+        .borrow::<UndoManagerProxyHostObject>(proxy)
+        .undo_manager;
+    let target = std::mem::replace(
+        &mut env
+            .objc
+            .borrow_mut::<NSUndoManagerHostObject>(undo_manager)
+            .prepared_target,
+        nil,
+    );
+    if target == nil {
+        log!(
+            "Warning: [(NSUndoManager*){:?} prepareWithInvocationTarget:] wasn't called before sending {} to its proxy, ignoring",
+            undo_manager,
+            selector.as_str(&env.mem)
+        );
+        env.cpu.regs_mut()[0..2].fill(0);
+        return;
+    }
+
+    let signature = env
+        .objc
+        .method_signature(&env.mem, target, selector)
+        .unwrap_or_else(|| {
+            log!(
+                "Warning: type encoding of {} unknown for {:?}, assuming arguments are words and not retaining them",
+                selector.as_str(&env.mem),
+                target
+            );
+            MethodSignature::guess_from_selector(selector.as_str(&env.mem))
+        });
+    let message = ForwardedMessage::capture(env, selector, signature);
+    env.cpu.regs_mut()[0..2].fill(0);
+
+    for object in message.object_args() {
+        retain(env, object);
+    }
+    register_action(
+        env,
+        undo_manager,
+        UndoAction::Invocation { target, message },
+    );
+}
+
+/// For use by `NSRunLoop`: close the groups opened automatically during this
+/// run loop iteration.
+pub fn handle_undo_managers(env: &mut Environment) {
+    let undo_managers = std::mem::take(
+        &mut env
+            .framework_state
+            .foundation
+            .ns_undo_manager
+            .open_event_groups,
+    );
+    for undo_manager in undo_managers {
+        let host_object = env.objc.borrow_mut::<NSUndoManagerHostObject>(undo_manager);
+        if !std::mem::take(&mut host_object.event_group_open) {
+            continue;
+        }
+        // If the app has opened a group itself and not closed it yet, the
+        // automatic group can't be closed either.
+        if host_object.open_groups.len() != 1 {
+            log!(
+                "Warning: (NSUndoManager*){:?} has unclosed undo groups at the end of the run loop iteration",
+                undo_manager
+            );
+            continue;
+        }
+        end_group(env, undo_manager);
+    }
+}
@@ -6,7 +6,9 @@
 //! The `NSValue` class cluster, including `NSNumber`.
 
 use super::NSUInteger;
-use crate::frameworks::foundation::ns_string::from_rust_string;
+use crate::frameworks::core_graphics::cg_affine_transform::CGAffineTransform;
+use crate::frameworks::core_graphics::{CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::ns_string::{from_rust_string, to_rust_string};
 use crate::frameworks::foundation::NSInteger;
 use crate::objc::{
     autorelease, id, msg, msg_class, objc_classes, retain, Class, ClassExports, HostObject,
@@ -40,6 +42,17 @@ enum NSNumberHostObject {
 }
 impl HostObject for NSNumberHostObject {}
 
+/// Host object for `NSValue`s that aren't `NSNumber`s. Only the types that are
+/// used so far are supported.
+#[derive(Debug)]
+enum NSValueHostObject {
+    CGPoint(CGPoint),
+    CGSize(CGSize),
+    CGRect(CGRect),
+    CGAffineTransform(CGAffineTransform),
+}
+impl HostObject for NSValueHostObject {}
+
 impl NSNumberHostObject {
     fn as_bool(&self) -> bool {
         match self {
@@ -62,10 +75,55 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
 
-// NSValue is an abstract class. None of the things it should provide are
+// NSValue is an abstract class. Most of the things it should provide are not
 // implemented here yet (TODO).
 @implementation NSValue: NSObject
 
+// These are from UIKit's NSValue(UIGeometryKeyCoding) category, but they can
+// live here since we don't support defining categories.
++ (id)valueWithCGPoint:(CGPoint)point {
+    let new = env.objc.alloc_object(this, Box::new(NSValueHostObject::CGPoint(point)), &mut env.mem);
+    autorelease(env, new)
+}
++ (id)valueWithCGSize:(CGSize)size {
+    let new = env.objc.alloc_object(this, Box::new(NSValueHostObject::CGSize(size)), &mut env.mem);
+    autorelease(env, new)
+}
++ (id)valueWithCGRect:(CGRect)rect {
+    let new = env.objc.alloc_object(this, Box::new(NSValueHostObject::CGRect(rect)), &mut env.mem);
+    autorelease(env, new)
+}
++ (id)valueWithCGAffineTransform:(CGAffineTransform)transform {
+    let host_object = Box::new(NSValueHostObject::CGAffineTransform(transform));
+    let new = env.objc.alloc_object(this, host_object, &mut env.mem);
+    autorelease(env, new)
+}
+
+- (CGPoint)CGPointValue {
+    let &NSValueHostObject::CGPoint(point) = env.objc.borrow(this) else {
+        panic!("{:?} is not a CGPoint value", this);
+    };
+    point
+}
+- (CGSize)CGSizeValue {
+    let &NSValueHostObject::CGSize(size) = env.objc.borrow(this) else {
+        panic!("{:?} is not a CGSize value", this);
+    };
+    size
+}
+- (CGRect)CGRectValue {
+    let &NSValueHostObject::CGRect(rect) = env.objc.borrow(this) else {
+        panic!("{:?} is not a CGRect value", this);
+    };
+    rect
+}
+- (CGAffineTransform)CGAffineTransformValue {
+    let &NSValueHostObject::CGAffineTransform(transform) = env.objc.borrow(this) else {
+        panic!("{:?} is not a CGAffineTransform value", this);
+    };
+    transform
+}
+
 // NSCopying implementation
 - (id)copyWithZone:(NSZonePtr)_zone {
     retain(env, this)
@@ -239,6 +297,40 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 @end
 
+// NSDecimalNumber is approximated with a double, which is precise enough for
+// what apps usually use it for, e.g. displaying prices.
+@implementation NSDecimalNumber: NSNumber
+
++ (id)decimalNumberWithString:(id)string { // NSString*
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithString:string];
+    autorelease(env, new)
+}
+
++ (id)zero {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithDouble:0.0];
+    autorelease(env, new)
+}
+
++ (id)one {
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithDouble:1.0];
+    autorelease(env, new)
+}
+
+- (id)initWithString:(id)string { // NSString*
+    // TODO: locale-specific decimal separators
+    let value = to_rust_string(env, string).trim().parse().unwrap_or(f64::NAN);
+    msg![env; this initWithDouble:value]
+}
+
+- (id)stringValue {
+    msg![env; this description]
+}
+
+@end
+
 };
 
 fn equality_helper(env: &mut Environment, this: id, other: id) -> bool {
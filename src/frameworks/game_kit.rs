@@ -0,0 +1,386 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! The GameKit framework.
+//!
+//! touchHLE can't connect to Game Center, so this is a local imitation of it:
+//! the local player is always signed in (see `--game-center-alias=`), and
+//! reported scores and achievements are saved in the app's sandbox, so that
+//! they survive relaunches and the app can read them back. Multiplayer isn't
+//! supported: matchmaking always fails with an error.
+//!
+//! Like on a real device, completion handlers are never called from within the
+//! method they're passed to, but later, from the run loop.
+
+pub mod gk_achievement;
+pub mod gk_leaderboard;
+pub mod gk_leaderboard_view_controller;
+pub mod gk_local_player;
+pub mod gk_matchmaker;
+pub mod gk_score;
+
+use crate::abi::CallFromHost;
+use crate::dyld::{ConstantExports, HostConstant};
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::{ns_string, NSInteger, NSTimeInterval};
+use crate::frameworks::uikit::ui_font::{
+    UITextAlignment, UITextAlignmentCenter, UITextAlignmentLeft,
+};
+use crate::frameworks::uikit::ui_view::ui_control::ui_button::UIButtonTypeRoundedRect;
+use crate::frameworks::uikit::ui_view::ui_control::{
+    UIControlEventTouchUpInside, UIControlStateNormal,
+};
+use crate::frameworks::uikit::ui_view::ui_navigation_bar::NAVIGATION_BAR_HEIGHT;
+use crate::objc::{block_invoke, id, msg, msg_class, nil, release};
+use crate::paths;
+use crate::Environment;
+use plist::{Dictionary, Value};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Instant;
+
+#[derive(Default)]
+pub struct State {
+    /// `GKLocalPlayer*`
+    local_player: Option<id>,
+    /// `GKMatchmaker*`
+    matchmaker: Option<id>,
+    /// Loaded from the sandbox when first needed.
+    data: Option<GameCenterData>,
+    /// Completion handlers waiting to be called by the run loop.
+    pending_handlers: Vec<PendingHandler>,
+}
+
+pub const GKErrorDomain: &str = "GKErrorDomain";
+
+type GKErrorCode = NSInteger;
+const GKErrorCommunicationsFailure: GKErrorCode = 3;
+const GKErrorNotAuthenticated: GKErrorCode = 6;
+
+pub const CONSTANTS: ConstantExports = &[
+    ("_GKErrorDomain", HostConstant::NSString(GKErrorDomain)),
+    (
+        "_GKPlayerAuthenticationDidChangeNotificationName",
+        HostConstant::NSString(gk_local_player::GKPlayerAuthenticationDidChangeNotificationName),
+    ),
+];
+
+/// Create an `NSError*` in [GKErrorDomain]. The caller owns the result.
+fn new_error(env: &mut Environment, code: GKErrorCode) -> id {
+    let domain = ns_string::get_static_str(env, GKErrorDomain);
+    let error: id = msg_class![env; NSError alloc];
+    msg![env; error initWithDomain:domain code:code userInfo:nil]
+}
+
+struct PendingHandler {
+    /// Heap copy of the block.
+    block: id,
+    /// Objects to pass to the block. Each of these is owned by the
+    /// [PendingHandler] and may be `nil`.
+    args: Vec<id>,
+}
+
+/// Schedule a completion handler to be called by the run loop. `handler` may
+/// be `nil`, in which case nothing happens. Ownership of `args` passes to this
+/// function, so the caller must not release them.
+fn call_handler_later(env: &mut Environment, handler: id, args: Vec<id>) {
+    if handler == nil {
+        for arg in args {
+            release(env, arg);
+        }
+        return;
+    }
+    // The handler is probably a stack block, which won't outlive the method
+    // it was passed to.
+    let block: id = msg![env; handler copy];
+    env.framework_state
+        .game_kit
+        .pending_handlers
+        .push(PendingHandler { block, args });
+}
+
+/// For use by `NSRunLoop`: call any completion handlers that are waiting.
+///
+/// Returns the time the next handler is due, if any.
+pub fn handle_completion_handlers(env: &mut Environment) -> Option<Instant> {
+    if env.framework_state.game_kit.pending_handlers.is_empty() {
+        return None;
+    }
+
+    // UIKit creates and drains autorelease pools when handling events.
+    let pool: id = msg_class![env; NSAutoreleasePool new];
+
+    let pending = std::mem::take(&mut env.framework_state.game_kit.pending_handlers);
+    for PendingHandler { block, args } in pending {
+        log_dbg!(
+            "Calling GameKit completion handler {:?} with {:?}",
+            block,
+            args
+        );
+        let invoke = block_invoke(env, block);
+        match args[..] {
+            [a] => {
+                () = invoke.call_from_host(env, (block, a));
+            }
+            [a, b] => {
+                () = invoke.call_from_host(env, (block, a, b));
+            }
+            [a, b, c] => {
+                () = invoke.call_from_host(env, (block, a, b, c));
+            }
+            _ => unreachable!(),
+        }
+        release(env, block);
+        for arg in args {
+            release(env, arg);
+        }
+    }
+
+    release(env, pool);
+
+    // Handlers may have caused more handlers to be scheduled.
+    if env.framework_state.game_kit.pending_handlers.is_empty() {
+        None
+    } else {
+        Some(env.clock.now())
+    }
+}
+
+#[derive(Clone)]
+struct StoredScore {
+    value: i64,
+    /// Seconds since the Apple epoch, like `NSDate`.
+    date: NSTimeInterval,
+}
+
+#[derive(Clone)]
+struct StoredAchievement {
+    percent_complete: f64,
+    /// Seconds since the Apple epoch, like `NSDate`.
+    date: NSTimeInterval,
+}
+
+/// Everything that is saved in the sandbox.
+#[derive(Default)]
+struct GameCenterData {
+    /// Scores for each leaderboard category, best first.
+    scores: BTreeMap<String, Vec<StoredScore>>,
+    achievements: BTreeMap<String, StoredAchievement>,
+}
+
+/// How many scores are kept for each leaderboard category.
+const MAX_SCORES_PER_CATEGORY: usize = 100;
+
+impl GameCenterData {
+    fn path(env: &Environment) -> PathBuf {
+        paths::user_data_base_path()
+            .join(paths::SANDBOX_DIR)
+            .join(env.bundle.bundle_identifier())
+            .join("GameCenter.plist")
+    }
+
+    fn load(path: &PathBuf) -> GameCenterData {
+        let mut data = GameCenterData::default();
+        let Ok(value) = Value::from_file(path) else {
+            return data;
+        };
+        let Some(root) = value.as_dictionary() else {
+            log!("Warning: {:?} is malformed, ignoring it", path);
+            return data;
+        };
+        if let Some(scores) = root.get("Scores").and_then(Value::as_dictionary) {
+            for (category, list) in scores {
+                let list = list
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_dictionary)
+                    .filter_map(|score| {
+                        Some(StoredScore {
+                            value: score.get("Value")?.as_signed_integer()?,
+                            date: score.get("Date")?.as_real()?,
+                        })
+                    })
+                    .collect();
+                data.scores.insert(category.clone(), list);
+            }
+        }
+        if let Some(achievements) = root.get("Achievements").and_then(Value::as_dictionary) {
+            for (identifier, achievement) in achievements {
+                let Some(achievement) = achievement.as_dictionary() else {
+                    continue;
+                };
+                let (Some(percent_complete), Some(date)) = (
+                    achievement.get("PercentComplete").and_then(Value::as_real),
+                    achievement.get("Date").and_then(Value::as_real),
+                ) else {
+                    continue;
+                };
+                data.achievements.insert(
+                    identifier.clone(),
+                    StoredAchievement {
+                        percent_complete,
+                        date,
+                    },
+                );
+            }
+        }
+        data
+    }
+
+    fn save(&self, path: &PathBuf) {
+        let mut scores = Dictionary::new();
+        for (category, list) in &self.scores {
+            let list = list
+                .iter()
+                .map(|score| {
+                    let mut dict = Dictionary::new();
+                    dict.insert("Value".to_string(), Value::Integer(score.value.into()));
+                    dict.insert("Date".to_string(), Value::Real(score.date));
+                    Value::Dictionary(dict)
+                })
+                .collect();
+            scores.insert(category.clone(), Value::Array(list));
+        }
+        let mut achievements = Dictionary::new();
+        for (identifier, achievement) in &self.achievements {
+            let mut dict = Dictionary::new();
+            dict.insert(
+                "PercentComplete".to_string(),
+                Value::Real(achievement.percent_complete),
+            );
+            dict.insert("Date".to_string(), Value::Real(achievement.date));
+            achievements.insert(identifier.clone(), Value::Dictionary(dict));
+        }
+        let mut root = Dictionary::new();
+        root.insert("Scores".to_string(), Value::Dictionary(scores));
+        root.insert("Achievements".to_string(), Value::Dictionary(achievements));
+        if let Err(e) = Value::Dictionary(root).to_file_xml(path) {
+            log!(
+                "Warning: couldn't save Game Center data to {:?}: {}",
+                path,
+                e
+            );
+        }
+    }
+
+    /// Get the data, loading it first if necessary.
+    fn get(env: &mut Environment) -> &mut GameCenterData {
+        if env.framework_state.game_kit.data.is_none() {
+            let data = GameCenterData::load(&GameCenterData::path(env));
+            env.framework_state.game_kit.data = Some(data);
+        }
+        env.framework_state.game_kit.data.as_mut().unwrap()
+    }
+
+    /// Modify the data and then save it.
+    fn update<F>(env: &mut Environment, f: F)
+    where
+        F: FnOnce(&mut GameCenterData),
+    {
+        f(GameCenterData::get(env));
+        let path = GameCenterData::path(env);
+        env.framework_state
+            .game_kit
+            .data
+            .as_ref()
+            .unwrap()
+            .save(&path);
+    }
+}
+
+/// For use by the view controllers' `loadView`: adds a title, a list of lines
+/// and a Done button below the navigation bar. The button sends
+/// `_touchHLE_done:` to `this`.
+fn add_list_content(env: &mut Environment, this: id, title: &str, lines: Vec<String>) {
+    let view: id = msg![env; this view];
+    let bounds: CGRect = msg![env; view bounds];
+    let width = bounds.size.width;
+
+    let background: id = msg_class![env; UIColor whiteColor];
+    () = msg![env; view setBackgroundColor:background];
+
+    const LINE_HEIGHT: CGFloat = 20.0;
+    const BUTTON_HEIGHT: CGFloat = 40.0;
+
+    let mut y = NAVIGATION_BAR_HEIGHT + 10.0;
+    let max_lines =
+        ((bounds.size.height - y - 30.0 - BUTTON_HEIGHT - 20.0) / LINE_HEIGHT).max(1.0) as usize;
+
+    let lines = if lines.is_empty() {
+        vec!["Nothing here yet.".to_string()]
+    } else if lines.len() > max_lines {
+        let more = lines.len() - (max_lines - 1);
+        let mut lines: Vec<String> = lines.into_iter().take(max_lines - 1).collect();
+        lines.push(format!("({} more)", more));
+        lines
+    } else {
+        lines
+    };
+
+    let mut add_label = |env: &mut Environment,
+                         text: String,
+                         font_size: CGFloat,
+                         height: CGFloat,
+                         alignment: UITextAlignment| {
+        let frame = CGRect {
+            origin: CGPoint { x: 20.0, y },
+            size: CGSize {
+                width: width - 40.0,
+                height,
+            },
+        };
+        let label: id = msg_class![env; UILabel alloc];
+        let label: id = msg![env; label initWithFrame:frame];
+        let text = ns_string::from_rust_string(env, text);
+        () = msg![env; label setText:text];
+        release(env, text);
+        () = msg![env; label setTextAlignment:alignment];
+        let font: id = msg_class![env; UIFont systemFontOfSize:font_size];
+        () = msg![env; label setFont:font];
+        let bg_color: id = msg_class![env; UIColor clearColor];
+        () = msg![env; label setBackgroundColor:bg_color];
+        () = msg![env; view addSubview:label];
+        release(env, label);
+        y += height;
+    };
+
+    add_label(env, title.to_string(), 20.0, 30.0, UITextAlignmentCenter);
+    for line in lines {
+        add_label(env, line, 14.0, LINE_HEIGHT, UITextAlignmentLeft);
+    }
+
+    let button_width = 120.0;
+    let button_frame = CGRect {
+        origin: CGPoint {
+            x: (width - button_width) / 2.0,
+            y: y + 10.0,
+        },
+        size: CGSize {
+            width: button_width,
+            height: BUTTON_HEIGHT,
+        },
+    };
+    let button: id = msg_class![env; UIButton buttonWithType:UIButtonTypeRoundedRect];
+    () = msg![env; button setFrame:button_frame];
+    let button_title = ns_string::get_static_str(env, "Done");
+    () = msg![env; button setTitle:button_title forState:UIControlStateNormal];
+    let selector = env.objc.lookup_selector("_touchHLE_done:").unwrap();
+    () = msg![env; button addTarget:this
+                             action:selector
+                   forControlEvents:UIControlEventTouchUpInside];
+    () = msg![env; view addSubview:button];
+}
+
+/// Check whether a delegate implements a callback.
+fn delegate_responds_to(env: &mut Environment, delegate: id, callback: &str) -> bool {
+    if delegate == nil {
+        return false;
+    }
+    let sel = env
+        .objc
+        .register_host_selector(callback.to_string(), &mut env.mem);
+    msg![env; delegate respondsToSelector:sel]
+}
@@ -0,0 +1,177 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `GKAchievement` and `GKAchievementDescription`.
+
+use super::gk_local_player::is_authenticated;
+use super::{
+    call_handler_later, new_error, GKErrorNotAuthenticated, GameCenterData, StoredAchievement,
+};
+use crate::frameworks::foundation::{ns_array, ns_string, NSTimeInterval};
+use crate::objc::{
+    id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
+};
+use crate::Environment;
+
+struct GKAchievementHostObject {
+    /// `NSString*`
+    identifier: id,
+    percent_complete: f64,
+    /// `NSDate*`, `nil` if never reported.
+    last_reported_date: id,
+}
+impl HostObject for GKAchievementHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation GKAchievement: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(GKAchievementHostObject {
+        identifier: nil,
+        percent_complete: 0.0,
+        last_reported_date: nil,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+// void (^)(NSArray *achievements, NSError *error)
++ (())loadAchievementsWithCompletionHandler:(id)handler {
+    if !is_authenticated(env) {
+        let error = new_error(env, GKErrorNotAuthenticated);
+        call_handler_later(env, handler, vec![nil, error]);
+        return;
+    }
+    let stored: Vec<(String, StoredAchievement)> = GameCenterData::get(env)
+        .achievements
+        .iter()
+        .map(|(identifier, achievement)| (identifier.clone(), achievement.clone()))
+        .collect();
+    let achievements: Vec<id> = stored
+        .into_iter()
+        .map(|(identifier, achievement)| new_achievement(env, identifier, &achievement))
+        .collect();
+    let achievements = ns_array::from_vec(env, achievements);
+    call_handler_later(env, handler, vec![achievements, nil]);
+}
+
++ (())resetAchievementsWithCompletionHandler:(id)handler { // void (^)(NSError*)
+    if !is_authenticated(env) {
+        let error = new_error(env, GKErrorNotAuthenticated);
+        call_handler_later(env, handler, vec![error]);
+        return;
+    }
+    log!("Game Center: resetting achievements");
+    GameCenterData::update(env, |data| data.achievements.clear());
+    call_handler_later(env, handler, vec![nil]);
+}
+
+- (id)initWithIdentifier:(id)identifier { // NSString*
+    let identifier: id = msg![env; identifier copy];
+    env.objc.borrow_mut::<GKAchievementHostObject>(this).identifier = identifier;
+    this
+}
+
+- (())dealloc {
+    let &GKAchievementHostObject { identifier, last_reported_date, .. } = env.objc.borrow(this);
+    release(env, identifier);
+    release(env, last_reported_date);
+    env.objc.dealloc_object(this, &mut env.mem);
+}
+
+- (id)identifier {
+    env.objc.borrow::<GKAchievementHostObject>(this).identifier
+}
+- (())setIdentifier:(id)identifier { // NSString*
+    let identifier: id = msg![env; identifier copy];
+    let old = std::mem::replace(&mut env.objc.borrow_mut::<GKAchievementHostObject>(this).identifier, identifier);
+    release(env, old);
+}
+
+- (f64)percentComplete {
+    env.objc.borrow::<GKAchievementHostObject>(this).percent_complete
+}
+- (())setPercentComplete:(f64)percent_complete {
+    env.objc.borrow_mut::<GKAchievementHostObject>(this).percent_complete = percent_complete;
+}
+- (bool)isCompleted {
+    env.objc.borrow::<GKAchievementHostObject>(this).percent_complete >= 100.0
+}
+- (bool)isHidden {
+    false
+}
+- (id)lastReportedDate {
+    env.objc.borrow::<GKAchievementHostObject>(this).last_reported_date
+}
+
+- (())reportAchievementWithCompletionHandler:(id)handler { // void (^)(NSError*)
+    if !is_authenticated(env) {
+        log!("Warning: [(GKAchievement*){:?} reportAchievementWithCompletionHandler:] called before the local player was authenticated", this);
+        let error = new_error(env, GKErrorNotAuthenticated);
+        call_handler_later(env, handler, vec![error]);
+        return;
+    }
+
+    let &GKAchievementHostObject { identifier, percent_complete, .. } = env.objc.borrow(this);
+    if identifier == nil {
+        log!("Warning: {:?} has no identifier, ignoring report", this);
+        call_handler_later(env, handler, vec![nil]);
+        return;
+    }
+    let identifier = ns_string::to_rust_string(env, identifier).into_owned();
+    let percent_complete = percent_complete.clamp(0.0, 100.0);
+
+    let date: id = msg_class![env; NSDate date];
+    retain(env, date);
+    let old = std::mem::replace(&mut env.objc.borrow_mut::<GKAchievementHostObject>(this).last_reported_date, date);
+    release(env, old);
+    let date: NSTimeInterval = msg![env; date timeIntervalSinceReferenceDate];
+
+    log!("Game Center: reporting achievement {:?} as {}% complete", identifier, percent_complete);
+    GameCenterData::update(env, |data| {
+        // Like on the real Game Center, progress can't go backwards.
+        let achievement = data
+            .achievements
+            .entry(identifier)
+            .or_insert(StoredAchievement { percent_complete, date });
+        if percent_complete >= achievement.percent_complete {
+            *achievement = StoredAchievement { percent_complete, date };
+        }
+    });
+    call_handler_later(env, handler, vec![nil]);
+}
+
+@end
+
+// Achievement descriptions come from iTunes Connect, which touchHLE knows
+// nothing about, so there are none.
+@implementation GKAchievementDescription: NSObject
+
+// void (^)(NSArray *descriptions, NSError *error)
++ (())loadAchievementDescriptionsWithCompletionHandler:(id)handler {
+    let descriptions = ns_array::from_vec(env, Vec::new());
+    call_handler_later(env, handler, vec![descriptions, nil]);
+}
+
+@end
+
+};
+
+/// Create a `GKAchievement*` for a stored achievement. The caller owns the
+/// result.
+fn new_achievement(env: &mut Environment, identifier: String, stored: &StoredAchievement) -> id {
+    let identifier = ns_string::from_rust_string(env, identifier);
+    let date: id = msg_class![env; NSDate dateWithTimeIntervalSinceReferenceDate:(stored.date)];
+    retain(env, date);
+    let new: id = msg_class![env; GKAchievement alloc];
+    *env.objc.borrow_mut(new) = GKAchievementHostObject {
+        identifier,
+        percent_complete: stored.percent_complete,
+        last_reported_date: date,
+    };
+    new
+}
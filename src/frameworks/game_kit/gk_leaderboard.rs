@@ -0,0 +1,221 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `GKLeaderboard`.
+//!
+//! Every score the local player reports is kept (up to a limit), so a
+//! leaderboard is effectively a list of the player's own best scores. touchHLE
+//! doesn't know whether a leaderboard is sorted with high or low scores first,
+//! so it assumes high scores are better, which is the most common case.
+
+use super::gk_local_player::is_authenticated;
+use super::gk_score::{category_key, new_score};
+use super::{call_handler_later, new_error, GKErrorNotAuthenticated, GameCenterData, StoredScore};
+use crate::frameworks::foundation::{ns_array, ns_string, NSInteger, NSRange, NSUInteger};
+use crate::objc::{
+    id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
+};
+use crate::Environment;
+
+pub(super) type GKLeaderboardTimeScope = NSInteger;
+const GKLeaderboardTimeScopeToday: GKLeaderboardTimeScope = 0;
+const GKLeaderboardTimeScopeWeek: GKLeaderboardTimeScope = 1;
+pub(super) const GKLeaderboardTimeScopeAllTime: GKLeaderboardTimeScope = 2;
+
+type GKLeaderboardPlayerScope = NSInteger;
+const GKLeaderboardPlayerScopeGlobal: GKLeaderboardPlayerScope = 0;
+
+struct GKLeaderboardHostObject {
+    /// `NSString*`
+    category: id,
+    time_scope: GKLeaderboardTimeScope,
+    player_scope: GKLeaderboardPlayerScope,
+    range_location: NSUInteger,
+    range_length: NSUInteger,
+    /// `NSArray*` of `GKScore*`, `nil` until scores are loaded.
+    scores: id,
+    /// `GKScore*`
+    local_player_score: id,
+}
+impl HostObject for GKLeaderboardHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation GKLeaderboard: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(GKLeaderboardHostObject {
+        category: nil,
+        time_scope: GKLeaderboardTimeScopeAllTime,
+        player_scope: GKLeaderboardPlayerScopeGlobal,
+        range_location: 1,
+        range_length: 25,
+        scores: nil,
+        local_player_score: nil,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+// void (^)(NSArray *categories, NSArray *titles, NSError *error)
++ (())loadCategoriesWithCompletionHandler:(id)handler {
+    if !is_authenticated(env) {
+        let error = new_error(env, GKErrorNotAuthenticated);
+        call_handler_later(env, handler, vec![nil, nil, error]);
+        return;
+    }
+    // Only categories that have scores are known, and touchHLE doesn't know
+    // their titles, so the category names are used instead.
+    let names: Vec<String> = GameCenterData::get(env)
+        .scores
+        .keys()
+        .filter(|name| !name.is_empty())
+        .cloned()
+        .collect();
+    let categories: Vec<id> = names
+        .iter()
+        .map(|name| ns_string::from_rust_string(env, name.clone()))
+        .collect();
+    let titles: Vec<id> = categories.iter().map(|&name| retain(env, name)).collect();
+    let categories = ns_array::from_vec(env, categories);
+    let titles = ns_array::from_vec(env, titles);
+    call_handler_later(env, handler, vec![categories, titles, nil]);
+}
+
+- (id)initWithPlayerIDs:(id)_player_ids { // NSArray* of NSString*
+    // The only player is the local player.
+    this
+}
+
+- (())dealloc {
+    let &GKLeaderboardHostObject { category, scores, local_player_score, .. } = env.objc.borrow(this);
+    release(env, category);
+    release(env, scores);
+    release(env, local_player_score);
+    env.objc.dealloc_object(this, &mut env.mem);
+}
+
+- (id)category {
+    env.objc.borrow::<GKLeaderboardHostObject>(this).category
+}
+- (())setCategory:(id)category { // NSString*
+    let category: id = msg![env; category copy];
+    let old = std::mem::replace(&mut env.objc.borrow_mut::<GKLeaderboardHostObject>(this).category, category);
+    release(env, old);
+}
+- (id)title {
+    env.objc.borrow::<GKLeaderboardHostObject>(this).category
+}
+
+- (GKLeaderboardTimeScope)timeScope {
+    env.objc.borrow::<GKLeaderboardHostObject>(this).time_scope
+}
+- (())setTimeScope:(GKLeaderboardTimeScope)time_scope {
+    env.objc.borrow_mut::<GKLeaderboardHostObject>(this).time_scope = time_scope;
+}
+
+// Friends-only leaderboards are the same, since the local player is
+// considered to be their own friend.
+- (GKLeaderboardPlayerScope)playerScope {
+    env.objc.borrow::<GKLeaderboardHostObject>(this).player_scope
+}
+- (())setPlayerScope:(GKLeaderboardPlayerScope)player_scope {
+    env.objc.borrow_mut::<GKLeaderboardHostObject>(this).player_scope = player_scope;
+}
+
+- (NSRange)range {
+    let host_object = env.objc.borrow::<GKLeaderboardHostObject>(this);
+    NSRange {
+        location: host_object.range_location,
+        length: host_object.range_length,
+    }
+}
+- (())setRange:(NSRange)range {
+    let host_object = env.objc.borrow_mut::<GKLeaderboardHostObject>(this);
+    host_object.range_location = range.location;
+    host_object.range_length = range.length;
+}
+
+- (id)scores {
+    env.objc.borrow::<GKLeaderboardHostObject>(this).scores
+}
+- (id)localPlayerScore {
+    env.objc.borrow::<GKLeaderboardHostObject>(this).local_player_score
+}
+- (bool)isLoading {
+    // Scores are loaded from the run loop, but they're ready immediately.
+    false
+}
+
+// void (^)(NSArray *scores, NSError *error)
+- (())loadScoresWithCompletionHandler:(id)handler {
+    if !is_authenticated(env) {
+        let error = new_error(env, GKErrorNotAuthenticated);
+        call_handler_later(env, handler, vec![nil, error]);
+        return;
+    }
+
+    let &GKLeaderboardHostObject {
+        category,
+        time_scope,
+        range_location,
+        range_length,
+        ..
+    } = env.objc.borrow(this);
+    let key = category_key(env, category);
+    let scores = scores_in_time_scope(env, &key, time_scope);
+
+    let local_player_score = scores
+        .first()
+        .map(|best| new_score(env, category, best, 1))
+        .unwrap_or(nil);
+    let first = range_location.max(1) as usize - 1;
+    let scores_in_range: Vec<id> = scores
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(range_length as usize)
+        .map(|(i, score)| new_score(env, category, score, i as NSInteger + 1))
+        .collect();
+    let scores_in_range = ns_array::from_vec(env, scores_in_range);
+
+    let host_object = env.objc.borrow_mut::<GKLeaderboardHostObject>(this);
+    let old_scores = std::mem::replace(&mut host_object.scores, scores_in_range);
+    let old_local_player_score = std::mem::replace(&mut host_object.local_player_score, local_player_score);
+    release(env, old_scores);
+    release(env, old_local_player_score);
+
+    retain(env, scores_in_range);
+    call_handler_later(env, handler, vec![scores_in_range, nil]);
+}
+
+@end
+
+};
+
+/// Get the stored scores for a category that were reported within a time
+/// scope, best first.
+pub(super) fn scores_in_time_scope(
+    env: &mut Environment,
+    category: &str,
+    time_scope: GKLeaderboardTimeScope,
+) -> Vec<StoredScore> {
+    const DAY: f64 = 24.0 * 60.0 * 60.0;
+    let max_age = match time_scope {
+        GKLeaderboardTimeScopeToday => DAY,
+        GKLeaderboardTimeScopeWeek => 7.0 * DAY,
+        _ => f64::INFINITY,
+    };
+    let now: f64 = msg_class![env; NSDate timeIntervalSinceReferenceDate];
+    GameCenterData::get(env)
+        .scores
+        .get(category)
+        .into_iter()
+        .flatten()
+        .filter(|score| now - score.date <= max_age)
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,176 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `GKLeaderboardViewController` and `GKAchievementViewController`.
+//!
+//! These are drawn by touchHLE using ordinary UIKit views, since the real
+//! Game Center UI isn't available. They list what's stored locally and have a
+//! Done button.
+
+use super::gk_leaderboard::{
+    scores_in_time_scope, GKLeaderboardTimeScope, GKLeaderboardTimeScopeAllTime,
+};
+use super::gk_score::category_key;
+use super::{add_list_content, delegate_responds_to, GameCenterData};
+use crate::frameworks::uikit::ui_view_controller::ui_navigation_controller::UINavigationControllerHostObject;
+use crate::objc::{
+    id, impl_HostObject_with_superclass, msg, msg_send, msg_super, nil, objc_classes, release,
+    retain, ClassExports, NSZonePtr,
+};
+use crate::Environment;
+
+#[derive(Default)]
+struct GKLeaderboardViewControllerHostObject {
+    superclass: UINavigationControllerHostObject,
+    /// Weak reference.
+    leaderboard_delegate: id,
+    /// `NSString*`
+    category: id,
+    time_scope: GKLeaderboardTimeScope,
+}
+impl_HostObject_with_superclass!(GKLeaderboardViewControllerHostObject);
+
+#[derive(Default)]
+struct GKAchievementViewControllerHostObject {
+    superclass: UINavigationControllerHostObject,
+    /// Weak reference.
+    achievement_delegate: id,
+}
+impl_HostObject_with_superclass!(GKAchievementViewControllerHostObject);
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation GKLeaderboardViewController: UINavigationController
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(GKLeaderboardViewControllerHostObject {
+        time_scope: GKLeaderboardTimeScopeAllTime,
+        ..Default::default()
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (())dealloc {
+    let category = env.objc.borrow::<GKLeaderboardViewControllerHostObject>(this).category;
+    release(env, category);
+    msg_super![env; this dealloc]
+}
+
+- (id)leaderboardDelegate {
+    env.objc.borrow::<GKLeaderboardViewControllerHostObject>(this).leaderboard_delegate
+}
+- (())setLeaderboardDelegate:(id)delegate {
+    env.objc.borrow_mut::<GKLeaderboardViewControllerHostObject>(this).leaderboard_delegate = delegate;
+}
+
+- (id)category {
+    env.objc.borrow::<GKLeaderboardViewControllerHostObject>(this).category
+}
+- (())setCategory:(id)category { // NSString*
+    let category: id = msg![env; category copy];
+    let old = std::mem::replace(&mut env.objc.borrow_mut::<GKLeaderboardViewControllerHostObject>(this).category, category);
+    release(env, old);
+}
+
+- (GKLeaderboardTimeScope)timeScope {
+    env.objc.borrow::<GKLeaderboardViewControllerHostObject>(this).time_scope
+}
+- (())setTimeScope:(GKLeaderboardTimeScope)time_scope {
+    env.objc.borrow_mut::<GKLeaderboardViewControllerHostObject>(this).time_scope = time_scope;
+}
+
+- (())loadView {
+    () = msg_super![env; this loadView];
+    let &GKLeaderboardViewControllerHostObject { category, time_scope, .. } = env.objc.borrow(this);
+    let lines = leaderboard_lines(env, category, time_scope);
+    add_list_content(env, this, "Leaderboards", lines);
+}
+
+- (())_touchHLE_done:(id)_sender {
+    let delegate = env.objc.borrow::<GKLeaderboardViewControllerHostObject>(this).leaderboard_delegate;
+    finish(env, this, delegate, "leaderboardViewControllerDidFinish:");
+}
+
+@end
+
+@implementation GKAchievementViewController: UINavigationController
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<GKAchievementViewControllerHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)achievementDelegate {
+    env.objc.borrow::<GKAchievementViewControllerHostObject>(this).achievement_delegate
+}
+- (())setAchievementDelegate:(id)delegate {
+    env.objc.borrow_mut::<GKAchievementViewControllerHostObject>(this).achievement_delegate = delegate;
+}
+
+- (())loadView {
+    () = msg_super![env; this loadView];
+    let lines = GameCenterData::get(env)
+        .achievements
+        .iter()
+        .map(|(identifier, achievement)| {
+            format!("{}: {:.0}%", identifier, achievement.percent_complete)
+        })
+        .collect();
+    add_list_content(env, this, "Achievements", lines);
+}
+
+- (())_touchHLE_done:(id)_sender {
+    let delegate = env.objc.borrow::<GKAchievementViewControllerHostObject>(this).achievement_delegate;
+    finish(env, this, delegate, "achievementViewControllerDidFinish:");
+}
+
+@end
+
+};
+
+/// List either the scores in one category, or the best score in each category
+/// if no category was chosen.
+fn leaderboard_lines(
+    env: &mut Environment,
+    category: id,
+    time_scope: GKLeaderboardTimeScope,
+) -> Vec<String> {
+    if category != nil {
+        let key = category_key(env, category);
+        return scores_in_time_scope(env, &key, time_scope)
+            .iter()
+            .enumerate()
+            .map(|(i, score)| format!("{}. {}", i + 1, score.value))
+            .collect();
+    }
+    let keys: Vec<String> = GameCenterData::get(env).scores.keys().cloned().collect();
+    keys.into_iter()
+        .filter_map(|key| {
+            let best = scores_in_time_scope(env, &key, time_scope).first()?.value;
+            let name = if key.is_empty() { "Default" } else { &key };
+            Some(format!("{}: {}", name, best))
+        })
+        .collect()
+}
+
+/// Tell the delegate that the user tapped Done. The delegate is responsible
+/// for dismissing the view controller.
+fn finish(env: &mut Environment, this: id, delegate: id, callback: &str) {
+    if delegate_responds_to(env, delegate, callback) {
+        // The delegate might release the view controller while dismissing it.
+        retain(env, this);
+        let sel = env.objc.lookup_selector(callback).unwrap();
+        () = msg_send(env, (delegate, sel, this));
+        release(env, this);
+    } else {
+        log!(
+            "Warning: {:?} has no delegate to dismiss it, dismissing it anyway",
+            this
+        );
+        () = msg![env; this dismissModalViewControllerAnimated:true];
+    }
+}
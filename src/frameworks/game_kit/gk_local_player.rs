@@ -0,0 +1,149 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `GKPlayer` and `GKLocalPlayer`.
+
+use super::call_handler_later;
+use crate::frameworks::foundation::{ns_array, ns_string, NSUInteger};
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
+    NSZonePtr,
+};
+use crate::Environment;
+
+pub const GKPlayerAuthenticationDidChangeNotificationName: &str =
+    "GKPlayerAuthenticationDidChangeNotificationName";
+
+/// The fake local player's ID. Real player IDs look like this too.
+pub const LOCAL_PLAYER_ID: &str = "G:1000000001";
+
+struct GKPlayerHostObject {
+    /// `NSString*`
+    player_id: id,
+    /// `NSString*`
+    alias: id,
+    /// Only meaningful for the local player.
+    authenticated: bool,
+}
+impl HostObject for GKPlayerHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation GKPlayer: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(GKPlayerHostObject {
+        player_id: nil,
+        alias: nil,
+        authenticated: false,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+// There are no other players, so only the local player can be loaded.
++ (())loadPlayersForIdentifiers:(id)identifiers // NSArray* of NSString*
+          withCompletionHandler:(id)handler {
+    let local_player: id = msg_class![env; GKLocalPlayer localPlayer];
+    let local_player_id: id = msg![env; local_player playerID];
+    let count: NSUInteger = msg![env; identifiers count];
+    let mut players = Vec::new();
+    for i in 0..count {
+        let identifier: id = msg![env; identifiers objectAtIndex:i];
+        let is_local_player: bool = msg![env; identifier isEqualToString:local_player_id];
+        if is_local_player {
+            players.push(retain(env, local_player));
+        }
+    }
+    let players = ns_array::from_vec(env, players);
+    call_handler_later(env, handler, vec![players, nil]);
+}
+
+- (())dealloc {
+    let &GKPlayerHostObject { player_id, alias, .. } = env.objc.borrow(this);
+    release(env, player_id);
+    release(env, alias);
+    env.objc.dealloc_object(this, &mut env.mem);
+}
+
+- (id)playerID {
+    env.objc.borrow::<GKPlayerHostObject>(this).player_id
+}
+- (id)alias {
+    env.objc.borrow::<GKPlayerHostObject>(this).alias
+}
+- (bool)isFriend {
+    false
+}
+
+@end
+
+@implementation GKLocalPlayer: GKPlayer
+
++ (id)localPlayer {
+    if let Some(player) = env.framework_state.game_kit.local_player {
+        return player;
+    }
+    let player_id = ns_string::get_static_str(env, LOCAL_PLAYER_ID);
+    let alias = env.options.game_center_alias.clone();
+    let alias = ns_string::from_rust_string(env, alias);
+    let host_object = Box::new(GKPlayerHostObject {
+        player_id,
+        alias,
+        authenticated: false,
+    });
+    let new = env.objc.alloc_static_object(this, host_object, &mut env.mem);
+    env.framework_state.game_kit.local_player = Some(new);
+    new
+}
+
+- (bool)isAuthenticated {
+    env.objc.borrow::<GKPlayerHostObject>(this).authenticated
+}
+- (bool)isUnderage {
+    false
+}
+
+- (())authenticateWithCompletionHandler:(id)handler { // void (^)(NSError*)
+    let already_authenticated = std::mem::replace(
+        &mut env.objc.borrow_mut::<GKPlayerHostObject>(this).authenticated,
+        true
+    );
+    if !already_authenticated {
+        log!("Signing in to the local imitation of Game Center as {:?}", env.options.game_center_alias);
+        let center: id = msg_class![env; NSNotificationCenter defaultCenter];
+        let name = ns_string::get_static_str(env, GKPlayerAuthenticationDidChangeNotificationName);
+        () = msg![env; center postNotificationName:name object:this];
+    }
+    call_handler_later(env, handler, vec![nil]);
+}
+
+// The local player has no friends. :(
+- (id)friends {
+    if env.objc.borrow::<GKPlayerHostObject>(this).authenticated {
+        let friends = ns_array::from_vec(env, Vec::new());
+        autorelease(env, friends)
+    } else {
+        nil
+    }
+}
+- (())loadFriendsWithCompletionHandler:(id)handler { // void (^)(NSArray*, NSError*)
+    let friends = ns_array::from_vec(env, Vec::new());
+    call_handler_later(env, handler, vec![friends, nil]);
+}
+
+@end
+
+};
+
+/// Check whether the local player has been authenticated, which is required
+/// before scores and achievements can be reported or loaded.
+pub(super) fn is_authenticated(env: &mut Environment) -> bool {
+    let local_player: id = msg_class![env; GKLocalPlayer localPlayer];
+    env.objc
+        .borrow::<GKPlayerHostObject>(local_player)
+        .authenticated
+}
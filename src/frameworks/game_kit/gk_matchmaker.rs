@@ -0,0 +1,148 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `GKMatchmaker`, `GKMatchRequest` and `GKMatchmakerViewController`.
+//!
+//! There's nobody to play with, so matchmaking always fails. What matters is
+//! that the app finds out, rather than waiting for a match forever.
+
+use super::{
+    add_list_content, call_handler_later, delegate_responds_to, new_error,
+    GKErrorCommunicationsFailure,
+};
+use crate::frameworks::foundation::NSUInteger;
+use crate::frameworks::uikit::ui_view_controller::ui_navigation_controller::UINavigationControllerHostObject;
+use crate::objc::{
+    id, impl_HostObject_with_superclass, msg, msg_super, nil, objc_classes, release, retain,
+    ClassExports, NSZonePtr, TrivialHostObject,
+};
+
+#[derive(Default)]
+struct GKMatchmakerViewControllerHostObject {
+    superclass: UINavigationControllerHostObject,
+    /// Weak reference.
+    matchmaker_delegate: id,
+}
+impl_HostObject_with_superclass!(GKMatchmakerViewControllerHostObject);
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation GKMatchRequest: NSObject
+
+// The request's contents don't matter, since it will fail anyway.
+
+- (NSUInteger)minPlayers {
+    2
+}
+- (())setMinPlayers:(NSUInteger)_min_players {
+}
+- (NSUInteger)maxPlayers {
+    4
+}
+- (())setMaxPlayers:(NSUInteger)_max_players {
+}
+- (())setPlayerGroup:(NSUInteger)_player_group {
+}
+- (())setPlayerAttributes:(u32)_player_attributes {
+}
+- (())setPlayersToInvite:(id)_players_to_invite { // NSArray*
+}
+
+@end
+
+@implementation GKMatchmaker: NSObject
+
++ (id)sharedMatchmaker {
+    if let Some(matchmaker) = env.framework_state.game_kit.matchmaker {
+        return matchmaker;
+    }
+    let new = env.objc.alloc_static_object(this, Box::new(TrivialHostObject), &mut env.mem);
+    env.framework_state.game_kit.matchmaker = Some(new);
+    new
+}
+
+- (())findMatchForRequest:(id)_request // GKMatchRequest*
+    withCompletionHandler:(id)handler { // void (^)(GKMatch*, NSError*)
+    log!("Game Center: matchmaking isn't supported, reporting an error");
+    let error = new_error(env, GKErrorCommunicationsFailure);
+    call_handler_later(env, handler, vec![nil, error]);
+}
+- (())findPlayersForHostedMatchRequest:(id)_request // GKMatchRequest*
+                 withCompletionHandler:(id)handler { // void (^)(NSArray*, NSError*)
+    log!("Game Center: matchmaking isn't supported, reporting an error");
+    let error = new_error(env, GKErrorCommunicationsFailure);
+    call_handler_later(env, handler, vec![nil, error]);
+}
+- (())addPlayersToMatch:(id)_match // GKMatch*
+           matchRequest:(id)_request // GKMatchRequest*
+      completionHandler:(id)handler { // void (^)(NSError*)
+    let error = new_error(env, GKErrorCommunicationsFailure);
+    call_handler_later(env, handler, vec![error]);
+}
+- (())cancel {
+}
+// Invitations never arrive, so the handler is never called.
+- (())setInviteHandler:(id)_handler {
+}
+- (id)inviteHandler {
+    nil
+}
+
+@end
+
+@implementation GKMatchmakerViewController: UINavigationController
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<GKMatchmakerViewControllerHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithMatchRequest:(id)_request { // GKMatchRequest*
+    msg![env; this init]
+}
+- (id)initWithInvite:(id)_invite { // GKInvite*
+    msg![env; this init]
+}
+
+- (id)matchmakerDelegate {
+    env.objc.borrow::<GKMatchmakerViewControllerHostObject>(this).matchmaker_delegate
+}
+- (())setMatchmakerDelegate:(id)delegate {
+    env.objc.borrow_mut::<GKMatchmakerViewControllerHostObject>(this).matchmaker_delegate = delegate;
+}
+
+- (())setHosted:(bool)_hosted {
+}
+
+- (())loadView {
+    () = msg_super![env; this loadView];
+    add_list_content(env, this, "Multiplayer", vec![
+        "touchHLE can't connect to other players.".to_string(),
+    ]);
+}
+
+- (())_touchHLE_done:(id)_sender {
+    let delegate = env.objc.borrow::<GKMatchmakerViewControllerHostObject>(this).matchmaker_delegate;
+    // The delegate is responsible for dismissing the view controller, and
+    // might release it in the process.
+    retain(env, this);
+    if delegate_responds_to(env, delegate, "matchmakerViewController:didFailWithError:") {
+        let error = new_error(env, GKErrorCommunicationsFailure);
+        () = msg![env; delegate matchmakerViewController:this didFailWithError:error];
+        release(env, error);
+    } else if delegate_responds_to(env, delegate, "matchmakerViewControllerWasCancelled:") {
+        () = msg![env; delegate matchmakerViewControllerWasCancelled:this];
+    } else {
+        log!("Warning: {:?} has no delegate to dismiss it, dismissing it anyway", this);
+        () = msg![env; this dismissModalViewControllerAnimated:true];
+    }
+    release(env, this);
+}
+
+@end
+
+};
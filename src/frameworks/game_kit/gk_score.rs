@@ -0,0 +1,155 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `GKScore`.
+
+use super::gk_local_player::{is_authenticated, LOCAL_PLAYER_ID};
+use super::{
+    call_handler_later, new_error, GKErrorNotAuthenticated, GameCenterData, StoredScore,
+    MAX_SCORES_PER_CATEGORY,
+};
+use crate::frameworks::foundation::{ns_string, NSInteger, NSTimeInterval};
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
+    NSZonePtr,
+};
+use crate::Environment;
+
+struct GKScoreHostObject {
+    /// `NSString*`
+    category: id,
+    value: i64,
+    /// `NSDate*`
+    date: id,
+    /// 1 is the best score. 0 if the score hasn't come from a leaderboard.
+    rank: NSInteger,
+}
+impl HostObject for GKScoreHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation GKScore: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(GKScoreHostObject {
+        category: nil,
+        value: 0,
+        date: nil,
+        rank: 0,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)init {
+    let date: id = msg_class![env; NSDate date];
+    retain(env, date);
+    env.objc.borrow_mut::<GKScoreHostObject>(this).date = date;
+    this
+}
+
+- (id)initWithCategory:(id)category { // NSString*
+    let this: id = msg![env; this init];
+    () = msg![env; this setCategory:category];
+    this
+}
+
+- (())dealloc {
+    let &GKScoreHostObject { category, date, .. } = env.objc.borrow(this);
+    release(env, category);
+    release(env, date);
+    env.objc.dealloc_object(this, &mut env.mem);
+}
+
+- (id)category {
+    env.objc.borrow::<GKScoreHostObject>(this).category
+}
+- (())setCategory:(id)category { // NSString*
+    let category: id = msg![env; category copy];
+    let old = std::mem::replace(&mut env.objc.borrow_mut::<GKScoreHostObject>(this).category, category);
+    release(env, old);
+}
+
+- (i64)value {
+    env.objc.borrow::<GKScoreHostObject>(this).value
+}
+- (())setValue:(i64)value {
+    env.objc.borrow_mut::<GKScoreHostObject>(this).value = value;
+}
+
+// Real leaderboards can have custom formats, e.g. for times, but touchHLE
+// doesn't know about them.
+- (id)formattedValue {
+    let value = env.objc.borrow::<GKScoreHostObject>(this).value;
+    let formatted = ns_string::from_rust_string(env, value.to_string());
+    autorelease(env, formatted)
+}
+
+- (id)date {
+    env.objc.borrow::<GKScoreHostObject>(this).date
+}
+- (id)playerID {
+    ns_string::get_static_str(env, LOCAL_PLAYER_ID)
+}
+- (NSInteger)rank {
+    env.objc.borrow::<GKScoreHostObject>(this).rank
+}
+
+- (())reportScoreWithCompletionHandler:(id)handler { // void (^)(NSError*)
+    if !is_authenticated(env) {
+        log!("Warning: [(GKScore*){:?} reportScoreWithCompletionHandler:] called before the local player was authenticated", this);
+        let error = new_error(env, GKErrorNotAuthenticated);
+        call_handler_later(env, handler, vec![error]);
+        return;
+    }
+
+    let &GKScoreHostObject { category, value, date, .. } = env.objc.borrow(this);
+    let category = category_key(env, category);
+    let date: NSTimeInterval = msg![env; date timeIntervalSinceReferenceDate];
+    log!("Game Center: reporting score {} in {:?}", value, category);
+    GameCenterData::update(env, |data| {
+        let scores = data.scores.entry(category).or_default();
+        // Insert after any equal scores, so that older ones rank higher.
+        let index = scores.partition_point(|score| score.value >= value);
+        scores.insert(index, StoredScore { value, date });
+        scores.truncate(MAX_SCORES_PER_CATEGORY);
+    });
+    call_handler_later(env, handler, vec![nil]);
+}
+
+@end
+
+};
+
+/// Get the key scores for a category are stored under. `nil` means the app's
+/// default leaderboard.
+pub(super) fn category_key(env: &mut Environment, category: id) -> String {
+    if category == nil {
+        String::new()
+    } else {
+        ns_string::to_rust_string(env, category).into_owned()
+    }
+}
+
+/// Create a `GKScore*` for a stored score. The caller owns the result.
+pub(super) fn new_score(
+    env: &mut Environment,
+    category: id,
+    score: &StoredScore,
+    rank: NSInteger,
+) -> id {
+    let date: id = msg_class![env; NSDate dateWithTimeIntervalSinceReferenceDate:(score.date)];
+    retain(env, date);
+    let category: id = msg![env; category copy];
+    let new: id = msg_class![env; GKScore alloc];
+    *env.objc.borrow_mut(new) = GKScoreHostObject {
+        category,
+        value: score.value,
+        date,
+        rank,
+    };
+    new
+}
@@ -0,0 +1,1149 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `libsqlite3.dylib` (SQLite).
+//!
+//! This is a thin layer on top of a host SQLite (bundled by `libsqlite3-sys`).
+//! Guest database and statement handles are opaque allocations in guest memory
+//! that map to host handles, much like the OpenAL implementation. Database
+//! files are looked up in the guest filesystem and the host SQLite opens the
+//! host file backing them. Any journal files SQLite creates next to them
+//! aren't visible in the guest filesystem, but they only exist temporarily.
+//!
+//! Resources:
+//! - [C/C++ Interface For SQLite Version 3](https://www.sqlite.org/c3ref/intro.html)
+
+use crate::abi::{CallFromHost, GuestFunction};
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::fs::{GuestOpenOptions, GuestPath};
+use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, Mem, MutPtr, MutVoidPtr, Ptr, SafeWrite};
+use crate::Environment;
+use libsqlite3_sys as ffi;
+use std::collections::HashMap;
+use std::ffi::{c_char, c_int, CStr, CString};
+
+#[derive(Default)]
+pub struct State {
+    databases: HashMap<MutPtr<GuestSqlite3>, DatabaseHostObject>,
+    statements: HashMap<MutPtr<GuestSqlite3Stmt>, StatementHostObject>,
+    /// Guest copy of the string returned by `sqlite3_libversion`.
+    libversion: Option<ConstPtr<u8>>,
+}
+impl State {
+    fn get(env: &mut Environment) -> &mut Self {
+        &mut env.framework_state.libsqlite3
+    }
+}
+
+struct DatabaseHostObject {
+    /// Null if the database couldn't be opened, see `open_error`.
+    raw: *mut ffi::sqlite3,
+    /// `sqlite3_open` returns a handle even when it fails, so that the app can
+    /// get the error message. There's no host handle in that case, so this is
+    /// the error code to report instead.
+    open_error: c_int,
+    /// Guest copy of the last message returned by `sqlite3_errmsg`.
+    errmsg: Option<MutPtr<u8>>,
+    /// Guest copy of the last message returned by `sqlite3_errmsg16`.
+    errmsg16: Option<MutPtr<u16>>,
+}
+
+struct StatementHostObject {
+    raw: *mut ffi::sqlite3_stmt,
+    db: MutPtr<GuestSqlite3>,
+    /// Guest copies of column values returned by `sqlite3_column_text` etc.
+    /// These are only valid until the next step, reset or finalize.
+    column_values: HashMap<(c_int, ColumnFormat), MutVoidPtr>,
+    /// Guest copies of column names. These are valid until finalize.
+    column_names: HashMap<(c_int, ColumnFormat), MutVoidPtr>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum ColumnFormat {
+    Utf8,
+    Utf16,
+    Blob,
+}
+
+/// Opaque type in guest memory standing in for [ffi::sqlite3] in host memory.
+pub struct GuestSqlite3 {
+    _filler: u8,
+}
+impl SafeWrite for GuestSqlite3 {}
+/// Opaque type in guest memory standing in for [ffi::sqlite3_stmt] in host
+/// memory.
+pub struct GuestSqlite3Stmt {
+    _filler: u8,
+}
+impl SafeWrite for GuestSqlite3Stmt {}
+
+/// Special destructor value meaning the data won't change or be freed while
+/// SQLite is using it.
+const SQLITE_STATIC: u32 = 0;
+/// Special destructor value meaning SQLite must make its own copy of the data.
+const SQLITE_TRANSIENT: u32 = u32::MAX;
+
+fn host_db(env: &mut Environment, db: MutPtr<GuestSqlite3>) -> *mut ffi::sqlite3 {
+    State::get(env).databases.get(&db).unwrap().raw
+}
+
+fn host_stmt(env: &mut Environment, stmt: MutPtr<GuestSqlite3Stmt>) -> *mut ffi::sqlite3_stmt {
+    State::get(env).statements.get(&stmt).unwrap().raw
+}
+
+/// Free the guest copies of column values, which happens whenever the
+/// statement is stepped, reset or finalized.
+fn free_column_values(env: &mut Environment, stmt: MutPtr<GuestSqlite3Stmt>) {
+    let values = std::mem::take(
+        &mut State::get(env)
+            .statements
+            .get_mut(&stmt)
+            .unwrap()
+            .column_values,
+    );
+    for (_, ptr) in values {
+        env.mem.free(ptr);
+    }
+}
+
+/// Read a UTF-16 string from guest memory. If `n_bytes` is negative, the
+/// string is null-terminated.
+fn read_utf16(mem: &Mem, ptr: ConstPtr<u16>, n_bytes: c_int) -> Vec<u16> {
+    let mut units = Vec::new();
+    let mut i = 0;
+    while n_bytes < 0 || i < (n_bytes as GuestUSize) / 2 {
+        let unit = mem.read(ptr + i);
+        if n_bytes < 0 && unit == 0 {
+            break;
+        }
+        units.push(unit);
+        i += 1;
+    }
+    units
+}
+
+/// Write a null-terminated UTF-16 string to newly-allocated guest memory.
+fn alloc_utf16(mem: &mut Mem, units: &[u16]) -> MutPtr<u16> {
+    let len: GuestUSize = units.len().try_into().unwrap();
+    let ptr: MutPtr<u16> = mem.alloc((len + 1) * 2).cast();
+    for (i, &unit) in units.iter().enumerate() {
+        mem.write(ptr + i as GuestUSize, unit);
+    }
+    mem.write(ptr + len, 0);
+    ptr
+}
+
+/// Call a guest destructor passed to a `sqlite3_bind_*` function, unless it's
+/// one of the special values. touchHLE always makes the host SQLite copy the
+/// data, so it can be called right away.
+fn call_bind_destructor(env: &mut Environment, destructor: GuestFunction, data: ConstVoidPtr) {
+    match destructor.addr_with_thumb_bit() {
+        SQLITE_STATIC | SQLITE_TRANSIENT => (),
+        _ => {
+            let () = destructor.call_from_host(env, (data,));
+        }
+    }
+}
+
+// === Opening and closing databases ===
+
+fn open_database(
+    env: &mut Environment,
+    filename: &str,
+    flags: c_int,
+    pp_db: MutPtr<MutPtr<GuestSqlite3>>,
+) -> c_int {
+    let (raw, rc) = open_host_database(env, filename, flags);
+    let guest_db = env.mem.alloc_and_write(GuestSqlite3 { _filler: 0 });
+    State::get(env).databases.insert(
+        guest_db,
+        DatabaseHostObject {
+            raw,
+            open_error: if raw.is_null() { rc } else { ffi::SQLITE_OK },
+            errmsg: None,
+            errmsg16: None,
+        },
+    );
+    log_dbg!(
+        "sqlite3_open({:?}, {:#x}) => {:?} (host: {:?}), {}",
+        filename,
+        flags,
+        guest_db,
+        raw,
+        rc
+    );
+    env.mem.write(pp_db, guest_db);
+    rc
+}
+
+fn open_host_database(
+    env: &mut Environment,
+    filename: &str,
+    mut flags: c_int,
+) -> (*mut ffi::sqlite3, c_int) {
+    // URI filenames aren't supported.
+    flags &= !ffi::SQLITE_OPEN_URI;
+
+    // In-memory and temporary databases don't need a file.
+    if filename.is_empty() || filename == ":memory:" {
+        return host_open_v2(filename, flags);
+    }
+
+    let path = GuestPath::new(filename);
+    if !env.fs.exists(path) {
+        if flags & ffi::SQLITE_OPEN_CREATE == 0 {
+            return (std::ptr::null_mut(), ffi::SQLITE_CANTOPEN);
+        }
+        // Create the file in the guest filesystem so it knows about it.
+        // SQLite treats an empty file as an empty database.
+        let mut options = GuestOpenOptions::new();
+        options.write().create();
+        if env.fs.open_with_options(path, options).is_err() {
+            return (std::ptr::null_mut(), ffi::SQLITE_CANTOPEN);
+        }
+    }
+
+    if let Some((host_path, writeable)) = env.fs.host_file_path(path) {
+        let Some(host_path) = host_path.to_str() else {
+            log!("Warning: host path {:?} isn't valid UTF-8", host_path);
+            return (std::ptr::null_mut(), ffi::SQLITE_CANTOPEN);
+        };
+        if !writeable {
+            flags &= !(ffi::SQLITE_OPEN_READWRITE | ffi::SQLITE_OPEN_CREATE);
+            flags |= ffi::SQLITE_OPEN_READONLY;
+        }
+        return host_open_v2(host_path, flags);
+    }
+
+    // Files in the app bundle might only exist inside the .ipa file, so the
+    // host SQLite can't open them directly. They're read-only, so the whole
+    // database can be loaded into memory instead.
+    let Ok(data) = env.fs.read(path) else {
+        return (std::ptr::null_mut(), ffi::SQLITE_CANTOPEN);
+    };
+    let (raw, rc) = host_open_v2(":memory:", ffi::SQLITE_OPEN_READWRITE);
+    if rc != ffi::SQLITE_OK {
+        return (raw, rc);
+    }
+    let rc = unsafe {
+        let size = data.len();
+        let buffer = ffi::sqlite3_malloc64(size as u64).cast::<u8>();
+        std::ptr::copy_nonoverlapping(data.as_ptr(), buffer, size);
+        ffi::sqlite3_deserialize(
+            raw,
+            b"main\0".as_ptr().cast(),
+            buffer,
+            size as ffi::sqlite3_int64,
+            size as ffi::sqlite3_int64,
+            (ffi::SQLITE_DESERIALIZE_FREEONCLOSE | ffi::SQLITE_DESERIALIZE_READONLY) as u32,
+        )
+    };
+    if rc != ffi::SQLITE_OK {
+        unsafe { ffi::sqlite3_close(raw) };
+        return (std::ptr::null_mut(), rc);
+    }
+    (raw, rc)
+}
+
+fn host_open_v2(filename: &str, flags: c_int) -> (*mut ffi::sqlite3, c_int) {
+    let filename = CString::new(filename).unwrap();
+    let mut raw = std::ptr::null_mut();
+    let rc = unsafe { ffi::sqlite3_open_v2(filename.as_ptr(), &mut raw, flags, std::ptr::null()) };
+    if rc != ffi::SQLITE_OK {
+        // Like the guest API, the host API returns a handle on failure, but
+        // the handle is replaced with the open error here.
+        unsafe { ffi::sqlite3_close(raw) };
+        return (std::ptr::null_mut(), rc);
+    }
+    (raw, rc)
+}
+
+fn sqlite3_open(
+    env: &mut Environment,
+    filename: ConstPtr<u8>,
+    pp_db: MutPtr<MutPtr<GuestSqlite3>>,
+) -> c_int {
+    let filename = env.mem.cstr_at_utf8(filename).unwrap().to_string();
+    let flags = ffi::SQLITE_OPEN_READWRITE | ffi::SQLITE_OPEN_CREATE;
+    open_database(env, &filename, flags, pp_db)
+}
+
+fn sqlite3_open16(
+    env: &mut Environment,
+    filename: ConstPtr<u16>,
+    pp_db: MutPtr<MutPtr<GuestSqlite3>>,
+) -> c_int {
+    let filename = String::from_utf16(&read_utf16(&env.mem, filename, -1)).unwrap();
+    let flags = ffi::SQLITE_OPEN_READWRITE | ffi::SQLITE_OPEN_CREATE;
+    open_database(env, &filename, flags, pp_db)
+}
+
+fn sqlite3_open_v2(
+    env: &mut Environment,
+    filename: ConstPtr<u8>,
+    pp_db: MutPtr<MutPtr<GuestSqlite3>>,
+    flags: c_int,
+    z_vfs: ConstPtr<u8>,
+) -> c_int {
+    if !z_vfs.is_null() {
+        log!(
+            "Warning: ignoring VFS {:?} for sqlite3_open_v2()",
+            env.mem.cstr_at_utf8(z_vfs)
+        );
+    }
+    let filename = env.mem.cstr_at_utf8(filename).unwrap().to_string();
+    open_database(env, &filename, flags, pp_db)
+}
+
+fn sqlite3_close(env: &mut Environment, db: MutPtr<GuestSqlite3>) -> c_int {
+    if db.is_null() {
+        return ffi::SQLITE_OK;
+    }
+    let raw = host_db(env, db);
+    if !raw.is_null() {
+        // This fails with SQLITE_BUSY if there are unfinalized statements, in
+        // which case the database stays open.
+        let rc = unsafe { ffi::sqlite3_close(raw) };
+        if rc != ffi::SQLITE_OK {
+            log_dbg!("sqlite3_close({:?}) => {}", db, rc);
+            return rc;
+        }
+    }
+    let host_object = State::get(env).databases.remove(&db).unwrap();
+    if let Some(errmsg) = host_object.errmsg {
+        env.mem.free(errmsg.cast());
+    }
+    if let Some(errmsg16) = host_object.errmsg16 {
+        env.mem.free(errmsg16.cast());
+    }
+    env.mem.free(db.cast());
+    log_dbg!("sqlite3_close({:?}) => SQLITE_OK", db);
+    ffi::SQLITE_OK
+}
+
+// === Errors ===
+
+fn sqlite3_errcode(env: &mut Environment, db: MutPtr<GuestSqlite3>) -> c_int {
+    let host_object = State::get(env).databases.get(&db).unwrap();
+    if host_object.raw.is_null() {
+        return host_object.open_error;
+    }
+    unsafe { ffi::sqlite3_errcode(host_object.raw) }
+}
+
+fn sqlite3_extended_errcode(env: &mut Environment, db: MutPtr<GuestSqlite3>) -> c_int {
+    let host_object = State::get(env).databases.get(&db).unwrap();
+    if host_object.raw.is_null() {
+        return host_object.open_error;
+    }
+    unsafe { ffi::sqlite3_extended_errcode(host_object.raw) }
+}
+
+fn host_errmsg(env: &mut Environment, db: MutPtr<GuestSqlite3>) -> String {
+    let host_object = State::get(env).databases.get(&db).unwrap();
+    let message = unsafe {
+        if host_object.raw.is_null() {
+            ffi::sqlite3_errstr(host_object.open_error)
+        } else {
+            ffi::sqlite3_errmsg(host_object.raw)
+        }
+    };
+    unsafe { CStr::from_ptr(message) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn sqlite3_errmsg(env: &mut Environment, db: MutPtr<GuestSqlite3>) -> ConstPtr<u8> {
+    let message = host_errmsg(env, db);
+    let new = env.mem.alloc_and_write_cstr(message.as_bytes());
+    let old = State::get(env)
+        .databases
+        .get_mut(&db)
+        .unwrap()
+        .errmsg
+        .replace(new);
+    if let Some(old) = old {
+        env.mem.free(old.cast());
+    }
+    new.cast_const()
+}
+
+fn sqlite3_errmsg16(env: &mut Environment, db: MutPtr<GuestSqlite3>) -> ConstPtr<u16> {
+    let message: Vec<u16> = host_errmsg(env, db).encode_utf16().collect();
+    let new = alloc_utf16(&mut env.mem, &message);
+    let old = State::get(env)
+        .databases
+        .get_mut(&db)
+        .unwrap()
+        .errmsg16
+        .replace(new);
+    if let Some(old) = old {
+        env.mem.free(old.cast());
+    }
+    new.cast_const()
+}
+
+// === Preparing and finalizing statements ===
+
+fn prepare_statement(
+    env: &mut Environment,
+    db: MutPtr<GuestSqlite3>,
+    sql: &[u8],
+    legacy: bool,
+    pp_stmt: MutPtr<MutPtr<GuestSqlite3Stmt>>,
+) -> (c_int, usize) {
+    let raw_db = host_db(env, db);
+    let mut raw = std::ptr::null_mut();
+    let mut tail = std::ptr::null();
+    let sql_ptr: *const c_char = sql.as_ptr().cast();
+    let rc = unsafe {
+        if legacy {
+            ffi::sqlite3_prepare(raw_db, sql_ptr, sql.len() as c_int, &mut raw, &mut tail)
+        } else {
+            ffi::sqlite3_prepare_v2(raw_db, sql_ptr, sql.len() as c_int, &mut raw, &mut tail)
+        }
+    };
+    // The tail is returned as an offset so the caller can translate it to a
+    // guest pointer.
+    let tail_offset = if tail.is_null() {
+        sql.len()
+    } else {
+        unsafe { tail.offset_from(sql_ptr) as usize }
+    };
+
+    let guest_stmt = if raw.is_null() {
+        // This happens for errors and for SQL without a statement in it, e.g.
+        // a comment.
+        Ptr::null()
+    } else {
+        let guest_stmt = env.mem.alloc_and_write(GuestSqlite3Stmt { _filler: 0 });
+        State::get(env).statements.insert(
+            guest_stmt,
+            StatementHostObject {
+                raw,
+                db,
+                column_values: HashMap::new(),
+                column_names: HashMap::new(),
+            },
+        );
+        guest_stmt
+    };
+    log_dbg!(
+        "sqlite3_prepare({:?}, {:?}) => {:?} (host: {:?}), {}",
+        db,
+        String::from_utf8_lossy(&sql[..tail_offset]),
+        guest_stmt,
+        raw,
+        rc
+    );
+    if !pp_stmt.is_null() {
+        env.mem.write(pp_stmt, guest_stmt);
+    }
+    (rc, tail_offset)
+}
+
+fn prepare_utf8(
+    env: &mut Environment,
+    db: MutPtr<GuestSqlite3>,
+    z_sql: ConstPtr<u8>,
+    n_byte: c_int,
+    pp_stmt: MutPtr<MutPtr<GuestSqlite3Stmt>>,
+    pz_tail: MutPtr<ConstPtr<u8>>,
+    legacy: bool,
+) -> c_int {
+    let sql = if n_byte < 0 {
+        env.mem.cstr_at(z_sql).to_vec()
+    } else {
+        env.mem.bytes_at(z_sql, n_byte as GuestUSize).to_vec()
+    };
+    let (rc, tail_offset) = prepare_statement(env, db, &sql, legacy, pp_stmt);
+    if !pz_tail.is_null() {
+        env.mem.write(pz_tail, z_sql + tail_offset as GuestUSize);
+    }
+    rc
+}
+
+fn sqlite3_prepare(
+    env: &mut Environment,
+    db: MutPtr<GuestSqlite3>,
+    z_sql: ConstPtr<u8>,
+    n_byte: c_int,
+    pp_stmt: MutPtr<MutPtr<GuestSqlite3Stmt>>,
+    pz_tail: MutPtr<ConstPtr<u8>>,
+) -> c_int {
+    prepare_utf8(
+        env, db, z_sql, n_byte, pp_stmt, pz_tail, /* legacy: */ true,
+    )
+}
+
+fn sqlite3_prepare_v2(
+    env: &mut Environment,
+    db: MutPtr<GuestSqlite3>,
+    z_sql: ConstPtr<u8>,
+    n_byte: c_int,
+    pp_stmt: MutPtr<MutPtr<GuestSqlite3Stmt>>,
+    pz_tail: MutPtr<ConstPtr<u8>>,
+) -> c_int {
+    prepare_utf8(
+        env, db, z_sql, n_byte, pp_stmt, pz_tail, /* legacy: */ false,
+    )
+}
+
+fn sqlite3_prepare16_v2(
+    env: &mut Environment,
+    db: MutPtr<GuestSqlite3>,
+    z_sql: ConstPtr<u16>,
+    n_byte: c_int,
+    pp_stmt: MutPtr<MutPtr<GuestSqlite3Stmt>>,
+    pz_tail: MutPtr<ConstPtr<u16>>,
+) -> c_int {
+    let units = read_utf16(&env.mem, z_sql, n_byte);
+    // Converting to UTF-8 means the tail offset has to be converted back.
+    let sql = String::from_utf16_lossy(&units);
+    let (rc, tail_offset) =
+        prepare_statement(env, db, sql.as_bytes(), /* legacy: */ false, pp_stmt);
+    if !pz_tail.is_null() {
+        let tail_units = sql[..tail_offset].encode_utf16().count();
+        env.mem.write(pz_tail, z_sql + tail_units as GuestUSize);
+    }
+    rc
+}
+
+fn sqlite3_finalize(env: &mut Environment, stmt: MutPtr<GuestSqlite3Stmt>) -> c_int {
+    if stmt.is_null() {
+        return ffi::SQLITE_OK;
+    }
+    free_column_values(env, stmt);
+    let host_object = State::get(env).statements.remove(&stmt).unwrap();
+    for (_, ptr) in host_object.column_names {
+        env.mem.free(ptr);
+    }
+    env.mem.free(stmt.cast());
+    // This returns the error from the most recent step, if any.
+    unsafe { ffi::sqlite3_finalize(host_object.raw) }
+}
+
+fn sqlite3_db_handle(
+    env: &mut Environment,
+    stmt: MutPtr<GuestSqlite3Stmt>,
+) -> MutPtr<GuestSqlite3> {
+    State::get(env).statements.get(&stmt).unwrap().db
+}
+
+// === Evaluating statements ===
+
+fn sqlite3_step(env: &mut Environment, stmt: MutPtr<GuestSqlite3Stmt>) -> c_int {
+    free_column_values(env, stmt);
+    let raw = host_stmt(env, stmt);
+    let rc = unsafe { ffi::sqlite3_step(raw) };
+    log_dbg!("sqlite3_step({:?}) => {}", stmt, rc);
+    rc
+}
+
+fn sqlite3_reset(env: &mut Environment, stmt: MutPtr<GuestSqlite3Stmt>) -> c_int {
+    free_column_values(env, stmt);
+    let raw = host_stmt(env, stmt);
+    unsafe { ffi::sqlite3_reset(raw) }
+}
+
+fn sqlite3_clear_bindings(env: &mut Environment, stmt: MutPtr<GuestSqlite3Stmt>) -> c_int {
+    let raw = host_stmt(env, stmt);
+    unsafe { ffi::sqlite3_clear_bindings(raw) }
+}
+
+// === Binding parameters ===
+
+fn sqlite3_bind_parameter_count(env: &mut Environment, stmt: MutPtr<GuestSqlite3Stmt>) -> c_int {
+    let raw = host_stmt(env, stmt);
+    unsafe { ffi::sqlite3_bind_parameter_count(raw) }
+}
+
+fn sqlite3_bind_parameter_index(
+    env: &mut Environment,
+    stmt: MutPtr<GuestSqlite3Stmt>,
+    z_name: ConstPtr<u8>,
+) -> c_int {
+    let raw = host_stmt(env, stmt);
+    let name = CString::new(env.mem.cstr_at(z_name)).unwrap();
+    unsafe { ffi::sqlite3_bind_parameter_index(raw, name.as_ptr()) }
+}
+
+fn sqlite3_bind_null(env: &mut Environment, stmt: MutPtr<GuestSqlite3Stmt>, index: c_int) -> c_int {
+    let raw = host_stmt(env, stmt);
+    unsafe { ffi::sqlite3_bind_null(raw, index) }
+}
+
+fn sqlite3_bind_int(
+    env: &mut Environment,
+    stmt: MutPtr<GuestSqlite3Stmt>,
+    index: c_int,
+    value: c_int,
+) -> c_int {
+    let raw = host_stmt(env, stmt);
+    unsafe { ffi::sqlite3_bind_int(raw, index, value) }
+}
+
+fn sqlite3_bind_int64(
+    env: &mut Environment,
+    stmt: MutPtr<GuestSqlite3Stmt>,
+    index: c_int,
+    value: i64,
+) -> c_int {
+    let raw = host_stmt(env, stmt);
+    unsafe { ffi::sqlite3_bind_int64(raw, index, value) }
+}
+
+fn sqlite3_bind_double(
+    env: &mut Environment,
+    stmt: MutPtr<GuestSqlite3Stmt>,
+    index: c_int,
+    value: f64,
+) -> c_int {
+    let raw = host_stmt(env, stmt);
+    unsafe { ffi::sqlite3_bind_double(raw, index, value) }
+}
+
+fn sqlite3_bind_zeroblob(
+    env: &mut Environment,
+    stmt: MutPtr<GuestSqlite3Stmt>,
+    index: c_int,
+    n: c_int,
+) -> c_int {
+    let raw = host_stmt(env, stmt);
+    unsafe { ffi::sqlite3_bind_zeroblob(raw, index, n) }
+}
+
+fn sqlite3_bind_text(
+    env: &mut Environment,
+    stmt: MutPtr<GuestSqlite3Stmt>,
+    index: c_int,
+    text: ConstPtr<u8>,
+    n: c_int,
+    destructor: GuestFunction,
+) -> c_int {
+    let raw = host_stmt(env, stmt);
+    let rc = if text.is_null() {
+        unsafe { ffi::sqlite3_bind_null(raw, index) }
+    } else {
+        let bytes = if n < 0 {
+            env.mem.cstr_at(text)
+        } else {
+            env.mem.bytes_at(text, n as GuestUSize)
+        };
+        unsafe {
+            ffi::sqlite3_bind_text(
+                raw,
+                index,
+                bytes.as_ptr().cast(),
+                bytes.len() as c_int,
+                ffi::SQLITE_TRANSIENT(),
+            )
+        }
+    };
+    call_bind_destructor(env, destructor, text.cast());
+    rc
+}
+
+fn sqlite3_bind_text16(
+    env: &mut Environment,
+    stmt: MutPtr<GuestSqlite3Stmt>,
+    index: c_int,
+    text: ConstPtr<u16>,
+    n: c_int,
+    destructor: GuestFunction,
+) -> c_int {
+    let raw = host_stmt(env, stmt);
+    let rc = if text.is_null() {
+        unsafe { ffi::sqlite3_bind_null(raw, index) }
+    } else {
+        let units = read_utf16(&env.mem, text, n);
+        unsafe {
+            ffi::sqlite3_bind_text16(
+                raw,
+                index,
+                units.as_ptr().cast(),
+                (units.len() * 2) as c_int,
+                ffi::SQLITE_TRANSIENT(),
+            )
+        }
+    };
+    call_bind_destructor(env, destructor, text.cast());
+    rc
+}
+
+fn sqlite3_bind_blob(
+    env: &mut Environment,
+    stmt: MutPtr<GuestSqlite3Stmt>,
+    index: c_int,
+    data: ConstVoidPtr,
+    n: c_int,
+    destructor: GuestFunction,
+) -> c_int {
+    let raw = host_stmt(env, stmt);
+    let rc = if data.is_null() {
+        unsafe { ffi::sqlite3_bind_null(raw, index) }
+    } else {
+        let bytes = env.mem.bytes_at(data.cast(), n.max(0) as GuestUSize);
+        unsafe {
+            ffi::sqlite3_bind_blob(
+                raw,
+                index,
+                bytes.as_ptr().cast(),
+                bytes.len() as c_int,
+                ffi::SQLITE_TRANSIENT(),
+            )
+        }
+    };
+    call_bind_destructor(env, destructor, data);
+    rc
+}
+
+// === Reading results ===
+
+fn sqlite3_column_count(env: &mut Environment, stmt: MutPtr<GuestSqlite3Stmt>) -> c_int {
+    let raw = host_stmt(env, stmt);
+    unsafe { ffi::sqlite3_column_count(raw) }
+}
+
+fn sqlite3_data_count(env: &mut Environment, stmt: MutPtr<GuestSqlite3Stmt>) -> c_int {
+    let raw = host_stmt(env, stmt);
+    unsafe { ffi::sqlite3_data_count(raw) }
+}
+
+fn sqlite3_column_type(env: &mut Environment, stmt: MutPtr<GuestSqlite3Stmt>, col: c_int) -> c_int {
+    let raw = host_stmt(env, stmt);
+    unsafe { ffi::sqlite3_column_type(raw, col) }
+}
+
+fn sqlite3_column_int(env: &mut Environment, stmt: MutPtr<GuestSqlite3Stmt>, col: c_int) -> c_int {
+    let raw = host_stmt(env, stmt);
+    unsafe { ffi::sqlite3_column_int(raw, col) }
+}
+
+fn sqlite3_column_int64(env: &mut Environment, stmt: MutPtr<GuestSqlite3Stmt>, col: c_int) -> i64 {
+    let raw = host_stmt(env, stmt);
+    unsafe { ffi::sqlite3_column_int64(raw, col) }
+}
+
+fn sqlite3_column_double(env: &mut Environment, stmt: MutPtr<GuestSqlite3Stmt>, col: c_int) -> f64 {
+    let raw = host_stmt(env, stmt);
+    unsafe { ffi::sqlite3_column_double(raw, col) }
+}
+
+fn sqlite3_column_bytes(
+    env: &mut Environment,
+    stmt: MutPtr<GuestSqlite3Stmt>,
+    col: c_int,
+) -> c_int {
+    let raw = host_stmt(env, stmt);
+    unsafe { ffi::sqlite3_column_bytes(raw, col) }
+}
+
+fn sqlite3_column_bytes16(
+    env: &mut Environment,
+    stmt: MutPtr<GuestSqlite3Stmt>,
+    col: c_int,
+) -> c_int {
+    let raw = host_stmt(env, stmt);
+    unsafe { ffi::sqlite3_column_bytes16(raw, col) }
+}
+
+/// Get a guest copy of a column value in the requested format. Like with the
+/// real SQLite, the result stays valid until the statement is stepped, reset
+/// or finalized.
+fn column_value(
+    env: &mut Environment,
+    stmt: MutPtr<GuestSqlite3Stmt>,
+    col: c_int,
+    format: ColumnFormat,
+) -> MutVoidPtr {
+    let host_object = State::get(env).statements.get(&stmt).unwrap();
+    if let Some(&ptr) = host_object.column_values.get(&(col, format)) {
+        return ptr;
+    }
+    let raw = host_object.raw;
+
+    let ptr = unsafe {
+        match format {
+            ColumnFormat::Utf8 => {
+                let text = ffi::sqlite3_column_text(raw, col);
+                if text.is_null() {
+                    return Ptr::null();
+                }
+                let len = ffi::sqlite3_column_bytes(raw, col) as usize;
+                let bytes = std::slice::from_raw_parts(text, len);
+                env.mem.alloc_and_write_cstr(bytes).cast()
+            }
+            ColumnFormat::Utf16 => {
+                let text = ffi::sqlite3_column_text16(raw, col);
+                if text.is_null() {
+                    return Ptr::null();
+                }
+                let len = ffi::sqlite3_column_bytes16(raw, col) as usize / 2;
+                let units = std::slice::from_raw_parts(text.cast::<u16>(), len);
+                alloc_utf16(&mut env.mem, units).cast()
+            }
+            ColumnFormat::Blob => {
+                let blob = ffi::sqlite3_column_blob(raw, col);
+                // Zero-length blobs are returned as NULL.
+                if blob.is_null() {
+                    return Ptr::null();
+                }
+                let len = ffi::sqlite3_column_bytes(raw, col) as GuestUSize;
+                let bytes = std::slice::from_raw_parts(blob.cast::<u8>(), len as usize);
+                let ptr = env.mem.alloc(len);
+                env.mem.bytes_at_mut(ptr.cast(), len).copy_from_slice(bytes);
+                ptr
+            }
+        }
+    };
+    State::get(env)
+        .statements
+        .get_mut(&stmt)
+        .unwrap()
+        .column_values
+        .insert((col, format), ptr);
+    ptr
+}
+
+fn sqlite3_column_text(
+    env: &mut Environment,
+    stmt: MutPtr<GuestSqlite3Stmt>,
+    col: c_int,
+) -> ConstPtr<u8> {
+    column_value(env, stmt, col, ColumnFormat::Utf8)
+        .cast()
+        .cast_const()
+}
+
+fn sqlite3_column_text16(
+    env: &mut Environment,
+    stmt: MutPtr<GuestSqlite3Stmt>,
+    col: c_int,
+) -> ConstVoidPtr {
+    column_value(env, stmt, col, ColumnFormat::Utf16).cast_const()
+}
+
+fn sqlite3_column_blob(
+    env: &mut Environment,
+    stmt: MutPtr<GuestSqlite3Stmt>,
+    col: c_int,
+) -> ConstVoidPtr {
+    column_value(env, stmt, col, ColumnFormat::Blob).cast_const()
+}
+
+/// Get a guest copy of a column name. These stay valid until the statement is
+/// finalized.
+fn column_name(
+    env: &mut Environment,
+    stmt: MutPtr<GuestSqlite3Stmt>,
+    col: c_int,
+    format: ColumnFormat,
+) -> MutVoidPtr {
+    let host_object = State::get(env).statements.get(&stmt).unwrap();
+    if let Some(&ptr) = host_object.column_names.get(&(col, format)) {
+        return ptr;
+    }
+    let raw = host_object.raw;
+    let name = unsafe { ffi::sqlite3_column_name(raw, col) };
+    if name.is_null() {
+        return Ptr::null();
+    }
+    let name = unsafe { CStr::from_ptr(name) };
+    let ptr = match format {
+        ColumnFormat::Utf16 => {
+            let units: Vec<u16> = name.to_string_lossy().encode_utf16().collect();
+            alloc_utf16(&mut env.mem, &units).cast()
+        }
+        _ => env.mem.alloc_and_write_cstr(name.to_bytes()).cast(),
+    };
+    State::get(env)
+        .statements
+        .get_mut(&stmt)
+        .unwrap()
+        .column_names
+        .insert((col, format), ptr);
+    ptr
+}
+
+fn sqlite3_column_name(
+    env: &mut Environment,
+    stmt: MutPtr<GuestSqlite3Stmt>,
+    col: c_int,
+) -> ConstPtr<u8> {
+    column_name(env, stmt, col, ColumnFormat::Utf8)
+        .cast()
+        .cast_const()
+}
+
+fn sqlite3_column_name16(
+    env: &mut Environment,
+    stmt: MutPtr<GuestSqlite3Stmt>,
+    col: c_int,
+) -> ConstVoidPtr {
+    column_name(env, stmt, col, ColumnFormat::Utf16).cast_const()
+}
+
+// === Convenience interfaces ===
+
+/// `sqlite3_exec` is implemented on top of the host prepare/step functions
+/// rather than the host `sqlite3_exec`, so that the guest callback can be
+/// called with arguments in guest memory.
+fn sqlite3_exec(
+    env: &mut Environment,
+    db: MutPtr<GuestSqlite3>,
+    sql: ConstPtr<u8>,
+    callback: GuestFunction, // int (*)(void*, int, char**, char**)
+    callback_arg: MutVoidPtr,
+    errmsg: MutPtr<MutPtr<u8>>,
+) -> c_int {
+    let raw_db = host_db(env, db);
+    let sql_bytes = env.mem.cstr_at(sql).to_vec();
+    let has_callback = !callback.to_ptr().is_null();
+
+    let mut offset = 0;
+    let mut rc = ffi::SQLITE_OK;
+    while rc == ffi::SQLITE_OK && offset < sql_bytes.len() {
+        let remaining = &sql_bytes[offset..];
+        let mut raw = std::ptr::null_mut();
+        let mut tail = std::ptr::null();
+        rc = unsafe {
+            ffi::sqlite3_prepare_v2(
+                raw_db,
+                remaining.as_ptr().cast(),
+                remaining.len() as c_int,
+                &mut raw,
+                &mut tail,
+            )
+        };
+        if rc != ffi::SQLITE_OK {
+            break;
+        }
+        offset += if tail.is_null() {
+            remaining.len()
+        } else {
+            unsafe { tail.offset_from(remaining.as_ptr().cast()) as usize }
+        };
+        if raw.is_null() {
+            // Whitespace or a comment.
+            continue;
+        }
+
+        let column_count = unsafe { ffi::sqlite3_column_count(raw) } as GuestUSize;
+        let mut column_names: Option<MutPtr<ConstPtr<u8>>> = None;
+        loop {
+            rc = unsafe { ffi::sqlite3_step(raw) };
+            if rc != ffi::SQLITE_ROW {
+                break;
+            }
+            if !has_callback {
+                continue;
+            }
+
+            let names = *column_names.get_or_insert_with(|| {
+                let names: MutPtr<ConstPtr<u8>> = env.mem.alloc(column_count * 4).cast();
+                for i in 0..column_count {
+                    let name = unsafe { CStr::from_ptr(ffi::sqlite3_column_name(raw, i as c_int)) };
+                    let name = env.mem.alloc_and_write_cstr(name.to_bytes());
+                    env.mem.write(names + i, name.cast_const());
+                }
+                names
+            });
+            let values: MutPtr<ConstPtr<u8>> = env.mem.alloc(column_count * 4).cast();
+            for i in 0..column_count {
+                let value = unsafe {
+                    let text = ffi::sqlite3_column_text(raw, i as c_int);
+                    if text.is_null() {
+                        Ptr::null()
+                    } else {
+                        let len = ffi::sqlite3_column_bytes(raw, i as c_int) as usize;
+                        let bytes = std::slice::from_raw_parts(text, len);
+                        env.mem.alloc_and_write_cstr(bytes).cast_const()
+                    }
+                };
+                env.mem.write(values + i, value);
+            }
+
+            let callback_rc: c_int =
+                callback.call_from_host(env, (callback_arg, column_count as c_int, values, names));
+
+            for i in 0..column_count {
+                let value = env.mem.read(values + i);
+                if !value.is_null() {
+                    env.mem.free(value.cast_mut().cast());
+                }
+            }
+            env.mem.free(values.cast());
+
+            if callback_rc != 0 {
+                rc = ffi::SQLITE_ABORT;
+                break;
+            }
+        }
+        if rc == ffi::SQLITE_DONE {
+            rc = ffi::SQLITE_OK;
+        }
+
+        if let Some(names) = column_names {
+            for i in 0..column_count {
+                let name = env.mem.read(names + i);
+                env.mem.free(name.cast_mut().cast());
+            }
+            env.mem.free(names.cast());
+        }
+        unsafe { ffi::sqlite3_finalize(raw) };
+    }
+
+    log_dbg!(
+        "sqlite3_exec({:?}, {:?}, {:?}, {:?}) => {}",
+        db,
+        String::from_utf8_lossy(&sql_bytes),
+        callback,
+        callback_arg,
+        rc
+    );
+
+    if !errmsg.is_null() {
+        let message = if rc == ffi::SQLITE_OK {
+            Ptr::null()
+        } else {
+            // The app is expected to free this with sqlite3_free().
+            let message = unsafe {
+                if rc == ffi::SQLITE_ABORT {
+                    CStr::from_ptr(ffi::sqlite3_errstr(rc))
+                } else {
+                    CStr::from_ptr(ffi::sqlite3_errmsg(raw_db))
+                }
+            };
+            env.mem.alloc_and_write_cstr(message.to_bytes())
+        };
+        env.mem.write(errmsg, message);
+    }
+    rc
+}
+
+fn sqlite3_last_insert_rowid(env: &mut Environment, db: MutPtr<GuestSqlite3>) -> i64 {
+    let raw = host_db(env, db);
+    unsafe { ffi::sqlite3_last_insert_rowid(raw) }
+}
+
+fn sqlite3_changes(env: &mut Environment, db: MutPtr<GuestSqlite3>) -> c_int {
+    let raw = host_db(env, db);
+    unsafe { ffi::sqlite3_changes(raw) }
+}
+
+fn sqlite3_total_changes(env: &mut Environment, db: MutPtr<GuestSqlite3>) -> c_int {
+    let raw = host_db(env, db);
+    unsafe { ffi::sqlite3_total_changes(raw) }
+}
+
+fn sqlite3_get_autocommit(env: &mut Environment, db: MutPtr<GuestSqlite3>) -> c_int {
+    let raw = host_db(env, db);
+    unsafe { ffi::sqlite3_get_autocommit(raw) }
+}
+
+fn sqlite3_busy_timeout(_env: &mut Environment, db: MutPtr<GuestSqlite3>, ms: c_int) -> c_int {
+    // The host SQLite would sleep while waiting for a lock, which would block
+    // the whole emulator. Only other connections in the same app can hold
+    // locks, and they can't release them while it's blocked, so waiting is
+    // pointless. SQLITE_BUSY is returned right away instead, as if there was
+    // no timeout, and apps are expected to retry.
+    log_dbg!("TODO: sqlite3_busy_timeout({:?}, {}) (ignored)", db, ms);
+    ffi::SQLITE_OK
+}
+
+// === Memory allocation ===
+
+// These use the guest heap, because strings returned by sqlite3_exec() must
+// be freed with sqlite3_free().
+
+fn sqlite3_malloc(env: &mut Environment, size: c_int) -> MutVoidPtr {
+    if size <= 0 {
+        return Ptr::null();
+    }
+    env.mem.try_alloc(size as GuestUSize).unwrap_or(Ptr::null())
+}
+
+fn sqlite3_free(env: &mut Environment, ptr: MutVoidPtr) {
+    if !ptr.is_null() {
+        env.mem.free(ptr);
+    }
+}
+
+// === Library information ===
+
+fn sqlite3_libversion(env: &mut Environment) -> ConstPtr<u8> {
+    if let Some(version) = State::get(env).libversion {
+        return version;
+    }
+    let version = unsafe { CStr::from_ptr(ffi::sqlite3_libversion()) };
+    let version = env
+        .mem
+        .alloc_and_write_cstr(version.to_bytes())
+        .cast_const();
+    State::get(env).libversion = Some(version);
+    version
+}
+
+fn sqlite3_libversion_number(_env: &mut Environment) -> c_int {
+    unsafe { ffi::sqlite3_libversion_number() }
+}
+
+fn sqlite3_threadsafe(_env: &mut Environment) -> c_int {
+    unsafe { ffi::sqlite3_threadsafe() }
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(sqlite3_open(_, _)),
+    export_c_func!(sqlite3_open16(_, _)),
+    export_c_func!(sqlite3_open_v2(_, _, _, _)),
+    export_c_func!(sqlite3_close(_)),
+    export_c_func!(sqlite3_errcode(_)),
+    export_c_func!(sqlite3_extended_errcode(_)),
+    export_c_func!(sqlite3_errmsg(_)),
+    export_c_func!(sqlite3_errmsg16(_)),
+    export_c_func!(sqlite3_prepare(_, _, _, _, _)),
+    export_c_func!(sqlite3_prepare_v2(_, _, _, _, _)),
+    export_c_func!(sqlite3_prepare16_v2(_, _, _, _, _)),
+    export_c_func!(sqlite3_finalize(_)),
+    export_c_func!(sqlite3_db_handle(_)),
+    export_c_func!(sqlite3_step(_)),
+    export_c_func!(sqlite3_reset(_)),
+    export_c_func!(sqlite3_clear_bindings(_)),
+    export_c_func!(sqlite3_bind_parameter_count(_)),
+    export_c_func!(sqlite3_bind_parameter_index(_, _)),
+    export_c_func!(sqlite3_bind_null(_, _)),
+    export_c_func!(sqlite3_bind_int(_, _, _)),
+    export_c_func!(sqlite3_bind_int64(_, _, _)),
+    export_c_func!(sqlite3_bind_double(_, _, _)),
+    export_c_func!(sqlite3_bind_zeroblob(_, _, _)),
+    export_c_func!(sqlite3_bind_text(_, _, _, _, _)),
+    export_c_func!(sqlite3_bind_text16(_, _, _, _, _)),
+    export_c_func!(sqlite3_bind_blob(_, _, _, _, _)),
+    export_c_func!(sqlite3_column_count(_)),
+    export_c_func!(sqlite3_data_count(_)),
+    export_c_func!(sqlite3_column_type(_, _)),
+    export_c_func!(sqlite3_column_int(_, _)),
+    export_c_func!(sqlite3_column_int64(_, _)),
+    export_c_func!(sqlite3_column_double(_, _)),
+    export_c_func!(sqlite3_column_bytes(_, _)),
+    export_c_func!(sqlite3_column_bytes16(_, _)),
+    export_c_func!(sqlite3_column_text(_, _)),
+    export_c_func!(sqlite3_column_text16(_, _)),
+    export_c_func!(sqlite3_column_blob(_, _)),
+    export_c_func!(sqlite3_column_name(_, _)),
+    export_c_func!(sqlite3_column_name16(_, _)),
+    export_c_func!(sqlite3_exec(_, _, _, _, _)),
+    export_c_func!(sqlite3_last_insert_rowid(_)),
+    export_c_func!(sqlite3_changes(_)),
+    export_c_func!(sqlite3_total_changes(_)),
+    export_c_func!(sqlite3_get_autocommit(_)),
+    export_c_func!(sqlite3_busy_timeout(_, _)),
+    export_c_func!(sqlite3_malloc(_)),
+    export_c_func!(sqlite3_free(_)),
+    export_c_func!(sqlite3_libversion()),
+    export_c_func!(sqlite3_libversion_number()),
+    export_c_func!(sqlite3_threadsafe()),
+];
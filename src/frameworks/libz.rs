@@ -0,0 +1,1129 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `libz.dylib` (zlib).
+//!
+//! The compression itself is done by flate2, which only handles raw deflate
+//! and zlib streams at this level, so the gzip wrapper is handled here.
+//! iPhone OS ships zlib 1.2.3, so that's the version this pretends to be.
+//!
+//! Resources:
+//! - [zlib Manual](https://zlib.net/manual.html)
+//! - [RFC 1952: GZIP file format specification](https://www.rfc-editor.org/rfc/rfc1952)
+
+use crate::abi::GuestFunction;
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::fs::{GuestFile, GuestOpenOptions, GuestPath};
+use crate::libc::posix_io::{SEEK_CUR, SEEK_SET};
+use crate::mem::{
+    guest_size_of, ConstPtr, ConstVoidPtr, MutPtr, MutVoidPtr, Ptr, SafeRead, SafeWrite,
+};
+use crate::Environment;
+use flate2::write::GzEncoder;
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use std::collections::{HashMap, VecDeque};
+use std::io::{Read, Write};
+
+#[derive(Default)]
+pub struct State {
+    /// Keyed by the `state` field of `z_stream`, which is an opaque guest
+    /// allocation, like the internal state pointer of the real zlib.
+    streams: HashMap<MutVoidPtr, StreamHostObject>,
+    gz_files: HashMap<MutPtr<GuestGzFile>, GzFileHostObject>,
+    /// Guest copies of strings returned to the app, which are never freed.
+    strings: HashMap<String, ConstPtr<u8>>,
+}
+impl State {
+    fn get(env: &mut Environment) -> &mut Self {
+        &mut env.framework_state.libz
+    }
+}
+
+/// Get a guest copy of a static string, e.g. for `z_stream.msg`.
+fn static_string(env: &mut Environment, string: &str) -> ConstPtr<u8> {
+    if let Some(&ptr) = State::get(env).strings.get(string) {
+        return ptr;
+    }
+    let ptr = env.mem.alloc_and_write_cstr(string.as_bytes()).cast_const();
+    State::get(env).strings.insert(string.to_string(), ptr);
+    ptr
+}
+
+const ZLIB_VERSION: &str = "1.2.3";
+
+const Z_OK: i32 = 0;
+const Z_STREAM_END: i32 = 1;
+const Z_ERRNO: i32 = -1;
+const Z_STREAM_ERROR: i32 = -2;
+const Z_DATA_ERROR: i32 = -3;
+const Z_BUF_ERROR: i32 = -5;
+const Z_VERSION_ERROR: i32 = -6;
+
+const Z_NO_FLUSH: i32 = 0;
+const Z_PARTIAL_FLUSH: i32 = 1;
+const Z_SYNC_FLUSH: i32 = 2;
+const Z_FULL_FLUSH: i32 = 3;
+const Z_FINISH: i32 = 4;
+
+const Z_DEFAULT_COMPRESSION: i32 = -1;
+const Z_DEFLATED: i32 = 8;
+
+#[allow(non_camel_case_types)]
+type uLong = u32;
+#[allow(non_camel_case_types)]
+type uInt = u32;
+
+#[allow(non_camel_case_types)]
+#[repr(C, packed)]
+struct z_stream {
+    next_in: ConstPtr<u8>,
+    avail_in: uInt,
+    total_in: uLong,
+    next_out: MutPtr<u8>,
+    avail_out: uInt,
+    total_out: uLong,
+    msg: ConstPtr<u8>,
+    state: MutVoidPtr,
+    zalloc: GuestFunction,
+    zfree: GuestFunction,
+    opaque: MutVoidPtr,
+    data_type: i32,
+    adler: uLong,
+    reserved: uLong,
+}
+unsafe impl SafeRead for z_stream {}
+
+// === Checksums ===
+
+const fn make_crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+static CRC32_TABLE: [u32; 256] = make_crc32_table();
+
+fn update_crc32(crc: u32, bytes: &[u8]) -> u32 {
+    let mut c = !crc;
+    for &byte in bytes {
+        c = CRC32_TABLE[((c ^ byte as u32) & 0xff) as usize] ^ (c >> 8);
+    }
+    !c
+}
+
+fn update_adler32(adler: u32, bytes: &[u8]) -> u32 {
+    const BASE: u32 = 65521;
+    // This is the largest number of bytes that can be summed before b could
+    // overflow.
+    const NMAX: usize = 5552;
+    let mut a = adler & 0xffff;
+    let mut b = adler >> 16;
+    for chunk in bytes.chunks(NMAX) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= BASE;
+        b %= BASE;
+    }
+    (b << 16) | a
+}
+
+fn crc32(env: &mut Environment, crc: uLong, buf: ConstPtr<u8>, len: uInt) -> uLong {
+    if buf.is_null() {
+        return 0;
+    }
+    update_crc32(crc, env.mem.bytes_at(buf, len))
+}
+
+fn adler32(env: &mut Environment, adler: uLong, buf: ConstPtr<u8>, len: uInt) -> uLong {
+    if buf.is_null() {
+        return 1;
+    }
+    update_adler32(adler, env.mem.bytes_at(buf, len))
+}
+
+// === Streams ===
+
+/// The header or trailer around the deflate data.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Wrapper {
+    Raw,
+    Zlib,
+    Gzip,
+    /// Zlib or gzip, detected from the first byte (inflate only).
+    Auto,
+}
+impl Wrapper {
+    /// Interpret the `windowBits` parameter. The window size itself is ignored
+    /// since flate2 always uses the maximum.
+    fn from_window_bits(window_bits: i32) -> Option<Wrapper> {
+        match window_bits {
+            -15..=-8 => Some(Wrapper::Raw),
+            8..=15 => Some(Wrapper::Zlib),
+            24..=31 => Some(Wrapper::Gzip),
+            40..=47 => Some(Wrapper::Auto),
+            _ => None,
+        }
+    }
+}
+
+/// Header for a gzip file with no optional fields.
+const GZIP_HEADER: [u8; 10] = [
+    0x1f, 0x8b, // magic number
+    8,    // compression method: deflate
+    0,    // flags
+    0, 0, 0, 0, // modification time: none
+    0, // extra flags
+    3, // OS: Unix
+];
+
+/// Check a gzip header and get its length, or [None] if more bytes are needed.
+fn gzip_header_len(bytes: &[u8]) -> Result<Option<usize>, ()> {
+    const FHCRC: u8 = 1 << 1;
+    const FEXTRA: u8 = 1 << 2;
+    const FNAME: u8 = 1 << 3;
+    const FCOMMENT: u8 = 1 << 4;
+
+    for (&byte, &expected) in bytes.iter().zip(&GZIP_HEADER[..3]) {
+        if byte != expected {
+            return Err(());
+        }
+    }
+    if bytes.len() < 10 {
+        return Ok(None);
+    }
+    let flags = bytes[3];
+    let mut len = 10;
+    if flags & FEXTRA != 0 {
+        let Some(xlen) = bytes.get(len..len + 2) else {
+            return Ok(None);
+        };
+        len += 2 + u16::from_le_bytes(xlen.try_into().unwrap()) as usize;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let Some(terminator) = bytes
+                .get(len..)
+                .and_then(|b| b.iter().position(|&c| c == 0))
+            else {
+                return Ok(None);
+            };
+            len += terminator + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        len += 2;
+    }
+    Ok(if bytes.len() >= len { Some(len) } else { None })
+}
+
+enum StreamHostObject {
+    Inflate(Inflater),
+    Deflate(Deflater),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum InflatePhase {
+    Header,
+    Body,
+    Trailer,
+    Done,
+}
+
+struct Inflater {
+    window_bits: i32,
+    wrapper: Wrapper,
+    /// Created once the wrapper is known.
+    decompress: Option<Decompress>,
+    phase: InflatePhase,
+    /// gzip header or trailer bytes collected so far.
+    buffer: Vec<u8>,
+    /// Adler-32 (zlib) or CRC-32 (gzip) of the output so far.
+    check: u32,
+    output_size: u32,
+}
+impl Inflater {
+    fn new(window_bits: i32) -> Option<Inflater> {
+        let wrapper = Wrapper::from_window_bits(window_bits)?;
+        let (decompress, phase) = match wrapper {
+            Wrapper::Raw => (Some(Decompress::new(false)), InflatePhase::Body),
+            Wrapper::Zlib => (Some(Decompress::new(true)), InflatePhase::Body),
+            Wrapper::Gzip | Wrapper::Auto => (None, InflatePhase::Header),
+        };
+        Some(Inflater {
+            window_bits,
+            wrapper,
+            decompress,
+            phase,
+            buffer: Vec::new(),
+            check: if wrapper == Wrapper::Zlib { 1 } else { 0 },
+            output_size: 0,
+        })
+    }
+
+    /// Decompress as much as possible. Returns the number of bytes consumed
+    /// and produced, and the zlib return code or an error message.
+    fn inflate(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        flush: FlushDecompress,
+    ) -> (usize, usize, Result<i32, &'static str>) {
+        let mut in_pos = 0;
+        let mut out_pos = 0;
+        let result = loop {
+            match self.phase {
+                InflatePhase::Done => break Ok(Z_STREAM_END),
+                InflatePhase::Header => {
+                    if self.wrapper == Wrapper::Auto {
+                        let Some(&first) = input.get(in_pos) else {
+                            break Ok(Z_OK);
+                        };
+                        if first != GZIP_HEADER[0] {
+                            self.wrapper = Wrapper::Zlib;
+                            self.decompress = Some(Decompress::new(true));
+                            self.check = 1;
+                            self.phase = InflatePhase::Body;
+                            continue;
+                        }
+                        self.wrapper = Wrapper::Gzip;
+                    }
+                    let already_buffered = self.buffer.len();
+                    self.buffer.extend_from_slice(&input[in_pos..]);
+                    match gzip_header_len(&self.buffer) {
+                        Err(()) => break Err("incorrect header check"),
+                        Ok(None) => {
+                            in_pos = input.len();
+                            break Ok(Z_OK);
+                        }
+                        Ok(Some(len)) => {
+                            in_pos += len - already_buffered;
+                            self.buffer.clear();
+                            self.decompress = Some(Decompress::new(false));
+                            self.phase = InflatePhase::Body;
+                        }
+                    }
+                }
+                InflatePhase::Body => {
+                    let decompress = self.decompress.as_mut().unwrap();
+                    let in_before = decompress.total_in();
+                    let out_before = decompress.total_out();
+                    let status =
+                        decompress.decompress(&input[in_pos..], &mut output[out_pos..], flush);
+                    let consumed = (decompress.total_in() - in_before) as usize;
+                    let produced = (decompress.total_out() - out_before) as usize;
+                    let produced_bytes = &output[out_pos..out_pos + produced];
+                    match self.wrapper {
+                        Wrapper::Zlib => self.check = update_adler32(self.check, produced_bytes),
+                        Wrapper::Gzip => self.check = update_crc32(self.check, produced_bytes),
+                        _ => (),
+                    }
+                    self.output_size = self.output_size.wrapping_add(produced as u32);
+                    in_pos += consumed;
+                    out_pos += produced;
+                    match status {
+                        Err(_) => break Err("invalid compressed data"),
+                        Ok(Status::StreamEnd) => {
+                            self.phase = if self.wrapper == Wrapper::Gzip {
+                                InflatePhase::Trailer
+                            } else {
+                                InflatePhase::Done
+                            };
+                        }
+                        Ok(Status::Ok | Status::BufError) => break Ok(Z_OK),
+                    }
+                }
+                InflatePhase::Trailer => {
+                    let wanted = 8 - self.buffer.len();
+                    let available = (input.len() - in_pos).min(wanted);
+                    self.buffer
+                        .extend_from_slice(&input[in_pos..in_pos + available]);
+                    in_pos += available;
+                    if self.buffer.len() < 8 {
+                        break Ok(Z_OK);
+                    }
+                    let crc = u32::from_le_bytes(self.buffer[0..4].try_into().unwrap());
+                    let size = u32::from_le_bytes(self.buffer[4..8].try_into().unwrap());
+                    if crc != self.check || size != self.output_size {
+                        break Err("incorrect data check");
+                    }
+                    self.buffer.clear();
+                    self.phase = InflatePhase::Done;
+                }
+            }
+        };
+
+        // As documented, Z_BUF_ERROR is returned if no progress was possible,
+        // or if Z_FINISH was used and the stream couldn't be finished.
+        let result = match result {
+            Ok(Z_OK)
+                if (in_pos == 0 && out_pos == 0) || matches!(flush, FlushDecompress::Finish) =>
+            {
+                Ok(Z_BUF_ERROR)
+            }
+            other => other,
+        };
+        (in_pos, out_pos, result)
+    }
+}
+
+struct Deflater {
+    level: Compression,
+    wrapper: Wrapper,
+    compress: Compress,
+    /// gzip header and trailer bytes that haven't been output yet.
+    pending: VecDeque<u8>,
+    /// Adler-32 (zlib) or CRC-32 (gzip) of the input so far.
+    check: u32,
+    input_size: u32,
+    /// The compressor has reached the end of the stream.
+    finished: bool,
+}
+impl Deflater {
+    fn new(level: Compression, wrapper: Wrapper) -> Deflater {
+        let compress = Compress::new(level, wrapper == Wrapper::Zlib);
+        let pending = if wrapper == Wrapper::Gzip {
+            VecDeque::from(GZIP_HEADER.to_vec())
+        } else {
+            VecDeque::new()
+        };
+        Deflater {
+            level,
+            wrapper,
+            compress,
+            pending,
+            check: if wrapper == Wrapper::Zlib { 1 } else { 0 },
+            input_size: 0,
+            finished: false,
+        }
+    }
+
+    fn drain_pending(&mut self, output: &mut [u8]) -> usize {
+        let count = self.pending.len().min(output.len());
+        for (dest, src) in output.iter_mut().zip(self.pending.drain(..count)) {
+            *dest = src;
+        }
+        count
+    }
+
+    /// Compress as much as possible. Returns the number of bytes consumed and
+    /// produced, and the zlib return code.
+    fn deflate(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        flush: FlushCompress,
+    ) -> (usize, usize, i32) {
+        let mut out_pos = self.drain_pending(output);
+        if self.finished {
+            let rc = if self.pending.is_empty() {
+                Z_STREAM_END
+            } else {
+                Z_OK
+            };
+            return (0, out_pos, rc);
+        }
+        if !self.pending.is_empty() {
+            return (0, out_pos, if out_pos == 0 { Z_BUF_ERROR } else { Z_OK });
+        }
+
+        let in_before = self.compress.total_in();
+        let out_before = self.compress.total_out();
+        let status = self.compress.compress(input, &mut output[out_pos..], flush);
+        let consumed = (self.compress.total_in() - in_before) as usize;
+        out_pos += (self.compress.total_out() - out_before) as usize;
+        match self.wrapper {
+            Wrapper::Zlib => self.check = update_adler32(self.check, &input[..consumed]),
+            Wrapper::Gzip => self.check = update_crc32(self.check, &input[..consumed]),
+            _ => (),
+        }
+        self.input_size = self.input_size.wrapping_add(consumed as u32);
+
+        let rc = match status {
+            Err(_) => Z_STREAM_ERROR,
+            Ok(Status::StreamEnd) => {
+                self.finished = true;
+                if self.wrapper == Wrapper::Gzip {
+                    self.pending.extend(self.check.to_le_bytes());
+                    self.pending.extend(self.input_size.to_le_bytes());
+                    out_pos += self.drain_pending(&mut output[out_pos..]);
+                }
+                if self.pending.is_empty() {
+                    Z_STREAM_END
+                } else {
+                    Z_OK
+                }
+            }
+            Ok(Status::Ok | Status::BufError) => {
+                if consumed == 0 && out_pos == 0 {
+                    Z_BUF_ERROR
+                } else {
+                    Z_OK
+                }
+            }
+        };
+        (consumed, out_pos, rc)
+    }
+}
+
+fn compression_level(level: i32) -> Option<Compression> {
+    match level {
+        Z_DEFAULT_COMPRESSION => Some(Compression::default()),
+        0..=9 => Some(Compression::new(level as u32)),
+        _ => None,
+    }
+}
+
+/// Check the version and structure size passed to the `*Init*_` functions,
+/// which the zlib headers pass automatically.
+fn check_version(env: &mut Environment, version: ConstPtr<u8>, stream_size: i32) -> bool {
+    !version.is_null()
+        && env.mem.read(version) == ZLIB_VERSION.as_bytes()[0]
+        && stream_size as u32 == guest_size_of::<z_stream>()
+}
+
+/// Set up a new stream. The fields that the app is meant to set up itself are
+/// left alone.
+fn init_stream(env: &mut Environment, strm: MutPtr<z_stream>, host_object: StreamHostObject) {
+    let check = match &host_object {
+        StreamHostObject::Inflate(inflater) => inflater.check,
+        StreamHostObject::Deflate(deflater) => deflater.check,
+    };
+    let state = env.mem.alloc(1);
+    State::get(env).streams.insert(state, host_object);
+    let mut stream = env.mem.read(strm);
+    stream.total_in = 0;
+    stream.total_out = 0;
+    stream.msg = Ptr::null();
+    stream.state = state;
+    stream.adler = check;
+    env.mem.write(strm, stream);
+}
+
+/// Reset a stream to its initial state, keeping its parameters.
+fn reset_stream(env: &mut Environment, strm: MutPtr<z_stream>) -> i32 {
+    if strm.is_null() {
+        return Z_STREAM_ERROR;
+    }
+    let mut stream = env.mem.read(strm);
+    let check = match State::get(env).streams.get_mut(&stream.state) {
+        Some(StreamHostObject::Inflate(inflater)) => {
+            *inflater = Inflater::new(inflater.window_bits).unwrap();
+            inflater.check
+        }
+        Some(StreamHostObject::Deflate(deflater)) => {
+            *deflater = Deflater::new(deflater.level, deflater.wrapper);
+            deflater.check
+        }
+        None => return Z_STREAM_ERROR,
+    };
+    stream.total_in = 0;
+    stream.total_out = 0;
+    stream.msg = Ptr::null();
+    stream.adler = check;
+    env.mem.write(strm, stream);
+    Z_OK
+}
+
+fn end_stream(env: &mut Environment, strm: MutPtr<z_stream>) -> i32 {
+    if strm.is_null() {
+        return Z_STREAM_ERROR;
+    }
+    let mut stream = env.mem.read(strm);
+    if State::get(env).streams.remove(&stream.state).is_none() {
+        return Z_STREAM_ERROR;
+    }
+    env.mem.free(stream.state);
+    stream.state = Ptr::null();
+    env.mem.write(strm, stream);
+    Z_OK
+}
+
+/// Run the inflater or deflater, reading and writing the input and output
+/// buffers and other fields in the guest's `z_stream`.
+fn process_stream(env: &mut Environment, strm: MutPtr<z_stream>, flush: i32) -> i32 {
+    if strm.is_null() {
+        return Z_STREAM_ERROR;
+    }
+    let mut stream = env.mem.read(strm);
+    let state = stream.state;
+    if !State::get(env).streams.contains_key(&state) {
+        return Z_STREAM_ERROR;
+    }
+
+    let input = if stream.avail_in == 0 {
+        Vec::new()
+    } else {
+        env.mem.bytes_at(stream.next_in, stream.avail_in).to_vec()
+    };
+    let mut output = vec![0u8; stream.avail_out as usize];
+
+    let host_object = State::get(env).streams.get_mut(&state).unwrap();
+    let (consumed, produced, result, check) = match host_object {
+        StreamHostObject::Inflate(inflater) => {
+            let flush = match flush {
+                Z_NO_FLUSH => FlushDecompress::None,
+                Z_FINISH => FlushDecompress::Finish,
+                _ => FlushDecompress::Sync,
+            };
+            let (consumed, produced, result) = inflater.inflate(&input, &mut output, flush);
+            (consumed, produced, result, inflater.check)
+        }
+        StreamHostObject::Deflate(deflater) => {
+            let flush = match flush {
+                Z_NO_FLUSH => FlushCompress::None,
+                Z_PARTIAL_FLUSH => FlushCompress::Partial,
+                Z_SYNC_FLUSH => FlushCompress::Sync,
+                Z_FULL_FLUSH => FlushCompress::Full,
+                Z_FINISH => FlushCompress::Finish,
+                _ => return Z_STREAM_ERROR,
+            };
+            let (consumed, produced, rc) = deflater.deflate(&input, &mut output, flush);
+            (consumed, produced, Ok(rc), deflater.check)
+        }
+    };
+
+    if produced > 0 {
+        env.mem
+            .bytes_at_mut(stream.next_out, produced as u32)
+            .copy_from_slice(&output[..produced]);
+    }
+    stream.next_in += consumed as u32;
+    stream.avail_in -= consumed as u32;
+    stream.total_in = stream.total_in.wrapping_add(consumed as u32);
+    stream.next_out += produced as u32;
+    stream.avail_out -= produced as u32;
+    stream.total_out = stream.total_out.wrapping_add(produced as u32);
+    stream.adler = check;
+    let rc = match result {
+        Ok(rc) => rc,
+        Err(message) => {
+            stream.msg = static_string(env, message);
+            Z_DATA_ERROR
+        }
+    };
+    env.mem.write(strm, stream);
+    log_dbg!(
+        "zlib stream {:?}: consumed {}, produced {}, flush {} => {}",
+        strm,
+        consumed,
+        produced,
+        flush,
+        rc
+    );
+    rc
+}
+
+fn deflateInit_(
+    env: &mut Environment,
+    strm: MutPtr<z_stream>,
+    level: i32,
+    version: ConstPtr<u8>,
+    stream_size: i32,
+) -> i32 {
+    deflateInit2_(
+        env,
+        strm,
+        level,
+        Z_DEFLATED,
+        /* window_bits: */ 15,
+        /* mem_level: */ 8,
+        /* strategy: */ 0,
+        version,
+        stream_size,
+    )
+}
+
+fn deflateInit2_(
+    env: &mut Environment,
+    strm: MutPtr<z_stream>,
+    level: i32,
+    method: i32,
+    window_bits: i32,
+    _mem_level: i32,
+    _strategy: i32,
+    version: ConstPtr<u8>,
+    stream_size: i32,
+) -> i32 {
+    if !check_version(env, version, stream_size) {
+        return Z_VERSION_ERROR;
+    }
+    let Some(level) = compression_level(level) else {
+        return Z_STREAM_ERROR;
+    };
+    let wrapper = match Wrapper::from_window_bits(window_bits) {
+        Some(Wrapper::Auto) | None => return Z_STREAM_ERROR,
+        Some(wrapper) => wrapper,
+    };
+    if strm.is_null() || method != Z_DEFLATED {
+        return Z_STREAM_ERROR;
+    }
+    let deflater = Deflater::new(level, wrapper);
+    init_stream(env, strm, StreamHostObject::Deflate(deflater));
+    log_dbg!(
+        "deflateInit2_({:?}, {:?}, {}) => Z_OK",
+        strm,
+        level,
+        window_bits
+    );
+    Z_OK
+}
+
+fn deflate(env: &mut Environment, strm: MutPtr<z_stream>, flush: i32) -> i32 {
+    process_stream(env, strm, flush)
+}
+
+fn deflateReset(env: &mut Environment, strm: MutPtr<z_stream>) -> i32 {
+    reset_stream(env, strm)
+}
+
+fn deflateEnd(env: &mut Environment, strm: MutPtr<z_stream>) -> i32 {
+    end_stream(env, strm)
+}
+
+fn deflateBound(_env: &mut Environment, _strm: MutPtr<z_stream>, source_len: uLong) -> uLong {
+    // Conservative upper bound from zlib 1.2.3, plus the largest wrapper
+    // (gzip: 10-byte header, 8-byte trailer).
+    source_len + ((source_len + 7) >> 3) + ((source_len + 63) >> 6) + 11 + 18
+}
+
+fn inflateInit_(
+    env: &mut Environment,
+    strm: MutPtr<z_stream>,
+    version: ConstPtr<u8>,
+    stream_size: i32,
+) -> i32 {
+    inflateInit2_(env, strm, /* window_bits: */ 15, version, stream_size)
+}
+
+fn inflateInit2_(
+    env: &mut Environment,
+    strm: MutPtr<z_stream>,
+    window_bits: i32,
+    version: ConstPtr<u8>,
+    stream_size: i32,
+) -> i32 {
+    if !check_version(env, version, stream_size) {
+        return Z_VERSION_ERROR;
+    }
+    if strm.is_null() {
+        return Z_STREAM_ERROR;
+    }
+    let Some(inflater) = Inflater::new(window_bits) else {
+        return Z_STREAM_ERROR;
+    };
+    init_stream(env, strm, StreamHostObject::Inflate(inflater));
+    log_dbg!("inflateInit2_({:?}, {}) => Z_OK", strm, window_bits);
+    Z_OK
+}
+
+fn inflate(env: &mut Environment, strm: MutPtr<z_stream>, flush: i32) -> i32 {
+    process_stream(env, strm, flush)
+}
+
+fn inflateReset(env: &mut Environment, strm: MutPtr<z_stream>) -> i32 {
+    reset_stream(env, strm)
+}
+
+fn inflateEnd(env: &mut Environment, strm: MutPtr<z_stream>) -> i32 {
+    end_stream(env, strm)
+}
+
+// === Utility functions ===
+
+fn compressBound(_env: &mut Environment, source_len: uLong) -> uLong {
+    // Same formula as zlib 1.2.3.
+    source_len + (source_len >> 12) + (source_len >> 14) + 11
+}
+
+fn compress(
+    env: &mut Environment,
+    dest: MutPtr<u8>,
+    dest_len: MutPtr<uLong>,
+    source: ConstPtr<u8>,
+    source_len: uLong,
+) -> i32 {
+    compress2(
+        env,
+        dest,
+        dest_len,
+        source,
+        source_len,
+        Z_DEFAULT_COMPRESSION,
+    )
+}
+
+fn compress2(
+    env: &mut Environment,
+    dest: MutPtr<u8>,
+    dest_len: MutPtr<uLong>,
+    source: ConstPtr<u8>,
+    source_len: uLong,
+    level: i32,
+) -> i32 {
+    let Some(level) = compression_level(level) else {
+        return Z_STREAM_ERROR;
+    };
+    let input = env.mem.bytes_at(source, source_len).to_vec();
+    let mut output = vec![0u8; env.mem.read(dest_len) as usize];
+    let mut compress = Compress::new(level, /* zlib_header: */ true);
+    let status = compress.compress(&input, &mut output, FlushCompress::Finish);
+    let produced = compress.total_out() as u32;
+    env.mem
+        .bytes_at_mut(dest, produced)
+        .copy_from_slice(&output[..produced as usize]);
+    env.mem.write(dest_len, produced);
+    match status {
+        Ok(Status::StreamEnd) => Z_OK,
+        _ => Z_BUF_ERROR,
+    }
+}
+
+fn uncompress(
+    env: &mut Environment,
+    dest: MutPtr<u8>,
+    dest_len: MutPtr<uLong>,
+    source: ConstPtr<u8>,
+    source_len: uLong,
+) -> i32 {
+    let input = env.mem.bytes_at(source, source_len).to_vec();
+    let mut output = vec![0u8; env.mem.read(dest_len) as usize];
+    let mut decompress = Decompress::new(/* zlib_header: */ true);
+    let status = decompress.decompress(&input, &mut output, FlushDecompress::Finish);
+    let produced = decompress.total_out() as u32;
+    env.mem
+        .bytes_at_mut(dest, produced)
+        .copy_from_slice(&output[..produced as usize]);
+    env.mem.write(dest_len, produced);
+    match status {
+        Ok(Status::StreamEnd) => Z_OK,
+        Err(_) => Z_DATA_ERROR,
+        // Out of output space.
+        Ok(_) if produced as usize == output.len() => Z_BUF_ERROR,
+        // Out of input, i.e. the data is truncated.
+        Ok(_) => Z_DATA_ERROR,
+    }
+}
+
+fn zlibVersion(env: &mut Environment) -> ConstPtr<u8> {
+    static_string(env, ZLIB_VERSION)
+}
+
+// === gzip files ===
+
+/// Opaque type in guest memory standing in for a [GzFileHostObject].
+pub struct GuestGzFile {
+    _filler: u8,
+}
+impl SafeWrite for GuestGzFile {}
+
+enum GzFileHostObject {
+    /// Files being read are decompressed entirely when opened, which makes
+    /// seeking easy.
+    Read {
+        data: Vec<u8>,
+        position: usize,
+        eof: bool,
+    },
+    Write {
+        encoder: GzEncoder<GuestFile>,
+        position: usize,
+    },
+}
+
+fn gzopen(env: &mut Environment, path: ConstPtr<u8>, mode: ConstPtr<u8>) -> MutPtr<GuestGzFile> {
+    let path_string = env.mem.cstr_at_utf8(path).unwrap().to_string();
+    let mode = env.mem.cstr_at_utf8(mode).unwrap().to_string();
+    let path = GuestPath::new(&path_string);
+
+    let level = mode
+        .chars()
+        .find_map(|c| c.to_digit(10))
+        .map_or(Compression::default(), Compression::new);
+
+    let host_object = if mode.contains('r') {
+        let Ok(file_data) = env.fs.read(path) else {
+            log!("Warning: gzopen() failed to open {:?}", path_string);
+            return Ptr::null();
+        };
+        // Like with zlib, files that aren't compressed are read as-is.
+        let data = if file_data.starts_with(&GZIP_HEADER[..2]) {
+            let mut data = Vec::new();
+            // Files can contain several gzip members one after another.
+            let mut decoder = flate2::read::MultiGzDecoder::new(&file_data[..]);
+            if let Err(e) = decoder.read_to_end(&mut data) {
+                log!(
+                    "Warning: error decompressing {:?} after {} bytes: {}",
+                    path_string,
+                    data.len(),
+                    e
+                );
+            }
+            data
+        } else {
+            file_data
+        };
+        GzFileHostObject::Read {
+            data,
+            position: 0,
+            eof: false,
+        }
+    } else if mode.contains('w') || mode.contains('a') {
+        let mut options = GuestOpenOptions::new();
+        if mode.contains('a') {
+            // Appending adds a new gzip member to the end of the file.
+            options.append().create();
+        } else {
+            options.write().create().truncate();
+        }
+        let Ok(file) = env.fs.open_with_options(path, options) else {
+            log!("Warning: gzopen() failed to open {:?}", path_string);
+            return Ptr::null();
+        };
+        GzFileHostObject::Write {
+            encoder: GzEncoder::new(file, level),
+            position: 0,
+        }
+    } else {
+        return Ptr::null();
+    };
+
+    let gz_file = env.mem.alloc_and_write(GuestGzFile { _filler: 0 });
+    State::get(env).gz_files.insert(gz_file, host_object);
+    log_dbg!("gzopen({:?}, {:?}) => {:?}", path_string, mode, gz_file);
+    gz_file
+}
+
+fn gzread(env: &mut Environment, file: MutPtr<GuestGzFile>, buf: MutVoidPtr, len: u32) -> i32 {
+    let Some(GzFileHostObject::Read {
+        data,
+        position,
+        eof,
+    }) = State::get(env).gz_files.get_mut(&file)
+    else {
+        return -1;
+    };
+    let count = (data.len() - *position).min(len as usize);
+    let bytes = data[*position..*position + count].to_vec();
+    *position += count;
+    if count < len as usize {
+        *eof = true;
+    }
+    env.mem
+        .bytes_at_mut(buf.cast(), count as u32)
+        .copy_from_slice(&bytes);
+    count as i32
+}
+
+fn gzgetc(env: &mut Environment, file: MutPtr<GuestGzFile>) -> i32 {
+    let Some(GzFileHostObject::Read {
+        data,
+        position,
+        eof,
+    }) = State::get(env).gz_files.get_mut(&file)
+    else {
+        return -1;
+    };
+    if let Some(&byte) = data.get(*position) {
+        *position += 1;
+        byte.into()
+    } else {
+        *eof = true;
+        -1
+    }
+}
+
+fn gzgets(
+    env: &mut Environment,
+    file: MutPtr<GuestGzFile>,
+    buf: MutPtr<u8>,
+    len: i32,
+) -> MutPtr<u8> {
+    let Some(GzFileHostObject::Read {
+        data,
+        position,
+        eof,
+    }) = State::get(env).gz_files.get_mut(&file)
+    else {
+        return Ptr::null();
+    };
+    if buf.is_null() || len < 1 {
+        return Ptr::null();
+    }
+    // Read up to and including a newline, leaving space for the terminator.
+    let remaining = &data[*position..];
+    let max = (len - 1) as usize;
+    let count = remaining
+        .iter()
+        .take(max)
+        .position(|&c| c == b'\n')
+        .map_or(remaining.len().min(max), |i| i + 1);
+    if count == 0 && max > 0 {
+        *eof = true;
+        return Ptr::null();
+    }
+    let line = remaining[..count].to_vec();
+    *position += count;
+    env.mem
+        .bytes_at_mut(buf, count as u32)
+        .copy_from_slice(&line);
+    env.mem.write(buf + count as u32, b'\0');
+    buf
+}
+
+fn gzwrite(env: &mut Environment, file: MutPtr<GuestGzFile>, buf: ConstVoidPtr, len: u32) -> i32 {
+    let bytes = env.mem.bytes_at(buf.cast(), len).to_vec();
+    let Some(GzFileHostObject::Write { encoder, position }) =
+        State::get(env).gz_files.get_mut(&file)
+    else {
+        return 0;
+    };
+    if encoder.write_all(&bytes).is_err() {
+        return 0;
+    }
+    *position += bytes.len();
+    len as i32
+}
+
+fn gzputs(env: &mut Environment, file: MutPtr<GuestGzFile>, s: ConstPtr<u8>) -> i32 {
+    let len = env.mem.cstr_at(s).len() as u32;
+    let written = gzwrite(env, file, s.cast(), len);
+    if written == 0 && len != 0 {
+        -1
+    } else {
+        written
+    }
+}
+
+fn gzeof(env: &mut Environment, file: MutPtr<GuestGzFile>) -> i32 {
+    match State::get(env).gz_files.get(&file) {
+        Some(&GzFileHostObject::Read { eof, .. }) => eof.into(),
+        _ => 0,
+    }
+}
+
+fn gzseek(env: &mut Environment, file: MutPtr<GuestGzFile>, offset: i32, whence: i32) -> i32 {
+    let Some(host_object) = State::get(env).gz_files.get_mut(&file) else {
+        return -1;
+    };
+    let current = match host_object {
+        GzFileHostObject::Read { position, .. } | GzFileHostObject::Write { position, .. } => {
+            *position
+        }
+    };
+    // SEEK_END isn't supported by zlib either.
+    let target = match whence {
+        SEEK_SET => offset as i64,
+        SEEK_CUR => current as i64 + offset as i64,
+        _ => return -1,
+    };
+    if target < 0 {
+        return -1;
+    }
+    let target = target as usize;
+    match host_object {
+        GzFileHostObject::Read {
+            position,
+            eof,
+            data,
+        } => {
+            *position = target.min(data.len());
+            *eof = false;
+        }
+        GzFileHostObject::Write { encoder, position } => {
+            // Only forward seeks are possible, and they write zeroes.
+            if target < *position {
+                return -1;
+            }
+            let zeroes = vec![0u8; target - *position];
+            if encoder.write_all(&zeroes).is_err() {
+                return -1;
+            }
+            *position = target;
+        }
+    }
+    target as i32
+}
+
+fn gzrewind(env: &mut Environment, file: MutPtr<GuestGzFile>) -> i32 {
+    match State::get(env).gz_files.get_mut(&file) {
+        Some(GzFileHostObject::Read { position, eof, .. }) => {
+            *position = 0;
+            *eof = false;
+            0
+        }
+        _ => -1,
+    }
+}
+
+fn gztell(env: &mut Environment, file: MutPtr<GuestGzFile>) -> i32 {
+    match State::get(env).gz_files.get(&file) {
+        Some(
+            GzFileHostObject::Read { position, .. } | GzFileHostObject::Write { position, .. },
+        ) => *position as i32,
+        None => -1,
+    }
+}
+
+fn gzclose(env: &mut Environment, file: MutPtr<GuestGzFile>) -> i32 {
+    let Some(host_object) = State::get(env).gz_files.remove(&file) else {
+        return Z_STREAM_ERROR;
+    };
+    env.mem.free(file.cast());
+    log_dbg!("gzclose({:?})", file);
+    match host_object {
+        GzFileHostObject::Read { .. } => Z_OK,
+        GzFileHostObject::Write { encoder, .. } => match encoder.finish() {
+            Ok(_) => Z_OK,
+            Err(_) => Z_ERRNO,
+        },
+    }
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(crc32(_, _, _)),
+    export_c_func!(adler32(_, _, _)),
+    export_c_func!(deflateInit_(_, _, _, _)),
+    export_c_func!(deflateInit2_(_, _, _, _, _, _, _, _)),
+    export_c_func!(deflate(_, _)),
+    export_c_func!(deflateReset(_)),
+    export_c_func!(deflateEnd(_)),
+    export_c_func!(deflateBound(_, _)),
+    export_c_func!(inflateInit_(_, _, _)),
+    export_c_func!(inflateInit2_(_, _, _, _)),
+    export_c_func!(inflate(_, _)),
+    export_c_func!(inflateReset(_)),
+    export_c_func!(inflateEnd(_)),
+    export_c_func!(compressBound(_)),
+    export_c_func!(compress(_, _, _, _)),
+    export_c_func!(compress2(_, _, _, _, _)),
+    export_c_func!(uncompress(_, _, _, _)),
+    export_c_func!(zlibVersion()),
+    export_c_func!(gzopen(_, _)),
+    export_c_func!(gzread(_, _, _)),
+    export_c_func!(gzgetc(_)),
+    export_c_func!(gzgets(_, _, _)),
+    export_c_func!(gzwrite(_, _, _)),
+    export_c_func!(gzputs(_, _)),
+    export_c_func!(gzeof(_)),
+    export_c_func!(gzseek(_, _, _)),
+    export_c_func!(gzrewind(_)),
+    export_c_func!(gztell(_)),
+    export_c_func!(gzclose(_)),
+];
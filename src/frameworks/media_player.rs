@@ -5,17 +5,28 @@
  */
 //! The Media Player framework.
 
+pub mod media_picker_controller;
 pub mod media_query;
 pub mod movie_player;
 pub mod music_player;
+pub mod volume_view;
 
 #[derive(Default)]
 pub struct State {
     movie_player: movie_player::State,
+    music_player: music_player::State,
+    volume_view: volume_view::State,
 }
 
 /// For use by `NSRunLoop`: check media players' status, send notifications if
-/// necessary.
-pub fn handle_players(env: &mut crate::Environment) {
-    movie_player::handle_players(env);
+/// necessary. Returns the time the next check is due, if there is one.
+pub fn handle_players(env: &mut crate::Environment) -> Option<std::time::Instant> {
+    movie_player::handle_players(env)
+}
+
+/// For use by [crate::frameworks::audio_toolbox::audio_session]: the simulated
+/// hardware output volume has changed, so update volume views and tell the app.
+pub fn output_volume_did_change(env: &mut crate::Environment, volume: f32) {
+    volume_view::system_volume_did_change(env, volume);
+    music_player::post_volume_notifications(env);
 }
@@ -0,0 +1,119 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `MPMediaPickerController`.
+//!
+//! touchHLE has no iPod library to pick from, so the picker behaves as if the
+//! user cancelled as soon as it has been presented. Apps then carry on with
+//! their own music.
+
+use crate::frameworks::foundation::NSUInteger;
+use crate::frameworks::uikit::ui_view_controller::UIViewControllerHostObject;
+use crate::objc::{
+    id, impl_HostObject_with_superclass, msg, msg_super, nil, objc_classes, release, retain,
+    ClassExports, NSZonePtr,
+};
+use crate::Environment;
+
+type MPMediaType = NSUInteger;
+const MPMediaTypeAny: MPMediaType = !0;
+
+#[derive(Default)]
+struct MPMediaPickerControllerHostObject {
+    superclass: UIViewControllerHostObject,
+    media_types: MPMediaType,
+    allows_picking_multiple_items: bool,
+    /// `NSString*`
+    prompt: id,
+    /// Weak reference.
+    delegate: id,
+}
+impl_HostObject_with_superclass!(MPMediaPickerControllerHostObject);
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation MPMediaPickerController: UIViewController
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(MPMediaPickerControllerHostObject {
+        media_types: MPMediaTypeAny,
+        ..Default::default()
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithMediaTypes:(MPMediaType)media_types {
+    let this: id = msg![env; this init];
+    env.objc.borrow_mut::<MPMediaPickerControllerHostObject>(this).media_types = media_types;
+    this
+}
+
+- (())dealloc {
+    let prompt = env.objc.borrow::<MPMediaPickerControllerHostObject>(this).prompt;
+    release(env, prompt);
+    msg_super![env; this dealloc]
+}
+
+- (MPMediaType)mediaTypes {
+    env.objc.borrow::<MPMediaPickerControllerHostObject>(this).media_types
+}
+
+- (id)delegate {
+    env.objc.borrow::<MPMediaPickerControllerHostObject>(this).delegate
+}
+- (())setDelegate:(id)delegate {
+    env.objc.borrow_mut::<MPMediaPickerControllerHostObject>(this).delegate = delegate;
+}
+
+- (bool)allowsPickingMultipleItems {
+    env.objc.borrow::<MPMediaPickerControllerHostObject>(this).allows_picking_multiple_items
+}
+- (())setAllowsPickingMultipleItems:(bool)allows {
+    env.objc.borrow_mut::<MPMediaPickerControllerHostObject>(this).allows_picking_multiple_items = allows;
+}
+
+- (id)prompt {
+    env.objc.borrow::<MPMediaPickerControllerHostObject>(this).prompt
+}
+- (())setPrompt:(id)prompt { // NSString*
+    let prompt: id = msg![env; prompt copy];
+    let old = std::mem::replace(
+        &mut env.objc.borrow_mut::<MPMediaPickerControllerHostObject>(this).prompt,
+        prompt,
+    );
+    release(env, old);
+}
+
+- (())viewDidAppear:(bool)animated {
+    () = msg_super![env; this viewDidAppear:animated];
+    let delegate = env.objc.borrow::<MPMediaPickerControllerHostObject>(this).delegate;
+    if delegate_responds_to(env, delegate, "mediaPickerDidCancel:") {
+        // The delegate is responsible for dismissing the picker, and might
+        // release it in the process.
+        retain(env, this);
+        () = msg![env; delegate mediaPickerDidCancel:this];
+        release(env, this);
+    } else {
+        log!("Warning: {:?} has no delegate to dismiss it, dismissing it anyway", this);
+        () = msg![env; this dismissModalViewControllerAnimated:true];
+    }
+}
+
+@end
+
+};
+
+/// Check whether a picker's delegate implements a callback.
+fn delegate_responds_to(env: &mut Environment, delegate: id, callback: &str) -> bool {
+    if delegate == nil {
+        return false;
+    }
+    let sel = env
+        .objc
+        .register_host_selector(callback.to_string(), &mut env.mem);
+    msg![env; delegate respondsToSelector:sel]
+}
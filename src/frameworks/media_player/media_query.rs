@@ -3,9 +3,26 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
-//! `MPMediaQuery`.
+//! `MPMediaQuery` and `MPMediaPropertyPredicate`.
+//!
+//! touchHLE has no iPod library, so every query's results are empty.
 
-use crate::objc::{id, nil, objc_classes, ClassExports};
+use crate::frameworks::foundation::{ns_array, NSInteger};
+use crate::objc::{autorelease, id, msg, objc_classes, Class, ClassExports, HostObject, NSZonePtr};
+use crate::Environment;
+
+type MPMediaGrouping = NSInteger;
+const MPMediaGroupingTitle: MPMediaGrouping = 0;
+const MPMediaGroupingAlbum: MPMediaGrouping = 1;
+const MPMediaGroupingArtist: MPMediaGrouping = 2;
+const MPMediaGroupingComposer: MPMediaGrouping = 4;
+const MPMediaGroupingGenre: MPMediaGrouping = 5;
+const MPMediaGroupingPlaylist: MPMediaGrouping = 6;
+
+struct MPMediaQueryHostObject {
+    grouping_type: MPMediaGrouping,
+}
+impl HostObject for MPMediaQueryHostObject {}
 
 pub const CLASSES: ClassExports = objc_classes! {
 
@@ -13,16 +30,86 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 @implementation MPMediaQuery: NSObject
 
-+ (id)playlistsQuery {
-    log!("TODO: [MPMediaQuery playlistsQuery] (not implemented yet)");
-    nil
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(MPMediaQueryHostObject {
+        grouping_type: MPMediaGroupingTitle,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
 }
 
 + (id)songsQuery {
-    log!("TODO: [MPMediaQuery songsQuery] (not implemented yet)");
-    nil
+    new_query(env, this, MPMediaGroupingTitle)
+}
++ (id)albumsQuery {
+    new_query(env, this, MPMediaGroupingAlbum)
+}
++ (id)artistsQuery {
+    new_query(env, this, MPMediaGroupingArtist)
+}
++ (id)composersQuery {
+    new_query(env, this, MPMediaGroupingComposer)
+}
++ (id)genresQuery {
+    new_query(env, this, MPMediaGroupingGenre)
+}
++ (id)playlistsQuery {
+    new_query(env, this, MPMediaGroupingPlaylist)
+}
+
+- (id)initWithFilterPredicates:(id)predicates { // NSSet*
+    log_dbg!("[(MPMediaQuery*){:?} initWithFilterPredicates:{:?}] ignoring predicates", this, predicates);
+    msg![env; this init]
+}
+
+- (())addFilterPredicate:(id)predicate { // MPMediaPredicate*
+    log_dbg!("[(MPMediaQuery*){:?} addFilterPredicate:{:?}] ignored", this, predicate);
+}
+- (())removeFilterPredicate:(id)predicate { // MPMediaPredicate*
+    log_dbg!("[(MPMediaQuery*){:?} removeFilterPredicate:{:?}] ignored", this, predicate);
+}
+
+- (MPMediaGrouping)groupingType {
+    env.objc.borrow::<MPMediaQueryHostObject>(this).grouping_type
+}
+- (())setGroupingType:(MPMediaGrouping)grouping_type {
+    env.objc.borrow_mut::<MPMediaQueryHostObject>(this).grouping_type = grouping_type;
+}
+
+- (id)items {
+    let items = ns_array::from_vec(env, Vec::new());
+    autorelease(env, items)
+}
+- (id)collections {
+    let collections = ns_array::from_vec(env, Vec::new());
+    autorelease(env, collections)
+}
+
+@end
+
+@implementation MPMediaPredicate: NSObject
+@end
+
+// The predicates are never evaluated, so their properties aren't kept.
+@implementation MPMediaPropertyPredicate: MPMediaPredicate
+
++ (id)predicateWithValue:(id)_value
+             forProperty:(id)_property { // NSString*
+    let predicate: id = msg![env; this new];
+    autorelease(env, predicate)
+}
++ (id)predicateWithValue:(id)_value
+             forProperty:(id)_property // NSString*
+          comparisonType:(NSInteger)_comparison_type {
+    let predicate: id = msg![env; this new];
+    autorelease(env, predicate)
 }
 
 @end
 
 };
+
+fn new_query(env: &mut Environment, class: Class, grouping_type: MPMediaGrouping) -> id {
+    let query: id = msg![env; class new];
+    () = msg![env; query setGroupingType:grouping_type];
+    autorelease(env, query)
+}
@@ -4,23 +4,36 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 //! `MPMoviePlayerController` etc.
+//!
+//! There is no actual video decoding yet. Nothing is shown while a movie
+//! "plays", but playback lasts as long as the movie would, if that can be found
+//! out from the file, so the user can skip it by tapping the screen like on a
+//! real device. Otherwise playback "finishes" as soon as the app next returns to
+//! the run loop. This is enough for the common case of an intro or cutscene
+//! video that the app waits on before continuing.
 
 use crate::dyld::{ConstantExports, HostConstant};
 use crate::frameworks::foundation::{ns_string, ns_url, NSInteger};
 use crate::frameworks::uikit::ui_device::UIDeviceOrientation;
-use crate::objc::{id, msg, msg_class, objc_classes, release, retain, ClassExports};
-use crate::Environment;
+use crate::objc::{
+    id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
+};
+use crate::{audio, Environment};
 use std::collections::VecDeque;
+use std::io::Cursor;
+use std::time::Instant;
 
 #[derive(Default)]
 pub struct State {
     active_player: Option<id>,
+    /// When the active player's movie will end, if it's still playing.
+    playback_ends_at: Option<Instant>,
     /// Various apps (e.g. Crash Bandicoot Nitro Kart 3D and Spore Origins)
     /// create or start a player and await some kind of notification, but can't
     /// handle it if that notification happens immediately. This queue lets us
     /// delay such notifications until the app next returns to the run loop,
     /// which seems to be late enough.
-    pending_notifications: VecDeque<(&'static str, id)>,
+    pending_notifications: VecDeque<PendingNotification>,
 }
 impl State {
     fn get(env: &mut Environment) -> &mut Self {
@@ -28,7 +41,37 @@ impl State {
     }
 }
 
+struct PendingNotification {
+    name: &'static str,
+    object: id,
+    /// Only used for [MPMoviePlayerPlaybackDidFinishNotification].
+    finish_reason: Option<MPMovieFinishReason>,
+}
+
+struct MPMoviePlayerControllerHostObject {
+    /// `NSURL*`
+    content_url: id,
+    /// `UIColor*`
+    background_color: id,
+    scaling_mode: MPMovieScalingMode,
+    movie_control_mode: MPMovieControlMode,
+    control_style: MPMovieControlStyle,
+}
+impl HostObject for MPMoviePlayerControllerHostObject {}
+
 type MPMovieScalingMode = NSInteger;
+const MPMovieScalingModeAspectFit: MPMovieScalingMode = 1;
+
+type MPMovieControlMode = NSInteger;
+const MPMovieControlModeHidden: MPMovieControlMode = 2;
+
+type MPMovieControlStyle = NSInteger;
+const MPMovieControlStyleNone: MPMovieControlStyle = 0;
+const MPMovieControlStyleDefault: MPMovieControlStyle = 2;
+
+type MPMovieFinishReason = NSInteger;
+const MPMovieFinishReasonPlaybackEnded: MPMovieFinishReason = 0;
+const MPMovieFinishReasonUserExited: MPMovieFinishReason = 2;
 
 // Values might not be correct, but as these are linked symbol constants, it
 // shouldn't matter.
@@ -37,9 +80,11 @@ pub const MPMoviePlayerPlaybackDidFinishNotification: &str =
 /// Apparently an undocumented, private API. Spore Origins uses it.
 pub const MPMoviePlayerContentPreloadDidFinishNotification: &str =
     "MPMoviePlayerContentPreloadDidFinishNotification";
+pub const MPMoviePlayerPlaybackDidFinishReasonUserInfoKey: &str =
+    "MPMoviePlayerPlaybackDidFinishReasonUserInfoKey";
 // TODO: More notifications?
 
-/// `NSNotificationName` values.
+/// `NSNotificationName` values and user info keys.
 pub const CONSTANTS: ConstantExports = &[
     (
         "_MPMoviePlayerPlaybackDidFinishNotification",
@@ -49,6 +94,10 @@ pub const CONSTANTS: ConstantExports = &[
         "_MPMoviePlayerContentPreloadDidFinishNotification",
         HostConstant::NSString(MPMoviePlayerContentPreloadDidFinishNotification),
     ),
+    (
+        "_MPMoviePlayerPlaybackDidFinishReasonUserInfoKey",
+        HostConstant::NSString(MPMoviePlayerPlaybackDidFinishReasonUserInfoKey),
+    ),
 ];
 
 pub const CLASSES: ClassExports = objc_classes! {
@@ -57,34 +106,82 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 @implementation MPMoviePlayerController: NSObject
 
-// TODO: actual playback
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(MPMoviePlayerControllerHostObject {
+        content_url: nil,
+        background_color: nil,
+        scaling_mode: MPMovieScalingModeAspectFit,
+        movie_control_mode: 0,
+        control_style: MPMovieControlStyleDefault,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
 
 - (id)initWithContentURL:(id)url { // NSURL*
     log!(
-        "TODO: [(MPMoviePlayerController*){:?} initWithContentURL:{:?} ({:?})]",
+        "TODO: [(MPMoviePlayerController*){:?} initWithContentURL:{:?} ({:?})] (no video playback)",
         this,
         url,
         ns_url::to_rust_path(env, url),
     );
 
+    retain(env, url);
+    env.objc.borrow_mut::<MPMoviePlayerControllerHostObject>(this).content_url = url;
+
     // Act as if loading immediately completed (Spore Origins waits for this).
-    State::get(env).pending_notifications.push_back(
-        (MPMoviePlayerContentPreloadDidFinishNotification, this)
-    );
+    State::get(env).pending_notifications.push_back(PendingNotification {
+        name: MPMoviePlayerContentPreloadDidFinishNotification,
+        object: this,
+        finish_reason: None,
+    });
 
     this
 }
 
-- (())setBackgroundColor:(id)_color { // UIColor*
-    // TODO
+- (())dealloc {
+    let &MPMoviePlayerControllerHostObject {
+        content_url,
+        background_color,
+        ..
+    } = env.objc.borrow(this);
+    release(env, content_url);
+    release(env, background_color);
+
+    // Don't deliver notifications for a deallocated object.
+    State::get(env).pending_notifications.retain(|notif| notif.object != this);
+
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (id)contentURL {
+    env.objc.borrow::<MPMoviePlayerControllerHostObject>(this).content_url
 }
 
-- (())setScalingMode:(MPMovieScalingMode)_mode {
-    // TODO
+- (id)backgroundColor {
+    env.objc.borrow::<MPMoviePlayerControllerHostObject>(this).background_color
+}
+- (())setBackgroundColor:(id)color { // UIColor*
+    retain(env, color);
+    let host_object = env.objc.borrow_mut::<MPMoviePlayerControllerHostObject>(this);
+    let old = std::mem::replace(&mut host_object.background_color, color);
+    release(env, old);
+}
+
+- (MPMovieScalingMode)scalingMode {
+    env.objc.borrow::<MPMoviePlayerControllerHostObject>(this).scaling_mode
+}
+- (())setScalingMode:(MPMovieScalingMode)mode {
+    // Ignored since nothing is displayed.
+    env.objc.borrow_mut::<MPMoviePlayerControllerHostObject>(this).scaling_mode = mode;
 }
 
 // Apparently an undocumented, private API, but Spore Origins uses it.
-- (())setMovieControlMode:(NSInteger)_mode {
+- (MPMovieControlMode)movieControlMode {
+    env.objc.borrow::<MPMoviePlayerControllerHostObject>(this).movie_control_mode
+}
+- (())setMovieControlMode:(MPMovieControlMode)mode {
+    env.objc.borrow_mut::<MPMoviePlayerControllerHostObject>(this).movie_control_mode = mode;
+
     // Game-specific hack :(
     // Spore Origins subscribes to the playback finished notification 0.2s after
     // starting playback, so it misses the notification we send. When it
@@ -92,12 +189,20 @@ pub const CLASSES: ClassExports = objc_classes! {
     // the notification again.
     if env.bundle.bundle_identifier().starts_with("com.ea.spore") {
         log!("Applying game-specific hack for Spore Origins: sending MPMoviePlayerPlaybackDidFinishNotification again.");
-        State::get(env).pending_notifications.push_back(
-            (MPMoviePlayerPlaybackDidFinishNotification, this)
-        );
+        State::get(env).pending_notifications.push_back(PendingNotification {
+            name: MPMoviePlayerPlaybackDidFinishNotification,
+            object: this,
+            finish_reason: Some(MPMovieFinishReasonPlaybackEnded),
+        });
     }
-    // As this is undocumented and we don't have real video playback yet, let's
-    // ignore it otherwise.
+}
+
+// iPhone OS 3.2 replacement for movieControlMode.
+- (MPMovieControlStyle)controlStyle {
+    env.objc.borrow::<MPMoviePlayerControllerHostObject>(this).control_style
+}
+- (())setControlStyle:(MPMovieControlStyle)style {
+    env.objc.borrow_mut::<MPMoviePlayerControllerHostObject>(this).control_style = style;
 }
 
 // Another undocumented one! But some apps may still use it :/
@@ -107,25 +212,74 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 // MPMediaPlayback implementation
+- (())prepareToPlay {
+    // Nothing to do.
+}
+
 - (())play {
-    log!("TODO: [(MPMoviePlayerController*){:?} play]", this);
-    if let Some(old) = env.framework_state.media_player.movie_player.active_player {
+    log!("TODO: [(MPMoviePlayerController*){:?} play] (no video playback)", this);
+    if let Some(old) = State::get(env).active_player {
+        if old == this {
+            return;
+        }
         let _: () = msg![env; old stop];
     }
-    assert!(env.framework_state.media_player.movie_player.active_player.is_none());
+    assert!(State::get(env).active_player.is_none());
     // Movie player is retained by the runtime until it is stopped
     retain(env, this);
-    env.framework_state.media_player.movie_player.active_player = Some(this);
+    State::get(env).active_player = Some(this);
 
-    // Act as if playback immediately completed (various apps wait for this).
-    State::get(env).pending_notifications.push_back(
-        (MPMoviePlayerPlaybackDidFinishNotification, this)
-    );
+    let url = env.objc.borrow::<MPMoviePlayerControllerHostObject>(this).content_url;
+    let duration = if url == nil {
+        None
+    } else {
+        let path = ns_url::to_rust_path(env, url);
+        env.fs
+            .read(path)
+            .ok()
+            .and_then(|bytes| audio::probe_mp4_duration(Cursor::new(bytes)))
+    };
+    let Some(duration) = duration else {
+        // Act as if playback immediately completed (various apps wait for
+        // this).
+        State::get(env).pending_notifications.push_back(PendingNotification {
+            name: MPMoviePlayerPlaybackDidFinishNotification,
+            object: this,
+            finish_reason: Some(MPMovieFinishReasonPlaybackEnded),
+        });
+        return;
+    };
+    if can_skip(env, this) {
+        echo!(
+            "A {:.1}s movie is playing, but can't be shown. Tap the screen to skip it.",
+            duration.as_secs_f64()
+        );
+    } else {
+        echo!(
+            "A {:.1}s movie is playing, but can't be shown. The app doesn't allow skipping it.",
+            duration.as_secs_f64()
+        );
+    }
+    State::get(env).playback_ends_at = Some(env.clock.now() + duration);
 }
 
 - (())stop {
-    log!("TODO: [(MPMoviePlayerController*){:?} stop]", this);
-    assert!(this == env.framework_state.media_player.movie_player.active_player.take().unwrap());
+    log_dbg!("[(MPMoviePlayerController*){:?} stop]", this);
+    // Stopping a player that isn't playing (e.g. because playback already
+    // finished) is harmless.
+    if State::get(env).active_player != Some(this) {
+        return;
+    }
+    State::get(env).active_player = None;
+    if State::get(env).playback_ends_at.take().is_some() {
+        // Playback is cut short, but the app might still be waiting for it to
+        // end.
+        State::get(env).pending_notifications.push_back(PendingNotification {
+            name: MPMoviePlayerPlaybackDidFinishNotification,
+            object: this,
+            finish_reason: Some(MPMovieFinishReasonPlaybackEnded),
+        });
+    }
     release(env, this);
 }
 
@@ -133,14 +287,90 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 };
 
+/// Whether the user is allowed to skip the movie, i.e. whether the player
+/// shows controls.
+fn can_skip(env: &Environment, player: id) -> bool {
+    let &MPMoviePlayerControllerHostObject {
+        movie_control_mode,
+        control_style,
+        ..
+    } = env.objc.borrow(player);
+    movie_control_mode != MPMovieControlModeHidden && control_style != MPMovieControlStyleNone
+}
+
+/// Called when the user taps the screen: if a movie is playing, it covers the
+/// screen, so the tap is consumed, and the movie is skipped if the player
+/// allows it, as on a real device. Returns [true] if the tap was consumed.
+pub fn handle_tap(env: &mut Environment) -> bool {
+    let state = State::get(env);
+    let (Some(player), Some(_)) = (state.active_player, state.playback_ends_at) else {
+        return false;
+    };
+    if !can_skip(env, player) {
+        log_dbg!("User tapped the screen, but the movie can't be skipped.");
+        return true;
+    }
+    log!("User tapped the screen, skipping movie.");
+    let state = State::get(env);
+    state.playback_ends_at = None;
+    // The player is released when the notification is sent.
+    state.pending_notifications.push_back(PendingNotification {
+        name: MPMoviePlayerPlaybackDidFinishNotification,
+        object: player,
+        finish_reason: Some(MPMovieFinishReasonUserExited),
+    });
+    true
+}
+
 /// For use by `NSRunLoop` via [super::handle_players]: check movie players'
-/// status, send notifications if necessary.
-pub(super) fn handle_players(env: &mut Environment) {
+/// status, send notifications if necessary. Returns the time playback will end,
+/// if a movie is playing.
+pub(super) fn handle_players(env: &mut Environment) -> Option<Instant> {
+    let now = env.clock.now();
+    let state = State::get(env);
+    if let (Some(player), Some(ends_at)) = (state.active_player, state.playback_ends_at) {
+        if ends_at <= now {
+            state.playback_ends_at = None;
+            state.pending_notifications.push_back(PendingNotification {
+                name: MPMoviePlayerPlaybackDidFinishNotification,
+                object: player,
+                finish_reason: Some(MPMovieFinishReasonPlaybackEnded),
+            });
+        }
+    }
+
     while let Some(notif) = State::get(env).pending_notifications.pop_front() {
-        let (name, object) = notif;
+        let PendingNotification {
+            name,
+            object,
+            finish_reason,
+        } = notif;
+
+        // Playback is over, so the player no longer needs to be kept alive.
+        // It's retained for the duration of the notification.
+        retain(env, object);
+        if finish_reason.is_some() && State::get(env).active_player == Some(object) {
+            State::get(env).active_player = None;
+            State::get(env).playback_ends_at = None;
+            release(env, object);
+        }
+
+        let user_info = if let Some(reason) = finish_reason {
+            let key =
+                ns_string::get_static_str(env, MPMoviePlayerPlaybackDidFinishReasonUserInfoKey);
+            let reason: id = msg_class![env; NSNumber numberWithInteger:reason];
+            msg_class![env; NSDictionary dictionaryWithObject:reason forKey:key]
+        } else {
+            nil
+        };
+
         let name = ns_string::get_static_str(env, name);
         let center: id = msg_class![env; NSNotificationCenter defaultCenter];
-        // TODO: should there be some user info attached?
-        let _: () = msg![env; center postNotificationName:name object:object];
+        let _: () = msg![env; center postNotificationName:name
+                                                   object:object
+                                                 userInfo:user_info];
+        release(env, object);
     }
+
+    State::get(env).playback_ends_at
 }
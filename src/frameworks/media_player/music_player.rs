@@ -4,16 +4,34 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 //! `MPMusicPlayerController` etc.
+//!
+//! touchHLE has no iPod library, so the music players never have anything to
+//! play: they are always stopped, with no item. This is enough for games that
+//! check whether the user's own music is playing before starting their own
+//! soundtrack. The volume is the simulated hardware output volume (see
+//! [crate::frameworks::audio_toolbox::audio_session::set_output_volume]).
 
-use crate::{
-    dyld::{ConstantExports, HostConstant},
-    objc::{id, nil, objc_classes, ClassExports},
-};
+use crate::dyld::{ConstantExports, HostConstant};
+use crate::frameworks::audio_toolbox::audio_session;
+use crate::frameworks::foundation::{ns_string, NSInteger, NSTimeInterval};
+use crate::objc::{id, msg, msg_class, nil, objc_classes, ClassExports, HostObject, NSZonePtr};
+use crate::Environment;
+
+type MPMusicPlaybackState = NSInteger;
+const MPMusicPlaybackStateStopped: MPMusicPlaybackState = 0;
+
+type MPMusicRepeatMode = NSInteger;
+const MPMusicRepeatModeDefault: MPMusicRepeatMode = 0;
+
+type MPMusicShuffleMode = NSInteger;
+const MPMusicShuffleModeDefault: MPMusicShuffleMode = 0;
 
 pub const MPMusicPlayerControllerNowPlayingItemDidChangeNotification: &str =
     "MPMusicPlayerControllerNowPlayingItemDidChangeNotification";
 pub const MPMusicPlayerControllerPlaybackStateDidChangeNotification: &str =
     "MPMusicPlayerControllerPlaybackStateDidChangeNotification";
+pub const MPMusicPlayerControllerVolumeDidChangeNotification: &str =
+    "MPMusicPlayerControllerVolumeDidChangeNotification";
 
 /// `NSNotificationName` values.
 pub const CONSTANTS: ConstantExports = &[
@@ -25,30 +43,158 @@ pub const CONSTANTS: ConstantExports = &[
         "_MPMusicPlayerControllerPlaybackStateDidChangeNotification",
         HostConstant::NSString(MPMusicPlayerControllerPlaybackStateDidChangeNotification),
     ),
+    (
+        "_MPMusicPlayerControllerVolumeDidChangeNotification",
+        HostConstant::NSString(MPMusicPlayerControllerVolumeDidChangeNotification),
+    ),
 ];
 
+#[derive(Default)]
+pub struct State {
+    /// The `iPodMusicPlayer` singleton, if it has been created.
+    ipod_music_player: Option<id>,
+    /// The `applicationMusicPlayer` singleton, if it has been created.
+    application_music_player: Option<id>,
+}
+impl State {
+    fn get(env: &mut Environment) -> &mut Self {
+        &mut env.framework_state.media_player.music_player
+    }
+}
+
+struct MPMusicPlayerControllerHostObject {
+    generating_playback_notifications: bool,
+    repeat_mode: MPMusicRepeatMode,
+    shuffle_mode: MPMusicShuffleMode,
+}
+impl HostObject for MPMusicPlayerControllerHostObject {}
+
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
 
 @implementation MPMusicPlayerController: NSObject
 
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(MPMusicPlayerControllerHostObject {
+        generating_playback_notifications: false,
+        repeat_mode: MPMusicRepeatModeDefault,
+        shuffle_mode: MPMusicShuffleModeDefault,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
 + (id)iPodMusicPlayer {
-    log_dbg!(
-        "TODO: [(MPMusicPlayerController*){:?} iPodMusicPlayer]",
-        this
-    );
-    nil
+    if let Some(player) = State::get(env).ipod_music_player {
+        return player;
+    }
+    let player: id = msg![env; this new];
+    State::get(env).ipod_music_player = Some(player);
+    player
 }
 
 + (id)applicationMusicPlayer {
-    log_dbg!(
-        "TODO: [(MPMusicPlayerController*){:?} applicationMusicPlayer]",
-        this
-    );
+    if let Some(player) = State::get(env).application_music_player {
+        return player;
+    }
+    let player: id = msg![env; this new];
+    State::get(env).application_music_player = Some(player);
+    player
+}
+
+- (MPMusicPlaybackState)playbackState {
+    MPMusicPlaybackStateStopped
+}
+
+- (id)nowPlayingItem {
     nil
 }
+- (())setNowPlayingItem:(id)item { // MPMediaItem*
+    log_dbg!("[(MPMusicPlayerController*){:?} setNowPlayingItem:{:?}] ignored", this, item);
+}
+
+- (NSTimeInterval)currentPlaybackTime {
+    0.0
+}
+- (())setCurrentPlaybackTime:(NSTimeInterval)time {
+    log_dbg!("[(MPMusicPlayerController*){:?} setCurrentPlaybackTime:{}] ignored", this, time);
+}
+
+- (MPMusicRepeatMode)repeatMode {
+    env.objc.borrow::<MPMusicPlayerControllerHostObject>(this).repeat_mode
+}
+- (())setRepeatMode:(MPMusicRepeatMode)mode {
+    env.objc.borrow_mut::<MPMusicPlayerControllerHostObject>(this).repeat_mode = mode;
+}
+
+- (MPMusicShuffleMode)shuffleMode {
+    env.objc.borrow::<MPMusicPlayerControllerHostObject>(this).shuffle_mode
+}
+- (())setShuffleMode:(MPMusicShuffleMode)mode {
+    env.objc.borrow_mut::<MPMusicPlayerControllerHostObject>(this).shuffle_mode = mode;
+}
+
+- (f32)volume {
+    audio_session::output_volume(env)
+}
+- (())setVolume:(f32)volume {
+    audio_session::set_output_volume(env, volume);
+}
+
+- (())beginGeneratingPlaybackNotifications {
+    env.objc.borrow_mut::<MPMusicPlayerControllerHostObject>(this).generating_playback_notifications = true;
+}
+- (())endGeneratingPlaybackNotifications {
+    env.objc.borrow_mut::<MPMusicPlayerControllerHostObject>(this).generating_playback_notifications = false;
+}
+
+// The queue is always empty, so there is never anything to play.
+
+- (())setQueueWithQuery:(id)query { // MPMediaQuery*
+    log_dbg!("[(MPMusicPlayerController*){:?} setQueueWithQuery:{:?}] ignored", this, query);
+}
+- (())setQueueWithItemCollection:(id)collection { // MPMediaItemCollection*
+    log_dbg!("[(MPMusicPlayerController*){:?} setQueueWithItemCollection:{:?}] ignored", this, collection);
+}
+
+- (())play {
+    log_dbg!("[(MPMusicPlayerController*){:?} play] ignored, nothing is queued", this);
+}
+- (())pause {}
+- (())stop {}
+- (())skipToNextItem {}
+- (())skipToBeginning {}
+- (())skipToPreviousItem {}
+- (())beginSeekingForward {}
+- (())beginSeekingBackward {}
+- (())endSeeking {}
 
 @end
 
 };
+
+/// Post `MPMusicPlayerControllerVolumeDidChangeNotification` for each music
+/// player that is generating notifications. See
+/// [super::output_volume_did_change].
+pub(super) fn post_volume_notifications(env: &mut Environment) {
+    let State {
+        ipod_music_player,
+        application_music_player,
+    } = *State::get(env);
+    for player in [ipod_music_player, application_music_player]
+        .into_iter()
+        .flatten()
+    {
+        if !env
+            .objc
+            .borrow::<MPMusicPlayerControllerHostObject>(player)
+            .generating_playback_notifications
+        {
+            continue;
+        }
+        let name =
+            ns_string::get_static_str(env, MPMusicPlayerControllerVolumeDidChangeNotification);
+        let center: id = msg_class![env; NSNotificationCenter defaultCenter];
+        let _: () = msg![env; center postNotificationName:name object:player];
+    }
+}
@@ -0,0 +1,301 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `MPVolumeView` and the system volume notification.
+//!
+//! The volume view is a slider for the simulated hardware output volume (see
+//! [crate::frameworks::audio_toolbox::audio_session::set_output_volume]).
+//! touchHLE has no `UISlider`, so the slider is made of plain views: a track,
+//! the filled part of the track, and a round thumb. There are no AirPlay or
+//! other routes to pick from, so the route button is never shown.
+
+use crate::frameworks::audio_toolbox::audio_session;
+use crate::frameworks::core_graphics::cg_color::{self, CGColorRelease};
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::ns_string;
+use crate::frameworks::uikit::ui_view::UIViewHostObject;
+use crate::objc::{
+    id, impl_HostObject_with_superclass, msg, msg_class, msg_super, nil, objc_classes, release,
+    ClassExports, NSZonePtr,
+};
+use crate::Environment;
+
+/// Posted by the private `AVSystemController` class on iPhone OS whenever the
+/// system volume changes. It isn't public API, but apps observe it anyway to
+/// find out when the volume buttons are pressed.
+pub const AVSystemController_SystemVolumeDidChangeNotification: &str =
+    "AVSystemController_SystemVolumeDidChangeNotification";
+const AVSystemController_AudioVolumeNotificationParameter: &str =
+    "AVSystemController_AudioVolumeNotificationParameter";
+const AVSystemController_AudioCategoryNotificationParameter: &str =
+    "AVSystemController_AudioCategoryNotificationParameter";
+const AVSystemController_AudioVolumeChangeReasonNotificationParameter: &str =
+    "AVSystemController_AudioVolumeChangeReasonNotificationParameter";
+
+const TRACK_HEIGHT: CGFloat = 9.0;
+const THUMB_SIZE: CGFloat = 23.0;
+
+#[derive(Default)]
+pub struct State {
+    /// Volume views that currently exist. These are weak references.
+    volume_views: Vec<id>,
+}
+impl State {
+    fn get(env: &mut Environment) -> &mut Self {
+        &mut env.framework_state.media_player.volume_view
+    }
+}
+
+#[derive(Default)]
+struct MPVolumeViewHostObject {
+    superclass: UIViewHostObject,
+    /// `UIView*`
+    track: id,
+    /// `UIView*`
+    track_fill: id,
+    /// `UIView*`
+    thumb: id,
+    shows_volume_slider: bool,
+}
+impl_HostObject_with_superclass!(MPVolumeViewHostObject);
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation MPVolumeView: UIView
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(MPVolumeViewHostObject {
+        shows_volume_slider: true,
+        ..Default::default()
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithFrame:(CGRect)frame {
+    let this: id = msg_super![env; this initWithFrame:frame];
+
+    let clear_color: id = msg_class![env; UIColor clearColor];
+    () = msg![env; this setBackgroundColor:clear_color];
+
+    let track: id = msg_class![env; UIView new];
+    let track_color: id = msg_class![env; UIColor colorWithWhite:(0.85 as CGFloat)
+                                                           alpha:(1.0 as CGFloat)];
+    () = msg![env; track setBackgroundColor:track_color];
+
+    let track_fill: id = msg_class![env; UIView new];
+    let fill_color: id = msg_class![env; UIColor colorWithRed:(0.16 as CGFloat)
+                                                         green:(0.45 as CGFloat)
+                                                          blue:(0.9 as CGFloat)
+                                                         alpha:(1.0 as CGFloat)];
+    () = msg![env; track_fill setBackgroundColor:fill_color];
+
+    let thumb: id = msg_class![env; UIView new];
+    let thumb_color: id = msg_class![env; UIColor whiteColor];
+    () = msg![env; thumb setBackgroundColor:thumb_color];
+
+    for (view, corner_radius) in [
+        (track, TRACK_HEIGHT / 2.0),
+        (track_fill, TRACK_HEIGHT / 2.0),
+        (thumb, THUMB_SIZE / 2.0),
+    ] {
+        let layer: id = msg![env; view layer];
+        () = msg![env; layer setCornerRadius:corner_radius];
+        () = msg![env; this addSubview:view];
+    }
+    let thumb_layer: id = msg![env; thumb layer];
+    () = msg![env; thumb_layer setBorderWidth:(1.0 as CGFloat)];
+    let border_color = cg_color::from_rgba(env, (0.6, 0.6, 0.6, 1.0));
+    () = msg![env; thumb_layer setBorderColor:border_color];
+    CGColorRelease(env, border_color);
+
+    let host_obj = env.objc.borrow_mut::<MPVolumeViewHostObject>(this);
+    host_obj.track = track;
+    host_obj.track_fill = track_fill;
+    host_obj.thumb = thumb;
+
+    State::get(env).volume_views.push(this);
+    () = msg![env; this layoutSubviews];
+
+    this
+}
+
+// TODO: initWithCoder:
+
+- (())dealloc {
+    let &MPVolumeViewHostObject {
+        track,
+        track_fill,
+        thumb,
+        ..
+    } = env.objc.borrow(this);
+    release(env, track);
+    release(env, track_fill);
+    release(env, thumb);
+    State::get(env).volume_views.retain(|&view| view != this);
+    msg_super![env; this dealloc]
+}
+
+- (bool)showsVolumeSlider {
+    env.objc.borrow::<MPVolumeViewHostObject>(this).shows_volume_slider
+}
+- (())setShowsVolumeSlider:(bool)shows {
+    env.objc.borrow_mut::<MPVolumeViewHostObject>(this).shows_volume_slider = shows;
+    () = msg![env; this layoutSubviews];
+}
+
+- (bool)showsRouteButton {
+    false
+}
+- (())setShowsRouteButton:(bool)shows {
+    log_dbg!("[(MPVolumeView*){:?} setShowsRouteButton:{}] ignored, there are no routes", this, shows);
+}
+
+- (CGSize)sizeThatFits:(CGSize)size {
+    CGSize {
+        width: size.width,
+        height: THUMB_SIZE,
+    }
+}
+
+- (())setFrame:(CGRect)frame {
+    () = msg_super![env; this setFrame:frame];
+    () = msg![env; this layoutSubviews];
+}
+- (())setBounds:(CGRect)bounds {
+    () = msg_super![env; this setBounds:bounds];
+    () = msg![env; this layoutSubviews];
+}
+
+- (())layoutSubviews {
+    let &MPVolumeViewHostObject {
+        track,
+        track_fill,
+        thumb,
+        shows_volume_slider,
+        ..
+    } = env.objc.borrow(this);
+    for view in [track, track_fill, thumb] {
+        () = msg![env; view setHidden:(!shows_volume_slider)];
+    }
+
+    let bounds: CGRect = msg![env; this bounds];
+    let middle = bounds.origin.y + bounds.size.height / 2.0;
+    let thumb_x = thumb_x_for_volume(bounds, audio_session::output_volume(env));
+
+    let track_frame = CGRect {
+        origin: CGPoint {
+            x: bounds.origin.x,
+            y: middle - TRACK_HEIGHT / 2.0,
+        },
+        size: CGSize {
+            width: bounds.size.width,
+            height: TRACK_HEIGHT,
+        },
+    };
+    () = msg![env; track setFrame:track_frame];
+    let fill_frame = CGRect {
+        size: CGSize {
+            width: thumb_x - bounds.origin.x,
+            height: TRACK_HEIGHT,
+        },
+        ..track_frame
+    };
+    () = msg![env; track_fill setFrame:fill_frame];
+    let thumb_frame = CGRect {
+        origin: CGPoint {
+            x: thumb_x - THUMB_SIZE / 2.0,
+            y: middle - THUMB_SIZE / 2.0,
+        },
+        size: CGSize {
+            width: THUMB_SIZE,
+            height: THUMB_SIZE,
+        },
+    };
+    () = msg![env; thumb setFrame:thumb_frame];
+}
+
+- (id)hitTest:(CGPoint)point
+    withEvent:(id)event { // UIEvent* (possibly nil)
+    // The parts of the slider are just for show, touches are handled here.
+    if msg![env; this pointInside:point withEvent:event] {
+        this
+    } else {
+        nil
+    }
+}
+
+- (())touchesBegan:(id)touches // NSSet* of UITouch*
+         withEvent:(id)_event { // UIEvent*
+    set_volume_from_touches(env, this, touches);
+}
+- (())touchesMoved:(id)touches // NSSet* of UITouch*
+         withEvent:(id)_event { // UIEvent*
+    set_volume_from_touches(env, this, touches);
+}
+- (())touchesEnded:(id)touches // NSSet* of UITouch*
+         withEvent:(id)_event { // UIEvent*
+    set_volume_from_touches(env, this, touches);
+}
+
+@end
+
+};
+
+/// Where the middle of the thumb is for a volume. The thumb stays within the
+/// bounds.
+fn thumb_x_for_volume(bounds: CGRect, volume: f32) -> CGFloat {
+    let range = (bounds.size.width - THUMB_SIZE).max(0.0);
+    bounds.origin.x + THUMB_SIZE / 2.0 + range * volume
+}
+
+fn set_volume_from_touches(env: &mut Environment, this: id, touches: id) {
+    if !env
+        .objc
+        .borrow::<MPVolumeViewHostObject>(this)
+        .shows_volume_slider
+    {
+        return;
+    }
+    let touch: id = msg![env; touches anyObject];
+    let location: CGPoint = msg![env; touch locationInView:this];
+    let bounds: CGRect = msg![env; this bounds];
+    let range = bounds.size.width - THUMB_SIZE;
+    if range <= 0.0 {
+        return;
+    }
+    let volume = (location.x - bounds.origin.x - THUMB_SIZE / 2.0) / range;
+    audio_session::set_output_volume_from_user(env, volume);
+}
+
+/// For use by [super::output_volume_did_change]: move the sliders of all the
+/// volume views, and post
+/// `AVSystemController_SystemVolumeDidChangeNotification`.
+pub(super) fn system_volume_did_change(env: &mut Environment, volume: f32) {
+    let volume_views = State::get(env).volume_views.clone();
+    for volume_view in volume_views {
+        () = msg![env; volume_view layoutSubviews];
+    }
+
+    let user_info: id = msg_class![env; NSMutableDictionary new];
+    let volume: id = msg_class![env; NSNumber numberWithFloat:volume];
+    let key = ns_string::get_static_str(env, AVSystemController_AudioVolumeNotificationParameter);
+    () = msg![env; user_info setObject:volume forKey:key];
+    let category = ns_string::get_static_str(env, "Audio/Video");
+    let key = ns_string::get_static_str(env, AVSystemController_AudioCategoryNotificationParameter);
+    () = msg![env; user_info setObject:category forKey:key];
+    let reason = ns_string::get_static_str(env, "ExplicitVolumeChange");
+    let key = ns_string::get_static_str(
+        env,
+        AVSystemController_AudioVolumeChangeReasonNotificationParameter,
+    );
+    () = msg![env; user_info setObject:reason forKey:key];
+
+    let name = ns_string::get_static_str(env, AVSystemController_SystemVolumeDidChangeNotification);
+    let center: id = msg_class![env; NSNotificationCenter defaultCenter];
+    () = msg![env; center postNotificationName:name object:nil userInfo:user_info];
+    release(env, user_info);
+}
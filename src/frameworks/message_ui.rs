@@ -0,0 +1,103 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! The Message UI framework.
+//!
+//! touchHLE can't send email or text messages, so by default `+canSendMail`
+//! and `+canSendText` return `NO` and well-behaved apps won't offer to compose
+//! anything. Apps that present a composer anyway get a placeholder screen with
+//! a Cancel button, which reports that the user cancelled, so that the app can
+//! carry on as normal. See also `--can-send-messages`.
+
+pub mod mf_mail_compose_view_controller;
+pub mod mf_message_compose_view_controller;
+
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::ns_string;
+use crate::frameworks::uikit::ui_font::UITextAlignmentCenter;
+use crate::frameworks::uikit::ui_view::ui_control::ui_button::UIButtonTypeRoundedRect;
+use crate::frameworks::uikit::ui_view::ui_control::{
+    UIControlEventTouchUpInside, UIControlStateNormal,
+};
+use crate::frameworks::uikit::ui_view::ui_navigation_bar::NAVIGATION_BAR_HEIGHT;
+use crate::objc::{id, msg, msg_class, nil, release};
+use crate::Environment;
+
+/// For use by the composers' `loadView`: adds the placeholder content below the
+/// navigation bar. The Cancel button sends `_touchHLE_cancel:` to `this`.
+fn add_placeholder_content(env: &mut Environment, this: id, title: &str) {
+    let view: id = msg![env; this view];
+    let bounds: CGRect = msg![env; view bounds];
+    let width = bounds.size.width;
+
+    let background: id = msg_class![env; UIColor whiteColor];
+    () = msg![env; view setBackgroundColor:background];
+
+    let mut y = NAVIGATION_BAR_HEIGHT + 20.0;
+    for (text, font_size, height) in [
+        (title, 20.0, 30.0),
+        (
+            "touchHLE can't send messages, so this screen is only a \
+             placeholder. Tap Cancel to return to the app.",
+            14.0,
+            80.0,
+        ),
+    ] {
+        let frame = CGRect {
+            origin: CGPoint { x: 20.0, y },
+            size: CGSize {
+                width: width - 40.0,
+                height,
+            },
+        };
+        let label: id = msg_class![env; UILabel alloc];
+        let label: id = msg![env; label initWithFrame:frame];
+        let text = ns_string::from_rust_string(env, text.to_string());
+        () = msg![env; label setText:text];
+        release(env, text);
+        () = msg![env; label setTextAlignment:UITextAlignmentCenter];
+        () = msg![env; label setNumberOfLines:0]; // unlimited
+        let font: id = msg_class![env; UIFont systemFontOfSize:(font_size as CGFloat)];
+        () = msg![env; label setFont:font];
+        let bg_color: id = msg_class![env; UIColor clearColor];
+        () = msg![env; label setBackgroundColor:bg_color];
+        () = msg![env; view addSubview:label];
+        release(env, label);
+        y += height + 10.0;
+    }
+
+    let button_width = 120.0;
+    let button_frame = CGRect {
+        origin: CGPoint {
+            x: (width - button_width) / 2.0,
+            y,
+        },
+        size: CGSize {
+            width: button_width,
+            height: 40.0,
+        },
+    };
+    let button: id = msg_class![env; UIButton buttonWithType:UIButtonTypeRoundedRect];
+    () = msg![env; button setFrame:button_frame];
+    let button_title = ns_string::get_static_str(env, "Cancel");
+    () = msg![env; button setTitle:button_title forState:UIControlStateNormal];
+    let selector = env.objc.lookup_selector("_touchHLE_cancel:").unwrap();
+    () = msg![env; button addTarget:this
+                             action:selector
+                   forControlEvents:UIControlEventTouchUpInside];
+    () = msg![env; view addSubview:button];
+}
+
+/// Check whether a composer's delegate implements the callback that tells it
+/// the composer has finished.
+fn delegate_responds_to(env: &mut Environment, delegate: id, callback: &str) -> bool {
+    if delegate == nil {
+        return false;
+    }
+    let sel = env
+        .objc
+        .register_host_selector(callback.to_string(), &mut env.mem);
+    msg![env; delegate respondsToSelector:sel]
+}
@@ -0,0 +1,104 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `MFMailComposeViewController`.
+
+use super::{add_placeholder_content, delegate_responds_to};
+use crate::dyld::{ConstantExports, HostConstant};
+use crate::frameworks::foundation::NSInteger;
+use crate::frameworks::uikit::ui_view_controller::ui_navigation_controller::UINavigationControllerHostObject;
+use crate::objc::{
+    id, impl_HostObject_with_superclass, msg, msg_super, nil, objc_classes, release, retain,
+    ClassExports, NSZonePtr,
+};
+
+type MFMailComposeResult = NSInteger;
+const MFMailComposeResultCancelled: MFMailComposeResult = 0;
+
+pub const MFMailComposeErrorDomain: &str = "MFMailComposeErrorDomain";
+
+pub const CONSTANTS: ConstantExports = &[(
+    "_MFMailComposeErrorDomain",
+    HostConstant::NSString(MFMailComposeErrorDomain),
+)];
+
+#[derive(Default)]
+struct MFMailComposeViewControllerHostObject {
+    superclass: UINavigationControllerHostObject,
+    /// Weak reference.
+    mail_compose_delegate: id,
+}
+impl_HostObject_with_superclass!(MFMailComposeViewControllerHostObject);
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation MFMailComposeViewController: UINavigationController
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<MFMailComposeViewControllerHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (bool)canSendMail {
+    env.options.can_send_messages
+}
+
+- (id)mailComposeDelegate {
+    env.objc.borrow::<MFMailComposeViewControllerHostObject>(this).mail_compose_delegate
+}
+- (())setMailComposeDelegate:(id)delegate {
+    env.objc.borrow_mut::<MFMailComposeViewControllerHostObject>(this).mail_compose_delegate = delegate;
+}
+
+// The message contents are ignored, since they can't be sent anyway.
+
+- (())setSubject:(id)subject { // NSString*
+    log_dbg!("[(MFMailComposeViewController*){:?} setSubject:{:?}] ignored", this, subject);
+}
+- (())setToRecipients:(id)recipients { // NSArray*
+    log_dbg!("[(MFMailComposeViewController*){:?} setToRecipients:{:?}] ignored", this, recipients);
+}
+- (())setCcRecipients:(id)recipients { // NSArray*
+    log_dbg!("[(MFMailComposeViewController*){:?} setCcRecipients:{:?}] ignored", this, recipients);
+}
+- (())setBccRecipients:(id)recipients { // NSArray*
+    log_dbg!("[(MFMailComposeViewController*){:?} setBccRecipients:{:?}] ignored", this, recipients);
+}
+- (())setMessageBody:(id)body // NSString*
+              isHTML:(bool)is_html {
+    log_dbg!("[(MFMailComposeViewController*){:?} setMessageBody:{:?} isHTML:{}] ignored", this, body, is_html);
+}
+- (())addAttachmentData:(id)data // NSData*
+               mimeType:(id)mime_type // NSString*
+               fileName:(id)file_name { // NSString*
+    log_dbg!("[(MFMailComposeViewController*){:?} addAttachmentData:{:?} mimeType:{:?} fileName:{:?}] ignored", this, data, mime_type, file_name);
+}
+
+- (())loadView {
+    () = msg_super![env; this loadView];
+    add_placeholder_content(env, this, "New Message");
+}
+
+- (())_touchHLE_cancel:(id)_sender {
+    let delegate = env.objc.borrow::<MFMailComposeViewControllerHostObject>(this).mail_compose_delegate;
+    if delegate_responds_to(env, delegate, "mailComposeController:didFinishWithResult:error:") {
+        // The delegate is responsible for dismissing the composer, and might
+        // release it in the process.
+        retain(env, this);
+        () = msg![env; delegate mailComposeController:this
+                                  didFinishWithResult:MFMailComposeResultCancelled
+                                                error:nil];
+        release(env, this);
+    } else {
+        log!("Warning: {:?} has no delegate to dismiss it, dismissing it anyway", this);
+        () = msg![env; this dismissModalViewControllerAnimated:true];
+    }
+}
+
+@end
+
+};
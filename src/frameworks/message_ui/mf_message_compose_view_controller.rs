@@ -0,0 +1,104 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `MFMessageComposeViewController`.
+
+use super::{add_placeholder_content, delegate_responds_to};
+use crate::frameworks::foundation::NSInteger;
+use crate::frameworks::uikit::ui_view_controller::ui_navigation_controller::UINavigationControllerHostObject;
+use crate::objc::{
+    id, impl_HostObject_with_superclass, msg, msg_super, nil, objc_classes, release, retain,
+    ClassExports, NSZonePtr,
+};
+
+type MessageComposeResult = NSInteger;
+const MessageComposeResultCancelled: MessageComposeResult = 0;
+
+#[derive(Default)]
+struct MFMessageComposeViewControllerHostObject {
+    superclass: UINavigationControllerHostObject,
+    /// Weak reference.
+    message_compose_delegate: id,
+    /// `NSArray*` of `NSString*`
+    recipients: id,
+    /// `NSString*`
+    body: id,
+}
+impl_HostObject_with_superclass!(MFMessageComposeViewControllerHostObject);
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation MFMessageComposeViewController: UINavigationController
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<MFMessageComposeViewControllerHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (bool)canSendText {
+    env.options.can_send_messages
+}
+
+- (())dealloc {
+    let &MFMessageComposeViewControllerHostObject { recipients, body, .. } = env.objc.borrow(this);
+    release(env, recipients);
+    release(env, body);
+    msg_super![env; this dealloc]
+}
+
+- (id)messageComposeDelegate {
+    env.objc.borrow::<MFMessageComposeViewControllerHostObject>(this).message_compose_delegate
+}
+- (())setMessageComposeDelegate:(id)delegate {
+    env.objc.borrow_mut::<MFMessageComposeViewControllerHostObject>(this).message_compose_delegate = delegate;
+}
+
+// Unlike the mail composer, these properties can be read back, so they are
+// stored even though the message can't be sent.
+
+- (id)recipients {
+    env.objc.borrow::<MFMessageComposeViewControllerHostObject>(this).recipients
+}
+- (())setRecipients:(id)recipients { // NSArray*
+    let recipients: id = msg![env; recipients copy];
+    let host_object = env.objc.borrow_mut::<MFMessageComposeViewControllerHostObject>(this);
+    let old = std::mem::replace(&mut host_object.recipients, recipients);
+    release(env, old);
+}
+- (id)body {
+    env.objc.borrow::<MFMessageComposeViewControllerHostObject>(this).body
+}
+- (())setBody:(id)body { // NSString*
+    let body: id = msg![env; body copy];
+    let host_object = env.objc.borrow_mut::<MFMessageComposeViewControllerHostObject>(this);
+    let old = std::mem::replace(&mut host_object.body, body);
+    release(env, old);
+}
+
+- (())loadView {
+    () = msg_super![env; this loadView];
+    add_placeholder_content(env, this, "New Text Message");
+}
+
+- (())_touchHLE_cancel:(id)_sender {
+    let delegate = env.objc.borrow::<MFMessageComposeViewControllerHostObject>(this).message_compose_delegate;
+    if delegate_responds_to(env, delegate, "messageComposeViewController:didFinishWithResult:") {
+        // The delegate is responsible for dismissing the composer, and might
+        // release it in the process.
+        retain(env, this);
+        () = msg![env; delegate messageComposeViewController:this
+                                         didFinishWithResult:MessageComposeResultCancelled];
+        release(env, this);
+    } else {
+        log!("Warning: {:?} has no delegate to dismiss it, dismissing it anyway", this);
+        () = msg![env; this dismissModalViewControllerAnimated:true];
+    }
+}
+
+@end
+
+};
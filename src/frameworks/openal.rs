@@ -17,6 +17,7 @@ use crate::audio::openal::alc_types::*;
 use crate::dyld::{export_c_func, FunctionExports};
 use crate::libc::string::strcmp;
 use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, MutPtr, MutVoidPtr, Ptr, SafeWrite};
+use crate::recording;
 use crate::Environment;
 use std::collections::HashMap;
 use std::ffi::{CStr, CString};
@@ -26,11 +27,73 @@ use touchHLE_openal_soft_wrapper::ALC_DEVICE_SPECIFIER;
 pub struct State {
     devices: HashMap<MutPtr<GuestALCdevice>, *mut ALCdevice>,
     contexts: HashMap<MutPtr<GuestALCcontext>, *mut ALCcontext>,
+    /// The app's own values for properties that are scaled while
+    /// fast-forwarding, for each context. See [apply_fast_forward].
+    unscaled: HashMap<*mut ALCcontext, UnscaledProperties>,
 }
 impl State {
     fn get(env: &mut Environment) -> &mut Self {
         &mut env.framework_state.openal
     }
+    fn current_unscaled(env: &mut Environment) -> Option<&mut UnscaledProperties> {
+        let host_context = unsafe { al::alcGetCurrentContext() };
+        State::get(env).unscaled.get_mut(&host_context)
+    }
+}
+
+struct UnscaledProperties {
+    /// `AL_PITCH` of each source.
+    source_pitches: HashMap<ALuint, ALfloat>,
+    /// `AL_GAIN` of the listener.
+    listener_gain: ALfloat,
+}
+impl Default for UnscaledProperties {
+    fn default() -> Self {
+        UnscaledProperties {
+            source_pitches: HashMap::new(),
+            listener_gain: 1.0,
+        }
+    }
+}
+
+fn current_pitch_and_gain(env: &Environment) -> (ALfloat, ALfloat) {
+    let (pitch, gain) = env
+        .clock
+        .audio_pitch_and_gain(env.options.fast_forward_audio);
+    (
+        pitch,
+        gain * env.framework_state.audio_toolbox.output_volume(),
+    )
+}
+
+/// Pause or resume mixing for all of the app's OpenAL devices. This is used
+/// to implement the audio session being inactive or interrupted.
+pub fn set_devices_paused(env: &mut Environment, paused: bool) {
+    for &device in State::get(env).devices.values() {
+        recording::set_al_device_paused(device, paused);
+    }
+}
+
+/// For use by [Environment::toggle_fast_forward]: update the pitch and gain of
+/// all the app's OpenAL sources and listeners to match the current speed (and
+/// output volume, see
+/// [crate::frameworks::audio_toolbox::audio_session::set_output_volume]).
+///
+/// Audio has to be played faster while fast-forwarding, because the app will
+/// be producing it faster. The app never sees the scaled values.
+pub fn apply_fast_forward(env: &mut Environment) {
+    let (pitch_scale, gain_scale) = current_pitch_and_gain(env);
+    let old_context = unsafe { al::alcGetCurrentContext() };
+    for (&host_context, unscaled) in State::get(env).unscaled.iter() {
+        unsafe {
+            al::alcMakeContextCurrent(host_context);
+            for (&source, &pitch) in unscaled.source_pitches.iter() {
+                al::alSourcef(source, al::AL_PITCH, pitch * pitch_scale);
+            }
+            al::alListenerf(al::AL_GAIN, unscaled.listener_gain * gain_scale);
+        }
+    }
+    unsafe { al::alcMakeContextCurrent(old_context) };
 }
 
 /// Opaque type in guest memory standing in for [ALCdevice] in host memory.
@@ -56,12 +119,16 @@ fn alcOpenDevice(env: &mut Environment, devicename: ConstPtr<u8>) -> MutPtr<Gues
         env.mem.free(d_name.cast_mut().cast());
     }
 
-    let res = unsafe { al::alcOpenDevice(std::ptr::null()) };
+    let res = recording::open_al_device();
     if res.is_null() {
         log_dbg!("alcOpenDevice(NULL) returned NULL");
         return Ptr::null();
     }
 
+    if !env.framework_state.audio_toolbox.output_enabled() {
+        recording::set_al_device_paused(res, true);
+    }
+
     let guest_res = env.mem.alloc_and_write(GuestALCdevice { _filler: 0 });
     State::get(env).devices.insert(guest_res, res);
     log_dbg!("alcOpenDevice(NULL) => {:?} (host: {:?})", guest_res, res,);
@@ -70,7 +137,7 @@ fn alcOpenDevice(env: &mut Environment, devicename: ConstPtr<u8>) -> MutPtr<Gues
 fn alcCloseDevice(env: &mut Environment, device: MutPtr<GuestALCdevice>) -> bool {
     let host_device = State::get(env).devices.remove(&device).unwrap();
     env.mem.free(device.cast());
-    let res = unsafe { al::alcCloseDevice(host_device) };
+    let res = recording::close_al_device(host_device);
     log_dbg!("alcCloseDevice({:?}) => {:?}", device, res,);
     res != al::ALC_FALSE
 }
@@ -106,7 +173,7 @@ fn alcCreateContext(
 
     let &host_device = State::get(env).devices.get(&device).unwrap();
 
-    let res = unsafe { al::alcCreateContext(host_device, std::ptr::null()) };
+    let res = recording::create_al_context(host_device);
     if res.is_null() {
         log_dbg!("alcCreateContext({:?}, NULL) returned NULL", device);
         return Ptr::null();
@@ -114,6 +181,15 @@ fn alcCreateContext(
 
     let guest_res = env.mem.alloc_and_write(GuestALCcontext { _filler: 0 });
     State::get(env).contexts.insert(guest_res, res);
+    State::get(env).unscaled.insert(res, Default::default());
+    // The listener gain might need scaling right away.
+    let old_context = unsafe { al::alcGetCurrentContext() };
+    let (_, gain_scale) = current_pitch_and_gain(env);
+    unsafe {
+        al::alcMakeContextCurrent(res);
+        al::alListenerf(al::AL_GAIN, gain_scale);
+        al::alcMakeContextCurrent(old_context);
+    }
     log_dbg!(
         "alcCreateContext({:?}, NULL) => {:?} (host: {:?})",
         device,
@@ -124,6 +200,7 @@ fn alcCreateContext(
 }
 fn alcDestroyContext(env: &mut Environment, context: MutPtr<GuestALCcontext>) {
     let host_context = State::get(env).contexts.remove(&context).unwrap();
+    State::get(env).unscaled.remove(&host_context);
     env.mem.free(context.cast());
     unsafe { al::alcDestroyContext(host_context) };
     log_dbg!("alcDestroyContext({:?})", context);
@@ -246,10 +323,22 @@ fn alIsSource(_env: &mut Environment, source: ALuint) -> ALboolean {
     unsafe { al::alIsSource(source) }
 }
 
-fn alListenerf(_env: &mut Environment, param: ALenum, value: ALfloat) {
+fn alListenerf(env: &mut Environment, param: ALenum, value: ALfloat) {
+    if param == al::AL_GAIN {
+        if let Some(unscaled) = State::current_unscaled(env) {
+            unscaled.listener_gain = value;
+        }
+        let (_, gain_scale) = current_pitch_and_gain(env);
+        unsafe { al::alListenerf(param, value * gain_scale) };
+        return;
+    }
     unsafe { al::alListenerf(param, value) };
 }
 fn alListenerfv(env: &mut Environment, param: ALenum, values: ConstPtr<ALfloat>) {
+    if param == al::AL_GAIN {
+        let value = env.mem.read(values);
+        return alListenerf(env, param, value);
+    }
     // we assume that at least 1 parameter should be passed
     let values = env.mem.ptr_at(values, 1);
     unsafe { al::alListenerfv(param, values) };
@@ -283,6 +372,12 @@ fn alListeneriv(env: &mut Environment, param: ALenum, values: ConstPtr<ALint>) {
 }
 
 fn alGetListenerf(env: &mut Environment, param: ALenum, value: MutPtr<ALfloat>) {
+    if param == al::AL_GAIN {
+        if let Some(&mut UnscaledProperties { listener_gain, .. }) = State::current_unscaled(env) {
+            env.mem.write(value, listener_gain);
+            return;
+        }
+    }
     unsafe { al::alGetListenerf(param, env.mem.ptr_at_mut(value, 1)) };
 }
 fn alGetListener3f(
@@ -300,6 +395,9 @@ fn alGetListener3f(
     env.mem.write(value3, values[2]);
 }
 fn alGetListenerfv(env: &mut Environment, param: ALenum, values: MutPtr<ALfloat>) {
+    if param == al::AL_GAIN {
+        return alGetListenerf(env, param, values);
+    }
     let values = env.mem.ptr_at_mut(values, 3); // upper bound
     unsafe { al::alGetListenerfv(param, values) };
 }
@@ -327,19 +425,53 @@ fn alGetListeneriv(env: &mut Environment, param: ALenum, values: MutPtr<ALint>)
 
 fn alGenSources(env: &mut Environment, n: ALsizei, sources: MutPtr<ALuint>) {
     let n_usize: GuestUSize = n.try_into().unwrap();
-    let sources = env.mem.ptr_at_mut(sources, n_usize);
-    unsafe { al::alGenSources(n, sources) };
+    let sources_ptr = env.mem.ptr_at_mut(sources, n_usize);
+    unsafe { al::alGenSources(n, sources_ptr) };
+
+    let (pitch_scale, _) = current_pitch_and_gain(env);
+    for i in 0..n_usize {
+        let source = env.mem.read(sources + i);
+        // Checking this rather than alGetError() avoids clearing the error.
+        if unsafe { al::alIsSource(source) } == 0 {
+            continue;
+        }
+        if let Some(unscaled) = State::current_unscaled(env) {
+            unscaled.source_pitches.insert(source, 1.0);
+        }
+        if pitch_scale != 1.0 {
+            unsafe { al::alSourcef(source, al::AL_PITCH, pitch_scale) };
+        }
+    }
 }
 fn alDeleteSources(env: &mut Environment, n: ALsizei, sources: ConstPtr<ALuint>) {
     let n_usize: GuestUSize = n.try_into().unwrap();
-    let sources = env.mem.ptr_at(sources, n_usize);
-    unsafe { al::alDeleteSources(n, sources) };
-}
-
-fn alSourcef(_env: &mut Environment, source: ALuint, param: ALenum, value: ALfloat) {
+    let source_names: Vec<ALuint> = (0..n_usize).map(|i| env.mem.read(sources + i)).collect();
+    if let Some(unscaled) = State::current_unscaled(env) {
+        for source in &source_names {
+            unscaled.source_pitches.remove(source);
+        }
+    }
+    unsafe { al::alDeleteSources(n, source_names.as_ptr()) };
+}
+
+fn alSourcef(env: &mut Environment, source: ALuint, param: ALenum, value: ALfloat) {
+    if param == al::AL_PITCH {
+        if let Some(unscaled) = State::current_unscaled(env) {
+            if let Some(pitch) = unscaled.source_pitches.get_mut(&source) {
+                *pitch = value;
+            }
+        }
+        let (pitch_scale, _) = current_pitch_and_gain(env);
+        unsafe { al::alSourcef(source, param, value * pitch_scale) };
+        return;
+    }
     unsafe { al::alSourcef(source, param, value) };
 }
 fn alSourcefv(env: &mut Environment, source: ALuint, param: ALenum, values: ConstPtr<ALfloat>) {
+    if param == al::AL_PITCH {
+        let value = env.mem.read(values);
+        return alSourcef(env, source, param, value);
+    }
     // we assume that at least 1 parameter should be passed
     let values = env.mem.ptr_at(values, 1);
     unsafe { al::alSourcefv(source, param, values) };
@@ -373,6 +505,14 @@ fn alSourceiv(env: &mut Environment, source: ALuint, param: ALenum, values: Cons
 }
 
 fn alGetSourcef(env: &mut Environment, source: ALuint, param: ALenum, value: MutPtr<ALfloat>) {
+    if param == al::AL_PITCH {
+        let pitch = State::current_unscaled(env)
+            .and_then(|unscaled| unscaled.source_pitches.get(&source).copied());
+        if let Some(pitch) = pitch {
+            env.mem.write(value, pitch);
+            return;
+        }
+    }
     unsafe { al::alGetSourcef(source, param, env.mem.ptr_at_mut(value, 1)) };
 }
 fn alGetSource3f(
@@ -398,6 +538,9 @@ fn alGetSource3f(
     env.mem.write(value3, values[2]);
 }
 fn alGetSourcefv(env: &mut Environment, source: ALuint, param: ALenum, values: MutPtr<ALfloat>) {
+    if param == al::AL_PITCH {
+        return alGetSourcef(env, source, param, values);
+    }
     let values = env.mem.ptr_at_mut(values, 3); // upper bound
     unsafe { al::alGetSourcefv(source, param, values) };
 }
@@ -475,12 +618,21 @@ fn alSourceUnqueueBuffers(
     // Limiting the number dequeued seems to be an effective workaround for the
     // apps that have been tested. That sample code isn't interested in actually
     // using the returned buffer IDs, so it's no problem that we write too few.
-    let buffers_processed = {
-        let mut val = 0;
-        unsafe { al::alGetSourcei(source, al::AL_BUFFERS_PROCESSED, &mut val) };
-        val
+    //
+    // The workaround is only applied for that exact pattern (unqueueing every
+    // queued buffer), so that apps streaming audio through a buffer queue get
+    // the AL_INVALID_VALUE error the spec requires if they try to unqueue
+    // more buffers than have been processed.
+    let (buffers_queued, buffers_processed) = {
+        let mut queued = 0;
+        let mut processed = 0;
+        unsafe {
+            al::alGetSourcei(source, al::AL_BUFFERS_QUEUED, &mut queued);
+            al::alGetSourcei(source, al::AL_BUFFERS_PROCESSED, &mut processed);
+        };
+        (queued, processed)
     };
-    let nb = if buffers_processed < nb {
+    let nb = if buffers_processed < nb && nb == buffers_queued {
         log_dbg!("Applying workaround for Apple sample code bug: ignoring unqueueing of {}/{} processed buffers from source {}", nb, buffers_processed, source);
         buffers_processed
     } else {
@@ -600,11 +752,15 @@ fn alcIsExtensionPresent(
 ) -> ALCboolean {
     0
 }
-fn alGetBufferf(_env: &mut Environment, _buffer: ALuint, _param: ALenum, _value: MutPtr<ALfloat>) {
-    todo!();
+fn alGetBufferf(env: &mut Environment, buffer: ALuint, param: ALenum, value: MutPtr<ALfloat>) {
+    unsafe { al::alGetBufferf(buffer, param, env.mem.ptr_at_mut(value, 1)) };
 }
-fn alGetBufferi(_env: &mut Environment, _buffer: ALuint, _param: ALenum, _value: MutPtr<ALint>) {
-    todo!();
+fn alGetBufferi(env: &mut Environment, buffer: ALuint, param: ALenum, value: MutPtr<ALint>) {
+    unsafe { al::alGetBufferi(buffer, param, env.mem.ptr_at_mut(value, 1)) };
+}
+fn alGetBufferiv(env: &mut Environment, buffer: ALuint, param: ALenum, values: MutPtr<ALint>) {
+    // All the buffer parameters in OpenAL 1.1 are single values.
+    unsafe { al::alGetBufferiv(buffer, param, env.mem.ptr_at_mut(values, 1)) };
 }
 fn alEnable(_env: &mut Environment, _capability: ALenum) {
     todo!();
@@ -648,17 +804,25 @@ fn alIsExtensionPresent(_env: &mut Environment, _extName: ConstPtr<u8>) -> ALboo
 fn alIsEnabled(_env: &mut Environment, _capability: ALenum) -> ALboolean {
     todo!();
 }
-fn alSourcePlayv(_env: &mut Environment, _nsources: ALsizei, _sources: ConstPtr<ALuint>) {
-    todo!();
+fn alSourcePlayv(env: &mut Environment, nsources: ALsizei, sources: ConstPtr<ALuint>) {
+    let n_usize: GuestUSize = nsources.try_into().unwrap();
+    let sources = env.mem.ptr_at(sources, n_usize);
+    unsafe { al::alSourcePlayv(nsources, sources) };
 }
-fn alSourcePausev(_env: &mut Environment, _nsources: ALsizei, _sources: ConstPtr<ALuint>) {
-    todo!();
+fn alSourcePausev(env: &mut Environment, nsources: ALsizei, sources: ConstPtr<ALuint>) {
+    let n_usize: GuestUSize = nsources.try_into().unwrap();
+    let sources = env.mem.ptr_at(sources, n_usize);
+    unsafe { al::alSourcePausev(nsources, sources) };
 }
-fn alSourceStopv(_env: &mut Environment, _nsources: ALsizei, _sources: ConstPtr<ALuint>) {
-    todo!();
+fn alSourceStopv(env: &mut Environment, nsources: ALsizei, sources: ConstPtr<ALuint>) {
+    let n_usize: GuestUSize = nsources.try_into().unwrap();
+    let sources = env.mem.ptr_at(sources, n_usize);
+    unsafe { al::alSourceStopv(nsources, sources) };
 }
-fn alSourceRewindv(_env: &mut Environment, _nsources: ALsizei, _sources: ConstPtr<ALuint>) {
-    todo!();
+fn alSourceRewindv(env: &mut Environment, nsources: ALsizei, sources: ConstPtr<ALuint>) {
+    let n_usize: GuestUSize = nsources.try_into().unwrap();
+    let sources = env.mem.ptr_at(sources, n_usize);
+    unsafe { al::alSourceRewindv(nsources, sources) };
 }
 
 pub const FUNCTIONS: FunctionExports = &[
@@ -720,6 +884,7 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(alIsBuffer(_)),
     export_c_func!(alGetBufferf(_, _, _)),
     export_c_func!(alGetBufferi(_, _, _)),
+    export_c_func!(alGetBufferiv(_, _, _)),
     export_c_func!(alEnable(_)),
     export_c_func!(alDisable(_)),
     export_c_func!(alDopplerFactor(_)),
@@ -5,7 +5,9 @@
  */
 //! EAGL.
 
+use super::gles_guest::RenderbufferStorage;
 use crate::dyld::{ConstantExports, HostConstant};
+use crate::environment::SpeedIndicator;
 use crate::frameworks::core_animation::ca_eagl_layer::{
     find_fullscreen_eagl_layer, get_pixels_vec_for_presenting, present_pixels,
 };
@@ -17,8 +19,11 @@ use crate::gles::present::{present_frame, FpsCounter};
 use crate::gles::{create_gles1_ctx, gles1_on_gl2, GLES};
 use crate::objc::{id, msg, nil, objc_classes, release, retain, ClassExports, HostObject};
 use crate::options::Options;
+use crate::recording;
 use crate::window::Window;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 use std::time::{Duration, Instant};
 
 // These are used by the EAGLDrawable protocol implemented by CAEAGLayer.
@@ -62,6 +67,15 @@ pub(super) struct EAGLContextHostObject {
     renderbuffer_drawable_bindings: HashMap<GLuint, id>,
     fps_counter: Option<FpsCounter>,
     next_frame_due: Option<Instant>,
+    /// The viewport the app last set, before the scale hack was applied. See
+    /// `glViewport` and `glGetIntegerv`.
+    pub(super) app_viewport: Option<[GLint; 4]>,
+    /// The scissor box the app last set, before the scale hack was applied.
+    pub(super) app_scissor_box: Option<[GLint; 4]>,
+    /// Storage requested for renderbuffers, for the scale hack. Renderbuffer
+    /// names belong to the sharegroup, so this is shared with the other
+    /// contexts in it.
+    pub(super) renderbuffer_storage: Rc<RefCell<HashMap<GLuint, RenderbufferStorage>>>,
 }
 impl HostObject for EAGLContextHostObject {}
 
@@ -77,6 +91,9 @@ pub const CLASSES: ClassExports = objc_classes! {
         renderbuffer_drawable_bindings: HashMap::new(),
         fps_counter: None,
         next_frame_due: None,
+        app_viewport: None,
+        app_scissor_box: None,
+        renderbuffer_storage: Default::default(),
     });
     env.objc.alloc_object(this, host_object, &mut env.mem)
 }
@@ -123,6 +140,8 @@ pub const CLASSES: ClassExports = objc_classes! {
 
     env.window.as_mut().unwrap().set_share_with_current_context(true);
     let res: id = msg![env; this initWithAPI:api];
+    let renderbuffer_storage = env.objc.borrow::<EAGLContextHostObject>(group).renderbuffer_storage.clone();
+    env.objc.borrow_mut::<EAGLContextHostObject>(this).renderbuffer_storage = renderbuffer_storage;
     // Setting current_ctx_thread to None should cause sync_context to
     // switch back to the right context if the app makes an OpenGL ES call.
     // (it's already done in initWithAPI: but we want to be explicit here.)
@@ -208,6 +227,10 @@ pub const CLASSES: ClassExports = objc_classes! {
         renderbuffer as _
     };
 
+    // The renderbuffer's storage is no longer what glRenderbufferStorageOES()
+    // might have allocated before.
+    env.objc.borrow::<EAGLContextHostObject>(this).renderbuffer_storage.borrow_mut().remove(&renderbuffer);
+
     retain(env, drawable);
     let host_obj = env.objc.borrow_mut::<EAGLContextHostObject>(this);
     if let Some(old_drawable) = host_obj.renderbuffer_drawable_bindings.insert(
@@ -225,7 +248,8 @@ pub const CLASSES: ClassExports = objc_classes! {
 
     // The presented frame should be displayed ASAP, but the next one must be
     // delayed, so this needs to be checked before returning.
-    let sleep_for = limit_framerate(&mut env.objc.borrow_mut::<EAGLContextHostObject>(this).next_frame_due, &env.options);
+    let now = env.clock.now();
+    let sleep_for = limit_framerate(&mut env.objc.borrow_mut::<EAGLContextHostObject>(this).next_frame_due, now, &env.options);
 
     if env.options.print_fps {
         env
@@ -266,8 +290,24 @@ pub const CLASSES: ClassExports = objc_classes! {
         );
         // re-borrow
         let gles = super::sync_context(&mut env.framework_state.opengles, &mut env.objc, env.window.as_mut().unwrap(), env.current_thread);
+        let recorded_frame = recording::wants_frame(env).then(|| unsafe {
+            read_renderbuffer(gles, Vec::new())
+        });
+        let speed_indicator = env.clock.speed_indicator();
+        let volume_indicator = env.framework_state.audio_toolbox.volume_indicator();
+        // re-borrow
+        let gles = super::sync_context(&mut env.framework_state.opengles, &mut env.objc, env.window.as_mut().unwrap(), env.current_thread);
         unsafe {
-            present_renderbuffer(gles, env.window.as_mut().unwrap());
+            present_renderbuffer(
+                gles,
+                env.window.as_mut().unwrap(),
+                speed_indicator,
+                volume_indicator,
+            );
+        }
+        env.frame_presented();
+        if let Some((pixels_vec, width, height)) = recorded_frame {
+            recording::frame_presented(env, pixels_vec, width, height);
         }
     } else {
         if fullscreen_layer != nil {
@@ -335,7 +375,11 @@ pub const CLASSES: ClassExports = objc_classes! {
 /// an interval's worth of accumulated slop. Allowing infinite accumulation of
 /// slop is not desirable, because if the game is running slowly for a long time
 /// and suddenly speeds back up, it will then run too fast for a long time.
-fn limit_framerate(next_frame_due: &mut Option<Instant>, options: &Options) -> Option<Duration> {
+fn limit_framerate(
+    next_frame_due: &mut Option<Instant>,
+    now: Instant,
+    options: &Options,
+) -> Option<Duration> {
     let interval = if let Some(fps) = options.fps_limit {
         1.0 / fps
     } else {
@@ -345,11 +389,10 @@ fn limit_framerate(next_frame_due: &mut Option<Instant>, options: &Options) -> O
 
     let &mut Some(current_frame_due) = next_frame_due else {
         // First frame presented: no delay yet.
-        *next_frame_due = Some(Instant::now() + interval_rust);
+        *next_frame_due = Some(now + interval_rust);
         return None;
     };
 
-    let now = Instant::now();
     *next_frame_due = if now > current_frame_due + interval_rust {
         // Too much slop has accumulated. Make the next frame wait for the next
         // interval.
@@ -502,7 +545,12 @@ unsafe fn read_renderbuffer(gles: &mut dyn GLES, mut pixel_buffer: Vec<u8>) -> (
 /// doing so. The front and back buffers are then swapped.
 ///
 /// The provided context must be current.
-unsafe fn present_renderbuffer(gles: &mut dyn GLES, window: &mut Window) {
+unsafe fn present_renderbuffer(
+    gles: &mut dyn GLES,
+    window: &mut Window,
+    speed_indicator: Option<SpeedIndicator>,
+    volume_indicator: Option<f32>,
+) {
     // We can't directly copy the content of the renderbuffer to the default
     // framebuffer (the window), but if we attach it to a framebuffer object, we
     // can use glCopyTexImage2D() to copy it to a texture, which we can then
@@ -625,6 +673,9 @@ unsafe fn present_renderbuffer(gles: &mut dyn GLES, window: &mut Window) {
         window.viewport(),
         window.rotation_matrix(),
         window.virtual_cursor_visible_at(),
+        &window.touch_indicators_visible_at(),
+        speed_indicator,
+        volume_indicator,
     );
 
     // Clean up the texture
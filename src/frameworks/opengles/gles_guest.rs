@@ -15,7 +15,7 @@
 use crate::dyld::{export_c_func, FunctionExports};
 use crate::gles::gles11_raw as gles11; // constants only
 use crate::gles::GLES;
-use crate::mem::{ConstPtr, ConstVoidPtr, GuestISize, GuestUSize, Mem, MutPtr, Ptr};
+use crate::mem::{ConstPtr, ConstVoidPtr, GuestISize, GuestUSize, Mem, MutPtr, MutVoidPtr, Ptr};
 use crate::Environment;
 
 // These types are the same size in guest code (32-bit) and host code (64-bit).
@@ -69,6 +69,143 @@ where
     res
 }
 
+/// Storage requested by the app for a renderbuffer with
+/// `glRenderbufferStorageOES`. This is only tracked when the scale hack is
+/// active.
+#[derive(Copy, Clone)]
+pub(super) struct RenderbufferStorage {
+    internalformat: GLenum,
+    width: GLsizei,
+    height: GLsizei,
+    /// Whether the real storage has been enlarged by the scale hack.
+    scaled: bool,
+}
+
+/// Whether the scale hack applies to the currently bound framebuffer.
+///
+/// The scale hack enlarges renderbuffers, including the one backing the
+/// `CAEAGLLayer`, but textures keep the size the app asked for, so a
+/// framebuffer rendering to a texture must not be scaled.
+unsafe fn bound_framebuffer_is_scaled(gles: &mut dyn GLES) -> bool {
+    let mut framebuffer = 0;
+    gles.GetIntegerv(gles11::FRAMEBUFFER_BINDING_OES, &mut framebuffer);
+    if framebuffer == 0 {
+        return true;
+    }
+    let mut object_type = 0;
+    gles.GetFramebufferAttachmentParameterivOES(
+        gles11::FRAMEBUFFER_OES,
+        gles11::COLOR_ATTACHMENT0_OES,
+        gles11::FRAMEBUFFER_ATTACHMENT_OBJECT_TYPE_OES,
+        &mut object_type,
+    );
+    object_type as GLenum != gles11::TEXTURE
+}
+
+/// Get the scale hack factor that applies to coordinates within the currently
+/// bound framebuffer (see [bound_framebuffer_is_scaled]).
+fn framebuffer_scale_factor(env: &mut Environment) -> GLsizei {
+    let factor = env.options.scale_hack.get() as GLsizei;
+    if factor == 1 {
+        return 1;
+    }
+    let scaled = with_ctx_and_mem(env, |gles, _mem| unsafe {
+        bound_framebuffer_is_scaled(gles)
+    });
+    if scaled {
+        factor
+    } else {
+        1
+    }
+}
+
+/// Make the sizes of the renderbuffers attached to the currently bound
+/// framebuffer consistent with whether that framebuffer is scaled, so that
+/// e.g. a depth renderbuffer attached alongside a texture isn't larger than
+/// the texture, which would make the framebuffer incomplete.
+///
+/// This is needed because the app allocates renderbuffer storage before it
+/// says what the renderbuffer will be attached to.
+fn update_renderbuffer_scaling(env: &mut Environment) {
+    let factor = env.options.scale_hack.get() as GLsizei;
+    if factor == 1 {
+        return;
+    }
+
+    let (scaled, attached) = with_ctx_and_mem(env, |gles, _mem| unsafe {
+        let scaled = bound_framebuffer_is_scaled(gles);
+        let mut attached = Vec::new();
+        for attachment in [
+            gles11::COLOR_ATTACHMENT0_OES,
+            gles11::DEPTH_ATTACHMENT_OES,
+            gles11::STENCIL_ATTACHMENT_OES,
+        ] {
+            let mut object_type = 0;
+            gles.GetFramebufferAttachmentParameterivOES(
+                gles11::FRAMEBUFFER_OES,
+                attachment,
+                gles11::FRAMEBUFFER_ATTACHMENT_OBJECT_TYPE_OES,
+                &mut object_type,
+            );
+            if object_type as GLenum != gles11::RENDERBUFFER_OES {
+                continue;
+            }
+            let mut name = 0;
+            gles.GetFramebufferAttachmentParameterivOES(
+                gles11::FRAMEBUFFER_OES,
+                attachment,
+                gles11::FRAMEBUFFER_ATTACHMENT_OBJECT_NAME_OES,
+                &mut name,
+            );
+            attached.push(name as GLuint);
+        }
+        (scaled, attached)
+    });
+
+    let storage_map = current_ctx_host_object(env).renderbuffer_storage.clone();
+    for renderbuffer in attached {
+        let mut storage_map = storage_map.borrow_mut();
+        let Some(storage) = storage_map.get_mut(&renderbuffer) else {
+            // Not allocated by glRenderbufferStorageOES, e.g. the renderbuffer
+            // backing a CAEAGLLayer, which is always scaled.
+            continue;
+        };
+        if storage.scaled == scaled {
+            continue;
+        }
+        storage.scaled = scaled;
+        let storage = *storage;
+        drop(storage_map);
+        log_dbg!(
+            "Reallocating renderbuffer {} {} scale hack",
+            renderbuffer,
+            if scaled { "with" } else { "without" }
+        );
+        with_ctx_and_mem(env, |gles, _mem| unsafe {
+            allocate_renderbuffer_storage(gles, renderbuffer, storage, factor)
+        });
+    }
+}
+
+unsafe fn allocate_renderbuffer_storage(
+    gles: &mut dyn GLES,
+    renderbuffer: GLuint,
+    storage: RenderbufferStorage,
+    factor: GLsizei,
+) {
+    let factor = if storage.scaled { factor } else { 1 };
+    let mut old_renderbuffer = 0;
+    gles.GetIntegerv(gles11::RENDERBUFFER_BINDING_OES, &mut old_renderbuffer);
+    gles.BindRenderbufferOES(gles11::RENDERBUFFER_OES, renderbuffer);
+    gles.RenderbufferStorageOES(
+        gles11::RENDERBUFFER_OES,
+        storage.internalformat,
+        storage.width * factor,
+        storage.height * factor,
+    );
+    gles.BindRenderbufferOES(gles11::RENDERBUFFER_OES, old_renderbuffer as _);
+}
+
 /// Useful for debugging
 #[allow(dead_code)]
 fn panic_on_gl_errors(gles: &mut dyn GLES) {
@@ -130,15 +267,67 @@ fn glGetBooleanv(env: &mut Environment, pname: GLenum, params: MutPtr<GLboolean>
         unsafe { gles.GetBooleanv(pname, params) };
     });
 }
+/// Get the app's own value of a parameter that the scale hack changes, if it
+/// has one. The app should see the values it set, which aren't necessarily the
+/// current values divided by the current framebuffer's scale factor, because
+/// a different framebuffer might have been bound when they were set.
+///
+/// The second value is the factor to divide the current value by, if the app
+/// hasn't set the parameter yet (its initial value comes from the size of the
+/// first framebuffer the context was used with).
+fn app_parameter_value(env: &mut Environment, pname: GLenum) -> (Option<[GLint; 4]>, GLsizei) {
+    if env.options.scale_hack.get() == 1 {
+        return (None, 1);
+    }
+    let value = match pname {
+        gles11::VIEWPORT => current_ctx_host_object(env).app_viewport,
+        gles11::SCISSOR_BOX => current_ctx_host_object(env).app_scissor_box,
+        _ => return (None, 1),
+    };
+    match value {
+        Some(value) => (Some(value), 1),
+        None => (None, framebuffer_scale_factor(env)),
+    }
+}
+
+fn current_ctx_host_object(env: &mut Environment) -> &mut super::eagl::EAGLContextHostObject {
+    let ctx = env
+        .framework_state
+        .opengles
+        .current_ctx_for_thread(env.current_thread)
+        .unwrap();
+    env.objc.borrow_mut(ctx)
+}
+
 fn glGetFloatv(env: &mut Environment, pname: GLenum, params: MutPtr<GLfloat>) {
     assert_ne!(gles11::NUM_COMPRESSED_TEXTURE_FORMATS, pname);
     assert_ne!(gles11::COMPRESSED_TEXTURE_FORMATS, pname);
+    let (app_value, factor) = app_parameter_value(env, pname);
+    if let Some(app_value) = app_value {
+        for (i, value) in app_value.into_iter().enumerate() {
+            env.mem.write(params + i as GuestUSize, value as GLfloat);
+        }
+        return;
+    }
     with_ctx_and_mem(env, |gles, mem| {
         let params = mem.ptr_at_mut(params, 16 /* upper bound */);
         unsafe { gles.GetFloatv(pname, params) };
+        if factor != 1 {
+            let params = unsafe { std::slice::from_raw_parts_mut(params, 4) };
+            for param in params {
+                *param /= factor as GLfloat;
+            }
+        }
     });
 }
 fn glGetIntegerv(env: &mut Environment, pname: GLenum, params: MutPtr<GLint>) {
+    let (app_value, factor) = app_parameter_value(env, pname);
+    if let Some(app_value) = app_value {
+        for (i, value) in app_value.into_iter().enumerate() {
+            env.mem.write(params + i as GuestUSize, value);
+        }
+        return;
+    }
     with_ctx_and_mem(env, |gles, mem| {
         match pname {
             gles11::NUM_COMPRESSED_TEXTURE_FORMATS => {
@@ -152,14 +341,27 @@ fn glGetIntegerv(env: &mut Environment, pname: GLenum, params: MutPtr<GLint>) {
             _ => {
                 let params = mem.ptr_at_mut(params, 16 /* upper bound */);
                 unsafe { gles.GetIntegerv(pname, params) };
+                if factor != 1 {
+                    let params = unsafe { std::slice::from_raw_parts_mut(params, 4) };
+                    for param in params {
+                        *param /= factor;
+                    }
+                }
             }
         }
     });
 }
 fn glGetPointerv(env: &mut Environment, pname: GLenum, params: MutPtr<ConstVoidPtr>) {
-    use crate::gles::gles1_on_gl2::{ArrayInfo, ARRAYS};
-    let &ArrayInfo { buffer_binding, .. } =
-        ARRAYS.iter().find(|info| info.pointer == pname).unwrap();
+    use crate::gles::gles1_on_gl2::ARRAYS;
+    let buffer_binding = if pname == gles11::POINT_SIZE_ARRAY_POINTER_OES {
+        gles11::POINT_SIZE_ARRAY_BUFFER_BINDING_OES
+    } else {
+        ARRAYS
+            .iter()
+            .find(|info| info.pointer == pname)
+            .unwrap()
+            .buffer_binding
+    };
     with_ctx_and_mem(env, |gles, mem| {
         // params always points to just one pointer for this function
         let mut host_pointer_or_offset = std::ptr::null();
@@ -282,7 +484,10 @@ fn glShadeModel(env: &mut Environment, mode: GLenum) {
 fn glScissor(env: &mut Environment, x: GLint, y: GLint, width: GLsizei, height: GLsizei) {
     // apply scale hack: assume framebuffer's size is larger than the app thinks
     // and scale scissor appropriately
-    let factor = env.options.scale_hack.get() as GLsizei;
+    let factor = framebuffer_scale_factor(env);
+    if env.options.scale_hack.get() != 1 {
+        current_ctx_host_object(env).app_scissor_box = Some([x, y, width, height]);
+    }
     let (x, y) = (x * factor, y * factor);
     let (width, height) = (width * factor, height * factor);
     with_ctx_and_mem(env, |gles, _mem| unsafe {
@@ -292,7 +497,10 @@ fn glScissor(env: &mut Environment, x: GLint, y: GLint, width: GLsizei, height:
 fn glViewport(env: &mut Environment, x: GLint, y: GLint, width: GLsizei, height: GLsizei) {
     // apply scale hack: assume framebuffer's size is larger than the app thinks
     // and scale viewport appropriately
-    let factor = env.options.scale_hack.get() as GLsizei;
+    let factor = framebuffer_scale_factor(env);
+    if env.options.scale_hack.get() != 1 {
+        current_ctx_host_object(env).app_viewport = Some([x, y, width, height]);
+    }
     let (x, y) = (x * factor, y * factor);
     let (width, height) = (width * factor, height * factor);
     with_ctx_and_mem(env, |gles, _mem| unsafe {
@@ -612,6 +820,18 @@ fn glVertexPointer(
         gles.VertexPointer(size, type_, stride, pointer)
     })
 }
+fn glPointSizePointerOES(
+    env: &mut Environment,
+    type_: GLenum,
+    stride: GLsizei,
+    pointer: ConstVoidPtr,
+) {
+    with_ctx_and_mem(env, |gles, mem| unsafe {
+        let pointer =
+            translate_pointer_or_offset_to_host(gles, mem, pointer, gles11::ARRAY_BUFFER_BINDING);
+        gles.PointSizePointerOES(type_, stride, pointer)
+    })
+}
 
 // Drawing
 fn glDrawArrays(env: &mut Environment, mode: GLenum, first: GLint, count: GLsizei) {
@@ -902,8 +1122,8 @@ fn glTexParameterxv(
         gles.TexParameterxv(target, pname, params)
     })
 }
-fn image_size_estimate(pixel_count: GuestUSize, format: GLenum, type_: GLenum) -> GuestUSize {
-    let bytes_per_pixel: GuestUSize = match type_ {
+fn bytes_per_pixel(format: GLenum, type_: GLenum) -> GuestUSize {
+    match type_ {
         gles11::UNSIGNED_BYTE => match format {
             gles11::ALPHA | gles11::LUMINANCE => 1,
             gles11::LUMINANCE_ALPHA => 2,
@@ -916,9 +1136,13 @@ fn image_size_estimate(pixel_count: GuestUSize, format: GLenum, type_: GLenum) -
         | gles11::UNSIGNED_SHORT_4_4_4_4
         | gles11::UNSIGNED_SHORT_5_5_5_1 => 2,
         _ => panic!("Unexpected type {:#x}", type_),
-    };
+    }
+}
+fn image_size_estimate(pixel_count: GuestUSize, format: GLenum, type_: GLenum) -> GuestUSize {
     // This is approximate, it doesn't account for alignment.
-    pixel_count.checked_mul(bytes_per_pixel).unwrap()
+    pixel_count
+        .checked_mul(bytes_per_pixel(format, type_))
+        .unwrap()
 }
 fn glTexImage2D(
     env: &mut Environment,
@@ -1001,6 +1225,69 @@ fn glCompressedTexImage2D(
         )
     })
 }
+fn glReadPixels(
+    env: &mut Environment,
+    x: GLint,
+    y: GLint,
+    width: GLsizei,
+    height: GLsizei,
+    format: GLenum,
+    type_: GLenum,
+    pixels: MutVoidPtr,
+) {
+    let factor = framebuffer_scale_factor(env);
+    with_ctx_and_mem(env, |gles, mem| unsafe {
+        let mut pack_alignment = 0;
+        gles.GetIntegerv(gles11::PACK_ALIGNMENT, &mut pack_alignment);
+        let pack_alignment: GuestUSize = pack_alignment.try_into().unwrap();
+        let bytes_per_pixel = bytes_per_pixel(format, type_);
+        let width_u: GuestUSize = width.try_into().unwrap();
+        let height_u: GuestUSize = height.try_into().unwrap();
+        let row_size = width_u
+            .checked_mul(bytes_per_pixel)
+            .unwrap()
+            .next_multiple_of(pack_alignment);
+        let size = row_size.checked_mul(height_u).unwrap();
+
+        if factor == 1 {
+            let pixels = mem.ptr_at_mut(pixels.cast::<u8>(), size);
+            gles.ReadPixels(x, y, width, height, format, type_, pixels.cast());
+            return;
+        }
+
+        // apply scale hack: the framebuffer is larger than the app thinks, so
+        // read the enlarged area and then keep one pixel from each block, so
+        // the app gets the resolution it expects.
+        let factor_u = factor as GuestUSize;
+        let scaled_row_size = (width_u * factor_u * bytes_per_pixel) as usize;
+        let mut scaled_pixels = vec![0u8; scaled_row_size * (height_u * factor_u) as usize];
+        gles.PixelStorei(gles11::PACK_ALIGNMENT, 1);
+        gles.ReadPixels(
+            x * factor,
+            y * factor,
+            width * factor,
+            height * factor,
+            format,
+            type_,
+            scaled_pixels.as_mut_ptr().cast(),
+        );
+        gles.PixelStorei(gles11::PACK_ALIGNMENT, pack_alignment as _);
+
+        let pixels = mem.bytes_at_mut(pixels.cast(), size);
+        let (row_size, bytes_per_pixel) = (row_size as usize, bytes_per_pixel as usize);
+        let factor = factor as usize;
+        for (row, dest_row) in pixels.chunks_mut(row_size).enumerate() {
+            let src_row = &scaled_pixels[row * factor * scaled_row_size..][..scaled_row_size];
+            for (col, dest_pixel) in dest_row[..width as usize * bytes_per_pixel]
+                .chunks_mut(bytes_per_pixel)
+                .enumerate()
+            {
+                let src_start = col * factor * bytes_per_pixel;
+                dest_pixel.copy_from_slice(&src_row[src_start..][..bytes_per_pixel]);
+            }
+        }
+    })
+}
 fn glCopyTexImage2D(
     env: &mut Environment,
     target: GLenum,
@@ -1103,12 +1390,37 @@ fn glRenderbufferStorageOES(
     width: GLsizei,
     height: GLsizei,
 ) {
-    // apply scale hack: give the app a larger framebuffer than it asked for
     let factor = env.options.scale_hack.get() as GLsizei;
-    let (width, height) = (width * factor, height * factor);
+    if factor == 1 {
+        with_ctx_and_mem(env, |gles, _mem| unsafe {
+            gles.RenderbufferStorageOES(target, internalformat, width, height)
+        });
+        return;
+    }
+
+    // apply scale hack: give the app a larger framebuffer than it asked for,
+    // unless the renderbuffer is used for rendering to a texture
+    assert!(target == gles11::RENDERBUFFER_OES);
+    let renderbuffer = with_ctx_and_mem(env, |gles, _mem| unsafe {
+        let mut renderbuffer = 0;
+        gles.GetIntegerv(gles11::RENDERBUFFER_BINDING_OES, &mut renderbuffer);
+        renderbuffer as GLuint
+    });
+    let storage = RenderbufferStorage {
+        internalformat,
+        width,
+        height,
+        scaled: true,
+    };
+    current_ctx_host_object(env)
+        .renderbuffer_storage
+        .borrow_mut()
+        .insert(renderbuffer, storage);
     with_ctx_and_mem(env, |gles, _mem| unsafe {
-        gles.RenderbufferStorageOES(target, internalformat, width, height)
-    })
+        allocate_renderbuffer_storage(gles, renderbuffer, storage, factor)
+    });
+    // The renderbuffer might already be attached to a framebuffer.
+    update_renderbuffer_scaling(env);
 }
 fn glFramebufferRenderbufferOES(
     env: &mut Environment,
@@ -1119,7 +1431,8 @@ fn glFramebufferRenderbufferOES(
 ) {
     with_ctx_and_mem(env, |gles, _mem| unsafe {
         gles.FramebufferRenderbufferOES(target, attachment, renderbuffertarget, renderbuffer)
-    })
+    });
+    update_renderbuffer_scaling(env);
 }
 fn glFramebufferTexture2DOES(
     env: &mut Environment,
@@ -1131,7 +1444,8 @@ fn glFramebufferTexture2DOES(
 ) {
     with_ctx_and_mem(env, |gles, _mem| unsafe {
         gles.FramebufferTexture2DOES(target, attachment, textarget, texture, level)
-    })
+    });
+    update_renderbuffer_scaling(env);
 }
 fn glGetFramebufferAttachmentParameterivOES(
     env: &mut Environment,
@@ -1152,6 +1466,21 @@ fn glGetRenderbufferParameterivOES(
     params: MutPtr<GLint>,
 ) {
     let factor = env.options.scale_hack.get() as GLint;
+    let renderbuffer = with_ctx_and_mem(env, |gles, _mem| unsafe {
+        let mut renderbuffer = 0;
+        gles.GetIntegerv(gles11::RENDERBUFFER_BINDING_OES, &mut renderbuffer);
+        renderbuffer as GLuint
+    });
+    // Renderbuffers not allocated by glRenderbufferStorageOES back a
+    // CAEAGLLayer, and are always scaled.
+    let factor = match current_ctx_host_object(env)
+        .renderbuffer_storage
+        .borrow()
+        .get(&renderbuffer)
+    {
+        Some(&RenderbufferStorage { scaled: false, .. }) => 1,
+        _ => factor,
+    };
     with_ctx_and_mem(env, |gles, mem| {
         let params = mem.ptr_at_mut(params, 1);
         unsafe { gles.GetRenderbufferParameterivOES(target, pname, params) };
@@ -1175,6 +1504,14 @@ fn glDeleteFramebuffersOES(env: &mut Environment, n: GLsizei, framebuffers: Cons
     })
 }
 fn glDeleteRenderbuffersOES(env: &mut Environment, n: GLsizei, renderbuffers: ConstPtr<GLuint>) {
+    let n_usize: GuestUSize = n.try_into().unwrap();
+    for i in 0..n_usize {
+        let renderbuffer = env.mem.read(renderbuffers + i);
+        current_ctx_host_object(env)
+            .renderbuffer_storage
+            .borrow_mut()
+            .remove(&renderbuffer);
+    }
     with_ctx_and_mem(env, |gles, mem| {
         let n_usize: GuestUSize = n.try_into().unwrap();
         let renderbuffers = mem.ptr_at(renderbuffers, n_usize);
@@ -1295,6 +1632,7 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(glNormalPointer(_, _, _)),
     export_c_func!(glTexCoordPointer(_, _, _, _)),
     export_c_func!(glVertexPointer(_, _, _, _)),
+    export_c_func!(glPointSizePointerOES(_, _, _)),
     // Drawing
     export_c_func!(glDrawArrays(_, _, _)),
     export_c_func!(glDrawElements(_, _, _, _)),
@@ -1340,6 +1678,7 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(glTexImage2D(_, _, _, _, _, _, _, _, _)),
     export_c_func!(glTexSubImage2D(_, _, _, _, _, _, _, _, _)),
     export_c_func!(glCompressedTexImage2D(_, _, _, _, _, _, _, _)),
+    export_c_func!(glReadPixels(_, _, _, _, _, _, _)),
     export_c_func!(glCopyTexImage2D(_, _, _, _, _, _, _, _)),
     export_c_func!(glCopyTexSubImage2D(_, _, _, _, _, _, _, _)),
     export_c_func!(glTexEnvf(_, _, _)),
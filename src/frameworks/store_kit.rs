@@ -3,6 +3,129 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
-//! StoreKit
+//! The StoreKit framework.
+//!
+//! touchHLE can't connect to the App Store, so in-app purchases are simulated
+//! according to `--in-app-purchases=`. Product requests always succeed, with
+//! products made up from the requested identifiers (see `--in-app-product=`).
+//! When purchases succeed, the purchased product identifiers are saved in the
+//! app's sandbox, so that restoring purchases works after a relaunch.
+//!
+//! Like on a real device, delegates and transaction observers are never called
+//! from within the method that caused the call, but later, from the run loop.
 
+pub mod sk_payment_queue;
 pub mod sk_product;
+pub mod sk_products_request;
+
+use crate::dyld::{ConstantExports, HostConstant};
+use crate::frameworks::foundation::{ns_string, NSInteger};
+use crate::objc::{id, msg, msg_class, nil};
+use crate::paths;
+use crate::Environment;
+use plist::{Dictionary, Value};
+use std::path::PathBuf;
+use std::time::Instant;
+
+#[derive(Default)]
+pub struct State {
+    /// `SKPaymentQueue*`
+    payment_queue: Option<id>,
+    /// `SKProductsRequest*`s that have been started, waiting for the run loop
+    /// to respond to them. Each is retained until it has been handled.
+    pending_requests: Vec<id>,
+    /// Identifiers of purchased products. Loaded from the sandbox when first
+    /// needed.
+    purchased_products: Option<Vec<String>>,
+}
+
+pub const SKErrorDomain: &str = "SKErrorDomain";
+
+type SKErrorCode = NSInteger;
+const SKErrorPaymentCancelled: SKErrorCode = 2;
+const SKErrorPaymentNotAllowed: SKErrorCode = 4;
+
+pub const CONSTANTS: ConstantExports = &[("_SKErrorDomain", HostConstant::NSString(SKErrorDomain))];
+
+/// Create an `NSError*` in [SKErrorDomain]. The caller owns the result.
+fn new_error(env: &mut Environment, code: SKErrorCode) -> id {
+    let domain = ns_string::get_static_str(env, SKErrorDomain);
+    let error: id = msg_class![env; NSError alloc];
+    msg![env; error initWithDomain:domain code:code userInfo:nil]
+}
+
+/// For use by `NSRunLoop`: respond to product requests and process payments.
+///
+/// Returns the time the next event is due, if any.
+pub fn handle_events(env: &mut Environment) -> Option<Instant> {
+    let requests_due = sk_products_request::handle_requests(env);
+    let queue_due = sk_payment_queue::handle_queue(env);
+    requests_due.into_iter().chain(queue_due).min()
+}
+
+fn purchased_products_path(env: &Environment) -> PathBuf {
+    paths::user_data_base_path()
+        .join(paths::SANDBOX_DIR)
+        .join(env.bundle.bundle_identifier())
+        .join("StoreKit.plist")
+}
+
+/// Get the identifiers of products that have been purchased, loading them from
+/// the sandbox first if necessary.
+fn purchased_products(env: &mut Environment) -> &[String] {
+    if env.framework_state.store_kit.purchased_products.is_none() {
+        let path = purchased_products_path(env);
+        let products = Value::from_file(&path)
+            .ok()
+            .and_then(|value| value.into_dictionary())
+            .and_then(|mut root| root.remove("PurchasedProducts"))
+            .and_then(|products| products.into_array())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|product| product.into_string())
+            .collect();
+        env.framework_state.store_kit.purchased_products = Some(products);
+    }
+    env.framework_state
+        .store_kit
+        .purchased_products
+        .as_ref()
+        .unwrap()
+}
+
+/// Remember that a product was purchased, and save this in the sandbox.
+fn add_purchased_product(env: &mut Environment, identifier: String) {
+    if purchased_products(env).contains(&identifier) {
+        return;
+    }
+    let path = purchased_products_path(env);
+    let products = env
+        .framework_state
+        .store_kit
+        .purchased_products
+        .as_mut()
+        .unwrap();
+    products.push(identifier);
+
+    let products = products.iter().cloned().map(Value::String).collect();
+    let mut root = Dictionary::new();
+    root.insert("PurchasedProducts".to_string(), Value::Array(products));
+    if let Err(e) = Value::Dictionary(root).to_file_xml(&path) {
+        log!(
+            "Warning: couldn't save in-app purchases to {:?}: {}",
+            path,
+            e
+        );
+    }
+}
+
+/// Check whether a delegate or observer implements a callback.
+fn delegate_responds_to(env: &mut Environment, delegate: id, callback: &str) -> bool {
+    if delegate == nil {
+        return false;
+    }
+    let sel = env
+        .objc
+        .register_host_selector(callback.to_string(), &mut env.mem);
+    msg![env; delegate respondsToSelector:sel]
+}
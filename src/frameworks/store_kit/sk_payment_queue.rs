@@ -0,0 +1,461 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `SKPaymentQueue`, `SKPayment` and `SKPaymentTransaction`.
+
+use super::{
+    add_purchased_product, delegate_responds_to, new_error, purchased_products,
+    SKErrorPaymentCancelled, SKErrorPaymentNotAllowed,
+};
+use crate::frameworks::foundation::{ns_array, ns_string, NSInteger};
+use crate::objc::{
+    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
+    NSZonePtr,
+};
+use crate::options::InAppPurchases;
+use crate::Environment;
+use std::time::Instant;
+
+type SKPaymentTransactionState = NSInteger;
+const SKPaymentTransactionStatePurchasing: SKPaymentTransactionState = 0;
+const SKPaymentTransactionStatePurchased: SKPaymentTransactionState = 1;
+const SKPaymentTransactionStateFailed: SKPaymentTransactionState = 2;
+const SKPaymentTransactionStateRestored: SKPaymentTransactionState = 3;
+
+struct SKPaymentHostObject {
+    /// `NSString*`
+    product_identifier: id,
+    quantity: NSInteger,
+}
+impl HostObject for SKPaymentHostObject {}
+
+struct SKPaymentTransactionHostObject {
+    /// `SKPayment*`
+    payment: id,
+    state: SKPaymentTransactionState,
+    /// `NSError*`, only for failed transactions.
+    error: id,
+    /// `NSString*`, `nil` until the transaction is purchased or restored.
+    identifier: id,
+    /// `NSDate*`, `nil` until the transaction is purchased or restored.
+    date: id,
+    /// `SKPaymentTransaction*`, only for restored transactions.
+    original: id,
+}
+impl HostObject for SKPaymentTransactionHostObject {}
+
+enum PaymentQueueEvent {
+    /// A transaction in the purchasing state.
+    Payment(id),
+    RestoreCompletedTransactions,
+}
+
+struct SKPaymentQueueHostObject {
+    /// Weak references.
+    observers: Vec<id>,
+    /// `SKPaymentTransaction*`s that haven't been finished yet. Each is
+    /// retained by the queue.
+    transactions: Vec<id>,
+    /// Events waiting to be handled by the run loop.
+    pending_events: Vec<PaymentQueueEvent>,
+    /// Used to make up transaction identifiers.
+    transaction_count: u64,
+}
+impl HostObject for SKPaymentQueueHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation SKPayment: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(SKPaymentHostObject {
+        product_identifier: nil,
+        quantity: 1,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
++ (id)paymentWithProductIdentifier:(id)identifier { // NSString*
+    let identifier: id = msg![env; identifier copy];
+    let new: id = msg![env; this new];
+    env.objc.borrow_mut::<SKPaymentHostObject>(new).product_identifier = identifier;
+    autorelease(env, new)
+}
+
++ (id)paymentWithProduct:(id)product { // SKProduct*
+    let identifier: id = msg![env; product productIdentifier];
+    msg![env; this paymentWithProductIdentifier:identifier]
+}
+
+- (())dealloc {
+    let product_identifier = env.objc.borrow::<SKPaymentHostObject>(this).product_identifier;
+    release(env, product_identifier);
+    env.objc.dealloc_object(this, &mut env.mem);
+}
+
+// NSCopying implementation
+- (id)copyWithZone:(NSZonePtr)_zone {
+    let &SKPaymentHostObject { product_identifier, quantity } = env.objc.borrow(this);
+    retain(env, product_identifier);
+    let new: id = msg_class![env; SKPayment new];
+    *env.objc.borrow_mut(new) = SKPaymentHostObject { product_identifier, quantity };
+    new
+}
+
+- (id)productIdentifier {
+    env.objc.borrow::<SKPaymentHostObject>(this).product_identifier
+}
+- (NSInteger)quantity {
+    env.objc.borrow::<SKPaymentHostObject>(this).quantity
+}
+- (id)requestData {
+    nil
+}
+
+@end
+
+@implementation SKMutablePayment: SKPayment
+
+- (())setProductIdentifier:(id)identifier { // NSString*
+    let identifier: id = msg![env; identifier copy];
+    let old = std::mem::replace(&mut env.objc.borrow_mut::<SKPaymentHostObject>(this).product_identifier, identifier);
+    release(env, old);
+}
+- (())setQuantity:(NSInteger)quantity {
+    env.objc.borrow_mut::<SKPaymentHostObject>(this).quantity = quantity;
+}
+- (())setRequestData:(id)_data { // NSData*
+}
+
+@end
+
+@implementation SKPaymentTransaction: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(SKPaymentTransactionHostObject {
+        payment: nil,
+        state: SKPaymentTransactionStatePurchasing,
+        error: nil,
+        identifier: nil,
+        date: nil,
+        original: nil,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (())dealloc {
+    let &SKPaymentTransactionHostObject {
+        payment,
+        error,
+        identifier,
+        date,
+        original,
+        ..
+    } = env.objc.borrow(this);
+    release(env, payment);
+    release(env, error);
+    release(env, identifier);
+    release(env, date);
+    release(env, original);
+    env.objc.dealloc_object(this, &mut env.mem);
+}
+
+- (id)payment {
+    env.objc.borrow::<SKPaymentTransactionHostObject>(this).payment
+}
+- (SKPaymentTransactionState)transactionState {
+    env.objc.borrow::<SKPaymentTransactionHostObject>(this).state
+}
+- (id)error {
+    env.objc.borrow::<SKPaymentTransactionHostObject>(this).error
+}
+- (id)transactionIdentifier {
+    env.objc.borrow::<SKPaymentTransactionHostObject>(this).identifier
+}
+- (id)transactionDate {
+    env.objc.borrow::<SKPaymentTransactionHostObject>(this).date
+}
+- (id)originalTransaction {
+    env.objc.borrow::<SKPaymentTransactionHostObject>(this).original
+}
+// There's no App Store to verify a receipt with, so the receipt is empty.
+- (id)transactionReceipt {
+    let state = env.objc.borrow::<SKPaymentTransactionHostObject>(this).state;
+    if state == SKPaymentTransactionStatePurchased || state == SKPaymentTransactionStateRestored {
+        let data: id = msg_class![env; NSData new];
+        autorelease(env, data)
+    } else {
+        nil
+    }
+}
+
+@end
+
+@implementation SKPaymentQueue: NSObject
+
++ (id)defaultQueue {
+    if let Some(queue) = env.framework_state.store_kit.payment_queue {
+        return queue;
+    }
+    let host_object = Box::new(SKPaymentQueueHostObject {
+        observers: Vec::new(),
+        transactions: Vec::new(),
+        pending_events: Vec::new(),
+        transaction_count: 0,
+    });
+    let new = env.objc.alloc_static_object(this, host_object, &mut env.mem);
+    env.framework_state.store_kit.payment_queue = Some(new);
+    new
+}
+
++ (bool)canMakePayments {
+    env.options.in_app_purchases != InAppPurchases::Disabled
+}
+
+- (())addTransactionObserver:(id)observer {
+    let host_object = env.objc.borrow_mut::<SKPaymentQueueHostObject>(this);
+    if !host_object.observers.contains(&observer) {
+        host_object.observers.push(observer);
+    }
+}
+- (())removeTransactionObserver:(id)observer {
+    let host_object = env.objc.borrow_mut::<SKPaymentQueueHostObject>(this);
+    host_object.observers.retain(|&other| other != observer);
+}
+
+- (id)transactions {
+    let transactions = env.objc.borrow::<SKPaymentQueueHostObject>(this).transactions.clone();
+    for &transaction in &transactions {
+        retain(env, transaction);
+    }
+    let transactions = ns_array::from_vec(env, transactions);
+    autorelease(env, transactions)
+}
+
+- (())addPayment:(id)payment { // SKPayment*
+    let payment: id = msg![env; payment copy];
+    let transaction: id = msg_class![env; SKPaymentTransaction new];
+    env.objc.borrow_mut::<SKPaymentTransactionHostObject>(transaction).payment = payment;
+
+    retain(env, transaction);
+    let host_object = env.objc.borrow_mut::<SKPaymentQueueHostObject>(this);
+    host_object.transactions.push(transaction);
+    host_object.pending_events.push(PaymentQueueEvent::Payment(transaction));
+}
+
+- (())restoreCompletedTransactions {
+    env.objc
+        .borrow_mut::<SKPaymentQueueHostObject>(this)
+        .pending_events
+        .push(PaymentQueueEvent::RestoreCompletedTransactions);
+}
+
+- (())finishTransaction:(id)transaction { // SKPaymentTransaction*
+    let state = env.objc.borrow::<SKPaymentTransactionHostObject>(transaction).state;
+    if state == SKPaymentTransactionStatePurchasing {
+        log!("Warning: [(SKPaymentQueue*){:?} finishTransaction:{:?}] called for a transaction that is still purchasing, ignoring", this, transaction);
+        return;
+    }
+    let transactions = &mut env.objc.borrow_mut::<SKPaymentQueueHostObject>(this).transactions;
+    if let Some(index) = transactions.iter().position(|&other| other == transaction) {
+        transactions.remove(index);
+        release(env, transaction);
+    }
+}
+
+@end
+
+};
+
+/// For use by [super::handle_events]: complete or fail payments and restore
+/// purchases, according to `--in-app-purchases=`.
+pub(super) fn handle_queue(env: &mut Environment) -> Option<Instant> {
+    let queue = env.framework_state.store_kit.payment_queue?;
+    let events = std::mem::take(
+        &mut env
+            .objc
+            .borrow_mut::<SKPaymentQueueHostObject>(queue)
+            .pending_events,
+    );
+    if events.is_empty() {
+        return None;
+    }
+
+    // UIKit creates and drains autorelease pools when handling events.
+    let pool: id = msg_class![env; NSAutoreleasePool new];
+
+    for event in events {
+        match event {
+            PaymentQueueEvent::Payment(transaction) => {
+                complete_payment(env, queue, transaction);
+                notify_updated_transactions(env, queue, vec![transaction]);
+                release(env, transaction);
+            }
+            PaymentQueueEvent::RestoreCompletedTransactions => {
+                restore_completed_transactions(env, queue);
+            }
+        }
+    }
+
+    release(env, pool);
+
+    // Observers may have added more payments.
+    let host_object = env.objc.borrow::<SKPaymentQueueHostObject>(queue);
+    if host_object.pending_events.is_empty() {
+        None
+    } else {
+        Some(env.clock.now())
+    }
+}
+
+/// Move a transaction out of the purchasing state.
+fn complete_payment(env: &mut Environment, queue: id, transaction: id) {
+    let payment = env
+        .objc
+        .borrow::<SKPaymentTransactionHostObject>(transaction)
+        .payment;
+    let identifier: id = msg![env; payment productIdentifier];
+    let identifier = ns_string::to_rust_string(env, identifier).into_owned();
+
+    match env.options.in_app_purchases {
+        InAppPurchases::Succeed => {
+            log!("StoreKit: simulating purchase of {:?}", identifier);
+            add_purchased_product(env, identifier);
+            set_purchased(env, queue, transaction, SKPaymentTransactionStatePurchased);
+        }
+        mode => {
+            let code = if mode == InAppPurchases::Fail {
+                SKErrorPaymentCancelled
+            } else {
+                SKErrorPaymentNotAllowed
+            };
+            log!("StoreKit: simulating failed purchase of {:?}", identifier);
+            let error = new_error(env, code);
+            let host_object = env
+                .objc
+                .borrow_mut::<SKPaymentTransactionHostObject>(transaction);
+            host_object.state = SKPaymentTransactionStateFailed;
+            host_object.error = error;
+        }
+    }
+}
+
+/// Put a transaction into the purchased or restored state, giving it an
+/// identifier and date.
+fn set_purchased(
+    env: &mut Environment,
+    queue: id,
+    transaction: id,
+    state: SKPaymentTransactionState,
+) {
+    let host_object = env.objc.borrow_mut::<SKPaymentQueueHostObject>(queue);
+    host_object.transaction_count += 1;
+    let number = 1000000000 + host_object.transaction_count;
+    let identifier = ns_string::from_rust_string(env, number.to_string());
+    let date: id = msg_class![env; NSDate date];
+    retain(env, date);
+
+    let host_object = env
+        .objc
+        .borrow_mut::<SKPaymentTransactionHostObject>(transaction);
+    host_object.state = state;
+    host_object.identifier = identifier;
+    host_object.date = date;
+}
+
+/// Restore every purchased product, or fail if purchases are disabled or
+/// failing.
+fn restore_completed_transactions(env: &mut Environment, queue: id) {
+    let mode = env.options.in_app_purchases;
+    if mode != InAppPurchases::Succeed {
+        log!("StoreKit: simulating failure to restore purchases");
+        let code = if mode == InAppPurchases::Fail {
+            SKErrorPaymentCancelled
+        } else {
+            SKErrorPaymentNotAllowed
+        };
+        let error = new_error(env, code);
+        for observer in observers(env, queue) {
+            if delegate_responds_to(
+                env,
+                observer,
+                "paymentQueue:restoreCompletedTransactionsFailedWithError:",
+            ) {
+                () = msg![env; observer paymentQueue:queue
+                   restoreCompletedTransactionsFailedWithError:error];
+            }
+        }
+        release(env, error);
+        return;
+    }
+
+    let identifiers = purchased_products(env).to_vec();
+    log!("StoreKit: restoring purchases: {:?}", identifiers);
+    let mut restored = Vec::new();
+    for identifier in identifiers {
+        let identifier = ns_string::from_rust_string(env, identifier);
+        let payment: id = msg_class![env; SKPayment paymentWithProductIdentifier:identifier];
+        release(env, identifier);
+
+        // The original purchase isn't stored, so a copy of it is made up.
+        let original: id = msg_class![env; SKPaymentTransaction new];
+        retain(env, payment);
+        env.objc
+            .borrow_mut::<SKPaymentTransactionHostObject>(original)
+            .payment = payment;
+        set_purchased(env, queue, original, SKPaymentTransactionStatePurchased);
+
+        let transaction: id = msg_class![env; SKPaymentTransaction new];
+        retain(env, payment);
+        let host_object = env
+            .objc
+            .borrow_mut::<SKPaymentTransactionHostObject>(transaction);
+        host_object.payment = payment;
+        host_object.original = original;
+        set_purchased(env, queue, transaction, SKPaymentTransactionStateRestored);
+
+        env.objc
+            .borrow_mut::<SKPaymentQueueHostObject>(queue)
+            .transactions
+            .push(transaction);
+        restored.push(transaction);
+    }
+
+    if !restored.is_empty() {
+        notify_updated_transactions(env, queue, restored);
+    }
+    for observer in observers(env, queue) {
+        if delegate_responds_to(
+            env,
+            observer,
+            "paymentQueueRestoreCompletedTransactionsFinished:",
+        ) {
+            () = msg![env; observer paymentQueueRestoreCompletedTransactionsFinished:queue];
+        }
+    }
+}
+
+/// Send `paymentQueue:updatedTransactions:` to every observer.
+fn notify_updated_transactions(env: &mut Environment, queue: id, transactions: Vec<id>) {
+    for &transaction in &transactions {
+        retain(env, transaction);
+    }
+    let transactions = ns_array::from_vec(env, transactions);
+    for observer in observers(env, queue) {
+        () = msg![env; observer paymentQueue:queue updatedTransactions:transactions];
+    }
+    release(env, transactions);
+}
+
+/// Get a copy of the list of observers, since observers might add or remove
+/// themselves while being notified.
+fn observers(env: &mut Environment, queue: id) -> Vec<id> {
+    env.objc
+        .borrow::<SKPaymentQueueHostObject>(queue)
+        .observers
+        .clone()
+}
@@ -3,16 +3,96 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
+//! `SKProduct`.
 
-use crate::objc::ClassExports;
-use crate::objc_classes;
+use crate::frameworks::foundation::ns_string;
+use crate::objc::{id, msg, msg_class, objc_classes, release, ClassExports, HostObject};
+use crate::Environment;
+
+struct SKProductHostObject {
+    /// `NSString*`
+    identifier: id,
+    /// `NSString*`
+    title: id,
+    /// `NSDecimalNumber*`
+    price: id,
+}
+impl HostObject for SKProductHostObject {}
 
 pub const CLASSES: ClassExports = objc_classes! {
 
 (env, this, _cmd);
 
 @implementation SKProduct: NSObject
-// TODO
+
+// Products can only be created by touchHLE, see new_product().
+
+- (())dealloc {
+    let &SKProductHostObject { identifier, title, price } = env.objc.borrow(this);
+    release(env, identifier);
+    release(env, title);
+    release(env, price);
+    env.objc.dealloc_object(this, &mut env.mem);
+}
+
+- (id)productIdentifier {
+    env.objc.borrow::<SKProductHostObject>(this).identifier
+}
+- (id)localizedTitle {
+    env.objc.borrow::<SKProductHostObject>(this).title
+}
+// touchHLE has no descriptions, so the title is the best substitute.
+- (id)localizedDescription {
+    env.objc.borrow::<SKProductHostObject>(this).title
+}
+- (id)price {
+    env.objc.borrow::<SKProductHostObject>(this).price
+}
+- (id)priceLocale {
+    msg_class![env; NSLocale currentLocale]
+}
+
 @end
 
 };
+
+/// Create an `SKProduct*` for a product identifier, using the details from
+/// `--in-app-product=` if there are any. The caller owns the result.
+pub(super) fn new_product(env: &mut Environment, identifier: &str) -> id {
+    let configured = env
+        .options
+        .in_app_products
+        .iter()
+        .find(|product| product.identifier == identifier)
+        .cloned();
+    let (title, price) = match configured {
+        Some(product) if !product.title.is_empty() => (product.title, product.price),
+        Some(product) => (default_title(identifier), product.price),
+        None => (default_title(identifier), 0.99),
+    };
+
+    let identifier = ns_string::from_rust_string(env, identifier.to_string());
+    let title = ns_string::from_rust_string(env, title);
+    let price: id = msg_class![env; NSDecimalNumber alloc];
+    let price: id = msg![env; price initWithDouble:price];
+
+    let host_object = Box::new(SKProductHostObject {
+        identifier,
+        title,
+        price,
+    });
+    let class = env.objc.get_known_class("SKProduct", &mut env.mem);
+    env.objc.alloc_object(class, host_object, &mut env.mem)
+}
+
+/// Make up a title from a product identifier, e.g. "Extra levels" for
+/// "com.example.game.extra_levels".
+fn default_title(identifier: &str) -> String {
+    let name = identifier.rsplit('.').next().unwrap();
+    let name = name.replace(['_', '-'], " ");
+    let mut chars = name.trim().chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => identifier.to_string(),
+    }
+}
@@ -0,0 +1,182 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `SKRequest`, `SKProductsRequest` and `SKProductsResponse`.
+
+use super::delegate_responds_to;
+use super::sk_product::new_product;
+use crate::frameworks::foundation::{ns_array, ns_string, NSUInteger};
+use crate::objc::{
+    id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
+};
+use crate::Environment;
+use std::time::Instant;
+
+/// `SKProductsRequest` is the only kind of request touchHLE supports, so this
+/// is used for both it and `SKRequest`.
+struct SKRequestHostObject {
+    /// Weak reference.
+    delegate: id,
+    /// `NSSet*` of `NSString*`
+    product_identifiers: id,
+}
+impl HostObject for SKRequestHostObject {}
+
+struct SKProductsResponseHostObject {
+    /// `NSArray*` of `SKProduct*`
+    products: id,
+    /// `NSArray*` of `NSString*`
+    invalid_product_identifiers: id,
+}
+impl HostObject for SKProductsResponseHostObject {}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation SKRequest: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(SKRequestHostObject {
+        delegate: nil,
+        product_identifiers: nil,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (())dealloc {
+    let product_identifiers = env.objc.borrow::<SKRequestHostObject>(this).product_identifiers;
+    release(env, product_identifiers);
+    env.objc.dealloc_object(this, &mut env.mem);
+}
+
+- (id)delegate {
+    env.objc.borrow::<SKRequestHostObject>(this).delegate
+}
+- (())setDelegate:(id)delegate {
+    env.objc.borrow_mut::<SKRequestHostObject>(this).delegate = delegate;
+}
+
+- (())start {
+    let pending = &env.framework_state.store_kit.pending_requests;
+    if pending.contains(&this) {
+        return;
+    }
+    retain(env, this);
+    env.framework_state.store_kit.pending_requests.push(this);
+}
+
+- (())cancel {
+    let pending = &mut env.framework_state.store_kit.pending_requests;
+    if let Some(index) = pending.iter().position(|&request| request == this) {
+        pending.remove(index);
+        release(env, this);
+    }
+}
+
+@end
+
+@implementation SKProductsRequest: SKRequest
+
+- (id)initWithProductIdentifiers:(id)product_identifiers { // NSSet*
+    let product_identifiers: id = msg![env; product_identifiers copy];
+    env.objc.borrow_mut::<SKRequestHostObject>(this).product_identifiers = product_identifiers;
+    this
+}
+
+@end
+
+@implementation SKProductsResponse: NSObject
+
+// Responses can only be created by touchHLE, see respond().
+
+- (())dealloc {
+    let &SKProductsResponseHostObject {
+        products,
+        invalid_product_identifiers,
+    } = env.objc.borrow(this);
+    release(env, products);
+    release(env, invalid_product_identifiers);
+    env.objc.dealloc_object(this, &mut env.mem);
+}
+
+- (id)products {
+    env.objc.borrow::<SKProductsResponseHostObject>(this).products
+}
+- (id)invalidProductIdentifiers {
+    env.objc.borrow::<SKProductsResponseHostObject>(this).invalid_product_identifiers
+}
+
+@end
+
+};
+
+/// For use by [super::handle_events]: respond to any requests that have been
+/// started.
+pub(super) fn handle_requests(env: &mut Environment) -> Option<Instant> {
+    if env.framework_state.store_kit.pending_requests.is_empty() {
+        return None;
+    }
+
+    // UIKit creates and drains autorelease pools when handling events.
+    let pool: id = msg_class![env; NSAutoreleasePool new];
+
+    let pending = std::mem::take(&mut env.framework_state.store_kit.pending_requests);
+    for request in pending {
+        respond(env, request);
+        release(env, request);
+    }
+
+    release(env, pool);
+
+    // Delegates may have started more requests.
+    if env.framework_state.store_kit.pending_requests.is_empty() {
+        None
+    } else {
+        Some(env.clock.now())
+    }
+}
+
+/// Send a request's delegate a response where every product exists.
+fn respond(env: &mut Environment, request: id) {
+    let &SKRequestHostObject {
+        delegate,
+        product_identifiers,
+    } = env.objc.borrow(request);
+
+    let identifiers: id = msg![env; product_identifiers allObjects];
+    let count: NSUInteger = msg![env; identifiers count];
+    let products: Vec<id> = (0..count)
+        .map(|i| {
+            let identifier: id = msg![env; identifiers objectAtIndex:i];
+            let identifier = ns_string::to_rust_string(env, identifier).into_owned();
+            log!(
+                "StoreKit: responding to request for product {:?}",
+                identifier
+            );
+            new_product(env, &identifier)
+        })
+        .collect();
+    let products = ns_array::from_vec(env, products);
+    let invalid_product_identifiers = ns_array::from_vec(env, Vec::new());
+
+    let host_object = Box::new(SKProductsResponseHostObject {
+        products,
+        invalid_product_identifiers,
+    });
+    let class = env.objc.get_known_class("SKProductsResponse", &mut env.mem);
+    let response = env.objc.alloc_object(class, host_object, &mut env.mem);
+
+    if delegate_responds_to(env, delegate, "productsRequest:didReceiveResponse:") {
+        () = msg![env; delegate productsRequest:request didReceiveResponse:response];
+    }
+    release(env, response);
+    // The delegate might have been released by the previous callback, but it
+    // should have cleared itself as the delegate in that case.
+    let delegate = env.objc.borrow::<SKRequestHostObject>(request).delegate;
+    if delegate_responds_to(env, delegate, "requestDidFinish:") {
+        () = msg![env; delegate requestDidFinish:request];
+    }
+}
@@ -9,9 +9,12 @@
 //! likely to use UIKit in very simple and limited ways, so this implementation
 //! will probably take a lot of shortcuts.
 
-use crate::{msg, Environment};
+use crate::environment::SpeedIndicator;
+use crate::frameworks::audio_toolbox::audio_session;
+use crate::{msg, profiler, recording, Environment};
 use std::time::Instant;
 
+pub mod status_bar;
 pub mod ui_accelerometer;
 pub mod ui_activity_indicator_view;
 pub mod ui_application;
@@ -23,6 +26,7 @@ pub mod ui_geometry;
 pub mod ui_graphics;
 pub mod ui_image;
 pub mod ui_image_picker_controller;
+pub mod ui_localized_indexed_collation;
 pub mod ui_nib;
 pub mod ui_responder;
 pub mod ui_screen;
@@ -32,28 +36,33 @@ pub mod ui_view_controller;
 
 #[derive(Default)]
 pub struct State {
+    status_bar: status_bar::State,
     ui_accelerometer: ui_accelerometer::State,
     ui_application: ui_application::State,
     ui_color: ui_color::State,
     ui_device: ui_device::State,
     ui_font: ui_font::State,
     ui_graphics: ui_graphics::State,
+    ui_localized_indexed_collation: ui_localized_indexed_collation::State,
     ui_screen: ui_screen::State,
     ui_touch: ui_touch::State,
     pub ui_view: ui_view::State,
+    ui_view_controller: ui_view_controller::State,
     ui_responder: ui_responder::State,
 }
 
 /// For use by `NSRunLoop`: handles any events that have queued up.
 ///
 /// Returns the next time this function must be called, if any, e.g. the next
-/// time an accelerometer input is due.
+/// time an accelerometer input is due or a transition must be advanced.
 pub fn handle_events(env: &mut Environment) -> Option<Instant> {
     use crate::window::Event;
     use crate::window::TextInputEvent;
 
+    env.wait_while_paused();
+
     loop {
-        // NSRunLoop will never call this function in headless mode.
+        // NSRunLoop will never call this function if there is no window.
         let Some(event) = env.window.as_mut().unwrap().pop_event() else {
             break;
         };
@@ -98,6 +107,45 @@ pub fn handle_events(env: &mut Environment) -> Option<Instant> {
                     log!("Ignoring EnterDebugger event: no debugger connected.");
                 }
             }
+            Event::FocusChanged(focused) => {
+                // Touches in progress won't get their end events, e.g. if the
+                // mouse button is released outside the window.
+                if !focused {
+                    ui_touch::cancel_all_touches(env);
+                }
+                if env.options.interrupt_audio_on_focus_loss {
+                    audio_session::set_interrupted(env, !focused);
+                }
+            }
+            Event::TakeScreenshot => recording::request_screenshot(env),
+            Event::ToggleFastForward => env.toggle_fast_forward(),
+            Event::TogglePause => env.toggle_pause(),
+            Event::AdvanceFrame => {
+                // Frame advance only makes sense while paused, so pause first.
+                if env.clock.speed_indicator() != Some(SpeedIndicator::Paused) {
+                    env.toggle_pause();
+                }
+            }
+            Event::SimulateMemoryWarning => {
+                log!("Sending memory warning at user's request.");
+                ui_application::send_memory_warning(env);
+            }
+            Event::WriteProfile => {
+                if env.profiler.is_some() {
+                    profiler::write_report(env);
+                } else {
+                    log!("Ignoring WriteProfile event: profiling is not enabled, see --profile=.");
+                }
+            }
+            Event::ScriptedQuit => {
+                echo!("Time given by --exit-after= has passed, exiting.");
+                ui_application::exit(env);
+            }
+            Event::PressVolumeButton(up) => audio_session::press_volume_button(env, up),
+            Event::ToggleAudioInterruption => {
+                let interrupted = audio_session::is_interrupted(env);
+                audio_session::set_interrupted(env, !interrupted);
+            }
             Event::TextInput(text_event) => {
                 let responder = env.framework_state.uikit.ui_responder.first_responder;
                 let class = msg![env; responder class];
@@ -120,5 +168,12 @@ pub fn handle_events(env: &mut Environment) -> Option<Instant> {
         }
     }
 
-    ui_accelerometer::handle_accelerometer(env)
+    ui_application::check_memory_usage(env);
+
+    let next_accelerometer = ui_accelerometer::handle_accelerometer(env);
+    let next_transition = ui_view_controller::handle_transitions(env);
+    match (next_accelerometer, next_transition) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
 }
@@ -0,0 +1,391 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! The status bar.
+//!
+//! On the real iPhone OS, the status bar is drawn by the system rather than
+//! the app, so it isn't part of the app's layer tree. Here it is drawn by the
+//! compositor on top of the app's content (see
+//! [crate::frameworks::core_animation::composition]), and UIKit only keeps
+//! track of whether it is visible and which style it has.
+//!
+//! The status bar always shows the carrier name (configurable with the
+//! `--status-bar-carrier=` option), the time from the host clock and a full
+//! battery.
+
+use crate::font::{Font, TextAlignment};
+use crate::frameworks::core_graphics::cg_affine_transform::{
+    CGAffineTransform, CGAffineTransformIdentity,
+};
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::NSInteger;
+use crate::libc::time::host_utc_offset;
+use crate::window::DeviceOrientation;
+use crate::Environment;
+use std::time::UNIX_EPOCH;
+
+/// Height of the status bar in points. In landscape orientations this is its
+/// width from the point of view of the (portrait) screen.
+pub const STATUS_BAR_HEIGHT: CGFloat = 20.0;
+
+pub type UIStatusBarStyle = NSInteger;
+pub const UIStatusBarStyleDefault: UIStatusBarStyle = 0;
+pub const UIStatusBarStyleBlackOpaque: UIStatusBarStyle = 1;
+pub const UIStatusBarStyleBlackTranslucent: UIStatusBarStyle = 2;
+
+#[derive(Default)]
+pub struct State {
+    pub(super) hidden: bool,
+    pub(super) style: UIStatusBarStyle,
+    /// Loaded the first time the status bar is drawn.
+    font: Option<Font>,
+}
+
+/// Everything that determines what the status bar looks like. The compositor
+/// only needs to redraw the status bar when this changes.
+#[derive(Clone, PartialEq)]
+pub struct Appearance {
+    style: UIStatusBarStyle,
+    orientation: DeviceOrientation,
+    carrier: String,
+    time: String,
+    /// See [crate::options::Options::scale_hack].
+    scale: u32,
+}
+
+/// Apply the `UIStatusBarHidden` and `UIStatusBarStyle` keys from the app's
+/// `Info.plist`. This should be called before the app gets a chance to change
+/// the status bar itself.
+pub(super) fn init_from_info_plist(env: &mut Environment) {
+    let hidden = env.bundle.status_bar_hidden();
+    let style = match env.bundle.status_bar_style() {
+        None | Some("UIStatusBarStyleDefault") => UIStatusBarStyleDefault,
+        Some("UIStatusBarStyleBlackOpaque") => UIStatusBarStyleBlackOpaque,
+        Some("UIStatusBarStyleBlackTranslucent") => UIStatusBarStyleBlackTranslucent,
+        Some(other) => {
+            log!(
+                "Warning: unknown UIStatusBarStyle {:?} in Info.plist, using the default",
+                other
+            );
+            UIStatusBarStyleDefault
+        }
+    };
+    log_dbg!("Status bar hidden: {}, style: {}", hidden, style);
+    let state = &mut env.framework_state.uikit.status_bar;
+    state.hidden = hidden;
+    state.style = style;
+}
+
+fn current_orientation(env: &Environment) -> DeviceOrientation {
+    env.window
+        .as_ref()
+        .map_or(DeviceOrientation::Portrait, |window| {
+            window.current_rotation()
+        })
+}
+
+/// Get the status bar's frame in (portrait) screen co-ordinates, or [None] if
+/// it is hidden. It is always at the top edge of the interface, so it moves to
+/// a different edge of the screen when the device is rotated.
+pub fn frame(env: &Environment) -> Option<CGRect> {
+    if env.framework_state.uikit.status_bar.hidden {
+        return None;
+    }
+    let (size, transform) = geometry(current_orientation(env));
+    Some(transform.apply_to_rect(CGRect {
+        origin: CGPoint { x: 0.0, y: 0.0 },
+        size,
+    }))
+}
+
+/// Remove the part of a rect covered by the status bar, e.g. to get
+/// `[UIScreen applicationFrame]` from `[UIScreen bounds]`.
+pub fn inset_rect(env: &Environment, rect: CGRect) -> CGRect {
+    let Some(bar) = frame(env) else {
+        return rect;
+    };
+    let CGRect {
+        origin: CGPoint { mut x, mut y },
+        size: CGSize {
+            mut width,
+            mut height,
+        },
+    } = rect;
+    match current_orientation(env) {
+        DeviceOrientation::Portrait => {
+            let bar_bottom = bar.origin.y + bar.size.height;
+            height -= (bar_bottom - y).max(0.0);
+            y = y.max(bar_bottom);
+        }
+        DeviceOrientation::LandscapeLeft => {
+            width -= (x + width - bar.origin.x).max(0.0);
+        }
+        DeviceOrientation::LandscapeRight => {
+            let bar_right = bar.origin.x + bar.size.width;
+            width -= (bar_right - x).max(0.0);
+            x = x.max(bar_right);
+        }
+    }
+    CGRect {
+        origin: CGPoint { x, y },
+        size: CGSize {
+            width: width.max(0.0),
+            height: height.max(0.0),
+        },
+    }
+}
+
+/// Get the size of the status bar in its own co-ordinate space (where it is
+/// always wider than it is tall), and the transform from that to (portrait)
+/// screen co-ordinates.
+fn geometry(orientation: DeviceOrientation) -> (CGSize, CGAffineTransform) {
+    // TODO: don't hardcode the screen size
+    let (screen_width, screen_height) = (320.0, 480.0);
+    match orientation {
+        DeviceOrientation::Portrait => (
+            CGSize {
+                width: screen_width,
+                height: STATUS_BAR_HEIGHT,
+            },
+            CGAffineTransformIdentity,
+        ),
+        // Rotated 90° counterclockwise, so the top of the interface is the
+        // right edge of the screen.
+        DeviceOrientation::LandscapeLeft => (
+            CGSize {
+                width: screen_height,
+                height: STATUS_BAR_HEIGHT,
+            },
+            CGAffineTransform {
+                a: 0.0,
+                b: 1.0,
+                c: -1.0,
+                d: 0.0,
+                tx: screen_width,
+                ty: 0.0,
+            },
+        ),
+        // Rotated 90° clockwise, so the top of the interface is the left edge
+        // of the screen.
+        DeviceOrientation::LandscapeRight => (
+            CGSize {
+                width: screen_height,
+                height: STATUS_BAR_HEIGHT,
+            },
+            CGAffineTransform {
+                a: 0.0,
+                b: -1.0,
+                c: 1.0,
+                d: 0.0,
+                tx: 0.0,
+                ty: screen_height,
+            },
+        ),
+    }
+}
+
+/// For use by the compositor: get what the status bar should currently look
+/// like, or [None] if it is hidden.
+pub fn appearance(env: &Environment, scale: u32) -> Option<Appearance> {
+    let &State { hidden, style, .. } = &env.framework_state.uikit.status_bar;
+    if hidden {
+        return None;
+    }
+    Some(Appearance {
+        style,
+        orientation: current_orientation(env),
+        carrier: env.options.status_bar_carrier.clone(),
+        time: format_time(env),
+        scale,
+    })
+}
+
+/// Format the current local time the way the iPhone OS status bar does, e.g.
+/// "9:41 AM".
+fn format_time(env: &Environment) -> String {
+    let timestamp = env
+        .clock
+        .system_now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() as i64);
+    let (offset, _is_dst) = host_utc_offset(timestamp);
+    let minute_of_day = (timestamp + i64::from(offset)).rem_euclid(24 * 60 * 60) / 60;
+    let (hour, minute) = (minute_of_day / 60, minute_of_day % 60);
+    let hour_12 = match hour % 12 {
+        0 => 12,
+        hour => hour,
+    };
+    let am_pm = if hour < 12 { "AM" } else { "PM" };
+    format!("{}:{:02} {}", hour_12, minute, am_pm)
+}
+
+impl Appearance {
+    /// See [geometry].
+    pub fn geometry(&self) -> (CGSize, CGAffineTransform) {
+        geometry(self.orientation)
+    }
+
+    /// Size in pixels of the bitmap produced by [rasterize].
+    pub fn pixel_dimensions(&self) -> (u32, u32) {
+        let (size, _) = self.geometry();
+        (
+            size.width as u32 * self.scale,
+            size.height as u32 * self.scale,
+        )
+    }
+}
+
+/// An RGBA8 bitmap with premultiplied alpha and top-to-bottom row order.
+struct Canvas {
+    pixels: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+impl Canvas {
+    /// Blend a premultiplied color onto a pixel with some coverage.
+    fn blend_pixel(&mut self, x: i32, y: i32, color: (f32, f32, f32, f32), coverage: f32) {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return;
+        }
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        let (r, g, b, a) = color;
+        let inv_a = 1.0 - a * coverage;
+        for (j, src) in [r, g, b, a].into_iter().enumerate() {
+            let dst = self.pixels[i + j] as f32 / 255.0;
+            let res = src * coverage + dst * inv_a;
+            self.pixels[i + j] = (res.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+    }
+
+    /// Fill a rectangle given in pixels.
+    fn fill_rect(&mut self, rect: (f32, f32, f32, f32), color: (f32, f32, f32, f32)) {
+        let (x, y, width, height) = rect;
+        for py in (y.round() as i32)..((y + height).round() as i32) {
+            for px in (x.round() as i32)..((x + width).round() as i32) {
+                self.blend_pixel(px, py, color, 1.0);
+            }
+        }
+    }
+}
+
+/// For use by the compositor: draw the status bar. The result is an RGBA8
+/// bitmap with premultiplied alpha, top-to-bottom row order and the size given
+/// by [Appearance::pixel_dimensions].
+pub fn rasterize(env: &mut Environment, appearance: &Appearance) -> Vec<u8> {
+    const FONT_SIZE: CGFloat = 12.0;
+    const MARGIN: CGFloat = 6.0;
+
+    let font = env
+        .framework_state
+        .uikit
+        .status_bar
+        .font
+        .get_or_insert_with(Font::sans_bold);
+
+    let scale = appearance.scale as CGFloat;
+    let (bar_size, _) = appearance.geometry();
+    let (width, height) = appearance.pixel_dimensions();
+    let mut canvas = Canvas {
+        pixels: vec![0; width as usize * height as usize * 4],
+        width,
+        height,
+    };
+
+    let foreground = match appearance.style {
+        UIStatusBarStyleBlackOpaque | UIStatusBarStyleBlackTranslucent => (1.0, 1.0, 1.0, 1.0),
+        _ => (0.0, 0.0, 0.0, 1.0),
+    };
+
+    // Background
+    for y in 0..height {
+        let color = match appearance.style {
+            UIStatusBarStyleBlackOpaque => (0.0, 0.0, 0.0, 1.0),
+            UIStatusBarStyleBlackTranslucent => (0.0, 0.0, 0.0, 0.5),
+            _ => {
+                // Light gray gradient, darkest at the bottom.
+                let t = y as f32 / height.saturating_sub(1).max(1) as f32;
+                let v = 0.93 - 0.25 * t;
+                (v, v, v, 1.0)
+            }
+        };
+        canvas.fill_rect((0.0, y as f32, width as f32, 1.0), color);
+    }
+
+    // Carrier name (left) and time (centered)
+    let font_size = FONT_SIZE * scale;
+    for (text, x, alignment) in [
+        (
+            appearance.carrier.as_str(),
+            MARGIN * scale,
+            TextAlignment::Left,
+        ),
+        (
+            appearance.time.as_str(),
+            bar_size.width * scale / 2.0,
+            TextAlignment::Center,
+        ),
+    ] {
+        let (_, text_height) = font.calculate_text_size(font_size, text, None);
+        let y = ((height as f32 - text_height) / 2.0).round();
+        font.draw(font_size, text, (x, y), None, alignment, |glyph| {
+            let (origin_x, origin_y) = glyph.origin();
+            let (glyph_width, glyph_height) = glyph.dimensions();
+            for gy in 0..glyph_height {
+                for gx in 0..glyph_width {
+                    let coverage = glyph.pixel_at((gx, gy));
+                    if coverage > 0.0 {
+                        canvas.blend_pixel(
+                            origin_x as i32 + gx,
+                            origin_y as i32 + gy,
+                            foreground,
+                            coverage,
+                        );
+                    }
+                }
+            }
+        });
+    }
+
+    // Battery (right), always full
+    let body_width = 22.0;
+    let body_height = 10.0;
+    let nub_width = 2.0;
+    let nub_height = 4.0;
+    let body_x = bar_size.width - MARGIN - nub_width - body_width;
+    let body_y = (STATUS_BAR_HEIGHT - body_height) / 2.0;
+    let scaled = |x: CGFloat, y: CGFloat, w: CGFloat, h: CGFloat| {
+        (x * scale, y * scale, w * scale, h * scale)
+    };
+    for rect in [
+        // Outline
+        scaled(body_x, body_y, body_width, 1.0),
+        scaled(body_x, body_y + body_height - 1.0, body_width, 1.0),
+        scaled(body_x, body_y + 1.0, 1.0, body_height - 2.0),
+        scaled(
+            body_x + body_width - 1.0,
+            body_y + 1.0,
+            1.0,
+            body_height - 2.0,
+        ),
+        // Nub
+        scaled(
+            body_x + body_width,
+            body_y + (body_height - nub_height) / 2.0,
+            nub_width,
+            nub_height,
+        ),
+        // Charge
+        scaled(
+            body_x + 2.0,
+            body_y + 2.0,
+            body_width - 4.0,
+            body_height - 4.0,
+        ),
+    ] {
+        canvas.fill_rect(rect, foreground);
+    }
+
+    canvas.pixels
+}
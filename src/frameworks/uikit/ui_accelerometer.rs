@@ -130,7 +130,7 @@ pub(super) fn handle_accelerometer(env: &mut Environment) -> Option<Instant> {
     let ns_interval = state.update_interval.unwrap_or(DEFAULT_UPDATE_INTERVAL);
     let rust_interval = Duration::from_secs_f64(ns_interval);
 
-    let now = Instant::now();
+    let now = env.clock.now();
     if let Some(due_by) = state.due_by {
         if due_by > now {
             return Some(due_by);
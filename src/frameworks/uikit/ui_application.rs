@@ -5,9 +5,11 @@
  */
 //! `UIApplication` and `UIApplicationMain`.
 
+use super::status_bar::{self, UIStatusBarStyle};
 use super::ui_device::*;
 use crate::dyld::{export_c_func, ConstantExports, FunctionExports, HostConstant};
-use crate::frameworks::foundation::{ns_array, ns_string, NSUInteger};
+use crate::frameworks::core_graphics::{CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::{ns_array, ns_cache, ns_string, NSInteger, NSUInteger};
 use crate::frameworks::uikit::ui_nib::load_main_nib_file;
 use crate::mem::MutPtr;
 use crate::objc::{
@@ -16,12 +18,15 @@ use crate::objc::{
 };
 use crate::window::DeviceOrientation;
 use crate::Environment;
+use std::time::{Duration, Instant};
 
 #[derive(Default)]
 pub struct State {
     /// [UIApplication sharedApplication]
     shared_application: Option<id>,
-    pub(super) status_bar_hidden: bool,
+    /// When the last memory warning was sent, if heap usage hasn't dropped
+    /// below the threshold since then.
+    last_memory_warning: Option<Instant>,
 }
 
 struct UIApplicationHostObject {
@@ -79,15 +84,41 @@ pub const CLASSES: ClassExports = objc_classes! {
     }
 }
 
-// TODO: statusBarHidden getter
+- (bool)isStatusBarHidden {
+    env.framework_state.uikit.status_bar.hidden
+}
 - (())setStatusBarHidden:(bool)hidden {
-    env.framework_state.uikit.ui_application.status_bar_hidden = hidden;
+    env.framework_state.uikit.status_bar.hidden = hidden;
 }
 - (())setStatusBarHidden:(bool)hidden
                 animated:(bool)_animated {
     // TODO: animation
     msg![env; this setStatusBarHidden:hidden]
 }
+- (())setStatusBarHidden:(bool)hidden
+           withAnimation:(NSInteger)_animation { // UIStatusBarAnimation
+    // TODO: animation
+    msg![env; this setStatusBarHidden:hidden]
+}
+
+- (UIStatusBarStyle)statusBarStyle {
+    env.framework_state.uikit.status_bar.style
+}
+- (())setStatusBarStyle:(UIStatusBarStyle)style {
+    env.framework_state.uikit.status_bar.style = style;
+}
+- (())setStatusBarStyle:(UIStatusBarStyle)style
+               animated:(bool)_animated {
+    // TODO: animation
+    msg![env; this setStatusBarStyle:style]
+}
+
+- (CGRect)statusBarFrame {
+    status_bar::frame(env).unwrap_or(CGRect {
+        origin: CGPoint { x: 0.0, y: 0.0 },
+        size: CGSize { width: 0.0, height: 0.0 },
+    })
+}
 
 - (UIInterfaceOrientation)statusBarOrientation {
     match env.window().current_rotation() {
@@ -178,6 +209,11 @@ pub(super) fn UIApplicationMain(
     principal_class_name: id, // NSString*
     delegate_class_name: id,  // NSString*
 ) {
+    // The Info.plist settings apply from the moment the app is launched, so
+    // views created while loading the main nib file already see the right
+    // application frame.
+    status_bar::init_from_info_plist(env);
+
     // UIKit creates and drains autorelease pools when handling events.
     // It's not clear what granularity this should happen with, but this
     // granularity has already caught several bugs. :)
@@ -307,9 +343,72 @@ pub(super) fn exit(env: &mut Environment) {
         let _: () = msg![env; pool drain];
     };
 
+    crate::recording::finish_recording(env);
+    crate::profiler::write_report(env);
     std::process::exit(0);
 }
 
+/// How often memory warnings are repeated while heap usage stays above the
+/// threshold.
+const MEMORY_WARNING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// For use by [super::handle_events]: send a memory warning if the app's heap
+/// usage is above the threshold set by `--memory-warning-threshold=`.
+pub(super) fn check_memory_usage(env: &mut Environment) {
+    let Some(threshold) = env.options.memory_warning_threshold else {
+        return;
+    };
+    let allocated = env.mem.allocated_bytes();
+    let state = &mut env.framework_state.uikit.ui_application;
+    if allocated < threshold {
+        state.last_memory_warning = None;
+        return;
+    }
+    let now = env.clock.now();
+    if state
+        .last_memory_warning
+        .is_some_and(|last| now.duration_since(last) < MEMORY_WARNING_INTERVAL)
+    {
+        return;
+    }
+    log!(
+        "App is using {} MiB of memory, sending memory warning.",
+        allocated / (1024 * 1024)
+    );
+    send_memory_warning(env);
+}
+
+/// Simulate the system running low on memory: the contents of every `NSCache`
+/// are evicted, the app delegate and view controllers are told, and
+/// `UIApplicationDidReceiveMemoryWarningNotification` is posted.
+pub(super) fn send_memory_warning(env: &mut Environment) {
+    env.framework_state.uikit.ui_application.last_memory_warning = Some(env.clock.now());
+
+    let pool: id = msg_class![env; NSAutoreleasePool new];
+
+    ns_cache::evict_all_caches(env);
+
+    let ui_application: id = msg_class![env; UIApplication sharedApplication];
+    let delegate: id = msg![env; ui_application delegate];
+    if delegate != nil
+        && env.objc.object_has_method_named(
+            &env.mem,
+            delegate,
+            "applicationDidReceiveMemoryWarning:",
+        )
+    {
+        () = msg![env; delegate applicationDidReceiveMemoryWarning:ui_application];
+    }
+
+    super::ui_view_controller::send_memory_warning(env);
+
+    let name = ns_string::get_static_str(env, UIApplicationDidReceiveMemoryWarningNotification);
+    let center: id = msg_class![env; NSNotificationCenter defaultCenter];
+    () = msg![env; center postNotificationName:name object:ui_application];
+
+    let _: () = msg![env; pool drain];
+}
+
 pub const UIApplicationDidReceiveMemoryWarningNotification: &str =
     "UIApplicationDidReceiveMemoryWarningNotification";
 pub const UIApplicationLaunchOptionsRemoteNotificationKey: &str =
@@ -3,14 +3,146 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
-//! `UIDevice`.
+//! `UIDevice`, and the simulated device model and OS version (`--device=` and
+//! `--os-version=`).
+//!
+//! The model and OS version are also visible through `NSProcessInfo` and
+//! `sysctl`, so those use [DeviceModel] and [crate::options::Options::os_version]
+//! too.
 
 use crate::dyld::ConstantExports;
 use crate::dyld::HostConstant;
 use crate::frameworks::foundation::ns_string;
 use crate::frameworks::foundation::NSInteger;
-use crate::objc::{id, objc_classes, ClassExports, TrivialHostObject};
+use crate::objc::{autorelease, id, msg, objc_classes, ClassExports, TrivialHostObject};
+use crate::paths;
 use crate::window::DeviceOrientation;
+use crate::Environment;
+use std::fmt::Write;
+use std::hash::{BuildHasher, Hasher};
+use std::time::SystemTime;
+
+/// Name of the file, in the app's directory in [paths::SANDBOX_DIR], where the
+/// app's `uniqueIdentifier` is stored. It's outside the app's home directory,
+/// so the app can't see it.
+const UNIQUE_IDENTIFIER_FILE: &str = "touchHLE_unique_identifier.txt";
+
+/// A device touchHLE can pretend to be, for the `--device=` option.
+///
+/// Later devices have Retina displays or run newer OS versions than touchHLE
+/// supports, so they aren't included.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DeviceModel {
+    IPhone2G,
+    IPhone3G,
+    IPhone3GS,
+    IPodTouch1G,
+    IPodTouch2G,
+    IPodTouch3G,
+}
+
+impl DeviceModel {
+    pub fn from_short_name(name: &str) -> Result<Self, ()> {
+        match name {
+            "iphone-2g" => Ok(DeviceModel::IPhone2G),
+            "iphone-3g" => Ok(DeviceModel::IPhone3G),
+            "iphone-3gs" => Ok(DeviceModel::IPhone3GS),
+            "ipod-touch-1g" => Ok(DeviceModel::IPodTouch1G),
+            "ipod-touch-2g" => Ok(DeviceModel::IPodTouch2G),
+            "ipod-touch-3g" => Ok(DeviceModel::IPodTouch3G),
+            _ => Err(()),
+        }
+    }
+
+    /// Marketing name, for messages.
+    pub fn display_name(self) -> &'static str {
+        match self {
+            DeviceModel::IPhone2G => "iPhone 2G",
+            DeviceModel::IPhone3G => "iPhone 3G",
+            DeviceModel::IPhone3GS => "iPhone 3GS",
+            DeviceModel::IPodTouch1G => "iPod touch (1st generation)",
+            DeviceModel::IPodTouch2G => "iPod touch (2nd generation)",
+            DeviceModel::IPodTouch3G => "iPod touch (3rd generation)",
+        }
+    }
+
+    /// Value of `-[UIDevice model]`.
+    pub fn model(self) -> &'static str {
+        match self {
+            DeviceModel::IPhone2G | DeviceModel::IPhone3G | DeviceModel::IPhone3GS => "iPhone",
+            DeviceModel::IPodTouch1G | DeviceModel::IPodTouch2G | DeviceModel::IPodTouch3G => {
+                "iPod touch"
+            }
+        }
+    }
+
+    /// Hardware identifier, i.e. the `hw.machine` sysctl.
+    pub fn machine(self) -> &'static str {
+        match self {
+            DeviceModel::IPhone2G => "iPhone1,1",
+            DeviceModel::IPhone3G => "iPhone1,2",
+            DeviceModel::IPhone3GS => "iPhone2,1",
+            DeviceModel::IPodTouch1G => "iPod1,1",
+            DeviceModel::IPodTouch2G => "iPod2,1",
+            DeviceModel::IPodTouch3G => "iPod3,1",
+        }
+    }
+
+    /// Board identifier, i.e. the `hw.model` sysctl.
+    pub fn board(self) -> &'static str {
+        match self {
+            DeviceModel::IPhone2G => "M68AP",
+            DeviceModel::IPhone3G => "N82AP",
+            DeviceModel::IPhone3GS => "N88AP",
+            DeviceModel::IPodTouch1G => "N45AP",
+            DeviceModel::IPodTouch2G => "N72AP",
+            DeviceModel::IPodTouch3G => "N18AP",
+        }
+    }
+
+    /// The OS version the device shipped with. It can't run older ones.
+    pub fn first_os_version(self) -> (u32, u32, u32) {
+        match self {
+            DeviceModel::IPhone2G => (1, 0, 0),
+            DeviceModel::IPhone3G => (2, 0, 0),
+            DeviceModel::IPhone3GS => (3, 0, 0),
+            DeviceModel::IPodTouch1G => (1, 1, 0),
+            DeviceModel::IPodTouch2G => (2, 1, 1),
+            DeviceModel::IPodTouch3G => (3, 1, 1),
+        }
+    }
+}
+
+/// Parse an OS version like `3.1.3` or `3.0`. A missing part is zero.
+pub fn parse_os_version(version: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = version.split('.').map(|part| part.parse::<u32>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().unwrap_or(Some(0))?;
+    let patch = parts.next().unwrap_or(Some(0))?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((major, minor, patch))
+}
+
+/// Get the build number for an OS version, if it's a known release. This is
+/// part of `-[NSProcessInfo operatingSystemVersionString]`.
+pub fn os_build(version: &str) -> Option<&'static str> {
+    match parse_os_version(version)? {
+        (2, 0, 0) => Some("5A347"),
+        (2, 0, 1) => Some("5B108"),
+        (2, 0, 2) => Some("5C1"),
+        (2, 1, 0) => Some("5F136"),
+        (2, 2, 0) => Some("5G77"),
+        (2, 2, 1) => Some("5H11"),
+        (3, 0, 0) => Some("7A341"),
+        (3, 0, 1) => Some("7A400"),
+        (3, 1, 0) => Some("7C144"),
+        (3, 1, 2) => Some("7D11"),
+        (3, 1, 3) => Some("7E18"),
+        _ => None,
+    }
+}
 
 pub const UIDeviceOrientationDidChangeNotification: &str =
     "UIDeviceOrientationDidChangeNotification";
@@ -31,6 +163,8 @@ pub const UIDeviceOrientationFaceDown: UIDeviceOrientation = 6;
 #[derive(Default)]
 pub struct State {
     current_device: Option<id>,
+    /// `NSString*`
+    unique_identifier: Option<id>,
 }
 
 pub const CONSTANTS: ConstantExports = &[(
@@ -65,13 +199,15 @@ pub const CLASSES: ClassExports = objc_classes! {
     log!("TODO: endGeneratingDeviceOrientationNotifications");
 }
 - (id)model {
-    // TODO: Hardcoded to iPhone for now
-    ns_string::get_static_str(env, "iPhone")
+    ns_string::get_static_str(env, env.options.device.model())
+}
+- (id)localizedModel {
+    msg![env; this model]
 }
 
 - (id)name {
-    // TODO: Hardcoded to iPhone for now
-    ns_string::get_static_str(env, "iPhone")
+    // This is the name the user gave the device, which defaults to the model.
+    msg![env; this model]
 }
 
 - (id)systemName {
@@ -80,13 +216,18 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 // NSString
 - (id)systemVersion {
-    ns_string::get_static_str(env, "2.0")
+    let version = ns_string::from_rust_string(env, env.options.os_version.clone());
+    autorelease(env, version)
 }
 
 - (id)uniqueIdentifier {
-    // Aspen Simulator returns (null) here
-    // TODO: what should be a correct value?
-    ns_string::get_static_str(env, "touchHLEdevice")
+    if let Some(identifier) = env.framework_state.uikit.ui_device.unique_identifier {
+        return identifier;
+    }
+    let identifier = unique_identifier(env);
+    let identifier = ns_string::from_rust_string(env, identifier);
+    env.framework_state.uikit.ui_device.unique_identifier = Some(identifier);
+    identifier
 }
 
 - (bool)isMultitaskingSupported {
@@ -104,3 +245,49 @@ pub const CLASSES: ClassExports = objc_classes! {
 @end
 
 };
+
+/// Get the app's `uniqueIdentifier`, a string of 40 hexadecimal digits. This
+/// is generated the first time the app asks for it, and then stored alongside
+/// the app's sandbox, so it stays the same even if touchHLE is upgraded.
+fn unique_identifier(env: &Environment) -> String {
+    let path = paths::user_data_base_path()
+        .join(paths::SANDBOX_DIR)
+        .join(env.bundle.bundle_identifier())
+        .join(UNIQUE_IDENTIFIER_FILE);
+    if let Ok(identifier) = std::fs::read_to_string(&path) {
+        let identifier = identifier.trim();
+        if identifier.len() == 40 && identifier.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return identifier.to_string();
+        }
+        log!(
+            "Warning: Ignoring invalid unique identifier in {}",
+            path.display()
+        );
+    }
+
+    // Real devices use a SHA-1 hash of hardware serial numbers. Any random
+    // value will do here. The standard library's hash keys are random, which
+    // saves depending on a random number crate.
+    let mut identifier = String::new();
+    while identifier.len() < 40 {
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos(),
+        );
+        write!(identifier, "{:016x}", hasher.finish()).unwrap();
+    }
+    identifier.truncate(40);
+
+    match std::fs::write(&path, &identifier) {
+        Ok(()) => log_dbg!("Saved unique identifier to {}", path.display()),
+        Err(e) => log!(
+            "Warning: Couldn't save unique identifier to {}: {}",
+            path.display(),
+            e
+        ),
+    }
+    identifier
+}
@@ -6,16 +6,24 @@
 //! `UIEvent`.
 
 use super::ui_touch::UITouchHostObject;
-use crate::frameworks::foundation::NSUInteger;
+use crate::frameworks::foundation::{NSInteger, NSTimeInterval, NSUInteger};
 use crate::mem::MutVoidPtr;
 use crate::objc::{
     id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
 };
 use crate::Environment;
 
+type UIEventType = NSInteger;
+const UIEventTypeTouches: UIEventType = 0;
+
+type UIEventSubtype = NSInteger;
+const UIEventSubtypeNone: UIEventSubtype = 0;
+
 pub(super) struct UIEventHostObject {
     /// `NSSet<UITouch*>*`
     touches: id,
+    /// Same timebase as `[NSProcessInfo systemUptime]`
+    timestamp: NSTimeInterval,
 }
 impl HostObject for UIEventHostObject {}
 
@@ -28,17 +36,19 @@ pub const CLASSES: ClassExports = objc_classes! {
 + (id)allocWithZone:(NSZonePtr)_zone {
     let host_object = Box::new(UIEventHostObject {
         touches: nil,
+        timestamp: 0.0,
     });
     env.objc.alloc_object(this, host_object, &mut env.mem)
 }
 
 - (())dealloc {
-    let &UIEventHostObject { touches } = env.objc.borrow(this);
+    let &UIEventHostObject { touches, .. } = env.objc.borrow(this);
     release(env, touches);
+    env.objc.dealloc_object(this, &mut env.mem)
 }
 
 - (id)touchesForView:(id)view_ {
-    let &UIEventHostObject { touches } = env.objc.borrow(this);
+    let &UIEventHostObject { touches, .. } = env.objc.borrow(this);
 
     let touches_for_view: id = msg_class![env; NSMutableSet allocWithZone:(MutVoidPtr::null())];
 
@@ -59,21 +69,31 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (id)allTouches {
-    let &UIEventHostObject { touches } = env.objc.borrow(this);
+    let &UIEventHostObject { touches, .. } = env.objc.borrow(this);
     touches
 }
 
-// TODO: more accessors
+- (NSTimeInterval)timestamp {
+    env.objc.borrow::<UIEventHostObject>(this).timestamp
+}
+
+- (UIEventType)type {
+    UIEventTypeTouches
+}
+- (UIEventSubtype)subtype {
+    UIEventSubtypeNone
+}
 
 @end
 
 };
 
 /// For use by [super::ui_touch]: create a `UIEvent` with a set of `UITouch*`
-pub(super) fn new_event(env: &mut Environment, touches: id) -> id {
+pub(super) fn new_event(env: &mut Environment, touches: id, timestamp: NSTimeInterval) -> id {
     let event: id = msg_class![env; UIEvent alloc];
     retain(env, touches);
     let borrow = env.objc.borrow_mut::<UIEventHostObject>(event);
     borrow.touches = touches;
+    borrow.timestamp = timestamp;
     event
 }
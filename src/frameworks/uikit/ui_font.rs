@@ -4,32 +4,187 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 //! `UIFont`.
+//!
+//! The fonts built into iPhone OS can't be redistributed, so they are
+//! substituted with similar free fonts (see `touchHLE_fonts/README.md`). Fonts
+//! that apps bundle and list under `UIAppFonts` in their `Info.plist` are
+//! loaded from the app bundle.
 
 use super::ui_graphics::UIGraphicsGetCurrentContext;
-use crate::font::{Font, TextAlignment, WrapMode};
+use crate::font::{parse_font_names, Font, TextAlignment, Truncation, Wrap, WrapMode};
 use crate::frameworks::core_graphics::cg_bitmap_context::CGBitmapContextDrawer;
 use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
-use crate::frameworks::foundation::NSInteger;
-use crate::objc::{autorelease, id, objc_classes, ClassExports, HostObject};
+use crate::frameworks::foundation::{ns_array, ns_string, NSInteger};
+use crate::objc::{autorelease, id, msg, objc_classes, ClassExports, HostObject};
 use crate::Environment;
+use std::collections::HashMap;
 use std::ops::Range;
 
 #[derive(Default)]
 pub(super) struct State {
-    regular: Option<Font>,
-    bold: Option<Font>,
-    italic: Option<Font>,
+    builtin: HashMap<(Substitute, Style), Font>,
     regular_ja: Option<Font>,
     bold_ja: Option<Font>,
+    /// Fonts from the app bundle. [None] until they're first needed.
+    app_fonts: Option<Vec<AppFont>>,
 }
 
-#[derive(Copy, Clone)]
-enum FontKind {
+/// Family of the free font used in place of a built-in font.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum Substitute {
+    Sans,
+    Serif,
+    Mono,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum Style {
     Regular,
     Bold,
     Italic,
 }
 
+/// Built-in fonts that can be requested by name: family name, font name, and
+/// what to draw them with. Bold italic fonts are drawn as bold, since there
+/// is no bold italic substitute. Fonts in the same family must be adjacent.
+const BUILTIN_FONTS: &[(&str, &str, Substitute, Style)] = &[
+    ("Helvetica", "Helvetica", Substitute::Sans, Style::Regular),
+    ("Helvetica", "Helvetica-Bold", Substitute::Sans, Style::Bold),
+    (
+        "Helvetica",
+        "Helvetica-Oblique",
+        Substitute::Sans,
+        Style::Italic,
+    ),
+    (
+        "Helvetica",
+        "Helvetica-BoldOblique",
+        Substitute::Sans,
+        Style::Bold,
+    ),
+    ("Arial", "ArialMT", Substitute::Sans, Style::Regular),
+    ("Arial", "Arial-BoldMT", Substitute::Sans, Style::Bold),
+    ("Arial", "Arial-ItalicMT", Substitute::Sans, Style::Italic),
+    ("Arial", "Arial-BoldItalicMT", Substitute::Sans, Style::Bold),
+    ("Verdana", "Verdana", Substitute::Sans, Style::Regular),
+    ("Verdana", "Verdana-Bold", Substitute::Sans, Style::Bold),
+    ("Verdana", "Verdana-Italic", Substitute::Sans, Style::Italic),
+    (
+        "Verdana",
+        "Verdana-BoldItalic",
+        Substitute::Sans,
+        Style::Bold,
+    ),
+    (
+        "Marker Felt",
+        "MarkerFelt-Thin",
+        Substitute::Sans,
+        Style::Regular,
+    ),
+    (
+        "Marker Felt",
+        "MarkerFelt-Wide",
+        Substitute::Sans,
+        Style::Bold,
+    ),
+    ("Georgia", "Georgia", Substitute::Serif, Style::Regular),
+    ("Georgia", "Georgia-Bold", Substitute::Serif, Style::Bold),
+    (
+        "Georgia",
+        "Georgia-Italic",
+        Substitute::Serif,
+        Style::Italic,
+    ),
+    (
+        "Georgia",
+        "Georgia-BoldItalic",
+        Substitute::Serif,
+        Style::Bold,
+    ),
+    (
+        "Times New Roman",
+        "TimesNewRomanPSMT",
+        Substitute::Serif,
+        Style::Regular,
+    ),
+    (
+        "Times New Roman",
+        "TimesNewRomanPS-BoldMT",
+        Substitute::Serif,
+        Style::Bold,
+    ),
+    (
+        "Times New Roman",
+        "TimesNewRomanPS-ItalicMT",
+        Substitute::Serif,
+        Style::Italic,
+    ),
+    (
+        "Times New Roman",
+        "TimesNewRomanPS-BoldItalicMT",
+        Substitute::Serif,
+        Style::Bold,
+    ),
+    ("Courier", "Courier", Substitute::Mono, Style::Regular),
+    ("Courier", "Courier-Bold", Substitute::Mono, Style::Bold),
+    (
+        "Courier",
+        "Courier-Oblique",
+        Substitute::Mono,
+        Style::Italic,
+    ),
+    (
+        "Courier",
+        "Courier-BoldOblique",
+        Substitute::Mono,
+        Style::Bold,
+    ),
+    (
+        "Courier New",
+        "CourierNewPSMT",
+        Substitute::Mono,
+        Style::Regular,
+    ),
+    (
+        "Courier New",
+        "CourierNewPS-BoldMT",
+        Substitute::Mono,
+        Style::Bold,
+    ),
+    (
+        "Courier New",
+        "CourierNewPS-ItalicMT",
+        Substitute::Mono,
+        Style::Italic,
+    ),
+    (
+        "Courier New",
+        "CourierNewPS-BoldItalicMT",
+        Substitute::Mono,
+        Style::Bold,
+    ),
+];
+/// Indices in [BUILTIN_FONTS] of the system fonts.
+const SYSTEM_FONT: usize = 0;
+const BOLD_SYSTEM_FONT: usize = 1;
+const ITALIC_SYSTEM_FONT: usize = 2;
+
+struct AppFont {
+    family: String,
+    /// PostScript name, which is what apps normally use to refer to a font.
+    name: String,
+    full_name: Option<String>,
+    font: Font,
+}
+
+#[derive(Copy, Clone)]
+enum FontKind {
+    /// Index into [BUILTIN_FONTS].
+    BuiltIn(usize),
+    /// Index into [State::app_fonts].
+    App(usize),
+}
+
 struct UIFontHostObject {
     size: CGFloat,
     kind: FontKind,
@@ -43,12 +198,9 @@ impl HostObject for UIFontHostObject {}
 pub type UILineBreakMode = NSInteger;
 pub const UILineBreakModeWordWrap: UILineBreakMode = 0;
 pub const UILineBreakModeCharacterWrap: UILineBreakMode = 1;
-#[allow(dead_code)]
 pub const UILineBreakModeClip: UILineBreakMode = 2;
-#[allow(dead_code)]
 pub const UILineBreakModeHeadTruncation: UILineBreakMode = 3;
 pub const UILineBreakModeTailTruncation: UILineBreakMode = 4;
-#[allow(dead_code)]
 pub const UILineBreakModeMiddleTruncation: UILineBreakMode = 5;
 
 /// Text alignment.
@@ -67,59 +219,261 @@ pub const CLASSES: ClassExports = objc_classes! {
 @implementation UIFont: NSObject
 
 + (id)systemFontOfSize:(CGFloat)size {
-    // Cache for later use
-    if env.framework_state.uikit.ui_font.regular.is_none() {
-        env.framework_state.uikit.ui_font.regular = Some(Font::sans_regular());
-    }
-    let host_object = UIFontHostObject {
-        size,
-        kind: FontKind::Regular,
-    };
-    let new = env.objc.alloc_object(this, Box::new(host_object), &mut env.mem);
-    autorelease(env, new)
+    new_font(env, this, FontKind::BuiltIn(SYSTEM_FONT), size)
 }
 + (id)boldSystemFontOfSize:(CGFloat)size {
-    // Cache for later use
-    if env.framework_state.uikit.ui_font.bold.is_none() {
-        env.framework_state.uikit.ui_font.bold = Some(Font::sans_bold());
-    }
-    let host_object = UIFontHostObject {
-        size,
-        kind: FontKind::Bold,
-    };
-    let new = env.objc.alloc_object(this, Box::new(host_object), &mut env.mem);
-    autorelease(env, new)
+    new_font(env, this, FontKind::BuiltIn(BOLD_SYSTEM_FONT), size)
 }
 + (id)italicSystemFontOfSize:(CGFloat)size {
-    // Cache for later use
-    if env.framework_state.uikit.ui_font.italic.is_none() {
-        env.framework_state.uikit.ui_font.italic = Some(Font::sans_italic());
-    }
-    let host_object = UIFontHostObject {
-        size,
-        kind: FontKind::Italic,
+    new_font(env, this, FontKind::BuiltIn(ITALIC_SYSTEM_FONT), size)
+}
+
++ (id)fontWithName:(id)name // NSString*
+              size:(CGFloat)size {
+    let name = ns_string::to_rust_string(env, name);
+    let kind = match find_font(env, &name) {
+        Some(kind) => kind,
+        None => {
+            // The real UIKit returns nil, but that would probably just
+            // result in nothing being drawn.
+            log!(
+                "Warning: Unknown font {:?}, using the system font instead",
+                name
+            );
+            FontKind::BuiltIn(SYSTEM_FONT)
+        }
     };
-    let new = env.objc.alloc_object(this, Box::new(host_object), &mut env.mem);
-    autorelease(env, new)
+    new_font(env, this, kind, size)
+}
+
++ (id)familyNames {
+    let mut families: Vec<String> = BUILTIN_FONTS
+        .iter()
+        .map(|&(family, ..)| family.to_string())
+        .collect();
+    families.extend(app_fonts(env).iter().map(|font| font.family.clone()));
+    families.sort();
+    families.dedup();
+    string_array(env, families)
+}
+
++ (id)fontNamesForFamilyName:(id)family { // NSString*
+    let family = ns_string::to_rust_string(env, family);
+    let mut names: Vec<String> = BUILTIN_FONTS
+        .iter()
+        .filter(|&&(builtin_family, ..)| builtin_family == family)
+        .map(|&(_, name, ..)| name.to_string())
+        .collect();
+    names.extend(
+        app_fonts(env)
+            .iter()
+            .filter(|font| font.family == family)
+            .map(|font| font.name.clone())
+    );
+    string_array(env, names)
+}
+
+- (id)fontWithSize:(CGFloat)size {
+    let kind = env.objc.borrow::<UIFontHostObject>(this).kind;
+    let class: id = msg![env; this class];
+    new_font(env, class, kind, size)
+}
+
+- (id)familyName {
+    let family = match env.objc.borrow::<UIFontHostObject>(this).kind {
+        FontKind::BuiltIn(idx) => BUILTIN_FONTS[idx].0.to_string(),
+        FontKind::App(idx) => app_fonts(env)[idx].family.clone(),
+    };
+    let family = ns_string::from_rust_string(env, family);
+    autorelease(env, family)
+}
+- (id)fontName {
+    let name = match env.objc.borrow::<UIFontHostObject>(this).kind {
+        FontKind::BuiltIn(idx) => BUILTIN_FONTS[idx].1.to_string(),
+        FontKind::App(idx) => app_fonts(env)[idx].name.clone(),
+    };
+    let name = ns_string::from_rust_string(env, name);
+    autorelease(env, name)
+}
+
+- (CGFloat)pointSize {
+    env.objc.borrow::<UIFontHostObject>(this).size
+}
+
+- (CGFloat)leading {
+    let &UIFontHostObject { size, kind } = env.objc.borrow(this);
+    get_font(&mut env.framework_state.uikit.ui_font, kind, "").leading(size)
 }
 
 @end
 
 };
 
-fn convert_line_break_mode(ui_mode: UILineBreakMode) -> WrapMode {
+fn new_font(env: &mut Environment, class: id, kind: FontKind, size: CGFloat) -> id {
+    let host_object = UIFontHostObject { size, kind };
+    let new = env
+        .objc
+        .alloc_object(class, Box::new(host_object), &mut env.mem);
+    autorelease(env, new)
+}
+
+fn string_array(env: &mut Environment, strings: Vec<String>) -> id {
+    let strings = strings
+        .into_iter()
+        .map(|string| ns_string::from_rust_string(env, string))
+        .collect();
+    let array = ns_array::from_vec(env, strings);
+    autorelease(env, array)
+}
+
+/// Look up a font by its PostScript name, full name or family name.
+fn find_font(env: &mut Environment, name: &str) -> Option<FontKind> {
+    let app_fonts = app_fonts(env);
+    if let Some(idx) = app_fonts
+        .iter()
+        .position(|font| font.name == name || font.full_name.as_deref() == Some(name))
+    {
+        return Some(FontKind::App(idx));
+    }
+    if let Some(idx) = BUILTIN_FONTS
+        .iter()
+        .position(|&(_, builtin_name, ..)| builtin_name == name)
+    {
+        return Some(FontKind::BuiltIn(idx));
+    }
+    // A family name gives the first (normally the regular) font of the
+    // family.
+    if let Some(idx) = app_fonts.iter().position(|font| font.family == name) {
+        return Some(FontKind::App(idx));
+    }
+    BUILTIN_FONTS
+        .iter()
+        .position(|&(family, ..)| family == name)
+        .map(FontKind::BuiltIn)
+}
+
+/// Get the fonts listed under `UIAppFonts`, loading them if necessary.
+fn app_fonts(env: &mut Environment) -> &[AppFont] {
+    if env.framework_state.uikit.ui_font.app_fonts.is_none() {
+        let mut app_fonts = Vec::new();
+        for path in env.bundle.app_font_paths() {
+            let Ok(bytes) = env.fs.read(&path) else {
+                log!("Warning: Couldn't read app font {:?}", path);
+                continue;
+            };
+            let names = parse_font_names(&bytes);
+            let Some(mut font) = Font::from_bytes(bytes) else {
+                log!("Warning: Couldn't parse app font {:?}", path);
+                continue;
+            };
+            // Apps' fonts often only cover the characters the app needs.
+            let fallback = get_builtin_font(
+                &mut env.framework_state.uikit.ui_font,
+                Substitute::Sans,
+                Style::Regular,
+            );
+            font.add_fallback(fallback);
+
+            let file_stem = path
+                .file_name()
+                .and_then(|name| name.rsplit_once('.').map(|(stem, _)| stem))
+                .unwrap_or_default()
+                .to_string();
+            let name = names.postscript_name.unwrap_or(file_stem);
+            let family = names.family.unwrap_or_else(|| name.clone());
+            log_dbg!("Loaded app font {:?} ({:?}) from {:?}", name, family, path);
+            app_fonts.push(AppFont {
+                family,
+                name,
+                full_name: names.full_name,
+                font,
+            });
+        }
+        env.framework_state.uikit.ui_font.app_fonts = Some(app_fonts);
+    }
+    env.framework_state
+        .uikit
+        .ui_font
+        .app_fonts
+        .as_deref()
+        .unwrap()
+}
+
+fn get_builtin_font(state: &mut State, substitute: Substitute, style: Style) -> &Font {
+    state.builtin.entry((substitute, style)).or_insert_with(|| {
+        let style_name = match style {
+            Style::Regular => "Regular",
+            Style::Bold => "Bold",
+            Style::Italic => "Italic",
+        };
+        let family_name = match substitute {
+            Substitute::Sans => "Sans",
+            Substitute::Serif => "Serif",
+            Substitute::Mono => "Mono",
+        };
+        let filename = format!("Liberation{}-{}.ttf", family_name, style_name);
+        if substitute != Substitute::Sans {
+            if let Some(font) = Font::try_from_resource_file(&filename) {
+                return font;
+            }
+            log!(
+                "Warning: {} is not available, using Liberation Sans instead",
+                filename
+            );
+        }
+        match style {
+            Style::Regular => Font::sans_regular(),
+            Style::Bold => Font::sans_bold(),
+            Style::Italic => Font::sans_italic(),
+        }
+    })
+}
+
+fn convert_line_break_mode(ui_mode: UILineBreakMode) -> (WrapMode, Truncation) {
     match ui_mode {
-        UILineBreakModeWordWrap => WrapMode::Word,
-        UILineBreakModeCharacterWrap => WrapMode::Char,
-        // TODO: support this properly; fake support is so that UILabel works,
-        // which has this as its default line break mode
-        UILineBreakModeTailTruncation => WrapMode::Word,
+        UILineBreakModeWordWrap => (WrapMode::Word, Truncation::Clip),
+        UILineBreakModeCharacterWrap => (WrapMode::Char, Truncation::Clip),
+        // The modes that don't specify a kind of wrapping still wrap at word
+        // boundaries when there's more than one line.
+        UILineBreakModeClip => (WrapMode::Word, Truncation::Clip),
+        UILineBreakModeHeadTruncation => (WrapMode::Word, Truncation::Head),
+        UILineBreakModeTailTruncation => (WrapMode::Word, Truncation::Tail),
+        UILineBreakModeMiddleTruncation => (WrapMode::Word, Truncation::Middle),
         _ => unimplemented!("TODO: line break mode {}", ui_mode),
     }
 }
 
+/// Make the [Wrap] for a UIKit line break mode. `height` limits the number of
+/// lines, or if it is [None], the text is limited to a single line.
+fn make_wrap(
+    font: &Font,
+    font_size: CGFloat,
+    width: CGFloat,
+    height: Option<CGFloat>,
+    ui_mode: UILineBreakMode,
+) -> Wrap {
+    let (mode, truncation) = convert_line_break_mode(ui_mode);
+    let max_lines = height.map_or(1, |height| font.max_lines_for_height(font_size, height));
+    Wrap {
+        width,
+        mode,
+        max_lines: Some(max_lines),
+        truncation,
+    }
+}
+
 #[rustfmt::skip]
 fn get_font<'a>(state: &'a mut State, kind: FontKind, text: &str) -> &'a Font {
+    let (substitute, style) = match kind {
+        FontKind::BuiltIn(idx) => {
+            let (_, _, substitute, style) = BUILTIN_FONTS[idx];
+            (substitute, style)
+        }
+        // App fonts are loaded before a UIFont can refer to them, and have
+        // their own fallbacks.
+        FontKind::App(idx) => return &state.app_fonts.as_ref().unwrap()[idx].font,
+    };
+
     // The default fonts (see font.rs) are the Liberation family, which are a
     // good substitute for Helvetica, the iPhone OS system font. Unfortunately,
     // there is no CJK support in these fonts. To support Super Monkey Ball in
@@ -132,15 +486,15 @@ fn get_font<'a>(state: &'a mut State, kind: FontKind, text: &str) -> &'a Font {
            (0xFF00..=0xFFEF).contains(&c) || // full-width/half-width chars
            (0x4e00..=0x9FA0).contains(&c) || // various kanji
            (0x3400..=0x4DBF).contains(&c) { // more kanji
-            match kind {
+            match style {
                 // CJK has no italic equivalent
-                FontKind::Regular | FontKind::Italic => {
+                Style::Regular | Style::Italic => {
                     if state.regular_ja.is_none() {
                         state.regular_ja = Some(Font::sans_regular_ja());
                     }
                     return state.regular_ja.as_ref().unwrap();
                 },
-                FontKind::Bold => {
+                Style::Bold => {
                     if state.bold_ja.is_none() {
                         state.bold_ja = Some(Font::sans_bold_ja());
                     }
@@ -150,11 +504,7 @@ fn get_font<'a>(state: &'a mut State, kind: FontKind, text: &str) -> &'a Font {
         }
     }
 
-    match kind {
-        FontKind::Regular => state.regular.as_ref().unwrap(),
-        FontKind::Bold => state.bold.as_ref().unwrap(),
-        FontKind::Italic => state.italic.as_ref().unwrap(),
-    }
+    get_builtin_font(state, substitute, style)
 }
 
 /// Called by the `sizeWithFont:` method family on `NSString`.
@@ -172,13 +522,44 @@ pub fn size_with_font(
         text,
     );
 
-    let wrap = constrained.map(|(size, ui_mode)| (size.width, convert_line_break_mode(ui_mode)));
+    let wrap = constrained.map(|(size, ui_mode)| {
+        make_wrap(
+            font,
+            host_object.size,
+            size.width,
+            Some(size.height),
+            ui_mode,
+        )
+    });
 
     let (width, height) = font.calculate_text_size(host_object.size, text, wrap);
 
     CGSize { width, height }
 }
 
+/// Called by `sizeWithFont:forWidth:lineBreakMode:` on `NSString`. This
+/// measures a single line of text.
+pub fn size_with_font_for_width(
+    env: &mut Environment,
+    font: id,
+    text: &str,
+    width: CGFloat,
+    line_break_mode: UILineBreakMode,
+) -> CGSize {
+    let host_object = env.objc.borrow::<UIFontHostObject>(font);
+
+    let font = get_font(
+        &mut env.framework_state.uikit.ui_font,
+        host_object.kind,
+        text,
+    );
+
+    let wrap = make_wrap(font, host_object.size, width, None, line_break_mode);
+    let (width, height) = font.calculate_text_size(host_object.size, text, Some(wrap));
+
+    CGSize { width, height }
+}
+
 #[inline(always)]
 fn draw_font_glyph(
     drawer: &mut CGBitmapContextDrawer,
@@ -251,11 +632,10 @@ pub fn draw_at_point(
         text,
     );
 
-    let width_and_line_break_mode =
-        width_and_line_break_mode.map(|(width, ui_mode)| (width, convert_line_break_mode(ui_mode)));
-    let clip_x = width_and_line_break_mode.map(|(width, _)| point.x..(point.x + width));
-    let (width, height) =
-        font.calculate_text_size(host_object.size, text, width_and_line_break_mode);
+    let wrap = width_and_line_break_mode
+        .map(|(width, ui_mode)| make_wrap(font, host_object.size, width, None, ui_mode));
+    let clip_x = wrap.map(|wrap| point.x..(point.x + wrap.width));
+    let (width, height) = font.calculate_text_size(host_object.size, text, wrap);
 
     let mut drawer = CGBitmapContextDrawer::new(&env.objc, &mut env.mem, context);
     let fill_color = drawer.rgb_fill_color();
@@ -264,7 +644,7 @@ pub fn draw_at_point(
         host_object.size,
         text,
         (point.x, point.y),
-        width_and_line_break_mode,
+        wrap,
         TextAlignment::Left,
         |raster_glyph| {
             draw_font_glyph(
@@ -291,8 +671,6 @@ pub fn draw_in_rect(
 ) -> CGSize {
     let context = UIGraphicsGetCurrentContext(env);
 
-    let text_size = size_with_font(env, font, text, Some((rect.size, line_break_mode)));
-
     let host_object = env.objc.borrow::<UIFontHostObject>(font);
 
     let font = get_font(
@@ -301,6 +679,17 @@ pub fn draw_in_rect(
         text,
     );
 
+    // The same line breaking is used for measuring and drawing, so the size
+    // returned always matches what was drawn.
+    let wrap = make_wrap(
+        font,
+        host_object.size,
+        rect.size.width,
+        Some(rect.size.height),
+        line_break_mode,
+    );
+    let (width, height) = font.calculate_text_size(host_object.size, text, Some(wrap));
+
     let mut drawer = CGBitmapContextDrawer::new(&env.objc, &mut env.mem, context);
     let fill_color = drawer.rgb_fill_color();
 
@@ -315,7 +704,7 @@ pub fn draw_in_rect(
         host_object.size,
         text,
         (rect.origin.x + origin_x_offset, rect.origin.y),
-        Some((rect.size.width, convert_line_break_mode(line_break_mode))),
+        Some(wrap),
         alignment,
         |raster_glyph| {
             draw_font_glyph(
@@ -328,5 +717,5 @@ pub fn draw_in_rect(
         },
     );
 
-    text_size
+    CGSize { width, height }
 }
@@ -2,12 +2,58 @@
 //!
 //! See also [crate::frameworks::core_graphics::cg_geometry].
 
+use crate::abi::{impl_GuestRet_for_large_struct, GuestArg};
 use crate::dyld::{export_c_func, FunctionExports};
-use crate::frameworks::core_graphics::{CGPoint, CGRect, CGSize};
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
 use crate::frameworks::foundation::ns_string;
+use crate::mem::SafeRead;
 use crate::objc::{autorelease, id};
 use crate::Environment;
 
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[repr(C, packed)]
+pub struct UIEdgeInsets {
+    pub top: CGFloat,
+    pub left: CGFloat,
+    pub bottom: CGFloat,
+    pub right: CGFloat,
+}
+unsafe impl SafeRead for UIEdgeInsets {}
+impl_GuestRet_for_large_struct!(UIEdgeInsets);
+impl GuestArg for UIEdgeInsets {
+    const REG_COUNT: usize = 4;
+
+    fn from_regs(regs: &[u32]) -> Self {
+        UIEdgeInsets {
+            top: GuestArg::from_regs(&regs[0..1]),
+            left: GuestArg::from_regs(&regs[1..2]),
+            bottom: GuestArg::from_regs(&regs[2..3]),
+            right: GuestArg::from_regs(&regs[3..4]),
+        }
+    }
+    fn to_regs(self, regs: &mut [u32]) {
+        self.top.to_regs(&mut regs[0..1]);
+        self.left.to_regs(&mut regs[1..2]);
+        self.bottom.to_regs(&mut regs[2..3]);
+        self.right.to_regs(&mut regs[3..4]);
+    }
+}
+impl UIEdgeInsets {
+    /// Equivalent of `UIEdgeInsetsInsetRect()`, which is an inline function.
+    pub fn inset_rect(self, rect: CGRect) -> CGRect {
+        CGRect {
+            origin: CGPoint {
+                x: rect.origin.x + self.left,
+                y: rect.origin.y + self.top,
+            },
+            size: CGSize {
+                width: rect.size.width - self.left - self.right,
+                height: rect.size.height - self.top - self.bottom,
+            },
+        }
+    }
+}
+
 // Apple's documentation says all of these return zeroes if the input is not
 // well-formed.
 pub fn CGPointFromString(env: &mut Environment, string: id) -> CGPoint {
@@ -6,15 +6,30 @@
 //! `UIGraphics.h`
 
 use crate::dyld::{export_c_func, FunctionExports};
+use crate::frameworks::core_graphics::cg_bitmap_context::{
+    CGBitmapContextCreate, CGBitmapContextCreateImage,
+};
+use crate::frameworks::core_graphics::cg_color_space::{
+    CGColorSpaceCreateDeviceRGB, CGColorSpaceRelease,
+};
 use crate::frameworks::core_graphics::cg_context::{
-    CGContextRef, CGContextRelease, CGContextRetain,
+    CGContextRef, CGContextRelease, CGContextRetain, CGContextScaleCTM, CGContextTranslateCTM,
+};
+use crate::frameworks::core_graphics::cg_image::{
+    kCGImageAlphaNoneSkipLast, kCGImageAlphaPremultipliedLast, kCGImageByteOrder32Big,
+    CGImageRelease,
 };
-use crate::objc::nil;
+use crate::frameworks::core_graphics::{CGFloat, CGSize};
+use crate::mem::{GuestUSize, Ptr};
+use crate::objc::{autorelease, id, msg, msg_class, nil};
 use crate::Environment;
 
 #[derive(Default)]
 pub(super) struct State {
     pub(super) context_stack: Vec<CGContextRef>,
+    /// Contexts created by `UIGraphicsBeginImageContext` and friends, with
+    /// their scale factors. These are also on the context stack.
+    image_contexts: Vec<(CGContextRef, CGFloat)>,
 }
 
 pub fn UIGraphicsPushContext(env: &mut Environment, context: CGContextRef) {
@@ -39,8 +54,101 @@ pub fn UIGraphicsGetCurrentContext(env: &mut Environment) -> CGContextRef {
         .unwrap_or(nil)
 }
 
+fn UIGraphicsBeginImageContext(env: &mut Environment, size: CGSize) {
+    UIGraphicsBeginImageContextWithOptions(env, size, false, 1.0);
+}
+fn UIGraphicsBeginImageContextWithOptions(
+    env: &mut Environment,
+    size: CGSize,
+    opaque: bool,
+    scale: CGFloat,
+) {
+    // A scale of 0 means the main screen's scale factor, and the devices
+    // touchHLE emulates all have a scale factor of 1.
+    let scale = if scale == 0.0 { 1.0 } else { scale };
+    let width = (size.width * scale).ceil().max(0.0) as GuestUSize;
+    let height = (size.height * scale).ceil().max(0.0) as GuestUSize;
+    if width == 0 || height == 0 {
+        log!(
+            "Warning: UIGraphicsBeginImageContextWithOptions() called with invalid size {:?}",
+            size
+        );
+        return;
+    }
+
+    let alpha_info = if opaque {
+        kCGImageAlphaNoneSkipLast
+    } else {
+        kCGImageAlphaPremultipliedLast
+    };
+    let color_space = CGColorSpaceCreateDeviceRGB(env);
+    let context = CGBitmapContextCreate(
+        env,
+        Ptr::null(),
+        width,
+        height,
+        8,
+        width.checked_mul(4).unwrap(),
+        color_space,
+        kCGImageByteOrder32Big | alpha_info,
+    );
+    CGColorSpaceRelease(env, color_space);
+
+    // UIKit's co-ordinate system has its origin in the top-left corner and
+    // uses points rather than pixels.
+    CGContextTranslateCTM(env, context, 0.0, height as CGFloat);
+    CGContextScaleCTM(env, context, scale, -scale);
+
+    UIGraphicsPushContext(env, context);
+    CGContextRelease(env, context); // the context stack owns it now
+    env.framework_state
+        .uikit
+        .ui_graphics
+        .image_contexts
+        .push((context, scale));
+}
+
+/// Get the scale factor of the current context, if it is an image context.
+fn current_image_context_scale(env: &mut Environment) -> Option<CGFloat> {
+    let context = UIGraphicsGetCurrentContext(env);
+    env.framework_state
+        .uikit
+        .ui_graphics
+        .image_contexts
+        .last()
+        .and_then(|&(image_context, scale)| (image_context == context).then_some(scale))
+}
+
+fn UIGraphicsGetImageFromCurrentImageContext(env: &mut Environment) -> id {
+    let Some(scale) = current_image_context_scale(env) else {
+        log!("Warning: UIGraphicsGetImageFromCurrentImageContext() without an image context");
+        return nil;
+    };
+    let context = UIGraphicsGetCurrentContext(env);
+    let cg_image = CGBitmapContextCreateImage(env, context);
+    let image: id = msg_class![env; UIImage alloc];
+    let image: id = msg![env; image initWithCGImage:cg_image
+                                              scale:scale
+                                        orientation:0]; // UIImageOrientationUp
+    CGImageRelease(env, cg_image);
+    autorelease(env, image)
+}
+
+fn UIGraphicsEndImageContext(env: &mut Environment) {
+    if current_image_context_scale(env).is_none() {
+        log!("Warning: UIGraphicsEndImageContext() called without a current image context");
+        return;
+    }
+    env.framework_state.uikit.ui_graphics.image_contexts.pop();
+    UIGraphicsPopContext(env);
+}
+
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(UIGraphicsPushContext(_)),
     export_c_func!(UIGraphicsPopContext()),
     export_c_func!(UIGraphicsGetCurrentContext()),
+    export_c_func!(UIGraphicsBeginImageContext(_)),
+    export_c_func!(UIGraphicsBeginImageContextWithOptions(_, _, _)),
+    export_c_func!(UIGraphicsGetImageFromCurrentImageContext()),
+    export_c_func!(UIGraphicsEndImageContext()),
 ];
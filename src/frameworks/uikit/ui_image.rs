@@ -5,9 +5,16 @@
  */
 //! `UIImage`.
 
-use crate::frameworks::core_graphics::cg_context::CGContextDrawImage;
-use crate::frameworks::core_graphics::cg_image::{self, CGImageRef, CGImageRelease, CGImageRetain};
-use crate::frameworks::core_graphics::{CGRect, CGSize};
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::frameworks::core_graphics::cg_context::{
+    kCGBlendModeNormal, CGBlendMode, CGContextDrawImage, CGContextRestoreGState,
+    CGContextSaveGState, CGContextScaleCTM, CGContextSetAlpha, CGContextSetBlendMode,
+    CGContextTranslateCTM,
+};
+use crate::frameworks::core_graphics::cg_image::{
+    self, CGImageCreateWithImageInRect, CGImageRef, CGImageRelease, CGImageRetain,
+};
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
 use crate::frameworks::foundation::{ns_data, ns_string, NSInteger};
 use crate::frameworks::uikit::ui_graphics::UIGraphicsGetCurrentContext;
 use crate::fs::GuestPath;
@@ -16,9 +23,15 @@ use crate::objc::{
     autorelease, id, msg, msg_class, nil, objc_classes, release, ClassExports, HostObject,
     NSZonePtr,
 };
+use crate::Environment;
 
 struct UIImageHostObject {
     cg_image: CGImageRef,
+    /// Number of pixels per point.
+    scale: CGFloat,
+    /// Set by `stretchableImageWithLeftCapWidth:topCapHeight:`.
+    left_cap_width: NSInteger,
+    top_cap_height: NSInteger,
 }
 impl HostObject for UIImageHostObject {}
 
@@ -29,7 +42,12 @@ pub const CLASSES: ClassExports = objc_classes! {
 @implementation UIImage: NSObject
 
 + (id)allocWithZone:(NSZonePtr)_zone {
-    let host_object = Box::new(UIImageHostObject { cg_image: nil });
+    let host_object = Box::new(UIImageHostObject {
+        cg_image: nil,
+        scale: 1.0,
+        left_cap_width: 0,
+        top_cap_height: 0,
+    });
     env.objc.alloc_object(this, host_object, &mut env.mem)
 }
 
@@ -39,6 +57,16 @@ pub const CLASSES: ClassExports = objc_classes! {
     autorelease(env, new)
 }
 
++ (id)imageWithCGImage:(CGImageRef)cg_image
+                 scale:(CGFloat)scale
+           orientation:(NSInteger)orientation { // UIImageOrientation
+    let new: id = msg![env; this alloc];
+    let new: id = msg![env; new initWithCGImage:cg_image
+                                          scale:scale
+                                    orientation:orientation];
+    autorelease(env, new)
+}
+
 + (id)imageNamed:(id)name { // NSString*
     // TODO: figure out whether this is actually correct in all cases
     let bundle: id = msg_class![env; NSBundle mainBundle];
@@ -63,7 +91,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (())dealloc {
-    let &UIImageHostObject { cg_image } = env.objc.borrow(this);
+    let &UIImageHostObject { cg_image, .. } = env.objc.borrow(this);
     CGImageRelease(env, cg_image);
 
     env.objc.dealloc_object(this, &mut env.mem)
@@ -75,6 +103,22 @@ pub const CLASSES: ClassExports = objc_classes! {
     this
 }
 
+- (id)initWithCGImage:(CGImageRef)cg_image
+                scale:(CGFloat)scale
+          orientation:(NSInteger)orientation { // UIImageOrientation
+    if orientation != 0 {
+        log!(
+            "TODO: [UIImage initWithCGImage:{:?} scale:{} orientation:{}]",
+            cg_image,
+            scale,
+            orientation
+        );
+    }
+    let this: id = msg![env; this initWithCGImage:cg_image];
+    env.objc.borrow_mut::<UIImageHostObject>(this).scale = scale;
+    this
+}
+
 - (id)initWithContentsOfFile:(id)path { // NSString*
     let path = ns_string::to_rust_string(env, path); // TODO: avoid copy
     let Ok(bytes) = env.fs.read(GuestPath::new(&path)) else {
@@ -113,21 +157,232 @@ pub const CLASSES: ClassExports = objc_classes! {
     0 // UIImageOrientationUp
 }
 
+- (CGFloat)scale {
+    env.objc.borrow::<UIImageHostObject>(this).scale
+}
+
 - (CGSize)size {
-    let image = env.objc.borrow::<UIImageHostObject>(this).cg_image;
-    let (width, height) = cg_image::borrow_image(&env.objc, image).dimensions();
+    let &UIImageHostObject { cg_image, scale, .. } = env.objc.borrow(this);
+    let (width, height) = cg_image::borrow_image(&env.objc, cg_image).dimensions();
     CGSize {
-        width: width as _,
-        height: height as _,
+        width: width as CGFloat / scale,
+        height: height as CGFloat / scale,
     }
 }
 
+- (id)stretchableImageWithLeftCapWidth:(NSInteger)left_cap_width
+                          topCapHeight:(NSInteger)top_cap_height {
+    let &UIImageHostObject { cg_image, scale, .. } = env.objc.borrow(this);
+    let new: id = msg_class![env; UIImage alloc];
+    let new: id = msg![env; new initWithCGImage:cg_image
+                                          scale:scale
+                                    orientation:0]; // UIImageOrientationUp
+    let host_object = env.objc.borrow_mut::<UIImageHostObject>(new);
+    host_object.left_cap_width = left_cap_width.max(0);
+    host_object.top_cap_height = top_cap_height.max(0);
+    autorelease(env, new)
+}
+
+- (NSInteger)leftCapWidth {
+    env.objc.borrow::<UIImageHostObject>(this).left_cap_width
+}
+- (NSInteger)topCapHeight {
+    env.objc.borrow::<UIImageHostObject>(this).top_cap_height
+}
+
+- (())drawAtPoint:(CGPoint)point {
+    () = msg![env; this drawAtPoint:point
+                          blendMode:kCGBlendModeNormal
+                              alpha:(1.0 as CGFloat)];
+}
+
+- (())drawAtPoint:(CGPoint)point
+        blendMode:(CGBlendMode)blend_mode
+            alpha:(CGFloat)alpha {
+    let size: CGSize = msg![env; this size];
+    let rect = CGRect { origin: point, size };
+    () = msg![env; this drawInRect:rect blendMode:blend_mode alpha:alpha];
+}
+
 - (())drawInRect:(CGRect)rect {
-    let context = UIGraphicsGetCurrentContext(env);
-    let image = env.objc.borrow::<UIImageHostObject>(this).cg_image;
-    CGContextDrawImage(env, context, rect, image);
+    () = msg![env; this drawInRect:rect
+                         blendMode:kCGBlendModeNormal
+                             alpha:(1.0 as CGFloat)];
+}
+
+- (())drawInRect:(CGRect)rect
+       blendMode:(CGBlendMode)blend_mode
+           alpha:(CGFloat)alpha {
+    draw_in_rect(env, this, rect, blend_mode, alpha);
 }
 
 @end
 
 };
+
+fn draw_in_rect(
+    env: &mut Environment,
+    image: id,
+    rect: CGRect,
+    blend_mode: CGBlendMode,
+    alpha: CGFloat,
+) {
+    let context = UIGraphicsGetCurrentContext(env);
+    if context == nil {
+        log!(
+            "Warning: [UIImage {:?} drawInRect:{:?}] called without a current context",
+            image,
+            rect
+        );
+        return;
+    }
+    let &UIImageHostObject {
+        cg_image,
+        scale,
+        left_cap_width,
+        top_cap_height,
+    } = env.objc.borrow(image);
+
+    CGContextSaveGState(env, context);
+    CGContextSetBlendMode(env, context, blend_mode);
+    CGContextSetAlpha(env, context, alpha);
+    // UIKit's co-ordinate system is flipped compared to Core Graphics', so
+    // the image would be upside-down if it were drawn directly.
+    CGContextTranslateCTM(
+        env,
+        context,
+        rect.origin.x,
+        rect.origin.y + rect.size.height,
+    );
+    CGContextScaleCTM(env, context, 1.0, -1.0);
+
+    if left_cap_width == 0 && top_cap_height == 0 {
+        let rect = CGRect {
+            origin: CGPoint { x: 0.0, y: 0.0 },
+            size: rect.size,
+        };
+        CGContextDrawImage(env, context, rect, cg_image);
+    } else {
+        let (width, height) = cg_image::borrow_image(&env.objc, cg_image).dimensions();
+        let (width, height) = (width as CGFloat / scale, height as CGFloat / scale);
+        let columns = stretch_spans(left_cap_width as CGFloat, width, rect.size.width);
+        let rows = stretch_spans(top_cap_height as CGFloat, height, rect.size.height);
+        for &(src_y0, src_y1, dst_y0, dst_y1) in &rows {
+            for &(src_x0, src_x1, dst_x0, dst_x1) in &columns {
+                if src_x0 >= src_x1 || src_y0 >= src_y1 || dst_x0 >= dst_x1 || dst_y0 >= dst_y1 {
+                    continue;
+                }
+                // The source rectangle is in pixels relative to the top-left
+                // corner, but the destination is relative to the bottom-left.
+                let src_rect = CGRect {
+                    origin: CGPoint {
+                        x: src_x0 * scale,
+                        y: src_y0 * scale,
+                    },
+                    size: CGSize {
+                        width: (src_x1 - src_x0) * scale,
+                        height: (src_y1 - src_y0) * scale,
+                    },
+                };
+                let dst_rect = CGRect {
+                    origin: CGPoint {
+                        x: dst_x0,
+                        y: rect.size.height - dst_y1,
+                    },
+                    size: CGSize {
+                        width: dst_x1 - dst_x0,
+                        height: dst_y1 - dst_y0,
+                    },
+                };
+                let patch = CGImageCreateWithImageInRect(env, cg_image, src_rect);
+                if patch != nil {
+                    CGContextDrawImage(env, context, dst_rect, patch);
+                    CGImageRelease(env, patch);
+                }
+            }
+        }
+    }
+
+    CGContextRestoreGState(env, context);
+}
+
+/// Split one axis of a stretchable image into spans of (source start, source
+/// end, destination start, destination end), measured from the left or top.
+/// The caps keep their size and the one-point-wide middle is stretched. A cap
+/// of zero means the whole axis is stretched.
+fn stretch_spans(
+    cap: CGFloat,
+    image_length: CGFloat,
+    dest_length: CGFloat,
+) -> Vec<(CGFloat, CGFloat, CGFloat, CGFloat)> {
+    if cap <= 0.0 || cap >= image_length {
+        return vec![(0.0, image_length, 0.0, dest_length)];
+    }
+    let end_cap = (image_length - cap - 1.0).max(0.0);
+    let middle_end = image_length - end_cap;
+    // The caps are squashed if there isn't room for them.
+    let squash = (dest_length / (cap + end_cap)).min(1.0);
+    let (dest_cap, dest_end_cap) = (cap * squash, end_cap * squash);
+    vec![
+        (0.0, cap, 0.0, dest_cap),
+        (cap, middle_end, dest_cap, dest_length - dest_end_cap),
+        (
+            middle_end,
+            image_length,
+            dest_length - dest_end_cap,
+            dest_length,
+        ),
+    ]
+}
+
+/// Make a copy of a `UIImage` with its colours scaled by `brightness` and its
+/// opacity scaled by `opacity`, keeping its scale and stretchable caps. This is
+/// how `UIButton` darkens and dims images. The copy is autoreleased.
+pub fn copy_with_adjusted_pixels(
+    env: &mut Environment,
+    image: id,
+    brightness: CGFloat,
+    opacity: CGFloat,
+) -> id {
+    let &UIImageHostObject {
+        cg_image,
+        scale,
+        left_cap_width,
+        top_cap_height,
+    } = env.objc.borrow(image);
+    let src = cg_image::borrow_image(&env.objc, cg_image);
+    let dimensions = src.dimensions();
+    // The alpha is premultiplied, so the colours have to be scaled by the
+    // opacity too.
+    let color_factor = brightness * opacity;
+    let mut pixels = Vec::with_capacity(src.pixels().len());
+    for rgba in src.pixels().chunks(4) {
+        for &c in &rgba[..3] {
+            pixels.push((c as CGFloat * color_factor).round().min(255.0) as u8);
+        }
+        pixels.push((rgba[3] as CGFloat * opacity).round().min(255.0) as u8);
+    }
+    let new_cg_image = cg_image::from_image(env, Image::from_pixel_vec(pixels, dimensions));
+
+    let new: id = msg_class![env; UIImage alloc];
+    let new: id = msg![env; new initWithCGImage:new_cg_image
+                                          scale:scale
+                                    orientation:0]; // UIImageOrientationUp
+    CGImageRelease(env, new_cg_image);
+    let host_object = env.objc.borrow_mut::<UIImageHostObject>(new);
+    host_object.left_cap_width = left_cap_width;
+    host_object.top_cap_height = top_cap_height;
+    autorelease(env, new)
+}
+
+fn UIImagePNGRepresentation(env: &mut Environment, image: id) -> id {
+    if image == nil {
+        return nil;
+    }
+    let cg_image = env.objc.borrow::<UIImageHostObject>(image).cg_image;
+    let png = cg_image::borrow_image(&env.objc, cg_image).to_png();
+    let data = ns_data::from_vec(env, png);
+    autorelease(env, data)
+}
+
+pub const FUNCTIONS: FunctionExports = &[export_c_func!(UIImagePNGRepresentation(_))];
@@ -0,0 +1,127 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `UILocalizedIndexedCollation`.
+//!
+//! The real implementation uses the collation rules of the current locale.
+//! This one always uses the English alphabet, with a "#" section for anything
+//! else. Accented Latin letters are put in the section for the base letter.
+
+use crate::frameworks::foundation::ns_string::{get_static_str, to_rust_string};
+use crate::frameworks::foundation::{ns_array, NSInteger, NSUInteger};
+use crate::objc::{
+    autorelease, id, msg, msg_send, objc_classes, ClassExports, TrivialHostObject, SEL,
+};
+use crate::Environment;
+
+#[derive(Default)]
+pub struct State {
+    current_collation: Option<id>,
+}
+
+const SECTION_TITLES: [&str; 27] = [
+    "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M", "N", "O", "P", "Q", "R", "S",
+    "T", "U", "V", "W", "X", "Y", "Z", "#",
+];
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation UILocalizedIndexedCollation: NSObject
+
++ (id)currentCollation {
+    if let Some(collation) = env
+        .framework_state
+        .uikit
+        .ui_localized_indexed_collation
+        .current_collation
+    {
+        collation
+    } else {
+        let new = env.objc.alloc_static_object(
+            this,
+            Box::new(TrivialHostObject),
+            &mut env.mem
+        );
+        env.framework_state.uikit.ui_localized_indexed_collation.current_collation = Some(new);
+        new
+    }
+}
+
+- (id)sectionTitles {
+    section_titles(env)
+}
+
+- (id)sectionIndexTitles {
+    section_titles(env)
+}
+
+- (NSInteger)sectionForSectionIndexTitleAtIndex:(NSInteger)index {
+    index
+}
+
+- (NSInteger)sectionForObject:(id)object
+      collationStringSelector:(SEL)selector {
+    let string: id = msg_send(env, (object, selector));
+    let string = to_rust_string(env, string);
+    section_for_string(&string).try_into().unwrap()
+}
+
+- (id)sortedArrayFromArray:(id)array // NSArray*
+   collationStringSelector:(SEL)selector {
+    let count: NSUInteger = msg![env; array count];
+    let mut objects = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        let object: id = msg![env; array objectAtIndex:i];
+        let string: id = msg_send(env, (object, selector));
+        let key: String = to_rust_string(env, string)
+            .chars()
+            .map(|c| fold_latin(c).to_ascii_lowercase())
+            .collect();
+        objects.push((key, object));
+    }
+    // This is a stable sort, so equal strings keep their order.
+    objects.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let objects = objects.into_iter().map(|(_, object)| object).collect();
+    let sorted = ns_array::from_vec(env, objects);
+    autorelease(env, sorted)
+}
+
+@end
+
+};
+
+fn section_titles(env: &mut Environment) -> id {
+    let titles = SECTION_TITLES
+        .iter()
+        .map(|&title| get_static_str(env, title))
+        .collect();
+    let titles = ns_array::from_vec(env, titles);
+    autorelease(env, titles)
+}
+
+/// Map accented Latin-1 letters to their base letter.
+fn fold_latin(c: char) -> char {
+    match c {
+        'À'..='Å' | 'à'..='å' => 'A',
+        'Ç' | 'ç' => 'C',
+        'È'..='Ë' | 'è'..='ë' => 'E',
+        'Ì'..='Ï' | 'ì'..='ï' => 'I',
+        'Ñ' | 'ñ' => 'N',
+        'Ò'..='Ö' | 'Ø' | 'ò'..='ö' | 'ø' => 'O',
+        'Ù'..='Ü' | 'ù'..='ü' => 'U',
+        'Ý' | 'ý' | 'ÿ' => 'Y',
+        _ => c,
+    }
+}
+
+/// Get the index of the section in [SECTION_TITLES] a string belongs in.
+fn section_for_string(string: &str) -> usize {
+    match string.trim_start().chars().next().map(fold_latin) {
+        Some(c) if c.is_ascii_alphabetic() => (c.to_ascii_uppercase() as u8 - b'A').into(),
+        _ => SECTION_TITLES.len() - 1,
+    }
+}
@@ -54,6 +54,16 @@ pub const CLASSES: ClassExports = objc_classes! {
     );
 }
 
+- (())touchesCancelled:(id)touches // NSSet* of UITouch*
+             withEvent:(id)event { // UIEvent*
+    log_dbg!(
+        "[{:?} touchesCancelled:{:?} withEvent:{:?}] (probably unhandled)",
+        this,
+        touches,
+        event,
+    );
+}
+
 - (bool)isFirstResponder {
     false
 }
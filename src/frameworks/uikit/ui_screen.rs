@@ -5,7 +5,8 @@
  */
 //! `UIScreen`.
 
-use crate::frameworks::core_graphics::{CGPoint, CGRect, CGSize};
+use super::status_bar;
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
 use crate::objc::{id, msg, objc_classes, ClassExports, TrivialHostObject};
 
 #[derive(Default)]
@@ -49,13 +50,14 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (CGRect)applicationFrame {
-    let mut bounds: CGRect = msg![env; this bounds];
-    const STATUS_BAR_HEIGHT: f32 = 20.0;
-    if !env.framework_state.uikit.ui_application.status_bar_hidden {
-        bounds.origin.y += STATUS_BAR_HEIGHT;
-        bounds.size.height -= STATUS_BAR_HEIGHT;
-    }
-    bounds
+    let bounds: CGRect = msg![env; this bounds];
+    status_bar::inset_rect(env, bounds)
+}
+
+- (CGFloat)scale {
+    // None of the devices touchHLE can simulate (see --device=) have a Retina
+    // display.
+    1.0
 }
 
 @end
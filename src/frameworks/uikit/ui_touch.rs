@@ -6,8 +6,9 @@
 //! `UITouch`.
 
 use super::ui_event;
-use crate::frameworks::core_graphics::{CGPoint, CGRect};
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect};
 use crate::frameworks::foundation::{NSInteger, NSTimeInterval, NSUInteger};
+use crate::frameworks::media_player;
 use crate::mem::MutVoidPtr;
 use crate::objc::{
     autorelease, id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject,
@@ -23,10 +24,31 @@ pub const UITouchPhaseBegan: UITouchPhase = 0;
 pub const UITouchPhaseMoved: UITouchPhase = 1;
 pub const UITouchPhaseStationary: UITouchPhase = 2;
 pub const UITouchPhaseEnded: UITouchPhase = 3;
+pub const UITouchPhaseCancelled: UITouchPhase = 4;
+
+/// How long after a tap ends the next one can begin and still count as part
+/// of a multiple tap. This is roughly what iOS uses.
+const MULTIPLE_TAP_INTERVAL: NSTimeInterval = 0.35;
+/// How far apart (in points) successive taps of a multiple tap can be. A touch
+/// that moves further than this from where it began isn't a tap at all.
+const MULTIPLE_TAP_DISTANCE: CGFloat = 45.0;
 
 #[derive(Default)]
 pub struct State {
     current_touches: HashMap<FingerId, id>,
+    /// The most recent touch that ended without moving far, for working out
+    /// `tapCount`.
+    last_tap: Option<Tap>,
+}
+
+struct Tap {
+    location: CGPoint,
+    ended: NSTimeInterval,
+    tap_count: NSUInteger,
+}
+
+fn distance(a: CGPoint, b: CGPoint) -> CGFloat {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()
 }
 
 pub(super) struct UITouchHostObject {
@@ -39,8 +61,11 @@ pub(super) struct UITouchHostObject {
     location: CGPoint,
     /// Relative to the screen
     previous_location: CGPoint,
+    /// Relative to the screen
+    initial_location: CGPoint,
     timestamp: NSTimeInterval,
     phase: UITouchPhase,
+    tap_count: NSUInteger,
 }
 impl HostObject for UITouchHostObject {}
 
@@ -56,8 +81,10 @@ pub const CLASSES: ClassExports = objc_classes! {
         window: nil,
         location: CGPoint { x: 0.0, y: 0.0 },
         previous_location: CGPoint { x: 0.0, y: 0.0 },
+        initial_location: CGPoint { x: 0.0, y: 0.0 },
         timestamp: 0.0,
         phase: UITouchPhaseBegan,
+        tap_count: 1,
     });
     env.objc.alloc_object(this, host_object, &mut env.mem)
 }
@@ -95,7 +122,7 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (NSUInteger)tapCount {
-    1 // TODO: support double-taps etc
+    env.objc.borrow::<UITouchHostObject>(this).tap_count
 }
 
 - (UITouchPhase)phase {
@@ -121,7 +148,41 @@ pub fn handle_event(env: &mut Environment, event: Event) {
     }
 }
 
+/// Create a `UIEvent` for a set of touches that just changed. Like on a real
+/// device, its `allTouches` also contains the other touches that are currently
+/// active (e.g. stationary ones).
+fn new_touch_event(env: &mut Environment, changed_touches: id, timestamp: NSTimeInterval) -> id {
+    let all_touches: id = msg_class![env; NSMutableSet allocWithZone:(MutVoidPtr::null())];
+    let changed_touches: id = msg![env; changed_touches allObjects];
+    let changed_count: NSUInteger = msg![env; changed_touches count];
+    for i in 0..changed_count {
+        let touch: id = msg![env; changed_touches objectAtIndex:i];
+        let _: () = msg![env; all_touches addObject:touch];
+    }
+    let current_touches: Vec<id> = env
+        .framework_state
+        .uikit
+        .ui_touch
+        .current_touches
+        .values()
+        .copied()
+        .collect();
+    for touch in current_touches {
+        let _: () = msg![env; all_touches addObject:touch];
+    }
+
+    let event = ui_event::new_event(env, all_touches, timestamp);
+    release(env, all_touches);
+    event
+}
+
 fn handle_touches_down(env: &mut Environment, map: HashMap<FingerId, Coords>) {
+    // Tapping while a movie is playing skips it rather than reaching the app.
+    // The touch move and up events will be ignored since there's no UITouch.
+    if media_player::movie_player::handle_tap(env) {
+        return;
+    }
+
     // Assumes the last window in the list is the one on top.
     // TODO: this is not correct once we support zPosition.
     let Some(&top_window) = env
@@ -151,8 +212,7 @@ fn handle_touches_down(env: &mut Environment, map: HashMap<FingerId, Coords>) {
         let current_touches = &mut env.framework_state.uikit.ui_touch.current_touches;
 
         if current_touches.contains_key(&finger_id) {
-            // this seems to happen only on the desktop with a single touch
-            assert_eq!(current_touches.len(), 1);
+            // this seems to happen only on the desktop
             log!(
                 "Warning: New touch {:?} initiated but current touch did not end yet, treating as movement.",
                 finger_id
@@ -167,6 +227,19 @@ fn handle_touches_down(env: &mut Environment, map: HashMap<FingerId, Coords>) {
             y: coords.1,
         };
 
+        let tap_count = match env.framework_state.uikit.ui_touch.last_tap {
+            Some(Tap {
+                location: last_location,
+                ended,
+                tap_count,
+            }) if timestamp - ended <= MULTIPLE_TAP_INTERVAL
+                && distance(location, last_location) <= MULTIPLE_TAP_DISTANCE =>
+            {
+                tap_count + 1
+            }
+            _ => 1,
+        };
+
         // TODO: is this the correct state of the UITouch and UIEvent during
         //       hit testing?
 
@@ -176,8 +249,10 @@ fn handle_touches_down(env: &mut Environment, map: HashMap<FingerId, Coords>) {
             window: nil,
             location,
             previous_location: location,
+            initial_location: location,
             timestamp,
             phase: UITouchPhaseBegan,
+            tap_count,
         };
         autorelease(env, new_touch);
 
@@ -192,7 +267,7 @@ fn handle_touches_down(env: &mut Environment, map: HashMap<FingerId, Coords>) {
         retain(env, new_touch);
     }
 
-    let event = ui_event::new_event(env, touches);
+    let event = new_touch_event(env, touches, timestamp);
     autorelease(env, event);
 
     // views with existing touches (see isMultipleTouchEnabled check below)
@@ -346,7 +421,7 @@ fn handle_touches_move(env: &mut Environment, map: HashMap<FingerId, Coords>) {
         let _: () = msg![env; touches addObject:touch];
     }
 
-    let event = ui_event::new_event(env, touches);
+    let event = new_touch_event(env, touches, timestamp);
     autorelease(env, event);
 
     for (view, touches) in view_touches {
@@ -402,6 +477,18 @@ fn handle_touches_up(env: &mut Environment, map: HashMap<FingerId, Coords>) {
         assert_eq!(host_object.phase, UITouchPhaseStationary);
         host_object.phase = UITouchPhaseEnded;
 
+        let &mut UITouchHostObject {
+            initial_location,
+            tap_count,
+            ..
+        } = host_object;
+        env.framework_state.uikit.ui_touch.last_tap =
+            (distance(location, initial_location) <= MULTIPLE_TAP_DISTANCE).then_some(Tap {
+                location,
+                ended: timestamp,
+                tap_count,
+            });
+
         let _: () = msg![env; touches addObject:touch];
 
         if let Entry::Vacant(e) = view_touches.entry(view) {
@@ -420,7 +507,7 @@ fn handle_touches_up(env: &mut Environment, map: HashMap<FingerId, Coords>) {
         retain(env, touch); // only owner now should be the NSSet
     }
 
-    let event = ui_event::new_event(env, touches);
+    let event = new_touch_event(env, touches, timestamp);
     autorelease(env, event);
 
     for (view, touches) in view_touches {
@@ -435,3 +522,60 @@ fn handle_touches_up(env: &mut Environment, map: HashMap<FingerId, Coords>) {
 
     release(env, pool);
 }
+
+/// For use when the window loses focus: cancel all touches that are in
+/// progress, because the events that would end them will never arrive. Apps
+/// get `touchesCancelled:withEvent:` rather than `touchesEnded:withEvent:`, so
+/// e.g. a drag doesn't end in an action.
+pub fn cancel_all_touches(env: &mut Environment) {
+    env.framework_state.uikit.ui_touch.last_tap = None;
+    let current_touches = std::mem::take(&mut env.framework_state.uikit.ui_touch.current_touches);
+    if current_touches.is_empty() {
+        return;
+    }
+
+    let pool: id = msg_class![env; NSAutoreleasePool new];
+
+    let timestamp: NSTimeInterval = msg_class![env; NSProcessInfo systemUptime];
+
+    let touches: id = msg_class![env; NSMutableSet allocWithZone:(MutVoidPtr::null())];
+
+    // view to set of touches for this view
+    let mut view_touches: HashMap<id, id> = HashMap::new();
+
+    for (finger_id, touch) in current_touches {
+        log_dbg!("Finger {:?} touch cancelled", finger_id);
+
+        let host_object = env.objc.borrow_mut::<UITouchHostObject>(touch);
+        host_object.previous_location = host_object.location;
+        host_object.timestamp = timestamp;
+        host_object.phase = UITouchPhaseCancelled;
+        let view = host_object.view;
+
+        let _: () = msg![env; touches addObject:touch];
+
+        if let Entry::Vacant(e) = view_touches.entry(view) {
+            let touches: id = msg_class![env; NSMutableSet allocWithZone:(MutVoidPtr::null())];
+            e.insert(touches);
+        }
+        let touches: id = *view_touches.get(&view).unwrap();
+        let _: () = msg![env; touches addObject:touch];
+
+        release(env, touch); // only owner now should be the NSSet
+    }
+
+    let event = new_touch_event(env, touches, timestamp);
+    autorelease(env, event);
+
+    for (view, touches) in view_touches {
+        log_dbg!(
+            "Sending [{:?} touchesCancelled:{:?} withEvent:{:?}]",
+            view,
+            touches,
+            event
+        );
+        let _: () = msg![env; view touchesCancelled:touches withEvent:event];
+    }
+
+    release(env, pool);
+}
@@ -12,19 +12,27 @@ pub mod ui_alert_view;
 pub mod ui_control;
 pub mod ui_image_view;
 pub mod ui_label;
+pub mod ui_navigation_bar;
 pub mod ui_scroll_view;
+pub mod ui_table_view_cell;
 pub mod ui_window;
 
 use super::ui_graphics::{UIGraphicsPopContext, UIGraphicsPushContext};
+use crate::frameworks::core_animation::ca_animation::{kCAFillModeBackwards, CACurrentMediaTime};
+use crate::frameworks::core_animation::ca_media_timing_function::{
+    kCAMediaTimingFunctionEaseIn, kCAMediaTimingFunctionEaseInEaseOut,
+    kCAMediaTimingFunctionEaseOut, kCAMediaTimingFunctionLinear,
+};
 use crate::frameworks::core_graphics::cg_affine_transform::CGAffineTransform;
 use crate::frameworks::core_graphics::cg_color::CGColorRef;
 use crate::frameworks::core_graphics::cg_context::{CGContextClearRect, CGContextRef};
 use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect};
 use crate::frameworks::foundation::ns_string::get_static_str;
-use crate::frameworks::foundation::{ns_array, NSInteger, NSUInteger};
+use crate::frameworks::foundation::{ns_array, NSInteger, NSTimeInterval, NSUInteger};
+use crate::mem::MutVoidPtr;
 use crate::objc::{
-    autorelease, id, msg, msg_class, nil, objc_classes, release, retain, Class, ClassExports,
-    HostObject, NSZonePtr,
+    autorelease, id, msg, msg_class, msg_send, nil, objc_classes, release, retain, Class,
+    ClassExports, HostObject, NSZonePtr, SEL,
 };
 use crate::Environment;
 
@@ -33,9 +41,59 @@ pub struct State {
     /// List of views for internal purposes. Non-retaining!
     pub(super) views: Vec<id>,
     pub ui_window: ui_window::State,
-}
-
-pub(super) struct UIViewHostObject {
+    /// Animation blocks opened with `beginAnimations:context:` that haven't
+    /// been committed yet, innermost last.
+    animation_blocks: Vec<AnimationBlock>,
+    /// Inverse of `areAnimationsEnabled`.
+    animations_disabled: bool,
+}
+
+type UIViewAnimationCurve = NSInteger;
+const UIViewAnimationCurveEaseInOut: UIViewAnimationCurve = 0;
+const UIViewAnimationCurveEaseIn: UIViewAnimationCurve = 1;
+const UIViewAnimationCurveEaseOut: UIViewAnimationCurve = 2;
+const UIViewAnimationCurveLinear: UIViewAnimationCurve = 3;
+
+/// State for a block of animations started with `beginAnimations:context:`.
+struct AnimationBlock {
+    /// `NSString*`, possibly `nil`. This is a strong reference.
+    animation_id: id,
+    context: MutVoidPtr,
+    duration: NSTimeInterval,
+    delay: NSTimeInterval,
+    curve: UIViewAnimationCurve,
+    /// This is a weak reference.
+    delegate: id,
+    will_start_selector: Option<SEL>,
+    did_stop_selector: Option<SEL>,
+    begins_from_current_state: bool,
+    repeat_count: f32,
+    repeat_autoreverses: bool,
+    /// Changes made within the block: the layer (a strong reference), its
+    /// animatable key path, and the value before the change (an `NSNumber*`
+    /// or `NSValue*`, also a strong reference).
+    changes: Vec<(id, &'static str, id)>,
+}
+
+/// Host object for the delegate shared by all the `CABasicAnimation`s created
+/// for an animation block, which calls the UIKit-style delegate methods once
+/// for the whole block.
+struct AnimationBlockDelegateHostObject {
+    /// `NSString*`, possibly `nil`. This is a strong reference.
+    animation_id: id,
+    context: MutVoidPtr,
+    /// This is a weak reference.
+    delegate: id,
+    will_start_selector: Option<SEL>,
+    did_stop_selector: Option<SEL>,
+    started: bool,
+    /// Number of animations that haven't stopped yet.
+    remaining: usize,
+    all_finished: bool,
+}
+impl HostObject for AnimationBlockDelegateHostObject {}
+
+pub struct UIViewHostObject {
     /// CALayer or subclass.
     layer: id,
     /// Subviews in back-to-front order. These are strong references.
@@ -62,6 +120,155 @@ impl Default for UIViewHostObject {
     }
 }
 
+fn current_animation_block(env: &mut Environment) -> Option<&mut AnimationBlock> {
+    env.framework_state
+        .uikit
+        .ui_view
+        .animation_blocks
+        .last_mut()
+}
+
+/// Called by the setters of animatable properties before making a change, so
+/// that it can be animated if there is an animation block open.
+fn record_animatable_change(env: &mut Environment, view: id, key_path: &'static str) {
+    if env.framework_state.uikit.ui_view.animations_disabled {
+        return;
+    }
+    let layer = env.objc.borrow::<UIViewHostObject>(view).layer;
+    let Some(block) = current_animation_block(env) else {
+        return;
+    };
+    // Only the value before the first change in the block matters.
+    if block
+        .changes
+        .iter()
+        .any(|&(other_layer, other_key_path, _)| other_layer == layer && other_key_path == key_path)
+    {
+        return;
+    }
+
+    // Without this option, any animation already in progress is cut short.
+    let source: id = if block.begins_from_current_state {
+        msg![env; layer presentationLayer]
+    } else {
+        layer
+    };
+    let old_value: id = match key_path {
+        "opacity" => {
+            let opacity: f32 = msg![env; source opacity];
+            msg_class![env; NSNumber numberWithFloat:opacity]
+        }
+        "position" => {
+            let position: CGPoint = msg![env; source position];
+            msg_class![env; NSValue valueWithCGPoint:position]
+        }
+        "bounds" => {
+            let bounds: CGRect = msg![env; source bounds];
+            msg_class![env; NSValue valueWithCGRect:bounds]
+        }
+        "transform" => {
+            let transform: CGAffineTransform = msg![env; source affineTransform];
+            msg_class![env; NSValue valueWithCGAffineTransform:transform]
+        }
+        _ => unreachable!(),
+    };
+    retain(env, layer);
+    retain(env, old_value);
+    current_animation_block(env)
+        .unwrap()
+        .changes
+        .push((layer, key_path, old_value));
+}
+
+/// Implementation of `commitAnimations`: creates a `CABasicAnimation` for each
+/// property changed within the block.
+fn commit_animation_block(env: &mut Environment, block: AnimationBlock) {
+    let AnimationBlock {
+        animation_id,
+        context,
+        duration,
+        delay,
+        curve,
+        delegate,
+        will_start_selector,
+        did_stop_selector,
+        begins_from_current_state: _,
+        repeat_count,
+        repeat_autoreverses,
+        changes,
+    } = block;
+
+    log_dbg!(
+        "Committing animation block {:?} with {} change(s), duration {}s, delay {}s",
+        animation_id,
+        changes.len(),
+        duration,
+        delay
+    );
+
+    let host_object = Box::new(AnimationBlockDelegateHostObject {
+        animation_id,
+        context,
+        delegate,
+        will_start_selector,
+        did_stop_selector,
+        started: false,
+        remaining: changes.len(),
+        all_finished: true,
+    });
+    let class = env
+        .objc
+        .get_known_class("_touchHLE_UIViewAnimationBlockDelegate", &mut env.mem);
+    let block_delegate = env.objc.alloc_object(class, host_object, &mut env.mem);
+
+    if changes.is_empty() {
+        // Nothing to animate, but the delegate still expects to hear about it.
+        env.objc
+            .borrow_mut::<AnimationBlockDelegateHostObject>(block_delegate)
+            .remaining = 1;
+        () = msg![env; block_delegate animationDidStart:nil];
+        () = msg![env; block_delegate animationDidStop:nil finished:true];
+        release(env, block_delegate);
+        return;
+    }
+
+    let timing_function_name = match curve {
+        UIViewAnimationCurveEaseIn => kCAMediaTimingFunctionEaseIn,
+        UIViewAnimationCurveEaseOut => kCAMediaTimingFunctionEaseOut,
+        UIViewAnimationCurveLinear => kCAMediaTimingFunctionLinear,
+        _ => kCAMediaTimingFunctionEaseInEaseOut,
+    };
+    let timing_function_name = get_static_str(env, timing_function_name);
+    let timing_function: id =
+        msg_class![env; CAMediaTimingFunction functionWithName:timing_function_name];
+    let begin_time = if delay > 0.0 {
+        CACurrentMediaTime(env) + delay
+    } else {
+        0.0
+    };
+    // The view should keep its old appearance during the delay.
+    let fill_mode = get_static_str(env, kCAFillModeBackwards);
+
+    for (layer, key_path, old_value) in changes {
+        let key_path = get_static_str(env, key_path);
+        let animation: id = msg_class![env; CABasicAnimation animationWithKeyPath:key_path];
+        () = msg![env; animation setFromValue:old_value];
+        () = msg![env; animation setDuration:duration];
+        () = msg![env; animation setBeginTime:begin_time];
+        () = msg![env; animation setFillMode:fill_mode];
+        () = msg![env; animation setTimingFunction:timing_function];
+        () = msg![env; animation setRepeatCount:repeat_count];
+        () = msg![env; animation setAutoreverses:repeat_autoreverses];
+        () = msg![env; animation setDelegate:block_delegate];
+        // This replaces any existing animation of the same property.
+        () = msg![env; layer addAnimation:animation forKey:key_path];
+        release(env, old_value);
+        release(env, layer);
+    }
+    // The animations retain the delegate.
+    release(env, block_delegate);
+}
+
 /// Shared parts of `initWithCoder:` and `initWithFrame:`. These can't call
 /// `init`: the subclass may have overridden `init` and will not expect to be
 /// called here.
@@ -98,6 +305,88 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.get_known_class("CALayer", &mut env.mem)
 }
 
+// Animation blocks
+
++ (())beginAnimations:(id)animation_id // NSString*
+              context:(MutVoidPtr)context {
+    retain(env, animation_id);
+    env.framework_state.uikit.ui_view.animation_blocks.push(AnimationBlock {
+        animation_id,
+        context,
+        duration: 0.2,
+        delay: 0.0,
+        curve: UIViewAnimationCurveEaseInOut,
+        delegate: nil,
+        will_start_selector: None,
+        did_stop_selector: None,
+        begins_from_current_state: false,
+        repeat_count: 0.0,
+        repeat_autoreverses: false,
+        changes: Vec::new(),
+    });
+}
+
++ (())commitAnimations {
+    let Some(block) = env.framework_state.uikit.ui_view.animation_blocks.pop() else {
+        log!("Warning: [UIView commitAnimations] called without an animation block, ignoring.");
+        return;
+    };
+    commit_animation_block(env, block);
+}
+
++ (())setAnimationDuration:(NSTimeInterval)duration {
+    if let Some(block) = current_animation_block(env) {
+        block.duration = duration;
+    }
+}
++ (())setAnimationDelay:(NSTimeInterval)delay {
+    if let Some(block) = current_animation_block(env) {
+        block.delay = delay;
+    }
+}
++ (())setAnimationCurve:(UIViewAnimationCurve)curve {
+    if let Some(block) = current_animation_block(env) {
+        block.curve = curve;
+    }
+}
++ (())setAnimationDelegate:(id)delegate {
+    if let Some(block) = current_animation_block(env) {
+        block.delegate = delegate;
+    }
+}
++ (())setAnimationWillStartSelector:(SEL)selector {
+    if let Some(block) = current_animation_block(env) {
+        block.will_start_selector = (!selector.is_null()).then_some(selector);
+    }
+}
++ (())setAnimationDidStopSelector:(SEL)selector {
+    if let Some(block) = current_animation_block(env) {
+        block.did_stop_selector = (!selector.is_null()).then_some(selector);
+    }
+}
++ (())setAnimationBeginsFromCurrentState:(bool)from_current_state {
+    if let Some(block) = current_animation_block(env) {
+        block.begins_from_current_state = from_current_state;
+    }
+}
++ (())setAnimationRepeatCount:(f32)repeat_count {
+    if let Some(block) = current_animation_block(env) {
+        block.repeat_count = repeat_count;
+    }
+}
++ (())setAnimationRepeatAutoreverses:(bool)repeat_autoreverses {
+    if let Some(block) = current_animation_block(env) {
+        block.repeat_autoreverses = repeat_autoreverses;
+    }
+}
+
++ (bool)areAnimationsEnabled {
+    !env.framework_state.uikit.ui_view.animations_disabled
+}
++ (())setAnimationsEnabled:(bool)enabled {
+    env.framework_state.uikit.ui_view.animations_disabled = !enabled;
+}
+
 // TODO: accessors etc
 
 // initWithCoder: and initWithFrame: are basically UIView's designated
@@ -329,8 +618,13 @@ pub const CLASSES: ClassExports = objc_classes! {
     msg![env; layer setHidden:hidden]
 }
 
+- (bool)clipsToBounds {
+    let layer = env.objc.borrow::<UIViewHostObject>(this).layer;
+    msg![env; layer masksToBounds]
+}
 - (())setClipsToBounds:(bool)clips {
-    log!("TODO: [{:?} setClipsToBounds:{}]", this, clips);
+    let layer = env.objc.borrow::<UIViewHostObject>(this).layer;
+    msg![env; layer setMasksToBounds:clips]
 }
 
 - (bool)isOpaque {
@@ -347,6 +641,7 @@ pub const CLASSES: ClassExports = objc_classes! {
     msg![env; layer opacity]
 }
 - (())setAlpha:(CGFloat)alpha {
+    record_animatable_change(env, this, "opacity");
     let layer = env.objc.borrow::<UIViewHostObject>(this).layer;
     msg![env; layer setOpacity:alpha]
 }
@@ -373,6 +668,7 @@ pub const CLASSES: ClassExports = objc_classes! {
     msg![env; layer bounds]
 }
 - (())setBounds:(CGRect)bounds {
+    record_animatable_change(env, this, "bounds");
     let layer = env.objc.borrow::<UIViewHostObject>(this).layer;
     msg![env; layer setBounds:bounds]
 }
@@ -382,6 +678,7 @@ pub const CLASSES: ClassExports = objc_classes! {
     msg![env; layer position]
 }
 - (())setCenter:(CGPoint)center {
+    record_animatable_change(env, this, "position");
     let layer = env.objc.borrow::<UIViewHostObject>(this).layer;
     msg![env; layer setPosition:center]
 }
@@ -390,12 +687,20 @@ pub const CLASSES: ClassExports = objc_classes! {
     msg![env; layer frame]
 }
 - (())setFrame:(CGRect)frame {
+    record_animatable_change(env, this, "position");
+    record_animatable_change(env, this, "bounds");
     let layer = env.objc.borrow::<UIViewHostObject>(this).layer;
     msg![env; layer setFrame:frame]
 }
 
+- (CGAffineTransform)transform {
+    let layer = env.objc.borrow::<UIViewHostObject>(this).layer;
+    msg![env; layer affineTransform]
+}
 - (())setTransform:(CGAffineTransform)transform {
-    log!("TODO: [{:?} setTransform:{:?}]", this, transform);
+    record_animatable_change(env, this, "transform");
+    let layer = env.objc.borrow::<UIViewHostObject>(this).layer;
+    msg![env; layer setAffineTransform:transform]
 }
 
 - (())setContentMode:(NSInteger)content_mode { // should be UIViewContentMode
@@ -449,12 +754,8 @@ pub const CLASSES: ClassExports = objc_classes! {
         if hidden || alpha < 0.01 || !interactible {
            continue;
         }
-        let frame: CGRect = msg![env; subview frame];
-        let bounds: CGRect = msg![env; subview bounds];
-        let point = CGPoint {
-            x: point.x - frame.origin.x + bounds.origin.x,
-            y: point.y - frame.origin.y + bounds.origin.y,
-        };
+        // This maps the point through the inverse of the subview's transform.
+        let point: CGPoint = msg![env; subview convertPoint:point fromView:this];
         let subview: id = msg![env; subview hitTest:point withEvent:event];
         if subview != nil {
             return subview;
@@ -520,4 +821,57 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 @end
 
+// Delegate for the animations created by an animation block, see
+// commit_animation_block().
+@implementation _touchHLE_UIViewAnimationBlockDelegate: NSObject
+
+- (())dealloc {
+    let animation_id = env.objc.borrow::<AnimationBlockDelegateHostObject>(this).animation_id;
+    release(env, animation_id);
+    env.objc.dealloc_object(this, &mut env.mem)
+}
+
+- (())animationDidStart:(id)_animation { // CAAnimation*
+    let host_obj = env.objc.borrow_mut::<AnimationBlockDelegateHostObject>(this);
+    if std::mem::replace(&mut host_obj.started, true) {
+        return;
+    }
+    let &mut AnimationBlockDelegateHostObject {
+        animation_id,
+        context,
+        delegate,
+        will_start_selector,
+        ..
+    } = host_obj;
+    if let (Some(selector), true) = (will_start_selector, delegate != nil) {
+        // The selector should be animationWillStart:context:
+        () = msg_send(env, (delegate, selector, animation_id, context));
+    }
+}
+
+- (())animationDidStop:(id)_animation // CAAnimation*
+              finished:(bool)finished {
+    let host_obj = env.objc.borrow_mut::<AnimationBlockDelegateHostObject>(this);
+    host_obj.remaining -= 1;
+    host_obj.all_finished &= finished;
+    if host_obj.remaining > 0 {
+        return;
+    }
+    let &mut AnimationBlockDelegateHostObject {
+        animation_id,
+        context,
+        delegate,
+        did_stop_selector,
+        all_finished,
+        ..
+    } = host_obj;
+    if let (Some(selector), true) = (did_stop_selector, delegate != nil) {
+        // The selector should be animationDidStop:finished:context:
+        let finished: id = msg_class![env; NSNumber numberWithBool:all_finished];
+        () = msg_send(env, (delegate, selector, animation_id, finished, context));
+    }
+}
+
+@end
+
 };
@@ -4,11 +4,21 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 //! `UIButton`.
-
-use super::{UIControlState, UIControlStateNormal};
-use crate::frameworks::core_graphics::{CGPoint, CGRect};
+//!
+//! Each of the title, title color, image and background image can be set per
+//! control state. The background image is drawn by the button itself, stretched
+//! to its bounds, while the image and title are shown by subviews laid out side
+//! by side in the middle.
+
+use super::{
+    UIControlState, UIControlStateDisabled, UIControlStateHighlighted, UIControlStateNormal,
+};
+use crate::frameworks::core_graphics::cg_color::{self, CGColorRelease};
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
 use crate::frameworks::foundation::NSInteger;
 use crate::frameworks::uikit::ui_font::UITextAlignmentCenter;
+use crate::frameworks::uikit::ui_geometry::UIEdgeInsets;
+use crate::frameworks::uikit::ui_image;
 use crate::objc::{
     autorelease, id, impl_HostObject_with_superclass, msg, msg_class, msg_super, nil, objc_classes,
     release, retain, ClassExports, NSZonePtr,
@@ -28,6 +38,13 @@ const UIButtonTypeInfoDark: UIButtonType = 4;
 #[allow(dead_code)]
 const UIButtonTypeContactAdd: UIButtonType = 5;
 
+/// How much a highlighted button's image is darkened by, if
+/// `adjustsImageWhenHighlighted` is set.
+const HIGHLIGHTED_IMAGE_BRIGHTNESS: CGFloat = 0.6;
+/// How much a disabled button's image is faded by, if
+/// `adjustsImageWhenDisabled` is set.
+const DISABLED_IMAGE_OPACITY: CGFloat = 0.5;
+
 pub struct UIButtonHostObject {
     superclass: super::UIControlHostObject,
     type_: UIButtonType,
@@ -35,8 +52,8 @@ pub struct UIButtonHostObject {
     title_label: id,
     /// `UIImageView*`
     image_view: id,
-    /// `UIImageView*`
-    background_image_view: id,
+    /// `UIImage*` drawn by `drawRect:`, already adjusted for the current state.
+    current_background_image: id,
     /// Values are `UIString*`
     titles_for_states: HashMap<UIControlState, id>,
     /// Values are `UIColor*`
@@ -45,6 +62,11 @@ pub struct UIButtonHostObject {
     images_for_states: HashMap<UIControlState, id>,
     /// Values are `UIImage*`
     background_images_for_states: HashMap<UIControlState, id>,
+    content_edge_insets: UIEdgeInsets,
+    title_edge_insets: UIEdgeInsets,
+    image_edge_insets: UIEdgeInsets,
+    adjusts_image_when_highlighted: bool,
+    adjusts_image_when_disabled: bool,
 }
 impl_HostObject_with_superclass!(UIButtonHostObject);
 impl Default for UIButtonHostObject {
@@ -54,15 +76,83 @@ impl Default for UIButtonHostObject {
             type_: UIButtonTypeCustom,
             title_label: nil,
             image_view: nil,
-            background_image_view: nil,
+            current_background_image: nil,
             titles_for_states: HashMap::new(),
             title_colors_for_states: HashMap::new(),
             images_for_states: HashMap::new(),
             background_images_for_states: HashMap::new(),
+            content_edge_insets: UIEdgeInsets::default(),
+            title_edge_insets: UIEdgeInsets::default(),
+            image_edge_insets: UIEdgeInsets::default(),
+            adjusts_image_when_highlighted: true,
+            adjusts_image_when_disabled: true,
         }
     }
 }
 
+/// Look up a per-state value. Like in UIKit, if there's no value for the
+/// exact state, the value for the normal state is used. The second part of
+/// the result is [true] if the value is specific to the state.
+fn value_for_state(values: &HashMap<UIControlState, id>, state: UIControlState) -> (id, bool) {
+    match values.get(&state) {
+        Some(&value) if value != nil => (value, true),
+        _ => (
+            values.get(&UIControlStateNormal).copied().unwrap_or(nil),
+            state == UIControlStateNormal,
+        ),
+    }
+}
+
+/// Replace a per-state value, retaining the new value and releasing the old
+/// one.
+fn set_value_for_state(
+    env: &mut Environment,
+    this: id,
+    get_values: fn(&mut UIButtonHostObject) -> &mut HashMap<UIControlState, id>,
+    state: UIControlState,
+    value: id,
+) {
+    retain(env, value);
+    let host_obj = env.objc.borrow_mut::<UIButtonHostObject>(this);
+    if let Some(old) = get_values(host_obj).insert(state, value) {
+        release(env, old);
+    }
+    update(env, this);
+}
+
+/// Get the image or background image for the current state. If the image
+/// isn't specific to the state, it's darkened or faded like in UIKit. The
+/// result is autoreleased if it's a new image.
+fn image_for_current_state(env: &mut Environment, this: id, background: bool) -> id {
+    let state: UIControlState = msg![env; this state];
+    let host_obj = env.objc.borrow::<UIButtonHostObject>(this);
+    let images = if background {
+        &host_obj.background_images_for_states
+    } else {
+        &host_obj.images_for_states
+    };
+    let (image, specific) = value_for_state(images, state);
+    if image == nil || specific {
+        return image;
+    }
+    let darken =
+        (state & UIControlStateHighlighted) != 0 && host_obj.adjusts_image_when_highlighted;
+    let fade = (state & UIControlStateDisabled) != 0 && host_obj.adjusts_image_when_disabled;
+    if !darken && !fade {
+        return image;
+    }
+    ui_image::copy_with_adjusted_pixels(
+        env,
+        image,
+        if darken {
+            HIGHLIGHTED_IMAGE_BRIGHTNESS
+        } else {
+            1.0
+        },
+        if fade { DISABLED_IMAGE_OPACITY } else { 1.0 },
+    )
+}
+
 fn update(env: &mut Environment, this: id) {
     let title_label: id = msg![env; this titleLabel];
     let title: id = msg![env; this currentTitle];
@@ -71,12 +161,36 @@ fn update(env: &mut Environment, this: id) {
     () = msg![env; title_label setTextColor:title_color];
 
     let image_view: id = msg![env; this imageView];
-    let image: id = msg![env; this currentImage];
+    let image = image_for_current_state(env, this, /* background: */ false);
     () = msg![env; image_view setImage:image];
 
-    let background_image_view: id = msg![env; this backgroundImageView];
-    let background_image: id = msg![env; this currentBackgroundImage];
-    () = msg![env; background_image_view setImage:background_image];
+    let background_image = image_for_current_state(env, this, /* background: */ true);
+    retain(env, background_image);
+    let old = std::mem::replace(
+        &mut env
+            .objc
+            .borrow_mut::<UIButtonHostObject>(this)
+            .current_background_image,
+        background_image,
+    );
+    release(env, old);
+
+    let host_obj = env.objc.borrow::<UIButtonHostObject>(this);
+    if host_obj.type_ == UIButtonTypeRoundedRect {
+        let highlighted: bool = msg![env; this isHighlighted];
+        let color: id = if highlighted {
+            msg_class![env; UIColor colorWithRed:(0.02 as CGFloat)
+                                           green:(0.45 as CGFloat)
+                                            blue:(0.93 as CGFloat)
+                                           alpha:(1.0 as CGFloat)]
+        } else {
+            msg_class![env; UIColor whiteColor]
+        };
+        () = msg![env; this setBackgroundColor:color];
+    }
+
+    () = msg![env; this setNeedsDisplay];
+    () = msg![env; this layoutSubviews];
 }
 
 pub const CLASSES: ClassExports = objc_classes! {
@@ -95,16 +209,27 @@ pub const CLASSES: ClassExports = objc_classes! {
     match type_ {
         UIButtonTypeCustom => (),
         UIButtonTypeRoundedRect => {
-            let bg_color: id = msg_class![env; UIColor whiteColor];
-            // TODO: set blue background image in highlighted state
-            // TODO: image highlighting?
-            () = msg![env; button setBackgroundColor:bg_color];
-            // On the real iPhone OS, this is a semi-dark, desaturated blue.
-            // Should we match it?
-            let text_color: id = msg_class![env; UIColor blackColor];
+            env.objc.borrow_mut::<UIButtonHostObject>(button).type_ = type_;
+            // The classic white rounded rectangle with a grey border, which
+            // turns blue when highlighted (see update()).
+            let layer: id = msg![env; button layer];
+            () = msg![env; layer setCornerRadius:(9.0 as CGFloat)];
+            () = msg![env; layer setBorderWidth:(1.0 as CGFloat)];
+            let border_color = cg_color::from_rgba(env, (0.5, 0.53, 0.58, 1.0));
+            () = msg![env; layer setBorderColor:border_color];
+            CGColorRelease(env, border_color);
+
+            let text_color: id = msg_class![env; UIColor colorWithRed:(0.2 as CGFloat)
+                                                                green:(0.31 as CGFloat)
+                                                                 blue:(0.52 as CGFloat)
+                                                                alpha:(1.0 as CGFloat)];
             () = msg![env; button setTitleColor:text_color
                                        forState:UIControlStateNormal];
-            // TODO: set border and corner rounding, once supported
+            let highlighted_text_color: id = msg_class![env; UIColor whiteColor];
+            () = msg![env; button setTitleColor:highlighted_text_color
+                                       forState:UIControlStateHighlighted];
+            let font: id = msg_class![env; UIFont boldSystemFontOfSize:(15.0 as CGFloat)];
+            () = msg![env; button setFont:font];
         },
         _ => {
             log!("TODO: UIButtonType {}", type_);
@@ -127,20 +252,17 @@ pub const CLASSES: ClassExports = objc_classes! {
     let text_color: id = msg_class![env; UIColor whiteColor];
 
     let image_view: id = msg_class![env; UIImageView new];
-    let background_image_view: id = msg_class![env; UIImageView new];
 
     let host_obj = env.objc.borrow_mut::<UIButtonHostObject>(this);
     host_obj.title_label = title_label;
     host_obj.image_view = image_view;
-    host_obj.background_image_view = background_image_view;
     host_obj.titles_for_states.insert(UIControlStateNormal, nil);
     host_obj.title_colors_for_states.insert(UIControlStateNormal, text_color);
     host_obj.images_for_states.insert(UIControlStateNormal, nil);
     host_obj.background_images_for_states.insert(UIControlStateNormal, nil);
 
-    () = msg![env; this addSubview:background_image_view];
-    () = msg![env; this addSubview:title_label];
     () = msg![env; this addSubview:image_view];
+    () = msg![env; this addSubview:title_label];
     update(env, this);
 
     this
@@ -154,16 +276,21 @@ pub const CLASSES: ClassExports = objc_classes! {
         type_: _,
         title_label,
         image_view,
-        background_image_view,
+        current_background_image,
         titles_for_states,
         title_colors_for_states,
         images_for_states,
-        background_images_for_states
+        background_images_for_states,
+        content_edge_insets: _,
+        title_edge_insets: _,
+        image_edge_insets: _,
+        adjusts_image_when_highlighted: _,
+        adjusts_image_when_disabled: _,
     } = std::mem::take(env.objc.borrow_mut(this));
 
     release(env, title_label);
     release(env, image_view);
-    release(env, background_image_view);
+    release(env, current_background_image);
     for (_state, title) in titles_for_states {
         release(env, title);
     }
@@ -180,14 +307,83 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (())layoutSubviews {
-    let label = env.objc.borrow_mut::<UIButtonHostObject>(this).title_label;
-    let background_image_view = env.objc.borrow_mut::<UIButtonHostObject>(this).background_image_view;
+    let &UIButtonHostObject {
+        title_label,
+        image_view,
+        content_edge_insets,
+        title_edge_insets,
+        image_edge_insets,
+        ..
+    } = env.objc.borrow(this);
     let bounds: CGRect = msg![env; this bounds];
+    let content_rect = content_edge_insets.inset_rect(bounds);
 
-    () = msg![env; background_image_view setFrame:bounds];
-    () = msg![env; label setFrame:bounds];
-    // TODO: layout for image
+    // An image that is too big is shrunk to fit.
+    let image: id = msg![env; image_view image];
+    let mut image_size = if image == nil {
+        CGSize::default()
+    } else {
+        msg![env; image size]
+    };
+    let fit = (content_rect.size.width / image_size.width)
+        .min(content_rect.size.height / image_size.height);
+    if fit < 1.0 {
+        image_size.width *= fit;
+        image_size.height *= fit;
+    }
+
+    // The title gets whatever space is left.
+    let title: id = msg![env; title_label text];
+    let mut title_size = if title == nil {
+        CGSize::default()
+    } else {
+        let font: id = msg![env; title_label font];
+        msg![env; title sizeWithFont:font]
+    };
+    title_size.width = title_size
+        .width
+        .min(content_rect.size.width - image_size.width)
+        .max(0.0);
+    title_size.height = title_size.height.min(content_rect.size.height);
+
+    // The image and title are centered side by side. Their insets move them
+    // relative to that position.
+    let left = content_rect.origin.x
+        + (content_rect.size.width - image_size.width - title_size.width) / 2.0;
+    let place = |x: CGFloat, size: CGSize, insets: UIEdgeInsets| CGRect {
+        origin: CGPoint {
+            x: x + (insets.left - insets.right) / 2.0,
+            y: content_rect.origin.y
+                + (content_rect.size.height - size.height) / 2.0
+                + (insets.top - insets.bottom) / 2.0,
+        },
+        size,
+    };
+    let image_frame = place(left, image_size, image_edge_insets);
+    let title_frame = place(left + image_size.width, title_size, title_edge_insets);
+    () = msg![env; image_view setFrame:image_frame];
+    () = msg![env; title_label setFrame:title_frame];
+}
 
+- (())drawRect:(CGRect)_rect {
+    let background_image = env.objc.borrow::<UIButtonHostObject>(this).current_background_image;
+    if background_image != nil {
+        let bounds: CGRect = msg![env; this bounds];
+        let rect = CGRect {
+            origin: CGPoint { x: 0.0, y: 0.0 },
+            size: bounds.size,
+        };
+        () = msg![env; background_image drawInRect:rect];
+    }
+}
+
+- (())setFrame:(CGRect)frame {
+    () = msg_super![env; this setFrame:frame];
+    update(env, this);
+}
+- (())setBounds:(CGRect)bounds {
+    () = msg_super![env; this setBounds:bounds];
+    update(env, this);
 }
 
 - (UIButtonType)buttonType {
@@ -202,9 +398,44 @@ pub const CLASSES: ClassExports = objc_classes! {
     env.objc.borrow_mut::<UIButtonHostObject>(this).image_view
 }
 
-- (id)backgroundImageView {
-    env.objc.borrow_mut::<UIButtonHostObject>(this).background_image_view
+
+- (UIEdgeInsets)contentEdgeInsets {
+    env.objc.borrow::<UIButtonHostObject>(this).content_edge_insets
+}
+- (())setContentEdgeInsets:(UIEdgeInsets)insets {
+    env.objc.borrow_mut::<UIButtonHostObject>(this).content_edge_insets = insets;
+    update(env, this);
+}
+- (UIEdgeInsets)titleEdgeInsets {
+    env.objc.borrow::<UIButtonHostObject>(this).title_edge_insets
+}
+- (())setTitleEdgeInsets:(UIEdgeInsets)insets {
+    env.objc.borrow_mut::<UIButtonHostObject>(this).title_edge_insets = insets;
+    update(env, this);
+}
+- (UIEdgeInsets)imageEdgeInsets {
+    env.objc.borrow::<UIButtonHostObject>(this).image_edge_insets
+}
+- (())setImageEdgeInsets:(UIEdgeInsets)insets {
+    env.objc.borrow_mut::<UIButtonHostObject>(this).image_edge_insets = insets;
+    update(env, this);
+}
+
+- (bool)adjustsImageWhenHighlighted {
+    env.objc.borrow::<UIButtonHostObject>(this).adjusts_image_when_highlighted
+}
+- (())setAdjustsImageWhenHighlighted:(bool)adjusts {
+    env.objc.borrow_mut::<UIButtonHostObject>(this).adjusts_image_when_highlighted = adjusts;
+    update(env, this);
 }
+- (bool)adjustsImageWhenDisabled {
+    env.objc.borrow::<UIButtonHostObject>(this).adjusts_image_when_disabled
+}
+- (())setAdjustsImageWhenDisabled:(bool)adjusts {
+    env.objc.borrow_mut::<UIButtonHostObject>(this).adjusts_image_when_disabled = adjusts;
+    update(env, this);
+}
+
 - (())setEnabled:(bool)enabled {
     () = msg_super![env; this setEnabled:enabled];
     update(env, this);
@@ -229,19 +460,11 @@ pub const CLASSES: ClassExports = objc_classes! {
     msg![env; this titleForState:state]
 }
 - (id)titleForState:(UIControlState)state {
-    let host_obj = env.objc.borrow::<UIButtonHostObject>(this);
-    host_obj.titles_for_states.get(&state).or_else(|| {
-        host_obj.titles_for_states.get(&UIControlStateNormal)
-    }).copied().unwrap()
+    value_for_state(&env.objc.borrow::<UIButtonHostObject>(this).titles_for_states, state).0
 }
 - (())setTitle:(id)title // NSString*
       forState:(UIControlState)state {
-    retain(env, title);
-    let host_obj = env.objc.borrow_mut::<UIButtonHostObject>(this);
-    if let Some(old) = host_obj.titles_for_states.insert(state, title) {
-        release(env, old);
-    }
-    update(env, this);
+    set_value_for_state(env, this, |host_obj| &mut host_obj.titles_for_states, state, title);
 }
 
 - (id)currentBackgroundImage {
@@ -249,18 +472,10 @@ pub const CLASSES: ClassExports = objc_classes! {
     msg![env; this backgroundImageForState:state]
 }
 - (id)backgroundImageForState:(UIControlState)state {
-    let host_obj = env.objc.borrow::<UIButtonHostObject>(this);
-    host_obj.background_images_for_states.get(&state).or_else(|| {
-        host_obj.background_images_for_states.get(&UIControlStateNormal)
-    }).copied().unwrap()
+    value_for_state(&env.objc.borrow::<UIButtonHostObject>(this).background_images_for_states, state).0
 }
 - (())setBackgroundImage:(id)image forState:(UIControlState)state {
-    retain(env,image);
-    let host_obj = env.objc.borrow_mut::<UIButtonHostObject>(this);
-    if let Some(old) = host_obj.background_images_for_states.insert(state, image) {
-        release(env, old);
-    }
-    update(env, this);
+    set_value_for_state(env, this, |host_obj| &mut host_obj.background_images_for_states, state, image);
 }
 
 - (id)currentTitleColor {
@@ -268,19 +483,11 @@ pub const CLASSES: ClassExports = objc_classes! {
     msg![env; this titleColorForState:state]
 }
 - (id)titleColorForState:(UIControlState)state {
-    let host_obj = env.objc.borrow::<UIButtonHostObject>(this);
-    host_obj.title_colors_for_states.get(&state).or_else(|| {
-        host_obj.title_colors_for_states.get(&UIControlStateNormal)
-    }).copied().unwrap()
+    value_for_state(&env.objc.borrow::<UIButtonHostObject>(this).title_colors_for_states, state).0
 }
 - (())setTitleColor:(id)color // UIColor*
       forState:(UIControlState)state {
-    retain(env, color);
-    let host_obj = env.objc.borrow_mut::<UIButtonHostObject>(this);
-    if let Some(old) = host_obj.title_colors_for_states.insert(state, color) {
-        release(env, old);
-    }
-    update(env, this);
+    set_value_for_state(env, this, |host_obj| &mut host_obj.title_colors_for_states, state, color);
 }
 
 - (id)currentImage {
@@ -288,19 +495,11 @@ pub const CLASSES: ClassExports = objc_classes! {
     msg![env; this imageForState:state]
 }
 - (id)imageForState:(UIControlState)state {
-    let host_obj = env.objc.borrow::<UIButtonHostObject>(this);
-    host_obj.images_for_states.get(&state).or_else(|| {
-        host_obj.images_for_states.get(&UIControlStateNormal)
-    }).copied().unwrap()
+    value_for_state(&env.objc.borrow::<UIButtonHostObject>(this).images_for_states, state).0
 }
 - (())setImage:(id)image // UIImage*
       forState:(UIControlState)state {
-    retain(env, image);
-    let host_obj = env.objc.borrow_mut::<UIButtonHostObject>(this);
-    if let Some(old) = host_obj.images_for_states.insert(state, image) {
-        release(env, old);
-    }
-    update(env, this);
+    set_value_for_state(env, this, |host_obj| &mut host_obj.images_for_states, state, image);
 }
 
 // TODO: actions, etc
@@ -192,9 +192,6 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 - (())setNumberOfLines:(NSInteger)number {
     env.objc.borrow_mut::<UILabelHostObject>(this).number_of_lines = number;
-    if number != 0 && number != 1 {
-        log!("TODO: UILabel numberOfLines > 1 (label {:?})", this);
-    }
     () = msg![env; this setNeedsDisplay];
 }
 
@@ -215,15 +212,26 @@ pub const CLASSES: ClassExports = objc_classes! {
     let (r, g, b, a) = ui_color::get_rgba(&env.objc, text_color);
     CGContextSetRGBFillColor(env, context, r, g, b, a);
 
-    // TODO: handle line counts other than 0 and 1 properly. 0 = unlimited
-    // (note the log message in setNumberOfLines:)
     let single_line = number_of_lines == 1;
 
     let calculated_size: CGSize = if single_line {
-        msg![env; text sizeWithFont:font]
+        msg![env; text sizeWithFont:font
+                           forWidth:(bounds.size.width)
+                      lineBreakMode:line_break_mode]
     } else {
+        // 0 means there is no limit other than the bounds.
+        let max_height = if number_of_lines > 0 {
+            let leading: CGFloat = msg![env; font leading];
+            bounds.size.height.min(leading * number_of_lines as CGFloat)
+        } else {
+            bounds.size.height
+        };
+        let max_size = CGSize {
+            width: bounds.size.width,
+            height: max_height,
+        };
         msg![env; text sizeWithFont:font
-                  constrainedToSize:(bounds.size)
+                  constrainedToSize:max_size
                       lineBreakMode:line_break_mode]
     };
 
@@ -238,7 +246,8 @@ pub const CLASSES: ClassExports = objc_classes! {
         size: CGSize {
             width: bounds.size.width,
             // This is necessary for when the calculated size is actually larger
-            // than the bounds.
+            // than the bounds. It also limits drawing to the same number of
+            // lines that were measured.
             height: calculated_size.height,
         },
     };
@@ -250,12 +259,15 @@ pub const CLASSES: ClassExports = objc_classes! {
             UITextAlignmentRight => 1.0,
             _ => unimplemented!(),
         };
+        let x_offset = x_offset * (bounds.size.width - calculated_size.width);
         let point = CGPoint {
-            x: rect.origin.x + x_offset * (bounds.size.width - calculated_size.width),
+            x: rect.origin.x + x_offset,
             y: rect.origin.y
         };
         msg![env; text drawAtPoint:point
-                          withFont:font]
+                          forWidth:(bounds.size.width - x_offset)
+                          withFont:font
+                     lineBreakMode:line_break_mode]
     } else {
         msg![env; text drawInRect:rect
                          withFont:font
@@ -0,0 +1,390 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `UINavigationBar` and `UINavigationItem`.
+
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::ns_string::get_static_str;
+use crate::frameworks::foundation::{ns_array, NSInteger, NSUInteger};
+use crate::frameworks::uikit::ui_font::UITextAlignmentCenter;
+use crate::frameworks::uikit::ui_view::ui_control::{
+    UIControlEventTouchUpInside, UIControlStateNormal,
+};
+use crate::objc::{
+    autorelease, id, impl_HostObject_with_superclass, msg, msg_class, msg_super, nil, objc_classes,
+    release, retain, ClassExports, HostObject, NSZonePtr, SEL,
+};
+use crate::Environment;
+
+/// The standard height of a navigation bar in portrait orientation.
+pub const NAVIGATION_BAR_HEIGHT: CGFloat = 44.0;
+
+type UIBarStyle = NSInteger;
+const UIBarStyleDefault: UIBarStyle = 0;
+#[allow(dead_code)]
+const UIBarStyleBlack: UIBarStyle = 1;
+
+struct UINavigationItemHostObject {
+    /// `NSString*`
+    title: id,
+    hides_back_button: bool,
+    /// The bar currently displaying this item, if any. This is a weak
+    /// reference.
+    navigation_bar: id,
+}
+impl HostObject for UINavigationItemHostObject {}
+
+struct UINavigationBarHostObject {
+    superclass: super::UIViewHostObject,
+    /// `UINavigationItem*`s in bottom-to-top order. These are strong
+    /// references.
+    items: Vec<id>,
+    /// Weak reference.
+    delegate: id,
+    bar_style: UIBarStyle,
+    /// `UILabel*`
+    title_label: id,
+    /// `UIButton*`
+    back_button: id,
+}
+impl_HostObject_with_superclass!(UINavigationBarHostObject);
+impl Default for UINavigationBarHostObject {
+    fn default() -> Self {
+        UINavigationBarHostObject {
+            superclass: Default::default(),
+            items: Vec::new(),
+            delegate: nil,
+            bar_style: UIBarStyleDefault,
+            title_label: nil,
+            back_button: nil,
+        }
+    }
+}
+
+/// Shared parts of `initWithCoder:` and `initWithFrame:`.
+fn init_common(env: &mut Environment, this: id) {
+    let title_label: id = msg_class![env; UILabel new];
+    let clear: id = msg_class![env; UIColor clearColor];
+    let white: id = msg_class![env; UIColor whiteColor];
+    let font: id = msg_class![env; UIFont boldSystemFontOfSize:(20.0 as CGFloat)];
+    () = msg![env; title_label setBackgroundColor:clear];
+    () = msg![env; title_label setTextColor:white];
+    () = msg![env; title_label setFont:font];
+    () = msg![env; title_label setTextAlignment:UITextAlignmentCenter];
+
+    let back_button: id = msg_class![env; UIButton new];
+    let back_color: id = msg_class![env; UIColor colorWithRed:(0.33 as CGFloat)
+                                                        green:(0.42 as CGFloat)
+                                                         blue:(0.54 as CGFloat)
+                                                        alpha:(1.0 as CGFloat)];
+    () = msg![env; back_button setBackgroundColor:back_color];
+    () = msg![env; back_button setTitleColor:white forState:UIControlStateNormal];
+    let font: id = msg_class![env; UIFont boldSystemFontOfSize:(12.0 as CGFloat)];
+    () = msg![env; back_button setFont:font];
+    let action: SEL = env
+        .objc
+        .lookup_selector("_touchHLE_backButtonPressed:")
+        .unwrap();
+    () = msg![env; back_button addTarget:this
+                                  action:action
+                        forControlEvents:UIControlEventTouchUpInside];
+
+    let host_obj = env.objc.borrow_mut::<UINavigationBarHostObject>(this);
+    host_obj.title_label = title_label;
+    host_obj.back_button = back_button;
+
+    () = msg![env; this addSubview:title_label];
+    () = msg![env; this addSubview:back_button];
+
+    let bar_style = env.objc.borrow::<UINavigationBarHostObject>(this).bar_style;
+    () = msg![env; this setBarStyle:bar_style];
+
+    update(env, this);
+}
+
+/// Update the title and back button to match the top item.
+fn update(env: &mut Environment, this: id) {
+    let &UINavigationBarHostObject {
+        ref items,
+        title_label,
+        back_button,
+        ..
+    } = env.objc.borrow(this);
+    let top_item = items.last().copied().unwrap_or(nil);
+    let back_item = if items.len() >= 2 {
+        items[items.len() - 2]
+    } else {
+        nil
+    };
+
+    let bounds: CGRect = msg![env; this bounds];
+
+    let title: id = msg![env; top_item title];
+    () = msg![env; title_label setText:title];
+    // Leave space for the back button on either side, so the title stays
+    // centered.
+    let title_frame = CGRect {
+        origin: CGPoint {
+            x: bounds.origin.x + 80.0,
+            y: bounds.origin.y,
+        },
+        size: CGSize {
+            width: (bounds.size.width - 160.0).max(0.0),
+            height: bounds.size.height,
+        },
+    };
+    () = msg![env; title_label setFrame:title_frame];
+
+    let hides_back_button: bool = msg![env; top_item hidesBackButton];
+    let show_back_button = back_item != nil && !hides_back_button;
+    () = msg![env; back_button setHidden:(!show_back_button)];
+    if show_back_button {
+        let mut back_title: id = msg![env; back_item title];
+        if back_title == nil {
+            back_title = get_static_str(env, "Back");
+        }
+        () = msg![env; back_button setTitle:back_title forState:UIControlStateNormal];
+        let back_frame = CGRect {
+            origin: CGPoint {
+                x: bounds.origin.x + 5.0,
+                y: bounds.origin.y + (bounds.size.height - 30.0) / 2.0,
+            },
+            size: CGSize {
+                width: 70.0,
+                height: 30.0,
+            },
+        };
+        () = msg![env; back_button setFrame:back_frame];
+        () = msg![env; back_button layoutSubviews];
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation UINavigationItem: NSObject
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::new(UINavigationItemHostObject {
+        title: nil,
+        hides_back_button: false,
+        navigation_bar: nil,
+    });
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithTitle:(id)title { // NSString*
+    () = msg![env; this setTitle:title];
+    this
+}
+
+- (())dealloc {
+    let title = env.objc.borrow::<UINavigationItemHostObject>(this).title;
+    release(env, title);
+    env.objc.dealloc_object(this, &mut env.mem);
+}
+
+- (id)title {
+    env.objc.borrow::<UINavigationItemHostObject>(this).title
+}
+- (())setTitle:(id)new_title { // NSString*
+    let new_title: id = msg![env; new_title copy];
+    let host_obj = env.objc.borrow_mut::<UINavigationItemHostObject>(this);
+    let old_title = std::mem::replace(&mut host_obj.title, new_title);
+    let navigation_bar = host_obj.navigation_bar;
+    release(env, old_title);
+    if navigation_bar != nil {
+        update(env, navigation_bar);
+    }
+}
+
+- (bool)hidesBackButton {
+    env.objc.borrow::<UINavigationItemHostObject>(this).hides_back_button
+}
+- (())setHidesBackButton:(bool)hides {
+    let host_obj = env.objc.borrow_mut::<UINavigationItemHostObject>(this);
+    host_obj.hides_back_button = hides;
+    let navigation_bar = host_obj.navigation_bar;
+    if navigation_bar != nil {
+        update(env, navigation_bar);
+    }
+}
+- (())setHidesBackButton:(bool)hides animated:(bool)_animated {
+    msg![env; this setHidesBackButton:hides]
+}
+
+// TODO: UIBarButtonItem support
+- (())setLeftBarButtonItem:(id)item {
+    log!("TODO: [(UINavigationItem*){:?} setLeftBarButtonItem:{:?}]", this, item);
+}
+- (())setRightBarButtonItem:(id)item {
+    log!("TODO: [(UINavigationItem*){:?} setRightBarButtonItem:{:?}]", this, item);
+}
+- (())setBackBarButtonItem:(id)item {
+    log!("TODO: [(UINavigationItem*){:?} setBackBarButtonItem:{:?}]", this, item);
+}
+- (())setTitleView:(id)view {
+    log!("TODO: [(UINavigationItem*){:?} setTitleView:{:?}]", this, view);
+}
+
+@end
+
+@implementation UINavigationBar: UIView
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<UINavigationBarHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithFrame:(CGRect)frame {
+    let this: id = msg_super![env; this initWithFrame:frame];
+    init_common(env, this);
+    this
+}
+
+- (id)initWithCoder:(id)coder {
+    let this: id = msg_super![env; this initWithCoder:coder];
+    // TODO: decode the items and bar style
+    init_common(env, this);
+    this
+}
+
+- (())dealloc {
+    let UINavigationBarHostObject {
+        superclass: _,
+        items,
+        delegate: _,
+        bar_style: _,
+        title_label,
+        back_button,
+    } = std::mem::take(env.objc.borrow_mut(this));
+
+    for item in items {
+        env.objc.borrow_mut::<UINavigationItemHostObject>(item).navigation_bar = nil;
+        release(env, item);
+    }
+    release(env, title_label);
+    release(env, back_button);
+
+    msg_super![env; this dealloc]
+}
+
+- (())layoutSubviews {
+    update(env, this);
+}
+
+- (id)delegate {
+    env.objc.borrow::<UINavigationBarHostObject>(this).delegate
+}
+- (())setDelegate:(id)delegate {
+    env.objc.borrow_mut::<UINavigationBarHostObject>(this).delegate = delegate;
+}
+
+- (UIBarStyle)barStyle {
+    env.objc.borrow::<UINavigationBarHostObject>(this).bar_style
+}
+- (())setBarStyle:(UIBarStyle)bar_style {
+    env.objc.borrow_mut::<UINavigationBarHostObject>(this).bar_style = bar_style;
+    // The real bar has a gradient, but a flat color is a good approximation.
+    let color: id = if bar_style == UIBarStyleDefault {
+        msg_class![env; UIColor colorWithRed:(0.43 as CGFloat)
+                                       green:(0.52 as CGFloat)
+                                        blue:(0.64 as CGFloat)
+                                       alpha:(1.0 as CGFloat)]
+    } else {
+        msg_class![env; UIColor blackColor]
+    };
+    () = msg_super![env; this setBackgroundColor:color];
+}
+
+- (id)items {
+    let items = env.objc.borrow::<UINavigationBarHostObject>(this).items.clone();
+    for &item in &items {
+        retain(env, item);
+    }
+    let items = ns_array::from_vec(env, items);
+    autorelease(env, items)
+}
+- (())setItems:(id)new_items { // NSArray* of UINavigationItem*
+    msg![env; this setItems:new_items animated:false]
+}
+- (())setItems:(id)new_items // NSArray* of UINavigationItem*
+      animated:(bool)_animated {
+    // TODO: animation
+    let old_items = std::mem::take(
+        &mut env.objc.borrow_mut::<UINavigationBarHostObject>(this).items
+    );
+    for &item in &old_items {
+        env.objc.borrow_mut::<UINavigationItemHostObject>(item).navigation_bar = nil;
+    }
+
+    let count: NSUInteger = msg![env; new_items count];
+    let mut items = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        let item: id = msg![env; new_items objectAtIndex:i];
+        retain(env, item);
+        env.objc.borrow_mut::<UINavigationItemHostObject>(item).navigation_bar = this;
+        items.push(item);
+    }
+    env.objc.borrow_mut::<UINavigationBarHostObject>(this).items = items;
+
+    for item in old_items {
+        release(env, item);
+    }
+    update(env, this);
+}
+
+- (id)topItem {
+    env.objc.borrow::<UINavigationBarHostObject>(this).items.last().copied().unwrap_or(nil)
+}
+- (id)backItem {
+    let items = &env.objc.borrow::<UINavigationBarHostObject>(this).items;
+    if items.len() >= 2 {
+        items[items.len() - 2]
+    } else {
+        nil
+    }
+}
+
+- (())pushNavigationItem:(id)item // UINavigationItem*
+                animated:(bool)_animated {
+    // TODO: animation
+    retain(env, item);
+    env.objc.borrow_mut::<UINavigationItemHostObject>(item).navigation_bar = this;
+    env.objc.borrow_mut::<UINavigationBarHostObject>(this).items.push(item);
+    update(env, this);
+}
+- (id)popNavigationItemAnimated:(bool)_animated {
+    // TODO: animation
+    let Some(item) = env.objc.borrow_mut::<UINavigationBarHostObject>(this).items.pop() else {
+        return nil;
+    };
+    env.objc.borrow_mut::<UINavigationItemHostObject>(item).navigation_bar = nil;
+    update(env, this);
+    autorelease(env, item)
+}
+
+- (())_touchHLE_backButtonPressed:(id)_sender {
+    let top_item: id = msg![env; this topItem];
+    if top_item == nil {
+        return;
+    }
+    let delegate = env.objc.borrow::<UINavigationBarHostObject>(this).delegate;
+    if delegate != nil {
+        let sel: SEL = env
+            .objc
+            .register_host_selector("navigationBar:shouldPopItem:".to_string(), &mut env.mem);
+        if msg![env; delegate respondsToSelector:sel]
+            && !msg![env; delegate navigationBar:this shouldPopItem:top_item] {
+            return;
+        }
+    }
+    let _: id = msg![env; this popNavigationItemAnimated:true];
+}
+
+@end
+
+};
@@ -5,6 +5,7 @@
  */
 //! `UIScrollView`.
 
+pub mod ui_table_view;
 pub mod ui_text_view;
 use crate::frameworks::core_graphics::{CGPoint, CGRect, CGSize};
 use crate::objc::{
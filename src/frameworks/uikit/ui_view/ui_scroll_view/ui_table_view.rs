@@ -0,0 +1,937 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `UITableView`.
+//!
+//! Only the parts of the data source and delegate protocols needed for simple
+//! lists are supported: sections with optional header titles, rows of fixed
+//! or delegate-provided height, selection, and the section index (the A–Z
+//! strip on the right edge). Cells are only requested from the data source
+//! when they become visible, and are reused like in UIKit.
+
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::ns_index_path::{index_path_for_row, to_section_and_row};
+use crate::frameworks::foundation::{ns_array, NSInteger, NSUInteger};
+use crate::frameworks::uikit::ui_font::UITextAlignmentCenter;
+use crate::frameworks::uikit::ui_view::ui_table_view_cell::DEFAULT_ROW_HEIGHT;
+use crate::objc::{
+    autorelease, id, impl_HostObject_with_superclass, msg, msg_class, msg_super, nil, objc_classes,
+    release, retain, Class, ClassExports, NSZonePtr, SEL,
+};
+use crate::Environment;
+
+type UITableViewStyle = NSInteger;
+const UITableViewStylePlain: UITableViewStyle = 0;
+
+type UITableViewScrollPosition = NSInteger;
+const UITableViewScrollPositionNone: UITableViewScrollPosition = 0;
+const UITableViewScrollPositionTop: UITableViewScrollPosition = 1;
+const UITableViewScrollPositionMiddle: UITableViewScrollPosition = 2;
+const UITableViewScrollPositionBottom: UITableViewScrollPosition = 3;
+
+const DEFAULT_SECTION_HEADER_HEIGHT: CGFloat = 22.0;
+/// Width of the section index strip.
+const INDEX_WIDTH: CGFloat = 24.0;
+/// Maximum height of each title in the section index strip.
+const INDEX_TITLE_HEIGHT: CGFloat = 16.0;
+/// How far a touch can move before it's a scroll rather than a tap.
+const TAP_SLOP: CGFloat = 8.0;
+
+/// Layout of a section, in content coordinates.
+struct TableSection {
+    /// Top of the section, including its header.
+    y: CGFloat,
+    /// `NSString*`, possibly `nil`. This is a strong reference.
+    header_title: id,
+    header_height: CGFloat,
+    /// Top of each row.
+    row_ys: Vec<CGFloat>,
+    row_heights: Vec<CGFloat>,
+}
+impl TableSection {
+    fn bottom(&self) -> CGFloat {
+        match (self.row_ys.last(), self.row_heights.last()) {
+            (Some(y), Some(height)) => y + height,
+            _ => self.y + self.header_height,
+        }
+    }
+}
+
+/// State of the touch currently being handled by the table view.
+#[derive(Default)]
+enum TouchTracking {
+    #[default]
+    None,
+    /// The touch started on the section index strip.
+    Index,
+    /// The touch started on a row, and might be a tap or a scroll.
+    Row { start: CGPoint, moved: bool },
+}
+
+pub struct UITableViewHostObject {
+    superclass: super::UIScrollViewHostObject,
+    /// `UITableViewDataSource`, weak reference
+    data_source: id,
+    style: UITableViewStyle,
+    row_height: CGFloat,
+    section_header_height: CGFloat,
+    /// The data source hasn't been asked about the contents yet, or they need
+    /// to be laid out again.
+    needs_reload: bool,
+    sections: Vec<TableSection>,
+    /// Cells that are currently subviews, with their section and row.
+    visible_cells: Vec<(NSUInteger, NSUInteger, id)>,
+    /// Cells that have scrolled out of view and can be reused. These are
+    /// strong references.
+    reusable_cells: Vec<id>,
+    /// Header views that are currently subviews, with their section.
+    visible_headers: Vec<(NSUInteger, id)>,
+    /// `NSArray*` of `NSString*` from `sectionIndexTitlesForTableView:`, or
+    /// `nil`. This is a strong reference.
+    index_titles: id,
+    /// `UIView*` for the section index strip, or `nil`. This is a strong
+    /// reference.
+    index_view: id,
+    selected_row: Option<(NSUInteger, NSUInteger)>,
+    touch_tracking: TouchTracking,
+}
+impl_HostObject_with_superclass!(UITableViewHostObject);
+impl Default for UITableViewHostObject {
+    fn default() -> Self {
+        UITableViewHostObject {
+            superclass: Default::default(),
+            data_source: nil,
+            style: UITableViewStylePlain,
+            row_height: DEFAULT_ROW_HEIGHT,
+            section_header_height: DEFAULT_SECTION_HEADER_HEIGHT,
+            needs_reload: true,
+            sections: Vec::new(),
+            visible_cells: Vec::new(),
+            reusable_cells: Vec::new(),
+            visible_headers: Vec::new(),
+            index_titles: nil,
+            index_view: nil,
+            selected_row: None,
+            touch_tracking: TouchTracking::None,
+        }
+    }
+}
+
+fn rect(x: CGFloat, y: CGFloat, width: CGFloat, height: CGFloat) -> CGRect {
+    CGRect {
+        origin: CGPoint { x, y },
+        size: CGSize { width, height },
+    }
+}
+
+fn responds(env: &mut Environment, object: id, selector: &str) -> bool {
+    if object == nil {
+        return false;
+    }
+    let sel: SEL = env
+        .objc
+        .register_host_selector(selector.to_string(), &mut env.mem);
+    msg![env; object respondsToSelector:sel]
+}
+
+/// Shared parts of `initWithCoder:` and `initWithFrame:style:`.
+fn init_common(env: &mut Environment, this: id) {
+    let white: id = msg_class![env; UIColor whiteColor];
+    () = msg![env; this setBackgroundColor:white];
+    () = msg![env; this setClipsToBounds:true];
+}
+
+/// Remove a cell from the table and put it in the reuse queue if possible.
+fn enqueue_cell(env: &mut Environment, this: id, cell: id) {
+    let reuse_identifier: id = msg![env; cell reuseIdentifier];
+    if reuse_identifier != nil {
+        retain(env, cell);
+        env.objc
+            .borrow_mut::<UITableViewHostObject>(this)
+            .reusable_cells
+            .push(cell);
+    }
+    () = msg![env; cell removeFromSuperview];
+}
+
+/// Ask the data source about the contents of the table and lay them out.
+fn reload_data(env: &mut Environment, this: id) {
+    let host_object = env.objc.borrow_mut::<UITableViewHostObject>(this);
+    host_object.needs_reload = false;
+    let visible_cells = std::mem::take(&mut host_object.visible_cells);
+    let visible_headers = std::mem::take(&mut host_object.visible_headers);
+    let old_sections = std::mem::take(&mut host_object.sections);
+    let old_index_titles = std::mem::replace(&mut host_object.index_titles, nil);
+    let old_index_view = std::mem::replace(&mut host_object.index_view, nil);
+    let &mut UITableViewHostObject {
+        data_source,
+        row_height,
+        section_header_height,
+        ..
+    } = host_object;
+
+    for (_, _, cell) in visible_cells {
+        enqueue_cell(env, this, cell);
+    }
+    for (_, header) in visible_headers {
+        () = msg![env; header removeFromSuperview];
+    }
+    for section in old_sections {
+        release(env, section.header_title);
+    }
+    release(env, old_index_titles);
+    if old_index_view != nil {
+        () = msg![env; old_index_view removeFromSuperview];
+        release(env, old_index_view);
+    }
+
+    let delegate: id = msg![env; this delegate];
+    let section_count: NSInteger = if data_source == nil {
+        0
+    } else if responds(env, data_source, "numberOfSectionsInTableView:") {
+        msg![env; data_source numberOfSectionsInTableView:this]
+    } else {
+        1
+    };
+    let has_titles = responds(env, data_source, "tableView:titleForHeaderInSection:");
+    let has_heights = responds(env, delegate, "tableView:heightForRowAtIndexPath:");
+
+    let mut sections = Vec::new();
+    let mut y = 0.0;
+    for section in 0..section_count.max(0) {
+        let row_count: NSInteger =
+            msg![env; data_source tableView:this numberOfRowsInSection:section];
+        let header_title: id = if has_titles {
+            msg![env; data_source tableView:this titleForHeaderInSection:section]
+        } else {
+            nil
+        };
+        let header_title: id = msg![env; header_title copy];
+        let header_height = if header_title != nil {
+            section_header_height
+        } else {
+            0.0
+        };
+        let section_y = y;
+        y += header_height;
+        let mut row_ys = Vec::with_capacity(row_count.max(0) as usize);
+        let mut row_heights = Vec::with_capacity(row_count.max(0) as usize);
+        for row in 0..row_count.max(0) {
+            let height = if has_heights {
+                let index_path = index_path_for_row(env, section as NSUInteger, row as NSUInteger);
+                msg![env; delegate tableView:this heightForRowAtIndexPath:index_path]
+            } else {
+                row_height
+            };
+            row_ys.push(y);
+            row_heights.push(height);
+            y += height;
+        }
+        sections.push(TableSection {
+            y: section_y,
+            header_title,
+            header_height,
+            row_ys,
+            row_heights,
+        });
+    }
+
+    let index_titles: id = if responds(env, data_source, "sectionIndexTitlesForTableView:") {
+        msg![env; data_source sectionIndexTitlesForTableView:this]
+    } else {
+        nil
+    };
+    retain(env, index_titles);
+
+    let host_object = env.objc.borrow_mut::<UITableViewHostObject>(this);
+    host_object.sections = sections;
+    host_object.index_titles = index_titles;
+
+    let bounds: CGRect = msg![env; this bounds];
+    let content_size = CGSize {
+        width: bounds.size.width,
+        height: y,
+    };
+    () = msg![env; this setContentSize:content_size];
+
+    if index_titles != nil {
+        create_index_view(env, this, index_titles);
+    }
+
+    // Keep the scroll position if possible. This also updates the visible
+    // rows.
+    let offset: CGPoint = msg![env; this contentOffset];
+    let max_y = (content_size.height - bounds.size.height).max(0.0);
+    let offset = CGPoint {
+        x: 0.0,
+        y: offset.y.min(max_y).max(0.0),
+    };
+    () = msg![env; this setContentOffset:offset];
+}
+
+/// Create the section index strip. It's a subview of the table, so it has to
+/// be moved when the table scrolls.
+fn create_index_view(env: &mut Environment, this: id, index_titles: id) {
+    let bounds: CGRect = msg![env; this bounds];
+    let count: NSUInteger = msg![env; index_titles count];
+    if count == 0 {
+        return;
+    }
+
+    let index_view: id = msg_class![env; UIView new];
+    let clear: id = msg_class![env; UIColor clearColor];
+    () = msg![env; index_view setBackgroundColor:clear];
+
+    let title_height = index_title_height(bounds.size.height, count);
+    let top = index_top(bounds.size.height, count);
+    let font: id = msg_class![env; UIFont boldSystemFontOfSize:(11.0 as CGFloat)];
+    let color: id = msg_class![env; UIColor colorWithWhite:(0.4 as CGFloat)
+                                                      alpha:(1.0 as CGFloat)];
+    for i in 0..count {
+        let title: id = msg![env; index_titles objectAtIndex:i];
+        let label: id = msg_class![env; UILabel new];
+        () = msg![env; label setText:title];
+        () = msg![env; label setFont:font];
+        () = msg![env; label setTextColor:color];
+        () = msg![env; label setBackgroundColor:clear];
+        () = msg![env; label setTextAlignment:UITextAlignmentCenter];
+        let frame = rect(
+            0.0,
+            top + title_height * i as CGFloat,
+            INDEX_WIDTH,
+            title_height,
+        );
+        () = msg![env; label setFrame:frame];
+        () = msg![env; index_view addSubview:label];
+        release(env, label);
+    }
+
+    () = msg![env; this addSubview:index_view];
+    env.objc
+        .borrow_mut::<UITableViewHostObject>(this)
+        .index_view = index_view;
+}
+
+fn index_title_height(height: CGFloat, count: NSUInteger) -> CGFloat {
+    (height / count as CGFloat).min(INDEX_TITLE_HEIGHT)
+}
+
+/// The titles are vertically centered in the strip.
+fn index_top(height: CGFloat, count: NSUInteger) -> CGFloat {
+    ((height - index_title_height(height, count) * count as CGFloat) / 2.0).max(0.0)
+}
+
+/// Create header views and request cells for anything that has scrolled into
+/// view, and remove anything that has scrolled out of view.
+fn update_visible_rows(env: &mut Environment, this: id) {
+    let bounds: CGRect = msg![env; this bounds];
+    let top = bounds.origin.y;
+    let bottom = top + bounds.size.height;
+    let width = bounds.size.width;
+    let visible = |y: CGFloat, height: CGFloat| y + height > top && y < bottom;
+
+    // Remove things that are no longer visible.
+    let host_object = env.objc.borrow_mut::<UITableViewHostObject>(this);
+    let mut to_remove = Vec::new();
+    host_object.visible_cells.retain(|&(section, row, cell)| {
+        let section = &host_object.sections[section as usize];
+        let keep = visible(
+            section.row_ys[row as usize],
+            section.row_heights[row as usize],
+        );
+        if !keep {
+            to_remove.push(cell);
+        }
+        keep
+    });
+    let mut headers_to_remove = Vec::new();
+    host_object.visible_headers.retain(|&(section, header)| {
+        let section = &host_object.sections[section as usize];
+        let keep = visible(section.y, section.header_height);
+        if !keep {
+            headers_to_remove.push(header);
+        }
+        keep
+    });
+    for cell in to_remove {
+        enqueue_cell(env, this, cell);
+    }
+    for header in headers_to_remove {
+        () = msg![env; header removeFromSuperview];
+    }
+
+    // Add things that have become visible.
+    let host_object = env.objc.borrow::<UITableViewHostObject>(this);
+    let mut new_rows = Vec::new();
+    let mut new_headers = Vec::new();
+    for (section_idx, section) in host_object.sections.iter().enumerate() {
+        let section_idx = section_idx as NSUInteger;
+        if section.bottom() <= top || section.y >= bottom {
+            continue;
+        }
+        if section.header_title != nil
+            && visible(section.y, section.header_height)
+            && !host_object
+                .visible_headers
+                .iter()
+                .any(|&(s, _)| s == section_idx)
+        {
+            new_headers.push((section_idx, section.y, section.header_height));
+        }
+        // Rows are sorted, so the first visible one can be found quickly.
+        let first = section.row_ys.partition_point(|&y| y < top);
+        let first = first.saturating_sub(1);
+        for row in first..section.row_ys.len() {
+            let (y, height) = (section.row_ys[row], section.row_heights[row]);
+            if y >= bottom {
+                break;
+            }
+            let row = row as NSUInteger;
+            if visible(y, height)
+                && !host_object
+                    .visible_cells
+                    .iter()
+                    .any(|&(s, r, _)| s == section_idx && r == row)
+            {
+                new_rows.push((section_idx, row, y, height));
+            }
+        }
+    }
+
+    for (section, y, height) in new_headers {
+        let title =
+            env.objc.borrow::<UITableViewHostObject>(this).sections[section as usize].header_title;
+        let header = new_header_view(env, title, rect(0.0, y, width, height));
+        () = msg![env; this addSubview:header];
+        release(env, header);
+        env.objc
+            .borrow_mut::<UITableViewHostObject>(this)
+            .visible_headers
+            .push((section, header));
+    }
+
+    let data_source = env.objc.borrow::<UITableViewHostObject>(this).data_source;
+    for (section, row, y, height) in new_rows {
+        let index_path = index_path_for_row(env, section, row);
+        let cell: id = msg![env; data_source tableView:this cellForRowAtIndexPath:index_path];
+        if cell == nil {
+            log!(
+                "Warning: table view {:?} data source {:?} returned no cell for row {} in section {}",
+                this,
+                data_source,
+                row,
+                section
+            );
+            continue;
+        }
+        () = msg![env; cell setFrame:(rect(0.0, y, width, height))];
+        let selected =
+            env.objc.borrow::<UITableViewHostObject>(this).selected_row == Some((section, row));
+        () = msg![env; cell setSelected:selected animated:false];
+        // FIXME: manually calling layoutSubviews shouldn't be needed?
+        () = msg![env; cell layoutSubviews];
+        () = msg![env; this addSubview:cell];
+        env.objc
+            .borrow_mut::<UITableViewHostObject>(this)
+            .visible_cells
+            .push((section, row, cell));
+    }
+
+    // The index strip stays on the right edge, above everything else.
+    let index_view = env.objc.borrow::<UITableViewHostObject>(this).index_view;
+    if index_view != nil {
+        let frame = rect(width - INDEX_WIDTH, top, INDEX_WIDTH, bounds.size.height);
+        () = msg![env; index_view setFrame:frame];
+        () = msg![env; this bringSubviewToFront:index_view];
+    }
+}
+
+/// Create a view for a section header. The caller owns the result.
+fn new_header_view(env: &mut Environment, title: id, frame: CGRect) -> id {
+    let header: id = msg_class![env; UIView alloc];
+    let header: id = msg![env; header initWithFrame:frame];
+    let background: id = msg_class![env; UIColor colorWithRed:(0.6 as CGFloat)
+                                                        green:(0.65 as CGFloat)
+                                                         blue:(0.7 as CGFloat)
+                                                        alpha:(1.0 as CGFloat)];
+    () = msg![env; header setBackgroundColor:background];
+
+    let label: id = msg_class![env; UILabel new];
+    let font: id = msg_class![env; UIFont boldSystemFontOfSize:(17.0 as CGFloat)];
+    let white: id = msg_class![env; UIColor whiteColor];
+    let clear: id = msg_class![env; UIColor clearColor];
+    () = msg![env; label setText:title];
+    () = msg![env; label setFont:font];
+    () = msg![env; label setTextColor:white];
+    () = msg![env; label setBackgroundColor:clear];
+    let label_frame = rect(
+        12.0,
+        0.0,
+        (frame.size.width - 24.0).max(0.0),
+        frame.size.height,
+    );
+    () = msg![env; label setFrame:label_frame];
+    () = msg![env; header addSubview:label];
+    release(env, label);
+    header
+}
+
+/// Find the row at a point in content coordinates.
+fn row_at_point(
+    env: &mut Environment,
+    this: id,
+    point: CGPoint,
+) -> Option<(NSUInteger, NSUInteger)> {
+    let sections = &env.objc.borrow::<UITableViewHostObject>(this).sections;
+    for (section_idx, section) in sections.iter().enumerate() {
+        if point.y < section.y || point.y >= section.bottom() {
+            continue;
+        }
+        let row = section.row_ys.partition_point(|&y| y <= point.y);
+        if row == 0 {
+            // The point is in the header.
+            return None;
+        }
+        return Some((section_idx as NSUInteger, (row - 1) as NSUInteger));
+    }
+    None
+}
+
+/// Scroll so that a content y co-ordinate is at the top, as far as possible.
+fn scroll_to_y(env: &mut Environment, this: id, y: CGFloat) {
+    let bounds: CGRect = msg![env; this bounds];
+    let content_size: CGSize = msg![env; this contentSize];
+    let max_y = (content_size.height - bounds.size.height).max(0.0);
+    let offset = CGPoint {
+        x: 0.0,
+        y: y.min(max_y).max(0.0),
+    };
+    () = msg![env; this setContentOffset:offset];
+}
+
+/// Handle a touch on the section index strip. `y` is relative to the top of
+/// the visible area.
+fn touch_index(env: &mut Environment, this: id, y: CGFloat) {
+    let &UITableViewHostObject {
+        data_source,
+        index_titles,
+        ..
+    } = env.objc.borrow(this);
+    let bounds: CGRect = msg![env; this bounds];
+    let count: NSUInteger = msg![env; index_titles count];
+    let title_height = index_title_height(bounds.size.height, count);
+    let top = index_top(bounds.size.height, count);
+    let index = ((y - top) / title_height).floor().max(0.0) as NSUInteger;
+    let index = index.min(count - 1);
+
+    let section: NSInteger = if responds(
+        env,
+        data_source,
+        "tableView:sectionForSectionIndexTitle:atIndex:",
+    ) {
+        let title: id = msg![env; index_titles objectAtIndex:index];
+        let index = index as NSInteger;
+        msg![env; data_source tableView:this sectionForSectionIndexTitle:title atIndex:index]
+    } else {
+        index as NSInteger
+    };
+    log_dbg!(
+        "Section index {} touched, jumping to section {}",
+        index,
+        section
+    );
+
+    let sections = &env.objc.borrow::<UITableViewHostObject>(this).sections;
+    let Some(section) = usize::try_from(section).ok().and_then(|s| sections.get(s)) else {
+        return;
+    };
+    let y = section.y;
+    scroll_to_y(env, this, y);
+}
+
+fn select_row(env: &mut Environment, this: id, new: Option<(NSUInteger, NSUInteger)>) {
+    let host_object = env.objc.borrow_mut::<UITableViewHostObject>(this);
+    let old = std::mem::replace(&mut host_object.selected_row, new);
+    let cells: Vec<_> = host_object
+        .visible_cells
+        .iter()
+        .filter(|&&(s, r, _)| Some((s, r)) == old || Some((s, r)) == new)
+        .map(|&(s, r, cell)| (Some((s, r)) == new, cell))
+        .collect();
+    for (selected, cell) in cells {
+        () = msg![env; cell setSelected:selected animated:false];
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation UITableView: UIScrollView
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<UITableViewHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithFrame:(CGRect)frame {
+    msg![env; this initWithFrame:frame style:UITableViewStylePlain]
+}
+
+- (id)initWithFrame:(CGRect)frame
+              style:(UITableViewStyle)style {
+    let this: id = msg_super![env; this initWithFrame:frame];
+    // TODO: draw the grouped style differently
+    env.objc.borrow_mut::<UITableViewHostObject>(this).style = style;
+    init_common(env, this);
+    this
+}
+
+- (id)initWithCoder:(id)coder {
+    let this: id = msg_super![env; this initWithCoder:coder];
+    // TODO: decode the style and other properties
+    init_common(env, this);
+    this
+}
+
+- (())dealloc {
+    let host_object = env.objc.borrow_mut::<UITableViewHostObject>(this);
+    let sections = std::mem::take(&mut host_object.sections);
+    let reusable_cells = std::mem::take(&mut host_object.reusable_cells);
+    let &mut UITableViewHostObject {
+        index_titles,
+        index_view,
+        ..
+    } = host_object;
+    for section in sections {
+        release(env, section.header_title);
+    }
+    for cell in reusable_cells {
+        release(env, cell);
+    }
+    release(env, index_titles);
+    release(env, index_view);
+    msg_super![env; this dealloc]
+}
+
+- (UITableViewStyle)style {
+    env.objc.borrow::<UITableViewHostObject>(this).style
+}
+
+- (id)dataSource {
+    env.objc.borrow::<UITableViewHostObject>(this).data_source
+}
+- (())setDataSource:(id)data_source {
+    let host_object = env.objc.borrow_mut::<UITableViewHostObject>(this);
+    host_object.data_source = data_source;
+    host_object.needs_reload = true;
+    () = msg![env; this setNeedsDisplay];
+}
+- (())setDelegate:(id)delegate {
+    () = msg_super![env; this setDelegate:delegate];
+    // Row heights might come from the delegate.
+    env.objc.borrow_mut::<UITableViewHostObject>(this).needs_reload = true;
+    () = msg![env; this setNeedsDisplay];
+}
+
+- (CGFloat)rowHeight {
+    env.objc.borrow::<UITableViewHostObject>(this).row_height
+}
+- (())setRowHeight:(CGFloat)row_height {
+    env.objc.borrow_mut::<UITableViewHostObject>(this).row_height = row_height;
+}
+- (CGFloat)sectionHeaderHeight {
+    env.objc.borrow::<UITableViewHostObject>(this).section_header_height
+}
+- (())setSectionHeaderHeight:(CGFloat)height {
+    env.objc.borrow_mut::<UITableViewHostObject>(this).section_header_height = height;
+}
+
+// TODO: draw separators differently depending on these
+- (())setSeparatorStyle:(NSInteger)_style {}
+- (())setSeparatorColor:(id)_color {}
+
+- (())reloadData {
+    reload_data(env, this);
+}
+
+- (NSInteger)numberOfSections {
+    if env.objc.borrow::<UITableViewHostObject>(this).needs_reload {
+        reload_data(env, this);
+    }
+    env.objc.borrow::<UITableViewHostObject>(this).sections.len().try_into().unwrap()
+}
+- (NSInteger)numberOfRowsInSection:(NSInteger)section {
+    if env.objc.borrow::<UITableViewHostObject>(this).needs_reload {
+        reload_data(env, this);
+    }
+    let sections = &env.objc.borrow::<UITableViewHostObject>(this).sections;
+    sections[section as usize].row_ys.len().try_into().unwrap()
+}
+
+- (id)dequeueReusableCellWithIdentifier:(id)identifier { // NSString*
+    let reusable_cells = env.objc.borrow::<UITableViewHostObject>(this).reusable_cells.clone();
+    for (i, cell) in reusable_cells.into_iter().enumerate() {
+        let cell_identifier: id = msg![env; cell reuseIdentifier];
+        if msg![env; cell_identifier isEqualToString:identifier] {
+            env.objc.borrow_mut::<UITableViewHostObject>(this).reusable_cells.remove(i);
+            () = msg![env; cell prepareForReuse];
+            return autorelease(env, cell);
+        }
+    }
+    nil
+}
+
+- (id)cellForRowAtIndexPath:(id)index_path { // NSIndexPath*
+    let (section, row) = to_section_and_row(env, index_path);
+    env.objc
+        .borrow::<UITableViewHostObject>(this)
+        .visible_cells
+        .iter()
+        .find(|&&(s, r, _)| s == section && r == row)
+        .map_or(nil, |&(_, _, cell)| cell)
+}
+
+- (id)visibleCells {
+    let cells = env.objc
+        .borrow::<UITableViewHostObject>(this)
+        .visible_cells
+        .iter()
+        .map(|&(_, _, cell)| cell)
+        .collect::<Vec<_>>();
+    for &cell in &cells {
+        retain(env, cell);
+    }
+    let cells = ns_array::from_vec(env, cells);
+    autorelease(env, cells)
+}
+
+- (id)indexPathsForVisibleRows {
+    let rows = env.objc
+        .borrow::<UITableViewHostObject>(this)
+        .visible_cells
+        .iter()
+        .map(|&(section, row, _)| (section, row))
+        .collect::<Vec<_>>();
+    let mut index_paths = Vec::with_capacity(rows.len());
+    for (section, row) in rows {
+        let index_path = index_path_for_row(env, section, row);
+        index_paths.push(retain(env, index_path));
+    }
+    let index_paths = ns_array::from_vec(env, index_paths);
+    autorelease(env, index_paths)
+}
+
+- (id)indexPathForRowAtPoint:(CGPoint)point {
+    match row_at_point(env, this, point) {
+        Some((section, row)) => index_path_for_row(env, section, row),
+        None => nil,
+    }
+}
+
+- (CGRect)rectForRowAtIndexPath:(id)index_path { // NSIndexPath*
+    let (section, row) = to_section_and_row(env, index_path);
+    let width = { let bounds: CGRect = msg![env; this bounds]; bounds.size.width };
+    let sections = &env.objc.borrow::<UITableViewHostObject>(this).sections;
+    match sections.get(section as usize) {
+        Some(section) if (row as usize) < section.row_ys.len() => rect(
+            0.0,
+            section.row_ys[row as usize],
+            width,
+            section.row_heights[row as usize],
+        ),
+        _ => rect(0.0, 0.0, 0.0, 0.0),
+    }
+}
+
+- (id)indexPathForSelectedRow {
+    match env.objc.borrow::<UITableViewHostObject>(this).selected_row {
+        Some((section, row)) => index_path_for_row(env, section, row),
+        None => nil,
+    }
+}
+
+- (())selectRowAtIndexPath:(id)index_path // NSIndexPath*
+                  animated:(bool)animated
+            scrollPosition:(UITableViewScrollPosition)scroll_position {
+    if index_path == nil {
+        select_row(env, this, None);
+        return;
+    }
+    let row = to_section_and_row(env, index_path);
+    select_row(env, this, Some(row));
+    if scroll_position != UITableViewScrollPositionNone {
+        () = msg![env; this scrollToRowAtIndexPath:index_path
+                                  atScrollPosition:scroll_position
+                                          animated:animated];
+    }
+}
+
+- (())deselectRowAtIndexPath:(id)index_path // NSIndexPath*
+                    animated:(bool)_animated {
+    let row = to_section_and_row(env, index_path);
+    if env.objc.borrow::<UITableViewHostObject>(this).selected_row == Some(row) {
+        select_row(env, this, None);
+    }
+}
+
+- (())scrollToRowAtIndexPath:(id)index_path // NSIndexPath*
+            atScrollPosition:(UITableViewScrollPosition)scroll_position
+                    animated:(bool)_animated {
+    let row_rect: CGRect = msg![env; this rectForRowAtIndexPath:index_path];
+    let bounds: CGRect = msg![env; this bounds];
+    let y = match scroll_position {
+        UITableViewScrollPositionTop => row_rect.origin.y,
+        UITableViewScrollPositionMiddle => {
+            row_rect.origin.y - (bounds.size.height - row_rect.size.height) / 2.0
+        }
+        UITableViewScrollPositionBottom => {
+            row_rect.origin.y - (bounds.size.height - row_rect.size.height)
+        }
+        // Scroll as little as possible.
+        _ => {
+            if row_rect.origin.y < bounds.origin.y {
+                row_rect.origin.y
+            } else if row_rect.origin.y + row_rect.size.height
+                > bounds.origin.y + bounds.size.height
+            {
+                row_rect.origin.y - (bounds.size.height - row_rect.size.height)
+            } else {
+                bounds.origin.y
+            }
+        }
+    };
+    scroll_to_y(env, this, y);
+}
+
+- (())setContentOffset:(CGPoint)offset {
+    () = msg_super![env; this setContentOffset:offset];
+    if !env.objc.borrow::<UITableViewHostObject>(this).needs_reload {
+        update_visible_rows(env, this);
+    }
+}
+
+- (())setFrame:(CGRect)frame {
+    let old_frame: CGRect = msg![env; this frame];
+    () = msg_super![env; this setFrame:frame];
+    if old_frame.size != frame.size {
+        env.objc.borrow_mut::<UITableViewHostObject>(this).needs_reload = true;
+        () = msg![env; this setNeedsDisplay];
+    }
+}
+
+// UIKit asks the data source for the contents of the table when it is first
+// laid out. touchHLE doesn't have layout passes, but the layer is always
+// displayed before it is composited, which is almost as good.
+- (())displayLayer:(id)_layer { // CALayer*
+    if env.objc.borrow::<UITableViewHostObject>(this).needs_reload {
+        reload_data(env, this);
+    }
+}
+
+// Touches on cells are handled by the table view, except for controls (e.g.
+// buttons) in the cells.
+- (id)hitTest:(CGPoint)point
+    withEvent:(id)event { // UIEvent*
+    let view: id = msg_super![env; this hitTest:point withEvent:event];
+    if view == nil || view == this {
+        return view;
+    }
+    let control_class: Class = env.objc.get_known_class("UIControl", &mut env.mem);
+    if msg![env; view isKindOfClass:control_class] {
+        view
+    } else {
+        this
+    }
+}
+
+- (())touchesBegan:(id)touches // NSSet* of UITouch*
+         withEvent:(id)_event { // UIEvent*
+    let touch: id = msg![env; touches anyObject];
+    let location: CGPoint = msg![env; touch locationInView:this];
+    let bounds: CGRect = msg![env; this bounds];
+    let index_view = env.objc.borrow::<UITableViewHostObject>(this).index_view;
+
+    let tracking = if index_view != nil
+        && location.x - bounds.origin.x >= bounds.size.width - INDEX_WIDTH
+    {
+        touch_index(env, this, location.y - bounds.origin.y);
+        TouchTracking::Index
+    } else {
+        let start = CGPoint {
+            x: location.x - bounds.origin.x,
+            y: location.y - bounds.origin.y,
+        };
+        TouchTracking::Row { start, moved: false }
+    };
+    env.objc.borrow_mut::<UITableViewHostObject>(this).touch_tracking = tracking;
+}
+
+- (())touchesMoved:(id)touches // NSSet* of UITouch*
+         withEvent:(id)event { // UIEvent*
+    let touch: id = msg![env; touches anyObject];
+    let location: CGPoint = msg![env; touch locationInView:this];
+    let bounds: CGRect = msg![env; this bounds];
+    let visible_location = CGPoint {
+        x: location.x - bounds.origin.x,
+        y: location.y - bounds.origin.y,
+    };
+
+    let host_object = env.objc.borrow_mut::<UITableViewHostObject>(this);
+    match host_object.touch_tracking {
+        TouchTracking::Index => {
+            touch_index(env, this, visible_location.y);
+            return;
+        }
+        TouchTracking::Row { start, moved: false } => {
+            if (visible_location.x - start.x).abs() > TAP_SLOP
+                || (visible_location.y - start.y).abs() > TAP_SLOP
+            {
+                host_object.touch_tracking = TouchTracking::Row { start, moved: true };
+            }
+        }
+        _ => (),
+    }
+    () = msg_super![env; this touchesMoved:touches withEvent:event];
+}
+
+- (())touchesCancelled:(id)_touches // NSSet* of UITouch*
+             withEvent:(id)_event { // UIEvent*
+    env.objc.borrow_mut::<UITableViewHostObject>(this).touch_tracking = TouchTracking::None;
+}
+
+- (())touchesEnded:(id)touches // NSSet* of UITouch*
+         withEvent:(id)_event { // UIEvent*
+    let tracking = std::mem::take(
+        &mut env.objc.borrow_mut::<UITableViewHostObject>(this).touch_tracking
+    );
+    let TouchTracking::Row { moved: false, .. } = tracking else {
+        return;
+    };
+
+    let touch: id = msg![env; touches anyObject];
+    let location: CGPoint = msg![env; touch locationInView:this];
+    let Some((section, row)) = row_at_point(env, this, location) else {
+        return;
+    };
+
+    let delegate: id = msg![env; this delegate];
+    let mut index_path = index_path_for_row(env, section, row);
+    if responds(env, delegate, "tableView:willSelectRowAtIndexPath:") {
+        index_path = msg![env; delegate tableView:this willSelectRowAtIndexPath:index_path];
+        if index_path == nil {
+            return;
+        }
+    }
+    let selected = to_section_and_row(env, index_path);
+    select_row(env, this, Some(selected));
+    if responds(env, delegate, "tableView:didSelectRowAtIndexPath:") {
+        () = msg![env; delegate tableView:this didSelectRowAtIndexPath:index_path];
+    }
+}
+
+@end
+
+};
@@ -10,7 +10,7 @@ use crate::frameworks::core_graphics::cg_geometry::CGPointZero;
 use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
 use crate::frameworks::uikit::ui_color;
 use crate::frameworks::uikit::ui_font::{
-    UILineBreakModeTailTruncation, UITextAlignment, UITextAlignmentLeft,
+    UILineBreakModeWordWrap, UITextAlignment, UITextAlignmentLeft,
 };
 use crate::frameworks::uikit::ui_graphics::UIGraphicsGetCurrentContext;
 use crate::objc::{
@@ -48,7 +48,11 @@ impl Default for UITextViewHostObject {
 // contentSize like font and text change.
 fn update_scroll(env: &mut Environment, this: id) {
     let bounds: CGRect = msg![env; this bounds];
-    let bound_size = bounds.size;
+    // The text can be as tall as it needs to be.
+    let bound_size = CGSize {
+        width: bounds.size.width,
+        height: CGFloat::MAX,
+    };
     let font: id = msg![env; this font];
     let text: id = msg![env; this text];
 
@@ -185,20 +189,21 @@ pub const CLASSES: ClassExports = objc_classes! {
     CGContextSetRGBFillColor(env, context, r, g, b, a);
 
     let content_offset: CGPoint = msg![env; this contentOffset];
+    let content_size: CGSize = msg![env; this contentSize];
     let rect = CGRect {
         origin: CGPointZero,
         // If size is not expanded by the offset,
         // the text is rendered truncated.
         size: CGSize {
             width: bounds.size.width + content_offset.x,
-            height: bounds.size.height + content_offset.y,
+            height: (bounds.size.height + content_offset.y).max(content_size.height),
         }
     };
 
     log_dbg!("UItextView text rendering in rect {:?}", rect);
     let _size: CGSize = msg![env; text drawInRect:rect
                                          withFont:font
-                                    lineBreakMode:UILineBreakModeTailTruncation
+                                    lineBreakMode:UILineBreakModeWordWrap
                                         alignment:text_alignment];
 }
 
@@ -0,0 +1,395 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `UITableViewCell`.
+
+use crate::frameworks::core_graphics::{CGFloat, CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::NSInteger;
+use crate::frameworks::uikit::ui_font::{UITextAlignmentLeft, UITextAlignmentRight};
+use crate::objc::{
+    id, impl_HostObject_with_superclass, msg, msg_class, msg_super, nil, objc_classes, release,
+    ClassExports, NSZonePtr,
+};
+use crate::Environment;
+
+/// The default height of a cell, which is also the default row height of a
+/// table view.
+pub const DEFAULT_ROW_HEIGHT: CGFloat = 44.0;
+
+type UITableViewCellStyle = NSInteger;
+const UITableViewCellStyleDefault: UITableViewCellStyle = 0;
+const UITableViewCellStyleValue1: UITableViewCellStyle = 1;
+const UITableViewCellStyleValue2: UITableViewCellStyle = 2;
+const UITableViewCellStyleSubtitle: UITableViewCellStyle = 3;
+
+type UITableViewCellSelectionStyle = NSInteger;
+const UITableViewCellSelectionStyleNone: UITableViewCellSelectionStyle = 0;
+const UITableViewCellSelectionStyleBlue: UITableViewCellSelectionStyle = 1;
+
+type UITableViewCellAccessoryType = NSInteger;
+const UITableViewCellAccessoryNone: UITableViewCellAccessoryType = 0;
+
+pub struct UITableViewCellHostObject {
+    superclass: super::UIViewHostObject,
+    style: UITableViewCellStyle,
+    /// `NSString*`
+    reuse_identifier: id,
+    /// `UIView*`
+    content_view: id,
+    /// `UILabel*`
+    text_label: id,
+    /// `UILabel*`, `nil` for the default style.
+    detail_text_label: id,
+    /// `UIImageView*`, created when first needed.
+    image_view: id,
+    /// `UIView*`
+    separator: id,
+    selection_style: UITableViewCellSelectionStyle,
+    accessory_type: UITableViewCellAccessoryType,
+    selected: bool,
+}
+impl_HostObject_with_superclass!(UITableViewCellHostObject);
+impl Default for UITableViewCellHostObject {
+    fn default() -> Self {
+        UITableViewCellHostObject {
+            superclass: Default::default(),
+            style: UITableViewCellStyleDefault,
+            reuse_identifier: nil,
+            content_view: nil,
+            text_label: nil,
+            detail_text_label: nil,
+            image_view: nil,
+            separator: nil,
+            selection_style: UITableViewCellSelectionStyleBlue,
+            accessory_type: UITableViewCellAccessoryNone,
+            selected: false,
+        }
+    }
+}
+
+fn new_label(env: &mut Environment, font_size: CGFloat, bold: bool) -> id {
+    let label: id = msg_class![env; UILabel new];
+    let font: id = if bold {
+        msg_class![env; UIFont boldSystemFontOfSize:font_size]
+    } else {
+        msg_class![env; UIFont systemFontOfSize:font_size]
+    };
+    let clear: id = msg_class![env; UIColor clearColor];
+    () = msg![env; label setFont:font];
+    () = msg![env; label setBackgroundColor:clear];
+    label
+}
+
+/// Shared parts of `initWithCoder:` and `initWithStyle:reuseIdentifier:`.
+fn init_common(env: &mut Environment, this: id, style: UITableViewCellStyle, reuse_identifier: id) {
+    let reuse_identifier: id = msg![env; reuse_identifier copy];
+
+    let content_view: id = msg_class![env; UIView new];
+    let clear: id = msg_class![env; UIColor clearColor];
+    () = msg![env; content_view setBackgroundColor:clear];
+
+    let (text_label, detail_text_label) = match style {
+        UITableViewCellStyleSubtitle => (new_label(env, 18.0, true), new_label(env, 14.0, false)),
+        UITableViewCellStyleValue1 => (new_label(env, 18.0, true), new_label(env, 17.0, false)),
+        UITableViewCellStyleValue2 => (new_label(env, 12.0, true), new_label(env, 15.0, true)),
+        _ => (new_label(env, 20.0, true), nil),
+    };
+    () = msg![env; content_view addSubview:text_label];
+    if detail_text_label != nil {
+        let color: id = match style {
+            UITableViewCellStyleSubtitle => msg_class![env; UIColor grayColor],
+            _ => msg_class![env; UIColor colorWithRed:(0.22 as CGFloat)
+                                                 green:(0.33 as CGFloat)
+                                                  blue:(0.53 as CGFloat)
+                                                 alpha:(1.0 as CGFloat)],
+        };
+        () = msg![env; detail_text_label setTextColor:color];
+        if style == UITableViewCellStyleValue1 {
+            () = msg![env; detail_text_label setTextAlignment:UITextAlignmentRight];
+        }
+        () = msg![env; content_view addSubview:detail_text_label];
+    }
+    if style == UITableViewCellStyleValue2 {
+        let color: id = msg![env; detail_text_label textColor];
+        () = msg![env; text_label setTextColor:color];
+        () = msg![env; text_label setTextAlignment:UITextAlignmentRight];
+        let black: id = msg_class![env; UIColor blackColor];
+        () = msg![env; detail_text_label setTextColor:black];
+        () = msg![env; detail_text_label setTextAlignment:UITextAlignmentLeft];
+    }
+
+    let separator: id = msg_class![env; UIView new];
+    let separator_color: id = msg_class![env; UIColor colorWithWhite:(0.88 as CGFloat)
+                                                                alpha:(1.0 as CGFloat)];
+    () = msg![env; separator setBackgroundColor:separator_color];
+
+    let white: id = msg_class![env; UIColor whiteColor];
+    () = msg![env; this setBackgroundColor:white];
+    () = msg![env; this addSubview:content_view];
+    () = msg![env; this addSubview:separator];
+
+    let host_object = env.objc.borrow_mut::<UITableViewCellHostObject>(this);
+    host_object.style = style;
+    host_object.reuse_identifier = reuse_identifier;
+    host_object.content_view = content_view;
+    host_object.text_label = text_label;
+    host_object.detail_text_label = detail_text_label;
+    host_object.separator = separator;
+
+    () = msg![env; this layoutSubviews];
+}
+
+/// Update the colors of the labels to match the selection state.
+fn update_selection(env: &mut Environment, this: id) {
+    let &UITableViewCellHostObject {
+        style,
+        text_label,
+        selection_style,
+        selected,
+        ..
+    } = env.objc.borrow(this);
+    let highlighted = selected && selection_style != UITableViewCellSelectionStyleNone;
+    let background: id = if !highlighted {
+        msg_class![env; UIColor whiteColor]
+    } else if selection_style == UITableViewCellSelectionStyleBlue {
+        msg_class![env; UIColor colorWithRed:(0.02 as CGFloat)
+                                       green:(0.45 as CGFloat)
+                                        blue:(0.89 as CGFloat)
+                                       alpha:(1.0 as CGFloat)]
+    } else {
+        msg_class![env; UIColor grayColor]
+    };
+    () = msg![env; this setBackgroundColor:background];
+    // Labels keep their own colors in most styles, but the main text is
+    // always readable against the selection color.
+    if style == UITableViewCellStyleDefault || style == UITableViewCellStyleSubtitle {
+        let text_color: id = if highlighted {
+            msg_class![env; UIColor whiteColor]
+        } else {
+            msg_class![env; UIColor blackColor]
+        };
+        () = msg![env; text_label setTextColor:text_color];
+    }
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation UITableViewCell: UIView
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<UITableViewCellHostObject>::default();
+    env.objc.alloc_object(this, host_object, &mut env.mem)
+}
+
+- (id)initWithStyle:(UITableViewCellStyle)style
+    reuseIdentifier:(id)reuse_identifier { // NSString*
+    let frame = CGRect {
+        origin: CGPoint { x: 0.0, y: 0.0 },
+        size: CGSize { width: 320.0, height: DEFAULT_ROW_HEIGHT },
+    };
+    let this: id = msg_super![env; this initWithFrame:frame];
+    init_common(env, this, style, reuse_identifier);
+    this
+}
+
+// iPhone OS 2.x initializer, deprecated in 3.0.
+- (id)initWithFrame:(CGRect)frame
+    reuseIdentifier:(id)reuse_identifier { // NSString*
+    let this: id = msg_super![env; this initWithFrame:frame];
+    init_common(env, this, UITableViewCellStyleDefault, reuse_identifier);
+    this
+}
+
+- (id)initWithFrame:(CGRect)frame {
+    msg![env; this initWithFrame:frame reuseIdentifier:nil]
+}
+
+- (id)initWithCoder:(id)coder {
+    let this: id = msg_super![env; this initWithCoder:coder];
+    // TODO: decode the style and reuse identifier
+    init_common(env, this, UITableViewCellStyleDefault, nil);
+    this
+}
+
+- (())dealloc {
+    let &UITableViewCellHostObject {
+        reuse_identifier,
+        content_view,
+        text_label,
+        detail_text_label,
+        image_view,
+        separator,
+        ..
+    } = env.objc.borrow(this);
+    release(env, reuse_identifier);
+    release(env, content_view);
+    release(env, text_label);
+    release(env, detail_text_label);
+    release(env, image_view);
+    release(env, separator);
+    msg_super![env; this dealloc]
+}
+
+- (id)reuseIdentifier {
+    env.objc.borrow::<UITableViewCellHostObject>(this).reuse_identifier
+}
+
+- (())prepareForReuse {
+    () = msg![env; this setSelected:false animated:false];
+}
+
+- (id)contentView {
+    env.objc.borrow::<UITableViewCellHostObject>(this).content_view
+}
+- (id)textLabel {
+    env.objc.borrow::<UITableViewCellHostObject>(this).text_label
+}
+- (id)detailTextLabel {
+    env.objc.borrow::<UITableViewCellHostObject>(this).detail_text_label
+}
+- (id)imageView {
+    let image_view = env.objc.borrow::<UITableViewCellHostObject>(this).image_view;
+    if image_view != nil {
+        return image_view;
+    }
+    let image_view: id = msg_class![env; UIImageView new];
+    let content_view = env.objc.borrow::<UITableViewCellHostObject>(this).content_view;
+    () = msg![env; content_view addSubview:image_view];
+    env.objc.borrow_mut::<UITableViewCellHostObject>(this).image_view = image_view;
+    () = msg![env; this layoutSubviews];
+    image_view
+}
+
+// iPhone OS 2.x accessors, deprecated in 3.0.
+- (id)text {
+    let label: id = msg![env; this textLabel];
+    msg![env; label text]
+}
+- (())setText:(id)text { // NSString*
+    let label: id = msg![env; this textLabel];
+    () = msg![env; label setText:text];
+}
+- (id)font {
+    let label: id = msg![env; this textLabel];
+    msg![env; label font]
+}
+- (())setFont:(id)font { // UIFont*
+    let label: id = msg![env; this textLabel];
+    () = msg![env; label setFont:font];
+}
+- (id)textColor {
+    let label: id = msg![env; this textLabel];
+    msg![env; label textColor]
+}
+- (())setTextColor:(id)color { // UIColor*
+    let label: id = msg![env; this textLabel];
+    () = msg![env; label setTextColor:color];
+}
+- (id)image {
+    let image_view: id = msg![env; this imageView];
+    msg![env; image_view image]
+}
+- (())setImage:(id)image { // UIImage*
+    let image_view: id = msg![env; this imageView];
+    () = msg![env; image_view setImage:image];
+    () = msg![env; this layoutSubviews];
+}
+
+- (UITableViewCellSelectionStyle)selectionStyle {
+    env.objc.borrow::<UITableViewCellHostObject>(this).selection_style
+}
+- (())setSelectionStyle:(UITableViewCellSelectionStyle)style {
+    env.objc.borrow_mut::<UITableViewCellHostObject>(this).selection_style = style;
+    update_selection(env, this);
+}
+
+// TODO: draw accessories
+- (UITableViewCellAccessoryType)accessoryType {
+    env.objc.borrow::<UITableViewCellHostObject>(this).accessory_type
+}
+- (())setAccessoryType:(UITableViewCellAccessoryType)accessory_type {
+    env.objc.borrow_mut::<UITableViewCellHostObject>(this).accessory_type = accessory_type;
+}
+
+- (bool)isSelected {
+    env.objc.borrow::<UITableViewCellHostObject>(this).selected
+}
+- (())setSelected:(bool)selected {
+    () = msg![env; this setSelected:selected animated:false];
+}
+- (())setSelected:(bool)selected
+         animated:(bool)_animated {
+    env.objc.borrow_mut::<UITableViewCellHostObject>(this).selected = selected;
+    update_selection(env, this);
+}
+- (bool)isHighlighted {
+    msg![env; this isSelected]
+}
+- (())setHighlighted:(bool)highlighted {
+    () = msg![env; this setSelected:highlighted animated:false];
+}
+- (())setHighlighted:(bool)highlighted
+            animated:(bool)_animated {
+    () = msg![env; this setSelected:highlighted animated:false];
+}
+
+- (())layoutSubviews {
+    let &UITableViewCellHostObject {
+        style,
+        content_view,
+        text_label,
+        detail_text_label,
+        image_view,
+        separator,
+        ..
+    } = env.objc.borrow(this);
+    let bounds: CGRect = msg![env; this bounds];
+    let CGSize { width, height } = bounds.size;
+    let rect = |x: CGFloat, y: CGFloat, width: CGFloat, height: CGFloat| CGRect {
+        origin: CGPoint { x, y },
+        size: CGSize { width, height },
+    };
+
+    () = msg![env; content_view setFrame:bounds];
+    () = msg![env; separator setFrame:(rect(0.0, height - 1.0, width, 1.0))];
+
+    let mut x = 10.0;
+    if image_view != nil {
+        let image: id = msg![env; image_view image];
+        if image != nil {
+            let size: CGSize = msg![env; image size];
+            let image_width = size.width.min(height);
+            () = msg![env; image_view setFrame:(rect(0.0, 0.0, image_width, height))];
+            x += image_width;
+        }
+    }
+    let text_width = (width - x - 10.0).max(0.0);
+
+    match style {
+        UITableViewCellStyleSubtitle => {
+            () = msg![env; text_label setFrame:(rect(x, 2.0, text_width, height * 0.55))];
+            let frame = rect(x, height * 0.55, text_width, height * 0.4);
+            () = msg![env; detail_text_label setFrame:frame];
+        }
+        UITableViewCellStyleValue1 => {
+            () = msg![env; text_label setFrame:(rect(x, 0.0, text_width, height))];
+            () = msg![env; detail_text_label setFrame:(rect(x, 0.0, text_width, height))];
+        }
+        UITableViewCellStyleValue2 => {
+            () = msg![env; text_label setFrame:(rect(x, 0.0, 70.0, height))];
+            let frame = rect(x + 80.0, 0.0, (text_width - 80.0).max(0.0), height);
+            () = msg![env; detail_text_label setFrame:frame];
+        }
+        _ => {
+            () = msg![env; text_label setFrame:(rect(x, 0.0, text_width, height))];
+        }
+    }
+}
+
+@end
+
+};
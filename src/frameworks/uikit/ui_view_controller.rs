@@ -5,14 +5,67 @@
  */
 //! `UIViewController`.
 
+pub mod ui_navigation_controller;
+pub mod ui_table_view_controller;
+
+use crate::frameworks::core_animation::ca_media_timing_function::kCAMediaTimingFunctionEaseInEaseOut;
+use crate::frameworks::core_graphics::{CGPoint, CGRect};
 use crate::frameworks::foundation::ns_string::get_static_str;
 use crate::objc::{
-    id, msg, msg_class, nil, objc_classes, release, retain, ClassExports, HostObject, NSZonePtr,
+    id, msg, msg_class, nil, objc_classes, release, retain, Class, ClassExports, HostObject,
+    NSZonePtr,
 };
+use crate::Environment;
+use std::time::{Duration, Instant};
 
 #[derive(Default)]
-struct UIViewControllerHostObject {
+pub struct State {
+    /// Transitions between view controllers that are currently animating, in
+    /// no particular order.
+    transitions: Vec<Transition>,
+    /// All the view controllers that currently exist, so they can be sent
+    /// memory warnings. These are weak references.
+    view_controllers: Vec<id>,
+}
+
+/// A simple sliding animation used when switching between view controllers.
+///
+/// The views' centers are set to their destinations straight away, and a
+/// `CABasicAnimation` on each view's layer makes them slide there, like
+/// UIKit's own transitions. Only the end of the transition is tracked here, so
+/// that `on_finish` can be called.
+struct Transition {
+    /// The view controller that started the transition, e.g. a
+    /// `UINavigationController`. This is a weak reference.
+    owner: id,
+    start: Instant,
+    /// Views being moved. These are strong references.
+    views: Vec<id>,
+    /// Called once the views have reached their destinations.
+    on_finish: Box<dyn FnOnce(&mut Environment)>,
+}
+
+/// Roughly matches the duration of UIKit's standard transitions.
+const TRANSITION_DURATION: Duration = Duration::from_millis(350);
+
+/// Key for the layer animations of a [Transition]. It's distinct from the key
+/// path so that the app's own animations of the same views don't replace it.
+const TRANSITION_ANIMATION_KEY: &str = "_touchHLE_viewControllerTransition";
+
+#[derive(Default)]
+pub struct UIViewControllerHostObject {
     view: id,
+    /// `NSString*`
+    title: id,
+    /// `UINavigationItem*`, created lazily.
+    navigation_item: id,
+    /// The containing view controller, e.g. a `UINavigationController`, or the
+    /// presenting view controller if this is a modal view controller. This is
+    /// a weak reference.
+    parent_view_controller: id,
+    /// The view controller presented modally by this one, if any. This is a
+    /// strong reference.
+    modal_view_controller: id,
 }
 impl HostObject for UIViewControllerHostObject {}
 
@@ -24,7 +77,9 @@ pub const CLASSES: ClassExports = objc_classes! {
 
 + (id)allocWithZone:(NSZonePtr)_zone {
     let host_object = Box::<UIViewControllerHostObject>::default();
-    env.objc.alloc_object(this, host_object, &mut env.mem)
+    let new = env.objc.alloc_object(this, host_object, &mut env.mem);
+    register_view_controller(env, new);
+    new
 }
 
 - (id)initWithCoder:(id)coder {
@@ -37,9 +92,23 @@ pub const CLASSES: ClassExports = objc_classes! {
 }
 
 - (())dealloc {
-    let &UIViewControllerHostObject { view } = env.objc.borrow(this);
+    let &UIViewControllerHostObject {
+        view,
+        title,
+        navigation_item,
+        parent_view_controller: _,
+        modal_view_controller,
+    } = env.objc.borrow(this);
 
     release(env, view);
+    release(env, title);
+    release(env, navigation_item);
+    release(env, modal_view_controller);
+
+    let view_controllers = &mut env.framework_state.uikit.ui_view_controller.view_controllers;
+    if let Some(index) = view_controllers.iter().position(|&vc| vc == this) {
+        view_controllers.swap_remove(index);
+    }
 
     env.objc.dealloc_object(this, &mut env.mem);
 }
@@ -67,15 +136,388 @@ pub const CLASSES: ClassExports = objc_classes! {
         view
     }
 }
+- (bool)isViewLoaded {
+    env.objc.borrow::<UIViewControllerHostObject>(this).view != nil
+}
+
+- (())didReceiveMemoryWarning {
+    // The real implementation releases the view if it isn't in a window, so
+    // it can be loaded again later. touchHLE's loadView can't reload a view
+    // from a nib file yet, so it's safer to keep it.
+}
+- (())viewDidUnload {
+    // Nothing to do by default.
+}
+
+- (id)title {
+    env.objc.borrow::<UIViewControllerHostObject>(this).title
+}
+- (())setTitle:(id)new_title { // NSString*
+    let new_title: id = msg![env; new_title copy];
+    let host_obj = env.objc.borrow_mut::<UIViewControllerHostObject>(this);
+    let old_title = std::mem::replace(&mut host_obj.title, new_title);
+    let navigation_item = host_obj.navigation_item;
+    release(env, old_title);
+    if navigation_item != nil {
+        () = msg![env; navigation_item setTitle:new_title];
+    }
+}
+
+- (id)navigationItem {
+    let &UIViewControllerHostObject {
+        navigation_item,
+        title,
+        ..
+    } = env.objc.borrow(this);
+    if navigation_item != nil {
+        return navigation_item;
+    }
+    let navigation_item: id = msg_class![env; UINavigationItem alloc];
+    let navigation_item: id = msg![env; navigation_item initWithTitle:title];
+    env.objc.borrow_mut::<UIViewControllerHostObject>(this).navigation_item = navigation_item;
+    navigation_item
+}
+
+- (id)parentViewController {
+    env.objc.borrow::<UIViewControllerHostObject>(this).parent_view_controller
+}
+- (id)navigationController {
+    // Looks up the navigation controller in the parent hierarchy
+    let nav_class = env.objc.get_known_class("UINavigationController", &mut env.mem);
+    let mut child = this;
+    let mut parent = env.objc.borrow::<UIViewControllerHostObject>(this).parent_view_controller;
+    while parent != nil {
+        // The presenting view controller doesn't count.
+        if env.objc.borrow::<UIViewControllerHostObject>(parent).modal_view_controller == child {
+            return nil;
+        }
+        let class: Class = msg![env; parent class];
+        if env.objc.class_is_subclass_of(class, nav_class) {
+            break;
+        }
+        child = parent;
+        parent = env.objc.borrow::<UIViewControllerHostObject>(parent).parent_view_controller;
+    }
+    parent
+}
+
+- (id)modalViewController {
+    env.objc.borrow::<UIViewControllerHostObject>(this).modal_view_controller
+}
+
+// Appearance callbacks that subclasses can override. The default
+// implementations do nothing.
+- (())viewWillAppear:(bool)_animated {}
+- (())viewDidAppear:(bool)_animated {}
+- (())viewWillDisappear:(bool)_animated {}
+- (())viewDidDisappear:(bool)_animated {}
 
 - (())setEditing:(bool)editing {
     log!("TODO: [(UIViewController*){:?} setEditing:{}]", this, editing); // TODO
 }
 
+// Modal presentation. Only the full-screen presentation style is supported,
+// so the presented view covers the whole window.
+
+- (())presentModalViewController:(id)modal // UIViewController*
+                        animated:(bool)animated {
+    if modal == nil {
+        log!("Warning: [(UIViewController*){:?} presentModalViewController:nil animated:{}] ignored", this, animated);
+        return;
+    }
+    let existing = env.objc.borrow::<UIViewControllerHostObject>(this).modal_view_controller;
+    if existing == modal {
+        log!("Warning: {:?} is already presenting {:?}, ignoring", this, modal);
+        return;
+    }
+    if existing != nil {
+        // Stack the new modal view controller on top of the existing one.
+        log_dbg!("{:?} is already presenting {:?}, presenting {:?} from it instead", this, existing, modal);
+        return msg![env; existing presentModalViewController:modal animated:animated];
+    }
+    if env.objc.borrow::<UIViewControllerHostObject>(modal).parent_view_controller != nil {
+        log!("Warning: {:?} already has a parent view controller, can't present it from {:?}", modal, this);
+        return;
+    }
+
+    let this_view: id = msg![env; this view];
+    let mut window: id = msg![env; this_view window];
+    if window == nil {
+        window = env
+            .framework_state
+            .uikit
+            .ui_view
+            .ui_window
+            .visible_windows
+            .last()
+            .copied()
+            .unwrap_or(nil);
+    }
+    if window == nil {
+        log!("Warning: no window to present {:?} in, ignoring", modal);
+        return;
+    }
+
+    finish_transitions_for(env, this);
+
+    retain(env, modal);
+    env.objc.borrow_mut::<UIViewControllerHostObject>(this).modal_view_controller = modal;
+    set_parent_view_controller(env, modal, this);
+
+    () = msg![env; this viewWillDisappear:animated];
+    () = msg![env; modal viewWillAppear:animated];
+
+    let screen: id = msg_class![env; UIScreen mainScreen];
+    let frame: CGRect = msg![env; screen applicationFrame];
+    let modal_view: id = msg![env; modal view];
+    () = msg![env; modal_view setFrame:frame];
+    () = msg![env; window addSubview:modal_view];
+
+    // Everything involved must stay alive until the transition is over.
+    retain(env, this);
+    let finish = move |env: &mut Environment| {
+        () = msg![env; this viewDidDisappear:animated];
+        () = msg![env; modal viewDidAppear:animated];
+        release(env, this);
+    };
+
+    if animated {
+        // Cover vertically: slide up from the bottom of the screen.
+        let screen_bounds: CGRect = msg![env; screen bounds];
+        let center: CGPoint = msg![env; modal_view center];
+        let moves = vec![(
+            modal_view,
+            CGPoint {
+                x: center.x,
+                y: center.y + screen_bounds.size.height,
+            },
+            center,
+        )];
+        start_transition(env, this, moves, Box::new(finish));
+    } else {
+        finish(env);
+    }
+}
+
 - (())dismissModalViewControllerAnimated:(bool)animated {
-    log!("TODO: [(UIViewController*){:?} dismissModalViewControllerAnimated:{}]", this, animated); // TODO
+    let modal = env.objc.borrow::<UIViewControllerHostObject>(this).modal_view_controller;
+    if modal == nil {
+        // If this is the modal view controller, the presenting view
+        // controller is responsible for dismissing it.
+        let parent = env.objc.borrow::<UIViewControllerHostObject>(this).parent_view_controller;
+        if parent != nil && env.objc.borrow::<UIViewControllerHostObject>(parent).modal_view_controller == this {
+            return msg![env; parent dismissModalViewControllerAnimated:animated];
+        }
+        log!("Warning: [(UIViewController*){:?} dismissModalViewControllerAnimated:{}] with no modal view controller, ignoring", this, animated);
+        return;
+    }
+
+    // Any view controllers stacked on top must go too.
+    let modal_modal = env.objc.borrow::<UIViewControllerHostObject>(modal).modal_view_controller;
+    if modal_modal != nil {
+        () = msg![env; modal dismissModalViewControllerAnimated:false];
+    }
+
+    finish_transitions_for(env, this);
+
+    // The modal view controller's reference is transferred to the
+    // transition.
+    env.objc.borrow_mut::<UIViewControllerHostObject>(this).modal_view_controller = nil;
+
+    () = msg![env; modal viewWillDisappear:animated];
+    () = msg![env; this viewWillAppear:animated];
+
+    let modal_view: id = msg![env; modal view];
+
+    retain(env, this);
+    let finish = move |env: &mut Environment| {
+        () = msg![env; modal_view removeFromSuperview];
+        set_parent_view_controller(env, modal, nil);
+        () = msg![env; modal viewDidDisappear:animated];
+        () = msg![env; this viewDidAppear:animated];
+        release(env, modal);
+        release(env, this);
+    };
+
+    if animated {
+        let screen: id = msg_class![env; UIScreen mainScreen];
+        let screen_bounds: CGRect = msg![env; screen bounds];
+        let center: CGPoint = msg![env; modal_view center];
+        let moves = vec![(
+            modal_view,
+            center,
+            CGPoint {
+                x: center.x,
+                y: center.y + screen_bounds.size.height,
+            },
+        )];
+        start_transition(env, this, moves, Box::new(finish));
+    } else {
+        finish(env);
+    }
 }
 
 @end
 
 };
+
+/// For use by container view controllers: sets the `parentViewController`
+/// (a weak reference).
+fn set_parent_view_controller(env: &mut Environment, child: id, parent: id) {
+    env.objc
+        .borrow_mut::<UIViewControllerHostObject>(child)
+        .parent_view_controller = parent;
+}
+
+/// Keep track of a new view controller so it can be sent memory warnings.
+/// This is needed by any subclass that doesn't call the superclass's
+/// `allocWithZone:`.
+pub(super) fn register_view_controller(env: &mut Environment, view_controller: id) {
+    env.framework_state
+        .uikit
+        .ui_view_controller
+        .view_controllers
+        .push(view_controller);
+}
+
+/// Send `didReceiveMemoryWarning` to every view controller.
+pub(super) fn send_memory_warning(env: &mut Environment) {
+    let view_controllers = env
+        .framework_state
+        .uikit
+        .ui_view_controller
+        .view_controllers
+        .clone();
+    for view_controller in view_controllers {
+        // One view controller might release another in response.
+        if !env
+            .framework_state
+            .uikit
+            .ui_view_controller
+            .view_controllers
+            .contains(&view_controller)
+        {
+            continue;
+        }
+        () = msg![env; view_controller didReceiveMemoryWarning];
+    }
+}
+
+/// Start moving some views between two centers. Any transition previously
+/// started by the same `owner` is completed immediately first.
+///
+/// `on_finish` is called once the views arrive, and is responsible for any
+/// clean-up like removing views from the hierarchy.
+fn start_transition(
+    env: &mut Environment,
+    owner: id,
+    moves: Vec<(id, CGPoint, CGPoint)>,
+    on_finish: Box<dyn FnOnce(&mut Environment)>,
+) {
+    finish_transitions_for(env, owner);
+
+    let key_path = get_static_str(env, "position");
+    let key = get_static_str(env, TRANSITION_ANIMATION_KEY);
+    let timing_function_name = get_static_str(env, kCAMediaTimingFunctionEaseInEaseOut);
+    let timing_function: id =
+        msg_class![env; CAMediaTimingFunction functionWithName:timing_function_name];
+    let mut views = Vec::with_capacity(moves.len());
+    for (view, from, to) in moves {
+        retain(env, view);
+        views.push(view);
+        () = msg![env; view setCenter:to];
+
+        // A view's center is its layer's position.
+        let from: id = msg_class![env; NSValue valueWithCGPoint:from];
+        let animation: id = msg_class![env; CABasicAnimation animationWithKeyPath:key_path];
+        () = msg![env; animation setFromValue:from];
+        () = msg![env; animation setDuration:(TRANSITION_DURATION.as_secs_f64())];
+        () = msg![env; animation setTimingFunction:timing_function];
+        let layer: id = msg![env; view layer];
+        () = msg![env; layer addAnimation:animation forKey:key];
+    }
+
+    env.framework_state
+        .uikit
+        .ui_view_controller
+        .transitions
+        .push(Transition {
+            owner,
+            start: env.clock.now(),
+            views,
+            on_finish,
+        });
+}
+
+/// Immediately complete any transitions started by `owner`.
+fn finish_transitions_for(env: &mut Environment, owner: id) {
+    let transitions = &mut env.framework_state.uikit.ui_view_controller.transitions;
+    let mut finished = Vec::new();
+    let mut i = 0;
+    while i < transitions.len() {
+        if transitions[i].owner == owner {
+            finished.push(transitions.remove(i));
+        } else {
+            i += 1;
+        }
+    }
+    for transition in finished {
+        finish_transition(env, transition);
+    }
+}
+
+fn finish_transition(env: &mut Environment, transition: Transition) {
+    let Transition {
+        views, on_finish, ..
+    } = transition;
+    let key = get_static_str(env, TRANSITION_ANIMATION_KEY);
+    for view in views {
+        // Has no effect if the animation already ended.
+        let layer: id = msg![env; view layer];
+        () = msg![env; layer removeAnimationForKey:key];
+        release(env, view);
+    }
+    on_finish(env);
+}
+
+/// For use by [super::handle_events]: finishes any transitions whose
+/// animations have ended.
+///
+/// Returns the next time this function must be called, if any.
+pub(super) fn handle_transitions(env: &mut Environment) -> Option<Instant> {
+    if env
+        .framework_state
+        .uikit
+        .ui_view_controller
+        .transitions
+        .is_empty()
+    {
+        return None;
+    }
+
+    let now = env.clock.now();
+
+    let transitions = std::mem::take(&mut env.framework_state.uikit.ui_view_controller.transitions);
+    let (finished, mut ongoing): (Vec<_>, Vec<_>) =
+        transitions.into_iter().partition(|transition| {
+            now.saturating_duration_since(transition.start) >= TRANSITION_DURATION
+        });
+    // Transitions may have been started while this function was running.
+    env.framework_state
+        .uikit
+        .ui_view_controller
+        .transitions
+        .append(&mut ongoing);
+
+    for transition in finished {
+        finish_transition(env, transition);
+    }
+
+    env.framework_state
+        .uikit
+        .ui_view_controller
+        .transitions
+        .iter()
+        .map(|transition| transition.start + TRANSITION_DURATION)
+        .min()
+}
@@ -0,0 +1,502 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `UINavigationController`.
+
+use super::{finish_transitions_for, set_parent_view_controller, start_transition};
+use crate::frameworks::core_graphics::{CGPoint, CGRect, CGSize};
+use crate::frameworks::foundation::{ns_array, NSUInteger};
+use crate::frameworks::uikit::ui_view::ui_navigation_bar::NAVIGATION_BAR_HEIGHT;
+use crate::objc::{
+    autorelease, id, impl_HostObject_with_superclass, msg, msg_class, msg_super, nil, objc_classes,
+    release, retain, ClassExports, NSZonePtr, SEL,
+};
+use crate::Environment;
+
+#[derive(Default)]
+pub struct UINavigationControllerHostObject {
+    superclass: super::UIViewControllerHostObject,
+    /// The navigation stack in bottom-to-top order. These are strong
+    /// references.
+    view_controllers: Vec<id>,
+    /// `UINavigationBar*`, created lazily.
+    navigation_bar: id,
+    navigation_bar_hidden: bool,
+    /// Weak reference.
+    delegate: id,
+}
+impl_HostObject_with_superclass!(UINavigationControllerHostObject);
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation UINavigationController: UIViewController
+
++ (id)allocWithZone:(NSZonePtr)_zone {
+    let host_object = Box::<UINavigationControllerHostObject>::default();
+    let new = env.objc.alloc_object(this, host_object, &mut env.mem);
+    super::register_view_controller(env, new);
+    new
+}
+
+- (id)initWithRootViewController:(id)root_view_controller {
+    let this: id = msg![env; this init];
+    () = msg![env; this pushViewController:root_view_controller animated:false];
+    this
+}
+
+// TODO: initWithCoder: (decoding the view controllers)
+
+- (())dealloc {
+    let host_obj = env.objc.borrow_mut::<UINavigationControllerHostObject>(this);
+    let view_controllers = std::mem::take(&mut host_obj.view_controllers);
+    let navigation_bar = std::mem::take(&mut host_obj.navigation_bar);
+    for view_controller in view_controllers {
+        set_parent_view_controller(env, view_controller, nil);
+        release(env, view_controller);
+    }
+    release(env, navigation_bar);
+
+    msg_super![env; this dealloc]
+}
+
+- (())loadView {
+    let screen: id = msg_class![env; UIScreen mainScreen];
+    let frame: CGRect = msg![env; screen applicationFrame];
+    let view: id = msg_class![env; UIView alloc];
+    let view: id = msg![env; view initWithFrame:frame];
+    () = msg![env; this setView:view];
+    release(env, view);
+
+    let navigation_bar = get_navigation_bar(env, this);
+    let bar_frame = CGRect {
+        origin: CGPoint { x: 0.0, y: 0.0 },
+        size: CGSize {
+            width: frame.size.width,
+            height: NAVIGATION_BAR_HEIGHT,
+        },
+    };
+    () = msg![env; navigation_bar setFrame:bar_frame];
+    () = msg![env; view addSubview:navigation_bar];
+
+    let top: id = msg![env; this topViewController];
+    if top != nil {
+        let top_view: id = msg![env; top view];
+        let content_frame = content_frame(env, this);
+        () = msg![env; top_view setFrame:content_frame];
+        () = msg![env; view addSubview:top_view];
+        () = msg![env; view bringSubviewToFront:navigation_bar];
+    }
+}
+
+- (id)delegate {
+    env.objc.borrow::<UINavigationControllerHostObject>(this).delegate
+}
+- (())setDelegate:(id)delegate {
+    env.objc.borrow_mut::<UINavigationControllerHostObject>(this).delegate = delegate;
+}
+
+- (id)navigationBar {
+    get_navigation_bar(env, this)
+}
+- (bool)isNavigationBarHidden {
+    env.objc.borrow::<UINavigationControllerHostObject>(this).navigation_bar_hidden
+}
+- (())setNavigationBarHidden:(bool)hidden {
+    env.objc.borrow_mut::<UINavigationControllerHostObject>(this).navigation_bar_hidden = hidden;
+    let navigation_bar = get_navigation_bar(env, this);
+    () = msg![env; navigation_bar setHidden:hidden];
+
+    // Resize the top view to fill the space.
+    let top: id = msg![env; this topViewController];
+    if top != nil && msg![env; this isViewLoaded] {
+        let top_view: id = msg![env; top view];
+        let content_frame = content_frame(env, this);
+        () = msg![env; top_view setFrame:content_frame];
+    }
+}
+- (())setNavigationBarHidden:(bool)hidden animated:(bool)_animated {
+    // TODO: animation
+    msg![env; this setNavigationBarHidden:hidden]
+}
+
+- (id)viewControllers {
+    let view_controllers = env
+        .objc
+        .borrow::<UINavigationControllerHostObject>(this)
+        .view_controllers
+        .clone();
+    for &view_controller in &view_controllers {
+        retain(env, view_controller);
+    }
+    let array = ns_array::from_vec(env, view_controllers);
+    autorelease(env, array)
+}
+- (())setViewControllers:(id)new_view_controllers { // NSArray*
+    msg![env; this setViewControllers:new_view_controllers animated:false]
+}
+- (())setViewControllers:(id)new_view_controllers // NSArray*
+                animated:(bool)animated {
+    let old_top: id = msg![env; this topViewController];
+
+    let count: NSUInteger = msg![env; new_view_controllers count];
+    let mut view_controllers = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        let view_controller: id = msg![env; new_view_controllers objectAtIndex:i];
+        retain(env, view_controller);
+        set_parent_view_controller(env, view_controller, this);
+        view_controllers.push(view_controller);
+    }
+    let old_view_controllers = std::mem::replace(
+        &mut env.objc.borrow_mut::<UINavigationControllerHostObject>(this).view_controllers,
+        view_controllers
+    );
+    let new_top: id = msg![env; this topViewController];
+
+    // The old top must stay alive until the transition has started.
+    retain(env, old_top);
+    for view_controller in old_view_controllers {
+        let host_obj = env.objc.borrow::<UINavigationControllerHostObject>(this);
+        if !host_obj.view_controllers.contains(&view_controller) {
+            set_parent_view_controller(env, view_controller, nil);
+        }
+        release(env, view_controller);
+    }
+
+    update_navigation_bar(env, this, animated);
+    if old_top != new_top {
+        show_view_controller(env, this, old_top, new_top, animated, /* push: */ true);
+    }
+    release(env, old_top);
+}
+
+- (id)topViewController {
+    env.objc
+        .borrow::<UINavigationControllerHostObject>(this)
+        .view_controllers
+        .last()
+        .copied()
+        .unwrap_or(nil)
+}
+- (id)visibleViewController {
+    // A modal view controller presented by the navigation controller or by
+    // its top view controller takes precedence.
+    let modal: id = msg![env; this modalViewController];
+    if modal != nil {
+        return modal;
+    }
+    let top: id = msg![env; this topViewController];
+    let modal: id = msg![env; top modalViewController];
+    if modal != nil {
+        modal
+    } else {
+        top
+    }
+}
+
+- (())pushViewController:(id)view_controller
+                animated:(bool)animated {
+    if view_controller == nil {
+        log!("Warning: [(UINavigationController*){:?} pushViewController:nil animated:{}] ignored", this, animated);
+        return;
+    }
+    let old_top: id = msg![env; this topViewController];
+
+    retain(env, view_controller);
+    set_parent_view_controller(env, view_controller, this);
+    env.objc
+        .borrow_mut::<UINavigationControllerHostObject>(this)
+        .view_controllers
+        .push(view_controller);
+
+    update_navigation_bar(env, this, animated);
+    show_view_controller(env, this, old_top, view_controller, animated, /* push: */ true);
+}
+
+- (id)popViewControllerAnimated:(bool)animated {
+    let host_obj = env.objc.borrow_mut::<UINavigationControllerHostObject>(this);
+    // The root view controller can't be popped.
+    if host_obj.view_controllers.len() <= 1 {
+        return nil;
+    }
+    let popped = host_obj.view_controllers.pop().unwrap();
+    set_parent_view_controller(env, popped, nil);
+    let new_top: id = msg![env; this topViewController];
+
+    update_navigation_bar(env, this, animated);
+    show_view_controller(env, this, popped, new_top, animated, /* push: */ false);
+
+    autorelease(env, popped)
+}
+
+- (id)popToRootViewControllerAnimated:(bool)animated {
+    let root = env
+        .objc
+        .borrow::<UINavigationControllerHostObject>(this)
+        .view_controllers
+        .first()
+        .copied()
+        .unwrap_or(nil);
+    msg![env; this popToViewController:root animated:animated]
+}
+
+- (id)popToViewController:(id)view_controller
+                 animated:(bool)animated {
+    let host_obj = env.objc.borrow_mut::<UINavigationControllerHostObject>(this);
+    let Some(idx) = host_obj.view_controllers.iter().position(|&vc| vc == view_controller) else {
+        log!(
+            "Warning: [(UINavigationController*){:?} popToViewController:{:?} animated:{}] for view controller not in stack",
+            this,
+            view_controller,
+            animated,
+        );
+        return nil;
+    };
+    let popped = host_obj.view_controllers.split_off(idx + 1);
+    let Some(&old_top) = popped.last() else {
+        let empty = ns_array::from_vec(env, Vec::new());
+        return autorelease(env, empty);
+    };
+    for &popped_view_controller in &popped {
+        set_parent_view_controller(env, popped_view_controller, nil);
+    }
+
+    update_navigation_bar(env, this, animated);
+    show_view_controller(env, this, old_top, view_controller, animated, /* push: */ false);
+
+    // The array takes ownership of the stack's references.
+    let popped = ns_array::from_vec(env, popped);
+    autorelease(env, popped)
+}
+
+// UINavigationBarDelegate implementation
+
+- (bool)navigationBar:(id)_navigation_bar
+        shouldPopItem:(id)item { // UINavigationItem*
+    // The back button was pressed. Popping the view controller will also
+    // update the bar's items, so the bar doesn't need to pop the item itself.
+    let top: id = msg![env; this topViewController];
+    let top_item: id = msg![env; top navigationItem];
+    if top_item == item {
+        let _: id = msg![env; this popViewControllerAnimated:true];
+    }
+    false
+}
+
+// Forward appearance callbacks to the top view controller, since its view is
+// part of ours.
+- (())viewWillAppear:(bool)animated {
+    let top: id = msg![env; this topViewController];
+    msg![env; top viewWillAppear:animated]
+}
+- (())viewDidAppear:(bool)animated {
+    let top: id = msg![env; this topViewController];
+    msg![env; top viewDidAppear:animated]
+}
+- (())viewWillDisappear:(bool)animated {
+    let top: id = msg![env; this topViewController];
+    msg![env; top viewWillDisappear:animated]
+}
+- (())viewDidDisappear:(bool)animated {
+    let top: id = msg![env; this topViewController];
+    msg![env; top viewDidDisappear:animated]
+}
+
+@end
+
+};
+
+fn get_navigation_bar(env: &mut Environment, this: id) -> id {
+    let host_obj = env.objc.borrow::<UINavigationControllerHostObject>(this);
+    if host_obj.navigation_bar != nil {
+        return host_obj.navigation_bar;
+    }
+    let hidden = host_obj.navigation_bar_hidden;
+
+    let screen: id = msg_class![env; UIScreen mainScreen];
+    let screen_bounds: CGRect = msg![env; screen bounds];
+    let frame = CGRect {
+        origin: CGPoint { x: 0.0, y: 0.0 },
+        size: CGSize {
+            width: screen_bounds.size.width,
+            height: NAVIGATION_BAR_HEIGHT,
+        },
+    };
+    let navigation_bar: id = msg_class![env; UINavigationBar alloc];
+    let navigation_bar: id = msg![env; navigation_bar initWithFrame:frame];
+    () = msg![env; navigation_bar setDelegate:this];
+    () = msg![env; navigation_bar setHidden:hidden];
+    env.objc
+        .borrow_mut::<UINavigationControllerHostObject>(this)
+        .navigation_bar = navigation_bar;
+
+    update_navigation_bar(env, this, /* animated: */ false);
+
+    navigation_bar
+}
+
+/// The frame for the top view controller's view, in the co-ordinate space of
+/// the navigation controller's view.
+fn content_frame(env: &mut Environment, this: id) -> CGRect {
+    let view: id = msg![env; this view];
+    let mut frame: CGRect = msg![env; view bounds];
+    if !env
+        .objc
+        .borrow::<UINavigationControllerHostObject>(this)
+        .navigation_bar_hidden
+    {
+        frame.origin.y += NAVIGATION_BAR_HEIGHT;
+        frame.size.height -= NAVIGATION_BAR_HEIGHT;
+    }
+    frame
+}
+
+/// Make the navigation bar's items match the navigation stack.
+fn update_navigation_bar(env: &mut Environment, this: id, animated: bool) {
+    let host_obj = env.objc.borrow::<UINavigationControllerHostObject>(this);
+    let navigation_bar = host_obj.navigation_bar;
+    if navigation_bar == nil {
+        // It will be updated when it is created.
+        return;
+    }
+    let view_controllers = host_obj.view_controllers.clone();
+
+    let mut items = Vec::with_capacity(view_controllers.len());
+    for view_controller in view_controllers {
+        let item: id = msg![env; view_controller navigationItem];
+        retain(env, item);
+        items.push(item);
+    }
+    let items = ns_array::from_vec(env, items);
+    () = msg![env; navigation_bar setItems:items animated:animated];
+    release(env, items);
+}
+
+/// Switch the visible view controller from `from` to `to` (either can be
+/// [nil]), sending the appearance callbacks and sliding the views if
+/// `animated` is set. `push` determines the direction of the slide.
+///
+/// This does nothing with the views if the navigation controller's own view
+/// hasn't been loaded yet.
+fn show_view_controller(
+    env: &mut Environment,
+    this: id,
+    from: id,
+    to: id,
+    animated: bool,
+    push: bool,
+) {
+    if from == to || !msg![env; this isViewLoaded] {
+        return;
+    }
+
+    // Views may be mid-animation and would end up in the wrong place.
+    finish_transitions_for(env, this);
+
+    // Everything involved must stay alive until the transition is over.
+    retain(env, this);
+    retain(env, from);
+    retain(env, to);
+
+    let container: id = msg![env; this view];
+    let content_frame = content_frame(env, this);
+    let delegate = env
+        .objc
+        .borrow::<UINavigationControllerHostObject>(this)
+        .delegate;
+
+    let from_view: id = if from != nil {
+        () = msg![env; from viewWillDisappear:animated];
+        msg![env; from view]
+    } else {
+        nil
+    };
+    let to_view: id = if to != nil {
+        if delegate != nil {
+            let sel: SEL = env.objc.register_host_selector(
+                "navigationController:willShowViewController:animated:".to_string(),
+                &mut env.mem,
+            );
+            if msg![env; delegate respondsToSelector:sel] {
+                () = msg![env; delegate navigationController:this
+                                      willShowViewController:to
+                                                    animated:animated];
+            }
+        }
+        () = msg![env; to viewWillAppear:animated];
+        let to_view: id = msg![env; to view];
+        () = msg![env; to_view setFrame:content_frame];
+        () = msg![env; container addSubview:to_view];
+        to_view
+    } else {
+        nil
+    };
+    let navigation_bar = get_navigation_bar(env, this);
+    () = msg![env; container bringSubviewToFront:navigation_bar];
+
+    let from_center: CGPoint = if from_view != nil {
+        msg![env; from_view center]
+    } else {
+        CGPoint { x: 0.0, y: 0.0 }
+    };
+
+    let finish = move |env: &mut Environment| {
+        if from != nil {
+            () = msg![env; from_view removeFromSuperview];
+            // Put the view back where it was, in case it's shown again.
+            () = msg![env; from_view setCenter:from_center];
+            () = msg![env; from viewDidDisappear:animated];
+        }
+        if to != nil {
+            () = msg![env; to viewDidAppear:animated];
+            let delegate = env
+                .objc
+                .borrow::<UINavigationControllerHostObject>(this)
+                .delegate;
+            if delegate != nil {
+                let sel: SEL = env.objc.register_host_selector(
+                    "navigationController:didShowViewController:animated:".to_string(),
+                    &mut env.mem,
+                );
+                if msg![env; delegate respondsToSelector:sel] {
+                    () = msg![env; delegate navigationController:this
+                                           didShowViewController:to
+                                                        animated:animated];
+                }
+            }
+        }
+        release(env, from);
+        release(env, to);
+        release(env, this);
+    };
+
+    if animated && from_view != nil && to_view != nil {
+        let width = content_frame.size.width;
+        let direction = if push { 1.0 } else { -1.0 };
+        let to_center: CGPoint = msg![env; to_view center];
+        let moves = vec![
+            (
+                to_view,
+                CGPoint {
+                    x: to_center.x + direction * width,
+                    y: to_center.y,
+                },
+                to_center,
+            ),
+            (
+                from_view,
+                from_center,
+                CGPoint {
+                    x: from_center.x - direction * width,
+                    y: from_center.y,
+                },
+            ),
+        ];
+        start_transition(env, this, moves, Box::new(finish));
+    } else {
+        finish(env);
+    }
+}
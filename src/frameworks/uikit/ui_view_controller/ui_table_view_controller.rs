@@ -0,0 +1,43 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `UITableViewController`.
+
+use crate::frameworks::core_graphics::CGRect;
+use crate::frameworks::foundation::NSInteger;
+use crate::objc::{id, msg, msg_class, objc_classes, release, ClassExports};
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+@implementation UITableViewController: UIViewController
+
+- (id)initWithStyle:(NSInteger)_style {
+    // TODO: use the style for the table view
+    msg![env; this init]
+}
+
+- (())loadView {
+    let screen: id = msg_class![env; UIScreen mainScreen];
+    let frame: CGRect = msg![env; screen applicationFrame];
+    let table_view: id = msg_class![env; UITableView alloc];
+    let table_view: id = msg![env; table_view initWithFrame:frame];
+    () = msg![env; table_view setDataSource:this];
+    () = msg![env; table_view setDelegate:this];
+    () = msg![env; this setView:table_view];
+    release(env, table_view);
+}
+
+- (id)tableView {
+    msg![env; this view]
+}
+- (())setTableView:(id)table_view { // UITableView*
+    () = msg![env; this setView:table_view];
+}
+
+@end
+
+};
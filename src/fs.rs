@@ -395,6 +395,13 @@ impl GuestFile {
             GuestFile::Directory => panic!("Attempt to resize a directory as a guest file"),
         }
     }
+    /// Get the metadata of the host file, if this is a host file.
+    pub fn host_metadata(&self) -> Option<fs::Metadata> {
+        match self {
+            GuestFile::File(file) => file.metadata().ok(),
+            _ => None,
+        }
+    }
 }
 
 impl Read for GuestFile {
@@ -679,6 +686,37 @@ impl Fs {
         matches!(self.lookup_node(path), Some(FsNode::Directory { .. }))
     }
 
+    /// Get the metadata of the host file or directory backing a file or
+    /// directory in the guest filesystem, if there is one. Files and
+    /// directories in the app bundle or bundled with touchHLE may not have one.
+    pub fn host_metadata(&self, path: &GuestPath) -> Option<fs::Metadata> {
+        match self.lookup_node(path)? {
+            FsNode::File {
+                location: FileLocation::Path(host_path),
+                ..
+            } => fs::metadata(host_path).ok(),
+            FsNode::Directory {
+                writeable: Some(host_path),
+                ..
+            } => fs::metadata(host_path).ok(),
+            _ => None,
+        }
+    }
+
+    /// Get the path of the host file backing a file in the guest filesystem,
+    /// if there is one, and whether it is writeable. This is for host
+    /// libraries that need to open files themselves (e.g. SQLite). Files in
+    /// the app bundle or bundled with touchHLE may not have one.
+    pub fn host_file_path(&self, path: &GuestPath) -> Option<(&Path, bool)> {
+        match self.lookup_node(path)? {
+            FsNode::File {
+                location: FileLocation::Path(host_path),
+                writeable,
+            } => Some((host_path, *writeable)),
+            _ => None,
+        }
+    }
+
     /// Get an iterator over the names of files/directories in a directory.
     pub fn enumerate<P: AsRef<GuestPath>>(
         &self,
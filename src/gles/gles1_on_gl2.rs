@@ -27,7 +27,7 @@ use super::util::{
 };
 use super::GLES;
 use crate::window::{GLContext, GLVersion, Window};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::ffi::CStr;
 
 /// List of capabilities shared by OpenGL ES 1.1 and OpenGL 2.1.
@@ -96,7 +96,8 @@ struct ArrayStateBackup {
 
 /// List of arrays shared by OpenGL ES 1.1 and OpenGL 2.1.
 ///
-/// TODO: GL_POINT_SIZE_ARRAY_OES?
+/// `GL_POINT_SIZE_ARRAY_OES` is not in this list because OpenGL 2.1 doesn't
+/// have it, see [PointSizeArray].
 pub const ARRAYS: &[ArrayInfo] = &[
     ArrayInfo {
         name: gl21::COLOR_ARRAY,
@@ -217,7 +218,7 @@ const GET_PARAMS: ParamTable = ParamTable(&[
     (gl21::POINT_DISTANCE_ATTENUATION, ParamType::Float, 3),
     (gl21::POINT_FADE_THRESHOLD_SIZE, ParamType::Float, 1),
     (gl21::POINT_SIZE, ParamType::Float, 1),
-    // TODO: POINT_SIZE_ARRAY_OES etc? (not shared)
+    // POINT_SIZE_ARRAY_OES etc are not shared, see PointSizeArray.
     (gl21::POINT_SIZE_MAX, ParamType::Float, 1),
     (gl21::POINT_SIZE_MIN, ParamType::Float, 1),
     (gl21::POINT_SIZE_RANGE, ParamType::Float, 2),
@@ -366,13 +367,170 @@ const TEX_PARAMS: ParamTable = ParamTable(&[
     (gl21::MAX_TEXTURE_MAX_ANISOTROPY_EXT, ParamType::Float, 1),
 ]);
 
+/// State of `GL_OES_point_size_array`, which OpenGL 2.1 doesn't have. When the
+/// array is enabled, points are drawn in batches of the same size with
+/// `glPointSize()`. Point size attenuation still applies to this, like it would
+/// on a real device.
+struct PointSizeArray {
+    enabled: bool,
+    type_: GLenum,
+    stride: GLsizei,
+    /// Pointer, or offset if `buffer_binding` is not zero.
+    pointer: *const GLvoid,
+    buffer_binding: GLuint,
+}
+impl Default for PointSizeArray {
+    fn default() -> Self {
+        PointSizeArray {
+            enabled: false,
+            type_: gl21::FLOAT,
+            stride: 0,
+            pointer: std::ptr::null(),
+            buffer_binding: 0,
+        }
+    }
+}
+
 pub struct GLES1OnGL2 {
     gl_ctx: GLContext,
     pointer_is_fixed_point: [bool; ARRAYS.len()],
     fixed_point_texture_units: HashSet<GLenum>,
     fixed_point_translation_buffers: [Vec<GLfloat>; ARRAYS.len()],
+    point_size_array: PointSizeArray,
 }
 impl GLES1OnGL2 {
+    /// Draw points that have sizes from [PointSizeArray], given the index of
+    /// each point in the arrays.
+    unsafe fn draw_points_with_size_array(&mut self, indices: &[GLint]) {
+        let PointSizeArray {
+            type_,
+            stride,
+            pointer,
+            buffer_binding,
+            ..
+        } = self.point_size_array;
+        // Both GL_FLOAT and GL_FIXED are four bytes.
+        let stride = if stride == 0 { 4 } else { stride as usize };
+
+        // The sizes are read before drawing anything, because the buffer can't
+        // be used for drawing while it's mapped.
+        let mut old_array_buffer = 0;
+        let base: *const u8 = if buffer_binding != 0 {
+            gl21::GetIntegerv(gl21::ARRAY_BUFFER_BINDING, &mut old_array_buffer);
+            gl21::BindBuffer(gl21::ARRAY_BUFFER, buffer_binding);
+            let mapped: *const u8 = gl21::MapBuffer(gl21::ARRAY_BUFFER, gl21::READ_ONLY).cast();
+            assert!(!mapped.is_null());
+            mapped.add(pointer as usize)
+        } else {
+            pointer.cast()
+        };
+        let sizes: Vec<GLfloat> = indices
+            .iter()
+            .map(|&index| {
+                let size_ptr = base.add(index as usize * stride);
+                if type_ == gles11::FIXED {
+                    fixed_to_float(size_ptr.cast::<GLfixed>().read_unaligned())
+                } else {
+                    size_ptr.cast::<GLfloat>().read_unaligned()
+                }
+            })
+            .collect();
+        if buffer_binding != 0 {
+            gl21::UnmapBuffer(gl21::ARRAY_BUFFER);
+            gl21::BindBuffer(gl21::ARRAY_BUFFER, old_array_buffer as _);
+        }
+
+        // Drawing one point at a time would mean thousands of draw calls per
+        // frame in particle-heavy scenes, so points with the same size are
+        // drawn together. This can change the order points are drawn in, which
+        // matters where they overlap, unless they're blended additively and
+        // don't write depth (typical for particles). Otherwise, only
+        // consecutive points with the same size are drawn together.
+        let mut blend_enabled = gl21::FALSE;
+        gl21::GetBooleanv(gl21::BLEND, &mut blend_enabled);
+        let mut blend_dst = 0;
+        gl21::GetIntegerv(gl21::BLEND_DST, &mut blend_dst);
+        let mut depth_writemask = gl21::FALSE;
+        gl21::GetBooleanv(gl21::DEPTH_WRITEMASK, &mut depth_writemask);
+        let order_independent = blend_enabled != gl21::FALSE
+            && blend_dst as GLenum == gl21::ONE
+            && depth_writemask == gl21::FALSE;
+
+        let mut batches: Vec<(GLfloat, Vec<GLuint>)> = Vec::new();
+        let mut batch_for_size: HashMap<u32, usize> = HashMap::new();
+        for (&index, &size) in indices.iter().zip(sizes.iter()) {
+            // Sizes that aren't positive are an error for glPointSize(), but
+            // are presumably clamped when they come from the array.
+            let size = size.max(f32::MIN_POSITIVE);
+            let batch_idx = if order_independent {
+                *batch_for_size.entry(size.to_bits()).or_insert_with(|| {
+                    batches.push((size, Vec::new()));
+                    batches.len() - 1
+                })
+            } else {
+                match batches.last() {
+                    Some(&(last_size, _)) if last_size == size => batches.len() - 1,
+                    _ => {
+                        batches.push((size, Vec::new()));
+                        batches.len() - 1
+                    }
+                }
+            };
+            batches[batch_idx].1.push(index as GLuint);
+        }
+
+        let mut old_point_size = 0.0;
+        gl21::GetFloatv(gl21::POINT_SIZE, &mut old_point_size);
+        // The batches' indices are in host memory.
+        let mut old_element_array_buffer = 0;
+        gl21::GetIntegerv(
+            gl21::ELEMENT_ARRAY_BUFFER_BINDING,
+            &mut old_element_array_buffer,
+        );
+        gl21::BindBuffer(gl21::ELEMENT_ARRAY_BUFFER, 0);
+        for (size, batch_indices) in batches {
+            gl21::PointSize(size);
+            gl21::DrawElements(
+                gl21::POINTS,
+                batch_indices.len() as GLsizei,
+                gl21::UNSIGNED_INT,
+                batch_indices.as_ptr().cast(),
+            );
+        }
+        gl21::BindBuffer(gl21::ELEMENT_ARRAY_BUFFER, old_element_array_buffer as _);
+        gl21::PointSize(old_point_size);
+    }
+
+    /// Read the indices for a `glDrawElements()` call, which might be in a
+    /// buffer object.
+    unsafe fn read_indices(count: GLsizei, type_: GLenum, indices: *const GLvoid) -> Vec<GLint> {
+        let mut index_buffer_binding = 0;
+        gl21::GetIntegerv(
+            gl21::ELEMENT_ARRAY_BUFFER_BINDING,
+            &mut index_buffer_binding,
+        );
+        // If a buffer is bound, the pointer is an offset into it.
+        let base: *const GLvoid = if index_buffer_binding != 0 {
+            let mapped: *const u8 =
+                gl21::MapBuffer(gl21::ELEMENT_ARRAY_BUFFER, gl21::READ_ONLY).cast();
+            assert!(!mapped.is_null());
+            mapped.add(indices as usize).cast()
+        } else {
+            indices
+        };
+        let result = (0..(count as usize))
+            .map(|i| match type_ {
+                gl21::UNSIGNED_BYTE => base.cast::<GLubyte>().add(i).read_unaligned() as _,
+                gl21::UNSIGNED_SHORT => base.cast::<GLushort>().add(i).read_unaligned() as _,
+                _ => unreachable!(),
+            })
+            .collect();
+        if index_buffer_binding != 0 {
+            gl21::UnmapBuffer(gl21::ELEMENT_ARRAY_BUFFER);
+        }
+        result
+    }
+
     /// If any arrays with fixed-point data are in use at the time of a draw
     /// call, this function will convert the data to floating-point and
     /// replace the pointers. [Self::restore_fixed_point_arrays] can be called
@@ -560,6 +718,7 @@ impl GLES for GLES1OnGL2 {
             pointer_is_fixed_point: [false; ARRAYS.len()],
             fixed_point_texture_units: HashSet::new(),
             fixed_point_translation_buffers: [Vec::new(), Vec::new(), Vec::new(), Vec::new()],
+            point_size_array: Default::default(),
         })
     }
 
@@ -596,6 +755,9 @@ impl GLES for GLES1OnGL2 {
         gl21::Enable(cap);
     }
     unsafe fn IsEnabled(&mut self, cap: GLenum) -> GLboolean {
+        if cap == gles11::POINT_SIZE_ARRAY_OES {
+            return self.point_size_array.enabled as _;
+        }
         assert!(
             CAPABILITIES.contains(&cap) || ARRAYS.iter().any(|&ArrayInfo { name, .. }| name == cap)
         );
@@ -615,14 +777,26 @@ impl GLES for GLES1OnGL2 {
         gl21::ClientActiveTexture(texture);
     }
     unsafe fn EnableClientState(&mut self, array: GLenum) {
+        if array == gles11::POINT_SIZE_ARRAY_OES {
+            self.point_size_array.enabled = true;
+            return;
+        }
         assert!(ARRAYS.iter().any(|&ArrayInfo { name, .. }| name == array));
         gl21::EnableClientState(array);
     }
     unsafe fn DisableClientState(&mut self, array: GLenum) {
+        if array == gles11::POINT_SIZE_ARRAY_OES {
+            self.point_size_array.enabled = false;
+            return;
+        }
         assert!(ARRAYS.iter().any(|&ArrayInfo { name, .. }| name == array));
         gl21::DisableClientState(array);
     }
     unsafe fn GetBooleanv(&mut self, pname: GLenum, params: *mut GLboolean) {
+        if pname == gles11::POINT_SIZE_ARRAY_OES {
+            params.write(self.point_size_array.enabled as _);
+            return;
+        }
         let (type_, _count) = GET_PARAMS.get_type_info(pname);
         // TODO: type conversion
         assert!(type_ == ParamType::Boolean);
@@ -636,24 +810,55 @@ impl GLES for GLES1OnGL2 {
         gl21::GetFloatv(pname, params);
     }
     unsafe fn GetIntegerv(&mut self, pname: GLenum, params: *mut GLint) {
+        match pname {
+            gles11::POINT_SIZE_ARRAY_OES => {
+                params.write(self.point_size_array.enabled as _);
+                return;
+            }
+            gles11::POINT_SIZE_ARRAY_TYPE_OES => {
+                params.write(self.point_size_array.type_ as _);
+                return;
+            }
+            gles11::POINT_SIZE_ARRAY_STRIDE_OES => {
+                params.write(self.point_size_array.stride);
+                return;
+            }
+            gles11::POINT_SIZE_ARRAY_BUFFER_BINDING_OES => {
+                params.write(self.point_size_array.buffer_binding as _);
+                return;
+            }
+            _ => (),
+        }
         let (type_, _count) = GET_PARAMS.get_type_info(pname);
         // TODO: type conversion
         assert!(type_ == ParamType::Int);
         gl21::GetIntegerv(pname, params);
     }
     unsafe fn GetTexEnviv(&mut self, target: GLenum, pname: GLenum, params: *mut GLint) {
-        let (type_, _count) = TEX_ENV_PARAMS.get_type_info(pname);
-        assert!(type_ == ParamType::Int);
-        assert_eq!(target, gl21::TEXTURE_ENV);
+        if target == gl21::POINT_SPRITE {
+            assert!(pname == gl21::COORD_REPLACE);
+        } else {
+            let (type_, _count) = TEX_ENV_PARAMS.get_type_info(pname);
+            assert!(type_ == ParamType::Int);
+            assert_eq!(target, gl21::TEXTURE_ENV);
+        }
         gl21::GetTexEnviv(target, pname, params);
     }
     unsafe fn GetTexEnvfv(&mut self, target: GLenum, pname: GLenum, params: *mut GLfloat) {
-        let (type_, _count) = TEX_ENV_PARAMS.get_type_info(pname);
-        assert!(type_ == ParamType::Float);
-        assert_eq!(target, gl21::TEXTURE_ENV);
+        if target == gl21::POINT_SPRITE {
+            assert!(pname == gl21::COORD_REPLACE);
+        } else {
+            let (type_, _count) = TEX_ENV_PARAMS.get_type_info(pname);
+            assert!(type_ == ParamType::Float);
+            assert_eq!(target, gl21::TEXTURE_ENV);
+        }
         gl21::GetTexEnvfv(target, pname, params);
     }
     unsafe fn GetPointerv(&mut self, pname: GLenum, params: *mut *const GLvoid) {
+        if pname == gles11::POINT_SIZE_ARRAY_POINTER_OES {
+            params.write(self.point_size_array.pointer);
+            return;
+        }
         assert!(ARRAYS
             .iter()
             .any(|&ArrayInfo { pointer, .. }| pname == pointer));
@@ -1094,6 +1299,24 @@ impl GLES for GLES1OnGL2 {
             gl21::VertexPointer(size, type_, stride, pointer)
         }
     }
+    unsafe fn PointSizePointerOES(
+        &mut self,
+        type_: GLenum,
+        stride: GLsizei,
+        pointer: *const GLvoid,
+    ) {
+        assert!(type_ == gles11::FIXED || type_ == gl21::FLOAT);
+        assert!(stride >= 0);
+        let mut buffer_binding = 0;
+        gl21::GetIntegerv(gl21::ARRAY_BUFFER_BINDING, &mut buffer_binding);
+        self.point_size_array = PointSizeArray {
+            enabled: self.point_size_array.enabled,
+            type_,
+            stride,
+            pointer,
+            buffer_binding: buffer_binding as _,
+        };
+    }
 
     // Drawing
     unsafe fn DrawArrays(&mut self, mode: GLenum, first: GLint, count: GLsizei) {
@@ -1110,7 +1333,12 @@ impl GLES for GLES1OnGL2 {
 
         let fixed_point_arrays_state_backup = self.translate_fixed_point_arrays(first, count);
 
-        gl21::DrawArrays(mode, first, count);
+        if mode == gl21::POINTS && self.point_size_array.enabled {
+            let indices: Vec<GLint> = (first..(first + count)).collect();
+            self.draw_points_with_size_array(&indices);
+        } else {
+            gl21::DrawArrays(mode, first, count);
+        }
 
         self.restore_fixed_point_arrays(fixed_point_arrays_state_backup);
     }
@@ -1186,7 +1414,12 @@ impl GLES for GLES1OnGL2 {
                 None
             };
 
-        gl21::DrawElements(mode, count, type_, indices);
+        if mode == gl21::POINTS && self.point_size_array.enabled {
+            let indices = Self::read_indices(count, type_, indices);
+            self.draw_points_with_size_array(&indices);
+        } else {
+            gl21::DrawElements(mode, count, type_, indices);
+        }
 
         if let Some(fixed_point_arrays_state_backup) = fixed_point_arrays_state_backup {
             self.restore_fixed_point_arrays(fixed_point_arrays_state_backup);
@@ -8,6 +8,7 @@
 
 use super::gles11_raw as gles11; // constants and types only
 use super::GLES;
+use crate::environment::SpeedIndicator;
 use crate::matrix::Matrix;
 use std::time::{Duration, Instant};
 
@@ -41,7 +42,11 @@ impl FpsCounter {
 /// Present the the latest frame (e.g. the app's splash screen or rendering
 /// output), provided as a texture bound to `GL_TEXTURE_2D`, by drawing it on
 /// the window. It may be rotated, scaled and/or letterboxed as necessary. The
-/// virtual cursor is also drawn if it should be currently visible.
+/// virtual cursor and touch indicators (see
+/// [crate::window::Window::touch_indicators_visible_at]) are also drawn if they
+/// should be currently visible, and so are the speed indicator (see
+/// [crate::environment::Clock::speed_indicator]) and the volume indicator (see
+/// [crate::frameworks::audio_toolbox::State::volume_indicator]).
 ///
 /// The provided context must be current.
 pub unsafe fn present_frame(
@@ -49,6 +54,9 @@ pub unsafe fn present_frame(
     viewport: (u32, u32, u32, u32),
     rotation_matrix: Matrix<2>,
     virtual_cursor_visible_at: Option<(f32, f32, bool)>,
+    touch_indicators_visible_at: &[(f32, f32, f32, bool)],
+    speed_indicator: Option<SpeedIndicator>,
+    volume_indicator: Option<f32>,
 ) {
     // While this is a generic utility, it is closely tied to
     // crate::frameworks::opengles::eagl::present_renderbuffer, which handles
@@ -83,27 +91,142 @@ pub unsafe fn present_frame(
     // clean this up so we don't need to worry about it in e.g. Core Animation
     gles.LoadIdentity();
 
+    if virtual_cursor_visible_at.is_none()
+        && touch_indicators_visible_at.is_empty()
+        && speed_indicator.is_none()
+        && volume_indicator.is_none()
+    {
+        return;
+    }
+
+    gles.DisableClientState(gles11::TEXTURE_COORD_ARRAY);
+    gles.Disable(gles11::TEXTURE_2D);
+
+    gles.Enable(gles11::BLEND);
+    gles.BlendFunc(gles11::ONE, gles11::ONE_MINUS_SRC_ALPHA);
+
+    // Draws triangles given as (x, y) pairs in window co-ordinates. The color
+    // is premultiplied.
+    let mut draw_triangles = |points: &[(f32, f32)], color: f32, alpha: f32| {
+        let (vx, vy, vw, vh) = viewport;
+
+        gles.Color4f(color, color, color, alpha);
+
+        let vertices: Vec<f32> = points
+            .iter()
+            .flat_map(|&(x, y)| {
+                let x = x - vx as f32;
+                let y = y - vy as f32;
+                [x / (vw as f32 / 2.0) - 1.0, 1.0 - y / (vh as f32 / 2.0)]
+            })
+            .collect();
+        gles.VertexPointer(2, gles11::FLOAT, 0, vertices.as_ptr() as *const GLvoid);
+        gles.DrawArrays(gles11::TRIANGLES, 0, points.len() as _);
+    };
+    // Draws a rectangle with the given top-left and bottom-right corners.
+    let rect = |(x1, y1): (f32, f32), (x2, y2): (f32, f32)| {
+        [(x1, y1), (x1, y2), (x2, y1), (x2, y1), (x1, y2), (x2, y2)]
+    };
+    // Draws a square centered on a point in window co-ordinates.
+    let mut draw_square = |x: f32, y: f32, radius: f32, color: f32, alpha: f32| {
+        draw_triangles(
+            &rect((x - radius, y - radius), (x + radius, y + radius)),
+            color,
+            alpha,
+        );
+    };
+
+    // Display touch indicators
+    for &(x, y, radius, pressed) in touch_indicators_visible_at {
+        let alpha = if pressed { 1.0 / 2.0 } else { 1.0 / 4.0 };
+        draw_square(x, y, radius, alpha, alpha);
+    }
+
     // Display virtual cursor
     if let Some((x, y, pressed)) = virtual_cursor_visible_at {
-        let (vx, vy, vw, vh) = viewport;
-        let x = x - vx as f32;
-        let y = y - vy as f32;
+        let alpha = if pressed { 2.0 / 3.0 } else { 1.0 / 3.0 };
+        draw_square(x, y, 10.0, 0.0, alpha);
+    }
+
+    // Display speed indicator in the top-right corner
+    if let Some(speed_indicator) = speed_indicator {
+        let (vx, vy, vw, _vh) = viewport;
+        let size = 24.0;
+        let x = (vx + vw) as f32 - size * 2.0;
+        let y = vy as f32 + size;
+
+        // Backdrop so it's visible on any background
+        draw_square(x + size / 2.0, y + size / 2.0, size * 0.75, 0.0, 0.5);
+
+        let play_triangle =
+            |x: f32, width: f32| [(x, y), (x, y + size), (x + width, y + size / 2.0)];
+        match speed_indicator {
+            SpeedIndicator::Paused => {
+                let bar = size / 3.0;
+                draw_triangles(&rect((x, y), (x + bar, y + size)), 1.0, 1.0);
+                draw_triangles(&rect((x + size - bar, y), (x + size, y + size)), 1.0, 1.0);
+            }
+            SpeedIndicator::Normal => {
+                draw_triangles(&play_triangle(x, size), 1.0, 1.0);
+            }
+            SpeedIndicator::FastForward(speed) => {
+                let half = size / 2.0;
+                if speed > 1.0 {
+                    draw_triangles(&play_triangle(x, half), 1.0, 1.0);
+                    draw_triangles(&play_triangle(x + half, half), 1.0, 1.0);
+                } else {
+                    // Slow motion: a single narrow triangle
+                    draw_triangles(&play_triangle(x + half / 2.0, half), 1.0, 1.0);
+                }
+            }
+        }
+    }
 
-        gles.DisableClientState(gles11::TEXTURE_COORD_ARRAY);
-        gles.Disable(gles11::TEXTURE_2D);
+    // Display volume indicator in the middle, like the iPhone OS volume HUD: a
+    // speaker and a row of 16 segments for the volume steps.
+    if let Some(volume) = volume_indicator {
+        let (vx, vy, vw, vh) = viewport;
+        let size = vw.min(vh) as f32 / 2.0;
+        let x = vx as f32 + (vw as f32 - size) / 2.0;
+        let y = vy as f32 + (vh as f32 - size) / 2.0;
 
-        gles.Enable(gles11::BLEND);
-        gles.BlendFunc(gles11::ONE, gles11::ONE_MINUS_SRC_ALPHA);
-        gles.Color4f(0.0, 0.0, 0.0, if pressed { 2.0 / 3.0 } else { 1.0 / 3.0 });
+        draw_triangles(&rect((x, y), (x + size, y + size)), 0.0, 0.5);
 
-        let radius = 10.0;
+        let unit = size / 16.0;
+        let speaker_x = x + unit * 6.0;
+        let speaker_y = y + unit * 3.0;
+        draw_triangles(
+            &rect(
+                (speaker_x, speaker_y + unit * 2.0),
+                (speaker_x + unit * 1.5, speaker_y + unit * 5.0),
+            ),
+            1.0,
+            1.0,
+        );
+        draw_triangles(
+            &[
+                (speaker_x + unit * 1.5, speaker_y + unit * 2.0),
+                (speaker_x + unit * 1.5, speaker_y + unit * 5.0),
+                (speaker_x + unit * 4.0, speaker_y),
+                (speaker_x + unit * 4.0, speaker_y),
+                (speaker_x + unit * 1.5, speaker_y + unit * 5.0),
+                (speaker_x + unit * 4.0, speaker_y + unit * 7.0),
+            ],
+            1.0,
+            1.0,
+        );
 
-        let mut vertices = vertices;
-        for i in (0..vertices.len()).step_by(2) {
-            vertices[i] = (vertices[i] * radius + x) / (vw as f32 / 2.0) - 1.0;
-            vertices[i + 1] = 1.0 - (vertices[i + 1] * radius + y) / (vh as f32 / 2.0);
+        let steps = (volume * 16.0).round() as u32;
+        let bar_y = y + unit * 12.0;
+        for step in 0..16 {
+            let step_x = x + unit * 2.125 + unit * 0.75 * step as f32;
+            // Dim segments for the steps above the current volume.
+            let alpha = if step < steps { 1.0 } else { 0.25 };
+            draw_triangles(
+                &rect((step_x, bar_y), (step_x + unit * 0.5, bar_y + unit)),
+                alpha,
+                alpha,
+            );
         }
-        gles.VertexPointer(2, gles11::FLOAT, 0, vertices.as_ptr() as *const GLvoid);
-        gles.DrawArrays(gles11::TRIANGLES, 0, 6);
     }
 }
@@ -0,0 +1,171 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Minimal HTTP client, for the app-facing networking APIs.
+//!
+//! Requests are made on a background thread, so that the app doesn't freeze
+//! while waiting for the server. Only plain `http:` URLs are supported, since
+//! touchHLE has no TLS implementation. Requests are made with HTTP/1.0, so
+//! that the server won't use chunked encoding or keep the connection open.
+//!
+//! Callers are responsible for checking `--allow-network-access` first.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::Duration;
+
+/// How long to wait for the server before giving up.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Response {
+    pub status_code: u16,
+    pub status_line: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// A request that is in progress on a background thread.
+pub struct PendingRequest {
+    receiver: Receiver<io::Result<Response>>,
+}
+
+impl PendingRequest {
+    pub fn start(request: Request) -> PendingRequest {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let _ = sender.send(perform(&request));
+        });
+        PendingRequest { receiver }
+    }
+
+    /// Check whether the request has finished, without blocking.
+    pub fn poll(&self) -> Option<io::Result<Response>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(io::Error::new(
+                io::ErrorKind::Other,
+                "HTTP request thread disappeared",
+            ))),
+        }
+    }
+}
+
+/// Split an `http:` URL into host, port and path.
+fn parse_url(url: &str) -> io::Result<(String, u16, String)> {
+    let unsupported = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unsupported URL {:?}", url),
+        )
+    };
+    let rest = url
+        .strip_prefix("http://")
+        .or_else(|| url.strip_prefix("HTTP://"))
+        .ok_or_else(unsupported)?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    // Credentials in URLs aren't supported.
+    if authority.is_empty() || authority.contains('@') {
+        return Err(unsupported());
+    }
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().map_err(|_| unsupported())?),
+        None => (authority, 80),
+    };
+    Ok((host.to_string(), port, path.to_string()))
+}
+
+fn perform(request: &Request) -> io::Result<Response> {
+    let (host, port, path) = parse_url(&request.url)?;
+    let mut stream = TcpStream::connect((host.as_str(), port))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut head = format!("{} {} HTTP/1.0\r\n", request.method, path);
+    let has_header = |name: &str| -> bool {
+        request
+            .headers
+            .iter()
+            .any(|(n, _)| n.eq_ignore_ascii_case(name))
+    };
+    if !has_header("Host") {
+        if port == 80 {
+            head.push_str(&format!("Host: {}\r\n", host));
+        } else {
+            head.push_str(&format!("Host: {}:{}\r\n", host, port));
+        }
+    }
+    if !request.body.is_empty() && !has_header("Content-Length") {
+        head.push_str(&format!("Content-Length: {}\r\n", request.body.len()));
+    }
+    for (name, value) in &request.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(&request.body)?;
+    stream.flush()?;
+
+    let mut reader = BufReader::new(stream);
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line)?;
+    let status_line = status_line.trim_end().to_string();
+    let status_code = status_line
+        .split(' ')
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid HTTP status line {:?}", status_line),
+            )
+        })?;
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    let mut body = Vec::new();
+    reader.read_to_end(&mut body)?;
+
+    log_dbg!(
+        "HTTP {} {} => {:?}, {} bytes",
+        request.method,
+        request.url,
+        status_line,
+        body.len()
+    );
+
+    Ok(Response {
+        status_code,
+        status_line,
+        headers,
+        body,
+    })
+}
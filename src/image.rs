@@ -98,6 +98,37 @@ impl Image {
         self.dimensions
     }
 
+    /// Make a new image by copying a rectangular region of this one. The
+    /// region must be within the image's bounds. 0 on the y axis is the top of
+    /// the image.
+    pub fn crop(&self, origin: (u32, u32), size: (u32, u32)) -> Image {
+        let (x, y) = (origin.0 as usize, origin.1 as usize);
+        let (width, height) = (size.0 as usize, size.1 as usize);
+        let src_width = self.dimensions.0 as usize;
+        assert!(x + width <= src_width && y + height <= self.dimensions.1 as usize);
+        let src_pixels = self.pixels();
+        let mut pixels = Vec::with_capacity(width * height * 4);
+        for row in y..(y + height) {
+            pixels.extend_from_slice(&src_pixels[(row * src_width + x) * 4..][..width * 4]);
+        }
+        Image::from_pixel_vec(pixels, size)
+    }
+
+    /// Encode the image as a PNG file. PNG has no way to represent
+    /// premultiplied alpha, so the pixels are un-premultiplied first.
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut pixels = self.pixels().to_vec();
+        for rgba in pixels.chunks_mut(4) {
+            let a = rgba[3];
+            if a != 0 && a != 255 {
+                for c in &mut rgba[..3] {
+                    *c = ((*c as u32 * 255 + a as u32 / 2) / a as u32).min(255) as u8;
+                }
+            }
+        }
+        encode_png(&pixels, self.dimensions.0, self.dimensions.1, true)
+    }
+
     /// Get image data as bytes (8 bits per channel sRGB RGBA with premultiplied
     /// alpha). Rows are in top-to-bottom order.
     pub fn pixels(&self) -> &[u8] {
@@ -185,6 +216,57 @@ impl Drop for Image {
     }
 }
 
+/// Encode RGB8 or RGBA8 pixels (top row first, straight alpha) as a PNG file.
+pub fn encode_png(pixels: &[u8], width: u32, height: u32, has_alpha: bool) -> Vec<u8> {
+    use flate2::write::ZlibEncoder;
+    use flate2::{Compression, Crc};
+    use std::io::Write;
+
+    fn write_png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+        png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        png.extend_from_slice(kind);
+        png.extend_from_slice(data);
+        let mut crc = Crc::new();
+        crc.update(kind);
+        crc.update(data);
+        png.extend_from_slice(&crc.sum().to_be_bytes());
+    }
+
+    let (color_type, channels) = if has_alpha { (6, 4) } else { (2, 3) };
+
+    let mut png = Vec::new();
+    png.extend_from_slice(b"\x89PNG\r\n\x1a\n");
+
+    let mut ihdr = Vec::new();
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, RGB(A), default compression, filtering and no
+    // interlace
+    ihdr.extend_from_slice(&[8, color_type, 0, 0, 0]);
+    write_png_chunk(&mut png, b"IHDR", &ihdr);
+
+    // Every row starts with the filter type. The Sub filter (1) is cheap and
+    // helps a lot with flat areas of color.
+    let row_size = width as usize * channels;
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+    let mut filtered_row = Vec::with_capacity(1 + row_size);
+    if row_size != 0 {
+        for row in pixels.chunks(row_size).take(height as usize) {
+            filtered_row.clear();
+            filtered_row.push(1);
+            filtered_row.extend_from_slice(&row[..channels]);
+            for i in channels..row_size {
+                filtered_row.push(row[i].wrapping_sub(row[i - channels]));
+            }
+            encoder.write_all(&filtered_row).unwrap();
+        }
+    }
+    write_png_chunk(&mut png, b"IDAT", &encoder.finish().unwrap());
+
+    write_png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
 /// Approximate implementation of sRGB gamma encoding.
 pub fn gamma_encode(intensity: f32) -> f32 {
     // TODO: This doesn't implement the linear section near zero.
@@ -30,8 +30,10 @@ mod log;
 mod abi;
 mod app_picker;
 mod audio;
+mod automation;
 mod bundle;
 mod cpu;
+mod crash_report;
 mod debug;
 mod dyld;
 mod environment;
@@ -40,6 +42,7 @@ mod frameworks;
 mod fs;
 mod gdb;
 mod gles;
+mod http;
 mod image;
 mod libc;
 mod licenses;
@@ -49,6 +52,8 @@ mod mem;
 mod objc;
 mod options;
 mod paths;
+mod profiler;
+mod recording;
 mod stack;
 mod window;
 
@@ -248,14 +253,10 @@ pub fn main<T: Iterator<Item = String>>(mut args: T) -> Result<(), String> {
     );
     echo!();
 
+    let parsed_minimum_os_version =
+        minimum_os_version.and_then(frameworks::uikit::ui_device::parse_os_version);
     if let Some(version) = minimum_os_version {
-        let (major, minor_etc) = version.split_once('.').unwrap();
-        let minor = minor_etc
-            .split_once('.')
-            .map_or(minor_etc, |(minor, _etc)| minor);
-        let major: u32 = major.parse().unwrap();
-        let minor: u32 = minor.parse().unwrap();
-        if major > 3 || (major == 3 && minor > 0) {
+        if parsed_minimum_os_version.map_or(true, |parsed| parsed >= (3, 1, 0)) {
             echo!("Warning: app requires OS version {}. Only iPhone OS 2.x and iPhone OS 3.0 apps are currently supported.", version);
         }
     }
@@ -264,6 +265,8 @@ pub fn main<T: Iterator<Item = String>>(mut args: T) -> Result<(), String> {
         return Ok(());
     }
 
+    app_picker::record_app_launch(app_id);
+
     let mut options = options::Options::default();
 
     // Apply options from files
@@ -321,7 +324,26 @@ pub fn main<T: Iterator<Item = String>>(mut args: T) -> Result<(), String> {
         assert!(parse_result == Ok(true));
     }
 
+    let os_version = frameworks::uikit::ui_device::parse_os_version(&options.os_version).unwrap();
+    if os_version < options.device.first_os_version() {
+        echo!(
+            "Warning: The {} can't run iPhone OS {}, but touchHLE will pretend it can.",
+            options.device.display_name(),
+            options.os_version
+        );
+    }
+    if let (Some(required), Some(minimum_os_version)) =
+        (parsed_minimum_os_version, minimum_os_version)
+    {
+        if required > os_version {
+            echo!(
+                "Warning: The app requires OS version {}, but touchHLE is pretending to be iPhone OS {}. The app might refuse to run or behave differently. Use --os-version= to change this.",
+                minimum_os_version,
+                options.os_version
+            );
+        }
+    }
+
     let mut env = Environment::new(bundle, fs, options, env_for_salvage)?;
-    env.run();
-    Ok(())
+    env.run()
 }
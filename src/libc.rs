@@ -11,6 +11,7 @@
 
 mod generic_char;
 
+pub mod arpa;
 pub mod clocale;
 pub mod ctype;
 pub mod cxxabi;
@@ -44,8 +45,11 @@ pub mod wchar;
 /// Container for state of various child modules
 #[derive(Default)]
 pub struct State {
+    arpa_inet: arpa::inet::State,
     dirent: dirent::State,
     keymgr: keymgr::State,
+    mmap: mmap::State,
+    netdb: netdb::State,
     posix_io: posix_io::State,
     pub pthread: pthread::State,
     pub semaphore: semaphore::State,
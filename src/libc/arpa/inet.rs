@@ -0,0 +1,89 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `arpa/inet.h`
+//!
+//! The byte order functions are usually macros, but they also exist as real
+//! functions that some apps call.
+
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::libc::sys::socket::in_addr_t;
+use crate::mem::{ConstPtr, MutPtr};
+use crate::Environment;
+use std::net::Ipv4Addr;
+
+pub const INADDR_NONE: in_addr_t = 0xffffffff;
+
+#[derive(Default)]
+pub struct State {
+    /// Static buffer for `inet_ntoa()`.
+    ntoa_buffer: Option<MutPtr<u8>>,
+}
+
+// The guest is little-endian, so these always swap.
+
+fn htonl(_env: &mut Environment, hostlong: u32) -> u32 {
+    hostlong.swap_bytes()
+}
+fn htons(_env: &mut Environment, hostshort: u16) -> u16 {
+    hostshort.swap_bytes()
+}
+fn ntohl(_env: &mut Environment, netlong: u32) -> u32 {
+    netlong.swap_bytes()
+}
+fn ntohs(_env: &mut Environment, netshort: u16) -> u16 {
+    netshort.swap_bytes()
+}
+
+/// Parse a dotted-decimal IPv4 address.
+/// TODO: support the other forms `inet_aton()` accepts (e.g. `127.1`).
+fn parse_address(env: &Environment, cp: ConstPtr<u8>) -> Option<Ipv4Addr> {
+    if cp.is_null() {
+        return None;
+    }
+    env.mem.cstr_at_utf8(cp).ok()?.parse().ok()
+}
+
+fn inet_addr(env: &mut Environment, cp: ConstPtr<u8>) -> in_addr_t {
+    let res = parse_address(env, cp).map_or(INADDR_NONE, |addr| u32::from(addr).swap_bytes());
+    log_dbg!("inet_addr({:?}) => {:#x}", cp, res);
+    res
+}
+
+fn inet_aton(env: &mut Environment, cp: ConstPtr<u8>, addr: MutPtr<in_addr_t>) -> i32 {
+    let Some(parsed) = parse_address(env, cp) else {
+        return 0;
+    };
+    if !addr.is_null() {
+        env.mem.write(addr, u32::from(parsed).swap_bytes());
+    }
+    1
+}
+
+fn inet_ntoa(env: &mut Environment, in_: in_addr_t) -> MutPtr<u8> {
+    // Long enough for "255.255.255.255\0".
+    const BUFFER_SIZE: u32 = 16;
+    let buffer = *env
+        .libc_state
+        .arpa_inet
+        .ntoa_buffer
+        .get_or_insert_with(|| env.mem.alloc(BUFFER_SIZE).cast());
+
+    let string = Ipv4Addr::from(in_.swap_bytes()).to_string();
+    let bytes = env.mem.bytes_at_mut(buffer, BUFFER_SIZE);
+    bytes[..string.len()].copy_from_slice(string.as_bytes());
+    bytes[string.len()] = b'\0';
+    buffer
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(htonl(_)),
+    export_c_func!(htons(_)),
+    export_c_func!(ntohl(_)),
+    export_c_func!(ntohs(_)),
+    export_c_func!(inet_addr(_)),
+    export_c_func!(inet_aton(_, _)),
+    export_c_func!(inet_ntoa(_)),
+];
@@ -7,23 +7,26 @@
 
 use crate::dyld::FunctionExports;
 use crate::fs::GuestPath;
-use crate::libc::errno::set_errno;
+use crate::libc::errno::{set_errno, EBADF, ENOENT, ENOTDIR};
 use crate::mem::{ConstPtr, MutPtr, Ptr, SafeRead};
-use crate::{export_c_func, impl_GuestRet_for_large_struct, Environment};
+use crate::{export_c_func, Environment};
 use std::collections::HashMap;
 
-/// This is an opaque struct and doesn't necessary
-/// corresponds the Apple's one
-/// TODO: match struct sizes
+/// Opaque type in guest memory standing in for [DirStream] in host memory.
 #[allow(clippy::upper_case_acronyms)]
 struct DIR {
-    idx: usize,
+    _filler: u8,
 }
 unsafe impl SafeRead for DIR {}
 
-// While early iOS is 32-bit system, underling file system uses 64-bit inodes!
+const DT_DIR: u8 = 4;
+const DT_REG: u8 = 8;
+
+/// Size of `d_name`. iPhone OS only has the 64-bit inode variant of
+/// `struct dirent`, which uses this rather than `__DARWIN_MAXNAMLEN + 1`.
 const MAXPATHLEN: usize = 1024;
 
+/// While early iOS is 32-bit system, underling file system uses 64-bit inodes!
 #[allow(non_camel_case_types)]
 #[derive(Debug)]
 #[repr(C, packed)]
@@ -36,12 +39,25 @@ struct dirent {
     d_name: [u8; MAXPATHLEN],
 }
 unsafe impl SafeRead for dirent {}
-impl_GuestRet_for_large_struct!(dirent);
+
+struct DirEntry {
+    name: String,
+    d_type: u8,
+    d_ino: u64,
+}
+
+/// Host state for a `DIR*`.
+struct DirStream {
+    entries: Vec<DirEntry>,
+    idx: usize,
+    /// Buffer returned by `readdir()`, which is overwritten by each call on the
+    /// same stream.
+    dirent: MutPtr<dirent>,
+}
 
 #[derive(Default)]
 pub struct State {
-    open_dirs: HashMap<MutPtr<DIR>, Vec<String>>,
-    read_dirs: HashMap<MutPtr<DIR>, Vec<MutPtr<dirent>>>,
+    open_dirs: HashMap<MutPtr<DIR>, DirStream>,
 }
 impl State {
     fn get_mut(env: &mut Environment) -> &mut Self {
@@ -49,89 +65,178 @@ impl State {
     }
 }
 
-fn opendir(env: &mut Environment, filename: ConstPtr<u8>) -> MutPtr<DIR> {
-    // TODO: handle errno properly
-    set_errno(env, 0);
+/// Make up a stable, non-zero inode number for a path. Some apps skip entries
+/// with an inode number of zero, because they're deleted files.
+pub fn fake_inode_number(path: &str) -> u64 {
+    // FNV-1a
+    let hash = path.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    hash.max(1)
+}
 
+fn opendir(env: &mut Environment, filename: ConstPtr<u8>) -> MutPtr<DIR> {
     let path_string = env.mem.cstr_at_utf8(filename).unwrap().to_owned();
-    log_dbg!("opendir: filename {}", path_string);
     let guest_path = GuestPath::new(&path_string);
-    let is_dir = env.fs.is_dir(guest_path);
-    if is_dir {
-        let dir = env.mem.alloc_and_write(DIR { idx: 0 });
-        log_dbg!("opendir: new DIR ptr: {:?}", dir);
-        let iter = env.fs.enumerate(guest_path).unwrap();
-        let vec = iter.map(|str| str.to_string()).collect();
-        assert!(!State::get_mut(env).open_dirs.contains_key(&dir));
-        State::get_mut(env).open_dirs.insert(dir, vec);
-        assert!(!State::get_mut(env).read_dirs.contains_key(&dir));
-        State::get_mut(env).read_dirs.insert(dir, Vec::new());
-        dir
-    } else {
-        Ptr::null()
-    }
-}
+    let Ok(names) = env.fs.enumerate(guest_path) else {
+        log_dbg!("opendir({:?}) => NULL", path_string);
+        let errno = if env.fs.is_file(guest_path) {
+            ENOTDIR
+        } else {
+            ENOENT
+        };
+        set_errno(env, errno);
+        return Ptr::null();
+    };
 
-// TODO: return '.' and '..' entries as well
-fn readdir(env: &mut Environment, dirp: MutPtr<DIR>) -> MutPtr<dirent> {
-    // TODO: handle errno properly
-    set_errno(env, 0);
+    // HFS+ returns "." and ".." first, and then the other entries in order of
+    // their names, which some apps may rely on.
+    let mut names: Vec<String> = names.map(|name| name.to_string()).collect();
+    names.sort();
+    let mut entries = vec![
+        DirEntry {
+            name: ".".to_string(),
+            d_type: DT_DIR,
+            d_ino: fake_inode_number(&path_string),
+        },
+        DirEntry {
+            name: "..".to_string(),
+            d_type: DT_DIR,
+            d_ino: fake_inode_number(&format!("{}/..", path_string)),
+        },
+    ];
+    for name in names {
+        let child_path = guest_path.join(&name);
+        entries.push(DirEntry {
+            d_type: if env.fs.is_file(&child_path) {
+                DT_REG
+            } else {
+                DT_DIR
+            },
+            d_ino: fake_inode_number(child_path.as_str()),
+            name,
+        });
+    }
 
-    let mut dir = env.mem.read(dirp);
-    let vec = env.libc_state.dirent.open_dirs.get(&dirp).unwrap();
+    let dir = env.mem.alloc_and_write(DIR { _filler: 0 });
+    let dirent = env.mem.alloc(std::mem::size_of::<dirent>() as _).cast();
     log_dbg!(
-        "readdir: dirp {:?}, idx {}, entry '{:?}'",
-        dirp,
-        dir.idx,
-        vec.get(dir.idx)
+        "opendir({:?}) => {:?} ({} entries)",
+        path_string,
+        dir,
+        entries.len()
     );
-    if let Some(str) = vec.get(dir.idx) {
-        dir.idx += 1;
-        env.mem.write(dirp, dir);
-
-        let len = str.len();
-        // TODO: fill other fields
-        let mut dirent = dirent {
-            d_ino: 0,
-            d_seekoff: 0,
-            d_reclen: 0,
-            d_namlen: len as u16,
-            d_type: 0,
-            d_name: [b'\0'; MAXPATHLEN],
-        };
-        dirent.d_name[..len].copy_from_slice(str.as_bytes());
-        let res = env.mem.alloc_and_write(dirent);
-        env.libc_state
-            .dirent
-            .read_dirs
-            .get_mut(&dirp)
-            .unwrap()
-            .push(res);
-        res
-    } else {
-        Ptr::null()
+    State::get_mut(env).open_dirs.insert(
+        dir,
+        DirStream {
+            entries,
+            idx: 0,
+            dirent,
+        },
+    );
+    dir
+}
+
+/// Get the next entry from a directory stream as a `struct dirent`, or [None]
+/// at the end of the stream.
+fn next_entry(env: &mut Environment, dirp: MutPtr<DIR>) -> Result<Option<dirent>, i32> {
+    let Some(stream) = State::get_mut(env).open_dirs.get_mut(&dirp) else {
+        return Err(EBADF);
+    };
+    let Some(entry) = stream.entries.get(stream.idx) else {
+        return Ok(None);
+    };
+    stream.idx += 1;
+
+    let name = entry.name.as_bytes();
+    // Leave space for the null terminator.
+    let len = name.len().min(MAXPATHLEN - 1);
+    if len < name.len() {
+        log!(
+            "Warning: truncating directory entry name {:?} to {} bytes",
+            entry.name,
+            len
+        );
     }
+    let mut dirent = dirent {
+        d_ino: entry.d_ino,
+        d_seekoff: stream.idx as u64,
+        // The real record length depends on the name length.
+        d_reclen: ((21 + len + 1 + 3) & !3) as u16,
+        d_namlen: len as u16,
+        d_type: entry.d_type,
+        d_name: [b'\0'; MAXPATHLEN],
+    };
+    dirent.d_name[..len].copy_from_slice(&name[..len]);
+    Ok(Some(dirent))
 }
 
-fn closedir(env: &mut Environment, dirp: MutPtr<DIR>) -> i32 {
-    // TODO: handle errno properly
-    set_errno(env, 0);
+fn readdir(env: &mut Environment, dirp: MutPtr<DIR>) -> MutPtr<dirent> {
+    match next_entry(env, dirp) {
+        Ok(Some(dirent)) => {
+            let res = State::get_mut(env).open_dirs[&dirp].dirent;
+            log_dbg!(
+                "readdir({:?}) => {:?} ({:?})",
+                dirp,
+                res,
+                std::str::from_utf8(&dirent.d_name[..dirent.d_namlen as usize])
+            );
+            env.mem.write(res, dirent);
+            res
+        }
+        // Reaching the end of the stream doesn't change errno.
+        Ok(None) => {
+            log_dbg!("readdir({:?}) => NULL", dirp);
+            Ptr::null()
+        }
+        Err(errno) => {
+            set_errno(env, errno);
+            Ptr::null()
+        }
+    }
+}
 
-    log_dbg!("closedir: dirp {:?}", dirp);
-    if let Some(vec) = env.libc_state.dirent.read_dirs.remove(&dirp) {
-        for dirent in vec {
-            env.mem.free(dirent.cast());
+fn readdir_r(
+    env: &mut Environment,
+    dirp: MutPtr<DIR>,
+    entry: MutPtr<dirent>,
+    result: MutPtr<MutPtr<dirent>>,
+) -> i32 {
+    match next_entry(env, dirp) {
+        Ok(Some(dirent)) => {
+            env.mem.write(entry, dirent);
+            env.mem.write(result, entry);
+            0
         }
+        Ok(None) => {
+            env.mem.write(result, Ptr::null());
+            0
+        }
+        Err(errno) => errno,
     }
-    if env.libc_state.dirent.open_dirs.remove(&dirp).is_some() {
-        // this avoid double free if closedir() is called twice
-        env.mem.free(dirp.cast());
+}
+
+fn rewinddir(env: &mut Environment, dirp: MutPtr<DIR>) {
+    if let Some(stream) = State::get_mut(env).open_dirs.get_mut(&dirp) {
+        stream.idx = 0;
     }
+}
+
+fn closedir(env: &mut Environment, dirp: MutPtr<DIR>) -> i32 {
+    log_dbg!("closedir({:?})", dirp);
+    let Some(stream) = State::get_mut(env).open_dirs.remove(&dirp) else {
+        set_errno(env, EBADF);
+        return -1;
+    };
+    env.mem.free(stream.dirent.cast());
+    env.mem.free(dirp.cast());
     0 // Success
 }
 
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(opendir(_)),
     export_c_func!(readdir(_)),
+    export_c_func!(readdir_r(_, _, _)),
+    export_c_func!(rewinddir(_)),
     export_c_func!(closedir(_)),
 ];
@@ -12,11 +12,38 @@ use crate::Environment;
 use std::io::Write;
 
 pub const EPERM: i32 = 1;
+pub const ENOENT: i32 = 2;
+pub const EINTR: i32 = 4;
+pub const EIO: i32 = 5;
 pub const EBADF: i32 = 9;
+pub const ENOMEM: i32 = 12;
 pub const EDEADLK: i32 = 11;
+pub const EACCES: i32 = 13;
+pub const EFAULT: i32 = 14;
 pub const EBUSY: i32 = 16;
 pub const EEXIST: i32 = 17;
+pub const ENOTDIR: i32 = 20;
 pub const EINVAL: i32 = 22;
+pub const EPIPE: i32 = 32;
+pub const EAGAIN: i32 = 35;
+pub const EWOULDBLOCK: i32 = EAGAIN;
+pub const EINPROGRESS: i32 = 36;
+pub const EALREADY: i32 = 37;
+pub const ENOTSOCK: i32 = 38;
+pub const EDESTADDRREQ: i32 = 39;
+pub const ENOPROTOOPT: i32 = 42;
+pub const EPROTONOSUPPORT: i32 = 43;
+pub const EAFNOSUPPORT: i32 = 47;
+pub const EADDRINUSE: i32 = 48;
+pub const EADDRNOTAVAIL: i32 = 49;
+pub const ENETUNREACH: i32 = 51;
+pub const ECONNABORTED: i32 = 53;
+pub const ECONNRESET: i32 = 54;
+pub const EISCONN: i32 = 56;
+pub const ENOTCONN: i32 = 57;
+pub const ETIMEDOUT: i32 = 60;
+pub const ECONNREFUSED: i32 = 61;
+pub const EOVERFLOW: i32 = 84;
 
 #[derive(Default)]
 pub struct State {
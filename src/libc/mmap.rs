@@ -3,42 +3,166 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
+//! `sys/mman.h`
+//!
+//! There's no virtual memory management in touchHLE, so mappings are just
+//! allocations. File mappings are copies of the file's contents at the time of
+//! mapping, so writes to a mapping won't reach the file and vice-versa. Every
+//! mapping is a separate copy, even if it's of the same file.
 
 use crate::dyld::FunctionExports;
 use crate::environment::Environment;
 use crate::export_c_func;
-use crate::libc::errno::set_errno;
+use crate::libc::errno::{set_errno, EBADF, EINVAL, ENOMEM};
 use crate::libc::posix_io;
-use crate::libc::posix_io::{off_t, FileDescriptor, SEEK_SET};
-use crate::mem::{GuestUSize, MutVoidPtr};
+use crate::libc::posix_io::{off_t, FileDescriptor};
+use crate::mem::{GuestUSize, MutVoidPtr, Ptr};
+use std::collections::HashMap;
 
-#[allow(dead_code)]
-const MAP_FILE: i32 = 0x0000;
+const PAGE_SIZE: GuestUSize = 4096;
+
+const PROT_WRITE: i32 = 0x02;
+
+const MAP_SHARED: i32 = 0x0001;
+const MAP_FIXED: i32 = 0x0010;
 const MAP_ANON: i32 = 0x1000;
 
-/// Our implementation of mmap is really simple: it's just load entirety of
-/// file in memory!
+/// `MAP_FAILED`
+const MAP_FAILED: MutVoidPtr = Ptr::from_bits(!0);
+
+struct Mapping {
+    /// The allocation the mapping is in. It's larger than the mapping, so that
+    /// the mapping can be page-aligned.
+    allocation: MutVoidPtr,
+    /// The length of the mapping, rounded up to a multiple of the page size.
+    len: GuestUSize,
+}
+
+#[derive(Default)]
+pub struct State {
+    /// Mappings by their start address.
+    mappings: HashMap<MutVoidPtr, Mapping>,
+}
+
+fn round_up_to_page_size(len: GuestUSize) -> GuestUSize {
+    len.div_ceil(PAGE_SIZE) * PAGE_SIZE
+}
+
 fn mmap(
     env: &mut Environment,
     addr: MutVoidPtr,
     len: GuestUSize,
-    _prot: i32,
+    prot: i32,
     flags: i32,
     fd: FileDescriptor,
     offset: off_t,
 ) -> MutVoidPtr {
-    // TODO: handle errno properly
-    set_errno(env, 0);
-
-    assert!(addr.is_null());
-    assert_eq!(offset, 0);
-    assert_eq!((flags & MAP_ANON), 0);
-    let new_offset = posix_io::lseek(env, fd, offset, SEEK_SET);
-    assert_eq!(new_offset, offset);
-    let ptr = env.mem.alloc(len);
-    let read = posix_io::read(env, fd, ptr, len);
-    assert_eq!(read as u32, len);
+    // Without MAP_FIXED, the address is only a hint and can be ignored.
+    assert!(flags & MAP_FIXED == 0, "MAP_FIXED is not supported");
+
+    if len == 0 || offset < 0 || offset % PAGE_SIZE as off_t != 0 {
+        log!(
+            "Warning: mmap({:?}, {:#x}, {:#x}, {:#x}, {}, {:#x}) failed: invalid length or offset",
+            addr,
+            len,
+            prot,
+            flags,
+            fd,
+            offset
+        );
+        set_errno(env, EINVAL);
+        return MAP_FAILED;
+    }
+
+    let len_rounded = round_up_to_page_size(len);
+    let Some(allocation) = env.mem.try_alloc(len_rounded + PAGE_SIZE - 1) else {
+        set_errno(env, ENOMEM);
+        return MAP_FAILED;
+    };
+    let ptr: MutVoidPtr = Ptr::from_bits(round_up_to_page_size(allocation.to_bits()));
+
+    if flags & MAP_ANON == 0 {
+        if flags & MAP_SHARED != 0 && prot & PROT_WRITE != 0 {
+            log!(
+                "Warning: mmap() of fd {} is shared and writable, but writes won't reach the file",
+                fd
+            );
+        }
+        // The rest of the last page after the end of the file is zero-filled,
+        // as it would be on a real system (the allocation is already zeroed).
+        let Some(bytes_read) = posix_io::read_at(env, fd, offset as u64, ptr, len) else {
+            env.mem.free(allocation);
+            log!("Warning: mmap() of bad fd {} failed", fd);
+            set_errno(env, EBADF);
+            return MAP_FAILED;
+        };
+        if bytes_read < len {
+            log_dbg!(
+                "mmap() of fd {} extends past the end of the file ({:#x} bytes read)",
+                fd,
+                bytes_read
+            );
+        }
+    }
+
+    log_dbg!(
+        "mmap({:?}, {:#x}, {:#x}, {:#x}, {}, {:#x}) => {:?}",
+        addr,
+        len,
+        prot,
+        flags,
+        fd,
+        offset,
+        ptr
+    );
+    env.libc_state.mmap.mappings.insert(
+        ptr,
+        Mapping {
+            allocation,
+            len: len_rounded,
+        },
+    );
     ptr
 }
 
-pub const FUNCTIONS: FunctionExports = &[export_c_func!(mmap(_, _, _, _, _, _))];
+fn munmap(env: &mut Environment, addr: MutVoidPtr, len: GuestUSize) -> i32 {
+    if len == 0 || addr.to_bits() % PAGE_SIZE != 0 {
+        set_errno(env, EINVAL);
+        return -1;
+    }
+
+    let mappings = &mut env.libc_state.mmap.mappings;
+    match mappings.get(&addr) {
+        Some(mapping) if mapping.len == round_up_to_page_size(len) => {
+            let allocation = mappings.remove(&addr).unwrap().allocation;
+            env.mem.free(allocation);
+            log_dbg!("munmap({:?}, {:#x}) => 0", addr, len);
+        }
+        _ => {
+            // Unmapping memory that isn't mapped is allowed, but this might
+            // also be a partial unmapping, which isn't supported.
+            log!(
+                "Warning: munmap({:?}, {:#x}) doesn't match a mapping, ignoring",
+                addr,
+                len
+            );
+        }
+    }
+    0 // success
+}
+
+fn mprotect(env: &mut Environment, addr: MutVoidPtr, len: GuestUSize, prot: i32) -> i32 {
+    if addr.to_bits() % PAGE_SIZE != 0 {
+        set_errno(env, EINVAL);
+        return -1;
+    }
+    // There's no memory protection in touchHLE.
+    log_dbg!("Ignoring mprotect({:?}, {:#x}, {:#x})", addr, len, prot);
+    0 // success
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(mmap(_, _, _, _, _, _)),
+    export_c_func!(munmap(_, _)),
+    export_c_func!(mprotect(_, _, _)),
+];
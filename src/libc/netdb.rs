@@ -7,21 +7,123 @@
 
 use crate::dyld::FunctionExports;
 use crate::export_c_func;
-use crate::mem::{ConstPtr, MutPtr, Ptr};
+use crate::libc::sys::socket::AF_INET;
+use crate::mem::{ConstPtr, MutPtr, MutVoidPtr, Ptr, SafeRead};
 use crate::Environment;
+use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs};
+
+#[derive(Default)]
+pub struct State {
+    /// Memory used by the result of the last `gethostbyname()` call, which is
+    /// freed on the next call.
+    hostent_allocations: Vec<MutVoidPtr>,
+}
 
-// TODO: struct definition
 #[allow(non_camel_case_types)]
-struct hostent {}
+#[repr(C, packed)]
+pub struct hostent {
+    h_name: MutPtr<u8>,
+    h_aliases: MutPtr<MutPtr<u8>>,
+    h_addrtype: i32,
+    h_length: i32,
+    /// Addresses are in network byte order.
+    h_addr_list: MutPtr<MutPtr<u8>>,
+}
+unsafe impl SafeRead for hostent {}
+
+/// Look up the IPv4 addresses for a host name. Numeric addresses don't need a
+/// DNS lookup, so they work even if network access is disabled.
+fn resolve(env: &Environment, name: &str) -> Vec<Ipv4Addr> {
+    if let Ok(addr) = name.parse::<Ipv4Addr>() {
+        return vec![addr];
+    }
+    if !env.options.network_access {
+        log!(
+            "Refusing to look up host {:?} because network access is disabled. Use the --allow-network-access option to enable it.",
+            name
+        );
+        return Vec::new();
+    }
+    let addrs = match (name, 0).to_socket_addrs() {
+        Ok(addrs) => addrs,
+        Err(e) => {
+            log!("Warning: lookup of host {:?} failed: {}", name, e);
+            return Vec::new();
+        }
+    };
+    let mut ipv4_addrs = Vec::new();
+    for addr in addrs {
+        if let SocketAddr::V4(addr) = addr {
+            if !ipv4_addrs.contains(addr.ip()) {
+                ipv4_addrs.push(*addr.ip());
+            }
+        }
+    }
+    ipv4_addrs
+}
 
 fn gethostbyname(env: &mut Environment, name: ConstPtr<u8>) -> MutPtr<hostent> {
-    log!(
-        "TODO: gethostbyname({:?} \"{}\") => NULL",
+    if name.is_null() {
+        return Ptr::null();
+    }
+    let name_str = env.mem.cstr_at_utf8(name).unwrap().to_owned();
+    let addrs = resolve(env, &name_str);
+    if addrs.is_empty() {
+        log!("gethostbyname({:?} {:?}) => NULL", name, name_str);
+        // TODO: set h_errno
+        return Ptr::null();
+    }
+
+    // The result is only valid until the next call, so the memory for the
+    // previous result can be reused.
+    for allocation in std::mem::take(&mut env.libc_state.netdb.hostent_allocations) {
+        env.mem.free(allocation);
+    }
+
+    let h_name = env.mem.alloc_and_write_cstr(name_str.as_bytes());
+    let h_aliases: MutPtr<MutPtr<u8>> = env.mem.alloc_and_write(Ptr::null());
+    let addr_ptrs: Vec<MutPtr<u8>> = addrs
+        .iter()
+        .map(|addr| {
+            let ptr: MutPtr<u8> = env.mem.alloc(4).cast();
+            env.mem.bytes_at_mut(ptr, 4).copy_from_slice(&addr.octets());
+            ptr
+        })
+        .collect();
+    let h_addr_list: MutPtr<MutPtr<u8>> = env
+        .mem
+        .alloc(((addr_ptrs.len() + 1) * 4).try_into().unwrap())
+        .cast();
+    for (i, &addr_ptr) in addr_ptrs.iter().enumerate() {
+        env.mem.write(h_addr_list + i.try_into().unwrap(), addr_ptr);
+    }
+    env.mem.write(
+        h_addr_list + addr_ptrs.len().try_into().unwrap(),
+        Ptr::null(),
+    );
+    let result = env.mem.alloc_and_write(hostent {
+        h_name,
+        h_aliases,
+        h_addrtype: AF_INET,
+        h_length: 4,
+        h_addr_list,
+    });
+
+    let allocations = &mut env.libc_state.netdb.hostent_allocations;
+    allocations.push(h_name.cast());
+    allocations.push(h_aliases.cast());
+    allocations.extend(addr_ptrs.iter().map(|ptr| ptr.cast()));
+    allocations.push(h_addr_list.cast());
+    allocations.push(result.cast());
+
+    log_dbg!(
+        "gethostbyname({:?} {:?}) => {:?} ({:?})",
         name,
-        env.mem.cstr_at_utf8(name).unwrap()
+        name_str,
+        result,
+        addrs
     );
-    // TODO: set h_errno
-    Ptr::null()
+    result
 }
 
 pub const FUNCTIONS: FunctionExports = &[export_c_func!(gethostbyname(_))];
@@ -11,6 +11,7 @@ use crate::abi::DotDotDot;
 use crate::dyld::{export_c_func, FunctionExports};
 use crate::fs::{GuestFile, GuestOpenOptions, GuestPath};
 use crate::libc::errno::{set_errno, EBADF};
+use crate::libc::sys::socket::{self, Socket};
 use crate::mem::{ConstPtr, ConstVoidPtr, GuestISize, GuestUSize, MutPtr, MutVoidPtr, Ptr};
 use crate::Environment;
 use std::io::{Read, Seek, SeekFrom, Write};
@@ -18,17 +19,54 @@ use std::io::{Read, Seek, SeekFrom, Write};
 #[derive(Default)]
 pub struct State {
     /// File descriptors _other than stdin, stdout, and stderr_
-    files: Vec<Option<PosixFileHostObject>>,
+    files: Vec<Option<OpenFd>>,
 }
 impl State {
     fn file_for_fd(&mut self, fd: FileDescriptor) -> Option<&mut PosixFileHostObject> {
-        self.files
-            .get_mut(fd_to_file_idx(fd))
-            .and_then(|file_or_none| file_or_none.as_mut())
+        match self.files.get_mut(fd_to_file_idx(fd)) {
+            Some(Some(OpenFd::File(file))) => Some(file),
+            _ => None,
+        }
+    }
+
+    /// For use by the sockets implementation: get the socket for a file
+    /// descriptor, if it is one.
+    pub(in crate::libc) fn socket_for_fd(&mut self, fd: FileDescriptor) -> Option<&mut Socket> {
+        match self.files.get_mut(fd_to_file_idx(fd)) {
+            Some(Some(OpenFd::Socket(socket))) => Some(socket),
+            _ => None,
+        }
+    }
+
+    /// Check whether a file descriptor is open, regardless of what it refers
+    /// to. stdin, stdout and stderr are always considered open.
+    pub(in crate::libc) fn is_open(&self, fd: FileDescriptor) -> bool {
+        (0..NORMAL_FILENO_BASE).contains(&fd)
+            || (fd >= NORMAL_FILENO_BASE
+                && matches!(self.files.get(fd_to_file_idx(fd)), Some(Some(_))))
+    }
+
+    /// Allocate the lowest free file descriptor for an object.
+    pub(in crate::libc) fn alloc_fd(&mut self, object: OpenFd) -> FileDescriptor {
+        let idx = if let Some(free_idx) = self.files.iter().position(|f| f.is_none()) {
+            self.files[free_idx] = Some(object);
+            free_idx
+        } else {
+            let idx = self.files.len();
+            self.files.push(Some(object));
+            idx
+        };
+        file_idx_to_fd(idx)
     }
 }
 
-struct PosixFileHostObject {
+/// Something a file descriptor can refer to.
+pub(in crate::libc) enum OpenFd {
+    File(PosixFileHostObject),
+    Socket(Socket),
+}
+
+pub(in crate::libc) struct PosixFileHostObject {
     file: GuestFile,
     needs_flush: bool,
     reached_eof: bool,
@@ -71,6 +109,8 @@ pub const O_EXCL: OpenFlag = 0x800;
 /// File control command flags.
 /// This alias is for readability, POSIX just uses `int`.
 pub type FileControlCommand = i32;
+const F_GETFL: FileControlCommand = 3;
+const F_SETFL: FileControlCommand = 4;
 const F_NOCACHE: FileControlCommand = 48;
 
 pub type FLockFlag = i32;
@@ -168,21 +208,7 @@ pub fn open_direct(env: &mut Environment, path: ConstPtr<u8>, flags: i32) -> Fil
                 reached_eof: false,
             };
 
-            let idx = if let Some(free_idx) = env
-                .libc_state
-                .posix_io
-                .files
-                .iter()
-                .position(|f| f.is_none())
-            {
-                env.libc_state.posix_io.files[free_idx] = Some(host_object);
-                free_idx
-            } else {
-                let idx = env.libc_state.posix_io.files.len();
-                env.libc_state.posix_io.files.push(Some(host_object));
-                idx
-            };
-            file_idx_to_fd(idx)
+            env.libc_state.posix_io.alloc_fd(OpenFd::File(host_object))
         }
         Err(()) => {
             // TODO: set errno
@@ -203,6 +229,33 @@ pub fn open_direct(env: &mut Environment, path: ConstPtr<u8>, flags: i32) -> Fil
     res
 }
 
+/// Read up to `size` bytes from an open file at `offset`, without changing the
+/// file's position. This is for use by `mmap()`. Returns [None] if the file
+/// descriptor isn't open, otherwise the number of bytes read.
+pub fn read_at(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    offset: u64,
+    buffer: MutVoidPtr,
+    size: GuestUSize,
+) -> Option<GuestUSize> {
+    let file = env.libc_state.posix_io.file_for_fd(fd)?;
+    let buffer_slice = env.mem.bytes_at_mut(buffer.cast(), size);
+
+    let old_pos = file.file.stream_position().unwrap();
+    file.file.seek(SeekFrom::Start(offset)).unwrap();
+    let mut bytes_read = 0;
+    while bytes_read < buffer_slice.len() {
+        match file.file.read(&mut buffer_slice[bytes_read..]) {
+            Ok(0) => break,
+            Ok(n) => bytes_read += n,
+            Err(e) => panic!("Error reading file for fd {}: {}", fd, e),
+        }
+    }
+    file.file.seek(SeekFrom::Start(old_pos)).unwrap();
+    Some(bytes_read as GuestUSize)
+}
+
 pub fn read(
     env: &mut Environment,
     fd: FileDescriptor,
@@ -217,6 +270,10 @@ pub fn read(
         return -1;
     }
 
+    if env.libc_state.posix_io.socket_for_fd(fd).is_some() {
+        return socket::recv(env, fd, buffer, size, 0);
+    }
+
     // TODO: error handling for unknown fd?
     let file = env.libc_state.posix_io.file_for_fd(fd).unwrap();
 
@@ -303,6 +360,10 @@ pub fn write(
     // TODO: handle errno properly
     set_errno(env, 0);
 
+    if env.libc_state.posix_io.socket_for_fd(fd).is_some() {
+        return socket::send(env, fd, buffer, size, 0);
+    }
+
     // TODO: error handling for unknown fd?
     let file = env.libc_state.posix_io.file_for_fd(fd).unwrap();
 
@@ -387,8 +448,16 @@ pub fn close(env: &mut Environment, fd: FileDescriptor) -> i32 {
         return 0;
     }
 
-    let result = match env.libc_state.posix_io.files[fd_to_file_idx(fd)].take() {
-        Some(file) => {
+    let result = match env
+        .libc_state
+        .posix_io
+        .files
+        .get_mut(fd_to_file_idx(fd))
+        .and_then(|fd_or_none| fd_or_none.take())
+    {
+        // The host socket is closed when it's dropped.
+        Some(OpenFd::Socket(_)) => 0,
+        Some(OpenFd::File(file)) => {
             // The actual closing of the file happens implicitly when `file`
             // falls out of scope. The return value is about whether actions
             // performed before closing succeed or not.
@@ -412,7 +481,7 @@ pub fn close(env: &mut Environment, fd: FileDescriptor) -> i32 {
             }
         }
         None => {
-            // TODO: set errno
+            set_errno(env, EBADF);
             -1
         }
     };
@@ -532,15 +601,49 @@ fn fcntl(
         return -1;
     }
 
-    assert_eq!(cmd, F_NOCACHE);
     let mut args = args.start();
-    let arg: i32 = args.next(env);
-    assert_eq!(arg, 1);
-    log!(
-        "TODO: Ignoring enabling F_NOCACHE for file descriptor {}",
-        fd
-    );
-    0 // success
+    match cmd {
+        F_GETFL => {
+            if let Some(socket) = env.libc_state.posix_io.socket_for_fd(fd) {
+                let mut flags = O_RDWR;
+                if socket.is_nonblocking() {
+                    flags |= O_NONBLOCK;
+                }
+                log_dbg!("fcntl({}, F_GETFL) => {:#x}", fd, flags);
+                flags
+            } else {
+                // TODO: remember the flags a file was opened with
+                log!("TODO: fcntl({}, F_GETFL) on a file, returning O_RDWR", fd);
+                O_RDWR
+            }
+        }
+        F_SETFL => {
+            let flags: OpenFlag = args.next(env);
+            if let Some(socket) = env.libc_state.posix_io.socket_for_fd(fd) {
+                log_dbg!("fcntl({}, F_SETFL, {:#x})", fd, flags);
+                socket.set_nonblocking(flags & O_NONBLOCK != 0);
+            } else {
+                // Note: NONBLOCK flag is ignored, assumption is all file I/O
+                // is fast
+                log!(
+                    "TODO: Ignoring fcntl({}, F_SETFL, {:#x}) on a file",
+                    fd,
+                    flags
+                );
+            }
+            0 // success
+        }
+        F_NOCACHE => {
+            let arg: i32 = args.next(env);
+            assert_eq!(arg, 1);
+            log!(
+                "TODO: Ignoring enabling F_NOCACHE for file descriptor {}",
+                fd
+            );
+            0 // success
+        }
+        _ => unimplemented!("fcntl({}, {}, ...)", fd, cmd),
+    }
 }
 
 fn flock(env: &mut Environment, fd: FileDescriptor, operation: FLockFlag) -> i32 {
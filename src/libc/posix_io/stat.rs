@@ -5,14 +5,16 @@
  */
 //! POSIX `sys/stat.h`
 
-use super::{close, off_t, open_direct, FileDescriptor};
+use super::{off_t, FileDescriptor};
 use crate::dyld::{export_c_func, FunctionExports};
 use crate::fs::{FsError, GuestFile, GuestPath};
-use crate::libc::errno::{set_errno, EEXIST};
-use crate::libc::time::timespec;
+use crate::libc::dirent::fake_inode_number;
+use crate::libc::errno::{set_errno, EBADF, EEXIST, EFAULT, ENOENT};
+use crate::libc::time::{time_t, timespec};
 use crate::mem::{ConstPtr, MutPtr, SafeRead};
 use crate::Environment;
 use std::io::{Seek, SeekFrom};
+use std::time::SystemTime;
 
 #[allow(non_camel_case_types)]
 pub type dev_t = u32;
@@ -35,8 +37,14 @@ pub type blksize_t = u32;
 pub const S_IFDIR: mode_t = 0o0040000;
 pub const S_IFREG: mode_t = 0o0100000;
 
+/// The user and group ID of the `mobile` user that apps run as.
+const MOBILE_UID: uid_t = 501;
+/// The block size of the iPhone's filesystem.
+const BLOCK_SIZE: blksize_t = 4096;
+/// Directory sizes aren't meaningful, but HFS+ reports a non-zero size.
+const DIRECTORY_SIZE: u64 = 102;
+
 #[allow(non_camel_case_types)]
-#[derive(Default)]
 #[repr(C, packed)]
 pub struct stat {
     st_dev: dev_t,
@@ -90,89 +98,130 @@ fn mkdir(env: &mut Environment, path: ConstPtr<u8>, mode: mode_t) -> i32 {
     }
 }
 
-/// Helper for [stat()] and [fstat()] that fills the data in the stat struct
-fn fstat_inner(env: &mut Environment, fd: FileDescriptor, buf: MutPtr<stat>) -> i32 {
-    // TODO: error handling for unknown fd?
-    let file = env.libc_state.posix_io.file_for_fd(fd).unwrap();
-
-    // FIXME: This implementation is highly incomplete. fstat() returns a huge
-    // struct with many kinds of data in it. This code is assuming the caller
-    // only wants a small part of it.
-
-    let mut stat = stat::default();
-
-    match file.file {
-        GuestFile::File(_) | GuestFile::IpaBundleFile(_) | GuestFile::ResourceFile(_) => {
-            stat.st_mode |= S_IFREG;
-
-            // Obtain file size
-            // TODO: Use the stream_len() method if that ever gets stabilized.
-            let old_pos = file.file.stream_position().unwrap();
-            stat.st_size = file
-                .file
-                .seek(SeekFrom::End(0))
-                .unwrap()
-                .try_into()
-                .unwrap();
-            file.file.seek(SeekFrom::Start(old_pos)).unwrap();
-        }
-        GuestFile::Directory => {
-            stat.st_mode |= S_IFDIR;
-
-            // TODO: st_size
+/// Fill in a stat struct. The host metadata is used for the times if it's
+/// available, otherwise they're the epoch.
+fn make_stat(is_dir: bool, size: u64, ino: ino_t, metadata: Option<std::fs::Metadata>) -> stat {
+    fn to_timespec(time: std::io::Result<SystemTime>) -> timespec {
+        let duration = time
+            .ok()
+            .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        timespec {
+            tv_sec: duration.as_secs().try_into().unwrap_or(time_t::MAX),
+            tv_nsec: duration.subsec_nanos() as _,
         }
     }
+    let (atime, mtime, birthtime) = match metadata {
+        Some(metadata) => (
+            to_timespec(metadata.accessed()),
+            to_timespec(metadata.modified()),
+            to_timespec(metadata.created()),
+        ),
+        None => Default::default(),
+    };
+    // Only the contents can be modified, so ctime is the same as mtime.
+    let ctime = timespec {
+        tv_sec: mtime.tv_sec,
+        tv_nsec: mtime.tv_nsec,
+    };
+
+    // Permissions are the same as for an app installed on a real device.
+    let (mode, nlink) = if is_dir {
+        (S_IFDIR | 0o755, 2)
+    } else {
+        (S_IFREG | 0o644, 1)
+    };
+    stat {
+        st_dev: 1,
+        st_mode: mode,
+        st_nlink: nlink,
+        st_ino: ino,
+        st_uid: MOBILE_UID,
+        st_gid: MOBILE_UID,
+        st_rdev: 0,
+        st_atimespec: atime,
+        st_mtimespec: mtime,
+        st_ctimespec: ctime,
+        st_birthtimespec: birthtime,
+        st_size: size.try_into().unwrap(),
+        // st_blocks is in 512-byte units, but space is allocated in blocks of
+        // st_blksize bytes.
+        st_blocks: size.div_ceil(BLOCK_SIZE as u64) * (BLOCK_SIZE as u64 / 512),
+        st_blksize: BLOCK_SIZE,
+        st_flags: 0,
+        st_gen: 0,
+        st_lspare: 0,
+        st_qspare: [0; 2],
+    }
+}
 
-    env.mem.write(buf, stat);
-
-    0 // success
+/// Get the size of a guest file without disturbing its position.
+fn file_size(file: &mut GuestFile) -> u64 {
+    // TODO: Use the stream_len() method if that ever gets stabilized.
+    let old_pos = file.stream_position().unwrap();
+    let size = file.seek(SeekFrom::End(0)).unwrap();
+    file.seek(SeekFrom::Start(old_pos)).unwrap();
+    size
 }
 
 fn fstat(env: &mut Environment, fd: FileDescriptor, buf: MutPtr<stat>) -> i32 {
-    // TODO: handle errno properly
-    set_errno(env, 0);
-
-    log!("Warning: fstat() call, this function is mostly unimplemented");
-    let result = fstat_inner(env, fd, buf);
-    log_dbg!("fstat({:?}, {:?}) -> {}", fd, buf, result);
-    result
+    let Some(file) = env.libc_state.posix_io.file_for_fd(fd) else {
+        log_dbg!("fstat({:?}, {:?}) => -1 (bad fd)", fd, buf);
+        set_errno(env, EBADF);
+        return -1;
+    };
+    // There's no path to make up an inode number from, but the fd is unique
+    // while the file is open.
+    let ino = fd as ino_t;
+    let stat = match file.file {
+        GuestFile::Directory => make_stat(true, DIRECTORY_SIZE, ino, None),
+        ref mut guest_file => {
+            let size = file_size(guest_file);
+            make_stat(false, size, ino, guest_file.host_metadata())
+        }
+    };
+    log_dbg!("fstat({:?}, {:?}) => 0, size {}", fd, buf, { stat.st_size });
+    env.mem.write(buf, stat);
+    0 // success
 }
 
 fn stat(env: &mut Environment, path: ConstPtr<u8>, buf: MutPtr<stat>) -> i32 {
-    // TODO: handle errno properly
-    set_errno(env, 0);
-
-    log!("Warning: stat() call, this function is mostly unimplemented");
-
-    fn do_stat(env: &mut Environment, path: ConstPtr<u8>, buf: MutPtr<stat>) -> i32 {
-        if path.is_null() {
-            return -1; // TODO: Set errno
-        }
-
-        // Open and reuse fstat implementation
-        let fd = open_direct(env, path, 0);
-        if fd == -1 {
-            return -1; // TODO: Set errno
-        }
-
-        let result = fstat_inner(env, fd, buf);
-        assert!(close(env, fd) == 0);
-        result
+    if path.is_null() {
+        set_errno(env, EFAULT);
+        return -1;
     }
-    let result = do_stat(env, path, buf);
+    let path_string = env.mem.cstr_at_utf8(path).unwrap().to_owned();
+    let guest_path = GuestPath::new(&path_string);
+
+    let ino = fake_inode_number(&path_string);
+    let stat = if env.fs.is_dir(guest_path) {
+        make_stat(true, DIRECTORY_SIZE, ino, env.fs.host_metadata(guest_path))
+    } else if let Ok(mut file) = env.fs.open(guest_path) {
+        let size = file_size(&mut file);
+        make_stat(false, size, ino, file.host_metadata())
+    } else {
+        // Apps often use stat() to check whether an optional file exists, so
+        // this isn't worth a warning.
+        log_dbg!("stat({:?}, {:?}) => -1 (not found)", path_string, buf);
+        set_errno(env, ENOENT);
+        return -1;
+    };
+    log_dbg!("stat({:?}, {:?}) => 0, size {}", path_string, buf, {
+        stat.st_size
+    });
+    env.mem.write(buf, stat);
+    0 // success
+}
 
-    log_dbg!(
-        "stat({:?} {:?}, {:?}) -> {}",
-        path,
-        env.mem.cstr_at_utf8(path),
-        buf,
-        result
-    );
-    result
+fn lstat(env: &mut Environment, path: ConstPtr<u8>, buf: MutPtr<stat>) -> i32 {
+    // The guest filesystem has no symlinks (symlinks in the app bundle are
+    // treated as copies of what they point to), so this is the same as stat().
+    stat(env, path, buf)
 }
 
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(mkdir(_, _)),
     export_c_func!(fstat(_, _)),
     export_c_func!(stat(_, _)),
+    export_c_func!(lstat(_, _)),
 ];
@@ -32,11 +32,13 @@ pub mod cond;
 pub mod key;
 pub mod mutex;
 pub mod once;
+pub mod rwlock;
 pub mod thread;
 
 #[derive(Default)]
 pub struct State {
     pub cond: cond::State,
     key: key::State,
+    pub rwlock: rwlock::State,
     thread: thread::State,
 }
@@ -3,45 +3,103 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
-//! Conditional variables.
+//! Condition variables.
+//!
+//! Waiting threads are blocked by the thread scheduler in
+//! [crate::Environment::run], which also reacquires the mutex for them when
+//! they are woken, see [ThreadBlock::Condition].
 
-use super::mutex::pthread_mutex_t;
-use crate::dyld::FunctionExports;
-use crate::libc::pthread::mutex::pthread_mutex_unlock;
-use crate::mem::{ConstPtr, MutPtr, SafeRead};
-use crate::{export_c_func, Environment};
-use std::collections::HashMap;
-
-use crate::environment::ThreadBlock;
+use super::mutex::{mutex_id_for_guest_mutex, pthread_mutex_t, pthread_mutex_unlock};
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::environment::{ThreadBlock, ThreadId};
+use crate::libc::errno::{EBUSY, EINVAL, ETIMEDOUT};
+use crate::libc::time::timespec;
+use crate::mem::{ConstPtr, MutPtr, Ptr, SafeRead};
+use crate::Environment;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
+/// Apple's implementation is a 4-byte magic number followed by a 4-byte opaque
+/// region. We only have to match the size theirs has.
 #[repr(C, packed)]
-struct pthread_condattr_t {}
+pub struct pthread_condattr_t {
+    /// Magic number (must be [MAGIC_CONDATTR])
+    magic: u32,
+    _unused: u32,
+}
 unsafe impl SafeRead for pthread_condattr_t {}
 
+/// Apple's implementation is a 4-byte magic number followed by a 24-byte
+/// opaque region. Like for mutexes, the actual data is stored on the host,
+/// determined by an identifier.
 #[repr(C, packed)]
-pub struct OpaqueCond {
-    _unused: i32,
+pub struct pthread_cond_t {
+    /// Magic number (must be [MAGIC_COND])
+    magic: u32,
+    cond_id: CondId,
 }
-unsafe impl SafeRead for OpaqueCond {}
+unsafe impl SafeRead for pthread_cond_t {}
+
+/// Arbitrarily-chosen magic number for `pthread_condattr_t` (not Apple's).
+const MAGIC_CONDATTR: u32 = u32::from_be_bytes(*b"CoAt");
+/// Arbitrarily-chosen magic number for `pthread_cond_t` (not Apple's).
+const MAGIC_COND: u32 = u32::from_be_bytes(*b"COND");
+/// Magic number used by `PTHREAD_COND_INITIALIZER`. This is part of the ABI!
+const MAGIC_COND_STATIC: u32 = 0x3CB0B1BB;
 
-pub type pthread_cond_t = MutPtr<OpaqueCond>;
+/// Unique identifier for condition variables.
+pub type CondId = u32;
 
 #[derive(Default)]
 pub struct State {
-    pub condition_variables: HashMap<pthread_cond_t, CondHostObject>,
-    pub mutexes: HashMap<pthread_cond_t, pthread_mutex_t>,
+    condition_variables: HashMap<CondId, CondHostObject>,
+    cond_count: CondId,
 }
 impl State {
-    fn get(env: &Environment) -> &Self {
-        &env.libc_state.pthread.cond
-    }
     fn get_mut(env: &mut Environment) -> &mut Self {
         &mut env.libc_state.pthread.cond
     }
+
+    /// For use by the thread scheduler: check if a thread is still waiting on
+    /// a condition variable, i.e. it hasn't been signalled yet.
+    pub fn is_waiting(&self, cond_id: CondId, thread: ThreadId) -> bool {
+        self.condition_variables[&cond_id].waiting.contains(&thread)
+    }
+
+    /// For use by the thread scheduler: stop a thread waiting on a condition
+    /// variable, because its wait timed out.
+    pub fn stop_waiting(&mut self, cond_id: CondId, thread: ThreadId) {
+        let waiting = &mut self.condition_variables.get_mut(&cond_id).unwrap().waiting;
+        waiting.retain(|&waiting_thread| waiting_thread != thread);
+    }
+}
+
+struct CondHostObject {
+    /// Threads waiting on this condition variable, in the order they started
+    /// waiting.
+    waiting: VecDeque<ThreadId>,
 }
 
-pub struct CondHostObject {
-    pub done: bool,
+fn pthread_condattr_init(env: &mut Environment, attr: MutPtr<pthread_condattr_t>) -> i32 {
+    env.mem.write(
+        attr,
+        pthread_condattr_t {
+            magic: MAGIC_CONDATTR,
+            _unused: 0,
+        },
+    );
+    0 // success
+}
+fn pthread_condattr_destroy(env: &mut Environment, attr: MutPtr<pthread_condattr_t>) -> i32 {
+    check_magic!(env, attr, MAGIC_CONDATTR);
+    env.mem.write(
+        attr,
+        pthread_condattr_t {
+            magic: 0,
+            _unused: 0,
+        },
+    );
+    0 // success
 }
 
 fn pthread_cond_init(
@@ -49,67 +107,209 @@ fn pthread_cond_init(
     cond: MutPtr<pthread_cond_t>,
     attr: ConstPtr<pthread_condattr_t>,
 ) -> i32 {
-    assert!(attr.is_null());
-    let opaque = env.mem.alloc_and_write(OpaqueCond { _unused: 0 });
-    env.mem.write(cond, opaque);
+    if !attr.is_null() {
+        // There are no attributes we support, so the object is only checked.
+        check_magic!(env, attr, MAGIC_CONDATTR);
+    }
 
-    assert!(!State::get(env).condition_variables.contains_key(&opaque));
-    State::get_mut(env)
-        .condition_variables
-        .insert(opaque, CondHostObject { done: false });
+    let state = State::get_mut(env);
+    let cond_id = state.cond_count;
+    state.cond_count = state.cond_count.checked_add(1).unwrap();
+    state.condition_variables.insert(
+        cond_id,
+        CondHostObject {
+            waiting: VecDeque::new(),
+        },
+    );
+    log_dbg!(
+        "Condition variable #{} created from pthread_cond_init ({:?})",
+        cond_id,
+        cond
+    );
+    env.mem.write(
+        cond,
+        pthread_cond_t {
+            magic: MAGIC_COND,
+            cond_id,
+        },
+    );
     0 // success
 }
 
-fn pthread_cond_wait(
+/// Get the host identifier for a condition variable, registering it first if
+/// it was statically initialized.
+fn check_or_register_cond(env: &mut Environment, cond: MutPtr<pthread_cond_t>) -> CondId {
+    let magic: u32 = env.mem.read(cond.cast());
+    if magic == MAGIC_COND_STATIC {
+        log_dbg!(
+            "Detected statically-initialized condition variable at {:?}, registering.",
+            cond
+        );
+        pthread_cond_init(env, cond, Ptr::null());
+    } else {
+        // As with mutexes, a mismatch almost certainly indicates memory
+        // corruption, so panicking is more useful than returning EINVAL.
+        assert_eq!(magic, MAGIC_COND);
+    }
+    env.mem.read(cond).cond_id
+}
+
+/// Shared implementation of the waiting functions. `deadline` is [None] if
+/// the wait has no timeout.
+fn wait_inner(
     env: &mut Environment,
     cond: MutPtr<pthread_cond_t>,
     mutex: MutPtr<pthread_mutex_t>,
+    deadline: Option<Instant>,
 ) -> i32 {
+    let cond_id = check_or_register_cond(env, cond);
+    let mutex_id = mutex_id_for_guest_mutex(env, mutex);
+
     let res = pthread_mutex_unlock(env, mutex);
-    assert_eq!(res, 0);
+    if res != 0 {
+        // The mutex wasn't locked by this thread.
+        return res;
+    }
+
     assert!(matches!(
         env.threads[env.current_thread].blocked_by,
         ThreadBlock::NotBlocked
     ));
     log_dbg!(
-        "Thread {} is blocking on condition variable {:?}",
+        "Thread {} is blocking on condition variable #{} ({:?}) with mutex #{}, deadline {:?}",
         env.current_thread,
-        cond
+        cond_id,
+        cond,
+        mutex_id,
+        deadline
     );
-    let cond_var = env.mem.read(cond);
-    env.threads[env.current_thread].blocked_by = ThreadBlock::Condition(cond_var);
-    assert!(!State::get(env).mutexes.contains_key(&cond_var));
-    let mutex_val = env.mem.read(mutex);
-    State::get_mut(env).mutexes.insert(cond_var, mutex_val);
+    let current_thread = env.current_thread;
+    State::get_mut(env)
+        .condition_variables
+        .get_mut(&cond_id)
+        .unwrap()
+        .waiting
+        .push_back(current_thread);
+    // The scheduler relocks the mutex once the thread is woken, and if the
+    // wait timed out, it replaces this return value with ETIMEDOUT.
+    env.threads[current_thread].blocked_by = ThreadBlock::Condition(cond_id, mutex_id, deadline);
     0 // success
 }
 
+fn pthread_cond_wait(
+    env: &mut Environment,
+    cond: MutPtr<pthread_cond_t>,
+    mutex: MutPtr<pthread_mutex_t>,
+) -> i32 {
+    wait_inner(env, cond, mutex, None)
+}
+
+fn pthread_cond_timedwait(
+    env: &mut Environment,
+    cond: MutPtr<pthread_cond_t>,
+    mutex: MutPtr<pthread_mutex_t>,
+    abstime: ConstPtr<timespec>,
+) -> i32 {
+    let timespec { tv_sec, tv_nsec } = env.mem.read(abstime);
+    if tv_sec < 0 || !(0..1_000_000_000).contains(&tv_nsec) {
+        return EINVAL;
+    }
+    // The deadline is in terms of the real-time clock, but the scheduler uses
+    // monotonic time.
+    let abstime = UNIX_EPOCH + Duration::new(tv_sec as u64, tv_nsec as u32);
+    let Ok(timeout) = abstime.duration_since(env.clock.system_now()) else {
+        log_dbg!(
+            "pthread_cond_timedwait({:?}, {:?}, {:?}) deadline already passed, returning ETIMEDOUT",
+            cond,
+            mutex,
+            abstime
+        );
+        // The mutex is still held, so there's nothing more to do.
+        return ETIMEDOUT;
+    };
+    wait_inner(env, cond, mutex, Some(env.clock.now() + timeout))
+}
+
+fn pthread_cond_timedwait_relative_np(
+    env: &mut Environment,
+    cond: MutPtr<pthread_cond_t>,
+    mutex: MutPtr<pthread_mutex_t>,
+    reltime: ConstPtr<timespec>,
+) -> i32 {
+    let timespec { tv_sec, tv_nsec } = env.mem.read(reltime);
+    if tv_sec < 0 || !(0..1_000_000_000).contains(&tv_nsec) {
+        return EINVAL;
+    }
+    let timeout = Duration::new(tv_sec as u64, tv_nsec as u32);
+    wait_inner(env, cond, mutex, Some(env.clock.now() + timeout))
+}
+
 fn pthread_cond_signal(env: &mut Environment, cond: MutPtr<pthread_cond_t>) -> i32 {
-    let cond_var = env.mem.read(cond);
+    let cond_id = check_or_register_cond(env, cond);
+    let woken = State::get_mut(env)
+        .condition_variables
+        .get_mut(&cond_id)
+        .unwrap()
+        .waiting
+        .pop_front();
     log_dbg!(
-        "Thread {} unblocks one thread waiting on condition variable {:?}",
+        "Thread {} signals condition variable #{} ({:?}), waking thread {:?}",
         env.current_thread,
-        cond
+        cond_id,
+        cond,
+        woken
+    );
+    0 // success
+}
+
+fn pthread_cond_broadcast(env: &mut Environment, cond: MutPtr<pthread_cond_t>) -> i32 {
+    let cond_id = check_or_register_cond(env, cond);
+    let woken = std::mem::take(
+        &mut State::get_mut(env)
+            .condition_variables
+            .get_mut(&cond_id)
+            .unwrap()
+            .waiting,
+    );
+    log_dbg!(
+        "Thread {} broadcasts condition variable #{} ({:?}), waking threads {:?}",
+        env.current_thread,
+        cond_id,
+        cond,
+        woken
     );
-    State::get_mut(env)
-        .condition_variables
-        .get_mut(&cond_var)
-        .unwrap()
-        .done = true;
     0 // success
 }
 
 fn pthread_cond_destroy(env: &mut Environment, cond: MutPtr<pthread_cond_t>) -> i32 {
-    let cond_var = env.mem.read(cond);
-    State::get_mut(env).condition_variables.remove(&cond_var);
-    State::get_mut(env).mutexes.remove(&cond_var);
-    env.mem.free(cond_var.cast());
+    let cond_id = check_or_register_cond(env, cond);
+    let state = State::get_mut(env);
+    if !state.condition_variables[&cond_id].waiting.is_empty() {
+        log_dbg!(
+            "Attempted to destroy condition variable #{} with waiting threads, returning EBUSY!",
+            cond_id
+        );
+        return EBUSY;
+    }
+    state.condition_variables.remove(&cond_id);
+    env.mem.write(
+        cond,
+        pthread_cond_t {
+            magic: 0,
+            cond_id: 0xFFFFFFFF,
+        },
+    );
     0 // success
 }
 
 pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(pthread_condattr_init(_)),
+    export_c_func!(pthread_condattr_destroy(_)),
     export_c_func!(pthread_cond_init(_, _)),
     export_c_func!(pthread_cond_wait(_, _)),
+    export_c_func!(pthread_cond_timedwait(_, _, _)),
+    export_c_func!(pthread_cond_timedwait_relative_np(_, _, _)),
     export_c_func!(pthread_cond_signal(_)),
+    export_c_func!(pthread_cond_broadcast(_)),
     export_c_func!(pthread_cond_destroy(_)),
 ];
@@ -126,6 +126,13 @@ fn check_or_register_mutex(env: &mut Environment, mutex: MutPtr<pthread_mutex_t>
     }
 }
 
+/// Get the host identifier for a guest mutex, registering it first if it was
+/// statically initialized. For use by condition variables.
+pub fn mutex_id_for_guest_mutex(env: &mut Environment, mutex: MutPtr<pthread_mutex_t>) -> MutexId {
+    check_or_register_mutex(env, mutex);
+    env.mem.read(mutex).mutex_id
+}
+
 pub fn pthread_mutex_lock(env: &mut Environment, mutex: MutPtr<pthread_mutex_t>) -> i32 {
     check_or_register_mutex(env, mutex);
     let mutex_data = env.mem.read(mutex);
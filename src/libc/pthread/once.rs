@@ -7,6 +7,7 @@
 
 use crate::abi::{CallFromHost, GuestFunction};
 use crate::dyld::{export_c_func, FunctionExports};
+use crate::environment::ThreadBlock;
 use crate::mem::{MutPtr, SafeRead};
 use crate::Environment;
 
@@ -14,15 +15,27 @@ use crate::Environment;
 const MAGIC_ONCE: u32 = 0x30B1BCBA;
 
 #[repr(C, packed)]
-struct pthread_once_t {
+pub struct pthread_once_t {
     /// Magic number (must be [MAGIC_ONCE])
     magic: u32,
-    /// Boolean marking whether this has been initialised yet. This seems to be
-    /// initialized to zero.
+    /// Marks whether this has been initialised yet: [ONCE_NOT_RUN],
+    /// [ONCE_RUNNING] or [ONCE_DONE].
     init: u32,
 }
 unsafe impl SafeRead for pthread_once_t {}
 
+/// `PTHREAD_ONCE_INIT` initializes this to zero.
+const ONCE_NOT_RUN: u32 = 0;
+/// Arbitrarily-chosen value (not Apple's).
+const ONCE_RUNNING: u32 = 1;
+const ONCE_DONE: u32 = 0xFFFFFFFF;
+
+/// For use by the thread scheduler: check if a `pthread_once` that a thread is
+/// blocked on has finished running.
+pub fn once_is_done(env: &Environment, once_control: MutPtr<pthread_once_t>) -> bool {
+    env.mem.read(once_control).init == ONCE_DONE
+}
+
 fn pthread_once(
     env: &mut Environment,
     once_control: MutPtr<pthread_once_t>,
@@ -31,21 +44,44 @@ fn pthread_once(
     let pthread_once_t { magic, init } = env.mem.read(once_control);
     assert!(magic == MAGIC_ONCE);
     match init {
-        0 => {
+        ONCE_NOT_RUN => {
             log_dbg!(
                 "pthread_once_t at {:?} hasn't been run yet, running init routine {:?}",
                 once_control,
                 init_routine
             );
-            let new_once = pthread_once_t {
-                magic,
-                init: 0xFFFFFFFF,
-            };
-            env.mem.write(once_control, new_once);
+            env.mem.write(
+                once_control,
+                pthread_once_t {
+                    magic,
+                    init: ONCE_RUNNING,
+                },
+            );
             () = init_routine.call_from_host(env, ());
+            env.mem.write(
+                once_control,
+                pthread_once_t {
+                    magic,
+                    init: ONCE_DONE,
+                },
+            );
             log_dbg!("Init routine {:?} done", init_routine);
         }
-        0xFFFFFFFF => {
+        ONCE_RUNNING => {
+            // Another thread is running the init routine right now. The caller
+            // must not return until it's finished.
+            log_dbg!(
+                "pthread_once_t at {:?} is being run by another thread, thread {} is blocking",
+                once_control,
+                env.current_thread
+            );
+            assert!(matches!(
+                env.threads[env.current_thread].blocked_by,
+                ThreadBlock::NotBlocked
+            ));
+            env.threads[env.current_thread].blocked_by = ThreadBlock::Once(once_control);
+        }
+        ONCE_DONE => {
             log_dbg!(
                 "pthread_once_t at {:?} has already been run, doing nothing",
                 once_control
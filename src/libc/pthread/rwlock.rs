@@ -0,0 +1,308 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Reader/writer locks.
+//!
+//! Writers are preferred: once a writer is waiting, new readers have to wait
+//! until it has had its turn, so that a steady stream of readers can't starve
+//! writers forever. Blocked threads are woken by the thread scheduler in
+//! [crate::Environment::run], see [ThreadBlock::RwLock].
+
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::environment::{ThreadBlock, ThreadId};
+use crate::libc::errno::{EBUSY, EDEADLK, EPERM};
+use crate::mem::{ConstPtr, MutPtr, Ptr, SafeRead};
+use crate::Environment;
+use std::collections::HashMap;
+
+/// Apple's implementation is a 4-byte magic number followed by a 12-byte
+/// opaque region. We only have to match the size theirs has.
+#[repr(C, packed)]
+pub struct pthread_rwlockattr_t {
+    /// Magic number (must be [MAGIC_RWLOCKATTR])
+    magic: u32,
+    _unused: [u32; 3],
+}
+unsafe impl SafeRead for pthread_rwlockattr_t {}
+
+/// Apple's implementation is a 4-byte magic number followed by a 124-byte
+/// opaque region. Like for mutexes, the actual data is stored on the host,
+/// determined by an identifier.
+#[repr(C, packed)]
+pub struct pthread_rwlock_t {
+    /// Magic number (must be [MAGIC_RWLOCK])
+    magic: u32,
+    rwlock_id: RwLockId,
+}
+unsafe impl SafeRead for pthread_rwlock_t {}
+
+/// Arbitrarily-chosen magic number for `pthread_rwlockattr_t` (not Apple's).
+const MAGIC_RWLOCKATTR: u32 = u32::from_be_bytes(*b"RwAt");
+/// Arbitrarily-chosen magic number for `pthread_rwlock_t` (not Apple's).
+const MAGIC_RWLOCK: u32 = u32::from_be_bytes(*b"RWLK");
+/// Magic number used by `PTHREAD_RWLOCK_INITIALIZER`. This is part of the ABI!
+const MAGIC_RWLOCK_STATIC: u32 = 0x2DA8B3B4;
+
+/// Unique identifier for reader/writer locks.
+pub type RwLockId = u32;
+
+#[derive(Default)]
+pub struct State {
+    rwlocks: HashMap<RwLockId, RwLockHostObject>,
+    rwlock_count: RwLockId,
+}
+impl State {
+    fn get_mut(env: &mut Environment) -> &mut Self {
+        &mut env.libc_state.pthread.rwlock
+    }
+
+    /// For use by the thread scheduler: try to take the lock for a thread that
+    /// is blocked on it. Returns [true] if the lock was taken.
+    pub fn try_lock_for_blocked_thread(
+        &mut self,
+        rwlock_id: RwLockId,
+        thread: ThreadId,
+        write: bool,
+    ) -> bool {
+        let rwlock = self.rwlocks.get_mut(&rwlock_id).unwrap();
+        if write {
+            if rwlock.writer.is_some() || rwlock.readers != 0 {
+                return false;
+            }
+            rwlock.waiting_writers -= 1;
+            rwlock.writer = Some(thread);
+        } else {
+            if rwlock.writer.is_some() || rwlock.waiting_writers != 0 {
+                return false;
+            }
+            rwlock.readers += 1;
+        }
+        true
+    }
+}
+
+struct RwLockHostObject {
+    /// Number of read locks currently held.
+    readers: u32,
+    /// Thread holding the write lock, if any.
+    writer: Option<ThreadId>,
+    /// Number of threads blocked waiting for the write lock.
+    waiting_writers: u32,
+}
+
+fn pthread_rwlockattr_init(env: &mut Environment, attr: MutPtr<pthread_rwlockattr_t>) -> i32 {
+    env.mem.write(
+        attr,
+        pthread_rwlockattr_t {
+            magic: MAGIC_RWLOCKATTR,
+            _unused: [0; 3],
+        },
+    );
+    0 // success
+}
+fn pthread_rwlockattr_destroy(env: &mut Environment, attr: MutPtr<pthread_rwlockattr_t>) -> i32 {
+    check_magic!(env, attr, MAGIC_RWLOCKATTR);
+    env.mem.write(
+        attr,
+        pthread_rwlockattr_t {
+            magic: 0,
+            _unused: [0; 3],
+        },
+    );
+    0 // success
+}
+
+fn pthread_rwlock_init(
+    env: &mut Environment,
+    rwlock: MutPtr<pthread_rwlock_t>,
+    attr: ConstPtr<pthread_rwlockattr_t>,
+) -> i32 {
+    if !attr.is_null() {
+        // There are no attributes we support, so the object is only checked.
+        check_magic!(env, attr, MAGIC_RWLOCKATTR);
+    }
+
+    let state = State::get_mut(env);
+    let rwlock_id = state.rwlock_count;
+    state.rwlock_count = state.rwlock_count.checked_add(1).unwrap();
+    state.rwlocks.insert(
+        rwlock_id,
+        RwLockHostObject {
+            readers: 0,
+            writer: None,
+            waiting_writers: 0,
+        },
+    );
+    log_dbg!(
+        "Reader/writer lock #{} created from pthread_rwlock_init ({:?})",
+        rwlock_id,
+        rwlock
+    );
+    env.mem.write(
+        rwlock,
+        pthread_rwlock_t {
+            magic: MAGIC_RWLOCK,
+            rwlock_id,
+        },
+    );
+    0 // success
+}
+
+/// Get the host identifier for a lock, registering it first if it was
+/// statically initialized.
+fn check_or_register_rwlock(env: &mut Environment, rwlock: MutPtr<pthread_rwlock_t>) -> RwLockId {
+    let magic: u32 = env.mem.read(rwlock.cast());
+    if magic == MAGIC_RWLOCK_STATIC {
+        log_dbg!(
+            "Detected statically-initialized reader/writer lock at {:?}, registering.",
+            rwlock
+        );
+        pthread_rwlock_init(env, rwlock, Ptr::null());
+    } else {
+        // As with mutexes, a mismatch almost certainly indicates memory
+        // corruption, so panicking is more useful than returning EINVAL.
+        assert_eq!(magic, MAGIC_RWLOCK);
+    }
+    env.mem.read(rwlock).rwlock_id
+}
+
+/// Shared implementation of the locking functions.
+fn lock_inner(
+    env: &mut Environment,
+    rwlock: MutPtr<pthread_rwlock_t>,
+    write: bool,
+    wait: bool,
+) -> i32 {
+    let rwlock_id = check_or_register_rwlock(env, rwlock);
+    let current_thread = env.current_thread;
+    let host_rwlock = State::get_mut(env).rwlocks.get_mut(&rwlock_id).unwrap();
+
+    if host_rwlock.writer == Some(current_thread) {
+        log_dbg!(
+            "Thread {} tried to lock reader/writer lock #{} it already holds the write lock for, returning EDEADLK!",
+            current_thread,
+            rwlock_id
+        );
+        return EDEADLK;
+    }
+
+    let available = if write {
+        host_rwlock.writer.is_none() && host_rwlock.readers == 0
+    } else {
+        host_rwlock.writer.is_none() && host_rwlock.waiting_writers == 0
+    };
+    if available {
+        if write {
+            host_rwlock.writer = Some(current_thread);
+        } else {
+            host_rwlock.readers += 1;
+        }
+        log_dbg!(
+            "Thread {} took {} lock on reader/writer lock #{} ({:?})",
+            current_thread,
+            if write { "write" } else { "read" },
+            rwlock_id,
+            rwlock
+        );
+        return 0; // success
+    }
+
+    if !wait {
+        return EBUSY;
+    }
+
+    if write {
+        host_rwlock.waiting_writers += 1;
+    }
+    assert!(matches!(
+        env.threads[current_thread].blocked_by,
+        ThreadBlock::NotBlocked
+    ));
+    log_dbg!(
+        "Thread {} is blocking on reader/writer lock #{} ({:?}) for {}",
+        current_thread,
+        rwlock_id,
+        rwlock,
+        if write { "writing" } else { "reading" }
+    );
+    env.threads[current_thread].blocked_by = ThreadBlock::RwLock(rwlock_id, write);
+    0 // success
+}
+
+fn pthread_rwlock_rdlock(env: &mut Environment, rwlock: MutPtr<pthread_rwlock_t>) -> i32 {
+    lock_inner(env, rwlock, /* write: */ false, /* wait: */ true)
+}
+fn pthread_rwlock_tryrdlock(env: &mut Environment, rwlock: MutPtr<pthread_rwlock_t>) -> i32 {
+    lock_inner(env, rwlock, /* write: */ false, /* wait: */ false)
+}
+fn pthread_rwlock_wrlock(env: &mut Environment, rwlock: MutPtr<pthread_rwlock_t>) -> i32 {
+    lock_inner(env, rwlock, /* write: */ true, /* wait: */ true)
+}
+fn pthread_rwlock_trywrlock(env: &mut Environment, rwlock: MutPtr<pthread_rwlock_t>) -> i32 {
+    lock_inner(env, rwlock, /* write: */ true, /* wait: */ false)
+}
+
+fn pthread_rwlock_unlock(env: &mut Environment, rwlock: MutPtr<pthread_rwlock_t>) -> i32 {
+    let rwlock_id = check_or_register_rwlock(env, rwlock);
+    let current_thread = env.current_thread;
+    let host_rwlock = State::get_mut(env).rwlocks.get_mut(&rwlock_id).unwrap();
+
+    if host_rwlock.writer == Some(current_thread) {
+        host_rwlock.writer = None;
+    } else if host_rwlock.writer.is_none() && host_rwlock.readers > 0 {
+        // Which threads hold read locks isn't tracked, so this can't check
+        // that the current thread is one of them.
+        host_rwlock.readers -= 1;
+    } else {
+        log_dbg!(
+            "Thread {} tried to unlock reader/writer lock #{} it doesn't hold, returning EPERM!",
+            current_thread,
+            rwlock_id
+        );
+        return EPERM;
+    }
+    log_dbg!(
+        "Thread {} unlocked reader/writer lock #{} ({:?})",
+        current_thread,
+        rwlock_id,
+        rwlock
+    );
+    0 // success
+}
+
+fn pthread_rwlock_destroy(env: &mut Environment, rwlock: MutPtr<pthread_rwlock_t>) -> i32 {
+    let rwlock_id = check_or_register_rwlock(env, rwlock);
+    let state = State::get_mut(env);
+    let host_rwlock = &state.rwlocks[&rwlock_id];
+    if host_rwlock.writer.is_some() || host_rwlock.readers != 0 || host_rwlock.waiting_writers != 0
+    {
+        log_dbg!(
+            "Attempted to destroy reader/writer lock #{} while it's in use, returning EBUSY!",
+            rwlock_id
+        );
+        return EBUSY;
+    }
+    state.rwlocks.remove(&rwlock_id);
+    env.mem.write(
+        rwlock,
+        pthread_rwlock_t {
+            magic: 0,
+            rwlock_id: 0xFFFFFFFF,
+        },
+    );
+    0 // success
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(pthread_rwlockattr_init(_)),
+    export_c_func!(pthread_rwlockattr_destroy(_)),
+    export_c_func!(pthread_rwlock_init(_, _)),
+    export_c_func!(pthread_rwlock_rdlock(_)),
+    export_c_func!(pthread_rwlock_tryrdlock(_)),
+    export_c_func!(pthread_rwlock_wrlock(_)),
+    export_c_func!(pthread_rwlock_trywrlock(_)),
+    export_c_func!(pthread_rwlock_unlock(_)),
+    export_c_func!(pthread_rwlock_destroy(_)),
+];
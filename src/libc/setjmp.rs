@@ -5,26 +5,146 @@
  */
 //! `setjmp.h`.
 //!
-//! We don't have a real implementation for this right now. It could be quite
-//! tricky to write one, considering that we would need to unwind through host
-//! code, and somehow do so selectively since we have a mix of stack frames from
-//! different guest threads. For the moment, we simply pray the app never throws
-//! exceptions.
-//!
 //! Note that `setjmp` and `longjmp` are defined as macros in the C standard,
-//! but it seems like the implementation of these on iPhone OS uses real
-//! functions, at least for the former.
+//! but the implementation of these on iPhone OS uses real functions.
+//!
+//! These are host functions that save and restore the guest's callee-saved
+//! registers. This works because host functions are called via a stub that
+//! returns to the address in LR once the host function is done, so restoring
+//! LR and SP in `longjmp` makes that stub return from `setjmp` again instead.
+//!
+//! This can't unwind host stack frames, so jumping out of a guest function
+//! that was called by host code (e.g. a callback, or a method called by
+//! `objc_msgSend` from a host function) won't work. Fortunately, apps using
+//! `setjmp` for error handling (libpng, interpreters) usually only jump within
+//! their own code. Objective-C exceptions also use `setjmp`, but they need
+//! host stack unwinding, so they are handled separately (see
+//! [crate::objc::catch_exception]).
 
+use crate::cpu::Cpu;
 use crate::dyld::{export_c_func, FunctionExports};
+use crate::mem::{MutPtr, SafeRead};
 use crate::Environment;
 
-/// The signature of this is incomplete because it's a stub (see module docs).
-fn setjmp(env: &mut Environment) -> i32 {
+/// The layout of `jmp_buf` for ARMv6 on Darwin, as used by Apple's assembly
+/// implementation (`_JBLEN` is 28 `int`s, `sigjmp_buf` has one more).
+///
+/// r9 isn't saved because it's not callee-saved in Apple's ARM ABI.
+#[allow(non_camel_case_types)]
+#[repr(C, packed)]
+pub struct jmp_buf {
+    r4: u32,
+    r5: u32,
+    r6: u32,
+    r7: u32,
+    r8: u32,
+    r10: u32,
+    r11: u32,
+    sp: u32,
+    lr: u32,
+    /// VFP registers d8-d15 (as s16-s31).
+    vfp: [u32; 16],
+    /// Space for the "format word" written by `FSTMX`.
+    _fstmx: u32,
+    /// Signal mask. touchHLE doesn't support signals, so this is always 0.
+    sig: u32,
+    /// Whether the signal mask was saved.
+    sig_flag: u32,
+}
+unsafe impl SafeRead for jmp_buf {}
+
+/// `d8` is made up of `s16` and `s17`.
+const FIRST_SAVED_VFP_REG: usize = 16;
+
+fn save_registers(env: &mut Environment, buf: MutPtr<jmp_buf>, save_mask: bool) {
+    let regs = env.cpu.regs();
+    let mut vfp = [0; 16];
+    vfp.copy_from_slice(&env.cpu.extregs()[FIRST_SAVED_VFP_REG..][..16]);
+    let saved = jmp_buf {
+        r4: regs[4],
+        r5: regs[5],
+        r6: regs[6],
+        r7: regs[7],
+        r8: regs[8],
+        r10: regs[10],
+        r11: regs[11],
+        sp: regs[Cpu::SP],
+        lr: regs[Cpu::LR],
+        vfp,
+        _fstmx: 0,
+        sig: 0,
+        sig_flag: save_mask.into(),
+    };
+    log_dbg!(
+        "setjmp({:?}): saved state for return to {:#x} with SP {:#x}",
+        buf,
+        regs[Cpu::LR],
+        regs[Cpu::SP]
+    );
+    env.mem.write(buf, saved);
+}
+
+/// Restores the registers and returns the value that `setjmp` should appear
+/// to return. This must be used as the return value of the host function,
+/// so that it ends up in r0.
+pub fn restore_registers(env: &mut Environment, buf: MutPtr<jmp_buf>, val: i32) -> i32 {
+    let saved = env.mem.read(buf);
+    let regs = env.cpu.regs_mut();
+    regs[4] = saved.r4;
+    regs[5] = saved.r5;
+    regs[6] = saved.r6;
+    regs[7] = saved.r7;
+    regs[8] = saved.r8;
+    regs[10] = saved.r10;
+    regs[11] = saved.r11;
+    regs[Cpu::SP] = saved.sp;
+    regs[Cpu::LR] = saved.lr;
+    let vfp = saved.vfp;
+    env.cpu.extregs_mut()[FIRST_SAVED_VFP_REG..][..16].copy_from_slice(&vfp);
+
+    // setjmp() returning 0 means it's the initial call, so longjmp() can't
+    // make it return 0.
+    let val = if val == 0 { 1 } else { val };
     log_dbg!(
-        "TODO: setjmp() at {:#x}",
-        env.cpu.regs()[crate::cpu::Cpu::LR]
+        "longjmp({:?}, {}): returning to {:#x} with SP {:#x}",
+        buf,
+        val,
+        { saved.lr },
+        { saved.sp }
     );
+    val
+}
+
+fn setjmp(env: &mut Environment, buf: MutPtr<jmp_buf>) -> i32 {
+    save_registers(env, buf, /* save_mask: */ true);
+    0 // no longjmp() was performed
+}
+fn _setjmp(env: &mut Environment, buf: MutPtr<jmp_buf>) -> i32 {
+    save_registers(env, buf, /* save_mask: */ false);
+    0 // no longjmp() was performed
+}
+fn sigsetjmp(env: &mut Environment, buf: MutPtr<jmp_buf>, save_mask: i32) -> i32 {
+    save_registers(env, buf, save_mask != 0);
     0 // no longjmp() was performed
 }
 
-pub const FUNCTIONS: FunctionExports = &[export_c_func!(setjmp())];
+// These never return to their caller, so their "return value" is the value the
+// corresponding setjmp() appears to return.
+fn longjmp(env: &mut Environment, buf: MutPtr<jmp_buf>, val: i32) -> i32 {
+    restore_registers(env, buf, val)
+}
+fn _longjmp(env: &mut Environment, buf: MutPtr<jmp_buf>, val: i32) -> i32 {
+    restore_registers(env, buf, val)
+}
+fn siglongjmp(env: &mut Environment, buf: MutPtr<jmp_buf>, val: i32) -> i32 {
+    restore_registers(env, buf, val)
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(setjmp(_)),
+    export_c_func!(_setjmp(_)),
+    export_c_func!(sigsetjmp(_, _)),
+    export_c_func!(longjmp(_, _)),
+    export_c_func!(_longjmp(_, _)),
+    export_c_func!(siglongjmp(_, _)),
+];
@@ -7,22 +7,419 @@
 
 use crate::abi::{DotDotDot, VaList};
 use crate::dyld::{export_c_func, FunctionExports};
-use crate::frameworks::foundation::{ns_string, unichar};
+use crate::frameworks::foundation::ns_string;
 use crate::libc::clocale::{setlocale, LC_CTYPE};
 use crate::libc::errno::set_errno;
 use crate::libc::posix_io::{STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO};
-use crate::libc::stdio::{fwrite, FILE};
-use crate::libc::stdlib::{atof_inner, strtol_inner, strtoul};
-use crate::libc::string::strlen;
+use crate::libc::stdio::{fwrite, EOF, FILE};
 use crate::libc::wchar::wchar_t;
 use crate::mem::{ConstPtr, GuestUSize, Mem, MutPtr, MutVoidPtr, Ptr};
 use crate::objc::{id, msg, nil};
 use crate::Environment;
-use std::collections::HashSet;
 use std::io::Write;
 
-const INTEGER_SPECIFIERS: [u8; 6] = [b'd', b'i', b'o', b'u', b'x', b'X'];
-const FLOAT_SPECIFIERS: [u8; 3] = [b'f', b'e', b'g'];
+/// Length modifier of a conversion specification, shared by the `printf` and
+/// `scanf` families.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum LengthModifier {
+    None,
+    /// `hh`
+    Char,
+    /// `h`
+    Short,
+    /// `l`
+    Long,
+    /// `ll`, `q` or `j`. These are the only 64-bit integer types.
+    LongLong,
+    /// `z` or `t`
+    Size,
+    /// `L`. `long double` is the same as `double` on iPhone OS.
+    LongDouble,
+}
+
+/// Returns the byte at `idx`, or `'\0'` if it is past the end.
+fn byte_at(string: &[u8], idx: usize) -> u8 {
+    string.get(idx).copied().unwrap_or(b'\0')
+}
+
+fn parse_length_modifier(format: &[u8], idx: &mut usize) -> LengthModifier {
+    let (modifier, len) = match (byte_at(format, *idx), byte_at(format, *idx + 1)) {
+        (b'h', b'h') => (LengthModifier::Char, 2),
+        (b'h', _) => (LengthModifier::Short, 1),
+        (b'l', b'l') => (LengthModifier::LongLong, 2),
+        (b'l', _) => (LengthModifier::Long, 1),
+        (b'q' | b'j', _) => (LengthModifier::LongLong, 1),
+        (b'z' | b't', _) => (LengthModifier::Size, 1),
+        (b'L', _) => (LengthModifier::LongDouble, 1),
+        _ => (LengthModifier::None, 0),
+    };
+    *idx += len;
+    modifier
+}
+
+/// Parses a non-negative decimal number, if there is one at `idx`.
+fn parse_decimal(format: &[u8], idx: &mut usize) -> Option<usize> {
+    let mut number = None;
+    while let c @ b'0'..=b'9' = byte_at(format, *idx) {
+        number = Some(number.unwrap_or(0) * 10 + (c - b'0') as usize);
+        *idx += 1;
+    }
+    number
+}
+
+/// Parses an argument position (`n$`) if there is one at `idx`, returning the
+/// zero-based argument index.
+fn parse_position(format: &[u8], idx: &mut usize) -> Option<usize> {
+    let mut end = *idx;
+    let position = parse_decimal(format, &mut end)?;
+    if position == 0 || byte_at(format, end) != b'$' {
+        return None;
+    }
+    *idx = end + 1;
+    Some(position - 1)
+}
+
+/// Field width or precision of a `printf` conversion specification.
+#[derive(Copy, Clone, Debug)]
+enum Count {
+    Literal(usize),
+    /// `*`: the value is taken from the argument with this index.
+    Arg(usize),
+}
+
+/// Size of a variadic argument in words. iPhone OS uses Apple's variant of the
+/// ARM ABI, where 64-bit types are only 4-byte aligned, so unlike in AAPCS
+/// there is never any padding before them in the argument list.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ArgSize {
+    Word,
+    DoubleWord,
+}
+
+/// A parsed `printf` conversion specification, e.g. `%-08.3lld`.
+#[derive(Debug)]
+struct FormatSpec {
+    /// `-` flag
+    left_justify: bool,
+    /// `+` flag
+    force_sign: bool,
+    /// ` ` flag
+    space_sign: bool,
+    /// `#` flag
+    alternate_form: bool,
+    /// `0` flag
+    zero_pad: bool,
+    width: Option<Count>,
+    precision: Option<Count>,
+    length: LengthModifier,
+    conversion: u8,
+    /// Index of the argument to be converted.
+    arg: usize,
+}
+
+enum FormatPiece {
+    Literal(u8),
+    Conversion(FormatSpec),
+}
+
+/// Parses a `printf` format string, returning the pieces along with the sizes
+/// of the arguments it consumes, indexed by their position. Positional
+/// arguments (`%2$d`, `%*3$d`) can refer to the arguments in any order, so all
+/// of this has to be known before any argument can be read.
+fn parse_format<const NS_LOG: bool>(format: &[u8]) -> (Vec<FormatPiece>, Vec<Option<ArgSize>>) {
+    let mut pieces = Vec::new();
+    let mut arg_sizes: Vec<Option<ArgSize>> = Vec::new();
+    let mut next_arg = 0;
+    let mut use_arg = |position: Option<usize>, size: ArgSize| {
+        let arg = position.unwrap_or_else(|| {
+            next_arg += 1;
+            next_arg - 1
+        });
+        if arg >= arg_sizes.len() {
+            arg_sizes.resize(arg + 1, None);
+        }
+        arg_sizes[arg] = Some(size);
+        arg
+    };
+
+    let mut idx = 0;
+    while idx < format.len() {
+        let c = format[idx];
+        idx += 1;
+        if c != b'%' {
+            pieces.push(FormatPiece::Literal(c));
+            continue;
+        }
+        if byte_at(format, idx) == b'%' {
+            idx += 1;
+            pieces.push(FormatPiece::Literal(b'%'));
+            continue;
+        }
+
+        let position = parse_position(format, &mut idx);
+
+        let mut spec = FormatSpec {
+            left_justify: false,
+            force_sign: false,
+            space_sign: false,
+            alternate_form: false,
+            zero_pad: false,
+            width: None,
+            precision: None,
+            length: LengthModifier::None,
+            conversion: b'\0',
+            arg: 0,
+        };
+        loop {
+            match byte_at(format, idx) {
+                b'-' => spec.left_justify = true,
+                b'+' => spec.force_sign = true,
+                b' ' => spec.space_sign = true,
+                b'#' => spec.alternate_form = true,
+                b'0' => spec.zero_pad = true,
+                _ => break,
+            }
+            idx += 1;
+        }
+
+        // The width and precision arguments come before the converted
+        // argument, so they have to be allocated first.
+        let mut parse_count = |idx: &mut usize| {
+            if byte_at(format, *idx) == b'*' {
+                *idx += 1;
+                let position = parse_position(format, idx);
+                Some(Count::Arg(use_arg(position, ArgSize::Word)))
+            } else {
+                parse_decimal(format, idx).map(Count::Literal)
+            }
+        };
+        spec.width = parse_count(&mut idx);
+        if byte_at(format, idx) == b'.' {
+            idx += 1;
+            // A lone `.` means a precision of zero.
+            spec.precision = Some(parse_count(&mut idx).unwrap_or(Count::Literal(0)));
+        }
+
+        spec.length = parse_length_modifier(format, &mut idx);
+
+        spec.conversion = byte_at(format, idx);
+        idx += 1;
+        let size = match spec.conversion {
+            b'd' | b'i' | b'o' | b'u' | b'x' | b'X' => {
+                if spec.length == LengthModifier::LongLong {
+                    ArgSize::DoubleWord
+                } else {
+                    ArgSize::Word
+                }
+            }
+            b'f' | b'F' | b'e' | b'E' | b'g' | b'G' => ArgSize::DoubleWord,
+            b'c' | b'C' | b's' | b'S' | b'p' | b'n' => ArgSize::Word,
+            b'@' if NS_LOG => ArgSize::Word,
+            // TODO: more specifiers
+            _ => unimplemented!(
+                "Format character '{}'. Formatted up to index {}",
+                spec.conversion as char,
+                idx
+            ),
+        };
+        spec.arg = use_arg(position, size);
+        pieces.push(FormatPiece::Conversion(spec));
+    }
+
+    (pieces, arg_sizes)
+}
+
+/// Appends `prefix` (a sign or `0x`) and `body` to `res`, padded to `width`.
+/// Zero-padding goes between the prefix and the body.
+fn write_padded(
+    res: &mut Vec<u8>,
+    width: usize,
+    left_justify: bool,
+    zero_pad: bool,
+    prefix: &[u8],
+    body: &[u8],
+) {
+    let padding = width.saturating_sub(prefix.len() + body.len());
+    if left_justify {
+        res.extend_from_slice(prefix);
+        res.extend_from_slice(body);
+        res.resize(res.len() + padding, b' ');
+    } else if zero_pad {
+        res.extend_from_slice(prefix);
+        res.resize(res.len() + padding, b'0');
+        res.extend_from_slice(body);
+    } else {
+        res.resize(res.len() + padding, b' ');
+        res.extend_from_slice(prefix);
+        res.extend_from_slice(body);
+    }
+}
+
+/// Returns the sign prefix for a signed conversion.
+fn sign_prefix(spec: &FormatSpec, negative: bool) -> &'static str {
+    if negative {
+        "-"
+    } else if spec.force_sign {
+        "+"
+    } else if spec.space_sign {
+        " "
+    } else {
+        ""
+    }
+}
+
+/// Formats an integer for the `d`, `i`, `o`, `u`, `x` and `X` conversions.
+/// `value` is the raw argument, which is truncated according to the length
+/// modifier. The prefix (sign or `0x`) is returned separately from the digits.
+fn format_integer(spec: &FormatSpec, value: u64, precision: Option<usize>) -> (String, String) {
+    let signed = matches!(spec.conversion, b'd' | b'i');
+    let (negative, magnitude) = if signed {
+        let value: i64 = match spec.length {
+            LengthModifier::Char => value as i8 as i64,
+            LengthModifier::Short => value as i16 as i64,
+            LengthModifier::LongLong => value as i64,
+            _ => value as i32 as i64,
+        };
+        (value < 0, value.unsigned_abs())
+    } else {
+        let value: u64 = match spec.length {
+            LengthModifier::Char => value as u8 as u64,
+            LengthModifier::Short => value as u16 as u64,
+            LengthModifier::LongLong => value,
+            _ => value as u32 as u64,
+        };
+        (false, value)
+    };
+
+    // A precision of zero means zero is converted to no digits at all.
+    let mut digits = if precision == Some(0) && magnitude == 0 {
+        String::new()
+    } else {
+        match spec.conversion {
+            b'o' => format!("{:o}", magnitude),
+            b'x' => format!("{:x}", magnitude),
+            b'X' => format!("{:X}", magnitude),
+            _ => format!("{}", magnitude),
+        }
+    };
+    if let Some(precision) = precision {
+        if digits.len() < precision {
+            digits = format!("{:0>1$}", digits, precision);
+        }
+    }
+
+    let prefix = match spec.conversion {
+        b'd' | b'i' => sign_prefix(spec, negative),
+        b'o' if spec.alternate_form && !digits.starts_with('0') => {
+            digits.insert(0, '0');
+            ""
+        }
+        b'x' if spec.alternate_form && magnitude != 0 => "0x",
+        b'X' if spec.alternate_form && magnitude != 0 => "0X",
+        _ => "",
+    };
+    (prefix.to_string(), digits)
+}
+
+/// `%f`-style formatting of a finite, non-negative float.
+fn format_fixed(value: f64, precision: usize, alternate_form: bool) -> String {
+    let mut formatted = format!("{:.*}", precision, value);
+    if alternate_form && precision == 0 {
+        formatted.push('.');
+    }
+    formatted
+}
+
+/// `%e`-style formatting of a finite, non-negative float. Unlike Rust, C
+/// always gives the exponent a sign and at least two digits.
+fn format_exponential(value: f64, precision: usize, alternate_form: bool) -> String {
+    let formatted = format!("{:.*e}", precision, value);
+    let (mantissa, exponent) = formatted.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    format!(
+        "{}{}e{}{:02}",
+        mantissa,
+        if alternate_form && precision == 0 {
+            "."
+        } else {
+            ""
+        },
+        if exponent < 0 { '-' } else { '+' },
+        exponent.unsigned_abs()
+    )
+}
+
+/// `%g`-style formatting of a finite, non-negative float, following the C99
+/// rules: the precision is the number of significant digits, and the `%e`
+/// style is used only if the exponent is less than -4 or not less than the
+/// precision.
+fn format_general(value: f64, precision: usize, alternate_form: bool) -> String {
+    let precision = precision.max(1);
+    // This has to be the exponent after rounding to the precision, e.g.
+    // 9.9999999 has the exponent 1 in this sense.
+    let exponent: i32 = if value == 0.0 {
+        0
+    } else {
+        let formatted = format!("{:.*e}", precision - 1, value);
+        formatted.split_once('e').unwrap().1.parse().unwrap()
+    };
+    let formatted = if exponent < -4 || exponent >= precision as i32 {
+        format_exponential(value, precision - 1, alternate_form)
+    } else {
+        let precision = (precision as i32 - 1 - exponent) as usize;
+        format_fixed(value, precision, alternate_form)
+    };
+    if alternate_form {
+        return formatted;
+    }
+
+    // Trailing zeros are removed from the fractional part, and so is the
+    // decimal point if nothing is left after it.
+    let (digits, exponent) = formatted.split_at(formatted.find('e').unwrap_or(formatted.len()));
+    if !digits.contains('.') {
+        return formatted;
+    }
+    let digits = digits.trim_end_matches('0').trim_end_matches('.');
+    format!("{}{}", digits, exponent)
+}
+
+/// Formats the magnitude of a float for the `f`, `F`, `e`, `E`, `g` and `G`
+/// conversions. The sign is handled by the caller.
+fn format_float(spec: &FormatSpec, value: f64, precision: Option<usize>) -> String {
+    let value = value.abs();
+    let formatted = if value.is_nan() {
+        "nan".to_string()
+    } else if value.is_infinite() {
+        "inf".to_string()
+    } else {
+        let precision_or_default = precision.unwrap_or(6);
+        match spec.conversion.to_ascii_lowercase() {
+            b'f' => format_fixed(value, precision_or_default, spec.alternate_form),
+            b'e' => format_exponential(value, precision_or_default, spec.alternate_form),
+            b'g' => format_general(value, precision_or_default, spec.alternate_form),
+            _ => unreachable!(),
+        }
+    };
+    if spec.conversion.is_ascii_uppercase() {
+        formatted.to_ascii_uppercase()
+    } else {
+        formatted
+    }
+}
+
+/// Reads at most `max_len` bytes of a C string. The string doesn't need to be
+/// null-terminated if it is at least that long.
+fn read_cstr_bounded(mem: &Mem, string: ConstPtr<u8>, max_len: Option<usize>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    while max_len.map_or(true, |max_len| bytes.len() < max_len) {
+        let c = mem.read(string + bytes.len().try_into().unwrap());
+        if c == b'\0' {
+            break;
+        }
+        bytes.push(c);
+    }
+    bytes
+}
 
 /// String formatting implementation for `printf` and `NSLog` function families.
 ///
@@ -36,349 +433,172 @@ pub fn printf_inner<const NS_LOG: bool, F: Fn(&Mem, GuestUSize) -> u8>(
     get_format_char: F,
     mut args: VaList,
 ) -> Vec<u8> {
-    let mut res = Vec::<u8>::new();
-
-    let mut format_char_idx = 0;
-
+    let mut format = Vec::new();
     loop {
-        let c = get_format_char(&env.mem, format_char_idx);
-        format_char_idx += 1;
-
+        let c = get_format_char(&env.mem, format.len().try_into().unwrap());
         if c == b'\0' {
             break;
         }
-        if c != b'%' {
-            res.push(c);
-            continue;
-        }
+        format.push(c);
+    }
 
-        let pad_char = if get_format_char(&env.mem, format_char_idx) == b'0' {
-            format_char_idx += 1;
-            '0'
-        } else {
-            ' '
-        };
+    let (pieces, arg_sizes) = parse_format::<NS_LOG>(&format);
 
-        let pad_width = if get_format_char(&env.mem, format_char_idx) == b'*' {
-            let pad_width = args.next::<i32>(env);
-            assert!(pad_width >= 0); // TODO: Implement right-padding
-            format_char_idx += 1;
-            pad_width
-        } else {
-            let mut pad_width: i32 = 0;
-            while let c @ b'0'..=b'9' = get_format_char(&env.mem, format_char_idx) {
-                pad_width = pad_width * 10 + (c - b'0') as i32;
-                format_char_idx += 1;
-            }
-            pad_width
-        };
+    // All arguments are read upfront, because with positional arguments the
+    // order they are used in may not be the order they were passed in.
+    let arg_values: Vec<u64> = arg_sizes
+        .iter()
+        .map(|&size| match size {
+            Some(ArgSize::DoubleWord) => args.next::<u64>(env),
+            // An argument that is skipped by the format string is assumed to
+            // be a word, since there's no way to know.
+            Some(ArgSize::Word) | None => args.next::<u32>(env).into(),
+        })
+        .collect();
+
+    let mut res = Vec::<u8>::new();
 
-        let precision = if get_format_char(&env.mem, format_char_idx) == b'.' {
-            format_char_idx += 1;
-            let mut precision = 0;
-            while let c @ b'0'..=b'9' = get_format_char(&env.mem, format_char_idx) {
-                precision = precision * 10 + (c - b'0') as usize;
-                format_char_idx += 1;
+    for piece in pieces {
+        let spec = match piece {
+            FormatPiece::Literal(c) => {
+                res.push(c);
+                continue;
             }
-            Some(precision)
-        } else {
-            None
+            FormatPiece::Conversion(spec) => spec,
         };
 
-        let length_modifier = if get_format_char(&env.mem, format_char_idx) == b'l' {
-            format_char_idx += 1;
-            if get_format_char(&env.mem, format_char_idx) == b'l' {
-                format_char_idx += 1;
-                Some("ll")
-            } else {
-                Some("l")
+        let value = arg_values[spec.arg];
+        let mut left_justify = spec.left_justify;
+        let width = match spec.width {
+            None => 0,
+            Some(Count::Literal(width)) => width,
+            Some(Count::Arg(arg)) => {
+                // A negative width argument is taken as a `-` flag.
+                let width = arg_values[arg] as u32 as i32;
+                left_justify |= width < 0;
+                width.unsigned_abs() as usize
             }
-        } else {
-            None
+        };
+        let precision = match spec.precision {
+            None => None,
+            Some(Count::Literal(precision)) => Some(precision),
+            // A negative precision argument is taken as if it were omitted.
+            Some(Count::Arg(arg)) => (arg_values[arg] as u32 as i32).try_into().ok(),
         };
 
-        let specifier = get_format_char(&env.mem, format_char_idx);
-        format_char_idx += 1;
-
-        assert!(specifier != b'\0');
-        if specifier == b'%' {
-            res.push(b'%');
-            continue;
-        }
-
-        if precision.is_some() {
-            assert!(
-                INTEGER_SPECIFIERS.contains(&specifier) || FLOAT_SPECIFIERS.contains(&specifier)
-            )
-        }
-
-        match specifier {
-            // Integer specifiers
-            b'c' => {
-                // TODO: support length modifier
-                assert!(length_modifier.is_none());
-                let c: u8 = args.next(env);
-                assert!(pad_char == ' ' && pad_width == 0); // TODO
-                res.push(c);
+        match spec.conversion {
+            b'd' | b'i' | b'o' | b'u' | b'x' | b'X' => {
+                let (prefix, digits) = format_integer(&spec, value, precision);
+                // The `0` flag is ignored if a precision is specified.
+                let zero_pad = spec.zero_pad && precision.is_none();
+                write_padded(
+                    &mut res,
+                    width,
+                    left_justify,
+                    zero_pad,
+                    prefix.as_bytes(),
+                    digits.as_bytes(),
+                );
+            }
+            b'f' | b'F' | b'e' | b'E' | b'g' | b'G' => {
+                let float = f64::from_bits(value);
+                let negative = float.is_sign_negative() && !float.is_nan();
+                let prefix = sign_prefix(&spec, negative);
+                let formatted = format_float(&spec, float, precision);
+                // Infinity and NaN are never zero-padded.
+                let zero_pad = spec.zero_pad && float.is_finite();
+                write_padded(
+                    &mut res,
+                    width,
+                    left_justify,
+                    zero_pad,
+                    prefix.as_bytes(),
+                    formatted.as_bytes(),
+                );
             }
-            // Apple extension? Seemingly works in both NSLog and printf.
-            b'C' => {
-                assert!(length_modifier.is_none());
-                let c: unichar = args.next(env);
-                // TODO
-                assert!(pad_char == ' ' && pad_width == 0);
-                // This will panic if it's a surrogate! This isn't good if
-                // targeting UTF-16 ([NSString stringWithFormat:] etc).
-                let c = char::from_u32(c.into()).unwrap();
-                write!(&mut res, "{}", c).unwrap();
+            b'c' if spec.length != LengthModifier::Long => {
+                let c = value as u8;
+                write_padded(&mut res, width, left_justify, false, b"", &[c]);
             }
-            b's' => {
-                // TODO: support length modifier
-                assert!(length_modifier.is_none());
-                let c_string: ConstPtr<u8> = args.next(env);
-                assert!(pad_char == ' ' && pad_width == 0); // TODO
-                if !c_string.is_null() {
-                    res.extend_from_slice(env.mem.cstr_at(c_string));
+            // `%C` is the same as `%lc`. Both `unichar` (NSLog) and `wint_t`
+            // (printf) are promoted to 32 bits.
+            b'c' | b'C' => {
+                let c = char::from_u32(value as u32).unwrap_or(char::REPLACEMENT_CHARACTER);
+                let mut buf = [0u8; 4];
+                let c = c.encode_utf8(&mut buf).as_bytes();
+                write_padded(&mut res, width, left_justify, false, b"", c);
+            }
+            b's' if spec.length != LengthModifier::Long => {
+                let c_string: ConstPtr<u8> = Ptr::from_bits(value as u32);
+                let string = if !c_string.is_null() {
+                    read_cstr_bounded(&env.mem, c_string, precision)
                 } else {
-                    res.extend_from_slice("(null)".as_bytes());
-                }
+                    b"(null)".to_vec()
+                };
+                write_padded(&mut res, width, left_justify, false, b"", &string);
             }
-            b'S' => {
-                // TODO: support length modifier
-                assert!(length_modifier.is_none());
+            // `%S` is the same as `%ls`.
+            b's' | b'S' => {
                 // TODO: support other locales
                 let ctype_locale = setlocale(env, LC_CTYPE, Ptr::null());
                 assert_eq!(env.mem.read(ctype_locale), b'C');
-                let w_string: ConstPtr<wchar_t> = args.next(env);
-                assert!(pad_char == ' ' && pad_width == 0); // TODO
-                if !w_string.is_null() {
-                    res.extend_from_slice(env.mem.wcstr_at(w_string).as_bytes());
+                let w_string: ConstPtr<wchar_t> = Ptr::from_bits(value as u32);
+                let mut string = if !w_string.is_null() {
+                    env.mem.wcstr_at(w_string)
                 } else {
-                    res.extend_from_slice("(null)".as_bytes());
-                }
-            }
-            b'd' | b'i' | b'u' => {
-                // Note: on 32-bit system int and long are i32,
-                // so single length_modifier is ignored (but not double one!)
-                let int: i64 = if specifier == b'u' {
-                    if length_modifier == Some("ll") {
-                        let uint: u64 = args.next(env);
-                        uint.try_into().unwrap()
-                    } else {
-                        let uint: u32 = args.next(env);
-                        uint.into()
-                    }
-                } else if length_modifier == Some("ll") {
-                    args.next(env)
-                } else {
-                    let int: i32 = args.next(env);
-                    int.into()
+                    "(null)".to_string()
                 };
-
-                let int_with_precision = if precision.is_some_and(|value| value > 0) {
-                    format!("{:01$}", int, precision.unwrap())
-                } else {
-                    format!("{}", int)
-                };
-
-                if pad_width > 0 {
-                    let pad_width = pad_width as usize;
-                    if pad_char == '0' && precision.is_none() {
-                        write!(&mut res, "{:0>1$}", int_with_precision, pad_width).unwrap();
-                    } else {
-                        write!(&mut res, "{:>1$}", int_with_precision, pad_width).unwrap();
+                // The precision is in bytes, but a partial character is never
+                // written.
+                if let Some(precision) = precision {
+                    while string.len() > precision {
+                        string.pop();
                     }
-                } else {
-                    res.extend_from_slice(int_with_precision.as_bytes());
+                }
+                write_padded(&mut res, width, left_justify, false, b"", string.as_bytes());
+            }
+            b'p' => {
+                let digits = format!("{:x}", value as u32);
+                write_padded(
+                    &mut res,
+                    width,
+                    left_justify,
+                    spec.zero_pad,
+                    b"0x",
+                    digits.as_bytes(),
+                );
+            }
+            b'n' => {
+                let count = res.len();
+                let ptr: MutVoidPtr = Ptr::from_bits(value as u32);
+                match spec.length {
+                    LengthModifier::Char => env.mem.write(ptr.cast(), count as u8),
+                    LengthModifier::Short => env.mem.write(ptr.cast(), count as u16),
+                    LengthModifier::LongLong => env.mem.write(ptr.cast(), count as u64),
+                    _ => env.mem.write(ptr.cast(), count as u32),
                 }
             }
             b'@' if NS_LOG => {
-                assert!(length_modifier.is_none());
-                let object: id = args.next(env);
+                let object: id = Ptr::from_bits(value as u32);
                 // TODO: use localized description if available?
                 let description: id = msg![env; object description];
-                if description != nil {
+                let description = if description != nil {
                     // TODO: avoid copy
                     // TODO: what if the description isn't valid UTF-16?
-                    let description = ns_string::to_rust_string(env, description);
-                    write!(&mut res, "{}", description).unwrap();
-                } else {
-                    write!(&mut res, "(null)").unwrap();
-                }
-            }
-            b'x' => {
-                assert!(precision.is_none());
-                // Note: on 32-bit system unsigned int and unsigned long
-                // are u32, so length_modifier is ignored
-                let uint: u32 = args.next(env);
-                if pad_width > 0 {
-                    let pad_width = pad_width as usize;
-                    if pad_char == '0' && precision.is_none() {
-                        write!(&mut res, "{:0>1$x}", uint, pad_width).unwrap();
-                    } else {
-                        write!(&mut res, "{:>1$x}", uint, pad_width).unwrap();
-                    }
-                } else {
-                    res.extend_from_slice(format!("{:x}", uint).as_bytes());
-                }
-            }
-            b'X' => {
-                assert!(precision.is_none());
-                // Note: on 32-bit system unsigned int and unsigned long
-                // are u32, so length_modifier is ignored
-                let uint: u32 = args.next(env);
-                if pad_width > 0 {
-                    let pad_width = pad_width as usize;
-                    if pad_char == '0' && precision.is_none() {
-                        write!(&mut res, "{:0>1$X}", uint, pad_width).unwrap();
-                    } else {
-                        write!(&mut res, "{:>1$X}", uint, pad_width).unwrap();
-                    }
+                    ns_string::to_rust_string(env, description).into_owned()
                 } else {
-                    res.extend_from_slice(format!("{:X}", uint).as_bytes());
-                }
-            }
-            b'p' => {
-                assert!(length_modifier.is_none());
-                let ptr: MutVoidPtr = args.next(env);
-                res.extend_from_slice(format!("{:?}", ptr).as_bytes());
-            }
-            // Float specifiers
-            b'f' => {
-                let float: f64 = args.next(env);
-                let pad_width = pad_width as usize;
-                let precision = precision.unwrap_or(6);
-                if pad_char == '0' {
-                    res.extend_from_slice(
-                        format!("{:01$.2$}", float, pad_width, precision).as_bytes(),
-                    );
-                } else {
-                    res.extend_from_slice(
-                        format!("{:1$.2$}", float, pad_width, precision).as_bytes(),
-                    );
-                }
-            }
-            b'e' => {
-                let float: f64 = args.next(env);
-                let pad_width = pad_width as usize;
-                let precision = precision.unwrap_or(6);
-
-                let exponent = float.abs().log10().floor();
-                let mantissa = float.abs() / 10f64.powf(exponent);
-                let sign = if float.is_sign_negative() { "-" } else { "" };
-                if pad_char == '0' {
-                    let float_exp_notation =
-                        format!("{0:.1$}e{2:+03}", mantissa, precision, exponent);
-                    res.extend_from_slice(
-                        format!(
-                            "{0}{1:0>2$}",
-                            sign,
-                            float_exp_notation,
-                            pad_width.saturating_sub(sign.len())
-                        )
-                        .as_bytes(),
-                    );
-                } else {
-                    let float_exp_notation =
-                        format!("{0}{1:.2$}e{3:+03}", sign, mantissa, precision, exponent);
-                    res.extend_from_slice(
-                        format!("{0:>1$}", float_exp_notation, pad_width).as_bytes(),
-                    );
-                }
-            }
-            b'g' => {
-                let float: f64 = args.next(env);
-                let pad_width = pad_width as usize;
-
-                let sign = if float.is_sign_negative() { "-" } else { "" };
-
-                let formatted_f_without_padding_or_sign = {
-                    // Precision in %g means max number of decimal digits in
-                    // the mantissa. For that, we first calculate the length
-                    // of the integer part and then we substract it from
-                    // precision and use the result in the format! statement
-                    let float_trunc_len = (float.abs().trunc() as i32).to_string().len();
-                    // Format without padding
-                    if let Some(precision) = precision {
-                        format!(
-                            "{:.1$}",
-                            float.abs(),
-                            precision.saturating_sub(float_trunc_len)
-                        )
-                    } else {
-                        format!("{:.4}", float.abs())
-                    }
-                };
-                let formatted_f = {
-                    if pad_char == '0' {
-                        format!(
-                            "{}{:0>2$}",
-                            sign,
-                            formatted_f_without_padding_or_sign,
-                            pad_width - sign.len()
-                        )
-                    } else {
-                        let formatted_f_with_sign =
-                            format!("{}{}", sign, formatted_f_without_padding_or_sign);
-                        format!("{:>1$}", formatted_f_with_sign, pad_width)
-                    }
-                };
-
-                let formatted_e_without_padding_or_sign = {
-                    let exponent = float.abs().log10().floor();
-                    let mantissa = float.abs() / 10f64.powf(exponent);
-                    // Precision in %g means max number of decimal digits in
-                    // the mantissa. For that, we first calculate the length
-                    // of the mantissa's int part and then we substract it from
-                    // precision and use the result in the format! statement
-                    let mantissa_trunc_len = (mantissa.trunc() as i32).to_string().len();
-                    // Format without padding
-                    if let Some(precision) = precision {
-                        if precision > mantissa_trunc_len {
-                            format!(
-                                "{0:.1$}e{2:+03}",
-                                mantissa,
-                                precision - mantissa_trunc_len,
-                                exponent
-                            )
-                        } else {
-                            format!("{:.0}e{:+03}", mantissa, exponent)
-                        }
-                    } else {
-                        format!("{}e{:+03}", mantissa, exponent)
-                    }
+                    "(null)".to_string()
                 };
-                let formatted_e = if pad_char == '0' {
-                    format!(
-                        "{0}{1:0>2$}",
-                        sign,
-                        formatted_e_without_padding_or_sign,
-                        pad_width.saturating_sub(sign.len())
-                    )
-                } else {
-                    let without_padding_with_sign =
-                        format!("{}{}", sign, formatted_e_without_padding_or_sign);
-                    format!("{0:>1$}", without_padding_with_sign, pad_width)
-                };
-
-                // Use shortest formatted string
-                let result = if formatted_e_without_padding_or_sign.len()
-                    < formatted_f_without_padding_or_sign.len()
-                    || precision.is_some_and(|x| x == 0)
-                {
-                    formatted_e
-                } else {
-                    formatted_f
-                };
-
-                res.extend_from_slice(result.as_bytes());
+                write_padded(
+                    &mut res,
+                    width,
+                    left_justify,
+                    false,
+                    b"",
+                    description.as_bytes(),
+                );
             }
-            // TODO: more specifiers
-            _ => unimplemented!(
-                "Format character '{}'. Formatted up to index {}",
-                specifier as char,
-                format_char_idx
-            ),
+            _ => unreachable!(),
         }
     }
 
@@ -578,171 +798,308 @@ fn printf(env: &mut Environment, format: ConstPtr<u8>, args: DotDotDot) -> i32 {
 
 // TODO: more printf variants
 
-fn sscanf_common(
-    env: &mut Environment,
-    src: ConstPtr<u8>,
-    format: ConstPtr<u8>,
-    mut args: VaList,
-) -> i32 {
-    let mut src_ptr = src.cast_mut();
-    let mut format_char_idx = 0;
+/// Parses an integer for the `scanf` family like `strtoull` would, but limited
+/// to `max_len` bytes. `base` 0 means the base is detected from the prefix.
+/// Returns the value (wrapped to 64 bits, negated if there was a `-`) and the
+/// number of bytes consumed, or [None] if there were no digits.
+fn scan_integer(string: &[u8], max_len: usize, base: u32) -> Option<(u64, usize)> {
+    let string = &string[..string.len().min(max_len)];
+    let mut idx = 0;
+
+    let negative = match byte_at(string, idx) {
+        b'-' => {
+            idx += 1;
+            true
+        }
+        b'+' => {
+            idx += 1;
+            false
+        }
+        _ => false,
+    };
 
-    let mut matched_args = 0;
+    let has_hex_prefix = byte_at(string, idx) == b'0'
+        && matches!(byte_at(string, idx + 1), b'x' | b'X')
+        && byte_at(string, idx + 2).is_ascii_hexdigit();
+    let base = match base {
+        0 if has_hex_prefix => 16,
+        0 if byte_at(string, idx) == b'0' => 8,
+        0 => 10,
+        base => base,
+    };
+    if base == 16 && has_hex_prefix {
+        idx += 2;
+    }
 
-    loop {
-        let c = env.mem.read(format + format_char_idx);
-        format_char_idx += 1;
+    let digits_start = idx;
+    let mut value: u64 = 0;
+    while let Some(digit) = (byte_at(string, idx) as char).to_digit(base) {
+        value = value.wrapping_mul(base.into()).wrapping_add(digit.into());
+        idx += 1;
+    }
+    if idx == digits_start {
+        return None;
+    }
 
-        if c == b'\0' {
-            break;
+    Some((
+        if negative {
+            value.wrapping_neg()
+        } else {
+            value
+        },
+        idx,
+    ))
+}
+
+/// Finds the length of a floating-point number for the `scanf` family,
+/// limited to `max_len` bytes, and parses it.
+fn scan_float(string: &[u8], max_len: usize) -> Option<(f64, usize)> {
+    let string = &string[..string.len().min(max_len)];
+    let mut idx = 0;
+    if matches!(byte_at(string, idx), b'+' | b'-') {
+        idx += 1;
+    }
+
+    let rest = &string[idx..];
+    let special_len = ["infinity", "inf", "nan"].iter().find_map(|special| {
+        (rest.len() >= special.len()
+            && rest[..special.len()].eq_ignore_ascii_case(special.as_bytes()))
+        .then_some(special.len())
+    });
+    if let Some(special_len) = special_len {
+        idx += special_len;
+    } else {
+        let mut digit_count = 0;
+        while byte_at(string, idx).is_ascii_digit() {
+            idx += 1;
+            digit_count += 1;
         }
-        if c != b'%' {
-            if isspace(env, format + format_char_idx - 1) && isspace(env, src_ptr.cast_const()) {
-                while isspace(env, src_ptr.cast_const()) {
-                    src_ptr += 1;
+        if byte_at(string, idx) == b'.' {
+            idx += 1;
+            while byte_at(string, idx).is_ascii_digit() {
+                idx += 1;
+                digit_count += 1;
+            }
+        }
+        if digit_count == 0 {
+            return None;
+        }
+        // The exponent is only part of the number if it has digits.
+        if matches!(byte_at(string, idx), b'e' | b'E') {
+            let mut exponent_end = idx + 1;
+            if matches!(byte_at(string, exponent_end), b'+' | b'-') {
+                exponent_end += 1;
+            }
+            if byte_at(string, exponent_end).is_ascii_digit() {
+                idx = exponent_end;
+                while byte_at(string, idx).is_ascii_digit() {
+                    idx += 1;
                 }
-                continue;
             }
-            let cc = env.mem.read(src_ptr);
-            if c != cc {
+        }
+    }
+
+    let number = std::str::from_utf8(&string[..idx]).unwrap();
+    Some((number.parse().unwrap(), idx))
+}
+
+/// Parses a `%[` set at `idx` (just after the `[`) and returns a function that
+/// checks whether a byte is matched by it.
+fn parse_scan_set(format: &[u8], idx: &mut usize) -> impl Fn(u8) -> bool {
+    let negated = byte_at(format, *idx) == b'^';
+    if negated {
+        *idx += 1;
+    }
+    let mut members = [false; 256];
+    // A `]` right at the start is a member rather than the end of the set.
+    let mut first = true;
+    loop {
+        let c = byte_at(format, *idx);
+        assert!(c != b'\0', "Unterminated %[ set in scanf format string");
+        *idx += 1;
+        if c == b']' && !first {
+            break;
+        }
+        first = false;
+        let range_end = byte_at(format, *idx + 1);
+        if byte_at(format, *idx) == b'-' && range_end != b']' && range_end != b'\0' {
+            *idx += 2;
+            for member in c..=range_end {
+                members[member as usize] = true;
+            }
+        } else {
+            members[c as usize] = true;
+        }
+    }
+    move |c| members[c as usize] != negated
+}
+
+/// Shared implementation of the `scanf` family. `src` and `format` don't
+/// include the null terminator.
+fn sscanf_common(env: &mut Environment, src: &[u8], format: &[u8], mut args: VaList) -> i32 {
+    let mut src_idx = 0;
+    let mut format_idx = 0;
+
+    let mut matched_args = 0;
+    // Running out of input before the first conversion means EOF is returned.
+    let mut converted_any = false;
+    let input_failure = |matched_args, converted_any| {
+        if converted_any {
+            matched_args
+        } else {
+            EOF
+        }
+    };
+
+    let skip_whitespace = |src_idx: &mut usize| {
+        while is_c_space(byte_at(src, *src_idx)) {
+            *src_idx += 1;
+        }
+    };
+
+    while format_idx < format.len() {
+        let c = format[format_idx];
+        format_idx += 1;
+
+        // Whitespace in the format matches any amount of whitespace,
+        // including none.
+        if is_c_space(c) {
+            skip_whitespace(&mut src_idx);
+            continue;
+        }
+        if c != b'%' || byte_at(format, format_idx) == b'%' {
+            if c == b'%' {
+                format_idx += 1;
+                skip_whitespace(&mut src_idx);
+            }
+            if src_idx == src.len() {
+                return input_failure(matched_args, converted_any);
+            }
+            if src[src_idx] != c {
                 return matched_args;
             }
-            src_ptr += 1;
+            src_idx += 1;
             continue;
         }
 
-        let mut max_width: i32 = 0;
-        while let c @ b'0'..=b'9' = env.mem.read(format + format_char_idx) {
-            max_width = max_width * 10 + (c - b'0') as i32;
-            format_char_idx += 1;
+        let suppress = byte_at(format, format_idx) == b'*';
+        if suppress {
+            format_idx += 1;
         }
+        let max_width = parse_decimal(format, &mut format_idx);
+        let length = parse_length_modifier(format, &mut format_idx);
+        let specifier = byte_at(format, format_idx);
+        format_idx += 1;
 
-        let length_modifier = if env.mem.read(format + format_char_idx) == b'h' {
-            format_char_idx += 1;
-            Some(b'h')
-        } else {
-            None
-        };
-
-        let specifier = env.mem.read(format + format_char_idx);
-        format_char_idx += 1;
+        if !matches!(specifier, b'c' | b'[' | b'n') {
+            skip_whitespace(&mut src_idx);
+        }
+        if specifier != b'n' && src_idx == src.len() {
+            return input_failure(matched_args, converted_any);
+        }
+        converted_any = true;
+        let rest = &src[src_idx..];
+        let max_len = max_width.unwrap_or(usize::MAX);
 
         match specifier {
-            b'd' | b'i' => {
-                let base: u32 = if specifier == b'd' {
-                    10
-                } else {
-                    // automatic base detection in strtol
-                    0
+            b'd' | b'i' | b'o' | b'u' | b'x' | b'X' | b'p' => {
+                let base = match specifier {
+                    b'd' | b'u' => 10,
+                    b'i' => 0,
+                    b'o' => 8,
+                    _ => 16,
                 };
-
-                match length_modifier {
-                    Some(lm) => {
-                        match lm {
-                            b'h' => {
-                                // signed short* or unsigned short*
-                                match strtol_inner(env, src_ptr.cast_const(), base) {
-                                    Ok((val, len)) => {
-                                        if max_width > 0 {
-                                            assert_eq!(max_width, len as i32);
-                                        }
-                                        src_ptr += len;
-                                        let c_int_ptr: ConstPtr<i16> = args.next(env);
-                                        env.mem
-                                            .write(c_int_ptr.cast_mut(), val.try_into().unwrap());
-                                    }
-                                    Err(_) => break,
-                                }
-                            }
-                            _ => unimplemented!(),
-                        }
+                let Some((value, len)) = scan_integer(rest, max_len, base) else {
+                    return matched_args;
+                };
+                src_idx += len;
+                if !suppress {
+                    let ptr: MutVoidPtr = args.next(env);
+                    match length {
+                        _ if specifier == b'p' => env.mem.write(ptr.cast(), value as u32),
+                        LengthModifier::Char => env.mem.write(ptr.cast(), value as u8),
+                        LengthModifier::Short => env.mem.write(ptr.cast(), value as u16),
+                        LengthModifier::LongLong => env.mem.write(ptr.cast(), value),
+                        _ => env.mem.write(ptr.cast(), value as u32),
                     }
-                    _ => match strtol_inner(env, src_ptr.cast_const(), base) {
-                        Ok((val, len)) => {
-                            src_ptr += len;
-                            let c_int_ptr: ConstPtr<i32> = args.next(env);
-                            env.mem.write(c_int_ptr.cast_mut(), val);
-                        }
-                        Err(_) => break,
-                    },
                 }
             }
-            b'f' => {
-                assert_eq!(max_width, 0);
-                assert!(length_modifier.is_none());
-                match atof_inner(env, src_ptr.cast_const()) {
-                    Ok((val, len)) => {
-                        src_ptr += len;
-                        let c_int_ptr: ConstPtr<f32> = args.next(env);
-                        env.mem.write(c_int_ptr.cast_mut(), val as f32);
+            b'f' | b'F' | b'e' | b'E' | b'g' | b'G' => {
+                let Some((value, len)) = scan_float(rest, max_len) else {
+                    return matched_args;
+                };
+                src_idx += len;
+                if !suppress {
+                    let ptr: MutVoidPtr = args.next(env);
+                    match length {
+                        LengthModifier::Long | LengthModifier::LongDouble => {
+                            env.mem.write(ptr.cast(), value)
+                        }
+                        _ => env.mem.write(ptr.cast(), value as f32),
                     }
-                    Err(_) => break,
-                }
-            }
-            b'x' | b'X' => {
-                let c_len: GuestUSize = strlen(env, src_ptr.cast_const());
-                if max_width != 0 {
-                    assert_eq!(c_len, max_width.try_into().unwrap());
                 }
-                let val: u32 = strtoul(env, src_ptr.cast_const(), Ptr::null(), 16);
-                src_ptr += c_len;
-                let c_u32_ptr: ConstPtr<u32> = args.next(env);
-                env.mem.write(c_u32_ptr.cast_mut(), val);
             }
-            b'[' => {
-                assert_eq!(max_width, 0);
-                assert!(length_modifier.is_none());
-                // TODO: support ranges like [0-9]
-                // [set] case
-                let mut c = env.mem.read(format + format_char_idx);
-                format_char_idx += 1;
-                // TODO: only `not in the set` for a moment
-                assert_eq!(c, b'^');
-                // Build set
-                let mut set: HashSet<u8> = HashSet::new();
-                // TODO: set can contain ']' as well
-                c = env.mem.read(format + format_char_idx);
-                format_char_idx += 1;
-                while c != b']' {
-                    set.insert(c);
-                    c = env.mem.read(format + format_char_idx);
-                    format_char_idx += 1;
-                }
-                let mut dst_ptr: MutPtr<u8> = args.next(env);
-                // Consume `src` while chars are not in the set
-                let mut cc = env.mem.read(src_ptr);
-                src_ptr += 1;
-                // TODO: handle end of src string
-                while !set.contains(&cc) {
-                    env.mem.write(dst_ptr, cc);
-                    dst_ptr += 1;
-                    cc = env.mem.read(src_ptr);
-                    src_ptr += 1;
+            b's' | b'[' | b'c' => {
+                let len = match specifier {
+                    b's' => rest
+                        .iter()
+                        .take(max_len)
+                        .take_while(|&&c| !is_c_space(c))
+                        .count(),
+                    b'[' => {
+                        let is_member = parse_scan_set(format, &mut format_idx);
+                        let len = rest
+                            .iter()
+                            .take(max_len)
+                            .take_while(|&&c| is_member(c))
+                            .count();
+                        if len == 0 {
+                            return matched_args;
+                        }
+                        len
+                    }
+                    _ => {
+                        let len = max_width.unwrap_or(1);
+                        if rest.len() < len {
+                            return input_failure(matched_args, converted_any);
+                        }
+                        len
+                    }
+                };
+                src_idx += len;
+                if !suppress {
+                    assert!(length == LengthModifier::None); // TODO: wide strings
+                    let dst: MutPtr<u8> = args.next(env);
+                    let len_guest: GuestUSize = len.try_into().unwrap();
+                    env.mem
+                        .bytes_at_mut(dst, len_guest)
+                        .copy_from_slice(&rest[..len]);
+                    // `%c` doesn't add a null terminator.
+                    if specifier != b'c' {
+                        env.mem.write(dst + len_guest, b'\0');
+                    }
                 }
-                // we need to backtrack one position
-                src_ptr -= 1;
-                env.mem.write(dst_ptr, b'\0');
             }
-            b's' => {
-                assert_eq!(max_width, 0);
-                assert!(length_modifier.is_none());
-                let mut dst_ptr: MutPtr<u8> = args.next(env);
-                loop {
-                    if !isspace(env, src_ptr.cast_const()) {
-                        env.mem.write(dst_ptr, env.mem.read(src_ptr));
-                        src_ptr += 1;
-                        dst_ptr += 1;
-                    } else {
-                        break;
+            b'n' => {
+                // This doesn't count as a conversion.
+                if !suppress {
+                    let ptr: MutVoidPtr = args.next(env);
+                    match length {
+                        LengthModifier::Char => env.mem.write(ptr.cast(), src_idx as u8),
+                        LengthModifier::Short => env.mem.write(ptr.cast(), src_idx as u16),
+                        LengthModifier::LongLong => env.mem.write(ptr.cast(), src_idx as u64),
+                        _ => env.mem.write(ptr.cast(), src_idx as u32),
                     }
                 }
-                env.mem.write(dst_ptr, b'\0');
+                continue;
             }
             // TODO: more specifiers
             _ => unimplemented!("Format character '{}'", specifier as char),
         }
 
-        matched_args += 1;
+        if !suppress {
+            matched_args += 1;
+        }
     }
 
     matched_args
@@ -760,7 +1117,26 @@ fn sscanf(env: &mut Environment, src: ConstPtr<u8>, format: ConstPtr<u8>, args:
         env.mem.cstr_at_utf8(format)
     );
 
-    sscanf_common(env, src, format, args.start())
+    let src = env.mem.cstr_at(src).to_vec();
+    let format = env.mem.cstr_at(format).to_vec();
+    sscanf_common(env, &src, &format, args.start())
+}
+
+fn sscanf_l(
+    env: &mut Environment,
+    src: ConstPtr<u8>,
+    locale: MutVoidPtr,
+    format: ConstPtr<u8>,
+    args: DotDotDot,
+) -> i32 {
+    // TODO: support other locales
+    log_dbg!(
+        "sscanf_l({:?}, {:?}, {:?}, ...) ignoring the locale",
+        src,
+        locale,
+        format
+    );
+    sscanf(env, src, format, args)
 }
 
 fn swscanf(
@@ -785,14 +1161,9 @@ fn swscanf(
         format,
         w_format
     );
-    // TODO: refactor code to parametrise sscanf_common()
-    // for normal and wide strings instead
-    let c_string = env.mem.alloc_and_write_cstr(w_string.as_bytes());
-    let c_format = env.mem.alloc_and_write_cstr(w_format.as_bytes());
-    let res = sscanf(env, c_string.cast_const(), c_format.cast_const(), args);
-    env.mem.free(c_string.cast());
-    env.mem.free(c_format.cast());
-    res
+    // TODO: parametrise sscanf_common() for normal and wide strings, so that
+    // %s etc. can write wide strings.
+    sscanf_common(env, w_string.as_bytes(), w_format.as_bytes(), args.start())
 }
 
 fn vsscanf(env: &mut Environment, src: ConstPtr<u8>, format: ConstPtr<u8>, arg: VaList) -> i32 {
@@ -806,7 +1177,9 @@ fn vsscanf(env: &mut Environment, src: ConstPtr<u8>, format: ConstPtr<u8>, arg:
         env.mem.cstr_at_utf8(format)
     );
 
-    sscanf_common(env, src, format, arg)
+    let src = env.mem.cstr_at(src).to_vec();
+    let format = env.mem.cstr_at(format).to_vec();
+    sscanf_common(env, &src, &format, arg)
 }
 
 fn fprintf(
@@ -858,6 +1231,7 @@ fn vfprintf(env: &mut Environment, stream: MutPtr<FILE>, format: ConstPtr<u8>, a
 
 pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(sscanf(_, _, _)),
+    export_c_func!(sscanf_l(_, _, _, _)),
     export_c_func!(swscanf(_, _, _)),
     export_c_func!(vsscanf(_, _, _)),
     export_c_func!(snprintf(_, _, _, _)),
@@ -874,8 +1248,7 @@ pub const FUNCTIONS: FunctionExports = &[
 
 // Helper function, not a part of printf family
 // TODO: write proper libc's isspace()
-pub fn isspace(env: &mut Environment, src: ConstPtr<u8>) -> bool {
-    let c = env.mem.read(src);
+fn is_c_space(c: u8) -> bool {
     // Rust's definition of whitespace excludes vertical tab, unlike C's
     c.is_ascii_whitespace() || c == b'\x0b'
 }
@@ -9,7 +9,7 @@ use crate::abi::{CallFromHost, GuestFunction};
 use crate::dyld::{export_c_func, export_c_func_aliased, FunctionExports};
 use crate::fs::{resolve_path, GuestPath};
 use crate::libc::clocale::{setlocale, LC_CTYPE};
-use crate::libc::errno::set_errno;
+use crate::libc::errno::{set_errno, ENOMEM};
 use crate::libc::string::strlen;
 use crate::libc::wchar::wchar_t;
 use crate::mem::{ConstPtr, ConstVoidPtr, GuestUSize, MutPtr, MutVoidPtr, Ptr};
@@ -33,15 +33,21 @@ fn malloc(env: &mut Environment, size: GuestUSize) -> MutVoidPtr {
     // TODO: handle errno properly
     set_errno(env, 0);
 
-    env.mem.alloc(size)
+    env.mem.try_alloc(size).unwrap_or_else(|| {
+        set_errno(env, ENOMEM);
+        Ptr::null()
+    })
 }
 
 fn calloc(env: &mut Environment, count: GuestUSize, size: GuestUSize) -> MutVoidPtr {
     // TODO: handle errno properly
     set_errno(env, 0);
 
-    let total = size.checked_mul(count).unwrap();
-    env.mem.alloc(total)
+    let Some(total) = size.checked_mul(count) else {
+        set_errno(env, ENOMEM);
+        return Ptr::null();
+    };
+    malloc(env, total)
 }
 
 fn realloc(env: &mut Environment, ptr: MutVoidPtr, size: GuestUSize) -> MutVoidPtr {
@@ -51,7 +57,10 @@ fn realloc(env: &mut Environment, ptr: MutVoidPtr, size: GuestUSize) -> MutVoidP
     if ptr.is_null() {
         return malloc(env, size);
     }
-    env.mem.realloc(ptr, size)
+    env.mem.try_realloc(ptr, size).unwrap_or_else(|| {
+        set_errno(env, ENOMEM);
+        Ptr::null()
+    })
 }
 
 fn free(env: &mut Environment, ptr: MutVoidPtr) {
@@ -214,6 +223,8 @@ fn exit(env: &mut Environment, exit_code: i32) {
     set_errno(env, 0);
 
     echo!("App called exit(), exiting.");
+    crate::recording::finish_recording(env);
+    crate::profiler::write_report(env);
     std::process::exit(exit_code);
 }
 
@@ -4,5 +4,7 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
+pub mod select;
+pub mod socket;
 pub mod timeb;
 pub mod utsname;
@@ -0,0 +1,149 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `sys/select.h`
+
+use super::socket::{socket_readiness, SocketWait};
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::libc::errno::{set_errno, EBADF, EINVAL};
+use crate::libc::time::timeval;
+use crate::mem::{MutPtr, SafeRead};
+use crate::Environment;
+use std::time::Duration;
+
+pub const FD_SETSIZE: i32 = 1024;
+
+#[allow(non_camel_case_types)]
+#[repr(C, packed)]
+pub struct fd_set {
+    fds_bits: [u32; FD_SETSIZE as usize / 32],
+}
+unsafe impl SafeRead for fd_set {}
+
+impl fd_set {
+    fn empty() -> Self {
+        fd_set {
+            fds_bits: [0; FD_SETSIZE as usize / 32],
+        }
+    }
+    fn is_set(&self, fd: i32) -> bool {
+        let bits = self.fds_bits;
+        bits[fd as usize / 32] & (1 << (fd % 32)) != 0
+    }
+    fn set(&mut self, fd: i32) {
+        let mut bits = self.fds_bits;
+        bits[fd as usize / 32] |= 1 << (fd % 32);
+        self.fds_bits = bits;
+    }
+}
+
+fn select(
+    env: &mut Environment,
+    nfds: i32,
+    readfds: MutPtr<fd_set>,
+    writefds: MutPtr<fd_set>,
+    errorfds: MutPtr<fd_set>,
+    timeout: MutPtr<timeval>,
+) -> i32 {
+    set_errno(env, 0);
+
+    if !(0..=FD_SETSIZE).contains(&nfds) {
+        set_errno(env, EINVAL);
+        return -1;
+    }
+
+    let read_set = |env: &Environment, set: MutPtr<fd_set>| {
+        if set.is_null() {
+            fd_set::empty()
+        } else {
+            env.mem.read(set)
+        }
+    };
+    let in_read = read_set(env, readfds);
+    let in_write = read_set(env, writefds);
+    let in_error = read_set(env, errorfds);
+
+    for fd in 0..nfds {
+        if (in_read.is_set(fd) || in_write.is_set(fd) || in_error.is_set(fd))
+            && !env.libc_state.posix_io.is_open(fd)
+        {
+            log!("Warning: select() with invalid file descriptor {}", fd);
+            set_errno(env, EBADF);
+            return -1;
+        }
+    }
+
+    let deadline = if timeout.is_null() {
+        None
+    } else {
+        let timeout = env.mem.read(timeout);
+        let (tv_sec, tv_usec) = (timeout.tv_sec, timeout.tv_usec);
+        if tv_sec < 0 || !(0..1_000_000).contains(&tv_usec) {
+            set_errno(env, EINVAL);
+            return -1;
+        }
+        let duration = Duration::from_secs(tv_sec as u64) + Duration::from_micros(tv_usec as u64);
+        Some(env.clock.now() + duration)
+    };
+
+    let (out_read, out_write, count) = loop {
+        let mut out_read = fd_set::empty();
+        let mut out_write = fd_set::empty();
+        let mut count = 0;
+        let mut wait_read = Vec::new();
+        let mut wait_write = Vec::new();
+        for fd in 0..nfds {
+            let wants_read = in_read.is_set(fd);
+            let wants_write = in_write.is_set(fd);
+            if !wants_read && !wants_write {
+                continue;
+            }
+            // Files are always ready.
+            let (readable, writable) = socket_readiness(env, fd).unwrap_or((true, true));
+            if wants_read && readable {
+                out_read.set(fd);
+                count += 1;
+            } else if wants_read {
+                wait_read.push(fd);
+            }
+            if wants_write && writable {
+                out_write.set(fd);
+                count += 1;
+            } else if wants_write {
+                wait_write.push(fd);
+            }
+        }
+
+        if count > 0 || deadline.map_or(false, |deadline| env.clock.now() >= deadline) {
+            break (out_read, out_write, count);
+        }
+        // Only this thread waits, other threads keep running.
+        env.block_on_socket(SocketWait::Any(wait_read, wait_write), deadline);
+    };
+
+    if !readfds.is_null() {
+        env.mem.write(readfds, out_read);
+    }
+    if !writefds.is_null() {
+        env.mem.write(writefds, out_write);
+    }
+    // Exceptional conditions (out-of-band data) are never reported.
+    if !errorfds.is_null() {
+        env.mem.write(errorfds, fd_set::empty());
+    }
+
+    log_dbg!(
+        "select({}, {:?}, {:?}, {:?}, {:?}) => {}",
+        nfds,
+        readfds,
+        writefds,
+        errorfds,
+        timeout,
+        count
+    );
+    count
+}
+
+pub const FUNCTIONS: FunctionExports = &[export_c_func!(select(_, _, _, _, _))];
@@ -0,0 +1,972 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! `sys/socket.h` and `netinet/in.h` (BSD sockets)
+//!
+//! Guest sockets are backed by host sockets from [std::net]. Only IPv4 TCP
+//! client sockets and UDP sockets are supported. TCP sockets don't get a host
+//! socket until they're connected, because [std::net] has no way to create an
+//! unconnected TCP socket. For the same reason, `connect()` is done on a
+//! helper thread.
+//!
+//! Host sockets are always in non-blocking mode. When a blocking guest socket
+//! would block, the calling guest thread is blocked instead (see
+//! [crate::environment::ThreadBlock::Socket]), so that other guest threads
+//! keep running.
+//!
+//! File descriptors for sockets are allocated from the same table as files,
+//! see [crate::libc::posix_io].
+
+use crate::dyld::{export_c_func, FunctionExports};
+use crate::libc::errno::{
+    set_errno, EACCES, EADDRINUSE, EADDRNOTAVAIL, EAFNOSUPPORT, EAGAIN, EALREADY, EBADF,
+    ECONNABORTED, ECONNREFUSED, ECONNRESET, EDESTADDRREQ, EFAULT, EINPROGRESS, EINTR, EINVAL, EIO,
+    EISCONN, ENETUNREACH, ENOPROTOOPT, ENOTCONN, ENOTSOCK, EPIPE, EPROTONOSUPPORT, ETIMEDOUT,
+    EWOULDBLOCK,
+};
+use crate::libc::posix_io::{FileDescriptor, OpenFd};
+use crate::mem::{ConstPtr, ConstVoidPtr, GuestISize, GuestUSize, MutPtr, MutVoidPtr, SafeRead};
+use crate::Environment;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, SocketAddrV4, TcpStream, UdpSocket};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::Duration;
+
+#[allow(non_camel_case_types)]
+pub type socklen_t = u32;
+#[allow(non_camel_case_types)]
+pub type sa_family_t = u8;
+#[allow(non_camel_case_types)]
+pub type in_port_t = u16;
+#[allow(non_camel_case_types)]
+pub type in_addr_t = u32;
+
+pub const AF_INET: i32 = 2;
+
+pub const SOCK_STREAM: i32 = 1;
+pub const SOCK_DGRAM: i32 = 2;
+
+pub const IPPROTO_TCP: i32 = 6;
+pub const IPPROTO_UDP: i32 = 17;
+
+pub const SOL_SOCKET: i32 = 0xffff;
+pub const SO_REUSEADDR: i32 = 0x4;
+pub const SO_KEEPALIVE: i32 = 0x8;
+pub const SO_BROADCAST: i32 = 0x20;
+pub const SO_SNDBUF: i32 = 0x1001;
+pub const SO_RCVBUF: i32 = 0x1002;
+pub const SO_ERROR: i32 = 0x1007;
+pub const SO_TYPE: i32 = 0x1008;
+pub const SO_NOSIGPIPE: i32 = 0x1022;
+
+pub const TCP_NODELAY: i32 = 0x1;
+
+pub const MSG_PEEK: i32 = 0x2;
+pub const MSG_DONTWAIT: i32 = 0x80;
+
+pub const SHUT_RD: i32 = 0;
+pub const SHUT_WR: i32 = 1;
+pub const SHUT_RDWR: i32 = 2;
+
+/// How often a guest thread blocked on a socket checks whether it's ready.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Opaque type, only ever used via pointers. The real layout is determined by
+/// the address family, and only [sockaddr_in] is supported.
+#[allow(non_camel_case_types)]
+pub struct sockaddr {}
+
+#[allow(non_camel_case_types)]
+#[derive(Default)]
+#[repr(C, packed)]
+pub struct sockaddr_in {
+    pub sin_len: u8,
+    pub sin_family: sa_family_t,
+    /// Network byte order (big-endian)!
+    pub sin_port: in_port_t,
+    /// Network byte order (big-endian)!
+    pub sin_addr: in_addr_t,
+    pub sin_zero: [u8; 8],
+}
+unsafe impl SafeRead for sockaddr_in {}
+
+impl sockaddr_in {
+    pub fn from_host(addr: SocketAddrV4) -> Self {
+        sockaddr_in {
+            sin_len: guest_size_of::<sockaddr_in>() as u8,
+            sin_family: AF_INET as sa_family_t,
+            sin_port: addr.port().swap_bytes(),
+            sin_addr: u32::from(*addr.ip()).swap_bytes(),
+            sin_zero: [0; 8],
+        }
+    }
+    pub fn to_host(&self) -> SocketAddrV4 {
+        let port = self.sin_port.swap_bytes();
+        let addr = self.sin_addr.swap_bytes();
+        SocketAddrV4::new(Ipv4Addr::from(addr), port)
+    }
+}
+
+fn guest_size_of<T>() -> GuestUSize {
+    std::mem::size_of::<T>().try_into().unwrap()
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum SocketType {
+    Stream,
+    Datagram,
+}
+
+enum Connection {
+    /// TCP socket that has not been connected (yet), or UDP socket that hasn't
+    /// been bound or sent anything yet.
+    None,
+    /// Non-blocking TCP `connect()` in progress on a helper thread.
+    Connecting(Receiver<io::Result<TcpStream>>),
+    Stream(TcpStream),
+    Datagram(UdpSocket),
+}
+
+pub struct Socket {
+    type_: SocketType,
+    connection: Connection,
+    nonblocking: bool,
+    /// Error from an asynchronous operation, reported by `SO_ERROR`.
+    pending_error: i32,
+    no_sigpipe: bool,
+    broadcast: bool,
+    no_delay: bool,
+}
+impl Socket {
+    pub(in crate::libc) fn is_nonblocking(&self) -> bool {
+        self.nonblocking
+    }
+
+    pub(in crate::libc) fn set_nonblocking(&mut self, nonblocking: bool) {
+        // The host socket is always non-blocking, this only affects whether
+        // the guest thread blocks.
+        self.nonblocking = nonblocking;
+    }
+
+    /// Check if a helper thread has finished connecting.
+    fn poll_connecting(&mut self) {
+        let Connection::Connecting(ref receiver) = self.connection else {
+            return;
+        };
+        match receiver.try_recv() {
+            Ok(Ok(stream)) => {
+                self.connection = Connection::Stream(stream);
+                self.configure_stream();
+            }
+            Ok(Err(e)) => {
+                self.pending_error = errno_for_io_error(&e);
+                self.connection = Connection::None;
+            }
+            Err(TryRecvError::Empty) => (),
+            Err(TryRecvError::Disconnected) => {
+                self.pending_error = EIO;
+                self.connection = Connection::None;
+            }
+        }
+    }
+
+    /// Apply options that were set before the host socket existed.
+    fn configure_stream(&self) {
+        let Connection::Stream(ref stream) = self.connection else {
+            unreachable!();
+        };
+        stream.set_nonblocking(true).unwrap();
+        if self.no_delay {
+            let _ = stream.set_nodelay(true);
+        }
+    }
+
+    /// Get the host UDP socket, binding it to an arbitrary port if it doesn't
+    /// exist yet (like the guest OS would do implicitly).
+    fn datagram_socket(&mut self) -> io::Result<&UdpSocket> {
+        assert!(self.type_ == SocketType::Datagram);
+        if let Connection::None = self.connection {
+            self.bind_datagram(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0))?;
+        }
+        let Connection::Datagram(ref socket) = self.connection else {
+            unreachable!();
+        };
+        Ok(socket)
+    }
+
+    fn bind_datagram(&mut self, addr: SocketAddrV4) -> io::Result<()> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        socket.set_broadcast(self.broadcast)?;
+        self.connection = Connection::Datagram(socket);
+        Ok(())
+    }
+
+    /// Check whether a read would not block. Errors and end-of-file count as
+    /// readable, because a read would return immediately.
+    fn is_readable(&mut self) -> bool {
+        self.poll_connecting();
+        let mut byte = [0u8];
+        let result = match self.connection {
+            Connection::Stream(ref stream) => stream.peek(&mut byte).map(|_| ()),
+            Connection::Datagram(ref socket) => socket.peek_from(&mut byte).map(|_| ()),
+            Connection::None | Connection::Connecting(_) => return false,
+        };
+        !matches!(result, Err(e) if e.kind() == io::ErrorKind::WouldBlock)
+    }
+
+    /// Check whether a write would not block. This is approximate: a host
+    /// socket whose send buffer is full will still be considered writable.
+    fn is_writable(&mut self) -> bool {
+        self.poll_connecting();
+        match self.connection {
+            Connection::Stream(_) | Connection::Datagram(_) => true,
+            // A failed connection attempt makes the socket writable, so that
+            // the app can find out about the error with SO_ERROR.
+            Connection::None => self.pending_error != 0 || self.type_ == SocketType::Datagram,
+            Connection::Connecting(_) => false,
+        }
+    }
+}
+
+/// What a guest thread blocked in a socket call is waiting for, see
+/// [crate::environment::ThreadBlock::Socket].
+#[derive(Debug, Clone)]
+pub enum SocketWait {
+    Readable(FileDescriptor),
+    Writable(FileDescriptor),
+    /// For `select()`: any socket in the first list becoming readable, or any
+    /// socket in the second list becoming writable.
+    Any(Vec<FileDescriptor>, Vec<FileDescriptor>),
+}
+impl SocketWait {
+    /// Check whether the wait is over. A socket that was closed in the
+    /// meantime counts as ready, so that the retried call can fail.
+    pub fn is_ready(&self, env: &mut Environment) -> bool {
+        let mut ready = |fd, write: bool| match env.libc_state.posix_io.socket_for_fd(fd) {
+            Some(socket) if write => socket.is_writable(),
+            Some(socket) => socket.is_readable(),
+            None => true,
+        };
+        match self {
+            SocketWait::Readable(fd) => ready(*fd, false),
+            SocketWait::Writable(fd) => ready(*fd, true),
+            SocketWait::Any(read, write) => {
+                read.iter().any(|&fd| ready(fd, false)) || write.iter().any(|&fd| ready(fd, true))
+            }
+        }
+    }
+}
+
+/// For use by `select()`: check whether a socket is ready for reading and/or
+/// writing. Returns [None] if `fd` isn't a socket.
+pub(in crate::libc) fn socket_readiness(
+    env: &mut Environment,
+    fd: FileDescriptor,
+) -> Option<(bool, bool)> {
+    let socket = env.libc_state.posix_io.socket_for_fd(fd)?;
+    Some((socket.is_readable(), socket.is_writable()))
+}
+
+/// Map a host I/O error to the closest guest `errno` value. The host's own
+/// error numbers can't be used because they differ between platforms.
+pub fn errno_for_io_error(e: &io::Error) -> i32 {
+    match e.kind() {
+        io::ErrorKind::WouldBlock => EWOULDBLOCK,
+        io::ErrorKind::ConnectionRefused => ECONNREFUSED,
+        io::ErrorKind::ConnectionReset => ECONNRESET,
+        io::ErrorKind::ConnectionAborted => ECONNABORTED,
+        io::ErrorKind::NotConnected => ENOTCONN,
+        io::ErrorKind::AddrInUse => EADDRINUSE,
+        io::ErrorKind::AddrNotAvailable => EADDRNOTAVAIL,
+        io::ErrorKind::BrokenPipe => EPIPE,
+        io::ErrorKind::TimedOut => ETIMEDOUT,
+        io::ErrorKind::Interrupted => EINTR,
+        io::ErrorKind::InvalidInput => EINVAL,
+        io::ErrorKind::PermissionDenied => EACCES,
+        _ => {
+            log!("Warning: unhandled host socket error {:?}, using EIO", e);
+            EIO
+        }
+    }
+}
+
+/// Check that `fd` is a socket, setting `errno` appropriately if it isn't.
+fn check_socket(env: &mut Environment, fd: FileDescriptor) -> bool {
+    if env.libc_state.posix_io.socket_for_fd(fd).is_some() {
+        return true;
+    }
+    let errno = if env.libc_state.posix_io.is_open(fd) {
+        ENOTSOCK
+    } else {
+        EBADF
+    };
+    set_errno(env, errno);
+    false
+}
+
+fn read_sockaddr_in(
+    env: &mut Environment,
+    address: ConstPtr<sockaddr>,
+    address_len: socklen_t,
+) -> Result<SocketAddrV4, i32> {
+    if address.is_null() {
+        return Err(EFAULT);
+    }
+    if address_len < guest_size_of::<sockaddr_in>() {
+        return Err(EINVAL);
+    }
+    let address: sockaddr_in = env.mem.read(address.cast());
+    if address.sin_family as i32 != AF_INET {
+        return Err(EAFNOSUPPORT);
+    }
+    Ok(address.to_host())
+}
+
+fn write_sockaddr_in(
+    env: &mut Environment,
+    address: MutPtr<sockaddr>,
+    address_len: MutPtr<socklen_t>,
+    value: SocketAddr,
+) {
+    if address.is_null() || address_len.is_null() {
+        return;
+    }
+    let SocketAddr::V4(value) = value else {
+        panic!("Unexpected IPv6 address {:?}", value);
+    };
+    let size = guest_size_of::<sockaddr_in>();
+    if env.mem.read(address_len) < size {
+        // TODO: truncate rather than writing nothing
+        log!(
+            "Warning: buffer for socket address {:?} is too small, not writing it",
+            value
+        );
+    } else {
+        env.mem.write(address.cast(), sockaddr_in::from_host(value));
+    }
+    env.mem.write(address_len, size);
+}
+
+fn socket(env: &mut Environment, domain: i32, type_: i32, protocol: i32) -> FileDescriptor {
+    set_errno(env, 0);
+
+    if domain != AF_INET {
+        log!(
+            "Warning: socket({}, {}, {}) failed, only AF_INET is supported",
+            domain,
+            type_,
+            protocol
+        );
+        set_errno(env, EAFNOSUPPORT);
+        return -1;
+    }
+    let socket_type = match (type_, protocol) {
+        (SOCK_STREAM, 0 | IPPROTO_TCP) => SocketType::Stream,
+        (SOCK_DGRAM, 0 | IPPROTO_UDP) => SocketType::Datagram,
+        _ => {
+            log!(
+                "Warning: socket({}, {}, {}) failed, unsupported type or protocol",
+                domain,
+                type_,
+                protocol
+            );
+            set_errno(env, EPROTONOSUPPORT);
+            return -1;
+        }
+    };
+
+    let fd = env.libc_state.posix_io.alloc_fd(OpenFd::Socket(Socket {
+        type_: socket_type,
+        connection: Connection::None,
+        nonblocking: false,
+        pending_error: 0,
+        no_sigpipe: false,
+        broadcast: false,
+        no_delay: false,
+    }));
+    log_dbg!("socket({}, {}, {}) => {}", domain, type_, protocol, fd);
+    fd
+}
+
+fn connect(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    address: ConstPtr<sockaddr>,
+    address_len: socklen_t,
+) -> i32 {
+    set_errno(env, 0);
+
+    let address = match read_sockaddr_in(env, address, address_len) {
+        Ok(address) => address,
+        Err(errno) => {
+            set_errno(env, errno);
+            return -1;
+        }
+    };
+    let network_access = env.options.network_access;
+    if !check_socket(env, fd) {
+        return -1;
+    }
+    let socket = env.libc_state.posix_io.socket_for_fd(fd).unwrap();
+    let nonblocking = socket.nonblocking;
+
+    let result = if !network_access {
+        log!(
+            "Refusing connection to {} because network access is disabled. Use the --allow-network-access option to enable it.",
+            address
+        );
+        Err(ENETUNREACH)
+    } else if socket.type_ == SocketType::Datagram {
+        // Connected UDP sockets are not supported yet, but apps rarely use
+        // them and it's mostly a convenience to avoid sendto().
+        log!("TODO: connect() on UDP socket {}", fd);
+        Err(EAFNOSUPPORT)
+    } else {
+        socket.poll_connecting();
+        match socket.connection {
+            Connection::Stream(_) => Err(EISCONN),
+            Connection::Connecting(_) => Err(EALREADY),
+            Connection::Datagram(_) => unreachable!(),
+            Connection::None => {
+                let (sender, receiver) = mpsc::channel();
+                std::thread::spawn(move || {
+                    let _ = sender.send(TcpStream::connect(address));
+                });
+                socket.connection = Connection::Connecting(receiver);
+                Err(EINPROGRESS)
+            }
+        }
+    };
+    // A blocking connect() waits for the helper thread to finish.
+    let result = match result {
+        Err(EINPROGRESS) if !nonblocking => {
+            env.block_on_socket(SocketWait::Writable(fd), None);
+            match env.libc_state.posix_io.socket_for_fd(fd) {
+                Some(socket) => {
+                    socket.poll_connecting();
+                    match socket.connection {
+                        Connection::Stream(_) => Ok(()),
+                        _ => Err(std::mem::take(&mut socket.pending_error)),
+                    }
+                }
+                None => Err(EBADF),
+            }
+        }
+        result => result,
+    };
+
+    log_dbg!("connect({}, {}) => {:?}", fd, address, result);
+    match result {
+        Ok(()) => 0,
+        Err(errno) => {
+            set_errno(env, errno);
+            -1
+        }
+    }
+}
+
+fn bind(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    address: ConstPtr<sockaddr>,
+    address_len: socklen_t,
+) -> i32 {
+    set_errno(env, 0);
+
+    let address = match read_sockaddr_in(env, address, address_len) {
+        Ok(address) => address,
+        Err(errno) => {
+            set_errno(env, errno);
+            return -1;
+        }
+    };
+    if !check_socket(env, fd) {
+        return -1;
+    }
+    let socket = env.libc_state.posix_io.socket_for_fd(fd).unwrap();
+
+    let result = match socket.type_ {
+        SocketType::Datagram if matches!(socket.connection, Connection::None) => socket
+            .bind_datagram(address)
+            .map_err(|e| errno_for_io_error(&e)),
+        SocketType::Datagram => Err(EINVAL),
+        SocketType::Stream => {
+            // TODO: support listening sockets
+            log!("TODO: bind() on TCP socket {} to {}", fd, address);
+            Err(EADDRNOTAVAIL)
+        }
+    };
+
+    log_dbg!("bind({}, {}) => {:?}", fd, address, result);
+    match result {
+        Ok(()) => 0,
+        Err(errno) => {
+            set_errno(env, errno);
+            -1
+        }
+    }
+}
+
+/// Helper for `send()`, `sendto()` and `write()`.
+fn send_inner(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    buffer: ConstVoidPtr,
+    length: GuestUSize,
+    flags: i32,
+    dest_address: Option<SocketAddrV4>,
+) -> GuestISize {
+    if flags & !MSG_DONTWAIT != 0 {
+        log!("TODO: send flags {:#x} for socket {}", flags, fd);
+    }
+    let network_access = env.options.network_access;
+    if !check_socket(env, fd) {
+        return -1;
+    }
+
+    let result = loop {
+        let Some(socket) = env.libc_state.posix_io.socket_for_fd(fd) else {
+            // Closed by another thread while this one was blocked.
+            break Err(EBADF);
+        };
+        socket.poll_connecting();
+
+        let no_sigpipe = socket.no_sigpipe;
+        let nonblocking = socket.nonblocking || flags & MSG_DONTWAIT != 0;
+        let data = env.mem.bytes_at(buffer.cast(), length);
+        let result = match (socket.type_, dest_address) {
+            (SocketType::Stream, _) => match socket.connection {
+                // The destination address is ignored for connected sockets.
+                Connection::Stream(ref mut stream) => stream.write(data).map_err(|e| {
+                    if e.kind() == io::ErrorKind::BrokenPipe && !no_sigpipe {
+                        log!("Warning: SIGPIPE would be raised for socket {}, but signals are not supported", fd);
+                    }
+                    errno_for_io_error(&e)
+                }),
+                Connection::Connecting(_) => Err(EAGAIN),
+                _ => Err(ENOTCONN),
+            },
+            (SocketType::Datagram, None) => Err(EDESTADDRREQ),
+            (SocketType::Datagram, Some(_)) if !network_access => Err(ENETUNREACH),
+            (SocketType::Datagram, Some(dest_address)) => socket
+                .datagram_socket()
+                .and_then(|s| s.send_to(data, dest_address))
+                .map_err(|e| errno_for_io_error(&e)),
+        };
+        match result {
+            Err(EWOULDBLOCK) if !nonblocking => env.block_on_socket(SocketWait::Writable(fd), None),
+            result => break result,
+        }
+    };
+
+    log_dbg!(
+        "send({}, {:?}, {:#x}, {:#x}, {:?}) => {:?}",
+        fd,
+        buffer,
+        length,
+        flags,
+        dest_address,
+        result
+    );
+    match result {
+        Ok(sent) => sent.try_into().unwrap(),
+        Err(errno) => {
+            set_errno(env, errno);
+            -1
+        }
+    }
+}
+
+/// Helper for `recv()`, `recvfrom()` and `read()`.
+fn recv_inner(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    buffer: MutVoidPtr,
+    length: GuestUSize,
+    flags: i32,
+) -> Result<(GuestUSize, Option<SocketAddr>), ()> {
+    if flags & !(MSG_DONTWAIT | MSG_PEEK) != 0 {
+        log!("TODO: recv flags {:#x} for socket {}", flags, fd);
+    }
+    if !check_socket(env, fd) {
+        return Err(());
+    }
+
+    let peek = flags & MSG_PEEK != 0;
+    let result = loop {
+        let Some(socket) = env.libc_state.posix_io.socket_for_fd(fd) else {
+            // Closed by another thread while this one was blocked.
+            break Err(EBADF);
+        };
+        socket.poll_connecting();
+
+        let nonblocking = socket.nonblocking || flags & MSG_DONTWAIT != 0;
+        let data = env.mem.bytes_at_mut(buffer.cast(), length);
+        let result = match socket.connection {
+            Connection::Stream(ref mut stream) => if peek {
+                stream.peek(data)
+            } else {
+                stream.read(data)
+            }
+            .map(|received| (received, None))
+            .map_err(|e| errno_for_io_error(&e)),
+            Connection::Datagram(ref socket) => if peek {
+                socket.peek_from(data)
+            } else {
+                socket.recv_from(data)
+            }
+            .map(|(received, from)| (received, Some(from)))
+            .map_err(|e| errno_for_io_error(&e)),
+            Connection::Connecting(_) => Err(EAGAIN),
+            // An unbound UDP socket can never receive anything.
+            Connection::None if socket.type_ == SocketType::Datagram && nonblocking => Err(EAGAIN),
+            Connection::None => Err(ENOTCONN),
+        };
+        match result {
+            Err(EWOULDBLOCK) if !nonblocking => env.block_on_socket(SocketWait::Readable(fd), None),
+            result => break result,
+        }
+    };
+
+    log_dbg!(
+        "recv({}, {:?}, {:#x}, {:#x}) => {:?}",
+        fd,
+        buffer,
+        length,
+        flags,
+        result
+    );
+    match result {
+        Ok((received, from)) => Ok((received.try_into().unwrap(), from)),
+        Err(errno) => {
+            set_errno(env, errno);
+            Err(())
+        }
+    }
+}
+
+pub fn send(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    buffer: ConstVoidPtr,
+    length: GuestUSize,
+    flags: i32,
+) -> GuestISize {
+    set_errno(env, 0);
+    send_inner(env, fd, buffer, length, flags, None)
+}
+
+fn sendto(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    buffer: ConstVoidPtr,
+    length: GuestUSize,
+    flags: i32,
+    dest_address: ConstPtr<sockaddr>,
+    dest_address_len: socklen_t,
+) -> GuestISize {
+    set_errno(env, 0);
+
+    let dest_address = if dest_address.is_null() {
+        None
+    } else {
+        match read_sockaddr_in(env, dest_address, dest_address_len) {
+            Ok(address) => Some(address),
+            Err(errno) => {
+                set_errno(env, errno);
+                return -1;
+            }
+        }
+    };
+    send_inner(env, fd, buffer, length, flags, dest_address)
+}
+
+pub fn recv(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    buffer: MutVoidPtr,
+    length: GuestUSize,
+    flags: i32,
+) -> GuestISize {
+    set_errno(env, 0);
+    match recv_inner(env, fd, buffer, length, flags) {
+        Ok((received, _)) => received.try_into().unwrap(),
+        Err(()) => -1,
+    }
+}
+
+fn recvfrom(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    buffer: MutVoidPtr,
+    length: GuestUSize,
+    flags: i32,
+    address: MutPtr<sockaddr>,
+    address_len: MutPtr<socklen_t>,
+) -> GuestISize {
+    set_errno(env, 0);
+    match recv_inner(env, fd, buffer, length, flags) {
+        Ok((received, from)) => {
+            if let Some(from) = from {
+                write_sockaddr_in(env, address, address_len, from);
+            } else if !address_len.is_null() {
+                env.mem.write(address_len, 0);
+            }
+            received.try_into().unwrap()
+        }
+        Err(()) => -1,
+    }
+}
+
+fn getsockname(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    address: MutPtr<sockaddr>,
+    address_len: MutPtr<socklen_t>,
+) -> i32 {
+    set_errno(env, 0);
+
+    if !check_socket(env, fd) {
+        return -1;
+    }
+    let socket = env.libc_state.posix_io.socket_for_fd(fd).unwrap();
+    socket.poll_connecting();
+    let local_addr = match socket.connection {
+        Connection::Stream(ref stream) => stream.local_addr(),
+        Connection::Datagram(ref socket) => socket.local_addr(),
+        Connection::None | Connection::Connecting(_) => {
+            Ok(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)))
+        }
+    };
+    match local_addr {
+        Ok(local_addr) => {
+            write_sockaddr_in(env, address, address_len, local_addr);
+            0
+        }
+        Err(e) => {
+            set_errno(env, errno_for_io_error(&e));
+            -1
+        }
+    }
+}
+
+fn getpeername(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    address: MutPtr<sockaddr>,
+    address_len: MutPtr<socklen_t>,
+) -> i32 {
+    set_errno(env, 0);
+
+    if !check_socket(env, fd) {
+        return -1;
+    }
+    let socket = env.libc_state.posix_io.socket_for_fd(fd).unwrap();
+    socket.poll_connecting();
+    let peer_addr = match socket.connection {
+        Connection::Stream(ref stream) => stream.peer_addr().map_err(|e| errno_for_io_error(&e)),
+        _ => Err(ENOTCONN),
+    };
+    match peer_addr {
+        Ok(peer_addr) => {
+            write_sockaddr_in(env, address, address_len, peer_addr);
+            0
+        }
+        Err(errno) => {
+            set_errno(env, errno);
+            -1
+        }
+    }
+}
+
+fn setsockopt(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    level: i32,
+    option_name: i32,
+    option_value: ConstVoidPtr,
+    option_len: socklen_t,
+) -> i32 {
+    set_errno(env, 0);
+
+    // All the supported options are ints.
+    if option_value.is_null() || option_len < guest_size_of::<i32>() {
+        set_errno(env, EINVAL);
+        return -1;
+    }
+    let value: i32 = env.mem.read(option_value.cast());
+    if !check_socket(env, fd) {
+        return -1;
+    }
+    let socket = env.libc_state.posix_io.socket_for_fd(fd).unwrap();
+    socket.poll_connecting();
+
+    let result = match (level, option_name) {
+        (SOL_SOCKET, SO_NOSIGPIPE) => {
+            socket.no_sigpipe = value != 0;
+            Ok(())
+        }
+        (SOL_SOCKET, SO_BROADCAST) => {
+            socket.broadcast = value != 0;
+            match socket.connection {
+                Connection::Datagram(ref socket) => socket
+                    .set_broadcast(value != 0)
+                    .map_err(|e| errno_for_io_error(&e)),
+                _ => Ok(()),
+            }
+        }
+        (IPPROTO_TCP, TCP_NODELAY) => {
+            socket.no_delay = value != 0;
+            match socket.connection {
+                Connection::Stream(ref stream) => stream
+                    .set_nodelay(value != 0)
+                    .map_err(|e| errno_for_io_error(&e)),
+                _ => Ok(()),
+            }
+        }
+        (SOL_SOCKET, SO_REUSEADDR | SO_KEEPALIVE | SO_SNDBUF | SO_RCVBUF) => {
+            log!(
+                "TODO: Ignoring setsockopt({}, SOL_SOCKET, {:#x}, {})",
+                fd,
+                option_name,
+                value
+            );
+            Ok(())
+        }
+        _ => {
+            log!(
+                "TODO: setsockopt({}, {:#x}, {:#x}, {}) is not supported",
+                fd,
+                level,
+                option_name,
+                value
+            );
+            Err(ENOPROTOOPT)
+        }
+    };
+
+    log_dbg!(
+        "setsockopt({}, {:#x}, {:#x}, {}) => {:?}",
+        fd,
+        level,
+        option_name,
+        value,
+        result
+    );
+    match result {
+        Ok(()) => 0,
+        Err(errno) => {
+            set_errno(env, errno);
+            -1
+        }
+    }
+}
+
+fn getsockopt(
+    env: &mut Environment,
+    fd: FileDescriptor,
+    level: i32,
+    option_name: i32,
+    option_value: MutVoidPtr,
+    option_len: MutPtr<socklen_t>,
+) -> i32 {
+    set_errno(env, 0);
+
+    if option_value.is_null()
+        || option_len.is_null()
+        || env.mem.read(option_len) < guest_size_of::<i32>()
+    {
+        set_errno(env, EINVAL);
+        return -1;
+    }
+    if !check_socket(env, fd) {
+        return -1;
+    }
+    let socket = env.libc_state.posix_io.socket_for_fd(fd).unwrap();
+    socket.poll_connecting();
+
+    let value = match (level, option_name) {
+        // Reading the error also clears it.
+        (SOL_SOCKET, SO_ERROR) => Some(std::mem::take(&mut socket.pending_error)),
+        (SOL_SOCKET, SO_TYPE) => Some(match socket.type_ {
+            SocketType::Stream => SOCK_STREAM,
+            SocketType::Datagram => SOCK_DGRAM,
+        }),
+        (SOL_SOCKET, SO_NOSIGPIPE) => Some(socket.no_sigpipe.into()),
+        (SOL_SOCKET, SO_BROADCAST) => Some(socket.broadcast.into()),
+        (IPPROTO_TCP, TCP_NODELAY) => Some(socket.no_delay.into()),
+        _ => None,
+    };
+
+    log_dbg!(
+        "getsockopt({}, {:#x}, {:#x}) => {:?}",
+        fd,
+        level,
+        option_name,
+        value
+    );
+    let Some(value) = value else {
+        log!(
+            "TODO: getsockopt({}, {:#x}, {:#x}) is not supported",
+            fd,
+            level,
+            option_name
+        );
+        set_errno(env, ENOPROTOOPT);
+        return -1;
+    };
+    env.mem.write(option_value.cast(), value);
+    env.mem.write(option_len, guest_size_of::<i32>());
+    0
+}
+
+fn shutdown(env: &mut Environment, fd: FileDescriptor, how: i32) -> i32 {
+    set_errno(env, 0);
+
+    if !check_socket(env, fd) {
+        return -1;
+    }
+    let socket = env.libc_state.posix_io.socket_for_fd(fd).unwrap();
+    socket.poll_connecting();
+
+    let how = match how {
+        SHUT_RD => Shutdown::Read,
+        SHUT_WR => Shutdown::Write,
+        SHUT_RDWR => Shutdown::Both,
+        _ => {
+            set_errno(env, EINVAL);
+            return -1;
+        }
+    };
+    let result = match socket.connection {
+        Connection::Stream(ref stream) => stream.shutdown(how).map_err(|e| errno_for_io_error(&e)),
+        _ => Err(ENOTCONN),
+    };
+
+    log_dbg!("shutdown({}, {:?}) => {:?}", fd, how, result);
+    match result {
+        Ok(()) => 0,
+        Err(errno) => {
+            set_errno(env, errno);
+            -1
+        }
+    }
+}
+
+pub const FUNCTIONS: FunctionExports = &[
+    export_c_func!(socket(_, _, _)),
+    export_c_func!(connect(_, _, _)),
+    export_c_func!(bind(_, _, _)),
+    export_c_func!(send(_, _, _, _)),
+    export_c_func!(sendto(_, _, _, _, _, _)),
+    export_c_func!(recv(_, _, _, _)),
+    export_c_func!(recvfrom(_, _, _, _, _, _)),
+    export_c_func!(getsockname(_, _, _)),
+    export_c_func!(getpeername(_, _, _)),
+    export_c_func!(setsockopt(_, _, _, _, _)),
+    export_c_func!(getsockopt(_, _, _, _, _)),
+    export_c_func!(shutdown(_, _)),
+];
@@ -27,7 +27,9 @@ struct timeb {
 unsafe impl SafeRead for timeb {}
 
 fn ftime(env: &mut Environment, tb: MutPtr<timeb>) -> i32 {
-    let epoch_duration = SystemTime::now()
+    let epoch_duration = env
+        .clock
+        .system_now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap();
     let time64 = epoch_duration.as_secs();
@@ -6,10 +6,53 @@
 //! `sys/sysctl.h`
 
 use crate::dyld::{export_c_func, FunctionExports};
-use crate::libc::errno::set_errno;
+use crate::libc::errno::{set_errno, ENOENT, ENOMEM};
 use crate::mem::{ConstPtr, GuestUSize, MutPtr, MutVoidPtr};
 use crate::Environment;
 
+const CTL_HW: i32 = 6;
+const HW_MACHINE: i32 = 1;
+const HW_MODEL: i32 = 2;
+
+/// Get the value of a string sysctl that touchHLE knows about. The strings
+/// depend on the simulated device (see
+/// [crate::frameworks::uikit::ui_device::DeviceModel]).
+fn string_value(env: &Environment, name: &str) -> Option<&'static str> {
+    match name {
+        "hw.machine" => Some(env.options.device.machine()),
+        "hw.model" => Some(env.options.device.board()),
+        _ => None,
+    }
+}
+
+/// Copy a string sysctl value (with its null terminator) to `oldp`, following
+/// the usual rules for `oldlenp`.
+fn read_string(
+    env: &mut Environment,
+    value: &str,
+    oldp: MutVoidPtr,
+    oldlenp: MutPtr<GuestUSize>,
+    newp: MutVoidPtr,
+) -> i32 {
+    assert!(newp.is_null()); // TODO: these values are read-only
+    assert!(!oldlenp.is_null()); // TODO
+    let len: GuestUSize = (value.len() + 1).try_into().unwrap();
+    if oldp.is_null() {
+        // The caller only wants to know how big the buffer should be.
+        env.mem.write(oldlenp, len);
+        return 0; // success
+    }
+    if env.mem.read(oldlenp) < len {
+        set_errno(env, ENOMEM);
+        return -1;
+    }
+    let buffer = env.mem.bytes_at_mut(oldp.cast(), len);
+    buffer[..value.len()].copy_from_slice(value.as_bytes());
+    buffer[value.len()] = b'\0';
+    env.mem.write(oldlenp, len);
+    0 // success
+}
+
 fn sysctl(
     env: &mut Environment,
     name: MutPtr<i32>,
@@ -22,6 +65,18 @@ fn sysctl(
     // TODO: handle errno properly
     set_errno(env, 0);
 
+    if name_len == 2 && env.mem.read(name) == CTL_HW {
+        let string_name = match env.mem.read(name + 1) {
+            HW_MACHINE => Some("hw.machine"),
+            HW_MODEL => Some("hw.model"),
+            _ => None,
+        };
+        if let Some(value) = string_name.and_then(|name| string_value(env, name)) {
+            log_dbg!("sysctl({:?}, {:#x}) => {:?}", name, name_len, value);
+            return read_string(env, value, oldp, oldlenp, newp);
+        }
+    }
+
     log!(
         "TODO: sysctl({:?}, {:#x}, {:?}, {:?}, {:?}, {:x})",
         name,
@@ -49,28 +104,20 @@ fn sysctlbyname(
     set_errno(env, 0);
 
     let name_str = env.mem.cstr_at_utf8(name).unwrap();
-    log_dbg!(
-        "TODO: sysctlbyname({:?}, {:?}, {:?}, {:?}, {:x})",
-        name_str,
-        oldp,
-        oldlenp,
-        newp,
-        newlen
-    );
-    assert_eq!(name_str, "hw.machine");
-    if oldp.is_null() && newp.is_null() {
-        // "iPhone1,1"
-        env.mem.write(oldlenp, 10);
-        return 0;
-    }
-    assert!(!oldp.is_null() && !oldlenp.is_null());
-    assert!(newp.is_null());
-    let hw_machine_str = env.mem.alloc_and_write_cstr(b"iPhone1,1");
-    assert_eq!(env.mem.read(oldlenp), 10);
-    env.mem
-        .memmove(oldp, hw_machine_str.cast().cast_const(), 10);
-    env.mem.free(hw_machine_str.cast());
-    0 // success
+    let Some(value) = string_value(env, name_str) else {
+        log!(
+            "TODO: sysctlbyname({:?}, {:?}, {:?}, {:?}, {:x}) for unknown name",
+            name_str,
+            oldp,
+            oldlenp,
+            newp,
+            newlen
+        );
+        set_errno(env, ENOENT);
+        return -1;
+    };
+    log_dbg!("sysctlbyname({:?}) => {:?}", name_str, value);
+    read_string(env, value, oldp, oldlenp, newp)
 }
 
 pub const FUNCTIONS: FunctionExports = &[
@@ -6,10 +6,11 @@
 //! `time.h` (C) and `sys/time.h` (POSIX)
 
 use crate::dyld::{export_c_func, FunctionExports};
-use crate::libc::errno::set_errno;
-use crate::mem::{guest_size_of, ConstPtr, MutPtr, Ptr, SafeRead};
+use crate::libc::errno::{set_errno, EOVERFLOW};
+use crate::mem::{guest_size_of, ConstPtr, GuestUSize, MutPtr, Ptr, SafeRead};
 use crate::Environment;
-use std::time::{Duration, Instant, SystemTime};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
 
 #[derive(Default)]
 pub struct State {
@@ -17,6 +18,8 @@ pub struct State {
     /// Temporary static storage for the return value of `gmtime` or
     /// `localtime`. The standard allows calls to either to overwrite it.
     gmtime_tmp: Option<MutPtr<tm>>,
+    /// Strings for `tm_zone`, by UTC offset in seconds.
+    time_zone_names: HashMap<i32, ConstPtr<u8>>,
 }
 
 // time.h (C)
@@ -31,7 +34,8 @@ type clock_t = u64;
 const CLOCKS_PER_SEC: clock_t = 1000000;
 
 fn clock(env: &mut Environment) -> clock_t {
-    Instant::now()
+    env.clock
+        .now()
         .duration_since(env.startup_time)
         .as_secs()
         .wrapping_mul(CLOCKS_PER_SEC)
@@ -41,7 +45,9 @@ fn time(env: &mut Environment, out: MutPtr<time_t>) -> time_t {
     // TODO: handle errno properly
     set_errno(env, 0);
 
-    let time64 = SystemTime::now()
+    let time64 = env
+        .clock
+        .system_now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap()
         .as_secs();
@@ -57,7 +63,8 @@ fn time(env: &mut Environment, out: MutPtr<time_t>) -> time_t {
 }
 
 fn tzset(_env: &mut Environment) {
-    log!("TODO: tzset()");
+    // The local time zone is looked up from the host every time it's needed,
+    // so there is nothing to do here.
 }
 
 #[allow(non_camel_case_types)]
@@ -249,42 +256,487 @@ fn test_timestamp_to_calendar_date() {
     do_test("Sat, 1955-03-26T20:47:45", -466053135);
 }
 
+/// Inverse of [timestamp_to_calendar_date], except that the fields are allowed
+/// to be outside their usual ranges (e.g. the 32nd day of a month is the 1st
+/// day of the next month), as `mktime` and `timegm` require. Only the year,
+/// month, day of the month and time of day are used.
+fn calendar_date_to_timestamp(date: &tm) -> i64 {
+    let month = i64::from(date.tm_mon);
+    let year = 1900 + i64::from(date.tm_year) + month.div_euclid(12);
+    let month_in_year = month.rem_euclid(12) as usize;
+
+    // See timestamp_to_calendar_date for why Y2K is used as the epoch.
+    let years_since_y2k = year - 2000;
+    let cycles_since_y2k = years_since_y2k.div_euclid(CYCLE_YEARS.into());
+    let year_in_cycle = years_since_y2k.rem_euclid(CYCLE_YEARS.into()) as usize;
+    let month_to_day = if is_leap_year(year_in_cycle as i32) {
+        &MONTH_TO_DAY_LEAP
+    } else {
+        &MONTH_TO_DAY_NONLEAP
+    };
+    let days_since_y2k = cycles_since_y2k * i64::from(CYCLE_DAYS)
+        + i64::from(YEAR_TO_DAY[year_in_cycle])
+        + i64::from(month_to_day[month_in_year])
+        + i64::from(date.tm_mday - 1);
+    let days_since_unix_epoch = days_since_y2k + 10957;
+
+    days_since_unix_epoch * 24 * 60 * 60
+        + i64::from(date.tm_hour) * 60 * 60
+        + i64::from(date.tm_min) * 60
+        + i64::from(date.tm_sec)
+}
+#[cfg(test)]
+#[test]
+fn test_calendar_date_to_timestamp() {
+    for timestamp in [1140398872, 2113022454, -1509557849, 0, 951782400] {
+        let date = timestamp_to_calendar_date(timestamp);
+        assert_eq!(calendar_date_to_timestamp(&date), i64::from(timestamp));
+    }
+    // 2009-01-32 25:00:-1 is 2009-02-02T00:59:59
+    let mut date = timestamp_to_calendar_date(0);
+    date.tm_year = 109;
+    date.tm_mon = 0;
+    date.tm_mday = 32;
+    date.tm_hour = 25;
+    date.tm_min = 0;
+    date.tm_sec = -1;
+    assert_eq!(calendar_date_to_timestamp(&date), 1233536399);
+    // Month 13 of 2007 is February 2008
+    date.tm_year = 107;
+    date.tm_mon = 13;
+    date.tm_mday = 29;
+    date.tm_hour = 0;
+    date.tm_sec = 0;
+    assert_eq!(calendar_date_to_timestamp(&date), 1204243200);
+}
+
+/// Get the host's offset from UTC in seconds at a particular time, and whether
+/// daylight saving time is in effect then. This uses the host C library, so
+/// the host's time zone rules (including the `TZ` environment variable) are
+/// respected.
+pub fn host_utc_offset(timestamp: i64) -> (i32, bool) {
+    /// Only the fields all platforms have in common (in the same order) are
+    /// declared. The padding is bigger than the rest of the struct is on any
+    /// platform.
+    #[allow(non_camel_case_types)]
+    #[repr(C)]
+    #[derive(Default)]
+    struct host_tm {
+        tm_sec: std::ffi::c_int,
+        tm_min: std::ffi::c_int,
+        tm_hour: std::ffi::c_int,
+        tm_mday: std::ffi::c_int,
+        tm_mon: std::ffi::c_int,
+        tm_year: std::ffi::c_int,
+        tm_wday: std::ffi::c_int,
+        tm_yday: std::ffi::c_int,
+        tm_isdst: std::ffi::c_int,
+        _rest: [u64; 8],
+    }
+    let mut host_tm = host_tm::default();
+
+    #[cfg(unix)]
+    let success = {
+        extern "C" {
+            fn localtime_r(
+                timestamp: *const std::ffi::c_long,
+                result: *mut host_tm,
+            ) -> *mut host_tm;
+        }
+        match std::ffi::c_long::try_from(timestamp) {
+            Ok(timestamp) => unsafe { !localtime_r(&timestamp, &mut host_tm).is_null() },
+            Err(_) => false,
+        }
+    };
+    #[cfg(windows)]
+    let success = {
+        extern "C" {
+            fn _localtime64_s(result: *mut host_tm, timestamp: *const i64) -> std::ffi::c_int;
+        }
+        unsafe { _localtime64_s(&mut host_tm, &timestamp) == 0 }
+    };
+    #[cfg(not(any(unix, windows)))]
+    let success = false;
+
+    if !success {
+        log!(
+            "Warning: couldn't get the host's local time for timestamp {}, assuming UTC",
+            timestamp
+        );
+        return (0, false);
+    }
+
+    let local_date = tm {
+        tm_sec: host_tm.tm_sec,
+        tm_min: host_tm.tm_min,
+        tm_hour: host_tm.tm_hour,
+        tm_mday: host_tm.tm_mday,
+        tm_mon: host_tm.tm_mon,
+        tm_year: host_tm.tm_year,
+        tm_wday: host_tm.tm_wday,
+        tm_yday: host_tm.tm_yday,
+        tm_isdst: host_tm.tm_isdst,
+        tm_gmtoff: 0,
+        tm_zone: Ptr::null(),
+    };
+    let offset = calendar_date_to_timestamp(&local_date) - timestamp;
+    (offset.try_into().unwrap(), host_tm.tm_isdst > 0)
+}
+
+/// Get the string for `tm_zone` for a UTC offset. The host's abbreviation for
+/// its time zone isn't available on all platforms, so a name like `GMT+0100`
+/// is made up instead.
+fn time_zone_name(env: &mut Environment, offset: i32) -> ConstPtr<u8> {
+    if let Some(&name) = env.libc_state.time.time_zone_names.get(&offset) {
+        return name;
+    }
+    let name = if offset == 0 {
+        "UTC".to_string()
+    } else {
+        format!(
+            "GMT{}{:02}{:02}",
+            if offset < 0 { '-' } else { '+' },
+            offset.unsigned_abs() / 3600,
+            (offset.unsigned_abs() % 3600) / 60
+        )
+    };
+    let name = env.mem.alloc_and_write_cstr(name.as_bytes()).cast_const();
+    env.libc_state.time.time_zone_names.insert(offset, name);
+    name
+}
+
+fn utc_calendar_date(env: &mut Environment, timestamp: time_t) -> tm {
+    let mut date = timestamp_to_calendar_date(timestamp);
+    date.tm_zone = time_zone_name(env, 0);
+    date
+}
+
+fn local_calendar_date(env: &mut Environment, timestamp: time_t) -> tm {
+    let (offset, is_dst) = host_utc_offset(timestamp.into());
+    let mut date = timestamp_to_calendar_date(timestamp.saturating_add(offset));
+    date.tm_isdst = is_dst.into();
+    date.tm_gmtoff = offset;
+    date.tm_zone = time_zone_name(env, offset);
+    date
+}
+
+/// Temporary static storage for the return value of `gmtime` or `localtime`.
+/// This doesn't have to be unique, they're allowed to share it.
+fn static_tm(env: &mut Environment) -> MutPtr<tm> {
+    *env.libc_state
+        .time
+        .gmtime_tmp
+        .get_or_insert_with(|| env.mem.alloc(guest_size_of::<tm>()).cast())
+}
+
 fn gmtime_r(env: &mut Environment, timestamp: ConstPtr<time_t>, res: MutPtr<tm>) -> MutPtr<tm> {
     let timestamp = env.mem.read(timestamp);
-    let calendar_date = timestamp_to_calendar_date(timestamp);
+    let calendar_date = utc_calendar_date(env, timestamp);
     env.mem.write(res, calendar_date);
     res
 }
 fn gmtime(env: &mut Environment, timestamp: ConstPtr<time_t>) -> MutPtr<tm> {
-    let tmp = *env
-        .libc_state
-        .time
-        .gmtime_tmp
-        .get_or_insert_with(|| env.mem.alloc(guest_size_of::<tm>()).cast());
+    let tmp = static_tm(env);
     gmtime_r(env, timestamp, tmp)
 }
 
 fn localtime_r(env: &mut Environment, timestamp: ConstPtr<time_t>, res: MutPtr<tm>) -> MutPtr<tm> {
-    // TODO: don't assume local time is UTC?
-    gmtime_r(env, timestamp, res)
+    let timestamp = env.mem.read(timestamp);
+    let calendar_date = local_calendar_date(env, timestamp);
+    env.mem.write(res, calendar_date);
+    res
 }
 fn localtime(env: &mut Environment, timestamp: ConstPtr<time_t>) -> MutPtr<tm> {
-    // TODO: don't assume local time is UTC?
-    // This doesn't have to be a unique temporary, gmtime and localtime are
-    // allowed to share it.
-    gmtime(env, timestamp)
+    let tmp = static_tm(env);
+    localtime_r(env, timestamp, tmp)
+}
+
+fn mktime(env: &mut Environment, date: MutPtr<tm>) -> time_t {
+    let date_value = env.mem.read(date);
+    let local_timestamp = calendar_date_to_timestamp(&date_value);
+
+    // The UTC offset depends on the time in UTC, which depends on the offset,
+    // so the guess has to be refined. This converges after two steps unless
+    // the local time falls into a gap created by a DST transition.
+    let (mut offset, mut is_dst) = host_utc_offset(local_timestamp);
+    for _ in 0..2 {
+        (offset, is_dst) = host_utc_offset(local_timestamp - i64::from(offset));
+    }
+    let mut timestamp = local_timestamp - i64::from(offset);
+
+    // If the app says whether DST is in effect, the time is interpreted
+    // accordingly even if that's wrong, like in other implementations.
+    let tm_isdst = date_value.tm_isdst;
+    if tm_isdst > 0 && !is_dst {
+        timestamp -= 60 * 60;
+    } else if tm_isdst == 0 && is_dst {
+        timestamp += 60 * 60;
+    }
+
+    let Ok(timestamp) = time_t::try_from(timestamp) else {
+        log!("Warning: mktime() result doesn't fit in time_t, returning -1");
+        set_errno(env, EOVERFLOW);
+        return -1;
+    };
+    // The struct is normalized and the day of the week and year are filled in.
+    let normalized = local_calendar_date(env, timestamp);
+    env.mem.write(date, normalized);
+    log_dbg!("mktime({:?}) => {}", date, timestamp);
+    timestamp
+}
+
+fn timegm(env: &mut Environment, date: MutPtr<tm>) -> time_t {
+    let timestamp = calendar_date_to_timestamp(&env.mem.read(date));
+    let Ok(timestamp) = time_t::try_from(timestamp) else {
+        log!("Warning: timegm() result doesn't fit in time_t, returning -1");
+        set_errno(env, EOVERFLOW);
+        return -1;
+    };
+    let normalized = utc_calendar_date(env, timestamp);
+    env.mem.write(date, normalized);
+    log_dbg!("timegm({:?}) => {}", date, timestamp);
+    timestamp
+}
+
+const WEEKDAY_NAMES: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Implementation of `strftime` for the C locale. `zone` is the string
+/// `tm_zone` points to.
+/// Get the ISO 8601 week-based year and week number of a date, for `%G`, `%g`
+/// and `%V`. Weeks start on Monday, and week 1 is the one containing the
+/// year's first Thursday, so the first and last few days of a year can belong
+/// to a week of the previous or next year.
+fn iso_8601_week(year: i64, yday: i32, wday: i32) -> (i64, i32) {
+    let days_in_year = |year: i64| if is_leap_year(year as i32) { 366 } else { 365 };
+    // Monday is 0.
+    let weekday = (wday + 6).rem_euclid(7);
+    let week = (yday - weekday + 10) / 7;
+    if week < 1 {
+        return iso_8601_week(year - 1, yday + days_in_year(year - 1), wday);
+    }
+    // This week's Thursday is in the next year.
+    if yday - weekday + 3 >= days_in_year(year) {
+        return (year + 1, 1);
+    }
+    (year, week)
+}
+
+fn format_calendar_date(format: &[u8], date: &tm, zone: &[u8]) -> Vec<u8> {
+    let tm {
+        tm_sec,
+        tm_min,
+        tm_hour,
+        tm_mday,
+        tm_mon,
+        tm_year,
+        tm_wday,
+        tm_yday,
+        tm_gmtoff,
+        ..
+    } = *date;
+    // Out-of-range values shouldn't crash the app.
+    let weekday_name = WEEKDAY_NAMES[tm_wday.rem_euclid(7) as usize];
+    let month_name = MONTH_NAMES[tm_mon.rem_euclid(12) as usize];
+    let year = 1900 + i64::from(tm_year);
+    let hour_12 = if tm_hour % 12 == 0 { 12 } else { tm_hour % 12 };
+    let (iso_year, iso_week) = iso_8601_week(year, tm_yday, tm_wday);
+
+    let mut res = Vec::new();
+    let mut idx = 0;
+    while idx < format.len() {
+        let c = format[idx];
+        idx += 1;
+        if c != b'%' {
+            res.push(c);
+            continue;
+        }
+        // The E and O modifiers make no difference in the C locale.
+        if matches!(format.get(idx), Some(b'E' | b'O')) {
+            idx += 1;
+        }
+        let Some(&specifier) = format.get(idx) else {
+            break;
+        };
+        idx += 1;
+        let formatted = match specifier {
+            b'a' => weekday_name[..3].to_string(),
+            b'A' => weekday_name.to_string(),
+            b'b' | b'h' => month_name[..3].to_string(),
+            b'B' => month_name.to_string(),
+            b'c' => {
+                res.extend(format_calendar_date(b"%a %b %e %H:%M:%S %Y", date, zone));
+                continue;
+            }
+            b'C' => format!("{:02}", year.div_euclid(100)),
+            b'd' => format!("{:02}", tm_mday),
+            b'D' | b'x' => {
+                res.extend(format_calendar_date(b"%m/%d/%y", date, zone));
+                continue;
+            }
+            b'e' => format!("{:2}", tm_mday),
+            b'F' => {
+                res.extend(format_calendar_date(b"%Y-%m-%d", date, zone));
+                continue;
+            }
+            b'G' => format!("{}", iso_year),
+            b'g' => format!("{:02}", iso_year.rem_euclid(100)),
+            b'H' => format!("{:02}", tm_hour),
+            b'I' => format!("{:02}", hour_12),
+            b'j' => format!("{:03}", tm_yday + 1),
+            b'k' => format!("{:2}", tm_hour),
+            b'l' => format!("{:2}", hour_12),
+            b'm' => format!("{:02}", tm_mon + 1),
+            b'M' => format!("{:02}", tm_min),
+            b'n' => "\n".to_string(),
+            b'p' => if tm_hour < 12 { "AM" } else { "PM" }.to_string(),
+            b'r' => {
+                res.extend(format_calendar_date(b"%I:%M:%S %p", date, zone));
+                continue;
+            }
+            b'R' => {
+                res.extend(format_calendar_date(b"%H:%M", date, zone));
+                continue;
+            }
+            b's' => format!(
+                "{}",
+                calendar_date_to_timestamp(date) - i64::from(tm_gmtoff)
+            ),
+            b'S' => format!("{:02}", tm_sec),
+            b't' => "\t".to_string(),
+            b'T' | b'X' => {
+                res.extend(format_calendar_date(b"%H:%M:%S", date, zone));
+                continue;
+            }
+            b'u' => format!("{}", if tm_wday == 0 { 7 } else { tm_wday }),
+            // Week of the year, where weeks start on Sunday (%U) or Monday
+            // (%W), and days before the first one are in week 0.
+            b'U' => format!("{:02}", (tm_yday + 7 - tm_wday) / 7),
+            b'V' => format!("{:02}", iso_week),
+            b'v' => {
+                res.extend(format_calendar_date(b"%e-%b-%Y", date, zone));
+                continue;
+            }
+            b'W' => format!("{:02}", (tm_yday + 7 - (tm_wday + 6) % 7) / 7),
+            b'w' => format!("{}", tm_wday),
+            b'y' => format!("{:02}", year.rem_euclid(100)),
+            b'Y' => format!("{}", year),
+            b'z' => format!(
+                "{}{:02}{:02}",
+                if tm_gmtoff < 0 { '-' } else { '+' },
+                tm_gmtoff.unsigned_abs() / 3600,
+                (tm_gmtoff.unsigned_abs() % 3600) / 60
+            ),
+            b'Z' => {
+                res.extend_from_slice(zone);
+                continue;
+            }
+            b'+' => {
+                res.extend(format_calendar_date(b"%a %b %e %H:%M:%S %Z %Y", date, zone));
+                continue;
+            }
+            b'%' => "%".to_string(),
+            _ => {
+                log!(
+                    "Warning: unknown strftime format character '{}', emitting it literally",
+                    specifier as char
+                );
+                res.extend_from_slice(&[b'%', specifier]);
+                continue;
+            }
+        };
+        res.extend_from_slice(formatted.as_bytes());
+    }
+    res
+}
+#[cfg(test)]
+#[test]
+fn test_format_calendar_date() {
+    let date = timestamp_to_calendar_date(1140398872);
+    assert_eq!(
+        format_calendar_date(b"%d/%m/%Y %H:%M:%S %j %a %b %p %% %I %u %y", &date, b""),
+        b"20/02/2006 01:27:52 051 Mon Feb AM % 01 1 06"
+    );
+    assert_eq!(
+        format_calendar_date(b"%c|%F|%e|%Z %z|%A %B", &date, b"UTC"),
+        b"Mon Feb 20 01:27:52 2006|2006-02-20|20|UTC +0000|Monday February"
+    );
+    assert_eq!(
+        format_calendar_date(b"%k|%l|%s|%G %g %V|%v|%+|%Q", &date, b"UTC"),
+        b" 1| 1|1140398872|2006 06 08|20-Feb-2006|Mon Feb 20 01:27:52 UTC 2006|%Q"
+    );
+    // The ISO 8601 week-based year can differ from the calendar year.
+    let date = timestamp_to_calendar_date(1104537600); // 2005-01-01
+    assert_eq!(format_calendar_date(b"%G-W%V", &date, b""), b"2004-W53");
+    let date = timestamp_to_calendar_date(1230508800); // 2008-12-29
+    assert_eq!(format_calendar_date(b"%G-W%V", &date, b""), b"2009-W01");
+}
+
+fn strftime(
+    env: &mut Environment,
+    s: MutPtr<u8>,
+    maxsize: GuestUSize,
+    format: ConstPtr<u8>,
+    date: ConstPtr<tm>,
+) -> GuestUSize {
+    let date_value = env.mem.read(date);
+    let zone = if date_value.tm_zone.is_null() {
+        Vec::new()
+    } else {
+        env.mem.cstr_at(date_value.tm_zone).to_vec()
+    };
+    let formatted = format_calendar_date(env.mem.cstr_at(format), &date_value, &zone);
+    log_dbg!(
+        "strftime({:?}, {}, {:?} ({:?}), {:?}) => {:?}",
+        s,
+        maxsize,
+        format,
+        env.mem.cstr_at_utf8(format),
+        date,
+        std::str::from_utf8(&formatted)
+    );
+
+    // If the result doesn't fit, nothing is written.
+    let len: GuestUSize = formatted.len().try_into().unwrap();
+    if len >= maxsize {
+        return 0;
+    }
+    env.mem.bytes_at_mut(s, len).copy_from_slice(&formatted);
+    env.mem.write(s + len, b'\0');
+    len
 }
 
 // sys/time.h (POSIX)
 
 #[allow(non_camel_case_types)]
-type suseconds_t = i32;
+pub type suseconds_t = i32;
 
 #[allow(non_camel_case_types)]
 #[repr(C, packed)]
-struct timeval {
-    tv_sec: time_t,
-    tv_usec: suseconds_t,
+pub struct timeval {
+    pub tv_sec: time_t,
+    pub tv_usec: suseconds_t,
 }
 unsafe impl SafeRead for timeval {}
 
@@ -292,8 +744,8 @@ unsafe impl SafeRead for timeval {}
 #[derive(Default)]
 #[repr(C, packed)]
 pub struct timespec {
-    tv_sec: time_t,
-    tv_nsec: i32,
+    pub tv_sec: time_t,
+    pub tv_nsec: i32,
 }
 unsafe impl SafeRead for timespec {}
 
@@ -313,12 +765,19 @@ fn gettimeofday(
     // TODO: handle errno properly
     set_errno(env, 0);
 
+    let time = env
+        .clock
+        .system_now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap();
+
     if !timezone_ptr.is_null() {
+        let (offset, is_dst) = host_utc_offset(time.as_secs().try_into().unwrap());
         env.mem.write(
             timezone_ptr,
             timezone {
-                tz_minuteswest: 0,
-                tz_dsttime: 0,
+                tz_minuteswest: -offset / 60,
+                tz_dsttime: is_dst.into(),
             },
         );
     }
@@ -327,10 +786,6 @@ fn gettimeofday(
         return 0; // success
     }
 
-    let time = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap();
-
     let time_s_64: u64 = time.as_secs();
     let tv_sec = time_s_64 as time_t;
     if !env.libc_state.time.y2k38_warned && time_s_64 != tv_sec as u64 {
@@ -366,6 +821,9 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(gmtime(_)),
     export_c_func!(localtime_r(_, _)),
     export_c_func!(localtime(_)),
+    export_c_func!(mktime(_)),
+    export_c_func!(timegm(_)),
+    export_c_func!(strftime(_, _, _, _)),
     export_c_func!(gettimeofday(_, _)),
     export_c_func!(nanosleep(_, _)),
 ];
@@ -20,10 +20,10 @@
 
 use crate::abi::GuestFunction;
 use crate::fs::{Fs, GuestPath};
-use crate::mem::{Mem, Ptr};
+use crate::mem::{Mem, MutPtr, Ptr};
 use mach_object::{
     cpu_subtype_t, vm_prot_t, DyLib, LoadCommand, MachCommand, OFile, Symbol, SymbolIter,
-    ThreadState, N_ARM_THUMB_DEF, S_LAZY_SYMBOL_POINTERS, S_MOD_INIT_FUNC_POINTERS,
+    ThreadState, N_ARM_THUMB_DEF, N_WEAK_REF, S_LAZY_SYMBOL_POINTERS, S_MOD_INIT_FUNC_POINTERS,
     S_NON_LAZY_SYMBOL_POINTERS, S_SYMBOL_STUBS,
 };
 use std::collections::HashMap;
@@ -46,11 +46,23 @@ pub struct MachO {
     /// can look things up quickly. Thumb function symbols always have the Thumb
     /// bit set.
     pub exported_symbols: HashMap<String, u32>,
+    /// Addresses and names of symbols that aren't exported by the binary, e.g.
+    /// static functions. These are only used for debugging purposes (see
+    /// [crate::profiler]). Like with [Self::exported_symbols], Thumb function
+    /// symbols have the Thumb bit set.
+    pub local_symbols: Vec<(u32, String)>,
     /// List of addresses and names of external relocations for the dynamic
     /// linker to resolve.
     pub external_relocations: Vec<(u32, String)>,
     /// Address/program counter value for the entry point.
     pub entry_point_pc: Option<u32>,
+    /// Address of the initialization routine from `LC_ROUTINES`, if any. This
+    /// must be called before the functions in `__mod_init_func`.
+    pub init_routine_pc: Option<u32>,
+    /// Offset that was added to all addresses in the binary because it
+    /// couldn't be loaded at its preferred address. This is only non-zero for
+    /// dylibs embedded in an app bundle.
+    pub slide: u32,
 }
 
 #[derive(Debug)]
@@ -94,6 +106,9 @@ pub struct DyldIndirectSymbolInfo {
     pub entry_size: u32,
     /// A list of symbol names corresponding to the entries.
     pub indirect_undef_symbols: Vec<Option<String>>,
+    /// For each entry, whether the symbol is a weak reference (`N_WEAK_REF`),
+    /// i.e. it's allowed to be missing and should then be NULL.
+    pub weak_undef_symbols: Vec<bool>,
 }
 
 /// Helper trait that makes [MachO::get_section] work. Yes, this is overkill. :)
@@ -142,7 +157,6 @@ enum Reloc {
         size: u32,
         type_: u32,
     },
-    #[allow(dead_code)]
     Local {
         addr: u32,
         section_idx: u32,
@@ -150,7 +164,6 @@ enum Reloc {
         size: u32,
         type_: u32,
     },
-    #[allow(dead_code)]
     Scattered {
         offset: u32,
         value: u32,
@@ -213,6 +226,260 @@ impl Reloc {
     }
 }
 
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..][..4].try_into().unwrap())
+}
+
+const LC_SEGMENT: u32 = 0x1;
+const LC_DYSYMTAB: u32 = 0xb;
+const LC_ROUTINES: u32 = 0x11;
+const LC_REEXPORT_DYLIB: u32 = 0x1f | 0x80000000;
+const LC_DYLD_INFO: u32 = 0x22;
+const LC_DYLD_INFO_ONLY: u32 = 0x22 | 0x80000000;
+
+/// Iterate over the raw load commands of a (32-bit, little-endian) Mach-O
+/// file. This is for the few things mach_object doesn't parse for us.
+fn raw_load_commands(bytes: &[u8]) -> impl Iterator<Item = (u32, &[u8])> {
+    let ncmds = read_u32(bytes, 16);
+    let mut offset = 28; // size of mach_header
+    (0..ncmds).map(move |_| {
+        let cmd = read_u32(bytes, offset);
+        let cmdsize = read_u32(bytes, offset + 4) as usize;
+        let data = &bytes[offset..][..cmdsize];
+        offset += cmdsize;
+        (cmd, data)
+    })
+}
+
+/// Get the (unslid) base addresses of the segments of a binary, in the order
+/// the rebase and bind opcodes refer to them.
+fn raw_segment_bases(bytes: &[u8]) -> Vec<u32> {
+    raw_load_commands(bytes)
+        .filter(|&(cmd, _)| cmd == LC_SEGMENT)
+        .map(|(_, data)| read_u32(data, 24))
+        .collect()
+}
+
+/// Decide where to load a binary. Dylibs embedded in an app bundle are usually
+/// linked to be loaded at address 0, which is impossible because of the null
+/// page (and the app binary is there anyway), so they get moved ("slid") to
+/// some newly-allocated memory. Returns the slide.
+fn choose_slide(bytes: &[u8], filetype: u32, into_mem: &mut Mem) -> u32 {
+    if filetype != mach_object::MH_DYLIB {
+        return 0;
+    }
+    let segments: Vec<(u32, u32)> = raw_load_commands(bytes)
+        .filter(|&(cmd, _)| cmd == LC_SEGMENT)
+        .filter(|&(_, data)| !data[8..24].starts_with(b"__LINKEDIT\0"))
+        .map(|(_, data)| (read_u32(data, 24), read_u32(data, 28)))
+        .collect();
+    let start = segments
+        .iter()
+        .map(|&(vmaddr, _)| vmaddr)
+        .min()
+        .unwrap_or(0);
+    if start != 0 {
+        return 0;
+    }
+    let end = segments
+        .iter()
+        .map(|&(vmaddr, vmsize)| vmaddr + vmsize)
+        .max()
+        .unwrap_or(0);
+    // Segments should be page-aligned.
+    const PAGE_SIZE: u32 = 0x1000;
+    let base = into_mem.alloc(end + PAGE_SIZE - 1).to_bits();
+    base.next_multiple_of(PAGE_SIZE)
+}
+
+fn read_uleb128(bytes: &[u8], offset: &mut usize) -> u32 {
+    let mut result: u32 = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*offset];
+        *offset += 1;
+        if shift < 32 {
+            result |= u32::from(byte & 0x7f) << shift;
+        }
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return result;
+        }
+    }
+}
+fn read_sleb128(bytes: &[u8], offset: &mut usize) -> i32 {
+    let mut result: i32 = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*offset];
+        *offset += 1;
+        if shift < 32 {
+            result |= i32::from(byte & 0x7f) << shift;
+        }
+        shift += 7;
+        if byte & 0x80 == 0 {
+            if shift < 32 && (byte & 0x40) != 0 {
+                result |= -1 << shift;
+            }
+            return result;
+        }
+    }
+}
+
+/// Apply the slide to the pointers listed by the rebase opcodes from
+/// `LC_DYLD_INFO`.
+///
+/// The opcode format is documented in `/usr/include/mach-o/loader.h` in the
+/// macOS SDK.
+fn apply_rebase_opcodes(opcodes: &[u8], segment_bases: &[u32], slide: u32, mem: &mut Mem) {
+    let mut rebase = |addr: u32| {
+        let ptr: MutPtr<u32> = Ptr::from_bits(addr + slide);
+        let value = mem.read(ptr);
+        mem.write(ptr, value.wrapping_add(slide));
+    };
+
+    let mut offset = 0;
+    let mut addr = 0;
+    while offset < opcodes.len() {
+        let byte = opcodes[offset];
+        offset += 1;
+        let immediate = u32::from(byte & 0x0f);
+        match byte & 0xf0 {
+            0x00 => break, // REBASE_OPCODE_DONE
+            0x10 => {
+                // REBASE_OPCODE_SET_TYPE_IMM
+                if immediate != 1 {
+                    log!("Warning: unhandled rebase type {}", immediate);
+                }
+            }
+            0x20 => {
+                // REBASE_OPCODE_SET_SEGMENT_AND_OFFSET_ULEB
+                addr = segment_bases[immediate as usize] + read_uleb128(opcodes, &mut offset);
+            }
+            0x30 => addr = addr.wrapping_add(read_uleb128(opcodes, &mut offset)),
+            0x40 => addr += immediate * 4,
+            0x50 => {
+                // REBASE_OPCODE_DO_REBASE_IMM_TIMES
+                for _ in 0..immediate {
+                    rebase(addr);
+                    addr += 4;
+                }
+            }
+            0x60 => {
+                // REBASE_OPCODE_DO_REBASE_ULEB_TIMES
+                for _ in 0..read_uleb128(opcodes, &mut offset) {
+                    rebase(addr);
+                    addr += 4;
+                }
+            }
+            0x70 => {
+                // REBASE_OPCODE_DO_REBASE_ADD_ADDR_ULEB
+                rebase(addr);
+                addr = addr.wrapping_add(read_uleb128(opcodes, &mut offset) + 4);
+            }
+            0x80 => {
+                // REBASE_OPCODE_DO_REBASE_ULEB_TIMES_SKIPPING_ULEB
+                let count = read_uleb128(opcodes, &mut offset);
+                let skip = read_uleb128(opcodes, &mut offset);
+                for _ in 0..count {
+                    rebase(addr);
+                    addr = addr.wrapping_add(skip + 4);
+                }
+            }
+            _ => panic!("Unexpected rebase opcode {:#x}", byte),
+        }
+    }
+}
+
+/// Parse the bind opcodes from `LC_DYLD_INFO` into a list of external
+/// relocations (see [MachO::external_relocations]). Any addend is written to
+/// the pointer's location, where the dynamic linker will pick it up.
+///
+/// Since touchHLE doesn't separate symbols by library, the library ordinals
+/// are ignored.
+fn parse_bind_opcodes(
+    opcodes: &[u8],
+    segment_bases: &[u32],
+    slide: u32,
+    mem: &mut Mem,
+    external_relocations: &mut Vec<(u32, String)>,
+) {
+    let mut offset = 0;
+    let mut addr = 0;
+    let mut symbol = String::new();
+    let mut type_ = 1;
+    let mut addend = 0;
+    let mut bind = |addr: u32, symbol: &str, type_: u8, addend: i32| {
+        if type_ != 1 {
+            log!(
+                "Warning: unhandled bind type {} for {:?} at {:#x}",
+                type_,
+                symbol,
+                addr
+            );
+            return;
+        }
+        let addr = addr + slide;
+        mem.write(Ptr::<i32, true>::from_bits(addr), addend);
+        external_relocations.push((addr, symbol.to_string()));
+    };
+
+    while offset < opcodes.len() {
+        let byte = opcodes[offset];
+        offset += 1;
+        let immediate = byte & 0x0f;
+        match byte & 0xf0 {
+            0x00 => {
+                // BIND_OPCODE_DONE. Unlike rebasing, this doesn't necessarily
+                // mean the end of the opcodes.
+            }
+            0x10 | 0x30 => (), // BIND_OPCODE_SET_DYLIB_ORDINAL_IMM/SPECIAL_IMM
+            0x20 => {
+                // BIND_OPCODE_SET_DYLIB_ORDINAL_ULEB
+                read_uleb128(opcodes, &mut offset);
+            }
+            0x40 => {
+                // BIND_OPCODE_SET_SYMBOL_TRAILING_FLAGS_IMM
+                let len = opcodes[offset..].iter().position(|&b| b == 0).unwrap();
+                symbol = String::from_utf8_lossy(&opcodes[offset..][..len]).into_owned();
+                offset += len + 1;
+            }
+            0x50 => type_ = immediate, // BIND_OPCODE_SET_TYPE_IMM
+            0x60 => addend = read_sleb128(opcodes, &mut offset),
+            0x70 => {
+                // BIND_OPCODE_SET_SEGMENT_AND_OFFSET_ULEB
+                addr = segment_bases[immediate as usize] + read_uleb128(opcodes, &mut offset);
+            }
+            0x80 => addr = addr.wrapping_add(read_uleb128(opcodes, &mut offset)),
+            0x90 => {
+                // BIND_OPCODE_DO_BIND
+                bind(addr, &symbol, type_, addend);
+                addr += 4;
+            }
+            0xa0 => {
+                // BIND_OPCODE_DO_BIND_ADD_ADDR_ULEB
+                bind(addr, &symbol, type_, addend);
+                addr = addr.wrapping_add(read_uleb128(opcodes, &mut offset) + 4);
+            }
+            0xb0 => {
+                // BIND_OPCODE_DO_BIND_ADD_ADDR_IMM_SCALED
+                bind(addr, &symbol, type_, addend);
+                addr += u32::from(immediate) * 4 + 4;
+            }
+            0xc0 => {
+                // BIND_OPCODE_DO_BIND_ULEB_TIMES_SKIPPING_ULEB
+                let count = read_uleb128(opcodes, &mut offset);
+                let skip = read_uleb128(opcodes, &mut offset);
+                for _ in 0..count {
+                    bind(addr, &symbol, type_, addend);
+                    addr = addr.wrapping_add(skip + 4);
+                }
+            }
+            _ => panic!("Unexpected bind opcode {:#x}", byte),
+        }
+    }
+}
+
 fn cpu_subtype_to_str(ty: cpu_subtype_t) -> &'static str {
     match ty {
         mach_object::CPU_SUBTYPE_ARM_ALL => "armv???",
@@ -296,6 +563,11 @@ impl MachO {
 
         let split_segs = (header.flags & mach_object::MH_SPLIT_SEGS) != 0;
 
+        let slide = choose_slide(bytes, header.filetype, into_mem);
+        if slide != 0 {
+            log!("Loading {:?} with a slide of {:#x}", name, slide);
+        }
+
         // Info used while parsing file
         let mut first_segment_base: Option<u32> = None;
         let mut first_read_write_segment_base: Option<u32> = None;
@@ -306,7 +578,9 @@ impl MachO {
         // Info used for the result
         let mut dynamic_libraries = Vec::new();
         let mut exported_symbols = HashMap::new();
+        let mut local_symbols = Vec::new();
         let mut indirect_undef_symbols: Vec<Option<String>> = Vec::new();
+        let mut weak_undef_symbols: Vec<bool> = Vec::new();
         let mut external_relocations: Vec<(u32, String)> = Vec::new();
         let mut entry_point_pc: Option<u32> = None;
 
@@ -359,7 +633,11 @@ impl MachO {
                     };
 
                     if load_me {
-                        into_mem.reserve(vmaddr, vmsize);
+                        // A slid binary's memory was already allocated.
+                        let vmaddr = vmaddr + slide;
+                        if slide == 0 {
+                            into_mem.reserve(vmaddr, vmsize);
+                        }
 
                         // If filesize is less than vmsize, the rest of the
                         // segment should be filled with zeroes. We are assuming
@@ -399,19 +677,24 @@ impl MachO {
                             }
                             if let Symbol::Defined {
                                 name: Some(name),
-                                external: true,
+                                external,
                                 entry,
                                 desc,
                                 ..
                             } = symbol
                             {
                                 let entry: u32 = entry.try_into().unwrap();
+                                let entry = entry + slide;
                                 let entry = if desc & N_ARM_THUMB_DEF != 0 {
                                     entry | GuestFunction::THUMB_BIT
                                 } else {
                                     entry
                                 };
-                                exported_symbols.insert(name.to_string(), entry);
+                                if external {
+                                    exported_symbols.insert(name.to_string(), entry);
+                                } else {
+                                    local_symbols.push((entry, name.to_string()));
+                                }
                             };
                         }
                     }
@@ -437,6 +720,10 @@ impl MachO {
                             is_64bit,
                             &mut cursor,
                         );
+                        weak_undef_symbols.push(matches!(
+                            sym,
+                            Some(Symbol::Undefined { desc, .. }) if desc & N_WEAK_REF != 0
+                        ));
                         indirect_undef_symbols.push(match sym {
                             // apparently used in apps?
                             Some(Symbol::Undefined { name: Some(n), .. }) => Some(String::from(n)),
@@ -468,6 +755,7 @@ impl MachO {
                         } else {
                             addr + first_segment_base.unwrap()
                         };
+                        let addr = addr + slide;
 
                         let mut cursor = cursor.clone();
                         let sym = get_sym_by_idx(
@@ -489,7 +777,7 @@ impl MachO {
                                 // Resolve them immediately, there is no value
                                 // in passing these on to Dyld.
                                 let addr = Ptr::from_bits(addr);
-                                let entry = entry as u32;
+                                let entry = entry as u32 + slide;
                                 let entry = if desc & N_ARM_THUMB_DEF != 0 {
                                     entry | GuestFunction::THUMB_BIT
                                 } else {
@@ -530,7 +818,7 @@ impl MachO {
                     };
                     // There should only be a single initial thread state.
                     assert!(entry_point_pc.is_none());
-                    entry_point_pc = Some(pc);
+                    entry_point_pc = Some(pc + slide);
                 }
                 // New-style entry point PC command
                 LoadCommand::EntryPoint {
@@ -544,17 +832,109 @@ impl MachO {
                     // (Presumably an executable won't use both commands?)
                     assert!(entry_point_pc.is_none());
                     let entryoff: u32 = entryoff.try_into().unwrap();
-                    entry_point_pc = Some(text_segment_base.unwrap() + entryoff);
+                    entry_point_pc = Some(text_segment_base.unwrap() + entryoff + slide);
                 }
-                // LoadCommand::DyldInfo is apparently a newer thing that 2008
-                // games don't have. Ignore for now? Unsure if/when iOS got it.
-                LoadCommand::DyldInfo { .. } => {
-                    log!("Warning! DyldInfo is not handled.");
+                _ => (),
+            }
+        }
+
+        // Things mach_object doesn't parse for us.
+        let mut init_routine_pc = None;
+        let mut local_relocations = None;
+        let mut dyld_info = None;
+        for (cmd, data) in raw_load_commands(bytes) {
+            match cmd {
+                LC_ROUTINES => init_routine_pc = Some(read_u32(data, 8) + slide),
+                LC_DYSYMTAB => local_relocations = Some((read_u32(data, 72), read_u32(data, 76))),
+                LC_DYLD_INFO | LC_DYLD_INFO_ONLY => {
+                    let field = |i| read_u32(data, 8 + i * 4) as usize;
+                    dyld_info = Some([
+                        (field(0), field(1)), // rebase
+                        (field(2), field(3)), // bind
+                        (field(4), field(5)), // weak bind
+                    ]);
+                }
+                LC_REEXPORT_DYLIB => {
+                    let name_offset = read_u32(data, 8) as usize;
+                    let dylib_name = &data[name_offset..];
+                    let dylib_name = dylib_name.split(|&b| b == 0).next().unwrap();
+                    // TODO: support re-exports
+                    log!(
+                        "Warning: ignoring re-exported dylib {:?} in {:?}",
+                        String::from_utf8_lossy(dylib_name),
+                        name
+                    );
                 }
                 _ => (),
             }
         }
 
+        // The dyld info (LC_DYLD_INFO) is a newer format that 2008 games
+        // don't have, but later apps and dylibs may. Older binaries use
+        // relocations instead.
+        if let Some([rebase, bind, weak_bind]) = dyld_info {
+            let segment_bases = raw_segment_bases(bytes);
+            if slide != 0 {
+                let (off, size) = rebase;
+                apply_rebase_opcodes(&bytes[off..][..size], &segment_bases, slide, into_mem);
+            }
+            // Binaries may have both formats for compatibility.
+            if external_relocations.is_empty() {
+                let (off, size) = bind;
+                parse_bind_opcodes(
+                    &bytes[off..][..size],
+                    &segment_bases,
+                    slide,
+                    into_mem,
+                    &mut external_relocations,
+                );
+            }
+            if weak_bind.1 != 0 {
+                // TODO: support weak binding
+                log!("Warning: ignoring weak bindings in {:?}", name);
+            }
+        } else if slide != 0 {
+            let (locreloff, nlocrel) = local_relocations.unwrap();
+            let locrels = &bytes[locreloff as usize..][..nlocrel as usize * 8];
+            for entry in locrels.chunks(8) {
+                let (addr, is_pc_relative, size, type_) =
+                    match Reloc::parse(is_bigend, entry.try_into().unwrap()) {
+                        Reloc::Local {
+                            addr,
+                            is_pc_relative,
+                            size,
+                            type_,
+                            ..
+                        } => (addr, is_pc_relative, size, type_),
+                        Reloc::Scattered {
+                            offset,
+                            is_pc_relative,
+                            size,
+                            type_,
+                            ..
+                        } => (offset, is_pc_relative, size, type_),
+                        Reloc::External { .. } => unreachable!(),
+                    };
+                match type_ {
+                    // ARM_RELOC_VANILLA, ARM_RELOC_PB_LA_PTR
+                    0 | 4 if !is_pc_relative && size == 4 => (),
+                    // ARM_RELOC_PAIR, ARM_RELOC_SECTDIFF,
+                    // ARM_RELOC_LOCAL_SECTDIFF: differences between two
+                    // addresses, which the slide doesn't affect
+                    1..=3 => continue,
+                    _ => panic!("Unhandled local relocation: {:?}", entry),
+                }
+                let addr = if split_segs {
+                    addr + first_read_write_segment_base.unwrap()
+                } else {
+                    addr + first_segment_base.unwrap()
+                };
+                let ptr: MutPtr<u32> = Ptr::from_bits(addr + slide);
+                let value = into_mem.read(ptr);
+                into_mem.write(ptr, value.wrapping_add(slide));
+            }
+        }
+
         let sections = all_sections
             .iter()
             .map(|section| {
@@ -562,6 +942,7 @@ impl MachO {
 
                 let name = section.sectname.clone();
                 let addr: u32 = section.addr.try_into().unwrap();
+                let addr = addr + slide;
                 let size: u32 = section.size.try_into().unwrap();
                 let type_ = section.flags.sect_type();
 
@@ -590,9 +971,11 @@ impl MachO {
                     let indirect_count = (size / entry_size) as usize;
                     let indirects = &mut indirect_undef_symbols[indirect_start..][..indirect_count];
                     let syms = indirects.iter_mut().map(|sym| sym.take()).collect();
+                    let weak = weak_undef_symbols[indirect_start..][..indirect_count].to_vec();
                     DyldIndirectSymbolInfo {
                         entry_size,
                         indirect_undef_symbols: syms,
+                        weak_undef_symbols: weak,
                     }
                 });
 
@@ -611,8 +994,11 @@ impl MachO {
             dynamic_libraries,
             sections,
             exported_symbols,
+            local_symbols,
             external_relocations,
             entry_point_pc,
+            init_routine_pc,
+            slide,
         })
     }
 
@@ -364,6 +364,18 @@ impl Mem {
             .get_mut(addr.to_bits() as usize..)?
             .get_mut(..count as usize)
     }
+    /// Special version of [Self::read] that returns [None] rather than
+    /// panicking on failure. Only for use by [crate::crash_report] and the
+    /// debugging helpers it relies on, which mustn't panic while a panic is
+    /// being reported.
+    pub fn read_fallible<T, const MUT: bool>(&self, ptr: Ptr<T, MUT>) -> Option<T>
+    where
+        T: SafeRead,
+    {
+        let bytes = self.get_bytes_fallible(Ptr::from_bits(ptr.to_bits()), guest_size_of::<T>())?;
+        // See Self::read.
+        Some(unsafe { bytes.as_ptr().cast::<T>().read_unaligned() })
+    }
 
     /// Get a slice for reading `count` bytes. This is the basic primitive for
     /// safe read-only memory access.
@@ -506,27 +518,71 @@ impl Mem {
             .copy_within(src..src.checked_add(size).unwrap(), dest)
     }
 
-    /// Allocate `size` bytes.
+    /// Allocate `size` bytes. This ignores the heap limit (see
+    /// [Self::set_heap_limit]), because touchHLE's own allocations, e.g. for
+    /// Objective-C objects, have no way to report failure.
     pub fn alloc(&mut self, size: GuestUSize) -> MutVoidPtr {
         let ptr = Ptr::from_bits(self.allocator.alloc(size));
         log_dbg!("Allocated {:?} ({:#x} bytes)", ptr, size);
         ptr
     }
 
+    /// Like [Self::alloc], but returns [None] if the heap limit (see
+    /// [Self::set_heap_limit]) has been reached. This is for `malloc()` and
+    /// friends, which can report failure to the app.
+    pub fn try_alloc(&mut self, size: GuestUSize) -> Option<MutVoidPtr> {
+        let Some(ptr) = self.allocator.try_alloc(size) else {
+            log!(
+                "Warning: allocation of {:#x} bytes failed, the app is out of memory!",
+                size
+            );
+            return None;
+        };
+        let ptr = Ptr::from_bits(ptr);
+        log_dbg!("Allocated {:?} ({:#x} bytes)", ptr, size);
+        Some(ptr)
+    }
+
+    /// Set the maximum total size of allocations, beyond which
+    /// [Self::try_alloc] and [Self::try_realloc] fail. Allocations made with
+    /// [Self::alloc] count towards it but are never refused. See
+    /// `--guest-memory=`.
+    pub fn set_heap_limit(&mut self, limit: GuestUSize) {
+        self.allocator.set_limit(limit);
+    }
+
+    /// Get the total size of the allocations currently made with the `alloc`
+    /// methods on this type, i.e. how much of the heap is in use.
+    pub fn allocated_bytes(&self) -> GuestUSize {
+        self.allocator.allocated_bytes()
+    }
+
     pub fn realloc(&mut self, old_ptr: MutVoidPtr, size: GuestUSize) -> MutVoidPtr {
         if old_ptr.is_null() {
             return self.alloc(size);
         }
+        let Some(new_ptr) = self.try_realloc(old_ptr, size) else {
+            panic!("Could not reallocate {:?} to {:#x} bytes", old_ptr, size);
+        };
+        new_ptr
+    }
+
+    /// Like [Self::realloc], but returns [None] rather than panicking if the
+    /// heap limit has been reached. The old allocation is left alone then.
+    pub fn try_realloc(&mut self, old_ptr: MutVoidPtr, size: GuestUSize) -> Option<MutVoidPtr> {
+        if old_ptr.is_null() {
+            return self.try_alloc(size);
+        }
         // TODO: for a moment we always assume that we do not have enough size
         //       to realloc inplace
         let old_size = self.allocator.find_allocated_size(old_ptr.to_bits());
         if old_size >= size {
-            return old_ptr;
+            return Some(old_ptr);
         }
-        let new_ptr = self.alloc(size);
+        let new_ptr = self.try_alloc(size)?;
         self.memmove(new_ptr, old_ptr.cast_const(), old_size);
         self.free(old_ptr);
-        new_ptr
+        Some(new_ptr)
     }
 
     /// Free an allocation made with one of the `alloc` methods on this type.
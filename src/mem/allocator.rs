@@ -12,6 +12,13 @@ use std::num::NonZeroU32;
 /// TODO: also do the 4096-byte alignment.
 pub const MIN_CHUNK_SIZE: GuestUSize = 16;
 
+/// Allocations at least this large are taken from the end of a free chunk
+/// rather than the start, so that they are kept apart from small allocations.
+/// Otherwise, small long-lived allocations could end up scattered between
+/// large ones, and when those are freed, the free space would be too
+/// fragmented to make new large allocations from.
+const LARGE_ALLOCATION_SIZE: GuestUSize = 1024 * 1024;
+
 /// A non-empty range of bytes in virtual address space.
 ///
 /// Similar to [`RangeInclusive<u32>`][std::ops::RangeInclusive] but with a
@@ -96,6 +103,44 @@ mod chunk_tests {
     }
 }
 
+#[cfg(test)]
+mod allocator_tests {
+    use super::Allocator;
+    #[test]
+    fn test_limit_and_large_allocations() {
+        let mut allocator = Allocator::new();
+        allocator.set_limit(4 * 1024 * 1024);
+
+        let small = allocator.try_alloc(100).unwrap();
+        let large = allocator.try_alloc(2 * 1024 * 1024).unwrap();
+        // Large allocations are kept away from small ones.
+        assert!(large - small > 1024 * 1024 * 1024);
+        assert_eq!(allocator.allocated_bytes(), 112 + 2 * 1024 * 1024);
+
+        assert_eq!(allocator.try_alloc(3 * 1024 * 1024), None);
+        assert_eq!(allocator.free(large), 2 * 1024 * 1024);
+        assert!(allocator.try_alloc(3 * 1024 * 1024).is_some());
+    }
+    #[test]
+    fn test_limit_only_refuses_try_alloc() {
+        let mut allocator = Allocator::new();
+        allocator.set_limit(1024 * 1024);
+
+        // Fill up the limit, like an app calling malloc() until it fails.
+        while allocator.try_alloc(64 * 1024).is_some() {}
+        assert_eq!(allocator.allocated_bytes(), 1024 * 1024);
+        assert_eq!(allocator.try_alloc(16), None);
+
+        // Allocations made by touchHLE itself, e.g. for Objective-C objects,
+        // still succeed, and count towards the limit.
+        let host = allocator.alloc(64 * 1024);
+        assert_eq!(allocator.allocated_bytes(), 1024 * 1024 + 64 * 1024);
+        assert_eq!(allocator.try_alloc(16), None);
+        assert_eq!(allocator.free(host), 64 * 1024);
+        assert_eq!(allocator.allocated_bytes(), 1024 * 1024);
+    }
+}
+
 /// Specialized collection types. They're kept in their own module so the
 /// allocator can only access them via their public methods, so that there's
 /// less places inconsistencies between the sub-collections could happen.
@@ -179,7 +224,12 @@ mod collections {
             Some(chunk)
         }
 
-        fn allocate_in_bucket(&mut self, size: GuestUSize, bucket: usize) -> Option<Chunk> {
+        fn allocate_in_bucket(
+            &mut self,
+            size: GuestUSize,
+            bucket: usize,
+            from_end: bool,
+        ) -> Option<Chunk> {
             let (idx, _) = {
                 let mut best_chunk: Option<(usize, GuestUSize)> = None;
 
@@ -211,6 +261,16 @@ mod collections {
                 return Some(existing);
             }
 
+            // Reserved chunks might not end on an aligned address, in which
+            // case the allocation can't be taken from the end.
+            let end = existing.last_byte().wrapping_add(1);
+            if from_end && end % MIN_CHUNK_SIZE == 0 {
+                let alloc = Chunk::new(end.wrapping_sub(size), size);
+                let rump = Chunk::new(existing.base, existing.size.get() - size);
+                self.insert(rump);
+                return Some(alloc);
+            }
+
             let alloc = Chunk::new(existing.base, size);
             let rump = Chunk::new(existing.base + size, existing.size.get() - size);
             self.insert(rump);
@@ -218,14 +278,14 @@ mod collections {
             Some(alloc)
         }
 
-        pub fn allocate(&mut self, size: GuestUSize) -> Option<Chunk> {
+        pub fn allocate(&mut self, size: GuestUSize, from_end: bool) -> Option<Chunk> {
             assert!(size >= MIN_CHUNK_SIZE);
 
             // Look in the smallest bucket first. This is the only bucket where
             // an exact match can be found.
 
             let bucket = Self::bucket_for(size);
-            if let Some(alloc) = self.allocate_in_bucket(size, bucket) {
+            if let Some(alloc) = self.allocate_in_bucket(size, bucket, from_end) {
                 return Some(alloc);
             }
 
@@ -237,7 +297,7 @@ mod collections {
                 .position(|bucket| !bucket.is_empty())?
                 + bucket
                 + 1;
-            self.allocate_in_bucket(size, bucket)
+            self.allocate_in_bucket(size, bucket, from_end)
         }
 
         pub fn iter(&self) -> impl Iterator<Item = Chunk> + '_ {
@@ -255,6 +315,11 @@ use collections::{ChunkMap, SizeBucketedChunkMap};
 pub struct Allocator {
     used_chunks: ChunkMap,
     unused_chunks: SizeBucketedChunkMap,
+    /// Total size of the chunks handed out by [Allocator::alloc] and not yet
+    /// freed. Reserved chunks aren't counted.
+    allocated_bytes: GuestUSize,
+    /// Maximum value of `allocated_bytes`, see [Allocator::set_limit].
+    limit: GuestUSize,
 }
 
 impl Allocator {
@@ -272,6 +337,8 @@ impl Allocator {
         Allocator {
             used_chunks,
             unused_chunks,
+            allocated_bytes: 0,
+            limit: GuestUSize::MAX,
         }
     }
 
@@ -299,23 +366,49 @@ impl Allocator {
         self.used_chunks.insert(chunk);
     }
 
-    pub fn alloc(&mut self, size: GuestUSize) -> VAddr {
-        let size = size.max(MIN_CHUNK_SIZE);
-        let size = if size % MIN_CHUNK_SIZE != 0 {
-            size + MIN_CHUNK_SIZE - (size % MIN_CHUNK_SIZE)
-        } else {
-            size
-        };
+    /// Limit the total size of allocations made with [Allocator::try_alloc].
+    /// The address space is much larger than the RAM of a real device, so
+    /// without a limit, an app that leaks memory would never find out.
+    pub fn set_limit(&mut self, limit: GuestUSize) {
+        self.limit = limit;
+    }
 
-        let Some(alloc) = self.unused_chunks.allocate(size) else {
+    /// Returns [None] if the limit has been reached or there's no free chunk
+    /// large enough. This is for allocations the app can handle failure of.
+    pub fn try_alloc(&mut self, size: GuestUSize) -> Option<VAddr> {
+        let size = Self::round_size(size)?;
+        if size > self.limit.saturating_sub(self.allocated_bytes) {
+            return None;
+        }
+        self.alloc_rounded(size)
+    }
+
+    /// Allocations made with this aren't refused once the limit is reached,
+    /// because there's no way to report failure for them, but they still count
+    /// towards it.
+    pub fn alloc(&mut self, size: GuestUSize) -> VAddr {
+        let Some(base) = Self::round_size(size).and_then(|size| self.alloc_rounded(size)) else {
             panic!(
-                "Could not find large enough chunk to allocate {:#x} bytes",
-                size
+                "Could not allocate {:#x} bytes: no free chunk large enough ({:#x} bytes are in use)",
+                size, self.allocated_bytes
             );
         };
+        base
+    }
+
+    fn round_size(size: GuestUSize) -> Option<GuestUSize> {
+        size.max(MIN_CHUNK_SIZE)
+            .checked_next_multiple_of(MIN_CHUNK_SIZE)
+    }
+
+    fn alloc_rounded(&mut self, size: GuestUSize) -> Option<VAddr> {
+        let alloc = self
+            .unused_chunks
+            .allocate(size, /* from_end: */ size >= LARGE_ALLOCATION_SIZE)?;
         self.used_chunks.insert(alloc);
+        self.allocated_bytes += alloc.size.get();
 
-        alloc.base
+        Some(alloc.base)
     }
 
     /// This is used for realloc
@@ -333,6 +426,7 @@ impl Allocator {
             log!("Can't free {:#x}, unknown allocation!", base);
             return 0;
         };
+        self.allocated_bytes = self.allocated_bytes.saturating_sub(freed.size.get());
 
         if let Some(adjacent) = self
             .unused_chunks
@@ -351,9 +445,15 @@ impl Allocator {
         freed.size.get()
     }
 
+    pub fn allocated_bytes(&self) -> GuestUSize {
+        self.allocated_bytes
+    }
+
     pub(super) fn reset_and_drain_used_chunks(&mut self) -> impl Iterator<Item = Chunk> {
         let chunks = std::mem::take(&mut self.used_chunks);
+        let limit = self.limit;
         *self = Allocator::new();
+        self.limit = limit;
         chunks.drain()
     }
 }
@@ -22,7 +22,10 @@ use crate::dyld::{export_c_func, FunctionExports};
 use crate::MutexId;
 use std::collections::HashMap;
 
+mod blocks;
 mod classes;
+mod exceptions;
+mod forwarding;
 mod messages;
 mod methods;
 mod objects;
@@ -30,7 +33,15 @@ mod properties;
 mod selectors;
 mod synchronization;
 
-pub use classes::{objc_classes, Class, ClassExports, ClassTemplate};
+pub use blocks::{block_invoke, concrete_block_class_name};
+pub use classes::{call_load_methods, objc_classes, Class, ClassExports, ClassTemplate};
+pub use exceptions::{
+    catch_exception, is_exception_unwind, objc_setUncaughtExceptionHandler, throw_exception,
+};
+pub use forwarding::{
+    split_type_encoding, EncodedType, ForwardedMessage, ForwardingHandler, MethodSignature,
+    ValueKind,
+};
 pub use messages::{
     autorelease, msg, msg_class, msg_send, msg_send_super2, msg_super, objc_super, release, retain,
 };
@@ -40,12 +51,25 @@ pub use objects::{
 };
 pub use selectors::{selector, SEL};
 
-use classes::{ClassHostObject, FakeClass, UnimplementedClass, CLASS_LISTS};
+use blocks::{_Block_copy, _Block_object_assign, _Block_object_dispose, _Block_release};
+use classes::{
+    class_getSuperclass, class_isMetaClass, objc_getClass, objc_getMetaClass, objc_lookUpClass,
+    ClassHostObject, FakeClass, UnimplementedClass, CLASS_LISTS,
+};
+use exceptions::{
+    objc_exception_extract, objc_exception_match, objc_exception_throw, objc_exception_try_enter,
+    objc_exception_try_exit,
+};
 use messages::{
     objc_msgSend, objc_msgSendSuper2, objc_msgSend_stret, MsgSendSignature, MsgSendSuperSignature,
 };
-use methods::method_list_t;
-use objects::{objc_object, HostObjectEntry};
+use methods::{
+    class_addMethod, class_copyMethodList, class_getClassMethod, class_getInstanceMethod,
+    class_getMethodImplementation, class_replaceMethod, class_respondsToSelector,
+    method_exchangeImplementations, method_getImplementation, method_getName,
+    method_getTypeEncoding, method_list_t, method_setImplementation,
+};
+use objects::{objc_object, object_getClass, HostObjectEntry};
 use properties::{objc_copyStruct, objc_getProperty, objc_setProperty};
 use selectors::sel_registerName;
 use synchronization::{objc_sync_enter, objc_sync_exit};
@@ -78,6 +102,34 @@ pub struct ObjC {
     /// Type information isn't part of the `objc_msgSend` ABI, so an alternative
     /// channel is needed.
     message_type_info: Option<(std::any::TypeId, &'static str)>,
+
+    /// `Method` structs handed out by the runtime reflection functions, for
+    /// each class and selector. See [methods::Method].
+    method_structs: HashMap<(Class, SEL), methods::Method>,
+    /// Reverse mapping of [Self::method_structs].
+    method_struct_owners: HashMap<methods::Method, (Class, SEL)>,
+    /// Guest function pointers created for host method implementations, so
+    /// that they can be handed out as `IMP`s. The key is the address of the
+    /// [HostIMP], the value is the function pointer's address.
+    host_imp_functions: HashMap<usize, u32>,
+    /// Reverse mapping of [Self::host_imp_functions].
+    host_imp_function_owners: HashMap<u32, &'static dyn HostIMP>,
+
+    /// `+load` methods from the app binary that haven't been called yet, in
+    /// the order they should be called. See [call_load_methods].
+    load_methods: Vec<(Class, methods::GuestIMP)>,
+
+    /// Handlers for messages that instances of a class have no method for.
+    /// See [forwarding].
+    forwarding_handlers: HashMap<Class, ForwardingHandler>,
+
+    /// Active `@try` blocks on each thread, innermost last. See [exceptions].
+    exception_handlers: HashMap<crate::ThreadId, Vec<exceptions::ExceptionHandler>>,
+    /// Exceptions thrown to a `@try` block that haven't been retrieved with
+    /// `objc_exception_extract` yet, keyed by the block's exception data.
+    caught_exceptions: HashMap<crate::mem::MutVoidPtr, id>,
+    /// See [objc_setUncaughtExceptionHandler].
+    uncaught_exception_handler: Option<crate::abi::GuestFunction>,
 }
 
 impl ObjC {
@@ -88,6 +140,15 @@ impl ObjC {
             classes: HashMap::new(),
             sync_mutexes: HashMap::new(),
             message_type_info: None,
+            method_structs: HashMap::new(),
+            method_struct_owners: HashMap::new(),
+            host_imp_functions: HashMap::new(),
+            host_imp_function_owners: HashMap::new(),
+            load_methods: Vec::new(),
+            forwarding_handlers: HashMap::new(),
+            exception_handlers: HashMap::new(),
+            caught_exceptions: HashMap::new(),
+            uncaught_exception_handler: None,
         }
     }
 }
@@ -101,5 +162,33 @@ pub const FUNCTIONS: FunctionExports = &[
     export_c_func!(objc_copyStruct(_, _, _, _, _)),
     export_c_func!(objc_sync_enter(_)),
     export_c_func!(objc_sync_exit(_)),
+    export_c_func!(objc_exception_try_enter(_)),
+    export_c_func!(objc_exception_try_exit(_)),
+    export_c_func!(objc_exception_throw(_)),
+    export_c_func!(objc_exception_extract(_)),
+    export_c_func!(objc_exception_match(_, _)),
+    export_c_func!(objc_setUncaughtExceptionHandler(_)),
     export_c_func!(sel_registerName(_)),
+    export_c_func!(objc_getClass(_)),
+    export_c_func!(objc_lookUpClass(_)),
+    export_c_func!(objc_getMetaClass(_)),
+    export_c_func!(object_getClass(_)),
+    export_c_func!(class_getSuperclass(_)),
+    export_c_func!(class_isMetaClass(_)),
+    export_c_func!(class_respondsToSelector(_, _)),
+    export_c_func!(class_getInstanceMethod(_, _)),
+    export_c_func!(class_getClassMethod(_, _)),
+    export_c_func!(class_getMethodImplementation(_, _)),
+    export_c_func!(class_addMethod(_, _, _, _)),
+    export_c_func!(class_replaceMethod(_, _, _, _)),
+    export_c_func!(class_copyMethodList(_, _)),
+    export_c_func!(method_getName(_)),
+    export_c_func!(method_getTypeEncoding(_)),
+    export_c_func!(method_getImplementation(_)),
+    export_c_func!(method_setImplementation(_, _)),
+    export_c_func!(method_exchangeImplementations(_, _)),
+    export_c_func!(_Block_copy(_)),
+    export_c_func!(_Block_release(_)),
+    export_c_func!(_Block_object_assign(_, _, _)),
+    export_c_func!(_Block_object_dispose(_, _)),
 ];
@@ -0,0 +1,368 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Blocks runtime (`_Block_copy` and friends).
+//!
+//! Blocks are a C language extension that Apple introduced in iPhone OS 4.
+//! The compiler does most of the work: a block literal is a struct on the
+//! stack (or a constant, if it captures nothing), and calling a block means
+//! calling the function pointer in that struct. The runtime only has to copy
+//! blocks to the heap and manage the lifetimes of their captured variables.
+//! Blocks are also Objective-C objects, which is how `[block copy]` and
+//! `[array addObject:block]` work.
+//!
+//! Resources:
+//! - The [Block Implementation Specification](https://clang.llvm.org/docs/Block-ABI-Apple.html).
+//! - Apple's libclosure, particularly [runtime.c](https://opensource.apple.com/source/libclosure/libclosure-38/runtime.c.auto.html).
+
+use super::{id, nil, objc_classes, release, retain, Class, ClassExports};
+use crate::abi::{CallFromHost, GuestFunction};
+use crate::mem::{ConstPtr, GuestUSize, MutPtr, MutVoidPtr, Ptr, SafeRead};
+use crate::Environment;
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone)]
+#[repr(C, packed)]
+struct Block_literal {
+    isa: Class,
+    flags: u32,
+    _reserved: u32,
+    invoke: GuestFunction,
+    descriptor: ConstPtr<Block_descriptor>,
+    // captured variables follow
+}
+unsafe impl SafeRead for Block_literal {}
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone)]
+#[repr(C, packed)]
+struct Block_descriptor {
+    _reserved: GuestUSize,
+    size: GuestUSize,
+    // Only present if BLOCK_HAS_COPY_DISPOSE is set.
+    copy_helper: GuestFunction,
+    dispose_helper: GuestFunction,
+}
+unsafe impl SafeRead for Block_descriptor {}
+
+/// Header of a `__block` variable.
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone)]
+#[repr(C, packed)]
+struct Block_byref {
+    isa: MutVoidPtr,
+    forwarding: MutPtr<Block_byref>,
+    flags: u32,
+    size: GuestUSize,
+    // Only present if BLOCK_BYREF_HAS_COPY_DISPOSE is set.
+    keep: GuestFunction,
+    destroy: GuestFunction,
+}
+unsafe impl SafeRead for Block_byref {}
+
+/// Size of [Block_byref] without the optional helpers.
+const BLOCK_BYREF_HEADER_SIZE: GuestUSize = 16;
+
+const BLOCK_REFCOUNT_MASK: u32 = 0xfffe;
+const BLOCK_NEEDS_FREE: u32 = 1 << 24;
+const BLOCK_HAS_COPY_DISPOSE: u32 = 1 << 25;
+const BLOCK_IS_GLOBAL: u32 = 1 << 28;
+const BLOCK_BYREF_HAS_COPY_DISPOSE: u32 = 1 << 25;
+
+const BLOCK_FIELD_IS_OBJECT: i32 = 3;
+const BLOCK_FIELD_IS_BLOCK: i32 = 7;
+const BLOCK_FIELD_IS_BYREF: i32 = 8;
+const BLOCK_BYREF_CALLER: i32 = 128;
+
+/// The reference count is stored in the flags, in units of 2.
+const REFCOUNT_ONE: u32 = 2;
+
+/// For use by [crate::dyld]: the class to link a `_NSConcrete*Block` symbol
+/// to, if any. These are the `isa` of block literals.
+pub fn concrete_block_class_name(symbol: &str) -> Option<&'static str> {
+    match symbol {
+        "__NSConcreteStackBlock" => Some("__NSStackBlock__"),
+        "__NSConcreteGlobalBlock" => Some("__NSGlobalBlock__"),
+        "__NSConcreteMallocBlock" => Some("__NSMallocBlock__"),
+        _ => None,
+    }
+}
+
+/// Get the function implementing a block. It must be called with the block
+/// itself as the first argument, followed by the block's own arguments, e.g.
+/// `() = block_invoke(env, block).call_from_host(env, (block, error))`.
+pub fn block_invoke(env: &Environment, block: id) -> GuestFunction {
+    assert!(block != nil);
+    env.mem.read(block.cast::<Block_literal>()).invoke
+}
+
+pub(super) fn _Block_copy(env: &mut Environment, block: id) -> id {
+    if block == nil {
+        return nil;
+    }
+    let literal_ptr = block.cast::<Block_literal>();
+    let literal = env.mem.read(literal_ptr);
+    if literal.flags & BLOCK_NEEDS_FREE != 0 {
+        // Already on the heap.
+        let flags = literal.flags + REFCOUNT_ONE;
+        assert!(flags & BLOCK_REFCOUNT_MASK != 0); // overflow
+        write_block_flags(env, literal_ptr, flags);
+        return block;
+    }
+    if literal.flags & BLOCK_IS_GLOBAL != 0 {
+        return block;
+    }
+
+    // Stack block: move it to the heap.
+    let descriptor_ptr = literal.descriptor;
+    let size = env.mem.read(descriptor_ptr.cast::<[GuestUSize; 2]>())[1];
+    let new_block: MutVoidPtr = env.mem.alloc(size);
+    env.mem.memmove(new_block, block.cast().cast_const(), size);
+    let new_literal_ptr = new_block.cast::<Block_literal>();
+    let isa = env.objc.get_known_class("__NSMallocBlock__", &mut env.mem);
+    env.mem.write(
+        new_literal_ptr,
+        Block_literal {
+            isa,
+            flags: (literal.flags & !BLOCK_REFCOUNT_MASK) | BLOCK_NEEDS_FREE | REFCOUNT_ONE,
+            ..literal
+        },
+    );
+    if literal.flags & BLOCK_HAS_COPY_DISPOSE != 0 {
+        let copy_helper = env.mem.read(descriptor_ptr).copy_helper;
+        let old_block: MutVoidPtr = block.cast();
+        () = copy_helper.call_from_host(env, (new_block, old_block));
+    }
+    log_dbg!("_Block_copy({:?}) => {:?}", block, new_block);
+    new_block.cast()
+}
+
+pub(super) fn _Block_release(env: &mut Environment, block: id) {
+    if block == nil {
+        return;
+    }
+    let literal_ptr = block.cast::<Block_literal>();
+    let literal = env.mem.read(literal_ptr);
+    if literal.flags & BLOCK_NEEDS_FREE == 0 {
+        // Stack and global blocks aren't reference-counted.
+        return;
+    }
+    assert!(literal.flags & BLOCK_REFCOUNT_MASK != 0);
+    let flags = literal.flags - REFCOUNT_ONE;
+    write_block_flags(env, literal_ptr, flags);
+    if flags & BLOCK_REFCOUNT_MASK != 0 {
+        return;
+    }
+    log_dbg!("_Block_release({:?}): freeing", block);
+    if literal.flags & BLOCK_HAS_COPY_DISPOSE != 0 {
+        let dispose_helper = env.mem.read(literal.descriptor).dispose_helper;
+        let block_ptr: MutVoidPtr = block.cast();
+        () = dispose_helper.call_from_host(env, (block_ptr,));
+    }
+    env.mem.free(block.cast());
+}
+
+fn write_block_flags(env: &mut Environment, literal_ptr: MutPtr<Block_literal>, flags: u32) {
+    let flags_ptr: MutPtr<u32> = Ptr::from_bits(literal_ptr.to_bits() + 4);
+    env.mem.write(flags_ptr, flags);
+}
+
+/// Called by compiler-generated copy helpers for each captured variable.
+pub(super) fn _Block_object_assign(
+    env: &mut Environment,
+    dest: MutPtr<MutVoidPtr>,
+    object: MutVoidPtr,
+    flags: i32,
+) {
+    // BLOCK_FIELD_IS_WEAK is ignored, since it only matters with garbage
+    // collection.
+    let value = if flags & BLOCK_BYREF_CALLER != 0 {
+        // Called from a __block variable's own helper, which handles the
+        // memory management itself.
+        object
+    } else if flags & BLOCK_FIELD_IS_BYREF != 0 {
+        byref_copy(env, object.cast()).cast()
+    } else if flags & BLOCK_FIELD_IS_BLOCK == BLOCK_FIELD_IS_BLOCK {
+        _Block_copy(env, object.cast()).cast()
+    } else if flags & BLOCK_FIELD_IS_OBJECT == BLOCK_FIELD_IS_OBJECT {
+        retain(env, object.cast()).cast()
+    } else {
+        panic!("Unexpected _Block_object_assign flags: {:#x}", flags);
+    };
+    env.mem.write(dest, value);
+}
+
+/// Called by compiler-generated dispose helpers for each captured variable.
+pub(super) fn _Block_object_dispose(env: &mut Environment, object: MutVoidPtr, flags: i32) {
+    if flags & BLOCK_BYREF_CALLER != 0 {
+        // See _Block_object_assign.
+    } else if flags & BLOCK_FIELD_IS_BYREF != 0 {
+        byref_release(env, object.cast());
+    } else if flags & BLOCK_FIELD_IS_BLOCK == BLOCK_FIELD_IS_BLOCK {
+        _Block_release(env, object.cast());
+    } else if flags & BLOCK_FIELD_IS_OBJECT == BLOCK_FIELD_IS_OBJECT {
+        release(env, object.cast());
+    } else {
+        panic!("Unexpected _Block_object_dispose flags: {:#x}", flags);
+    }
+}
+
+/// Move a `__block` variable to the heap, or add a reference to it if it's
+/// already there.
+fn byref_copy(env: &mut Environment, byref_ptr: MutPtr<Block_byref>) -> MutPtr<Block_byref> {
+    let src = read_byref(env, byref_ptr);
+    let forwarding = read_byref(env, src.forwarding);
+    if forwarding.flags & BLOCK_REFCOUNT_MASK == 0 {
+        let copy_ptr: MutPtr<Block_byref> = env.mem.alloc(src.size).cast();
+        let mut copy = Block_byref {
+            isa: Ptr::null(),
+            forwarding: copy_ptr,
+            // One reference for the stack copy and one for the block.
+            flags: src.flags | BLOCK_NEEDS_FREE | (REFCOUNT_ONE * 2),
+            size: src.size,
+            keep: GuestFunction::from_addr_with_thumb_bit(0),
+            destroy: GuestFunction::from_addr_with_thumb_bit(0),
+        };
+        let src_forwarding_ptr: MutPtr<MutPtr<Block_byref>> =
+            Ptr::from_bits(byref_ptr.to_bits() + 4);
+        env.mem.write(src_forwarding_ptr, copy_ptr);
+        if src.flags & BLOCK_BYREF_HAS_COPY_DISPOSE != 0 {
+            copy.keep = src.keep;
+            copy.destroy = src.destroy;
+            env.mem.write(copy_ptr, copy);
+            () = src.keep.call_from_host(env, (copy_ptr, byref_ptr));
+        } else {
+            write_byref_header(env, copy_ptr, copy);
+            env.mem.memmove(
+                Ptr::from_bits(copy_ptr.to_bits() + BLOCK_BYREF_HEADER_SIZE),
+                Ptr::from_bits(byref_ptr.to_bits() + BLOCK_BYREF_HEADER_SIZE),
+                src.size - BLOCK_BYREF_HEADER_SIZE,
+            );
+        }
+        copy_ptr
+    } else {
+        if forwarding.flags & BLOCK_NEEDS_FREE != 0 {
+            write_byref_flags(env, src.forwarding, forwarding.flags + REFCOUNT_ONE);
+        }
+        src.forwarding
+    }
+}
+
+fn byref_release(env: &mut Environment, byref_ptr: MutPtr<Block_byref>) {
+    let byref_ptr = read_byref(env, byref_ptr).forwarding;
+    let byref = read_byref(env, byref_ptr);
+    if byref.flags & BLOCK_NEEDS_FREE == 0 {
+        return;
+    }
+    assert!(byref.flags & BLOCK_REFCOUNT_MASK != 0);
+    let flags = byref.flags - REFCOUNT_ONE;
+    write_byref_flags(env, byref_ptr, flags);
+    if flags & BLOCK_REFCOUNT_MASK != 0 {
+        return;
+    }
+    if byref.flags & BLOCK_BYREF_HAS_COPY_DISPOSE != 0 {
+        () = byref.destroy.call_from_host(env, (byref_ptr,));
+    }
+    env.mem.free(byref_ptr.cast());
+}
+
+/// Read a `__block` variable's header, including the helpers only if present.
+fn read_byref(env: &Environment, byref_ptr: MutPtr<Block_byref>) -> Block_byref {
+    let [isa, forwarding, flags, size] = env.mem.read(byref_ptr.cast::<[u32; 4]>());
+    let (keep, destroy) = if flags & BLOCK_BYREF_HAS_COPY_DISPOSE != 0 {
+        let byref = env.mem.read(byref_ptr);
+        (byref.keep, byref.destroy)
+    } else {
+        (
+            GuestFunction::from_addr_with_thumb_bit(0),
+            GuestFunction::from_addr_with_thumb_bit(0),
+        )
+    };
+    Block_byref {
+        isa: Ptr::from_bits(isa),
+        forwarding: Ptr::from_bits(forwarding),
+        flags,
+        size,
+        keep,
+        destroy,
+    }
+}
+
+fn write_byref_header(env: &mut Environment, byref_ptr: MutPtr<Block_byref>, byref: Block_byref) {
+    let header = [
+        byref.isa.to_bits(),
+        byref.forwarding.to_bits(),
+        byref.flags,
+        byref.size,
+    ];
+    env.mem.write(byref_ptr.cast::<[u32; 4]>(), header);
+}
+
+fn write_byref_flags(env: &mut Environment, byref_ptr: MutPtr<Block_byref>, flags: u32) {
+    let flags_ptr: MutPtr<u32> = Ptr::from_bits(byref_ptr.to_bits() + 8);
+    env.mem.write(flags_ptr, flags);
+}
+
+pub const CLASSES: ClassExports = objc_classes! {
+
+(env, this, _cmd);
+
+// Blocks don't have host objects, so none of these can use env.objc.borrow().
+
+@implementation NSBlock: NSObject
+
+- (id)copy {
+    _Block_copy(env, this)
+}
+- (id)copyWithZone:(crate::objc::NSZonePtr)_zone {
+    _Block_copy(env, this)
+}
+
+- (())invoke {
+    () = block_invoke(env, this).call_from_host(env, (this,));
+}
+
+@end
+
+// Stack and global blocks are never freed, so retain and release do nothing.
+
+@implementation __NSStackBlock__: NSBlock
+
+- (id)retain {
+    this
+}
+- (())release {
+}
+- (id)autorelease {
+    this
+}
+
+@end
+
+@implementation __NSGlobalBlock__: NSBlock
+
+- (id)retain {
+    this
+}
+- (())release {
+}
+- (id)autorelease {
+    this
+}
+
+@end
+
+@implementation __NSMallocBlock__: NSBlock
+
+- (id)retain {
+    _Block_copy(env, this)
+}
+- (())release {
+    _Block_release(env, this)
+}
+
+@end
+
+};
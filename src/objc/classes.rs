@@ -13,12 +13,15 @@
 mod class_lists;
 pub(super) use class_lists::CLASS_LISTS;
 
+use super::methods::find_method_in_bin_list;
 use super::{
     id, method_list_t, nil, objc_object, AnyHostObject, HostIMP, HostObject, ObjC, IMP, SEL,
 };
+use crate::abi::CallFromHost;
 use crate::mach_o::MachO;
 use crate::mem::{guest_size_of, ConstPtr, ConstVoidPtr, GuestUSize, Mem, Ptr, SafeRead};
-use std::collections::HashMap;
+use crate::Environment;
+use std::collections::{HashMap, HashSet};
 
 /// Generic pointer to an Objective-C class or metaclass.
 ///
@@ -38,6 +41,9 @@ pub(super) struct ClassHostObject {
     pub(super) is_metaclass: bool,
     pub(super) superclass: Class,
     pub(super) methods: HashMap<SEL, IMP>,
+    /// Objective-C type encodings of methods, where known. This is currently
+    /// only the case for methods from the app binary.
+    pub(super) method_types: HashMap<SEL, ConstPtr<u8>>,
     /// Offset into the allocated memory for the object where the ivars of
     /// instances of this class or metaclass (respectively: normal objects or
     /// classes) should live. This is always >= the value in the superclass.
@@ -359,6 +365,7 @@ impl ClassHostObject {
                     (objc.selectors[name], IMP::Host(host_imp))
                 }),
             ),
+            method_types: HashMap::new(),
             // maybe this should be 0 for NSObject? does it matter?
             _instance_start: size,
             instance_size: size,
@@ -384,6 +391,7 @@ impl ClassHostObject {
             is_metaclass,
             superclass,
             methods: HashMap::new(),
+            method_types: HashMap::new(),
             _instance_start: instance_start,
             instance_size,
         };
@@ -476,6 +484,25 @@ impl ObjC {
         self.link_class_inner(name, /* is_metaclass: */ false, mem, false)
     }
 
+    /// For use by the runtime reflection functions: get a class by name, if it
+    /// exists. Unlike [Self::get_known_class], this doesn't panic if there's
+    /// no implementation, and unimplemented classes are treated as missing.
+    fn lookup_class(&mut self, name: &str, mem: &mut Mem) -> Option<Class> {
+        let class = if let Some(class) = self.get_class(name, /* is_metaclass: */ false, mem) {
+            class
+        } else if Self::find_template(name).is_some() {
+            self.link_class_inner(name, /* is_metaclass: */ false, mem, false)
+        } else {
+            return None;
+        };
+        let any = self.get_host_object(class).unwrap().as_any();
+        if any.is::<UnimplementedClass>() {
+            None
+        } else {
+            Some(class)
+        }
+    }
+
     fn link_class_inner(
         &mut self,
         name: &str,
@@ -585,9 +612,11 @@ impl ObjC {
 
         assert!(list.size % 4 == 0);
         let base: ConstPtr<Class> = Ptr::from_bits(list.addr);
+        let mut classes = Vec::new();
         for i in 0..(list.size / 4) {
             let class = mem.read(base + i);
             let metaclass = Self::read_isa(class, mem);
+            classes.push(class);
 
             let name = if let Some(fakes) = substitute_classes(mem, class, metaclass) {
                 let (class_host_object, metaclass_host_object) = fakes;
@@ -616,6 +645,49 @@ impl ObjC {
 
             self.classes.insert(name.to_string(), class);
         }
+
+        // Superclasses' +load methods must be called before subclasses'.
+        let bin_classes = HashSet::from_iter(classes.iter().copied());
+        let mut scheduled = HashSet::new();
+        for class in classes {
+            self.schedule_class_load_method(class, &bin_classes, &mut scheduled, mem);
+        }
+    }
+
+    /// Add the `+load` method of a class from the app binary, if it has one,
+    /// to the list of methods to be called by [call_load_methods], after those
+    /// of its superclasses.
+    fn schedule_class_load_method(
+        &mut self,
+        class: Class,
+        bin_classes: &HashSet<Class>,
+        scheduled: &mut HashSet<Class>,
+        mem: &Mem,
+    ) {
+        // Host classes don't have +load methods, and neither can their
+        // superclasses be from the app binary.
+        if !bin_classes.contains(&class) || !scheduled.insert(class) {
+            return;
+        }
+        // Fake classes are ignored.
+        let Some(&ClassHostObject { superclass, .. }) =
+            self.get_host_object(class).unwrap().as_any().downcast_ref()
+        else {
+            return;
+        };
+        if superclass != nil {
+            self.schedule_class_load_method(superclass, bin_classes, scheduled, mem);
+        }
+
+        // The method table can't be used because it may contain methods from
+        // categories, and +load isn't inherited, so the lookup must use the
+        // class's own method list in the binary.
+        let metaclass = Self::read_isa(class, mem);
+        let class_t { data, .. } = mem.read(metaclass.cast());
+        let class_rw_t { base_methods, .. } = mem.read(data);
+        if let Some(imp) = find_method_in_bin_list(base_methods, "load", mem) {
+            self.load_methods.push((class, imp));
+        }
     }
 
     /// For use by [crate::dyld]: register all the categories from the
@@ -681,6 +753,18 @@ impl ObjC {
                 host_obj.add_methods_from_bin(methods, mem, self);
                 *self.borrow_mut::<ClassHostObject>(class) = host_obj;
             }
+
+            // Categories' +load methods are called after all classes' ones.
+            if let Some(imp) = find_method_in_bin_list(data.class_methods, "load", mem) {
+                if self
+                    .get_host_object(class)
+                    .unwrap()
+                    .as_any()
+                    .is::<ClassHostObject>()
+                {
+                    self.load_methods.push((class, imp));
+                }
+            }
         }
     }
 
@@ -716,4 +800,141 @@ impl ObjC {
             panic!();
         }
     }
+
+    /// Get a method's name in the usual `-[Class selector]` format (or
+    /// `+[Class selector]` if `class` is a metaclass). For debugging purposes.
+    pub fn get_method_name(&self, class: Class, selector: SEL, mem: &Mem) -> String {
+        let host_object = self.get_host_object(class).unwrap();
+        let is_metaclass = if let Some(&ClassHostObject { is_metaclass, .. }) =
+            host_object.as_any().downcast_ref()
+        {
+            is_metaclass
+        } else if let Some(&UnimplementedClass { is_metaclass, .. }) =
+            host_object.as_any().downcast_ref()
+        {
+            is_metaclass
+        } else if let Some(&FakeClass { is_metaclass, .. }) = host_object.as_any().downcast_ref() {
+            is_metaclass
+        } else {
+            panic!();
+        };
+        format!(
+            "{}[{} {}]",
+            if is_metaclass { '+' } else { '-' },
+            self.get_class_name(class),
+            selector.as_str(mem)
+        )
+    }
+
+    /// Get the name of the method that a message send is for, in the same
+    /// format as [Self::get_method_name]. For use by [crate::crash_report], so
+    /// unlike most methods this returns [None] rather than panicking if the
+    /// receiver or selector isn't valid.
+    pub fn describe_message_send(&self, receiver: id, selector: SEL, mem: &Mem) -> Option<String> {
+        if receiver == nil || !self.selectors.values().any(|&sel| sel == selector) {
+            return None;
+        }
+        let class = mem.read_fallible(receiver)?.isa;
+        let host_object = self.get_host_object(class)?.as_any();
+        if !(host_object.is::<ClassHostObject>()
+            || host_object.is::<UnimplementedClass>()
+            || host_object.is::<FakeClass>())
+        {
+            return None;
+        }
+        Some(self.get_method_name(class, selector, mem))
+    }
+
+    /// Get the addresses and names of all methods of known classes that are
+    /// implemented by guest code. For debugging purposes (see
+    /// [crate::profiler]).
+    pub fn get_guest_method_symbols(&self, mem: &Mem) -> Vec<(u32, String)> {
+        let mut symbols = Vec::new();
+        for &class in self.classes.values() {
+            let metaclass = Self::read_isa(class, mem);
+            for class in [class, metaclass] {
+                let Some(ClassHostObject { methods, .. }) =
+                    self.get_host_object(class).unwrap().as_any().downcast_ref()
+                else {
+                    continue;
+                };
+                for (&selector, imp) in methods {
+                    if let IMP::Guest(guest_imp) = *imp {
+                        symbols.push((
+                            guest_imp.addr_with_thumb_bit(),
+                            self.get_method_name(class, selector, mem),
+                        ));
+                    }
+                }
+            }
+        }
+        symbols
+    }
+}
+
+/// For use by [crate::Environment]: call the `+load` methods of classes and
+/// categories from the app binary. This must happen before the app binary's
+/// static initializers are run.
+///
+/// Like in Apple's runtime, superclasses' methods are called before
+/// subclasses', and classes' methods are called before categories'.
+pub fn call_load_methods(env: &mut Environment) {
+    let load_methods = std::mem::take(&mut env.objc.load_methods);
+    if load_methods.is_empty() {
+        return;
+    }
+    let load_sel = env.objc.lookup_selector("load").unwrap();
+    for (class, imp) in load_methods {
+        log_dbg!(
+            "Calling +[{} load] ({:?})",
+            env.objc.get_class_name(class),
+            imp
+        );
+        () = imp.call_from_host(env, (class, load_sel));
+    }
+}
+
+pub(super) fn objc_getClass(env: &mut Environment, name: ConstPtr<u8>) -> Class {
+    let name = env.mem.cstr_at_utf8(name).unwrap().to_string();
+    let class = env.objc.lookup_class(&name, &mut env.mem).unwrap_or(nil);
+    log_dbg!("objc_getClass({:?}) => {:?}", name, class);
+    class
+}
+
+pub(super) fn objc_lookUpClass(env: &mut Environment, name: ConstPtr<u8>) -> Class {
+    // The difference from objc_getClass() is that this doesn't call the class
+    // handler callback, which touchHLE doesn't support anyway.
+    objc_getClass(env, name)
+}
+
+pub(super) fn objc_getMetaClass(env: &mut Environment, name: ConstPtr<u8>) -> Class {
+    let class = objc_getClass(env, name);
+    if class == nil {
+        nil
+    } else {
+        ObjC::read_isa(class, &env.mem)
+    }
+}
+
+pub(super) fn class_getSuperclass(env: &mut Environment, class: Class) -> Class {
+    if class == nil {
+        return nil;
+    }
+    env.objc.borrow::<ClassHostObject>(class).superclass
+}
+
+pub(super) fn class_isMetaClass(env: &mut Environment, class: Class) -> bool {
+    if class == nil {
+        return false;
+    }
+    let host_object = env.objc.get_host_object(class).unwrap().as_any();
+    if let Some(&ClassHostObject { is_metaclass, .. }) = host_object.downcast_ref() {
+        is_metaclass
+    } else if let Some(&UnimplementedClass { is_metaclass, .. }) = host_object.downcast_ref() {
+        is_metaclass
+    } else if let Some(&FakeClass { is_metaclass, .. }) = host_object.downcast_ref() {
+        is_metaclass
+    } else {
+        panic!();
+    }
 }
@@ -7,24 +7,34 @@
 //! very long and frequently-updated list.
 
 use crate::frameworks::{
-    av_audio, core_animation, core_foundation, core_graphics, foundation, media_player, opengles,
-    store_kit, uikit,
+    av_audio, cf_network, core_animation, core_foundation, core_graphics, core_location,
+    foundation, game_kit, media_player, message_ui, opengles, store_kit, uikit,
 };
 
 /// All the lists of classes that the runtime should search through.
 pub const CLASS_LISTS: &[super::ClassExports] = &[
-    crate::app_picker::CLASSES, // Not a framework! Special internal classes.
+    crate::app_picker::CLASSES,   // Not a framework! Special internal classes.
+    crate::objc::blocks::CLASSES, // Not a framework! Part of the runtime.
+    cf_network::cf_http_message::CLASSES, // Special internal classes.
+    core_animation::ca_animation::CLASSES,
+    core_animation::ca_display_link::CLASSES,
     core_animation::ca_eagl_layer::CLASSES,
     core_animation::ca_layer::CLASSES,
+    core_animation::ca_media_timing_function::CLASSES,
     core_graphics::cg_data_provider::CLASSES,
     core_graphics::cg_color::CLASSES,
     core_graphics::cg_color_space::CLASSES,
     core_graphics::cg_context::CLASSES,
+    core_graphics::cg_gradient::CLASSES,
     core_graphics::cg_image::CLASSES,
-    core_foundation::cf_run_loop_timer::CLASSES, // Special internal classes.
+    core_foundation::cf_run_loop_observer::CLASSES, // Special internal classes.
+    core_foundation::cf_run_loop_timer::CLASSES,    // Special internal classes.
+    core_location::cl_location::CLASSES,
+    core_location::cl_location_manager::CLASSES,
     foundation::ns_array::CLASSES,
     foundation::ns_autorelease_pool::CLASSES,
     foundation::ns_bundle::CLASSES,
+    foundation::ns_cache::CLASSES,
     foundation::ns_character_set::CLASSES,
     foundation::ns_coder::CLASSES,
     foundation::ns_data::CLASSES,
@@ -33,33 +43,51 @@ pub const CLASS_LISTS: &[super::ClassExports] = &[
     foundation::ns_dictionary::CLASSES,
     foundation::ns_enumerator::CLASSES,
     foundation::ns_error::CLASSES,
+    foundation::ns_exception::CLASSES,
     foundation::ns_file_handle::CLASSES,
     foundation::ns_file_manager::CLASSES,
+    foundation::ns_index_path::CLASSES,
+    foundation::ns_invocation::CLASSES,
     foundation::ns_keyed_unarchiver::CLASSES,
     foundation::ns_locale::CLASSES,
     foundation::ns_lock::CLASSES,
     foundation::ns_notification::CLASSES,
     foundation::ns_notification_center::CLASSES,
     foundation::ns_null::CLASSES,
+    foundation::ns_number_formatter::CLASSES,
     foundation::ns_object::CLASSES,
     foundation::ns_process_info::CLASSES,
     foundation::ns_run_loop::CLASSES,
     foundation::ns_set::CLASSES,
+    foundation::ns_stream::CLASSES,
     foundation::ns_string::CLASSES,
     foundation::ns_thread::CLASSES,
     foundation::ns_timer::CLASSES,
     foundation::ns_time_zone::CLASSES,
+    foundation::ns_undo_manager::CLASSES,
     foundation::ns_url::CLASSES,
     foundation::ns_url_connection::CLASSES,
     foundation::ns_url_request::CLASSES,
     foundation::ns_user_defaults::CLASSES,
     foundation::ns_value::CLASSES,
+    game_kit::gk_achievement::CLASSES,
+    game_kit::gk_leaderboard::CLASSES,
+    game_kit::gk_leaderboard_view_controller::CLASSES,
+    game_kit::gk_local_player::CLASSES,
+    game_kit::gk_matchmaker::CLASSES,
+    game_kit::gk_score::CLASSES,
     av_audio::av_audio_player::CLASSES,
     media_player::movie_player::CLASSES,
     media_player::music_player::CLASSES,
     media_player::media_query::CLASSES,
+    media_player::media_picker_controller::CLASSES,
+    media_player::volume_view::CLASSES,
+    message_ui::mf_mail_compose_view_controller::CLASSES,
+    message_ui::mf_message_compose_view_controller::CLASSES,
     opengles::eagl::CLASSES,
+    store_kit::sk_payment_queue::CLASSES,
     store_kit::sk_product::CLASSES,
+    store_kit::sk_products_request::CLASSES,
     uikit::ui_accelerometer::CLASSES,
     uikit::ui_activity_indicator_view::CLASSES,
     uikit::ui_application::CLASSES,
@@ -69,6 +97,7 @@ pub const CLASS_LISTS: &[super::ClassExports] = &[
     uikit::ui_font::CLASSES,
     uikit::ui_image::CLASSES,
     uikit::ui_image_picker_controller::CLASSES,
+    uikit::ui_localized_indexed_collation::CLASSES,
     uikit::ui_nib::CLASSES,
     uikit::ui_responder::CLASSES,
     uikit::ui_screen::CLASSES,
@@ -80,8 +109,13 @@ pub const CLASS_LISTS: &[super::ClassExports] = &[
     uikit::ui_view::ui_control::ui_text_field::CLASSES,
     uikit::ui_view::ui_image_view::CLASSES,
     uikit::ui_view::ui_label::CLASSES,
+    uikit::ui_view::ui_navigation_bar::CLASSES,
     uikit::ui_view::ui_scroll_view::CLASSES,
+    uikit::ui_view::ui_scroll_view::ui_table_view::CLASSES,
     uikit::ui_view::ui_scroll_view::ui_text_view::CLASSES,
+    uikit::ui_view::ui_table_view_cell::CLASSES,
     uikit::ui_view::ui_window::CLASSES,
     uikit::ui_view_controller::CLASSES,
+    uikit::ui_view_controller::ui_navigation_controller::CLASSES,
+    uikit::ui_view_controller::ui_table_view_controller::CLASSES,
 ];
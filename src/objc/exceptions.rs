@@ -0,0 +1,214 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Objective-C exceptions (`@try`, `@catch`, `@finally` and `@throw`).
+//!
+//! Apps for iPhone OS 2 and 3 use the "fragile" exception ABI, which is built
+//! on `setjmp` and `longjmp` rather than zero-cost unwinding. A `@try` block
+//! calls `objc_exception_try_enter` with an exception data struct on the stack
+//! and then calls `_setjmp` on the `jmp_buf` at the start of that struct. If
+//! an exception is thrown, the registers saved in that buffer are restored, so
+//! `_setjmp` returns a second time, and the compiled code then uses
+//! `objc_exception_extract` and `objc_exception_match` to pick a `@catch`
+//! block. `@finally` blocks and rethrowing are handled entirely by the
+//! compiled code.
+//!
+//! Unlike a plain `longjmp` (see [crate::libc::setjmp]), exceptions are often
+//! thrown by host code (e.g. `-[NSArray objectAtIndex:]`) or pass through it
+//! (e.g. a guest method called by a host method), so the host stack needs to
+//! be unwound too. This is done by Rust unwinding with a special payload,
+//! which is caught once it reaches the call of the host function that was
+//! made from the guest code with the `@try` block. Each handler therefore
+//! records how many host-to-guest calls were in progress when it was entered.
+//!
+//! Resources:
+//! - [objc4's `objc-exception.mm`](https://opensource.apple.com/source/objc4/objc4-437/runtime/objc-exception.m.auto.html), which contains the fragile ABI implementation.
+
+use super::{id, msg, nil, Class, ObjC};
+use crate::abi::{CallFromHost, GuestFunction};
+use crate::frameworks::foundation::ns_string;
+use crate::libc::setjmp::restore_registers;
+use crate::mem::MutVoidPtr;
+use crate::Environment;
+use crate::ThreadId;
+use std::any::Any;
+
+/// An active `@try` block.
+pub(super) struct ExceptionHandler {
+    /// The exception data struct passed to `objc_exception_try_enter`.
+    data: MutVoidPtr,
+    /// [Environment::guest_call_depth] when the `@try` block was entered.
+    guest_call_depth: usize,
+}
+
+/// Payload for the Rust unwinding used when an exception is thrown.
+struct ExceptionUnwind {
+    thread: ThreadId,
+    guest_call_depth: usize,
+    data: MutVoidPtr,
+}
+
+pub(super) fn objc_exception_try_enter(env: &mut Environment, data: MutVoidPtr) {
+    let guest_call_depth = env.guest_call_depth();
+    log_dbg!(
+        "objc_exception_try_enter({:?}) on thread {} at depth {}",
+        data,
+        env.current_thread,
+        guest_call_depth
+    );
+    env.objc.caught_exceptions.remove(&data);
+    env.objc
+        .exception_handlers
+        .entry(env.current_thread)
+        .or_default()
+        .push(ExceptionHandler {
+            data,
+            guest_call_depth,
+        });
+}
+
+pub(super) fn objc_exception_try_exit(env: &mut Environment, data: MutVoidPtr) {
+    log_dbg!("objc_exception_try_exit({:?})", data);
+    let handlers = env
+        .objc
+        .exception_handlers
+        .get_mut(&env.current_thread)
+        .unwrap();
+    let handler = handlers.pop().unwrap();
+    assert!(handler.data == data);
+}
+
+pub(super) fn objc_exception_throw(env: &mut Environment, exception: id) {
+    throw_exception(env, exception)
+}
+
+pub(super) fn objc_exception_extract(env: &mut Environment, data: MutVoidPtr) -> id {
+    env.objc.caught_exceptions.remove(&data).unwrap_or(nil)
+}
+
+pub(super) fn objc_exception_match(env: &mut Environment, class: Class, exception: id) -> bool {
+    msg![env; exception isKindOfClass:class]
+}
+
+/// Set the function to call before terminating due to an uncaught exception,
+/// returning the previous one. Foundation's `NSSetUncaughtExceptionHandler`
+/// uses this.
+pub fn objc_setUncaughtExceptionHandler(
+    env: &mut Environment,
+    handler: GuestFunction,
+) -> GuestFunction {
+    let handler = (handler.addr_with_thumb_bit() != 0).then_some(handler);
+    std::mem::replace(&mut env.objc.uncaught_exception_handler, handler)
+        .unwrap_or(GuestFunction::from_addr_with_thumb_bit(0))
+}
+
+/// Throw an Objective-C exception, like `@throw`. Execution resumes in the
+/// innermost `@try` block on the current thread. If there is none, the
+/// uncaught exception handler is called and the app is terminated.
+pub fn throw_exception(env: &mut Environment, exception: id) -> ! {
+    let thread = env.current_thread;
+    let current_depth = env.guest_call_depth();
+    let handlers = env.objc.exception_handlers.entry(thread).or_default();
+    // A handler for a host-to-guest call that has already finished can only
+    // be left behind if its guest code skipped objc_exception_try_exit, e.g.
+    // with longjmp(). Its stack frame is gone, so it can't be used.
+    while handlers
+        .last()
+        .is_some_and(|handler| handler.guest_call_depth > current_depth)
+    {
+        handlers.pop();
+    }
+    let Some(handler) = handlers.pop() else {
+        uncaught_exception(env, exception);
+    };
+
+    log_dbg!(
+        "Throwing exception {:?} to handler {:?} on thread {} at depth {}",
+        exception,
+        handler.data,
+        thread,
+        handler.guest_call_depth
+    );
+    env.objc.caught_exceptions.insert(handler.data, exception);
+    std::panic::resume_unwind(Box::new(ExceptionUnwind {
+        thread,
+        guest_call_depth: handler.guest_call_depth,
+        data: handler.data,
+    }))
+}
+
+/// Called by [Environment] when unwinding reaches a host function that was
+/// called from guest code. If the unwinding is for an exception that should
+/// be caught by a `@try` block in that guest code, the CPU state is set up so
+/// that the block's `_setjmp` returns again. Otherwise, unwinding continues.
+///
+/// `guest_call_depth` and `return_pc` are the values of
+/// [Environment::guest_call_depth] and the PC at the time of the call.
+pub fn catch_exception(
+    env: &mut Environment,
+    payload: Box<dyn Any + Send>,
+    guest_call_depth: usize,
+    return_pc: GuestFunction,
+) {
+    let unwind = match payload.downcast::<ExceptionUnwind>() {
+        Ok(unwind)
+            if unwind.thread == env.current_thread
+                && unwind.guest_call_depth == guest_call_depth =>
+        {
+            unwind
+        }
+        Ok(unwind) => std::panic::resume_unwind(unwind),
+        // A real panic.
+        Err(payload) => std::panic::resume_unwind(payload),
+    };
+
+    log_dbg!(
+        "Caught exception for handler {:?} on thread {}",
+        unwind.data,
+        unwind.thread
+    );
+    // Nested host-to-guest calls may have been abandoned, so the PC needs to
+    // be put back. The host function's stub then returns to the LR restored
+    // from the jmp_buf at the start of the exception data.
+    env.cpu.branch(return_pc);
+    let res = restore_registers(env, unwind.data.cast(), 1);
+    env.cpu.regs_mut()[0] = res as u32;
+}
+
+/// Whether a panic payload is an exception being thrown (see
+/// [catch_exception]) rather than a real panic.
+pub fn is_exception_unwind(payload: &(dyn Any + Send)) -> bool {
+    payload.is::<ExceptionUnwind>()
+}
+
+#[cold]
+fn uncaught_exception(env: &mut Environment, exception: id) -> ! {
+    if let Some(handler) = env.objc.uncaught_exception_handler {
+        log_dbg!("Calling uncaught exception handler {:?}", handler);
+        () = handler.call_from_host(env, (exception,));
+    }
+
+    let ns_exception_class = env.objc.get_known_class("NSException", &mut env.mem);
+    let is_ns_exception: bool = msg![env; exception isKindOfClass:ns_exception_class];
+    let (name, reason): (String, id) = if is_ns_exception {
+        let name: id = msg![env; exception name];
+        let reason: id = msg![env; exception reason];
+        (ns_string::to_rust_string(env, name).into_owned(), reason)
+    } else {
+        // Any object can be thrown, not just an NSException.
+        let class = ObjC::read_isa(exception, &env.mem);
+        let description: id = msg![env; exception description];
+        (env.objc.get_class_name(class).to_string(), description)
+    };
+    let reason = if reason == nil {
+        "(null)".into()
+    } else {
+        ns_string::to_rust_string(env, reason)
+    };
+    panic!(
+        "*** Terminating app due to uncaught exception '{}', reason: '{}'",
+        name, reason
+    );
+}
@@ -0,0 +1,553 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Message forwarding, i.e. handling messages an object has no method for.
+//!
+//! Host classes can install a [ForwardingHandler] with
+//! [ObjC::set_forwarding_handler]. The handler can capture the message's
+//! arguments as a [ForwardedMessage], which can later be sent to another
+//! object.
+//!
+//! Otherwise, messages are forwarded like Apple's runtime does (see
+//! [forward_message]): first to the object returned by
+//! `forwardingTargetForSelector:`, then as an `NSInvocation` passed to
+//! `forwardInvocation:`, with the arguments described by the result of
+//! `methodSignatureForSelector:`. See
+//! [crate::frameworks::foundation::ns_invocation].
+//!
+//! Since `objc_msgSend` passes through its arguments without knowing what they
+//! are, the arguments can only be captured with the help of a method
+//! signature, parsed from an Objective-C type encoding.
+//!
+//! Resources:
+//! - Apple's [Type Encodings](https://developer.apple.com/library/archive/documentation/Cocoa/Conceptual/ObjCRuntimeGuide/Articles/ocrtTypeEncodings.html)
+//! - Apple's [Writing ARMv6 code for iOS](https://developer.apple.com/documentation/xcode/writing-armv6-code-for-ios)
+
+use super::messages::{objc_msgSend, objc_msgSend_stret};
+use super::{id, msg, nil, release, Class, ClassHostObject, ObjC, SEL};
+use crate::abi::{extend_stack_for_args, write_next_arg};
+use crate::cpu::Cpu;
+use crate::frameworks::foundation::ns_invocation;
+use crate::mem::{ConstPtr, GuestUSize, Mem, MutVoidPtr, Ptr};
+use crate::Environment;
+
+/// Function called by `objc_msgSend` when the receiver's class has no method
+/// for the selector. It is called with the guest registers and stack exactly
+/// as they were for the original message, so [ForwardedMessage::capture] can
+/// be used. The handler is responsible for setting the return value.
+pub type ForwardingHandler = fn(env: &mut Environment, receiver: id, selector: SEL);
+
+/// The kind of a value, as far as the calling convention is concerned.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ValueKind {
+    Void,
+    /// `id` or `Class`. These might need to be retained.
+    Object,
+    /// Any other type that fits in 4 bytes, e.g. `int`, `float`, pointers.
+    Word,
+    /// `long long` or `double`.
+    DoubleWord,
+    /// A struct, union or array passed by value, e.g. `CGPoint`.
+    Struct {
+        size: GuestUSize,
+    },
+}
+impl ValueKind {
+    /// How many registers or stack words does this use as an argument?
+    pub fn word_count(self) -> usize {
+        match self {
+            ValueKind::Void => 0,
+            ValueKind::Object | ValueKind::Word => 1,
+            ValueKind::DoubleWord => 2,
+            ValueKind::Struct { size } => size.div_ceil(4) as usize,
+        }
+    }
+}
+
+/// A single type from a method type encoding.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EncodedType {
+    /// The type's own encoding, without the offset, e.g. `{CGPoint=ff}`.
+    pub encoding: String,
+    pub kind: ValueKind,
+    pub size: GuestUSize,
+}
+
+/// Split a method type encoding into the return type followed by the argument
+/// types (including `self` and `_cmd`). Returns [None] if it is malformed or
+/// uses something unsupported (e.g. bitfields).
+pub fn split_type_encoding(encoding: &str) -> Option<Vec<EncodedType>> {
+    let mut rest = encoding.as_bytes();
+    let mut types = Vec::new();
+    while !rest.is_empty() {
+        let before = rest;
+        let (kind, size, _) = parse_type(&mut rest)?;
+        let encoding = &before[..before.len() - rest.len()];
+        types.push(EncodedType {
+            encoding: String::from_utf8(encoding.to_vec()).ok()?,
+            kind,
+            size,
+        });
+        skip_offset(&mut rest);
+    }
+    Some(types)
+}
+
+/// Parsed Objective-C method type encoding, e.g. `v16@0:4{CGPoint=ff}8`.
+#[derive(Clone, Debug)]
+pub struct MethodSignature {
+    pub return_kind: ValueKind,
+    /// Kinds of the arguments, not including `self` and `_cmd`.
+    pub arg_kinds: Vec<ValueKind>,
+}
+impl MethodSignature {
+    /// Parse a type encoding. Returns [None] if it is malformed or uses
+    /// something unsupported (e.g. bitfields).
+    pub fn from_type_encoding(encoding: &str) -> Option<MethodSignature> {
+        Self::from_encoded_types(&split_type_encoding(encoding)?)
+    }
+
+    /// Like [MethodSignature::from_type_encoding], but for an encoding that
+    /// has already been split with [split_type_encoding].
+    pub fn from_encoded_types(types: &[EncodedType]) -> Option<MethodSignature> {
+        let (return_type, arg_types) = types.split_first()?;
+        // The first two arguments are always self and _cmd.
+        if arg_types.len() < 2 || arg_types[0].kind != ValueKind::Object {
+            return None;
+        }
+        Some(MethodSignature {
+            return_kind: return_type.kind,
+            arg_kinds: arg_types[2..].iter().map(|ty| ty.kind).collect(),
+        })
+    }
+
+    /// Guess a signature from the selector alone, assuming every argument and
+    /// the return value is a single word. This is only a fallback for when
+    /// there's no type encoding.
+    pub fn guess_from_selector(selector: &str) -> MethodSignature {
+        MethodSignature {
+            return_kind: ValueKind::Word,
+            arg_kinds: vec![ValueKind::Word; selector.matches(':').count()],
+        }
+    }
+
+    /// Is the return value written to memory via a pointer passed as a hidden
+    /// first argument (the `objc_msgSend_stret` convention)?
+    pub fn returns_via_pointer(&self) -> bool {
+        matches!(self.return_kind, ValueKind::Struct { size } if size > 4)
+    }
+
+    pub fn arg_word_count(&self) -> usize {
+        self.arg_kinds.iter().map(|&kind| kind.word_count()).sum()
+    }
+}
+
+/// Skip the stack offset (or register number) that follows each type in a
+/// method type encoding.
+fn skip_offset(rest: &mut &[u8]) {
+    while let [b'+' | b'-' | b'0'..=b'9', tail @ ..] = rest {
+        *rest = tail;
+    }
+}
+
+fn parse_number(rest: &mut &[u8]) -> Option<GuestUSize> {
+    let digits = rest.iter().take_while(|c| c.is_ascii_digit()).count();
+    let number = std::str::from_utf8(&rest[..digits]).ok()?.parse().ok()?;
+    *rest = &rest[digits..];
+    Some(number)
+}
+
+/// Parse a single type from the start of a type encoding, returning its kind,
+/// size and alignment. In Apple's ARMv6 ABI, nothing is aligned to more than 4
+/// bytes.
+fn parse_type(rest: &mut &[u8]) -> Option<(ValueKind, GuestUSize, GuestUSize)> {
+    // Type qualifiers (const, in, out, etc) don't matter here.
+    while let [b'r' | b'n' | b'N' | b'o' | b'O' | b'R' | b'V' | b'A', tail @ ..] = rest {
+        *rest = tail;
+    }
+    let (&c, tail) = rest.split_first()?;
+    *rest = tail;
+    Some(match c {
+        b'v' => (ValueKind::Void, 0, 1),
+        b'c' | b'C' | b'B' => (ValueKind::Word, 1, 1),
+        b's' | b'S' => (ValueKind::Word, 2, 2),
+        b'i' | b'I' | b'l' | b'L' | b'f' | b'*' | b':' | b'?' => (ValueKind::Word, 4, 4),
+        b'q' | b'Q' | b'd' => (ValueKind::DoubleWord, 8, 4),
+        b'#' => (ValueKind::Object, 4, 4),
+        b'@' => {
+            match rest {
+                // Block
+                [b'?', tail @ ..] => *rest = tail,
+                // Class name
+                [b'"', tail @ ..] => {
+                    let end = tail.iter().position(|&c| c == b'"')?;
+                    *rest = &tail[end + 1..];
+                }
+                _ => (),
+            }
+            (ValueKind::Object, 4, 4)
+        }
+        b'^' => {
+            // The pointee type doesn't matter, but it has to be skipped.
+            parse_type(rest)?;
+            (ValueKind::Word, 4, 4)
+        }
+        b'[' => {
+            let count = parse_number(rest)?;
+            let (_, size, align) = parse_type(rest)?;
+            let [b']', tail @ ..] = rest else {
+                return None;
+            };
+            *rest = tail;
+            let size = size * count;
+            (ValueKind::Struct { size }, size, align)
+        }
+        b'{' | b'(' => {
+            let is_union = c == b'(';
+            let close = if is_union { b')' } else { b'}' };
+            // Skip the name. A struct only referenced by a pointer may have no
+            // member list.
+            let name_end = rest.iter().position(|&c| c == b'=' || c == close)?;
+            let has_members = rest[name_end] == b'=';
+            *rest = &rest[name_end + 1..];
+            let mut size = 0;
+            let mut align = 1;
+            if has_members {
+                loop {
+                    if let [c, tail @ ..] = rest {
+                        if *c == close {
+                            *rest = tail;
+                            break;
+                        }
+                    }
+                    // Members might have names in quotes, e.g. in ivars.
+                    if let [b'"', tail @ ..] = rest {
+                        let end = tail.iter().position(|&c| c == b'"')?;
+                        *rest = &tail[end + 1..];
+                    }
+                    let (_, member_size, member_align) = parse_type(rest)?;
+                    align = align.max(member_align);
+                    if is_union {
+                        size = size.max(member_size);
+                    } else {
+                        size = size.next_multiple_of(member_align) + member_size;
+                    }
+                }
+            }
+            let size = size.next_multiple_of(align);
+            (ValueKind::Struct { size }, size, align)
+        }
+        // Bitfields and anything else are unsupported.
+        _ => return None,
+    })
+}
+
+impl ObjC {
+    /// Make `objc_msgSend` call a handler when an instance of `class` (or of a
+    /// subclass) receives a message it has no method for.
+    pub fn set_forwarding_handler(&mut self, class: Class, handler: ForwardingHandler) {
+        self.forwarding_handlers.insert(class, handler);
+    }
+
+    /// Find the forwarding handler for a class, if it or a superclass has one.
+    pub(super) fn find_forwarding_handler(&self, class: Class) -> Option<ForwardingHandler> {
+        let mut class = class;
+        while class != nil {
+            if let Some(&handler) = self.forwarding_handlers.get(&class) {
+                return Some(handler);
+            }
+            let host_object: &ClassHostObject =
+                self.get_host_object(class)?.as_any().downcast_ref()?;
+            class = host_object.superclass;
+        }
+        None
+    }
+
+    /// Get the type encoding of the method instances of a class would use for
+    /// a selector, if they have such a method and its type encoding is known.
+    /// Type encodings are currently only known for methods from the app
+    /// binary.
+    pub fn method_type_encoding<'a>(
+        &self,
+        mem: &'a Mem,
+        class: Class,
+        sel: SEL,
+    ) -> Option<&'a str> {
+        let owner = self.find_method_owner(class, sel)?;
+        let &types = self
+            .borrow::<ClassHostObject>(owner)
+            .method_types
+            .get(&sel)?;
+        mem.cstr_at_utf8(types).ok()
+    }
+
+    /// Get the signature of the method an object would use for a selector, if
+    /// it has such a method and its type encoding is known. See
+    /// [ObjC::method_type_encoding].
+    pub fn method_signature(&self, mem: &Mem, object: id, sel: SEL) -> Option<MethodSignature> {
+        let class = ObjC::read_isa(object, mem);
+        let encoding = self.method_type_encoding(mem, class, sel)?;
+        let signature = MethodSignature::from_type_encoding(encoding);
+        if signature.is_none() {
+            log!(
+                "Warning: couldn't parse type encoding {:?} of method {}",
+                encoding,
+                sel.as_str(mem)
+            );
+        }
+        signature
+    }
+}
+
+/// A message that has been forwarded, with its arguments.
+#[derive(Clone, Debug)]
+pub struct ForwardedMessage {
+    pub selector: SEL,
+    pub signature: MethodSignature,
+    /// The arguments after `self` and `_cmd`, as they would be passed in
+    /// registers and on the stack.
+    pub arg_words: Vec<u32>,
+}
+impl ForwardedMessage {
+    /// Capture the arguments of the message being forwarded. This must only
+    /// be called from a [ForwardingHandler].
+    pub fn capture(
+        env: &mut Environment,
+        selector: SEL,
+        signature: MethodSignature,
+    ) -> ForwardedMessage {
+        // Skip the struct return pointer (if any), self and _cmd.
+        let first = if signature.returns_via_pointer() {
+            3
+        } else {
+            2
+        };
+        let regs = env.cpu.regs();
+        let stack_ptr: ConstPtr<u32> = Ptr::from_bits(regs[Cpu::SP]);
+        let arg_words = (first..first + signature.arg_word_count())
+            .map(|i| {
+                if i < 4 {
+                    regs[i]
+                } else {
+                    env.mem.read(stack_ptr + (i - 4).try_into().unwrap())
+                }
+            })
+            .collect();
+        ForwardedMessage {
+            selector,
+            signature,
+            arg_words,
+        }
+    }
+
+    /// Get the object arguments, e.g. so they can be retained.
+    pub fn object_args(&self) -> Vec<id> {
+        let mut offset = 0;
+        let mut objects = Vec::new();
+        for &kind in &self.signature.arg_kinds {
+            if kind == ValueKind::Object {
+                objects.push(Ptr::from_bits(self.arg_words[offset]));
+            }
+            offset += kind.word_count();
+        }
+        objects
+    }
+
+    /// Send the message to an object. The return value is returned as bytes,
+    /// e.g. 4 bytes for a [ValueKind::Word], or the struct's size for a
+    /// [ValueKind::Struct].
+    pub fn send(&self, env: &mut Environment, receiver: id) -> Vec<u8> {
+        let regs = env.cpu.regs_mut();
+        let old_sp = regs[Cpu::SP];
+
+        let stret_ptr: Option<MutVoidPtr> = match self.signature.return_kind {
+            ValueKind::Struct { size } if self.signature.returns_via_pointer() => {
+                regs[Cpu::SP] -= size.next_multiple_of(4);
+                Some(Ptr::from_bits(regs[Cpu::SP]))
+            }
+            _ => None,
+        };
+
+        let word_count = stret_ptr.is_some() as usize + 2 + self.arg_words.len();
+        extend_stack_for_args(word_count, regs);
+        let mut reg_offset = 0;
+        if let Some(stret_ptr) = stret_ptr {
+            write_next_arg(&mut reg_offset, regs, &mut env.mem, stret_ptr);
+        }
+        write_next_arg(&mut reg_offset, regs, &mut env.mem, receiver);
+        write_next_arg(&mut reg_offset, regs, &mut env.mem, self.selector);
+        for &word in &self.arg_words {
+            write_next_arg(&mut reg_offset, regs, &mut env.mem, word);
+        }
+
+        // The arguments are already in place, so this behaves like a call from
+        // the guest.
+        if let Some(stret_ptr) = stret_ptr {
+            objc_msgSend_stret(env, stret_ptr, receiver, self.selector);
+        } else {
+            objc_msgSend(env, receiver, self.selector);
+        }
+
+        let regs = env.cpu.regs();
+        let return_value = match self.signature.return_kind {
+            ValueKind::Void => Vec::new(),
+            ValueKind::Object | ValueKind::Word => regs[0].to_le_bytes().to_vec(),
+            ValueKind::DoubleWord => [regs[0], regs[1]]
+                .iter()
+                .flat_map(|word| word.to_le_bytes())
+                .collect(),
+            ValueKind::Struct { size } => match stret_ptr {
+                Some(stret_ptr) => env.mem.bytes_at(stret_ptr.cast(), size).to_vec(),
+                None => regs[0].to_le_bytes()[..size as usize].to_vec(),
+            },
+        };
+
+        env.cpu.regs_mut()[Cpu::SP] = old_sp;
+
+        return_value
+    }
+}
+
+/// For use by `objc_msgSend` when the receiver has no method for the selector
+/// and no [ForwardingHandler]: forward the message like Apple's runtime does.
+/// Returns [false] if the receiver doesn't support forwarding at all, i.e. its
+/// root class isn't `NSObject`.
+///
+/// `stret` says whether this is `objc_msgSend_stret`, which is needed to find
+/// the receiver in the registers.
+pub(super) fn forward_message(
+    env: &mut Environment,
+    receiver: id,
+    selector: SEL,
+    stret: bool,
+) -> bool {
+    let class = ObjC::read_isa(receiver, &env.mem);
+    let has_method = |env: &Environment, name: &str| {
+        env.objc
+            .lookup_selector(name)
+            .is_some_and(|sel| env.objc.find_method_owner(class, sel).is_some())
+    };
+    if ![
+        "forwardingTargetForSelector:",
+        "methodSignatureForSelector:",
+        "forwardInvocation:",
+    ]
+    .into_iter()
+    .all(|name| has_method(env, name))
+    {
+        return false;
+    }
+
+    // Sending messages overwrites the argument registers, so they need to be
+    // restored before passing on the original message. Stack arguments are
+    // safe.
+    let saved_regs: [u32; 4] = env.cpu.regs()[0..4].try_into().unwrap();
+
+    let target: id = msg![env; receiver forwardingTargetForSelector:selector];
+    if target != nil && target != receiver {
+        log_dbg!(
+            "Forwarding [{:?} {}] to {:?}",
+            receiver,
+            selector.as_str(&env.mem),
+            target
+        );
+        let regs = env.cpu.regs_mut();
+        regs[0..4].copy_from_slice(&saved_regs);
+        regs[stret as usize] = target.to_bits();
+        if stret {
+            objc_msgSend_stret(env, Ptr::from_bits(saved_regs[0]), target, selector);
+        } else {
+            objc_msgSend(env, target, selector);
+        }
+        return true;
+    }
+
+    let signature: id = msg![env; receiver methodSignatureForSelector:selector];
+    if signature == nil {
+        () = msg![env; receiver doesNotRecognizeSelector:selector];
+        env.cpu.regs_mut()[0..2].fill(0);
+        return true;
+    }
+
+    log_dbg!(
+        "Forwarding [{:?} {}] as an invocation",
+        receiver,
+        selector.as_str(&env.mem)
+    );
+    env.cpu.regs_mut()[0..4].copy_from_slice(&saved_regs);
+    let method_signature = ns_invocation::method_signature_for(env, signature);
+    let stret_ptr: Option<MutVoidPtr> = method_signature
+        .returns_via_pointer()
+        .then(|| Ptr::from_bits(saved_regs[0]));
+    let message = ForwardedMessage::capture(env, selector, method_signature);
+    let invocation = ns_invocation::new_invocation(env, signature, receiver, &message);
+
+    () = msg![env; receiver forwardInvocation:invocation];
+
+    let return_value = ns_invocation::return_value(env, invocation);
+    release(env, invocation);
+    if let Some(stret_ptr) = stret_ptr {
+        env.mem
+            .bytes_at_mut(stret_ptr.cast(), return_value.len() as GuestUSize)
+            .copy_from_slice(&return_value);
+    } else {
+        let mut words = [0u8; 8];
+        let len = return_value.len().min(8);
+        words[..len].copy_from_slice(&return_value[..len]);
+        let regs = env.cpu.regs_mut();
+        regs[0] = u32::from_le_bytes(words[0..4].try_into().unwrap());
+        regs[1] = u32::from_le_bytes(words[4..8].try_into().unwrap());
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_type_encodings() {
+        let signature = MethodSignature::from_type_encoding("v12@0:4I8").unwrap();
+        assert_eq!(signature.return_kind, ValueKind::Void);
+        assert_eq!(signature.arg_kinds, [ValueKind::Word]);
+
+        let signature =
+            MethodSignature::from_type_encoding("@28@0:4{CGPoint=ff}8@\"NSString\"16d20").unwrap();
+        assert_eq!(signature.return_kind, ValueKind::Object);
+        assert_eq!(
+            signature.arg_kinds,
+            [
+                ValueKind::Struct { size: 8 },
+                ValueKind::Object,
+                ValueKind::DoubleWord
+            ]
+        );
+        assert_eq!(signature.arg_word_count(), 5);
+
+        let signature = MethodSignature::from_type_encoding(
+            "{CGRect={CGPoint=ff}{CGSize=ff}}12@0:4^{__CFString}8",
+        )
+        .unwrap();
+        assert_eq!(signature.return_kind, ValueKind::Struct { size: 16 });
+        assert!(signature.returns_via_pointer());
+        assert_eq!(signature.arg_kinds, [ValueKind::Word]);
+    }
+
+    #[test]
+    fn split_type_encodings() {
+        let types = split_type_encoding("v20@0:4r*8{CGPoint=ff}12").unwrap();
+        let encodings: Vec<&str> = types.iter().map(|ty| ty.encoding.as_str()).collect();
+        assert_eq!(encodings, ["v", "@", ":", "r*", "{CGPoint=ff}"]);
+        let sizes: Vec<GuestUSize> = types.iter().map(|ty| ty.size).collect();
+        assert_eq!(sizes, [0, 4, 4, 4, 8]);
+
+        // Offsets are optional.
+        let types = split_type_encoding("c@:d").unwrap();
+        assert_eq!(types[0].size, 1);
+        assert_eq!(types[3].kind, ValueKind::DoubleWord);
+
+        assert!(split_type_encoding("v@:b3").is_none());
+    }
+}
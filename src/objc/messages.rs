@@ -11,9 +11,10 @@
 //! - Mike Ash's [objc_msgSend's New Prototype](https://www.mikeash.com/pyblog/objc_msgsends-new-prototype.html)
 //! - Peter Steinberger's [Calling Super at Runtime in Swift](https://steipete.com/posts/calling-super-at-runtime/) explains `objc_msgSendSuper2`
 
-use super::{id, nil, Class, ObjC, IMP, SEL};
+use super::{forwarding, id, nil, Class, ObjC, IMP, SEL};
 use crate::abi::{CallFromHost, GuestRet};
 use crate::mem::{ConstPtr, MutVoidPtr, SafeRead};
+use crate::profiler;
 use crate::Environment;
 use std::any::TypeId;
 
@@ -29,8 +30,17 @@ use std::any::TypeId;
 /// Similarly, the return value of `objc_msgSend` is whatever value is returned
 /// by the method implementation. We are relying on CallFromGuest not
 /// overwriting it.
+///
+/// `stret` is only needed for message forwarding, which needs to know where
+/// the receiver is in the registers.
 #[allow(non_snake_case)]
-fn objc_msgSend_inner(env: &mut Environment, receiver: id, selector: SEL, super2: Option<Class>) {
+fn objc_msgSend_inner(
+    env: &mut Environment,
+    receiver: id,
+    selector: SEL,
+    super2: Option<Class>,
+    stret: bool,
+) {
     let message_type_info = env.objc.message_type_info.take();
 
     if receiver == nil {
@@ -50,6 +60,16 @@ fn objc_msgSend_inner(env: &mut Environment, receiver: id, selector: SEL, super2
         if class == nil {
             assert!(class != orig_class);
 
+            if let Some(handler) = env.objc.find_forwarding_handler(orig_class) {
+                log_dbg!("Forwarding [{:?} {}]", receiver, selector.as_str(&env.mem));
+                handler(env, receiver, selector);
+                return;
+            }
+
+            if forwarding::forward_message(env, receiver, selector, stret) {
+                return;
+            }
+
             let class_host_object = env.objc.get_host_object(orig_class).unwrap();
             let &super::ClassHostObject {
                 ref name,
@@ -89,7 +109,7 @@ fn objc_msgSend_inner(env: &mut Environment, receiver: id, selector: SEL, super2
             }
 
             if let Some(imp) = methods.get(&selector) {
-                match imp {
+                match *imp {
                     IMP::Host(host_imp) => {
                         // TODO: do type checks when calling GuestIMPs too.
                         // That requires using Objective-C type strings, rather
@@ -112,7 +132,12 @@ Type mismatch when sending message {} to {:?}!
                                 );
                             }
                         }
-                        host_imp.call_from_guest(env)
+                        let profiling = profiler::host_call_start(
+                            env,
+                            profiler::HostCall::Method(class, selector),
+                        );
+                        host_imp.call_from_guest(env);
+                        profiler::host_call_end(env, profiling);
                     }
                     // We can't create a new stack frame, because that would
                     // interfere with pass-through of stack arguments.
@@ -160,7 +185,9 @@ Type mismatch when sending message {} to {:?}!
 /// Standard variant of `objc_msgSend`. See [objc_msgSend_inner].
 #[allow(non_snake_case)]
 pub(super) fn objc_msgSend(env: &mut Environment, receiver: id, selector: SEL) {
-    objc_msgSend_inner(env, receiver, selector, /* super2: */ None)
+    objc_msgSend_inner(
+        env, receiver, selector, /* super2: */ None, /* stret: */ false,
+    )
 }
 
 /// Variant of `objc_msgSend` for methods that return a struct via a pointer.
@@ -178,7 +205,9 @@ pub(super) fn objc_msgSend_stret(
     receiver: id,
     selector: SEL,
 ) {
-    objc_msgSend_inner(env, receiver, selector, /* super2: */ None)
+    objc_msgSend_inner(
+        env, receiver, selector, /* super2: */ None, /* stret: */ true,
+    )
 }
 
 #[repr(C, packed)]
@@ -212,7 +241,13 @@ pub(super) fn objc_msgSendSuper2(
     // Rewrite first argument to match the normal ABI.
     crate::abi::write_next_arg(&mut 0, env.cpu.regs_mut(), &mut env.mem, receiver);
 
-    objc_msgSend_inner(env, receiver, selector, /* super2: */ Some(class))
+    objc_msgSend_inner(
+        env,
+        receiver,
+        selector,
+        /* super2: */ Some(class),
+        /* stret: */ false,
+    )
 }
 
 /// Trait that assists with type-checking of [msg_send]'s arguments.
@@ -7,12 +7,18 @@
 //!
 //! Resources:
 //! - [Apple's documentation of `class_addMethod`](https://developer.apple.com/documentation/objectivec/1418901-class_addmethod?language=objc)
+//! - [Apple's documentation of `method_exchangeImplementations`](https://developer.apple.com/documentation/objectivec/1418769-method_exchangeimplementations?language=objc)
+//!
+//! Methods added at runtime (`class_addMethod` etc) are stored in the same
+//! per-class table as other methods, so a class implemented on the host can
+//! have guest methods and vice versa. `objc_msgSend` handles either kind.
 
 use super::{
     id, nil, objc_super, Class, ClassHostObject, MsgSendSignature, MsgSendSuperSignature, ObjC, SEL,
 };
 use crate::abi::{CallFromGuest, DotDotDot, GuestArg, GuestFunction, GuestRet};
-use crate::mem::{guest_size_of, ConstPtr, GuestUSize, Mem, Ptr, SafeRead};
+use crate::dyld::HostFunction;
+use crate::mem::{guest_size_of, ConstPtr, GuestUSize, Mem, MutPtr, Ptr, SafeRead};
 use crate::Environment;
 use std::any::TypeId;
 
@@ -24,6 +30,7 @@ use std::any::TypeId;
 /// "guest methods" (functions in the guest app). Either way, the function needs
 /// to conform to the same ABI: [id] and [SEL] must be its first two parameters.
 #[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone)]
 pub enum IMP {
     Host(&'static dyn HostIMP),
     Guest(GuestIMP),
@@ -33,6 +40,9 @@ pub enum IMP {
 pub trait HostIMP: CallFromGuest {
     /// See [MsgSendSignature::type_info].
     fn type_info(&self) -> (TypeId, &'static str);
+    /// Get the same function as a [HostFunction], so that a guest function
+    /// pointer can be created for it.
+    fn to_host_function(&'static self) -> HostFunction;
 }
 
 macro_rules! impl_HostIMP {
@@ -45,6 +55,9 @@ macro_rules! impl_HostIMP {
             fn type_info(&self) -> (TypeId, &'static str) {
                 <(R, (id, SEL, $($P,)*)) as MsgSendSignature>::type_info()
             }
+            fn to_host_function(&'static self) -> HostFunction {
+                self
+            }
         }
         impl<R, $($P,)*> HostIMP for fn(&mut Environment, id, SEL, $($P,)* DotDotDot) -> R
         where
@@ -54,6 +67,9 @@ macro_rules! impl_HostIMP {
             fn type_info(&self) -> (TypeId, &'static str) {
                 todo!("host-to-host message calls with var-args"); // TODO
             }
+            fn to_host_function(&'static self) -> HostFunction {
+                self
+            }
         }
 
         // Currently there is a one-to-one mapping between valid host IMP
@@ -101,7 +117,7 @@ unsafe impl SafeRead for method_list_t {}
 ///
 /// The name, field names and field layout are based on what Ghidra outputs.
 #[repr(C, packed)]
-struct method_t {
+pub(super) struct method_t {
     name: ConstPtr<u8>,
     types: ConstPtr<u8>,
     imp: GuestIMP,
@@ -126,21 +142,42 @@ impl ClassHostObject {
             let method_ptr: ConstPtr<method_t> =
                 Ptr::from_bits(methods_base_ptr.to_bits() + i * entsize);
 
-            // TODO: support type strings
-            let method_t {
-                name,
-                types: _,
-                imp,
-            } = mem.read(method_ptr);
+            let method_t { name, types, imp } = mem.read(method_ptr);
 
             // There is no guarantee this string is unique or known.
             // We must deduplicate it like any other.
             let sel = objc.register_bin_selector(name, mem);
             self.methods.insert(sel, IMP::Guest(imp));
+            if !types.is_null() {
+                self.method_types.insert(sel, types);
+            } else {
+                self.method_types.remove(&sel);
+            }
         }
     }
 }
 
+/// Look up a method by name in a method list from the app binary, without
+/// adding it to a class. This is needed for `+load`, which isn't inherited and
+/// isn't affected by categories.
+pub(super) fn find_method_in_bin_list(
+    method_list_ptr: ConstPtr<method_list_t>,
+    sel_name: &str,
+    mem: &Mem,
+) -> Option<GuestIMP> {
+    if method_list_ptr.is_null() {
+        return None;
+    }
+    let method_list_t { entsize, count } = mem.read(method_list_ptr);
+    let methods_base_ptr: ConstPtr<method_t> = (method_list_ptr + 1).cast();
+    (0..count).find_map(|i| {
+        let method_ptr: ConstPtr<method_t> =
+            Ptr::from_bits(methods_base_ptr.to_bits() + i * entsize);
+        let method_t { name, imp, .. } = mem.read(method_ptr);
+        (mem.cstr_at(name) == sel_name.as_bytes()).then_some(imp)
+    })
+}
+
 impl ObjC {
     /// Checks if the provided class has a method in its class chain (that is
     /// to say, objects of the given class respond to a selector).
@@ -220,3 +257,311 @@ impl ObjC {
         }
     }
 }
+
+/// Type for the opaque pointers to methods returned by the runtime reflection
+/// functions, e.g. `class_getInstanceMethod`.
+///
+/// The name is standard Objective-C. These point to a [method_t] created by
+/// the runtime on demand (not the one in the app binary, if any), because
+/// host methods don't have one. The actual method implementation is always
+/// looked up in the class, so the `imp` field is only kept up to date for the
+/// guest's convenience.
+pub(super) type Method = MutPtr<method_t>;
+
+impl ObjC {
+    /// Find the class in the superclass chain of `class` (including `class`
+    /// itself) that has a method for a selector, if any.
+    pub(super) fn find_method_owner(&self, class: Class, sel: SEL) -> Option<Class> {
+        let mut class = class;
+        while class != nil {
+            // Placeholder classes have no methods.
+            let host_object: &ClassHostObject =
+                self.get_host_object(class)?.as_any().downcast_ref()?;
+            if host_object.methods.contains_key(&sel) {
+                return Some(class);
+            }
+            class = host_object.superclass;
+        }
+        None
+    }
+
+    /// Convert a guest function pointer to an [IMP]. If it's the guest function
+    /// pointer for a host method implementation (see [imp_to_guest_function]),
+    /// the host method implementation is used, so type checking still works.
+    fn guest_function_to_imp(&self, function: GuestFunction) -> IMP {
+        match self
+            .host_imp_function_owners
+            .get(&function.addr_with_thumb_bit())
+        {
+            Some(&host_imp) => IMP::Host(host_imp),
+            None => IMP::Guest(function),
+        }
+    }
+}
+
+/// Get a guest function pointer for an [IMP]. For host method
+/// implementations, one is created the first time this is needed.
+fn imp_to_guest_function(env: &mut Environment, imp: IMP) -> GuestFunction {
+    let host_imp = match imp {
+        IMP::Guest(guest_imp) => return guest_imp,
+        IMP::Host(host_imp) => host_imp,
+    };
+    let key = host_imp as *const dyn HostIMP as *const () as usize;
+    if let Some(&addr) = env.objc.host_imp_functions.get(&key) {
+        return GuestFunction::from_addr_with_thumb_bit(addr);
+    }
+    let function = env.dyld.create_guest_function(
+        &mut env.mem,
+        "(host method implementation)",
+        host_imp.to_host_function(),
+    );
+    env.cpu
+        .invalidate_cache_range(function.addr_without_thumb_bit(), 8);
+    let addr = function.addr_with_thumb_bit();
+    env.objc.host_imp_functions.insert(key, addr);
+    env.objc.host_imp_function_owners.insert(addr, host_imp);
+    function
+}
+
+/// Get the [Method] for a method of a class (not one of its superclasses),
+/// creating it if necessary.
+fn get_method(env: &mut Environment, class: Class, sel: SEL, types: ConstPtr<u8>) -> Method {
+    if let Some(&method) = env.objc.method_structs.get(&(class, sel)) {
+        return method;
+    }
+    let host_object = env.objc.borrow::<ClassHostObject>(class);
+    let imp = host_object.methods[&sel];
+    let types = if types.is_null() {
+        host_object
+            .method_types
+            .get(&sel)
+            .copied()
+            .unwrap_or(Ptr::null())
+    } else {
+        types
+    };
+    let imp = imp_to_guest_function(env, imp);
+    let method = env.mem.alloc_and_write(method_t {
+        name: sel.to_ptr(),
+        types,
+        imp,
+    });
+    env.objc.method_structs.insert((class, sel), method);
+    env.objc.method_struct_owners.insert(method, (class, sel));
+    method
+}
+
+/// Replace the implementation of a method of a class and return the old one,
+/// if any.
+fn set_method_imp(env: &mut Environment, class: Class, sel: SEL, imp: IMP) -> Option<IMP> {
+    let old = env
+        .objc
+        .borrow_mut::<ClassHostObject>(class)
+        .methods
+        .insert(sel, imp);
+    if let Some(&method) = env.objc.method_structs.get(&(class, sel)) {
+        let imp = imp_to_guest_function(env, imp);
+        let method_t { name, types, .. } = env.mem.read(method);
+        env.mem.write(method, method_t { name, types, imp });
+    }
+    old
+}
+
+/// Record the type encoding of a method added at runtime, if there is one.
+fn set_method_types(env: &mut Environment, class: Class, sel: SEL, types: ConstPtr<u8>) {
+    if !types.is_null() {
+        env.objc
+            .borrow_mut::<ClassHostObject>(class)
+            .method_types
+            .insert(sel, types);
+    }
+}
+
+pub(super) fn class_getInstanceMethod(env: &mut Environment, class: Class, sel: SEL) -> Method {
+    if class == nil || sel.is_null() {
+        return Ptr::null();
+    }
+    match env.objc.find_method_owner(class, sel) {
+        Some(owner) => get_method(env, owner, sel, Ptr::null()),
+        None => Ptr::null(),
+    }
+}
+
+pub(super) fn class_getClassMethod(env: &mut Environment, class: Class, sel: SEL) -> Method {
+    if class == nil {
+        return Ptr::null();
+    }
+    let metaclass = ObjC::read_isa(class, &env.mem);
+    class_getInstanceMethod(env, metaclass, sel)
+}
+
+pub(super) fn class_getMethodImplementation(
+    env: &mut Environment,
+    class: Class,
+    sel: SEL,
+) -> GuestFunction {
+    let method = class_getInstanceMethod(env, class, sel);
+    if method.is_null() {
+        // TODO: Apple's runtime returns a forwarding function here, but
+        // touchHLE doesn't support message forwarding yet.
+        log!(
+            "TODO: class_getMethodImplementation({:?}, {:?}) for missing method, returning NULL",
+            class,
+            sel
+        );
+        return GuestFunction::from_addr_with_thumb_bit(0);
+    }
+    method_getImplementation(env, method)
+}
+
+pub(super) fn class_respondsToSelector(env: &mut Environment, class: Class, sel: SEL) -> bool {
+    class != nil && !sel.is_null() && env.objc.find_method_owner(class, sel).is_some()
+}
+
+pub(super) fn class_addMethod(
+    env: &mut Environment,
+    class: Class,
+    sel: SEL,
+    imp: GuestFunction,
+    types: ConstPtr<u8>,
+) -> bool {
+    log_dbg!(
+        "class_addMethod({:?}, {:?} ({}), {:?}, {:?})",
+        class,
+        sel,
+        sel.as_str(&env.mem),
+        imp,
+        types
+    );
+    // Overriding a superclass's method is allowed, replacing one isn't.
+    if env
+        .objc
+        .borrow::<ClassHostObject>(class)
+        .methods
+        .contains_key(&sel)
+    {
+        return false;
+    }
+    let imp = env.objc.guest_function_to_imp(imp);
+    set_method_imp(env, class, sel, imp);
+    set_method_types(env, class, sel, types);
+    get_method(env, class, sel, types);
+    true
+}
+
+pub(super) fn class_replaceMethod(
+    env: &mut Environment,
+    class: Class,
+    sel: SEL,
+    imp: GuestFunction,
+    types: ConstPtr<u8>,
+) -> GuestFunction {
+    log_dbg!(
+        "class_replaceMethod({:?}, {:?} ({}), {:?}, {:?})",
+        class,
+        sel,
+        sel.as_str(&env.mem),
+        imp,
+        types
+    );
+    let imp = env.objc.guest_function_to_imp(imp);
+    match set_method_imp(env, class, sel, imp) {
+        Some(old_imp) => imp_to_guest_function(env, old_imp),
+        None => {
+            set_method_types(env, class, sel, types);
+            get_method(env, class, sel, types);
+            GuestFunction::from_addr_with_thumb_bit(0)
+        }
+    }
+}
+
+/// Returns a list of the methods of a class (not its superclasses) that the
+/// caller must `free`.
+pub(super) fn class_copyMethodList(
+    env: &mut Environment,
+    class: Class,
+    out_count: MutPtr<GuestUSize>,
+) -> MutPtr<Method> {
+    let mut sels: Vec<SEL> = if class == nil {
+        Vec::new()
+    } else {
+        let methods = &env.objc.borrow::<ClassHostObject>(class).methods;
+        methods.keys().copied().collect()
+    };
+    // The order is unspecified, but it should at least be deterministic.
+    sels.sort_by(|a, b| a.as_str(&env.mem).cmp(b.as_str(&env.mem)));
+
+    let count: GuestUSize = sels.len().try_into().unwrap();
+    if !out_count.is_null() {
+        env.mem.write(out_count, count);
+    }
+    if count == 0 {
+        return Ptr::null();
+    }
+
+    // The list is NULL-terminated.
+    let list: MutPtr<Method> = env
+        .mem
+        .alloc((count + 1) * guest_size_of::<Method>())
+        .cast();
+    for (i, sel) in sels.into_iter().enumerate() {
+        let method = get_method(env, class, sel, Ptr::null());
+        env.mem.write(list + i.try_into().unwrap(), method);
+    }
+    env.mem.write(list + count, Ptr::null());
+    list
+}
+
+pub(super) fn method_getName(env: &mut Environment, method: Method) -> SEL {
+    env.objc.method_struct_owners[&method].1
+}
+
+pub(super) fn method_getTypeEncoding(env: &mut Environment, method: Method) -> ConstPtr<u8> {
+    // TODO: type strings for host methods
+    env.mem.read(method).types
+}
+
+pub(super) fn method_getImplementation(env: &mut Environment, method: Method) -> GuestFunction {
+    if method.is_null() {
+        return GuestFunction::from_addr_with_thumb_bit(0);
+    }
+    let (class, sel) = env.objc.method_struct_owners[&method];
+    let imp = env.objc.borrow::<ClassHostObject>(class).methods[&sel];
+    imp_to_guest_function(env, imp)
+}
+
+pub(super) fn method_setImplementation(
+    env: &mut Environment,
+    method: Method,
+    imp: GuestFunction,
+) -> GuestFunction {
+    let (class, sel) = env.objc.method_struct_owners[&method];
+    log_dbg!(
+        "method_setImplementation({:?} ({:?} {}), {:?})",
+        method,
+        class,
+        sel.as_str(&env.mem),
+        imp
+    );
+    let imp = env.objc.guest_function_to_imp(imp);
+    let old_imp = set_method_imp(env, class, sel, imp).unwrap();
+    imp_to_guest_function(env, old_imp)
+}
+
+pub(super) fn method_exchangeImplementations(env: &mut Environment, a: Method, b: Method) {
+    let (class_a, sel_a) = env.objc.method_struct_owners[&a];
+    let (class_b, sel_b) = env.objc.method_struct_owners[&b];
+    log_dbg!(
+        "method_exchangeImplementations({:?} ({:?} {}), {:?} ({:?} {}))",
+        a,
+        class_a,
+        sel_a.as_str(&env.mem),
+        b,
+        class_b,
+        sel_b.as_str(&env.mem)
+    );
+    let imp_a = env.objc.borrow::<ClassHostObject>(class_a).methods[&sel_a];
+    let imp_b = env.objc.borrow::<ClassHostObject>(class_b).methods[&sel_b];
+    set_method_imp(env, class_a, sel_a, imp_b);
+    set_method_imp(env, class_b, sel_b, imp_a);
+}
@@ -22,6 +22,7 @@
 
 use super::{Class, ClassHostObject};
 use crate::mem::{guest_size_of, GuestUSize, Mem, MutPtr, Ptr, SafeRead};
+use crate::Environment;
 use std::any::Any;
 use std::num::NonZeroU32;
 
@@ -349,3 +350,11 @@ impl super::ObjC {
         mem.free(object.cast());
     }
 }
+
+pub(super) fn object_getClass(env: &mut Environment, object: id) -> Class {
+    if object == nil {
+        nil
+    } else {
+        super::ObjC::read_isa(object, &env.mem)
+    }
+}
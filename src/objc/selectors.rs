@@ -65,6 +65,10 @@ impl SEL {
     pub fn is_null(self) -> bool {
         self.0.is_null()
     }
+    /// Get the C string pointer for this selector, e.g. for `method_t`.
+    pub(super) fn to_ptr(self) -> ConstPtr<u8> {
+        self.0
+    }
 }
 
 impl ObjC {
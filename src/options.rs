@@ -5,12 +5,17 @@
  */
 //! Parsing and management of user-configurable options, e.g. for input methods.
 
+use crate::automation::parse_duration;
+use crate::frameworks::uikit::ui_device::{parse_os_version, DeviceModel};
 use crate::gles::GLESImplementation;
+use crate::paths;
 use crate::window::DeviceOrientation;
 use std::collections::HashMap;
 use std::io::{BufRead, BufReader, Read};
 use std::net::{SocketAddr, ToSocketAddrs};
 use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::time::Duration;
 
 pub const OPTIONS_HELP: &str =
     include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/OPTIONS_HELP.txt"));
@@ -30,25 +35,130 @@ pub enum Button {
     LeftShoulder,
 }
 
+impl Button {
+    fn from_name(name: &str) -> Result<Button, ()> {
+        match name {
+            "DPadLeft" => Ok(Button::DPadLeft),
+            "DPadUp" => Ok(Button::DPadUp),
+            "DPadRight" => Ok(Button::DPadRight),
+            "DPadDown" => Ok(Button::DPadDown),
+            "Start" => Ok(Button::Start),
+            "A" => Ok(Button::A),
+            "B" => Ok(Button::B),
+            "X" => Ok(Button::X),
+            "Y" => Ok(Button::Y),
+            "LeftShoulder" => Ok(Button::LeftShoulder),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Input that a region of the touch screen is bound to, for the
+/// `--touch-region=` option.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TouchRegionBinding {
+    /// Touch the center of the region while the button is held.
+    Button(Button),
+    /// Touch the center of the region while the key (SDL2 key name) is held.
+    Key(String),
+    /// Touch the region while the analog stick (left if [true]) is outside its
+    /// dead zone, with the touch position following the stick.
+    Stick(bool),
+}
+
+/// Region of the touch screen for the `--touch-region=` option.
+#[derive(Clone, Debug)]
+pub struct TouchRegion {
+    pub name: String,
+    pub binding: TouchRegionBinding,
+    pub center: (f32, f32),
+    pub radius: f32,
+}
+
+/// Game controller rumble strength for `--rumble=` option.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Rumble {
+    Off,
+    Low,
+    High,
+}
+
+/// What to do with audio while fast-forwarding, for `--ff-audio=` option.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FastForwardAudio {
+    SpeedUp,
+    Mute,
+}
+
+/// Outcome of in-app purchases, for `--in-app-purchases=` option.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum InAppPurchases {
+    Disabled,
+    Fail,
+    Succeed,
+}
+
+/// Product details for `--in-app-product=` option.
+#[derive(Clone, Debug)]
+pub struct InAppProduct {
+    pub identifier: String,
+    pub title: String,
+    pub price: f64,
+}
+
 /// Struct containing all user-configurable options.
 pub struct Options {
     pub fullscreen: bool,
     pub initial_orientation: DeviceOrientation,
     pub scale_hack: NonZeroU32,
+    pub status_bar_carrier: String,
     pub deadzone: f32,
+    pub rumble: Rumble,
     pub x_tilt_range: f32,
     pub y_tilt_range: f32,
     pub x_tilt_offset: f32,
     pub y_tilt_offset: f32,
     pub button_to_touch: HashMap<Button, (f32, f32)>,
+    pub touch_regions: Vec<TouchRegion>,
     pub stabilize_virtual_cursor: Option<(f32, f32)>,
+    pub pinch_scroll_step: f32,
+    pub pinch_marker: bool,
     pub gles1_implementation: Option<GLESImplementation>,
     pub direct_memory_access: bool,
     pub gdb_listen_addrs: Option<Vec<SocketAddr>>,
+    pub profile: Option<PathBuf>,
+    pub profile_stacks: Option<PathBuf>,
     pub preferred_languages: Option<Vec<String>>,
     pub headless: bool,
+    pub input_script: Option<PathBuf>,
+    pub exit_after: Option<Duration>,
+    pub screenshot_at: Vec<(Duration, PathBuf)>,
     pub print_fps: bool,
     pub fps_limit: Option<f64>,
+    pub fast_forward_speed: f64,
+    pub fast_forward_audio: FastForwardAudio,
+    pub other_audio_is_playing: bool,
+    pub interrupt_audio_on_focus_loss: bool,
+    /// Simulated hardware output volume, from 0 to 1.
+    pub volume: f32,
+    /// SDL2 key names.
+    pub volume_up_key: String,
+    pub volume_down_key: String,
+    pub record_video: Option<PathBuf>,
+    pub network_access: bool,
+    pub can_send_messages: bool,
+    pub location: Option<(f64, f64)>,
+    pub game_center_alias: String,
+    pub in_app_purchases: InAppPurchases,
+    pub in_app_products: Vec<InAppProduct>,
+    pub strict_binding: bool,
+    /// In bytes.
+    pub memory_warning_threshold: Option<u32>,
+    /// In bytes.
+    pub guest_memory: u32,
+    pub device: DeviceModel,
+    /// Validated with [parse_os_version].
+    pub os_version: String,
 }
 
 impl Default for Options {
@@ -57,20 +167,49 @@ impl Default for Options {
             fullscreen: false,
             initial_orientation: DeviceOrientation::Portrait,
             scale_hack: NonZeroU32::new(1).unwrap(),
+            status_bar_carrier: "Carrier".to_string(),
             deadzone: 0.1,
+            rumble: Rumble::High,
             x_tilt_range: 60.0,
             y_tilt_range: 60.0,
             x_tilt_offset: 0.0,
             y_tilt_offset: 0.0,
             button_to_touch: HashMap::new(),
+            touch_regions: Vec::new(),
             stabilize_virtual_cursor: None,
+            pinch_scroll_step: 10.0,
+            pinch_marker: true,
             gles1_implementation: None,
             direct_memory_access: true,
             gdb_listen_addrs: None,
+            profile: None,
+            profile_stacks: None,
             preferred_languages: None,
             headless: false,
+            input_script: None,
+            exit_after: None,
+            screenshot_at: Vec::new(),
             print_fps: false,
             fps_limit: Some(60.0), // Original iPhone is 60Hz and uses v-sync
+            fast_forward_speed: 3.0,
+            fast_forward_audio: FastForwardAudio::SpeedUp,
+            other_audio_is_playing: false,
+            interrupt_audio_on_focus_loss: false,
+            volume: 1.0,
+            volume_up_key: "PageUp".to_string(),
+            volume_down_key: "PageDown".to_string(),
+            record_video: None,
+            network_access: false,
+            can_send_messages: false,
+            location: None,
+            game_center_alias: "Player".to_string(),
+            in_app_purchases: InAppPurchases::Disabled,
+            in_app_products: Vec::new(),
+            strict_binding: false,
+            memory_warning_threshold: Some(128 * 1024 * 1024),
+            guest_memory: 256 * 1024 * 1024, // iPhone 3GS
+            device: DeviceModel::IPhone2G,
+            os_version: "2.0".to_string(),
         }
     }
 }
@@ -100,8 +239,17 @@ impl Options {
             self.scale_hack = value
                 .parse()
                 .map_err(|_| "Invalid scale hack factor".to_string())?;
+        } else if let Some(value) = arg.strip_prefix("--status-bar-carrier=") {
+            self.status_bar_carrier = value.to_string();
         } else if let Some(value) = arg.strip_prefix("--deadzone=") {
             self.deadzone = parse_degrees(value, "deadzone")?;
+        } else if let Some(value) = arg.strip_prefix("--rumble=") {
+            self.rumble = match value {
+                "off" => Rumble::Off,
+                "low" => Rumble::Low,
+                "high" => Rumble::High,
+                _ => return Err("Unrecognized --rumble= value".to_string()),
+            };
         } else if let Some(value) = arg.strip_prefix("--x-tilt-range=") {
             self.x_tilt_range = parse_degrees(value, "X tilt range")?;
         } else if let Some(value) = arg.strip_prefix("--y-tilt-range=") {
@@ -117,19 +265,8 @@ impl Options {
             let (x, y) = coords
                 .split_once(',')
                 .ok_or_else(|| "--button-to-touch= requires three values".to_string())?;
-            let button = match button {
-                "DPadLeft" => Ok(Button::DPadLeft),
-                "DPadUp" => Ok(Button::DPadUp),
-                "DPadRight" => Ok(Button::DPadRight),
-                "DPadDown" => Ok(Button::DPadDown),
-                "Start" => Ok(Button::Start),
-                "A" => Ok(Button::A),
-                "B" => Ok(Button::B),
-                "X" => Ok(Button::X),
-                "Y" => Ok(Button::Y),
-                "LeftShoulder" => Ok(Button::LeftShoulder),
-                _ => Err("Invalid button for --button-to-touch=".to_string()),
-            }?;
+            let button = Button::from_name(button)
+                .map_err(|_| "Invalid button for --button-to-touch=".to_string())?;
             let x: f32 = x
                 .parse()
                 .map_err(|_| "Invalid X co-ordinate for --button-to-touch=".to_string())?;
@@ -137,6 +274,43 @@ impl Options {
                 .parse()
                 .map_err(|_| "Invalid Y co-ordinate for --button-to-touch=".to_string())?;
             self.button_to_touch.insert(button, (x, y));
+        } else if let Some(values) = arg.strip_prefix("--touch-region=") {
+            let values: Vec<&str> = values.split(',').collect();
+            let &[name, binding, x, y, radius] = &values[..] else {
+                return Err("--touch-region= requires five values".to_string());
+            };
+            let binding = if let Some(key) = binding.strip_prefix("Key:") {
+                TouchRegionBinding::Key(key.to_string())
+            } else if binding == "LeftStick" {
+                TouchRegionBinding::Stick(true)
+            } else if binding == "RightStick" {
+                TouchRegionBinding::Stick(false)
+            } else {
+                TouchRegionBinding::Button(
+                    Button::from_name(binding)
+                        .map_err(|_| "Invalid binding for --touch-region=".to_string())?,
+                )
+            };
+            let parse = |value: &str, what: &str| {
+                value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|value| value.is_finite())
+                    .ok_or_else(|| format!("Invalid {} for --touch-region=", what))
+            };
+            let region = TouchRegion {
+                name: name.to_string(),
+                binding,
+                center: (parse(x, "X co-ordinate")?, parse(y, "Y co-ordinate")?),
+                radius: parse(radius, "radius")?,
+            };
+            // A region with the same name as an existing one replaces it, so
+            // that regions from touchHLE_default_options.txt can be adjusted.
+            if let Some(existing) = self.touch_regions.iter_mut().find(|r| r.name == name) {
+                *existing = region;
+            } else {
+                self.touch_regions.push(region);
+            }
         } else if let Some(value) = arg.strip_prefix("--stabilize-virtual-cursor=") {
             let (smoothing_strength, sticky_radius) = value
                 .split_once(',')
@@ -156,6 +330,14 @@ impl Options {
                     "Invalid sticky radius for --stabilize-virtual-cursor=".to_string()
                 })?;
             self.stabilize_virtual_cursor = Some((smoothing_strength, sticky_radius));
+        } else if let Some(value) = arg.strip_prefix("--pinch-scroll-step=") {
+            self.pinch_scroll_step = value
+                .parse()
+                .ok()
+                .filter(|step: &f32| step.is_finite())
+                .ok_or_else(|| "Invalid value for --pinch-scroll-step=".to_string())?;
+        } else if arg == "--disable-pinch-marker" {
+            self.pinch_marker = false;
         } else if let Some(value) = arg.strip_prefix("--gles1=") {
             self.gles1_implementation = Some(
                 GLESImplementation::from_short_name(value)
@@ -169,10 +351,39 @@ impl Options {
                 .map_err(|e| format!("Could not resolve GDB server listen address: {}", e))?
                 .collect();
             self.gdb_listen_addrs = Some(addrs);
+        } else if let Some(value) = arg.strip_prefix("--profile=") {
+            if value.is_empty() {
+                return Err("--profile= requires a file path".to_string());
+            }
+            self.profile = Some(PathBuf::from(value));
+        } else if let Some(value) = arg.strip_prefix("--profile-stacks=") {
+            if value.is_empty() {
+                return Err("--profile-stacks= requires a file path".to_string());
+            }
+            self.profile_stacks = Some(PathBuf::from(value));
         } else if let Some(value) = arg.strip_prefix("--preferred-languages=") {
             self.preferred_languages = Some(value.split(',').map(ToOwned::to_owned).collect());
         } else if arg == "--headless" {
             self.headless = true;
+        } else if let Some(value) = arg.strip_prefix("--input-script=") {
+            if value.is_empty() {
+                return Err("--input-script= requires a file path".to_string());
+            }
+            self.input_script = Some(PathBuf::from(value));
+        } else if let Some(value) = arg.strip_prefix("--exit-after=") {
+            let duration = parse_duration(value)
+                .ok_or_else(|| "Invalid value for --exit-after=".to_string())?;
+            self.exit_after = Some(duration);
+        } else if let Some(value) = arg.strip_prefix("--screenshot-at=") {
+            let (time, path) = value
+                .split_once(',')
+                .ok_or_else(|| "--screenshot-at= requires a time and a file path".to_string())?;
+            let time = parse_duration(time)
+                .ok_or_else(|| "Invalid time for --screenshot-at=".to_string())?;
+            if path.is_empty() {
+                return Err("--screenshot-at= requires a file path".to_string());
+            }
+            self.screenshot_at.push((time, PathBuf::from(path)));
         } else if arg == "--print-fps" {
             self.print_fps = true;
         } else if let Some(value) = arg.strip_prefix("--fps-limit=") {
@@ -186,6 +397,125 @@ impl Options {
                     .ok_or_else(|| "Invalid value for --fps-limit=".to_string())?;
                 self.fps_limit = Some(limit);
             }
+        } else if let Some(value) = arg.strip_prefix("--ff-speed=") {
+            self.fast_forward_speed = value
+                .parse()
+                .ok()
+                .filter(|&v: &f64| v > 0.0 && v.is_finite())
+                .ok_or_else(|| "Invalid value for --ff-speed=".to_string())?;
+        } else if let Some(value) = arg.strip_prefix("--ff-audio=") {
+            self.fast_forward_audio = match value {
+                "speed-up" => FastForwardAudio::SpeedUp,
+                "mute" => FastForwardAudio::Mute,
+                _ => return Err("Unrecognized --ff-audio= value".to_string()),
+            };
+        } else if arg == "--other-audio-is-playing" {
+            self.other_audio_is_playing = true;
+        } else if arg == "--interrupt-audio-on-focus-loss" {
+            self.interrupt_audio_on_focus_loss = true;
+        } else if let Some(value) = arg.strip_prefix("--volume=") {
+            self.volume = value
+                .parse::<f32>()
+                .ok()
+                .filter(|volume| (0.0..=1.0).contains(volume))
+                .ok_or_else(|| "Volume must be between 0 and 1".to_string())?;
+        } else if let Some(value) = arg.strip_prefix("--volume-up-key=") {
+            self.volume_up_key = value.to_string();
+        } else if let Some(value) = arg.strip_prefix("--volume-down-key=") {
+            self.volume_down_key = value.to_string();
+        } else if let Some(value) = arg.strip_prefix("--record-video=") {
+            if value.is_empty() {
+                return Err("--record-video= requires a file path".to_string());
+            }
+            self.record_video = Some(PathBuf::from(value));
+        } else if arg == "--allow-network-access" {
+            self.network_access = true;
+        } else if arg == "--can-send-messages" {
+            self.can_send_messages = true;
+        } else if let Some(value) = arg.strip_prefix("--location=") {
+            let (latitude, longitude) = value
+                .split_once(',')
+                .ok_or_else(|| "--location= requires a latitude and longitude".to_string())?;
+            let latitude: f64 = latitude
+                .trim()
+                .parse()
+                .ok()
+                .filter(|v: &f64| (-90.0..=90.0).contains(v))
+                .ok_or_else(|| "Invalid latitude for --location=".to_string())?;
+            let longitude: f64 = longitude
+                .trim()
+                .parse()
+                .ok()
+                .filter(|v: &f64| (-180.0..=180.0).contains(v))
+                .ok_or_else(|| "Invalid longitude for --location=".to_string())?;
+            self.location = Some((latitude, longitude));
+        } else if let Some(value) = arg.strip_prefix("--game-center-alias=") {
+            if value.is_empty() {
+                return Err("--game-center-alias= requires a name".to_string());
+            }
+            self.game_center_alias = value.to_string();
+        } else if let Some(value) = arg.strip_prefix("--in-app-purchases=") {
+            self.in_app_purchases = match value {
+                "disabled" => InAppPurchases::Disabled,
+                "fail" => InAppPurchases::Fail,
+                "succeed" => InAppPurchases::Succeed,
+                _ => return Err("Unrecognized --in-app-purchases= value".to_string()),
+            };
+        } else if let Some(value) = arg.strip_prefix("--in-app-product=") {
+            let mut parts = value.splitn(3, ',');
+            let identifier = parts.next().unwrap();
+            if identifier.is_empty() {
+                return Err("--in-app-product= requires a product identifier".to_string());
+            }
+            let title = parts.next().unwrap_or("");
+            let price = match parts.next() {
+                Some(price) => price
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|&v: &f64| v >= 0.0 && v.is_finite())
+                    .ok_or_else(|| "Invalid price for --in-app-product=".to_string())?,
+                None => 0.99,
+            };
+            let product = InAppProduct {
+                identifier: identifier.to_string(),
+                title: title.to_string(),
+                price,
+            };
+            // A later option for the same product replaces an earlier one, so
+            // that app-specific options can override the defaults.
+            self.in_app_products
+                .retain(|other| other.identifier != product.identifier);
+            self.in_app_products.push(product);
+        } else if let Some(value) = arg.strip_prefix("--memory-warning-threshold=") {
+            let mib: u32 = value
+                .parse()
+                .ok()
+                .filter(|&v| v < 4096)
+                .ok_or_else(|| "Invalid value for --memory-warning-threshold=".to_string())?;
+            self.memory_warning_threshold = (mib != 0).then_some(mib * 1024 * 1024);
+        } else if let Some(value) = arg.strip_prefix("--guest-memory=") {
+            let (number, unit) = if let Some(number) = value.strip_suffix(['G', 'g']) {
+                (number, 1024 * 1024 * 1024)
+            } else {
+                (value.strip_suffix(['M', 'm']).unwrap_or(value), 1024 * 1024)
+            };
+            self.guest_memory = number
+                .parse()
+                .ok()
+                .and_then(|v: u32| v.checked_mul(unit))
+                .filter(|&v| (16 * 1024 * 1024..=3 * 1024 * 1024 * 1024).contains(&v))
+                .ok_or_else(|| "Invalid value for --guest-memory=".to_string())?;
+        } else if let Some(value) = arg.strip_prefix("--device=") {
+            self.device = DeviceModel::from_short_name(value)
+                .map_err(|_| "Unrecognized --device= value".to_string())?;
+        } else if let Some(value) = arg.strip_prefix("--os-version=") {
+            if parse_os_version(value).is_none() {
+                return Err("Invalid value for --os-version=".to_string());
+            }
+            self.os_version = value.to_string();
+        } else if arg == "--strict-binding" {
+            self.strict_binding = true;
         } else {
             return Ok(false);
         };
@@ -235,3 +565,82 @@ pub fn get_options_from_file<F: Read>(file: F, app_id: &str) -> Result<Option<St
     }
     Ok(None)
 }
+
+/// Replace the app-specific options for `app_id` in the contents of an options
+/// file, returning the new contents. The app's line is removed if `options` is
+/// empty, and appended if there wasn't one already. Other lines, including
+/// comments, are kept as-is.
+pub fn set_options_in_file_contents(contents: &str, app_id: &str, options: &str) -> String {
+    let new_line = if options.is_empty() {
+        None
+    } else {
+        Some(format!("{}: {}", app_id, options))
+    };
+
+    let mut new_contents = String::with_capacity(contents.len());
+    let mut replaced = false;
+    for line in contents.lines() {
+        let line_app_id = line
+            .split_once('#')
+            .map_or(line, |(rest, _)| rest)
+            .split_once(':')
+            .map(|(line_app_id, _)| line_app_id.trim());
+        // Only the first matching line is used by get_options_from_file(), so
+        // it's the only one replaced.
+        if !replaced && line_app_id == Some(app_id) {
+            replaced = true;
+            if let Some(ref new_line) = new_line {
+                new_contents.push_str(new_line);
+                new_contents.push('\n');
+            }
+            continue;
+        }
+        new_contents.push_str(line);
+        new_contents.push('\n');
+    }
+    if !replaced {
+        if let Some(new_line) = new_line {
+            new_contents.push_str(&new_line);
+            new_contents.push('\n');
+        }
+    }
+    new_contents
+}
+
+fn user_options_file_path() -> PathBuf {
+    paths::user_data_base_path().join(paths::USER_OPTIONS_FILE)
+}
+
+/// Read [paths::USER_OPTIONS_FILE]. It not existing yet is not an error.
+pub fn read_user_options_file() -> Result<String, String> {
+    let path = user_options_file_path();
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => Ok(contents),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(format!("Couldn't read {}: {}", path.display(), e)),
+    }
+}
+
+/// Replace the app's line in [paths::USER_OPTIONS_FILE], keeping the rest of
+/// the file intact.
+pub fn save_user_options(app_id: &str, app_options: &str) -> Result<(), String> {
+    let path = user_options_file_path();
+    let contents = read_user_options_file()?;
+    let contents = set_options_in_file_contents(&contents, app_id, app_options);
+    std::fs::write(&path, contents).map_err(|e| format!("Couldn't write {}: {}", path.display(), e))
+}
+
+/// Save a single option (e.g. `--volume=0.5`) for an app in
+/// [paths::USER_OPTIONS_FILE], replacing any earlier value of it. `prefix` is
+/// the part that doesn't change (e.g. `--volume=`). The app's other options
+/// are kept.
+pub fn save_user_option(app_id: &str, prefix: &str, arg: &str) -> Result<(), String> {
+    let contents = read_user_options_file()?;
+    let app_options = get_options_from_file(contents.as_bytes(), app_id)?.unwrap_or_default();
+    let mut args: Vec<&str> = app_options
+        .split_ascii_whitespace()
+        .filter(|other_arg| !other_arg.starts_with(prefix))
+        .collect();
+    args.push(arg);
+    save_user_options(app_id, &args.join(" "))
+}
@@ -12,10 +12,12 @@
 //!   the platform these may or may not be ordinary files, and must be accessed
 //!   through [ResourceFile].
 //! * Files the user is expected to modify, but not touchHLE: [APPS_DIR],
-//!   [USER_OPTIONS_FILE]. These are ordinary files and are found in
+//!   [USER_OPTIONS_FILE] (except that the app picker can save per-app options
+//!   to the latter). These are ordinary files and are found in
 //!   [user_data_base_path].
 //! * Files that touchHLE will create and modify, and the user may modify if
-//!   they want to: [SANDBOX_DIR]. These are ordinary files and are found in
+//!   they want to: [SANDBOX_DIR], [SCREENSHOTS_DIR], [CRASH_REPORTS_DIR],
+//!   [LAST_PLAYED_FILE]. These are ordinary files and are found in
 //!   [user_data_base_path].
 //!
 //! See also [crate::fs], which provides a virtual filesystem for the guest app
@@ -80,6 +82,17 @@ pub const USER_OPTIONS_FILE: &str = "touchHLE_options.txt";
 /// the `Documents` directory.
 pub const SANDBOX_DIR: &str = "touchHLE_sandbox";
 
+/// Name of the directory where touchHLE will save screenshots.
+pub const SCREENSHOTS_DIR: &str = "touchHLE_screenshots";
+
+/// Name of the directory where touchHLE will save crash reports (see
+/// [crate::crash_report]).
+pub const CRASH_REPORTS_DIR: &str = "touchHLE_crash_reports";
+
+/// Name of the file where touchHLE records when each app was last launched,
+/// so the app picker can sort by it.
+pub const LAST_PLAYED_FILE: &str = "touchHLE_last_played.txt";
+
 /// Get a platform-specific base path needed for accessing touchHLE's
 /// user-modifiable files. This is empty on platforms other than Android.
 pub fn user_data_base_path() -> &'static Path {
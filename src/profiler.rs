@@ -0,0 +1,442 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Sampling profiler for guest code (`--profile=` and `--profile-stacks=`).
+//!
+//! While guest code is running, the CPU is stopped every
+//! [SAMPLE_INTERVAL_TICKS] ticks so the PC, the current thread and the chain
+//! of return addresses on its stack can be recorded. No guest code runs while
+//! a host function is running, so calls from guest code to host functions
+//! (both functions linked by [crate::dyld] and methods called via
+//! `objc_msgSend`) are instead counted and timed individually. The times are
+//! wall-clock times and include any guest code called by the host function,
+//! and time spent blocked (e.g. in `usleep()`).
+//!
+//! Addresses are symbolized when the report is written, using the symbols of
+//! the loaded binaries and the methods of Objective-C classes. An address is
+//! attributed to the nearest preceding symbol in the same section, which
+//! means static functions in a stripped binary are attributed to whichever
+//! function precedes them. Addresses with no symbol are shown as
+//! `sub_XXXXXXXX`.
+//!
+//! The report is written when touchHLE exits, and when F4 is pressed. The
+//! optional stack file uses the "collapsed stacks" format that tools like
+//! [FlameGraph](https://github.com/brendangregg/FlameGraph) and
+//! [speedscope](https://www.speedscope.app/) accept.
+//!
+//! When profiling isn't enabled, the only cost is checking whether
+//! [Environment::profiler] is [None] at each host function call.
+
+use crate::objc::{Class, SEL};
+use crate::{Environment, ThreadId};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How many CPU ticks of guest code execution there are between each sample.
+pub const SAMPLE_INTERVAL_TICKS: u64 = 10_000;
+
+/// Frames further up the stack than this are ignored, to bound the cost of
+/// taking a sample (and of deep recursion).
+const MAX_STACK_DEPTH: usize = 128;
+
+/// A call from guest code to the host.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum HostCall {
+    /// A host function called via a stub created by [crate::dyld].
+    Function(&'static str),
+    /// A host method called by `objc_msgSend`. The class is the one the method
+    /// was found in.
+    Method(Class, SEL),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum Frame {
+    Guest(u32),
+    Host(HostCall),
+    /// A return to host code that isn't from a [HostCall] (e.g. a thread's
+    /// start routine), or which has already been unwound.
+    UnknownHost,
+}
+
+#[derive(Default)]
+struct HostCallStats {
+    calls: u64,
+    time: Duration,
+}
+
+pub struct Profiler {
+    report_path: Option<PathBuf>,
+    stacks_path: Option<PathBuf>,
+    started: Instant,
+    ticks_until_sample: u64,
+    /// Number of samples taken for each distinct combination of thread and
+    /// stack. Stacks are innermost frame first.
+    stacks: HashMap<(ThreadId, Vec<Frame>), u64>,
+    host_calls: HashMap<HostCall, HostCallStats>,
+    /// Host calls in progress on each thread, innermost last.
+    active_host_calls: HashMap<ThreadId, Vec<HostCall>>,
+}
+
+impl Profiler {
+    pub fn new(report_path: Option<PathBuf>, stacks_path: Option<PathBuf>) -> Profiler {
+        Profiler {
+            report_path,
+            stacks_path,
+            started: Instant::now(),
+            ticks_until_sample: SAMPLE_INTERVAL_TICKS,
+            stacks: HashMap::new(),
+            host_calls: HashMap::new(),
+            active_host_calls: HashMap::new(),
+        }
+    }
+
+    /// How many more ticks of guest code can run before the next sample is
+    /// due. This is never zero.
+    pub fn ticks_until_sample(&self) -> u64 {
+        self.ticks_until_sample
+    }
+}
+
+/// For use by [Environment]: account for `ticks` CPU ticks of guest code that
+/// just ran on the current thread, and take a sample if one is due.
+pub fn guest_ticks_elapsed(env: &mut Environment, ticks: u64) {
+    let profiler = env.profiler.as_mut().unwrap();
+    profiler.ticks_until_sample = profiler.ticks_until_sample.saturating_sub(ticks);
+    if profiler.ticks_until_sample != 0 {
+        return;
+    }
+    profiler.ticks_until_sample = SAMPLE_INTERVAL_TICKS;
+
+    let pc = env.cpu.pc_with_thumb_bit().addr_without_thumb_bit();
+    let return_to_host_routine_addr = env.dyld.return_to_host_routine().addr_with_thumb_bit();
+    let return_addresses = env.stack_return_addresses(MAX_STACK_DEPTH);
+
+    let profiler = env.profiler.as_mut().unwrap();
+    let mut host_calls = profiler
+        .active_host_calls
+        .get(&env.current_thread)
+        .map_or(&[][..], |calls| &calls[..])
+        .iter()
+        .rev();
+    let mut stack = Vec::with_capacity(return_addresses.len() + 1);
+    stack.push(Frame::Guest(pc));
+    for addr in return_addresses {
+        stack.push(if addr == return_to_host_routine_addr {
+            host_calls
+                .next()
+                .map_or(Frame::UnknownHost, |&call| Frame::Host(call))
+        } else {
+            // The return address is after the call instruction, which could
+            // be the last instruction of the function.
+            Frame::Guest((addr & !1).wrapping_sub(2))
+        });
+    }
+    *profiler
+        .stacks
+        .entry((env.current_thread, stack))
+        .or_default() += 1;
+}
+
+/// Information about a host call in progress, see [host_call_start].
+pub struct HostCallInProgress {
+    thread: ThreadId,
+    depth: usize,
+    call: HostCall,
+    started: Instant,
+}
+
+/// Call this before guest code calls into the host, and pass the result to
+/// [host_call_end] once it returns.
+#[inline]
+pub fn host_call_start(env: &mut Environment, call: HostCall) -> Option<HostCallInProgress> {
+    let profiler = env.profiler.as_mut()?;
+    let calls = profiler
+        .active_host_calls
+        .entry(env.current_thread)
+        .or_default();
+    let depth = calls.len();
+    calls.push(call);
+    Some(HostCallInProgress {
+        thread: env.current_thread,
+        depth,
+        call,
+        started: Instant::now(),
+    })
+}
+
+/// See [host_call_start].
+#[inline]
+pub fn host_call_end(env: &mut Environment, in_progress: Option<HostCallInProgress>) {
+    let Some(HostCallInProgress {
+        thread,
+        depth,
+        call,
+        started,
+    }) = in_progress
+    else {
+        return;
+    };
+    let elapsed = started.elapsed();
+    let profiler = env.profiler.as_mut().unwrap();
+    // Calls made within this one could have been unwound by an Objective-C
+    // exception without ending, so this doesn't just pop the last call.
+    profiler
+        .active_host_calls
+        .get_mut(&thread)
+        .unwrap()
+        .truncate(depth);
+    let stats = profiler.host_calls.entry(call).or_default();
+    stats.calls += 1;
+    stats.time += elapsed;
+}
+
+/// Maps guest addresses to symbol names. This is also used for crash reports
+/// (see [crate::crash_report]).
+pub struct Symbolizer {
+    /// Symbol addresses (without the Thumb bit) and names, sorted by address.
+    symbols: Vec<(u32, String)>,
+    /// Address ranges of sections in the loaded binaries.
+    sections: Vec<std::ops::Range<u32>>,
+}
+
+impl Symbolizer {
+    pub fn new(env: &Environment) -> Symbolizer {
+        let mut symbols = Vec::new();
+        let mut sections = Vec::new();
+        for bin in &env.bins {
+            let names = bin
+                .exported_symbols
+                .iter()
+                .map(|(name, &addr)| (addr, name))
+                .chain(bin.local_symbols.iter().map(|(addr, name)| (*addr, name)));
+            for (addr, name) in names {
+                // C symbols have a leading underscore added by the compiler.
+                let name = name.strip_prefix('_').unwrap_or(name);
+                symbols.push((addr & !1, name.to_string()));
+            }
+            sections.extend(
+                bin.sections
+                    .iter()
+                    .map(|section| section.addr..(section.addr + section.size)),
+            );
+        }
+        symbols.extend(
+            env.objc
+                .get_guest_method_symbols(&env.mem)
+                .into_iter()
+                .map(|(addr, name)| (addr & !1, name)),
+        );
+        // Prefer Objective-C method names over symbols for the same address,
+        // since the latter are often less readable.
+        symbols.reverse();
+        symbols.sort_by_key(|&(addr, _)| addr);
+        symbols.dedup_by_key(|&mut (addr, _)| addr);
+        Symbolizer { symbols, sections }
+    }
+
+    /// Find the nearest symbol preceding an address in the same section, and
+    /// return its address and name.
+    pub fn lookup(&self, addr: u32) -> Option<(u32, &str)> {
+        let section = self.sections.iter().find(|range| range.contains(&addr))?;
+        let idx = self
+            .symbols
+            .partition_point(|&(sym_addr, _)| sym_addr <= addr)
+            .checked_sub(1)?;
+        let (sym_addr, ref name) = self.symbols[idx];
+        section
+            .contains(&sym_addr)
+            .then_some((sym_addr, name.as_str()))
+    }
+
+    fn symbolize(&self, addr: u32) -> String {
+        match self.lookup(addr) {
+            Some((_, name)) => name.to_string(),
+            None => format!("sub_{:08x}", addr),
+        }
+    }
+
+    fn frame_name(&self, env: &Environment, frame: Frame) -> String {
+        match frame {
+            Frame::Guest(addr) => self.symbolize(addr),
+            Frame::Host(call) => host_call_name(env, call),
+            Frame::UnknownHost => "[host]".to_string(),
+        }
+    }
+}
+
+fn host_call_name(env: &Environment, call: HostCall) -> String {
+    match call {
+        HostCall::Function(symbol) => symbol.strip_prefix('_').unwrap_or(symbol).to_string(),
+        HostCall::Method(class, selector) => env.objc.get_method_name(class, selector, &env.mem),
+    }
+}
+
+/// Write the profiling report (and stack file, if requested), covering
+/// everything since profiling started. Does nothing if profiling isn't
+/// enabled.
+pub fn write_report(env: &Environment) {
+    let Some(profiler) = env.profiler.as_ref() else {
+        return;
+    };
+    let symbolizer = Symbolizer::new(env);
+    let elapsed = profiler.started.elapsed();
+
+    // Symbolize each distinct stack only once.
+    let stacks: Vec<(ThreadId, Vec<String>, u64)> = profiler
+        .stacks
+        .iter()
+        .map(|((thread, frames), &count)| {
+            let names = frames
+                .iter()
+                .map(|&frame| symbolizer.frame_name(env, frame))
+                .collect();
+            (*thread, names, count)
+        })
+        .collect();
+    let total_samples: u64 = stacks.iter().map(|&(_, _, count)| count).sum();
+
+    if let Some(ref path) = profiler.report_path {
+        let report = format_report(env, profiler, &stacks, total_samples, elapsed);
+        write_file(path, &report);
+    }
+    if let Some(ref path) = profiler.stacks_path {
+        let mut collapsed = String::new();
+        for (thread, names, count) in &stacks {
+            write!(collapsed, "thread {}", thread).unwrap();
+            for name in names.iter().rev() {
+                write!(collapsed, ";{}", name.replace(';', ":")).unwrap();
+            }
+            writeln!(collapsed, " {}", count).unwrap();
+        }
+        write_file(path, &collapsed);
+    }
+}
+
+fn format_report(
+    env: &Environment,
+    profiler: &Profiler,
+    stacks: &[(ThreadId, Vec<String>, u64)],
+    total_samples: u64,
+    elapsed: Duration,
+) -> String {
+    let mut self_samples: HashMap<&str, u64> = HashMap::new();
+    let mut total_samples_by_name: HashMap<&str, u64> = HashMap::new();
+    let mut thread_samples: HashMap<ThreadId, u64> = HashMap::new();
+    for (thread, names, count) in stacks {
+        *self_samples.entry(names[0].as_str()).or_default() += count;
+        // Recursive functions should only be counted once per sample.
+        let mut seen = Vec::with_capacity(names.len());
+        for name in names {
+            if !seen.contains(&name) {
+                seen.push(name);
+                *total_samples_by_name.entry(name.as_str()).or_default() += count;
+            }
+        }
+        *thread_samples.entry(*thread).or_default() += count;
+    }
+
+    let percent = |count: u64| count as f64 * 100.0 / total_samples.max(1) as f64;
+
+    let mut report = String::new();
+    writeln!(
+        report,
+        "touchHLE profile of {:?} over {:.2}s",
+        env.bundle.display_name(),
+        elapsed.as_secs_f64()
+    )
+    .unwrap();
+    writeln!(
+        report,
+        "{} samples of guest code, one every {} CPU ticks",
+        total_samples, SAMPLE_INTERVAL_TICKS
+    )
+    .unwrap();
+
+    writeln!(report, "\nSamples by thread:").unwrap();
+    let mut thread_samples: Vec<_> = thread_samples.into_iter().collect();
+    thread_samples.sort();
+    for (thread, count) in thread_samples {
+        writeln!(
+            report,
+            "{:>8} {:6.2}%  thread {}",
+            count,
+            percent(count),
+            thread
+        )
+        .unwrap();
+    }
+
+    writeln!(
+        report,
+        "\nGuest code (self: samples in the function itself; total: samples in\n\
+        the function or anything it called):"
+    )
+    .unwrap();
+    writeln!(
+        report,
+        "{:>8} {:>7} {:>7}  function",
+        "samples", "self", "total"
+    )
+    .unwrap();
+    let mut functions: Vec<(&str, u64, u64)> = total_samples_by_name
+        .iter()
+        .map(|(&name, &total)| {
+            let self_count = self_samples.get(name).copied().unwrap_or(0);
+            (name, self_count, total)
+        })
+        .collect();
+    functions.sort_by(|a, b| b.1.cmp(&a.1).then(b.2.cmp(&a.2)).then(a.0.cmp(b.0)));
+    for (name, self_count, total) in functions {
+        writeln!(
+            report,
+            "{:>8} {:6.2}% {:6.2}%  {}",
+            self_count,
+            percent(self_count),
+            percent(total),
+            name
+        )
+        .unwrap();
+    }
+
+    writeln!(
+        report,
+        "\nHost calls (wall-clock time, including guest code called by the host\n\
+        and time spent blocked):"
+    )
+    .unwrap();
+    writeln!(
+        report,
+        "{:>10} {:>12} {:>12}  function",
+        "calls", "total (ms)", "mean (µs)"
+    )
+    .unwrap();
+    let mut host_calls: Vec<(String, &HostCallStats)> = profiler
+        .host_calls
+        .iter()
+        .map(|(&call, stats)| (host_call_name(env, call), stats))
+        .collect();
+    host_calls.sort_by(|a, b| b.1.time.cmp(&a.1.time).then(a.0.cmp(&b.0)));
+    for (name, stats) in host_calls {
+        writeln!(
+            report,
+            "{:>10} {:12.3} {:12.3}  {}",
+            stats.calls,
+            stats.time.as_secs_f64() * 1e3,
+            stats.time.as_secs_f64() * 1e6 / stats.calls as f64,
+            name
+        )
+        .unwrap();
+    }
+    report
+}
+
+fn write_file(path: &Path, contents: &str) {
+    match std::fs::write(path, contents) {
+        Ok(()) => log!("Wrote profile to {:?}.", path),
+        Err(e) => log!("Warning: couldn't write profile to {:?}: {}", path, e),
+    }
+}
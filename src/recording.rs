@@ -0,0 +1,687 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+//! Video recording (`--record-video=`) and screenshots.
+//!
+//! Recordings are AVI files with PNG-compressed video frames at a constant
+//! 60fps and uncompressed 16-bit stereo audio. This avoids depending on a video
+//! encoder, at the cost of large files, which can be converted with other tools
+//! afterwards.
+//!
+//! Frames are captured when they are presented, at the resolution the app
+//! renders at, and then rotated to match the window. Encoding happens on a
+//! background host thread. If the encoder can't keep up, frames are dropped.
+//! Each frame is put on the 60fps timeline according to when it was presented,
+//! so dropped frames or a fluctuating framerate don't affect A/V sync.
+//!
+//! OpenAL Soft can't give us a copy of what it outputs, so while recording,
+//! all OpenAL devices are loopback devices (`ALC_SOFT_loopback`) and touchHLE
+//! does the final mix and output itself. This is why [open_al_device] and
+//! friends must be used instead of the OpenAL functions they wrap.
+//!
+//! Resources:
+//! - [AVI RIFF File Reference](https://learn.microsoft.com/en-us/windows/win32/directshow/avi-riff-file-reference)
+//! - [PNG Specification](https://www.w3.org/TR/png/)
+
+use crate::audio::openal as al;
+use crate::audio::openal::alc_types::{ALCboolean, ALCcontext, ALCdevice, ALCint};
+use crate::image::encode_png;
+use crate::matrix::Matrix;
+use crate::window::{AudioOutput, DeviceOrientation};
+use crate::Environment;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+const FRAME_RATE: u32 = 60;
+const SAMPLE_RATE: u32 = 44100;
+const CHANNELS: u32 = 2;
+const BYTES_PER_AUDIO_FRAME: u32 = CHANNELS * 2;
+/// How many frames can be waiting for the encoder before new ones get dropped.
+const MAX_PENDING_FRAMES: usize = 4;
+/// Audio is mixed just in time, so the host output needs some headroom to
+/// avoid gaps between each time it's mixed.
+const OUTPUT_LATENCY: Duration = Duration::from_millis(50);
+/// AVI files use 32-bit offsets and sizes. Leave some room for the index.
+const MAX_AVI_SIZE: u64 = u32::MAX as u64 - (256 << 20);
+
+/// Wrapper so the device list can be in a `static`. The devices are only
+/// actually used from the emulator thread.
+struct LoopbackDevice {
+    device: *mut ALCdevice,
+    paused: bool,
+}
+unsafe impl Send for LoopbackDevice {}
+
+/// Whether new OpenAL devices should be loopback devices. This has to be
+/// decided before the app opens any, so it's set for the whole session.
+static USE_LOOPBACK: AtomicBool = AtomicBool::new(false);
+static LOOPBACK_DEVICES: Mutex<Vec<LoopbackDevice>> = Mutex::new(Vec::new());
+
+/// Open a host OpenAL device for output. Use this instead of
+/// `alcOpenDevice(NULL)`.
+pub fn open_al_device() -> *mut ALCdevice {
+    if !USE_LOOPBACK.load(Ordering::Relaxed) {
+        let device = unsafe { al::alcOpenDevice(std::ptr::null()) };
+        if !device.is_null() {
+            return device;
+        }
+        // Apps rarely cope with having no audio device, so try to give them
+        // OpenAL Soft's null device, which mixes but outputs nothing.
+        log!("Warning: Could not open the audio output device, using a null device instead.");
+        return unsafe { al::alcOpenDevice(b"No Output\0".as_ptr().cast()) };
+    }
+    let device = unsafe { al::alcLoopbackOpenDeviceSOFT(std::ptr::null()) };
+    if !device.is_null() {
+        LOOPBACK_DEVICES.lock().unwrap().push(LoopbackDevice {
+            device,
+            paused: false,
+        });
+    }
+    device
+}
+
+fn is_loopback_device(device: *mut ALCdevice) -> bool {
+    LOOPBACK_DEVICES
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|loopback| loopback.device == device)
+}
+
+/// Create a host OpenAL context for a device from [open_al_device]. Use this
+/// instead of `alcCreateContext(device, NULL)`.
+pub fn create_al_context(device: *mut ALCdevice) -> *mut ALCcontext {
+    if !is_loopback_device(device) {
+        return unsafe { al::alcCreateContext(device, std::ptr::null()) };
+    }
+    // Loopback devices don't have a default format.
+    let attrs: [ALCint; 7] = [
+        al::ALC_FREQUENCY,
+        SAMPLE_RATE as _,
+        al::ALC_FORMAT_CHANNELS_SOFT,
+        al::ALC_STEREO_SOFT,
+        al::ALC_FORMAT_TYPE_SOFT,
+        al::ALC_SHORT_SOFT,
+        0,
+    ];
+    unsafe { al::alcCreateContext(device, attrs.as_ptr()) }
+}
+
+/// Close a host OpenAL device from [open_al_device]. Use this instead of
+/// `alcCloseDevice()`.
+pub fn close_al_device(device: *mut ALCdevice) -> ALCboolean {
+    LOOPBACK_DEVICES
+        .lock()
+        .unwrap()
+        .retain(|loopback| loopback.device != device);
+    unsafe { al::alcCloseDevice(device) }
+}
+
+/// Pause or resume mixing for a host OpenAL device from [open_al_device]. Use
+/// this instead of `alcDevicePauseSOFT()` and `alcDeviceResumeSOFT()`.
+pub fn set_al_device_paused(device: *mut ALCdevice, paused: bool) {
+    // Loopback devices can't be paused, we just have to stop rendering them.
+    if let Some(loopback) = LOOPBACK_DEVICES
+        .lock()
+        .unwrap()
+        .iter_mut()
+        .find(|loopback| loopback.device == device)
+    {
+        loopback.paused = paused;
+        return;
+    }
+    if paused {
+        unsafe { al::alcDevicePauseSOFT(device) };
+    } else {
+        unsafe { al::alcDeviceResumeSOFT(device) };
+    }
+}
+
+#[derive(Default)]
+pub struct State {
+    recorder: Option<Recorder>,
+    /// Paths to save the next presented frame to.
+    screenshot_paths: Vec<PathBuf>,
+    /// Threads saving screenshots, which must finish before touchHLE exits.
+    screenshot_threads: Vec<JoinHandle<()>>,
+}
+
+struct Recorder {
+    start: Instant,
+    /// [None] once the encoder thread has been told to finish.
+    sender: Option<Sender<Message>>,
+    pending_frames: Arc<AtomicUsize>,
+    encoder_thread: Option<JoinHandle<()>>,
+    audio_output: AudioOutput,
+    audio_frames_mixed: u64,
+}
+
+enum Message {
+    Frame(Frame, Duration),
+    Audio(Vec<i16>),
+}
+
+/// A presented frame, as read back from OpenGL ES.
+struct Frame {
+    /// RGBA8 pixels with the bottom row first.
+    pixels: Vec<u8>,
+    width: u32,
+    height: u32,
+    /// See [crate::window::Window::rotation_matrix].
+    rotation: Matrix<2>,
+    /// Whether width and height should be swapped when rotating.
+    landscape: bool,
+}
+impl Frame {
+    /// Get the frame's pixels as RGB8 with the top row first, rotated the same
+    /// way [crate::gles::present::present_frame] would rotate them.
+    fn to_rgb(&self) -> (Vec<u8>, u32, u32) {
+        let (out_width, out_height) = if self.landscape {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        };
+        let columns = self.rotation.columns();
+        let mut rgb = Vec::with_capacity(out_width as usize * out_height as usize * 3);
+        for out_y in 0..out_height {
+            for out_x in 0..out_width {
+                // Window co-ordinates, y-up like OpenGL's.
+                let u = (out_x as f32 + 0.5) / out_width as f32;
+                let v = 1.0 - (out_y as f32 + 0.5) / out_height as f32;
+                // Texture co-ordinates, as they would be transformed by the
+                // texture matrix and then wrapped by GL_REPEAT.
+                let s = (columns[0][0] * u + columns[1][0] * v).rem_euclid(1.0);
+                let t = (columns[0][1] * u + columns[1][1] * v).rem_euclid(1.0);
+                let x = ((s * self.width as f32) as u32).min(self.width - 1);
+                let y = ((t * self.height as f32) as u32).min(self.height - 1);
+                let idx = (y as usize * self.width as usize + x as usize) * 4;
+                rgb.extend_from_slice(&self.pixels[idx..idx + 3]);
+            }
+        }
+        (rgb, out_width, out_height)
+    }
+}
+
+/// Start recording to a file. Called during startup when `--record-video=` is
+/// used.
+pub fn start_recording(env: &mut Environment, path: &Path) -> Result<(), String> {
+    let Some(window) = env.window.as_ref() else {
+        return Err("Video recording is not supported without a window".to_string());
+    };
+    if !path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("avi"))
+    {
+        log!(
+            "Warning: {} will be an AVI file regardless of its extension.",
+            path.display()
+        );
+    }
+
+    let audio_output = window.open_audio_output(SAMPLE_RATE, CHANNELS as u8)?;
+    let latency_frames = (OUTPUT_LATENCY.as_secs_f64() * SAMPLE_RATE as f64) as usize;
+    audio_output.queue(&vec![0; latency_frames * CHANNELS as usize]);
+    let file = File::create(path)
+        .map_err(|e| format!("Could not create video file {}: {}", path.display(), e))?;
+    let writer = AviWriter::new(BufWriter::new(file))
+        .map_err(|e| format!("Could not write to video file {}: {}", path.display(), e))?;
+
+    USE_LOOPBACK.store(true, Ordering::Relaxed);
+
+    let (sender, receiver) = channel();
+    let pending_frames = Arc::new(AtomicUsize::new(0));
+    let pending_frames_for_encoder = pending_frames.clone();
+    let encoder_thread = std::thread::Builder::new()
+        .name("video encoder".to_string())
+        .spawn(move || run_encoder(writer, receiver, pending_frames_for_encoder))
+        .unwrap();
+
+    echo!("Recording video to {}.", path.display());
+    env.recording.recorder = Some(Recorder {
+        start: Instant::now(),
+        sender: Some(sender),
+        pending_frames,
+        encoder_thread: Some(encoder_thread),
+        audio_output,
+        audio_frames_mixed: 0,
+    });
+    Ok(())
+}
+
+/// Finish the recording, if there is one, and wait for the file to be written.
+/// This must be called before touchHLE exits, or the file will be unusable.
+/// This also waits for any screenshots to be saved.
+pub fn finish_recording(env: &mut Environment) {
+    for thread in std::mem::take(&mut env.recording.screenshot_threads) {
+        let _ = thread.join();
+    }
+    let Some(recorder) = env.recording.recorder.as_mut() else {
+        return;
+    };
+    // Dropping the sender tells the encoder thread to finish.
+    if recorder.sender.take().is_none() {
+        return;
+    }
+    echo!("Finishing video recording...");
+    if let Some(thread) = recorder.encoder_thread.take() {
+        let _ = thread.join();
+    }
+}
+
+/// Save a screenshot of the next presented frame to the screenshots directory.
+pub fn request_screenshot(env: &mut Environment) {
+    let path = crate::paths::user_data_base_path()
+        .join(crate::paths::SCREENSHOTS_DIR)
+        .join(format!(
+            "{}-{}.png",
+            env.bundle.bundle_identifier(),
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_millis()
+        ));
+    request_screenshot_to(env, path);
+}
+
+/// Save a screenshot of the next presented frame to a particular path.
+pub fn request_screenshot_to(env: &mut Environment, path: PathBuf) {
+    env.recording.screenshot_paths.push(path);
+}
+
+/// Whether the presentation code should read back the frame it's presenting
+/// and call [frame_presented].
+pub fn wants_frame(env: &Environment) -> bool {
+    !env.recording.screenshot_paths.is_empty()
+        || env
+            .recording
+            .recorder
+            .as_ref()
+            .is_some_and(|recorder| recorder.sender.is_some())
+}
+
+/// For use by the presentation code: handle a presented frame, given as RGBA8
+/// pixels with the bottom row first (i.e. as `glReadPixels()` returns them).
+pub fn frame_presented(env: &mut Environment, pixels: Vec<u8>, width: u32, height: u32) {
+    let window = env.window.as_ref().unwrap();
+    let frame = Frame {
+        pixels,
+        width,
+        height,
+        rotation: window.rotation_matrix(),
+        landscape: window.current_rotation() != DeviceOrientation::Portrait,
+    };
+
+    for path in std::mem::take(&mut env.recording.screenshot_paths) {
+        let frame = Frame {
+            pixels: frame.pixels.clone(),
+            ..frame
+        };
+        let thread = std::thread::spawn(move || save_screenshot(frame, &path));
+        let threads = &mut env.recording.screenshot_threads;
+        threads.retain(|thread| !thread.is_finished());
+        threads.push(thread);
+    }
+
+    pump_audio(env);
+
+    let Some(recorder) = env.recording.recorder.as_mut() else {
+        return;
+    };
+    let Some(sender) = recorder.sender.as_ref() else {
+        return;
+    };
+    if recorder.pending_frames.load(Ordering::Relaxed) >= MAX_PENDING_FRAMES {
+        log_dbg!("Encoder is busy, dropping frame");
+        return;
+    }
+    recorder.pending_frames.fetch_add(1, Ordering::Relaxed);
+    let _ = sender.send(Message::Frame(frame, recorder.start.elapsed()));
+}
+
+/// Mix and output the audio from the loopback devices that is due by now. This
+/// needs to be called frequently while recording, e.g. by `NSRunLoop`.
+pub fn pump_audio(env: &mut Environment) {
+    let output_enabled = env.framework_state.audio_toolbox.output_enabled();
+    let Some(recorder) = env.recording.recorder.as_mut() else {
+        return;
+    };
+
+    let due = (recorder.start.elapsed().as_secs_f64() * SAMPLE_RATE as f64) as u64;
+    let frame_count = due - recorder.audio_frames_mixed;
+    if frame_count == 0 {
+        return;
+    }
+    recorder.audio_frames_mixed = due;
+    let sample_count = (frame_count * CHANNELS as u64) as usize;
+
+    let mut mix = vec![0i32; sample_count];
+    if output_enabled {
+        let mut rendered = vec![0i16; sample_count];
+        for loopback in LOOPBACK_DEVICES.lock().unwrap().iter() {
+            if loopback.paused {
+                continue;
+            }
+            unsafe {
+                al::alcRenderSamplesSOFT(
+                    loopback.device,
+                    rendered.as_mut_ptr().cast(),
+                    frame_count.try_into().unwrap(),
+                )
+            };
+            for (mixed, &sample) in mix.iter_mut().zip(rendered.iter()) {
+                *mixed += sample as i32;
+            }
+        }
+    }
+    let samples: Vec<i16> = mix
+        .into_iter()
+        .map(|sample| sample.clamp(i16::MIN as i32, i16::MAX as i32) as i16)
+        .collect();
+
+    recorder.audio_output.queue(&samples);
+    if let Some(sender) = recorder.sender.as_ref() {
+        let _ = sender.send(Message::Audio(samples));
+    }
+}
+
+fn save_screenshot(frame: Frame, path: &Path) {
+    let (rgb, width, height) = frame.to_rgb();
+    let png = encode_png(&rgb, width, height, /* has_alpha: */ false);
+    let res = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(path, png));
+    match res {
+        Ok(()) => echo!("Saved screenshot to {}.", path.display()),
+        Err(e) => log!("Could not save screenshot to {}: {}", path.display(), e),
+    }
+}
+
+fn run_encoder(
+    mut writer: AviWriter<BufWriter<File>>,
+    receiver: Receiver<Message>,
+    pending_frames: Arc<AtomicUsize>,
+) {
+    let mut res = Ok(());
+    // The loop ends when the sender is dropped.
+    while let Ok(message) = receiver.recv() {
+        if res.is_err() {
+            // Keep receiving so the sender isn't blocked, but stop writing.
+            if let Message::Frame(..) = message {
+                pending_frames.fetch_sub(1, Ordering::Relaxed);
+            }
+            continue;
+        }
+        res = match message {
+            Message::Frame(frame, timestamp) => {
+                let (rgb, width, height) = frame.to_rgb();
+                let png = encode_png(&rgb, width, height, /* has_alpha: */ false);
+                pending_frames.fetch_sub(1, Ordering::Relaxed);
+                let slot = (timestamp.as_secs_f64() * FRAME_RATE as f64).round() as u32;
+                writer.write_frame(&png, width, height, slot)
+            }
+            Message::Audio(samples) => writer.write_audio(&samples),
+        };
+        if let Err(ref e) = res {
+            log!("Error while writing video file, stopping recording: {}", e);
+        }
+    }
+    match writer.finish() {
+        Ok(()) => echo!("Finished video recording."),
+        Err(e) => log!("Error while finishing video file: {}", e),
+    }
+}
+
+/// Minimal streaming AVI writer with one video and one audio stream.
+struct AviWriter<W: Write + Seek> {
+    file: W,
+    /// Current position in the file.
+    position: u64,
+    /// Position of the `movi` list's type, which index offsets are relative to.
+    movi_position: u64,
+    /// Entries for the `idx1` chunk: chunk ID, flags, offset and size.
+    index: Vec<([u8; 4], u32, u32, u32)>,
+    video_size: Option<(u32, u32)>,
+    /// Number of video frames written so far, including empty ones.
+    video_frames: u32,
+    max_video_chunk_size: u32,
+    audio_bytes: u64,
+    /// Set once the file has reached [MAX_AVI_SIZE].
+    full: bool,
+}
+
+fn put_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+fn riff_chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(8 + data.len() + 1);
+    chunk.extend_from_slice(id);
+    put_u32(&mut chunk, data.len() as u32);
+    chunk.extend_from_slice(data);
+    if data.len() % 2 != 0 {
+        chunk.push(0);
+    }
+    chunk
+}
+fn riff_list(list_type: &[u8; 4], contents: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(4 + contents.len());
+    data.extend_from_slice(list_type);
+    data.extend_from_slice(contents);
+    riff_chunk(b"LIST", &data)
+}
+
+impl<W: Write + Seek> AviWriter<W> {
+    fn new(file: W) -> std::io::Result<Self> {
+        let mut writer = AviWriter {
+            file,
+            position: 0,
+            movi_position: 0,
+            index: Vec::new(),
+            video_size: None,
+            video_frames: 0,
+            max_video_chunk_size: 0,
+            audio_bytes: 0,
+            full: false,
+        };
+        // The real header is written once the sizes are known, but it always
+        // has the same length.
+        let header = writer.header(0, 0);
+        writer.file.write_all(&header)?;
+        writer.position = header.len() as u64;
+        writer.movi_position = writer.position - 4;
+        Ok(writer)
+    }
+
+    fn header(&self, riff_size: u32, movi_size: u32) -> Vec<u8> {
+        let (width, height) = self.video_size.unwrap_or((0, 0));
+        let audio_frames = (self.audio_bytes / BYTES_PER_AUDIO_FRAME as u64) as u32;
+
+        let mut avih = Vec::new();
+        put_u32(&mut avih, 1_000_000 / FRAME_RATE); // dwMicroSecPerFrame
+        put_u32(&mut avih, 0); // dwMaxBytesPerSec
+        put_u32(&mut avih, 0); // dwPaddingGranularity
+        put_u32(&mut avih, 0x10); // dwFlags: AVIF_HASINDEX
+        put_u32(&mut avih, self.video_frames); // dwTotalFrames
+        put_u32(&mut avih, 0); // dwInitialFrames
+        put_u32(&mut avih, 2); // dwStreams
+        put_u32(&mut avih, self.max_video_chunk_size); // dwSuggestedBufferSize
+        put_u32(&mut avih, width); // dwWidth
+        put_u32(&mut avih, height); // dwHeight
+        avih.extend_from_slice(&[0; 16]); // dwReserved
+
+        let stream_header = |kind: &[u8; 4],
+                             handler: &[u8; 4],
+                             scale: u32,
+                             rate: u32,
+                             length: u32,
+                             buffer_size: u32,
+                             sample_size: u32| {
+            let mut strh = Vec::new();
+            strh.extend_from_slice(kind); // fccType
+            strh.extend_from_slice(handler); // fccHandler
+            put_u32(&mut strh, 0); // dwFlags
+            put_u16(&mut strh, 0); // wPriority
+            put_u16(&mut strh, 0); // wLanguage
+            put_u32(&mut strh, 0); // dwInitialFrames
+            put_u32(&mut strh, scale); // dwScale
+            put_u32(&mut strh, rate); // dwRate
+            put_u32(&mut strh, 0); // dwStart
+            put_u32(&mut strh, length); // dwLength
+            put_u32(&mut strh, buffer_size); // dwSuggestedBufferSize
+            put_u32(&mut strh, u32::MAX); // dwQuality
+            put_u32(&mut strh, sample_size); // dwSampleSize
+            put_u16(&mut strh, 0); // rcFrame
+            put_u16(&mut strh, 0);
+            put_u16(&mut strh, width as u16);
+            put_u16(&mut strh, height as u16);
+            riff_chunk(b"strh", &strh)
+        };
+
+        let mut video_strf = Vec::new(); // BITMAPINFOHEADER
+        put_u32(&mut video_strf, 40); // biSize
+        put_u32(&mut video_strf, width); // biWidth
+        put_u32(&mut video_strf, height); // biHeight
+        put_u16(&mut video_strf, 1); // biPlanes
+        put_u16(&mut video_strf, 24); // biBitCount
+        video_strf.extend_from_slice(b"MPNG"); // biCompression
+        put_u32(&mut video_strf, width * height * 3); // biSizeImage
+        video_strf.extend_from_slice(&[0; 16]); // biXPelsPerMeter etc
+        let mut video_strl = stream_header(
+            b"vids",
+            b"MPNG",
+            1,
+            FRAME_RATE,
+            self.video_frames,
+            self.max_video_chunk_size,
+            0,
+        );
+        video_strl.extend_from_slice(&riff_chunk(b"strf", &video_strf));
+
+        let mut audio_strf = Vec::new(); // WAVEFORMATEX
+        put_u16(&mut audio_strf, 1); // wFormatTag: WAVE_FORMAT_PCM
+        put_u16(&mut audio_strf, CHANNELS as u16); // nChannels
+        put_u32(&mut audio_strf, SAMPLE_RATE); // nSamplesPerSec
+        put_u32(&mut audio_strf, SAMPLE_RATE * BYTES_PER_AUDIO_FRAME); // nAvgBytesPerSec
+        put_u16(&mut audio_strf, BYTES_PER_AUDIO_FRAME as u16); // nBlockAlign
+        put_u16(&mut audio_strf, 16); // wBitsPerSample
+        put_u16(&mut audio_strf, 0); // cbSize
+        let mut audio_strl = stream_header(
+            b"auds",
+            &[0; 4],
+            BYTES_PER_AUDIO_FRAME,
+            SAMPLE_RATE * BYTES_PER_AUDIO_FRAME,
+            audio_frames,
+            0,
+            BYTES_PER_AUDIO_FRAME,
+        );
+        audio_strl.extend_from_slice(&riff_chunk(b"strf", &audio_strf));
+
+        let mut hdrl = riff_chunk(b"avih", &avih);
+        hdrl.extend_from_slice(&riff_list(b"strl", &video_strl));
+        hdrl.extend_from_slice(&riff_list(b"strl", &audio_strl));
+
+        let mut header = Vec::new();
+        header.extend_from_slice(b"RIFF");
+        put_u32(&mut header, riff_size);
+        header.extend_from_slice(b"AVI ");
+        header.extend_from_slice(&riff_list(b"hdrl", &hdrl));
+        // Start of the movi list, which the chunks are appended to.
+        header.extend_from_slice(b"LIST");
+        put_u32(&mut header, movi_size);
+        header.extend_from_slice(b"movi");
+        header
+    }
+
+    fn write_chunk(&mut self, id: &[u8; 4], data: &[u8], flags: u32) -> std::io::Result<()> {
+        let chunk = riff_chunk(id, data);
+        if self.position + chunk.len() as u64 > MAX_AVI_SIZE {
+            if !self.full {
+                log!("Video file has reached the maximum size, recording stopped.");
+                self.full = true;
+            }
+            return Ok(());
+        }
+        self.file.write_all(&chunk)?;
+        self.index.push((
+            *id,
+            flags,
+            (self.position - self.movi_position) as u32,
+            data.len() as u32,
+        ));
+        self.position += chunk.len() as u64;
+        Ok(())
+    }
+
+    /// Write a PNG-encoded frame, to be displayed in the given 60fps interval.
+    fn write_frame(
+        &mut self,
+        png: &[u8],
+        width: u32,
+        height: u32,
+        slot: u32,
+    ) -> std::io::Result<()> {
+        if *self.video_size.get_or_insert((width, height)) != (width, height) {
+            log_dbg!("Frame size changed, dropping frame");
+            return Ok(());
+        }
+        if slot < self.video_frames || self.full {
+            // There's already a frame for this interval.
+            return Ok(());
+        }
+        // Empty chunks mean the previous frame is repeated.
+        while self.video_frames < slot && !self.full {
+            self.write_chunk(b"00dc", &[], 0)?;
+            self.video_frames += 1;
+        }
+        const AVIIF_KEYFRAME: u32 = 0x10;
+        self.write_chunk(b"00dc", png, AVIIF_KEYFRAME)?;
+        if !self.full {
+            self.video_frames += 1;
+            self.max_video_chunk_size = self.max_video_chunk_size.max(png.len() as u32);
+        }
+        Ok(())
+    }
+
+    fn write_audio(&mut self, samples: &[i16]) -> std::io::Result<()> {
+        let bytes: Vec<u8> = samples
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        self.write_chunk(b"01wb", &bytes, 0x10)?;
+        if !self.full {
+            self.audio_bytes += bytes.len() as u64;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> std::io::Result<()> {
+        let movi_size = (self.position - self.movi_position) as u32;
+
+        let mut idx1 = Vec::with_capacity(self.index.len() * 16);
+        for &(id, flags, offset, size) in &self.index {
+            idx1.extend_from_slice(&id);
+            put_u32(&mut idx1, flags);
+            put_u32(&mut idx1, offset);
+            put_u32(&mut idx1, size);
+        }
+        let idx1 = riff_chunk(b"idx1", &idx1);
+        self.file.write_all(&idx1)?;
+        let riff_size = (self.position + idx1.len() as u64 - 8) as u32;
+
+        let header = self.header(riff_size, movi_size);
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)?;
+        self.file.flush()
+    }
+}
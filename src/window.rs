@@ -16,7 +16,8 @@ use crate::gles::present::present_frame;
 use crate::gles::{create_gles1_ctx, GLES};
 use crate::image::Image;
 use crate::matrix::Matrix;
-use crate::options::Options;
+use crate::options::{Options, Rumble, TouchRegion, TouchRegionBinding};
+use sdl2::event::WindowEvent;
 use sdl2::mouse::MouseButton;
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::surface::Surface;
@@ -74,6 +75,12 @@ pub enum FingerId {
     Touch(i64),
     VirtualCursor,
     ButtonToTouch(crate::options::Button),
+    /// Second touch used to simulate pinch gestures with the mouse.
+    PinchMirror,
+    /// Index into [Options::touch_regions].
+    TouchRegion(usize),
+    /// Touch injected by `--input-script=` (see [crate::automation]).
+    Script,
 }
 pub type Coords = (f32, f32);
 
@@ -101,6 +108,29 @@ pub enum Event {
     /// take over.
     EnterDebugger,
     TextInput(TextInputEvent),
+    /// The window lost (`false`) or regained (`true`) input focus.
+    FocusChanged(bool),
+    /// User pressed F11, requesting that a simulated audio interruption begin
+    /// or end.
+    ToggleAudioInterruption,
+    /// User pressed F10, requesting that a screenshot be saved.
+    TakeScreenshot,
+    /// User pressed F5, requesting that fast-forwarding start or stop.
+    ToggleFastForward,
+    /// User pressed F6, requesting that the app be paused or resumed.
+    TogglePause,
+    /// User pressed F7, requesting that the paused app advance by one frame.
+    AdvanceFrame,
+    /// User pressed F8, requesting that the app be sent a memory warning.
+    SimulateMemoryWarning,
+    /// User pressed F4, requesting that the profiling report be written.
+    WriteProfile,
+    /// The time given by `--exit-after=` has passed (see
+    /// [crate::automation]).
+    ScriptedQuit,
+    /// User pressed the key for the simulated volume up (`true`) or down
+    /// (`false`) button (see `--volume-up-key=` and `--volume-down-key=`).
+    PressVolumeButton(bool),
 }
 
 pub enum GLVersion {
@@ -112,6 +142,17 @@ pub enum GLVersion {
 
 pub struct GLContext(sdl2::video::GLContext);
 
+/// Stream of audio samples to be played, for when touchHLE does its own mixing
+/// (see [crate::recording]). The samples are interleaved signed 16-bit.
+pub struct AudioOutput(sdl2::audio::AudioQueue<i16>);
+impl AudioOutput {
+    pub fn queue(&self, samples: &[i16]) {
+        if let Err(e) = self.0.queue_audio(samples) {
+            log!("Warning: could not queue audio output: {}", e);
+        }
+    }
+}
+
 fn surface_from_image(image: &Image) -> Surface {
     let src_pixels = image.pixels();
     let (width, height) = image.dimensions();
@@ -134,7 +175,7 @@ fn surface_from_image(image: &Image) -> Surface {
 }
 
 pub struct Window {
-    _sdl_ctx: sdl2::Sdl,
+    sdl_ctx: sdl2::Sdl,
     video_ctx: sdl2::VideoSubsystem,
     window: sdl2::video::Window,
     event_pump: sdl2::EventPump,
@@ -158,11 +199,33 @@ pub struct Window {
     app_gl_ctx_no_longer_current: bool,
     controller_ctx: sdl2::GameControllerSubsystem,
     controllers: Vec<sdl2::controller::GameController>,
+    /// Instance ID of the controller that most recently had input, used to
+    /// pick which controller should rumble.
+    last_used_controller: Option<u32>,
     _sensor_ctx: sdl2::SensorSubsystem,
     accelerometer: Option<sdl2::sensor::Sensor>,
     virtual_cursor_last: Option<(f32, f32, bool, bool)>,
     virtual_cursor_last_unsticky: Option<(f32, f32, Instant)>,
     virtual_accelerometer_last: Option<(f32, f32, bool)>,
+    /// Copy of `touch_regions` on [Options].
+    touch_regions: Vec<TouchRegion>,
+    /// Current position (in [Options::touch_regions] co-ordinates) of the
+    /// touch for each touch region, if it's being touched.
+    touch_region_positions: Vec<Option<Coords>>,
+    /// Whether indicators for the touch regions should be drawn (toggled with
+    /// F9).
+    show_touch_regions: bool,
+    /// Window co-ordinates of the mouse while its left button is held.
+    mouse_held_at: Option<Coords>,
+    /// Whether the mouse touch is accompanied by a mirrored touch, to simulate
+    /// a pinch gesture (see [Self::pinch_mirror_position]).
+    pinch_active: bool,
+    /// Set when a pinch gesture ends because Shift is released while the
+    /// mouse button is still held. Both touches have ended by then, so mouse
+    /// movement is ignored until the button is released.
+    mouse_touch_ended: bool,
+    /// Copy of `pinch_marker` on [Options].
+    pinch_marker: bool,
 }
 impl Window {
     /// Returns [true] if touchHLE is running on a device where we should always
@@ -177,9 +240,17 @@ impl Window {
         icon: Option<Image>,
         launch_image: Option<Image>,
         options: &Options,
-    ) -> Window {
-        let sdl_ctx = sdl2::init().unwrap();
-        let video_ctx = sdl_ctx.video().unwrap();
+    ) -> Result<Window, String> {
+        if options.headless {
+            // SDL's offscreen driver has no visible windows and no input, but
+            // can still create OpenGL contexts (via EGL, which might be a
+            // software renderer), so the app can run as normal.
+            sdl2::hint::set("SDL_VIDEODRIVER", "offscreen");
+            sdl2::hint::set("SDL_AUDIODRIVER", "dummy");
+        }
+
+        let sdl_ctx = sdl2::init()?;
+        let video_ctx = sdl_ctx.video()?;
 
         // The "hidapi" feature of rust-sdl2 is enabled so that sdl2::sensor
         // is available, but we don't want to enable SDL's HIDAPI controller
@@ -213,7 +284,15 @@ impl Window {
         let device_orientation = options.initial_orientation;
         let fullscreen = options.fullscreen;
 
-        let mut window = if Self::rotatable_fullscreen() {
+        let mut window = if options.headless {
+            let (width, height) = size_for_orientation(device_orientation, scale_hack);
+            video_ctx
+                .window(title, width, height)
+                .hidden()
+                .opengl()
+                .build()
+                .map_err(|e| e.to_string())?
+        } else if Self::rotatable_fullscreen() {
             // Without this, SDL will force fullscreen mode to be portrait.
             set_sdl2_orientation(device_orientation);
             let screen_size = video_ctx.display_bounds(0).unwrap().size();
@@ -278,7 +357,7 @@ impl Window {
         let max_height = window.size().1;
 
         let mut window = Window {
-            _sdl_ctx: sdl_ctx,
+            sdl_ctx,
             video_ctx,
             window,
             event_pump,
@@ -298,11 +377,19 @@ impl Window {
             app_gl_ctx_no_longer_current: false,
             controller_ctx,
             controllers: Vec::new(),
+            last_used_controller: None,
             _sensor_ctx: sensor_ctx,
             accelerometer,
             virtual_cursor_last: None,
             virtual_cursor_last_unsticky: None,
             virtual_accelerometer_last: None,
+            touch_regions: options.touch_regions.clone(),
+            touch_region_positions: vec![None; options.touch_regions.len()],
+            show_touch_regions: false,
+            mouse_held_at: None,
+            pinch_active: false,
+            mouse_touch_ended: false,
+            pinch_marker: options.pinch_marker,
         };
 
         // Set up OpenGL ES context used for splash screen and app UI rendering
@@ -318,7 +405,7 @@ impl Window {
             window.display_splash();
         }
 
-        window
+        Ok(window)
     }
 
     /// Poll for events from the OS. This needs to be done reasonably often
@@ -387,6 +474,50 @@ impl Window {
             let (screen_width, screen_height) = window.window.drawable_size();
             (screen_width as f32 * x, screen_height as f32 * y)
         }
+        /// Move, press or release the simulated finger of a touch region,
+        /// queueing the corresponding touch event. The position is in
+        /// [Options::touch_regions] co-ordinates, and is [None] if the region
+        /// is no longer touched.
+        fn set_touch_region_position(window: &mut Window, idx: usize, new: Option<Coords>) {
+            let old = std::mem::replace(&mut window.touch_region_positions[idx], new);
+            let finger_id = FingerId::TouchRegion(idx);
+            let event = match (old, new) {
+                (None, Some(new)) => {
+                    let coords = transform_input_coords(window, new, true);
+                    Event::TouchesDown(HashMap::from([(finger_id, coords)]))
+                }
+                (Some(old), Some(new)) if old != new => {
+                    let coords = transform_input_coords(window, new, true);
+                    Event::TouchesMove(HashMap::from([(finger_id, coords)]))
+                }
+                (Some(old), None) => {
+                    let coords = transform_input_coords(window, old, true);
+                    Event::TouchesUp(HashMap::from([(finger_id, coords)]))
+                }
+                _ => return,
+            };
+            window.event_queue.push_back(event);
+        }
+
+        fn shift_held(window: &Window) -> bool {
+            window
+                .sdl_ctx
+                .keyboard()
+                .mod_state()
+                .intersects(sdl2::keyboard::Mod::LSHIFTMOD | sdl2::keyboard::Mod::RSHIFTMOD)
+        }
+        /// Get the touch co-ordinates of the mouse, and of the mirrored touch
+        /// if there's a pinch gesture.
+        fn mouse_touches(window: &Window, pos: Coords) -> HashMap<FingerId, Coords> {
+            let mut map =
+                HashMap::from([(FingerId::Mouse, transform_input_coords(window, pos, false))]);
+            if window.pinch_active {
+                let mirror_pos = window.pinch_mirror_position(pos);
+                let coords = transform_input_coords(window, mirror_pos, false);
+                map.insert(FingerId::PinchMirror, coords);
+            }
+            map
+        }
 
         let mut controller_updated = false;
         // event_pump doesn't have a method to peek on events
@@ -442,6 +573,62 @@ impl Window {
                 _ => {}
             }
 
+            // Touch regions bound to buttons and keys
+            enum RegionInput {
+                Button(sdl2::controller::Button),
+                Key(sdl2::keyboard::Keycode),
+            }
+            let region_input = match event {
+                E::KeyDown {
+                    keycode: Some(sdl2::keyboard::Keycode::F9),
+                    repeat: false,
+                    ..
+                } => {
+                    self.show_touch_regions = !self.show_touch_regions;
+                    if self.touch_regions.is_empty() {
+                        log!("There are no touch regions to show, see --touch-region=.");
+                    }
+                    continue;
+                }
+                E::KeyDown {
+                    keycode: Some(keycode),
+                    repeat: false,
+                    ..
+                } => Some((RegionInput::Key(keycode), true)),
+                E::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => Some((RegionInput::Key(keycode), false)),
+                E::ControllerButtonDown { button, .. } => Some((RegionInput::Button(button), true)),
+                E::ControllerButtonUp { button, .. } => Some((RegionInput::Button(button), false)),
+                _ => None,
+            };
+            if let Some((input, pressed)) = region_input {
+                let mut matched_key = false;
+                for idx in 0..self.touch_regions.len() {
+                    let region = &self.touch_regions[idx];
+                    let matches = match (&region.binding, &input) {
+                        (TouchRegionBinding::Button(bound), &RegionInput::Button(button)) => {
+                            translate_button(button) == Some(*bound)
+                        }
+                        (TouchRegionBinding::Key(bound), RegionInput::Key(keycode)) => {
+                            keycode.name().eq_ignore_ascii_case(bound)
+                        }
+                        _ => false,
+                    };
+                    if matches {
+                        matched_key |= matches!(input, RegionInput::Key(_));
+                        let center = region.center;
+                        set_touch_region_position(self, idx, pressed.then_some(center));
+                    }
+                }
+                // Controller buttons are used for other things too, so only key
+                // presses are consumed.
+                if matched_key {
+                    continue;
+                }
+            }
+
             self.event_queue.push_back(match event {
                 E::Quit { .. } => Event::Quit,
                 E::MouseButtonDown {
@@ -450,16 +637,25 @@ impl Window {
                     mouse_btn: MouseButton::Left,
                     ..
                 } => {
-                    let coords = transform_input_coords(self, (x as f32, y as f32), false);
-                    log_dbg!("MouseButtonDown x {}, y {}, coords {:?}", x, y, coords);
-                    Event::TouchesDown(HashMap::from([(FingerId::Mouse, coords)]))
+                    let pos = (x as f32, y as f32);
+                    self.mouse_held_at = Some(pos);
+                    self.mouse_touch_ended = false;
+                    self.pinch_active = shift_held(self);
+                    let map = mouse_touches(self, pos);
+                    log_dbg!("MouseButtonDown x {}, y {}, touches {:?}", x, y, map);
+                    Event::TouchesDown(map)
                 }
                 E::MouseMotion {
                     x, y, mousestate, ..
                 } if mousestate.left() => {
-                    let coords = transform_input_coords(self, (x as f32, y as f32), false);
-                    log_dbg!("MouseMotion x {}, y {}, coords {:?}", x, y, coords);
-                    Event::TouchesMove(HashMap::from([(FingerId::Mouse, coords)]))
+                    if self.mouse_touch_ended {
+                        continue;
+                    }
+                    let pos = (x as f32, y as f32);
+                    self.mouse_held_at = Some(pos);
+                    let map = mouse_touches(self, pos);
+                    log_dbg!("MouseMotion x {}, y {}, touches {:?}", x, y, map);
+                    Event::TouchesMove(map)
                 }
                 E::MouseButtonUp {
                     x,
@@ -467,9 +663,63 @@ impl Window {
                     mouse_btn: MouseButton::Left,
                     ..
                 } => {
-                    let coords = transform_input_coords(self, (x as f32, y as f32), false);
-                    log_dbg!("MouseButtonUp x {}, y {}, coords {:?}", x, y, coords);
-                    Event::TouchesUp(HashMap::from([(FingerId::Mouse, coords)]))
+                    self.mouse_held_at = None;
+                    if std::mem::take(&mut self.mouse_touch_ended) {
+                        continue;
+                    }
+                    let map = mouse_touches(self, (x as f32, y as f32));
+                    self.pinch_active = false;
+                    log_dbg!("MouseButtonUp x {}, y {}, touches {:?}", x, y, map);
+                    Event::TouchesUp(map)
+                }
+                E::KeyDown {
+                    keycode: Some(sdl2::keyboard::Keycode::LShift | sdl2::keyboard::Keycode::RShift),
+                    repeat: false,
+                    ..
+                } => {
+                    // Start a pinch gesture if the mouse is already held.
+                    let Some(pos) = self.mouse_held_at else {
+                        continue;
+                    };
+                    if self.pinch_active || self.mouse_touch_ended {
+                        continue;
+                    }
+                    self.pinch_active = true;
+                    let coords = transform_input_coords(self, self.pinch_mirror_position(pos), false);
+                    Event::TouchesDown(HashMap::from([(FingerId::PinchMirror, coords)]))
+                }
+                E::KeyUp {
+                    keycode: Some(sdl2::keyboard::Keycode::LShift | sdl2::keyboard::Keycode::RShift),
+                    ..
+                } => {
+                    // Both touches of a pinch gesture end together.
+                    let Some(pos) = self.mouse_held_at else {
+                        continue;
+                    };
+                    if !self.pinch_active {
+                        continue;
+                    }
+                    let map = mouse_touches(self, pos);
+                    self.pinch_active = false;
+                    self.mouse_touch_ended = true;
+                    Event::TouchesUp(map)
+                }
+                E::MouseWheel { y, direction, .. } => {
+                    // Don't interfere with a touch that's in progress.
+                    if self.mouse_held_at.is_some() {
+                        continue;
+                    }
+                    let steps = match direction {
+                        sdl2::mouse::MouseWheelDirection::Flipped => -y,
+                        _ => y,
+                    };
+                    if steps == 0 {
+                        continue;
+                    }
+                    self.queue_scroll_pinch(steps as f32 * options.pinch_scroll_step, |window, pos| {
+                        transform_input_coords(window, pos, false)
+                    });
+                    continue;
                 }
                 E::ControllerDeviceAdded { which, .. } => {
                     self.controller_added(which);
@@ -483,6 +733,9 @@ impl Window {
                 // handled with polling, rather than being event-based.
                 E::ControllerButtonUp { button, .. } | E::ControllerButtonDown { button, .. } => {
                     controller_updated = true;
+                    if let E::ControllerButtonDown { which, .. } = event {
+                        self.last_used_controller = Some(which);
+                    }
                     let Some(button) = translate_button(button) else {
                         continue;
                     };
@@ -507,8 +760,12 @@ impl Window {
                         _ => unreachable!(),
                     }
                 }
-                E::ControllerAxisMotion { .. } => {
+                E::ControllerAxisMotion { which, value, .. } => {
                     controller_updated = true;
+                    // Ignore drift within the dead zone.
+                    if (value as f32 / i16::MAX as f32).abs() > options.deadzone {
+                        self.last_used_controller = Some(which);
+                    }
                     continue;
                 }
                 E::AppWillEnterBackground { .. } => {
@@ -623,6 +880,69 @@ impl Window {
                     echo!("F12 pressed, EnterDebugger event queued.");
                     Event::EnterDebugger
                 }
+                E::KeyDown {
+                    keycode: Some(sdl2::keyboard::Keycode::F11),
+                    repeat: false,
+                    ..
+                } => Event::ToggleAudioInterruption,
+                E::KeyDown {
+                    keycode: Some(sdl2::keyboard::Keycode::F10),
+                    repeat: false,
+                    ..
+                } => Event::TakeScreenshot,
+                E::KeyDown {
+                    keycode: Some(sdl2::keyboard::Keycode::F5),
+                    repeat: false,
+                    ..
+                } => Event::ToggleFastForward,
+                E::KeyDown {
+                    keycode: Some(sdl2::keyboard::Keycode::F6),
+                    repeat: false,
+                    ..
+                } => Event::TogglePause,
+                E::KeyDown {
+                    keycode: Some(sdl2::keyboard::Keycode::F7),
+                    repeat: false,
+                    ..
+                } => Event::AdvanceFrame,
+                E::KeyDown {
+                    keycode: Some(sdl2::keyboard::Keycode::F8),
+                    repeat: false,
+                    ..
+                } => Event::SimulateMemoryWarning,
+                E::KeyDown {
+                    keycode: Some(sdl2::keyboard::Keycode::F4),
+                    repeat: false,
+                    ..
+                } => Event::WriteProfile,
+                E::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } if keycode.name().eq_ignore_ascii_case(&options.volume_up_key) => {
+                    Event::PressVolumeButton(true)
+                }
+                E::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } if keycode.name().eq_ignore_ascii_case(&options.volume_down_key) => {
+                    Event::PressVolumeButton(false)
+                }
+                E::Window {
+                    win_event: WindowEvent::FocusLost,
+                    ..
+                } => {
+                    // The touch is cancelled when focus is lost, so the rest
+                    // of the mouse drag must be ignored.
+                    if self.mouse_held_at.is_some() {
+                        self.mouse_touch_ended = true;
+                    }
+                    self.pinch_active = false;
+                    Event::FocusChanged(false)
+                }
+                E::Window {
+                    win_event: WindowEvent::FocusGained,
+                    ..
+                } => Event::FocusChanged(true),
                 E::KeyDown {
                     keycode: Some(sdl2::keyboard::Keycode::Backspace),
                     ..
@@ -646,6 +966,22 @@ impl Window {
         }
 
         if controller_updated {
+            for idx in 0..self.touch_regions.len() {
+                let region = &self.touch_regions[idx];
+                let TouchRegionBinding::Stick(left) = region.binding else {
+                    continue;
+                };
+                let (x, y, _) = self.get_controller_stick(options, left);
+                let new = if x != 0.0 || y != 0.0 {
+                    // The stick's range is a circle, like the region.
+                    let (center_x, center_y) = region.center;
+                    Some((center_x + x * region.radius, center_y + y * region.radius))
+                } else {
+                    None
+                };
+                set_touch_region_position(self, idx, new);
+            }
+
             let (new_x, new_y, pressed, pressed_changed, moved) =
                 self.update_virtual_cursor(options);
             self.event_queue
@@ -670,9 +1006,36 @@ impl Window {
     /// Pop an event from the queue (in FIFO order, except for high priority
     /// events)
     pub fn pop_event(&mut self) -> Option<Event> {
-        self.high_priority_event
+        let event = self
+            .high_priority_event
             .take()
-            .or_else(|| self.event_queue.pop_front())
+            .or_else(|| self.event_queue.pop_front());
+        // Like iOS, coalesce touch movements that queued up because the app
+        // couldn't keep up, so each touch's previous location is the one the
+        // app last saw.
+        let Some(Event::TouchesMove(mut map)) = event else {
+            return event;
+        };
+        while let Some(Event::TouchesMove(_)) = self.event_queue.front() {
+            let Some(Event::TouchesMove(next)) = self.event_queue.pop_front() else {
+                unreachable!();
+            };
+            map.extend(next);
+        }
+        Some(Event::TouchesMove(map))
+    }
+
+    /// Add an event to the back of the queue, as if it came from the OS.
+    pub fn push_event(&mut self, event: Event) {
+        self.event_queue.push_back(event);
+    }
+
+    /// Put events back at the front of the queue, in the same order, so they
+    /// are the next to be popped.
+    pub fn push_front_events(&mut self, events: Vec<Event>) {
+        for event in events.into_iter().rev() {
+            self.event_queue.push_front(event);
+        }
     }
 
     fn controller_added(&mut self, joystick_idx: u32) {
@@ -702,6 +1065,41 @@ impl Window {
         };
         let controller = self.controllers.remove(idx);
         log!("Warning: Controller disconnected: {}", controller.name());
+        if self.last_used_controller == Some(instance_id) {
+            self.last_used_controller = None;
+        }
+    }
+
+    /// Make a game controller rumble, to simulate the device vibrating. Calling
+    /// this again while the controller is already rumbling restarts the pulse
+    /// rather than extending it.
+    ///
+    /// The most recently used controller is picked, or the first one if none
+    /// have been used yet. If it doesn't support rumble, nothing happens.
+    pub fn rumble(&mut self, options: &Options) {
+        // The iPhone's vibration motor is quite strong and it vibrates for
+        // about 0.4s.
+        const DURATION_MS: u32 = 400;
+        let strength = match options.rumble {
+            Rumble::Off => return,
+            Rumble::Low => 0x4000,
+            Rumble::High => 0xFFFF,
+        };
+
+        let idx = self
+            .last_used_controller
+            .and_then(|instance_id| {
+                self.controllers
+                    .iter()
+                    .position(|controller| controller.instance_id() == instance_id)
+            })
+            .unwrap_or(0);
+        let Some(controller) = self.controllers.get_mut(idx) else {
+            return;
+        };
+        if let Err(e) = controller.set_rumble(strength, strength, DURATION_MS) {
+            log_dbg!("Couldn't rumble {}: {}", controller.name(), e);
+        }
     }
     pub fn print_accelerometer_notice(&self) {
         log!("This app uses the accelerometer.");
@@ -747,6 +1145,8 @@ impl Window {
             self.virtual_accelerometer_last
                 .map(|(x, y, _right_click_hold)| (x, y))
                 .unwrap()
+        } else if self.stick_is_bound_to_touch_region(true) {
+            (0.0, 0.0)
         } else {
             // Get left analog stick input. The range is [-1, 1] on each axis.
             let (x, y, _) = self.get_controller_stick(options, true);
@@ -808,12 +1208,107 @@ impl Window {
         }
     }
 
+    /// For use when redrawing the screen: Get the on-screen position, radius
+    /// and press state of indicators for the touch regions, if they are
+    /// visible, and for the mirrored touch of a pinch gesture. Stick regions
+    /// get an extra small indicator showing where they're being touched.
+    pub fn touch_indicators_visible_at(&self) -> Vec<(f32, f32, f32, bool)> {
+        let mut indicators = Vec::new();
+
+        if self.pinch_active && self.pinch_marker {
+            if let Some(pos) = self.mouse_held_at {
+                let (x, y) = self.pinch_mirror_position(pos);
+                indicators.push((x, y, 10.0, true));
+            }
+        }
+
+        if !self.show_touch_regions {
+            return indicators;
+        }
+
+        let (vx, vy, vw, vh) = self.viewport();
+        let (width, height) =
+            size_for_orientation(self.device_orientation, NonZeroU32::new(1).unwrap());
+        let scale_x = vw as f32 / width as f32;
+        let scale_y = vh as f32 / height as f32;
+        let to_window = |(x, y): Coords| (vx as f32 + x * scale_x, vy as f32 + y * scale_y);
+
+        for (region, &position) in self.touch_regions.iter().zip(&self.touch_region_positions) {
+            let (x, y) = to_window(region.center);
+            indicators.push((x, y, region.radius * scale_x, position.is_some()));
+            if let (TouchRegionBinding::Stick(_), Some(position)) = (&region.binding, position) {
+                let (x, y) = to_window(position);
+                indicators.push((x, y, region.radius * scale_x / 4.0, true));
+            }
+        }
+        indicators
+    }
+
+    /// Get the window co-ordinates of the mirrored touch for a pinch gesture,
+    /// given the mouse's window co-ordinates. It's reflected about the center
+    /// of the screen.
+    fn pinch_mirror_position(&self, (x, y): Coords) -> Coords {
+        let (vx, vy, vw, vh) = self.viewport();
+        let center_x = vx as f32 + vw as f32 / 2.0;
+        let center_y = vy as f32 + vh as f32 / 2.0;
+        (center_x * 2.0 - x, center_y * 2.0 - y)
+    }
+
+    /// Queue the events for a short pinch gesture centered on the screen, for
+    /// simulating pinches with the mouse wheel. Each finger moves `distance`
+    /// pixels outwards (or inwards, if negative). `transform` converts window
+    /// co-ordinates to touch co-ordinates.
+    fn queue_scroll_pinch(&mut self, distance: f32, transform: impl Fn(&Window, Coords) -> Coords) {
+        // Enough intermediate steps that apps which only look at the change
+        // between successive touch moves still see a gesture.
+        const MOVES: u32 = 4;
+
+        let (vx, vy, vw, vh) = self.viewport();
+        let center_x = vx as f32 + vw as f32 / 2.0;
+        let center_y = vy as f32 + vh as f32 / 2.0;
+        let (width, _) = size_for_orientation(self.device_orientation, NonZeroU32::new(1).unwrap());
+        let scale = vw as f32 / width as f32;
+        // Start far enough apart that pinching in has some room.
+        let start = vw as f32 / 4.0;
+        let end = (start + distance * scale).max(1.0);
+
+        let touches = |window: &Window, offset: f32| {
+            HashMap::from([
+                (
+                    FingerId::Mouse,
+                    transform(window, (center_x - offset, center_y)),
+                ),
+                (
+                    FingerId::PinchMirror,
+                    transform(window, (center_x + offset, center_y)),
+                ),
+            ])
+        };
+        let mut events = vec![Event::TouchesDown(touches(self, start))];
+        for i in 1..=MOVES {
+            let offset = start + (end - start) * (i as f32 / MOVES as f32);
+            events.push(Event::TouchesMove(touches(self, offset)));
+        }
+        events.push(Event::TouchesUp(touches(self, end)));
+        self.event_queue.extend(events);
+    }
+
+    fn stick_is_bound_to_touch_region(&self, left: bool) -> bool {
+        self.touch_regions
+            .iter()
+            .any(|region| region.binding == TouchRegionBinding::Stick(left))
+    }
+
     /// Update the virtual cursor's position, click state and visibility, then
     /// return the new position, pressed state, whether the press state changed
     /// and whether the cursor moved.
     fn update_virtual_cursor(&mut self, options: &Options) -> (f32, f32, bool, bool, bool) {
         // Get right analog stick input. The range is [-1, 1] on each axis.
-        let (x, y, pressed) = self.get_controller_stick(options, false);
+        let (x, y, pressed) = if self.stick_is_bound_to_touch_region(false) {
+            (0.0, 0.0, false)
+        } else {
+            self.get_controller_stick(options, false)
+        };
 
         // The cursor is intended to only show up once you move the analog stick
         // out of its deadzone, or while the button is held.
@@ -1038,7 +1533,13 @@ impl Window {
             );
 
             present_frame(
-                gl_ctx, viewport, matrix, /* virtual_cursor_visible_at: */ None,
+                gl_ctx,
+                viewport,
+                matrix,
+                /* virtual_cursor_visible_at: */ None,
+                /* touch_indicators_visible_at: */ &[],
+                /* speed_indicator: */ None,
+                /* volume_indicator: */ None,
             );
 
             gl_ctx.DeleteTextures(1, &texture);
@@ -1050,6 +1551,20 @@ impl Window {
         // onto image so we can rotate later if necessary
     }
 
+    /// Open a new audio output stream. Audio is usually played by OpenAL,
+    /// which doesn't need this.
+    pub fn open_audio_output(&self, sample_rate: u32, channels: u8) -> Result<AudioOutput, String> {
+        let audio_ctx = self.sdl_ctx.audio()?;
+        let spec = sdl2::audio::AudioSpecDesired {
+            freq: Some(sample_rate.try_into().unwrap()),
+            channels: Some(channels),
+            samples: None,
+        };
+        let queue = audio_ctx.open_queue::<i16, _>(None, &spec)?;
+        queue.resume();
+        Ok(AudioOutput(queue))
+    }
+
     /// Swap front-buffer and back-buffer so the result of OpenGL rendering is
     /// presented.
     pub fn swap_window(&self) {
@@ -110,12 +110,18 @@ typedef struct opaque_pthread_mutexattr_t opaque_pthread_mutexattr_t;
 typedef struct opaque_pthread_mutexattr_t *__pthread_mutexattr_t;
 typedef __pthread_mutexattr_t pthread_mutexattr_t;
 
-typedef struct opaque_pthread_cond_t opaque_pthread_cond_t;
-typedef struct opaque_pthread_cond_t *__pthread_cond_t;
+struct _opaque_pthread_cond_t {
+  long __sig;
+  char __opaque[24];
+};
+typedef struct _opaque_pthread_cond_t __pthread_cond_t;
 typedef __pthread_cond_t pthread_cond_t;
 
-typedef struct opaque_pthread_condattr_t opaque_pthread_condattr_t;
-typedef struct opaque_pthread_condattr_t *__pthread_condattr_t;
+struct _opaque_pthread_condattr_t {
+  long __sig;
+  char __opaque[4];
+};
+typedef struct _opaque_pthread_condattr_t __pthread_condattr_t;
 typedef __pthread_condattr_t pthread_condattr_t;
 
 int pthread_create(pthread_t *, const pthread_attr_t *, void *(*)(void *),
@@ -161,6 +167,32 @@ DIR *opendir(const char *);
 struct dirent *readdir(DIR *);
 int closedir(DIR *);
 
+// <setjmp.h>
+typedef int jmp_buf[(10 + 16 + 2)];
+int setjmp(jmp_buf);
+void longjmp(jmp_buf, int);
+
+// <time.h>
+typedef long time_t;
+struct tm {
+  int tm_sec;
+  int tm_min;
+  int tm_hour;
+  int tm_mday;
+  int tm_mon;
+  int tm_year;
+  int tm_wday;
+  int tm_yday;
+  int tm_isdst;
+  long tm_gmtoff;
+  char *tm_zone;
+};
+struct tm *gmtime_r(const time_t *, struct tm *);
+struct tm *localtime_r(const time_t *, struct tm *);
+time_t mktime(struct tm *);
+time_t timegm(struct tm *);
+size_t strftime(char *, size_t, const char *, const struct tm *);
+
 // <wchar.h>
 int swscanf(const wchar_t *, const wchar_t *, ...);
 
@@ -193,6 +225,30 @@ CFComparisonResult CFStringCompare(CFStringRef a, CFStringRef b,
 CFRange CFStringFind(CFStringRef theString, CFStringRef stringToFind,
                      CFOptionFlags compareOptions);
 
+// <objc/runtime.h>
+typedef struct objc_object *id;
+typedef struct objc_class *Class;
+typedef struct objc_selector *SEL;
+typedef struct objc_method *Method;
+typedef id (*IMP)(id, SEL, ...);
+typedef signed char BOOL;
+
+Class objc_getClass(const char *);
+Class object_getClass(id);
+SEL sel_registerName(const char *);
+BOOL class_respondsToSelector(Class, SEL);
+BOOL class_addMethod(Class, SEL, IMP, const char *);
+Method class_getInstanceMethod(Class, SEL);
+Method *class_copyMethodList(Class, unsigned int *);
+IMP method_getImplementation(Method);
+void method_exchangeImplementations(Method, Method);
+id objc_msgSend(id, SEL, ...);
+
+// Symbols that don't exist anywhere, for testing how dyld handles them.
+int touchHLE_missing_function(int);
+extern int touchHLE_missing_weak_variable __attribute__((weak_import));
+void touchHLE_missing_weak_function(void) __attribute__((weak_import));
+
 // === Main code ===
 
 int int_compar(const void *a, const void *b) { return *(int *)a - *(int *)b; }
@@ -343,6 +399,24 @@ int test_vsnprintf() {
                    4294967296);
   res += !!strcmp(str, "10 100 4294967296 10 100 4294967296");
   free(str);
+  str = str_format("%d %lld %d %llx", 1, -5000000000, 2, 0x123456789ab);
+  res += !!strcmp(str, "1 -5000000000 2 123456789ab");
+  free(str);
+  // Test flags and other conversions
+  str = str_format("%-5d|%+d|% d|%#x|%#o|%5.2f|%-8.3s|%c|%%|%hhd|%hu", 42, 42,
+                   42, 255, 8, 3.14159, "abcdef", 'A', 300, 70000);
+  res += !!strcmp(str, "42   |+42| 42|0xff|010| 3.14|abc     |A|%|44|4464");
+  free(str);
+  // Test %g digit counts
+  str = str_format("%g|%g|%g|%g|%G|%.10g|%E", 100000.0, 1000000.0, 0.0001,
+                   0.00001, 1e-10, 1.0 / 3, 12345.678);
+  res += !!strcmp(str,
+                  "100000|1e+06|0.0001|1e-05|1E-10|0.3333333333|1.234568E+04");
+  free(str);
+  // Test positional arguments
+  str = str_format("%2$s %1$s %3$*4$d", "world", "hello", 7, 3);
+  res += !!strcmp(str, "hello world   7");
+  free(str);
 
   return res;
 }
@@ -397,6 +471,21 @@ int test_sscanf() {
   matched = sscanf("09", "%i", &a);
   if (!(matched == 1 && a == 0))
     return -16;
+  double lf;
+  long long ll;
+  int n;
+  matched = sscanf("12 skip 3.5e2;-7000000000", "%d %*s %lf;%lld%n", &a, &lf,
+                   &ll, &n);
+  if (!(matched == 3 && a == 12 && lf == 350.0 && ll == -7000000000LL &&
+        n == 25))
+    return -17;
+  matched = sscanf("abc123", "%[a-c]%2d%c", str, &a, &str[3]);
+  if (!(matched == 3 && strncmp(str, "abc", 3) == 0 && a == 12 &&
+        str[3] == '3'))
+    return -18;
+  matched = sscanf("  ", "%d", &a);
+  if (matched != -1)
+    return -19;
   return 0;
 }
 
@@ -778,6 +867,162 @@ int test_cond_var() {
   return done == 1 ? 0 : -1;
 }
 
+jmp_buf jump_env;
+int jump_depth = 0;
+
+void jump_from_depth(int depth) {
+  // Make sure each frame uses some stack.
+  volatile int padding[16];
+  padding[0] = depth;
+  jump_depth = depth;
+  if (depth == 5) {
+    longjmp(jump_env, depth * padding[0]);
+  }
+  jump_from_depth(depth + 1);
+  // This should never be reached.
+  jump_depth = -1;
+}
+
+void jump_with_zero() { longjmp(jump_env, 0); }
+
+int test_setjmp() {
+  volatile int counter = 0;
+  // This isn't modified after setjmp(), so it must be preserved, whether it's
+  // in a callee-saved VFP register or on the stack.
+  double scale = atof("1.5");
+
+  int res = setjmp(jump_env);
+  counter++;
+  if (res == 0) {
+    if (counter != 1) {
+      return -1;
+    }
+    jump_from_depth(1);
+    return -2;
+  }
+  if (res != 25 || counter != 2 || jump_depth != 5 || scale * 2 != 3.0) {
+    return -3;
+  }
+
+  // longjmp() with 0 must make setjmp() return 1.
+  res = setjmp(jump_env);
+  if (res == 0) {
+    jump_with_zero();
+    return -4;
+  }
+  if (res != 1) {
+    return -5;
+  }
+
+  // Check that normal execution continues correctly afterwards.
+  return strlen("after longjmp") == 13 ? 0 : -6;
+}
+
+int test_time_functions() {
+  // 2009-01-32 25:00:-1 is normalized to 2009-02-02 00:59:59.
+  struct tm date = {0};
+  date.tm_year = 109;
+  date.tm_mday = 32;
+  date.tm_hour = 25;
+  date.tm_sec = -1;
+  time_t timestamp = timegm(&date);
+  if (timestamp != 1233536399 || date.tm_mon != 1 || date.tm_mday != 2 ||
+      date.tm_hour != 0 || date.tm_wday != 1 || date.tm_yday != 32)
+    return -1;
+
+  struct tm date2;
+  if (gmtime_r(&timestamp, &date2) != &date2 || date2.tm_min != 59)
+    return -2;
+  char buf[64];
+  size_t len = strftime(buf, sizeof(buf), "%d/%m/%Y %H:%M:%S %j %a %b %p %%",
+                        &date2);
+  if (len != 36 ||
+      strcmp(buf, "02/02/2009 00:59:59 033 Mon Feb AM %") != 0)
+    return -3;
+  // The result doesn't fit, so 0 is returned.
+  if (strftime(buf, 10, "%d/%m/%Y %H:%M", &date2) != 0)
+    return -4;
+
+  // Local time depends on the host, but mktime() and localtime_r() must
+  // agree with each other.
+  struct tm local = {0};
+  local.tm_year = 110;
+  local.tm_mon = 13;
+  local.tm_mday = 1;
+  local.tm_hour = 12;
+  local.tm_isdst = -1;
+  timestamp = mktime(&local);
+  struct tm local2;
+  localtime_r(&timestamp, &local2);
+  if (local.tm_year != 111 || local.tm_mon != 1 || local.tm_mday != 1 ||
+      local2.tm_year != 111 || local2.tm_mon != 1 || local2.tm_mday != 1 ||
+      local2.tm_hour != 12 || local2.tm_wday != 2)
+    return -5;
+
+  return 0;
+}
+
+int objc_doubled_imp(id self, SEL _cmd, int x) { return x * 2; }
+int objc_tripled_imp(id self, SEL _cmd, int x) { return x * 3; }
+
+int test_objc_runtime() {
+  Class cls = objc_getClass("NSObject");
+  if (cls == NULL || objc_getClass("NoSuchClass") != NULL)
+    return -1;
+  Class metaclass = object_getClass((id)cls);
+  if (metaclass == NULL || metaclass == cls)
+    return -2;
+  if (!class_respondsToSelector(metaclass, sel_registerName("new")) ||
+      class_respondsToSelector(cls, sel_registerName("new")))
+    return -3;
+
+  // Guest methods on a host class
+  SEL doubled = sel_registerName("touchHLE_doubled:");
+  SEL tripled = sel_registerName("touchHLE_tripled:");
+  if (class_respondsToSelector(cls, doubled))
+    return -4;
+  if (!class_addMethod(cls, doubled, (IMP)&objc_doubled_imp, "i@:i") ||
+      !class_addMethod(cls, tripled, (IMP)&objc_tripled_imp, "i@:i"))
+    return -5;
+  if (class_addMethod(cls, doubled, (IMP)&objc_tripled_imp, "i@:i"))
+    return -6;
+  if (!class_respondsToSelector(cls, doubled))
+    return -7;
+  Method doubled_method = class_getInstanceMethod(cls, doubled);
+  if (method_getImplementation(doubled_method) != (IMP)&objc_doubled_imp)
+    return -8;
+  id obj = objc_msgSend((id)cls, sel_registerName("new"));
+  int (*send_int)(id, SEL, int) = (int (*)(id, SEL, int))objc_msgSend;
+  if (send_int(obj, doubled, 21) != 42)
+    return -9;
+  method_exchangeImplementations(doubled_method,
+                                 class_getInstanceMethod(cls, tripled));
+  if (send_int(obj, doubled, 21) != 63 || send_int(obj, tripled, 21) != 42)
+    return -10;
+
+  // Host methods called through a function pointer
+  SEL hash = sel_registerName("hash");
+  IMP hash_imp = method_getImplementation(class_getInstanceMethod(cls, hash));
+  if (hash_imp == NULL || hash_imp(obj, hash) != obj)
+    return -11;
+
+  unsigned int count = 0;
+  Method *methods = class_copyMethodList(cls, &count);
+  if (methods == NULL || count < 3 || methods[count] != NULL)
+    return -12;
+  int found = 0;
+  for (unsigned int i = 0; i < count; i++) {
+    if (methods[i] == doubled_method)
+      found++;
+  }
+  free(methods);
+  if (found != 1)
+    return -13;
+
+  objc_msgSend(obj, sel_registerName("release"));
+  return 0;
+}
+
 int test_strncpy() {
   char *src = "test\0abcd";
   char dst[10];
@@ -1106,6 +1351,21 @@ int test_open() {
   return 0;
 }
 
+int test_missing_symbols() {
+  // Calls to missing functions return 0 (unless --strict-binding is used).
+  if (touchHLE_missing_function(123) != 0) {
+    return -1;
+  }
+  // Missing weak imports are NULL, so that apps can check for them.
+  if (&touchHLE_missing_weak_variable != NULL) {
+    return -2;
+  }
+  if (touchHLE_missing_weak_function != NULL) {
+    return -3;
+  }
+  return 0;
+}
+
 // clang-format off
 #define FUNC_DEF(func)                                                         \
   { &func, #func }
@@ -1141,6 +1401,10 @@ struct {
     FUNC_DEF(test_fwrite),
     FUNC_DEF(test_open),
     FUNC_DEF(test_cond_var),
+    FUNC_DEF(test_setjmp),
+    FUNC_DEF(test_time_functions),
+    FUNC_DEF(test_objc_runtime),
+    FUNC_DEF(test_missing_symbols),
 };
 // clang-format on
 